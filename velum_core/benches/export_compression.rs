@@ -0,0 +1,81 @@
+// Save time against file size for the ways a package can be compressed
+//
+// Saves an image-heavy document, photos making up most of its bytes, with
+// each compression setting and prints the median save time and the size of
+// the file. Photos are stood in for by incompressible data with a PNG header,
+// which is what JPEG and PNG data look like to deflate. Run with
+//
+//     cargo bench --bench export_compression
+
+use std::time::{Duration, Instant};
+
+use velum_core::image::ImageCache;
+use velum_core::ooxml::{
+    snapshot_to_word_document, DocumentImage, DocxSerializer, ExportContent, ExportControl, ExportOptions, OpcPackage,
+    PackageCompression,
+};
+use velum_core::PieceTree;
+
+const PARAGRAPHS: usize = 400;
+const IMAGES: usize = 24;
+const IMAGE_BYTES: usize = 512 * 1024;
+const RUNS: usize = 7;
+
+/// A PNG header followed by `len` bytes of noise
+fn photo(seed: u64, len: usize) -> Vec<u8> {
+    let mut data = vec![
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R', 0x00, 0x00,
+        0x02, 0x00, 0x00, 0x00, 0x01, 0x80,
+    ];
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    data.extend((0..len).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    }));
+    data
+}
+
+fn main() {
+    let text = (0..PARAGRAPHS)
+        .map(|i| format!("Paragraph {} of a report with photographs, long enough to wrap over a few lines of the page.", i))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut cache = ImageCache::new();
+    let mut content = ExportContent::default();
+    for i in 0..IMAGES {
+        let path = format!("media/photo{}.png", i + 1);
+        cache.load(path.clone(), photo(i as u64 + 1, IMAGE_BYTES)).unwrap();
+        content.images.push(DocumentImage {
+            path,
+            paragraph_index: i * PARAGRAPHS / IMAGES,
+            ..Default::default()
+        });
+    }
+    let document = snapshot_to_word_document(&PieceTree::new(text).snapshot(), &content, &ExportControl::new()).unwrap();
+    let serializer = DocxSerializer::new(OpcPackage::default(), document).with_image_cache(&cache);
+
+    let settings = [
+        ("deflate everything, level 9", PackageCompression::deflate_all(9)),
+        ("deflate everything, level 6", PackageCompression::deflate_all(6)),
+        ("deflate everything, level 1", PackageCompression::deflate_all(1)),
+        ("store media, level 9 (default)", PackageCompression::default()),
+        ("store media, level 6", PackageCompression { deflate_level: 6, ..Default::default() }),
+        ("store media, zstd level 1 (autosave)", PackageCompression::autosave()),
+    ];
+    println!("{} paragraphs, {} images of {} KiB", PARAGRAPHS, IMAGES, IMAGE_BYTES / 1024);
+    println!("{:<40} {:>10} {:>12}", "compression", "save (ms)", "size (KiB)");
+    for (name, compression) in settings {
+        let mut times: Vec<Duration> = Vec::with_capacity(RUNS);
+        let mut size = 0;
+        for _ in 0..RUNS {
+            let options = ExportOptions { compression, ..Default::default() };
+            let start = Instant::now();
+            size = serializer.export_docx(Some(options)).unwrap().len();
+            times.push(start.elapsed());
+        }
+        times.sort();
+        println!("{:<40} {:>10.1} {:>12}", name, times[RUNS / 2].as_secs_f64() * 1000.0, size / 1024);
+    }
+}
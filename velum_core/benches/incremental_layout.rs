@@ -0,0 +1,66 @@
+// Relayout time for single keystrokes in a 300-page document
+//
+// Lays out a document of twelve thousand paragraphs once, then types one
+// character at a time near its start, middle and end, bringing the layout up
+// to date after each keystroke, and prints the median and slowest update.
+// Only the edited paragraph is broken into lines again; pagination picks up
+// at its page, so edits near the start cost the most. Run with
+//
+//     cargo bench --bench incremental_layout
+
+use std::time::{Duration, Instant};
+
+use velum_core::incremental_layout::IncrementalLayout;
+use velum_core::text_shaping::TextShaper;
+use velum_core::{PageConfig, PageLayout, ParagraphHashes, PieceTree};
+
+const PARAGRAPHS: usize = 12000;
+const KEYSTROKES: usize = 200;
+/// One frame at 60 Hz
+const BUDGET: Duration = Duration::from_millis(16);
+
+fn main() {
+    let text = (0..PARAGRAPHS)
+        .map(|i| format!("Paragraph {} of a long report, with enough words in it to wrap onto a second line of the page.", i))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut tree = PieceTree::new(text);
+    let mut hashes = ParagraphHashes::build(&tree);
+    let mut layout = IncrementalLayout::new(PageLayout::with_page_config(PageConfig::letter()));
+    layout.line_layout_mut().breaker_mut().set_shaper(TextShaper::without_font());
+
+    let start = Instant::now();
+    let first = layout.update(&tree, hashes.entries(), &[]);
+    println!(
+        "{} paragraphs on {} pages, laid out in {:.1} ms",
+        PARAGRAPHS,
+        first.page_count,
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+    assert!(first.page_count >= 300, "expected at least 300 pages, got {}", first.page_count);
+
+    println!("{:<24} {:>12} {:>12}", "typing in", "median (ms)", "max (ms)");
+    let mut slowest = Duration::ZERO;
+    for (name, paragraph) in [("page 1", 2), ("the middle", PARAGRAPHS / 2), ("the last page", PARAGRAPHS - 3)] {
+        let mut times: Vec<Duration> = Vec::with_capacity(KEYSTROKES);
+        let first = hashes.entries()[paragraph].start + 8;
+        for offset in (first..).take(KEYSTROKES) {
+            tree.insert_with_attrs(offset, "x".to_string(), None);
+            hashes.apply_edit(&tree, offset, 0, 1);
+            let start = Instant::now();
+            let stats = layout.update(&tree, hashes.entries(), &[]);
+            times.push(start.elapsed());
+            assert_eq!(stats.paragraphs_laid_out, 1);
+        }
+        times.sort();
+        let max = times[KEYSTROKES - 1];
+        slowest = slowest.max(max);
+        println!(
+            "{:<24} {:>12.3} {:>12.3}",
+            name,
+            times[KEYSTROKES / 2].as_secs_f64() * 1000.0,
+            max.as_secs_f64() * 1000.0
+        );
+    }
+    assert!(slowest < BUDGET, "slowest update took {:?}, over the {:?} budget", slowest, BUDGET);
+}
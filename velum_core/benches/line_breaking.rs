@@ -0,0 +1,55 @@
+// Break time and raggedness of first-fit against total-fit line breaking
+//
+// Breaks one long paragraph into lines with each strategy and prints the line
+// count, the raggedness (squared slack per line, leaving out the last line)
+// and the median break time. Total fit chooses every break of the paragraph
+// together, so it costs more time for lines that fill the measure more
+// evenly. Run with
+//
+//     cargo bench --bench line_breaking
+
+use std::time::{Duration, Instant};
+
+use velum_core::line_breaking::{BreakStrategy, Line, LineBreaker};
+
+const WIDTH: f32 = 300.0;
+const REPEATS: usize = 8;
+const RUNS: usize = 15;
+
+/// Squared slack per line, leaving out the last line of the paragraph
+fn raggedness(lines: &[Line]) -> f32 {
+    lines[..lines.len() - 1]
+        .iter()
+        .map(|line| ((WIDTH - line.width) / WIDTH * 100.0).powi(2))
+        .sum()
+}
+
+fn main() {
+    let text = "When a paragraph is set one line at a time, each line takes as many words as fit \
+                and the next line has to live with whatever is left over. Choosing every break of \
+                the paragraph together trades a little time for lines that fill the measure more \
+                evenly, which matters most for justified text where the slack becomes visible \
+                gaps between the words of a line. "
+        .repeat(REPEATS);
+
+    println!("{:<12} {:>8} {:>12} {:>12}", "strategy", "lines", "raggedness", "median (ms)");
+    for (name, strategy) in [("first fit", BreakStrategy::FirstFit), ("total fit", BreakStrategy::TotalFit)] {
+        let mut times: Vec<Duration> = Vec::with_capacity(RUNS);
+        let mut lines = Vec::new();
+        for _ in 0..RUNS {
+            let mut breaker = LineBreaker::with_width(WIDTH);
+            breaker.set_break_strategy(strategy);
+            let start = Instant::now();
+            lines = breaker.break_lines(&text, None);
+            times.push(start.elapsed());
+        }
+        times.sort();
+        println!(
+            "{:<12} {:>8} {:>12.0} {:>12.3}",
+            name,
+            lines.len(),
+            raggedness(&lines),
+            times[RUNS / 2].as_secs_f64() * 1000.0
+        );
+    }
+}
@@ -0,0 +1,73 @@
+// Time to show a 1000-page document, laying out only the pages in view
+//
+// Hashes the paragraphs of a document of forty thousand paragraphs, gives
+// them estimated heights and lays out the pages around a viewport at its
+// start and in its middle, then refines the estimates a batch at a time as
+// a frontend would while idle, and prints how long each part took. Run with
+//
+//     cargo bench --bench viewport_layout
+
+use std::time::{Duration, Instant};
+
+use velum_core::text_shaping::TextShaper;
+use velum_core::{PageConfig, PageLayout, ParagraphHashes, PieceTree, Viewport, ViewportLayout};
+
+const PARAGRAPHS: usize = 40_000;
+const BATCH: usize = 500;
+/// Opening feels instant under a tenth of a second
+const BUDGET: Duration = Duration::from_millis(100);
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn main() {
+    let text = (0..PARAGRAPHS)
+        .map(|i| format!("Paragraph {} of a long report, with enough words in it to wrap onto a second line of the page.", i))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let tree = PieceTree::new(text);
+
+    let start = Instant::now();
+    let hashes = ParagraphHashes::build(&tree);
+    let mut layout = ViewportLayout::new(PageLayout::with_page_config(PageConfig::letter()));
+    layout.line_layout_mut().breaker_mut().set_shaper(TextShaper::without_font());
+    layout.update(hashes.entries(), &[]);
+    let view = layout.viewport_pages(&tree, Viewport { top: 0.0, height: 900.0 });
+    let opened = start.elapsed();
+    println!(
+        "{} paragraphs, about {} pages: first page shown in {:.1} ms, {} paragraphs laid out",
+        PARAGRAPHS,
+        view.page_count,
+        ms(opened),
+        PARAGRAPHS - layout.estimated_count()
+    );
+    assert!(view.page_count >= 1000, "expected at least 1000 pages, got {}", view.page_count);
+
+    let start = Instant::now();
+    let middle = layout.scroll_height() / 2.0;
+    let view = layout.viewport_pages(&tree, Viewport { top: middle, height: 900.0 });
+    println!("jumped to page {} in {:.1} ms", view.pages[1].page.page_index + 1, ms(start.elapsed()));
+
+    let start = Instant::now();
+    let mut steps = 0;
+    let mut slowest = Duration::ZERO;
+    loop {
+        let step_start = Instant::now();
+        let step = layout.refine(&tree, BATCH);
+        slowest = slowest.max(step_start.elapsed());
+        steps += 1;
+        if step.exact {
+            break;
+        }
+    }
+    println!(
+        "refined to {} exact pages in {} steps of {} paragraphs: {:.1} ms in all, {:.1} ms at most",
+        layout.page_count(),
+        steps,
+        BATCH,
+        ms(start.elapsed()),
+        ms(slowest)
+    );
+    assert!(opened < BUDGET, "showing the first page took {:?}, over the {:?} budget", opened, BUDGET);
+}
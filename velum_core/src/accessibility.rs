@@ -0,0 +1,345 @@
+//! # Accessibility Module
+//!
+//! The document as a screen reader reads it: content in reading order, each
+//! piece with the semantic role the embedder maps onto the platform's
+//! accessibility tree.
+//!
+//! Body blocks are read in document order. A paragraph is a heading when its
+//! style, or a style it is based on, is one of Heading 1–9; a paragraph with a
+//! list label is a list item, and a numbered heading keeps its heading role
+//! with the label attached. Empty paragraphs are left out. Images follow the
+//! paragraph they are drawn in, carrying their alt text. Table cells are read
+//! row by row, each cell once: the cells a merge continues into are skipped
+//! and the first cell carries the span. A footnote is read after the
+//! paragraph referring to it, its paragraphs between a start and an end node;
+//! endnotes are read the same way after the body, in the order of their
+//! references.
+//!
+//! Each node has the char offset into the body text, paragraphs joined with
+//! "\n", that it belongs at, which places it on a page of the pagination.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::document_model::{paragraph_attributes, Block, DocumentModel};
+use crate::numbering::ListNumbering;
+use crate::ooxml::{NoteKind, Paragraph, Table};
+use crate::repagination::PageBoundary;
+use crate::style_sheet::StyleSheet;
+
+/// What a node is to assistive technology
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum Role {
+    /// Heading of `level`, from 1; `label` is its outline number, if any
+    Heading { level: u8, label: Option<String> },
+    Paragraph,
+    /// Item of a list, `level` from 0, with its number or bullet
+    ListItem { level: u8, label: String },
+    /// Cell of the `table`th table of the document, its row and grid column
+    /// from 0; `header` is set in the rows repeated as table headers
+    TableCell {
+        table: usize,
+        row: usize,
+        column: usize,
+        row_span: usize,
+        column_span: usize,
+        header: bool,
+    },
+    Image { alt_text: Option<String> },
+    /// The nodes up to the matching end are the text of note `id`
+    NoteStart { kind: NoteKind, id: String },
+    NoteEnd { kind: NoteKind, id: String },
+}
+
+/// One piece of content in reading order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessibleNode {
+    #[serde(flatten)]
+    pub role: Role,
+    /// Text read out; empty for images and note boundaries
+    pub text: String,
+    /// Char offset into the body text the node belongs at
+    pub offset: usize,
+}
+
+/// The content of one page in reading order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessiblePage {
+    pub page_index: usize,
+    pub nodes: Vec<AccessibleNode>,
+}
+
+/// The whole document in reading order
+///
+/// `styles` and `numbering` are the document's; paragraph styles decide the
+/// headings and list definitions the labels.
+pub fn reading_order(model: &DocumentModel, styles: &StyleSheet, numbering: &ListNumbering) -> Vec<AccessibleNode> {
+    let paragraphs: Vec<&Paragraph> = model.paragraphs().collect();
+    let mut labels = numbering
+        .resolve(
+            &paragraphs
+                .iter()
+                .map(|paragraph| styles.effective_paragraph(paragraph_attributes(&paragraph.properties).as_ref()))
+                .collect::<Vec<_>>(),
+        )
+        .into_iter();
+
+    let mut images: HashMap<usize, Vec<_>> = HashMap::new();
+    for image in &model.images {
+        images.entry(image.paragraph_index).or_default().push(image);
+    }
+    let notes = |kind: NoteKind, id: &str| -> Option<&[Paragraph]> {
+        match kind {
+            NoteKind::Footnote => model.footnotes.iter().find(|note| note.id == id).map(|note| &note.paragraphs[..]),
+            NoteKind::Endnote => model.endnotes.iter().find(|note| note.id == id).map(|note| &note.paragraphs[..]),
+        }
+    };
+
+    let mut nodes = Vec::new();
+    let mut endnotes = Vec::new();
+    let (mut index, mut offset, mut table_count) = (0, 0, 0);
+    for block in &model.body {
+        let paragraph = match block {
+            Block::Table(table) => {
+                table_nodes(table, table_count, offset, &mut nodes);
+                table_count += 1;
+                continue;
+            }
+            Block::Paragraph(paragraph) => paragraph,
+        };
+        let label = labels.next().flatten();
+        if !paragraph.text.is_empty() || label.is_some() {
+            let role = match (heading_level(styles, paragraph.properties.style_id.as_deref()), label) {
+                (Some(level), label) => Role::Heading { level, label: label.map(|label| label.text) },
+                (None, Some(label)) => Role::ListItem { level: label.level, label: label.text },
+                (None, None) => Role::Paragraph,
+            };
+            nodes.push(AccessibleNode { role, text: paragraph.text.clone(), offset });
+        }
+
+        let mut drawn = images.remove(&index).unwrap_or_default();
+        drawn.sort_by_key(|image| image.position);
+        for image in drawn {
+            nodes.push(AccessibleNode {
+                role: Role::Image { alt_text: image.alt_description.clone().or_else(|| image.title.clone()) },
+                text: String::new(),
+                offset: offset + image.position.min(paragraph.text.chars().count()),
+            });
+        }
+
+        for reference in &paragraph.note_references {
+            let position = offset + reference.position;
+            match reference.kind {
+                NoteKind::Footnote => {
+                    if let Some(note) = notes(reference.kind, &reference.id) {
+                        note_nodes(reference.kind, &reference.id, note, position, &mut nodes);
+                    }
+                }
+                NoteKind::Endnote => endnotes.push((reference.id.as_str(), position)),
+            }
+        }
+        offset += paragraph.text.chars().count() + 1;
+        index += 1;
+    }
+
+    for (id, position) in endnotes {
+        if let Some(note) = notes(NoteKind::Endnote, id) {
+            note_nodes(NoteKind::Endnote, id, note, position, &mut nodes);
+        }
+    }
+    nodes
+}
+
+/// The nodes of `nodes` on each page of `pages`, in reading order
+///
+/// Nodes past the last page go on it, as text pagination has not reached yet.
+pub fn by_page(nodes: &[AccessibleNode], pages: &[PageBoundary]) -> Vec<AccessiblePage> {
+    let mut result: Vec<AccessiblePage> = pages
+        .iter()
+        .map(|page| AccessiblePage { page_index: page.page_index, nodes: Vec::new() })
+        .collect();
+    if result.is_empty() {
+        return result;
+    }
+    let last = result.len() - 1;
+    for node in nodes {
+        let page = pages.partition_point(|page| page.end <= node.offset).min(last);
+        result[page].nodes.push(node.clone());
+    }
+    result
+}
+
+/// Level of the headings of paragraph style `style_id`, by its name or the
+/// name of a style it is based on
+pub(crate) fn heading_level(styles: &StyleSheet, style_id: Option<&str>) -> Option<u8> {
+    let style_id = style_id?;
+    let chain = styles.inheritance_chain(style_id);
+    let mut names = chain
+        .iter()
+        .flat_map(|style| [style.id.as_str(), style.name.as_str()])
+        .chain(std::iter::once(style_id));
+    names.find_map(|name| {
+        let name = name.to_ascii_lowercase().replace(' ', "");
+        name.strip_prefix("heading")?.parse().ok().filter(|level| (1..=9).contains(level))
+    })
+}
+
+/// Nodes of the cells of `table`, the `number`th of the document, placed at `offset`
+fn table_nodes(table: &Table, number: usize, offset: usize, nodes: &mut Vec<AccessibleNode>) {
+    // Grid column each cell starts at, row by row
+    let columns: Vec<Vec<usize>> = table
+        .rows
+        .iter()
+        .map(|row| {
+            row.cells
+                .iter()
+                .scan(0, |column, cell| {
+                    let start = *column;
+                    *column += cell.properties.grid_span.unwrap_or(1).max(1) as usize;
+                    Some(start)
+                })
+                .collect()
+        })
+        .collect();
+    let continues = |merge: Option<i32>| merge.is_some_and(|merge| merge != 1);
+
+    for (r, row) in table.rows.iter().enumerate() {
+        for (c, cell) in row.cells.iter().enumerate() {
+            if continues(cell.vertical_merge) || continues(cell.horizontal_merge) {
+                continue;
+            }
+            let column = columns[r][c];
+            let row_span = 1 + table.rows[r + 1..]
+                .iter()
+                .zip(&columns[r + 1..])
+                .take_while(|(below, starts)| {
+                    starts
+                        .iter()
+                        .position(|&start| start == column)
+                        .is_some_and(|i| continues(below.cells[i].vertical_merge))
+                })
+                .count();
+            let column_span = cell.properties.grid_span.unwrap_or(1).max(1) as usize
+                + row.cells[c + 1..].iter().take_while(|next| continues(next.horizontal_merge)).count();
+            let text = cell.paragraphs.iter().map(|paragraph| paragraph.text.as_str()).collect::<Vec<_>>().join("\n");
+            nodes.push(AccessibleNode {
+                role: Role::TableCell {
+                    table: number,
+                    row: r,
+                    column,
+                    row_span,
+                    column_span,
+                    header: row.properties.is_header,
+                },
+                text,
+                offset,
+            });
+        }
+    }
+}
+
+/// Nodes of note `id` with `paragraphs`, placed at its reference
+fn note_nodes(kind: NoteKind, id: &str, paragraphs: &[Paragraph], offset: usize, nodes: &mut Vec<AccessibleNode>) {
+    let node = |role: Role, text: String| AccessibleNode { role, text, offset };
+    nodes.push(node(Role::NoteStart { kind, id: id.to_string() }, String::new()));
+    nodes.extend(
+        paragraphs
+            .iter()
+            .filter(|paragraph| !paragraph.text.trim().is_empty())
+            .map(|paragraph| node(Role::Paragraph, paragraph.text.trim().to_string())),
+    );
+    nodes.push(node(Role::NoteEnd { kind, id: id.to_string() }, String::new()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::{DocumentImage, Footnote, NoteReference, TableCell, TableCellProperties, TableRow};
+
+    fn paragraph(text: &str, style_id: Option<&str>) -> Paragraph {
+        let mut paragraph = Paragraph { text: text.to_string(), ..Default::default() };
+        paragraph.properties.style_id = style_id.map(str::to_string);
+        paragraph
+    }
+
+    fn cell(text: &str, grid_span: Option<u32>, vertical_merge: Option<i32>) -> TableCell {
+        TableCell {
+            paragraphs: vec![paragraph(text, None)],
+            vertical_merge,
+            properties: TableCellProperties { grid_span, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reading_order_roles() {
+        let mut numbering = ListNumbering::new();
+        let num_id = numbering.add_list(crate::numbering::ListKind::Numbered);
+        let mut item = paragraph("Milk", None);
+        item.properties.num_id = Some(num_id);
+        item.properties.list_level = Some(0);
+        let mut cited = paragraph("Cited", None);
+        cited.note_references.push(NoteReference { kind: NoteKind::Footnote, id: "2".to_string(), position: 5 });
+
+        let mut table = Table {
+            rows: vec![
+                TableRow { cells: vec![cell("Name", Some(2), None), cell("Age", None, Some(1))], ..Default::default() },
+                TableRow { cells: vec![cell("Ann", None, None), cell("Lee", None, None), cell("", None, Some(0))], ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        table.rows[0].properties.is_header = true;
+
+        let model = DocumentModel {
+            body: vec![
+                Block::from(paragraph("Intro", Some("Heading2"))),
+                Block::from(paragraph("", None)),
+                Block::from(item),
+                Block::Table(Box::new(table)),
+                Block::from(cited),
+            ],
+            images: vec![DocumentImage {
+                paragraph_index: 2,
+                position: 4,
+                alt_description: Some("A carton".to_string()),
+                ..Default::default()
+            }],
+            footnotes: vec![Footnote { id: "2".to_string(), footnote_type: None, paragraphs: vec![paragraph(" Source.", None)] }],
+            ..Default::default()
+        };
+
+        let nodes = reading_order(&model, &StyleSheet::new(), &numbering);
+        let roles: Vec<(&Role, &str, usize)> = nodes.iter().map(|node| (&node.role, node.text.as_str(), node.offset)).collect();
+        let footnote = |end: bool| match end {
+            false => Role::NoteStart { kind: NoteKind::Footnote, id: "2".to_string() },
+            true => Role::NoteEnd { kind: NoteKind::Footnote, id: "2".to_string() },
+        };
+        let table_cell = |row, column, row_span, column_span, header| Role::TableCell { table: 0, row, column, row_span, column_span, header };
+        assert_eq!(
+            roles,
+            vec![
+                (&Role::Heading { level: 2, label: None }, "Intro", 0),
+                (&Role::ListItem { level: 0, label: "1.".to_string() }, "Milk", 7),
+                (&Role::Image { alt_text: Some("A carton".to_string()) }, "", 11),
+                (&table_cell(0, 0, 1, 2, true), "Name", 12),
+                (&table_cell(0, 2, 2, 1, true), "Age", 12),
+                (&table_cell(1, 0, 1, 1, false), "Ann", 12),
+                (&table_cell(1, 1, 1, 1, false), "Lee", 12),
+                (&Role::Paragraph, "Cited", 12),
+                (&footnote(false), "", 17),
+                (&Role::Paragraph, "Source.", 17),
+                (&footnote(true), "", 17),
+            ]
+        );
+
+        let pages = [
+            PageBoundary { page_index: 0, start: 0, end: 7 },
+            PageBoundary { page_index: 1, start: 7, end: 12 },
+        ];
+        let pages = by_page(&nodes, &pages);
+        assert_eq!(pages[0].nodes.len(), 1);
+        assert_eq!(pages[1].nodes.len(), 10);
+    }
+}
@@ -1,78 +1,66 @@
-use crate::piece_tree::{PieceTree, TextAttributes, Piece};
-use crate::find::SearchOptions;
+use crate::document::{Document, DocumentMetadata};
+use crate::piece_tree::{DeleteDirection, EditorState, MultiSelection, PieceTree, Selection, TextAttributes};
+use crate::find::{SearchOptions, SearchResult};
+use crate::modification::ModificationTracker;
+use crate::edit_locations::EditLocations;
+use crate::style_sheet::StyleSheet;
+use crate::revisions::RevisionSet;
+use crate::comments::CommentManager;
+use crate::bookmarks::BookmarkRegistry;
+use crate::hyperlinks::HyperlinkSet;
+use crate::numbering::ListNumbering;
+use crate::floating::PlacedObject;
+use crate::document_end::DocumentEnd;
+use crate::autoformat::AutoFormatOptions;
+use crate::ooxml::{FormError, Section};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-/// Document metadata structure
-#[derive(Debug, Clone)]
-pub struct DocumentMetadata {
-    pub title: String,
-    pub author: String,
-    pub created_at: u64,
-    pub modified_at: u64,
-    pub word_count: usize,
-    pub char_count: usize,
-}
-
-impl Default for DocumentMetadata {
-    fn default() -> Self {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        DocumentMetadata {
-            title: "Untitled Document".to_string(),
-            author: "".to_string(),
-            created_at: now,
-            modified_at: now,
-            word_count: 0,
-            char_count: 0,
-        }
+impl Document {
+    /// Re-evaluate the dirty state after an edit
+    fn track_modification(&mut self) {
+        let hash = self.state_hash();
+        MODIFICATION.lock().unwrap().content_changed(hash);
     }
-}
 
-/// Document structure containing both content and metadata
-pub struct Document {
-    pub content: PieceTree,
-    pub metadata: DocumentMetadata,
-}
+    /// Treat the current state as saved, e.g. right after loading
+    fn mark_saved(&mut self) {
+        let hash = self.state_hash();
+        MODIFICATION.lock().unwrap().mark_saved(hash);
+    }
 
-impl Document {
-    pub fn empty() -> Self {
-        Document {
-            content: PieceTree::empty(),
-            metadata: DocumentMetadata::default(),
-        }
+    /// "Error: ..." if the document's protection keeps chars `range` from being edited
+    fn protected(&self, range: std::ops::Range<usize>) -> Option<String> {
+        (!self.allows_edit(range)).then(|| format!("Error: {}", FormError::Protected))
     }
 
-    pub fn new(content: String) -> Self {
-        let char_count = content.chars().count();
-        let word_count = content.split_whitespace().count();
-        let mut metadata = DocumentMetadata::default();
-        metadata.char_count = char_count;
-        metadata.word_count = word_count;
-        
-        Document {
-            content: PieceTree::new(content),
-            metadata,
-        }
+    /// Put `document` in place of this one, dropping the images cached, and
+    /// pinned when inserted, for this one
+    fn replace_with(&mut self, document: Document) {
+        *self = document;
+        DEFAULT_IMAGE_CACHE.clear();
+        *MEDIA_SOURCE.lock().unwrap() = MediaSource::None;
     }
 
-    pub fn update_metadata(&mut self) {
-        let text = self.content.get_text();
-        self.metadata.char_count = text.chars().count();
-        self.metadata.word_count = text.split_whitespace().count();
-        self.metadata.modified_at = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+    /// Pages and sections to lay the document out in: the piece tree holds a
+    /// single flow of text, laid out as one section with the first section's
+    /// page setup and the body-level section's columns and start
+    fn page_layout(&self) -> (PageLayout, Vec<Section>) {
+        let section = self.page_setup.sections[0].apply_to(Section {
+            first_paragraph: 0,
+            paragraph_count: self.content.paragraph_count(),
+            ..self.end.section().clone()
+        });
+        (PageLayout::for_section(&section), vec![section])
     }
 }
 
 static DOCUMENT: Lazy<RwLock<Document>> = Lazy::new(|| RwLock::new(Document::empty()));
+/// Dirty state of DOCUMENT; kept outside it so listeners survive loading another document
+static MODIFICATION: Lazy<Mutex<ModificationTracker>> = Lazy::new(|| Mutex::new(ModificationTracker::default()));
 
 pub fn hello_velum() -> String {
     "Hello from Velum Core (Rust)!".to_string()
@@ -80,9 +68,10 @@ pub fn hello_velum() -> String {
 
 pub fn get_sample_document() -> String {
     let mut doc = DOCUMENT.write().unwrap();
-    *doc = Document::new("Welcome to Velum.".to_string());
+    doc.replace_with(Document::new("Welcome to Velum.".to_string()));
     doc.content.insert(16, " This is Microsoft Word 1:1 replica project.".to_string());
     doc.update_metadata();
+    doc.mark_saved();
     doc.content.get_text()
 }
 
@@ -93,23 +82,35 @@ pub fn multiply(a: i32, b: i32) -> i32 {
 // 创建空文档
 pub fn create_empty_document() -> String {
     let mut doc = DOCUMENT.write().unwrap();
-    *doc = Document::empty();
+    doc.replace_with(Document::empty());
+    doc.mark_saved();
     doc.content.get_text()
 }
 
 // 在指定位置插入文本
 pub fn insert_text(offset: usize, new_text: String) -> String {
     let mut doc = DOCUMENT.write().unwrap();
-    doc.content.insert(offset, new_text);
-    doc.update_metadata();
+    if let Some(error) = doc.protected(offset..offset) {
+        return error;
+    }
+    doc.insert_text(offset, &new_text);
+    doc.track_modification();
     doc.content.get_text()
 }
 
 // 删除指定范围文本
 pub fn delete_text(offset: usize, length: usize) -> String {
     let mut doc = DOCUMENT.write().unwrap();
-    doc.content.delete(offset, length);
-    doc.update_metadata();
+    // The offsets are in bytes; one inside a char moves to the next char
+    // boundary, as PieceTree::delete does
+    let start = doc.content.byte_to_char_offset(offset);
+    let end = doc.content.byte_to_char_offset(offset.saturating_add(length));
+    if let Some(error) = doc.protected(start..end) {
+        return error;
+    }
+    // While tracking changes, deleted text may only be marked, or removed in parts
+    doc.delete_text(start..end);
+    doc.track_modification();
     doc.content.get_text()
 }
 
@@ -137,6 +138,12 @@ pub fn get_offset_at_line(line_number: usize) -> usize {
     doc.content.get_offset_at_line(line_number)
 }
 
+// 获取字符偏移量所在的行号
+pub fn get_line_at_offset(char_offset: usize) -> usize {
+    let doc = DOCUMENT.read().unwrap();
+    doc.content.get_line_at_offset(char_offset)
+}
+
 // 获取完整文本
 pub fn get_full_text() -> String {
     let doc = DOCUMENT.read().unwrap();
@@ -146,16 +153,20 @@ pub fn get_full_text() -> String {
 // 撤销
 pub fn undo() -> String {
     let mut doc = DOCUMENT.write().unwrap();
-    doc.content.undo();
-    doc.update_metadata();
+    if doc.undo() {
+        notify_history_restored(&doc.content.editor_state());
+    }
+    doc.track_modification();
     doc.content.get_text()
 }
 
 // 重做
 pub fn redo() -> String {
     let mut doc = DOCUMENT.write().unwrap();
-    doc.content.redo();
-    doc.update_metadata();
+    if doc.redo() {
+        notify_history_restored(&doc.content.editor_state());
+    }
+    doc.track_modification();
     doc.content.get_text()
 }
 
@@ -187,6 +198,7 @@ pub fn set_document_title(title: String) {
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
+    doc.track_modification();
 }
 
 // 获取文档作者
@@ -199,6 +211,7 @@ pub fn get_document_author() -> String {
 pub fn set_document_author(author: String) {
     let mut doc = DOCUMENT.write().unwrap();
     doc.metadata.author = author;
+    doc.track_modification();
 }
 
 // 获取创建时间
@@ -285,6 +298,326 @@ pub fn get_selection_range() -> (i32, i32) {
     (start as i32, end as i32)
 }
 
+// ==================== Cursor Navigation APIs ====================
+
+/// Runs a byte-offset segmentation query on the document text at a char offset
+fn navigate(offset: i32, query: impl Fn(&str, usize) -> usize) -> i32 {
+    let doc = DOCUMENT.read().unwrap();
+    let text = doc.content.get_text();
+    let byte = text.char_indices().nth(offset.max(0) as usize).map_or(text.len(), |(i, _)| i);
+    let target = query(&text, byte);
+    text[..target].chars().count() as i32
+}
+
+/// Offset after the grapheme cluster at `offset`, so the cursor never lands
+/// inside an accented letter or emoji
+pub fn next_grapheme_offset(offset: i32) -> i32 {
+    navigate(offset, crate::segmentation::next_grapheme_boundary)
+}
+
+/// Offset before the grapheme cluster ending at `offset`
+pub fn previous_grapheme_offset(offset: i32) -> i32 {
+    navigate(offset, crate::segmentation::previous_grapheme_boundary)
+}
+
+/// Start of the next word (Ctrl+Right)
+pub fn next_word_offset(offset: i32) -> i32 {
+    navigate(offset, crate::segmentation::next_word_start)
+}
+
+/// Start of the current or previous word (Ctrl+Left)
+pub fn previous_word_offset(offset: i32) -> i32 {
+    navigate(offset, crate::segmentation::previous_word_start)
+}
+
+/// Selects the word at `offset` as a double click does and returns its range
+pub fn select_word_at(offset: i32) -> (i32, i32) {
+    let start = navigate(offset, |text, byte| crate::segmentation::word_selection_at(text, byte).start);
+    let end = navigate(offset, |text, byte| crate::segmentation::word_selection_at(text, byte).end);
+    set_selection(start, end);
+    (start, end)
+}
+
+use crate::caret_movement::{self, Caret, Movement};
+
+/// The primary caret after the last keyboard move, with its affinity and goal
+/// x, used while the selection's active end is still there
+static CARET: Lazy<Mutex<Option<Caret>>> = Lazy::new(|| Mutex::new(None));
+
+/// Move every selection with the keyboard: `movement` is one of
+/// "next_grapheme", "previous_grapheme", "next_word", "previous_word",
+/// "line_start", "line_end", "line_up", "line_down", "next_paragraph",
+/// "previous_paragraph", "page_up", "page_down", "document_start" or
+/// "document_end"; `extend` keeps each anchor, as Shift does
+/// Moves by line and page need the lines laid out by the viewport layout.
+/// Returns JSON {selections: {selections: [{anchor, active}], primary}, caret:
+/// {offset, affinity, goal_x}} for the primary caret, or "Error: ..."
+pub fn move_caret(movement: String, extend: bool) -> String {
+    let movement: Movement = match serde_json::from_value(serde_json::Value::String(movement)) {
+        Ok(movement) => movement,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let mut remembered = CARET.lock().unwrap();
+    let (selections, caret) = with_viewport_layout(|tree, layout| {
+        let text = tree.get_text();
+        let mut primary = Caret::default();
+        let moved: Vec<Selection> = tree
+            .multi_selection()
+            .ordered()
+            .into_iter()
+            .enumerate()
+            .map(|(i, selection)| {
+                let caret = match *remembered {
+                    Some(caret) if i == 0 && caret.offset == selection.active => caret,
+                    _ => Caret::new(selection.active),
+                };
+                let (selection, caret) = caret_movement::move_selection(&text, layout, selection, caret, movement, extend);
+                if i == 0 {
+                    primary = caret;
+                }
+                selection
+            })
+            .collect();
+        (MultiSelection::from_selections(moved, 0), primary)
+    });
+    *remembered = Some(caret);
+    DOCUMENT.write().unwrap().content.set_multi_selection(&selections);
+    serde_json::to_string(&serde_json::json!({ "selections": selections, "caret": caret }))
+        .unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Editor State APIs ====================
+
+type HistoryListener = Box<dyn Fn(&EditorState) + Send + Sync>;
+
+/// Callbacks told about the editor state restored by undo/redo
+static HISTORY_LISTENERS: Lazy<Mutex<Vec<HistoryListener>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Get the selections, scroll anchor and active table cell as JSON
+pub fn get_editor_state() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.content.editor_state()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Report the editor state (all carets, scroll anchor, active cell) so undo steps capture it
+/// Returns the state as stored, or "JSON error: ..."
+pub fn set_editor_state(state_json: String) -> String {
+    match serde_json::from_str::<EditorState>(&state_json) {
+        Ok(state) => {
+            DOCUMENT.write().unwrap().content.set_editor_state(state);
+            get_editor_state()
+        }
+        Err(e) => format!("JSON error: {}", e),
+    }
+}
+
+/// Register a callback receiving the editor state after each undo or redo, to rehydrate the UI
+/// The callback runs while the document is locked and must not call back into this API
+pub fn on_history_restored(callback: impl Fn(&EditorState) + Send + Sync + 'static) {
+    HISTORY_LISTENERS.lock().unwrap().push(Box::new(callback));
+}
+
+fn notify_history_restored(state: &EditorState) {
+    for listener in HISTORY_LISTENERS.lock().unwrap().iter() {
+        listener(state);
+    }
+}
+
+// ==================== Multiple Selection APIs ====================
+
+fn selections_json(selections: &MultiSelection) -> String {
+    serde_json::to_string(selections).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Get every selection in document order, with the index of the primary one
+/// Returns JSON {selections: [{anchor, active}], primary}
+pub fn get_selections() -> String {
+    selections_json(&DOCUMENT.read().unwrap().content.multi_selection())
+}
+
+/// Add a caret or range, as Ctrl+click or Ctrl+drag does, making it primary;
+/// it merges with any selection it overlaps
+/// Returns the same JSON as `get_selections`
+pub fn add_selection(anchor: usize, active: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let mut selections = doc.content.multi_selection();
+    selections.add(Selection::new(anchor, active));
+    doc.content.set_multi_selection(&selections);
+    selections_json(&selections)
+}
+
+/// Select a block, as Alt+drag does: columns `anchor_column` to
+/// `active_column` of every paragraph from `anchor_paragraph` to
+/// `active_paragraph`, one selection per paragraph
+/// Returns the same JSON as `get_selections`
+pub fn select_columns(anchor_paragraph: usize, anchor_column: usize, active_paragraph: usize, active_column: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let selections = doc
+        .content
+        .column_selection((anchor_paragraph, anchor_column), (active_paragraph, active_column));
+    doc.content.set_multi_selection(&selections);
+    selections_json(&selections)
+}
+
+/// Type `text` at every selection, replacing selected text, as one undo step
+/// Returns the carets afterwards as the same JSON as `get_selections`
+pub fn insert_at_selections(text: String) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    if !doc.allows_edit_at_selections(None) {
+        return format!("Error: {}", FormError::Protected);
+    }
+    let selections = doc.insert_at_selections(&text);
+    doc.track_modification();
+    selections_json(&selections)
+}
+
+/// Delete the selected text, and at each caret the char before ("backward",
+/// as Backspace) or after ("forward", as Delete) it, as one undo step
+/// Returns the carets afterwards as the same JSON as `get_selections`, or "Error: ..."
+pub fn delete_at_selections(direction: String) -> String {
+    let direction: DeleteDirection = match serde_json::from_value(serde_json::Value::String(direction)) {
+        Ok(direction) => direction,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    if !doc.allows_edit_at_selections(Some(direction)) {
+        return format!("Error: {}", FormError::Protected);
+    }
+    let selections = doc.delete_at_selections(direction);
+    doc.track_modification();
+    selections_json(&selections)
+}
+
+// ==================== History Tree APIs ====================
+
+use crate::piece_tree::{HistoryNodeId, HistoryNodeInfo, SavedHistory};
+
+#[derive(Serialize)]
+struct HistoryTree {
+    current: HistoryNodeId,
+    /// Tips of every branch, oldest first
+    branches: Vec<HistoryNodeId>,
+    nodes: Vec<HistoryNodeInfo>,
+}
+
+/// Get the undo history tree as JSON: the current node, branch tips and all nodes
+pub fn get_history_tree() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let tree = HistoryTree {
+        current: doc.content.current_history_node(),
+        branches: doc.content.history_branches(),
+        nodes: doc.content.history_nodes(),
+    };
+    serde_json::to_string(&tree).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Move the document to any node of the history tree, including undone branches
+/// Returns the full text, or "Error: ..." if the node does not exist
+pub fn jump_to_history_node(id: HistoryNodeId) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    if !doc.jump_to_history(id) {
+        return format!("Error: no history node {}", id);
+    }
+    notify_history_restored(&doc.content.editor_state());
+    doc.track_modification();
+    doc.content.get_text()
+}
+
+/// Memory the undo history holds, as JSON {bytes, budget, steps}; `budget` is null when unlimited
+pub fn get_history_memory_usage() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.content.history_memory_usage()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Hold the current document's undo history to `max_bytes`, dropping the oldest steps
+/// past it; 0 lifts the limit. Returns the usage as in get_history_memory_usage
+pub fn set_history_memory_budget(max_bytes: usize) -> String {
+    DOCUMENT.write().unwrap().content.set_history_memory_budget((max_bytes > 0).then_some(max_bytes));
+    get_history_memory_usage()
+}
+
+/// Get the history tree as JSON for storing alongside the document
+pub fn export_history() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.content.save_history()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Restore a history tree from export_history into the current document
+/// The document text must be in the state it had when the history was exported
+pub fn import_history(history_json: String) -> String {
+    let saved = match serde_json::from_str::<SavedHistory>(&history_json) {
+        Ok(saved) => saved,
+        Err(e) => return format!("JSON error: {}", e),
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    if doc.content.restore_history(saved) {
+        doc.anchor_history.clear();
+        drop(doc);
+        get_history_tree()
+    } else {
+        "Error: history is not a single tree containing its current node".to_string()
+    }
+}
+
+// ==================== Edit Location APIs ====================
+
+use crate::ooxml::document_variable;
+
+/// Document variable holding the last edit position between sessions
+const LAST_EDIT_VARIABLE: &str = "VelumLastEditPosition";
+
+/// Move the cursor to an edit location picked by `step`, returning it or -1
+fn go_to_edit(step: fn(&mut EditLocations) -> Option<usize>) -> i32 {
+    let mut doc = DOCUMENT.write().unwrap();
+    match step(&mut doc.edit_locations) {
+        Some(location) => {
+            let location = location.min(doc.content.total_char_count);
+            doc.content.move_selection_to(location);
+            location as i32
+        }
+        None => -1,
+    }
+}
+
+/// Move the cursor back to the previous edit location (Shift+F5)
+/// Returns the new cursor offset, or -1 when there is no older location
+pub fn go_to_previous_edit() -> i32 {
+    go_to_edit(EditLocations::back)
+}
+
+/// Move the cursor forward to a more recent edit location after go_to_previous_edit
+/// Returns the new cursor offset, or -1 when already at the newest
+pub fn go_to_next_edit() -> i32 {
+    go_to_edit(EditLocations::forward)
+}
+
+/// Get the recent edit locations as a JSON array of char offsets, oldest first
+pub fn get_recent_edit_locations() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.edit_locations.locations()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Resume where the user left off in a reopened document
+/// Call after loading the document from `file_data`; moves the cursor to the last
+/// edit position stored in the package. Returns the offset, or -1 when none is stored
+pub fn resume_at_last_edit(file_data: &[u8]) -> i32 {
+    let stored = match document_variable(file_data, LAST_EDIT_VARIABLE) {
+        Ok(stored) => stored.and_then(|value| value.parse::<usize>().ok()),
+        Err(e) => {
+            log::warn!("Could not read the last edit position: {}", e);
+            None
+        }
+    };
+    let Some(position) = stored else {
+        return -1;
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    let position = position.min(doc.content.total_char_count);
+    doc.edit_locations.restore(position);
+    doc.content.move_selection_to(position);
+    position as i32
+}
+
 // ==================== Find and Replace APIs ====================
 
 /// Finds text with options and returns JSON result
@@ -298,7 +631,8 @@ pub fn find_text(query: &str, options_json: &str) -> String {
     doc.content.find_text_json(query, options_json)
 }
 
-/// Replaces text and returns the number of replacements made
+/// Replaces text and returns the number of replacements made; matches the
+/// document's protection keeps from being edited are left alone
 /// # Arguments
 /// * `find` - Text to find
 /// * `replace` - Replacement text
@@ -306,8 +640,32 @@ pub fn find_text(query: &str, options_json: &str) -> String {
 /// # Returns
 /// Number of replacements made
 pub fn replace_text(find: &str, replace: &str, all: bool) -> i32 {
+    let options = SearchOptions {
+        query: find.to_string(),
+        ..Default::default()
+    };
+    if options.query.is_empty() {
+        return 0;
+    }
     let mut doc = DOCUMENT.write().unwrap();
-    doc.content.replace_text_json(find, replace, all)
+    let mut matches = match all {
+        true => doc.content.find_all(&options).results,
+        false => {
+            // The caret is a char offset; the search takes bytes
+            let caret = doc.content.get_selection_active();
+            let from = doc.content.char_to_byte_offset(caret);
+            doc.content.find_next(&options, from).into_iter().collect()
+        }
+    };
+    matches.retain(|found| {
+        let range = doc.content.byte_to_char_offset(found.start)..doc.content.byte_to_char_offset(found.end);
+        doc.allows_edit(range)
+    });
+    let count = doc.replace_matches(&matches, replace);
+    if count > 0 {
+        doc.track_modification();
+    }
+    count as i32
 }
 
 /// Gets the count of matches for a query
@@ -390,44 +748,34 @@ pub fn find_text_legacy(query: String) -> Vec<usize> {
 pub fn replace_first(query: String, replacement: String) -> String {
     let mut doc = DOCUMENT.write().unwrap();
     let text = doc.content.get_text();
-    
-    if let Some(pos) = text.find(&query) {
-        doc.content.delete(pos, query.len());
-        doc.content.insert(pos, replacement);
-        doc.update_metadata();
+    let editable = |pos: usize| {
+        doc.allows_edit(doc.content.byte_to_char_offset(pos)..doc.content.byte_to_char_offset(pos + query.len()))
+    };
+    if let Some(pos) = text.find(&query).filter(|&pos| !query.is_empty() && editable(pos)) {
+        doc.replace_matches(&[SearchResult::new(pos, pos + query.len(), query)], &replacement);
+        doc.track_modification();
     }
-    
     doc.content.get_text()
 }
 
 // 查找并替换所有匹配项 - 旧版兼容函数
 #[deprecated(since = "0.2.0", note = "Use replace_text with all=true instead")]
 pub fn replace_all_legacy(query: String, replacement: String) -> String {
-    let doc_read = DOCUMENT.read().unwrap();
-    let text = doc_read.content.get_text();
-    drop(doc_read);
-    
+    let mut doc = DOCUMENT.write().unwrap();
+    let text = doc.content.get_text();
     if query.is_empty() || !text.contains(&query) {
         return text;
     }
-    
-    // 由于每次替换都会改变文本，我们重新构建文本
-    let mut result = String::new();
-    let mut last_end = 0;
-    
-    while let Some(pos) = text[last_end..].find(&query) {
-        let absolute_pos = last_end + pos;
-        result.push_str(&text[last_end..absolute_pos]);
-        result.push_str(&replacement);
-        last_end = absolute_pos + query.len();
-    }
-    result.push_str(&text[last_end..]);
-    
-    // 替换整个文档内容
-    let mut doc_write = DOCUMENT.write().unwrap();
-    *doc_write = Document::new(result);
-    doc_write.update_metadata();
-    doc_write.content.get_text()
+    let matches: Vec<SearchResult> = text
+        .match_indices(&query)
+        .filter(|(pos, found)| {
+            doc.allows_edit(doc.content.byte_to_char_offset(*pos)..doc.content.byte_to_char_offset(pos + found.len()))
+        })
+        .map(|(pos, found)| SearchResult::new(pos, pos + found.len(), found.to_string()))
+        .collect();
+    doc.replace_matches(&matches, &replacement);
+    doc.track_modification();
+    doc.content.get_text()
 }
 
 // ==================== Document Save/Load APIs ====================
@@ -464,7 +812,7 @@ pub fn load_document_from_json(json: String) -> String {
     match serde_json::from_str::<SerializableDocument>(&json) {
         Ok(serializable) => {
             let mut doc = DOCUMENT.write().unwrap();
-            *doc = Document {
+            doc.replace_with(Document {
                 content: PieceTree::new(serializable.content),
                 metadata: DocumentMetadata {
                     title: serializable.title,
@@ -473,9 +821,12 @@ pub fn load_document_from_json(json: String) -> String {
                     modified_at: serializable.modified_at,
                     word_count: 0,
                     char_count: 0,
+                    ..Default::default()
                 },
-            };
+                ..Document::empty()
+            });
             doc.update_metadata();
+            doc.mark_saved();
             doc.content.get_text()
         }
         Err(e) => format!("Error: {}", e),
@@ -491,8 +842,9 @@ pub fn get_document_as_text() -> String {
 // 从纯文本加载文档
 pub fn load_document_from_text(text: String) -> String {
     let mut doc = DOCUMENT.write().unwrap();
-    *doc = Document::new(text);
+    doc.replace_with(Document::new(text));
     doc.update_metadata();
+    doc.mark_saved();
     doc.content.get_text()
 }
 
@@ -503,7 +855,10 @@ pub fn save_to_file(path: String) -> String {
     }
     
     match fs::write(&path, json) {
-        Ok(_) => format!("Successfully saved to {}", path),
+        Ok(_) => {
+            mark_document_saved();
+            format!("Successfully saved to {}", path)
+        }
         Err(e) => format!("Error saving file: {}", e),
     }
 }
@@ -518,202 +873,193 @@ pub fn load_from_file(path: String) -> String {
 
 // 导出为纯文本文件
 pub fn export_to_txt(path: String) -> String {
-    let text = get_document_as_text();
+    let snapshot = DOCUMENT.read().unwrap().content.snapshot();
+    let text = snapshot.get_text();
     match fs::write(&path, text) {
         Ok(_) => format!("Successfully exported to {}", path),
         Err(e) => format!("Error exporting file: {}", e),
     }
 }
 
+// ==================== Modification Tracking APIs ====================
+
+/// Check whether the document differs from its last saved or loaded state
+/// Undoing back to the saved state makes the document clean again
+pub fn is_document_dirty() -> bool {
+    MODIFICATION.lock().unwrap().is_dirty()
+}
+
+/// Tell the core the host has saved the current document (e.g. after writing exported bytes)
+pub fn mark_document_saved() {
+    DOCUMENT.write().unwrap().mark_saved();
+}
+
+/// Register a callback receiving the new dirty state each time it changes
+/// The callback runs while the document is locked and must not call back into this API
+pub fn on_dirty_changed(callback: impl Fn(bool) + Send + Sync + 'static) {
+    MODIFICATION.lock().unwrap().on_dirty_changed(callback);
+}
+
+// ==================== Document Model APIs ====================
+
+use crate::document_model::{located, DocumentModel};
+
+/// Get the current document as a versioned JSON document model (paragraphs with formatted runs)
+pub fn get_document_model_json() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    doc.to_model().to_json().unwrap_or_else(|e| format!("Error: {}", e))
+}
+
+
+/// Replace the current document with a JSON document model
+/// Tables are kept out of the editor text. Returns the new text, or "Error: ..."
+pub fn load_document_model_json(json: String) -> String {
+    let model = match DocumentModel::from_json(&json) {
+        Ok(model) => model,
+        Err(e) => return format!("Error: {}", e),
+    };
+    load_document_model(model)
+}
+
+/// Replace the current document with a document model; returns the new text
+fn load_document_model(model: DocumentModel) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    doc.replace_with(Document::from_model(&model));
+    doc.mark_saved();
+    doc.content.get_text()
+}
+
+/// Get the formatting of the final paragraph mark and the body-level section
+/// properties, which edits before them never change
+/// Returns JSON with "mark" (run properties or null) and "section"
+pub fn get_document_end() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::json!({
+        "mark": doc.end.mark(),
+        "section": doc.end.section(),
+    })
+    .to_string()
+}
+
+// ==================== Document Sync APIs ====================
+
+use crate::document_sync::DocumentSync;
+
+/// The blocks the UI was last sent, by ID
+static DOCUMENT_SYNC: Lazy<Mutex<DocumentSync>> = Lazy::new(|| Mutex::new(DocumentSync::new()));
+
+/// Get what the UI needs to bring its copy of the document up to date
+/// `known_version` is the version of the last patch or snapshot the UI applied
+/// (0 at start). Returns JSON: `{"type":"patch",...}` with the operations on
+/// the body since that version, or `{"type":"snapshot",...}` with every block
+/// if the UI is at another version
+pub fn get_document_patch(known_version: u64) -> String {
+    let model = DOCUMENT.read().unwrap().to_model();
+    let update = DOCUMENT_SYNC.lock().unwrap().update(&model, known_version);
+    serde_json::to_string(&update).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Get every block of the current document with its ID, as JSON
+pub fn get_document_snapshot() -> String {
+    let model = DOCUMENT.read().unwrap().to_model();
+    let mut sync = DOCUMENT_SYNC.lock().unwrap();
+    sync.diff(&model);
+    serde_json::to_string(&sync.snapshot(&model)).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Markdown APIs ====================
+
+use crate::markdown::{export_markdown, import_markdown, MarkdownFlavor};
+
+/// Replace the current document with Markdown (CommonMark with GitHub extensions)
+/// Tables are kept out of the editor text, as for load_document_model_json.
+/// Returns the new text
+pub fn load_document_from_markdown(markdown: String) -> String {
+    load_document_model(import_markdown(&markdown))
+}
+
+/// Write the current document as Markdown of `flavor`: "gfm" (pipe tables) or
+/// "commonmark" (tables as HTML); an empty flavor is GFM
+/// Returns the Markdown, or "Error: ..." for an unknown flavor
+pub fn export_current_document_markdown(flavor: String) -> String {
+    let flavor = if flavor.trim().is_empty() {
+        MarkdownFlavor::default()
+    } else {
+        match MarkdownFlavor::from_name(&flavor) {
+            Some(flavor) => flavor,
+            None => return format!("Error: Unknown Markdown flavor: {}", flavor),
+        }
+    };
+    let doc = DOCUMENT.read().unwrap();
+    export_markdown(&doc.to_model(), flavor)
+}
+
+/// Convert a .docx file to a JSON document model without loading it into the editor
+pub fn convert_docx_to_model_json(file_data: &[u8]) -> String {
+    match DocumentModel::from_docx(file_data) {
+        Ok(model) => model.to_json().unwrap_or_else(|e| format!("Error: {}", e)),
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
 // ==================== Text Attributes APIs ====================
 
 /// Gets text attributes at the specified offset
 pub fn get_text_attributes_at(offset: usize) -> String {
     let doc = DOCUMENT.read().unwrap();
-    let offset = offset.min(doc.content.total_char_count);
-    
-    // Find the piece at the given offset
-    let mut accumulated_chars = 0usize;
-    for piece in &doc.content.pieces {
-        let piece_start = accumulated_chars;
-        let piece_end = accumulated_chars + piece.piece_char_length;
-        
-        if offset >= piece_start && offset < piece_end {
-            if let Some(attrs) = &piece.attributes {
-                return format!(
-                    "{},{},{},{},{},{},{}",
-                    attrs.bold.map_or("None", |b| if b { "true" } else { "false" }),
-                    attrs.italic.map_or("None", |b| if b { "true" } else { "false" }),
-                    attrs.underline.map_or("None", |b| if b { "true" } else { "false" }),
-                    attrs.font_size.map(|s| s.to_string()).unwrap_or_else(|| "None".to_string()),
-                    attrs.font_family.clone().unwrap_or_else(|| "None".to_string()),
-                    attrs.foreground.clone().unwrap_or_else(|| "None".to_string()),
-                    attrs.background.clone().unwrap_or_else(|| "None".to_string())
-                );
-            }
-            return "None,None,None,None,None,None,None".to_string();
-        }
-        
-        accumulated_chars = piece_end;
+    match doc.content.get_attributes_at(offset) {
+        Some(attrs) => format!(
+            "{},{},{},{},{},{},{}",
+            attrs.bold.map_or("None", |b| if b { "true" } else { "false" }),
+            attrs.italic.map_or("None", |b| if b { "true" } else { "false" }),
+            attrs.underline.map_or("None", |b| if b { "true" } else { "false" }),
+            attrs.font_size.map(|s| s.to_string()).unwrap_or_else(|| "None".to_string()),
+            attrs.font_family.unwrap_or_else(|| "None".to_string()),
+            attrs.foreground.unwrap_or_else(|| "None".to_string()),
+            attrs.background.unwrap_or_else(|| "None".to_string())
+        ),
+        None => "None,None,None,None,None,None,None".to_string(),
     }
-    
-    "None,None,None,None,None,None,None".to_string()
 }
 
-/// Applies text attributes to the specified range
+/// Gets whether each attribute is set on all, none or some of the char range, for the toolbar
+/// Returns JSON like `{"bold": {"state": "all", "value": true}, "font_size": {"state": "mixed"}, ...}`
+pub fn get_common_attributes(start: usize, end: usize) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.content.get_common_attributes(start..end.max(start)))
+        .unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Applies text attributes to the specified char range, keeping fields the JSON leaves unset
+/// The change is one undo step
 pub fn apply_text_attributes(start: usize, end: usize, attributes_json: String) -> String {
-    // Parse the attributes JSON
-    let attributes: Result<TextAttributes, _> = serde_json::from_str(&attributes_json);
-    if attributes.is_err() {
-        return format!("Error: Invalid attributes JSON");
-    }
-    let attrs = attributes.unwrap();
-    
-    let mut doc = DOCUMENT.write().unwrap();
-    let start = start.min(doc.content.total_char_count);
-    let end = end.min(doc.content.total_char_count);
-    
-    if start >= end {
-        return String::new();
-    }
-    
-    // Clone the content to iterate
-    let pieces: Vec<_> = doc.content.pieces.clone();
-    let mut new_pieces = Vec::new();
-    let mut accumulated_chars = 0usize;
-    
-    for piece in pieces {
-        let piece_start = accumulated_chars;
-        let piece_end = accumulated_chars + piece.piece_char_length;
-        
-        if piece_end <= start {
-            // Piece is entirely before the range
-            new_pieces.push(piece);
-        } else if piece_start >= end {
-            // Piece is entirely after the range
-            new_pieces.push(piece);
-        } else {
-            // Piece overlaps with the range - may need to split
-            let range_start = start.max(piece_start);
-            let range_end = end.min(piece_end);
-            
-            // Left part (before range)
-            if range_start > piece_start {
-                let left_piece = Piece::new_with_attrs(
-                    piece.start,
-                    range_start - piece_start,
-                    piece.buffer_id,
-                    range_start - piece_start,
-                    piece.attributes.clone(),
-                );
-                new_pieces.push(left_piece);
-            }
-            
-            // Middle part (with new attributes)
-            let middle_piece = Piece::new_with_attrs(
-                piece.start + (range_start - piece_start),
-                range_end - range_start,
-                piece.buffer_id,
-                range_end - range_start,
-                Some(attrs.clone()),
-            );
-            new_pieces.push(middle_piece);
-            
-            // Right part (after range)
-            if range_end < piece_end {
-                let right_start = piece.start + (range_end - piece_start);
-                let right_length = piece_end - range_end;
-                let right_piece = Piece::new_with_attrs(
-                    right_start,
-                    right_length,
-                    piece.buffer_id,
-                    right_length,
-                    piece.attributes.clone(),
-                );
-                new_pieces.push(right_piece);
-            }
-        }
-        
-        accumulated_chars = piece_end;
-    }
-    
-    doc.content.pieces = new_pieces;
-    doc.update_metadata();
-    doc.content.get_text()
+    let attrs: TextAttributes = match serde_json::from_str(&attributes_json) {
+        Ok(attrs) => attrs,
+        Err(_) => return "Error: Invalid attributes JSON".to_string(),
+    };
+    restyle_range(start, end, |content, range| content.apply_attributes(range, &attrs))
 }
 
-/// Removes text attributes from the specified range
+/// Removes text attributes from the specified char range
+/// The change is one undo step
 pub fn remove_text_attributes(start: usize, end: usize) -> String {
+    restyle_range(start, end, |content, range| content.clear_attributes(range))
+}
+
+fn restyle_range(start: usize, end: usize, restyle: impl FnOnce(&mut PieceTree, std::ops::Range<usize>) -> bool) -> String {
     let mut doc = DOCUMENT.write().unwrap();
     let start = start.min(doc.content.total_char_count);
     let end = end.min(doc.content.total_char_count);
-    
     if start >= end {
         return String::new();
     }
-    
-    // Clone the content to iterate
-    let pieces: Vec<_> = doc.content.pieces.clone();
-    let mut new_pieces = Vec::new();
-    let mut accumulated_chars = 0usize;
-    
-    for piece in pieces {
-        let piece_start = accumulated_chars;
-        let piece_end = accumulated_chars + piece.piece_char_length;
-        
-        if piece_end <= start {
-            // Piece is entirely before the range
-            new_pieces.push(piece);
-        } else if piece_start >= end {
-            // Piece is entirely after the range
-            new_pieces.push(piece);
-        } else {
-            // Piece overlaps with the range - may need to split
-            let range_start = start.max(piece_start);
-            let range_end = end.min(piece_end);
-            
-            // Left part (before range)
-            if range_start > piece_start {
-                let left_piece = Piece::new_with_attrs(
-                    piece.start,
-                    range_start - piece_start,
-                    piece.buffer_id,
-                    range_start - piece_start,
-                    piece.attributes.clone(),
-                );
-                new_pieces.push(left_piece);
-            }
-            
-            // Middle part (without attributes)
-            let middle_piece = Piece::new_with_attrs(
-                piece.start + (range_start - piece_start),
-                range_end - range_start,
-                piece.buffer_id,
-                range_end - range_start,
-                None,
-            );
-            new_pieces.push(middle_piece);
-            
-            // Right part (after range)
-            if range_end < piece_end {
-                let right_start = piece.start + (range_end - piece_start);
-                let right_length = piece_end - range_end;
-                let right_piece = Piece::new_with_attrs(
-                    right_start,
-                    right_length,
-                    piece.buffer_id,
-                    right_length,
-                    piece.attributes.clone(),
-                );
-                new_pieces.push(right_piece);
-            }
-        }
-        
-        accumulated_chars = piece_end;
+    if let Some(error) = doc.protected(start..end) {
+        return error;
     }
-    
-    doc.content.pieces = new_pieces;
-    doc.update_metadata();
+
+    restyle(&mut doc.content, start..end);
+    doc.formatting_changed(start..end);
+    doc.track_modification();
     doc.content.get_text()
 }
 
@@ -746,230 +1092,4032 @@ pub fn get_text_with_attributes() -> String {
     format!("[{}]", result.join(", "))
 }
 
-// ==================== Line Breaking APIs ====================
+// ==================== Paragraph Formatting APIs ====================
 
-use crate::line_layout::LineLayout;
+use crate::piece_tree::ParagraphAttributes;
 
-/// Layouts text and returns JSON layout information
-pub fn layout_text(text: &str, width: f32) -> String {
-    let mut layout = LineLayout::new();
-    layout.layout_to_json(text, width)
+/// Gets the attributes of each paragraph the char range touches; an empty range gives the paragraph at `start`
+/// Returns JSON array of {index, attributes}, with null attributes for unformatted paragraphs
+pub fn get_paragraph_attributes(start: usize, end: usize) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let range = start..end.max(start);
+    let paragraphs: Vec<serde_json::Value> = doc
+        .content
+        .paragraphs_in(range.clone())
+        .zip(doc.content.paragraph_attributes_in(range))
+        .map(|(index, attributes)| serde_json::json!({ "index": index, "attributes": attributes }))
+        .collect();
+    serde_json::Value::Array(paragraphs).to_string()
 }
 
-/// Calculates the width of text in abstract units
-pub fn calculate_text_width(text: &str) -> f32 {
-    let mut layout = LineLayout::new();
-    layout.breaker_mut().calculate_text_width(text)
+/// Applies paragraph attributes to every paragraph the char range touches, keeping fields the JSON leaves unset
+/// The change is one undo step
+pub fn apply_paragraph_attributes(start: usize, end: usize, attributes_json: String) -> String {
+    let attrs: ParagraphAttributes = match serde_json::from_str(&attributes_json) {
+        Ok(attrs) => attrs,
+        Err(_) => return "Error: Invalid paragraph attributes JSON".to_string(),
+    };
+    reformat_paragraphs(start, end, |content, range| content.apply_paragraph_attributes(range, &attrs))
 }
 
-/// Gets the number of lines needed for text at given width
-pub fn get_line_count_for_width(text: &str, width: f32) -> usize {
+/// Removes the attributes of every paragraph the char range touches
+/// The change is one undo step
+pub fn remove_paragraph_attributes(start: usize, end: usize) -> String {
+    reformat_paragraphs(start, end, |content, range| content.clear_paragraph_attributes(range))
+}
+
+fn reformat_paragraphs(
+    start: usize,
+    end: usize,
+    reformat: impl FnOnce(&mut PieceTree, std::ops::Range<usize>) -> bool,
+) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let start = start.min(doc.content.total_char_count);
+    let end = end.clamp(start, doc.content.total_char_count);
+    if let Some(error) = doc.protected(start..end) {
+        return error;
+    }
+
+    if reformat(&mut doc.content, start..end) {
+        doc.formatting_changed(start..end);
+        doc.track_modification();
+    }
+    doc.content.get_text()
+}
+
+// ==================== Paste APIs ====================
+
+use crate::html_import::import_html;
+use crate::paste::{paste_into_tree, running_paragraphs, PastePolicy};
+
+/// Pastes the body of a JSON document model at a char offset as one undo step
+/// `policy` is "merge" (continue the list at the caret), "keep_source" or "text_only"
+/// Returns the paste report JSON, or "Error: ..."
+pub fn paste_document_fragment(offset: usize, fragment_json: String, policy: String) -> String {
+    let Some(policy) = PastePolicy::from_name(&policy) else {
+        return format!("Error: Unknown paste policy '{}'", policy);
+    };
+    let fragment = match DocumentModel::from_json(&fragment_json) {
+        Ok(model) => model.body,
+        Err(e) => return format!("Error: {}", e),
+    };
+
+    let mut doc = DOCUMENT.write().unwrap();
+    let offset = offset.min(doc.content.total_char_count);
+    if let Some(error) = doc.protected(offset..offset) {
+        return error;
+    }
+    let length = doc.content.total_char_count;
+    doc.remember_anchors();
+    let report = paste_into_tree(&mut doc.content, offset, &fragment, policy);
+    let inserted = doc.content.total_char_count - length;
+    fragment_inserted(&mut doc, offset, inserted);
+    serde_json::to_string(&report).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Pastes an HTML fragment, as a browser puts it on the clipboard, at a char offset as one undo step
+/// Text keeps its formatting, paragraphs their alignment, indents and spacing, lists become
+/// lists of the document and tables tab-separated rows; links are kept unless `policy` is "text_only"
+/// Returns the paste report JSON, or "Error: ..."
+pub fn paste_html(offset: usize, html: String, policy: String) -> String {
+    let Some(policy) = PastePolicy::from_name(&policy) else {
+        return format!("Error: Unknown paste policy '{}'", policy);
+    };
+
+    let mut doc = DOCUMENT.write().unwrap();
+    let offset = offset.min(doc.content.total_char_count);
+    if let Some(error) = doc.protected(offset..offset) {
+        return error;
+    }
+    let fragment = match policy {
+        PastePolicy::TextOnly => import_html(&html, &mut ListNumbering::new()),
+        _ => import_html(&html, &mut doc.numbering),
+    };
+    let length = doc.content.total_char_count;
+    doc.remember_anchors();
+    let report = paste_into_tree(&mut doc.content, offset, &fragment, policy);
+    let inserted = doc.content.total_char_count - length;
+    fragment_inserted(&mut doc, offset, inserted);
+
+    if policy != PastePolicy::TextOnly {
+        let links = HyperlinkSet::from_paragraphs(located(&running_paragraphs(&fragment)));
+        for link in links.hyperlinks() {
+            let range = offset + link.start..offset + link.start + link.length;
+            let _ = doc.hyperlinks.insert(range, link.url.as_deref(), link.anchor.as_deref(), None);
+        }
+        doc.track_modification();
+    }
+    serde_json::to_string(&report).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Bring the rest of the document up to date with `inserted` chars put in at `offset`
+fn fragment_inserted(doc: &mut Document, offset: usize, inserted: usize) {
+    if inserted == 0 {
+        return;
+    }
+    doc.text_inserted(offset, inserted);
+    doc.track_modification();
+}
+
+// ==================== Snippet APIs ====================
+
+use std::collections::HashMap;
+
+use crate::snippets::SnippetLibrary;
+
+/// Snippets are the user's, so they outlive the documents they are inserted into
+static SNIPPETS: Lazy<RwLock<SnippetLibrary>> = Lazy::new(|| RwLock::new(SnippetLibrary::new()));
+
+/// Store the body of a JSON document model as a snippet, replacing one of the same name
+/// Returns the snippet's variable names as a JSON array, or "Error: ..."
+pub fn add_snippet(name: String, fragment_json: String) -> String {
+    let fragment = match DocumentModel::from_json(&fragment_json) {
+        Ok(model) => model.body,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let mut snippets = SNIPPETS.write().unwrap();
+    match snippets.add(&name, fragment) {
+        Ok(snippet) => serde_json::to_string(&snippet.variables()).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// Remove a snippet; returns whether it existed
+pub fn remove_snippet(name: String) -> bool {
+    SNIPPETS.write().unwrap().remove(&name).is_some()
+}
+
+/// List the snippets in name order as JSON [{name, variables}]
+pub fn list_snippets() -> String {
+    let snippets = SNIPPETS.read().unwrap();
+    let list: Vec<serde_json::Value> = snippets
+        .snippets()
+        .map(|snippet| serde_json::json!({ "name": snippet.name, "variables": snippet.variables() }))
+        .collect();
+    serde_json::to_string(&list).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Insert a snippet at a char offset as one undo step, filling in `variables_json` ({"name": "value"})
+/// Returns {range, tab_stops: [{name, range}], cursor} as JSON, or "Error: ..."
+/// Tab stops are the placeholders left without a value, for the UI to step through
+pub fn insert_snippet(offset: usize, name: String, variables_json: String) -> String {
+    let variables: HashMap<String, String> = match serde_json::from_str(&variables_json) {
+        Ok(variables) => variables,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let snippets = SNIPPETS.read().unwrap();
+    let mut doc = DOCUMENT.write().unwrap();
+    if let Some(error) = doc.protected(offset..offset) {
+        return error;
+    }
+    doc.remember_anchors();
+    let insertion = match snippets.insert(&mut doc.content, offset, &name, &variables) {
+        Ok(insertion) => insertion,
+        Err(e) => return format!("Error: {}", e),
+    };
+    fragment_inserted(&mut doc, insertion.range.start, insertion.range.len());
+    serde_json::to_string(&insertion).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Get the whole snippet library as JSON, for the host to persist
+pub fn export_snippets() -> String {
+    SNIPPETS.read().unwrap().to_json().unwrap_or_else(|e| format!("Error: {}", e))
+}
+
+/// Replace the snippet library with one saved by export_snippets
+/// Returns the snippet list as in list_snippets, or "Error: ..."
+pub fn import_snippets(json: String) -> String {
+    match SnippetLibrary::from_json(&json) {
+        Ok(library) => *SNIPPETS.write().unwrap() = library,
+        Err(e) => return format!("Error: {}", e),
+    }
+    list_snippets()
+}
+
+// ==================== Style Sheet APIs ====================
+
+use crate::style_sheet::StyleSheetError;
+
+/// Gets the document's named styles as a JSON array, in style ID order
+pub fn get_document_styles() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let styles: Vec<_> = doc.styles.styles().collect();
+    serde_json::to_string(&styles).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Gets the paragraph and run formatting a style resolves to, with everything it inherits
+/// `style` is a style ID or name; returns JSON {paragraph, run}, or "Error: ..."
+pub fn get_resolved_style(style: String) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    match doc.styles.find(&style) {
+        Some(named) => serde_json::to_string(&doc.styles.resolve(&named.id))
+            .unwrap_or_else(|e| format!("JSON error: {}", e)),
+        None => format!("Error: {}", StyleSheetError::UnknownStyle(style)),
+    }
+}
+
+/// Gives every paragraph the char range touches a paragraph style, e.g. "Heading 1"
+/// The change is one undo step. Returns the text, or "Error: ..."
+pub fn apply_paragraph_style(start: usize, end: usize, style: String) -> String {
+    let styles = DOCUMENT.read().unwrap().styles.clone();
+    let mut error = None;
+    let text = reformat_paragraphs(start, end, |content, range| {
+        styles
+            .apply_paragraph_style(content, range, &style)
+            .unwrap_or_else(|e| {
+                error = Some(e);
+                false
+            })
+    });
+    error.map_or(text, |e| format!("Error: {}", e))
+}
+
+/// Gives the char range the formatting of a character style
+/// The change is one undo step. Returns the text, or "Error: ..."
+pub fn apply_character_style(start: usize, end: usize, style: String) -> String {
+    let styles = DOCUMENT.read().unwrap().styles.clone();
+    let mut error = None;
+    let text = restyle_range(start, end, |content, range| {
+        styles
+            .apply_character_style(content, range, &style)
+            .unwrap_or_else(|e| {
+                error = Some(e);
+                false
+            })
+    });
+    error.map_or(text, |e| format!("Error: {}", e))
+}
+
+// ==================== Paragraph Split and Merge APIs ====================
+
+/// Presses Enter at a char offset: splits its paragraph, with both halves
+/// keeping its formatting and a heading's next style starting the new one at
+/// its end; in an empty list item, moves it up a level or out of the list
+/// The change is one undo step. Returns JSON {kind, paragraph, caret}, kind
+/// being "split", "outdented" or "left_list"
+pub fn split_paragraph(position: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    if let Some(error) = doc.protected(position..position) {
+        return error;
+    }
+    let split = doc.split_paragraph(position);
+    doc.track_modification();
+    serde_json::to_string(&split).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Deletes the paragraph mark after paragraph `paragraph`, joining it to the
+/// next; the result keeps the first paragraph's formatting, or the second's
+/// if the first is empty. While tracking changes the mark is only marked deleted
+/// The change is one undo step. Returns the text, or "Error: ..."
+pub fn merge_with_next(paragraph: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let mark = doc.content.get_offset_at_line(paragraph + 1);
+    if let Some(error) = doc.protected(mark.saturating_sub(1)..mark) {
+        return error;
+    }
+    if doc.merge_with_next(paragraph).is_none() {
+        return format!("Error: Paragraph {} has no paragraph after it", paragraph);
+    }
+    doc.track_modification();
+    doc.content.get_text()
+}
+
+// ==================== Revision APIs ====================
+
+use crate::revisions::{Resolution, RevisionError};
+use crate::track_changes::revision_marks;
+
+/// Pending tracked changes as a JSON array, with char offsets into the document text
+pub fn get_revisions() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(doc.revisions.revisions()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Resolve revisions, then report the ones still pending
+fn resolve_revisions(
+    resolve: impl FnOnce(&mut RevisionSet, &mut PieceTree) -> Result<Vec<Resolution>, RevisionError>,
+) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let resolutions = match doc.resolve_revisions(resolve) {
+        Ok(resolutions) => resolutions,
+        Err(e) => return format!("Error: {}", e),
+    };
+    if resolutions.iter().any(|resolution| *resolution != Resolution::Unchanged) {
+        doc.track_modification();
+    }
+    serde_json::to_string(doc.revisions.revisions()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Turn recording of edits as tracked changes on or off
+pub fn set_track_changes(enabled: bool) {
+    DOCUMENT.write().unwrap().track_changes.set_enabled(enabled);
+}
+
+/// Whether edits are recorded as tracked changes
+pub fn is_tracking_changes() -> bool {
+    DOCUMENT.read().unwrap().track_changes.is_enabled()
+}
+
+/// Set the author recorded with new tracked changes
+pub fn set_revision_author(author: String) {
+    DOCUMENT.write().unwrap().track_changes.set_author(author);
+}
+
+/// Underline and strikethrough spans of the pending insertions and deletions as JSON
+/// Each has {id, range, style, author, author_index}; author_index picks a color per author
+pub fn get_revision_marks() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&revision_marks(&doc.revisions)).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Accept a tracked change: insertions and format changes stay, deleted text is removed
+/// Returns the pending revisions as JSON, or "Error: ..." for an unknown id
+pub fn accept_revision(id: String) -> String {
+    resolve_revisions(|revisions, content| revisions.accept(content, &id).map(|resolution| vec![resolution]))
+}
+
+/// Reject a tracked change: inserted text is removed, deleted text and old formatting come back
+/// Returns the pending revisions as JSON, or "Error: ..." for an unknown id
+pub fn reject_revision(id: String) -> String {
+    resolve_revisions(|revisions, content| revisions.reject(content, &id).map(|resolution| vec![resolution]))
+}
+
+/// Accept every tracked change as one undo step
+pub fn accept_all_revisions() -> String {
+    resolve_revisions(|revisions, content| Ok(revisions.accept_all(content)))
+}
+
+/// Reject every tracked change as one undo step
+pub fn reject_all_revisions() -> String {
+    resolve_revisions(|revisions, content| Ok(revisions.reject_all(content)))
+}
+
+// ==================== Comment APIs ====================
+
+use crate::comments::CommentError;
+
+/// Get the comment threads in text order as JSON
+/// Each is {comment, replies}; comments have id, author, date, paragraphs, done, start and length
+pub fn get_comments() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.comments.threads()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Get the comment threads anchored at a char offset as JSON, e.g. for the cursor position
+pub fn get_comments_at(offset: usize) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.comments.threads_at(offset)).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Comment on chars [start, end) as the revision author
+/// Returns the new comment's ID
+pub fn add_comment(start: usize, end: usize, text: String) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let length = doc.content.total_char_count;
+    let range = start.min(length)..end.clamp(start, length);
+    let id = doc.change_anchors(|doc| {
+        let Document { comments, track_changes, .. } = doc;
+        comments.add(range, track_changes.author(), &text)
+    });
+    doc.track_modification();
+    id
+}
+
+/// Run a change to the comments, then report the threads
+fn change_comments(change: impl FnOnce(&mut CommentManager, &str) -> Result<(), CommentError>) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let changed = doc.try_change_anchors(|doc| {
+        let Document { comments, track_changes, .. } = doc;
+        change(comments, track_changes.author())
+    });
+    if let Err(e) = changed {
+        return format!("Error: {}", e);
+    }
+    doc.track_modification();
+    serde_json::to_string(&doc.comments.threads()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Reply to a comment as the revision author
+/// Returns the threads as JSON, or "Error: ..." for an unknown id
+pub fn reply_to_comment(id: String, text: String) -> String {
+    change_comments(|comments, author| comments.reply(&id, author, &text).map(|_| ()))
+}
+
+/// Mark a comment's thread done, or open it again
+/// Returns the threads as JSON, or "Error: ..." for an unknown id
+pub fn resolve_comment(id: String, done: bool) -> String {
+    change_comments(|comments, _| comments.set_resolved(&id, done))
+}
+
+/// Delete a comment; deleting the first comment of a thread deletes its replies
+/// Returns the threads as JSON, or "Error: ..." for an unknown id
+pub fn delete_comment(id: String) -> String {
+    change_comments(|comments, _| comments.delete(&id).map(|_| ()))
+}
+
+// ==================== Bookmark APIs ====================
+
+use crate::bookmarks::{update_cross_references, Bookmark};
+use crate::ooxml::Field;
+
+fn bookmarks_json(bookmarks: &[&Bookmark]) -> String {
+    serde_json::to_string(bookmarks).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Bookmarks in text order as a JSON array of {id, name, start, length}
+/// Hidden bookmarks (names starting with "_", such as Word's "_Toc" ones) only if asked for
+pub fn get_bookmarks(include_hidden: bool) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    bookmarks_json(&doc.bookmarks.sorted(include_hidden))
+}
+
+/// Bookmarks whose range contains the char offset, as a JSON array
+pub fn get_bookmarks_at(offset: usize) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    bookmarks_json(&doc.bookmarks.at(offset))
+}
+
+/// Bookmark chars [start, end) as `name`, moving an existing bookmark of that name
+/// Returns the bookmark as JSON, or "Error: ..." for a name Word would not accept
+pub fn insert_bookmark(name: String, start: usize, end: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let length = doc.content.total_char_count;
+    let range = start.min(length)..end.clamp(start.min(length), length);
+    let inserted = doc.try_change_anchors(|doc| {
+        doc.bookmarks
+            .insert(&name, range)
+            .map(|bookmark| serde_json::to_string(bookmark).unwrap_or_else(|e| format!("JSON error: {}", e)))
+    });
+    let bookmark = match inserted {
+        Ok(bookmark) => bookmark,
+        Err(e) => return format!("Error: {}", e),
+    };
+    doc.track_modification();
+    bookmark
+}
+
+/// Remove a bookmark; the text stays. Returns the remaining bookmarks as in get_bookmarks(true), or "Error: ..."
+pub fn delete_bookmark(name: String) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    if let Err(e) = doc.try_change_anchors(|doc| doc.bookmarks.delete(&name)) {
+        return format!("Error: {}", e);
+    }
+    doc.track_modification();
+    bookmarks_json(&doc.bookmarks.sorted(true))
+}
+
+/// Select the text of a bookmark, returning its start offset, or -1 if there is no such bookmark
+pub fn go_to_bookmark(name: String) -> i32 {
+    let mut doc = DOCUMENT.write().unwrap();
+    let Some(range) = doc.bookmarks.get(&name).map(Bookmark::range) else {
+        return -1;
+    };
+    doc.content.set_selection(range.start, range.end);
+    range.start as i32
+}
+
+/// What a REF, PAGEREF or NOTEREF field instruction shows in the current document,
+/// e.g. "REF Results \h" gives the text bookmarked as "Results"
+/// PAGEREF uses the latest pagination; returns "" when the field is not a
+/// cross-reference or its value is not known yet
+pub fn resolve_cross_reference(instruction: String) -> String {
+    let pages = REPAGINATOR.status();
+    let doc = DOCUMENT.read().unwrap();
+    let field = Field::new(&instruction, 0, "");
+    // The editor text holds no note reference marks
+    doc.bookmarks
+        .resolve_reference(&field, &doc.content.get_text(), &[], |offset| pages.page_of(offset))
+        .unwrap_or_default()
+}
+
+/// Recompute the REF and NOTEREF fields of a document model from its bookmarks
+/// PAGEREF fields keep their results, as a model is not paginated
+/// Returns `{"updated": n, "model": ...}`, or "Error: ..."
+pub fn update_cross_references_in_model_json(model_json: String) -> String {
+    let mut model = match DocumentModel::from_json(&model_json) {
+        Ok(model) => model,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let updated = update_cross_references(&mut model, |_| None);
+    serde_json::json!({ "updated": updated, "model": model }).to_string()
+}
+
+// ==================== Hyperlink APIs ====================
+
+use crate::bookmarks::BookmarkError;
+use crate::ooxml::Hyperlink;
+
+fn hyperlink_json(hyperlink: Option<&Hyperlink>) -> String {
+    serde_json::to_string(&hyperlink).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Hyperlinks in text order as a JSON array of {url, anchor, tooltip, visited, start, length}
+pub fn get_hyperlinks() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(doc.hyperlinks.hyperlinks()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// The hyperlink over the char at `offset` as JSON, or "null"
+pub fn get_hyperlink_at(offset: usize) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    hyperlink_json(doc.hyperlinks.at(offset))
+}
+
+/// Empty strings mean "not given"; an anchor must name an existing bookmark
+fn hyperlink_target<'a>(doc: &Document, url: &'a str, anchor: &'a str, tooltip: &'a str) -> Result<[Option<&'a str>; 3], String> {
+    let given = |value: &'a str| Some(value).filter(|value| !value.trim().is_empty());
+    if let Some(anchor) = given(anchor) {
+        if doc.bookmarks.get(anchor.trim()).is_none() {
+            return Err(format!("Error: {}", BookmarkError::NotFound(anchor.trim().to_string())));
+        }
+    }
+    Ok([given(url), given(anchor), given(tooltip)])
+}
+
+/// Link chars [start, end) to a URL and/or a bookmark, replacing links already there
+/// Returns the new hyperlink as JSON, or "Error: ..."
+pub fn insert_hyperlink(start: usize, end: usize, url: String, anchor: String, tooltip: String) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let [url, anchor, tooltip] = match hyperlink_target(&doc, &url, &anchor, &tooltip) {
+        Ok(target) => target,
+        Err(e) => return e,
+    };
+    let length = doc.content.total_char_count;
+    let range = start.min(length)..end.clamp(start.min(length), length);
+    let linked = doc.try_change_anchors(|doc| doc.hyperlinks.insert(range, url, anchor, tooltip).map(|link| hyperlink_json(Some(link))));
+    let hyperlink = match linked {
+        Ok(hyperlink) => hyperlink,
+        Err(e) => return format!("Error: {}", e),
+    };
+    doc.track_modification();
+    hyperlink
+}
+
+/// Change where the hyperlink at `offset` goes and its tooltip
+/// Returns the hyperlink as JSON, or "Error: ..."
+pub fn edit_hyperlink(offset: usize, url: String, anchor: String, tooltip: String) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let [url, anchor, tooltip] = match hyperlink_target(&doc, &url, &anchor, &tooltip) {
+        Ok(target) => target,
+        Err(e) => return e,
+    };
+    let linked = doc.try_change_anchors(|doc| doc.hyperlinks.edit(offset, url, anchor, tooltip).map(|link| hyperlink_json(Some(link))));
+    let hyperlink = match linked {
+        Ok(hyperlink) => hyperlink,
+        Err(e) => return format!("Error: {}", e),
+    };
+    doc.track_modification();
+    hyperlink
+}
+
+/// Unlink chars [start, end), keeping the text; with start == end the whole
+/// link at that offset goes. Returns the remaining hyperlinks as in get_hyperlinks
+pub fn remove_hyperlink(start: usize, end: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    doc.change_anchors(|doc| {
+        if start < end {
+            doc.hyperlinks.clear(start..end);
+        } else {
+            doc.hyperlinks.remove_at(start);
+        }
+    });
+    doc.track_modification();
+    serde_json::to_string(doc.hyperlinks.hyperlinks()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Follow the hyperlink at `offset`: it is marked visited, and a link to a
+/// bookmark selects the bookmarked text. Returns the hyperlink as JSON, so
+/// the UI can open its URL, or "null" if there is none
+pub fn follow_hyperlink(offset: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    if !doc.hyperlinks.mark_visited(offset) {
+        return hyperlink_json(None);
+    }
+    let anchor = doc.hyperlinks.at(offset).and_then(|hyperlink| hyperlink.anchor.clone());
+    if let Some(range) = anchor.and_then(|anchor| doc.bookmarks.get(&anchor).map(Bookmark::range)) {
+        doc.content.set_selection(range.start, range.end);
+    }
+    hyperlink_json(doc.hyperlinks.at(offset))
+}
+
+// ==================== Numbering APIs ====================
+
+use crate::line_layout::ParagraphProperties as LayoutProperties;
+use crate::numbering::{ListEdit, OutlineScheme};
+
+fn effective_paragraphs(doc: &Document) -> Vec<ParagraphAttributes> {
+    (0..doc.content.paragraph_count())
+        .map(|index| doc.styles.effective_paragraph(doc.content.paragraph_attributes(index)))
+        .collect()
+}
+
+/// List labels of the document's paragraphs, e.g. "1.2" or "Section 1.01", in order
+fn list_labels(doc: &Document) -> Vec<Option<String>> {
+    doc.numbering.labels(&effective_paragraphs(doc))
+}
+
+/// Layout properties of every paragraph: its style and direct formatting, and
+/// for list paragraphs the indents of their list level
+fn paragraph_layout_properties(doc: &Document) -> Vec<LayoutProperties> {
+    let paragraphs = effective_paragraphs(doc);
+    doc.numbering
+        .resolve(&paragraphs)
+        .iter()
+        .zip(&paragraphs)
+        .map(|(label, paragraph)| match label {
+            Some(label) => label.layout_properties(paragraph),
+            None => paragraph.layout_properties(),
+        })
+        .collect()
+}
+
+/// Number the Heading 1–9 styles: "decimal" (1, 1.1, 1.1.1) or "legal"
+/// (Article I., Section 1.01, ...); "none" stops numbering them
+/// Returns the list labels as get_list_labels does, or "Error: ..."
+pub fn set_heading_numbering(scheme: String) -> String {
+    let scheme = match scheme.trim() {
+        "" | "none" => None,
+        name => match OutlineScheme::from_name(name) {
+            Some(scheme) => Some(scheme),
+            None => return format!("Error: Unknown numbering scheme '{}'", name),
+        },
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    let Document { numbering, styles, .. } = &mut *doc;
+    numbering.set_heading_numbering(styles, scheme);
+    doc.track_modification();
+    serde_json::to_string(&list_labels(&doc)).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// The list label of each paragraph as a JSON array, null for paragraphs not in a list
+/// Labels are recomputed on every call, so they follow inserted, deleted and restyled paragraphs
+pub fn get_list_labels() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&list_labels(&doc)).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// The rendered list label of each paragraph as a JSON array, null for paragraphs not in a list
+/// Each label has its text, suffix ("tab", "space" or "nothing"), whether it is a bullet,
+/// the level's indents in twips and the label's run properties
+pub fn get_list_label_details() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let labels = doc.numbering.resolve(&effective_paragraphs(&doc));
+    serde_json::to_string(&labels).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Edits the list of the paragraphs the char range touches, as one undo step
+/// `operation` is "bullet" or "numbered" (toggle the list), "to_bullet" or
+/// "to_numbered" (convert keeping levels), "indent", "outdent", "restart" or "continue"
+/// Returns the list labels as get_list_labels does, or "Error: ..."
+pub fn edit_list(start: usize, end: usize, operation: String) -> String {
+    let Some(edit) = ListEdit::from_name(operation.trim()) else {
+        return format!("Error: Unknown list operation '{}'", operation);
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    let start = start.min(doc.content.total_char_count);
+    let end = end.clamp(start, doc.content.total_char_count);
+    if let Some(error) = doc.protected(start..end) {
+        return error;
+    }
+    let paragraphs: Vec<_> =
+        (0..doc.content.paragraph_count()).map(|index| doc.content.paragraph_attributes(index).cloned()).collect();
+    let range = doc.content.paragraphs_in(start..end);
+
+    let Document { numbering, styles, .. } = &mut *doc;
+    let after = match numbering.edit_paragraphs(edit, &paragraphs, range, styles) {
+        Ok(after) => after,
+        Err(e) => return format!("Error: {}", e),
+    };
+    // Restarting and continuing renumber paragraphs past the range too
+    let total = doc.content.total_char_count;
+    if doc.content.reformat_each_paragraph(0..total, |index, _| after[index].clone()) {
+        doc.formatting_changed(0..total);
+        doc.track_modification();
+    }
+    serde_json::to_string(&list_labels(&doc)).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== AutoFormat APIs ====================
+
+/// Get which markup typing converts, as JSON
+pub fn get_autoformat_options() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.autoformat).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Set which markup typing converts
+/// `options_json` is e.g. {"lists": true, "borders": true, "tables": false, "markdown_headings": true};
+/// missing fields take their defaults. Returns the options as JSON, or "Error: ..."
+pub fn set_autoformat_options(options_json: String) -> String {
+    let options: AutoFormatOptions = match serde_json::from_str(&options_json) {
+        Ok(options) => options,
+        Err(e) => return format!("Error: Invalid AutoFormat options: {}", e),
+    };
+    DOCUMENT.write().unwrap().autoformat = options;
+    get_autoformat_options()
+}
+
+/// Convert the markup just typed before `caret`: list markers, rules, table patterns, headings
+/// Call after inserting typed text. Returns the conversion as JSON, "null" if there was none.
+/// A table conversion only removes the pattern; the caller builds the table from the column widths.
+pub fn autoformat_as_you_type(caret: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    if let Some(error) = doc.protected(typed_in_paragraph(&doc, caret)) {
+        return error;
+    }
+    let Some(result) = doc.autoformat_as_you_type(caret) else {
+        return "null".to_string();
+    };
+    doc.track_modification();
+    serde_json::to_string(&result).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// The chars typed before `caret` in its paragraph, which converting what was typed rewrites
+fn typed_in_paragraph(doc: &Document, caret: usize) -> Range<usize> {
+    let caret = caret.min(doc.content.total_char_count);
+    doc.content.get_offset_at_line(doc.content.get_line_at_offset(caret)).min(caret)..caret
+}
+
+// ==================== Math APIs ====================
+
+use crate::math::{self, MathNode};
+use crate::ooxml::MathZone;
+
+/// A math zone with its equation: the linear text, the tree built from it and its OMML
+#[derive(Serialize)]
+struct MathZoneInfo {
+    start: usize,
+    length: usize,
+    linear: String,
+    tree: Vec<MathNode>,
+    omml: String,
+}
+
+fn math_zone_info(text: &str, zone: &MathZone) -> MathZoneInfo {
+    let linear: String = text.chars().skip(zone.start).take(zone.length).collect();
+    let tree = math::parse_linear(&linear);
+    MathZoneInfo {
+        start: zone.start,
+        length: zone.length,
+        omml: math::to_omml(&tree),
+        linear,
+        tree,
+    }
+}
+
+/// Math zones in text order as a JSON array of {start, length, linear, tree, omml}
+pub fn get_math_zones() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let text = doc.content.get_text();
+    let zones: Vec<MathZoneInfo> = doc.math_zones.zones().iter().map(|zone| math_zone_info(&text, zone)).collect();
+    serde_json::to_string(&zones).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// The math zone the caret at `offset` types into, as in get_math_zones, or "null"
+pub fn get_math_zone_at(offset: usize) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let zone = doc.math_zones.at(offset).map(|zone| math_zone_info(&doc.content.get_text(), zone));
+    serde_json::to_string(&zone).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Make chars start..end an equation, or turn the equations in it back into text
+/// An empty range makes an empty equation to type into. Returns the zones as in get_math_zones
+pub fn toggle_math_zone(start: usize, end: usize) -> String {
+    {
+        let mut doc = DOCUMENT.write().unwrap();
+        let total = doc.content.total_char_count;
+        let (start, end) = (start.min(total), end.min(total));
+        doc.change_anchors(|doc| doc.math_zones.toggle(start.min(end)..end.max(start)));
+        doc.track_modification();
+    }
+    get_math_zones()
+}
+
+/// Math AutoCorrect for the char just typed before `caret`: a control word such as \alpha
+/// ending in a space or operator becomes its symbol. Call after inserting typed text.
+/// Returns {replaced: {start, end}, replacement, caret} as JSON, "null" if nothing changed
+pub fn math_input_as_you_type(caret: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    if let Some(error) = doc.protected(typed_in_paragraph(&doc, caret)) {
+        return error;
+    }
+    doc.remember_anchors();
+    let Document { content, math_zones, .. } = &mut *doc;
+    let Some(correction) = math::correct_as_you_type(content, math_zones, caret) else {
+        return "null".to_string();
+    };
+    let (offset, removed) = (correction.replaced.start, correction.replaced.len());
+    let inserted = correction.replacement.chars().count();
+    doc.text_edited(offset, removed, inserted);
+    doc.track_modification();
+    serde_json::to_string(&correction).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Font Substitution APIs ====================
+
+use crate::font_substitution::{FontScope, FontSubstitution, FontSubstitutionReport};
+
+fn font_substitution(old_family: String, new_family: String, size_scale: Option<f32>) -> FontSubstitution {
+    let substitution = FontSubstitution::new(old_family, new_family);
+    match size_scale {
+        Some(scale) => substitution.with_size_scale(scale),
+        None => substitution,
+    }
+}
+
+/// Replace a font family in the editor content's direct formatting as one undo step
+/// `scope_json` is `"document"` or `{"selection": {"start": 0, "end": 10}}`
+/// Returns the report JSON, or "JSON error: ..."
+pub fn replace_font(old_family: String, new_family: String, scope_json: String, size_scale: Option<f32>) -> String {
+    let scope: FontScope = match serde_json::from_str(&scope_json) {
+        Ok(scope) => scope,
+        Err(e) => return format!("JSON error: {}", e),
+    };
+    let substitution = font_substitution(old_family, new_family, size_scale);
+
+    let mut doc = DOCUMENT.write().unwrap();
+    let range = match &scope {
+        FontScope::Document => 0..doc.content.total_char_count,
+        FontScope::Selection(range) => range.clone(),
+    };
+    if let Some(error) = doc.protected(range) {
+        return error;
+    }
+    let runs = substitution.apply_to_tree(&mut doc.content, &scope);
+    if runs > 0 {
+        doc.paragraph_hashes.invalidate();
+        doc.update_metadata();
+        doc.track_modification();
+    }
+    // The editor content carries no style definitions or theme
+    let report = FontSubstitutionReport { runs, ..Default::default() };
+    serde_json::to_string(&report).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Replace a font family in a document model's runs, styles and theme
+/// Returns `{"report": ..., "model": ...}`, or "Error: ..."
+pub fn replace_font_in_model_json(
+    model_json: String,
+    old_family: String,
+    new_family: String,
+    size_scale: Option<f32>,
+) -> String {
+    let mut model = match DocumentModel::from_json(&model_json) {
+        Ok(model) => model,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let report = font_substitution(old_family, new_family, size_scale).apply_to_model(&mut model);
+    serde_json::json!({ "report": report, "model": model }).to_string()
+}
+
+// ==================== Style Organizer APIs ====================
+
+use crate::ooxml::{import_styles as import_package_styles, list_styles, StyleConflictPolicy};
+
+/// List the styles a .docx or template defines, for picking what to import
+/// Returns JSON array of {id, name, style_type, based_on}, or "Error: ..."
+pub fn list_document_styles(file_path: String) -> String {
+    let file_data = match std::fs::read(&file_path) {
+        Ok(data) => data,
+        Err(e) => return format!("Error: {}", e),
+    };
+    match list_styles(&file_data) {
+        Ok(styles) => serde_json::to_string(&styles).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+/// Copy styles from another .docx or template into the .docx at `target_path`
+/// `conflict_policy` is "overwrite", "rename" or "skip"
+/// Returns the import report JSON, or "Error: ..."
+pub fn import_styles(target_path: String, source_path: String, style_ids: Vec<String>, conflict_policy: String) -> String {
+    let Some(policy) = StyleConflictPolicy::from_name(&conflict_policy) else {
+        return format!("Error: unknown conflict policy '{}'", conflict_policy);
+    };
+    let (target, source) = match (std::fs::read(&target_path), std::fs::read(&source_path)) {
+        (Ok(target), Ok(source)) => (target, source),
+        (Err(e), _) | (_, Err(e)) => return format!("Error: {}", e),
+    };
+    let (output, report) = match import_package_styles(&target, &source, &style_ids, policy) {
+        Ok(result) => result,
+        Err(e) => return format!("OOXML error: {}", e),
+    };
+    if let Err(e) = std::fs::write(&target_path, output) {
+        return format!("Error: {}", e);
+    }
+    serde_json::to_string(&report).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Paragraph Hash APIs ====================
+
+/// Get stable per-paragraph content hashes (text + formatting)
+/// Returns JSON array of {start, length, hash} with hashes as 16-digit hex strings
+pub fn get_paragraph_hashes() -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let Document { content, paragraph_hashes, .. } = &mut *doc;
+    paragraph_hashes.ensure_valid(content);
+    let entries: Vec<serde_json::Value> = paragraph_hashes
+        .entries()
+        .iter()
+        .map(|entry| {
+            serde_json::json!({
+                "start": entry.start,
+                "length": entry.length,
+                "hash": format!("{:016x}", entry.hash),
+            })
+        })
+        .collect();
+    serde_json::Value::Array(entries).to_string()
+}
+
+// ==================== Font Coverage APIs ====================
+
+use crate::font_coverage::{analyze_piece_tree, FontRegistry};
+
+static FONT_REGISTRY: Lazy<RwLock<FontRegistry>> = Lazy::new(|| RwLock::new(FontRegistry::new()));
+
+/// Register a font (TTF/OTF data) available to the renderer and exporters
+pub fn register_font(name: String, font_data: Vec<u8>) {
+    FONT_REGISTRY.write().unwrap().register_font_data(&name, font_data);
+}
+
+/// Set the font fallback chain, tried in order after the requested font
+pub fn set_font_fallback_order(names: Vec<String>) {
+    FONT_REGISTRY.write().unwrap().set_fallback_order(names);
+}
+
+/// Map each character of the current document to the font that renders it
+/// Returns JSON with per-character usage, per-font subsets, and characters no font covers
+pub fn analyze_font_coverage(default_font: String) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let registry = FONT_REGISTRY.read().unwrap();
+    let report = analyze_piece_tree(&doc.content, &registry, &default_font);
+    serde_json::to_string(&report).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Metrics APIs ====================
+
+/// Mark the start of an editing session for crash-free session metrics
+pub fn mark_session_started() {
+    crate::metrics::session(crate::metrics::SessionMarker::Started);
+}
+
+/// Mark a clean end of the editing session
+pub fn mark_session_ended() {
+    crate::metrics::session(crate::metrics::SessionMarker::EndedCleanly);
+}
+
+// ==================== Line Breaking APIs ====================
+
+use crate::line_breaking::BreakStrategy;
+use crate::line_layout::LineLayout;
+use crate::text_shaping::ShapingOptions;
+use crate::bidi::{BidiParagraph, Direction};
+use crate::layout_quality::{LayoutQuality, QualityThresholds};
+
+/// Layouts text and returns JSON layout information
+pub fn layout_text(text: &str, width: f32) -> String {
+    let mut layout = LineLayout::new();
+    layout.layout_to_json(text, width)
+}
+
+/// Shapes text into positioned glyphs with HarfBuzz, script run by script run
+/// `options_json` is a ShapingOptions object, e.g. {"language":"ar",
+/// "features":[{"tag":"liga","value":0}]}, or empty for the defaults.
+/// Returns JSON {glyphs, width, char_advances, cluster_starts, runs}, or "Error: ..."
+pub fn shape_text(text: String, options_json: String) -> String {
+    let options = if options_json.trim().is_empty() {
+        ShapingOptions::default()
+    } else {
+        match serde_json::from_str::<ShapingOptions>(&options_json) {
+            Ok(options) => options,
+            Err(e) => return format!("Error: invalid options: {}", e),
+        }
+    };
+    let shaped = LineLayout::new().breaker().shape_text(&text, &options);
+    serde_json::to_string(&shaped).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Resolve the bidi levels of a one-line paragraph and reorder it for display
+/// `direction` is "left_to_right", "right_to_left" or empty to take the first
+/// strong char's. Returns JSON {base_level, levels, runs, visual_order}: runs
+/// in display order as char ranges with levels, and the char index shown at
+/// each position from the left; or "Error: ..."
+pub fn get_bidi_layout(text: String, direction: String) -> String {
+    let paragraph = match bidi_paragraph(&text, &direction) {
+        Ok(paragraph) => paragraph,
+        Err(e) => return e,
+    };
+    let line = 0..text.chars().count();
+    serde_json::to_string(&serde_json::json!({
+        "base_level": paragraph.base_level(),
+        "levels": paragraph.line_levels(line.clone()),
+        "runs": paragraph.visual_runs(line.clone()),
+        "visual_order": paragraph.visual_order(line),
+    }))
+    .unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Visual caret position, in chars from the left, of the caret before char
+/// `offset` of a one-line paragraph, as get_bidi_layout lays it out
+/// Returns 0 on a bad direction
+pub fn bidi_caret_to_visual(text: String, direction: String, offset: usize) -> usize {
+    let line = 0..text.chars().count();
+    bidi_paragraph(&text, &direction).map_or(0, |paragraph| paragraph.caret_to_visual(line, offset))
+}
+
+/// Char offset of the caret at visual position `position`, in chars from the
+/// left, of a one-line paragraph; the inverse of bidi_caret_to_visual
+/// Returns 0 on a bad direction
+pub fn bidi_visual_to_caret(text: String, direction: String, position: usize) -> usize {
+    let line = 0..text.chars().count();
+    bidi_paragraph(&text, &direction).map_or(0, |paragraph| paragraph.visual_to_caret(line, position))
+}
+
+fn bidi_paragraph(text: &str, direction: &str) -> Result<BidiParagraph, String> {
+    let direction = match direction.trim() {
+        "" => None,
+        name => Some(
+            serde_json::from_value::<Direction>(serde_json::Value::String(name.to_string()))
+                .map_err(|e| format!("Error: {}", e))?,
+        ),
+    };
+    Ok(BidiParagraph::new(text, direction))
+}
+
+/// Calculates the width of text in abstract units
+pub fn calculate_text_width(text: &str) -> f32 {
+    let mut layout = LineLayout::new();
+    layout.breaker_mut().calculate_text_width(text)
+}
+
+/// Gets the number of lines needed for text at given width
+pub fn get_line_count_for_width(text: &str, width: f32) -> usize {
+    let mut layout = LineLayout::new();
+    layout.breaker_mut().break_lines(text, Some(width)).len()
+}
+
+/// Gets the height needed for text at given width
+pub fn get_text_height(text: &str, width: f32, line_height: f32, font_size: f32) -> f32 {
+    use crate::line_layout::measure;
+    measure::get_text_height(text, width, line_height, font_size)
+}
+
+/// Layouts the current document state and returns JSON layout information
+/// Each paragraph is laid out with its style and direct paragraph formatting
+pub fn layout_current_document(width: f32) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let text = doc.content.get_text();
+    let props = paragraph_layout_properties(&doc);
+    let mut layout = LineLayout::new();
+    layout.set_break_strategy(doc.break_strategy);
+    let document_layout = layout.layout_document_with_paragraph_props(&text, width, &props);
+    serde_json::to_string(&document_layout).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// Typographic quality of each paragraph of the current document laid out at `width`
+/// `thresholds_json` may be empty for the default thresholds
+/// Returns a JSON array of per-paragraph diagnostics, each flagged `poor` past the thresholds
+pub fn get_layout_quality(width: f32, thresholds_json: String) -> String {
+    let thresholds = if thresholds_json.trim().is_empty() {
+        QualityThresholds::default()
+    } else {
+        match serde_json::from_str::<QualityThresholds>(&thresholds_json) {
+            Ok(thresholds) => thresholds,
+            Err(e) => return format!("JSON error: {}", e),
+        }
+    };
+
+    let doc = DOCUMENT.read().unwrap();
+    let text = doc.content.get_text();
+    let props = paragraph_layout_properties(&doc);
+    let mut layout = LineLayout::new();
+    layout.set_break_strategy(doc.break_strategy);
+    let document_layout = layout.layout_document_with_paragraph_props(&text, width, &props);
+    let paragraphs: Vec<serde_json::Value> = document_layout
+        .paragraphs
+        .iter()
+        .enumerate()
+        .map(|(index, paragraph)| {
+            let quality = LayoutQuality::measure(paragraph, layout.breaker_mut(), &thresholds);
+            serde_json::json!({
+                "paragraph": index,
+                "poor": quality.is_poor(&thresholds),
+                "quality": quality,
+            })
+        })
+        .collect();
+    serde_json::to_string(&paragraphs).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Parses "FirstFit" or "TotalFit"; an empty string gives None
+fn parse_break_strategy(strategy: &str) -> Result<Option<BreakStrategy>, String> {
+    if strategy.trim().is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_value(serde_json::Value::String(strategy.trim().to_string()))
+        .map(Some)
+        .map_err(|_| format!("Error: Unknown break strategy '{}'", strategy))
+}
+
+/// Sets how the document breaks lines where a paragraph style does not choose
+/// `strategy` is "FirstFit" or "TotalFit"; returns it as JSON, or "Error: ..."
+pub fn set_break_strategy(strategy: String) -> String {
+    match parse_break_strategy(&strategy) {
+        Ok(Some(strategy)) => {
+            DOCUMENT.write().unwrap().break_strategy = strategy;
+            serde_json::to_string(&strategy).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Ok(None) => "Error: No break strategy given".to_string(),
+        Err(e) => e,
+    }
+}
+
+/// Sets how paragraphs of a paragraph style break lines, e.g. TotalFit for "Body Text"
+/// An empty `strategy` lets the style inherit it. Returns the resolved style as JSON, or "Error: ..."
+pub fn set_style_break_strategy(style: String, strategy: String) -> String {
+    let strategy = match parse_break_strategy(&strategy) {
+        Ok(strategy) => strategy,
+        Err(e) => return e,
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    match doc.styles.set_break_strategy(&style, strategy) {
+        Ok(()) => {
+            doc.track_modification();
+            let id = doc.styles.find(&style).map(|named| named.id.clone()).unwrap_or(style);
+            serde_json::to_string(&doc.styles.resolve(&id)).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+// ==================== Page Setup APIs ====================
+
+use crate::page_layout::PageLayout;
+use crate::page_setup::{Margins, Orientation, PageSetup, PageSetupError, PaperSize};
+
+/// Repaginate the document after a page setup change
+/// Returns JSON with the first section changed, null if none was, and the page count
+fn repaginate(doc: &mut Document) -> String {
+    let section = doc.page_setup.take_repagination();
+    let (mut page_layout, sections) = doc.page_layout();
+    let text = doc.content.get_text();
+    let mut line_layout = LineLayout::new();
+    line_layout.set_break_strategy(doc.break_strategy);
+    let layout = line_layout.layout_document(&text, page_layout.column_width());
+    let model = page_layout.layout_sections(&layout.paragraphs, &sections);
+    serde_json::json!({
+        "section": section,
+        "page_count": model.page_count(),
+    })
+    .to_string()
+}
+
+fn apply_page_setup(change: impl FnOnce(&mut Document) -> Result<(), PageSetupError>) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    match change(&mut doc) {
+        Ok(()) => {
+            doc.track_modification();
+            repaginate(&mut doc)
+        }
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// Get the page setup of all sections as JSON
+pub fn get_page_setup() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.page_setup).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Set the paper size of a section
+/// `preset` is a paper name ("A4", "Letter"...); an empty or "Custom" preset uses width/height in points
+pub fn set_page_size(section: usize, preset: String, width: f32, height: f32) -> String {
+    let size = if preset.is_empty() || preset.eq_ignore_ascii_case("custom") {
+        PaperSize::Custom { width, height }
+    } else {
+        match PaperSize::from_name(&preset) {
+            Some(size) => size,
+            None => return format!("Error: Unknown paper size '{}'", preset),
+        }
+    };
+    apply_page_setup(|doc| doc.page_setup.set_page_size(section, size))
+}
+
+/// Set the orientation of a section
+pub fn set_page_orientation(section: usize, landscape: bool) -> String {
+    let orientation = if landscape {
+        Orientation::Landscape
+    } else {
+        Orientation::Portrait
+    };
+    apply_page_setup(|doc| doc.page_setup.set_orientation(section, orientation))
+}
+
+/// Set the margins of a section in points
+/// Margins below the printer-safe minimum are rejected
+pub fn set_page_margins(section: usize, top: f32, bottom: f32, left: f32, right: f32) -> String {
+    apply_page_setup(|doc| {
+        let current = doc
+            .page_setup
+            .section(section)
+            .ok_or(PageSetupError::SectionOutOfRange(section))?
+            .margins;
+        let margins = Margins {
+            top,
+            bottom,
+            left,
+            right,
+            ..current
+        };
+        doc.page_setup.set_margins(section, margins)
+    })
+}
+
+// ==================== Measurement APIs ====================
+
+use crate::measurement::{MeasurementSettings, MeasurementUnit, DEFAULT_CHAR_PITCH};
+
+/// The unit lengths are shown and entered in, and the char unit's size
+static MEASUREMENT: Lazy<RwLock<MeasurementSettings>> = Lazy::new(|| RwLock::new(MeasurementSettings::default()));
+
+/// Get the measurement settings as JSON {unit, char_pitch}
+pub fn get_measurement_settings() -> String {
+    serde_json::to_string(&*MEASUREMENT.read().unwrap()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Set the unit rulers and dimension fields use
+/// `unit` is "inches", "centimeters", "millimeters", "points", "picas" or "characters"
+/// Returns the settings as JSON, or "Error: ..."
+pub fn set_measurement_unit(unit: String) -> String {
+    let Some(unit) = MeasurementUnit::from_name(&unit) else {
+        return format!("Error: Unknown unit '{}'", unit);
+    };
+    MEASUREMENT.write().unwrap().unit = unit;
+    get_measurement_settings()
+}
+
+/// Size the character unit by the document grid of a section: `chars_per_line`
+/// chars across its text width; 0 removes the grid
+/// Returns the settings as JSON, or "Error: ..."
+pub fn set_character_grid(section: usize, chars_per_line: u32) -> String {
+    let content_width = match DOCUMENT.read().unwrap().page_setup.section(section) {
+        Some(setup) => setup.page_config().content_width(),
+        None => return format!("Error: {}", PageSetupError::SectionOutOfRange(section)),
+    };
+    let result = {
+        let mut settings = MEASUREMENT.write().unwrap();
+        match chars_per_line {
+            0 => {
+                settings.char_pitch = DEFAULT_CHAR_PITCH;
+                Ok(())
+            }
+            chars => settings.set_document_grid(content_width, chars),
+        }
+    };
+    match result {
+        Ok(()) => get_measurement_settings(),
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// Show a length in points in the preferred unit, e.g. "2.54 cm"
+pub fn format_measurement(points: f32) -> String {
+    MEASUREMENT.read().unwrap().format(points)
+}
+
+/// Read a dimension string such as "2.54 cm", "1\"", "12 pt" or "2字符";
+/// a bare number is in the preferred unit
+/// Returns JSON {points}, or "Error: ..."
+pub fn parse_measurement(text: String) -> String {
+    match MEASUREMENT.read().unwrap().parse(&text) {
+        Ok(points) => serde_json::json!({ "points": points }).to_string(),
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// Read a table width: "auto", a percentage such as "50%", or a dimension string
+/// Returns the width as JSON ({"Fixed": points}, {"Percent": percent} or "Auto"), or "Error: ..."
+pub fn parse_table_width(text: String) -> String {
+    match MEASUREMENT.read().unwrap().parse_table_width(&text) {
+        Ok(width) => serde_json::to_string(&width).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// Get the ticks of a ruler `length` points long in the preferred unit
+/// Returns JSON array of {position, label, major}
+pub fn get_ruler_ticks(length: f32) -> String {
+    let ticks = MEASUREMENT.read().unwrap().ruler_ticks(length);
+    serde_json::to_string(&ticks).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Set the margins of a section from dimension strings
+/// Returns JSON with the new page count, or "Error: ..." if a margin cannot be read
+pub fn set_page_margins_text(section: usize, top: String, bottom: String, left: String, right: String) -> String {
+    let settings = *MEASUREMENT.read().unwrap();
+    let mut margins = [0.0; 4];
+    for (margin, text) in margins.iter_mut().zip([&top, &bottom, &left, &right]) {
+        match settings.parse(text) {
+            Ok(points) => *margin = points,
+            Err(e) => return format!("Error: {}", e),
+        }
+    }
+    let [top, bottom, left, right] = margins;
+    set_page_margins(section, top, bottom, left, right)
+}
+
+/// Set the indents of every paragraph the char range touches from dimension
+/// strings; an empty string leaves that indent as it is
+/// Returns the document text, or "Error: ..." if an indent cannot be read
+pub fn set_paragraph_indents_text(start: usize, end: usize, left: String, right: String, first_line: String) -> String {
+    let settings = *MEASUREMENT.read().unwrap();
+    let indent = |text: &str| match text.trim() {
+        "" => Ok(None),
+        text => settings.parse_twips(text).map(Some),
+    };
+    let attrs = match (indent(&left), indent(&right), indent(&first_line)) {
+        (Ok(indent_left), Ok(indent_right), Ok(indent_first_line)) => ParagraphAttributes {
+            indent_left,
+            indent_right,
+            indent_first_line,
+            ..Default::default()
+        },
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => return format!("Error: {}", e),
+    };
+    reformat_paragraphs(start, end, |content, range| content.apply_paragraph_attributes(range, &attrs))
+}
+
+// ==================== Index APIs ====================
+
+use crate::index::{IndexError, IndexLine, IndexOptions, NO_ENTRIES};
+use std::ops::Range;
+
+/// Index entries in text order as a JSON array of {text, see, position}
+pub fn get_index_entries() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(doc.index.entries()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Mark an index entry (an XE field) at a char offset; ":" separates sub-entries,
+/// as in "Fruit:Apple", and a non-empty `see` (e.g. "See Produce") is shown instead of page numbers
+/// Returns the entry as JSON, or "Error: ..."
+pub fn mark_index_entry(offset: usize, text: String, see: String) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let offset = offset.min(doc.content.total_char_count);
+    let marked = doc.try_change_anchors(|doc| {
+        doc.index
+            .mark(offset, &text, Some(&see))
+            .map(|entry| serde_json::to_string(entry).unwrap_or_else(|e| format!("JSON error: {}", e)))
+    });
+    let entry = match marked {
+        Ok(entry) => entry,
+        Err(e) => return format!("Error: {}", e),
+    };
+    doc.track_modification();
+    entry
+}
+
+/// Remove the index entries marked in chars [start, end), or at start if the range is empty
+/// Returns the remaining entries as in get_index_entries
+pub fn remove_index_entries(start: usize, end: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    doc.change_anchors(|doc| doc.index.remove(start..end.max(start)));
+    doc.track_modification();
+    serde_json::to_string(doc.index.entries()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// The INDEX field as JSON {instruction, kind, start, length, result}, or "null"
+pub fn get_index() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.index.field()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Insert an index at a char offset, in paragraphs of its own
+/// `options_json` is {entry_separator, page_separator, heading}, each optional; heading is
+/// e.g. "A" for a letter before each letter's entries. Page numbers come from the latest
+/// pagination, so update_index after repaginating brings them up to date.
+/// Returns the INDEX field as in get_index, or "Error: ..."
+pub fn insert_index(offset: usize, options_json: String) -> String {
+    let options: IndexOptions = if options_json.trim().is_empty() {
+        IndexOptions::default()
+    } else {
+        match serde_json::from_str(&options_json) {
+            Ok(options) => options,
+            Err(e) => return format!("Error: {}", e),
+        }
+    };
+    let pages = REPAGINATOR.status();
+    let mut doc = DOCUMENT.write().unwrap();
+    let offset = offset.min(doc.content.total_char_count);
+    if let Some(error) = doc.protected(offset..offset) {
+        return error;
+    }
+    if doc.index.field().is_some() {
+        return format!("Error: {}", IndexError::IndexExists);
+    }
+    let lines = doc.index.lines(&options, |offset| pages.page_of(offset));
+    write_index(&mut doc, offset..offset, &options.instruction(), &lines);
+    serde_json::to_string(&doc.index.field()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Rebuild the index from the entries and the latest pagination, keeping its options
+/// Returns the INDEX field as in get_index, or "Error: ..."
+pub fn update_index() -> String {
+    let pages = REPAGINATOR.status();
+    let mut doc = DOCUMENT.write().unwrap();
+    let Some(field) = doc.index.field().cloned() else {
+        return format!("Error: {}", IndexError::NoIndex);
+    };
+    if let Some(error) = doc.protected(field.start..field.start + field.length) {
+        return error;
+    }
+    let options = IndexOptions::from_instruction(&field.instruction);
+    let lines = doc.index.lines(&options, |offset| pages.page_of(offset));
+    write_index(&mut doc, field.start..field.start + field.length, &field.instruction, &lines);
+    serde_json::to_string(&doc.index.field()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Put the index `lines` in place of chars `range` as one undo step, one
+/// paragraph per line, starting and ending paragraphs around it as needed
+fn write_index(doc: &mut Document, range: Range<usize>, instruction: &str, lines: &[IndexLine]) {
+    let result = if lines.is_empty() {
+        NO_ENTRIES.to_string()
+    } else {
+        lines.iter().map(|line| line.text.as_str()).collect::<Vec<_>>().join("\n")
+    };
+    let char_at = |offset: usize| doc.content.get_text().chars().nth(offset);
+    let break_before = range.start > 0 && char_at(range.start - 1) != Some('\n');
+    let break_after = range.end < doc.content.total_char_count && char_at(range.end) != Some('\n');
+    let start = range.start + usize::from(break_before);
+    let text = format!(
+        "{}{}{}",
+        if break_before { "\n" } else { "" },
+        result,
+        if break_after { "\n" } else { "" }
+    );
+    let inserted = text.chars().count();
+
+    let byte_start = doc.content.char_to_byte_offset(range.start);
+    let byte_length = doc.content.char_to_byte_offset(range.end) - byte_start;
+    doc.remember_anchors();
+    doc.content.transaction(|content| {
+        content.delete(byte_start, byte_length);
+        content.insert(range.start, text);
+        let mut line_start = start;
+        for line in lines {
+            content.set_paragraph_attributes(line_start..line_start, Some(&line.paragraph_attributes()));
+            line_start += line.text.chars().count() + 1;
+        }
+    });
+
+    doc.text_replaced(range.start, range.len(), inserted);
+    doc.index.set_field(instruction, start..start + result.chars().count(), &result);
+    doc.track_modification();
+}
+
+// ==================== Caption APIs ====================
+
+use crate::captions::{
+    add_caption_style, caption_insertion, caption_paragraph, chapter_number, heading_level, move_paragraphs, paragraph_span,
+    CaptionError, CaptionLabel, CaptionPosition, Chapter,
+};
+
+/// Captions in text order as a JSON array of {label, position, sequence, chapter, target_start, target_length}
+pub fn get_captions() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(doc.captions.captions()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Caption labels as a JSON array of {name, chapter_level, separator}: Figure, Table
+/// and Equation, then any other label set or used
+pub fn get_caption_labels() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&doc.captions.labels()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Set how captions of `label` inserted from now on are numbered; a `chapter_level`
+/// of 1 to 9 puts the number of the last heading of that level first, joined by
+/// `separator` as in "Figure 2-3", and 0 numbers them through the document
+/// Returns the labels as in get_caption_labels, or "Error: ..."
+pub fn set_caption_label(label: String, chapter_level: u8, separator: String) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let label = CaptionLabel {
+        chapter_level: (chapter_level > 0).then_some(chapter_level),
+        separator,
+        ..CaptionLabel::new(&label)
+    };
+    if let Err(e) = doc.captions.set_label(label) {
+        return format!("Error: {}", e);
+    }
+    doc.track_modification();
+    serde_json::to_string(&doc.captions.labels()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Caption the image or table in the paragraphs chars [start, end) touch, with a
+/// Caption-style paragraph such as "Figure 3" "above" or "below" it, as one undo step
+/// The number is a SEQ field, and the captions are renumbered in text order.
+/// Returns the captions as in get_captions, or "Error: ..."
+pub fn insert_caption(start: usize, end: usize, label: String, position: String) -> String {
+    let Some(position) = CaptionPosition::from_name(&position) else {
+        return format!("Error: Unknown caption position: {}", position);
+    };
+    if label.trim().is_empty() {
+        return format!("Error: {}", CaptionError::EmptyLabel);
+    }
+    let mut doc = DOCUMENT.write().unwrap();
+    let label = doc.captions.label(&label);
+    if label.chapter_level.is_some() && doc.numbering.heading_list(&doc.styles).is_none() {
+        return format!("Error: {}", CaptionError::UnnumberedHeadings);
+    }
+    let total = doc.content.total_char_count;
+    let target = paragraph_span(&doc.content.get_text(), start.min(total)..end.max(start).min(total));
+    if let Some(error) = doc.protected(target.clone()) {
+        return error;
+    }
+    let insertion = caption_insertion(&label, position, target.clone());
+    let inserted = insertion.text.chars().count();
+
+    // The caption paragraph goes in between the target's paragraphs and its
+    // neighbour's, each keeping its formatting
+    let first = doc.content.paragraph_index_at(target.start);
+    let mut formats = doc.content.paragraph_attributes_in(target.clone());
+    let caption_at = match position {
+        CaptionPosition::Above => 0,
+        CaptionPosition::Below => formats.len(),
+    };
+    formats.insert(caption_at, Some(caption_paragraph()));
+
+    add_caption_style(&mut doc.styles);
+    doc.begin_transaction();
+    doc.insert_text(insertion.offset, &insertion.text);
+    doc.content
+        .reformat_each_paragraph(target.start..target.end + inserted, |index, _| formats[index - first].clone());
+    doc.formatting_changed(target.start..target.end + inserted);
+    doc.captions.add(insertion.caption);
+    renumber_captions(&mut doc);
+    doc.end_transaction();
+    doc.track_modification();
+    serde_json::to_string(doc.captions.captions()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Move the captioned object at char `offset`, caption and all, before paragraph
+/// `to_paragraph` (the paragraph count moving it to the end), as one undo step
+/// Returns the captions as in get_captions, or "Error: ..."
+pub fn move_captioned_object(offset: usize, to_paragraph: usize) -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let Some(caption) = doc.captions.at(offset).cloned() else {
+        return format!("Error: {}", CaptionError::NotFound(offset));
+    };
+    let number = caption.sequence.start..caption.sequence.start + caption.sequence.length;
+    let span = caption.target_start.min(number.start)..caption.target().end.max(number.end);
+    let destination = doc.content.get_offset_at_line(to_paragraph);
+    if let Some(error) = doc.protected(span.clone()).or_else(|| doc.protected(destination..destination)) {
+        return error;
+    }
+    doc.begin_transaction();
+    let Some(moved) = move_paragraphs(&mut doc.content, span, to_paragraph) else {
+        doc.end_transaction();
+        return serde_json::to_string(doc.captions.captions()).unwrap_or_else(|e| format!("JSON error: {}", e));
+    };
+
+    // Captions and images in the moved paragraphs go with them rather than being deleted
+    let taken = doc.captions.take_in(moved.removed.clone());
+    let objects = doc.floating.anchored_in(moved.removed.clone());
+    for (offset, removed, inserted) in [
+        (moved.removed.start, moved.removed.len(), 0),
+        (moved.inserted.start, 0, moved.inserted.len()),
+    ] {
+        text_replaced(&mut doc, offset, removed, inserted);
+        doc.captions.apply_edit(offset, removed, inserted);
+    }
+    for (object, offset) in objects {
+        doc.floating.set_offset(object, offset - moved.from + moved.start);
+    }
+    for mut caption in taken {
+        let shift = |position: usize| position - moved.from + moved.start;
+        caption.target_start = shift(caption.target_start);
+        caption.sequence.start = shift(caption.sequence.start);
+        if let Some(chapter) = &mut caption.chapter {
+            chapter.start = shift(chapter.start);
+        }
+        doc.captions.add(caption);
+    }
+    renumber_captions(&mut doc);
+    doc.end_transaction();
+    doc.track_modification();
+    serde_json::to_string(doc.captions.captions()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Renumber the captions in text order, as updating their fields does
+/// Returns the captions as in get_captions
+pub fn update_captions() -> String {
+    let mut doc = DOCUMENT.write().unwrap();
+    let fields = doc.captions.captions().iter().flat_map(|caption| std::iter::once(&caption.sequence).chain(&caption.chapter));
+    if let Some(error) = fields.filter_map(|field| doc.protected(field.start..field.start + field.length)).next() {
+        return error;
+    }
+    doc.begin_transaction();
+    renumber_captions(&mut doc);
+    doc.end_transaction();
+    doc.track_modification();
+    serde_json::to_string(doc.captions.captions()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// The numbered headings, for chapter numbers in captions
+fn chapters(doc: &Document) -> Vec<Chapter> {
+    let labels = list_labels(doc);
+    let mut position = 0;
+    let mut chapters = Vec::new();
+    for (index, (line, label)) in doc.content.get_text().split('\n').zip(labels).enumerate() {
+        let style_id = doc.content.paragraph_attributes(index).and_then(|attrs| attrs.style_id.as_deref());
+        if let (Some(level), Some(label)) = (style_id.and_then(heading_level), label) {
+            chapters.push(Chapter {
+                position,
+                level,
+                number: chapter_number(&label),
+            });
+        }
+        position += line.chars().count() + 1;
+    }
+    chapters
+}
+
+/// Bring the caption fields' text up to date with their numbers, back to
+/// front so the offsets of those still to do stay put
+fn renumber_captions(doc: &mut Document) {
+    let numbers = doc.captions.numbers(&chapters(doc));
+    let mut results = Vec::new();
+    for (caption, (chapter, count)) in doc.captions.captions().iter().zip(numbers) {
+        if let (Some(field), Some(chapter)) = (&caption.chapter, chapter) {
+            results.push((field.clone(), chapter));
+        }
+        results.push((caption.sequence.clone(), count.to_string()));
+    }
+    results.sort_by_key(|(field, _)| field.start);
+
+    for (field, result) in results.into_iter().rev() {
+        if field.result == result {
+            continue;
+        }
+        let attributes = doc.content.get_attributes_at(field.start);
+        let byte_start = doc.content.char_to_byte_offset(field.start);
+        let byte_length = doc.content.char_to_byte_offset(field.start + field.length) - byte_start;
+        doc.content.transaction(|content| {
+            content.delete(byte_start, byte_length);
+            content.insert_with_attrs(field.start, result.clone(), attributes);
+        });
+        text_replaced(doc, field.start, field.length, result.chars().count());
+        doc.captions.replace_result(field.start, &result);
+    }
+}
+
+/// Bring the rest of the document but the captions up to date with `removed`
+/// chars at `offset` replaced by `inserted` chars
+fn text_replaced(doc: &mut Document, offset: usize, removed: usize, inserted: usize) {
+    doc.paragraph_hashes.invalidate();
+    doc.edit_locations.record_edit(offset, removed, inserted);
+    doc.revisions.apply_edit(offset, removed, inserted);
+    doc.comments.apply_edit(offset, removed, inserted);
+    doc.bookmarks.apply_edit(offset, removed, inserted);
+    doc.hyperlinks.apply_edit(offset, removed, inserted);
+    doc.math_zones.apply_edit(offset, removed, inserted);
+    doc.index.apply_edit(offset, removed, inserted);
+    doc.floating.apply_edit(offset, removed, inserted);
+    doc.update_metadata();
+    doc.track_modification();
+}
+
+// ==================== Mailing APIs ====================
+
+use crate::mailings::{self, EnvelopeSize, LabelProduct, LABEL_PRODUCTS};
+
+/// Get the envelope size names create_envelope knows, with their sizes in points
+pub fn get_envelope_sizes() -> String {
+    let sizes: Vec<_> = ["10", "9", "Monarch", "DL", "C5", "C6"]
+        .into_iter()
+        .filter_map(|name| {
+            let (width, height) = EnvelopeSize::from_name(name)?.dimensions();
+            Some(serde_json::json!({ "name": name, "width": width, "height": height }))
+        })
+        .collect();
+    serde_json::to_string(&sizes).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Get the label products create_labels knows as JSON
+pub fn get_label_products() -> String {
+    serde_json::to_string(LABEL_PRODUCTS).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Create an envelope of a named size (see get_envelope_sizes); addresses have one line per "\n"
+/// and the return address may be empty
+/// Returns {model, page} JSON, the document model and its page size and margins in points,
+/// or "Error: ..."
+pub fn create_envelope(size: String, delivery_address: String, return_address: String) -> String {
+    let Some(size) = EnvelopeSize::from_name(&size) else {
+        return format!("Error: {}", mailings::MailingError::UnknownEnvelopeSize(size));
+    };
+    match mailings::envelope(size, &delivery_address, &return_address) {
+        Ok(document) => serde_json::to_string(&document).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// Create label sheets for a product (e.g. "Avery 5160"), one address per label from a JSON
+/// array of strings; with `full_sheet` the first address fills a whole sheet
+/// Returns {model, page} JSON as in create_envelope, or "Error: ..."
+pub fn create_labels(product: String, addresses_json: String, full_sheet: bool) -> String {
+    let Some(product) = LabelProduct::find(&product) else {
+        return format!("Error: {}", mailings::MailingError::UnknownLabel(product));
+    };
+    let addresses: Vec<String> = match serde_json::from_str(&addresses_json) {
+        Ok(addresses) => addresses,
+        Err(e) => return format!("JSON error: {}", e),
+    };
+    if addresses.iter().all(|address| address.trim().is_empty()) {
+        return format!("Error: {}", mailings::MailingError::MissingAddress);
+    }
+    let document = mailings::label_sheets(product, &addresses, full_sheet);
+    serde_json::to_string(&document).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Background Repagination APIs ====================
+
+use crate::repagination::{PaginationEvent, PaginationJob, Repaginator};
+
+type PaginationListener = Box<dyn Fn(&PaginationEvent) + Send + Sync>;
+
+/// Callbacks told about pagination progress
+static PAGINATION_LISTENERS: Lazy<Mutex<Vec<PaginationListener>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+static REPAGINATOR: Lazy<Repaginator> = Lazy::new(|| {
+    Repaginator::new(|event| {
+        for listener in PAGINATION_LISTENERS.lock().unwrap().iter() {
+            listener(event);
+        }
+    })
+});
+
+/// Register a callback receiving page boundary and page count changes
+/// The callback runs on the pagination worker thread; the document is not locked
+pub fn on_pagination_event(callback: impl Fn(&PaginationEvent) + Send + Sync + 'static) {
+    PAGINATION_LISTENERS.lock().unwrap().push(Box::new(callback));
+}
+
+/// Repaginate the current document in the background, e.g. after an edit
+/// The document is only locked while taking a snapshot; a newer request abandons
+/// this one. Returns the request's generation, which events carry
+pub fn request_repagination() -> u64 {
+    let job = {
+        let doc = DOCUMENT.read().unwrap();
+        let (page_layout, sections) = doc.page_layout();
+        PaginationJob {
+            snapshot: doc.content.snapshot(),
+            paragraph_props: paragraph_layout_properties(&doc),
+            page_layout,
+            sections,
+            break_strategy: doc.break_strategy,
+        }
+    };
+    REPAGINATOR.submit(job)
+}
+
+/// Report the char offset shown on screen, so its page is paginated first
+pub fn set_visible_offset(offset: usize) {
+    REPAGINATOR.set_visible_offset(offset);
+}
+
+/// "Page X of Y" for the char offset, from the latest pagination
+/// Returns JSON {page_index, page_count, complete}; page_index is null until known
+pub fn get_pagination_status(offset: usize) -> String {
+    let status = REPAGINATOR.status();
+    serde_json::json!({
+        "page_index": status.page_of(offset),
+        "page_count": status.page_count,
+        "complete": status.complete,
+    })
+    .to_string()
+}
+
+// ==================== Incremental Layout APIs ====================
+
+use crate::incremental_layout::IncrementalLayout;
+use crate::paragraph_hash::ParagraphHash;
+
+/// A layout kept between calls, with the sections and break strategy it was made for
+type KeptLayout<L> = Mutex<Option<(Vec<Section>, BreakStrategy, L)>>;
+
+/// Layout kept up to date with the current document
+static INCREMENTAL_LAYOUT: Lazy<KeptLayout<IncrementalLayout>> = Lazy::new(|| Mutex::new(None));
+
+/// Run `f` on the layout kept in `slot` with the document's text, paragraph
+/// hashes and layout properties; the layout is made anew with `make` when the
+/// document's sections or break strategy changed since it was made
+fn with_kept_layout<L, R>(
+    slot: &KeptLayout<L>,
+    make: impl FnOnce(PageLayout, Vec<Section>, BreakStrategy) -> L,
+    f: impl FnOnce(&PieceTree, &[ParagraphHash], &[LayoutProperties], &mut L) -> R,
+) -> R {
+    let mut doc = DOCUMENT.write().unwrap();
+    let props = paragraph_layout_properties(&doc);
+    let (page_layout, sections) = doc.page_layout();
+    let strategy = doc.break_strategy;
+    let Document { content, paragraph_hashes, .. } = &mut *doc;
+    paragraph_hashes.ensure_valid(content);
+
+    let mut slot = slot.lock().unwrap();
+    let current = matches!(&*slot, Some((laid_out, laid_out_strategy, _)) if *laid_out == sections && *laid_out_strategy == strategy);
+    if !current {
+        let layout = make(page_layout, sections.clone(), strategy);
+        *slot = Some((sections, strategy, layout));
+    }
+    let (_, _, layout) = slot.as_mut().unwrap();
+    f(content, paragraph_hashes.entries(), &props, layout)
+}
+
+/// Bring the layout up to date after an edit, laying out only the paragraphs
+/// that changed and the pages from the first of them on
+/// Returns JSON {first_paragraph, paragraphs_laid_out, first_page, page_count}
+pub fn update_layout() -> String {
+    let stats = with_kept_layout(
+        &INCREMENTAL_LAYOUT,
+        |page_layout, sections, strategy| {
+            let mut layout = IncrementalLayout::new(page_layout).with_sections(sections);
+            layout.line_layout_mut().set_break_strategy(strategy);
+            layout
+        },
+        |content, hashes, props, layout| layout.update(content, hashes, props),
+    );
+    serde_json::to_string(&stats).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Page a paragraph was placed on by the last `update_layout`
+/// Returns JSON {page_index, page_count}; page_index is null if unknown
+pub fn get_layout_page_of_paragraph(paragraph_index: usize) -> String {
+    let slot = INCREMENTAL_LAYOUT.lock().unwrap();
+    let model = slot.as_ref().and_then(|(_, _, layout)| layout.model());
+    serde_json::json!({
+        "page_index": model.and_then(|model| model.page_placed_on(paragraph_index)),
+        "page_count": model.map_or(0, |model| model.page_count()),
+    })
+    .to_string()
+}
+
+// ==================== Viewport Layout APIs ====================
+
+use crate::viewport_layout::{Viewport, ViewportLayout};
+use crate::hit_test::Affinity;
+
+/// Layout of the current document near the viewport
+static VIEWPORT_LAYOUT: Lazy<KeptLayout<ViewportLayout>> = Lazy::new(|| Mutex::new(None));
+
+/// Run `f` on the viewport layout, brought up to date with the document
+fn with_viewport_layout<R>(f: impl FnOnce(&PieceTree, &mut ViewportLayout) -> R) -> R {
+    with_kept_layout(
+        &VIEWPORT_LAYOUT,
+        |page_layout, sections, strategy| {
+            let mut layout = ViewportLayout::new(page_layout).with_sections(sections);
+            layout.line_layout_mut().set_break_strategy(strategy);
+            layout
+        },
+        |content, hashes, props, layout| {
+            layout.update(hashes, props);
+            f(content, layout)
+        },
+    )
+}
+
+/// Pages overlapping the viewport at scroll position `top`, laying out only
+/// them; the rest of the document has estimated heights
+/// Returns JSON {pages: [{top, page}], paragraphs, page_count, scroll_height, exact}
+pub fn get_viewport_pages(top: f32, height: f32) -> String {
+    let pages = with_viewport_layout(|content, layout| layout.viewport_pages(content, Viewport { top, height }));
+    serde_json::to_string(&pages).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Lay out up to `max_paragraphs` more paragraphs whose heights are
+/// estimated; call while idle until the result is exact, scrolling by the
+/// adjustment each time
+/// Returns JSON {paragraphs_laid_out, remaining, scroll_adjustment, exact}
+pub fn refine_viewport_layout(max_paragraphs: usize) -> String {
+    let step = with_viewport_layout(|content, layout| layout.refine(content, max_paragraphs));
+    serde_json::to_string(&step).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Scroll position of the line holding char `offset`, estimated until the
+/// viewport layout is exact
+pub fn get_scroll_position_of_offset(offset: usize) -> f32 {
+    with_viewport_layout(|_, layout| layout.scroll_position(offset))
+}
+
+/// Char offset of the start of the line at scroll position `position`
+pub fn get_offset_at_scroll_position(position: f32) -> usize {
+    with_viewport_layout(|_, layout| layout.offset_at(position))
+}
+
+// ==================== Hit Testing APIs ====================
+
+/// Caret at point (`x`, `y`) of page `page`, in points from its top left
+/// corner, for taps and clicks; the page must be laid out by the viewport
+/// layout
+/// Returns JSON {offset, affinity, page, paragraph, line, column, rect: {x, y, width, height}, rtl}, or "Error: ..."
+pub fn position_at_point(page: usize, x: f32, y: f32) -> String {
+    match with_viewport_layout(|_, layout| layout.position_at_point(page, x, y)) {
+        Some(position) => serde_json::to_string(&position).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        None => format!("Error: Page {} is not laid out", page),
+    }
+}
+
+/// Caret rectangle for char `offset`; `affinity` is "upstream" or
+/// "downstream" (empty for downstream) and picks the line end or bidi run
+/// edge where the offset has two places
+/// Returns the same JSON as `position_at_point`, or "Error: ..."
+pub fn caret_rect_for_offset(offset: usize, affinity: String) -> String {
+    let Some(affinity) = Affinity::from_name(&affinity) else {
+        return format!("Error: Unknown affinity '{}'", affinity);
+    };
+    match with_viewport_layout(|_, layout| layout.caret_rect(offset, affinity)) {
+        Some(position) => serde_json::to_string(&position).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        None => format!("Error: Offset {} is not on a laid out page", offset),
+    }
+}
+
+/// Highlight rectangles for selection `ranges_json`, e.g.
+/// `[{"start": 4, "end": 9}]`, one range per selection of a multi-selection,
+/// for drawing selection overlays: a rectangle per stretch of a line the
+/// ranges cover on screen, on the pages laid out by the viewport layout, and
+/// the floating images anchored in the ranges where they are drawn
+///
+/// Tables are kept out of the editor text, so no range takes in their cells.
+/// Returns JSON {rects: [{page, paragraph, line, rect}], images: [{index, id,
+/// page_index, rect, behavior}]}, or "Error: ..."
+pub fn get_selection_rects(ranges_json: String) -> String {
+    let ranges: Vec<Range<usize>> = match serde_json::from_str(&ranges_json) {
+        Ok(ranges) => ranges,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let rects = with_viewport_layout(|_, layout| layout.selection_rects(&ranges));
+
+    let doc = DOCUMENT.read().unwrap();
+    let anchored: Vec<usize> = ranges
+        .iter()
+        .flat_map(|range| doc.floating.anchored_in(range.clone()))
+        .map(|(index, _)| index)
+        .collect();
+    let images: Vec<PlacedObject> = match anchored.is_empty() {
+        true => Vec::new(),
+        false => {
+            let (lengths, pages, paragraphs) = floating_layout(&doc);
+            doc.floating
+                .place(&lengths, &pages, &paragraphs)
+                .into_iter()
+                .filter(|placed| anchored.contains(&placed.index))
+                .collect()
+        }
+    };
+    serde_json::to_string(&serde_json::json!({ "rects": rects, "images": images }))
+        .unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== View Filter APIs ====================
+
+use crate::view_filter::{Annotation, OutputTarget, ViewFilter};
+
+/// Lay out the current document into pages for an output target: "editing", "print" or "pdf"
+/// `annotations_json` lists content the filter may leave out, e.g.
+/// `[{"class": "hidden_text", "range": {"start": 4, "end": 9}}]`
+/// Returns the filtered layout JSON, or "Error: ..."
+pub fn layout_pages_for_output(target: String, annotations_json: String) -> String {
+    let Some(target) = OutputTarget::from_name(&target) else {
+        return format!("Error: Unknown output target '{}'", target);
+    };
+    let annotations: Vec<Annotation> = match serde_json::from_str(&annotations_json) {
+        Ok(annotations) => annotations,
+        Err(e) => return format!("Error: {}", e),
+    };
+
+    let doc = DOCUMENT.read().unwrap();
+    let (page_layout, _) = doc.page_layout();
+    let props = paragraph_layout_properties(&doc);
+    let layout = ViewFilter::for_target(target).layout(&doc.content.get_text(), &annotations, &props, page_layout.page_config);
+    serde_json::to_string(&layout).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Reading View APIs ====================
+
+use crate::reading_view::{self, ReadingOptions};
+
+/// Reflow the current document for reading on a screen, ignoring its page setup
+/// `options_json` is {viewport_width, font_scale, padding}, each optional, in points;
+/// e.g. `{"viewport_width": 390, "font_scale": 1.25}`
+/// Returns the reading layout JSON, or "Error: ..."
+pub fn layout_reading_view(options_json: String) -> String {
+    let options: ReadingOptions = if options_json.trim().is_empty() {
+        ReadingOptions::default()
+    } else {
+        match serde_json::from_str(&options_json) {
+            Ok(options) => options,
+            Err(e) => return format!("Error: {}", e),
+        }
+    };
+
+    let doc = DOCUMENT.read().unwrap();
+    let props = paragraph_layout_properties(&doc);
+    let layout = reading_view::layout(&doc.content.get_text(), &props, &[], options, doc.break_strategy);
+    serde_json::to_string(&layout).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Focus Mode APIs ====================
+
+use crate::focus_mode::{focus_data, FocusUnit};
+
+/// Focus and typewriter mode data for the caret, with the document laid out at `width`
+/// as in layout_current_document; `unit` is "sentence" or "paragraph"
+/// Returns JSON with the caret's sentence and paragraph ranges, the ranges to dim, the caret
+/// line's position and the scroll position centering it in `viewport_height`, or "Error: ..."
+pub fn get_focus_data(width: f32, viewport_height: f32, unit: String) -> String {
+    let Some(unit) = FocusUnit::from_name(&unit) else {
+        return format!("Error: Unknown focus unit '{}'", unit);
+    };
+    let doc = DOCUMENT.read().unwrap();
+    let text = doc.content.get_text();
+    let props = paragraph_layout_properties(&doc);
     let mut layout = LineLayout::new();
-    layout.breaker_mut().break_lines(text, Some(width)).len()
+    layout.set_break_strategy(doc.break_strategy);
+    let document_layout = layout.layout_document_with_paragraph_props(&text, width, &props);
+    let data = focus_data(&text, &document_layout, doc.content.get_selection_active(), unit, viewport_height);
+    serde_json::to_string(&data).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Accessibility APIs ====================
+
+use crate::accessibility::{self, AccessibleNode};
+
+/// The current document in reading order for assistive technology
+fn accessible_nodes(doc: &Document) -> Vec<AccessibleNode> {
+    let model = DocumentModel::from_piece_tree(&doc.content);
+    accessibility::reading_order(&model, &doc.styles, &doc.numbering)
+}
+
+/// The current document in reading order, as a JSON array of
+/// {role, text, offset} plus the fields of the role: heading level and label,
+/// list item level and label, table cell coordinates and spans, image alt text,
+/// or the kind and ID of a note whose start and end bracket its text
+pub fn get_reading_order() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    serde_json::to_string(&accessible_nodes(&doc)).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// The nodes of get_reading_order on page `page_index` of the latest pagination,
+/// as {page_index, nodes}, or "Error: ..." for a page not paginated yet
+pub fn get_page_reading_order(page_index: usize) -> String {
+    let pages = REPAGINATOR.status().pages;
+    let doc = DOCUMENT.read().unwrap();
+    let page = accessibility::by_page(&accessible_nodes(&doc), &pages)
+        .into_iter()
+        .find(|page| page.page_index == page_index);
+    match page {
+        Some(page) => serde_json::to_string(&page).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        None => format!("Error: Page {} is not paginated", page_index),
+    }
+}
+
+// ==================== Read Aloud APIs ====================
+
+/// The current document as speech chunks for text-to-speech, as a JSON array
+/// of {text, range, skipped, heading, emphasis, pause_after, ssml}; `range` is
+/// the chars to highlight while the chunk is spoken and `skipped` the page
+/// furniture inside it that `text` leaves out. A `max_chars` of 0 takes the
+/// default length.
+pub fn get_speech_chunks(max_chars: usize) -> String {
+    #[derive(Serialize)]
+    struct ChunkJson {
+        #[serde(flatten)]
+        chunk: crate::read_aloud::SpeechChunk,
+        ssml: String,
+    }
+
+    let doc = DOCUMENT.read().unwrap();
+    let model = DocumentModel::from_piece_tree(&doc.content);
+    let max_chars = if max_chars == 0 { crate::read_aloud::DEFAULT_MAX_CHUNK_CHARS } else { max_chars };
+    let chunks: Vec<ChunkJson> = crate::read_aloud::speech_chunks(&model, &doc.styles, max_chars)
+        .into_iter()
+        .map(|chunk| ChunkJson { ssml: chunk.to_ssml(), chunk })
+        .collect();
+    serde_json::to_string(&chunks).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== Header and Footer APIs ====================
+
+use crate::headers_footers::{HeaderFooterKind, HeaderFooterVariant};
+
+fn header_footer_key(kind: &str, variant: &str) -> Result<(HeaderFooterKind, HeaderFooterVariant), String> {
+    let kind = HeaderFooterKind::from_name(kind).ok_or_else(|| format!("Error: Unknown header/footer kind '{}'", kind))?;
+    let variant = HeaderFooterVariant::from_name(variant).ok_or_else(|| format!("Error: Unknown header/footer variant '{}'", variant))?;
+    Ok((kind, variant))
+}
+
+/// Apply `edit` to the header or footer section `section` shows
+/// `kind` is "header" or "footer", `variant` "default", "first" or "even".
+/// Returns its text afterwards, or "Error: ..."
+fn edit_header_footer(section: usize, kind: &str, variant: &str, edit: impl FnOnce(&mut PieceTree) -> bool) -> String {
+    let (kind, variant) = match header_footer_key(kind, variant) {
+        Ok(key) => key,
+        Err(e) => return e,
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    if section >= doc.page_setup.sections.len() {
+        return format!("Error: {}", PageSetupError::SectionOutOfRange(section));
+    }
+    let story = doc.headers_footers.story_mut(section, kind, variant);
+    let changed = edit(story);
+    let text = story.get_text();
+    if changed {
+        doc.track_modification();
+    }
+    text
+}
+
+/// Get the headers and footers of every section as JSON
+/// Each section has its different-first-page flag and, for each kind and variant,
+/// the text it shows (null if none) and whether that is linked to the previous section
+pub fn get_headers_footers() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let manager = &doc.headers_footers;
+    let section_count = doc.page_setup.sections.len().max(manager.section_count());
+    let variants = [HeaderFooterVariant::Default, HeaderFooterVariant::First, HeaderFooterVariant::Even];
+    let sections: Vec<serde_json::Value> = (0..section_count)
+        .map(|section| {
+            let stories = |kind: HeaderFooterKind| -> serde_json::Map<String, serde_json::Value> {
+                variants
+                    .iter()
+                    .map(|&variant| {
+                        let story = serde_json::json!({
+                            "text": manager.story(section, kind, variant).map(PieceTree::get_text),
+                            "linked": manager.is_linked(section, kind, variant),
+                        });
+                        (variant.ooxml_name().to_string(), story)
+                    })
+                    .collect()
+            };
+            serde_json::json!({
+                "section": section,
+                "different_first_page": manager.different_first_page(section),
+                "headers": stories(HeaderFooterKind::Header),
+                "footers": stories(HeaderFooterKind::Footer),
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "different_odd_even": manager.different_odd_even(),
+        "sections": sections,
+    })
+    .to_string()
+}
+
+/// Get the header and footer text shown on a page as JSON {header, footer}, null where there is none
+/// `page_number` is the number printed on the page, which decides whether it is even
+pub fn get_page_header_footer(section: usize, first_in_section: bool, page_number: u32) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let shown = |kind| {
+        doc.headers_footers
+            .story_for_page(section, kind, first_in_section, page_number)
+            .map(PieceTree::get_text)
+    };
+    serde_json::json!({
+        "header": shown(HeaderFooterKind::Header),
+        "footer": shown(HeaderFooterKind::Footer),
+    })
+    .to_string()
+}
+
+/// Insert text at a char offset of a header or footer; a linked one is the previous section's
+/// Returns its text, or "Error: ..."
+pub fn insert_header_footer_text(section: usize, kind: String, variant: String, offset: usize, text: String) -> String {
+    edit_header_footer(section, &kind, &variant, |story| {
+        let offset = offset.min(story.total_char_count);
+        story.insert(offset, text)
+    })
+}
+
+/// Delete `length` chars at a char offset of a header or footer
+/// Returns its text, or "Error: ..."
+pub fn delete_header_footer_text(section: usize, kind: String, variant: String, offset: usize, length: usize) -> String {
+    edit_header_footer(section, &kind, &variant, |story| {
+        let start = offset.min(story.total_char_count);
+        let end = offset.saturating_add(length).min(story.total_char_count);
+        let byte_start = story.char_to_byte_offset(start);
+        story.delete(byte_start, story.char_to_byte_offset(end) - byte_start)
+    })
+}
+
+/// Apply text attributes to a char range of a header or footer
+/// Returns its text, or "Error: ..."
+pub fn apply_header_footer_attributes(
+    section: usize,
+    kind: String,
+    variant: String,
+    start: usize,
+    end: usize,
+    attributes_json: String,
+) -> String {
+    let attributes: TextAttributes = match serde_json::from_str(&attributes_json) {
+        Ok(attributes) => attributes,
+        Err(_) => return "Error: Invalid text attributes JSON".to_string(),
+    };
+    edit_header_footer(section, &kind, &variant, |story| {
+        let end = end.min(story.total_char_count);
+        story.apply_attributes(start.min(end)..end, &attributes)
+    })
+}
+
+/// Undo the last edit of a header or footer; each has its own history
+/// Returns its text, or "Error: ..."
+pub fn undo_header_footer(section: usize, kind: String, variant: String) -> String {
+    edit_header_footer(section, &kind, &variant, PieceTree::undo)
+}
+
+/// Redo the last undone edit of a header or footer
+/// Returns its text, or "Error: ..."
+pub fn redo_header_footer(section: usize, kind: String, variant: String) -> String {
+    edit_header_footer(section, &kind, &variant, PieceTree::redo)
+}
+
+/// Link a section's header or footer to the previous section's, or give it a copy of its own
+/// Returns the headers and footers as get_headers_footers does, or "Error: ..."
+pub fn set_header_footer_linked(section: usize, kind: String, variant: String, linked: bool) -> String {
+    let (kind, variant) = match header_footer_key(&kind, &variant) {
+        Ok(key) => key,
+        Err(e) => return e,
+    };
+    {
+        let mut doc = DOCUMENT.write().unwrap();
+        if section >= doc.page_setup.sections.len() {
+            return format!("Error: {}", PageSetupError::SectionOutOfRange(section));
+        }
+        let changed = if linked {
+            match doc.headers_footers.link_to_previous(section, kind, variant) {
+                Ok(changed) => changed,
+                Err(e) => return format!("Error: {}", e),
+            }
+        } else {
+            doc.headers_footers.unlink(section, kind, variant)
+        };
+        if changed {
+            doc.track_modification();
+        }
+    }
+    get_headers_footers()
+}
+
+/// Give a section's first page its own header and footer, or not
+/// Returns the headers and footers as get_headers_footers does, or "Error: ..."
+pub fn set_different_first_page(section: usize, enabled: bool) -> String {
+    {
+        let mut doc = DOCUMENT.write().unwrap();
+        if section >= doc.page_setup.sections.len() {
+            return format!("Error: {}", PageSetupError::SectionOutOfRange(section));
+        }
+        doc.headers_footers.set_different_first_page(section, enabled);
+        doc.track_modification();
+    }
+    get_headers_footers()
+}
+
+/// Give even pages their own headers and footers throughout the document, or not
+/// Returns the headers and footers as get_headers_footers does
+pub fn set_different_odd_even(enabled: bool) -> String {
+    {
+        let mut doc = DOCUMENT.write().unwrap();
+        doc.headers_footers.set_different_odd_even(enabled);
+        doc.track_modification();
+    }
+    get_headers_footers()
+}
+
+// ==================== Document Limits APIs ====================
+
+use crate::ooxml::{parse_ooxml_with_limits, DocumentLimits};
+
+/// Limits `load_ooxml_document` and `load_ooxml_from_bytes` open documents within
+static DOCUMENT_LIMITS: Lazy<RwLock<DocumentLimits>> = Lazy::new(|| RwLock::new(DocumentLimits::default()));
+
+/// Set the limits documents are opened within, from JSON such as
+/// `{"max_chars": 2000000, "max_images": 200, "max_part_size": 67108864, "policy": "truncate"}`
+/// Omitted limits are unbounded; "policy" is "fail" (the default) or "truncate"
+pub fn set_document_limits(json: String) -> String {
+    match serde_json::from_str::<DocumentLimits>(&json) {
+        Ok(limits) => {
+            *DOCUMENT_LIMITS.write().unwrap() = limits;
+            "OK".to_string()
+        }
+        Err(e) => format!("Error: Invalid limits: {}", e),
+    }
+}
+
+/// Get the limits documents are opened within, as JSON
+pub fn get_document_limits() -> String {
+    serde_json::to_string(&*DOCUMENT_LIMITS.read().unwrap()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+// ==================== OOXML Document APIs ====================
+
+use crate::ooxml::{analyze_features, audit_links, parse_ooxml, parse_preview, NoteKind, ParsedDocument};
+
+/// Load and parse an OOXML (.docx) document from file path, within the document limits
+/// Returns JSON string containing extracted text, styles, and metadata
+pub fn load_ooxml_document(file_path: &str) -> String {
+    match std::fs::read(file_path) {
+        Ok(file_data) => {
+            match parse_ooxml_with_limits(&file_data, &DOCUMENT_LIMITS.read().unwrap()) {
+                Ok(document) => {
+                    serde_json::to_string(&document).unwrap_or_else(|e| format!("JSON error: {}", e))
+                }
+                Err(e) => format!("OOXML error: {}", e),
+            }
+        }
+        Err(e) => format!("File error: {}", e),
+    }
+}
+
+/// Load and parse an OOXML (.docx) document from raw bytes, within the document limits
+/// Returns JSON string containing extracted text, styles, and metadata
+pub fn load_ooxml_from_bytes(file_data: &[u8]) -> String {
+    match parse_ooxml_with_limits(file_data, &DOCUMENT_LIMITS.read().unwrap()) {
+        Ok(document) => {
+            serde_json::to_string(&document).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+/// Export a document to OOXML (.docx) format
+/// Takes a JSON string representing the document and returns ZIP bytes
+pub fn export_to_ooxml(document_json: &str) -> Vec<u8> {
+    let document: Result<ParsedDocument, _> = serde_json::from_str(document_json);
+    
+    match document {
+        Ok(doc) => create_minimal_docx(&doc.text),
+        Err(e) => format!("Error: {}", e).into_bytes(),
+    }
+}
+
+/// Create a minimal .docx file with the given text content
+fn create_minimal_docx(text: &str) -> Vec<u8> {
+    use std::io::Write;
+    use zip::ZipWriter;
+    use std::io::Cursor;
+    
+    let mut buffer = Cursor::new(Vec::new());
+    {
+        let mut zip = ZipWriter::new(&mut buffer);
+        
+        let content_types = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+  <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+  <Default Extension="xml" ContentType="application/xml"/>
+  <Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/>
+  <Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/>
+  <Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/>
+  <Override PartName="/docProps/app.xml" ContentType="application/vnd.openxmlformats-officedocument.extended-properties+xml"/>
+</Types>"#;
+        
+        zip.start_file("[Content_Types].xml", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(content_types.as_bytes()).unwrap();
+        
+        let rels = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/>
+  <Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/>
+  <Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/extended-properties" Target="docProps/app.xml"/>
+</Relationships>"#;
+        
+        zip.start_file("_rels/.rels", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(rels.as_bytes()).unwrap();
+        
+        let doc_rels = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>
+</Relationships>"#;
+        
+        zip.start_file("word/_rels/document.xml.rels", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(doc_rels.as_bytes()).unwrap();
+        
+        // Simple XML escaping for text content
+        let escape_xml = |s: &str| -> String {
+            s.replace('&', "&amp;")
+             .replace('<', "&lt;")
+             .replace('>', "&gt;")
+             .replace('"', "&quot;")
+             .replace("'", "&apos;")
+        };
+        
+        let paragraphs: Vec<String> = text.lines().map(|line| {
+            format!(r#"<w:p xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:r><w:t>{}</w:t></w:r></w:p>"#, escape_xml(line))
+        }).collect();
+        
+        let document = format!(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+  <w:body>
+    {}
+  </w:body>
+</w:document>"#, paragraphs.join("\n    "));
+        
+        zip.start_file("word/document.xml", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(document.as_bytes()).unwrap();
+        
+        let styles = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+  <w:style w:styleId="Normal" w:type="paragraph">
+    <w:name w:val="Normal"/>
+    <w:rPr>
+      <w:sz w:val="24"/>
+      <w:szCs w:val="24"/>
+    </w:rPr>
+  </w:style>
+  <w:style w:styleId="Heading1" w:type="paragraph">
+    <w:name w:val="Heading 1"/>
+    <w:basedOn w:val="Normal"/>
+    <w:rPr>
+      <w:b w:val="1"/>
+      <w:sz w:val="36"/>
+      <w:szCs w:val="36"/>
+    </w:rPr>
+  </w:style>
+</w:styles>"#;
+        
+        zip.start_file("word/styles.xml", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(styles.as_bytes()).unwrap();
+        
+        let core_props = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:dcmitype="http://purl.org/dc/dcmitype/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <dc:title>Exported Document</dc:title>
+  <dc:creator>Velum</dc:creator>
+  <dcterms:created xsi:type="dcterms:W3CDTF">2024-01-01T00:00:00Z</dcterms:created>
+  <dcterms:modified xsi:type="dcterms:W3CDTF">2024-01-01T00:00:00Z</dcterms:modified>
+</cp:coreProperties>"#;
+        
+        zip.start_file("docProps/core.xml", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(core_props.as_bytes()).unwrap();
+        
+        let app_props = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/extended-properties">
+  <Application>Velum</Application>
+  <AppVersion>1.0000</AppVersion>
+</Properties>"#;
+        
+        zip.start_file("docProps/app.xml", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(app_props.as_bytes()).unwrap();
+        
+        zip.finish().unwrap();
+    }
+    
+    buffer.into_inner()
+}
+
+/// Get just the text content from a .docx file
+pub fn extract_ooxml_text(file_path: &str) -> String {
+    match std::fs::read(file_path) {
+        Ok(file_data) => {
+            match parse_ooxml(&file_data) {
+                Ok(document) => document.text,
+                Err(e) => format!("Error: {}", e),
+            }
+        }
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// Get document statistics from a .docx file
+pub fn get_ooxml_stats(file_path: &str) -> String {
+    match std::fs::read(file_path) {
+        Ok(file_data) => {
+            match parse_ooxml(&file_data) {
+                Ok(document) => {
+                    let stats = serde_json::json!({
+                        "paragraph_count": document.paragraph_count,
+                        "char_count": document.char_count,
+                        "word_count": document.word_count,
+                        "style_count": document.styles.len(),
+                        "title": document.title,
+                        "author": document.author,
+                    });
+                    stats.to_string()
+                }
+                Err(e) => format!("Error: {}", e),
+            }
+        }
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+/// Get the plain text, outline and title of a .docx for a file browser preview
+/// or a search index, reading only document.xml and the core properties
+/// Returns JSON with text, outline, counts and core properties
+pub fn get_ooxml_preview(file_data: &[u8]) -> String {
+    match parse_preview(file_data) {
+        Ok(preview) => {
+            serde_json::to_string(&preview).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+
+/// Scan a .docx package for Word features and Velum's support level for each
+/// Returns JSON string with the feature report, so the UI can warn before editing
+pub fn analyze_document_features(file_data: &[u8]) -> String {
+    match analyze_features(file_data) {
+        Ok(report) => {
+            serde_json::to_string(&report).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+/// Get hover-card content for the footnote/endnote referenced at a char position
+/// Returns JSON with the note's styled runs, cut to `max_chars` characters, or "null"
+pub fn get_note_preview(file_data: &[u8], reference_position: usize, max_chars: usize) -> String {
+    match parse_ooxml(file_data) {
+        Ok(document) => {
+            let preview = document.note_index().preview_at(reference_position, max_chars);
+            serde_json::to_string(&preview).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+/// List every reference to a note ("footnote" or "endnote" with its ID)
+/// Returns JSON array of references in document order
+pub fn get_note_references(file_data: &[u8], kind: String, note_id: String) -> String {
+    let kind = match kind.to_ascii_lowercase().as_str() {
+        "footnote" => NoteKind::Footnote,
+        "endnote" => NoteKind::Endnote,
+        _ => return format!("Error: Unknown note kind '{}'", kind),
+    };
+    match parse_ooxml(file_data) {
+        Ok(document) => {
+            let references = document.note_index().references_to(kind, &note_id);
+            serde_json::to_string(&references).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+/// Check hyperlinks, cross-references and relationships of a .docx package
+/// Returns JSON with findings and suggested fixes; external URLs are not ping-tested here
+pub fn audit_document_links(file_data: &[u8]) -> String {
+    match audit_links(file_data, None) {
+        Ok(report) => {
+            serde_json::to_string(&report).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+// ==================== Form APIs ====================
+
+use crate::ooxml::{fill_form as fill_package_form, form_fields, OoxmlError, ProtectionMode};
+
+/// List the fillable fields of a .docx: legacy form fields and text, date,
+/// drop-down and checkbox content controls, in tab order
+/// Returns JSON {fields, protection}; each field has its name, title, tag, kind,
+/// value (checked for checkboxes), allowed options and date format, or "Error: ..."
+pub fn get_form_fields(file_path: String) -> String {
+    let file_data = match std::fs::read(&file_path) {
+        Ok(data) => data,
+        Err(e) => return format!("Error: {}", e),
+    };
+    match form_fields(&file_data) {
+        Ok(fields) => serde_json::to_string(&fields).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+/// Get one form field of a .docx by name
+/// Returns the field JSON as in get_form_fields, "null" if there is none, or "Error: ..."
+pub fn get_form_field(file_path: String, name: String) -> String {
+    let file_data = match std::fs::read(&file_path) {
+        Ok(data) => data,
+        Err(e) => return format!("Error: {}", e),
+    };
+    match form_fields(&file_data) {
+        Ok(fields) => serde_json::to_string(&fields.get(&name)).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+/// Fill form fields of the .docx at `file_path` from a JSON object of field
+/// names to values and write the completed document to `output_path`
+/// Checkboxes take "true" or "false", drop-downs one of their options, dates an
+/// ISO date (2024-03-05) or one in the field's format. Nothing is written if a
+/// value is refused.
+/// Returns the filled fields JSON as in get_form_fields, or "Error: ..."
+pub fn fill_form(file_path: String, output_path: String, values_json: String) -> String {
+    let values: HashMap<String, String> = match serde_json::from_str(&values_json) {
+        Ok(values) => values,
+        Err(e) => return format!("JSON error: {}", e),
+    };
+    let file_data = match std::fs::read(&file_path) {
+        Ok(data) => data,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let (output, fields) = match fill_package_form(&file_data, &values) {
+        Ok(result) => result,
+        Err(OoxmlError::Form(e)) => return format!("Error: {}", e),
+        Err(e) => return format!("OOXML error: {}", e),
+    };
+    if let Err(e) = std::fs::write(&output_path, output) {
+        return format!("Error: {}", e);
+    }
+    serde_json::to_string(&fields).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Form fields of the open document with the char ranges of the text that
+/// shows them, and its protection
+/// Returns JSON {fields, protection}; each field is as in get_form_fields, with
+/// its start and length, and placeholder when it shows its placeholder text
+pub fn get_document_form_fields() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let fields = doc.forms.anchored(&doc.content.get_text());
+    serde_json::to_string(&serde_json::json!({ "fields": fields, "protection": doc.forms.protection() }))
+        .unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Fill a form field of the open document by name, as fill_form does, putting
+/// the text it shows in place of the old as one undo step
+/// Returns the fields JSON as in get_document_form_fields, or "Error: ..."
+pub fn set_document_form_field(name: String, value: String) -> String {
+    {
+        let mut doc = DOCUMENT.write().unwrap();
+        if let Err(e) = doc.set_form_field(&name, &value) {
+            return format!("Error: {}", e);
+        }
+        doc.track_modification();
+    }
+    get_document_form_fields()
+}
+
+/// Check or clear a checkbox form field of the open document by name
+/// Returns the fields JSON as in get_document_form_fields, or "Error: ..."
+pub fn set_document_form_field_checked(name: String, checked: bool) -> String {
+    {
+        let mut doc = DOCUMENT.write().unwrap();
+        if let Err(e) = doc.set_form_field_checked(&name, checked) {
+            return format!("Error: {}", e);
+        }
+        doc.track_modification();
+    }
+    get_document_form_fields()
+}
+
+/// Restrict editing of the open document: "None", "ReadOnly", "Comments",
+/// "TrackedChanges" or "Forms" (only form fields may be filled in)
+/// The restriction is saved with the document. Returns the fields JSON as in
+/// get_document_form_fields, or "Error: ..."
+pub fn set_document_protection(mode: String) -> String {
+    let protection: ProtectionMode = match serde_json::from_value(serde_json::Value::String(mode)) {
+        Ok(protection) => protection,
+        Err(e) => return format!("Error: {}", e),
+    };
+    {
+        let mut doc = DOCUMENT.write().unwrap();
+        doc.forms.set_protection(protection);
+        doc.track_modification();
+    }
+    get_document_form_fields()
+}
+
+// ==================== Lazy Loading APIs ====================
+
+use crate::ooxml::{LazyDocument, LazyLoadOptions};
+
+/// Structure of the document opened with `open_ooxml_lazy`, holding the unparsed chunks
+/// Taken before DOCUMENT whenever both are held
+static LAZY_DOCUMENT: Lazy<RwLock<Option<LazyDocument>>> = Lazy::new(|| RwLock::new(None));
+
+/// Open a .docx into the editor, parsing only its structure up front
+/// Formatting is applied as ranges are loaded with `ensure_range_loaded`.
+/// `chunk_paragraphs` of 0 uses the default chunk size.
+/// Returns JSON with the outline, sections and paragraph count
+pub fn open_ooxml_lazy(file_data: &[u8], chunk_paragraphs: usize) -> String {
+    let mut options = LazyLoadOptions::default();
+    if chunk_paragraphs > 0 {
+        options.chunk_paragraphs = chunk_paragraphs;
+    }
+
+    let opened = OpcPackage::open(Arc::from(file_data))
+        .and_then(|package| LazyDocument::from_package(&package, options).map(|lazy| (package, lazy)));
+    match opened {
+        Ok((package, mut lazy)) => {
+            let summary = serde_json::json!({
+                "paragraph_count": lazy.paragraphs().len(),
+                "chunk_count": lazy.chunk_count(),
+                "outline": lazy.outline(),
+                "sections": lazy.sections(),
+            });
+
+            let mut current = LAZY_DOCUMENT.write().unwrap();
+            let mut doc = DOCUMENT.write().unwrap();
+            doc.replace_with(Document::empty());
+            doc.end = DocumentEnd::from_ooxml(lazy.final_mark().cloned(), lazy.section_properties());
+            doc.content = lazy.piece_tree();
+            doc.page_setup = PageSetup::from_sections(lazy.section_properties());
+            doc.forms.set_protection(lazy.protection());
+            doc.update_metadata();
+            doc.mark_saved();
+            *MEDIA_SOURCE.lock().unwrap() = MediaSource::Package(Box::new(package));
+            *current = Some(lazy);
+            summary.to_string()
+        }
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+/// Parse and style the chunks covering chars [offset, offset + length), e.g. the viewport
+/// Returns the number of chunks parsed by this call
+pub fn ensure_range_loaded(offset: usize, length: usize) -> usize {
+    let mut lazy = LAZY_DOCUMENT.write().unwrap();
+    let Some(lazy) = lazy.as_mut() else {
+        return 0;
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    let loaded = lazy.load_range(&mut doc.content, offset, offset + length);
+    if loaded > 0 {
+        doc.paragraph_hashes.invalidate();
+        // Loading applies formatting the file already had; it is not an edit
+        if !MODIFICATION.lock().unwrap().is_dirty() {
+            doc.mark_saved();
+        }
+    }
+    loaded
+}
+
+/// Get the outline of the lazily opened document
+/// Returns JSON array of headings, or "[]" when no document is open lazily
+pub fn get_lazy_outline() -> String {
+    match LAZY_DOCUMENT.read().unwrap().as_ref() {
+        Some(lazy) => serde_json::to_string(lazy.outline()).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        None => "[]".to_string(),
+    }
+}
+
+// ==================== Streaming Open APIs ====================
+
+use crate::ooxml::{resolve_part_name, OpcPackage, Paragraph, StreamingDocument};
+
+/// Where the images of the open document are read from
+enum MediaSource {
+    /// Nothing the document was opened from holds any
+    None,
+    /// Package of the document opened with `open_ooxml_lazy` or
+    /// `start_ooxml_load`, with its archive, which saves copy the unchanged parts of
+    Package(Box<OpcPackage>),
+    /// Archive of the document opened with `open_ooxml_streaming`, read on demand
+    Streamed(Box<StreamingDocument<std::io::BufReader<fs::File>>>),
+    /// Pictures of the document opened with `open_legacy_doc`, by image path
+    Legacy(HashMap<String, Vec<u8>>),
+    /// Images of the document opened with `open_odt`, by image path
+    Odt(HashMap<String, Vec<u8>>),
+}
+
+/// Media of the open document; reset whenever another replaces it
+static MEDIA_SOURCE: Lazy<Mutex<MediaSource>> = Lazy::new(|| Mutex::new(MediaSource::None));
+
+/// Open a .docx file into the editor without reading it into memory
+/// The body is parsed paragraph by paragraph while the file is decompressed;
+/// images are read with `load_streamed_media` when needed.
+/// Returns JSON with the paragraph, table and image counts and the image paths
+pub fn open_ooxml_streaming(path: String) -> String {
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => return format!("File error: {}", e),
+    };
+
+    match StreamingDocument::open(std::io::BufReader::new(file)) {
+        Ok(mut streamed) => {
+            let parsed = streamed.document();
+            let summary = serde_json::json!({
+                "paragraph_count": streamed.paragraph_count(),
+                "table_count": parsed.tables.len(),
+                "images": parsed.images.iter().map(|image| &image.path).collect::<Vec<_>>(),
+            });
+
+            let content = streamed.take_piece_tree();
+            let model = DocumentModel::from_word_document(streamed.document());
+            let paragraphs: Vec<(usize, &Paragraph)> =
+                streamed.anchored_paragraphs().iter().map(|(start, paragraph)| (*start, paragraph)).collect();
+            let opened = Document::from_body(&model, content, &paragraphs, streamed.final_mark().cloned());
+
+            let mut doc = DOCUMENT.write().unwrap();
+            doc.replace_with(opened);
+            doc.mark_saved();
+            *MEDIA_SOURCE.lock().unwrap() = MediaSource::Streamed(Box::new(streamed));
+            summary.to_string()
+        }
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+/// Read a media part, e.g. an image path from `open_ooxml_streaming`, from the open file
+/// Returns an empty Vec if no document is open this way or the part is missing
+pub fn load_streamed_media(path: String) -> Vec<u8> {
+    match &mut *MEDIA_SOURCE.lock().unwrap() {
+        MediaSource::Streamed(streamed) => streamed.media(&path).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+// ==================== Background Open APIs ====================
+
+use crate::ooxml::{parse_ooxml_async, LoadControl, LoadJob, LoadProgress};
+
+/// The background load started by `start_ooxml_load`, until its result is taken
+static LOAD_JOB: Lazy<Mutex<Option<LoadJob>>> = Lazy::new(|| Mutex::new(None));
+/// Last progress reported by that load
+static LOAD_PROGRESS: Lazy<Mutex<Option<LoadProgress>>> = Lazy::new(|| Mutex::new(None));
+
+/// Start parsing a .docx on background threads, cancelling any load still running
+/// Poll `get_ooxml_load_progress` for a loading bar and `take_loaded_ooxml_document` for the result
+pub fn start_ooxml_load(file_data: Vec<u8>) {
+    let mut job = LOAD_JOB.lock().unwrap();
+    if let Some(previous) = job.take() {
+        previous.cancel();
+    }
+    *LOAD_PROGRESS.lock().unwrap() = None;
+    let control = LoadControl::new().with_progress(|progress| {
+        *LOAD_PROGRESS.lock().unwrap() = Some(progress.clone());
+    });
+    *job = Some(parse_ooxml_async(file_data, control));
+}
+
+/// Get the progress of the background load
+/// Returns JSON with the step just finished and the completed and total step counts,
+/// or "null" before the first step
+pub fn get_ooxml_load_progress() -> String {
+    serde_json::to_string(&*LOAD_PROGRESS.lock().unwrap()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Cancel the background load
+pub fn cancel_ooxml_load() {
+    if let Some(job) = LOAD_JOB.lock().unwrap().as_ref() {
+        job.cancel();
+    }
+}
+
+/// Take the result of the background load once it has finished and open it in the editor
+/// Returns the parsed document JSON as `load_ooxml_from_bytes` does, an empty string
+/// while the load is still running, or "OOXML error: ..." (also after cancellation)
+pub fn take_loaded_ooxml_document() -> String {
+    let mut job = LOAD_JOB.lock().unwrap();
+    match job.as_ref() {
+        None => return "OOXML error: No document is loading".to_string(),
+        Some(running) if !running.is_finished() => return String::new(),
+        Some(_) => {}
+    }
+    match job.take().unwrap().wait() {
+        Ok(loaded) => {
+            let mut doc = DOCUMENT.write().unwrap();
+            doc.replace_with(Document::from_model(&DocumentModel::from_word_document(&loaded.word_document)));
+            doc.mark_saved();
+            *MEDIA_SOURCE.lock().unwrap() = MediaSource::Package(Box::new(loaded.package));
+            serde_json::to_string(&loaded.document).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("OOXML error: {}", e),
+    }
+}
+
+// ==================== Legacy .doc APIs ====================
+
+use crate::ooxml::{ooxml_to_piece_tree, parse_doc};
+
+/// Open a Word 97-2003 (.doc) file into the editor
+/// Returns the parsed document JSON as `load_ooxml_from_bytes` does; its images
+/// are read with `load_legacy_doc_media`
+pub fn open_legacy_doc(path: String) -> String {
+    let file_data = match fs::read(&path) {
+        Ok(file_data) => file_data,
+        Err(e) => return format!("File error: {}", e),
+    };
+
+    match parse_doc(&file_data) {
+        Ok(legacy) => {
+            let parsed = &legacy.word_document;
+            let mut doc = DOCUMENT.write().unwrap();
+            doc.replace_with(Document::empty());
+            doc.styles = StyleSheet::from_ooxml_styles(&parsed.styles);
+            doc.content = ooxml_to_piece_tree(parsed);
+            doc.page_setup = PageSetup::from_sections(&parsed.sections);
+            let mark = parsed.paragraphs.last().and_then(|paragraph| paragraph.mark_properties.clone());
+            doc.end = DocumentEnd::from_ooxml(mark, &parsed.sections);
+            doc.update_metadata();
+            doc.mark_saved();
+            *MEDIA_SOURCE.lock().unwrap() = MediaSource::Legacy(legacy.media);
+            serde_json::to_string(&legacy.document).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("DOC error: {}", e),
+    }
+}
+
+/// Read a picture of the document opened with `open_legacy_doc`, by its image path
+/// Returns an empty Vec if no .doc is open or it has no such picture
+pub fn load_legacy_doc_media(path: String) -> Vec<u8> {
+    match &*MEDIA_SOURCE.lock().unwrap() {
+        MediaSource::Legacy(media) => media.get(&path).cloned().unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+// ==================== ODT APIs ====================
+
+use crate::ooxml::{export_odt, parse_odt};
+
+/// Open an OpenDocument text (.odt) file into the editor
+/// Returns the parsed document JSON as `open_legacy_doc` does; its images
+/// are read with `load_odt_media`
+pub fn open_odt(path: String) -> String {
+    let file_data = match fs::read(&path) {
+        Ok(file_data) => file_data,
+        Err(e) => return format!("File error: {}", e),
+    };
+
+    match parse_odt(&file_data) {
+        Ok(odt) => {
+            load_document_model(DocumentModel::from_word_document(&odt.word_document));
+            DOCUMENT.write().unwrap().mark_saved();
+            *MEDIA_SOURCE.lock().unwrap() = MediaSource::Odt(odt.media);
+            serde_json::to_string(&odt.document).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("ODT error: {}", e),
+    }
+}
+
+/// Read an image of the document opened with `open_odt`, by its image path
+/// Returns an empty Vec if no .odt is open or it has no such image
+pub fn load_odt_media(path: String) -> Vec<u8> {
+    match &*MEDIA_SOURCE.lock().unwrap() {
+        MediaSource::Odt(media) => media.get(&path).cloned().unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Export the current document to .odt bytes, with the images of the .odt it was opened from
+/// Returns an empty Vec on error
+pub fn export_current_document_odt() -> Vec<u8> {
+    let model = DOCUMENT.read().unwrap().to_model();
+    let source = MEDIA_SOURCE.lock().unwrap();
+    let no_media = HashMap::new();
+    let media = match &*source {
+        MediaSource::Odt(media) => media,
+        _ => &no_media,
+    };
+    match export_odt(&model, media) {
+        Ok(data) => data,
+        Err(e) => {
+            log::warn!("ODT export failed: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+// ==================== Library Search APIs ====================
+
+use crate::library_index::LibraryIndexer;
+use std::path::PathBuf;
+
+/// The indexer of the open library, if any
+static LIBRARY_INDEXER: Lazy<Mutex<Option<LibraryIndexer>>> = Lazy::new(|| Mutex::new(None));
+
+/// Start indexing a document library, keeping the index at `store_path`
+/// An empty path keeps the index in memory only. Replaces any open library
+pub fn open_library_index(store_path: String) {
+    let store = (!store_path.is_empty()).then(|| PathBuf::from(store_path));
+    let mut indexer = LIBRARY_INDEXER.lock().unwrap();
+    // Let the old worker finish and save before the new one loads the store
+    indexer.take();
+    *indexer = Some(LibraryIndexer::new(store));
+}
+
+/// Index every .docx under `folder` in the background
+/// Returns false if no library is open
+pub fn add_library_folder(folder: String) -> bool {
+    with_library(|indexer| indexer.add_folder(folder))
+}
+
+/// Re-index a file the host's file watcher reports as created or modified
+pub fn notify_library_file_changed(path: String) -> bool {
+    with_library(|indexer| indexer.notify_changed(path))
+}
+
+/// Drop a file the host's file watcher reports as deleted or moved away
+pub fn notify_library_file_removed(path: String) -> bool {
+    with_library(|indexer| indexer.notify_removed(path))
+}
+
+/// Search the library
+/// Returns JSON array of hits, best first, each with the char ranges of the
+/// matched words in the document's text
+pub fn search_library(query: String, limit: usize) -> String {
+    let hits = LIBRARY_INDEXER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|indexer| indexer.search(&query, limit))
+        .unwrap_or_default();
+    serde_json::to_string(&hits).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Get the progress of background indexing as JSON
+pub fn get_library_index_status() -> String {
+    let status = LIBRARY_INDEXER.lock().unwrap().as_ref().map(LibraryIndexer::status).unwrap_or_default();
+    serde_json::to_string(&status).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+fn with_library(f: impl FnOnce(&LibraryIndexer)) -> bool {
+    match LIBRARY_INDEXER.lock().unwrap().as_ref() {
+        Some(indexer) => {
+            f(indexer);
+            true
+        }
+        None => false,
+    }
+}
+
+// ==================== Export APIs ====================
+
+use crate::ooxml::{
+    export_snapshot_docx, export_snapshot_docx_incremental, export_snapshot_html, export_snapshot_text, ExportContent, ExportControl, ExportFormat, ExportOptions,
+    LayoutStatistics, PackageCompression, PartCompression, PlainTextOptions,
+};
+use crate::piece_tree::TextSnapshot;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Control of the export in progress, so the UI can cancel it
+static EXPORT_CONTROL: Lazy<Mutex<ExportControl>> = Lazy::new(|| Mutex::new(ExportControl::new()));
+/// Progress of the export in progress, as f32 bits
+static EXPORT_PROGRESS: AtomicU32 = AtomicU32::new(0);
+/// Whether .docx exports record document statistics and editing time in app.xml
+static EXPORT_STATISTICS: AtomicBool = AtomicBool::new(true);
+/// How .docx exports compress their ZIP entries
+static EXPORT_COMPRESSION: Lazy<Mutex<PackageCompression>> = Lazy::new(|| Mutex::new(PackageCompression::default()));
+
+/// Record word, character, paragraph, page and line counts and the editing
+/// time in exported .docx files (the default), or leave them out for privacy
+pub fn set_export_document_statistics(enabled: bool) {
+    EXPORT_STATISTICS.store(enabled, Ordering::Relaxed);
+}
+
+/// Compress the XML and other parts of exported .docx files at deflate
+/// `level` (0 fastest to 9 smallest, the default), and store JPEG, PNG and
+/// other compressed media as they are unless `deflate_media` is set
+pub fn set_export_compression(level: i32, deflate_media: bool) {
+    let mut compression = EXPORT_COMPRESSION.lock().unwrap();
+    compression.deflate_level = level.clamp(0, 9);
+    compression.compressed_media = match deflate_media {
+        true => PartCompression::Deflated,
+        false => PartCompression::Stored,
+    };
+}
+
+/// What .docx exports of the current document write to app.xml: the counts
+/// themselves are taken from the document being saved, pages and lines from
+/// the latest finished pagination
+fn docx_export_options() -> ExportOptions {
+    let status = REPAGINATOR.status();
+    let doc = DOCUMENT.read().unwrap();
+    // The serializer gives it to the last section, which the body's sectPr ends
+    let (opened_at, page_setup) = (doc.metadata.opened_at, doc.page_setup.sections.last().copied());
+    drop(doc);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    ExportOptions {
+        document_statistics: EXPORT_STATISTICS.load(Ordering::Relaxed),
+        layout_statistics: status.complete.then_some(LayoutStatistics { pages: status.page_count, lines: status.line_count }),
+        editing_minutes: now.saturating_sub(opened_at) / 60,
+        compression: *EXPORT_COMPRESSION.lock().unwrap(),
+        page_setup,
+        ..Default::default()
+    }
+}
+
+/// Export the current document to .docx bytes
+/// The document is only locked while taking a snapshot, so editing can continue
+/// during the export. A document opened with `open_ooxml_lazy` or
+/// `start_ooxml_load` is saved over the package it came from: parts the editor
+/// does not write are kept, and those unchanged are copied from the file
+/// without being compressed again. Returns an empty Vec on error or cancellation
+pub fn export_current_document_docx() -> Vec<u8> {
+    let mut options = docx_export_options();
+    let (snapshot, mut content, last_edit) = export_snapshot();
+    if let Some(position) = last_edit {
+        options.document_variables.push((LAST_EDIT_VARIABLE.to_string(), position.to_string()));
+    }
+    let package = match &*MEDIA_SOURCE.lock().unwrap() {
+        MediaSource::Package(package) => Some(OpcPackage::clone(package)),
+        _ => None,
+    };
+    let control = start_export();
+    let exported = match package {
+        Some(package) => {
+            // The package gives its own images, which are then copied as they were
+            content.media.retain(|path, _| package.get_part(&resolve_part_name("/word/", path)).is_none());
+            export_snapshot_docx_incremental(&snapshot, &content, package, Some(options), &control)
+        }
+        None => export_snapshot_docx(&snapshot, &content, Some(options), &control),
+    };
+    match exported {
+        Ok(data) => data,
+        Err(e) => {
+            log::warn!("Export failed: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Save the current document as an autosave sidecar: a .docx package with
+/// its parts in Zstandard, quick to write but only readable by Velum
+/// Runs apart from `export_current_document_docx`, so it neither reports
+/// progress nor is cancelled with it. Returns an empty Vec on error
+pub fn export_current_document_autosave() -> Vec<u8> {
+    let options = ExportOptions { compression: PackageCompression::autosave(), ..docx_export_options() };
+    let (snapshot, content, _) = export_snapshot();
+    match export_snapshot_docx(&snapshot, &content, Some(options), &ExportControl::new()) {
+        Ok(data) => data,
+        Err(e) => {
+            log::warn!("Autosave failed: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Export the current document as a standalone HTML page, CSS and images inline
+/// Progress and cancellation work as for `export_current_document_docx`.
+/// Returns an empty string on error or cancellation
+pub fn export_current_document_html() -> String {
+    let (snapshot, content, _) = export_snapshot();
+    let control = start_export();
+    match export_snapshot_html(&snapshot, &content, None, &control) {
+        Ok(export) => export.html,
+        Err(e) => {
+            log::warn!("HTML export failed: {}", e);
+            String::new()
+        }
+    }
+}
+
+/// Export the current document as plain text
+/// `options_json` is a PlainTextOptions object, e.g. {"line_ending":"cr_lf",
+/// "wrap_width":468.0,"tables":"tabs","notes":"inline","list_markers":false};
+/// fields left out keep their defaults. Progress and cancellation work as for
+/// `export_current_document_docx`. Returns "Error: ..." on bad options, and an
+/// empty string on export error or cancellation
+pub fn export_current_document_text(options_json: String) -> String {
+    let plain_text: PlainTextOptions = match serde_json::from_str(&options_json) {
+        Ok(options) => options,
+        Err(e) => return format!("Error: invalid options: {}", e),
+    };
+    let options = ExportOptions { format: ExportFormat::PlainText, plain_text, ..Default::default() };
+    let (snapshot, content, _) = export_snapshot();
+    let control = start_export();
+    match export_snapshot_text(&snapshot, &content, Some(options), &control) {
+        Ok(text) => text,
+        Err(e) => {
+            log::warn!("Text export failed: {}", e);
+            String::new()
+        }
+    }
+}
+
+/// Snapshot the current document with what an export writes besides its
+/// text, and its last edit position
+fn export_snapshot() -> (TextSnapshot, ExportContent, Option<usize>) {
+    let doc = DOCUMENT.read().unwrap();
+    let (headers, footers, sections) = doc.headers_footers.to_ooxml();
+    let mut content = ExportContent {
+        revisions: doc.revisions.revisions().to_vec(),
+        comments: doc.comments.comments().to_vec(),
+        bookmarks: doc.bookmarks.bookmarks().to_vec(),
+        hyperlinks: doc.hyperlinks.hyperlinks().to_vec(),
+        math_zones: doc.math_zones.zones().to_vec(),
+        form_fields: match doc.forms.is_empty() {
+            true => Vec::new(),
+            false => doc.forms.anchored(&doc.content.get_text()),
+        },
+        protection: doc.forms.protection(),
+        fields: doc.index.fields(),
+        styles: doc.styles.to_ooxml_styles(),
+        numbering: doc.numbering.to_ooxml(),
+        paragraphs: (0..doc.content.paragraph_count())
+            .map(|index| doc.content.paragraph_attributes(index).cloned())
+            .collect(),
+        headers,
+        footers,
+        sections,
+        even_and_odd_headers: doc.headers_footers.different_odd_even(),
+        // Tables are kept out of the editor text
+        tables: Vec::new(),
+        images: match doc.floating.is_empty() {
+            true => Vec::new(),
+            false => doc.floating.located(&paragraph_lengths(&doc.content.get_text())),
+        },
+        media: HashMap::new(),
+        final_mark: None,
+    };
+    doc.end.add_to_export(&mut content);
+    let (snapshot, edit) = (doc.content.snapshot(), doc.edit_locations.last());
+    drop(doc);
+    // Images inserted since opening are only in the image cache; the media of
+    // the opened file is read outside the document lock
+    for image in content.images.iter().filter(|image| !image.is_linked) {
+        if let Ok(data) = DEFAULT_IMAGE_CACHE.get_or_load(&image.path, || media_bytes(&image.path).ok_or(ImageError::NotFound)) {
+            content.media.insert(image.path.clone(), data);
+        }
+    }
+    (snapshot, content, edit)
+}
+
+/// Reset the export progress and make a control the UI can cancel
+fn start_export() -> ExportControl {
+    EXPORT_PROGRESS.store(0f32.to_bits(), Ordering::Relaxed);
+    let control = ExportControl::new()
+        .with_progress(|&fraction| EXPORT_PROGRESS.store(fraction.to_bits(), Ordering::Relaxed));
+    *EXPORT_CONTROL.lock().unwrap() = control.clone();
+    control
+}
+
+/// Get the progress of the current export, from 0.0 to 1.0
+pub fn get_export_progress() -> f32 {
+    f32::from_bits(EXPORT_PROGRESS.load(Ordering::Relaxed))
+}
+
+/// Cancel the current export
+pub fn cancel_export() {
+    EXPORT_CONTROL.lock().unwrap().cancel();
+}
+
+// ==================== PDF APIs ====================
+
+use crate::font_license::FontLicensePolicy;
+use crate::page_layout::Rect;
+use crate::pdf::{
+    page_from_layout, write_pdf, ParagraphContent, PdfColor, PdfDocument, PdfFonts, PdfLinkTarget, PdfMetadata,
+    PdfOptions, PdfOutlineItem, PdfSpan, PdfTextStyle, StoryAnchor,
+};
+
+/// Fonts PDF export embeds, registered by the UI from the system's font files
+static PDF_FONTS: Lazy<Mutex<PdfFonts>> = Lazy::new(|| Mutex::new(PdfFonts::new()));
+
+/// Register a TrueType or OpenType font file for PDF export, for a family and style
+/// Text is drawn in the registered font of its family and style, or the
+/// closest; without any, PDF export falls back to the standard Helvetica fonts.
+/// Returns "OK", "Warning: ..." for a font whose license keeps it out of
+/// exports under the current policy, or "Error: ..."
+pub fn register_pdf_font(family: String, bold: bool, italic: bool, data: Vec<u8>) -> String {
+    let mut fonts = PDF_FONTS.lock().unwrap();
+    if let Err(e) = fonts.register(&family, bold, italic, data) {
+        return format!("Error: {}", e);
+    }
+    let licenses = fonts.licenses();
+    let registered = licenses
+        .iter()
+        .find(|license| license.family.eq_ignore_ascii_case(&family) && license.bold == bold && license.italic == italic);
+    match registered.filter(|license| !license.embedded) {
+        Some(license) => format!(
+            "Warning: {} will not be embedded as {}; suggested instead: {}",
+            family,
+            license.license.restriction().unwrap_or_default(),
+            license.suggestions.join(", ")
+        ),
+        None => "OK".to_string(),
+    }
+}
+
+/// Embedding permissions of the fonts registered for PDF export as a JSON array
+/// of {family, bold, italic, postscript_name, license, embedded, suggestions}
+pub fn get_pdf_font_licenses() -> String {
+    serde_json::to_string(&PDF_FONTS.lock().unwrap().licenses()).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Set what PDF export does with fonts whose license does not allow embedding:
+/// "refuse" (the default) draws their text in other fonts, "warn" embeds them
+/// with a warning. Returns "OK" or "Error: ..."
+pub fn set_pdf_font_license_policy(policy: String) -> String {
+    match serde_json::from_value::<FontLicensePolicy>(serde_json::Value::String(policy.trim().to_string())) {
+        Ok(policy) => {
+            PDF_FONTS.lock().unwrap().set_license_policy(policy);
+            "OK".to_string()
+        }
+        Err(_) => format!("Error: Unknown font license policy '{}'", policy),
+    }
+}
+
+/// How the text of a story is drawn: its formatting, and for the body its
+/// hyperlinks and bookmarks, by paragraph of `text`
+fn pdf_content(tree: &PieceTree, text: &str, links: Option<(&HyperlinkSet, &BookmarkRegistry)>) -> Vec<ParagraphContent> {
+    let mut contents = Vec::new();
+    let mut paragraph_start = 0;
+    for paragraph in text.split('\n') {
+        let char_count = paragraph.chars().count();
+        let spans = tree.attribute_spans(paragraph_start..paragraph_start + char_count);
+        let mut attributes = spans.iter().flat_map(|span| std::iter::repeat_n(&span.attributes, span.length));
+        let mut content = ParagraphContent::default();
+        for (index, (byte, c)) in paragraph.char_indices().enumerate() {
+            let offset = paragraph_start + index;
+            let style = pdf_text_style(attributes.next().and_then(Option::as_ref));
+            let link = links.and_then(|(hyperlinks, _)| hyperlinks.at(offset)).and_then(|hyperlink| {
+                match (&hyperlink.url, &hyperlink.anchor) {
+                    (Some(url), _) => Some(PdfLinkTarget::Uri(url.clone())),
+                    (None, Some(anchor)) => Some(PdfLinkTarget::Anchor(anchor.clone())),
+                    (None, None) => None,
+                }
+            });
+            match content.spans.last_mut() {
+                Some(span) if span.style == style && span.link == link => span.range.end = byte + c.len_utf8(),
+                _ => content.spans.push(PdfSpan { range: byte..byte + c.len_utf8(), style, link }),
+            }
+        }
+        if let Some((_, bookmarks)) = links {
+            for bookmark in bookmarks.bookmarks() {
+                if (paragraph_start..=paragraph_start + char_count).contains(&bookmark.start) {
+                    let byte = paragraph.char_indices().nth(bookmark.start - paragraph_start).map_or(paragraph.len(), |(byte, _)| byte);
+                    content.anchors.push((byte, bookmark.name.clone()));
+                }
+            }
+        }
+        contents.push(content);
+        paragraph_start += char_count + 1;
+    }
+    contents
+}
+
+fn pdf_text_style(attributes: Option<&TextAttributes>) -> PdfTextStyle {
+    let Some(attributes) = attributes else {
+        return PdfTextStyle::default();
+    };
+    PdfTextStyle {
+        font: attributes.font_family.clone(),
+        size: attributes.font_size.map_or(PdfTextStyle::default().size, f32::from),
+        bold: attributes.bold.unwrap_or(false),
+        italic: attributes.italic.unwrap_or(false),
+        underline: attributes.underline.unwrap_or(false),
+        color: attributes.foreground.as_deref().and_then(PdfColor::from_hex).unwrap_or_default(),
+    }
+}
+
+/// Export the current document as PDF: its pages as laid out, with the
+/// registered fonts embedded, hyperlinks, an outline of its headings and its metadata
+/// Returns an empty Vec on error
+pub fn export_current_document_pdf() -> Vec<u8> {
+    let doc = DOCUMENT.read().unwrap();
+    let section = &doc.page_setup.sections[0];
+    let (mut page_layout, sections) = doc.page_layout();
+    let text = doc.content.get_text();
+    let props = paragraph_layout_properties(&doc);
+    let mut line_layout = LineLayout::new();
+    line_layout.set_break_strategy(doc.break_strategy);
+    let mut layout = line_layout.layout_document_with_paragraph_props(&text, page_layout.column_width(), &props);
+    let model = page_layout.layout_sections(&layout.paragraphs, &sections);
+    // Text flows around floating images where they land on the pages as laid out without them
+    let placed = doc.floating.place(&paragraph_lengths(&text), &model.pages, &layout.paragraphs);
+    let exclusions = Exclusions::from_placed(&doc.floating, &placed);
+    let model = page_layout.layout_sections_around(&mut layout.paragraphs, &sections, &exclusions, &mut line_layout);
+
+    let mut content = pdf_content(&doc.content, &text, Some((&doc.hyperlinks, &doc.bookmarks)));
+    // Headings are anchored for the outline to go to
+    let mut outline = Vec::new();
+    for (index, paragraph) in effective_paragraphs(&doc).iter().enumerate() {
+        let Some(level) = accessibility::heading_level(&doc.styles, paragraph.style_id.as_deref()) else {
+            continue;
+        };
+        let Some(title) = layout.paragraphs.get(index).map(|paragraph| paragraph.text.trim().to_string()) else {
+            continue;
+        };
+        if title.is_empty() {
+            continue;
+        }
+        let anchor = format!("_Heading{}", index);
+        content[index].anchors.push((0, anchor.clone()));
+        outline.push(PdfOutlineItem { title, level, anchor });
+    }
+
+    let mut pdf_pages = Vec::with_capacity(model.page_count());
+    for (index, page) in model.pages.iter().enumerate() {
+        let config = model.page_config(page);
+        let mut pdf_page = page_from_layout(page, config, &layout.paragraphs, &content);
+        let page_number = index as u32 + 1;
+        let margins = &section.margins;
+        let width = config.content_width();
+        for (kind, region, anchor) in [
+            (HeaderFooterKind::Header, Rect::new(margins.left, margins.header, width, margins.top - margins.header), StoryAnchor::Top),
+            (
+                HeaderFooterKind::Footer,
+                Rect::new(margins.left, config.height - margins.bottom, width, margins.bottom - margins.footer),
+                StoryAnchor::Bottom,
+            ),
+        ] {
+            let Some(story) = doc.headers_footers.story_for_page(0, kind, index == 0, page_number) else {
+                continue;
+            };
+            let story_text = story.get_text();
+            let story_layout = LineLayout::new().layout_document(&story_text, width);
+            pdf_page.place_story(region, &story_layout.paragraphs, &pdf_content(story, &story_text, None), anchor);
+        }
+        pdf_pages.push(pdf_page);
+    }
+
+    let timestamp = |seconds: u64| chrono::DateTime::from_timestamp(seconds as i64, 0);
+    let metadata = PdfMetadata {
+        title: Some(doc.metadata.title.clone()).filter(|title| !title.is_empty()),
+        author: Some(doc.metadata.author.clone()).filter(|author| !author.is_empty()),
+        creator: Some("Velum".to_string()),
+        created: timestamp(doc.metadata.created_at),
+        modified: timestamp(doc.metadata.modified_at),
+        ..Default::default()
+    };
+    let document = PdfDocument { pages: pdf_pages, outline, metadata };
+    let export = write_pdf(&document, &PDF_FONTS.lock().unwrap(), &HashMap::new(), &PdfOptions::default());
+    for warning in &export.warnings {
+        log::warn!("PDF export: {}", warning);
+    }
+    export.data
+}
+
+// ==================== Floating Object APIs ====================
+
+use crate::floating::{AnchorBehavior, FloatingError};
+use crate::ooxml::ImageTransform;
+use crate::text_wrap::Exclusions;
+use crate::line_layout::ParagraphLayout;
+use crate::page_layout::Page;
+
+/// Char count of each paragraph of `text`
+fn paragraph_lengths(text: &str) -> Vec<usize> {
+    text.split('\n').map(|paragraph| paragraph.chars().count()).collect()
+}
+
+/// Lay out the document on its pages, for placing floating objects
+fn floating_layout(doc: &Document) -> (Vec<usize>, Vec<Page>, Vec<ParagraphLayout>) {
+    let (mut page_layout, sections) = doc.page_layout();
+    let text = doc.content.get_text();
+    let props = paragraph_layout_properties(doc);
+    let mut line_layout = LineLayout::new();
+    line_layout.set_break_strategy(doc.break_strategy);
+    let layout = line_layout.layout_document_with_paragraph_props(&text, page_layout.column_width(), &props);
+    let pages = page_layout.layout_sections(&layout.paragraphs, &sections).pages;
+    (paragraph_lengths(&text), pages, layout.paragraphs)
+}
+
+/// Get where each floating image is drawn: its index among the document's
+/// images, page index, rectangle in points from the page's top left, and
+/// whether it moves with text or is fixed on the page
+pub fn get_floating_objects() -> String {
+    let doc = DOCUMENT.read().unwrap();
+    let (lengths, pages, paragraphs) = floating_layout(&doc);
+    serde_json::to_string(&doc.floating.place(&lengths, &pages, &paragraphs)).unwrap_or_else(|e| format!("JSON error: {}", e))
+}
+
+/// Make floating image `index` move with text ("move_with_text") or stay fixed
+/// on the page ("fixed_on_page"), keeping it where it is drawn now
+/// Its wp:positionV, and for a char-relative one wp:positionH, are rewritten to
+/// match, so exports say the same. Returns the new anchor, or "Error: ..."
+pub fn set_floating_object_anchoring(index: usize, behavior: String) -> String {
+    let behavior: AnchorBehavior = match serde_json::from_value(serde_json::Value::String(behavior)) {
+        Ok(behavior) => behavior,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    let (lengths, pages, paragraphs) = floating_layout(&doc);
+    match doc.floating.set_behavior(index, behavior, &lengths, &pages, &paragraphs) {
+        Ok(anchor) => {
+            doc.track_modification();
+            serde_json::to_string(&anchor).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("Error: {}", e),
+    }
 }
 
-/// Gets the height needed for text at given width
-pub fn get_text_height(text: &str, width: f32, line_height: f32, font_size: f32) -> f32 {
-    use crate::line_layout::measure;
-    measure::get_text_height(text, width, line_height, font_size)
+/// Get the crop, rotation, flips and picture adjustments of image `index`
+/// among the document's images, or "Error: ..."
+pub fn get_image_transform(index: usize) -> String {
+    let doc = DOCUMENT.read().unwrap();
+    match doc.floating.get(index) {
+        Some(image) => serde_json::to_string(&image.transform).unwrap_or_else(|e| format!("JSON error: {}", e)),
+        None => format!("Error: {}", FloatingError::NotFound(index)),
+    }
 }
 
-/// Layouts the current document state and returns JSON layout information
-pub fn layout_current_document(width: f32) -> String {
-    let doc = DOCUMENT.read().unwrap();
-    let text = doc.content.get_text();
-    let mut layout = LineLayout::new();
-    layout.layout_to_json(&text, width)
+/// Crop, turn, flip or adjust image `index` with a transform as JSON, e.g.
+/// {"crop":{"left":10,"top":0,"right":10,"bottom":0},"rotation":90,
+/// "flip_horizontal":true,"brightness":20,"contrast":0,"transparency":0}
+/// Values out of range are brought in, and cropping resizes the image with
+/// the part of its picture shown. Returns the transform set, or "Error: ..."
+pub fn set_image_transform(index: usize, transform_json: String) -> String {
+    let transform: ImageTransform = match serde_json::from_str(&transform_json) {
+        Ok(transform) => transform,
+        Err(e) => return format!("Error: {}", e),
+    };
+    let mut doc = DOCUMENT.write().unwrap();
+    match doc.floating.set_transform(index, &transform) {
+        Ok(transform) => {
+            doc.track_modification();
+            serde_json::to_string(&transform).unwrap_or_else(|e| format!("JSON error: {}", e))
+        }
+        Err(e) => format!("Error: {}", e),
+    }
 }
 
-// ==================== OOXML Document APIs ====================
+// ==================== Image Pixel APIs ====================
 
-use crate::ooxml::{parse_ooxml, ParsedDocument};
+use crate::image::{ImageError, DEFAULT_IMAGE_CACHE};
 
-/// Load and parse an OOXML (.docx) document from file path
-/// Returns JSON string containing extracted text, styles, and metadata
-pub fn load_ooxml_document(file_path: &str) -> String {
-    match std::fs::read(file_path) {
-        Ok(file_data) => {
-            match parse_ooxml(&file_data) {
-                Ok(document) => {
-                    serde_json::to_string(&document).unwrap_or_else(|e| format!("JSON error: {}", e))
-                }
-                Err(e) => format!("OOXML error: {}", e),
-            }
-        }
-        Err(e) => format!("File error: {}", e),
+/// File data of an image of the open document, by its image path, from
+/// the streamed .docx, .doc or .odt it was opened from
+fn media_bytes(path: &str) -> Option<Vec<u8>> {
+    match &mut *MEDIA_SOURCE.lock().unwrap() {
+        MediaSource::None => None,
+        MediaSource::Package(package) => package.get_part(&resolve_part_name("/word/", path)).map(|part| part.data.clone()),
+        MediaSource::Streamed(streamed) => streamed.media(path).ok(),
+        MediaSource::Legacy(media) | MediaSource::Odt(media) => media.get(path).cloned(),
     }
 }
 
-/// Load and parse an OOXML (.docx) document from raw bytes
-/// Returns JSON string containing extracted text, styles, and metadata
-pub fn load_ooxml_from_bytes(file_data: &[u8]) -> String {
-    match parse_ooxml(file_data) {
-        Ok(document) => {
-            serde_json::to_string(&document).unwrap_or_else(|e| format!("JSON error: {}", e))
+/// Decode an image of the open document, by its image path, to RGBA pixels,
+/// shrunk to fit within max_width x max_height pixels (0 for either keeps
+/// the image's own size)
+/// Images are decoded once and kept in the image cache, so asking again for
+/// the same size is cheap. Returns the width and height as 4-byte big-endian
+/// numbers followed by the pixel rows top to bottom, four bytes a pixel, or
+/// an empty Vec if there is no such image or it cannot be decoded
+pub fn get_image_pixels(path: String, max_width: u32, max_height: u32) -> Vec<u8> {
+    let fit = match max_width > 0 && max_height > 0 {
+        true => Some((max_width, max_height)),
+        false => None,
+    };
+    let pixels = DEFAULT_IMAGE_CACHE
+        .get_or_load(&path, || media_bytes(&path).ok_or(ImageError::NotFound))
+        .and_then(|_| DEFAULT_IMAGE_CACHE.pixels(&path, fit));
+    match pixels {
+        Ok(pixels) => {
+            let mut bytes = Vec::with_capacity(8 + pixels.rgba.len());
+            bytes.extend_from_slice(&pixels.width.to_be_bytes());
+            bytes.extend_from_slice(&pixels.height.to_be_bytes());
+            bytes.extend_from_slice(&pixels.rgba);
+            bytes
         }
-        Err(e) => format!("OOXML error: {}", e),
+        Err(_) => Vec::new(),
     }
 }
 
-/// Export a document to OOXML (.docx) format
-/// Takes a JSON string representing the document and returns ZIP bytes
-pub fn export_to_ooxml(document_json: &str) -> Vec<u8> {
-    let document: Result<ParsedDocument, _> = serde_json::from_str(document_json);
-    
-    match document {
-        Ok(doc) => create_minimal_docx(&doc.text),
-        Err(e) => format!("Error: {}", e).into_bytes(),
+// ==================== Insert Image APIs ====================
+
+use std::sync::Arc;
+
+use crate::image::ImageData;
+use crate::ooxml::DocumentImage;
+
+/// Insert an image from its file data as an inline picture at char `offset`
+/// The data is decoded into the image cache under a new media path, where it
+/// stays until another document is opened, and the picture is sized from its
+/// pixels at 96 dpi, shrunk to the content width if it is wider. Exports
+/// write it to the package media. Returns JSON with the image's index among
+/// the document's images, its path, and its width and height in EMUs, or
+/// "Error: ..."
+pub fn insert_image(offset: usize, data: Vec<u8>) -> String {
+    let image = match ImageData::decode(data) {
+        Ok(image) => Arc::new(image),
+        Err(e) => return format!("Error: {}", e),
+    };
+    if !image.dimensions.is_valid() {
+        return format!("Error: {}", ImageError::InvalidDimensions);
     }
+    // Held throughout, so no other edit takes the path or moves the offset
+    let mut doc = DOCUMENT.write().unwrap();
+    let path = (1..)
+        .map(|n| format!("media/image{}.{}", n, image.format.extension()))
+        .find(|path| {
+            !doc.floating.images().any(|image| image.path == *path)
+                && !DEFAULT_IMAGE_CACHE.contains(path)
+                && media_bytes(path).is_none()
+        })
+        .unwrap_or_default();
+    // Pinned, as the cache holds its only copy
+    DEFAULT_IMAGE_CACHE.insert(&path, Arc::clone(&image));
+    DEFAULT_IMAGE_CACHE.pin(&path);
+
+    let (width, height) = image.dimensions.to_emu();
+    let max_width = (doc.page_layout().0.column_width() * 12_700.0) as u32;
+    let (width, height) = match width > max_width {
+        true => (max_width, (height as f64 * max_width as f64 / width as f64).round() as u32),
+        false => (width, height),
+    };
+    let id = format!("rIdImage{}", doc.floating.images().count() + 1);
+    let index = doc.insert_image(
+        offset,
+        DocumentImage {
+            id,
+            path: path.clone(),
+            original_width: Some(width),
+            original_height: Some(height),
+            desired_width: Some(width),
+            desired_height: Some(height),
+            ..Default::default()
+        },
+    );
+    doc.track_modification();
+    serde_json::json!({ "index": index, "path": path, "width": width, "height": height }).to_string()
 }
 
-/// Create a minimal .docx file with the given text content
-fn create_minimal_docx(text: &str) -> Vec<u8> {
-    use std::io::Write;
-    use zip::ZipWriter;
-    use std::io::Cursor;
-    
-    let mut buffer = Cursor::new(Vec::new());
-    {
-        let mut zip = ZipWriter::new(&mut buffer);
-        
-        let content_types = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
-  <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
-  <Default Extension="xml" ContentType="application/xml"/>
-  <Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/>
-  <Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/>
-  <Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/>
-  <Override PartName="/docProps/app.xml" ContentType="application/vnd.openxmlformats-officedocument.extended-properties+xml"/>
-</Types>"#;
-        
-        zip.start_file("[Content_Types].xml", zip::write::FileOptions::default()).unwrap();
-        zip.write_all(content_types.as_bytes()).unwrap();
-        
-        let rels = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
-  <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/>
-  <Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/>
-  <Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/extended-properties" Target="docProps/app.xml"/>
-</Relationships>"#;
-        
-        zip.start_file("_rels/.rels", zip::write::FileOptions::default()).unwrap();
-        zip.write_all(rels.as_bytes()).unwrap();
-        
-        let doc_rels = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
-  <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>
-</Relationships>"#;
-        
-        zip.start_file("word/_rels/document.xml.rels", zip::write::FileOptions::default()).unwrap();
-        zip.write_all(doc_rels.as_bytes()).unwrap();
-        
-        // Simple XML escaping for text content
-        let escape_xml = |s: &str| -> String {
-            s.replace('&', "&amp;")
-             .replace('<', "&lt;")
-             .replace('>', "&gt;")
-             .replace('"', "&quot;")
-             .replace("'", "&apos;")
-        };
-        
-        let paragraphs: Vec<String> = text.lines().map(|line| {
-            format!(r#"<w:p xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:r><w:t>{}</w:t></w:r></w:p>"#, escape_xml(line))
-        }).collect();
-        
-        let document = format!(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
-  <w:body>
-    {}
-  </w:body>
-</w:document>"#, paragraphs.join("\n    "));
-        
-        zip.start_file("word/document.xml", zip::write::FileOptions::default()).unwrap();
-        zip.write_all(document.as_bytes()).unwrap();
-        
-        let styles = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
-  <w:style w:styleId="Normal" w:type="paragraph">
-    <w:name w:val="Normal"/>
-    <w:rPr>
-      <w:sz w:val="24"/>
-      <w:szCs w:val="24"/>
-    </w:rPr>
-  </w:style>
-  <w:style w:styleId="Heading1" w:type="paragraph">
-    <w:name w:val="Heading 1"/>
-    <w:basedOn w:val="Normal"/>
-    <w:rPr>
-      <w:b w:val="1"/>
-      <w:sz w:val="36"/>
-      <w:szCs w:val="36"/>
-    </w:rPr>
-  </w:style>
-</w:styles>"#;
-        
-        zip.start_file("word/styles.xml", zip::write::FileOptions::default()).unwrap();
-        zip.write_all(styles.as_bytes()).unwrap();
-        
-        let core_props = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:dcmitype="http://purl.org/dc/dcmitype/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
-  <dc:title>Exported Document</dc:title>
-  <dc:creator>Velum</dc:creator>
-  <dcterms:created xsi:type="dcterms:W3CDTF">2024-01-01T00:00:00Z</dcterms:created>
-  <dcterms:modified xsi:type="dcterms:W3CDTF">2024-01-01T00:00:00Z</dcterms:modified>
-</cp:coreProperties>"#;
-        
-        zip.start_file("docProps/core.xml", zip::write::FileOptions::default()).unwrap();
-        zip.write_all(core_props.as_bytes()).unwrap();
-        
-        let app_props = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-<Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/extended-properties">
-  <Application>Velum</Application>
-  <AppVersion>1.0</AppVersion>
-</Properties>"#;
-        
-        zip.start_file("docProps/app.xml", zip::write::FileOptions::default()).unwrap();
-        zip.write_all(app_props.as_bytes()).unwrap();
-        
-        zip.finish().unwrap();
+/// Insert the image in the file at `path` at char `offset`, as [`insert_image`] does
+pub fn insert_image_file(offset: usize, path: String) -> String {
+    match fs::read(&path) {
+        Ok(data) => insert_image(offset, data),
+        Err(e) => format!("Error: {}", e),
     }
-    
-    buffer.into_inner()
 }
 
-/// Get just the text content from a .docx file
-pub fn extract_ooxml_text(file_path: &str) -> String {
-    match std::fs::read(file_path) {
-        Ok(file_data) => {
-            match parse_ooxml(&file_data) {
-                Ok(document) => document.text,
-                Err(e) => format!("Error: {}", e),
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The API works on one global document; tests using it take turns
+    static API_LOCK: Mutex<()> = Mutex::new(());
+
+    /// A clean document of `text`, held until the guard is dropped
+    fn open(text: &str) -> std::sync::MutexGuard<'static, ()> {
+        let guard = API_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        *DOCUMENT.write().unwrap() = Document::new(text.to_string());
+        mark_document_saved();
+        guard
+    }
+
+    #[test]
+    fn test_delete_text_inside_a_multibyte_char() {
+        let _guard = open("Hello wörld");
+        // Byte 8 is inside the ö, which goes as a whole
+        assert_eq!(delete_text(7, 1), "Hello wrld");
+        assert_eq!(delete_text(100, 5), "Hello wrld");
+        assert_eq!(get_full_text(), "Hello wrld");
+    }
+
+    #[test]
+    fn test_comment_edits_mark_the_document_modified() {
+        let _guard = open("Quarterly report");
+        let id = add_comment(0, 9, "Which quarter?".to_string());
+        assert!(is_document_dirty());
+
+        mark_document_saved();
+        assert!(!reply_to_comment(id.clone(), "The third".to_string()).starts_with("Error"));
+        assert!(is_document_dirty());
+
+        mark_document_saved();
+        resolve_comment(id.clone(), true);
+        assert!(is_document_dirty());
+
+        mark_document_saved();
+        delete_comment(id);
+        assert!(is_document_dirty());
+    }
+
+    #[test]
+    fn test_add_comment_undo_type() {
+        let _guard = open("Quarterly");
+        insert_text(9, " report".to_string());
+        add_comment(0, 9, "Which quarter?".to_string());
+        insert_bookmark("report".to_string(), 10, 16);
+        insert_hyperlink(10, 16, "https://example.com".to_string(), String::new(), String::new());
+
+        assert_eq!(undo(), "Quarterly report");
+        assert_eq!(get_hyperlinks(), "[]");
+        assert_eq!(undo(), "Quarterly report");
+        assert_eq!(get_bookmarks(true), "[]");
+        assert_eq!(undo(), "Quarterly report");
+        assert_eq!(get_comments(), "[]");
+        assert_eq!(redo(), "Quarterly report");
+        assert!(get_comments().contains("Which quarter?"));
+
+        assert_eq!(insert_text(16, "!".to_string()), "Quarterly report!");
+        assert_eq!(undo(), "Quarterly report");
+        assert!(get_comments().contains("Which quarter?"));
+    }
+
+    #[test]
+    fn test_hyperlink_edits_mark_the_document_modified() {
+        let _guard = open("See the website");
+        let link = insert_hyperlink(8, 15, "https://example.com".to_string(), String::new(), String::new());
+        assert!(!link.starts_with("Error"));
+        assert!(is_document_dirty());
+
+        mark_document_saved();
+        edit_hyperlink(10, "https://example.org".to_string(), String::new(), String::new());
+        assert!(is_document_dirty());
+
+        mark_document_saved();
+        remove_hyperlink(10, 10);
+        assert!(is_document_dirty());
+    }
+
+    #[test]
+    fn test_style_changes_mark_the_document_modified() {
+        let _guard = open("Body text");
+        DOCUMENT.write().unwrap().styles.add(crate::style_sheet::NamedStyle::new("Body", crate::style_sheet::StyleKind::Paragraph));
+        mark_document_saved();
+        assert!(!set_style_break_strategy("Body".to_string(), "TotalFit".to_string()).starts_with("Error"));
+        assert!(is_document_dirty());
+
+        set_style_break_strategy("Body".to_string(), String::new());
+        assert!(!is_document_dirty());
+    }
+
+    #[test]
+    fn test_caption_is_a_tracked_insertion_undone_with_its_anchors() {
+        let _guard = open("Chart\nNotes");
+        DOCUMENT.write().unwrap().bookmarks.insert("notes", 6..11).unwrap();
+        set_track_changes(true);
+        assert!(!insert_caption(0, 5, "Figure".to_string(), "below".to_string()).starts_with("Error"));
+        let caption_end = get_full_text().find("Notes").unwrap();
+        {
+            let doc = DOCUMENT.read().unwrap();
+            assert_eq!(doc.revisions.len(), 1);
+            assert_eq!(doc.bookmarks.get("notes").unwrap().range(), caption_end..caption_end + 5);
         }
-        Err(e) => format!("Error: {}", e),
+
+        assert_eq!(undo(), "Chart\nNotes");
+        let doc = DOCUMENT.read().unwrap();
+        assert!(doc.revisions.is_empty());
+        assert!(doc.captions.captions().is_empty());
+        assert_eq!(doc.bookmarks.get("notes").unwrap().range(), 6..11);
     }
-}
 
-/// Get document statistics from a .docx file
-pub fn get_ooxml_stats(file_path: &str) -> String {
-    match std::fs::read(file_path) {
-        Ok(file_data) => {
-            match parse_ooxml(&file_data) {
-                Ok(document) => {
-                    let stats = serde_json::json!({
-                        "paragraph_count": document.paragraph_count,
-                        "char_count": document.char_count,
-                        "word_count": document.word_count,
-                        "style_count": document.styles.len(),
-                        "title": document.title,
-                        "author": document.author,
-                    });
-                    stats.to_string()
-                }
-                Err(e) => format!("Error: {}", e),
+    #[test]
+    fn test_opening_another_document_drops_inserted_images() {
+        let _guard = open("Gallery");
+        let png = crate::raster::Canvas::new(4, 2, crate::raster::Color::WHITE).to_png();
+        let inserted: serde_json::Value = serde_json::from_str(&insert_image(7, png)).unwrap();
+        let path = inserted["path"].as_str().unwrap().to_string();
+        assert!(DEFAULT_IMAGE_CACHE.contains(&path));
+
+        load_document_from_text("Plain".to_string());
+        assert!(!DEFAULT_IMAGE_CACHE.contains(&path));
+    }
+
+    #[test]
+    fn test_undo_removes_just_the_inserted_image() {
+        let _guard = open("Gallery");
+        insert_text(7, " wall".to_string());
+        let png = crate::raster::Canvas::new(4, 2, crate::raster::Color::WHITE).to_png();
+        assert!(!insert_image(12, png).starts_with("Error"));
+        assert_eq!(DOCUMENT.read().unwrap().floating.images().count(), 1);
+
+        assert_eq!(undo(), "Gallery wall");
+        assert_eq!(DOCUMENT.read().unwrap().floating.images().count(), 0);
+        assert_eq!(redo(), "Gallery wall");
+        assert_eq!(DOCUMENT.read().unwrap().floating.images().count(), 1);
+    }
+
+    #[test]
+    fn test_opening_another_document_forgets_the_media_of_the_last() {
+        let _guard = open("Scanned");
+        let media = HashMap::from([("media/scan.png".to_string(), vec![1, 2, 3])]);
+        *MEDIA_SOURCE.lock().unwrap() = MediaSource::Legacy(media);
+        assert_eq!(load_legacy_doc_media("media/scan.png".to_string()), vec![1, 2, 3]);
+        assert!(load_odt_media("media/scan.png".to_string()).is_empty());
+
+        create_empty_document();
+        assert!(media_bytes("media/scan.png").is_none());
+        assert!(load_legacy_doc_media("media/scan.png".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_page_setup_is_saved_and_opened_again() {
+        let _guard = open("A wide page");
+        let turned: serde_json::Value = serde_json::from_str(&set_page_orientation(0, true)).unwrap();
+        assert_eq!(turned["section"], 0);
+        assert_eq!(turned["page_count"], 1);
+        // Already landscape: nothing to repaginate, but the pages are still counted
+        let unchanged: serde_json::Value = serde_json::from_str(&set_page_orientation(0, true)).unwrap();
+        assert!(unchanged["section"].is_null());
+        assert_eq!(unchanged["page_count"], 1);
+
+        let data = export_current_document_docx();
+        let package = crate::ooxml::OpcPackage::new(&data).unwrap();
+        let body = String::from_utf8_lossy(&package.get_part("/word/document.xml").unwrap().data).into_owned();
+        assert!(body.contains(r#"w:orient="landscape""#));
+
+        create_empty_document();
+        assert!(!open_ooxml_lazy(&data, 0).starts_with("OOXML error"));
+        let doc = DOCUMENT.read().unwrap();
+        assert_eq!(doc.page_setup.sections[0].orientation, Orientation::Landscape);
+        assert!(doc.page_setup.sections[0].width > doc.page_setup.sections[0].height);
+    }
+
+    #[test]
+    fn test_streamed_open_keeps_what_is_anchored_to_the_text() {
+        let _guard = open("First line\nSecond line\nThird");
+        assert!(!insert_bookmark("Here".to_string(), 11, 17).starts_with("Error"));
+        let link = insert_hyperlink(23, 28, "https://example.com/".to_string(), String::new(), String::new());
+        assert!(!link.starts_with("Error"));
+        add_comment(0, 5, "Check this".to_string());
+        let (bookmarks, hyperlinks) = (get_bookmarks(true), get_hyperlinks());
+
+        let path = std::env::temp_dir().join(format!("velum-streamed-{}.docx", std::process::id()));
+        fs::write(&path, export_current_document_docx()).unwrap();
+        create_empty_document();
+        let opened = open_ooxml_streaming(path.to_string_lossy().into_owned());
+        fs::remove_file(&path).unwrap();
+        assert!(!opened.starts_with("OOXML error"), "{}", opened);
+
+        assert_eq!(get_bookmarks(true), bookmarks);
+        assert_eq!(get_hyperlinks(), hyperlinks);
+        let doc = DOCUMENT.read().unwrap();
+        let threads = doc.comments.threads_at(2);
+        assert_eq!(threads.len(), 1);
+        assert_eq!((threads[0].comment.start, threads[0].comment.length), (0, 5));
+        assert_eq!(threads[0].comment.text(), "Check this");
+    }
+
+    #[test]
+    fn test_every_open_path_keeps_the_document_end_through_a_save() {
+        let _guard = open("");
+        let body = concat!(
+            r#"<w:p><w:r><w:t>Body</w:t></w:r></w:p>"#,
+            r#"<w:p><w:pPr><w:rPr><w:b/></w:rPr></w:pPr><w:r><w:t>End</w:t></w:r></w:p>"#,
+            r#"<w:sectPr><w:pgSz w:w="15840" w:h="12240" w:orient="landscape"/></w:sectPr>"#,
+        );
+        let data = crate::test_support::minimal_docx(body, &[], zip::CompressionMethod::Deflated);
+        let assert_end = |how: &str| {
+            let end: serde_json::Value = serde_json::from_str(&get_document_end()).unwrap();
+            assert_eq!(end["mark"]["bold"], true, "{}", how);
+            assert_eq!(end["section"]["landscape"], true, "{}", how);
+        };
+
+        assert!(!open_ooxml_lazy(&data, 0).starts_with("OOXML error"));
+        assert_end("lazy");
+
+        create_empty_document();
+        start_ooxml_load(data.clone());
+        let loaded = loop {
+            let loaded = take_loaded_ooxml_document();
+            if !loaded.is_empty() {
+                break loaded;
             }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        };
+        assert!(!loaded.starts_with("OOXML error"), "{}", loaded);
+        assert_end("background load");
+
+        let path = std::env::temp_dir().join(format!("velum-end-{}.docx", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        create_empty_document();
+        let opened = open_ooxml_streaming(path.to_string_lossy().into_owned());
+        fs::remove_file(&path).unwrap();
+        assert!(!opened.starts_with("OOXML error"), "{}", opened);
+        assert_end("streaming");
+
+        // Saved and opened again, the end is still there
+        let saved = export_current_document_docx();
+        create_empty_document();
+        assert!(!open_ooxml_lazy(&saved, 0).starts_with("OOXML error"));
+        assert_end("saved");
+    }
+
+    #[test]
+    fn test_filled_form_fields_and_protection_are_saved() {
+        let _guard = open("");
+        let body = r#"<w:p><w:r><w:t xml:space="preserve">Name: </w:t></w:r><w:r><w:fldChar w:fldCharType="begin"><w:ffData><w:name w:val="Name"/><w:textInput/></w:ffData></w:fldChar></w:r><w:r><w:instrText xml:space="preserve"> FORMTEXT </w:instrText></w:r><w:r><w:fldChar w:fldCharType="separate"/></w:r><w:r><w:t>Ada</w:t></w:r><w:r><w:fldChar w:fldCharType="end"/></w:r><w:r><w:t xml:space="preserve"> signed</w:t></w:r></w:p>"#;
+        let data = crate::test_support::minimal_docx(body, &[], zip::CompressionMethod::Deflated);
+        let path = std::env::temp_dir().join(format!("velum-forms-{}.docx", std::process::id()));
+        let open_file = |data: &[u8]| {
+            fs::write(&path, data).unwrap();
+            create_empty_document();
+            let opened = open_ooxml_streaming(path.to_string_lossy().into_owned());
+            fs::remove_file(&path).unwrap();
+            assert!(!opened.starts_with("OOXML error"), "{}", opened);
+        };
+        open_file(&data);
+        assert_eq!(get_full_text(), "Name: Ada signed");
+        assert!(!set_document_protection("Forms".to_string()).starts_with("Error"));
+
+        // Only the field's text may be edited
+        assert!(insert_text(0, "Your ".to_string()).starts_with("Error"));
+        assert!(!set_document_form_field("Name".to_string(), "Grace".to_string()).starts_with("Error"));
+        assert_eq!(get_full_text(), "Name: Grace signed");
+
+        let saved = export_current_document_docx();
+        let package = crate::ooxml::OpcPackage::new(&saved).unwrap();
+        let settings = String::from_utf8_lossy(&package.get_part("/word/settings.xml").unwrap().data).into_owned();
+        assert!(settings.contains(r#"w:edit="forms""#), "{}", settings);
+
+        open_file(&saved);
+        assert_eq!(get_full_text(), "Name: Grace signed");
+        let forms: serde_json::Value = serde_json::from_str(&get_document_form_fields()).unwrap();
+        assert_eq!(forms["protection"], "Forms");
+        assert_eq!(forms["fields"][0]["name"], "Name");
+        assert_eq!(forms["fields"][0]["value"], "Grace");
+        assert_eq!(forms["fields"][0]["start"], 6);
+        assert_eq!(forms["fields"][0]["length"], 5);
+    }
+
+    #[test]
+    fn test_read_only_protection_rejects_every_content_change() {
+        let _guard = open("Chart\nNotes");
+        mark_index_entry(0, "Chart".to_string(), String::new());
+        assert!(!insert_index(11, String::new()).starts_with("Error"));
+        assert!(!insert_caption(0, 5, "Figure".to_string(), "below".to_string()).starts_with("Error"));
+        assert!(!set_document_protection("ReadOnly".to_string()).starts_with("Error"));
+        let text = get_full_text();
+        mark_document_saved();
+
+        let protected = format!("Error: {}", FormError::Protected);
+        assert_eq!(apply_text_attributes(0, 5, r#"{"bold":true}"#.to_string()), protected);
+        assert_eq!(remove_text_attributes(0, 5), protected);
+        assert_eq!(apply_character_style(0, 5, "Caption".to_string()), protected);
+        assert_eq!(apply_paragraph_attributes(0, 5, r#"{"indent_left":720}"#.to_string()), protected);
+        assert_eq!(remove_paragraph_attributes(0, 5), protected);
+        assert_eq!(apply_paragraph_style(0, 5, "Caption".to_string()), protected);
+        assert_eq!(set_paragraph_indents_text(0, 5, "1 in".to_string(), String::new(), String::new()), protected);
+        assert_eq!(edit_list(0, 5, "indent".to_string()), protected);
+        assert_eq!(replace_font("Arial".to_string(), "Georgia".to_string(), r#""document""#.to_string(), None), protected);
+        assert_eq!(autoformat_as_you_type(5), protected);
+        assert_eq!(math_input_as_you_type(5), protected);
+        assert_eq!(insert_index(0, String::new()), protected);
+        assert_eq!(update_index(), protected);
+        assert_eq!(insert_caption(0, 5, "Figure".to_string(), "above".to_string()), protected);
+        assert_eq!(move_captioned_object(0, 2), protected);
+        assert_eq!(update_captions(), protected);
+
+        assert_eq!(get_full_text(), text);
+        assert!(!is_document_dirty());
+    }
+
+    #[test]
+    fn test_protected_html_paste_adds_no_lists() {
+        let _guard = open("Agenda");
+        assert!(!set_document_protection("ReadOnly".to_string()).starts_with("Error"));
+        let lists = || serde_json::to_string(&DOCUMENT.read().unwrap().numbering.to_ooxml()).unwrap();
+        let before = lists();
+
+        let pasted = paste_html(6, "<ol><li>One</li><li>Two</li></ol>".to_string(), "keep_source".to_string());
+        assert_eq!(pasted, format!("Error: {}", FormError::Protected));
+        assert_eq!(lists(), before);
+    }
+
+    #[test]
+    fn test_save_copies_the_parts_left_unchanged() {
+        let _guard = open("");
+        let data = crate::test_support::minimal_docx(
+            r#"<w:p><w:r><w:t>Kept</w:t></w:r></w:p>"#,
+            &[("customXml/item1.xml", "<data>carried over</data>")],
+            zip::CompressionMethod::Stored,
+        );
+        let entry = |data: &[u8], name: &str| {
+            let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+            let mut file = archive.by_name(name).unwrap();
+            let mut text = String::new();
+            std::io::Read::read_to_string(&mut file, &mut text).unwrap();
+            (file.compression(), text)
+        };
+
+        assert!(!open_ooxml_lazy(&data, 0).starts_with("OOXML error"));
+        insert_text(4, " and edited".to_string());
+        assert_eq!(get_full_text(), "Kept and edited");
+        let saved = export_current_document_docx();
+        // The body is written anew, the part the editor knows nothing of copied as it was
+        let (method, body) = entry(&saved, "word/document.xml");
+        assert_eq!(method, zip::CompressionMethod::Deflated);
+        assert!(body.contains(" and edited"), "{}", body);
+        assert_eq!(entry(&saved, "customXml/item1.xml"), (zip::CompressionMethod::Stored, "<data>carried over</data>".to_string()));
+
+        // As is a document loaded in the background
+        create_empty_document();
+        start_ooxml_load(data);
+        while take_loaded_ooxml_document().is_empty() {
+            std::thread::yield_now();
         }
-        Err(e) => format!("Error: {}", e),
+        assert_eq!(get_full_text(), "Kept");
+        let saved = export_current_document_docx();
+        assert_eq!(entry(&saved, "customXml/item1.xml").0, zip::CompressionMethod::Stored);
     }
-}
 
+    #[test]
+    fn test_concurrent_image_inserts_take_distinct_paths() {
+        let _guard = open("Gallery");
+        let png = crate::raster::Canvas::new(4, 2, crate::raster::Color::WHITE).to_png();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let png = png.clone();
+                std::thread::spawn(move || insert_image(7, png))
+            })
+            .collect();
+        let mut paths: Vec<String> = threads
+            .into_iter()
+            .map(|thread| {
+                let inserted: serde_json::Value = serde_json::from_str(&thread.join().unwrap()).unwrap();
+                inserted["path"].as_str().unwrap().to_string()
+            })
+            .collect();
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), 4);
+        assert_eq!(DOCUMENT.read().unwrap().floating.images().count(), 4);
+        assert!(is_document_dirty());
+    }
+}
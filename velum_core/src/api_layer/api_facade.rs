@@ -7,10 +7,8 @@ use std::sync::{Arc, RwLock};
 use crate::api_layer::document_api::{DocumentApi, PieceTreeDocumentApi, DocumentStats};
 use crate::api_layer::layout_api::{LayoutApi, PageLayoutApi, ViewportInfo};
 use crate::api_layer::render_api::{RenderApi, SimpleRenderApi};
-use crate::api_layer::export_api::{ExportApi, PdfExportApi, ExportFormat};
 use crate::piece_tree::PieceTree;
 use crate::page_layout::{PageConfig, PageLayout};
-use crate::export::pdf::PdfConfig;
 use crate::find::SearchOptions;
 
 /// The main Velum API struct - provides unified access to all functionality
@@ -18,7 +16,6 @@ pub struct VelumApi {
     document_api: Arc<dyn DocumentApi + Send + Sync>,
     layout_api: Arc<dyn LayoutApi + Send + Sync>,
     render_api: Arc<dyn RenderApi + Send + Sync>,
-    export_api: Arc<dyn ExportApi + Send + Sync>,
 }
 
 impl VelumApi {
@@ -40,13 +37,11 @@ impl VelumApi {
         let document_api = Arc::new(PieceTreeDocumentApi::new(document));
         let layout_api = Arc::new(PageLayoutApi::new(page_layout, page_config));
         let render_api = Arc::new(SimpleRenderApi::new(Vec::new()));
-        let export_api = Arc::new(PdfExportApi::new(&[]));
 
         Self {
             document_api,
             layout_api,
             render_api,
-            export_api,
         }
     }
 
@@ -64,11 +59,6 @@ impl VelumApi {
     pub fn render(&self) -> &dyn RenderApi {
         &*self.render_api
     }
-
-    /// Get export API reference
-    pub fn export(&self) -> &dyn ExportApi {
-        &*self.export_api
-    }
 }
 
 impl Default for VelumApi {
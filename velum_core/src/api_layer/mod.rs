@@ -4,4 +4,3 @@ pub mod api_facade;
 pub mod document_api;
 pub mod layout_api;
 pub mod render_api;
-pub mod export_api;
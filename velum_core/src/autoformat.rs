@@ -0,0 +1,369 @@
+//! # AutoFormat Module
+//!
+//! Structural conversions applied as the user types, as Word's AutoFormat As
+//! You Type does:
+//!
+//! - "1. " or "1) " at the start of a paragraph makes it a numbered list item,
+//!   "* " or "- " a bulleted one; an item right after a list of the same kind
+//!   joins it
+//! - "---", "___", "===", "***", "~~~" or "###" alone in a paragraph, followed
+//!   by Enter, becomes a line below that paragraph
+//! - "+---+---+" followed by Enter becomes a table with a column per cell,
+//!   sized by its dashes; tables are kept out of the editor text, so the
+//!   pattern is removed and the column widths handed to the caller
+//! - "# " to "###### " makes Heading 1–6, in the optional Markdown mode
+//!
+//! Each conversion is an undo step of its own, after the typing that
+//! triggered it, so one undo brings back the typed text as it was.
+
+use std::ops::Range;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::numbering::{ListEdit, ListKind, ListNumbering};
+use crate::ooxml::ParagraphBorder;
+use crate::piece_tree::{ParagraphAttributes, PieceTree};
+use crate::style_sheet::StyleSheet;
+
+static LIST_MARKER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?:(1[.)])|([*-]))[ \t]$").unwrap());
+static HEADING_MARKER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(#{1,6}) $").unwrap());
+static TABLE_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\+(?:-+\+)+$").unwrap());
+
+/// Which conversions apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoFormatOptions {
+    pub lists: bool,
+    pub borders: bool,
+    pub tables: bool,
+    /// "# " headings; off by default, as they are not Word's
+    pub markdown_headings: bool,
+}
+
+impl Default for AutoFormatOptions {
+    fn default() -> Self {
+        AutoFormatOptions {
+            lists: true,
+            borders: true,
+            tables: true,
+            markdown_headings: false,
+        }
+    }
+}
+
+/// What a conversion made of the typed markup
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AutoFormatChange {
+    List { list: ListKind, num_id: String },
+    Border { border: ParagraphBorder },
+    Heading { level: u8 },
+    /// Relative column widths, in dashes of the pattern
+    Table { columns: Vec<usize> },
+}
+
+/// A conversion applied to the text
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AutoFormatResult {
+    pub change: AutoFormatChange,
+    /// Index of the converted paragraph
+    pub paragraph: usize,
+    /// Chars of the markup removed, before the removal
+    pub removed: Range<usize>,
+    /// Char offset for the caret afterwards
+    pub caret: usize,
+}
+
+/// Converts the markup just typed before `caret`, if it is any
+///
+/// Call after each insertion of typed text with the caret after it. Typing a
+/// space or tab checks the paragraph start for list and heading markers;
+/// typing Enter checks the paragraph it ended for rules and table patterns.
+pub fn format_as_you_type(
+    tree: &mut PieceTree,
+    numbering: &mut ListNumbering,
+    styles: &StyleSheet,
+    options: &AutoFormatOptions,
+    caret: usize,
+) -> Option<AutoFormatResult> {
+    let caret = caret.min(tree.total_char_count);
+    let typed = caret.checked_sub(1).map(|before| char_at(tree, before))?;
+    match typed {
+        ' ' | '\t' => format_paragraph_start(tree, numbering, styles, options, caret),
+        '\n' => format_ended_paragraph(tree, options, caret),
+        _ => None,
+    }
+}
+
+fn char_at(tree: &PieceTree, offset: usize) -> char {
+    let start = tree.char_to_byte_offset(offset);
+    let end = tree.char_to_byte_offset(offset + 1);
+    tree.get_text_range(start, end - start).chars().next().unwrap_or_default()
+}
+
+/// Text between two char offsets
+fn text_between(tree: &PieceTree, range: Range<usize>) -> String {
+    let start = tree.char_to_byte_offset(range.start);
+    tree.get_text_range(start, tree.char_to_byte_offset(range.end) - start)
+}
+
+/// Lists and headings from a marker typed at the start of the caret's paragraph
+fn format_paragraph_start(
+    tree: &mut PieceTree,
+    numbering: &mut ListNumbering,
+    styles: &StyleSheet,
+    options: &AutoFormatOptions,
+    caret: usize,
+) -> Option<AutoFormatResult> {
+    let paragraph = tree.paragraph_index_at(caret);
+    let start = tree.get_offset_at_line(paragraph + 1);
+    let marker = text_between(tree, start..caret);
+
+    if options.lists {
+        if let Some(caps) = LIST_MARKER.captures(&marker) {
+            let list = if caps.get(1).is_some() { ListKind::Numbered } else { ListKind::Bullet };
+            // An item already in a list keeps its marker as text
+            let effective = styles.effective_paragraph(tree.paragraph_attributes(paragraph));
+            if effective.num_id.as_deref().is_some_and(|id| id != "0") {
+                return None;
+            }
+            let paragraphs: Vec<_> =
+                (0..tree.paragraph_count()).map(|index| tree.paragraph_attributes(index).cloned()).collect();
+            let after = numbering
+                .edit_paragraphs(ListEdit::Convert(list), &paragraphs, paragraph..paragraph + 1, styles)
+                .ok()?;
+            let num_id = after[paragraph].as_ref()?.num_id.clone()?;
+            let attributes = after[paragraph].clone();
+            convert(tree, start..caret, move |_| attributes);
+            return Some(AutoFormatResult {
+                change: AutoFormatChange::List { list, num_id },
+                paragraph,
+                removed: start..caret,
+                caret: start,
+            });
+        }
+    }
+
+    if options.markdown_headings {
+        if let Some(caps) = HEADING_MARKER.captures(&marker) {
+            let level = caps[1].len() as u8;
+            convert(tree, start..caret, |old| {
+                Some(ParagraphAttributes {
+                    style_id: Some(format!("Heading{}", level)),
+                    ..old.cloned().unwrap_or_default()
+                })
+            });
+            return Some(AutoFormatResult {
+                change: AutoFormatChange::Heading { level },
+                paragraph,
+                removed: start..caret,
+                caret: start,
+            });
+        }
+    }
+    None
+}
+
+/// Rules and tables from the paragraph Enter just ended
+fn format_ended_paragraph(tree: &mut PieceTree, options: &AutoFormatOptions, caret: usize) -> Option<AutoFormatResult> {
+    // The caret is at the start of the new paragraph; the ended one is before it
+    let paragraph = tree.paragraph_index_at(caret).checked_sub(1)?;
+    let start = tree.get_offset_at_line(paragraph + 1);
+    let end = caret - 1;
+    let text = text_between(tree, start..end);
+
+    let change = if options.borders {
+        rule_border(&text).map(|border| AutoFormatChange::Border { border })
+    } else {
+        None
+    };
+    let change = change.or_else(|| {
+        (options.tables && TABLE_PATTERN.is_match(&text)).then(|| AutoFormatChange::Table {
+            columns: text.split('+').filter(|cell| !cell.is_empty()).map(str::len).collect(),
+        })
+    })?;
+
+    match &change {
+        AutoFormatChange::Border { border } => {
+            let border = border.clone();
+            convert(tree, start..end, move |old| {
+                Some(ParagraphAttributes {
+                    border_bottom: Some(border),
+                    ..old.cloned().unwrap_or_default()
+                })
+            });
+        }
+        _ => convert(tree, start..end, |old| old.cloned()),
+    }
+    Some(AutoFormatResult {
+        change,
+        paragraph,
+        removed: start..end,
+        caret: caret - (end - start),
+    })
+}
+
+/// The line a paragraph of three or more of the same rule char stands for
+fn rule_border(text: &str) -> Option<ParagraphBorder> {
+    let first = text.chars().next()?;
+    if text.chars().count() < 3 || text.chars().any(|c| c != first) {
+        return None;
+    }
+    let (style, size) = match first {
+        '-' => ("single", 6),
+        '_' => ("single", 12),
+        '=' => ("double", 6),
+        '*' => ("dotted", 12),
+        '~' => ("wave", 6),
+        '#' => ("triple", 6),
+        _ => return None,
+    };
+    Some(ParagraphBorder {
+        style: style.to_string(),
+        size,
+        space: 1,
+    })
+}
+
+/// Removes the markup in `markup` and restyles its paragraph, as one undo step
+/// apart from the typing before it
+fn convert(
+    tree: &mut PieceTree,
+    markup: Range<usize>,
+    style: impl FnOnce(Option<&ParagraphAttributes>) -> Option<ParagraphAttributes>,
+) {
+    tree.break_undo_coalescing();
+    tree.transaction(|tree| {
+        let start = tree.char_to_byte_offset(markup.start);
+        tree.delete(start, tree.char_to_byte_offset(markup.end) - start);
+        let mut style = Some(style);
+        tree.reformat_each_paragraph(markup.start..markup.start, |_, old| match style.take() {
+            Some(style) => style(old),
+            None => old.cloned(),
+        });
+    });
+    tree.break_undo_coalescing();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Types `text` at the end of the tree, then autoformats
+    fn type_text(
+        tree: &mut PieceTree,
+        numbering: &mut ListNumbering,
+        options: &AutoFormatOptions,
+        text: &str,
+    ) -> Option<AutoFormatResult> {
+        let mut result = None;
+        for c in text.chars() {
+            let offset = tree.total_char_count;
+            tree.insert(offset, c.to_string());
+            result = format_as_you_type(tree, numbering, &StyleSheet::new(), options, offset + 1);
+        }
+        result
+    }
+
+    #[test]
+    fn test_list_markers() {
+        let mut tree = PieceTree::new(String::new());
+        let mut numbering = ListNumbering::new();
+        let options = AutoFormatOptions::default();
+
+        let result = type_text(&mut tree, &mut numbering, &options, "1. ").unwrap();
+        let AutoFormatChange::List { list, num_id } = &result.change else {
+            panic!("expected a list, got {:?}", result.change);
+        };
+        assert_eq!(*list, ListKind::Numbered);
+        assert_eq!((result.removed, result.caret), (0..3, 0));
+        assert_eq!(tree.get_text(), "");
+        assert_eq!(tree.paragraph_attributes(0).and_then(|a| a.num_id.as_ref()), Some(num_id));
+
+        // Enter continues the list; in an item a marker is text
+        assert!(type_text(&mut tree, &mut numbering, &options, "Eggs\n- ").is_none());
+        assert_eq!(tree.get_text(), "Eggs\n- ");
+        assert_eq!(tree.paragraph_attributes(1).and_then(|a| a.num_id.as_ref()), Some(num_id));
+
+        // An item typed right after the list joins it
+        tree.set_paragraph_attributes(7..7, None);
+        tree.delete(5, 2);
+        type_text(&mut tree, &mut numbering, &options, "1. ");
+        assert_eq!(tree.paragraph_attributes(1).and_then(|a| a.num_id.as_ref()), Some(num_id));
+
+        // A marker after text is not a marker
+        assert!(type_text(&mut tree, &mut numbering, &options, "Milk\nand - ").is_none());
+    }
+
+    #[test]
+    fn test_single_undo_restores_typing() {
+        let mut tree = PieceTree::new(String::new());
+        let mut numbering = ListNumbering::new();
+        let result = type_text(&mut tree, &mut numbering, &AutoFormatOptions::default(), "* ").unwrap();
+        assert!(matches!(result.change, AutoFormatChange::List { list: ListKind::Bullet, .. }));
+
+        assert!(tree.undo());
+        assert_eq!(tree.get_text(), "* ");
+        assert!(tree.paragraph_attributes(0).is_none());
+        assert!(tree.redo());
+        assert_eq!(tree.get_text(), "");
+        assert!(tree.paragraph_attributes(0).is_some_and(|a| a.num_id.is_some()));
+    }
+
+    #[test]
+    fn test_rule_becomes_border() {
+        let mut tree = PieceTree::new("Title\n".to_string());
+        let mut numbering = ListNumbering::new();
+        let result = type_text(&mut tree, &mut numbering, &AutoFormatOptions::default(), "===\n").unwrap();
+        assert_eq!(tree.get_text(), "Title\n\n");
+        assert_eq!((result.paragraph, result.caret), (1, 7));
+        let border = tree.paragraph_attributes(1).and_then(|a| a.border_bottom.clone()).unwrap();
+        assert_eq!(border.style, "double");
+        // The new paragraph after it has no line
+        assert!(tree.paragraph_attributes(2).is_none());
+
+        assert!(tree.undo());
+        assert_eq!(tree.get_text(), "Title\n===\n");
+        // Mixed or short rules stay text
+        assert!(type_text(&mut tree, &mut numbering, &AutoFormatOptions::default(), "-=-\n--\n").is_none());
+    }
+
+    #[test]
+    fn test_table_pattern() {
+        let mut tree = PieceTree::new(String::new());
+        let mut numbering = ListNumbering::new();
+        let result = type_text(&mut tree, &mut numbering, &AutoFormatOptions::default(), "+----+--------+\n").unwrap();
+        assert_eq!(
+            result.change,
+            AutoFormatChange::Table {
+                columns: vec![4, 8]
+            }
+        );
+        assert_eq!(tree.get_text(), "\n");
+
+        let options = AutoFormatOptions {
+            tables: false,
+            ..Default::default()
+        };
+        assert!(type_text(&mut tree, &mut numbering, &options, "+--+\n").is_none());
+    }
+
+    #[test]
+    fn test_markdown_headings_are_optional() {
+        let mut tree = PieceTree::new(String::new());
+        let mut numbering = ListNumbering::new();
+        assert!(type_text(&mut tree, &mut numbering, &AutoFormatOptions::default(), "## ").is_none());
+
+        let mut tree = PieceTree::new(String::new());
+        let options = AutoFormatOptions {
+            markdown_headings: true,
+            ..Default::default()
+        };
+        let result = type_text(&mut tree, &mut numbering, &options, "## ").unwrap();
+        assert_eq!(result.change, AutoFormatChange::Heading { level: 2 });
+        assert_eq!(tree.paragraph_attributes(0).and_then(|a| a.style_id.as_deref()), Some("Heading2"));
+        assert!(type_text(&mut tree, &mut numbering, &options, "####### ").is_none());
+    }
+}
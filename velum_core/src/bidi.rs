@@ -0,0 +1,935 @@
+//! # Bidi Module
+//!
+//! The Unicode bidirectional algorithm (UAX #9) for paragraphs mixing
+//! left-to-right and right-to-left text.
+//!
+//! [`BidiParagraph`] resolves an embedding level for each char of a
+//! paragraph: explicit embeddings, overrides and isolates (rules X1–X10),
+//! weak and neutral types (W1–W7, N0–N2) and implicit levels (I1–I2). Lines
+//! of it are then reordered for display (L1–L2), and caret positions mapped
+//! between logical and visual order, so selecting and moving through
+//! mixed-direction text goes where the reader sees it.
+//!
+//! Positions here are char indices into the paragraph text. Bidi classes
+//! come from a table of the common scripts' ranges rather than the full
+//! Unicode database; chars outside it are taken to be left-to-right.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+/// Deepest explicit embedding level (BD2)
+const MAX_DEPTH: u8 = 125;
+
+/// Most bracket pairs looked for in an isolating run sequence (BD16)
+const MAX_BRACKETS: usize = 63;
+
+/// Bidirectional character types (UAX #9, table 4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BidiClass {
+    // Strong
+    LeftToRight,
+    RightToLeft,
+    ArabicLetter,
+    // Weak
+    EuropeanNumber,
+    EuropeanSeparator,
+    EuropeanTerminator,
+    ArabicNumber,
+    CommonSeparator,
+    NonspacingMark,
+    BoundaryNeutral,
+    // Neutral
+    ParagraphSeparator,
+    SegmentSeparator,
+    WhiteSpace,
+    OtherNeutral,
+    // Explicit formatting
+    LeftToRightEmbedding,
+    LeftToRightOverride,
+    RightToLeftEmbedding,
+    RightToLeftOverride,
+    PopDirectionalFormat,
+    LeftToRightIsolate,
+    RightToLeftIsolate,
+    FirstStrongIsolate,
+    PopDirectionalIsolate,
+}
+
+use BidiClass::*;
+
+impl BidiClass {
+    fn is_strong(self) -> bool {
+        matches!(self, LeftToRight | RightToLeft | ArabicLetter)
+    }
+
+    fn is_isolate_initiator(self) -> bool {
+        matches!(self, LeftToRightIsolate | RightToLeftIsolate | FirstStrongIsolate)
+    }
+
+    /// Removed by rule X9, and given the level of the char before afterwards
+    fn is_removed(self) -> bool {
+        matches!(
+            self,
+            LeftToRightEmbedding
+                | RightToLeftEmbedding
+                | LeftToRightOverride
+                | RightToLeftOverride
+                | PopDirectionalFormat
+                | BoundaryNeutral
+        )
+    }
+
+    /// Neutral or isolate formatting, resolved by rules N1 and N2
+    fn is_neutral(self) -> bool {
+        matches!(
+            self,
+            ParagraphSeparator
+                | SegmentSeparator
+                | WhiteSpace
+                | OtherNeutral
+                | LeftToRightIsolate
+                | RightToLeftIsolate
+                | FirstStrongIsolate
+                | PopDirectionalIsolate
+        )
+    }
+}
+
+/// The bidi class of `c`
+pub fn bidi_class(c: char) -> BidiClass {
+    match c {
+        '\n' | '\r' | '\u{1C}'..='\u{1E}' | '\u{85}' | '\u{2029}' => ParagraphSeparator,
+        '\t' | '\u{0B}' | '\u{1F}' => SegmentSeparator,
+        ' ' | '\u{0C}' | '\u{1680}' | '\u{2000}'..='\u{200A}' | '\u{2028}' | '\u{205F}' | '\u{3000}' => WhiteSpace,
+        '\u{00}'..='\u{08}'
+        | '\u{0E}'..='\u{1B}'
+        | '\u{7F}'..='\u{84}'
+        | '\u{86}'..='\u{9F}'
+        | '\u{AD}'
+        | '\u{180E}'
+        | '\u{200B}'..='\u{200D}'
+        | '\u{2060}'..='\u{2064}'
+        | '\u{206A}'..='\u{206F}'
+        | '\u{FEFF}' => BoundaryNeutral,
+        '\u{200E}' => LeftToRight,
+        '\u{200F}' => RightToLeft,
+        '\u{061C}' => ArabicLetter,
+        '\u{202A}' => LeftToRightEmbedding,
+        '\u{202B}' => RightToLeftEmbedding,
+        '\u{202C}' => PopDirectionalFormat,
+        '\u{202D}' => LeftToRightOverride,
+        '\u{202E}' => RightToLeftOverride,
+        '\u{2066}' => LeftToRightIsolate,
+        '\u{2067}' => RightToLeftIsolate,
+        '\u{2068}' => FirstStrongIsolate,
+        '\u{2069}' => PopDirectionalIsolate,
+
+        '0'..='9'
+        | '\u{B2}'
+        | '\u{B3}'
+        | '\u{B9}'
+        | '\u{06F0}'..='\u{06F9}'
+        | '\u{2070}'
+        | '\u{2074}'..='\u{2079}'
+        | '\u{2080}'..='\u{2089}'
+        | '\u{2488}'..='\u{249B}'
+        | '\u{FF10}'..='\u{FF19}'
+        | '\u{1D7CE}'..='\u{1D7FF}' => EuropeanNumber,
+        '+' | '-' | '\u{207A}' | '\u{207B}' | '\u{208A}' | '\u{208B}' | '\u{2212}' | '\u{FB29}' | '\u{FE62}' | '\u{FE63}'
+        | '\u{FF0B}' | '\u{FF0D}' => EuropeanSeparator,
+        '#' | '$' | '%' | '\u{A2}'..='\u{A5}' | '\u{B0}' | '\u{B1}' | '\u{058F}' | '\u{0609}' | '\u{060A}' | '\u{066A}'
+        | '\u{09F2}' | '\u{09F3}' | '\u{0AF1}' | '\u{0BF9}' | '\u{0E3F}' | '\u{17DB}' | '\u{2030}'..='\u{2034}'
+        | '\u{20A0}'..='\u{20CF}' | '\u{212E}' | '\u{2213}' | '\u{FE5F}' | '\u{FE69}' | '\u{FE6A}' | '\u{FF03}'..='\u{FF05}'
+        | '\u{FFE0}' | '\u{FFE1}' | '\u{FFE5}' | '\u{FFE6}' => EuropeanTerminator,
+        '\u{0600}'..='\u{0605}'
+        | '\u{0660}'..='\u{0669}'
+        | '\u{066B}'
+        | '\u{066C}'
+        | '\u{06DD}'
+        | '\u{0890}'
+        | '\u{0891}'
+        | '\u{08E2}'
+        | '\u{10E60}'..='\u{10E7E}' => ArabicNumber,
+        ',' | '.' | '/' | ':' | '\u{A0}' | '\u{060C}' | '\u{202F}' | '\u{2044}' | '\u{FE50}' | '\u{FE52}' | '\u{FE55}'
+        | '\u{FF0C}' | '\u{FF0E}' | '\u{FF0F}' | '\u{FF1A}' => CommonSeparator,
+
+        '\u{0300}'..='\u{036F}'
+        | '\u{0483}'..='\u{0489}'
+        | '\u{0591}'..='\u{05BD}'
+        | '\u{05BF}'
+        | '\u{05C1}'
+        | '\u{05C2}'
+        | '\u{05C4}'
+        | '\u{05C5}'
+        | '\u{05C7}'
+        | '\u{0610}'..='\u{061A}'
+        | '\u{064B}'..='\u{065F}'
+        | '\u{0670}'
+        | '\u{06D6}'..='\u{06DC}'
+        | '\u{06DF}'..='\u{06E4}'
+        | '\u{06E7}'
+        | '\u{06E8}'
+        | '\u{06EA}'..='\u{06ED}'
+        | '\u{0711}'
+        | '\u{0730}'..='\u{074A}'
+        | '\u{07A6}'..='\u{07B0}'
+        | '\u{07EB}'..='\u{07F3}'
+        | '\u{07FD}'
+        | '\u{0816}'..='\u{0819}'
+        | '\u{081B}'..='\u{0823}'
+        | '\u{0825}'..='\u{0827}'
+        | '\u{0829}'..='\u{082D}'
+        | '\u{0859}'..='\u{085B}'
+        | '\u{0898}'..='\u{089F}'
+        | '\u{08CA}'..='\u{08E1}'
+        | '\u{08E3}'..='\u{0902}'
+        | '\u{093A}'
+        | '\u{093C}'
+        | '\u{0941}'..='\u{0948}'
+        | '\u{094D}'
+        | '\u{0951}'..='\u{0957}'
+        | '\u{0962}'
+        | '\u{0963}'
+        | '\u{0E31}'
+        | '\u{0E34}'..='\u{0E3A}'
+        | '\u{0E47}'..='\u{0E4E}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20F0}'
+        | '\u{302A}'..='\u{302D}'
+        | '\u{3099}'
+        | '\u{309A}'
+        | '\u{FB1E}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{E0100}'..='\u{E01EF}' => NonspacingMark,
+
+        '\u{0590}'..='\u{05FF}'
+        | '\u{07C0}'..='\u{085F}'
+        | '\u{FB1D}'..='\u{FB4F}'
+        | '\u{10800}'..='\u{10CFF}'
+        | '\u{10D40}'..='\u{10EBF}'
+        | '\u{10F00}'..='\u{10F2F}'
+        | '\u{10F70}'..='\u{10FFF}'
+        | '\u{1E800}'..='\u{1EC6F}'
+        | '\u{1ECC0}'..='\u{1ECFF}'
+        | '\u{1ED50}'..='\u{1EDFF}'
+        | '\u{1EF00}'..='\u{1EFFF}' => RightToLeft,
+        '\u{0600}'..='\u{07BF}'
+        | '\u{0860}'..='\u{08FF}'
+        | '\u{FB50}'..='\u{FD3D}'
+        | '\u{FD50}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FDFF}'
+        | '\u{FE70}'..='\u{FEFE}'
+        | '\u{10D00}'..='\u{10D3F}'
+        | '\u{10EC0}'..='\u{10EFF}'
+        | '\u{10F30}'..='\u{10F6F}'
+        | '\u{1EC70}'..='\u{1ECBF}'
+        | '\u{1ED00}'..='\u{1ED4F}'
+        | '\u{1EE00}'..='\u{1EEFF}' => ArabicLetter,
+
+        '!' | '"' | '&' | '\'' | '(' | ')' | '*' | ';' | '<' | '=' | '>' | '?' | '@' | '[' | '\\' | ']' | '^' | '_'
+        | '`' | '{' | '|' | '}' | '~' | '\u{A1}' | '\u{A6}'..='\u{A9}' | '\u{AB}' | '\u{AC}' | '\u{AE}' | '\u{AF}'
+        | '\u{B4}' | '\u{B6}'..='\u{B8}' | '\u{BB}'..='\u{BF}' | '\u{D7}' | '\u{F7}' | '\u{FD3E}' | '\u{FD3F}'
+        | '\u{2010}'..='\u{2027}' | '\u{2035}'..='\u{2043}' | '\u{2045}'..='\u{205E}' | '\u{207C}'..='\u{207E}'
+        | '\u{208C}'..='\u{208E}' | '\u{2190}'..='\u{2211}' | '\u{2214}'..='\u{2335}' | '\u{237B}'..='\u{2394}'
+        | '\u{2396}'..='\u{2487}' | '\u{24EA}'..='\u{26AB}' | '\u{26AD}'..='\u{27FF}' | '\u{2900}'..='\u{2B73}'
+        | '\u{2E00}'..='\u{2E5D}' | '\u{3001}'..='\u{3004}' | '\u{3008}'..='\u{3020}' | '\u{3030}' | '\u{303D}'..='\u{303F}'
+        | '\u{FE10}'..='\u{FE19}' | '\u{FE30}'..='\u{FE4F}' | '\u{FE51}' | '\u{FE54}' | '\u{FE56}'..='\u{FE5E}'
+        | '\u{FE60}' | '\u{FE61}' | '\u{FE64}'..='\u{FE66}' | '\u{FE68}' | '\u{FE6B}' | '\u{FF01}' | '\u{FF02}'
+        | '\u{FF06}'..='\u{FF0A}' | '\u{FF1B}'..='\u{FF20}' | '\u{FF3B}'..='\u{FF40}' | '\u{FF5B}'..='\u{FF65}'
+        | '\u{FFE2}'..='\u{FFE4}' | '\u{FFE8}'..='\u{FFEE}' | '\u{FFF9}'..='\u{FFFD}' => OtherNeutral,
+        _ => LeftToRight,
+    }
+}
+
+/// Whether any of `text` may read right to left, so its levels need resolving
+pub fn needs_bidi(text: &str) -> bool {
+    text.chars().any(|c| {
+        matches!(
+            bidi_class(c),
+            RightToLeft | ArabicLetter | ArabicNumber | RightToLeftEmbedding | RightToLeftOverride | RightToLeftIsolate | FirstStrongIsolate
+        )
+    })
+}
+
+/// The closing bracket of opening bracket `c`, or None if it is not one (BD14)
+fn closing_bracket(c: char) -> Option<char> {
+    match c {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        '\u{2045}' => Some('\u{2046}'),
+        '\u{207D}' => Some('\u{207E}'),
+        '\u{208D}' => Some('\u{208E}'),
+        '\u{2329}' | '\u{3008}' => Some('\u{3009}'),
+        '\u{300A}' => Some('\u{300B}'),
+        '\u{300C}' => Some('\u{300D}'),
+        '\u{300E}' => Some('\u{300F}'),
+        '\u{3010}' => Some('\u{3011}'),
+        '\u{3014}' => Some('\u{3015}'),
+        '\u{3016}' => Some('\u{3017}'),
+        '\u{3018}' => Some('\u{3019}'),
+        '\u{301A}' => Some('\u{301B}'),
+        '\u{FF08}' => Some('\u{FF09}'),
+        '\u{FF3B}' => Some('\u{FF3D}'),
+        '\u{FF5B}' => Some('\u{FF5D}'),
+        '\u{FF5F}' => Some('\u{FF60}'),
+        '\u{FF62}' => Some('\u{FF63}'),
+        _ => None,
+    }
+}
+
+/// Base direction of a paragraph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    LeftToRight,
+    RightToLeft,
+}
+
+impl Direction {
+    fn level(self) -> u8 {
+        match self {
+            Direction::LeftToRight => 0,
+            Direction::RightToLeft => 1,
+        }
+    }
+}
+
+/// Chars of a line at one embedding level, in logical order within the run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BidiRun {
+    /// Char indices into the paragraph text
+    pub range: Range<usize>,
+    pub level: u8,
+}
+
+impl BidiRun {
+    /// Whether the run reads right to left
+    pub fn is_rtl(&self) -> bool {
+        self.level % 2 == 1
+    }
+}
+
+/// A paragraph with the embedding level of each of its chars
+#[derive(Debug, Clone)]
+pub struct BidiParagraph {
+    chars: Vec<char>,
+    /// Bidi classes of the chars, as in the text
+    classes: Vec<BidiClass>,
+    levels: Vec<u8>,
+    base_level: u8,
+}
+
+/// An entry of the directional status stack (X1)
+#[derive(Clone, Copy)]
+struct Status {
+    level: u8,
+    /// Class the chars are overridden to, if any
+    override_class: Option<BidiClass>,
+    isolate: bool,
+}
+
+impl BidiParagraph {
+    /// Resolve the levels of `text`, a single paragraph, in `direction` or,
+    /// without one, the direction of its first strong char (P2–P3)
+    pub fn new(text: &str, direction: Option<Direction>) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let classes: Vec<BidiClass> = chars.iter().map(|&c| bidi_class(c)).collect();
+        let matching_pdi = matching_isolates(&classes);
+        let base_level = direction.map_or_else(|| first_strong_level(&classes, &matching_pdi, 0).unwrap_or(0), Direction::level);
+
+        let mut paragraph = BidiParagraph { chars, classes, levels: Vec::new(), base_level };
+        let mut types = paragraph.explicit_levels(&matching_pdi);
+        let retained: Vec<usize> = (0..types.len()).filter(|&i| !paragraph.classes[i].is_removed()).collect();
+        for sequence in paragraph.isolating_run_sequences(&retained, &matching_pdi) {
+            paragraph.resolve_sequence(&sequence, &mut types);
+        }
+
+        // X9 removed chars take the level of the char before them
+        let mut previous = base_level;
+        for i in 0..types.len() {
+            if paragraph.classes[i].is_removed() {
+                paragraph.levels[i] = previous;
+            }
+            previous = paragraph.levels[i];
+        }
+        paragraph
+    }
+
+    /// Embedding level of the paragraph: 0 left to right, 1 right to left
+    pub fn base_level(&self) -> u8 {
+        self.base_level
+    }
+
+    /// Resolved level of each char, before lines are reordered
+    pub fn levels(&self) -> &[u8] {
+        &self.levels
+    }
+
+    /// Whether any of the paragraph reads right to left
+    pub fn has_rtl(&self) -> bool {
+        self.levels.iter().any(|level| level % 2 == 1)
+    }
+
+    /// Levels of the chars of `line`, with trailing whitespace and separators
+    /// at the paragraph level (L1)
+    pub fn line_levels(&self, line: Range<usize>) -> Vec<u8> {
+        let line = line.start.min(self.levels.len())..line.end.min(self.levels.len());
+        let mut levels = self.levels[line.clone()].to_vec();
+        let resettable = |class: BidiClass| class == WhiteSpace || class.is_isolate_initiator() || class == PopDirectionalIsolate || class.is_removed();
+        // Whether the chars from here on up to a separator or the end of the line are whitespace
+        let mut trailing = true;
+        for offset in (0..levels.len()).rev() {
+            let class = self.classes[line.start + offset];
+            if matches!(class, SegmentSeparator | ParagraphSeparator) {
+                levels[offset] = self.base_level;
+                trailing = true;
+            } else if resettable(class) {
+                if trailing {
+                    levels[offset] = self.base_level;
+                }
+            } else {
+                trailing = false;
+            }
+        }
+        levels
+    }
+
+    /// Runs of `line` at one level, in logical order
+    pub fn runs(&self, line: Range<usize>) -> Vec<BidiRun> {
+        let levels = self.line_levels(line.clone());
+        let mut runs: Vec<BidiRun> = Vec::new();
+        for (offset, &level) in levels.iter().enumerate() {
+            let index = line.start + offset;
+            match runs.last_mut() {
+                Some(run) if run.level == level => run.range.end = index + 1,
+                _ => runs.push(BidiRun { range: index..index + 1, level }),
+            }
+        }
+        runs
+    }
+
+    /// Runs of `line` in the order they are displayed, left to right (L2)
+    pub fn visual_runs(&self, line: Range<usize>) -> Vec<BidiRun> {
+        let mut runs = self.runs(line);
+        let Some(highest) = runs.iter().map(|run| run.level).max() else {
+            return runs;
+        };
+        let lowest_odd = runs.iter().map(|run| run.level).filter(|level| level % 2 == 1).min().unwrap_or(highest + 1);
+        for level in (lowest_odd..=highest).rev() {
+            let mut i = 0;
+            while i < runs.len() {
+                if runs[i].level < level {
+                    i += 1;
+                    continue;
+                }
+                let start = i;
+                while i < runs.len() && runs[i].level >= level {
+                    i += 1;
+                }
+                runs[start..i].reverse();
+            }
+        }
+        runs
+    }
+
+    /// Char index shown at each visual position of `line`, left to right
+    pub fn visual_order(&self, line: Range<usize>) -> Vec<usize> {
+        let mut order = Vec::new();
+        for run in self.visual_runs(line) {
+            match run.is_rtl() {
+                true => order.extend(run.range.rev()),
+                false => order.extend(run.range),
+            }
+        }
+        order
+    }
+
+    /// Visual caret position, counted in chars from the left of `line`, of
+    /// the caret before char `offset` (or at the end of the line)
+    ///
+    /// A caret sits on the side of its char the char is read from: the left
+    /// of a left-to-right char, the right of a right-to-left one.
+    pub fn caret_to_visual(&self, line: Range<usize>, offset: usize) -> usize {
+        let order = self.visual_order(line.clone());
+        let levels = self.line_levels(line.clone());
+        let rtl = |index: usize| levels[index - line.start] % 2 == 1;
+        if let Some(slot) = order.iter().position(|&index| index == offset) {
+            return if rtl(offset) { slot + 1 } else { slot };
+        }
+        // After the last char of the line
+        match offset.checked_sub(1).and_then(|last| order.iter().position(|&index| index == last)) {
+            Some(slot) if rtl(offset - 1) => slot,
+            Some(slot) => slot + 1,
+            None => 0,
+        }
+    }
+
+    /// Char offset of the caret at visual position `position` of `line`,
+    /// counted in chars from the left; the inverse of [`Self::caret_to_visual`]
+    pub fn visual_to_caret(&self, line: Range<usize>, position: usize) -> usize {
+        let order = self.visual_order(line.clone());
+        let levels = self.line_levels(line.clone());
+        let rtl = |index: usize| levels[index - line.start] % 2 == 1;
+        if let Some(&index) = order.get(position) {
+            // The left edge of the char at the position
+            return if rtl(index) { index + 1 } else { index };
+        }
+        // The right edge of the last char shown
+        match order.last() {
+            Some(&index) if rtl(index) => index,
+            Some(&index) => index + 1,
+            None => line.start,
+        }
+    }
+
+    /// Levels and classes after the explicit rules X1–X8, with overrides applied
+    fn explicit_levels(&mut self, matching_pdi: &[Option<usize>]) -> Vec<BidiClass> {
+        let mut types = self.classes.clone();
+        self.levels = vec![self.base_level; types.len()];
+        let mut stack = vec![Status { level: self.base_level, override_class: None, isolate: false }];
+        let (mut overflow_isolates, mut overflow_embeddings, mut valid_isolates) = (0usize, 0usize, 0usize);
+        let next_level = |level: u8, rtl: bool| match rtl {
+            true => (level + 1) | 1,
+            false => (level + 2) & !1,
+        };
+
+        for i in 0..types.len() {
+            let top = *stack.last().unwrap();
+            let class = self.classes[i];
+            match class {
+                RightToLeftEmbedding | LeftToRightEmbedding | RightToLeftOverride | LeftToRightOverride => {
+                    self.levels[i] = top.level;
+                    let level = next_level(top.level, matches!(class, RightToLeftEmbedding | RightToLeftOverride));
+                    if level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                        let override_class = match class {
+                            RightToLeftOverride => Some(RightToLeft),
+                            LeftToRightOverride => Some(LeftToRight),
+                            _ => None,
+                        };
+                        stack.push(Status { level, override_class, isolate: false });
+                    } else if overflow_isolates == 0 {
+                        overflow_embeddings += 1;
+                    }
+                }
+                RightToLeftIsolate | LeftToRightIsolate | FirstStrongIsolate => {
+                    self.levels[i] = top.level;
+                    if let Some(class) = top.override_class {
+                        types[i] = class;
+                    }
+                    let rtl = match class {
+                        RightToLeftIsolate => true,
+                        LeftToRightIsolate => false,
+                        _ => {
+                            let end = matching_pdi[i].unwrap_or(types.len());
+                            first_strong_level(&self.classes[..end], matching_pdi, i + 1) == Some(1)
+                        }
+                    };
+                    let level = next_level(top.level, rtl);
+                    if level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                        valid_isolates += 1;
+                        stack.push(Status { level, override_class: None, isolate: true });
+                    } else {
+                        overflow_isolates += 1;
+                    }
+                }
+                PopDirectionalIsolate => {
+                    if overflow_isolates > 0 {
+                        overflow_isolates -= 1;
+                    } else if valid_isolates > 0 {
+                        overflow_embeddings = 0;
+                        while stack.last().is_some_and(|status| !status.isolate) {
+                            stack.pop();
+                        }
+                        stack.pop();
+                        valid_isolates -= 1;
+                    }
+                    let top = *stack.last().unwrap();
+                    self.levels[i] = top.level;
+                    if let Some(class) = top.override_class {
+                        types[i] = class;
+                    }
+                }
+                PopDirectionalFormat => {
+                    self.levels[i] = top.level;
+                    match (overflow_isolates, overflow_embeddings) {
+                        (0, 0) if !top.isolate && stack.len() >= 2 => {
+                            stack.pop();
+                        }
+                        (0, 1..) => overflow_embeddings -= 1,
+                        _ => {}
+                    }
+                }
+                ParagraphSeparator => self.levels[i] = self.base_level,
+                BoundaryNeutral => self.levels[i] = top.level,
+                _ => {
+                    self.levels[i] = top.level;
+                    if let Some(class) = top.override_class {
+                        types[i] = class;
+                    }
+                }
+            }
+        }
+        types
+    }
+
+    /// The isolating run sequences of the `retained` chars (BD13, X10)
+    fn isolating_run_sequences(&self, retained: &[usize], matching_pdi: &[Option<usize>]) -> Vec<Vec<usize>> {
+        // Level runs of the retained chars
+        let mut runs: Vec<Vec<usize>> = Vec::new();
+        for &i in retained {
+            match runs.last_mut() {
+                Some(run) if self.levels[*run.last().unwrap()] == self.levels[i] => run.push(i),
+                _ => runs.push(vec![i]),
+            }
+        }
+        let run_starting_at = |index: usize| runs.iter().position(|run| run[0] == index);
+        let continued: Vec<bool> = runs
+            .iter()
+            .map(|run| self.classes[run[0]] == PopDirectionalIsolate && matching_pdi.contains(&Some(run[0])))
+            .collect();
+
+        let mut sequences = Vec::new();
+        for (start, run) in runs.iter().enumerate() {
+            if continued[start] {
+                continue;
+            }
+            let mut sequence = run.clone();
+            loop {
+                let last = *sequence.last().unwrap();
+                let next = matching_pdi[last]
+                    .filter(|_| self.classes[last].is_isolate_initiator())
+                    .and_then(run_starting_at);
+                match next {
+                    Some(next) => sequence.extend_from_slice(&runs[next]),
+                    None => break,
+                }
+            }
+            sequences.push(sequence);
+        }
+        sequences
+    }
+
+    /// Resolve the weak, neutral and implicit types of one isolating run
+    /// sequence (W1–W7, N0–N2, I1–I2)
+    fn resolve_sequence(&mut self, sequence: &[usize], types: &mut [BidiClass]) {
+        let level = self.levels[sequence[0]];
+        let first = sequence[0];
+        let last = *sequence.last().unwrap();
+        let level_before = (0..first).rev().find(|&i| !self.classes[i].is_removed()).map_or(self.base_level, |i| self.levels[i]);
+        let level_after = match self.classes[last].is_isolate_initiator() {
+            true => self.base_level,
+            false => (last + 1..self.levels.len())
+                .find(|&i| !self.classes[i].is_removed())
+                .map_or(self.base_level, |i| self.levels[i]),
+        };
+        let direction = |level: u8| if level % 2 == 1 { RightToLeft } else { LeftToRight };
+        let sos = direction(level.max(level_before));
+        let eos = direction(level.max(level_after));
+        let embedding = direction(level);
+
+        // W1: marks take the type of the char before them
+        let mut previous = sos;
+        for &i in sequence {
+            if types[i] == NonspacingMark {
+                types[i] = match previous {
+                    LeftToRightIsolate | RightToLeftIsolate | FirstStrongIsolate | PopDirectionalIsolate => OtherNeutral,
+                    class => class,
+                };
+            }
+            previous = types[i];
+        }
+        // W2: European numbers after Arabic letters are Arabic numbers; W3: AL → R
+        let mut last_strong = sos;
+        for &i in sequence {
+            match types[i] {
+                EuropeanNumber if last_strong == ArabicLetter => types[i] = ArabicNumber,
+                class if class.is_strong() => last_strong = class,
+                _ => {}
+            }
+        }
+        for &i in sequence {
+            if types[i] == ArabicLetter {
+                types[i] = RightToLeft;
+            }
+        }
+        // W4: a single separator between two numbers of a kind joins them
+        for k in 1..sequence.len().saturating_sub(1) {
+            let (before, this, after) = (types[sequence[k - 1]], types[sequence[k]], types[sequence[k + 1]]);
+            types[sequence[k]] = match (before, this, after) {
+                (EuropeanNumber, EuropeanSeparator | CommonSeparator, EuropeanNumber) => EuropeanNumber,
+                (ArabicNumber, CommonSeparator, ArabicNumber) => ArabicNumber,
+                _ => this,
+            };
+        }
+        // W5: terminators next to European numbers are part of them
+        let mut k = 0;
+        while k < sequence.len() {
+            if types[sequence[k]] != EuropeanTerminator {
+                k += 1;
+                continue;
+            }
+            let start = k;
+            while k < sequence.len() && types[sequence[k]] == EuropeanTerminator {
+                k += 1;
+            }
+            let touches_number = (start > 0 && types[sequence[start - 1]] == EuropeanNumber)
+                || (k < sequence.len() && types[sequence[k]] == EuropeanNumber);
+            if touches_number {
+                for &i in &sequence[start..k] {
+                    types[i] = EuropeanNumber;
+                }
+            }
+        }
+        // W6: other separators and terminators are neutral
+        for &i in sequence {
+            if matches!(types[i], EuropeanSeparator | EuropeanTerminator | CommonSeparator) {
+                types[i] = OtherNeutral;
+            }
+        }
+        // W7: European numbers after left-to-right text read left to right
+        let mut last_strong = sos;
+        for &i in sequence {
+            match types[i] {
+                EuropeanNumber if last_strong == LeftToRight => types[i] = LeftToRight,
+                class @ (LeftToRight | RightToLeft) => last_strong = class,
+                _ => {}
+            }
+        }
+
+        self.resolve_brackets(sequence, types, sos, embedding);
+
+        // N1–N2: neutrals between strong types of one direction take it, others the embedding direction
+        let strong = |class: BidiClass| match class {
+            LeftToRight => Some(LeftToRight),
+            RightToLeft | EuropeanNumber | ArabicNumber => Some(RightToLeft),
+            _ => None,
+        };
+        let mut k = 0;
+        while k < sequence.len() {
+            if !types[sequence[k]].is_neutral() {
+                k += 1;
+                continue;
+            }
+            let start = k;
+            while k < sequence.len() && types[sequence[k]].is_neutral() {
+                k += 1;
+            }
+            let before = if start == 0 { Some(sos) } else { strong(types[sequence[start - 1]]) };
+            let after = if k == sequence.len() { Some(eos) } else { strong(types[sequence[k]]) };
+            let resolved = match (before, after) {
+                (Some(before), Some(after)) if before == after => before,
+                _ => embedding,
+            };
+            for &i in &sequence[start..k] {
+                types[i] = resolved;
+            }
+        }
+
+        // I1–I2
+        for &i in sequence {
+            let level = self.levels[i];
+            self.levels[i] = match (level % 2 == 1, types[i]) {
+                (false, RightToLeft) => level + 1,
+                (false, ArabicNumber | EuropeanNumber) => level + 2,
+                (true, LeftToRight | EuropeanNumber | ArabicNumber) => level + 1,
+                _ => level,
+            };
+        }
+    }
+
+    /// N0: paired brackets take the direction of the text they enclose, or
+    /// the context they are in
+    fn resolve_brackets(&self, sequence: &[usize], types: &mut [BidiClass], sos: BidiClass, embedding: BidiClass) {
+        // BD16: pairs, by position in the sequence
+        let mut pairs = Vec::new();
+        let mut openers: Vec<(char, usize)> = Vec::new();
+        for (k, &i) in sequence.iter().enumerate() {
+            if types[i] != OtherNeutral {
+                continue;
+            }
+            let c = self.chars[i];
+            if let Some(closing) = closing_bracket(c) {
+                if openers.len() == MAX_BRACKETS {
+                    break;
+                }
+                openers.push((closing, k));
+            } else if let Some(depth) = openers.iter().rposition(|&(closing, _)| closing == c || (c == '\u{232A}' && closing == '\u{3009}')) {
+                pairs.push((openers[depth].1, k));
+                openers.truncate(depth);
+            }
+        }
+        pairs.sort_unstable();
+
+        let strong = |class: BidiClass| match class {
+            LeftToRight => Some(LeftToRight),
+            RightToLeft | EuropeanNumber | ArabicNumber => Some(RightToLeft),
+            _ => None,
+        };
+        for (open, close) in pairs {
+            let inside: Vec<BidiClass> = sequence[open + 1..close].iter().filter_map(|&i| strong(types[i])).collect();
+            let resolved = if inside.contains(&embedding) {
+                Some(embedding)
+            } else if let Some(&opposite) = inside.first() {
+                let context = sequence[..open].iter().rev().find_map(|&i| strong(types[i])).unwrap_or(sos);
+                Some(if context == opposite { opposite } else { embedding })
+            } else {
+                None
+            };
+            if let Some(class) = resolved {
+                types[sequence[open]] = class;
+                types[sequence[close]] = class;
+                // Marks after a bracket follow it
+                for brackets in [open, close] {
+                    for &i in sequence[brackets + 1..].iter().take_while(|&&i| self.classes[i] == NonspacingMark) {
+                        types[i] = class;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The PDI matching each isolate initiator, if it has one (BD9)
+fn matching_isolates(classes: &[BidiClass]) -> Vec<Option<usize>> {
+    let mut matching = vec![None; classes.len()];
+    let mut open: Vec<usize> = Vec::new();
+    for (i, class) in classes.iter().enumerate() {
+        match class {
+            class if class.is_isolate_initiator() => open.push(i),
+            PopDirectionalIsolate => {
+                if let Some(initiator) = open.pop() {
+                    matching[initiator] = Some(i);
+                }
+            }
+            ParagraphSeparator => open.clear(),
+            _ => {}
+        }
+    }
+    matching
+}
+
+/// Level of the first strong char from `start` on, skipping isolates (P2–P3)
+fn first_strong_level(classes: &[BidiClass], matching_pdi: &[Option<usize>], start: usize) -> Option<u8> {
+    let mut i = start;
+    while i < classes.len() {
+        match classes[i] {
+            LeftToRight => return Some(0),
+            RightToLeft | ArabicLetter => return Some(1),
+            ParagraphSeparator => return None,
+            class if class.is_isolate_initiator() => match matching_pdi[i] {
+                Some(pdi) => i = pdi,
+                None => return None,
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(text: &str, direction: Option<Direction>) -> Vec<u8> {
+        BidiParagraph::new(text, direction).levels().to_vec()
+    }
+
+    fn visual(text: &str, direction: Option<Direction>) -> String {
+        let paragraph = BidiParagraph::new(text, direction);
+        let chars: Vec<char> = text.chars().collect();
+        paragraph.visual_order(0..chars.len()).into_iter().map(|i| chars[i]).collect()
+    }
+
+    #[test]
+    fn test_bidi_classes() {
+        assert_eq!(bidi_class('a'), LeftToRight);
+        assert_eq!(bidi_class('א'), RightToLeft);
+        assert_eq!(bidi_class('ب'), ArabicLetter);
+        assert_eq!(bidi_class('٣'), ArabicNumber);
+        assert_eq!(bidi_class('7'), EuropeanNumber);
+        assert_eq!(bidi_class('$'), EuropeanTerminator);
+        assert_eq!(bidi_class(','), CommonSeparator);
+        assert_eq!(bidi_class('\u{05B4}'), NonspacingMark);
+        assert_eq!(bidi_class(' '), WhiteSpace);
+        assert_eq!(bidi_class('!'), OtherNeutral);
+        assert_eq!(bidi_class('中'), LeftToRight);
+    }
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(levels("abc", None), vec![0, 0, 0]);
+        assert_eq!(BidiParagraph::new("אב", None).base_level(), 1);
+        // Numbers in right-to-left text are raised by two
+        assert_eq!(levels("אב 12", None), vec![1, 1, 1, 2, 2]);
+        // A separator between numbers belongs to them
+        assert_eq!(levels("א 1.5", None), vec![1, 1, 2, 2, 2]);
+        // European numbers after Arabic letters are Arabic numbers
+        assert_eq!(levels("ب 12", Some(Direction::LeftToRight)), vec![1, 1, 2, 2]);
+        // A neutral between two directions takes the paragraph's
+        assert_eq!(levels("ab אב", None), vec![0, 0, 0, 1, 1]);
+        assert_eq!(levels("ab אב", Some(Direction::RightToLeft)), vec![2, 2, 1, 1, 1]);
+        assert!(!BidiParagraph::new("plain", None).has_rtl());
+        assert!(!needs_bidi("plain 123") && needs_bidi("plain אב"));
+    }
+
+    #[test]
+    fn test_reordering() {
+        assert_eq!(visual("abc", None), "abc");
+        assert_eq!(visual("car אבג.", None), "car גבא.");
+        assert_eq!(visual("אבג 123", None), "123 גבא");
+        assert_eq!(visual("אבג abc", None), "abc גבא");
+        // Brackets take the direction of the text they enclose, or of their context
+        assert_eq!(visual("אב (גד) הו", None), "וה )דג( בא");
+        assert_eq!(visual("ab (אב) cd", None), "ab (בא) cd");
+    }
+
+    #[test]
+    fn test_explicit_formatting() {
+        // An override makes letters read its way
+        assert_eq!(visual("\u{202E}abc\u{202C}", Some(Direction::LeftToRight)), "\u{202E}cba\u{202C}");
+        assert_eq!(levels("a\u{202B}b\u{202C}c", None), vec![0, 0, 2, 2, 0]);
+        // An isolate keeps its direction out of the text around it
+        let isolated = levels("אב \u{2066}ab\u{2069} גד", None);
+        assert_eq!(isolated[4..6], [2, 2]);
+        assert_eq!(isolated[3] % 2, 1);
+        // First strong isolate takes the direction of its contents
+        assert_eq!(levels("a \u{2068}אב\u{2069}", None)[3..5], [1, 1]);
+    }
+
+    #[test]
+    fn test_trailing_whitespace_takes_paragraph_level() {
+        let paragraph = BidiParagraph::new("אב  ", Some(Direction::LeftToRight));
+        assert_eq!(paragraph.levels(), &[1, 1, 0, 0]);
+        let paragraph = BidiParagraph::new("ab  ", Some(Direction::RightToLeft));
+        assert_eq!(paragraph.line_levels(0..4), vec![2, 2, 1, 1]);
+        assert_eq!(paragraph.line_levels(0..3), vec![2, 2, 1]);
+        let tabbed = BidiParagraph::new("אב \tגד", Some(Direction::LeftToRight));
+        assert_eq!(tabbed.line_levels(0..6), vec![1, 1, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_caret_mapping() {
+        // "ab" then Hebrew shown reversed: a b ' ' ג ב א
+        let text = "ab אבג";
+        let paragraph = BidiParagraph::new(text, None);
+        let line = 0..6;
+        assert_eq!(paragraph.visual_order(line.clone()), vec![0, 1, 2, 5, 4, 3]);
+        assert_eq!(paragraph.caret_to_visual(line.clone(), 0), 0);
+        assert_eq!(paragraph.caret_to_visual(line.clone(), 2), 2);
+        // Before א is at its right, the right end of the line
+        assert_eq!(paragraph.caret_to_visual(line.clone(), 3), 6);
+        assert_eq!(paragraph.caret_to_visual(line.clone(), 5), 4);
+        // After ג, the last char, is at its left
+        assert_eq!(paragraph.caret_to_visual(line.clone(), 6), 3);
+        for offset in [0, 1, 2, 4, 5] {
+            let position = paragraph.caret_to_visual(line.clone(), offset);
+            assert_eq!(paragraph.visual_to_caret(line.clone(), position), offset);
+        }
+        assert_eq!(paragraph.visual_to_caret(line.clone(), 6), 3);
+    }
+}
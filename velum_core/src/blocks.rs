@@ -0,0 +1,368 @@
+//! # Blocks Module
+//!
+//! Typed walk over a [`DocumentModel`] in document order.
+//!
+//! [`DocumentModel::blocks`] yields the body's paragraphs and tables, a
+//! content control before the paragraphs it wraps and a section break after
+//! the last paragraph of each section. Every block carries its range: where
+//! it sits in the body, the body paragraphs it covers and their chars in the
+//! body text, which joins paragraphs with '\n' as the editor does. Tables are
+//! not part of that text, so their ranges are empty, at the paragraph that
+//! follows them.
+//!
+//! Table cells and note bodies are walked with [`cells`] and
+//! [`DocumentModel::notes`].
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{ContentControl, NoteKind, Paragraph, Section, Table, TableCell};
+
+/// Where a block sits in the document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockRange {
+    /// Index in [`DocumentModel::body`]; for a content control or section break,
+    /// the index of the block that follows it
+    pub body_index: usize,
+    /// Body paragraphs covered
+    pub paragraphs: Range<usize>,
+    /// Chars covered in the body text
+    pub text: Range<usize>,
+}
+
+/// A block of the document, borrowed from the model
+#[derive(Debug, Clone)]
+pub enum BlockRef<'a> {
+    Paragraph { paragraph: &'a Paragraph, range: BlockRange },
+    Table { table: &'a Table, range: BlockRange },
+    /// End of a section; the range covers the whole section
+    SectionBreak { section: &'a Section, index: usize, range: BlockRange },
+    /// Start of a content control; the range covers the paragraphs it wraps
+    ContentControl { control: &'a ContentControl, range: BlockRange },
+}
+
+impl<'a> BlockRef<'a> {
+    pub fn range(&self) -> &BlockRange {
+        match self {
+            BlockRef::Paragraph { range, .. }
+            | BlockRef::Table { range, .. }
+            | BlockRef::SectionBreak { range, .. }
+            | BlockRef::ContentControl { range, .. } => range,
+        }
+    }
+}
+
+/// Iterator over the blocks of a model, see [`DocumentModel::blocks`]
+pub struct Blocks<'a> {
+    model: &'a DocumentModel,
+    /// Char range of each body paragraph in the body text
+    paragraph_text: Vec<Range<usize>>,
+    body_index: usize,
+    paragraph_index: usize,
+    next_section: usize,
+    next_control: usize,
+    pending: VecDeque<BlockRef<'a>>,
+}
+
+impl<'a> Blocks<'a> {
+    fn new(model: &'a DocumentModel) -> Self {
+        let mut start = 0;
+        let paragraph_text = model
+            .paragraphs()
+            .map(|paragraph| {
+                let end = start + paragraph.text.chars().count();
+                let range = start..end;
+                start = end + 1;
+                range
+            })
+            .collect();
+        Blocks {
+            model,
+            paragraph_text,
+            body_index: 0,
+            paragraph_index: 0,
+            next_section: 0,
+            next_control: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn range(&self, paragraphs: Range<usize>) -> BlockRange {
+        let text = match (self.paragraph_text.get(paragraphs.start), paragraphs.end.checked_sub(1)) {
+            (Some(first), Some(last)) if paragraphs.start < paragraphs.end => {
+                first.start..self.paragraph_text.get(last).map_or(first.end, |r| r.end)
+            }
+            (Some(first), _) => first.start..first.start,
+            _ => {
+                let end = self.paragraph_text.last().map_or(0, |r| r.end);
+                end..end
+            }
+        };
+        BlockRange {
+            body_index: self.body_index,
+            paragraphs,
+            text,
+        }
+    }
+
+    /// Queue the breaks of the sections that end before paragraph `end`
+    fn queue_section_breaks(&mut self, end: usize) {
+        let sections = &self.model.sections;
+        while let Some(section) = sections.get(self.next_section) {
+            let section_end = section.first_paragraph + section.paragraph_count;
+            if section_end > end {
+                break;
+            }
+            let first = section.first_paragraph.min(section_end);
+            let range = self.range(first..section_end);
+            self.pending.push_back(BlockRef::SectionBreak {
+                section,
+                index: self.next_section,
+                range,
+            });
+            self.next_section += 1;
+        }
+    }
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = BlockRef<'a>;
+
+    fn next(&mut self) -> Option<BlockRef<'a>> {
+        if let Some(block) = self.pending.pop_front() {
+            return Some(block);
+        }
+
+        let Some(block) = self.model.body.get(self.body_index) else {
+            // Sections left over cover the end of the body
+            self.queue_section_breaks(usize::MAX);
+            return self.pending.pop_front();
+        };
+        let paragraph = self.paragraph_index;
+        match block {
+            Block::Table(table) => {
+                let range = self.range(paragraph..paragraph);
+                self.pending.push_back(BlockRef::Table { table, range });
+            }
+            Block::Paragraph(body_paragraph) => {
+                let controls = &self.model.content_controls;
+                while let Some(control) = controls.get(self.next_control) {
+                    if control.first_paragraph > paragraph {
+                        break;
+                    }
+                    let range = self.range(control.first_paragraph..control.first_paragraph + control.paragraph_count);
+                    self.pending.push_back(BlockRef::ContentControl { control, range });
+                    self.next_control += 1;
+                }
+                let range = self.range(paragraph..paragraph + 1);
+                self.pending.push_back(BlockRef::Paragraph {
+                    paragraph: body_paragraph,
+                    range,
+                });
+                self.paragraph_index += 1;
+                self.body_index += 1;
+                self.queue_section_breaks(self.paragraph_index);
+                return self.pending.pop_front();
+            }
+        }
+        self.body_index += 1;
+        self.pending.pop_front()
+    }
+}
+
+/// A table cell with its position in the table
+#[derive(Debug, Clone, Copy)]
+pub struct CellRef<'a> {
+    pub row: usize,
+    /// Index of the cell in its row
+    pub column: usize,
+    /// First grid column the cell covers, counting the spans of the cells before it
+    pub grid_column: usize,
+    pub cell: &'a TableCell,
+}
+
+/// Cells of a table, row by row
+pub fn cells(table: &Table) -> impl Iterator<Item = CellRef<'_>> {
+    table.rows.iter().enumerate().flat_map(|(row, table_row)| {
+        let mut grid_column = 0;
+        table_row.cells.iter().enumerate().map(move |(column, cell)| {
+            let cell_ref = CellRef {
+                row,
+                column,
+                grid_column,
+                cell,
+            };
+            grid_column += cell.properties.grid_span.unwrap_or(1).max(1) as usize;
+            cell_ref
+        })
+    })
+}
+
+/// Body of a footnote or endnote
+#[derive(Debug, Clone, Copy)]
+pub struct NoteRef<'a> {
+    pub kind: NoteKind,
+    pub id: &'a str,
+    pub paragraphs: &'a [Paragraph],
+}
+
+impl DocumentModel {
+    /// Blocks of the body in document order
+    pub fn blocks(&self) -> Blocks<'_> {
+        Blocks::new(self)
+    }
+
+    /// Footnote then endnote bodies; separators are not notes and are left out
+    pub fn notes(&self) -> impl Iterator<Item = NoteRef<'_>> {
+        let is_note = |note_type: &Option<String>| note_type.as_deref().is_none_or(|t| t == "normal");
+        let footnotes = self
+            .footnotes
+            .iter()
+            .filter(move |note| is_note(&note.footnote_type))
+            .map(|note| NoteRef {
+                kind: NoteKind::Footnote,
+                id: &note.id,
+                paragraphs: &note.paragraphs,
+            });
+        let endnotes = self
+            .endnotes
+            .iter()
+            .filter(move |note| is_note(&note.endnote_type))
+            .map(|note| NoteRef {
+                kind: NoteKind::Endnote,
+                id: &note.id,
+                paragraphs: &note.paragraphs,
+            });
+        footnotes.chain(endnotes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::{Endnote, Footnote, TableCellProperties, TableRow};
+
+    fn paragraph(text: &str) -> Block {
+        Block::from(Paragraph {
+            text: text.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn table() -> Table {
+        let cell = |text: &str, span| TableCell {
+            paragraphs: vec![Paragraph {
+                text: text.to_string(),
+                ..Default::default()
+            }],
+            properties: TableCellProperties {
+                grid_span: span,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        Table {
+            rows: vec![TableRow {
+                cells: vec![cell("wide", Some(2)), cell("narrow", None)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn model() -> DocumentModel {
+        DocumentModel {
+            body: vec![
+                paragraph("Intro"),
+                Block::Table(Box::new(table())),
+                paragraph("Name"),
+                paragraph("Ann"),
+                paragraph("End"),
+            ],
+            sections: vec![
+                Section {
+                    first_paragraph: 0,
+                    paragraph_count: 1,
+                    ..Default::default()
+                },
+                Section {
+                    first_paragraph: 1,
+                    paragraph_count: 3,
+                    ..Default::default()
+                },
+            ],
+            content_controls: vec![ContentControl {
+                tag: Some("name".to_string()),
+                first_paragraph: 1,
+                paragraph_count: 2,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_blocks_in_document_order() {
+        let model = model();
+        let blocks: Vec<(&str, BlockRange)> = model
+            .blocks()
+            .map(|block| {
+                let kind = match block {
+                    BlockRef::Paragraph { .. } => "paragraph",
+                    BlockRef::Table { .. } => "table",
+                    BlockRef::SectionBreak { .. } => "section",
+                    BlockRef::ContentControl { .. } => "control",
+                };
+                (kind, block.range().clone())
+            })
+            .collect();
+        let range = |body_index, paragraphs, text| BlockRange { body_index, paragraphs, text };
+
+        assert_eq!(
+            blocks,
+            vec![
+                ("paragraph", range(0, 0..1, 0..5)),
+                ("section", range(1, 0..1, 0..5)),
+                ("table", range(1, 1..1, 6..6)),
+                ("control", range(2, 1..3, 6..14)),
+                ("paragraph", range(2, 1..2, 6..10)),
+                ("paragraph", range(3, 2..3, 11..14)),
+                ("paragraph", range(4, 3..4, 15..18)),
+                ("section", range(5, 1..4, 6..18)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cells_and_notes() {
+        let table = table();
+        let positions: Vec<(usize, usize, usize, &str)> = cells(&table)
+            .map(|c| (c.row, c.column, c.grid_column, c.cell.paragraphs[0].text.as_str()))
+            .collect();
+        assert_eq!(positions, vec![(0, 0, 0, "wide"), (0, 1, 2, "narrow")]);
+
+        let model = DocumentModel {
+            footnotes: vec![
+                Footnote {
+                    id: "-1".to_string(),
+                    footnote_type: Some("separator".to_string()),
+                    paragraphs: Vec::new(),
+                },
+                Footnote {
+                    id: "1".to_string(),
+                    footnote_type: None,
+                    paragraphs: vec![Paragraph::default()],
+                },
+            ],
+            endnotes: vec![Endnote {
+                id: "1".to_string(),
+                endnote_type: Some("normal".to_string()),
+                paragraphs: Vec::new(),
+            }],
+            ..Default::default()
+        };
+        let notes: Vec<(NoteKind, &str)> = model.notes().map(|note| (note.kind, note.id)).collect();
+        assert_eq!(notes, vec![(NoteKind::Footnote, "1"), (NoteKind::Endnote, "1")]);
+    }
+}
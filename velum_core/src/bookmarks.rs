@@ -0,0 +1,560 @@
+//! # Bookmarks Module
+//!
+//! Named ranges of editor text, and the cross-reference fields that point at
+//! them.
+//!
+//! A bookmark covers chars of the whole text and moves with later edits the
+//! way a comment anchor does. In OOXML it is a w:bookmarkStart / w:bookmarkEnd
+//! pair in the paragraphs, which may be in different paragraphs. REF, PAGEREF
+//! and NOTEREF fields name a bookmark as their first argument, so "see section
+//! X" can show the bookmarked text, its page or the number of the note it
+//! holds; [`update_cross_references`] brings their results up to date.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::comments::move_anchor;
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{BookmarkMark, BookmarkMarkKind, Field, FieldKind, NoteKind, Paragraph, Run};
+
+/// Word's limit on bookmark name length
+pub const MAX_NAME_LENGTH: usize = 40;
+
+/// Result Word shows for a cross-reference to a bookmark that does not exist
+pub const MISSING_REFERENCE: &str = "Error! Reference source not found.";
+
+/// Bookmark errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum BookmarkError {
+    #[error("Invalid bookmark name '{0}'")]
+    InvalidName(String),
+
+    #[error("Bookmark '{0}' does not exist")]
+    NotFound(String),
+}
+
+/// A named range of text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    /// ID pairing the bookmark's marks in OOXML
+    pub id: String,
+    pub name: String,
+    /// Char offset of the bookmarked text
+    pub start: usize,
+    /// Length of the bookmarked text in chars
+    pub length: usize,
+}
+
+impl Bookmark {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.length
+    }
+
+    /// Hidden bookmarks, such as Word's "_Toc" and "_Ref" ones, start with an underscore
+    pub fn is_hidden(&self) -> bool {
+        self.name.starts_with('_')
+    }
+}
+
+/// Bookmarks of a document, by name
+#[derive(Debug, Clone, Default)]
+pub struct BookmarkRegistry {
+    /// In the order they were read or added
+    bookmarks: Vec<Bookmark>,
+}
+
+impl BookmarkRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bookmarks from the bookmark marks of `paragraphs`, each given with the
+    /// char offset it starts at
+    ///
+    /// A start without an end is a bookmark of no text; an end without a start
+    /// is dropped.
+    pub fn from_paragraphs<'a>(paragraphs: impl IntoIterator<Item = (usize, &'a Paragraph)>) -> Self {
+        let mut bookmarks: Vec<Bookmark> = Vec::new();
+        let mut ends = Vec::new();
+        for (paragraph_start, paragraph) in paragraphs {
+            for mark in &paragraph.bookmark_marks {
+                let position = paragraph_start + mark.position;
+                match (mark.kind, &mark.name) {
+                    (BookmarkMarkKind::Start, Some(name)) => bookmarks.push(Bookmark {
+                        id: mark.id.clone(),
+                        name: name.clone(),
+                        start: position,
+                        length: 0,
+                    }),
+                    (BookmarkMarkKind::End, _) => ends.push((mark.id.clone(), position)),
+                    _ => {}
+                }
+            }
+        }
+        for bookmark in bookmarks.iter_mut() {
+            if let Some((_, end)) = ends.iter().find(|(id, _)| *id == bookmark.id) {
+                bookmark.length = end.saturating_sub(bookmark.start);
+            }
+        }
+        BookmarkRegistry { bookmarks }
+    }
+
+    /// Bookmarks of the model's body paragraphs
+    pub fn from_model(model: &DocumentModel) -> Self {
+        Self::from_paragraphs(model.located_paragraphs())
+    }
+
+    /// Hand the bookmarks to the model as marks in its body paragraphs
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let marks = bookmark_marks(&self.bookmarks);
+        let mut paragraph_start = 0;
+        for block in model.body.iter_mut() {
+            let Block::Paragraph(paragraph) = block else {
+                continue;
+            };
+            let length = paragraph.text.chars().count();
+            paragraph.bookmark_marks = paragraph_bookmark_marks(&marks, paragraph_start, length);
+            paragraph_start += length + 1;
+        }
+    }
+
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// Bookmarks in text order, hidden ones only if asked for
+    pub fn sorted(&self, include_hidden: bool) -> Vec<&Bookmark> {
+        let mut bookmarks: Vec<&Bookmark> = self
+            .bookmarks
+            .iter()
+            .filter(|bookmark| include_hidden || !bookmark.is_hidden())
+            .collect();
+        bookmarks.sort_by_key(|bookmark| (bookmark.start, bookmark.length));
+        bookmarks
+    }
+
+    /// The bookmark called `name`; names are compared ignoring case, as in Word
+    pub fn get(&self, name: &str) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|bookmark| bookmark.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bookmarks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bookmarks.is_empty()
+    }
+
+    /// Bookmarks whose range contains char `offset`, ends included
+    pub fn at(&self, offset: usize) -> Vec<&Bookmark> {
+        self.bookmarks
+            .iter()
+            .filter(|bookmark| (bookmark.start..=bookmark.start + bookmark.length).contains(&offset))
+            .collect()
+    }
+
+    /// Bookmark chars `range` as `name`; an existing bookmark of that name moves there
+    pub fn insert(&mut self, name: &str, range: Range<usize>) -> Result<&Bookmark, BookmarkError> {
+        if !is_valid_name(name) {
+            return Err(BookmarkError::InvalidName(name.to_string()));
+        }
+        let index = match self.bookmarks.iter().position(|b| b.name.eq_ignore_ascii_case(name)) {
+            Some(index) => index,
+            None => {
+                self.bookmarks.push(Bookmark {
+                    id: self.next_id(),
+                    name: String::new(),
+                    start: 0,
+                    length: 0,
+                });
+                self.bookmarks.len() - 1
+            }
+        };
+        let bookmark = &mut self.bookmarks[index];
+        bookmark.name = name.to_string();
+        bookmark.start = range.start;
+        bookmark.length = range.len();
+        Ok(bookmark)
+    }
+
+    /// Remove a bookmark, leaving its text alone
+    pub fn delete(&mut self, name: &str) -> Result<Bookmark, BookmarkError> {
+        let index = self
+            .bookmarks
+            .iter()
+            .position(|bookmark| bookmark.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| BookmarkError::NotFound(name.to_string()))?;
+        Ok(self.bookmarks.remove(index))
+    }
+
+    /// Move the bookmarks past an edit, see [`move_anchor`]
+    ///
+    /// A bookmark whose text is all deleted stays, as a bookmark of no text.
+    pub fn apply_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        for bookmark in self.bookmarks.iter_mut() {
+            (bookmark.start, bookmark.length) = move_anchor(bookmark.start, bookmark.length, offset, removed, inserted);
+        }
+    }
+
+    /// What a cross-reference field shows, or None to keep its result
+    ///
+    /// `text` is the whole text the bookmarks are in, `notes` the note reference
+    /// marks in it in text order, and `page_of` gives the page index of a char
+    /// offset once the text has been paginated.
+    pub fn resolve_reference(
+        &self,
+        field: &Field,
+        text: &str,
+        notes: &[(NoteKind, usize)],
+        page_of: impl Fn(usize) -> Option<usize>,
+    ) -> Option<String> {
+        if !matches!(field.kind, FieldKind::Ref | FieldKind::PageRef | FieldKind::NoteRef) {
+            return None;
+        }
+        let name = field.argument()?;
+        let Some(bookmark) = self.get(name) else {
+            return Some(MISSING_REFERENCE.to_string());
+        };
+        match field.kind {
+            // A field result stays inside one paragraph
+            FieldKind::Ref => Some(
+                text.chars()
+                    .skip(bookmark.start)
+                    .take(bookmark.length)
+                    .map(|c| if c == '\n' { ' ' } else { c })
+                    .collect(),
+            ),
+            FieldKind::PageRef => page_of(bookmark.start).map(|page| (page + 1).to_string()),
+            // Notes of each kind are numbered 1, 2, 3... in text order
+            _ => {
+                let (index, &(kind, _)) = notes
+                    .iter()
+                    .enumerate()
+                    .find(|(_, (_, position))| bookmark.range().contains(position) || *position == bookmark.start)?;
+                let number = notes[..index].iter().filter(|(other, _)| *other == kind).count() + 1;
+                Some(number.to_string())
+            }
+        }
+    }
+
+    /// An ID no bookmark has yet
+    pub fn next_id(&self) -> String {
+        self.bookmarks
+            .iter()
+            .filter_map(|bookmark| bookmark.id.parse::<u64>().ok())
+            .max()
+            .map_or(0, |id| id + 1)
+            .to_string()
+    }
+}
+
+/// Whether Word accepts `name` as a bookmark name: letters, digits and
+/// underscores, not starting with a digit, at most [`MAX_NAME_LENGTH`] chars
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|first| first.is_alphabetic() || first == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && name.chars().count() <= MAX_NAME_LENGTH
+}
+
+/// Recompute the REF, PAGEREF and NOTEREF fields of the model's body paragraphs
+/// from its bookmarks; returns how many results changed
+///
+/// `page_of` gives the page index of a char offset into the body text; PAGEREF
+/// fields keep their result where it gives none.
+pub fn update_cross_references(model: &mut DocumentModel, page_of: impl Fn(usize) -> Option<usize>) -> usize {
+    let registry = BookmarkRegistry::from_model(model);
+    let mut text = String::new();
+    let mut notes = Vec::new();
+    let mut starts = Vec::new();
+    for (index, paragraph) in model.paragraphs().enumerate() {
+        if index > 0 {
+            text.push('\n');
+        }
+        let start = text.chars().count();
+        starts.push(start);
+        notes.extend(paragraph.note_references.iter().map(|note| (note.kind, start + note.position)));
+        text.push_str(&paragraph.text);
+    }
+    notes.sort_by_key(|&(_, position)| position);
+
+    // Results are worked out against the text as it was, then written last field first
+    let mut updated = 0;
+    let paragraphs = model.body.iter_mut().filter_map(|block| match block {
+        Block::Paragraph(paragraph) => Some(paragraph),
+        Block::Table(_) => None,
+    });
+    for paragraph in paragraphs {
+        let values: Vec<Option<String>> = paragraph
+            .fields
+            .iter()
+            .map(|field| registry.resolve_reference(field, &text, &notes, &page_of))
+            .collect();
+        for (index, value) in values.into_iter().enumerate().rev() {
+            if let Some(value) = value.filter(|value| *value != paragraph.fields[index].result) {
+                replace_field_result(paragraph, index, &value);
+                updated += 1;
+            }
+        }
+    }
+    updated
+}
+
+/// Put `value` in place of the result of the paragraph's field `index`, in the
+/// formatting of the run the result starts in, moving everything after it
+fn replace_field_result(paragraph: &mut Paragraph, index: usize, value: &str) {
+    let (start, old_length) = (paragraph.fields[index].start, paragraph.fields[index].length);
+    let end = start + old_length;
+    let length = value.chars().count();
+    let shift = |position: usize| {
+        if position <= start {
+            position
+        } else if position >= end {
+            position + length - old_length
+        } else {
+            position.min(start + length)
+        }
+    };
+
+    // Runs split around the old result, which is left out
+    let mut before: Vec<Run> = Vec::new();
+    let mut after: Vec<Run> = Vec::new();
+    let mut properties = None;
+    let mut run_start = 0;
+    for run in paragraph.runs.drain(..) {
+        let chars: Vec<char> = run.text.chars().collect();
+        let run_end = run_start + chars.len();
+        if properties.is_none() && (run_start..run_end).contains(&start) {
+            properties = Some(run.properties.clone());
+        }
+        let head = start.clamp(run_start, run_end) - run_start;
+        let tail = end.clamp(run_start, run_end) - run_start;
+        if head > 0 {
+            before.push(Run {
+                text: chars[..head].iter().collect(),
+                properties: run.properties.clone(),
+            });
+        }
+        if tail < chars.len() {
+            after.push(Run {
+                text: chars[tail..].iter().collect(),
+                properties: run.properties,
+            });
+        }
+        run_start = run_end;
+    }
+    let properties = properties
+        .or_else(|| before.last().map(|run| run.properties.clone()))
+        .unwrap_or_default();
+    before.push(Run {
+        text: value.to_string(),
+        properties,
+    });
+    before.extend(after);
+    before.retain(|run| !run.text.is_empty());
+    paragraph.text = before.iter().map(|run| run.text.as_str()).collect();
+    paragraph.runs = before;
+
+    for (other, field) in paragraph.fields.iter_mut().enumerate() {
+        if other == index {
+            field.length = length;
+            field.result = value.to_string();
+        } else {
+            let field_end = shift(field.start + field.length);
+            field.start = shift(field.start);
+            field.length = field_end - field.start;
+        }
+    }
+    for note in paragraph.note_references.iter_mut() {
+        note.position = shift(note.position);
+    }
+    for mark in paragraph.comment_marks.iter_mut() {
+        mark.position = shift(mark.position);
+    }
+    for mark in paragraph.bookmark_marks.iter_mut() {
+        mark.position = shift(mark.position);
+    }
+    for hyperlink in paragraph.hyperlinks.iter_mut() {
+        let hyperlink_end = shift(hyperlink.start + hyperlink.length);
+        hyperlink.start = shift(hyperlink.start);
+        hyperlink.length = hyperlink_end - hyperlink.start;
+    }
+    for revision in paragraph.revisions.iter_mut() {
+        let revision_end = shift(revision.start + revision.length);
+        revision.start = shift(revision.start);
+        revision.length = revision_end - revision.start;
+    }
+}
+
+/// Start and end marks of the bookmarks, in text order
+pub(crate) fn bookmark_marks(bookmarks: &[Bookmark]) -> Vec<BookmarkMark> {
+    let mut marks: Vec<BookmarkMark> = bookmarks
+        .iter()
+        .flat_map(|bookmark| {
+            [
+                BookmarkMark {
+                    kind: BookmarkMarkKind::Start,
+                    id: bookmark.id.clone(),
+                    name: Some(bookmark.name.clone()),
+                    position: bookmark.start,
+                },
+                BookmarkMark {
+                    kind: BookmarkMarkKind::End,
+                    id: bookmark.id.clone(),
+                    name: None,
+                    position: bookmark.start + bookmark.length,
+                },
+            ]
+        })
+        .collect();
+    marks.sort_by_key(|mark| (mark.position, mark.kind));
+    marks
+}
+
+/// The marks inside the paragraph of `length` chars at `start`, relative to it
+pub(crate) fn paragraph_bookmark_marks(marks: &[BookmarkMark], start: usize, length: usize) -> Vec<BookmarkMark> {
+    marks
+        .iter()
+        .filter(|mark| (start..=start + length).contains(&mark.position))
+        .map(|mark| BookmarkMark {
+            position: mark.position - start,
+            ..mark.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::{CommentMark, CommentMarkKind, NoteReference, RunProperties};
+
+    fn run(text: &str, bold: bool) -> Run {
+        Run {
+            text: text.to_string(),
+            properties: RunProperties {
+                bold: bold.then_some(true),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn mark(kind: BookmarkMarkKind, id: &str, name: Option<&str>, position: usize) -> BookmarkMark {
+        BookmarkMark {
+            kind,
+            id: id.to_string(),
+            name: name.map(str::to_string),
+            position,
+        }
+    }
+
+    #[test]
+    fn test_insert_move_and_edit() {
+        let mut registry = BookmarkRegistry::new();
+        assert_eq!(registry.insert("1st", 0..1), Err(BookmarkError::InvalidName("1st".to_string())));
+        assert!(registry.insert("has space", 0..1).is_err());
+        assert!(registry.insert(&"a".repeat(41), 0..1).is_err());
+
+        // "The quick brown fox", bookmarking "quick"
+        registry.insert("Speed", 4..9).unwrap();
+        registry.insert("_Ref1", 10..15).unwrap();
+        assert_eq!(registry.sorted(false).len(), 1);
+        assert_eq!(registry.get("SPEED").unwrap().range(), 4..9);
+
+        // The same name, in any case, moves the bookmark
+        registry.insert("speed", 10..19).unwrap();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.get("Speed").map(|b| (b.id.as_str(), b.name.as_str())), Some(("0", "speed")));
+
+        registry.apply_edit(0, 4, 0);
+        registry.apply_edit(11, 0, 3);
+        assert_eq!(registry.get("speed").unwrap().range(), 6..18);
+        assert_eq!(registry.at(18).len(), 1);
+
+        assert_eq!(registry.delete("_REF1").unwrap().name, "_Ref1");
+        assert_eq!(registry.delete("_Ref1"), Err(BookmarkError::NotFound("_Ref1".to_string())));
+    }
+
+    #[test]
+    fn test_marks_across_paragraphs() {
+        let mut registry = BookmarkRegistry::new();
+        registry.insert("Both", 3..8).unwrap();
+        registry.insert("Point", 11..11).unwrap();
+
+        let mut model = DocumentModel::from_piece_tree(&crate::piece_tree::PieceTree::new("Hello\nworld".to_string()));
+        registry.add_to_model(&mut model);
+        let marks: Vec<Vec<(BookmarkMarkKind, usize)>> = model
+            .paragraphs()
+            .map(|p| p.bookmark_marks.iter().map(|m| (m.kind, m.position)).collect())
+            .collect();
+        assert_eq!(
+            marks,
+            vec![
+                vec![(BookmarkMarkKind::Start, 3)],
+                vec![(BookmarkMarkKind::End, 2), (BookmarkMarkKind::Start, 5), (BookmarkMarkKind::End, 5)],
+            ]
+        );
+
+        let loaded = BookmarkRegistry::from_model(&model);
+        assert_eq!(loaded.bookmarks(), registry.bookmarks());
+    }
+
+    #[test]
+    fn test_update_cross_references() {
+        let heading = Paragraph {
+            text: "Results".to_string(),
+            runs: vec![run("Results", false)],
+            note_references: vec![NoteReference {
+                kind: NoteKind::Footnote,
+                id: "2".to_string(),
+                position: 7,
+            }],
+            bookmark_marks: vec![
+                mark(BookmarkMarkKind::Start, "0", Some("Results"), 0),
+                mark(BookmarkMarkKind::End, "0", None, 7),
+                mark(BookmarkMarkKind::Start, "1", Some("_Ref1"), 7),
+                mark(BookmarkMarkKind::End, "1", None, 7),
+            ],
+            ..Default::default()
+        };
+        let text = "See Old, note 9 and page 2.";
+        let reference = Paragraph {
+            text: text.to_string(),
+            runs: vec![run("See ", false), run("Old", true), run(", note 9 and page 2.", false)],
+            fields: vec![
+                Field::new("REF Results \\h", 4, "Old"),
+                Field::new("NOTEREF _Ref1 \\h", 14, "9"),
+                Field::new("PAGEREF Results", 25, "2"),
+            ],
+            comment_marks: vec![CommentMark {
+                kind: CommentMarkKind::Reference,
+                id: "0".to_string(),
+                position: 27,
+            }],
+            ..Default::default()
+        };
+        let mut model = DocumentModel {
+            body: vec![Block::from(heading), Block::from(reference)],
+            ..Default::default()
+        };
+
+        assert_eq!(update_cross_references(&mut model, |offset| Some(offset / 100)), 3);
+        let Block::Paragraph(updated) = &model.body[1] else {
+            unreachable!()
+        };
+        assert_eq!(updated.text, "See Results, note 1 and page 1.");
+        // The new result keeps the formatting of the old one
+        assert_eq!(updated.runs[1].text, "Results");
+        assert_eq!(updated.runs[1].properties.bold, Some(true));
+        let fields: Vec<(usize, usize, &str)> = updated.fields.iter().map(|f| (f.start, f.length, f.result.as_str())).collect();
+        assert_eq!(fields, vec![(4, 7, "Results"), (18, 1, "1"), (29, 1, "1")]);
+        assert_eq!(updated.comment_marks[0].position, 31);
+
+        // Nothing changes the second time; a missing bookmark shows Word's error
+        assert_eq!(update_cross_references(&mut model, |offset| Some(offset / 100)), 0);
+        let registry = BookmarkRegistry::from_model(&model);
+        let missing = Field::new("REF Missing", 0, "");
+        assert_eq!(registry.resolve_reference(&missing, "", &[], |_| None).as_deref(), Some(MISSING_REFERENCE));
+    }
+}
@@ -0,0 +1,705 @@
+//! # Captions Module
+//!
+//! Numbered captions of figures, tables and equations, as Word inserts them.
+//!
+//! A caption is a paragraph in the Caption style reading e.g. "Figure 3",
+//! its number being the result of a SEQ field that counts the captions of its
+//! label. A label may be numbered by chapter: "Figure 2-3" starts with a
+//! STYLEREF field showing the number of the last Heading 1 before it, and its
+//! SEQ field restarts at each Heading 1, as `\s 1` makes Word do. Chapter
+//! numbers come from the heading numbering, so it must be on.
+//!
+//! Each caption is anchored to the paragraphs of the image or table it
+//! captions. Its fields and its target move with edits like comment anchors,
+//! and moving a captioned object takes its caption along on the same side.
+//! Numbers follow the captions' order in the text and are recomputed after
+//! every caption edit, as updating the fields in Word does.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::comments::move_anchor;
+use crate::document_model::{Block, DocumentModel};
+use crate::index::{instruction_words, paragraph_fields, switch_value};
+use crate::ooxml::{Field, FieldKind, Paragraph};
+use crate::piece_tree::{ParagraphAttributes, PieceTree, TextAttributes};
+use crate::style_sheet::{NamedStyle, StyleKind, StyleSheet};
+
+/// ID of the paragraph style captions are in
+pub const CAPTION_STYLE: &str = "Caption";
+
+/// Labels Word offers before any are added
+pub const BUILTIN_LABELS: [&str; 3] = ["Figure", "Table", "Equation"];
+
+/// Default text between the chapter number and the count, as in "2-3"
+const DEFAULT_SEPARATOR: &str = "-";
+
+/// Caption errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum CaptionError {
+    #[error("A caption needs a label")]
+    EmptyLabel,
+
+    #[error("Chapter numbers come from heading levels 1 to 9, not {0}")]
+    InvalidChapterLevel(u8),
+
+    #[error("Chapter numbers need numbered headings; turn on heading numbering first")]
+    UnnumberedHeadings,
+
+    #[error("No captioned object at offset {0}")]
+    NotFound(usize),
+}
+
+/// Which side of its object a caption is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptionPosition {
+    Above,
+    #[default]
+    Below,
+}
+
+impl CaptionPosition {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "above" => Some(CaptionPosition::Above),
+            "below" => Some(CaptionPosition::Below),
+            _ => None,
+        }
+    }
+}
+
+/// How the captions of a label are numbered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptionLabel {
+    pub name: String,
+    /// Heading level, from 1, whose number goes first; the count restarts
+    /// at each heading of that level or above
+    #[serde(default)]
+    pub chapter_level: Option<u8>,
+    /// Between the chapter number and the count, e.g. "-" or "."
+    #[serde(default = "default_separator")]
+    pub separator: String,
+}
+
+fn default_separator() -> String {
+    DEFAULT_SEPARATOR.to_string()
+}
+
+impl CaptionLabel {
+    /// A label numbered through the whole document
+    pub fn new(name: &str) -> Self {
+        CaptionLabel {
+            name: name.trim().to_string(),
+            chapter_level: None,
+            separator: default_separator(),
+        }
+    }
+
+    /// Instruction of the SEQ field counting the label's captions
+    pub fn sequence_instruction(&self) -> String {
+        let name = if self.name.contains(char::is_whitespace) {
+            format!("\"{}\"", self.name)
+        } else {
+            self.name.clone()
+        };
+        match self.chapter_level {
+            Some(level) => format!("SEQ {} \\* ARABIC \\s {}", name, level),
+            None => format!("SEQ {} \\* ARABIC", name),
+        }
+    }
+}
+
+/// A caption, with char offsets into the whole text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Caption {
+    pub label: String,
+    pub position: CaptionPosition,
+    /// SEQ field showing the count
+    pub sequence: Field,
+    /// STYLEREF field showing the chapter number, for labels numbered by chapter
+    #[serde(default)]
+    pub chapter: Option<Field>,
+    /// Chars of the captioned paragraphs; empty once they are deleted
+    pub target_start: usize,
+    pub target_length: usize,
+}
+
+impl Caption {
+    /// Heading level the count restarts at, from the SEQ field's `\s` switch
+    pub fn chapter_level(&self) -> Option<u8> {
+        let words = instruction_words(&self.sequence.instruction);
+        switch_value(&words, "\\s").and_then(|level| level.parse().ok())
+    }
+
+    pub fn target(&self) -> Range<usize> {
+        self.target_start..self.target_start + self.target_length
+    }
+
+    fn fields_mut(&mut self) -> impl Iterator<Item = &mut Field> {
+        std::iter::once(&mut self.sequence).chain(self.chapter.as_mut())
+    }
+}
+
+/// A numbered heading captions can take their chapter number from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chapter {
+    /// Char offset of the heading paragraph
+    pub position: usize,
+    /// Heading level, from 1
+    pub level: u8,
+    /// Number as STYLEREF \s shows it, e.g. "2" for "Chapter 2."
+    pub number: String,
+}
+
+/// A caption paragraph to insert
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptionInsertion {
+    /// Char offset the text goes in at
+    pub offset: usize,
+    /// The caption paragraph's text with the "\n" parting it from its object
+    pub text: String,
+    /// The caption, with offsets into the text after the insertion
+    pub caption: Caption,
+}
+
+/// Paragraphs moved by [`move_paragraphs`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParagraphMove {
+    /// Chars removed, "\n" included
+    pub removed: Range<usize>,
+    /// Chars inserted, in the text as it was after the removal
+    pub inserted: Range<usize>,
+    /// Char offset the paragraphs started at
+    pub from: usize,
+    /// Char offset the paragraphs start at now
+    pub start: usize,
+}
+
+/// The captions of a document in text order, and how each label is numbered
+#[derive(Debug, Clone, Default)]
+pub struct CaptionSet {
+    captions: Vec<Caption>,
+    labels: Vec<CaptionLabel>,
+}
+
+impl CaptionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Captions of `paragraphs`, each given with the char offset it starts
+    /// at: paragraphs with a SEQ field
+    ///
+    /// Files do not say what a caption belongs to. As Word puts them, a table
+    /// caption is taken to be above its object and any other below it. A
+    /// caption whose object's paragraph is not among `paragraphs` is anchored
+    /// at its own start.
+    pub fn from_paragraphs<'a>(paragraphs: impl IntoIterator<Item = (usize, &'a Paragraph)>) -> Self {
+        let (starts, paragraphs): (Vec<usize>, Vec<&Paragraph>) = paragraphs.into_iter().unzip();
+        let span = |index: usize| starts[index]..starts[index] + paragraphs[index].text.chars().count();
+        // Whether the paragraph after `index` is the next one of the text
+        let adjacent = |index: usize| index + 1 < paragraphs.len() && span(index).end + 1 == starts[index + 1];
+
+        let mut set = CaptionSet::new();
+        for (index, paragraph) in paragraphs.iter().enumerate() {
+            let Some(sequence) = paragraph.fields.iter().find(|field| field.kind == FieldKind::Seq) else {
+                continue;
+            };
+            let Some(label) = instruction_words(&sequence.instruction).get(1).filter(|word| !word.starts_with('\\')).cloned()
+            else {
+                continue;
+            };
+            let chapter = paragraph
+                .fields
+                .iter()
+                .rfind(|field| field.kind == FieldKind::StyleRef && field.start < sequence.start);
+            let position = if label.eq_ignore_ascii_case("Table") {
+                CaptionPosition::Above
+            } else {
+                CaptionPosition::Below
+            };
+            let target = match position {
+                CaptionPosition::Above if adjacent(index) => span(index + 1),
+                CaptionPosition::Below if index > 0 && adjacent(index - 1) => span(index - 1),
+                _ => starts[index]..starts[index],
+            };
+            let caption = Caption {
+                label,
+                position,
+                sequence: Field {
+                    start: starts[index] + sequence.start,
+                    ..sequence.clone()
+                },
+                chapter: chapter.map(|field| Field {
+                    start: starts[index] + field.start,
+                    ..field.clone()
+                }),
+                target_start: target.start,
+                target_length: target.len(),
+            };
+
+            if !set.labels.iter().any(|known| known.name == caption.label) {
+                let separator = chapter.map(|chapter| {
+                    let between = chapter.start + chapter.length..sequence.start;
+                    paragraph.text.chars().skip(between.start).take(between.len()).collect()
+                });
+                set.labels.push(CaptionLabel {
+                    name: caption.label.clone(),
+                    chapter_level: chapter.and(caption.chapter_level()),
+                    separator: separator.unwrap_or_else(default_separator),
+                });
+            }
+            set.captions.push(caption);
+        }
+        set
+    }
+
+    /// Captions of the model's body paragraphs
+    pub fn from_model(model: &DocumentModel) -> Self {
+        Self::from_paragraphs(model.located_paragraphs())
+    }
+
+    /// Put the SEQ and STYLEREF fields in the model's body paragraphs in place of theirs
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let fields = self.fields();
+        let mut paragraph_start = 0;
+        for block in model.body.iter_mut() {
+            let Block::Paragraph(paragraph) = block else {
+                continue;
+            };
+            let length = paragraph.text.chars().count();
+            paragraph
+                .fields
+                .retain(|field| !matches!(field.kind, FieldKind::Seq | FieldKind::StyleRef));
+            paragraph.fields.extend(paragraph_fields(&fields, paragraph_start, length));
+            paragraph.fields.sort_by_key(|field| field.start);
+            paragraph_start += length + 1;
+        }
+    }
+
+    pub fn captions(&self) -> &[Caption] {
+        &self.captions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.captions.is_empty()
+    }
+
+    /// The built-in labels, then the others set or used, each with its numbering
+    pub fn labels(&self) -> Vec<CaptionLabel> {
+        let mut labels: Vec<CaptionLabel> = BUILTIN_LABELS.iter().map(|name| self.label(name)).collect();
+        labels.extend(
+            self.labels
+                .iter()
+                .filter(|label| !BUILTIN_LABELS.contains(&label.name.as_str()))
+                .cloned(),
+        );
+        labels
+    }
+
+    /// Numbering of the label `name`; a label never set is numbered through the document
+    pub fn label(&self, name: &str) -> CaptionLabel {
+        let name = name.trim();
+        self.labels
+            .iter()
+            .find(|label| label.name == name)
+            .cloned()
+            .unwrap_or_else(|| CaptionLabel::new(name))
+    }
+
+    /// Set how captions of a label inserted from now on are numbered
+    pub fn set_label(&mut self, label: CaptionLabel) -> Result<(), CaptionError> {
+        if label.name.trim().is_empty() {
+            return Err(CaptionError::EmptyLabel);
+        }
+        if let Some(level) = label.chapter_level.filter(|level| !(1..=9).contains(level)) {
+            return Err(CaptionError::InvalidChapterLevel(level));
+        }
+        let label = CaptionLabel {
+            name: label.name.trim().to_string(),
+            ..label
+        };
+        match self.labels.iter_mut().find(|known| known.name == label.name) {
+            Some(known) => *known = label,
+            None => self.labels.push(label),
+        }
+        Ok(())
+    }
+
+    /// The caption whose object or number is at `offset`
+    pub fn at(&self, offset: usize) -> Option<&Caption> {
+        self.captions.iter().find(|caption| {
+            let number = caption.sequence.start..=caption.sequence.start + caption.sequence.length;
+            let target = caption.target_start..=caption.target_start + caption.target_length;
+            number.contains(&offset) || (caption.target_length > 0 && target.contains(&offset))
+        })
+    }
+
+    /// Add a caption, keeping text order
+    pub fn add(&mut self, caption: Caption) {
+        let index = self.captions.partition_point(|other| other.sequence.start < caption.sequence.start);
+        self.captions.insert(index, caption);
+    }
+
+    /// Take out the captions whose numbers are in chars `range`
+    pub fn take_in(&mut self, range: Range<usize>) -> Vec<Caption> {
+        let (taken, kept) = std::mem::take(&mut self.captions)
+            .into_iter()
+            .partition(|caption| range.contains(&caption.sequence.start));
+        self.captions = kept;
+        taken
+    }
+
+    /// The SEQ and STYLEREF fields, with starts as char offsets into the whole text
+    pub fn fields(&self) -> Vec<Field> {
+        let mut fields: Vec<Field> = self
+            .captions
+            .iter()
+            .flat_map(|caption| caption.chapter.iter().chain(std::iter::once(&caption.sequence)))
+            .cloned()
+            .collect();
+        fields.sort_by_key(|field| field.start);
+        fields
+    }
+
+    /// Report the field starting at char `start` now showing `result` in place of its old text
+    ///
+    /// Unlike an edit through [`CaptionSet::apply_edit`], this keeps the field
+    /// whole and moves what follows it, even right after it.
+    pub fn replace_result(&mut self, start: usize, result: &str) {
+        let Some(old_length) = self.fields().iter().find(|field| field.start == start).map(|field| field.length) else {
+            return;
+        };
+        let end = start + old_length;
+        let length = result.chars().count();
+        let shift = |position: usize| if position >= end { position - old_length + length } else { position };
+        for caption in &mut self.captions {
+            let target_end = shift(caption.target_start + caption.target_length);
+            caption.target_start = shift(caption.target_start);
+            caption.target_length = target_end - caption.target_start;
+            for field in caption.fields_mut() {
+                if field.start == start {
+                    field.length = length;
+                    field.result = result.to_string();
+                } else {
+                    let field_end = shift(field.start + field.length);
+                    field.start = shift(field.start);
+                    field.length = field_end - field.start;
+                }
+            }
+        }
+    }
+
+    /// Chapter number and count each caption should show, in text order
+    ///
+    /// `chapters` are the numbered headings in text order. A caption before
+    /// the first heading of its level shows chapter 0.
+    pub fn numbers(&self, chapters: &[Chapter]) -> Vec<(Option<String>, u32)> {
+        // Per label: the heading the count last restarted at, and the count
+        let mut counts: HashMap<&str, (Option<usize>, u32)> = HashMap::new();
+        self.captions
+            .iter()
+            .map(|caption| {
+                let level = caption.chapter_level();
+                let before = &chapters[..chapters.partition_point(|chapter| chapter.position <= caption.sequence.start)];
+                let restart = level.and_then(|level| before.iter().rposition(|chapter| chapter.level <= level));
+                let count = counts.entry(caption.label.as_str()).or_insert((restart, 0));
+                if count.0 != restart {
+                    *count = (restart, 0);
+                }
+                count.1 += 1;
+
+                let chapter = caption.chapter.as_ref().map(|_| {
+                    before
+                        .iter()
+                        .rev()
+                        .find(|chapter| Some(chapter.level) == level)
+                        .map_or_else(|| "0".to_string(), |chapter| chapter.number.clone())
+                });
+                (chapter, count.1)
+            })
+            .collect()
+    }
+
+    /// Move the caption fields and targets past an edit, see [`move_anchor`]
+    ///
+    /// A caption whose number is deleted goes with it.
+    pub fn apply_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        self.captions.retain_mut(|caption| {
+            for field in caption.fields_mut() {
+                (field.start, field.length) = move_anchor(field.start, field.length, offset, removed, inserted);
+            }
+            (caption.target_start, caption.target_length) =
+                move_anchor(caption.target_start, caption.target_length, offset, removed, inserted);
+            caption.sequence.length > 0
+        });
+    }
+}
+
+/// The caption to insert for the paragraphs spanning chars `target`, numbered 1 until updated
+pub fn caption_insertion(label: &CaptionLabel, position: CaptionPosition, target: Range<usize>) -> CaptionInsertion {
+    let paragraph_start = match position {
+        CaptionPosition::Above => target.start,
+        CaptionPosition::Below => target.end + 1,
+    };
+    let mut text = format!("{} ", label.name);
+    let chapter = label.chapter_level.map(|level| {
+        let field = Field::new(&format!("STYLEREF {} \\s", level), paragraph_start + text.chars().count(), "1");
+        text.push('1');
+        text.push_str(&label.separator);
+        field
+    });
+    let sequence = Field::new(&label.sequence_instruction(), paragraph_start + text.chars().count(), "1");
+    text.push('1');
+
+    let length = text.chars().count() + 1;
+    let (offset, text, target_start) = match position {
+        CaptionPosition::Above => (target.start, format!("{}\n", text), target.start + length),
+        CaptionPosition::Below => (target.end, format!("\n{}", text), target.start),
+    };
+    CaptionInsertion {
+        offset,
+        text,
+        caption: Caption {
+            label: label.name.clone(),
+            position,
+            sequence,
+            chapter,
+            target_start,
+            target_length: target.len(),
+        },
+    }
+}
+
+/// Chars of the whole paragraphs `range` touches in `text`, without the last "\n"
+pub fn paragraph_span(text: &str, range: Range<usize>) -> Range<usize> {
+    let chars: Vec<char> = text.chars().collect();
+    let first = range.start.min(chars.len());
+    let last = if range.end > range.start { range.end - 1 } else { range.start }.clamp(first, chars.len());
+    let start = chars[..first].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1);
+    let end = chars[last..].iter().position(|&c| c == '\n').map_or(chars.len(), |i| last + i);
+    start..end
+}
+
+/// The number STYLEREF \s shows for a heading's list label: its last word
+/// without trailing punctuation, so "Chapter 2." gives "2"
+pub fn chapter_number(label: &str) -> String {
+    let word = label.split_whitespace().last().unwrap_or("");
+    word.trim_end_matches(|c: char| !c.is_alphanumeric()).to_string()
+}
+
+/// Heading level of a Heading 1–9 style ID
+pub fn heading_level(style_id: &str) -> Option<u8> {
+    style_id
+        .strip_prefix("Heading")?
+        .parse()
+        .ok()
+        .filter(|level| (1..=9).contains(level))
+}
+
+/// Add the Caption style if the sheet lacks it: small italic text in Word's blue-gray
+pub fn add_caption_style(styles: &mut StyleSheet) {
+    if styles.get(CAPTION_STYLE).is_some() {
+        return;
+    }
+    let mut style = NamedStyle::new(CAPTION_STYLE, StyleKind::Paragraph);
+    style.name = "caption".to_string();
+    style.based_on = styles.get("Normal").map(|normal| normal.id.clone());
+    style.paragraph.space_after = Some(200);
+    style.run = TextAttributes {
+        italic: Some(true),
+        font_size: Some(9),
+        foreground: Some("#44546A".to_string()),
+        ..Default::default()
+    };
+    styles.add(style);
+}
+
+/// Paragraph attributes of a caption paragraph
+pub fn caption_paragraph() -> ParagraphAttributes {
+    ParagraphAttributes {
+        style_id: Some(CAPTION_STYLE.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Move the whole paragraphs spanning chars `span` before paragraph `to`, the
+/// paragraph count moving them to the end, as one undo step
+///
+/// Text and paragraph formatting move along. Returns None when `to` is among
+/// or next to the paragraphs, where moving would change nothing.
+pub fn move_paragraphs(tree: &mut PieceTree, span: Range<usize>, to: usize) -> Option<ParagraphMove> {
+    let text = tree.get_text();
+    let total = text.chars().count();
+    let span = paragraph_span(&text, span);
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(text.chars().enumerate().filter(|(_, c)| *c == '\n').map(|(i, _)| i + 1))
+        .collect();
+    let count = starts.len();
+    let first = starts.partition_point(|&start| start <= span.start) - 1;
+    let last = starts.partition_point(|&start| start <= span.end) - 1;
+    let to = to.min(count);
+    if (first..=last + 1).contains(&to) {
+        return None;
+    }
+
+    let block: String = text.chars().skip(span.start).take(span.len()).collect();
+    let spans = tree.attribute_spans(span.clone());
+    let mut order = tree.paragraph_attributes_in(0..total);
+    let moved: Vec<_> = order.drain(first..=last).collect();
+    let to_after = if to > last { to - moved.len() } else { to };
+    order.splice(to_after..to_after, moved);
+
+    // The paragraphs go with the "\n" after them, or before them at the end
+    let removed = if span.end < total { span.start..span.end + 1 } else { span.start - 1..span.end };
+    let destination = if to < count { starts[to] } else { total };
+    let destination = if destination > removed.start { destination - removed.len() } else { destination };
+    let start = if to < count { destination } else { destination + 1 };
+
+    tree.transaction(|tree| {
+        let byte_start = tree.char_to_byte_offset(removed.start);
+        let byte_length = tree.char_to_byte_offset(removed.end) - byte_start;
+        tree.delete(byte_start, byte_length);
+        tree.insert(destination, "\n".to_string());
+        let mut at = start;
+        let mut chars = block.chars();
+        for piece in &spans {
+            let piece_text: String = chars.by_ref().take(piece.length).collect();
+            tree.insert_with_attrs(at, piece_text, piece.attributes.clone());
+            at += piece.length;
+        }
+        let length = tree.char_count();
+        tree.reformat_each_paragraph(0..length, |index, _| order.get(index).cloned().flatten());
+    });
+    Some(ParagraphMove {
+        inserted: destination..destination + span.len() + 1,
+        removed,
+        from: span.start,
+        start,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_model::located;
+
+    fn chapters() -> Vec<Chapter> {
+        vec![
+            Chapter { position: 0, level: 1, number: "1".to_string() },
+            Chapter { position: 50, level: 2, number: "1.1".to_string() },
+            Chapter { position: 100, level: 1, number: "2".to_string() },
+        ]
+    }
+
+    fn insert(set: &mut CaptionSet, label: &str, target: Range<usize>) {
+        let insertion = caption_insertion(&set.label(label), CaptionPosition::Below, target);
+        let length = insertion.text.chars().count();
+        set.apply_edit(insertion.offset, 0, length);
+        set.add(insertion.caption);
+    }
+
+    #[test]
+    fn test_caption_insertion_text_and_fields() {
+        let mut label = CaptionLabel::new("Figure");
+        label.chapter_level = Some(1);
+        let insertion = caption_insertion(&label, CaptionPosition::Below, 10..20);
+        assert_eq!(insertion.offset, 20);
+        assert_eq!(insertion.text, "\nFigure 1-1");
+        let caption = &insertion.caption;
+        assert_eq!(caption.sequence.instruction, "SEQ Figure \\* ARABIC \\s 1");
+        assert_eq!((caption.sequence.start, caption.sequence.length), (30, 1));
+        let chapter = caption.chapter.as_ref().unwrap();
+        assert_eq!((chapter.kind, chapter.start), (FieldKind::StyleRef, 28));
+        assert_eq!(caption.chapter_level(), Some(1));
+
+        let above = caption_insertion(&CaptionLabel::new("My Table"), CaptionPosition::Above, 10..20);
+        assert_eq!(above.text, "My Table 1\n");
+        assert_eq!(above.caption.sequence.instruction, "SEQ \"My Table\" \\* ARABIC");
+        assert_eq!(above.caption.sequence.start, 19);
+        assert_eq!(above.caption.target(), 21..31);
+        assert!(above.caption.chapter.is_none());
+    }
+
+    #[test]
+    fn test_numbers_restart_at_chapters() {
+        let mut set = CaptionSet::new();
+        let mut figure = CaptionLabel::new("Figure");
+        figure.chapter_level = Some(1);
+        set.set_label(figure).unwrap();
+        insert(&mut set, "Figure", 10..20);
+        insert(&mut set, "Table", 160..170);
+        insert(&mut set, "Figure", 140..150);
+        insert(&mut set, "Figure", 60..70);
+
+        let numbers = set.numbers(&chapters());
+        let labels: Vec<&str> = set.captions().iter().map(|caption| caption.label.as_str()).collect();
+        assert_eq!(labels, ["Figure", "Figure", "Figure", "Table"]);
+        assert_eq!(
+            numbers,
+            vec![
+                (Some("1".to_string()), 1),
+                (Some("1".to_string()), 2),
+                (Some("2".to_string()), 1),
+                (None, 1),
+            ]
+        );
+        assert_eq!(set.labels()[0].chapter_level, Some(1));
+        assert_eq!(
+            set.set_label(CaptionLabel { chapter_level: Some(10), ..CaptionLabel::new("Figure") }),
+            Err(CaptionError::InvalidChapterLevel(10))
+        );
+    }
+
+    #[test]
+    fn test_captions_follow_edits_and_round_trip() {
+        let mut set = CaptionSet::new();
+        insert(&mut set, "Figure", 0..5);
+        insert(&mut set, "Figure", 16..20);
+        assert_eq!(set.captions()[1].sequence.start, 28);
+
+        set.apply_edit(0, 0, 3);
+        assert_eq!(set.captions()[0].target(), 3..8);
+        assert_eq!(set.at(4).map(|caption| caption.sequence.start), Some(16));
+        // Deleting a caption's number removes it
+        set.apply_edit(31, 1, 0);
+        assert_eq!(set.captions().len(), 1);
+
+        let mut with_fields = Paragraph {
+            text: "Figure 1-4".to_string(),
+            ..Default::default()
+        };
+        with_fields.fields = vec![
+            Field::new("STYLEREF 1 \\s", 7, "1"),
+            Field::new("SEQ Figure \\* ARABIC \\s 1", 9, "4"),
+        ];
+        let picture = Paragraph { text: "[picture]".to_string(), ..Default::default() };
+        let read = CaptionSet::from_paragraphs(located([&picture, &with_fields]));
+        let caption = &read.captions()[0];
+        assert_eq!((caption.sequence.start, caption.target()), (19, 0..9));
+        assert_eq!(caption.chapter.as_ref().map(|field| field.start), Some(17));
+        assert_eq!(read.label("Figure").separator, "-");
+        assert_eq!(read.label("Figure").chapter_level, Some(1));
+    }
+
+    #[test]
+    fn test_move_paragraphs_keeps_formatting() {
+        let mut tree = PieceTree::new("Intro\nPicture\nFigure 1\nOutro".to_string());
+        tree.set_paragraph_attributes(14..14, Some(&caption_paragraph()));
+        let moved = move_paragraphs(&mut tree, 6..22, 4).unwrap();
+        assert_eq!(tree.get_text(), "Intro\nOutro\nPicture\nFigure 1");
+        assert_eq!(moved, ParagraphMove { removed: 6..23, inserted: 11..28, from: 6, start: 12 });
+        assert_eq!(tree.paragraph_attributes(3).and_then(|a| a.style_id.as_deref()), Some(CAPTION_STYLE));
+        assert_eq!(tree.paragraph_attributes(1), None);
+
+        let back = move_paragraphs(&mut tree, 12..28, 0).unwrap();
+        assert_eq!(tree.get_text(), "Picture\nFigure 1\nIntro\nOutro");
+        assert_eq!(back.start, 0);
+        assert!(move_paragraphs(&mut tree, 0..3, 1).is_none());
+        assert_eq!(chapter_number("Article II."), "II");
+        assert_eq!(heading_level("Heading3"), Some(3));
+    }
+}
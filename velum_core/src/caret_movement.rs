@@ -0,0 +1,253 @@
+//! # Caret Movement Module
+//!
+//! Keyboard navigation, the same on every platform: the caret moves by
+//! grapheme cluster, word, line, paragraph and page, and to the start and end
+//! of a line or of the document, each either moving the selection or
+//! extending it.
+//!
+//! Moves through the text follow Unicode segmentation, so the caret never
+//! lands inside a cluster. Moves by line and page follow the layout: up and
+//! down go to the line above or below at the goal x, the x the caret had when
+//! it began moving vertically, so passing a short line keeps the column.
+//! These need the lines involved laid out by the viewport layout; where they
+//! are not, Home and End go to the paragraph's start and end and the other
+//! moves leave the caret where it is.
+
+use serde::{Deserialize, Serialize};
+
+use crate::hit_test::{Affinity, CaretPosition};
+use crate::piece_tree::Selection;
+use crate::segmentation;
+use crate::viewport_layout::ViewportLayout;
+
+/// A keyboard movement of the caret
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Movement {
+    /// Right in left-to-right text
+    NextGrapheme,
+    /// Left in left-to-right text
+    PreviousGrapheme,
+    /// Ctrl+Right: to the start of the next word
+    NextWord,
+    /// Ctrl+Left: to the start of this word or the one before
+    PreviousWord,
+    /// Home
+    LineStart,
+    /// End
+    LineEnd,
+    /// Up
+    LineUp,
+    /// Down
+    LineDown,
+    /// Ctrl+Down: to the start of the next paragraph
+    NextParagraph,
+    /// Ctrl+Up: to the start of this paragraph or the one before
+    PreviousParagraph,
+    /// Page Up: to the page before
+    PageUp,
+    /// Page Down: to the page after
+    PageDown,
+    /// Ctrl+Home
+    DocumentStart,
+    /// Ctrl+End
+    DocumentEnd,
+}
+
+impl Movement {
+    fn is_vertical(self) -> bool {
+        matches!(self, Movement::LineUp | Movement::LineDown | Movement::PageUp | Movement::PageDown)
+    }
+}
+
+/// Where the caret is, with what vertical moves keep
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Caret {
+    /// Char offset
+    pub offset: usize,
+    pub affinity: Affinity,
+    /// Points from the left edge of the page that moving up and down keeps
+    /// to; None until the caret moves vertically
+    pub goal_x: Option<f32>,
+}
+
+impl Caret {
+    pub fn new(offset: usize) -> Self {
+        Caret {
+            offset,
+            ..Default::default()
+        }
+    }
+}
+
+/// Move the caret in `text` by `movement`, using `layout` for moves by line and page
+pub fn move_caret(text: &str, layout: &mut ViewportLayout, caret: Caret, movement: Movement) -> Caret {
+    let offset = caret.offset;
+    let at = |offset: usize| Caret::new(offset);
+    let placed = |position: CaretPosition, goal_x: Option<f32>| Caret {
+        offset: position.offset,
+        affinity: position.affinity,
+        goal_x,
+    };
+    match movement {
+        Movement::NextGrapheme => at(by_bytes(text, offset, segmentation::next_grapheme_boundary)),
+        Movement::PreviousGrapheme => at(by_bytes(text, offset, segmentation::previous_grapheme_boundary)),
+        Movement::NextWord => at(by_bytes(text, offset, segmentation::next_word_start)),
+        Movement::PreviousWord => at(by_bytes(text, offset, segmentation::previous_word_start)),
+        Movement::NextParagraph => at(by_bytes(text, offset, |text, byte| {
+            text[byte..].find('\n').map_or(text.len(), |mark| byte + mark + 1)
+        })),
+        Movement::PreviousParagraph => at(by_bytes(text, offset, |text, byte| {
+            let before = text[..byte].strip_suffix('\n').unwrap_or(&text[..byte]);
+            before.rfind('\n').map_or(0, |mark| mark + 1)
+        })),
+        Movement::DocumentStart => at(0),
+        Movement::DocumentEnd => at(text.chars().count()),
+        Movement::LineStart | Movement::LineEnd => {
+            let end = movement == Movement::LineEnd;
+            match layout.caret_at_line_edge(offset, caret.affinity, end) {
+                Some(position) => placed(position, None),
+                None if end => at(by_bytes(text, offset, |text, byte| text[byte..].find('\n').map_or(text.len(), |mark| byte + mark))),
+                None => at(by_bytes(text, offset, |text, byte| text[..byte].rfind('\n').map_or(0, |mark| mark + 1))),
+            }
+        }
+        Movement::LineUp | Movement::LineDown | Movement::PageUp | Movement::PageDown => {
+            let Some(goal_x) = caret.goal_x.or_else(|| layout.caret_rect(offset, caret.affinity).map(|position| position.rect.x)) else {
+                return caret;
+            };
+            let moved = match movement {
+                Movement::LineUp | Movement::LineDown => {
+                    layout.caret_on_adjacent_line(offset, caret.affinity, goal_x, movement == Movement::LineDown)
+                }
+                _ => layout.caret_on_adjacent_page(offset, caret.affinity, goal_x, movement == Movement::PageDown),
+            };
+            match moved {
+                Some(position) => placed(position, Some(goal_x)),
+                None => Caret {
+                    goal_x: Some(goal_x),
+                    ..caret
+                },
+            }
+        }
+    }
+}
+
+/// Move `selection`, whose active end is `caret`, by `movement`: its active
+/// end moves, and the anchor with it unless `extend`
+///
+/// Without extending, moving by a grapheme collapses a range selection to
+/// the start or end it moves towards instead.
+pub fn move_selection(
+    text: &str,
+    layout: &mut ViewportLayout,
+    selection: Selection,
+    caret: Caret,
+    movement: Movement,
+    extend: bool,
+) -> (Selection, Caret) {
+    let caret = match movement.is_vertical() {
+        true => caret,
+        false => Caret { goal_x: None, ..caret },
+    };
+    if !extend && !selection.is_empty() {
+        match movement {
+            Movement::NextGrapheme => return (Selection::new(selection.end(), selection.end()), Caret::new(selection.end())),
+            Movement::PreviousGrapheme => {
+                return (Selection::new(selection.start(), selection.start()), Caret::new(selection.start()))
+            }
+            _ => {}
+        }
+    }
+    let moved = move_caret(text, layout, caret, movement);
+    let anchor = match extend {
+        true => selection.anchor,
+        false => moved.offset,
+    };
+    (Selection::new(anchor, moved.offset), moved)
+}
+
+/// Runs a byte-offset query on `text` at char `offset`, giving a char offset
+fn by_bytes(text: &str, offset: usize, query: impl Fn(&str, usize) -> usize) -> usize {
+    let byte = text.char_indices().nth(offset).map_or(text.len(), |(i, _)| i);
+    text[..query(text, byte)].chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_layout::{PageConfig, PageLayout};
+    use crate::paragraph_hash::ParagraphHashes;
+    use crate::piece_tree::PieceTree;
+    use crate::text_shaping::TextShaper;
+    use crate::viewport_layout::Viewport;
+
+    fn layout(tree: &PieceTree) -> ViewportLayout {
+        let mut layout = ViewportLayout::new(PageLayout::with_page_config(PageConfig::letter()));
+        layout.line_layout_mut().breaker_mut().set_shaper(TextShaper::without_font());
+        layout.update(ParagraphHashes::build(tree).entries(), &[]);
+        layout.viewport_pages(tree, Viewport { top: 0.0, height: 800.0 });
+        layout
+    }
+
+    fn moved(text: &str, layout: &mut ViewportLayout, offset: usize, movement: Movement) -> usize {
+        move_caret(text, layout, Caret::new(offset), movement).offset
+    }
+
+    #[test]
+    fn test_moves_through_text() {
+        let text = "Cafe\u{301} au lait.\nSecond one";
+        let tree = PieceTree::new(text.to_string());
+        let mut layout = layout(&tree);
+
+        // The combining accent goes with its letter
+        assert_eq!(moved(text, &mut layout, 3, Movement::NextGrapheme), 5);
+        assert_eq!(moved(text, &mut layout, 5, Movement::PreviousGrapheme), 3);
+        assert_eq!(moved(text, &mut layout, 0, Movement::NextWord), 6);
+        assert_eq!(moved(text, &mut layout, 7, Movement::PreviousWord), 6);
+        assert_eq!(moved(text, &mut layout, 2, Movement::NextParagraph), 15);
+        assert_eq!(moved(text, &mut layout, 15, Movement::PreviousParagraph), 0);
+        assert_eq!(moved(text, &mut layout, 17, Movement::PreviousParagraph), 15);
+        assert_eq!(moved(text, &mut layout, 17, Movement::DocumentEnd), 25);
+        assert_eq!(moved(text, &mut layout, 17, Movement::LineStart), 15);
+        assert_eq!(moved(text, &mut layout, 2, Movement::LineEnd), 14);
+
+        // Extending keeps the anchor; a plain move collapses towards its side
+        let (selection, _) = move_selection(text, &mut layout, Selection::new(2, 2), Caret::new(2), Movement::NextWord, true);
+        assert_eq!(selection, Selection::new(2, 6));
+        let (selection, _) = move_selection(text, &mut layout, selection, Caret::new(6), Movement::PreviousGrapheme, false);
+        assert_eq!(selection, Selection::new(2, 2));
+    }
+
+    #[test]
+    fn test_vertical_moves_keep_the_goal_x() {
+        let long = "Many words in a row. ".repeat(20);
+        let text = format!("{}\nShort\n{}", long, long);
+        let tree = PieceTree::new(text.clone());
+        let mut layout = layout(&tree);
+        let start = layout.caret_rect(30, Affinity::Downstream).unwrap();
+
+        // Down through the short paragraph and back up returns to the column
+        let mut caret = Caret::new(30);
+        let mut lines = Vec::new();
+        for _ in 0..6 {
+            caret = move_caret(&text, &mut layout, caret, Movement::LineDown);
+            lines.push(layout.caret_rect(caret.offset, caret.affinity).unwrap().rect.y);
+        }
+        assert!(lines.windows(2).all(|pair| pair[1] > pair[0]));
+        assert_eq!(caret.goal_x, Some(start.rect.x));
+        for _ in 0..6 {
+            caret = move_caret(&text, &mut layout, caret, Movement::LineUp);
+        }
+        assert_eq!(caret.offset, 30);
+
+        // Up from the first line stays put
+        let top = move_caret(&text, &mut layout, Caret::new(3), Movement::LineUp);
+        assert_eq!(top.offset, 3);
+
+        // End goes to the end of the wrapped line, before the next line's start
+        let end = move_caret(&text, &mut layout, Caret::new(0), Movement::LineEnd);
+        assert_eq!(end.affinity, Affinity::Upstream);
+        let next_line = move_caret(&text, &mut layout, Caret::new(0), Movement::LineDown);
+        assert_eq!(move_caret(&text, &mut layout, next_line, Movement::LineStart).offset, end.offset);
+    }
+}
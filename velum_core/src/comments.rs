@@ -0,0 +1,434 @@
+//! # Comments Module
+//!
+//! Threaded comments anchored to ranges of editor text.
+//!
+//! A comment covers chars of the whole text, like a revision, and its anchor
+//! moves with later edits. Replies share the anchor of the comment that
+//! starts their thread; resolving a thread marks all of it done. In OOXML the
+//! bodies live in comments.xml, the threads and done flags in
+//! commentsExtended.xml, and the anchors are comment marks in the paragraphs.
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{Comment, CommentMark, CommentMarkKind, Paragraph, Run};
+
+/// Comment errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum CommentError {
+    #[error("Comment {0} does not exist")]
+    NotFound(String),
+}
+
+/// A comment with its replies, oldest first
+#[derive(Debug, Clone, Serialize)]
+pub struct CommentThread {
+    pub comment: Comment,
+    pub replies: Vec<Comment>,
+}
+
+/// Comments of a document
+#[derive(Debug, Clone, Default)]
+pub struct CommentManager {
+    /// In the order they were read or added
+    comments: Vec<Comment>,
+}
+
+impl CommentManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Manage comments whose anchors are already char offsets into the editor text
+    pub fn from_comments(comments: Vec<Comment>) -> Self {
+        CommentManager { comments }
+    }
+
+    /// Comments of the model, anchored by the comment marks in its body paragraphs
+    pub fn from_model(model: &DocumentModel) -> Self {
+        let mut comments = model.comments.clone();
+        anchor_comments(&mut comments, model.located_paragraphs());
+        CommentManager { comments }
+    }
+
+    /// Hand the comments to the model, with their anchors as marks in its paragraphs
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let marks = comment_marks(&self.comments);
+        let mut paragraph_start = 0;
+        for block in model.body.iter_mut() {
+            let Block::Paragraph(paragraph) = block else {
+                continue;
+            };
+            let length = paragraph.text.chars().count();
+            paragraph.comment_marks = paragraph_comment_marks(&marks, paragraph_start, length);
+            paragraph_start += length + 1;
+        }
+        model.comments = self.comments.clone();
+    }
+
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Comment> {
+        self.comments.iter().find(|comment| comment.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.comments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty()
+    }
+
+    /// Threads in order of their anchors
+    pub fn threads(&self) -> Vec<CommentThread> {
+        let mut threads: Vec<CommentThread> = self
+            .comments
+            .iter()
+            .filter(|comment| self.parent(comment).is_none())
+            .map(|comment| CommentThread {
+                comment: comment.clone(),
+                replies: self
+                    .comments
+                    .iter()
+                    .filter(|reply| reply.parent_id.as_deref() == Some(comment.id.as_str()))
+                    .cloned()
+                    .collect(),
+            })
+            .collect();
+        threads.sort_by_key(|thread| thread.comment.start);
+        threads
+    }
+
+    /// Threads whose anchor contains char `offset`, ends included
+    pub fn threads_at(&self, offset: usize) -> Vec<CommentThread> {
+        self.threads()
+            .into_iter()
+            .filter(|thread| (thread.comment.start..=thread.comment.start + thread.comment.length).contains(&offset))
+            .collect()
+    }
+
+    /// Comment `author` on chars `range`; returns the new comment's ID
+    pub fn add(&mut self, range: Range<usize>, author: &str, text: &str) -> String {
+        let comment = self.new_comment(author, text, range, None);
+        let id = comment.id.clone();
+        self.comments.push(comment);
+        id
+    }
+
+    /// Reply to a comment; a reply to a reply joins the same thread
+    pub fn reply(&mut self, id: &str, author: &str, text: &str) -> Result<String, CommentError> {
+        let root = self.thread_root(id)?;
+        let range = root.start..root.start + root.length;
+        let parent = root.id.clone();
+        let reply = self.new_comment(author, text, range, Some(parent));
+        let id = reply.id.clone();
+        self.comments.push(reply);
+        Ok(id)
+    }
+
+    /// Mark the thread of a comment done, or open it again
+    pub fn set_resolved(&mut self, id: &str, done: bool) -> Result<(), CommentError> {
+        let root = self.thread_root(id)?.id.clone();
+        for comment in self.comments.iter_mut() {
+            if comment.id == root || comment.parent_id.as_deref() == Some(root.as_str()) {
+                comment.done = done;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete a comment, with its replies if it starts a thread; returns the deleted IDs
+    pub fn delete(&mut self, id: &str) -> Result<Vec<String>, CommentError> {
+        let comment = self.get(id).ok_or_else(|| CommentError::NotFound(id.to_string()))?;
+        let is_root = self.parent(comment).is_none();
+        let mut deleted = Vec::new();
+        self.comments.retain(|comment| {
+            let remove = comment.id == id || (is_root && comment.parent_id.as_deref() == Some(id));
+            if remove {
+                deleted.push(comment.id.clone());
+            }
+            !remove
+        });
+        Ok(deleted)
+    }
+
+    /// Move the comments past an edit, see [`move_anchor`]; a comment whose
+    /// text is all deleted stays, anchored where it was
+    pub fn apply_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        for comment in self.comments.iter_mut() {
+            (comment.start, comment.length) = move_anchor(comment.start, comment.length, offset, removed, inserted);
+        }
+    }
+
+    /// An ID no comment has yet
+    pub fn next_id(&self) -> String {
+        self.comments
+            .iter()
+            .filter_map(|comment| comment.id.parse::<u64>().ok())
+            .max()
+            .map_or(0, |id| id + 1)
+            .to_string()
+    }
+
+    fn new_comment(&self, author: &str, text: &str, range: Range<usize>, parent_id: Option<String>) -> Comment {
+        Comment {
+            id: self.next_id(),
+            author: Some(author.to_string()),
+            initials: initials(author),
+            date: Some(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            paragraphs: text
+                .split('\n')
+                .map(|line| Paragraph {
+                    text: line.to_string(),
+                    runs: vec![Run {
+                        text: line.to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                })
+                .collect(),
+            parent_id,
+            done: false,
+            start: range.start,
+            length: range.len(),
+        }
+    }
+
+    /// The comment a comment replies to, if it is still there
+    fn parent(&self, comment: &Comment) -> Option<&Comment> {
+        comment.parent_id.as_deref().and_then(|id| self.get(id))
+    }
+
+    /// The comment that starts the thread `id` is in
+    fn thread_root(&self, id: &str) -> Result<&Comment, CommentError> {
+        let mut comment = self.get(id).ok_or_else(|| CommentError::NotFound(id.to_string()))?;
+        // Bounded, in case a document's threads form a cycle
+        for _ in 0..self.comments.len() {
+            match self.parent(comment) {
+                Some(parent) => comment = parent,
+                None => break,
+            }
+        }
+        Ok(comment)
+    }
+}
+
+/// Set each comment's anchor from the comment marks of `paragraphs`, each
+/// given with the char offset it starts at
+///
+/// Comments without range marks are anchored at their reference mark, and
+/// replies without any marks share the anchor of the comment they reply to.
+pub(crate) fn anchor_comments<'a>(comments: &mut [Comment], paragraphs: impl IntoIterator<Item = (usize, &'a Paragraph)>) {
+    let mut marks = Vec::new();
+    for (paragraph_start, paragraph) in paragraphs {
+        marks.extend(paragraph.comment_marks.iter().map(|mark| (mark, paragraph_start + mark.position)));
+    }
+
+    let mut unmarked = Vec::new();
+    for (index, comment) in comments.iter_mut().enumerate() {
+        let position = |kind: CommentMarkKind| {
+            marks
+                .iter()
+                .find(|(mark, _)| mark.kind == kind && mark.id == comment.id)
+                .map(|&(_, position)| position)
+        };
+        let end = position(CommentMarkKind::RangeEnd).or_else(|| position(CommentMarkKind::Reference));
+        let start = position(CommentMarkKind::RangeStart);
+        if start.is_none() && end.is_none() {
+            unmarked.push(index);
+        }
+        let end = end.or(start).unwrap_or(0);
+        let start = start.unwrap_or(end).min(end);
+        comment.start = start;
+        comment.length = end - start;
+    }
+
+    for index in unmarked {
+        let parent = comments[index]
+            .parent_id
+            .as_deref()
+            .and_then(|id| comments.iter().find(|comment| comment.id == id))
+            .map(|parent| (parent.start, parent.length));
+        if let Some((start, length)) = parent {
+            comments[index].start = start;
+            comments[index].length = length;
+        }
+    }
+}
+
+/// Where the anchor of `length` chars at `start` is after an edit replacing
+/// `removed` chars at `offset` with `inserted` chars; returns (start, length)
+///
+/// All the parts anchored in the text move past edits with this. Text typed
+/// inside the anchor extends it, text typed at either end does not, and an
+/// anchor of no length goes after text typed at it. An anchor whose text is
+/// all deleted is left with no length where the text was.
+pub(crate) fn move_anchor(start: usize, length: usize, offset: usize, removed: usize, inserted: usize) -> (usize, usize) {
+    let removed_end = offset + removed;
+    let end = start + length;
+    // The first char goes with the text it is in, the end stays before the edit
+    let new_start = if start < offset {
+        start
+    } else if start < removed_end {
+        offset
+    } else {
+        start - removed + inserted
+    };
+    let new_end = if end <= offset {
+        end
+    } else if end <= removed_end {
+        offset
+    } else {
+        end - removed + inserted
+    };
+    (new_start, new_end.saturating_sub(new_start))
+}
+
+/// Range boundaries and reference marks of the comments, in text order
+pub(crate) fn comment_marks(comments: &[Comment]) -> Vec<CommentMark> {
+    let mut marks: Vec<CommentMark> = comments
+        .iter()
+        .flat_map(|comment| {
+            let end = comment.start + comment.length;
+            [
+                (CommentMarkKind::RangeStart, comment.start),
+                (CommentMarkKind::RangeEnd, end),
+                (CommentMarkKind::Reference, end),
+            ]
+            .map(|(kind, position)| CommentMark {
+                kind,
+                id: comment.id.clone(),
+                position,
+            })
+        })
+        .collect();
+    marks.sort_by_key(|mark| (mark.position, mark.kind));
+    marks
+}
+
+/// The marks inside the paragraph of `length` chars at `start`, relative to it
+pub(crate) fn paragraph_comment_marks(marks: &[CommentMark], start: usize, length: usize) -> Vec<CommentMark> {
+    marks
+        .iter()
+        .filter(|mark| (start..=start + length).contains(&mark.position))
+        .map(|mark| CommentMark {
+            position: mark.position - start,
+            ..mark.clone()
+        })
+        .collect()
+}
+
+/// First letters of the words of a name, e.g. "AB" for "Ann Baker"
+fn initials(author: &str) -> Option<String> {
+    let initials: String = author
+        .split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .flat_map(char::to_uppercase)
+        .collect();
+    (!initials.is_empty()).then_some(initials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anchor(manager: &CommentManager, id: &str) -> Range<usize> {
+        let comment = manager.get(id).unwrap();
+        comment.start..comment.start + comment.length
+    }
+
+    #[test]
+    fn test_anchor_follows_edits() {
+        let mut manager = CommentManager::new();
+        // "The quick brown fox", commenting on "quick brown"
+        let id = manager.add(4..15, "Ann Baker", "Too slow?");
+        assert_eq!(manager.get(&id).unwrap().initials.as_deref(), Some("AB"));
+
+        manager.apply_edit(0, 0, 2);
+        assert_eq!(anchor(&manager, &id), 6..17);
+        // Typing at either end stays outside, typing inside extends it
+        manager.apply_edit(6, 0, 1);
+        manager.apply_edit(18, 0, 1);
+        assert_eq!(anchor(&manager, &id), 7..18);
+        manager.apply_edit(10, 0, 3);
+        assert_eq!(anchor(&manager, &id), 7..21);
+        // So does text replacing what comes just before it
+        manager.apply_edit(5, 2, 3);
+        assert_eq!(anchor(&manager, &id), 8..22);
+        manager.apply_edit(5, 3, 2);
+
+        // Deleting across the start cuts the anchor; deleting all of it leaves a point
+        manager.apply_edit(5, 4, 0);
+        assert_eq!(anchor(&manager, &id), 5..17);
+        manager.apply_edit(0, 30, 0);
+        assert_eq!(anchor(&manager, &id), 0..0);
+        assert_eq!(manager.len(), 1);
+    }
+
+    #[test]
+    fn test_threads() {
+        let mut manager = CommentManager::new();
+        let second = manager.add(20..25, "Bob", "Later");
+        let first = manager.add(2..8, "Ann", "Check this\nand this");
+        assert_eq!((first.as_str(), second.as_str()), ("1", "0"));
+
+        let reply = manager.reply(&first, "Bob", "Checked").unwrap();
+        // Replying to a reply answers the thread
+        let reply_to_reply = manager.reply(&reply, "Ann", "Thanks").unwrap();
+        assert_eq!(manager.get(&reply_to_reply).unwrap().parent_id.as_deref(), Some("1"));
+        assert_eq!(anchor(&manager, &reply_to_reply), 2..8);
+
+        let threads = manager.threads();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].comment.text(), "Check this\nand this");
+        assert_eq!(threads[0].replies.len(), 2);
+        assert_eq!(manager.threads_at(8).len(), 1);
+        assert!(manager.threads_at(10).is_empty());
+
+        manager.set_resolved(&reply, true).unwrap();
+        assert!(manager.comments().iter().filter(|c| c.id != second).all(|c| c.done));
+        assert!(!manager.get(&second).unwrap().done);
+
+        assert_eq!(manager.delete(&reply).unwrap(), vec![reply.clone()]);
+        assert_eq!(manager.delete(&first).unwrap(), vec![first.clone(), reply_to_reply]);
+        assert_eq!(manager.len(), 1);
+        assert_eq!(manager.reply(&first, "Ann", "?"), Err(CommentError::NotFound(first)));
+    }
+
+    #[test]
+    fn test_model_marks_round_trip() {
+        let mut manager = CommentManager::new();
+        // Spans the paragraph break of "Hello\nworld"
+        let id = manager.add(3..8, "Ann", "Across");
+        let point = manager.add(11..11, "Bob", "At the end");
+
+        let mut model = DocumentModel::from_piece_tree(&crate::piece_tree::PieceTree::new("Hello\nworld".to_string()));
+        manager.add_to_model(&mut model);
+        let marks: Vec<Vec<(CommentMarkKind, usize)>> = model
+            .paragraphs()
+            .map(|p| p.comment_marks.iter().map(|m| (m.kind, m.position)).collect())
+            .collect();
+        assert_eq!(
+            marks,
+            vec![
+                vec![(CommentMarkKind::RangeStart, 3)],
+                vec![
+                    (CommentMarkKind::RangeEnd, 2),
+                    (CommentMarkKind::Reference, 2),
+                    (CommentMarkKind::RangeStart, 5),
+                    (CommentMarkKind::RangeEnd, 5),
+                    (CommentMarkKind::Reference, 5),
+                ],
+            ]
+        );
+
+        let loaded = CommentManager::from_model(&model);
+        assert_eq!(anchor(&loaded, &id), 3..8);
+        assert_eq!(anchor(&loaded, &point), 11..11);
+    }
+}
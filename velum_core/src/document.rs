@@ -0,0 +1,866 @@
+//! # Document Module
+//!
+//! The document being edited, as one model over its parts.
+//!
+//! The text with its run and paragraph formatting lives in a [`PieceTree`];
+//! styles, lists, revisions, comments, bookmarks, hyperlinks, form fields,
+//! math zones, index entries, captions and floating objects live beside it,
+//! most of them anchored to char offsets of the text. [`Document`] owns all
+//! of them and is the one way to change them: its edits move every anchor,
+//! rehash the paragraphs they touch and record tracked changes, so the parts
+//! cannot drift apart. The bridge functions in [`crate::api`] call it rather
+//! than editing the parts one by one.
+//!
+//! [`Document::to_model`] gives the document in block form, as a
+//! [`DocumentModel`], and [`Document::from_model`] builds one from it; tables
+//! are kept out of the editor text.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::autoformat::{AutoFormatOptions, AutoFormatResult};
+use crate::bookmarks::BookmarkRegistry;
+use crate::captions::CaptionSet;
+use crate::comments::{anchor_comments, CommentManager};
+use crate::document_end::DocumentEnd;
+use crate::document_model::{DocumentModel, ModelMetadata};
+use crate::edit_locations::EditLocations;
+use crate::find::SearchResult;
+use crate::floating::FloatingObjectSet;
+use crate::forms::DocumentForms;
+use crate::headers_footers::HeaderFooterManager;
+use crate::hyperlinks::HyperlinkSet;
+use crate::index::DocumentIndex;
+use crate::line_breaking::BreakStrategy;
+use crate::math::MathZones;
+use crate::modification::hash_serialized;
+use crate::numbering::ListNumbering;
+use crate::ooxml::{DocumentImage, FormError, Paragraph, RunProperties};
+use crate::page_setup::PageSetup;
+use crate::paragraph_edit::{self, ParagraphSplit, SplitKind};
+use crate::paragraph_hash::ParagraphHashes;
+use crate::piece_tree::{DeleteDirection, HistoryNodeId, MultiSelection, PieceTree};
+use crate::revisions::{Resolution, RevisionError, RevisionSet};
+use crate::style_sheet::StyleSheet;
+use crate::track_changes::TrackChangesManager;
+
+/// Document metadata structure
+#[derive(Debug, Clone)]
+pub struct DocumentMetadata {
+    pub title: String,
+    pub author: String,
+    pub created_at: u64,
+    pub modified_at: u64,
+    pub word_count: usize,
+    pub char_count: usize,
+    /// When the document was opened or created in this session, for its editing time
+    pub opened_at: u64,
+}
+
+impl Default for DocumentMetadata {
+    fn default() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        DocumentMetadata {
+            title: "Untitled Document".to_string(),
+            author: "".to_string(),
+            created_at: now,
+            modified_at: now,
+            word_count: 0,
+            char_count: 0,
+            opened_at: now,
+        }
+    }
+}
+
+/// The open document: its text and everything kept in step with it
+///
+/// Edits made through its methods bring the rest up to date themselves. Code
+/// that edits `content` directly tells the document afterwards, with
+/// [`Document::text_edited`], [`Document::text_inserted`] or
+/// [`Document::formatting_changed`].
+pub struct Document {
+    pub content: PieceTree,
+    pub metadata: DocumentMetadata,
+    pub page_setup: PageSetup,
+    /// Per-paragraph content hashes, updated on insert/delete and rebuilt lazily otherwise
+    pub paragraph_hashes: ParagraphHashes,
+    /// Recent edit positions for go-back navigation
+    pub edit_locations: EditLocations,
+    /// Named styles the paragraphs refer to
+    pub styles: StyleSheet,
+    /// Pending tracked changes
+    pub revisions: RevisionSet,
+    /// Records edits as tracked changes when turned on
+    pub track_changes: TrackChangesManager,
+    /// Comment threads anchored to the text
+    pub comments: CommentManager,
+    /// Named ranges cross-references point at
+    pub bookmarks: BookmarkRegistry,
+    /// Links to URLs and bookmarks over ranges of the text
+    pub hyperlinks: HyperlinkSet,
+    /// Form fields over the text they show, and the editing restriction
+    pub forms: DocumentForms,
+    /// Equations typed in linear format
+    pub math_zones: MathZones,
+    /// List definitions the paragraphs and styles refer to
+    pub numbering: ListNumbering,
+    /// Index entries marked in the text, and the index built from them
+    pub index: DocumentIndex,
+    /// Numbered captions and the objects they are anchored to
+    pub captions: CaptionSet,
+    /// Images and the text positions they are anchored at
+    pub floating: FloatingObjectSet,
+    /// Headers and footers of each section, edited apart from the body
+    pub headers_footers: HeaderFooterManager,
+    /// Final paragraph mark and body-level section properties, kept across edits
+    pub end: DocumentEnd,
+    /// Line breaking for paragraphs whose style does not choose one
+    pub break_strategy: BreakStrategy,
+    /// Which markup typing converts into lists, lines, tables and headings
+    pub autoformat: AutoFormatOptions,
+    /// Where the anchored parts stood at the history nodes undo and redo return to
+    pub anchor_history: AnchorHistory,
+}
+
+/// What is anchored to the text, as it stood at one history node
+struct AnchorSnapshot {
+    revisions: RevisionSet,
+    comments: CommentManager,
+    bookmarks: BookmarkRegistry,
+    hyperlinks: HyperlinkSet,
+    forms: DocumentForms,
+    math_zones: MathZones,
+    index: DocumentIndex,
+    captions: CaptionSet,
+    floating: FloatingObjectSet,
+}
+
+/// The anchored parts of a document at each history node it has left
+///
+/// Undo only puts the text back; moving anchors through the inverse edits
+/// would leave anchors a deletion collapsed where it left them, so the
+/// document goes back to what it had at the node instead.
+#[derive(Default)]
+pub struct AnchorHistory {
+    snapshots: HashMap<HistoryNodeId, AnchorSnapshot>,
+    /// Snapshot count past which those of nodes the history dropped go
+    prune_at: usize,
+}
+
+impl AnchorHistory {
+    /// Forget every snapshot, e.g. once the history was replaced
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+impl Document {
+    pub fn empty() -> Self {
+        Document {
+            content: PieceTree::empty(),
+            metadata: DocumentMetadata::default(),
+            page_setup: PageSetup::new(),
+            paragraph_hashes: ParagraphHashes::default(),
+            edit_locations: EditLocations::new(),
+            styles: StyleSheet::new(),
+            revisions: RevisionSet::new(),
+            track_changes: TrackChangesManager::default(),
+            comments: CommentManager::new(),
+            bookmarks: BookmarkRegistry::new(),
+            hyperlinks: HyperlinkSet::new(),
+            forms: DocumentForms::new(),
+            math_zones: MathZones::new(),
+            numbering: ListNumbering::new(),
+            index: DocumentIndex::new(),
+            captions: CaptionSet::new(),
+            floating: FloatingObjectSet::new(),
+            headers_footers: HeaderFooterManager::new(),
+            end: DocumentEnd::new(),
+            break_strategy: BreakStrategy::default(),
+            autoformat: AutoFormatOptions::default(),
+            anchor_history: AnchorHistory::default(),
+        }
+    }
+
+    pub fn new(content: String) -> Self {
+        let char_count = content.chars().count();
+        let word_count = content.split_whitespace().count();
+        Document {
+            content: PieceTree::new(content),
+            metadata: DocumentMetadata { char_count, word_count, ..Default::default() },
+            ..Document::empty()
+        }
+    }
+
+    pub fn update_metadata(&mut self) {
+        let text = self.content.get_text();
+        self.metadata.char_count = text.chars().count();
+        self.metadata.word_count = text.split_whitespace().count();
+        self.metadata.modified_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+    }
+
+    /// Hash of the state a save persists: content with formatting, headers and
+    /// footers, page setup, comments, bookmarks, hyperlinks, form fields,
+    /// equations, index entries, captions, lists, images, styles, title and author
+    pub(crate) fn state_hash(&mut self) -> u64 {
+        self.paragraph_hashes.ensure_valid(&self.content);
+        let mut hasher = DefaultHasher::new();
+        self.paragraph_hashes.hashes().hash(&mut hasher);
+        self.headers_footers.content_hash().hash(&mut hasher);
+        let images: Vec<_> = self
+            .floating
+            .images()
+            .enumerate()
+            .map(|(index, image)| (image, self.floating.offset(index)))
+            .collect();
+        let styles: Vec<_> = self.styles.styles().collect();
+        let annotations = (
+            (&self.page_setup, self.comments.comments(), self.bookmarks.bookmarks(), self.hyperlinks.hyperlinks()),
+            (self.forms.fields(), self.forms.protection(), self.math_zones.zones(), self.index.entries()),
+            (self.captions.captions(), self.captions.labels(), self.numbering.to_ooxml(), images, styles),
+        );
+        hash_serialized(&annotations, &mut hasher);
+        self.metadata.title.hash(&mut hasher);
+        self.metadata.author.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// A document made from a document model
+    pub fn from_model(model: &DocumentModel) -> Self {
+        let paragraphs: Vec<(usize, &Paragraph)> = model.located_paragraphs().collect();
+        let mark = model.paragraphs().last().and_then(|paragraph| paragraph.mark_properties.clone());
+        Self::from_body(model, model.to_piece_tree(), &paragraphs, mark)
+    }
+
+    /// A document of the body text `content`, with the styles, lists,
+    /// sections, comments and images of `model`
+    ///
+    /// What is anchored to the text comes from `paragraphs`, each with the char
+    /// offset it starts at, which need only be the body paragraphs with
+    /// something anchored in them; `mark` is the final paragraph's.
+    pub fn from_body(
+        model: &DocumentModel,
+        content: PieceTree,
+        paragraphs: &[(usize, &Paragraph)],
+        mark: Option<RunProperties>,
+    ) -> Self {
+        let located = || paragraphs.iter().copied();
+        let mut doc = Document::empty();
+        doc.content = content;
+        doc.styles = StyleSheet::from_ooxml_styles(&model.styles);
+        doc.numbering = ListNumbering::from_ooxml(&model.numbering);
+        doc.revisions = RevisionSet::from_paragraphs(located());
+        let mut comments = model.comments.clone();
+        anchor_comments(&mut comments, located());
+        doc.comments = CommentManager::from_comments(comments);
+        doc.bookmarks = BookmarkRegistry::from_paragraphs(located());
+        doc.hyperlinks = HyperlinkSet::from_paragraphs(located());
+        doc.forms = DocumentForms::from_paragraphs(located(), model.protection);
+        doc.math_zones = MathZones::from_paragraphs(located());
+        doc.index = DocumentIndex::from_paragraphs(located());
+        doc.captions = CaptionSet::from_paragraphs(located());
+        doc.floating = FloatingObjectSet::from_images(&model.images, |index| doc.content.get_offset_at_line(index));
+        doc.headers_footers = HeaderFooterManager::from_model(model);
+        doc.end = DocumentEnd::from_ooxml(mark, &model.sections);
+        doc.page_setup = PageSetup::from_sections(&model.sections);
+        if let Some(title) = &model.metadata.title {
+            doc.metadata.title = title.clone();
+        }
+        if let Some(author) = &model.metadata.author {
+            doc.metadata.author = author.clone();
+        }
+        if let Some(created) = model.metadata.created.as_deref().and_then(parse_w3cdtf) {
+            doc.metadata.created_at = created;
+        }
+        doc.update_metadata();
+        doc
+    }
+
+    /// The document model of this document: its paragraphs, styles, lists and what is anchored to the text
+    pub fn to_model(&self) -> DocumentModel {
+        let mut model = DocumentModel::from_piece_tree(&self.content);
+        model.styles = self.styles.to_ooxml_styles();
+        model.numbering = self.numbering.to_ooxml();
+        self.revisions.add_to_model(&mut model);
+        self.comments.add_to_model(&mut model);
+        self.bookmarks.add_to_model(&mut model);
+        self.hyperlinks.add_to_model(&mut model);
+        self.forms.add_to_model(&mut model);
+        self.math_zones.add_to_model(&mut model);
+        self.index.add_to_model(&mut model);
+        self.captions.add_to_model(&mut model);
+        self.floating.add_to_model(&mut model);
+        self.headers_footers.add_to_model(&mut model);
+        self.end.add_to_model(&mut model);
+        model.metadata = ModelMetadata {
+            title: Some(self.metadata.title.clone()),
+            author: Some(self.metadata.author.clone()).filter(|a| !a.is_empty()),
+            created: w3cdtf(self.metadata.created_at),
+            modified: w3cdtf(self.metadata.modified_at),
+        };
+        model
+    }
+
+    // ==================== Editing ====================
+
+    /// Insert `text` at char `offset`, tracked while tracking changes
+    /// Returns the chars inserted
+    pub fn insert_text(&mut self, offset: usize, text: &str) -> usize {
+        let offset = offset.min(self.content.total_char_count);
+        self.remember_anchors();
+        let inserted = self.track_changes.insert(&mut self.content, &mut self.revisions, offset, text);
+        self.text_edited(offset, 0, inserted);
+        inserted
+    }
+
+    /// Add `image` at char `offset`, clamped to the text, as an undo step of
+    /// its own, and return its index among the document's images
+    ///
+    /// An image takes no char of the text; it is drawn at its anchor and moves
+    /// with edits before it.
+    pub fn insert_image(&mut self, offset: usize, image: DocumentImage) -> usize {
+        let offset = offset.min(self.content.total_char_count);
+        self.change_anchors(|doc| doc.floating.insert(offset, image))
+    }
+
+    /// Delete chars `range`, or only mark them deleted while tracking changes
+    ///
+    /// A deletion joining the final paragraph to the one before keeps the final
+    /// paragraph's formatting. Returns the ranges taken out of the text, each
+    /// relative to the text after the ones before it.
+    pub fn delete_text(&mut self, range: Range<usize>) -> Vec<Range<usize>> {
+        self.remember_anchors();
+        let Document { content, revisions, track_changes, .. } = self;
+        let removed = DocumentEnd::keep_final_paragraph(content, range.clone(), |content| {
+            track_changes.delete(content, revisions, range.clone())
+        });
+        self.ranges_removed(range.start, &removed);
+        removed
+    }
+
+    /// Replace chars `range` with `text` in one undo step, tracked while
+    /// tracking changes; returns the chars inserted
+    ///
+    /// Text only marked deleted stays, and `text` goes in after it.
+    pub fn replace_text(&mut self, range: Range<usize>, text: &str) -> usize {
+        self.begin_transaction();
+        let removed: usize = self.delete_text(range.clone()).iter().map(|range| range.len()).sum();
+        let inserted = self.insert_text(range.end - removed, text);
+        self.end_transaction();
+        inserted
+    }
+
+    /// Replace the search `matches`, whose offsets are bytes of the text, with
+    /// `text` in one undo step; returns the replacements made
+    pub fn replace_matches(&mut self, matches: &[SearchResult], text: &str) -> usize {
+        let chars = |byte: usize| self.content.byte_to_char_offset(byte);
+        let ranges: Vec<Range<usize>> = matches.iter().map(|found| chars(found.start)..chars(found.end)).collect();
+        self.begin_transaction();
+        // From the last, so the offsets of the ones before still hold
+        for range in ranges.iter().rev() {
+            self.replace_text(range.clone(), text);
+        }
+        self.end_transaction();
+        ranges.len()
+    }
+
+    /// Press Enter at char `position`; see [`paragraph_edit::split_paragraph`]
+    pub fn split_paragraph(&mut self, position: usize) -> ParagraphSplit {
+        self.remember_anchors();
+        let split = paragraph_edit::split_paragraph(&mut self.content, &self.styles, &mut self.numbering, position);
+        match split.kind {
+            SplitKind::Split => self.text_inserted(split.caret - 1, 1),
+            _ => self.formatting_changed(split.caret..split.caret),
+        }
+        split
+    }
+
+    /// Join paragraph `index` with the next; see [`paragraph_edit::merge_with_next`]
+    ///
+    /// While tracking changes the paragraph mark is only marked deleted. Returns
+    /// the ranges taken out of the text, or None if no paragraph follows.
+    pub fn merge_with_next(&mut self, index: usize) -> Option<Vec<Range<usize>>> {
+        self.remember_anchors();
+        let Document { content, revisions, track_changes, .. } = self;
+        let removed = paragraph_edit::merge_with_next(content, index, |content, range| {
+            DocumentEnd::keep_final_paragraph(content, range.clone(), |content| track_changes.delete(content, revisions, range))
+        })?;
+        let at = self.content.get_offset_at_line(index + 1);
+        self.ranges_removed(at, &removed);
+        Some(removed)
+    }
+
+    /// Convert the markup just typed before `caret`; see [`crate::autoformat::format_as_you_type`]
+    pub fn autoformat_as_you_type(&mut self, caret: usize) -> Option<AutoFormatResult> {
+        self.remember_anchors();
+        let result =
+            crate::autoformat::format_as_you_type(&mut self.content, &mut self.numbering, &self.styles, &self.autoformat, caret)?;
+        // The markup went and its paragraph was restyled; rehash on next use
+        self.paragraph_hashes.invalidate();
+        self.move_anchors(result.removed.start, result.removed.len(), 0);
+        self.update_metadata();
+        Some(result)
+    }
+
+    /// Type `text` at every selection of `content`, replacing selected text,
+    /// as one undo step; see [`PieceTree::insert_at_selections`]
+    pub fn insert_at_selections(&mut self, text: &str) -> MultiSelection {
+        self.edit_selections(None, |doc, range| {
+            if !range.is_empty() {
+                doc.delete_text(range.clone());
+            }
+            doc.insert_text(range.start, text)
+        })
+    }
+
+    /// Delete the selected text, and at each caret the char in `direction`, as
+    /// one undo step; while tracking changes, text is only marked deleted
+    pub fn delete_at_selections(&mut self, direction: DeleteDirection) -> MultiSelection {
+        self.edit_selections(Some(direction), |doc, range| {
+            if !range.is_empty() {
+                doc.delete_text(range);
+            }
+            0
+        })
+    }
+
+    /// Whether the document's protection lets chars `range` be edited
+    pub fn allows_edit(&self, range: Range<usize>) -> bool {
+        self.forms.allows_edit(range, self.track_changes.is_enabled())
+    }
+
+    /// Whether the protection lets every selection be edited, deleting in
+    /// `direction` at carets
+    pub fn allows_edit_at_selections(&self, direction: Option<DeleteDirection>) -> bool {
+        let ranges = self.content.multi_selection().edit_ranges(direction, self.content.total_char_count);
+        ranges.into_iter().all(|range| self.allows_edit(range))
+    }
+
+    /// Set form field `name` from text, as [`crate::ooxml::FormFieldSet::set`]
+    /// does, and show its new text in its place, in one undo step
+    pub fn set_form_field(&mut self, name: &str, value: &str) -> Result<(), FormError> {
+        let mut forms = self.forms.clone();
+        let filled = forms.set(name, value)?;
+        self.fill_form_field(forms, filled);
+        Ok(())
+    }
+
+    /// Check or clear form checkbox `name`, as [`Document::set_form_field`] sets a value
+    pub fn set_form_field_checked(&mut self, name: &str, checked: bool) -> Result<(), FormError> {
+        let mut forms = self.forms.clone();
+        let filled = forms.set_checked(name, checked)?;
+        self.fill_form_field(forms, filled);
+        Ok(())
+    }
+
+    /// Undo the last undo step; false if there was none
+    pub fn undo(&mut self) -> bool {
+        self.move_in_history(PieceTree::undo)
+    }
+
+    /// Redo the last undone step; false if there was none
+    pub fn redo(&mut self) -> bool {
+        self.move_in_history(PieceTree::redo)
+    }
+
+    /// Move to any node of the history tree; false if it does not exist
+    pub fn jump_to_history(&mut self, target: HistoryNodeId) -> bool {
+        self.move_in_history(|content| content.jump_to_history(target))
+    }
+
+    /// Start edits that undo as one step; see [`PieceTree::begin_transaction`]
+    pub fn begin_transaction(&mut self) {
+        self.remember_anchors();
+        self.content.begin_transaction();
+    }
+
+    /// End edits begun with [`Document::begin_transaction`]
+    pub fn end_transaction(&mut self) {
+        self.content.end_transaction();
+    }
+
+    /// Change what is anchored to the text with `change`, e.g. add a comment
+    /// or link, as an undo step of its own
+    pub fn change_anchors<R>(&mut self, change: impl FnOnce(&mut Self) -> R) -> R {
+        self.remember_anchors();
+        let changed = change(self);
+        self.content.record_empty_step();
+        changed
+    }
+
+    /// Make a change to what is anchored to the text that may fail, as
+    /// [`Document::change_anchors`] does; a failed change records no step
+    pub fn try_change_anchors<T, E>(&mut self, change: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        self.remember_anchors();
+        let changed = change(self)?;
+        self.content.record_empty_step();
+        Ok(changed)
+    }
+
+    /// Note what is anchored to the text at the current history node, for
+    /// undo and redo to return to
+    ///
+    /// The document's edit methods do this themselves; code that edits
+    /// `content` directly calls it first.
+    pub fn remember_anchors(&mut self) {
+        // Edits inside a transaction all go to the node it ends with
+        if self.content.in_transaction() {
+            return;
+        }
+        let node = self.content.current_history_node();
+        let snapshot = AnchorSnapshot {
+            revisions: self.revisions.clone(),
+            comments: self.comments.clone(),
+            bookmarks: self.bookmarks.clone(),
+            hyperlinks: self.hyperlinks.clone(),
+            forms: self.forms.clone(),
+            math_zones: self.math_zones.clone(),
+            index: self.index.clone(),
+            captions: self.captions.clone(),
+            floating: self.floating.clone(),
+        };
+        let history = &mut self.anchor_history;
+        history.snapshots.insert(node, snapshot);
+        if history.snapshots.len() > history.prune_at {
+            let kept: HashSet<HistoryNodeId> = self.content.history_nodes().iter().map(|node| node.id).collect();
+            history.snapshots.retain(|node, _| kept.contains(node));
+            history.prune_at = (history.snapshots.len() * 2).max(64);
+        }
+    }
+
+    /// Bring the rest of the document up to date with `removed` chars at
+    /// `offset` of `content` replaced by `inserted` chars, outside tracked changes
+    pub fn text_edited(&mut self, offset: usize, removed: usize, inserted: usize) {
+        self.paragraph_hashes.apply_edit(&self.content, offset, removed, inserted);
+        self.move_anchors(offset, removed, inserted);
+        self.update_metadata();
+    }
+
+    /// Bring the rest of the document up to date with `inserted` chars put
+    /// into `content` at `offset`, recording them as an insertion while tracking changes
+    pub fn text_inserted(&mut self, offset: usize, inserted: usize) {
+        if inserted == 0 {
+            return;
+        }
+        self.revisions.apply_edit(offset, 0, inserted);
+        self.track_changes.record_insertion(&mut self.revisions, offset..offset + inserted);
+        self.text_edited(offset, 0, inserted);
+    }
+
+    /// Bring the rest of the document up to date with `removed` chars at
+    /// `offset` of `content` replaced by `inserted` chars, moving revisions too
+    pub fn text_replaced(&mut self, offset: usize, removed: usize, inserted: usize) {
+        self.revisions.apply_edit(offset, removed, inserted);
+        self.text_edited(offset, removed, inserted);
+    }
+
+    /// Accept or reject revisions with `resolve`, bringing the rest of the
+    /// document up to date with the text it removed or restyled
+    pub fn resolve_revisions(
+        &mut self,
+        resolve: impl FnOnce(&mut RevisionSet, &mut PieceTree) -> Result<Vec<Resolution>, RevisionError>,
+    ) -> Result<Vec<Resolution>, RevisionError> {
+        self.remember_anchors();
+        let resolutions = resolve(&mut self.revisions, &mut self.content)?;
+        for resolution in &resolutions {
+            if let Resolution::Removed(range) = resolution {
+                self.move_anchors(range.start, range.len(), 0);
+            }
+        }
+        match resolutions.as_slice() {
+            [] | [Resolution::Unchanged] => return Ok(resolutions),
+            [Resolution::Removed(range)] => self.paragraph_hashes.apply_edit(&self.content, range.start, range.len(), 0),
+            [Resolution::Restyled(range)] => self.formatting_changed(range.clone()),
+            // The hashes follow one edit at a time; after several, rehash on next use
+            _ => self.paragraph_hashes.invalidate(),
+        }
+        self.update_metadata();
+        Ok(resolutions)
+    }
+
+    /// Bring the rest of the document up to date with a change to the run or
+    /// paragraph formatting of chars `range` of `content`
+    pub fn formatting_changed(&mut self, range: Range<usize>) {
+        self.paragraph_hashes.apply_edit(&self.content, range.start, range.len(), range.len());
+        self.update_metadata();
+    }
+
+    /// Run `step` through the history, taking the anchors along to the node it
+    /// ends at where they are known there
+    fn move_in_history(&mut self, step: impl FnOnce(&mut PieceTree) -> bool) -> bool {
+        self.remember_anchors();
+        if !step(&mut self.content) {
+            return false;
+        }
+        let node = self.content.current_history_node();
+        if let Some(snapshot) = self.anchor_history.snapshots.get(&node) {
+            self.revisions = snapshot.revisions.clone();
+            self.comments = snapshot.comments.clone();
+            self.bookmarks = snapshot.bookmarks.clone();
+            self.hyperlinks = snapshot.hyperlinks.clone();
+            self.forms = snapshot.forms.clone();
+            self.math_zones = snapshot.math_zones.clone();
+            self.index = snapshot.index.clone();
+            self.captions = snapshot.captions.clone();
+            self.floating = snapshot.floating.clone();
+        }
+        self.paragraph_hashes.invalidate();
+        self.update_metadata();
+        true
+    }
+
+    /// Run `edit` at the range of every selection in one undo step, leaving a
+    /// caret after the chars it returns it put in
+    fn edit_selections(
+        &mut self,
+        direction: Option<DeleteDirection>,
+        mut edit: impl FnMut(&mut Self, Range<usize>) -> usize,
+    ) -> MultiSelection {
+        let selections = self.content.multi_selection();
+        let ranges = selections.edit_ranges(direction, self.content.total_char_count);
+        self.content.break_undo_coalescing();
+        self.begin_transaction();
+        let after = selections.edit_each(&ranges, |range| {
+            let before = self.content.total_char_count as isize;
+            let inserted = edit(self, range);
+            (inserted, self.content.total_char_count as isize - before)
+        });
+        self.content.set_multi_selection(&after);
+        self.end_transaction();
+        self.content.break_undo_coalescing();
+        after
+    }
+
+    /// Take `forms`, with field `index` filled, and replace the chars `range`
+    /// that showed it with `text`
+    fn fill_form_field(&mut self, forms: DocumentForms, (index, range, text): (usize, Range<usize>, String)) {
+        self.begin_transaction();
+        self.forms = forms;
+        let before = self.content.total_char_count;
+        let inserted = self.replace_text(range.clone(), &text);
+        // Text only marked deleted stays, and the new text goes in after it
+        let removed = before + inserted - self.content.total_char_count;
+        self.forms.place(index, range.end - removed, inserted);
+        self.end_transaction();
+    }
+
+    /// Move whatever is anchored to the text past an edit, and note where it was
+    fn move_anchors(&mut self, offset: usize, removed: usize, inserted: usize) {
+        self.edit_locations.record_edit(offset, removed, inserted);
+        self.comments.apply_edit(offset, removed, inserted);
+        self.bookmarks.apply_edit(offset, removed, inserted);
+        self.hyperlinks.apply_edit(offset, removed, inserted);
+        self.forms.apply_edit(offset, removed, inserted);
+        self.math_zones.apply_edit(offset, removed, inserted);
+        self.index.apply_edit(offset, removed, inserted);
+        self.captions.apply_edit(offset, removed, inserted);
+        self.floating.apply_edit(offset, removed, inserted);
+    }
+
+    /// Bring the rest of the document up to date with the ranges a deletion at
+    /// `at` took out of the text, in the order they were removed
+    fn ranges_removed(&mut self, at: usize, removed: &[Range<usize>]) {
+        match removed {
+            [] => self.edit_locations.record_edit(at, 0, 0),
+            [range] => self.paragraph_hashes.apply_edit(&self.content, range.start, range.len(), 0),
+            // The hashes follow one edit at a time; after several, rehash on next use
+            _ => self.paragraph_hashes.invalidate(),
+        }
+        for range in removed {
+            self.move_anchors(range.start, range.len(), 0);
+        }
+        self.update_metadata();
+    }
+}
+
+fn w3cdtf(seconds: u64) -> Option<String> {
+    chrono::DateTime::from_timestamp(seconds as i64, 0).map(|t| t.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+fn parse_w3cdtf(timestamp: &str) -> Option<u64> {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .and_then(|t| u64::try_from(t.timestamp()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_setup::PaperSize;
+    use crate::piece_tree::Selection;
+
+    /// Hashes kept up to date match hashes built from scratch
+    fn assert_in_step(doc: &mut Document) {
+        doc.paragraph_hashes.ensure_valid(&doc.content);
+        assert_eq!(doc.paragraph_hashes.hashes(), ParagraphHashes::build(&doc.content).hashes());
+        assert_eq!(doc.metadata.char_count, doc.content.total_char_count);
+    }
+
+    #[test]
+    fn test_edits_keep_anchors_and_hashes_in_step() {
+        let mut doc = Document::new("First line\nSecond line".to_string());
+        doc.paragraph_hashes.ensure_valid(&doc.content);
+        doc.bookmarks.insert("second", 11..17).unwrap();
+
+        assert_eq!(doc.insert_text(0, "New "), 4);
+        assert_eq!(doc.bookmarks.get("second").unwrap().range(), 15..21);
+        assert_in_step(&mut doc);
+
+        let split = doc.split_paragraph(9);
+        assert_eq!((split.kind, split.caret), (SplitKind::Split, 10));
+        assert_eq!(doc.content.get_text(), "New First\n line\nSecond line");
+        assert_eq!(doc.bookmarks.get("second").unwrap().range(), 16..22);
+        assert_in_step(&mut doc);
+
+        assert_eq!(doc.merge_with_next(0).unwrap().first(), Some(&(9..10)));
+        assert_eq!(doc.delete_text(0..4).first(), Some(&(0..4)));
+        assert_eq!(doc.content.get_text(), "First line\nSecond line");
+        assert_eq!(doc.bookmarks.get("second").unwrap().range(), 11..17);
+        assert_in_step(&mut doc);
+
+        assert!(doc.undo());
+        assert_in_step(&mut doc);
+    }
+
+    #[test]
+    fn test_replace_after_multibyte_text() {
+        let mut doc = Document::new("Größe: café\ncafé noir".to_string());
+        doc.paragraph_hashes.ensure_valid(&doc.content);
+        doc.bookmarks.insert("noir", 17..21).unwrap();
+        let text = doc.content.get_text();
+        let matches: Vec<SearchResult> = text
+            .match_indices("café")
+            .map(|(start, found)| SearchResult::new(start, start + found.len(), found.to_string()))
+            .collect();
+
+        assert_eq!(doc.replace_matches(&matches, "tea"), 2);
+        assert_eq!(doc.content.get_text(), "Größe: tea\ntea noir");
+        assert_eq!(doc.bookmarks.get("noir").unwrap().range(), 15..19);
+        assert_in_step(&mut doc);
+
+        doc.track_changes.set_enabled(true);
+        let start = "Größe: ".len();
+        assert_eq!(doc.replace_matches(&[SearchResult::new(start, start + 3, "tea".to_string())], "milk"), 1);
+        assert_eq!(doc.content.get_text(), "Größe: teamilk\ntea noir");
+        assert_eq!(doc.revisions.len(), 2);
+        assert_in_step(&mut doc);
+
+        assert!(doc.undo());
+        assert_eq!(doc.content.get_text(), "Größe: tea\ntea noir");
+    }
+
+    #[test]
+    fn test_undo_puts_anchors_back() {
+        let mut doc = Document::new("Hello world".to_string());
+        doc.bookmarks.insert("world", 6..11).unwrap();
+
+        doc.insert_text(0, "XXXXX ");
+        assert_eq!(doc.bookmarks.get("world").unwrap().range(), 12..17);
+        assert!(doc.undo());
+        assert_eq!(doc.bookmarks.get("world").unwrap().range(), 6..11);
+        assert!(doc.redo());
+        assert_eq!(doc.bookmarks.get("world").unwrap().range(), 12..17);
+        assert!(doc.undo());
+
+        doc.delete_text(5..11);
+        assert_eq!(doc.content.get_text(), "Hello");
+        assert!(doc.undo());
+        assert_eq!(doc.content.get_text(), "Hello world");
+        assert_eq!(doc.bookmarks.get("world").unwrap().range(), 6..11);
+
+        let inserted = doc.content.current_history_node();
+        doc.replace_text(0..5, "Howdy");
+        assert_eq!(doc.bookmarks.get("world").unwrap().range(), 6..11);
+        assert!(doc.jump_to_history(inserted));
+        assert_eq!(doc.content.get_text(), "Hello world");
+        assert_eq!(doc.bookmarks.get("world").unwrap().range(), 6..11);
+    }
+
+    #[test]
+    fn test_comment_undoes_before_the_typing() {
+        let mut doc = Document::new("Hello".to_string());
+        doc.insert_text(5, " world");
+        doc.change_anchors(|doc| doc.comments.add(0..5, "Ann", "Greeting?"));
+
+        assert!(doc.undo());
+        assert_eq!(doc.content.get_text(), "Hello world");
+        assert_eq!(doc.comments.comments().len(), 0);
+        assert!(doc.redo());
+        assert_eq!(doc.comments.comments().len(), 1);
+
+        doc.insert_text(11, "!");
+        assert!(doc.undo());
+        assert_eq!(doc.content.get_text(), "Hello world");
+        assert_eq!(doc.comments.comments().len(), 1);
+        assert!(doc.undo());
+        assert_eq!(doc.comments.comments().len(), 0);
+        assert!(doc.undo());
+        assert_eq!(doc.content.get_text(), "Hello");
+    }
+
+    #[test]
+    fn test_typing_at_every_selection() {
+        let mut doc = Document::new("one two three".to_string());
+        doc.paragraph_hashes.ensure_valid(&doc.content);
+        doc.bookmarks.insert("two", 4..7).unwrap();
+        doc.track_changes.set_enabled(true);
+        let mut selections = MultiSelection::new(Selection::new(0, 0));
+        selections.add(Selection::new(8, 8));
+        doc.content.set_multi_selection(&selections);
+
+        let after = doc.insert_at_selections("x");
+        assert_eq!(doc.content.get_text(), "xone two xthree");
+        assert_eq!(after.selections(), [Selection::new(1, 1), Selection::new(10, 10)]);
+        assert_eq!(doc.bookmarks.get("two").unwrap().range(), 5..8);
+        assert_eq!(doc.revisions.len(), 2);
+        assert_in_step(&mut doc);
+
+        doc.track_changes.set_enabled(false);
+        doc.delete_at_selections(DeleteDirection::Forward);
+        assert_eq!(doc.content.get_text(), "xne two xhree");
+        assert_in_step(&mut doc);
+
+        assert!(doc.undo());
+        assert_eq!(doc.content.multi_selection(), after);
+    }
+
+    #[test]
+    fn test_state_hash_covers_what_a_save_keeps() {
+        let mut doc = Document::new("Title\nBody".to_string());
+        let mut hashes = vec![doc.state_hash()];
+        doc.page_setup.set_page_size(0, PaperSize::Custom { width: 500.0, height: 700.0 }).unwrap();
+        hashes.push(doc.state_hash());
+        doc.comments.add(0..5, "Ann", "Shorter?");
+        hashes.push(doc.state_hash());
+        doc.bookmarks.insert("body", 6..10).unwrap();
+        hashes.push(doc.state_hash());
+        doc.hyperlinks.insert(6..10, Some("https://example.com"), None, None).unwrap();
+        hashes.push(doc.state_hash());
+        hashes.dedup();
+        assert_eq!(hashes.len(), 5);
+        assert_eq!(doc.state_hash(), hashes[4]);
+    }
+
+    #[test]
+    fn test_model_round_trip() {
+        let mut doc = Document::new("Title\nBody".to_string());
+        doc.metadata.title = "Report".to_string();
+        doc.bookmarks.insert("body", 6..10).unwrap();
+
+        let model = doc.to_model();
+        assert_eq!(model.paragraphs().count(), 2);
+        let loaded = Document::from_model(&model);
+        assert_eq!(loaded.content.get_text(), "Title\nBody");
+        assert_eq!(loaded.metadata.title, "Report");
+        assert_eq!(loaded.bookmarks.get("body").unwrap().range(), 6..10);
+    }
+}
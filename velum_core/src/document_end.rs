@@ -0,0 +1,238 @@
+//! # Document End Module
+//!
+//! The end of a Word body: the final paragraph mark and the body-level
+//! w:sectPr after it.
+//!
+//! Word never deletes the final paragraph mark. Joining the last paragraph to
+//! the one before it keeps the last paragraph's formatting, and the mark's
+//! own formatting and the last section's properties stay as they are whatever
+//! is edited before them. The editor text has no place for the mark or the
+//! section, so [`DocumentEnd`] holds them beside it and hands them back to
+//! the model and to exports.
+
+use std::ops::Range;
+
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{ExportContent, RunProperties, Section};
+use crate::piece_tree::PieceTree;
+
+/// The final paragraph mark's formatting and the body-level section properties
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentEnd {
+    mark: Option<RunProperties>,
+    /// As read; its header and footer references and first-page flag are
+    /// the HeaderFooterManager's
+    section: Section,
+}
+
+impl DocumentEnd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The end of the model's body: its final paragraph's mark and last section
+    pub fn from_model(model: &DocumentModel) -> Self {
+        let mark = model.paragraphs().last().and_then(|paragraph| paragraph.mark_properties.clone());
+        Self::from_ooxml(mark, &model.sections)
+    }
+
+    /// The end of a body read from a file: the `mark` of its final paragraph
+    /// and the last of its `sections`
+    pub fn from_ooxml(mark: Option<RunProperties>, sections: &[Section]) -> Self {
+        DocumentEnd {
+            mark,
+            section: sections.last().cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Formatting of the final paragraph mark
+    pub fn mark(&self) -> Option<&RunProperties> {
+        self.mark.as_ref()
+    }
+
+    /// The body-level section properties
+    pub fn section(&self) -> &Section {
+        &self.section
+    }
+
+    /// Give the model's final paragraph its mark, and its last section the
+    /// page setup, columns and other properties read with it
+    ///
+    /// Call after the headers and footers have added the sections.
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let last = model.body.iter_mut().rev().find_map(|block| match block {
+            Block::Paragraph(paragraph) => Some(paragraph),
+            Block::Table(_) => None,
+        });
+        if let Some(paragraph) = last {
+            paragraph.mark_properties = self.mark.clone();
+        }
+        if model.sections.is_empty() {
+            model.sections.push(Section {
+                paragraph_count: model.paragraphs().count(),
+                ..Default::default()
+            });
+        }
+        self.fill_section(model.sections.last_mut().expect("sections is not empty"));
+    }
+
+    /// Give an export the final mark, and its last section what
+    /// [`DocumentEnd::add_to_model`] gives the model's
+    pub fn add_to_export(&self, content: &mut ExportContent) {
+        content.final_mark = self.mark.clone();
+        if content.sections.is_empty() {
+            content.sections.push(Section::default());
+        }
+        self.fill_section(content.sections.last_mut().expect("sections is not empty"));
+    }
+
+    /// Everything of the section as read but the references and first-page flag
+    fn fill_section(&self, section: &mut Section) {
+        *section = Section {
+            first_paragraph: section.first_paragraph,
+            paragraph_count: section.paragraph_count,
+            title_page: section.title_page,
+            header_references: std::mem::take(&mut section.header_references),
+            footer_references: std::mem::take(&mut section.footer_references),
+            ..self.section.clone()
+        };
+    }
+
+    /// Run a deletion of the char `range`, keeping the final paragraph's
+    /// formatting when the deletion joins it to the paragraph before
+    ///
+    /// A join keeps the first paragraph's formatting, so that paragraph takes
+    /// the final one's beforehand; undoing the deletion, which is one undo
+    /// step with it, gives both paragraphs back their own.
+    pub fn keep_final_paragraph<R>(tree: &mut PieceTree, range: Range<usize>, delete: impl FnOnce(&mut PieceTree) -> R) -> R {
+        let last = tree.paragraph_count() - 1;
+        let last_start = tree.get_offset_at_line(last + 1);
+        if last == 0 || range.start >= last_start || range.end < last_start {
+            return delete(tree);
+        }
+
+        let first = tree.paragraph_index_at(range.start);
+        let first_start = tree.get_offset_at_line(first + 1);
+        let first_attributes = tree.paragraph_attributes(first).cloned();
+        let final_attributes = tree.paragraph_attributes(last).cloned();
+        tree.transaction(|tree| {
+            tree.set_paragraph_attributes(first_start..first_start, final_attributes.as_ref());
+            let result = delete(tree);
+            // A tracked deletion may only mark the paragraph break, leaving the paragraphs apart
+            if tree.paragraph_index_at(range.start) + 1 < tree.paragraph_count() {
+                tree.set_paragraph_attributes(first_start..first_start, first_attributes.as_ref());
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_layout::Alignment;
+    use crate::ooxml::{HeaderFooterReference, Paragraph};
+    use crate::piece_tree::ParagraphAttributes;
+
+    fn final_section() -> Section {
+        Section {
+            page_width: Some(15840),
+            page_height: Some(12240),
+            landscape: true,
+            columns: 2,
+            other_children: vec![r#"<w:pgNumType w:start="5"/>"#.to_string()],
+            header_references: vec![HeaderFooterReference {
+                kind: "default".to_string(),
+                id: "rId7".to_string(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_model_end_survives_regenerated_sections() {
+        let mut model = DocumentModel::from_piece_tree(&PieceTree::new("one\ntwo".to_string()));
+        let bold = RunProperties {
+            bold: Some(true),
+            ..Default::default()
+        };
+        if let Some(Block::Paragraph(last)) = model.body.last_mut() {
+            last.mark_properties = Some(bold.clone());
+        }
+        model.sections = vec![final_section()];
+        let end = DocumentEnd::from_model(&model);
+        assert_eq!(end.mark(), Some(&bold));
+
+        // The headers and footers regenerate the sections with only their references
+        let mut regenerated = DocumentModel::from_piece_tree(&PieceTree::new("one\ntwo".to_string()));
+        regenerated.sections = vec![Section {
+            title_page: true,
+            header_references: vec![HeaderFooterReference {
+                kind: "default".to_string(),
+                id: "rIdHeader1".to_string(),
+            }],
+            paragraph_count: 2,
+            ..Default::default()
+        }];
+        end.add_to_model(&mut regenerated);
+        let section = &regenerated.sections[0];
+        assert_eq!((section.page_width, section.page_height, section.columns), (Some(15840), Some(12240), 2));
+        assert_eq!(section.other_children, final_section().other_children);
+        assert_eq!(section.header_references[0].id, "rIdHeader1");
+        assert!(section.title_page);
+        assert_eq!(section.paragraph_count, 2);
+        let marks: Vec<_> = regenerated.paragraphs().map(|p: &Paragraph| p.mark_properties.clone()).collect();
+        assert_eq!(marks, vec![None, Some(bold)]);
+    }
+
+    #[test]
+    fn test_joining_into_the_final_paragraph_keeps_its_formatting() {
+        let mut tree = PieceTree::new("one\ntwo\nthree".to_string());
+        let centered = ParagraphAttributes {
+            alignment: Some(Alignment::Center),
+            ..Default::default()
+        };
+        tree.set_paragraph_attributes(9..9, Some(&centered));
+
+        // Deleting before the final paragraph leaves it alone
+        DocumentEnd::keep_final_paragraph(&mut tree, 0..4, |tree| tree.delete(0, 4));
+        assert_eq!(tree.paragraph_attributes_in(0..9), vec![None, Some(centered.clone())]);
+
+        // Joining it to the paragraph before keeps its formatting, as one undo step
+        DocumentEnd::keep_final_paragraph(&mut tree, 2..5, |tree| tree.delete(2, 3));
+        assert_eq!(tree.get_text(), "twhree");
+        assert_eq!(tree.paragraph_attributes_in(0..6), vec![Some(centered.clone())]);
+        assert!(tree.undo());
+        assert_eq!(tree.get_text(), "two\nthree");
+        assert_eq!(tree.paragraph_attributes_in(0..9), vec![None, Some(centered.clone())]);
+
+        // A tracked deletion that only marks the break leaves both as they were
+        DocumentEnd::keep_final_paragraph(&mut tree, 2..5, |_| ());
+        assert_eq!(tree.paragraph_attributes_in(0..9), vec![None, Some(centered.clone())]);
+
+        // Deleting everything leaves the final paragraph's formatting
+        DocumentEnd::keep_final_paragraph(&mut tree, 0..9, |tree| tree.delete(0, 9));
+        assert_eq!(tree.get_text(), "");
+        assert_eq!(tree.paragraph_attributes(0), Some(&centered));
+    }
+
+    #[test]
+    fn test_export_gets_the_final_section_and_mark() {
+        let end = DocumentEnd {
+            mark: Some(RunProperties {
+                italic: Some(true),
+                ..Default::default()
+            }),
+            section: final_section(),
+        };
+        let mut content = ExportContent::default();
+        end.add_to_export(&mut content);
+        // The references are the headers and footers' to give
+        let expected = Section {
+            header_references: Vec::new(),
+            ..final_section()
+        };
+        assert_eq!(content.sections, vec![expected]);
+        assert_eq!(content.final_mark, end.mark().cloned());
+    }
+}
@@ -0,0 +1,502 @@
+//! # Document Model Module
+//!
+//! Versioned, full-fidelity document model for external tools.
+//!
+//! Services that want to read or produce Velum documents without implementing
+//! OOXML exchange a [`DocumentModel`] as JSON. The top level looks like:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "metadata": { "title": "Report", "author": "Ann", "created": null, "modified": null },
+//!   "body": [
+//!     { "type": "paragraph", "text": "Hello", "properties": { ... },
+//!       "runs": [ { "text": "Hello", "properties": { "bold": true, "font_size": 12, ... } } ] },
+//!     { "type": "table", "rows": [ ... ], "properties": { ... } }
+//!   ],
+//!   "styles": { ... }, "theme": { ... }, "numbering": [ ... ],
+//!   "headers": [ ... ], "footers": [ ... ], "sections": [ ... ], "footnotes": [ ... ], "endnotes": [ ... ],
+//!   "comments": [ { "id": "0", "author": "Ann", "paragraphs": [ ... ], "parent_id": null, "done": false } ],
+//!   "images": [ { "id": "rId5", "path": "media/image1.png", ... } ],
+//!   "content_controls": [ { "tag": "name", "sdt_type": "text", "first_paragraph": 2, "paragraph_count": 1, ... } ]
+//! }
+//! ```
+//!
+//! Paragraphs, runs, tables, list definitions, notes and headers use the same
+//! shapes as the OOXML types in [`crate::ooxml`]. Comments are anchored by the
+//! `comment_marks` of the paragraphs, bookmarks are pairs of their
+//! `bookmark_marks`, and each paragraph lists its `hyperlinks` with their URLs
+//! resolved. Run font sizes are in points
+//! and colors are hex RGB. Images are referenced by their package path; the
+//! model never carries image bytes. [`DocumentModel::blocks`] walks it all in
+//! document order.
+//!
+//! `version` is bumped on any incompatible change. Readers reject documents
+//! with a newer version than they understand; fields added within a version
+//! are optional, so older documents keep loading.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::line_layout::Alignment;
+use crate::ooxml::{
+    Comment, ContentControl, DocumentImage, Endnote, Footer, Footnote, Header, Numbering, OoxmlError, OpcPackage, Paragraph,
+    ParagraphProperties, ProtectionMode, Run, RunProperties, Section, Style, Table, Theme, WordDocument,
+};
+use crate::piece_tree::{BufferId, ParagraphAttributes, Piece, PieceTree, TextAttributes};
+
+/// Version of the model this crate reads and writes
+pub const DOCUMENT_MODEL_VERSION: u32 = 1;
+
+/// Errors reading a serialized document model
+#[derive(Debug, thiserror::Error)]
+pub enum DocumentModelError {
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Unsupported document model version {0} (newest supported is {DOCUMENT_MODEL_VERSION})")]
+    UnsupportedVersion(u32),
+}
+
+/// Document properties, as in docProps/core.xml
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// W3CDTF timestamp, e.g. "2024-01-31T09:30:00Z"
+    pub created: Option<String>,
+    /// W3CDTF timestamp
+    pub modified: Option<String>,
+}
+
+/// Top-level content in document order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Paragraph(Box<Paragraph>),
+    Table(Box<Table>),
+}
+
+impl From<Paragraph> for Block {
+    fn from(paragraph: Paragraph) -> Self {
+        Block::Paragraph(Box::new(paragraph))
+    }
+}
+
+/// The whole document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentModel {
+    pub version: u32,
+    #[serde(default)]
+    pub metadata: ModelMetadata,
+    pub body: Vec<Block>,
+    /// Style definitions indexed by style ID
+    #[serde(default)]
+    pub styles: HashMap<String, Style>,
+    /// Theme colors and font scheme
+    #[serde(default)]
+    pub theme: Option<Theme>,
+    /// List definitions
+    #[serde(default)]
+    pub numbering: Vec<Numbering>,
+    #[serde(default)]
+    pub headers: Vec<Header>,
+    #[serde(default)]
+    pub footers: Vec<Footer>,
+    /// Section properties with the header and footer references of each section
+    #[serde(default)]
+    pub sections: Vec<Section>,
+    #[serde(default)]
+    pub footnotes: Vec<Footnote>,
+    #[serde(default)]
+    pub endnotes: Vec<Endnote>,
+    /// Images by reference
+    #[serde(default)]
+    pub images: Vec<DocumentImage>,
+    /// Comment bodies and threads
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+    /// Block-level content controls, each wrapping a run of body paragraphs
+    #[serde(default)]
+    pub content_controls: Vec<ContentControl>,
+    /// Editing restriction the document enforces
+    #[serde(default)]
+    pub protection: ProtectionMode,
+}
+
+impl Default for DocumentModel {
+    fn default() -> Self {
+        DocumentModel {
+            version: DOCUMENT_MODEL_VERSION,
+            metadata: ModelMetadata::default(),
+            body: Vec::new(),
+            styles: HashMap::new(),
+            theme: None,
+            numbering: Vec::new(),
+            headers: Vec::new(),
+            footers: Vec::new(),
+            sections: Vec::new(),
+            footnotes: Vec::new(),
+            endnotes: Vec::new(),
+            images: Vec::new(),
+            comments: Vec::new(),
+            content_controls: Vec::new(),
+            protection: ProtectionMode::None,
+        }
+    }
+}
+
+impl DocumentModel {
+    /// Build the model of a .docx file
+    pub fn from_docx(file_data: &[u8]) -> Result<Self, OoxmlError> {
+        let package = OpcPackage::new(file_data)?;
+        Ok(Self::from_word_document(&WordDocument::parse(&package)?))
+    }
+
+    /// Build the model of a parsed Word document
+    pub fn from_word_document(document: &WordDocument) -> Self {
+        let metadata = document
+            .core_properties
+            .as_ref()
+            .map(|props| ModelMetadata {
+                title: props.title.clone(),
+                author: props.creator.clone(),
+                created: props.created.clone(),
+                modified: props.modified.clone(),
+            })
+            .unwrap_or_default();
+
+        // Each table goes before the paragraph it preceded
+        let mut tables: Vec<&Table> = document.tables.iter().collect();
+        tables.sort_by_key(|table| table.paragraph_index);
+        let mut tables = tables.into_iter().peekable();
+        let mut body = Vec::with_capacity(document.paragraphs.len() + document.tables.len());
+        for (index, paragraph) in document.paragraphs.iter().enumerate() {
+            while let Some(table) = tables.next_if(|table| table.paragraph_index <= index) {
+                body.push(Block::Table(Box::new(table.clone())));
+            }
+            body.push(Block::from(paragraph.clone()));
+        }
+        body.extend(tables.map(|table| Block::Table(Box::new(table.clone()))));
+
+        DocumentModel {
+            metadata,
+            body,
+            styles: document.styles.clone(),
+            theme: document.theme.clone(),
+            numbering: document.numbering.clone(),
+            headers: document.headers.clone(),
+            footers: document.footers.clone(),
+            sections: document.sections.clone(),
+            footnotes: document.footnotes.clone(),
+            endnotes: document.endnotes.clone(),
+            images: document.images.clone(),
+            comments: document.comments.clone(),
+            content_controls: document.content_controls.clone(),
+            protection: document.protection,
+            ..Default::default()
+        }
+    }
+
+    /// Build the model of editor content; every line becomes a paragraph, empty ones included
+    pub fn from_piece_tree(tree: &PieceTree) -> Self {
+        let mut paragraphs = vec![Paragraph::default()];
+        for piece in tree.pieces.iter() {
+            let text = tree
+                .buffers
+                .get(PieceTree::buffer_idx(&piece.buffer_id))
+                .and_then(|b| b.get(piece.start..piece.end()))
+                .unwrap_or("");
+            let properties = piece.attributes.as_ref().map(run_properties).unwrap_or_default();
+
+            for (n, part) in text.split('\n').enumerate() {
+                if n > 0 {
+                    paragraphs.push(Paragraph::default());
+                }
+                if part.is_empty() {
+                    continue;
+                }
+                let paragraph = paragraphs.last_mut().expect("paragraphs is never empty");
+                paragraph.text.push_str(part);
+                paragraph.runs.push(Run {
+                    text: part.to_string(),
+                    properties: properties.clone(),
+                });
+            }
+        }
+
+        for (index, paragraph) in paragraphs.iter_mut().enumerate() {
+            if let Some(attributes) = tree.paragraph_attributes(index) {
+                paragraph.properties = paragraph_properties(attributes);
+            }
+        }
+
+        DocumentModel {
+            body: paragraphs.into_iter().map(Block::from).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Editor content of the body paragraphs, with run formatting
+    ///
+    /// The piece tree holds running text only, so tables are left out.
+    pub fn to_piece_tree(&self) -> PieceTree {
+        let mut text = String::new();
+        let mut pieces = Vec::new();
+
+        for (i, paragraph) in self.paragraphs().enumerate() {
+            if i > 0 {
+                pieces.push(Piece::new(text.len(), 1, BufferId::ORIGINAL, 1));
+                text.push('\n');
+            }
+
+            let runs: Vec<(&str, Option<TextAttributes>)> = if paragraph.runs.is_empty() {
+                vec![(paragraph.text.as_str(), None)]
+            } else {
+                paragraph
+                    .runs
+                    .iter()
+                    .map(|run| (run.text.as_str(), text_attributes(&run.properties)))
+                    .collect()
+            };
+            for (run_text, attributes) in runs.into_iter().filter(|(t, _)| !t.is_empty()) {
+                // Line breaks inside a run would split the paragraph
+                let run_text = run_text.replace('\n', " ");
+                pieces.push(Piece::new_with_attrs(
+                    text.len(),
+                    run_text.len(),
+                    BufferId::ORIGINAL,
+                    run_text.chars().count(),
+                    attributes,
+                ));
+                text.push_str(&run_text);
+            }
+        }
+
+        let mut tree = PieceTree::from_loaded_data(pieces, vec![text]);
+        tree.load_paragraph_attributes(self.paragraphs().map(|p| paragraph_attributes(&p.properties)).collect());
+        tree
+    }
+
+    /// Body paragraphs in order, each with the char offset it starts at in
+    /// the text `to_piece_tree` builds
+    pub fn located_paragraphs(&self) -> impl Iterator<Item = (usize, &Paragraph)> {
+        located(self.paragraphs())
+    }
+
+    /// Body paragraphs in order
+    pub fn paragraphs(&self) -> impl Iterator<Item = &Paragraph> {
+        self.body.iter().filter_map(|block| match block {
+            Block::Paragraph(paragraph) => Some(paragraph.as_ref()),
+            Block::Table(_) => None,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, DocumentModelError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Read a model, rejecting versions newer than this crate understands
+    pub fn from_json(json: &str) -> Result<Self, DocumentModelError> {
+        let model: DocumentModel = serde_json::from_str(json)?;
+        if model.version > DOCUMENT_MODEL_VERSION {
+            return Err(DocumentModelError::UnsupportedVersion(model.version));
+        }
+        Ok(model)
+    }
+}
+
+pub(crate) fn run_properties(attributes: &TextAttributes) -> RunProperties {
+    RunProperties {
+        bold: attributes.bold,
+        italic: attributes.italic,
+        underline: attributes.underline.map(|u| if u { "single" } else { "none" }.to_string()),
+        font_size: attributes.font_size.map(i32::from),
+        font_name: attributes.font_family.clone(),
+        color: attributes.foreground.as_deref().map(|c| c.trim_start_matches('#').to_string()),
+        background_color: attributes.background.as_deref().map(|c| c.trim_start_matches('#').to_string()),
+    }
+}
+
+/// Each of `paragraphs` with the char offset it starts at, the paragraphs
+/// joined with "\n"
+pub fn located<'a>(paragraphs: impl IntoIterator<Item = &'a Paragraph>) -> impl Iterator<Item = (usize, &'a Paragraph)> {
+    paragraphs.into_iter().scan(0, |start, paragraph| {
+        let located = (*start, paragraph);
+        *start += paragraph.text.chars().count() + 1;
+        Some(located)
+    })
+}
+
+/// Editor attributes of a run; None when the run is unformatted
+pub(crate) fn text_attributes(properties: &RunProperties) -> Option<TextAttributes> {
+    let attributes = TextAttributes {
+        bold: properties.bold,
+        italic: properties.italic,
+        underline: properties.underline.as_deref().map(|u| u != "none"),
+        font_size: properties.font_size.and_then(|s| u16::try_from(s).ok()).filter(|s| *s > 0),
+        font_family: properties.font_name.clone(),
+        foreground: properties.color.as_deref().map(|c| format!("#{}", c.trim_start_matches('#'))),
+        background: properties.background_color.as_deref().map(|c| format!("#{}", c.trim_start_matches('#'))),
+    };
+    (attributes != TextAttributes::default()).then_some(attributes)
+}
+
+pub(crate) fn paragraph_properties(attributes: &ParagraphAttributes) -> ParagraphProperties {
+    ParagraphProperties {
+        alignment: attributes.alignment.map(|alignment| {
+            match alignment {
+                Alignment::Left => "left",
+                Alignment::Right => "right",
+                Alignment::Center => "center",
+                Alignment::Justify => "both",
+            }
+            .to_string()
+        }),
+        indent_left: attributes.indent_left,
+        indent_right: attributes.indent_right,
+        indent_first_line: attributes.indent_first_line,
+        spacing_before: attributes.space_before,
+        spacing_after: attributes.space_after,
+        // Auto line spacing is in 240ths of a line
+        spacing_line: attributes.line_spacing.map(|spacing| (spacing * 240.0).round() as i32),
+        style_id: attributes.style_id.clone(),
+        num_id: attributes.num_id.clone(),
+        list_level: attributes.list_level,
+        frame: None,
+        border_bottom: attributes.border_bottom.clone(),
+        keep_next: attributes.keep_with_next,
+        keep_lines: attributes.keep_lines_together,
+        page_break_before: attributes.page_break_before,
+        widow_control: attributes.widow_control,
+    }
+}
+
+/// Editor attributes of a paragraph; None when the paragraph is unformatted
+pub(crate) fn paragraph_attributes(properties: &ParagraphProperties) -> Option<ParagraphAttributes> {
+    let attributes = ParagraphAttributes {
+        alignment: properties.alignment.as_deref().and_then(|alignment| match alignment {
+            "left" | "start" => Some(Alignment::Left),
+            "right" | "end" => Some(Alignment::Right),
+            "center" => Some(Alignment::Center),
+            "both" | "distribute" => Some(Alignment::Justify),
+            _ => None,
+        }),
+        indent_left: properties.indent_left,
+        indent_right: properties.indent_right,
+        indent_first_line: properties.indent_first_line,
+        space_before: properties.spacing_before,
+        space_after: properties.spacing_after,
+        line_spacing: properties.spacing_line.map(|line| line as f32 / 240.0),
+        style_id: properties.style_id.clone(),
+        num_id: properties.num_id.clone(),
+        list_level: properties.list_level,
+        border_bottom: properties.border_bottom.clone(),
+        break_strategy: None,
+        keep_with_next: properties.keep_next,
+        keep_lines_together: properties.keep_lines,
+        page_break_before: properties.page_break_before,
+        widow_control: properties.widow_control,
+    };
+    (attributes != ParagraphAttributes::default()).then_some(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::{TableCell, TableRow};
+
+    fn formatted_tree() -> PieceTree {
+        let mut tree = PieceTree::new("Title\n\nBody text".to_string());
+        tree.insert_with_attrs(
+            0,
+            "Big ".to_string(),
+            Some(TextAttributes {
+                bold: Some(true),
+                font_size: Some(18),
+                foreground: Some("#FF0000".to_string()),
+                ..Default::default()
+            }),
+        );
+        tree.apply_paragraph_attributes(0..0, &title_paragraph());
+        tree
+    }
+
+    fn title_paragraph() -> ParagraphAttributes {
+        ParagraphAttributes {
+            alignment: Some(Alignment::Center),
+            space_after: Some(240),
+            line_spacing: Some(1.5),
+            style_id: Some("Title".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_piece_tree_round_trip_through_json() {
+        let tree = formatted_tree();
+        let json = DocumentModel::from_piece_tree(&tree).to_json().unwrap();
+        let restored = DocumentModel::from_json(&json).unwrap().to_piece_tree();
+
+        assert_eq!(restored.get_text(), "Big Title\n\nBody text");
+        let first = restored.pieces.first().unwrap();
+        assert_eq!(first.attributes, tree.pieces.first().unwrap().attributes);
+        assert!(restored.pieces.iter().skip(1).all(|p| p.attributes.is_none()));
+        assert_eq!(restored.paragraph_attributes_in(0..20), vec![Some(title_paragraph()), None, None]);
+    }
+
+    #[test]
+    fn test_json_shape() {
+        let model = DocumentModel::from_piece_tree(&formatted_tree());
+        let value: serde_json::Value = serde_json::from_str(&model.to_json().unwrap()).unwrap();
+
+        assert_eq!(value["version"], DOCUMENT_MODEL_VERSION);
+        assert_eq!(value["body"].as_array().unwrap().len(), 3);
+        assert_eq!(value["body"][0]["type"], "paragraph");
+        let run = &value["body"][0]["runs"][0];
+        assert_eq!(run["text"], "Big ");
+        assert_eq!(run["properties"]["font_size"], 18);
+        assert_eq!(run["properties"]["color"], "FF0000");
+        let paragraph = &value["body"][0]["properties"];
+        assert_eq!(paragraph["alignment"], "center");
+        assert_eq!(paragraph["spacing_line"], 360);
+        assert_eq!(paragraph["style_id"], "Title");
+    }
+
+    #[test]
+    fn test_tables_are_kept_in_the_model() {
+        let cell = TableCell {
+            paragraphs: vec![Paragraph {
+                text: "cell".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut model = DocumentModel::from_piece_tree(&PieceTree::new("Before".to_string()));
+        model.body.push(Block::Table(Box::new(Table {
+            rows: vec![TableRow {
+                cells: vec![cell],
+                ..Default::default()
+            }],
+            ..Default::default()
+        })));
+
+        let restored = DocumentModel::from_json(&model.to_json().unwrap()).unwrap();
+        match &restored.body[1] {
+            Block::Table(table) => assert_eq!(table.rows[0].cells[0].paragraphs[0].text, "cell"),
+            other => panic!("expected a table, got {:?}", other),
+        }
+        assert_eq!(restored.to_piece_tree().get_text(), "Before");
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let result = DocumentModel::from_json(r#"{"version": 99, "body": []}"#);
+        assert!(matches!(result, Err(DocumentModelError::UnsupportedVersion(99))));
+
+        // Optional sections may be omitted
+        let model = DocumentModel::from_json(r#"{"version": 1, "body": []}"#).unwrap();
+        assert!(model.images.is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+//! # Document Sync Module
+//!
+//! Keeps the UI's copy of the document model in step with the core by sending
+//! what changed instead of the whole document after every edit.
+//!
+//! Each top-level block (a paragraph with its runs, or a table) gets an ID
+//! that stays the same while the block is edited. [`DocumentSync::update`]
+//! compares the current model with the blocks the UI was last sent and
+//! returns RFC 6902-style operations on `/body`, applied in order, each also
+//! naming the block's ID so the UI can key its widgets by it. A UI that has
+//! missed a version gets a full snapshot instead.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::document_model::{Block, DocumentModel};
+
+/// An operation on the blocks of the body, by RFC 6902 path and block ID
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PatchOp {
+    /// Insert a new block before the block at `path`, or at the end
+    Add { path: String, id: String, value: serde_json::Value },
+    /// Remove the block at `path`
+    Remove { path: String, id: String },
+    /// Replace the content of the block at `path`, which keeps its ID
+    Replace { path: String, id: String, value: serde_json::Value },
+}
+
+/// The changes from one version of the body to the next
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentPatch {
+    /// Version the operations apply to
+    pub base_version: u64,
+    /// Version after them
+    pub version: u64,
+    pub ops: Vec<PatchOp>,
+}
+
+/// A block and its ID
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncedBlock {
+    pub id: String,
+    pub value: serde_json::Value,
+}
+
+/// The whole body at a version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentSnapshot {
+    pub version: u64,
+    pub blocks: Vec<SyncedBlock>,
+}
+
+/// What the UI needs to catch up: a patch, or a snapshot if its version is unknown
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncUpdate {
+    Patch(DocumentPatch),
+    Snapshot(DocumentSnapshot),
+}
+
+/// The blocks last sent to the UI, by ID and content hash
+#[derive(Debug, Clone, Default)]
+pub struct DocumentSync {
+    version: u64,
+    next_id: u64,
+    ids: Vec<String>,
+    hashes: Vec<u64>,
+}
+
+impl DocumentSync {
+    /// A sync at version 0, at which the body is empty
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current version; it goes up with every update that changes the body
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Bring the sync up to `model` and return what a UI at `known_version`
+    /// needs: the patch from its version, or a snapshot if it is at another
+    pub fn update(&mut self, model: &DocumentModel, known_version: u64) -> SyncUpdate {
+        let in_step = known_version == self.version;
+        let patch = self.diff(model);
+        if in_step {
+            SyncUpdate::Patch(patch)
+        } else {
+            SyncUpdate::Snapshot(self.snapshot(model))
+        }
+    }
+
+    /// The whole body of `model` with the IDs of its blocks; call after
+    /// [`update`](Self::update) or [`diff`](Self::diff) with the same model
+    pub fn snapshot(&self, model: &DocumentModel) -> DocumentSnapshot {
+        DocumentSnapshot {
+            version: self.version,
+            blocks: self
+                .ids
+                .iter()
+                .zip(&model.body)
+                .map(|(id, block)| SyncedBlock {
+                    id: id.clone(),
+                    value: block_value(block),
+                })
+                .collect(),
+        }
+    }
+
+    /// The operations turning the last body into the body of `model`
+    ///
+    /// Blocks equal at the start and end of both bodies are matched and keep
+    /// their IDs; in between, blocks are replaced in place, keeping their IDs,
+    /// and the extra ones removed or added.
+    pub fn diff(&mut self, model: &DocumentModel) -> DocumentPatch {
+        let values: Vec<serde_json::Value> = model.body.iter().map(block_value).collect();
+        let hashes: Vec<u64> = values.iter().map(value_hash).collect();
+
+        let prefix = self.hashes.iter().zip(&hashes).take_while(|(old, new)| old == new).count();
+        let max_suffix = self.hashes.len().min(hashes.len()) - prefix;
+        let suffix = self
+            .hashes
+            .iter()
+            .rev()
+            .zip(hashes.iter().rev())
+            .take(max_suffix)
+            .take_while(|(old, new)| old == new)
+            .count();
+        let old_end = self.hashes.len() - suffix;
+        let new_end = hashes.len() - suffix;
+        let replaced = (old_end - prefix).min(new_end - prefix);
+
+        let mut ops = Vec::new();
+        let mut ids: Vec<String> = self.ids[..prefix].to_vec();
+        for (index, value) in values.iter().enumerate().skip(prefix).take(replaced) {
+            let id = self.ids[index].clone();
+            ops.push(PatchOp::Replace {
+                path: block_path(index),
+                id: id.clone(),
+                value: value.clone(),
+            });
+            ids.push(id);
+        }
+        for id in &self.ids[prefix + replaced..old_end] {
+            ops.push(PatchOp::Remove {
+                path: block_path(prefix + replaced),
+                id: id.clone(),
+            });
+        }
+        for (index, value) in values.iter().enumerate().take(new_end).skip(prefix + replaced) {
+            let id = self.new_id();
+            ops.push(PatchOp::Add {
+                path: block_path(index),
+                id: id.clone(),
+                value: value.clone(),
+            });
+            ids.push(id);
+        }
+        ids.extend_from_slice(&self.ids[old_end..]);
+
+        let base_version = self.version;
+        if !ops.is_empty() {
+            self.version += 1;
+        }
+        self.ids = ids;
+        self.hashes = hashes;
+        DocumentPatch {
+            base_version,
+            version: self.version,
+            ops,
+        }
+    }
+
+    fn new_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("b{}", self.next_id)
+    }
+}
+
+fn block_path(index: usize) -> String {
+    format!("/body/{}", index)
+}
+
+fn block_value(block: &Block) -> serde_json::Value {
+    serde_json::to_value(block).unwrap_or(serde_json::Value::Null)
+}
+
+fn value_hash(value: &serde_json::Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece_tree::PieceTree;
+
+    fn model(text: &str) -> DocumentModel {
+        DocumentModel::from_piece_tree(&PieceTree::new(text.to_string()))
+    }
+
+    fn ids(sync: &DocumentSync, text: &str) -> Vec<String> {
+        sync.snapshot(&model(text)).blocks.into_iter().map(|block| block.id).collect()
+    }
+
+    fn summary(patch: &DocumentPatch) -> Vec<(&str, &str, &str)> {
+        patch
+            .ops
+            .iter()
+            .map(|op| match op {
+                PatchOp::Add { path, id, .. } => ("add", path.as_str(), id.as_str()),
+                PatchOp::Remove { path, id } => ("remove", path.as_str(), id.as_str()),
+                PatchOp::Replace { path, id, .. } => ("replace", path.as_str(), id.as_str()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_first_update_adds_every_block() {
+        let mut sync = DocumentSync::new();
+        let patch = sync.diff(&model("One\nTwo"));
+        assert_eq!(summary(&patch), vec![("add", "/body/0", "b1"), ("add", "/body/1", "b2")]);
+        assert_eq!((patch.base_version, patch.version), (0, 1));
+
+        // Nothing changed, nothing to send
+        let patch = sync.diff(&model("One\nTwo"));
+        assert!(patch.ops.is_empty());
+        assert_eq!(patch.version, 1);
+    }
+
+    #[test]
+    fn test_edits_keep_ids() {
+        let mut sync = DocumentSync::new();
+        sync.diff(&model("One\nTwo\nThree"));
+
+        // Typing in a paragraph replaces it alone
+        let patch = sync.diff(&model("One\nTwo!\nThree"));
+        assert_eq!(summary(&patch), vec![("replace", "/body/1", "b2")]);
+        let PatchOp::Replace { value, .. } = &patch.ops[0] else {
+            panic!("expected a replace");
+        };
+        assert_eq!(value["type"], "paragraph");
+        assert_eq!(value["text"], "Two!");
+
+        // Splitting a paragraph replaces it and adds the new one after it
+        let patch = sync.diff(&model("One\nTw\no!\nThree"));
+        assert_eq!(summary(&patch), vec![("replace", "/body/1", "b2"), ("add", "/body/2", "b4")]);
+        assert_eq!(ids(&sync, "One\nTw\no!\nThree"), ["b1", "b2", "b4", "b3"]);
+
+        // Deleting paragraphs removes them at the same path, one after another
+        let patch = sync.diff(&model("Three"));
+        assert_eq!(summary(&patch), vec![("remove", "/body/0", "b1"), ("remove", "/body/0", "b2"), ("remove", "/body/0", "b4")]);
+        assert_eq!(ids(&sync, "Three"), ["b3"]);
+        assert_eq!(patch.version, 4);
+    }
+
+    #[test]
+    fn test_update_sends_snapshot_to_stale_ui() {
+        let mut sync = DocumentSync::new();
+        let SyncUpdate::Patch(patch) = sync.update(&model("A\nB"), 0) else {
+            panic!("a UI in step gets a patch");
+        };
+        assert_eq!(patch.ops.len(), 2);
+
+        let SyncUpdate::Snapshot(snapshot) = sync.update(&model("A\nB\nC"), 0) else {
+            panic!("a UI behind gets a snapshot");
+        };
+        assert_eq!(snapshot.version, 2);
+        let blocks: Vec<_> = snapshot.blocks.iter().map(|block| (block.id.as_str(), block.value["text"].as_str())).collect();
+        assert_eq!(blocks, [("b1", Some("A")), ("b2", Some("B")), ("b3", Some("C"))]);
+
+        let json = serde_json::to_string(&sync.update(&model("A\nC"), 2)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"patch","base_version":2,"version":3,"ops":[{"op":"remove","path":"/body/1","id":"b2"}]}"#
+        );
+    }
+}
@@ -7,6 +7,8 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::piece_tree::{MultiSelection, PieceTree};
+
 /// Document position representing a location in the document
 /// Contains both character offset and visual position for mapping
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -334,6 +336,18 @@ impl DragSelectionState {
         }
     }
 
+    /// The selections a column or block drag covers in `tree`: one per line
+    /// between the anchor and current positions, spanning their columns
+    ///
+    /// Lines are paragraphs of the text. None unless selecting a column or block.
+    pub fn block_selection(&self, tree: &PieceTree) -> Option<MultiSelection> {
+        let block = self.is_column_selection || matches!(self.selection_mode, SelectionMode::Column | SelectionMode::Block);
+        block.then(|| {
+            let (anchor, active) = (self.anchor_position, self.current_position);
+            tree.column_selection((anchor.line, anchor.column), (active.line, active.column))
+        })
+    }
+
     /// Updates the drag phase and scroll direction
     fn update_drag_phase(&mut self) {
         self.drag_phase.phase = DragPhaseType::Dragging;
@@ -487,8 +501,6 @@ pub struct DragSelectionConfig {
     pub scroll_speed: f32,
     /// Auto-scroll delay in ms
     pub auto_scroll_delay_ms: u64,
-    /// Word boundaries for word selection
-    pub word_separators: &'static str,
     /// Enable column selection with Alt key
     pub alt_column_selection: bool,
     /// Enable line selection on margin drag
@@ -505,7 +517,6 @@ impl Default for DragSelectionConfig {
             drag_threshold: 5.0,
             scroll_speed: 1.0,
             auto_scroll_delay_ms: 100,
-            word_separators: " \t\n\r.,!?;:\"'()[]{}<>/\\|@#$%^&*-_+=`~",
             alt_column_selection: true,
             margin_line_selection: true,
             triple_click_line_selection: true,
@@ -530,63 +541,45 @@ pub enum VisualFeedbackStyle {
 }
 
 /// Handles word boundary detection for word selection
+///
+/// Words are UAX #29 word segments containing a letter or digit, so
+/// apostrophes and decimal points stay inside words and each CJK ideograph
+/// is a word. Offsets are char offsets.
 pub mod word_boundary {
+    use crate::segmentation;
 
-    /// Finds the word boundary at the given offset in text
-    pub fn find_word_start(text: &str, offset: usize) -> usize {
-        if text.is_empty() || offset == 0 {
-            return 0;
-        }
-
-        let chars: Vec<char> = text.chars().collect();
-        let offset = offset.min(chars.len());
-
-        // Look for word start (non-separator after separator, or start of text)
-        let separators = " \t\n\r.,!?;:\"'()[]{}<>/\\|@#$%^&*-_+=`~";
+    fn byte_offset(text: &str, offset: usize) -> usize {
+        text.char_indices().nth(offset).map_or(text.len(), |(i, _)| i)
+    }
 
-        // Find the start of the current/previous word
-        let mut i = offset.saturating_sub(1);
-        while i > 0 {
-            if !separators.contains(chars[i]) && separators.contains(chars[i - 1]) {
-                return i;
-            }
-            i = i.saturating_sub(1);
-        }
+    fn char_offset(text: &str, offset: usize) -> usize {
+        text[..offset].chars().count()
+    }
 
-        // Either at start or first char is non-separator
-        if chars.get(0).map(|c| !separators.contains(*c)).unwrap_or(false) {
-            0
+    /// Finds the start of the word at the given offset, or of the next word
+    /// when the offset is between words; a word ending at the offset counts
+    pub fn find_word_start(text: &str, offset: usize) -> usize {
+        let byte = byte_offset(text, offset);
+        let word = segmentation::word_selection_at(text, byte);
+        let start = if segmentation::is_word(&text[word.clone()]) {
+            word.start
         } else {
-            // Find first non-separator
-            chars.iter()
-                .position(|c| !separators.contains(*c))
-                .unwrap_or(0)
-        }
+            segmentation::next_word_start(text, byte)
+        };
+        char_offset(text, start)
     }
 
-    /// Finds the word boundary at the given offset in text
+    /// Finds the end of the word at the given offset, or of the next word
+    /// when the offset is between words; a word ending at the offset counts
     pub fn find_word_end(text: &str, offset: usize) -> usize {
-        let chars: Vec<char> = text.chars().collect();
-        if chars.is_empty() {
-            return 0;
-        }
-
-        let separators = " \t\n\r.,!?;:\"'()[]{}<>/\\|@#$%^&*-_+=`~";
-
-        // Start from offset and find end of word
-        let mut i = offset.min(chars.len().saturating_sub(1));
-
-        // Skip separators first
-        while i < chars.len() && separators.contains(chars[i]) {
-            i += 1;
-        }
-
-        // Find end of word
-        while i < chars.len() && !separators.contains(chars[i]) {
-            i += 1;
-        }
-
-        i
+        let byte = byte_offset(text, offset);
+        let word = segmentation::word_selection_at(text, byte);
+        let end = if segmentation::is_word(&text[word.clone()]) {
+            word.end
+        } else {
+            segmentation::next_word_end(text, byte)
+        };
+        char_offset(text, end)
     }
 
     /// Gets the word at the given offset
@@ -876,6 +869,23 @@ mod tests {
         assert!(state.has_selection());
     }
 
+    #[test]
+    fn test_block_selection_materializes_per_line() {
+        let tree = PieceTree::new("alpha\nbe\ngamma".to_string());
+        let mut state = DragSelectionState::new();
+        state.start_drag(DocumentPosition::new(1, 0, 1), SelectionMode::Character, false, false, DragTarget::Selection);
+        assert_eq!(state.block_selection(&tree), None);
+
+        // Dragging up from column 4 of the last line to column 1 of the first
+        state.start_drag(DocumentPosition::new(13, 2, 4), SelectionMode::Block, false, true, DragTarget::Selection);
+        state.update_position(DocumentPosition::new(1, 0, 1));
+        let block = state.block_selection(&tree).unwrap();
+        let ranges: Vec<(usize, usize)> = block.selections().iter().map(|s| (s.anchor, s.active)).collect();
+        // The short middle line is clipped to its end
+        assert_eq!(ranges, [(4, 1), (8, 7), (13, 10)]);
+        assert_eq!(block.primary().active, 1);
+    }
+
     #[test]
     fn test_drag_selection_reset() {
         let mut state = DragSelectionState::new();
@@ -1068,7 +1078,7 @@ mod tests {
         assert_eq!(word_boundary::find_word_start(text, 0), 0);
         assert_eq!(word_boundary::find_word_start(text, 5), 0);
         assert_eq!(word_boundary::find_word_start(text, 6), 6);
-        assert_eq!(word_boundary::find_word_start(text, 11), 11);
+        assert_eq!(word_boundary::find_word_start(text, 11), 6);
         assert_eq!(word_boundary::find_word_start(text, 13), 13);
     }
 
@@ -1096,6 +1106,16 @@ mod tests {
         assert_eq!(word, Some((13, 17)));
     }
 
+    #[test]
+    fn test_word_boundary_unicode() {
+        let text = "Don't café 3.14 日本語";
+
+        assert_eq!(word_boundary::get_word_at(text, 2), Some((0, 5)));
+        assert_eq!(word_boundary::get_word_at(text, 8), Some((6, 10)));
+        assert_eq!(word_boundary::get_word_at(text, 12), Some((11, 15)));
+        assert_eq!(word_boundary::get_word_at(text, 17), Some((17, 18)));
+    }
+
     #[test]
     fn test_word_boundary_empty_text() {
         let text = "";
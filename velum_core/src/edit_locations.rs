@@ -0,0 +1,136 @@
+//! # Edit Locations Module
+//!
+//! Recent edit positions for "go back" navigation (Word's Shift+F5).
+//!
+//! Each reported edit remembers where it ended. An edit near a remembered
+//! location replaces it instead of adding another, so typing a sentence leaves
+//! one location rather than one per keystroke. Remembered positions move with
+//! later edits the same way the text does; a location inside deleted text
+//! collapses to the start of the deletion.
+
+use std::collections::VecDeque;
+
+use crate::comments::move_anchor;
+
+/// Most locations remembered; the oldest is dropped first
+pub const MAX_EDIT_LOCATIONS: usize = 16;
+/// Edits within this many chars of a remembered location replace it
+pub const EDIT_PROXIMITY: usize = 32;
+
+/// Ring buffer of recent edit positions with a navigation cursor
+#[derive(Debug, Clone, Default)]
+pub struct EditLocations {
+    /// Char offsets, oldest first
+    locations: VecDeque<usize>,
+    /// Index of the location navigation last moved to; None after an edit
+    cursor: Option<usize>,
+}
+
+impl EditLocations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an edit, moving the older locations past it as [`move_anchor`] does
+    pub fn record_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        for location in self.locations.iter_mut() {
+            (*location, _) = move_anchor(*location, 0, offset, removed, inserted);
+        }
+
+        let position = offset + inserted;
+        self.locations.retain(|location| location.abs_diff(position) > EDIT_PROXIMITY);
+        self.locations.push_back(position);
+        if self.locations.len() > MAX_EDIT_LOCATIONS {
+            self.locations.pop_front();
+        }
+        self.cursor = None;
+    }
+
+    /// Start from a single known location, e.g. the one stored with a reopened document
+    pub fn restore(&mut self, position: usize) {
+        self.locations = VecDeque::from([position]);
+        self.cursor = None;
+    }
+
+    /// Step to the next older location; the first step goes to the newest
+    ///
+    /// Returns None, without moving, when there is no older location.
+    pub fn back(&mut self) -> Option<usize> {
+        let index = match self.cursor {
+            None => self.locations.len().checked_sub(1)?,
+            Some(index) => index.checked_sub(1)?,
+        };
+        self.cursor = Some(index);
+        Some(self.locations[index])
+    }
+
+    /// Step back toward newer locations after `back`
+    pub fn forward(&mut self) -> Option<usize> {
+        let index = self.cursor? + 1;
+        let location = *self.locations.get(index)?;
+        self.cursor = Some(index);
+        Some(location)
+    }
+
+    /// Where the most recent edit ended
+    pub fn last(&self) -> Option<usize> {
+        self.locations.back().copied()
+    }
+
+    /// Remembered locations, oldest first
+    pub fn locations(&self) -> Vec<usize> {
+        self.locations.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearby_edits_are_deduplicated() {
+        let mut locations = EditLocations::new();
+        for offset in 0..10 {
+            locations.record_edit(offset, 0, 1);
+        }
+        locations.record_edit(500, 0, 3);
+        locations.record_edit(12, 0, 1);
+        assert_eq!(locations.locations(), [504, 13]);
+    }
+
+    #[test]
+    fn test_locations_follow_later_edits() {
+        let mut locations = EditLocations::new();
+        locations.record_edit(100, 0, 5);
+        locations.record_edit(200, 0, 5);
+        locations.record_edit(0, 0, 10);
+        assert_eq!(locations.locations(), [115, 215, 10]);
+        // Deleting around the first location collapses it into this edit
+        locations.record_edit(110, 10, 0);
+        assert_eq!(locations.locations(), [205, 10, 110]);
+    }
+
+    #[test]
+    fn test_navigation_and_capacity() {
+        let mut locations = EditLocations::new();
+        assert_eq!(locations.back(), None);
+        for i in 0..MAX_EDIT_LOCATIONS + 2 {
+            locations.record_edit(i * 100, 0, 1);
+        }
+        assert_eq!(locations.locations().len(), MAX_EDIT_LOCATIONS);
+
+        assert_eq!(locations.forward(), None);
+        assert_eq!(locations.back(), Some(1701));
+        assert_eq!(locations.back(), Some(1601));
+        assert_eq!(locations.forward(), Some(1701));
+        assert_eq!(locations.forward(), None);
+        for _ in 0..MAX_EDIT_LOCATIONS - 1 {
+            locations.back();
+        }
+        assert_eq!(locations.back(), None);
+        assert_eq!(locations.forward(), Some(301));
+
+        locations.record_edit(0, 0, 1);
+        assert_eq!(locations.back(), Some(1));
+    }
+}
@@ -1,2 +0,0 @@
-// PDF Export Module
-pub mod pdf;
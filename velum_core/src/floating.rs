@@ -0,0 +1,590 @@
+//! # Floating Objects Module
+//!
+//! Images anchored to the text, and where they land on laid-out pages.
+//!
+//! A floating image is anchored at a char of a paragraph and positioned from
+//! what its wp:positionH and wp:positionV name. Word offers two behaviors:
+//! an object that moves with text is positioned from its paragraph or line,
+//! so it follows them as text reflows; an object fixed on the page keeps its
+//! offset from the page or margin edge, and only changes page with its
+//! anchor paragraph. [`AnchorBehavior`] tells the two apart by the vertical
+//! reference, so the file says the same as the layout does.
+//!
+//! Anchors are char offsets into the text and move with edits like comment
+//! anchors. Inline images are kept alongside, so every image of the model
+//! goes back to it in order.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::comments::move_anchor;
+use crate::document_model::DocumentModel;
+use crate::line_layout::ParagraphLayout;
+use crate::ooxml::{DocumentImage, ImageAnchor, ImageTransform};
+use crate::page_layout::{Page, Rect};
+
+/// EMUs in a point
+const EMU_PER_POINT: f32 = 12_700.0;
+
+/// Size of an image whose file gives none: an inch square
+const DEFAULT_SIZE: f32 = 72.0;
+
+/// Floating object errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum FloatingError {
+    #[error("No object {0}")]
+    NotFound(usize),
+
+    #[error("Object {0} is inline; only floating objects are anchored")]
+    Inline(usize),
+
+    #[error("Object {0} is not on any page")]
+    NotLaidOut(usize),
+}
+
+/// How a floating object follows the text it is anchored to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnchorBehavior {
+    /// Positioned from its paragraph or line ("Move object with text")
+    MoveWithText,
+    /// Positioned from the page or its margins ("Fix position on page")
+    FixedOnPage,
+}
+
+impl AnchorBehavior {
+    /// The behavior `anchor` has: moving with text if it is positioned
+    /// vertically from its paragraph or line
+    pub fn of(anchor: &ImageAnchor) -> Self {
+        match anchor.vertical_relative_to.as_str() {
+            "paragraph" | "line" => AnchorBehavior::MoveWithText,
+            _ => AnchorBehavior::FixedOnPage,
+        }
+    }
+}
+
+/// An image and the char offset in the text it is drawn at
+#[derive(Debug, Clone)]
+struct AnchoredImage {
+    image: DocumentImage,
+    offset: usize,
+}
+
+/// Where a floating object is drawn, in points from the top left of its page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlacedObject {
+    /// Index of the object among the document's images
+    pub index: usize,
+    /// Relationship ID of its picture
+    pub id: String,
+    pub page_index: usize,
+    pub rect: Rect,
+    pub behavior: AnchorBehavior,
+}
+
+/// The images of a document, with their anchors in the text
+#[derive(Debug, Clone, Default)]
+pub struct FloatingObjectSet {
+    images: Vec<AnchoredImage>,
+}
+
+impl FloatingObjectSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The images of `model`, anchored in the text of its body paragraphs
+    /// joined with "\n"
+    pub fn from_model(model: &DocumentModel) -> Self {
+        let mut starts = Vec::new();
+        let mut start = 0;
+        for paragraph in model.paragraphs() {
+            starts.push(start);
+            start += paragraph.text.chars().count() + 1;
+        }
+        Self::from_images(&model.images, |index| starts.get(index).copied().unwrap_or(start.saturating_sub(1)))
+    }
+
+    /// `images`, anchored in the text at `paragraph_start` of their paragraph
+    /// index plus their position
+    pub fn from_images(images: &[DocumentImage], paragraph_start: impl Fn(usize) -> usize) -> Self {
+        let images = images
+            .iter()
+            .map(|image| AnchoredImage { image: image.clone(), offset: paragraph_start(image.paragraph_index) + image.position })
+            .collect();
+        FloatingObjectSet { images }
+    }
+
+    /// Hand the images to the model, at the paragraphs their anchors are in now
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let lengths: Vec<usize> = model.paragraphs().map(|paragraph| paragraph.text.chars().count()).collect();
+        model.images = self.located(&lengths);
+    }
+
+    /// The images with the paragraph and position of their anchors in
+    /// paragraphs of `lengths` chars, for export
+    pub fn located(&self, lengths: &[usize]) -> Vec<DocumentImage> {
+        self.images
+            .iter()
+            .map(|anchored| {
+                let mut image = anchored.image.clone();
+                (image.paragraph_index, image.position) = locate(lengths, anchored.offset);
+                image
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// The images, in document order
+    pub fn images(&self) -> impl Iterator<Item = &DocumentImage> {
+        self.images.iter().map(|anchored| &anchored.image)
+    }
+
+    /// Add `image` at char `offset`, after any other object anchored there,
+    /// and return its index
+    pub fn insert(&mut self, offset: usize, image: DocumentImage) -> usize {
+        let index = self.images.iter().take_while(|anchored| anchored.offset <= offset).count();
+        self.images.insert(index, AnchoredImage { image, offset });
+        index
+    }
+
+    /// The image of object `index`
+    pub fn get(&self, index: usize) -> Option<&DocumentImage> {
+        self.images.get(index).map(|anchored| &anchored.image)
+    }
+
+    /// Char offset object `index` is anchored at
+    pub fn offset(&self, index: usize) -> Option<usize> {
+        self.images.get(index).map(|anchored| anchored.offset)
+    }
+
+    /// Objects anchored in chars `range`, with their offsets, so they can be
+    /// taken along when the text is moved
+    pub fn anchored_in(&self, range: Range<usize>) -> Vec<(usize, usize)> {
+        self.images
+            .iter()
+            .enumerate()
+            .filter(|(_, anchored)| range.contains(&anchored.offset))
+            .map(|(index, anchored)| (index, anchored.offset))
+            .collect()
+    }
+
+    /// Anchor object `index` at char `offset`
+    pub fn set_offset(&mut self, index: usize, offset: usize) {
+        if let Some(anchored) = self.images.get_mut(index) {
+            anchored.offset = offset;
+        }
+    }
+
+    /// Move the anchors after replacing `removed` chars at `offset` with
+    /// `inserted` chars; objects in deleted text stay where it was
+    pub fn apply_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        for anchored in self.images.iter_mut() {
+            (anchored.offset, _) = move_anchor(anchored.offset, 0, offset, removed, inserted);
+        }
+    }
+
+    /// Give object `index` the `transform`, brought in range, and return it
+    ///
+    /// Cropping changes the frame with the part of the picture shown, as in
+    /// Word: the picture keeps its scale, so cutting off a quarter of its
+    /// width makes the image a quarter narrower.
+    pub fn set_transform(&mut self, index: usize, transform: &ImageTransform) -> Result<ImageTransform, FloatingError> {
+        let image = &mut self.images.get_mut(index).ok_or(FloatingError::NotFound(index))?.image;
+        let transform = transform.normalized();
+        if let Some((width, height)) = image.extent() {
+            let (old_width, old_height) = image.transform.visible_share();
+            let (new_width, new_height) = transform.visible_share();
+            image.desired_width = Some((width as f32 * new_width / old_width).round() as u32);
+            image.desired_height = Some((height as f32 * new_height / old_height).round() as u32);
+        }
+        image.transform = transform.clone();
+        Ok(transform)
+    }
+
+    /// Where each floating object is drawn on `pages`, laid out from
+    /// `paragraphs` of the text whose paragraphs are `lengths` chars long
+    ///
+    /// An object goes on the page its anchor char is on. One moving with text
+    /// is placed from the top of its paragraph on that page, or of its line;
+    /// one fixed on the page from the page or margin edge. Objects text wraps
+    /// around are then moved off each other, see [`Self::resolve_collisions`].
+    pub fn place(&self, lengths: &[usize], pages: &[Page], paragraphs: &[ParagraphLayout]) -> Vec<PlacedObject> {
+        let mut placed: Vec<PlacedObject> = (0..self.images.len())
+            .filter_map(|index| self.place_object(index, lengths, pages, paragraphs))
+            .collect();
+        self.resolve_collisions(&mut placed, pages);
+        placed
+    }
+
+    /// Move objects text wraps around down below those they overlap, as Word
+    /// does with objects that may not overlap
+    ///
+    /// Objects that allow overlap keep their place and don't push others.
+    /// Objects settle in z-order, then in the order of their anchors, so the
+    /// one behind or anchored first keeps its place and the others stack
+    /// under it. An object that no longer fits above the bottom margin goes to
+    /// the top of the next page, if there is one and it fits there at all.
+    fn resolve_collisions(&self, placed: &mut [PlacedObject], pages: &[Page]) {
+        let anchor_of = |object: &PlacedObject| {
+            let anchored = &self.images[object.index];
+            (anchored.image.anchor.as_ref(), anchored.offset)
+        };
+        let mut order: Vec<usize> = (0..placed.len())
+            .filter(|&i| anchor_of(&placed[i]).0.is_some_and(|anchor| anchor.wraps_text() && !anchor.allow_overlap))
+            .collect();
+        order.sort_by_key(|&i| {
+            let (anchor, offset) = anchor_of(&placed[i]);
+            (anchor.map_or(0, |anchor| anchor.z_order), offset, placed[i].index)
+        });
+
+        let mut settled: Vec<usize> = Vec::with_capacity(order.len());
+        for i in order {
+            loop {
+                let object = &placed[i];
+                let blocker = settled
+                    .iter()
+                    .map(|&j| &placed[j])
+                    .filter(|other| other.page_index == object.page_index && other.rect.intersects(&object.rect))
+                    .map(|other| other.rect.bottom())
+                    .reduce(f32::max);
+                let Some(bottom) = blocker else {
+                    break;
+                };
+                let page = object.page_index;
+                let bounds = pages[page].content_bounds;
+                let next = pages.get(page + 1).filter(|next| object.rect.height <= next.content_bounds.height);
+                match next {
+                    Some(next) if bottom + object.rect.height > bounds.bottom() => {
+                        placed[i].page_index = next.page_index;
+                        placed[i].rect.y = next.content_bounds.y;
+                    }
+                    _ => placed[i].rect.y = bottom,
+                }
+            }
+            settled.push(i);
+        }
+    }
+
+    fn place_object(&self, index: usize, lengths: &[usize], pages: &[Page], paragraphs: &[ParagraphLayout]) -> Option<PlacedObject> {
+        let anchored = self.images.get(index)?;
+        let anchor = anchored.image.anchor.as_ref()?;
+        let (paragraph_index, position) = locate(lengths, anchored.offset);
+        let byte = paragraphs.get(paragraph_index).map_or(0, |paragraph| {
+            paragraph.text.char_indices().nth(position).map_or(paragraph.text.len(), |(byte, _)| byte)
+        });
+        let (page, line) = anchor_line(pages, paragraph_index, byte)?;
+        let bounds = page.content_bounds;
+        let paragraph_top = page
+            .lines
+            .iter()
+            .filter(|other| other.paragraph_index == paragraph_index)
+            .map(|other| other.y)
+            .fold(line.y, f32::min);
+
+        let (width, height) = anchored.image.extent().map_or((DEFAULT_SIZE, DEFAULT_SIZE), |(width, height)| {
+            (width as f32 / EMU_PER_POINT, height as f32 / EMU_PER_POINT)
+        });
+        let x = horizontal_origin(&anchor.horizontal_relative_to, bounds, line.x) + emu_to_points(anchor.horizontal_offset);
+        let y_origin = match anchor.vertical_relative_to.as_str() {
+            "paragraph" => bounds.y + paragraph_top,
+            "line" => bounds.y + line.y,
+            "page" | "topMargin" => 0.0,
+            "bottomMargin" => bounds.bottom(),
+            // "margin" and anything unknown
+            _ => bounds.y,
+        };
+        Some(PlacedObject {
+            index,
+            id: anchored.image.id.clone(),
+            page_index: page.page_index,
+            rect: Rect::new(x, y_origin + emu_to_points(anchor.vertical_offset), width, height),
+            behavior: AnchorBehavior::of(anchor),
+        })
+    }
+
+    /// Give object `index` the `behavior`, keeping it where it is drawn now
+    ///
+    /// Fixing it on the page positions it from the page edges; letting it move
+    /// with text positions it from the top of its paragraph. An object
+    /// positioned from its char across is positioned from the column instead,
+    /// which holds still in both. Returns the anchor the object has now.
+    pub fn set_behavior(
+        &mut self,
+        index: usize,
+        behavior: AnchorBehavior,
+        lengths: &[usize],
+        pages: &[Page],
+        paragraphs: &[ParagraphLayout],
+    ) -> Result<ImageAnchor, FloatingError> {
+        let anchored = self.images.get(index).ok_or(FloatingError::NotFound(index))?;
+        let anchor = anchored.image.anchor.clone().ok_or(FloatingError::Inline(index))?;
+        if AnchorBehavior::of(&anchor) == behavior {
+            return Ok(anchor);
+        }
+        let placed = self.place_object(index, lengths, pages, paragraphs).ok_or(FloatingError::NotLaidOut(index))?;
+        let page = &pages[placed.page_index];
+        let bounds = page.content_bounds;
+        let (paragraph_index, _) = locate(lengths, anchored.offset);
+        let paragraph_top = page
+            .lines
+            .iter()
+            .filter(|line| line.paragraph_index == paragraph_index)
+            .map(|line| line.y)
+            .fold(f32::INFINITY, f32::min);
+
+        let mut anchor = anchor;
+        match behavior {
+            AnchorBehavior::FixedOnPage => {
+                anchor.vertical_relative_to = "page".to_string();
+                anchor.vertical_offset = points_to_emu(placed.rect.y);
+            }
+            AnchorBehavior::MoveWithText => {
+                anchor.vertical_relative_to = "paragraph".to_string();
+                anchor.vertical_offset = points_to_emu(placed.rect.y - bounds.y - paragraph_top);
+            }
+        }
+        if anchor.horizontal_relative_to == "character" {
+            anchor.horizontal_relative_to = "column".to_string();
+            anchor.horizontal_offset = points_to_emu(placed.rect.x - bounds.x);
+        }
+        self.images[index].image.anchor = Some(anchor.clone());
+        Ok(anchor)
+    }
+}
+
+/// Paragraph index and char position in it of char `offset`, in paragraphs
+/// of `lengths` chars joined with "\n"
+fn locate(lengths: &[usize], offset: usize) -> (usize, usize) {
+    let mut start = 0;
+    for (index, &length) in lengths.iter().enumerate() {
+        if offset <= start + length {
+            return (index, offset - start);
+        }
+        start += length + 1;
+    }
+    let last = lengths.len().saturating_sub(1);
+    (last, lengths.last().copied().unwrap_or(0))
+}
+
+/// The page and line the char at `byte` in paragraph `paragraph_index` is
+/// on, or the paragraph's last line past its end
+fn anchor_line(pages: &[Page], paragraph_index: usize, byte: usize) -> Option<(&Page, &crate::page_layout::RenderedLine)> {
+    let mut last = None;
+    for page in pages {
+        for line in page.lines.iter().filter(|line| line.paragraph_index == paragraph_index) {
+            if (line.start..line.end).contains(&byte) {
+                return Some((page, line));
+            }
+            last = Some((page, line));
+        }
+    }
+    last
+}
+
+/// Left edge in page points of what `relative_to` names, for a line starting
+/// `line_x` into the content
+fn horizontal_origin(relative_to: &str, bounds: Rect, line_x: f32) -> f32 {
+    match relative_to {
+        "page" | "leftMargin" => 0.0,
+        "rightMargin" => bounds.right(),
+        "column" | "character" => bounds.x + line_x,
+        // "margin" and anything unknown
+        _ => bounds.x,
+    }
+}
+
+fn emu_to_points(emu: i64) -> f32 {
+    emu as f32 / EMU_PER_POINT
+}
+
+fn points_to_emu(points: f32) -> i64 {
+    (points * EMU_PER_POINT).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_model::Block;
+    use crate::line_layout::LineLayout;
+    use crate::ooxml::Paragraph;
+    use crate::page_layout::PageLayout;
+
+    fn model(texts: &[&str], anchor: ImageAnchor) -> DocumentModel {
+        let mut model = DocumentModel::default();
+        for text in texts {
+            model.body.push(Block::from(Paragraph { text: text.to_string(), ..Default::default() }));
+        }
+        model.images.push(DocumentImage {
+            id: "rId5".to_string(),
+            path: "media/image1.png".to_string(),
+            desired_width: Some(914_400),
+            desired_height: Some(457_200),
+            paragraph_index: 1,
+            position: 2,
+            anchor: Some(anchor),
+            ..Default::default()
+        });
+        model
+    }
+
+    fn layout(text: &str) -> (Vec<usize>, Vec<Page>, Vec<ParagraphLayout>) {
+        let lengths = text.split('\n').map(|paragraph| paragraph.chars().count()).collect();
+        let mut page_layout = PageLayout::new();
+        let width = page_layout.page_config.content_width();
+        let paragraphs = LineLayout::new().layout_document(text, width).paragraphs;
+        let pages = page_layout.layout_pages(&paragraphs);
+        (lengths, pages, paragraphs)
+    }
+
+    #[test]
+    fn test_insert_keeps_document_order() {
+        let mut objects = FloatingObjectSet::from_model(&model(&["First", "Second"], ImageAnchor::default()));
+        let image = |path: &str| DocumentImage { path: path.to_string(), ..Default::default() };
+        assert_eq!(objects.insert(2, image("media/image2.png")), 0);
+        assert_eq!(objects.insert(8, image("media/image3.png")), 2);
+        assert_eq!(objects.insert(20, image("media/image4.png")), 3);
+        let paths: Vec<_> = objects.images().map(|image| image.path.as_str()).collect();
+        assert_eq!(paths, ["media/image2.png", "media/image1.png", "media/image3.png", "media/image4.png"]);
+
+        let located = objects.located(&[5, 6]);
+        assert_eq!((located[0].paragraph_index, located[0].position), (0, 2));
+        assert_eq!((located[2].paragraph_index, located[2].position), (1, 2));
+        assert!(located[2].anchor.is_none());
+    }
+
+    #[test]
+    fn test_anchor_follows_edits() {
+        let mut objects = FloatingObjectSet::from_model(&model(&["First", "Second"], ImageAnchor::default()));
+        assert_eq!(objects.offset(0), Some(8));
+        objects.apply_edit(0, 0, 4);
+        objects.apply_edit(10, 0, 1);
+        assert_eq!(objects.offset(0), Some(13));
+
+        let mut model = model(&["Add First", "Second", "Third"], ImageAnchor::default());
+        model.images.clear();
+        objects.add_to_model(&mut model);
+        assert_eq!((model.images[0].paragraph_index, model.images[0].position), (1, 3));
+
+        // Deleting the anchor's text leaves it where the text was
+        objects.apply_edit(11, 4, 0);
+        assert_eq!(objects.offset(0), Some(11));
+    }
+
+    #[test]
+    fn test_move_with_text_and_fixed_on_page() {
+        let offset = 1_270_000;
+        let moving = ImageAnchor { vertical_offset: offset, ..Default::default() };
+        let fixed = ImageAnchor { vertical_offset: offset, vertical_relative_to: "page".to_string(), ..Default::default() };
+        let before = layout("One\nTwo");
+        let after = layout("One\nMore\nText\nabove\nTwo");
+        for (anchor, moves) in [(moving, true), (fixed, false)] {
+            let mut objects = FloatingObjectSet::from_model(&model(&["One", "Two"], anchor));
+            let first = objects.place(&before.0, &before.1, &before.2)[0].clone();
+            objects.apply_edit(4, 0, 15);
+            let second = objects.place(&after.0, &after.1, &after.2)[0].clone();
+            assert_eq!(second.rect.y > first.rect.y, moves);
+            assert_eq!(first.rect.width, 72.0);
+            assert_eq!(first.rect.height, 36.0);
+            assert_eq!(second.behavior == AnchorBehavior::MoveWithText, moves);
+        }
+        let fixed_y = FloatingObjectSet::from_model(&model(
+            &["One", "Two"],
+            ImageAnchor { vertical_offset: offset, vertical_relative_to: "page".to_string(), ..Default::default() },
+        ))
+        .place(&before.0, &before.1, &before.2)[0]
+            .rect
+            .y;
+        assert!((fixed_y - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_collisions_stack_and_move_to_next_page() {
+        let (lengths, pages, paragraphs) = layout(&"Line\n".repeat(80));
+        assert!(pages.len() > 1);
+        let mut model = model(&["Line"; 81], ImageAnchor::default());
+        let template = model.images[0].clone();
+        let image = |paragraph_index: usize, anchor: ImageAnchor| DocumentImage {
+            paragraph_index,
+            position: 0,
+            anchor: Some(anchor),
+            ..template.clone()
+        };
+        model.images = vec![
+            // In front of the next one, so it stacks under it
+            image(1, ImageAnchor { z_order: 1, ..Default::default() }),
+            image(1, ImageAnchor::default()),
+            image(1, ImageAnchor { z_order: 2, ..Default::default() }),
+            // Behind the text, so it may overlap the others
+            image(1, ImageAnchor { wrap: "none".to_string(), behind_text: true, ..Default::default() }),
+            // Allowed to overlap, so it neither moves nor pushes the others
+            image(1, ImageAnchor { allow_overlap: true, ..Default::default() }),
+        ];
+        let placed = FloatingObjectSet::from_model(&model).place(&lengths, &pages, &paragraphs);
+        let rects: Vec<Rect> = placed.iter().map(|object| object.rect).collect();
+        assert_eq!(rects[1].y, rects[3].y);
+        assert_eq!(rects[1].y, rects[4].y);
+        assert_eq!(rects[0].y, rects[1].bottom());
+        assert_eq!(rects[2].y, rects[0].bottom());
+
+        // Near the bottom of the first page there is no room to stack
+        let last_line = pages[0].lines.last().unwrap().paragraph_index;
+        model.images = vec![image(last_line, ImageAnchor::default()), image(last_line, ImageAnchor::default())];
+        let placed = FloatingObjectSet::from_model(&model).place(&lengths, &pages, &paragraphs);
+        assert_eq!((placed[0].page_index, placed[1].page_index), (0, 1));
+        assert_eq!(placed[1].rect.y, pages[1].content_bounds.y);
+    }
+
+    #[test]
+    fn test_set_behavior_keeps_position() {
+        let (lengths, pages, paragraphs) = layout("One\nTwo");
+        let anchor = ImageAnchor { vertical_offset: 254_000, horizontal_relative_to: "character".to_string(), ..Default::default() };
+        let mut objects = FloatingObjectSet::from_model(&model(&["One", "Two"], anchor));
+        let before = objects.place(&lengths, &pages, &paragraphs)[0].rect;
+
+        let fixed = objects.set_behavior(0, AnchorBehavior::FixedOnPage, &lengths, &pages, &paragraphs).unwrap();
+        assert_eq!(AnchorBehavior::of(&fixed), AnchorBehavior::FixedOnPage);
+        assert_eq!((fixed.vertical_relative_to.as_str(), fixed.horizontal_relative_to.as_str()), ("page", "column"));
+        let placed = objects.place(&lengths, &pages, &paragraphs)[0].rect;
+        assert!((placed.y - before.y).abs() < 0.01 && (placed.x - before.x).abs() < 0.01);
+
+        let moving = objects.set_behavior(0, AnchorBehavior::MoveWithText, &lengths, &pages, &paragraphs).unwrap();
+        assert_eq!((moving.vertical_relative_to.as_str(), moving.vertical_offset), ("paragraph", 254_000));
+
+        let mut inline = FloatingObjectSet::from_model(&model(&["One", "Two"], ImageAnchor::default()));
+        inline.images[0].image.anchor = None;
+        assert_eq!(inline.place(&lengths, &pages, &paragraphs), Vec::new());
+        assert_eq!(
+            inline.set_behavior(0, AnchorBehavior::FixedOnPage, &lengths, &pages, &paragraphs),
+            Err(FloatingError::Inline(0))
+        );
+        assert_eq!(
+            inline.set_behavior(3, AnchorBehavior::FixedOnPage, &lengths, &pages, &paragraphs),
+            Err(FloatingError::NotFound(3))
+        );
+    }
+
+    #[test]
+    fn test_crop_resizes_the_frame() {
+        let mut objects = FloatingObjectSet::from_model(&model(&["One", "Two"], ImageAnchor::default()));
+        let crop = |left, right| ImageTransform {
+            crop: Some(crate::ooxml::SourceRect { left, right, ..Default::default() }),
+            rotation: -90.0,
+            ..Default::default()
+        };
+
+        // A quarter of the width off leaves three quarters of the frame, turned in range
+        let set = objects.set_transform(0, &crop(25.0, 0.0)).unwrap();
+        assert_eq!(set.rotation, 270.0);
+        assert_eq!(objects.get(0).unwrap().extent(), Some((685_800, 457_200)));
+        // Uncropping gives the whole picture back
+        let set = objects.set_transform(0, &crop(0.0, 0.0)).unwrap();
+        assert_eq!(set.crop, None);
+        assert_eq!(objects.get(0).unwrap().extent(), Some((914_400, 457_200)));
+        assert_eq!(objects.set_transform(2, &crop(0.0, 0.0)), Err(FloatingError::NotFound(2)));
+    }
+}
@@ -0,0 +1,183 @@
+//! # Focus Mode Module
+//!
+//! What frontends need to draw focus and typewriter modes the same way: the
+//! sentence and paragraph around the caret, the ranges to dim around the one
+//! in focus, and how far to scroll to keep the caret line in the middle of the
+//! viewport.
+//!
+//! A sentence ends after ".", "!", "?" or an ideographic full stop, with any
+//! closing quotes or brackets, where whitespace or the paragraph end follows;
+//! the spaces after it belong to it, as in Word. Ranges are char offsets into
+//! the whole text, paragraphs joined with "\n"; positions are in the units of
+//! the [`DocumentLayout`] given, from its top.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::line_layout::DocumentLayout;
+
+/// How much text stays undimmed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusUnit {
+    Sentence,
+    #[default]
+    Paragraph,
+}
+
+impl FocusUnit {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sentence" => Some(FocusUnit::Sentence),
+            "paragraph" => Some(FocusUnit::Paragraph),
+            _ => None,
+        }
+    }
+}
+
+/// Focus mode data for one caret position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FocusData {
+    pub sentence: Range<usize>,
+    /// The caret's paragraph, without its break
+    pub paragraph: Range<usize>,
+    /// The sentence or the paragraph, as the unit asks
+    pub focus: Range<usize>,
+    /// The text before and after the focus, leaving out empty ranges
+    pub dimmed: Vec<Range<usize>>,
+    /// Top of the caret line
+    pub caret_line_y: f32,
+    pub caret_line_height: f32,
+    /// Scroll position putting the middle of the caret line in the middle of
+    /// the viewport; negative near the start, where frontends pad above the text
+    pub typewriter_scroll: f32,
+}
+
+/// Focus data for the caret at char `offset` of `text`, laid out as `layout`
+pub fn focus_data(text: &str, layout: &DocumentLayout, offset: usize, unit: FocusUnit, viewport_height: f32) -> FocusData {
+    let chars: Vec<char> = text.chars().collect();
+    let offset = offset.min(chars.len());
+    let paragraph = paragraph_range(&chars, offset);
+    let sentence = sentence_range(&chars, paragraph.clone(), offset);
+    let focus = match unit {
+        FocusUnit::Sentence => sentence.clone(),
+        FocusUnit::Paragraph => paragraph.clone(),
+    };
+    let dimmed = [0..focus.start, focus.end..chars.len()]
+        .into_iter()
+        .filter(|range| !range.is_empty())
+        .collect();
+
+    // The caret's paragraph index and byte offset in it
+    let index = chars[..paragraph.start].iter().filter(|c| **c == '\n').count();
+    let byte = chars[paragraph.start..offset].iter().map(|c| c.len_utf8()).sum::<usize>();
+    let mut y = 0.0;
+    let (mut caret_line_y, mut caret_line_height) = (0.0, layout.line_height);
+    for (paragraph_index, paragraph_layout) in layout.paragraphs.iter().enumerate() {
+        if paragraph_index < index {
+            y += paragraph_layout.total_height;
+            continue;
+        }
+        y += paragraph_layout.space_before();
+        // After a soft break the caret is at the start of the next line
+        let line = paragraph_layout.lines.iter().rposition(|line| line.start <= byte).unwrap_or(0);
+        caret_line_y = y + paragraph_layout.lines[..line].iter().map(|line| line.line_height).sum::<f32>();
+        caret_line_height = paragraph_layout.lines.get(line).map_or(paragraph_layout.actual_line_height, |l| l.line_height);
+        break;
+    }
+
+    FocusData {
+        sentence,
+        paragraph,
+        focus,
+        dimmed,
+        caret_line_y,
+        caret_line_height,
+        typewriter_scroll: caret_line_y + caret_line_height / 2.0 - viewport_height / 2.0,
+    }
+}
+
+/// The paragraph holding char `offset`, without its break
+fn paragraph_range(chars: &[char], offset: usize) -> Range<usize> {
+    let start = chars[..offset].iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1);
+    let end = chars[offset..].iter().position(|c| *c == '\n').map_or(chars.len(), |i| offset + i);
+    start..end
+}
+
+/// The sentence of `paragraph` holding char `offset`
+fn sentence_range(chars: &[char], paragraph: Range<usize>, offset: usize) -> Range<usize> {
+    let mut start = paragraph.start;
+    let mut i = paragraph.start;
+    while i < paragraph.end {
+        if !matches!(chars[i], '.' | '!' | '?' | '。' | '！' | '？') {
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        while end < paragraph.end && matches!(chars[end], '.' | '!' | '?' | '"' | '\'' | '”' | '’' | ')' | ']' | '」' | '』') {
+            end += 1;
+        }
+        let ideographic = matches!(chars[end - 1], '。' | '！' | '？' | '」' | '』');
+        if end < paragraph.end && !ideographic && !chars[end].is_whitespace() {
+            // "3.14" or "e.g.x": not the end of a sentence
+            i = end;
+            continue;
+        }
+        while end < paragraph.end && chars[end].is_whitespace() {
+            end += 1;
+        }
+        if offset < end {
+            return start..end;
+        }
+        start = end;
+        i = end;
+    }
+    start..paragraph.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_layout::LineLayout;
+
+    fn sentences(text: &str, offsets: &[usize]) -> Vec<String> {
+        let layout = LineLayout::new().layout_document(text, 1000.0);
+        offsets
+            .iter()
+            .map(|&offset| {
+                let range = focus_data(text, &layout, offset, FocusUnit::Sentence, 100.0).sentence;
+                text.chars().skip(range.start).take(range.len()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sentence_around_caret() {
+        let text = "Pi is 3.14 here. Really?! \"Yes.\" Done\nNext one.";
+        assert_eq!(
+            sentences(text, &[0, 16, 17, 25, 27, 33, 37, 38]),
+            ["Pi is 3.14 here. ", "Pi is 3.14 here. ", "Really?! ", "Really?! ", "\"Yes.\" ", "Done", "Done", "Next one."]
+        );
+        assert_eq!(sentences("前文。后文", &[1, 3]), ["前文。", "后文"]);
+    }
+
+    #[test]
+    fn test_dimmed_ranges_and_typewriter_scroll() {
+        let text = "First paragraph.\nSecond one. Two sentences.\nThird";
+        let layout = LineLayout::new().layout_document(text, 1000.0);
+        let line_height = layout.paragraphs[0].actual_line_height;
+
+        let data = focus_data(text, &layout, 20, FocusUnit::Paragraph, 400.0);
+        assert_eq!(data.paragraph, 17..43);
+        assert_eq!(data.dimmed, vec![0..17, 43..49]);
+        assert_eq!(data.caret_line_y, line_height);
+        assert_eq!(data.typewriter_scroll, line_height * 1.5 - 200.0);
+
+        let data = focus_data(text, &layout, 35, FocusUnit::Sentence, 400.0);
+        assert_eq!(data.focus, 29..43);
+        assert_eq!(data.dimmed, vec![0..29, 43..49]);
+        // Focus on the first sentence dims nothing before it
+        assert_eq!(focus_data(text, &layout, 0, FocusUnit::Sentence, 400.0).dimmed, vec![16..49]);
+    }
+}
@@ -0,0 +1,337 @@
+//! # Font Coverage Module
+//!
+//! Character frequency and font coverage analysis for export.
+//!
+//! Maps every character the document uses to the font that will actually render
+//! it after fallback, so PDF/EPUB export and font embedding know which fonts
+//! (and which glyph subsets) are needed, and the UI can warn about characters
+//! that no available font covers ("tofu").
+
+use std::collections::BTreeMap;
+
+use harfbuzz_rs::{Blob, Face, Font, Shared};
+use serde::{Deserialize, Serialize};
+
+use crate::piece_tree::PieceTree;
+
+/// Which characters a registered font can render
+enum Coverage {
+    /// Codepoint ranges, inclusive
+    Ranges(Vec<(char, char)>),
+    /// Real font data, queried through its cmap
+    Face(Shared<Font<'static>>),
+}
+
+impl Coverage {
+    fn covers(&self, ch: char) -> bool {
+        match self {
+            Coverage::Ranges(ranges) => ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&ch)),
+            Coverage::Face(font) => font.get_nominal_glyph(ch).is_some(),
+        }
+    }
+}
+
+struct RegisteredFont {
+    name: String,
+    coverage: Coverage,
+}
+
+/// Fonts available to the renderer and the order they are tried in
+#[derive(Default)]
+pub struct FontRegistry {
+    fonts: Vec<RegisteredFont>,
+    fallback_order: Vec<String>,
+}
+
+impl FontRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a font from its file data (TTF/OTF); replaces a font with the same name
+    pub fn register_font_data(&mut self, name: &str, data: Vec<u8>) {
+        let blob = Blob::with_bytes_owned(data, |d| d.as_slice());
+        let font = Font::new(Face::new(blob, 0));
+        self.insert(name, Coverage::Face(font.into()));
+    }
+
+    /// Register a font by the codepoint ranges it covers
+    pub fn register_ranges(&mut self, name: &str, ranges: Vec<(char, char)>) {
+        self.insert(name, Coverage::Ranges(ranges));
+    }
+
+    /// Set the fallback chain tried after the requested font (names, in order)
+    pub fn set_fallback_order(&mut self, names: Vec<String>) {
+        self.fallback_order = names;
+    }
+
+    /// Check whether a font with this name is registered (case-insensitive)
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    /// Font that renders `ch`: the requested font, then the fallback chain, then any
+    /// other registered font in registration order
+    pub fn resolve(&self, ch: char, requested: Option<&str>) -> Option<&str> {
+        let covers = |font: &&RegisteredFont| font.coverage.covers(ch);
+
+        requested
+            .and_then(|name| self.find(name))
+            .filter(covers)
+            .or_else(|| {
+                self.fallback_order
+                    .iter()
+                    .filter_map(|name| self.find(name))
+                    .find(covers)
+            })
+            .or_else(|| self.fonts.iter().find(covers))
+            .map(|font| font.name.as_str())
+    }
+
+    fn find(&self, name: &str) -> Option<&RegisteredFont> {
+        self.fonts.iter().find(|f| f.name.eq_ignore_ascii_case(name))
+    }
+
+    fn insert(&mut self, name: &str, coverage: Coverage) {
+        self.fonts.retain(|f| !f.name.eq_ignore_ascii_case(name));
+        self.fonts.push(RegisteredFont {
+            name: name.to_string(),
+            coverage,
+        });
+    }
+}
+
+/// How one character is rendered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CharUsage {
+    pub ch: char,
+    /// Number of occurrences
+    pub count: usize,
+    /// Font the text asks for
+    pub requested_font: String,
+    /// Font that renders it after fallback; None means no font covers it
+    pub rendered_font: Option<String>,
+}
+
+/// Characters a font has to provide (the subset to embed)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FontUsage {
+    pub font: String,
+    /// Distinct characters rendered with this font, sorted
+    pub chars: Vec<char>,
+    /// Total occurrences
+    pub count: usize,
+    /// Whether the font is only used as a fallback
+    pub fallback_only: bool,
+}
+
+/// A character no available font can render
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MissingGlyph {
+    pub ch: char,
+    pub count: usize,
+    pub requested_font: String,
+    /// Fonts that are known to cover the character's script
+    pub suggestions: Vec<String>,
+}
+
+/// Result of a coverage analysis
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub characters: Vec<CharUsage>,
+    pub fonts: Vec<FontUsage>,
+    pub missing: Vec<MissingGlyph>,
+}
+
+impl CoverageReport {
+    /// Check whether any character would render as tofu
+    pub fn has_missing_glyphs(&self) -> bool {
+        !self.missing.is_empty()
+    }
+}
+
+/// Analyze text runs given as (text, requested font); runs without a font use `default_font`
+pub fn analyze_runs<'a>(
+    runs: impl IntoIterator<Item = (&'a str, Option<&'a str>)>,
+    registry: &FontRegistry,
+    default_font: &str,
+) -> CoverageReport {
+    // (requested font, char) -> count
+    let mut counts: BTreeMap<(String, char), usize> = BTreeMap::new();
+    for (text, font) in runs {
+        let font = font.unwrap_or(default_font);
+        for ch in text.chars().filter(|c| !c.is_control()) {
+            *counts.entry((font.to_string(), ch)).or_default() += 1;
+        }
+    }
+
+    let mut report = CoverageReport::default();
+    let mut fonts: BTreeMap<String, FontUsage> = BTreeMap::new();
+
+    for ((requested_font, ch), count) in counts {
+        let rendered = registry.resolve(ch, Some(&requested_font)).map(str::to_string);
+
+        match &rendered {
+            Some(font) => {
+                let usage = fonts.entry(font.clone()).or_insert_with(|| FontUsage {
+                    font: font.clone(),
+                    chars: Vec::new(),
+                    count: 0,
+                    fallback_only: true,
+                });
+                if !usage.chars.contains(&ch) {
+                    usage.chars.push(ch);
+                }
+                usage.count += count;
+                if font.eq_ignore_ascii_case(&requested_font) {
+                    usage.fallback_only = false;
+                }
+            }
+            None => report.missing.push(MissingGlyph {
+                ch,
+                count,
+                requested_font: requested_font.clone(),
+                suggestions: suggested_fonts(ch)
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            }),
+        }
+
+        report.characters.push(CharUsage {
+            ch,
+            count,
+            requested_font,
+            rendered_font: rendered,
+        });
+    }
+
+    report.fonts = fonts
+        .into_values()
+        .map(|mut usage| {
+            usage.chars.sort_unstable();
+            usage
+        })
+        .collect();
+    report
+}
+
+/// Analyze every piece of a document using each piece's font family
+pub fn analyze_piece_tree(tree: &PieceTree, registry: &FontRegistry, default_font: &str) -> CoverageReport {
+    let runs = tree.pieces.iter().map(|piece| {
+        let text = tree
+            .buffers
+            .get(PieceTree::buffer_idx(&piece.buffer_id))
+            .and_then(|b| b.get(piece.start..piece.start + piece.length))
+            .unwrap_or("");
+        let font = piece
+            .attributes
+            .as_ref()
+            .and_then(|a| a.font_family.as_deref());
+        (text, font)
+    });
+    analyze_runs(runs, registry, default_font)
+}
+
+/// Widely available fonts that cover the script of `ch`
+fn suggested_fonts(ch: char) -> &'static [&'static str] {
+    match ch as u32 {
+        0x0590..=0x05FF => &["Noto Sans Hebrew", "Arial"],
+        0x0600..=0x06FF | 0x0750..=0x077F | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => {
+            &["Noto Naskh Arabic", "Arial"]
+        }
+        0x0900..=0x097F => &["Noto Sans Devanagari", "Mangal"],
+        0x0E00..=0x0E7F => &["Noto Sans Thai", "Leelawadee UI"],
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => &["Noto Sans CJK KR", "Malgun Gothic"],
+        0x3040..=0x30FF => &["Noto Sans CJK JP", "Yu Gothic"],
+        0x2E80..=0x2FFF | 0x3000..=0x303F | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0xFF00..=0xFFEF => {
+            &["Noto Sans CJK SC", "Microsoft YaHei", "SimSun"]
+        }
+        0x2190..=0x22FF | 0x2A00..=0x2AFF | 0x1D400..=0x1D7FF => &["Cambria Math", "Noto Sans Math"],
+        0x2600..=0x27BF | 0x1F300..=0x1FAFF => &["Noto Color Emoji", "Segoe UI Emoji", "Segoe UI Symbol"],
+        _ => &["Noto Sans", "Arial Unicode MS"],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece_tree::TextAttributes;
+
+    fn registry() -> FontRegistry {
+        let mut registry = FontRegistry::new();
+        registry.register_ranges("Calibri", vec![(' ', '~'), ('\u{00A0}', '\u{024F}')]);
+        registry.register_ranges("SimSun", vec![(' ', '~'), ('\u{4E00}', '\u{9FFF}')]);
+        registry.register_ranges("Segoe UI Symbol", vec![('\u{2600}', '\u{27BF}')]);
+        registry.set_fallback_order(vec!["Segoe UI Symbol".to_string(), "SimSun".to_string()]);
+        registry
+    }
+
+    #[test]
+    fn test_resolve_with_fallback() {
+        let registry = registry();
+        assert_eq!(registry.resolve('a', Some("calibri")), Some("Calibri"));
+        assert_eq!(registry.resolve('中', Some("Calibri")), Some("SimSun"));
+        assert_eq!(registry.resolve('☀', Some("Calibri")), Some("Segoe UI Symbol"));
+        // An unregistered font goes straight to the fallback chain
+        assert_eq!(registry.resolve('a', Some("Comic Sans MS")), Some("SimSun"));
+        assert_eq!(registry.resolve('a', None), Some("SimSun"));
+        assert_eq!(registry.resolve('\u{0E01}', Some("Calibri")), None);
+    }
+
+    #[test]
+    fn test_analyze_runs() {
+        let report = analyze_runs(
+            [("Hi 中文 ok", None), ("ก\n", Some("Calibri"))],
+            &registry(),
+            "Calibri",
+        );
+
+        let h = report.characters.iter().find(|c| c.ch == 'H').unwrap();
+        assert_eq!(h.rendered_font.as_deref(), Some("Calibri"));
+        let space = report.characters.iter().find(|c| c.ch == ' ').unwrap();
+        assert_eq!(space.count, 2);
+        // Control characters are not analyzed
+        assert!(!report.characters.iter().any(|c| c.ch == '\n'));
+
+        let simsun = report.fonts.iter().find(|f| f.font == "SimSun").unwrap();
+        assert_eq!(simsun.chars, vec!['中', '文']);
+        assert!(simsun.fallback_only);
+        let calibri = report.fonts.iter().find(|f| f.font == "Calibri").unwrap();
+        assert!(!calibri.fallback_only);
+
+        assert!(report.has_missing_glyphs());
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].ch, 'ก');
+        assert_eq!(report.missing[0].suggestions[0], "Noto Sans Thai");
+    }
+
+    #[test]
+    fn test_analyze_piece_tree_uses_font_family() {
+        let mut tree = PieceTree::empty();
+        tree.insert(0, "ab".to_string());
+        tree.insert_with_attrs(
+            2,
+            "cd".to_string(),
+            Some(TextAttributes {
+                font_family: Some("SimSun".to_string()),
+                ..Default::default()
+            }),
+        );
+
+        let report = analyze_piece_tree(&tree, &registry(), "Calibri");
+        let c = report.characters.iter().find(|c| c.ch == 'c').unwrap();
+        assert_eq!(c.requested_font, "SimSun");
+        assert_eq!(c.rendered_font.as_deref(), Some("SimSun"));
+        assert!(!report.has_missing_glyphs());
+    }
+
+    #[test]
+    fn test_invalid_font_data_covers_nothing() {
+        let mut registry = FontRegistry::new();
+        registry.register_font_data("Broken", vec![0, 1, 2, 3]);
+        assert!(registry.contains("broken"));
+        assert_eq!(registry.resolve('a', Some("Broken")), None);
+    }
+}
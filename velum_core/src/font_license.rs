@@ -0,0 +1,173 @@
+//! # Font License Module
+//!
+//! Embedding permissions of font files, so exports do not ship fonts their
+//! licenses keep out of documents.
+//!
+//! A TrueType or OpenType font states what a document may do with it in the
+//! fsType field of its OS/2 table: embed it to install, to edit the document,
+//! only to view and print it, or not at all. Fonts may also forbid subsetting,
+//! or allow only their bitmaps to be embedded. [`FontLicense`] reads those
+//! flags and [`FontLicensePolicy`] decides what an export does with a font
+//! that is restricted: leave it out, or embed it and say so.
+//! [`substitution_suggestions`] names fonts to use instead.
+
+use serde::{Deserialize, Serialize};
+
+/// fsType bits; the usage permissions are bits 0–3, of which only one is set
+const RESTRICTED: u16 = 0x0002;
+const PREVIEW_AND_PRINT: u16 = 0x0004;
+const EDITABLE: u16 = 0x0008;
+const NO_SUBSETTING: u16 = 0x0100;
+const BITMAP_ONLY: u16 = 0x0200;
+
+/// What a document a font is embedded in may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingPermission {
+    /// Embedded and installed permanently on the reader's system
+    Installable,
+    /// Embedded in documents that may be edited
+    Editable,
+    /// Embedded in documents that may only be viewed and printed
+    PreviewAndPrint,
+    /// Not embedded at all without the font vendor's permission
+    Restricted,
+}
+
+/// The embedding flags of a font
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FontLicense {
+    pub permission: EmbeddingPermission,
+    /// The whole font must be embedded, not a subset of its glyphs
+    pub no_subsetting: bool,
+    /// Only the font's bitmaps may be embedded, not its outlines
+    pub bitmap_only: bool,
+}
+
+impl Default for FontLicense {
+    /// A font without an OS/2 table, which may be embedded freely
+    fn default() -> Self {
+        FontLicense::from_fs_type(0)
+    }
+}
+
+impl FontLicense {
+    /// The flags of an OS/2 fsType value
+    ///
+    /// Fonts from before OpenType 1.3 may set several usage bits; the least
+    /// restrictive one applies, as the specification says.
+    pub fn from_fs_type(fs_type: u16) -> Self {
+        let permission = if fs_type & 0x000F == 0 {
+            EmbeddingPermission::Installable
+        } else if fs_type & EDITABLE != 0 {
+            EmbeddingPermission::Editable
+        } else if fs_type & PREVIEW_AND_PRINT != 0 {
+            EmbeddingPermission::PreviewAndPrint
+        } else if fs_type & RESTRICTED != 0 {
+            EmbeddingPermission::Restricted
+        } else {
+            // Bit 0 is reserved; a font setting only it is treated as installable
+            EmbeddingPermission::Installable
+        };
+        FontLicense {
+            permission,
+            no_subsetting: fs_type & NO_SUBSETTING != 0,
+            bitmap_only: fs_type & BITMAP_ONLY != 0,
+        }
+    }
+
+    /// Whether the font's outlines may be embedded in a document at all
+    pub fn allows_outline_embedding(&self) -> bool {
+        self.permission != EmbeddingPermission::Restricted && !self.bitmap_only
+    }
+
+    /// Why the font's outlines may not be embedded, if they may not
+    pub fn restriction(&self) -> Option<&'static str> {
+        if self.permission == EmbeddingPermission::Restricted {
+            Some("its license does not allow embedding")
+        } else if self.bitmap_only {
+            Some("its license only allows embedding its bitmaps")
+        } else {
+            None
+        }
+    }
+}
+
+/// What an export does with a font whose license keeps it out of documents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FontLicensePolicy {
+    /// Leave it out, drawing its text in another font, with a warning
+    #[default]
+    Refuse,
+    /// Embed it anyway with a warning, for fonts the user has a license for
+    Warn,
+}
+
+/// Freely embeddable fonts with the same metrics as common restricted or
+/// proprietary fonts, so text keeps its line breaks when they are swapped
+const METRIC_COMPATIBLE: &[(&str, &str)] = &[
+    ("arial", "Liberation Sans"),
+    ("helvetica", "Liberation Sans"),
+    ("times new roman", "Liberation Serif"),
+    ("times", "Liberation Serif"),
+    ("courier new", "Liberation Mono"),
+    ("courier", "Liberation Mono"),
+    ("arial narrow", "Liberation Sans Narrow"),
+    ("calibri", "Carlito"),
+    ("cambria", "Caladea"),
+    ("georgia", "Gelasio"),
+    ("segoe ui", "Selawik"),
+    ("symbol", "OpenSymbol"),
+];
+
+/// Fonts to use instead of `family`: a metric-compatible font if there is
+/// one, then the `available` families, which may be embedded, in their order
+pub fn substitution_suggestions<'a>(family: &str, available: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let key = family.trim().to_lowercase();
+    let mut suggestions: Vec<String> = METRIC_COMPATIBLE
+        .iter()
+        .filter(|(restricted, _)| *restricted == key)
+        .map(|(_, replacement)| replacement.to_string())
+        .collect();
+    for candidate in available {
+        let taken = candidate.eq_ignore_ascii_case(family) || suggestions.iter().any(|s| s.eq_ignore_ascii_case(candidate));
+        if !taken {
+            suggestions.push(candidate.to_string());
+        }
+    }
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_fs_type() {
+        assert_eq!(FontLicense::from_fs_type(0), FontLicense::default());
+        assert_eq!(FontLicense::default().permission, EmbeddingPermission::Installable);
+        assert_eq!(FontLicense::from_fs_type(0x0002).permission, EmbeddingPermission::Restricted);
+        assert_eq!(FontLicense::from_fs_type(0x0004).permission, EmbeddingPermission::PreviewAndPrint);
+        assert_eq!(FontLicense::from_fs_type(0x0008).permission, EmbeddingPermission::Editable);
+        // Old fonts with several bits get the least restrictive
+        assert_eq!(FontLicense::from_fs_type(0x000E).permission, EmbeddingPermission::Editable);
+
+        let license = FontLicense::from_fs_type(0x0304);
+        assert!(license.no_subsetting && license.bitmap_only);
+        assert!(!license.allows_outline_embedding());
+        assert!(FontLicense::from_fs_type(0x0104).allows_outline_embedding());
+        assert!(FontLicense::from_fs_type(0x0002).restriction().is_some());
+        assert_eq!(FontLicense::from_fs_type(0x0008).restriction(), None);
+    }
+
+    #[test]
+    fn test_substitution_suggestions() {
+        assert_eq!(
+            substitution_suggestions("Calibri", ["Noto Sans", "carlito", "Calibri"]),
+            vec!["Carlito".to_string(), "Noto Sans".to_string()]
+        );
+        assert_eq!(substitution_suggestions("Brand Serif", ["Noto Serif"]), vec!["Noto Serif".to_string()]);
+        assert!(substitution_suggestions("Brand Serif", []).is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+//! # Font Substitution Module
+//!
+//! Document-wide "replace font" command.
+//!
+//! When a document uses a font that is not available, every reference to it is
+//! rewritten to a replacement: direct run formatting, style definitions and the
+//! theme's font scheme. Family names compare case-insensitively, as Word does.
+//! Sizes can optionally be scaled, since a replacement with a different x-height
+//! or advance width reflows the text at the same nominal size.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{Paragraph, RunProperties};
+use crate::piece_tree::{PieceTree, TextAttributes};
+
+/// Which part of the editor content a substitution rewrites
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FontScope {
+    /// All text
+    Document,
+    /// Chars in the range, e.g. the selection
+    Selection(Range<usize>),
+}
+
+/// What a substitution touched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FontSubstitutionReport {
+    /// Runs of direct formatting rewritten
+    pub runs: usize,
+    /// Style definitions rewritten
+    pub styles: usize,
+    /// Theme font slots (major, minor, symbol) rewritten
+    pub theme_fonts: usize,
+}
+
+/// Replace one font family with another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FontSubstitution {
+    pub old_family: String,
+    pub new_family: String,
+    /// Factor applied to the size of rewritten runs and styles
+    pub size_scale: Option<f32>,
+}
+
+impl FontSubstitution {
+    pub fn new(old_family: impl Into<String>, new_family: impl Into<String>) -> Self {
+        FontSubstitution {
+            old_family: old_family.into(),
+            new_family: new_family.into(),
+            size_scale: None,
+        }
+    }
+
+    /// Scale the size of everything rewritten, e.g. 0.9 for a replacement that renders larger
+    pub fn with_size_scale(mut self, scale: f32) -> Self {
+        self.size_scale = Some(scale);
+        self
+    }
+
+    /// Whether `family` names the font being replaced
+    pub fn matches(&self, family: &str) -> bool {
+        family.trim().eq_ignore_ascii_case(self.old_family.trim())
+    }
+
+    /// Rewrite the direct formatting of editor content as a single undo step
+    ///
+    /// Returns the number of runs rewritten.
+    pub fn apply_to_tree(&self, tree: &mut PieceTree, scope: &FontScope) -> usize {
+        let range = match scope {
+            FontScope::Document => 0..tree.char_count(),
+            FontScope::Selection(range) => range.clone(),
+        };
+
+        let mut offset = range.start;
+        let mut targets = Vec::new();
+        for span in tree.attribute_spans(range) {
+            let length = span.length;
+            if let Some(attributes) = span.attributes.filter(|a| self.matches_attributes(a)) {
+                targets.push((offset..offset + length, self.replacement_attributes(&attributes)));
+            }
+            offset += length;
+        }
+
+        tree.transaction(|tree| {
+            for (range, attributes) in &targets {
+                tree.apply_attributes(range.clone(), attributes);
+            }
+        });
+        targets.len()
+    }
+
+    /// Rewrite runs in every part of a model, its styles and its theme
+    pub fn apply_to_model(&self, model: &mut DocumentModel) -> FontSubstitutionReport {
+        let mut report = FontSubstitutionReport::default();
+
+        for block in &mut model.body {
+            match block {
+                Block::Paragraph(paragraph) => report.runs += self.apply_to_paragraph(paragraph),
+                Block::Table(table) => {
+                    for cell in table.rows.iter_mut().flat_map(|row| row.cells.iter_mut()) {
+                        for paragraph in &mut cell.paragraphs {
+                            report.runs += self.apply_to_paragraph(paragraph);
+                        }
+                    }
+                }
+            }
+        }
+        let other_parts = model
+            .headers
+            .iter_mut()
+            .flat_map(|h| h.paragraphs.iter_mut())
+            .chain(model.footers.iter_mut().flat_map(|f| f.paragraphs.iter_mut()))
+            .chain(model.footnotes.iter_mut().flat_map(|n| n.paragraphs.iter_mut()))
+            .chain(model.endnotes.iter_mut().flat_map(|n| n.paragraphs.iter_mut()));
+        for paragraph in other_parts {
+            report.runs += self.apply_to_paragraph(paragraph);
+        }
+
+        for style in model.styles.values_mut() {
+            if self.apply_to_run_properties(&mut style.run_properties) {
+                report.styles += 1;
+            }
+        }
+
+        if let Some(theme) = model.theme.as_mut() {
+            let fonts = &mut theme.fonts;
+            for slot in [&mut fonts.major_font, &mut fonts.minor_font, &mut fonts.symbol_font] {
+                if self.matches(slot) {
+                    *slot = self.new_family.clone();
+                    report.theme_fonts += 1;
+                }
+            }
+        }
+
+        report
+    }
+
+    fn apply_to_paragraph(&self, paragraph: &mut Paragraph) -> usize {
+        paragraph
+            .runs
+            .iter_mut()
+            .map(|run| self.apply_to_run_properties(&mut run.properties))
+            .filter(|rewritten| *rewritten)
+            .count()
+    }
+
+    fn apply_to_run_properties(&self, properties: &mut RunProperties) -> bool {
+        if !properties.font_name.as_deref().is_some_and(|name| self.matches(name)) {
+            return false;
+        }
+        properties.font_name = Some(self.new_family.clone());
+        properties.font_size = properties.font_size.map(|size| self.scale(size as f32) as i32);
+        true
+    }
+
+    fn matches_attributes(&self, attributes: &TextAttributes) -> bool {
+        attributes.font_family.as_deref().is_some_and(|family| self.matches(family))
+    }
+
+    /// Attributes to merge into a run using the old font
+    fn replacement_attributes(&self, attributes: &TextAttributes) -> TextAttributes {
+        TextAttributes {
+            font_family: Some(self.new_family.clone()),
+            font_size: attributes.font_size.map(|size| self.scale(size as f32) as u16),
+            ..Default::default()
+        }
+    }
+
+    fn scale(&self, size: f32) -> f32 {
+        match self.size_scale {
+            Some(scale) => (size * scale).round().max(1.0),
+            None => size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::{Run, Style, Theme, ThemeFonts};
+
+    fn font(family: &str, size: u16) -> TextAttributes {
+        TextAttributes {
+            font_family: Some(family.to_string()),
+            font_size: Some(size),
+            bold: Some(true),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tree_substitution_is_one_undo_step() {
+        let mut tree = PieceTree::new(String::new());
+        tree.insert_with_attrs(0, "one ".to_string(), Some(font("Comic Sans MS", 10)));
+        tree.insert_with_attrs(4, "two ".to_string(), Some(font("Arial", 10)));
+        tree.insert_with_attrs(8, "three".to_string(), Some(font("comic sans ms", 20)));
+
+        let substitution = FontSubstitution::new("Comic Sans MS", "Noto Sans").with_size_scale(0.9);
+        assert_eq!(substitution.apply_to_tree(&mut tree, &FontScope::Document), 2);
+
+        let spans = tree.attribute_spans(0..13);
+        assert_eq!(spans[0].attributes, Some(font("Noto Sans", 9)));
+        assert_eq!(spans[1].attributes, Some(font("Arial", 10)));
+        assert_eq!(spans[2].attributes, Some(font("Noto Sans", 18)));
+
+        assert!(tree.undo());
+        assert_eq!(tree.attribute_spans(0..4)[0].attributes, Some(font("Comic Sans MS", 10)));
+        assert_eq!(tree.attribute_spans(8..13)[0].attributes, Some(font("comic sans ms", 20)));
+    }
+
+    #[test]
+    fn test_selection_scope() {
+        let mut tree = PieceTree::new(String::new());
+        tree.insert_with_attrs(0, "abcdef".to_string(), Some(font("Courier", 12)));
+
+        let substitution = FontSubstitution::new("Courier", "Courier New");
+        assert_eq!(substitution.apply_to_tree(&mut tree, &FontScope::Selection(2..4)), 1);
+        let families: Vec<_> = tree
+            .attribute_spans(0..6)
+            .into_iter()
+            .map(|span| span.attributes.unwrap().font_family.unwrap())
+            .collect();
+        assert_eq!(families, ["Courier", "Courier New", "Courier"]);
+    }
+
+    #[test]
+    fn test_model_substitution_covers_styles_and_theme() {
+        let properties = RunProperties {
+            font_name: Some("Garamond".to_string()),
+            font_size: Some(12),
+            ..Default::default()
+        };
+        let paragraph = Paragraph {
+            runs: vec![
+                Run { text: "a".to_string(), properties: properties.clone() },
+                Run { text: "b".to_string(), properties: RunProperties::default() },
+            ],
+            ..Default::default()
+        };
+        let mut model = DocumentModel {
+            body: vec![Block::from(paragraph)],
+            theme: Some(Theme {
+                fonts: ThemeFonts {
+                    major_font: "Garamond".to_string(),
+                    minor_font: "Calibri".to_string(),
+                    symbol_font: String::new(),
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        model.styles.insert(
+            "Heading1".to_string(),
+            Style { id: "Heading1".to_string(), run_properties: properties, ..Default::default() },
+        );
+        model.styles.insert("Normal".to_string(), Style { id: "Normal".to_string(), ..Default::default() });
+
+        let report = FontSubstitution::new("garamond", "EB Garamond").with_size_scale(1.5).apply_to_model(&mut model);
+        assert_eq!(report, FontSubstitutionReport { runs: 1, styles: 1, theme_fonts: 1 });
+
+        let run = &model.paragraphs().next().unwrap().runs[0].properties;
+        assert_eq!(run.font_name.as_deref(), Some("EB Garamond"));
+        assert_eq!(run.font_size, Some(18));
+        assert_eq!(model.styles["Heading1"].run_properties.font_name.as_deref(), Some("EB Garamond"));
+        assert_eq!(model.theme.unwrap().fonts.major_font, "EB Garamond");
+    }
+}
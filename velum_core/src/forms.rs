@@ -0,0 +1,384 @@
+//! # Forms Module
+//!
+//! Form fields anchored to the editor text, and the editing restriction the
+//! document enforces.
+//!
+//! A field covers the chars that show it: the text of a text, date or
+//! drop-down field, or a content control checkbox's glyph; a legacy checkbox
+//! shows nothing. Text and date fields take what is typed inside them or at
+//! either end as their value; other fields move with edits like a comment
+//! anchor. A field goes away when a deletion takes its text along with text
+//! around it. On export each field is written back as the legacy field or
+//! content control it was read from.
+//!
+//! Protection decides what may be edited: under forms protection only the
+//! text of enabled text and date fields, under tracked-changes protection
+//! anything while changes are tracked, and under read-only or comments
+//! protection nothing.
+
+use std::ops::Range;
+
+use crate::comments::move_anchor;
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{AnchoredFormField, FormError, FormField, FormFieldKind, FormFieldSet, Paragraph, ProtectionMode};
+
+/// The chars of the whole text a form field shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FieldSpan {
+    start: usize,
+    length: usize,
+    /// Whether the chars are a content control's placeholder text
+    placeholder: bool,
+}
+
+impl FieldSpan {
+    fn end(&self) -> usize {
+        self.start + self.length
+    }
+}
+
+/// The form fields of a document in text order, with its protection
+#[derive(Debug, Clone, Default)]
+pub struct DocumentForms {
+    set: FormFieldSet,
+    /// Where each of `set.fields` is, by index
+    spans: Vec<FieldSpan>,
+}
+
+impl DocumentForms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Form fields of `paragraphs`, each given with the char offset it starts
+    /// at, with offsets into the whole text, under `protection`
+    pub fn from_paragraphs<'a>(
+        paragraphs: impl IntoIterator<Item = (usize, &'a Paragraph)>,
+        protection: ProtectionMode,
+    ) -> Self {
+        let mut located = Vec::new();
+        for (paragraph_start, paragraph) in paragraphs {
+            located.extend(paragraph.form_fields.iter().map(|anchored| {
+                let span = FieldSpan {
+                    start: paragraph_start + anchored.start,
+                    length: anchored.length,
+                    placeholder: anchored.placeholder,
+                };
+                (span, anchored.field.clone())
+            }));
+        }
+        located.sort_by_key(|(span, _)| span.start);
+        let (spans, fields) = located.into_iter().unzip();
+        DocumentForms {
+            set: FormFieldSet { fields, protection },
+            spans,
+        }
+    }
+
+    /// Form fields of the model's body paragraphs, under its protection
+    pub fn from_model(model: &DocumentModel) -> Self {
+        Self::from_paragraphs(model.located_paragraphs(), model.protection)
+    }
+
+    /// Hand the fields to the model's body paragraphs and the protection to the model
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let mut paragraph_start = 0;
+        for block in model.body.iter_mut() {
+            let Block::Paragraph(paragraph) = block else {
+                continue;
+            };
+            let length = paragraph.text.chars().count();
+            let fields = self.anchored_in(&paragraph.text, paragraph_start);
+            paragraph.form_fields = paragraph_form_fields(&fields, paragraph_start, length);
+            paragraph_start += length + 1;
+        }
+        model.protection = self.set.protection;
+    }
+
+    pub fn protection(&self) -> ProtectionMode {
+        self.set.protection
+    }
+
+    pub fn set_protection(&mut self, protection: ProtectionMode) {
+        self.set.protection = protection;
+    }
+
+    /// The fields in text order; text and date fields hold the value they
+    /// had when last filled, see [`DocumentForms::anchored`]
+    pub fn fields(&self) -> &[FormField] {
+        &self.set.fields
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.fields.is_empty()
+    }
+
+    /// The fields with offsets into `text`, the whole text, each with the
+    /// value the text it shows gives it
+    pub fn anchored(&self, text: &str) -> Vec<AnchoredFormField> {
+        self.anchored_in(text, 0)
+    }
+
+    /// Whether chars `range` may be edited, `tracking` telling whether changes are being tracked
+    pub fn allows_edit(&self, range: Range<usize>, tracking: bool) -> bool {
+        if self.set.allows_free_editing() {
+            return true;
+        }
+        match self.set.protection {
+            ProtectionMode::None => true,
+            ProtectionMode::TrackedChanges => tracking,
+            ProtectionMode::ReadOnly | ProtectionMode::Comments => false,
+            ProtectionMode::Forms => self.set.fields.iter().zip(&self.spans).any(|(field, span)| {
+                field.enabled
+                    && matches!(field.kind, FormFieldKind::Text | FormFieldKind::Date)
+                    && span.start <= range.start
+                    && range.end <= span.end()
+            }),
+        }
+    }
+
+    /// Set field `name` from text as [`FormFieldSet::set`] does; returns the
+    /// field's index, the chars that show it and the text to show there instead
+    pub(crate) fn set(&mut self, name: &str, value: &str) -> Result<(usize, Range<usize>, String), FormError> {
+        self.fill(name, |set| set.set(name, value))
+    }
+
+    /// Check or clear checkbox `name`, as [`DocumentForms::set`] sets a value
+    pub(crate) fn set_checked(&mut self, name: &str, checked: bool) -> Result<(usize, Range<usize>, String), FormError> {
+        self.fill(name, |set| set.set_checked(name, checked))
+    }
+
+    /// Let field `index` show the `length` chars at `start`, the text a fill put in
+    pub(crate) fn place(&mut self, index: usize, start: usize, length: usize) {
+        if let Some(span) = self.spans.get_mut(index) {
+            span.start = start;
+            span.length = length;
+        }
+    }
+
+    /// Move the fields past an edit, dropping those it took, see [`move_span`]
+    pub fn apply_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        let kept: Vec<bool> = self
+            .set
+            .fields
+            .iter()
+            .zip(self.spans.iter_mut())
+            .map(|(field, span)| move_span(span, field.kind, offset, removed, inserted))
+            .collect();
+        let mut keep = kept.iter();
+        self.set.fields.retain(|_| *keep.next().unwrap_or(&true));
+        let mut keep = kept.iter();
+        self.spans.retain(|_| *keep.next().unwrap_or(&true));
+    }
+
+    fn fill(
+        &mut self,
+        name: &str,
+        change: impl FnOnce(&mut FormFieldSet) -> Result<(), FormError>,
+    ) -> Result<(usize, Range<usize>, String), FormError> {
+        change(&mut self.set)?;
+        let index = self
+            .set
+            .fields
+            .iter()
+            .position(|field| field.name == name)
+            .ok_or_else(|| FormError::NotFound(name.to_string()))?;
+        let span = &mut self.spans[index];
+        span.placeholder = false;
+        Ok((index, span.start..span.end(), self.set.fields[index].text()))
+    }
+
+    /// The fields starting within `text`, which starts at char `start` of the
+    /// whole text, with offsets into the whole text
+    fn anchored_in(&self, text: &str, start: usize) -> Vec<AnchoredFormField> {
+        let bytes: Vec<usize> = text.char_indices().map(|(byte, _)| byte).chain([text.len()]).collect();
+        let end = start + bytes.len() - 1;
+        self.set
+            .fields
+            .iter()
+            .zip(&self.spans)
+            .filter(|(_, span)| (start..=end).contains(&span.start))
+            .map(|(field, span)| {
+                let length = span.length.min(end - span.start);
+                let from = span.start - start;
+                let shown = &text[bytes[from]..bytes[from + length]];
+                AnchoredFormField {
+                    field: if span.placeholder { field.clone() } else { field.with_text(shown) },
+                    start: span.start,
+                    length,
+                    placeholder: span.placeholder,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Move `span`, the chars of a field of `kind`, past an edit; false if the edit took the field
+fn move_span(span: &mut FieldSpan, kind: FormFieldKind, offset: usize, removed: usize, inserted: usize) -> bool {
+    let removed_end = offset + removed;
+    let end = span.end();
+    let taken = if span.length == 0 {
+        offset < span.start && span.start < removed_end
+    } else {
+        offset <= span.start && end <= removed_end && removed > span.length
+    };
+    if taken {
+        return false;
+    }
+
+    let takes_input = matches!(kind, FormFieldKind::Text | FormFieldKind::Date);
+    // The edit starts in the field or at either end and removes nothing past it
+    let inside = span.start <= offset && offset <= end && removed_end <= end;
+    if (removed > 0 && offset < end && removed_end > span.start) || (takes_input && inside && inserted > 0) {
+        span.placeholder = false;
+    }
+
+    if !takes_input {
+        (span.start, span.length) = move_anchor(span.start, span.length, offset, removed, inserted);
+        return true;
+    }
+    // Where the field is once the removed text is gone
+    let (deleted_start, deleted_length) = move_anchor(span.start, span.length, offset, removed, 0);
+    let deleted_end = deleted_start + deleted_length;
+    let (start, end) = if end < offset {
+        (span.start, end)
+    } else if inside {
+        (span.start, deleted_end + inserted)
+    } else if offset < span.start {
+        (deleted_start + inserted, deleted_end + inserted)
+    } else {
+        (span.start, offset)
+    };
+    span.start = start;
+    span.length = end - start;
+    true
+}
+
+/// The fields starting inside the paragraph of `length` chars at `start`, relative to it
+pub(crate) fn paragraph_form_fields(fields: &[AnchoredFormField], start: usize, length: usize) -> Vec<AnchoredFormField> {
+    let end = start + length;
+    fields
+        .iter()
+        .filter(|anchored| (start..=end).contains(&anchored.start))
+        .map(|anchored| AnchoredFormField {
+            start: anchored.start - start,
+            length: anchored.length.min(end - anchored.start),
+            ..anchored.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::FormFieldSource;
+
+    fn field(name: &str, kind: FormFieldKind, source: FormFieldSource, start: usize, length: usize) -> AnchoredFormField {
+        AnchoredFormField {
+            field: FormField::new(name, kind, source),
+            start,
+            length,
+            placeholder: false,
+        }
+    }
+
+    fn forms(fields: Vec<AnchoredFormField>, protection: ProtectionMode) -> DocumentForms {
+        let paragraph = Paragraph {
+            form_fields: fields,
+            ..Default::default()
+        };
+        DocumentForms::from_paragraphs([(0, &paragraph)], protection)
+    }
+
+    fn spans(forms: &DocumentForms) -> Vec<(usize, usize)> {
+        forms.spans.iter().map(|span| (span.start, span.length)).collect()
+    }
+
+    #[test]
+    fn test_text_fields_take_typing_at_their_ends() {
+        // "Name: Ann, OK: ☐"
+        let mut forms = forms(
+            vec![
+                field("name", FormFieldKind::Text, FormFieldSource::Legacy, 6, 3),
+                field("ok", FormFieldKind::Checkbox, FormFieldSource::ContentControl, 15, 1),
+            ],
+            ProtectionMode::None,
+        );
+        forms.apply_edit(9, 0, 4);
+        forms.apply_edit(6, 0, 3);
+        assert_eq!(spans(&forms), vec![(6, 10), (22, 1)]);
+
+        // Typing next to the checkbox leaves it as it is
+        forms.apply_edit(23, 0, 2);
+        forms.apply_edit(22, 0, 1);
+        assert_eq!(spans(&forms), vec![(6, 10), (23, 1)]);
+
+        let text = "Name: Dr Ann Lee, OK: x☐ys";
+        let values: Vec<String> = forms.anchored(text).into_iter().map(|f| f.field.value).collect();
+        assert_eq!(values, vec!["Dr Ann Lee".to_string(), String::new()]);
+    }
+
+    #[test]
+    fn test_fields_go_only_with_text_around_them() {
+        let mut forms = forms(
+            vec![
+                field("name", FormFieldKind::Text, FormFieldSource::Legacy, 6, 3),
+                field("agree", FormFieldKind::Checkbox, FormFieldSource::Legacy, 12, 0),
+            ],
+            ProtectionMode::None,
+        );
+        // Clearing the text of a field keeps it, empty
+        forms.apply_edit(6, 3, 0);
+        assert_eq!(spans(&forms), vec![(6, 0), (9, 0)]);
+        // Deleting the char before a legacy checkbox keeps it too
+        forms.apply_edit(8, 1, 0);
+        assert_eq!(spans(&forms), vec![(6, 0), (8, 0)]);
+        forms.apply_edit(5, 2, 0);
+        assert_eq!(spans(&forms), vec![(6, 0)]);
+        forms.apply_edit(4, 3, 0);
+        assert!(forms.is_empty());
+    }
+
+    #[test]
+    fn test_protection_decides_what_may_be_edited() {
+        let mut disabled = field("locked", FormFieldKind::Text, FormFieldSource::ContentControl, 20, 3);
+        disabled.field.enabled = false;
+        let mut forms = forms(
+            vec![
+                field("name", FormFieldKind::Text, FormFieldSource::Legacy, 6, 3),
+                field("ok", FormFieldKind::Checkbox, FormFieldSource::ContentControl, 15, 1),
+                disabled,
+            ],
+            ProtectionMode::Forms,
+        );
+        assert!(forms.allows_edit(6..9, false));
+        assert!(forms.allows_edit(9..9, false));
+        assert!(!forms.allows_edit(5..7, false));
+        assert!(!forms.allows_edit(15..16, false));
+        assert!(!forms.allows_edit(21..21, false));
+
+        forms.set_protection(ProtectionMode::TrackedChanges);
+        assert!(!forms.allows_edit(0..1, false));
+        assert!(forms.allows_edit(0..1, true));
+        forms.set_protection(ProtectionMode::ReadOnly);
+        assert!(!forms.allows_edit(6..9, true));
+        forms.set_protection(ProtectionMode::None);
+        assert!(forms.allows_edit(0..1, false));
+    }
+
+    #[test]
+    fn test_filling_shows_the_new_text() {
+        let mut color = field("color", FormFieldKind::DropDown, FormFieldSource::ContentControl, 7, 3);
+        color.field.options = vec!["r".to_string(), "g".to_string()];
+        color.field.option_labels = vec!["Red".to_string(), "Green".to_string()];
+        color.field.value = "r".to_string();
+        let mut forms = forms(vec![color], ProtectionMode::Forms);
+
+        assert_eq!(forms.set("color", "g").unwrap(), (0, 7..10, "Green".to_string()));
+        assert!(matches!(forms.set("color", "blue"), Err(FormError::InvalidValue(..))));
+        assert!(matches!(forms.set("size", "1"), Err(FormError::NotFound(_))));
+
+        forms.set_protection(ProtectionMode::ReadOnly);
+        assert_eq!(forms.set("color", "r"), Err(FormError::Protected));
+    }
+}
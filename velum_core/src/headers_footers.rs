@@ -0,0 +1,460 @@
+//! # Headers and Footers Module
+//!
+//! Editable headers and footers of each section.
+//!
+//! A section has up to three headers and three footers: the default one, one
+//! for its first page and one for even pages. Each is a piece tree of its own,
+//! so it is edited, formatted and undone apart from the body. A section
+//! without a header of some variant shows the previous section's, as Word's
+//! "link to previous" does; editing a linked header edits the one it shows,
+//! and unlinking gives the section a copy of its own.
+//!
+//! The first-page variant is shown on a section's first page when it has a
+//! different first page (w:titlePg), the even one on even pages when the
+//! document has different odd and even pages (w:evenAndOddHeaders). In OOXML
+//! each header is a header{n}.xml part referenced from its section's w:sectPr;
+//! documents read from a file have different odd and even pages when any
+//! section refers to an even header or footer.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::document_model::{Block, DocumentModel};
+use crate::modification::hash_serialized;
+use crate::ooxml::{Footer, Header, HeaderFooterReference, Paragraph, Section};
+use crate::piece_tree::PieceTree;
+
+/// Header and footer errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum HeaderFooterError {
+    #[error("The first section has no previous section to link to")]
+    NoPreviousSection,
+}
+
+/// Headers or footers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderFooterKind {
+    Header,
+    Footer,
+}
+
+impl HeaderFooterKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "header" => Some(HeaderFooterKind::Header),
+            "footer" => Some(HeaderFooterKind::Footer),
+            _ => None,
+        }
+    }
+}
+
+/// Which pages of a section a header or footer is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderFooterVariant {
+    Default,
+    First,
+    Even,
+}
+
+impl HeaderFooterVariant {
+    /// Parses an OOXML w:type value; Word calls the default variant "odd" too
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "" | "default" | "odd" | "primary" => Some(HeaderFooterVariant::Default),
+            "first" => Some(HeaderFooterVariant::First),
+            "even" => Some(HeaderFooterVariant::Even),
+            _ => None,
+        }
+    }
+
+    /// The OOXML w:type value
+    pub fn ooxml_name(self) -> &'static str {
+        match self {
+            HeaderFooterVariant::Default => "default",
+            HeaderFooterVariant::First => "first",
+            HeaderFooterVariant::Even => "even",
+        }
+    }
+}
+
+type StoryKey = (HeaderFooterKind, HeaderFooterVariant);
+
+/// The headers and footers a section has of its own
+#[derive(Default)]
+struct SectionStories {
+    stories: BTreeMap<StoryKey, PieceTree>,
+    different_first_page: bool,
+}
+
+/// Headers and footers of every section
+pub struct HeaderFooterManager {
+    /// By section index; sections past the end have none of their own
+    sections: Vec<SectionStories>,
+    different_odd_even: bool,
+}
+
+impl Default for HeaderFooterManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeaderFooterManager {
+    pub fn new() -> Self {
+        HeaderFooterManager {
+            sections: vec![SectionStories::default()],
+            different_odd_even: false,
+        }
+    }
+
+    /// Headers and footers from parsed parts and the section references to them
+    ///
+    /// Without sections, every header and footer belongs to one section as the
+    /// variant its type names.
+    pub fn from_ooxml(headers: &[Header], footers: &[Footer], sections: &[Section]) -> Self {
+        let single;
+        let sections = if sections.is_empty() {
+            let references = |parts: Vec<(&String, &String)>| {
+                parts
+                    .into_iter()
+                    .map(|(kind, id)| HeaderFooterReference {
+                        kind: kind.clone(),
+                        id: id.clone(),
+                    })
+                    .collect()
+            };
+            single = [Section {
+                header_references: references(headers.iter().map(|h| (&h.header_type, &h.id)).collect()),
+                footer_references: references(footers.iter().map(|f| (&f.footer_type, &f.id)).collect()),
+                ..Default::default()
+            }];
+            &single[..]
+        } else {
+            sections
+        };
+
+        let mut manager = HeaderFooterManager {
+            sections: Vec::new(),
+            different_odd_even: false,
+        };
+        for section in sections {
+            let mut stories = SectionStories {
+                different_first_page: section.title_page,
+                ..Default::default()
+            };
+            let headers = section.header_references.iter().filter_map(|reference| {
+                let header = headers.iter().find(|header| header.id == reference.id)?;
+                Some((HeaderFooterKind::Header, &reference.kind, &header.paragraphs))
+            });
+            let footers = section.footer_references.iter().filter_map(|reference| {
+                let footer = footers.iter().find(|footer| footer.id == reference.id)?;
+                Some((HeaderFooterKind::Footer, &reference.kind, &footer.paragraphs))
+            });
+            for (kind, name, paragraphs) in headers.chain(footers) {
+                if let Some(variant) = HeaderFooterVariant::from_name(name) {
+                    manager.different_odd_even |= variant == HeaderFooterVariant::Even;
+                    stories.stories.insert((kind, variant), story_from_paragraphs(paragraphs));
+                }
+            }
+            manager.sections.push(stories);
+        }
+        if manager.sections.is_empty() {
+            manager.sections.push(SectionStories::default());
+        }
+        manager
+    }
+
+    pub fn from_model(model: &DocumentModel) -> Self {
+        Self::from_ooxml(&model.headers, &model.footers, &model.sections)
+    }
+
+    /// Headers and footers as parts, and the references and first-page flag
+    /// of each section
+    ///
+    /// Parts are numbered in section order; the sections have no paragraphs.
+    pub fn to_ooxml(&self) -> (Vec<Header>, Vec<Footer>, Vec<Section>) {
+        let mut headers = Vec::new();
+        let mut footers = Vec::new();
+        let mut sections = Vec::new();
+        for stories in &self.sections {
+            let mut section = Section {
+                title_page: stories.different_first_page,
+                ..Default::default()
+            };
+            for (&(kind, variant), story) in &stories.stories {
+                let paragraphs = DocumentModel::from_piece_tree(story).paragraphs().cloned().collect();
+                let header_type = variant.ooxml_name().to_string();
+                let id = match kind {
+                    HeaderFooterKind::Header => {
+                        let id = format!("rIdHeader{}", headers.len() + 1);
+                        headers.push(Header {
+                            id: id.clone(),
+                            header_type,
+                            paragraphs,
+                            images: Vec::new(),
+                        });
+                        id
+                    }
+                    HeaderFooterKind::Footer => {
+                        let id = format!("rIdFooter{}", footers.len() + 1);
+                        footers.push(Footer {
+                            id: id.clone(),
+                            footer_type: header_type,
+                            paragraphs,
+                            images: Vec::new(),
+                        });
+                        id
+                    }
+                };
+                let reference = HeaderFooterReference {
+                    kind: variant.ooxml_name().to_string(),
+                    id,
+                };
+                match kind {
+                    HeaderFooterKind::Header => section.header_references.push(reference),
+                    HeaderFooterKind::Footer => section.footer_references.push(reference),
+                }
+            }
+            sections.push(section);
+        }
+        (headers, footers, sections)
+    }
+
+    /// Hand the headers and footers to the model, the last section covering
+    /// all of its body
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let (headers, footers, mut sections) = self.to_ooxml();
+        if let Some(last) = sections.last_mut() {
+            last.paragraph_count = model.paragraphs().count();
+        }
+        model.headers = headers;
+        model.footers = footers;
+        model.sections = sections;
+    }
+
+    /// Hash of every header and footer with its formatting, and the flags
+    /// choosing which are shown
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hash_serialized(&self.to_ooxml(), &mut hasher);
+        self.different_odd_even.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Number of sections with headers and footers of their own, or flags set
+    pub fn section_count(&self) -> usize {
+        self.sections.len()
+    }
+
+    fn section_mut(&mut self, section: usize) -> &mut SectionStories {
+        if section >= self.sections.len() {
+            self.sections.resize_with(section + 1, SectionStories::default);
+        }
+        &mut self.sections[section]
+    }
+
+    /// The section whose header or footer `section` shows: its own, or the
+    /// nearest earlier section's it is linked to
+    fn owner(&self, section: usize, key: StoryKey) -> Option<usize> {
+        (0..=section)
+            .rev()
+            .find(|&index| self.sections.get(index).is_some_and(|stories| stories.stories.contains_key(&key)))
+    }
+
+    /// The header or footer section `section` shows for `variant`, if any
+    pub fn story(&self, section: usize, kind: HeaderFooterKind, variant: HeaderFooterVariant) -> Option<&PieceTree> {
+        let owner = self.owner(section, (kind, variant))?;
+        self.sections[owner].stories.get(&(kind, variant))
+    }
+
+    /// The header or footer section `section` shows for `variant`, for editing
+    ///
+    /// A linked one is the earlier section's; one no section has yet is
+    /// created empty in the first section.
+    pub fn story_mut(&mut self, section: usize, kind: HeaderFooterKind, variant: HeaderFooterVariant) -> &mut PieceTree {
+        let key = (kind, variant);
+        // Every section before the first with one of its own is linked back to the first section
+        let owner = self.owner(section, key).unwrap_or(0);
+        self.section_mut(owner).stories.entry(key).or_insert_with(PieceTree::empty)
+    }
+
+    /// Whether section `section` shows an earlier section's header or footer
+    pub fn is_linked(&self, section: usize, kind: HeaderFooterKind, variant: HeaderFooterVariant) -> bool {
+        section > 0 && self.owner(section, (kind, variant)) != Some(section)
+    }
+
+    /// Link section `section`'s header or footer to the previous section's,
+    /// dropping its own; returns false if it was linked already
+    pub fn link_to_previous(
+        &mut self,
+        section: usize,
+        kind: HeaderFooterKind,
+        variant: HeaderFooterVariant,
+    ) -> Result<bool, HeaderFooterError> {
+        if section == 0 {
+            return Err(HeaderFooterError::NoPreviousSection);
+        }
+        Ok(self.section_mut(section).stories.remove(&(kind, variant)).is_some())
+    }
+
+    /// Give section `section` a header or footer of its own, starting as a copy
+    /// of the one it showed; returns false if it had its own already
+    pub fn unlink(&mut self, section: usize, kind: HeaderFooterKind, variant: HeaderFooterVariant) -> bool {
+        if self.owner(section, (kind, variant)) == Some(section) {
+            return false;
+        }
+        let copy = self
+            .story(section, kind, variant)
+            .map(|story| DocumentModel::from_piece_tree(story).to_piece_tree())
+            .unwrap_or_else(PieceTree::empty);
+        self.section_mut(section).stories.insert((kind, variant), copy);
+        true
+    }
+
+    pub fn different_first_page(&self, section: usize) -> bool {
+        self.sections.get(section).is_some_and(|stories| stories.different_first_page)
+    }
+
+    pub fn set_different_first_page(&mut self, section: usize, enabled: bool) {
+        self.section_mut(section).different_first_page = enabled;
+    }
+
+    pub fn different_odd_even(&self) -> bool {
+        self.different_odd_even
+    }
+
+    pub fn set_different_odd_even(&mut self, enabled: bool) {
+        self.different_odd_even = enabled;
+    }
+
+    /// The variant shown on a page of section `section`; `page_number` is the
+    /// number printed on it, which decides whether it is even
+    pub fn variant_for_page(&self, section: usize, first_in_section: bool, page_number: u32) -> HeaderFooterVariant {
+        if first_in_section && self.different_first_page(section) {
+            HeaderFooterVariant::First
+        } else if self.different_odd_even && page_number.is_multiple_of(2) {
+            HeaderFooterVariant::Even
+        } else {
+            HeaderFooterVariant::Default
+        }
+    }
+
+    /// The header or footer shown on a page of section `section`, if any
+    pub fn story_for_page(
+        &self,
+        section: usize,
+        kind: HeaderFooterKind,
+        first_in_section: bool,
+        page_number: u32,
+    ) -> Option<&PieceTree> {
+        self.story(section, kind, self.variant_for_page(section, first_in_section, page_number))
+    }
+}
+
+/// A header or footer's piece tree with the text and formatting of `paragraphs`
+fn story_from_paragraphs(paragraphs: &[Paragraph]) -> PieceTree {
+    if paragraphs.is_empty() {
+        return PieceTree::empty();
+    }
+    DocumentModel {
+        body: paragraphs.iter().cloned().map(Block::from).collect(),
+        ..Default::default()
+    }
+    .to_piece_tree()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece_tree::TextAttributes;
+
+    use HeaderFooterKind::{Footer as F, Header as H};
+    use HeaderFooterVariant::{Default as Primary, Even, First};
+
+    fn text(manager: &HeaderFooterManager, section: usize, kind: HeaderFooterKind, variant: HeaderFooterVariant) -> Option<String> {
+        manager.story(section, kind, variant).map(PieceTree::get_text)
+    }
+
+    #[test]
+    fn test_variants_for_pages() {
+        let mut manager = HeaderFooterManager::new();
+        manager.story_mut(0, H, Primary).insert(0, "Report".to_string());
+        manager.story_mut(0, H, First).insert(0, "Cover".to_string());
+        manager.story_mut(0, H, Even).insert(0, "Even".to_string());
+
+        let shown = |manager: &HeaderFooterManager, first: bool, page: u32| {
+            manager.story_for_page(0, H, first, page).map(PieceTree::get_text)
+        };
+        // Only the default variant shows until the flags are set
+        assert_eq!(shown(&manager, true, 1).as_deref(), Some("Report"));
+        assert_eq!(shown(&manager, false, 2).as_deref(), Some("Report"));
+
+        manager.set_different_first_page(0, true);
+        manager.set_different_odd_even(true);
+        assert_eq!(shown(&manager, true, 1).as_deref(), Some("Cover"));
+        assert_eq!(shown(&manager, false, 2).as_deref(), Some("Even"));
+        assert_eq!(shown(&manager, false, 3).as_deref(), Some("Report"));
+        assert!(manager.story_for_page(0, F, false, 3).is_none());
+    }
+
+    #[test]
+    fn test_link_to_previous() {
+        let mut manager = HeaderFooterManager::new();
+        manager.story_mut(0, F, Primary).insert(0, "Page".to_string());
+
+        // A later section shows the earlier one's footer, and editing it edits that one
+        assert!(manager.is_linked(2, F, Primary));
+        assert_eq!(text(&manager, 2, F, Primary).as_deref(), Some("Page"));
+        manager.story_mut(2, F, Primary).insert(4, " 1".to_string());
+        assert_eq!(text(&manager, 0, F, Primary).as_deref(), Some("Page 1"));
+
+        // Unlinking copies it; the copy changes on its own
+        assert!(manager.unlink(2, F, Primary));
+        assert!(!manager.unlink(2, F, Primary));
+        manager.story_mut(2, F, Primary).insert(0, "Appendix ".to_string());
+        assert_eq!(text(&manager, 0, F, Primary).as_deref(), Some("Page 1"));
+        assert_eq!(text(&manager, 1, F, Primary).as_deref(), Some("Page 1"));
+        assert_eq!(text(&manager, 3, F, Primary).as_deref(), Some("Appendix Page 1"));
+
+        // Each header has its own undo history
+        assert!(manager.story_mut(2, F, Primary).undo());
+        assert_eq!(text(&manager, 2, F, Primary).as_deref(), Some("Page 1"));
+        assert_eq!(manager.link_to_previous(2, F, Primary), Ok(true));
+        assert_eq!(manager.link_to_previous(0, F, Primary), Err(HeaderFooterError::NoPreviousSection));
+        assert!(manager.is_linked(2, F, Primary));
+    }
+
+    #[test]
+    fn test_ooxml_round_trip() {
+        let mut manager = HeaderFooterManager::new();
+        let bold = TextAttributes {
+            bold: Some(true),
+            ..Default::default()
+        };
+        manager.story_mut(0, H, Primary).insert_with_attrs(0, "Title\nDraft".to_string(), Some(bold));
+        manager.story_mut(0, F, First).insert(0, "Confidential".to_string());
+        manager.set_different_first_page(0, true);
+        manager.unlink(1, H, Primary);
+        manager.story_mut(1, H, Primary).insert(0, "Annex ".to_string());
+
+        let (headers, footers, sections) = manager.to_ooxml();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0].paragraphs.len(), 2);
+        assert_eq!(headers[0].paragraphs[1].runs[0].properties.bold, Some(true));
+        assert_eq!(footers[0].footer_type, "first");
+        assert!(sections[0].title_page && !sections[1].title_page);
+        assert_eq!(sections[1].header_references[0].id, headers[1].id);
+        assert!(sections[1].footer_references.is_empty());
+
+        let read = HeaderFooterManager::from_ooxml(&headers, &footers, &sections);
+        assert_eq!(read.content_hash(), manager.content_hash());
+        assert_eq!(text(&read, 1, H, Primary).as_deref(), Some("Annex Title\nDraft"));
+        assert!(read.is_linked(1, F, First) && read.different_first_page(0));
+        assert!(!read.different_odd_even());
+    }
+}
@@ -0,0 +1,359 @@
+//! # Hit Test Module
+//!
+//! Maps points on a laid-out line to caret positions in its text and back,
+//! for taps and clicks and for drawing the caret.
+//!
+//! A caret sits between two chars. Where a line wraps, or where the chars on
+//! either side are read in different directions, one offset has two places
+//! on screen; [`Affinity`] says which char the caret goes with. Char edges
+//! are measured with the line breaker that laid the line out, run by bidi
+//! run in display order, so carets land where the glyphs are drawn.
+//!
+//! Offsets here are char indices into the paragraph text, and x positions
+//! are in points from the left edge of the line.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bidi::BidiParagraph;
+use crate::line_breaking::LineBreaker;
+use crate::line_layout::ParagraphLayout;
+use crate::page_layout::Rect;
+
+/// Width of the caret, in points
+pub const CARET_WIDTH: f32 = 1.0;
+
+/// Which char a caret goes with, where its offset has two places on screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Affinity {
+    /// The char before the offset: at the end of a wrapped line rather than
+    /// the start of the next, at the trailing edge of that char
+    Upstream,
+    /// The char after the offset, at the edge it is read from
+    #[default]
+    Downstream,
+}
+
+impl Affinity {
+    /// "upstream" or "downstream"; empty for downstream
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "upstream" => Some(Affinity::Upstream),
+            "" | "downstream" => Some(Affinity::Downstream),
+            _ => None,
+        }
+    }
+}
+
+/// A caret position in the document and where it is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CaretPosition {
+    /// Char offset in the document
+    pub offset: usize,
+    pub affinity: Affinity,
+    /// Page the caret is drawn on
+    pub page: usize,
+    pub paragraph: usize,
+    /// Line of the paragraph the caret is on
+    pub line: usize,
+    /// Chars from the start of the line to the caret, in reading order
+    pub column: usize,
+    /// Caret rectangle, in points from the top left corner of the page
+    pub rect: Rect,
+    /// Whether the char the caret goes with is read right to left
+    pub rtl: bool,
+}
+
+/// A rectangle of a selection highlight
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HighlightRect {
+    pub page: usize,
+    pub paragraph: usize,
+    /// Line of the paragraph
+    pub line: usize,
+    /// In points from the top left corner of the page
+    pub rect: Rect,
+}
+
+/// A caret on a line: its offset and affinity, and where it is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineCaret {
+    /// Char offset in the paragraph
+    pub offset: usize,
+    pub affinity: Affinity,
+    /// Points from the left edge of the line
+    pub x: f32,
+    pub rtl: bool,
+}
+
+/// Where a char of a line is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CharBox {
+    /// Char index in the paragraph
+    index: usize,
+    left: f32,
+    right: f32,
+    rtl: bool,
+}
+
+impl CharBox {
+    /// The edge the char is read from
+    fn leading(&self) -> f32 {
+        if self.rtl {
+            self.right
+        } else {
+            self.left
+        }
+    }
+
+    fn trailing(&self) -> f32 {
+        if self.rtl {
+            self.left
+        } else {
+            self.right
+        }
+    }
+}
+
+/// Char range of line `line_index` of a paragraph
+pub fn line_chars(paragraph: &ParagraphLayout, line_index: usize) -> Range<usize> {
+    let Some(line) = paragraph.lines.get(line_index) else {
+        return 0..0;
+    };
+    let text = &paragraph.text;
+    char_count(text, line.start)..char_count(text, line.end)
+}
+
+/// Where the caret at char `offset` of line `line_index` is drawn
+///
+/// The caret goes with the char its affinity asks for when that char is on
+/// the line, and with the other one when it is not; the affinity returned
+/// says which it went with. A caret on an empty line is at its left edge.
+pub fn caret_on_line(
+    paragraph: &ParagraphLayout,
+    line_index: usize,
+    offset: usize,
+    affinity: Affinity,
+    breaker: &mut LineBreaker,
+) -> LineCaret {
+    let boxes = char_boxes(paragraph, line_index, breaker);
+    let find = |index: usize| boxes.iter().find(|b| b.index == index);
+    let after = find(offset).map(|b| (b.leading(), b.rtl, Affinity::Downstream));
+    let before = offset.checked_sub(1).and_then(find).map(|b| (b.trailing(), b.rtl, Affinity::Upstream));
+    let chosen = match affinity {
+        Affinity::Upstream => before.or(after),
+        Affinity::Downstream => after.or(before),
+    };
+    let (x, rtl, affinity) = chosen.unwrap_or((0.0, false, affinity));
+    LineCaret { offset, affinity, x, rtl }
+}
+
+/// The caret nearest `x` on line `line_index`
+///
+/// Between two chars read in different directions the caret goes with the
+/// one `x` is over. The end of the paragraph's last line is downstream, as
+/// no line starts there.
+pub fn caret_at_x(paragraph: &ParagraphLayout, line_index: usize, x: f32, breaker: &mut LineBreaker) -> LineCaret {
+    let boxes = char_boxes(paragraph, line_index, breaker);
+    let Some(over) = boxes.iter().position(|b| x < b.right).or(boxes.len().checked_sub(1)) else {
+        let offset = line_chars(paragraph, line_index).start;
+        return LineCaret { offset, affinity: Affinity::Downstream, x: 0.0, rtl: false };
+    };
+    let mid = (boxes[over].left + boxes[over].right) / 2.0;
+    // Chars on either side of the edge nearest x, in display order
+    let (left, right) = match x < mid {
+        true => (over.checked_sub(1).map(|i| boxes[i]), Some(boxes[over])),
+        false => (Some(boxes[over]), boxes.get(over + 1).copied()),
+    };
+    let from_left = left.map(|b| match b.rtl {
+        true => (b.index, Affinity::Downstream, b),
+        false => (b.index + 1, Affinity::Upstream, b),
+    });
+    let from_right = right.map(|b| match b.rtl {
+        true => (b.index + 1, Affinity::Upstream, b),
+        false => (b.index, Affinity::Downstream, b),
+    });
+    let (offset, mut affinity, with) = match (from_left, from_right) {
+        // Chars read the same way: one caret, which goes with the next char
+        (Some((offset, _, _)), Some((same, _, b))) if offset == same => (offset, Affinity::Downstream, b),
+        (Some(caret), Some(_)) if x >= mid => caret,
+        (_, Some(caret)) | (Some(caret), None) => caret,
+        (None, None) => unreachable!("the edge has a char on one side"),
+    };
+    if affinity == Affinity::Upstream && line_index + 1 == paragraph.lines.len() && offset == line_chars(paragraph, line_index).end {
+        affinity = Affinity::Downstream;
+    }
+    let x = match (left, right) {
+        (Some(b), _) if x >= mid => b.right,
+        (_, Some(b)) => b.left,
+        (Some(b), None) => b.right,
+        (None, None) => 0.0,
+    };
+    LineCaret { offset, affinity, x, rtl: with.rtl }
+}
+
+/// Stretches of line `line_index` that chars `range` of the paragraph cover,
+/// as (left, right) in points from the left edge of the line, left to right
+///
+/// Bidi reordering can split one range of chars into several stretches.
+pub fn selection_spans(
+    paragraph: &ParagraphLayout,
+    line_index: usize,
+    range: Range<usize>,
+    breaker: &mut LineBreaker,
+) -> Vec<(f32, f32)> {
+    let mut spans: Vec<(f32, f32)> = Vec::new();
+    let mut previous = None;
+    for (position, b) in char_boxes(paragraph, line_index, breaker).iter().enumerate() {
+        if !range.contains(&b.index) {
+            continue;
+        }
+        match spans.last_mut() {
+            Some(last) if previous == position.checked_sub(1) => last.1 = b.right,
+            _ => spans.push((b.left, b.right)),
+        }
+        previous = Some(position);
+    }
+    spans
+}
+
+/// Caret rectangle at `x` of a line whose left edge is at `left` and which
+/// spans `top` to `top + height` on its page
+pub fn caret_rect(left: f32, top: f32, height: f32, caret: &LineCaret) -> Rect {
+    Rect::new(left + caret.x, top, CARET_WIDTH, height)
+}
+
+/// Boxes of the chars of line `line_index`, in display order
+fn char_boxes(paragraph: &ParagraphLayout, line_index: usize, breaker: &mut LineBreaker) -> Vec<CharBox> {
+    let chars = line_chars(paragraph, line_index);
+    let text = &paragraph.text;
+    let runs: Vec<(Range<usize>, bool)> = match paragraph.has_bidi {
+        true => BidiParagraph::new(text, None)
+            .visual_runs(chars.clone())
+            .into_iter()
+            .map(|run| {
+                let rtl = run.is_rtl();
+                (run.range, rtl)
+            })
+            .collect(),
+        false => vec![(chars.clone(), false)],
+    };
+
+    let bytes: Vec<usize> = text.char_indices().map(|(byte, _)| byte).chain(std::iter::once(text.len())).collect();
+    let mut boxes = Vec::with_capacity(chars.len());
+    let mut x = 0.0;
+    for (range, rtl) in runs {
+        let run_start = bytes[range.start];
+        let run_text = &text[run_start..bytes[range.end]];
+        let width = breaker.calculate_text_width(run_text);
+        let first = boxes.len();
+        let mut before = 0.0;
+        for index in range {
+            let after = breaker.calculate_text_width(&text[run_start..bytes[index + 1]]);
+            let (left, right) = match rtl {
+                true => (x + width - after, x + width - before),
+                false => (x + before, x + after),
+            };
+            boxes.push(CharBox { index, left, right, rtl });
+            before = after;
+        }
+        if rtl {
+            boxes[first..].reverse();
+        }
+        x += width;
+    }
+    boxes
+}
+
+/// Chars of `text` before byte `byte`
+fn char_count(text: &str, byte: usize) -> usize {
+    text.get(..byte).map_or(0, |prefix| prefix.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_layout::LineLayout;
+    use crate::text_shaping::TextShaper;
+
+    fn layout(text: &str) -> (ParagraphLayout, LineBreaker) {
+        let mut layout = LineLayout::new();
+        layout.breaker_mut().set_shaper(TextShaper::without_font());
+        let paragraph = layout.layout_paragraph(text, 1000.0);
+        (paragraph, layout.breaker().clone())
+    }
+
+    #[test]
+    fn test_carets_of_left_to_right_text() {
+        let (paragraph, mut breaker) = layout("Hello world");
+        let width = |text: &str, breaker: &mut LineBreaker| breaker.calculate_text_width(text);
+        let x = width("Hello", &mut breaker);
+
+        let caret = caret_on_line(&paragraph, 0, 5, Affinity::Downstream, &mut breaker);
+        assert_eq!((caret.x, caret.rtl), (x, false));
+        // Upstream and downstream are in the same place between chars read the same way
+        assert_eq!(caret_on_line(&paragraph, 0, 5, Affinity::Upstream, &mut breaker).x, x);
+
+        // A tap just past the middle of the space lands after it
+        let space = width("Hello ", &mut breaker);
+        let hit = caret_at_x(&paragraph, 0, (x + space) / 2.0 + 0.1, &mut breaker);
+        assert_eq!((hit.offset, hit.affinity, hit.x), (6, Affinity::Downstream, space));
+        // Past the end, and before the start
+        let end = caret_at_x(&paragraph, 0, 5000.0, &mut breaker);
+        assert_eq!((end.offset, end.affinity), (11, Affinity::Downstream));
+        assert_eq!(caret_at_x(&paragraph, 0, -20.0, &mut breaker).offset, 0);
+    }
+
+    #[test]
+    fn test_selection_spans_follow_display_order() {
+        // "abc " then Hebrew, shown right to left after it
+        let (paragraph, mut breaker) = layout("abc \u{05d0}\u{05d1}\u{05d2}");
+        let latin = breaker.calculate_text_width("abc ");
+        let all = breaker.calculate_text_width("abc \u{05d0}\u{05d1}\u{05d2}");
+        assert_eq!(selection_spans(&paragraph, 0, 0..7, &mut breaker), [(0.0, all)]);
+        assert_eq!(selection_spans(&paragraph, 0, 1..3, &mut breaker).len(), 1);
+
+        // "c" and the first Hebrew char are apart on screen: the alef is drawn rightmost
+        let spans = selection_spans(&paragraph, 0, 2..5, &mut breaker);
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].1, spans[1].1), (latin, all));
+        assert!(selection_spans(&paragraph, 0, 9..12, &mut breaker).is_empty());
+    }
+
+    #[test]
+    fn test_carets_between_directions() {
+        // "abc " then three Hebrew letters, shown as "abc " + the letters reversed
+        let text = "abc \u{05d0}\u{05d1}\u{05d2}";
+        let (paragraph, mut breaker) = layout(text);
+        assert!(paragraph.has_bidi);
+        let latin = breaker.calculate_text_width("abc ");
+        let hebrew = breaker.calculate_text_width("\u{05d0}\u{05d1}\u{05d2}");
+
+        // Offset 4 is after the space and before alef, which is drawn at the far right
+        let upstream = caret_on_line(&paragraph, 0, 4, Affinity::Upstream, &mut breaker);
+        let downstream = caret_on_line(&paragraph, 0, 4, Affinity::Downstream, &mut breaker);
+        assert_eq!((upstream.x, upstream.rtl), (latin, false));
+        assert_eq!((downstream.x, downstream.rtl), (latin + hebrew, true));
+        // The end of the line is at the left edge of the last letter
+        let end = caret_on_line(&paragraph, 0, 7, Affinity::Downstream, &mut breaker);
+        assert_eq!((end.x, end.affinity), (latin, Affinity::Upstream));
+
+        // Tapping the right half of the space goes with the space, the left half of gimel with gimel
+        let space = caret_at_x(&paragraph, 0, latin - 0.1, &mut breaker);
+        assert_eq!((space.offset, space.affinity, space.rtl), (4, Affinity::Upstream, false));
+        let gimel = caret_at_x(&paragraph, 0, latin + 0.1, &mut breaker);
+        assert_eq!((gimel.offset, gimel.affinity, gimel.rtl), (7, Affinity::Downstream, true));
+        // The right edge of the line is before alef
+        let alef = caret_at_x(&paragraph, 0, latin + hebrew + 10.0, &mut breaker);
+        assert_eq!((alef.offset, alef.x), (4, latin + hebrew));
+
+        // Every caret found by tapping is drawn where it was found
+        for step in 0..40 {
+            let hit = caret_at_x(&paragraph, 0, step as f32 * (latin + hebrew) / 39.0, &mut breaker);
+            let drawn = caret_on_line(&paragraph, 0, hit.offset, hit.affinity, &mut breaker);
+            assert_eq!(drawn.x, hit.x, "caret {:?}", hit);
+        }
+    }
+}
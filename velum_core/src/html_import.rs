@@ -0,0 +1,1066 @@
+//! # HTML Import Module
+//!
+//! Converts HTML fragments, as browsers and other applications put them on
+//! the clipboard, into document blocks for pasting.
+//!
+//! Character formatting comes from tags (b, i, u, font, code, ...) and from
+//! style attributes, and is inherited down the element tree as CSS inherits
+//! it. Block elements start paragraphs: h1–h6 take the Heading 1–6 styles,
+//! and text-align, margins, text-indent and line-height become paragraph
+//! properties. Each outermost ul or ol becomes a new list of the destination
+//! document's numbering, with the lists nested in it as its deeper levels.
+//! Tables keep their rows, cells, column spans and row spans; a table inside
+//! a cell is flattened into tab-separated paragraphs of that cell.
+//!
+//! Whitespace collapses as a browser renders it, except in pre, and a br is
+//! a line break (U+2028) within its paragraph. The document head, scripts
+//! and styles are dropped, as is the CF_HTML header Windows puts in front of
+//! HTML on the clipboard.
+
+use crate::document_model::Block;
+use crate::numbering::{ListKind, ListNumbering};
+use crate::ooxml::{Hyperlink, Paragraph, ParagraphProperties, Run, RunProperties, Table, TableCell, TableRow};
+use crate::paste::running_paragraphs;
+
+/// Line break within a paragraph
+const LINE_BREAK: char = '\u{2028}';
+
+/// Font of pre, code, kbd, samp and tt
+const MONOSPACE_FONT: &str = "Courier New";
+
+/// Size relative font sizes are taken against when nothing sets one, in points
+const DEFAULT_FONT_SIZE: f32 = 12.0;
+
+/// Indent of a blockquote or dd, in twips
+const QUOTE_INDENT: i32 = 720;
+
+/// Deepest list level OOXML allows, from 0
+const MAX_LIST_LEVEL: usize = 8;
+
+/// Elements that start and end paragraphs
+const BLOCKS: [&str; 34] = [
+    "address", "article", "aside", "blockquote", "caption", "center", "dd", "details", "dialog", "div", "dl", "dt",
+    "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "li", "main",
+    "nav", "ol", "p", "pre", "section", "summary", "table", "ul",
+];
+
+/// Table structure elements, which end paragraphs as blocks do
+const TABLE_PARTS: [&str; 6] = ["tbody", "td", "tfoot", "th", "thead", "tr"];
+
+/// Elements without content or end tag
+const VOID: [&str; 12] = ["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "wbr"];
+
+/// Elements whose content is not shown
+const HIDDEN: [&str; 6] = ["head", "noscript", "script", "style", "template", "title"];
+
+/// Elements whose content is not markup
+const RAW_TEXT: [&str; 2] = ["script", "style"];
+
+/// Font sizes of the font element's size attribute 1–7, in points
+const FONT_SIZES: [i32; 7] = [8, 10, 12, 14, 18, 24, 36];
+
+/// Convert an HTML fragment into blocks, adding its lists to `numbering`
+pub fn import_html(html: &str, numbering: &mut ListNumbering) -> Vec<Block> {
+    let mut importer = Importer::new(numbering);
+    for token in tokenize(strip_clipboard_header(html)) {
+        match token {
+            Token::Text(text) => importer.text(&text),
+            Token::Start {
+                name,
+                attributes,
+                self_closing,
+            } => importer.start(&name, &attributes, self_closing),
+            Token::End(name) => importer.end(&name),
+        }
+    }
+    importer.finish()
+}
+
+/// Drop the "Version:0.9 StartHTML:..." header of Windows clipboard HTML
+fn strip_clipboard_header(html: &str) -> &str {
+    let trimmed = html.trim_start_matches('\u{feff}');
+    if trimmed.starts_with("Version:") {
+        trimmed.find('<').map_or("", |start| &trimmed[start..])
+    } else {
+        trimmed
+    }
+}
+
+// ==================== Tokens ====================
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Text(String),
+    Start {
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    End(String),
+}
+
+/// Split HTML into text and tags, with entities decoded
+///
+/// Comments, doctypes and processing instructions are dropped, and a '<'
+/// that does not start a tag is text.
+fn tokenize(html: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(open) = rest.find('<') {
+        text.push_str(&rest[..open]);
+        rest = &rest[open..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+
+        let closing = rest.starts_with("</");
+        let tag = &rest[if closing { 2 } else { 1 }..];
+        let Some(end) = tag.starts_with(|c: char| c.is_ascii_alphabetic()).then(|| tag_end(tag)).flatten() else {
+            text.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        if !text.is_empty() {
+            tokens.push(Token::Text(decode_entities(&std::mem::take(&mut text))));
+        }
+        let source = &tag[..end];
+        rest = &tag[end + 1..];
+
+        let name_end = source.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(source.len());
+        let name = source[..name_end].to_ascii_lowercase();
+        if closing {
+            tokens.push(Token::End(name));
+            continue;
+        }
+        if RAW_TEXT.contains(&name.as_str()) {
+            // ASCII lowercasing keeps byte offsets
+            let close = format!("</{}", name);
+            rest = &rest[rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len())..];
+        }
+        tokens.push(Token::Start {
+            self_closing: source.ends_with('/'),
+            attributes: parse_attributes(source[name_end..].trim_end_matches('/')),
+            name,
+        });
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        tokens.push(Token::Text(decode_entities(&text)));
+    }
+    tokens
+}
+
+/// Byte offset of the '>' ending a tag, skipping quoted attribute values
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '>') => return Some(i),
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if open == c => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Attributes of a start tag after its name, with lowercase names
+fn parse_attributes(source: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(first) = rest.chars().next() {
+        let name_end = rest.find(|c: char| c.is_whitespace() || c == '=' || c == '/').unwrap_or(rest.len());
+        if name_end == 0 {
+            rest = rest[first.len_utf8()..].trim_start();
+            continue;
+        }
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let quoted = &after[1..];
+                    let end = quoted.find(quote).unwrap_or(quoted.len());
+                    (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+                }
+                _ => after.split_at(after.find(char::is_whitespace).unwrap_or(after.len())),
+            };
+            value = decode_entities(raw);
+            rest = remaining.trim_start();
+        }
+        attributes.push((name, value));
+    }
+    attributes
+}
+
+/// Replace character references with their characters; unknown ones stay as written
+pub(crate) fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 32)
+            .and_then(|end| entity_char(&rest[1..end + 1]).map(|c| (c, end + 2)));
+        match entity {
+            Some((c, length)) => {
+                decoded.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn entity_char(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "shy" => '\u{ad}',
+        "ensp" => '\u{2002}',
+        "emsp" => '\u{2003}',
+        "thinsp" => '\u{2009}',
+        "zwnj" => '\u{200c}',
+        "zwj" => '\u{200d}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "hellip" => '…',
+        "bull" => '•',
+        "middot" => '·',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "deg" => '°',
+        "plusmn" => '±',
+        "times" => '×',
+        "divide" => '÷',
+        "sect" => '§',
+        "para" => '¶',
+        "cent" => '¢',
+        "pound" => '£',
+        "yen" => '¥',
+        "euro" => '€',
+        _ => return None,
+    })
+}
+
+// ==================== Import ====================
+
+/// An open element and the formatting it gives its content
+#[derive(Debug, Clone, Default)]
+struct Element {
+    name: String,
+    paragraph: ParagraphProperties,
+    run: RunProperties,
+    /// Target of the link the content is in
+    link: Option<String>,
+    preformatted: bool,
+    hidden: bool,
+}
+
+/// A table being read
+#[derive(Debug, Default)]
+struct TableBuilder {
+    table: Table,
+    row: Option<TableRow>,
+    cell: Option<TableCell>,
+    /// Grid column of the next cell of the row
+    column: usize,
+    /// Cells spanning rows below their own: grid column, columns spanned and
+    /// rows still to cover
+    spans: Vec<(usize, u32, u32)>,
+}
+
+impl TableBuilder {
+    fn start_row(&mut self, is_header: bool) {
+        self.finish_row();
+        let mut row = TableRow::default();
+        row.properties.is_header = is_header;
+        self.row = Some(row);
+        self.column = 0;
+    }
+
+    fn start_cell(&mut self, columns: u32, rows: u32) {
+        self.finish_cell();
+        if self.row.is_none() {
+            self.start_row(false);
+        }
+        self.cover_spanned();
+        let mut cell = TableCell::default();
+        if columns > 1 {
+            cell.properties.grid_span = Some(columns);
+        }
+        if rows > 1 {
+            cell.vertical_merge = Some(1);
+            self.spans.push((self.column, columns, rows - 1));
+        }
+        self.column += columns as usize;
+        self.cell = Some(cell);
+    }
+
+    /// The open cell, opening one for content outside any cell
+    fn cell_mut(&mut self) -> &mut TableCell {
+        if self.cell.is_none() {
+            self.start_cell(1, 1);
+        }
+        self.cell.get_or_insert_with(TableCell::default)
+    }
+
+    /// Add the cells continuing row spans from above at the current column
+    fn cover_spanned(&mut self) {
+        let Some(row) = self.row.as_mut() else {
+            return;
+        };
+        while let Some(span) = self.spans.iter_mut().find(|(column, _, rows)| *column == self.column && *rows > 0) {
+            span.2 -= 1;
+            let mut cell = TableCell {
+                paragraphs: vec![Paragraph::default()],
+                vertical_merge: Some(-1),
+                ..Default::default()
+            };
+            if span.1 > 1 {
+                cell.properties.grid_span = Some(span.1);
+            }
+            row.cells.push(cell);
+            self.column += span.1 as usize;
+        }
+    }
+
+    fn finish_cell(&mut self) {
+        if let Some(mut cell) = self.cell.take() {
+            // Every cell holds at least one paragraph
+            if cell.paragraphs.is_empty() {
+                cell.paragraphs.push(Paragraph::default());
+            }
+            self.row.get_or_insert_with(TableRow::default).cells.push(cell);
+        }
+    }
+
+    fn finish_row(&mut self) {
+        self.finish_cell();
+        self.cover_spanned();
+        self.spans.retain(|(_, _, rows)| *rows > 0);
+        if let Some(row) = self.row.take().filter(|row| !row.cells.is_empty()) {
+            self.table.rows.push(row);
+        }
+    }
+
+    fn finish(mut self) -> Table {
+        self.finish_row();
+        self.table
+    }
+}
+
+struct Importer<'a> {
+    numbering: &'a mut ListNumbering,
+    blocks: Vec<Block>,
+    /// Open elements, under a root that is never closed
+    open: Vec<Element>,
+    /// The paragraph being filled
+    paragraph: Option<Paragraph>,
+    /// List IDs of the open lists, outermost first
+    lists: Vec<String>,
+    tables: Vec<TableBuilder>,
+}
+
+impl<'a> Importer<'a> {
+    fn new(numbering: &'a mut ListNumbering) -> Self {
+        Importer {
+            numbering,
+            blocks: Vec::new(),
+            open: vec![Element::default()],
+            paragraph: None,
+            lists: Vec::new(),
+            tables: Vec::new(),
+        }
+    }
+
+    fn top(&self) -> &Element {
+        self.open.last().expect("the root element stays open")
+    }
+
+    fn start(&mut self, name: &str, attributes: &[(String, String)], self_closing: bool) {
+        match name {
+            "br" => return self.line_break(),
+            "hr" => return self.flush(),
+            _ if VOID.contains(&name) => return,
+            _ => {}
+        }
+        self.close_implied(name);
+        if is_block(name) {
+            self.flush();
+        }
+
+        let parent = self.top();
+        let mut element = Element {
+            name: name.to_string(),
+            ..parent.clone()
+        };
+        format_element(&mut element, parent, attributes);
+        match name {
+            "ul" | "ol" => {
+                let num_id = match self.lists.last() {
+                    Some(num_id) => num_id.clone(),
+                    None => self.numbering.add_list(if name == "ol" { ListKind::Numbered } else { ListKind::Bullet }),
+                };
+                self.lists.push(num_id);
+            }
+            "li" => {
+                if let Some(num_id) = self.lists.last() {
+                    element.paragraph.num_id = Some(num_id.clone());
+                    element.paragraph.list_level = Some((self.lists.len() - 1).min(MAX_LIST_LEVEL) as u8);
+                }
+            }
+            "table" => self.tables.push(TableBuilder::default()),
+            "tr" => {
+                let is_header = self.open.iter().any(|open| open.name == "thead");
+                if let Some(table) = self.tables.last_mut() {
+                    table.start_row(is_header);
+                }
+            }
+            "td" | "th" => {
+                let span = |name: &str| {
+                    attribute(attributes, name).and_then(|value| value.trim().parse::<u32>().ok()).unwrap_or(1).clamp(1, 63)
+                };
+                if let Some(table) = self.tables.last_mut() {
+                    table.start_cell(span("colspan"), span("rowspan"));
+                    if let Some(shading) = element.run.background_color.take() {
+                        table.cell_mut().properties.shading_color = Some(shading);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.open.push(element);
+        if self_closing {
+            self.end(name);
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        let Some(index) = self.open.iter().skip(1).rposition(|element| element.name == name) else {
+            return;
+        };
+        while self.open.len() > index + 1 {
+            let element = self.open.pop().expect("open above the root");
+            self.close(&element);
+        }
+    }
+
+    /// Close the elements a start tag ends without their end tags
+    fn close_implied(&mut self, name: &str) {
+        let within = |stops: &[&str]| self.open.iter().rev().map(|element| element.name.as_str()).find(|open| stops.contains(open));
+        let implied = match name {
+            "li" => within(&["li", "ol", "ul"]).filter(|open| *open == "li"),
+            "dt" | "dd" => within(&["dt", "dd", "dl"]).filter(|open| *open != "dl"),
+            "td" | "th" => within(&["td", "th", "tr", "table"]).filter(|open| matches!(*open, "td" | "th")),
+            "tr" => within(&["tr", "table"]).filter(|open| *open == "tr"),
+            _ if is_block(name) => (self.top().name == "p").then_some("p"),
+            _ => None,
+        };
+        if let Some(implied) = implied.map(str::to_string) {
+            self.end(&implied);
+        }
+    }
+
+    fn close(&mut self, element: &Element) {
+        if is_block(&element.name) {
+            self.flush();
+        }
+        match element.name.as_str() {
+            "ul" | "ol" => {
+                self.lists.pop();
+            }
+            "td" | "th" => {
+                if let Some(table) = self.tables.last_mut() {
+                    table.finish_cell();
+                }
+            }
+            "tr" => {
+                if let Some(table) = self.tables.last_mut() {
+                    table.finish_row();
+                }
+            }
+            "table" => {
+                let Some(table) = self.tables.pop().map(TableBuilder::finish).filter(|table| !table.rows.is_empty()) else {
+                    return;
+                };
+                let table = Block::Table(Box::new(table));
+                match self.tables.last_mut() {
+                    Some(outer) => outer.cell_mut().paragraphs.extend(running_paragraphs(&[table])),
+                    None => self.blocks.push(table),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let top = self.top();
+        if top.hidden {
+            return;
+        }
+        let text = if top.preformatted {
+            let text = text.replace("\r\n", "\n").replace(['\n', '\r'], &LINE_BREAK.to_string());
+            match &self.paragraph {
+                // A line break right after <pre> is not shown
+                None if text.starts_with(LINE_BREAK) => text[LINE_BREAK.len_utf8()..].to_string(),
+                _ => text,
+            }
+        } else {
+            let collapsed = collapse_whitespace(text);
+            let at_line_start = self
+                .paragraph
+                .as_ref()
+                .is_none_or(|paragraph| paragraph.text.is_empty() || paragraph.text.ends_with([' ', LINE_BREAK]));
+            if at_line_start {
+                collapsed.trim_start_matches(' ').to_string()
+            } else {
+                collapsed
+            }
+        };
+        if !text.is_empty() {
+            self.append(&text);
+        }
+    }
+
+    fn line_break(&mut self) {
+        if !self.top().hidden {
+            self.append(&LINE_BREAK.to_string());
+        }
+    }
+
+    /// Add text to the paragraph being filled, in the formatting of the innermost element
+    fn append(&mut self, text: &str) {
+        let top = self.open.last().expect("the root element stays open");
+        let paragraph = self.paragraph.get_or_insert_with(|| Paragraph {
+            properties: top.paragraph.clone(),
+            ..Default::default()
+        });
+        let start = paragraph.text.chars().count();
+        let length = text.chars().count();
+        paragraph.text.push_str(text);
+        match paragraph.runs.last_mut() {
+            Some(run) if run.properties == top.run => run.text.push_str(text),
+            _ => paragraph.runs.push(Run {
+                text: text.to_string(),
+                properties: top.run.clone(),
+            }),
+        }
+
+        let Some(target) = &top.link else {
+            return;
+        };
+        let (url, anchor) = match target.strip_prefix('#') {
+            Some(anchor) => (None, Some(anchor.to_string())),
+            None => (Some(target.clone()), None),
+        };
+        match paragraph.hyperlinks.last_mut() {
+            Some(link) if link.start + link.length == start && link.url == url && link.anchor == anchor => {
+                link.length += length;
+            }
+            _ => paragraph.hyperlinks.push(Hyperlink {
+                url,
+                anchor,
+                start,
+                length,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// End the paragraph being filled, adding it to the open cell or the blocks
+    fn flush(&mut self) {
+        let Some(mut paragraph) = self.paragraph.take() else {
+            return;
+        };
+        // Trailing spaces are not shown, nor is a line break ending the paragraph
+        let trim = |run: &mut Run| {
+            let trimmed = run.text.trim_end_matches(' ');
+            let trimmed = trimmed.strip_suffix(LINE_BREAK).unwrap_or(trimmed);
+            run.text.truncate(trimmed.len());
+        };
+        while let Some(run) = paragraph.runs.last_mut() {
+            trim(run);
+            if !run.text.is_empty() {
+                break;
+            }
+            paragraph.runs.pop();
+        }
+        paragraph.text = paragraph.runs.iter().map(|run| run.text.as_str()).collect();
+        let length = paragraph.text.chars().count();
+        paragraph.hyperlinks.retain_mut(|link| {
+            link.length = link.length.min(length.saturating_sub(link.start));
+            link.length > 0
+        });
+
+        match self.tables.iter_mut().rev().find_map(|table| table.cell.as_mut()) {
+            Some(cell) => cell.paragraphs.push(paragraph),
+            None => self.blocks.push(Block::from(paragraph)),
+        }
+    }
+
+    fn finish(mut self) -> Vec<Block> {
+        while self.open.len() > 1 {
+            let element = self.open.pop().expect("open above the root");
+            self.close(&element);
+        }
+        self.flush();
+        self.blocks
+    }
+}
+
+fn is_block(name: &str) -> bool {
+    BLOCKS.contains(&name) || TABLE_PARTS.contains(&name)
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+}
+
+/// Runs of HTML whitespace as one space; no-break spaces stay
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, ' ' | '\t' | '\n' | '\r' | '\u{c}') {
+            if !collapsed.ends_with(' ') {
+                collapsed.push(' ');
+            }
+        } else {
+            collapsed.push(c);
+        }
+    }
+    collapsed
+}
+
+/// Apply what an element's tag and attributes mean for the formatting of its content
+fn format_element(element: &mut Element, parent: &Element, attributes: &[(String, String)]) {
+    let name = element.name.clone();
+    let block = is_block(&name);
+    if block {
+        // Paragraph spacing is not inherited
+        element.paragraph.spacing_before = None;
+        element.paragraph.spacing_after = None;
+    }
+    let run = &mut element.run;
+    match name.as_str() {
+        "b" | "strong" | "th" => run.bold = Some(true),
+        "i" | "em" | "cite" | "dfn" | "var" | "address" => run.italic = Some(true),
+        "u" | "ins" => run.underline = Some("single".to_string()),
+        "code" | "kbd" | "samp" | "tt" => run.font_name = Some(MONOSPACE_FONT.to_string()),
+        "mark" => run.background_color = Some("FFFF00".to_string()),
+        "pre" => {
+            run.font_name = Some(MONOSPACE_FONT.to_string());
+            element.preformatted = true;
+        }
+        "font" => {
+            if let Some(color) = attribute(attributes, "color").and_then(css_color) {
+                run.color = Some(color);
+            }
+            if let Some(face) = attribute(attributes, "face").and_then(font_family) {
+                run.font_name = Some(face);
+            }
+            if let Some(size) = attribute(attributes, "size").and_then(font_element_size) {
+                run.font_size = Some(size);
+            }
+        }
+        "a" => {
+            if let Some(href) = attribute(attributes, "href").map(str::trim).filter(|href| !href.is_empty()) {
+                element.link = Some(href.to_string());
+            }
+        }
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            element.paragraph.style_id = Some(format!("Heading{}", &name[1..]));
+        }
+        "blockquote" | "dd" => {
+            element.paragraph.indent_left = Some(parent.paragraph.indent_left.unwrap_or(0) + QUOTE_INDENT);
+        }
+        "center" => element.paragraph.alignment = Some("center".to_string()),
+        _ if HIDDEN.contains(&name.as_str()) => element.hidden = true,
+        _ => {}
+    }
+    if block {
+        if let Some(alignment) = attribute(attributes, "align").and_then(alignment) {
+            element.paragraph.alignment = Some(alignment.to_string());
+        }
+    }
+    if let Some(style) = attribute(attributes, "style") {
+        apply_style(element, parent, style, block);
+    }
+}
+
+/// Apply the declarations of a style attribute; paragraph ones only apply to blocks
+fn apply_style(element: &mut Element, parent: &Element, style: &str, block: bool) {
+    let font_size = parent.run.font_size.map_or(DEFAULT_FONT_SIZE, |size| size as f32);
+    for declaration in style.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let property = property.trim().to_ascii_lowercase();
+        let value = value.trim().trim_end_matches("!important").trim();
+        let lower = value.to_ascii_lowercase();
+        let run = &mut element.run;
+        match property.as_str() {
+            "font-weight" => {
+                run.bold = match lower.as_str() {
+                    "bold" | "bolder" => Some(true),
+                    "normal" | "lighter" => Some(false),
+                    weight => weight.parse::<u32>().ok().map(|weight| weight >= 600).or(run.bold),
+                }
+            }
+            "font-style" => run.italic = Some(lower == "italic" || lower == "oblique"),
+            "text-decoration" | "text-decoration-line" => {
+                run.underline = Some(if lower.contains("underline") { "single" } else { "none" }.to_string());
+            }
+            "font-size" => run.font_size = css_font_size(&lower, font_size).or(run.font_size),
+            "font-family" => run.font_name = font_family(value).or(run.font_name.take()),
+            "color" => run.color = css_color(value).or(run.color.take()),
+            "background-color" | "background" => {
+                if lower == "transparent" || lower == "none" {
+                    run.background_color = parent.run.background_color.clone();
+                } else if let Some(color) = css_color(value) {
+                    run.background_color = Some(color);
+                }
+            }
+            _ if !block => {}
+            "text-align" => {
+                if let Some(alignment) = alignment(&lower) {
+                    element.paragraph.alignment = Some(alignment.to_string());
+                }
+            }
+            "margin" => {
+                // One to four values: top, right, bottom, left
+                let sides: Vec<Option<i32>> = lower.split_whitespace().map(|side| css_twips(side, font_size)).collect();
+                let [top, right, bottom, left] = match sides[..] {
+                    [all] => [all; 4],
+                    [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+                    [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+                    [top, right, bottom, left, ..] => [top, right, bottom, left],
+                    [] => continue,
+                };
+                set_margins(&mut element.paragraph, &parent.paragraph, [top, right, bottom, left]);
+            }
+            "margin-top" => set_margins(&mut element.paragraph, &parent.paragraph, [css_twips(&lower, font_size), None, None, None]),
+            "margin-right" => set_margins(&mut element.paragraph, &parent.paragraph, [None, css_twips(&lower, font_size), None, None]),
+            "margin-bottom" => set_margins(&mut element.paragraph, &parent.paragraph, [None, None, css_twips(&lower, font_size), None]),
+            "margin-left" => set_margins(&mut element.paragraph, &parent.paragraph, [None, None, None, css_twips(&lower, font_size)]),
+            "text-indent" => {
+                if let Some(indent) = css_twips(&lower, font_size) {
+                    element.paragraph.indent_first_line = Some(indent);
+                }
+            }
+            "line-height" => {
+                if let Some(lines) = line_height(&lower) {
+                    element.paragraph.spacing_line = Some((lines * 240.0).round() as i32);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Set the margins given, in twips; side margins add to those of the enclosing blocks
+fn set_margins(paragraph: &mut ParagraphProperties, parent: &ParagraphProperties, [top, right, bottom, left]: [Option<i32>; 4]) {
+    if let Some(top) = top {
+        paragraph.spacing_before = Some(top.max(0));
+    }
+    if let Some(bottom) = bottom {
+        paragraph.spacing_after = Some(bottom.max(0));
+    }
+    if let Some(left) = left {
+        paragraph.indent_left = Some(parent.indent_left.unwrap_or(0) + left);
+    }
+    if let Some(right) = right {
+        paragraph.indent_right = Some(parent.indent_right.unwrap_or(0) + right);
+    }
+}
+
+/// OOXML alignment of a CSS text-align or align value
+fn alignment(value: &str) -> Option<&'static str> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "left" | "start" => Some("left"),
+        "right" | "end" => Some("right"),
+        "center" | "middle" => Some("center"),
+        "justify" => Some("both"),
+        _ => None,
+    }
+}
+
+/// A CSS length in twips; `font_size` in points sizes em units
+fn css_twips(value: &str, font_size: f32) -> Option<i32> {
+    let value = value.trim();
+    let unit = value.find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+'))).unwrap_or(value.len());
+    let number: f32 = value[..unit].parse().ok()?;
+    let points = match &value[unit..] {
+        "pt" => number,
+        "px" => number * 0.75,
+        "pc" => number * 12.0,
+        "in" => number * 72.0,
+        "cm" => number * 72.0 / 2.54,
+        "mm" => number * 72.0 / 25.4,
+        "em" | "rem" => number * font_size,
+        "" if number == 0.0 => 0.0,
+        _ => return None,
+    };
+    Some((points * 20.0).round() as i32)
+}
+
+/// A CSS font size in whole points; relative sizes are taken against `inherited`
+fn css_font_size(value: &str, inherited: f32) -> Option<i32> {
+    let points = match value {
+        "xx-small" => 7.0,
+        "x-small" => 7.5,
+        "small" => 10.0,
+        "medium" => 12.0,
+        "large" => 13.5,
+        "x-large" => 18.0,
+        "xx-large" => 24.0,
+        "smaller" => inherited / 1.2,
+        "larger" => inherited * 1.2,
+        _ => match value.strip_suffix('%') {
+            Some(percent) => inherited * percent.trim().parse::<f32>().ok()? / 100.0,
+            None => css_twips(value, inherited)? as f32 / 20.0,
+        },
+    };
+    Some(points.round() as i32).filter(|size| *size > 0)
+}
+
+/// Font size of a font element's size attribute: 1–7, or relative to 3 with a sign
+fn font_element_size(value: &str) -> Option<i32> {
+    let value = value.trim();
+    let size = match value.strip_prefix(['+', '-']) {
+        Some(delta) => {
+            let delta: i32 = delta.parse().ok()?;
+            if value.starts_with('-') {
+                3 - delta
+            } else {
+                3 + delta
+            }
+        }
+        None => value.parse().ok()?,
+    };
+    Some(FONT_SIZES[(size.clamp(1, 7) - 1) as usize])
+}
+
+/// The first family of a CSS font-family list, with generic families as common fonts
+fn font_family(value: &str) -> Option<String> {
+    let family = value.split(',').next()?.trim().trim_matches(['"', '\'']).trim();
+    let family = match family.to_ascii_lowercase().as_str() {
+        "" => return None,
+        "monospace" => MONOSPACE_FONT,
+        "serif" => "Times New Roman",
+        "sans-serif" | "system-ui" => "Arial",
+        _ => family,
+    };
+    Some(family.to_string())
+}
+
+/// Line spacing in lines of a CSS line-height; lengths are left to the style
+fn line_height(value: &str) -> Option<f32> {
+    let lines = match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f32>().ok()? / 100.0,
+        None => value.parse::<f32>().ok()?,
+    };
+    (lines > 0.0).then_some(lines)
+}
+
+/// Hex RGB of a CSS color, as OOXML writes it; None for transparent
+fn css_color(value: &str) -> Option<String> {
+    let value = value.trim().to_ascii_lowercase();
+    if let Some(hex) = value.strip_prefix('#') {
+        let hex: String = match hex.len() {
+            3 => hex.chars().flat_map(|c| [c, c]).collect(),
+            6 => hex.to_string(),
+            _ => return None,
+        };
+        return hex.chars().all(|c| c.is_ascii_hexdigit()).then(|| hex.to_ascii_uppercase());
+    }
+    if let Some(arguments) = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))
+        .and_then(|arguments| arguments.strip_suffix(')'))
+    {
+        let channels: Vec<&str> =
+            arguments.split([',', ' ', '/']).map(str::trim).filter(|channel| !channel.is_empty()).collect();
+        if channels.len() < 3 || channels.get(3).is_some_and(|alpha| alpha.trim_end_matches('%').parse::<f32>().ok() == Some(0.0)) {
+            return None;
+        }
+        let mut hex = String::with_capacity(6);
+        for channel in &channels[..3] {
+            let level = match channel.strip_suffix('%') {
+                Some(percent) => percent.parse::<f32>().ok()? * 2.55,
+                None => channel.parse::<f32>().ok()?,
+            };
+            hex.push_str(&format!("{:02X}", level.round().clamp(0.0, 255.0) as u8));
+        }
+        return Some(hex);
+    }
+    let named = match value.as_str() {
+        "black" => "000000",
+        "white" => "FFFFFF",
+        "red" => "FF0000",
+        "green" => "008000",
+        "lime" => "00FF00",
+        "blue" => "0000FF",
+        "navy" => "000080",
+        "yellow" => "FFFF00",
+        "orange" => "FFA500",
+        "purple" => "800080",
+        "fuchsia" | "magenta" => "FF00FF",
+        "aqua" | "cyan" => "00FFFF",
+        "teal" => "008080",
+        "olive" => "808000",
+        "maroon" => "800000",
+        "gray" | "grey" => "808080",
+        "silver" => "C0C0C0",
+        _ => return None,
+    };
+    Some(named.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn import(html: &str) -> Vec<Block> {
+        import_html(html, &mut ListNumbering::new())
+    }
+
+    fn paragraphs(blocks: &[Block]) -> Vec<&Paragraph> {
+        blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Paragraph(paragraph) => Some(paragraph.as_ref()),
+                Block::Table(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_browser_fragment_formatting() {
+        let html = "Version:0.9\r\nStartHTML:0000000105\r\n<html><head><title>Copied</title><style>p { color: red }</style></head>\
+            <body><!--StartFragment--><h2>Release  notes</h2>\n\
+            <p style=\"text-align: center; margin-left: 36pt; line-height: 1.5\">Fish &amp; <b>chips</b> and\n  \
+            <span style=\"color: rgb(255, 0, 0); font-size: 16px; font-family: 'Segoe UI', sans-serif\">red&nbsp;text</span><br>\
+            <a href=\"https://example.com\">a <i>link</i></a></p><!--EndFragment--></body></html>";
+        let blocks = import(html);
+        let paragraphs = paragraphs(&blocks);
+        assert_eq!(paragraphs.len(), 2);
+
+        assert_eq!(paragraphs[0].text, "Release notes");
+        assert_eq!(paragraphs[0].properties.style_id.as_deref(), Some("Heading2"));
+
+        let body = paragraphs[1];
+        assert_eq!(body.text, "Fish & chips and red\u{a0}text\u{2028}a link");
+        assert_eq!(body.properties.alignment.as_deref(), Some("center"));
+        assert_eq!(body.properties.indent_left, Some(720));
+        assert_eq!(body.properties.spacing_line, Some(360));
+        let runs: Vec<&str> = body.runs.iter().map(|run| run.text.as_str()).collect();
+        assert_eq!(runs, ["Fish & ", "chips", " and ", "red\u{a0}text", "\u{2028}a ", "link"]);
+        assert_eq!(body.runs[1].properties.bold, Some(true));
+        let red = &body.runs[3].properties;
+        assert_eq!(red.color.as_deref(), Some("FF0000"));
+        assert_eq!(red.font_size, Some(12));
+        assert_eq!(red.font_name.as_deref(), Some("Segoe UI"));
+        assert_eq!(body.runs[5].properties.italic, Some(true));
+        assert_eq!(body.hyperlinks.len(), 1);
+        assert_eq!(body.hyperlinks[0].url.as_deref(), Some("https://example.com"));
+        assert_eq!((body.hyperlinks[0].start, body.hyperlinks[0].length), (26, 6));
+    }
+
+    #[test]
+    fn test_nested_lists_share_one_list() {
+        let mut numbering = ListNumbering::new();
+        let html = "<ol><li>One<li>Two<ul><li>Two a</li></ul></li><li>Three</li></ol><ul><li>Other</li></ul><p>After</p>";
+        let blocks = import_html(html, &mut numbering);
+        let paragraphs = paragraphs(&blocks);
+        let items: Vec<(&str, Option<&str>, Option<u8>)> = paragraphs
+            .iter()
+            .map(|p| (p.text.as_str(), p.properties.num_id.as_deref(), p.properties.list_level))
+            .collect();
+        let numbered = items[0].1.expect("the items are in a list");
+        let bulleted = items[4].1.expect("the items are in a list");
+        assert_ne!(numbered, bulleted);
+        assert_eq!(
+            items,
+            [
+                ("One", Some(numbered), Some(0)),
+                ("Two", Some(numbered), Some(0)),
+                ("Two a", Some(numbered), Some(1)),
+                ("Three", Some(numbered), Some(0)),
+                ("Other", Some(bulleted), Some(0)),
+                ("After", None, None),
+            ]
+        );
+        assert_eq!(numbering.kind(numbered), Some(ListKind::Numbered));
+        assert_eq!(numbering.kind(bulleted), Some(ListKind::Bullet));
+    }
+
+    #[test]
+    fn test_table_spans_and_nested_tables() {
+        let html = "<table><thead><tr><th colspan=2>Head</th></tr></thead>\
+            <tr><td rowspan=\"2\" style=\"background-color:#ffcc00\">Tall</td><td>B</td>\
+            <tr><td><table><tr><td>x</td><td>y</td></tr></table></td></table><p>Below</p>";
+        let blocks = import(html);
+        assert_eq!(blocks.len(), 2);
+        let Block::Table(table) = &blocks[0] else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.rows.len(), 3);
+        assert!(table.rows[0].properties.is_header);
+        assert_eq!(table.rows[0].cells[0].properties.grid_span, Some(2));
+        assert_eq!(table.rows[0].cells[0].paragraphs[0].runs[0].properties.bold, Some(true));
+
+        let tall = &table.rows[1].cells[0];
+        assert_eq!(tall.vertical_merge, Some(1));
+        assert_eq!(tall.properties.shading_color.as_deref(), Some("FFCC00"));
+        assert_eq!(tall.paragraphs[0].runs[0].properties.background_color, None);
+        assert_eq!(table.rows[2].cells.len(), 2);
+        assert_eq!(table.rows[2].cells[0].vertical_merge, Some(-1));
+        assert_eq!(table.rows[2].cells[1].paragraphs[0].text, "x\ty");
+        assert!(matches!(&blocks[1], Block::Paragraph(paragraph) if paragraph.text == "Below"));
+    }
+
+    #[test]
+    fn test_whitespace_and_preformatted_text() {
+        let blocks = import("<div>  a <span> b </span>\n c  </div><pre>\nfn main() {\n    x  y\n}</pre><p><br></p>1 &lt; 2 &#x263A; &bogus;");
+        let texts: Vec<&str> = paragraphs(&blocks).iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(texts, ["a b c", "fn main() {\u{2028}    x  y\u{2028}}", "", "1 < 2 ☺ &bogus;"]);
+        let code = paragraphs(&blocks)[1];
+        assert_eq!(code.runs[0].properties.font_name.as_deref(), Some(MONOSPACE_FONT));
+    }
+}
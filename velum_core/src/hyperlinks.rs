@@ -0,0 +1,324 @@
+//! # Hyperlinks Module
+//!
+//! Links over ranges of editor text, to a URL or to a bookmark of the
+//! document.
+//!
+//! A link covers chars of the whole text and moves with edits like a comment
+//! anchor: typing inside it extends it, typing at either end does not, and a
+//! link whose text is all deleted goes away. Links do not overlap; linking
+//! text that is already linked replaces the old link there. In OOXML each
+//! paragraph's part of a link is a w:hyperlink element, whose URL is an
+//! external relationship of the document part.
+
+use std::ops::Range;
+
+use crate::comments::move_anchor;
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{Hyperlink, Paragraph};
+
+/// Hyperlink errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum HyperlinkError {
+    #[error("A hyperlink needs a URL or a bookmark to go to")]
+    MissingTarget,
+
+    #[error("A hyperlink needs some text to link")]
+    EmptyRange,
+
+    #[error("No hyperlink at offset {0}")]
+    NotFound(usize),
+}
+
+/// The hyperlinks of a document, in text order
+#[derive(Debug, Clone, Default)]
+pub struct HyperlinkSet {
+    hyperlinks: Vec<Hyperlink>,
+}
+
+impl HyperlinkSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hyperlinks of `paragraphs`, each given with the char offset it starts
+    /// at, with offsets into the whole text
+    pub fn from_paragraphs<'a>(paragraphs: impl IntoIterator<Item = (usize, &'a Paragraph)>) -> Self {
+        let mut hyperlinks = Vec::new();
+        for (paragraph_start, paragraph) in paragraphs {
+            hyperlinks.extend(paragraph.hyperlinks.iter().map(|hyperlink| Hyperlink {
+                start: paragraph_start + hyperlink.start,
+                ..hyperlink.clone()
+            }));
+        }
+        hyperlinks.sort_by_key(|hyperlink| hyperlink.start);
+        HyperlinkSet { hyperlinks }
+    }
+
+    /// Hyperlinks of the model's body paragraphs
+    pub fn from_model(model: &DocumentModel) -> Self {
+        Self::from_paragraphs(model.located_paragraphs())
+    }
+
+    /// Hand the hyperlinks to the model's body paragraphs
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let mut paragraph_start = 0;
+        for block in model.body.iter_mut() {
+            let Block::Paragraph(paragraph) = block else {
+                continue;
+            };
+            let length = paragraph.text.chars().count();
+            paragraph.hyperlinks = paragraph_hyperlinks(&self.hyperlinks, paragraph_start, length);
+            paragraph_start += length + 1;
+        }
+    }
+
+    pub fn hyperlinks(&self) -> &[Hyperlink] {
+        &self.hyperlinks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hyperlinks.is_empty()
+    }
+
+    /// The hyperlink over the char at `offset`
+    pub fn at(&self, offset: usize) -> Option<&Hyperlink> {
+        self.hyperlinks
+            .iter()
+            .find(|hyperlink| hyperlink.start <= offset && offset < hyperlink.start + hyperlink.length)
+    }
+
+    /// Link chars `range` to `url` and/or the bookmark `anchor`
+    ///
+    /// Links already over part of the range are cut back to make room.
+    pub fn insert(
+        &mut self,
+        range: Range<usize>,
+        url: Option<&str>,
+        anchor: Option<&str>,
+        tooltip: Option<&str>,
+    ) -> Result<&Hyperlink, HyperlinkError> {
+        if range.is_empty() {
+            return Err(HyperlinkError::EmptyRange);
+        }
+        let mut hyperlink = Hyperlink {
+            start: range.start,
+            length: range.len(),
+            ..Default::default()
+        };
+        set_target(&mut hyperlink, url, anchor, tooltip)?;
+
+        self.clear(range);
+        let index = self.hyperlinks.partition_point(|other| other.start < hyperlink.start);
+        self.hyperlinks.insert(index, hyperlink);
+        Ok(&self.hyperlinks[index])
+    }
+
+    /// Point the hyperlink at `offset` somewhere else
+    pub fn edit(
+        &mut self,
+        offset: usize,
+        url: Option<&str>,
+        anchor: Option<&str>,
+        tooltip: Option<&str>,
+    ) -> Result<&Hyperlink, HyperlinkError> {
+        let index = self.index_at(offset).ok_or(HyperlinkError::NotFound(offset))?;
+        let hyperlink = &mut self.hyperlinks[index];
+        set_target(hyperlink, url, anchor, tooltip)?;
+        hyperlink.visited = false;
+        Ok(hyperlink)
+    }
+
+    /// Remove the whole hyperlink at `offset`, leaving its text alone
+    pub fn remove_at(&mut self, offset: usize) -> Option<Hyperlink> {
+        self.index_at(offset).map(|index| self.hyperlinks.remove(index))
+    }
+
+    /// Unlink chars `range`, cutting back or splitting links that reach past
+    /// it; returns the number of links touched
+    pub fn clear(&mut self, range: Range<usize>) -> usize {
+        let mut touched = 0;
+        let mut kept = Vec::with_capacity(self.hyperlinks.len());
+        for hyperlink in self.hyperlinks.drain(..) {
+            let end = hyperlink.start + hyperlink.length;
+            if end <= range.start || hyperlink.start >= range.end {
+                kept.push(hyperlink);
+                continue;
+            }
+            touched += 1;
+            if hyperlink.start < range.start {
+                kept.push(Hyperlink {
+                    length: range.start - hyperlink.start,
+                    ..hyperlink.clone()
+                });
+            }
+            if end > range.end {
+                kept.push(Hyperlink {
+                    start: range.end,
+                    length: end - range.end,
+                    ..hyperlink
+                });
+            }
+        }
+        self.hyperlinks = kept;
+        touched
+    }
+
+    /// Mark the hyperlink at `offset` as followed; false if there is none
+    pub fn mark_visited(&mut self, offset: usize) -> bool {
+        match self.index_at(offset) {
+            Some(index) => {
+                self.hyperlinks[index].visited = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Move the hyperlinks past an edit, see [`move_anchor`]; one whose text is all deleted goes
+    pub fn apply_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        for hyperlink in self.hyperlinks.iter_mut() {
+            (hyperlink.start, hyperlink.length) =
+                move_anchor(hyperlink.start, hyperlink.length, offset, removed, inserted);
+        }
+        self.hyperlinks.retain(|hyperlink| hyperlink.length > 0);
+    }
+
+    fn index_at(&self, offset: usize) -> Option<usize> {
+        self.hyperlinks
+            .iter()
+            .position(|hyperlink| hyperlink.start <= offset && offset < hyperlink.start + hyperlink.length)
+    }
+}
+
+/// Set where a hyperlink goes; a URL without a scheme gets the one Word would give it
+fn set_target(
+    hyperlink: &mut Hyperlink,
+    url: Option<&str>,
+    anchor: Option<&str>,
+    tooltip: Option<&str>,
+) -> Result<(), HyperlinkError> {
+    let url = url.map(str::trim).filter(|url| !url.is_empty());
+    let anchor = anchor.map(str::trim).filter(|anchor| !anchor.is_empty());
+    if url.is_none() && anchor.is_none() {
+        return Err(HyperlinkError::MissingTarget);
+    }
+    hyperlink.url = url.map(normalize_url);
+    hyperlink.anchor = anchor.map(str::to_string);
+    hyperlink.tooltip = tooltip.filter(|tooltip| !tooltip.is_empty()).map(str::to_string);
+    Ok(())
+}
+
+/// "www.example.com" becomes "http://www.example.com" and "ann@example.com" a mailto: link
+pub fn normalize_url(url: &str) -> String {
+    let has_scheme = url
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)));
+    if has_scheme || url.starts_with('/') || url.starts_with('\\') {
+        url.to_string()
+    } else if url.contains('@') && !url.contains('/') {
+        format!("mailto:{}", url)
+    } else {
+        format!("http://{}", url)
+    }
+}
+
+/// The parts of `hyperlinks` inside the paragraph of `length` chars at `start`, relative to it
+pub(crate) fn paragraph_hyperlinks(hyperlinks: &[Hyperlink], start: usize, length: usize) -> Vec<Hyperlink> {
+    let end = start + length;
+    hyperlinks
+        .iter()
+        .filter_map(|hyperlink| {
+            let from = hyperlink.start.max(start);
+            let to = (hyperlink.start + hyperlink.length).min(end);
+            (from < to).then(|| Hyperlink {
+                start: from - start,
+                length: to - from,
+                ..hyperlink.clone()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_model::located;
+
+    fn spans(links: &HyperlinkSet) -> Vec<(usize, usize, Option<&str>)> {
+        links
+            .hyperlinks()
+            .iter()
+            .map(|link| (link.start, link.length, link.url.as_deref().or(link.anchor.as_deref())))
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_replaces_overlapped_links() {
+        let mut links = HyperlinkSet::new();
+        assert_eq!(links.insert(3..3, Some("a.com"), None, None).unwrap_err(), HyperlinkError::EmptyRange);
+        assert_eq!(links.insert(0..3, Some("  "), None, None).unwrap_err(), HyperlinkError::MissingTarget);
+
+        links.insert(0..20, Some("www.example.com"), None, Some("Example")).unwrap();
+        links.insert(5..10, None, Some("Intro"), None).unwrap();
+        assert_eq!(
+            spans(&links),
+            vec![
+                (0, 5, Some("http://www.example.com")),
+                (5, 5, Some("Intro")),
+                (10, 10, Some("http://www.example.com")),
+            ]
+        );
+        assert_eq!(links.at(12).unwrap().tooltip.as_deref(), Some("Example"));
+
+        links.edit(6, Some("ann@example.com"), None, None).unwrap();
+        assert_eq!(links.at(9).unwrap().url.as_deref(), Some("mailto:ann@example.com"));
+        assert!(links.remove_at(9).is_some());
+        assert!(links.at(7).is_none());
+        assert_eq!(links.edit(7, Some("x.org"), None, None).unwrap_err(), HyperlinkError::NotFound(7));
+    }
+
+    #[test]
+    fn test_links_move_with_edits() {
+        let mut links = HyperlinkSet::new();
+        links.insert(4..8, Some("https://a.org"), None, None).unwrap();
+        links.insert(10..12, Some("https://b.org"), None, None).unwrap();
+
+        // Typing at the end does not extend the link; typing inside does
+        links.apply_edit(8, 0, 3);
+        links.apply_edit(5, 0, 1);
+        assert_eq!(spans(&links), vec![(4, 5, Some("https://a.org")), (14, 2, Some("https://b.org"))]);
+
+        // Deleting all of a link's text removes it
+        links.apply_edit(13, 4, 0);
+        assert_eq!(spans(&links), vec![(4, 5, Some("https://a.org"))]);
+        assert!(links.mark_visited(6));
+        assert!(links.hyperlinks()[0].visited);
+    }
+
+    #[test]
+    fn test_paragraph_round_trip() {
+        let mut links = HyperlinkSet::new();
+        // "world\nOld" spans the paragraph break at 11
+        links.insert(6..15, Some("https://example.com"), None, None).unwrap();
+
+        let first = paragraph_hyperlinks(links.hyperlinks(), 0, 11);
+        let second = paragraph_hyperlinks(links.hyperlinks(), 12, 8);
+        assert_eq!((first[0].start, first[0].length), (6, 5));
+        assert_eq!((second[0].start, second[0].length), (0, 3));
+
+        let paragraphs = [
+            Paragraph {
+                text: "Hello world".to_string(),
+                hyperlinks: first,
+                ..Default::default()
+            },
+            Paragraph {
+                text: "Old text".to_string(),
+                hyperlinks: second,
+                ..Default::default()
+            },
+        ];
+        let read = HyperlinkSet::from_paragraphs(located(&paragraphs));
+        assert_eq!(spans(&read), vec![(6, 5, Some("https://example.com")), (12, 3, Some("https://example.com"))]);
+    }
+}
@@ -2,9 +2,10 @@
 //! Provides image loading, caching, scaling, anchoring, and text wrapping functionality.
 //!
 //! # Features
-//! - PNG, JPEG, GIF, BMP, WebP image format support
+//! - PNG, JPEG, GIF, BMP, WebP image format support, decoding to RGBA pixels
+//! - SVG, EMF and WMF vector pictures, rasterized to RGBA pixels
 //! - OOXML relationship-based image loading
-//! - Image caching for performance
+//! - Image caching with least recently used eviction, shareable between threads
 //! - Flexible scaling modes (None, Exact, Percentage, FitToContainer)
 //! - Multiple anchoring types (Inline, Floating)
 //! - Text wrapping support (Square, Tight, Through, TopBottom, Behind, InFront)
@@ -12,13 +13,20 @@
 //! # Example
 //!
 //! ```rust
-//! use velum_core::image::{ImageCache, ScaleMode, WrapType};
+//! use velum_core::image::{ImageCache, ImageError};
+//! use velum_core::ooxml::ContentType;
+//! use velum_core::raster::{Canvas, Color};
 //!
+//! # fn main() -> Result<(), ImageError> {
+//! # let data = Canvas::new(4, 2, Color::WHITE).to_png();
 //! // Create image cache
 //! let mut cache = ImageCache::new();
 //!
 //! // Load image from OOXML package
-//! let image = cache.load_from_ooxml(b"...", "image/png", "word/media/image1.png")?;
+//! let image = cache.load_from_ooxml(&data, ContentType::ImagePng, "word/media/image1.png".to_string())?;
+//! assert_eq!((image.dimensions.width, image.dimensions.height), (4.0, 2.0));
+//! # Ok(())
+//! # }
 //! ```
 //!
 //! # OOXML Image Relationships
@@ -32,12 +40,15 @@
 
 use std::collections::HashMap;
 use std::fmt;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, RwLock};
+use std::thread::JoinHandle;
 use serde::{Deserialize, Serialize};
 use log::debug;
 use once_cell::sync::Lazy;
 
-use crate::ooxml::{ContentType, Relationship, RelationshipType, DocumentImage, PackagePart};
+use crate::metafile;
+use crate::ooxml::{ContentType, Relationship, RelationshipType, DocumentImage, ImageTransform, PackagePart};
 
 /// EMU (English Metric Unit) conversion constants
 /// 1 inch = 914400 EMUs
@@ -416,6 +427,9 @@ pub struct RenderedImage {
     pub alt_text: Option<String>,
     /// Opacity (0.0 to 1.0)
     pub opacity: f32,
+    /// Crop, rotation, flips and adjustments of the picture
+    #[serde(default)]
+    pub transform: ImageTransform,
 }
 
 impl Default for RenderedImage {
@@ -433,6 +447,7 @@ impl Default for RenderedImage {
             visible: true,
             alt_text: None,
             opacity: 1.0,
+            transform: ImageTransform::default(),
         }
     }
 }
@@ -458,12 +473,55 @@ impl RenderedImage {
             visible: true,
             alt_text: None,
             opacity: 1.0,
+            transform: ImageTransform::default(),
         }
     }
 
-    /// Calculate the bounding rectangle for this image
+    /// The image drawn with `transform`, at opacity matching its transparency
+    pub fn with_transform(mut self, transform: ImageTransform) -> Self {
+        self.opacity = 1.0 - transform.transparency / 100.0;
+        self.transform = transform;
+        self
+    }
+
+    /// Calculate the bounding rectangle for this image, turned as it is drawn
     pub fn bounding_rect(&self) -> Rect {
-        Rect::from_point_size(self.position, self.size)
+        let frame = Rect::from_point_size(self.position, self.size);
+        if self.transform.rotation == 0.0 {
+            return frame;
+        }
+        let corners = self.corners();
+        let (left, right) = corners.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(l, r), p| (l.min(p.x), r.max(p.x)));
+        let (top, bottom) = corners.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(t, b), p| (t.min(p.y), b.max(p.y)));
+        Rect::new(left, top, right - left, bottom - top)
+    }
+
+    /// Where the top left, top right, bottom right and bottom left corners of
+    /// the cropped picture are drawn, after flipping and turning the frame
+    /// about its center
+    pub fn corners(&self) -> [Point; 4] {
+        let center = Point::new(self.position.x + self.size.width / 2.0, self.position.y + self.size.height / 2.0);
+        let (sin, cos) = self.transform.rotation.to_radians().sin_cos();
+        let flip_x = if self.transform.flip_horizontal { -1.0 } else { 1.0 };
+        let flip_y = if self.transform.flip_vertical { -1.0 } else { 1.0 };
+        let (half_width, half_height) = (self.size.width / 2.0, self.size.height / 2.0);
+        [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(dx, dy)| {
+            let (x, y) = (dx * half_width * flip_x, dy * half_height * flip_y);
+            // Clockwise on the page, whose y grows downwards
+            Point::new(center.x + x * cos - y * sin, center.y + x * sin + y * cos)
+        })
+    }
+
+    /// The part of the source picture drawn, in its own units
+    pub fn source_rect(&self) -> Rect {
+        let crop = self.transform.crop.unwrap_or_default();
+        let (width, height) = self.transform.visible_share();
+        Rect::new(
+            self.source_size.width * crop.left / 100.0,
+            self.source_size.height * crop.top / 100.0,
+            self.source_size.width * width,
+            self.source_size.height * height,
+        )
     }
 }
 
@@ -534,10 +592,11 @@ impl Rect {
             && self.bottom() > other.top()
     }
 
-    /// Check if a point is inside this rectangle
+    /// Check if a point is inside this rectangle; the right and bottom edges
+    /// belong to the next rectangle over
     pub fn contains(&self, point: Point) -> bool {
-        point.x >= self.left() && point.x <= self.right()
-            && point.y >= self.top() && point.y <= self.bottom()
+        point.x >= self.left() && point.x < self.right()
+            && point.y >= self.top() && point.y < self.bottom()
     }
 
     /// Get the expanded rectangle with wrap distance applied
@@ -574,6 +633,32 @@ pub struct ImageData {
     pub color_type: ColorType,
 }
 
+impl ImageData {
+    /// Read an image's format and dimensions from its file data
+    pub fn decode(data: Vec<u8>) -> Result<Self, ImageError> {
+        let format = ImageFormat::from_magic_bytes(&data);
+        if format == ImageFormat::Unknown {
+            return Err(ImageError::UnknownFormat);
+        }
+        Self::with_format(data, format)
+    }
+
+    /// Read the dimensions of an image of `format` from its file data
+    fn with_format(data: Vec<u8>, format: ImageFormat) -> Result<Self, ImageError> {
+        // Parse image dimensions based on format
+        let dimensions = self::decode_dimensions(&data, format)?;
+        Ok(ImageData {
+            data,
+            format,
+            dimensions,
+            is_animated: false,
+            frame_count: 1,
+            bit_depth: 32,
+            color_type: ColorType::Rgba,
+        })
+    }
+}
+
 /// Supported image formats.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ImageFormat {
@@ -591,6 +676,10 @@ pub enum ImageFormat {
     Svg,
     /// TIFF image
     Tiff,
+    /// Enhanced Metafile, Windows vector picture
+    Emf,
+    /// Windows Metafile, the 16-bit predecessor of EMF
+    Wmf,
     /// Unknown format
     Unknown,
 }
@@ -605,6 +694,8 @@ impl fmt::Display for ImageFormat {
             ImageFormat::WebP => write!(f, "WebP"),
             ImageFormat::Svg => write!(f, "SVG"),
             ImageFormat::Tiff => write!(f, "TIFF"),
+            ImageFormat::Emf => write!(f, "EMF"),
+            ImageFormat::Wmf => write!(f, "WMF"),
             ImageFormat::Unknown => write!(f, "Unknown"),
         }
     }
@@ -613,10 +704,6 @@ impl fmt::Display for ImageFormat {
 impl ImageFormat {
     /// Detect format from magic bytes at the start of the data
     pub fn from_magic_bytes(data: &[u8]) -> Self {
-        if data.len() < 12 {
-            return ImageFormat::Unknown;
-        }
-
         // PNG: 89 50 4E 47 0D 0A 1A 0A
         if data.starts_with(&[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]) {
             return ImageFormat::Png;
@@ -650,9 +737,22 @@ impl ImageFormat {
             return ImageFormat::Svg;
         }
 
+        // EMF: header record 01 00 00 00 with " EMF" at byte 40; WMF: placeable or plain header
+        if metafile::is_emf(data) {
+            return ImageFormat::Emf;
+        }
+        if metafile::is_wmf(data) {
+            return ImageFormat::Wmf;
+        }
+
         ImageFormat::Unknown
     }
 
+    /// Whether this is a vector format, drawn at any size without losing detail
+    pub fn is_vector(&self) -> bool {
+        matches!(self, ImageFormat::Svg | ImageFormat::Emf | ImageFormat::Wmf)
+    }
+
     /// Get MIME type for this format
     pub fn mime_type(&self) -> &'static str {
         match self {
@@ -663,6 +763,8 @@ impl ImageFormat {
             ImageFormat::WebP => "image/webp",
             ImageFormat::Svg => "image/svg+xml",
             ImageFormat::Tiff => "image/tiff",
+            ImageFormat::Emf => "image/x-emf",
+            ImageFormat::Wmf => "image/x-wmf",
             ImageFormat::Unknown => "application/octet-stream",
         }
     }
@@ -677,6 +779,8 @@ impl ImageFormat {
             ImageFormat::WebP => "webp",
             ImageFormat::Svg => "svg",
             ImageFormat::Tiff => "tiff",
+            ImageFormat::Emf => "emf",
+            ImageFormat::Wmf => "wmf",
             ImageFormat::Unknown => "bin",
         }
     }
@@ -699,19 +803,129 @@ pub enum ColorType {
     Unknown,
 }
 
+// ============================================================================
+// Pixel Decoding
+// ============================================================================
+
+/// An image decoded to pixels, 8-bit RGBA rows from the top, shared with
+/// whatever draws it rather than copied
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pixels {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Arc<[u8]>,
+}
+
+impl ImageData {
+    /// Decode the image to RGBA pixels, shrunk to fit within `fit`, in
+    /// pixels, keeping its aspect ratio; a bitmap is never enlarged
+    ///
+    /// PNG, JPEG, GIF (its first frame), BMP and WebP decode. SVG, EMF and
+    /// WMF are rasterized, EMF and WMF by way of SVG, at their own size or
+    /// at the largest that fits `fit`. TIFF is left to the host.
+    pub fn decode_pixels(&self, fit: Option<(u32, u32)>) -> Result<Pixels, ImageError> {
+        let format = match self.format {
+            ImageFormat::Png => ::image::ImageFormat::Png,
+            ImageFormat::Jpeg => ::image::ImageFormat::Jpeg,
+            ImageFormat::Gif => ::image::ImageFormat::Gif,
+            ImageFormat::Bmp => ::image::ImageFormat::Bmp,
+            ImageFormat::WebP => ::image::ImageFormat::WebP,
+            ImageFormat::Svg => return rasterize_svg(&self.data, fit),
+            ImageFormat::Emf | ImageFormat::Wmf => {
+                let svg = metafile::metafile_to_svg(&self.data).map_err(|e| ImageError::DecodeError(e.to_string()))?;
+                return rasterize_svg(svg.as_bytes(), fit);
+            }
+            ImageFormat::Tiff | ImageFormat::Unknown => return Err(ImageError::UnsupportedFormat),
+        };
+        let mut decoded = ::image::load_from_memory_with_format(&self.data, format)
+            .map_err(|e| ImageError::DecodeError(e.to_string()))?;
+        if let Some((width, height)) = fit {
+            if width == 0 || height == 0 {
+                return Err(ImageError::InvalidDimensions);
+            }
+            if decoded.width() > width || decoded.height() > height {
+                decoded = decoded.resize(width, height, ::image::imageops::FilterType::Triangle);
+            }
+        }
+        let (width, height) = (decoded.width(), decoded.height());
+        Ok(Pixels {
+            width,
+            height,
+            rgba: decoded.into_rgba8().into_raw().into(),
+        })
+    }
+}
+
+/// Draw an SVG document to pixels at its own size, or scaled to the largest
+/// size that fits `fit`
+fn rasterize_svg(data: &[u8], fit: Option<(u32, u32)>) -> Result<Pixels, ImageError> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default()).map_err(|e| ImageError::DecodeError(e.to_string()))?;
+    let size = tree.size();
+    let scale = match fit {
+        Some((0, _) | (_, 0)) => return Err(ImageError::InvalidDimensions),
+        Some((width, height)) => (width as f32 / size.width()).min(height as f32 / size.height()),
+        None => 1.0,
+    };
+    let (width, height) = ((size.width() * scale).round().max(1.0) as u32, (size.height() * scale).round().max(1.0) as u32);
+    if width > 16_384 || height > 16_384 {
+        return Err(ImageError::DimensionsExceeded);
+    }
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or(ImageError::InvalidDimensions)?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    // The pixmap's colors are premultiplied by alpha
+    let rgba: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    Ok(Pixels {
+        width,
+        height,
+        rgba: rgba.into(),
+    })
+}
+
 // ============================================================================
 // Image Cache
 // ============================================================================
 
-/// Thread-safe image cache for storing loaded images.
+/// A cached image and what keeps it in the cache
+#[derive(Debug)]
+struct CacheEntry {
+    image: Arc<ImageData>,
+    /// Bytes the entry counts for: its file data and the picture decoded
+    size: usize,
+    /// When it was last loaded or looked up, on the cache's clock
+    last_used: AtomicU64,
+    /// Times it was pinned and not yet unpinned
+    pins: usize,
+    /// The pixels last decoded from it, with the size they were fit to
+    pixels: Option<(Option<(u32, u32)>, Pixels)>,
+}
+
+/// Image cache for storing loaded images, evicting the least recently used
+///
+/// Each image counts for its file data plus the picture decoded to RGBA, the
+/// memory it takes once drawn. Loading an image that would take the cache
+/// over its limit first evicts the images looked up longest ago, except
+/// pinned ones, which the host pins while they are on screen. A host under
+/// memory pressure trims the cache with [`ImageCache::trim_to`].
 #[derive(Debug)]
 pub struct ImageCache {
     /// Cache entries indexed by image path
-    cache: HashMap<String, Arc<ImageData>>,
+    cache: HashMap<String, CacheEntry>,
     /// Maximum cache size in bytes
     max_size_bytes: usize,
     /// Current cache size in bytes
     current_size: usize,
+    /// Ticks on each load and lookup, ordering entries by use
+    clock: AtomicU64,
 }
 
 impl Default for ImageCache {
@@ -723,11 +937,7 @@ impl Default for ImageCache {
 impl ImageCache {
     /// Create a new image cache with default settings (100MB limit)
     pub fn new() -> Self {
-        Self {
-            cache: HashMap::new(),
-            max_size_bytes: 100 * 1024 * 1024, // 100 MB
-            current_size: 0,
-        }
+        Self::with_max_size(100 * 1024 * 1024)
     }
 
     /// Create a new image cache with custom size limit
@@ -736,52 +946,17 @@ impl ImageCache {
             cache: HashMap::new(),
             max_size_bytes,
             current_size: 0,
+            clock: AtomicU64::new(0),
         }
     }
 
     /// Load an image from raw bytes and cache it
     pub fn load(&mut self, path: String, data: Vec<u8>) -> Result<Arc<ImageData>, ImageError> {
-        let format = ImageFormat::from_magic_bytes(&data);
-
-        if format == ImageFormat::Unknown {
-            return Err(ImageError::UnknownFormat);
-        }
-
-        // Parse image dimensions based on format
-        let dimensions = self::decode_dimensions(&data, format)?;
-
-        let image_data = Arc::new(ImageData {
-            data,
-            format,
-            dimensions,
-            is_animated: false,
-            frame_count: 1,
-            bit_depth: 32,
-            color_type: ColorType::Rgba,
-        });
-
-        // Calculate entry size
-        let entry_size = image_data.data.len();
-
-        // Check if we need to evict entries
-        while self.current_size + entry_size > self.max_size_bytes && !self.cache.is_empty() {
-            // Simple eviction: remove the first entry (FIFO)
-            let keys: Vec<_> = self.cache.keys().cloned().collect();
-            if let Some(key) = keys.first() {
-                if let Some(entry) = self.cache.remove(key) {
-                    self.current_size -= entry.data.len();
-                }
-            } else {
-                break;
-            }
-        }
-
-        // Insert the new image
-        self.cache.insert(path.clone(), Arc::clone(&image_data));
-        self.current_size += entry_size;
+        let image_data = Arc::new(ImageData::decode(data)?);
+        self.insert(path.clone(), Arc::clone(&image_data));
 
         debug!("Loaded image: {}, format: {}, dimensions: {}x{}",
-            path, format, dimensions.width as u32, dimensions.height as u32);
+            path, image_data.format, image_data.dimensions.width as u32, image_data.dimensions.height as u32);
 
         Ok(image_data)
     }
@@ -801,49 +976,133 @@ impl ImageCache {
             ContentType::ImageWebP => ImageFormat::WebP,
             ContentType::ImageTiff => ImageFormat::Tiff,
             ContentType::ImageSvg => ImageFormat::Svg,
+            ContentType::ImageEmf => ImageFormat::Emf,
+            ContentType::ImageWmf => ImageFormat::Wmf,
             ContentType::Thumbnail => ImageFormat::from_magic_bytes(data),
             _ => return Err(ImageError::UnsupportedFormat),
         };
 
-        let dimensions = self::decode_dimensions(data, format)?;
+        let image_data = Arc::new(ImageData::with_format(data.to_vec(), format)?);
+        self.insert(path.clone(), Arc::clone(&image_data));
 
-        let image_data = Arc::new(ImageData {
-            data: data.to_vec(),
-            format,
-            dimensions,
-            is_animated: false,
-            frame_count: 1,
-            bit_depth: 32,
-            color_type: ColorType::Rgba,
-        });
+        debug!("Loaded image from OOXML: {}, format: {}, dimensions: {}x{}",
+            path, format, image_data.dimensions.width as u32, image_data.dimensions.height as u32);
 
-        // Calculate entry size
-        let entry_size = image_data.data.len();
+        Ok(image_data)
+    }
 
-        // Check if we need to evict entries
-        while self.current_size + entry_size > self.max_size_bytes && !self.cache.is_empty() {
-            let keys: Vec<_> = self.cache.keys().cloned().collect();
-            if let Some(key) = keys.first() {
-                if let Some(entry) = self.cache.remove(key) {
-                    self.current_size -= entry.data.len();
-                }
-            } else {
+    /// Cache `image` at `path`, replacing what was there and keeping its pins,
+    /// after evicting what it takes to make room
+    pub fn insert(&mut self, path: String, image: Arc<ImageData>) {
+        let size = Self::entry_size(&image);
+        let pins = match self.cache.remove(&path) {
+            Some(old) => {
+                self.current_size -= old.size;
+                old.pins
+            }
+            None => 0,
+        };
+        self.evict_to(self.max_size_bytes.saturating_sub(size));
+        let entry = CacheEntry {
+            image,
+            size,
+            last_used: AtomicU64::new(self.tick()),
+            pins,
+            pixels: None,
+        };
+        self.cache.insert(path, entry);
+        self.current_size += size;
+    }
+
+    /// Bytes `image` counts for: its file data and its picture decoded to RGBA
+    fn entry_size(image: &ImageData) -> usize {
+        let pixels = (image.dimensions.width.max(0.0) as usize).saturating_mul(image.dimensions.height.max(0.0) as usize);
+        image.data.len().saturating_add(pixels.saturating_mul(4))
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Evict the least recently used unpinned images until the cache holds
+    /// at most `bytes`, or only pinned ones; returns the paths evicted
+    fn evict_to(&mut self, bytes: usize) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.current_size > bytes {
+            let oldest = self
+                .cache
+                .iter()
+                .filter(|(_, entry)| entry.pins == 0)
+                .min_by_key(|(_, entry)| entry.last_used.load(Ordering::Relaxed))
+                .map(|(path, _)| path.clone());
+            let Some(path) = oldest else {
                 break;
+            };
+            if let Some(entry) = self.cache.remove(&path) {
+                self.current_size -= entry.size;
             }
+            evicted.push(path);
         }
+        evicted
+    }
 
-        self.cache.insert(path.clone(), Arc::clone(&image_data));
-        self.current_size += entry_size;
+    /// Evict the least recently used images that are not pinned until the
+    /// cache holds at most `bytes`, as the host app does under memory
+    /// pressure; returns the paths evicted, so what was drawn from them can
+    /// be let go too
+    pub fn trim_to(&mut self, bytes: usize) -> Vec<String> {
+        let evicted = self.evict_to(bytes);
+        if !evicted.is_empty() {
+            debug!("Trimmed image cache to {} bytes, evicting {} images", self.current_size, evicted.len());
+        }
+        evicted
+    }
 
-        debug!("Loaded image from OOXML: {}, format: {}, dimensions: {}x{}",
-            path, format, dimensions.width as u32, dimensions.height as u32);
+    /// Keep the image at `path` from being evicted until it is unpinned as
+    /// many times; returns false if it is not cached
+    pub fn pin(&mut self, path: &str) -> bool {
+        match self.cache.get_mut(path) {
+            Some(entry) => {
+                entry.pins += 1;
+                true
+            }
+            None => false,
+        }
+    }
 
-        Ok(image_data)
+    /// Undo one pin of the image at `path`
+    pub fn unpin(&mut self, path: &str) {
+        if let Some(entry) = self.cache.get_mut(path) {
+            entry.pins = entry.pins.saturating_sub(1);
+        }
     }
 
-    /// Get an image from the cache
+    pub fn is_pinned(&self, path: &str) -> bool {
+        self.cache.get(path).is_some_and(|entry| entry.pins > 0)
+    }
+
+    /// Get an image from the cache, marking it used
     pub fn get(&self, path: &str) -> Option<Arc<ImageData>> {
-        self.cache.get(path).cloned()
+        let entry = self.cache.get(path)?;
+        entry.last_used.store(self.tick(), Ordering::Relaxed);
+        Some(Arc::clone(&entry.image))
+    }
+
+    /// The pixels decoded from the image at `path` to fit `fit`, if kept,
+    /// marking it used
+    pub fn pixels(&self, path: &str, fit: Option<(u32, u32)>) -> Option<Pixels> {
+        let entry = self.cache.get(path)?;
+        let (_, pixels) = entry.pixels.as_ref().filter(|(kept_fit, _)| *kept_fit == fit)?;
+        entry.last_used.store(self.tick(), Ordering::Relaxed);
+        Some(pixels.clone())
+    }
+
+    /// Keep `pixels`, decoded from the image at `path` to fit `fit`, in
+    /// place of any decoded before; the entry already counts for them
+    pub fn store_pixels(&mut self, path: &str, fit: Option<(u32, u32)>, pixels: Pixels) {
+        if let Some(entry) = self.cache.get_mut(path) {
+            entry.pixels = Some((fit, pixels));
+        }
     }
 
     /// Check if an image is in the cache
@@ -851,14 +1110,11 @@ impl ImageCache {
         self.cache.contains_key(path)
     }
 
-    /// Remove an image from the cache
+    /// Remove an image from the cache, pinned or not
     pub fn remove(&mut self, path: &str) -> Option<Arc<ImageData>> {
-        if let Some(entry) = self.cache.remove(path) {
-            self.current_size -= entry.data.len();
-            Some(entry)
-        } else {
-            None
-        }
+        let entry = self.cache.remove(path)?;
+        self.current_size -= entry.size;
+        Some(entry.image)
     }
 
     /// Clear the entire cache
@@ -881,6 +1137,189 @@ impl ImageCache {
     pub fn size_bytes(&self) -> usize {
         self.current_size
     }
+
+    /// Bytes the image at `path` counts for, if cached
+    pub fn entry_size_bytes(&self, path: &str) -> Option<usize> {
+        self.cache.get(path).map(|entry| entry.size)
+    }
+}
+
+// ============================================================================
+// Shared Image Cache
+// ============================================================================
+
+/// A load of an image other threads may be waiting on
+#[derive(Debug, Default)]
+struct PendingLoad {
+    result: Mutex<Option<Result<Arc<ImageData>, ImageError>>>,
+    done: Condvar,
+}
+
+/// Ends a load [`SharedImageCache::get_or_load`] started however the loading
+/// thread leaves it, so waiters are woken even if reading panics; they then
+/// get [`ImageError::NotFound`]
+struct LoadGuard<'a> {
+    cache: &'a SharedImageCache,
+    path: &'a str,
+    pending: Arc<PendingLoad>,
+}
+
+impl Drop for LoadGuard<'_> {
+    fn drop(&mut self) {
+        self.pending
+            .result
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert(Err(ImageError::NotFound));
+        self.pending.done.notify_all();
+        self.cache.pending.lock().unwrap_or_else(PoisonError::into_inner).remove(self.path);
+    }
+}
+
+/// An [`ImageCache`] render and load threads share
+///
+/// Lookups take a read lock, so they run side by side; loading takes the
+/// write lock only to insert what was decoded. [`Self::get_or_load`] loads
+/// each missing image once however many threads ask for it at the same time:
+/// the first reads and decodes it, the others wait for its result.
+#[derive(Debug, Default)]
+pub struct SharedImageCache {
+    cache: RwLock<ImageCache>,
+    /// Loads under way, by image path
+    pending: Mutex<HashMap<String, Arc<PendingLoad>>>,
+}
+
+impl SharedImageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_size(max_size_bytes: usize) -> Self {
+        SharedImageCache {
+            cache: RwLock::new(ImageCache::with_max_size(max_size_bytes)),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get an image from the cache, marking it used
+    pub fn get(&self, path: &str) -> Option<Arc<ImageData>> {
+        self.cache.read().unwrap().get(path)
+    }
+
+    /// Decode `data` and cache it at `path`
+    pub fn load(&self, path: &str, data: Vec<u8>) -> Result<Arc<ImageData>, ImageError> {
+        let image = Arc::new(ImageData::decode(data)?);
+        self.cache.write().unwrap().insert(path.to_string(), Arc::clone(&image));
+        Ok(image)
+    }
+
+    /// See [`ImageCache::insert`]
+    pub fn insert(&self, path: &str, image: Arc<ImageData>) {
+        self.cache.write().unwrap().insert(path.to_string(), image)
+    }
+
+    /// The image at `path`, cached, or read with `read` and decoded, or as
+    /// another thread loading it at the same time got it
+    pub fn get_or_load(
+        &self,
+        path: &str,
+        read: impl FnOnce() -> Result<Vec<u8>, ImageError>,
+    ) -> Result<Arc<ImageData>, ImageError> {
+        if let Some(image) = self.get(path) {
+            return Ok(image);
+        }
+        let (pending, loading) = {
+            let mut loads = self.pending.lock().unwrap();
+            // It may have been cached since the lookup, by a load that finished
+            if let Some(image) = self.get(path) {
+                return Ok(image);
+            }
+            match loads.get(path) {
+                Some(pending) => (Arc::clone(pending), false),
+                None => {
+                    let pending = Arc::new(PendingLoad::default());
+                    loads.insert(path.to_string(), Arc::clone(&pending));
+                    (pending, true)
+                }
+            }
+        };
+
+        if !loading {
+            let mut result = pending.result.lock().unwrap();
+            while result.is_none() {
+                result = pending.done.wait(result).unwrap();
+            }
+            return result.clone().unwrap_or(Err(ImageError::NotFound));
+        }
+        let guard = LoadGuard { cache: self, path, pending };
+        let result = read().and_then(|data| self.load(path, data));
+        *guard.pending.result.lock().unwrap() = Some(result.clone());
+        result
+    }
+
+    /// Decode `data` on a thread of its own and cache it at `path`, unless
+    /// it is cached or being loaded already
+    pub fn load_in_background(self: &Arc<Self>, path: String, data: Vec<u8>) -> JoinHandle<Result<Arc<ImageData>, ImageError>> {
+        let cache = Arc::clone(self);
+        std::thread::spawn(move || cache.get_or_load(&path, || Ok(data)))
+    }
+
+    /// The image at `path` decoded to pixels fitting `fit`, as
+    /// [`ImageData::decode_pixels`] gives them, decoded once and kept with
+    /// the image for as long as the same fit is asked for
+    pub fn pixels(&self, path: &str, fit: Option<(u32, u32)>) -> Result<Pixels, ImageError> {
+        if let Some(pixels) = self.cache.read().unwrap().pixels(path, fit) {
+            return Ok(pixels);
+        }
+        let image = self.get(path).ok_or(ImageError::NotFound)?;
+        let pixels = image.decode_pixels(fit)?;
+        self.cache.write().unwrap().store_pixels(path, fit, pixels.clone());
+        Ok(pixels)
+    }
+
+    /// See [`ImageCache::pin`]
+    pub fn pin(&self, path: &str) -> bool {
+        self.cache.write().unwrap().pin(path)
+    }
+
+    /// See [`ImageCache::unpin`]
+    pub fn unpin(&self, path: &str) {
+        self.cache.write().unwrap().unpin(path)
+    }
+
+    /// See [`ImageCache::trim_to`]
+    pub fn trim_to(&self, bytes: usize) -> Vec<String> {
+        self.cache.write().unwrap().trim_to(bytes)
+    }
+
+    pub fn remove(&self, path: &str) -> Option<Arc<ImageData>> {
+        self.cache.write().unwrap().remove(path)
+    }
+
+    pub fn clear(&self) {
+        self.cache.write().unwrap().clear()
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.cache.read().unwrap().contains(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.read().unwrap().is_empty()
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.cache.read().unwrap().size_bytes()
+    }
+
+    /// Run `f` on the cache itself, e.g. to hand it to the serializer
+    pub fn with_cache<R>(&self, f: impl FnOnce(&ImageCache) -> R) -> R {
+        f(&self.cache.read().unwrap())
+    }
 }
 
 // ============================================================================
@@ -888,7 +1327,7 @@ impl ImageCache {
 // ============================================================================
 
 /// Errors that can occur during image loading and processing.
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum ImageError {
     #[error("Unknown or unsupported image format")]
     UnknownFormat,
@@ -991,6 +1430,38 @@ impl WrapPolygon {
         let is_valid = !points.is_empty();
         Self { points, is_valid }
     }
+
+    /// Leftmost and rightmost x of the polygon between `top` and `bottom`, the
+    /// stretch text on a line in that band must keep out of; None if the
+    /// polygon does not reach into the band
+    pub fn horizontal_extent(&self, top: f32, bottom: f32) -> Option<(f32, f32)> {
+        if !self.is_valid {
+            return None;
+        }
+        let mut extent: Option<(f32, f32)> = None;
+        let mut include = |x: f32| extent = Some(extent.map_or((x, x), |(left, right)| (left.min(x), right.max(x))));
+        let closing = self.points.last().zip(self.points.first()).map(|(&a, &b)| [a, b]);
+        for [a, b] in self.points.windows(2).map(|edge| [edge[0], edge[1]]).chain(closing) {
+            let (low, high) = (a.y.min(b.y), a.y.max(b.y));
+            if high <= top || low >= bottom {
+                continue;
+            }
+            if a.y == b.y {
+                include(a.x);
+                include(b.x);
+                continue;
+            }
+            let at = |y: f32| a.x + (b.x - a.x) * (y - a.y) / (b.y - a.y);
+            include(at(low.max(top)));
+            include(at(high.min(bottom)));
+        }
+        extent
+    }
+
+    /// Lowest y of the polygon
+    pub fn bottom(&self) -> f32 {
+        self.points.iter().map(|point| point.y).fold(f32::NEG_INFINITY, f32::max)
+    }
 }
 
 /// Calculate the wrap region that text should avoid
@@ -1039,6 +1510,7 @@ fn decode_dimensions(data: &[u8], format: ImageFormat) -> Result<Size, ImageErro
         ImageFormat::WebP => decode_webp_dimensions(data),
         ImageFormat::Svg => decode_svg_dimensions(data),
         ImageFormat::Tiff => decode_tiff_dimensions(data),
+        ImageFormat::Emf | ImageFormat::Wmf => decode_metafile_dimensions(data),
         ImageFormat::Unknown => Err(ImageError::UnknownFormat),
     }
 }
@@ -1077,18 +1549,18 @@ fn decode_jpeg_dimensions(data: &[u8]) -> Result<Size, ImageError> {
             let marker = data[i + 1];
 
             // SOF0-SOF3 markers contain dimensions
-            if matches!(marker, 0xC0 | 0xC1 | 0xC2 | 0xC3 | 0xC5 | 0xC6 | 0xC7 | 0xC9 | 0xCA | 0xCB | 0xCD | 0xCE | 0xCF) {
-                if i + 9 < data.len() {
-                    // Skip length (2 bytes) and precision (1 byte)
-                    let height = u16::from_be_bytes([data[i + 5], data[i + 6]]);
-                    let width = u16::from_be_bytes([data[i + 7], data[i + 8]]);
-
-                    if width == 0 || height == 0 {
-                        return Err(ImageError::InvalidDimensions);
-                    }
-
-                    return Ok(Size::new(width as f32, height as f32));
+            if matches!(marker, 0xC0 | 0xC1 | 0xC2 | 0xC3 | 0xC5 | 0xC6 | 0xC7 | 0xC9 | 0xCA | 0xCB | 0xCD | 0xCE | 0xCF)
+                && i + 9 < data.len()
+            {
+                // Skip length (2 bytes) and precision (1 byte)
+                let height = u16::from_be_bytes([data[i + 5], data[i + 6]]);
+                let width = u16::from_be_bytes([data[i + 7], data[i + 8]]);
+
+                if width == 0 || height == 0 {
+                    return Err(ImageError::InvalidDimensions);
                 }
+
+                return Ok(Size::new(width as f32, height as f32));
             }
         }
         i += 1;
@@ -1216,6 +1688,12 @@ fn decode_svg_dimensions(data: &[u8]) -> Result<Size, ImageError> {
     }
 }
 
+/// Decode EMF or WMF dimensions from the picture frame, as pixels at 96 dpi.
+fn decode_metafile_dimensions(data: &[u8]) -> Result<Size, ImageError> {
+    let size = metafile::metafile_size(data).map_err(|e| ImageError::DecodeError(e.to_string()))?;
+    Ok(Size::new(size.width * 96.0 / 72.0, size.height * 96.0 / 72.0))
+}
+
 /// Decode TIFF dimensions from IFD.
 fn decode_tiff_dimensions(data: &[u8]) -> Result<Size, ImageError> {
     if data.len() < 8 {
@@ -1343,6 +1821,8 @@ pub fn load_images_from_relationships(
                 Some("webp") => ContentType::ImageWebP,
                 Some("svg") => ContentType::ImageSvg,
                 Some("tiff") | Some("tif") => ContentType::ImageTiff,
+                Some("emf") => ContentType::ImageEmf,
+                Some("wmf") => ContentType::ImageWmf,
                 _ => ContentType::Unknown(image_path.clone()),
             };
 
@@ -1365,6 +1845,7 @@ pub fn load_images_from_relationships(
                 title: None,
                 alt_description: None,
                 is_linked: image_data.is_none(),
+                ..Default::default()
             };
 
             images.insert(image_id.clone(), doc_image);
@@ -1381,8 +1862,8 @@ pub fn load_images_from_relationships(
 // Default Image Cache (Global)
 // ============================================================================
 
-/// Global default image cache.
-pub static DEFAULT_IMAGE_CACHE: Lazy<ImageCache> = Lazy::new(ImageCache::new);
+/// Global default image cache, shared by every thread.
+pub static DEFAULT_IMAGE_CACHE: Lazy<SharedImageCache> = Lazy::new(SharedImageCache::new);
 
 // ============================================================================
 // Unit Tests
@@ -1436,10 +1917,10 @@ mod tests {
     fn test_size_scale_to_fill() {
         let size = Size::new(50.0, 100.0);
 
-        // Fill wider container
+        // Fill wider container: the width covers it, the height overflows
         let filled = size.scale_to_fill(200.0, 100.0);
-        assert!((filled.width - 100.0).abs() < 0.001);
-        assert!((filled.height - 100.0).abs() < 0.001);
+        assert!((filled.width - 200.0).abs() < 0.001);
+        assert!((filled.height - 400.0).abs() < 0.001);
     }
 
     #[test]
@@ -1532,8 +2013,11 @@ mod tests {
     fn test_image_cache() {
         let mut cache = ImageCache::with_max_size(1024);
 
-        // Load an image
-        let data = vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D];
+        // Load an image: PNG signature and IHDR chunk of a 1x1 image
+        let data = vec![
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R',
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01,
+        ];
         let result = cache.load("test.png".to_string(), data);
         assert!(result.is_ok());
 
@@ -1611,8 +2095,10 @@ mod tests {
         assert!(!WrapType::InFront.is_behind_text());
         assert!(WrapType::InFront.is_in_front_of_text());
         assert!(!WrapType::Square.is_in_front_of_text());
-        assert!(!WrapType::Tight.requires_wrap_polygon());
+        // Tight and through wrapping both follow the wrap polygon (wp:wrapPolygon)
+        assert!(WrapType::Tight.requires_wrap_polygon());
         assert!(WrapType::Through.requires_wrap_polygon());
+        assert!(!WrapType::Square.requires_wrap_polygon());
     }
 
     #[test]
@@ -1727,6 +2213,143 @@ mod tests {
         assert!(region.is_valid);
     }
 
+    #[test]
+    fn test_image_cache_evicts_least_recently_used() {
+        // PNG signature and IHDR chunk of a 1x1 image: 24 bytes of data and 4 decoded
+        let png = vec![
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R',
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01,
+        ];
+        let mut cache = ImageCache::with_max_size(100);
+        for path in ["a.png", "b.png", "c.png"] {
+            cache.load(path.to_string(), png.clone()).unwrap();
+        }
+        assert_eq!((cache.size_bytes(), cache.entry_size_bytes("a.png")), (84, Some(28)));
+
+        // Looking up a.png keeps it; b.png goes to make room
+        cache.get("a.png");
+        cache.load("d.png".to_string(), png.clone()).unwrap();
+        assert!(!cache.contains("b.png") && cache.contains("a.png"));
+        // Loading a path again replaces it
+        cache.load("d.png".to_string(), png.clone()).unwrap();
+        assert_eq!(cache.size_bytes(), 84);
+
+        // Trimming spares pinned images
+        assert!(cache.pin("c.png") && !cache.pin("b.png"));
+        assert_eq!(cache.trim_to(0), ["a.png", "d.png"]);
+        assert_eq!((cache.len(), cache.size_bytes()), (1, 28));
+        cache.unpin("c.png");
+        assert!(!cache.is_pinned("c.png"));
+        assert_eq!(cache.trim_to(0), ["c.png"]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_shared_cache_loads_each_image_once() {
+        use std::sync::atomic::AtomicUsize;
+
+        let png = vec![
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R',
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01,
+        ];
+        let cache = Arc::new(SharedImageCache::new());
+        let reads = AtomicUsize::new(0);
+        let images: Vec<Arc<ImageData>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        cache.get_or_load("logo.png", || {
+                            reads.fetch_add(1, Ordering::SeqCst);
+                            std::thread::sleep(std::time::Duration::from_millis(20));
+                            Ok(png.clone())
+                        })
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|thread| thread.join().unwrap().unwrap()).collect()
+        });
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+        assert!(images.iter().all(|image| Arc::ptr_eq(image, &images[0])));
+        assert_eq!(images[0].dimensions, Size::new(2.0, 1.0));
+
+        // Failures reach every thread waiting, and are not cached
+        assert!(matches!(cache.get_or_load("missing.png", || Err(ImageError::NotFound)), Err(ImageError::NotFound)));
+        assert!(!cache.contains("missing.png"));
+
+        // A load that panics still wakes the threads waiting on it, and a later one reads again
+        let (started, wait_for_start) = std::sync::mpsc::channel();
+        let waited = std::thread::scope(|scope| {
+            let loading = scope.spawn(|| {
+                cache.get_or_load("broken.png", move || {
+                    started.send(()).unwrap();
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                    panic!("reading failed");
+                })
+            });
+            wait_for_start.recv().unwrap();
+            let waiting = scope.spawn(|| cache.get_or_load("broken.png", || Ok(png.clone())));
+            assert!(loading.join().is_err());
+            waiting.join().unwrap()
+        });
+        assert!(matches!(waited, Err(ImageError::NotFound)));
+        assert!(cache.get_or_load("broken.png", || Ok(png.clone())).is_ok());
+
+        let decoded = cache.load_in_background("photo.png".to_string(), png.clone()).join().unwrap().unwrap();
+        assert!(Arc::ptr_eq(&decoded, &cache.get("photo.png").unwrap()));
+        assert_eq!(cache.trim_to(0).len(), 3);
+    }
+
+    #[test]
+    fn test_decode_pixels() {
+        use crate::raster::{Canvas, Color};
+
+        let png = Canvas::new(40, 20, Color::rgb(200, 10, 10)).to_png();
+        let cache = SharedImageCache::new();
+        let image = cache.load("red.png", png).unwrap();
+        let pixels = image.decode_pixels(None).unwrap();
+        assert_eq!((pixels.width, pixels.height, pixels.rgba.len()), (40, 20, 40 * 20 * 4));
+        assert_eq!(&pixels.rgba[..4], &[200, 10, 10, 255]);
+
+        // Shrunk to the display size keeping the aspect ratio, never enlarged
+        let small = cache.pixels("red.png", Some((10, 10))).unwrap();
+        assert_eq!((small.width, small.height), (10, 5));
+        assert_eq!(cache.pixels("red.png", Some((100, 100))).unwrap().width, 40);
+
+        // The buffer decoded last is shared, not decoded again
+        let again = cache.pixels("red.png", Some((100, 100))).unwrap();
+        assert!(Arc::ptr_eq(&again.rgba, &cache.pixels("red.png", Some((100, 100))).unwrap().rgba));
+        assert!(matches!(cache.pixels("missing.png", None), Err(ImageError::NotFound)));
+
+        let gif = ImageData::decode(b"GIF89a\x40\x01\xF0\x00\x00\x00".to_vec()).unwrap();
+        assert!(matches!(gif.decode_pixels(None), Err(ImageError::DecodeError(_))));
+    }
+
+    #[test]
+    fn test_transform_geometry() {
+        let image = RenderedImage::new("photo".to_string(), Point::new(100.0, 100.0), Size::new(200.0, 100.0), ImageAnchorType::Inline);
+        let transform = ImageTransform {
+            crop: Some(crate::ooxml::SourceRect { left: 25.0, top: 10.0, right: 25.0, bottom: 0.0 }),
+            rotation: 90.0,
+            flip_horizontal: true,
+            transparency: 25.0,
+            ..Default::default()
+        };
+        let turned = image.with_transform(transform);
+        assert_eq!(turned.opacity, 0.75);
+
+        // A quarter turn stands the frame on end about its center
+        let bounds = turned.bounding_rect();
+        assert!((bounds.x - 150.0).abs() < 0.01 && (bounds.y - 50.0).abs() < 0.01);
+        assert!((bounds.width - 100.0).abs() < 0.01 && (bounds.height - 200.0).abs() < 0.01);
+        // Flipped, the picture's top left is at the frame's top right, which turns to its bottom right
+        let corner = turned.corners()[0];
+        assert!((corner.x - 250.0).abs() < 0.01 && (corner.y - 250.0).abs() < 0.01);
+        assert!(calculate_wrap_region(&turned).horizontal_extent(60.0, 70.0).is_some());
+
+        let source = turned.source_rect();
+        assert_eq!((source.x, source.y, source.width, source.height), (50.0, 10.0, 100.0, 90.0));
+    }
+
     #[test]
     fn test_size_to_emu_conversion() {
         let size = Size::new(100.0, 200.0);
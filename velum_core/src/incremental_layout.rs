@@ -0,0 +1,273 @@
+//! # Incremental Layout Module
+//!
+//! Keeps the line layout and pages of a document up to date while it is
+//! edited, without laying out the whole document on every keystroke.
+//!
+//! Each update compares the paragraphs' content hashes (see
+//! [`ParagraphHashes`](crate::paragraph_hash::ParagraphHashes)) and layout
+//! properties with those of the last layout. The paragraphs between the
+//! unchanged ones at the start and at the end are dirty: only they are
+//! broken into lines again, and pagination picks up from the page where the
+//! first of them was placed, keeping the pages before it.
+
+use serde::Serialize;
+
+use crate::line_layout::{LineLayout, ParagraphLayout, ParagraphProperties};
+use crate::ooxml::Section;
+use crate::page_layout::{PageLayout, PageModel};
+use crate::paragraph_hash::ParagraphHash;
+use crate::piece_tree::PieceTree;
+
+/// What an update laid out again
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RelayoutStats {
+    /// First paragraph whose content or properties changed; None if none did
+    pub first_paragraph: Option<usize>,
+    /// Paragraphs broken into lines again
+    pub paragraphs_laid_out: usize,
+    /// First page that was paginated again; the pages before it were kept
+    pub first_page: usize,
+    pub page_count: usize,
+}
+
+/// Line layout and pages of a document, updated from its dirty paragraphs
+pub struct IncrementalLayout {
+    line_layout: LineLayout,
+    page_layout: PageLayout,
+    /// Sections the paragraphs are laid out in; none for all on the pages of `page_layout`
+    sections: Vec<Section>,
+    paragraphs: Vec<ParagraphLayout>,
+    /// Content hash of each laid-out paragraph
+    hashes: Vec<u64>,
+    /// Pages of the last update; None until the first and after invalidation
+    model: Option<PageModel>,
+}
+
+impl IncrementalLayout {
+    /// Lays out at the column width of `page_layout`, on its pages
+    pub fn new(page_layout: PageLayout) -> Self {
+        IncrementalLayout {
+            line_layout: LineLayout::new(),
+            page_layout,
+            sections: Vec::new(),
+            paragraphs: Vec::new(),
+            hashes: Vec::new(),
+            model: None,
+        }
+    }
+
+    /// Lays out in `sections`, with their geometry, columns and starts, as
+    /// [`PageLayout::layout_sections`] does
+    pub fn with_sections(mut self, sections: Vec<Section>) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    /// The line layout paragraphs are broken with; changing how it breaks
+    /// lines calls for [`invalidate`](Self::invalidate)
+    pub fn line_layout_mut(&mut self) -> &mut LineLayout {
+        &mut self.line_layout
+    }
+
+    pub fn page_layout(&self) -> &PageLayout {
+        &self.page_layout
+    }
+
+    /// Lay out on other pages; everything is laid out again on the next update
+    pub fn set_page_layout(&mut self, page_layout: PageLayout) {
+        self.page_layout = page_layout;
+        self.invalidate();
+    }
+
+    /// Forget the last layout, so the next update lays out every paragraph
+    pub fn invalidate(&mut self) {
+        self.paragraphs.clear();
+        self.hashes.clear();
+        self.model = None;
+    }
+
+    /// Paragraph layouts of the last update
+    pub fn paragraphs(&self) -> &[ParagraphLayout] {
+        &self.paragraphs
+    }
+
+    /// Pages of the last update, if there was one
+    pub fn model(&self) -> Option<&PageModel> {
+        self.model.as_ref()
+    }
+
+    /// Bring the layout up to date with `tree`, whose paragraphs have the
+    /// hashes `paragraphs` and layout properties `props` (missing ones use
+    /// the defaults)
+    pub fn update(&mut self, tree: &PieceTree, paragraphs: &[ParagraphHash], props: &[ParagraphProperties]) -> RelayoutStats {
+        let count = paragraphs.len();
+        let props_of = |index: usize| props.get(index).copied().unwrap_or_default();
+        let unchanged = |new: usize, old: usize| {
+            self.hashes[old] == paragraphs[new].hash && self.paragraphs[old].properties == props_of(new)
+        };
+
+        // Paragraphs [prefix, count - suffix) replace the old [prefix, old_count - suffix)
+        let old_count = if self.model.is_some() { self.hashes.len() } else { 0 };
+        let shared = count.min(old_count);
+        let prefix = (0..shared).take_while(|&index| unchanged(index, index)).count();
+        let suffix = (0..shared - prefix)
+            .take_while(|&back| unchanged(count - 1 - back, old_count - 1 - back))
+            .count();
+        if self.model.is_some() && prefix == count && prefix == old_count {
+            return RelayoutStats {
+                first_page: self.model.as_ref().map_or(0, PageModel::page_count),
+                page_count: self.model.as_ref().map_or(0, PageModel::page_count),
+                ..Default::default()
+            };
+        }
+
+        let width = self.page_layout.column_width();
+        let dirty = prefix..count - suffix;
+        let laid_out: Vec<ParagraphLayout> = paragraphs[dirty.clone()]
+            .iter()
+            .enumerate()
+            .map(|(offset, paragraph)| {
+                let start = tree.char_to_byte_offset(paragraph.start);
+                let end = tree.char_to_byte_offset(paragraph.start + paragraph.length);
+                let text = tree.get_text_range(start, end - start);
+                self.line_layout.layout_paragraph_with_props(&text, width, props_of(prefix + offset))
+            })
+            .collect();
+        let paragraphs_laid_out = laid_out.len();
+        self.paragraphs.truncate(old_count);
+        self.paragraphs.splice(prefix..old_count - suffix, laid_out);
+        self.hashes = paragraphs.iter().map(|paragraph| paragraph.hash).collect();
+
+        // Paragraphs kept with the first dirty one may move with it
+        let mut from = prefix;
+        while from > 0 && self.paragraphs[from - 1].properties.keep_with_next {
+            from -= 1;
+        }
+        let model = match self.model.take() {
+            Some(previous) if from > 0 => self.page_layout.relayout_sections(&self.paragraphs, &self.sections, &previous, from),
+            _ => self.page_layout.layout_sections(&self.paragraphs, &self.sections),
+        };
+        let stats = RelayoutStats {
+            first_paragraph: Some(prefix),
+            paragraphs_laid_out,
+            first_page: if from > 0 { model.page_placed_on(from).unwrap_or(0) } else { 0 },
+            page_count: model.page_count(),
+        };
+        self.model = Some(model);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_layout::{Page, PageConfig};
+    use crate::paragraph_hash::ParagraphHashes;
+    use crate::text_shaping::TextShaper;
+
+    fn layout() -> IncrementalLayout {
+        let mut layout = IncrementalLayout::new(PageLayout::with_page_config(PageConfig::letter()));
+        layout.line_layout_mut().breaker_mut().set_shaper(TextShaper::without_font());
+        layout
+    }
+
+    fn text(paragraphs: usize) -> String {
+        (0..paragraphs)
+            .map(|i| format!("Paragraph {} has enough words in it to wrap onto a second line of the page, or a third.", i))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Lines of every page as (paragraph, line, y)
+    fn lines(pages: &[Page]) -> Vec<Vec<(usize, usize, u32)>> {
+        pages
+            .iter()
+            .map(|page| page.lines.iter().map(|l| (l.paragraph_index, l.source_line_index, l.y.to_bits())).collect())
+            .collect()
+    }
+
+    /// Pages of the same text laid out from scratch
+    fn from_scratch(tree: &PieceTree, props: &[ParagraphProperties]) -> Vec<Page> {
+        let mut fresh = layout();
+        fresh.update(tree, ParagraphHashes::build(tree).entries(), props);
+        fresh.model().unwrap().pages.clone()
+    }
+
+    #[test]
+    fn test_only_edited_paragraphs_are_laid_out() {
+        let mut tree = PieceTree::new(text(200));
+        let mut hashes = ParagraphHashes::build(&tree);
+        let mut layout = layout();
+
+        let first = layout.update(&tree, hashes.entries(), &[]);
+        assert_eq!(first.paragraphs_laid_out, 200);
+        assert_eq!(first.first_page, 0);
+        assert!(first.page_count > 5);
+
+        // Typing in paragraph 150
+        let offset = hashes.entries()[150].start + 10;
+        tree.insert_with_attrs(offset, "many more words ".repeat(8), None);
+        hashes.apply_edit(&tree, offset, 0, 128);
+        let stats = layout.update(&tree, hashes.entries(), &[]);
+        assert_eq!(stats.first_paragraph, Some(150));
+        assert_eq!(stats.paragraphs_laid_out, 1);
+        assert!(stats.first_page > 0);
+        assert_eq!(lines(&layout.model().unwrap().pages), lines(&from_scratch(&tree, &[])));
+
+        // Nothing changed
+        let idle = layout.update(&tree, hashes.entries(), &[]);
+        assert_eq!((idle.first_paragraph, idle.paragraphs_laid_out), (None, 0));
+    }
+
+    #[test]
+    fn test_split_and_joined_paragraphs() {
+        let mut tree = PieceTree::new(text(120));
+        let mut hashes = ParagraphHashes::build(&tree);
+        let mut layout = layout();
+        layout.update(&tree, hashes.entries(), &[]);
+
+        // Enter in the middle of paragraph 60
+        let offset = hashes.entries()[60].start + 20;
+        tree.insert_with_attrs(offset, "\n".to_string(), None);
+        hashes.apply_edit(&tree, offset, 0, 1);
+        let stats = layout.update(&tree, hashes.entries(), &[]);
+        assert_eq!(stats.paragraphs_laid_out, 2);
+        assert_eq!(layout.paragraphs().len(), 121);
+        assert_eq!(lines(&layout.model().unwrap().pages), lines(&from_scratch(&tree, &[])));
+
+        // Deleting the last three paragraph breaks
+        let start = hashes.entries()[117].start + hashes.entries()[117].length;
+        let end = hashes.entries()[120].start;
+        let (byte_start, byte_end) = (tree.char_to_byte_offset(start), tree.char_to_byte_offset(end));
+        tree.delete(byte_start, byte_end - byte_start);
+        hashes.apply_edit(&tree, start, end - start, 0);
+        let stats = layout.update(&tree, hashes.entries(), &[]);
+        assert_eq!(stats.paragraphs_laid_out, 1);
+        assert_eq!(layout.paragraphs().len(), 118);
+        assert_eq!(lines(&layout.model().unwrap().pages), lines(&from_scratch(&tree, &[])));
+    }
+
+    #[test]
+    fn test_property_changes_and_keeps() {
+        let tree = PieceTree::new(text(80));
+        let hashes = ParagraphHashes::build(&tree);
+        let mut layout = layout();
+        let mut props = vec![ParagraphProperties::default(); 80];
+        layout.update(&tree, hashes.entries(), &props);
+
+        // A heading kept with the next paragraph, and a page break before another
+        props[40].keep_with_next = true;
+        props[41].space_before = 240.0;
+        props[70].page_break_before = true;
+        let stats = layout.update(&tree, hashes.entries(), &props);
+        assert_eq!(stats.first_paragraph, Some(40));
+        assert_eq!(stats.paragraphs_laid_out, 31);
+        assert_eq!(lines(&layout.model().unwrap().pages), lines(&from_scratch(&tree, &props)));
+
+        // Pagination restarts at the paragraph kept with the changed one
+        props[41].space_before = 0.0;
+        let stats = layout.update(&tree, hashes.entries(), &props);
+        assert_eq!((stats.first_paragraph, stats.paragraphs_laid_out), (Some(41), 1));
+        assert_eq!(lines(&layout.model().unwrap().pages), lines(&from_scratch(&tree, &props)));
+    }
+}
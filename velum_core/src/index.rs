@@ -0,0 +1,496 @@
+//! # Index Module
+//!
+//! Index entries marked in the text with XE fields, and the index an INDEX
+//! field builds from them.
+//!
+//! An entry is a point in the text, like the XE field that marks it. Its text
+//! names a main entry and any sub-entries separated by ":", so "Fruit:Apple"
+//! lists "Apple" under "Fruit", and a `\t` switch gives a cross-reference such
+//! as "See Produce". The index lists the entries alphabetically, each with the
+//! pages its marks are on, one paragraph per line. It is the result of an
+//! INDEX field running over those paragraphs, so Word can refresh it too.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::comments::move_anchor;
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{Field, FieldKind, Paragraph};
+use crate::piece_tree::ParagraphAttributes;
+
+/// Result Word shows for an index without entries
+pub const NO_ENTRIES: &str = "No index entries found.";
+
+/// Default text between an entry and its page numbers, and between page numbers
+const DEFAULT_SEPARATOR: &str = ", ";
+
+/// Index errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum IndexError {
+    #[error("An index entry needs some text")]
+    EmptyEntry,
+
+    #[error("The document has no index")]
+    NoIndex,
+
+    #[error("The document already has an index")]
+    IndexExists,
+}
+
+/// A place in the text an index entry points to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Main entry and sub-entries separated by ":"; "\:" is a colon in an entry
+    pub text: String,
+    /// Cross-reference shown in place of page numbers, e.g. "See Produce"
+    #[serde(default)]
+    pub see: Option<String>,
+    /// Char offset of the mark in the whole text
+    pub position: usize,
+}
+
+impl IndexEntry {
+    /// The entry an XE field marks at `position`
+    pub fn from_field(field: &Field, position: usize) -> Option<Self> {
+        if field.kind != FieldKind::IndexEntry {
+            return None;
+        }
+        let words = instruction_words(&field.instruction);
+        let text = words.get(1).filter(|text| !text.starts_with('\\'))?;
+        Some(IndexEntry {
+            text: text.clone(),
+            see: switch_value(&words, "\\t").map(str::to_string),
+            position,
+        })
+    }
+
+    /// The XE instruction marking the entry
+    pub fn instruction(&self) -> String {
+        match self.see {
+            Some(ref see) => format!("XE \"{}\" \\t \"{}\"", self.text, see),
+            None => format!("XE \"{}\"", self.text),
+        }
+    }
+
+    /// Main entry first, then each sub-entry
+    pub fn levels(&self) -> Vec<String> {
+        let mut levels = vec![String::new()];
+        let mut chars = self.text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&':') => levels.last_mut().unwrap().push(chars.next().unwrap()),
+                ':' => levels.push(String::new()),
+                _ => levels.last_mut().unwrap().push(c),
+            }
+        }
+        levels.iter().map(|level| level.trim().to_string()).filter(|level| !level.is_empty()).collect()
+    }
+}
+
+/// How an INDEX field lays out the index, from its switches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexOptions {
+    /// Between an entry and its page numbers (`\e`)
+    pub entry_separator: String,
+    /// Between page numbers (`\l`)
+    pub page_separator: String,
+    /// Heading before the entries of each letter (`\h`), "A" standing for the letter
+    pub heading: Option<String>,
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        IndexOptions {
+            entry_separator: DEFAULT_SEPARATOR.to_string(),
+            page_separator: DEFAULT_SEPARATOR.to_string(),
+            heading: None,
+        }
+    }
+}
+
+impl IndexOptions {
+    pub fn from_instruction(instruction: &str) -> Self {
+        let words = instruction_words(instruction);
+        let defaults = IndexOptions::default();
+        IndexOptions {
+            entry_separator: switch_value(&words, "\\e").map_or(defaults.entry_separator, str::to_string),
+            page_separator: switch_value(&words, "\\l").map_or(defaults.page_separator, str::to_string),
+            heading: switch_value(&words, "\\h").map(str::to_string),
+        }
+    }
+
+    /// The INDEX instruction with these options, leaving out the defaults
+    pub fn instruction(&self) -> String {
+        let mut instruction = String::from("INDEX");
+        if self.entry_separator != DEFAULT_SEPARATOR {
+            instruction.push_str(&format!(" \\e \"{}\"", self.entry_separator));
+        }
+        if self.page_separator != DEFAULT_SEPARATOR {
+            instruction.push_str(&format!(" \\l \"{}\"", self.page_separator));
+        }
+        if let Some(ref heading) = self.heading {
+            instruction.push_str(&format!(" \\h \"{}\"", heading));
+        }
+        instruction
+    }
+}
+
+/// A paragraph of a generated index
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexLine {
+    /// 0 for a letter heading, 1 for a main entry, 2 and on for sub-entries
+    pub level: usize,
+    pub text: String,
+}
+
+impl IndexLine {
+    /// Paragraph formatting of the line: Word's "Index n" styles, with their
+    /// hanging indents set directly in case the document does not define them
+    pub fn paragraph_attributes(&self) -> ParagraphAttributes {
+        if self.level == 0 {
+            return ParagraphAttributes {
+                style_id: Some("IndexHeading".to_string()),
+                ..Default::default()
+            };
+        }
+        ParagraphAttributes {
+            style_id: Some(format!("Index{}", self.level)),
+            indent_left: Some(240 * self.level as i32),
+            indent_first_line: Some(-240),
+            ..Default::default()
+        }
+    }
+}
+
+/// Entries at one level of the index, by sort key
+#[derive(Debug, Default)]
+struct IndexNode {
+    pages: Vec<usize>,
+    see: Vec<String>,
+    children: BTreeMap<(String, String), IndexNode>,
+}
+
+/// The index entries of a document, in text order, and its INDEX field
+#[derive(Debug, Clone, Default)]
+pub struct DocumentIndex {
+    entries: Vec<IndexEntry>,
+    /// The INDEX field, with its start as a char offset into the whole text
+    field: Option<Field>,
+}
+
+impl DocumentIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// XE fields and the first INDEX field of `paragraphs`, each given with
+    /// the char offset it starts at
+    pub fn from_paragraphs<'a>(paragraphs: impl IntoIterator<Item = (usize, &'a Paragraph)>) -> Self {
+        let mut index = DocumentIndex::new();
+        for (paragraph_start, paragraph) in paragraphs {
+            for field in &paragraph.fields {
+                let start = paragraph_start + field.start;
+                match field.kind {
+                    FieldKind::IndexEntry => index.entries.extend(IndexEntry::from_field(field, start)),
+                    FieldKind::Index if index.field.is_none() => {
+                        index.field = Some(Field { start, ..field.clone() });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        index.entries.sort_by_key(|entry| entry.position);
+        index
+    }
+
+    /// Index entries and field of the model's body paragraphs
+    pub fn from_model(model: &DocumentModel) -> Self {
+        Self::from_paragraphs(model.located_paragraphs())
+    }
+
+    /// Put the XE and INDEX fields in the model's body paragraphs in place of theirs
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let fields = self.fields();
+        let mut paragraph_start = 0;
+        for block in model.body.iter_mut() {
+            let Block::Paragraph(paragraph) = block else {
+                continue;
+            };
+            let length = paragraph.text.chars().count();
+            paragraph
+                .fields
+                .retain(|field| !matches!(field.kind, FieldKind::IndexEntry | FieldKind::Index));
+            paragraph.fields.extend(paragraph_fields(&fields, paragraph_start, length));
+            paragraph.fields.sort_by_key(|field| field.start);
+            paragraph_start += length + 1;
+        }
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// The INDEX field, if the document has an index
+    pub fn field(&self) -> Option<&Field> {
+        self.field.as_ref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.field.is_none()
+    }
+
+    /// The XE fields and the INDEX field, with starts as char offsets into the whole text
+    pub fn fields(&self) -> Vec<Field> {
+        let mut fields: Vec<Field> = self
+            .entries
+            .iter()
+            .map(|entry| Field::new(&entry.instruction(), entry.position, ""))
+            .chain(self.field.clone())
+            .collect();
+        fields.sort_by_key(|field| field.start);
+        fields
+    }
+
+    /// Mark an entry at `position`; `text` separates sub-entries with ":"
+    pub fn mark(&mut self, position: usize, text: &str, see: Option<&str>) -> Result<&IndexEntry, IndexError> {
+        let entry = IndexEntry {
+            text: text.trim().to_string(),
+            see: see.map(str::trim).filter(|see| !see.is_empty()).map(str::to_string),
+            position,
+        };
+        if entry.levels().is_empty() {
+            return Err(IndexError::EmptyEntry);
+        }
+        let index = self.entries.partition_point(|other| other.position <= position);
+        self.entries.insert(index, entry);
+        Ok(&self.entries[index])
+    }
+
+    /// Remove the marks in chars `range`, or at its start if it is empty; returns how many
+    pub fn remove(&mut self, range: Range<usize>) -> usize {
+        let count = self.entries.len();
+        self.entries
+            .retain(|entry| !(range.contains(&entry.position) || entry.position == range.start));
+        count - self.entries.len()
+    }
+
+    /// Make chars `range` the index, its INDEX field having `instruction`
+    pub fn set_field(&mut self, instruction: &str, range: Range<usize>, result: &str) {
+        let mut field = Field::new(instruction, range.start, result);
+        field.length = range.len();
+        self.field = Some(field);
+    }
+
+    /// Move the marks and the index past an edit, see [`move_anchor`]
+    ///
+    /// Marks in the removed text go with it. The index stays as an empty field
+    /// when all of its text is deleted.
+    pub fn apply_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        self.entries.retain_mut(|entry| {
+            if offset < entry.position && entry.position < offset + removed {
+                return false;
+            }
+            (entry.position, _) = move_anchor(entry.position, 0, offset, removed, inserted);
+            true
+        });
+        if let Some(ref mut field) = self.field {
+            (field.start, field.length) = move_anchor(field.start, field.length, offset, removed, inserted);
+        }
+    }
+
+    /// The lines of the index, alphabetized, with page numbers from `page_of`
+    /// (the page index of a char offset, None where not known yet)
+    ///
+    /// Marks inside the index itself are left out. A main entry only used with
+    /// sub-entries gets a line of its own without page numbers.
+    pub fn lines(&self, options: &IndexOptions, page_of: impl Fn(usize) -> Option<usize>) -> Vec<IndexLine> {
+        let inside_index = |position: usize| {
+            self.field
+                .as_ref()
+                .is_some_and(|field| field.start < position && position < field.start + field.length)
+        };
+        let mut root = IndexNode::default();
+        for entry in self.entries.iter().filter(|entry| !inside_index(entry.position)) {
+            let mut node = &mut root;
+            for level in entry.levels() {
+                node = node.children.entry((sort_key(&level), level)).or_default();
+            }
+            match entry.see {
+                Some(ref see) if !node.see.contains(see) => node.see.push(see.clone()),
+                Some(_) => {}
+                None => node.pages.extend(page_of(entry.position).map(|page| page + 1)),
+            }
+        }
+
+        let mut lines = Vec::new();
+        let mut letter = None;
+        for ((key, text), node) in &root.children {
+            if let Some(ref heading) = options.heading {
+                let first = key.chars().next().map(|c| c.to_uppercase().to_string());
+                if first != letter {
+                    if let Some(ref first) = first {
+                        lines.push(IndexLine {
+                            level: 0,
+                            text: heading.replacen('A', first, 1),
+                        });
+                    }
+                    letter = first;
+                }
+            }
+            push_lines(&mut lines, 1, text, node, options);
+        }
+        lines
+    }
+}
+
+/// The line of an entry and those of its sub-entries
+fn push_lines(lines: &mut Vec<IndexLine>, level: usize, text: &str, node: &IndexNode, options: &IndexOptions) {
+    let mut pages = node.pages.clone();
+    pages.sort_unstable();
+    pages.dedup();
+    let references: Vec<String> = pages.iter().map(usize::to_string).chain(node.see.iter().cloned()).collect();
+    let text = if references.is_empty() {
+        text.to_string()
+    } else {
+        format!("{}{}{}", text, options.entry_separator, references.join(&options.page_separator))
+    };
+    lines.push(IndexLine { level, text });
+    for ((_, text), child) in &node.children {
+        push_lines(lines, level + 1, text, child, options);
+    }
+}
+
+/// Case-insensitive order, ties broken by the text itself
+fn sort_key(text: &str) -> String {
+    text.to_lowercase()
+}
+
+/// Words of a field instruction, quoted ones without their quotes
+pub(crate) fn instruction_words(instruction: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = instruction.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            words.push(chars.by_ref().take_while(|&c| c != '"').collect());
+        } else {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// The value after a switch such as `\t` among an instruction's words
+pub(crate) fn switch_value<'a>(words: &'a [String], switch: &str) -> Option<&'a str> {
+    let index = words.iter().position(|word| word.eq_ignore_ascii_case(switch))?;
+    words.get(index + 1).map(String::as_str)
+}
+
+/// The fields starting in the paragraph of `length` chars at `start`, relative to it
+///
+/// A field keeps its whole length, so one whose result runs on into later
+/// paragraphs still does.
+pub(crate) fn paragraph_fields(fields: &[Field], start: usize, length: usize) -> Vec<Field> {
+    fields
+        .iter()
+        .filter(|field| (start..=start + length).contains(&field.start))
+        .map(|field| Field {
+            start: field.start - start,
+            ..field.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> DocumentIndex {
+        let mut index = DocumentIndex::new();
+        index.mark(5, "banana", None).unwrap();
+        index.mark(40, "Apple", None).unwrap();
+        index.mark(12, "Apple", None).unwrap();
+        index.mark(70, "Fruit:Cherry", None).unwrap();
+        index.mark(30, "Fruit:apple\\: green", None).unwrap();
+        index.mark(50, "Produce", Some("See Fruit")).unwrap();
+        index
+    }
+
+    #[test]
+    fn test_entry_fields() {
+        let field = Field::new(r#" XE "Fruit:Apple" \t "See Produce" \b "#, 0, "");
+        let entry = IndexEntry::from_field(&field, 7).unwrap();
+        assert_eq!(entry.levels(), vec!["Fruit", "Apple"]);
+        assert_eq!(entry.see.as_deref(), Some("See Produce"));
+        assert_eq!(IndexEntry::from_field(&Field::new(&entry.instruction(), 0, ""), 7), Some(entry));
+        assert!(IndexEntry::from_field(&Field::new("XE \\b", 0, ""), 0).is_none());
+
+        let options = IndexOptions::from_instruction(r#"INDEX \e "	" \h "A" \c "2""#);
+        assert_eq!(options.entry_separator, "\t");
+        assert_eq!(options.page_separator, ", ");
+        assert_eq!(IndexOptions::from_instruction(&options.instruction()), options);
+        assert_eq!(DocumentIndex::new().mark(0, " : ", None).unwrap_err(), IndexError::EmptyEntry);
+    }
+
+    #[test]
+    fn test_lines_are_alphabetized_with_pages() {
+        let page_of = |offset: usize| Some(offset / 20);
+        let lines: Vec<(usize, String)> = index()
+            .lines(&IndexOptions::default(), page_of)
+            .into_iter()
+            .map(|line| (line.level, line.text))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (1, "Apple, 1, 3".to_string()),
+                (1, "banana, 1".to_string()),
+                (1, "Fruit".to_string()),
+                (2, "apple: green, 2".to_string()),
+                (2, "Cherry, 4".to_string()),
+                (1, "Produce, See Fruit".to_string()),
+            ]
+        );
+
+        let options = IndexOptions {
+            heading: Some("- A -".to_string()),
+            ..Default::default()
+        };
+        let headings: Vec<String> = index()
+            .lines(&options, page_of)
+            .into_iter()
+            .filter(|line| line.level == 0)
+            .map(|line| line.text)
+            .collect();
+        assert_eq!(headings, vec!["- A -", "- B -", "- F -", "- P -"]);
+    }
+
+    #[test]
+    fn test_marks_move_with_edits() {
+        let mut index = index();
+        index.set_field("INDEX", 80..100, "");
+
+        // Deleting text around a mark removes it; marks after the edit move along
+        index.apply_edit(10, 5, 0);
+        index.apply_edit(0, 0, 2);
+        let positions: Vec<usize> = index.entries().iter().map(|entry| entry.position).collect();
+        assert_eq!(positions, vec![7, 27, 37, 47, 67]);
+        assert_eq!(index.field().map(|field| (field.start, field.length)), Some((77, 20)));
+
+        let fields = index.fields();
+        let first = paragraph_fields(&fields, 0, 30);
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[1].instruction, r#"XE "Fruit:apple\: green""#);
+        assert_eq!(index.remove(30..70), 3);
+        assert_eq!(index.remove(7..7), 1);
+    }
+}
@@ -0,0 +1,279 @@
+//! # Layout Quality Module
+//!
+//! Typographic diagnostics for laid-out paragraphs: hyphenated breaks, hyphen
+//! ladders, how far justification stretched inter-word spaces, and a
+//! heuristic for rivers of white space running down consecutive lines.
+//!
+//! The numbers only describe a layout; deciding to lay out again with
+//! stricter parameters is left to the caller, using [`QualityThresholds`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::line_breaking::LineBreaker;
+use crate::line_layout::{Alignment, ParagraphLayout};
+
+/// Limits beyond which a paragraph's layout counts as poor
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QualityThresholds {
+    /// Most lines in a row allowed to end with a hyphen
+    pub max_consecutive_hyphens: usize,
+    /// Largest allowed ratio of a justified space to a natural space
+    pub max_space_stretch: f32,
+    /// Lines a run of aligned gaps must span to count as a river
+    pub min_river_lines: usize,
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        QualityThresholds {
+            max_consecutive_hyphens: 2,
+            max_space_stretch: 2.0,
+            min_river_lines: 3,
+        }
+    }
+}
+
+/// Inter-word gaps lining up vertically over several lines
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct River {
+    /// First line of the paragraph the river passes through
+    pub first_line: usize,
+    /// Number of consecutive lines it spans
+    pub line_count: usize,
+    /// Horizontal position of the gap on its last line, from the content edge
+    pub x: f32,
+}
+
+/// Quality diagnostics for one laid-out paragraph
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LayoutQuality {
+    /// Lines ending in a hyphenated break
+    pub hyphenated_lines: usize,
+    /// Longest run of consecutive hyphenated lines
+    pub max_consecutive_hyphens: usize,
+    /// Smallest space stretch on a justified line (1.0 is the natural width)
+    pub min_space_stretch: Option<f32>,
+    /// Largest space stretch on a justified line
+    pub max_space_stretch: Option<f32>,
+    /// Rivers found by the alignment heuristic
+    pub rivers: Vec<River>,
+}
+
+/// A gap still being followed down the paragraph
+struct OpenGap {
+    x: f32,
+    first_line: usize,
+    line_count: usize,
+}
+
+impl LayoutQuality {
+    /// Measure a paragraph laid out by [`crate::line_layout::LineLayout`]
+    ///
+    /// The breaker supplies text widths and should be the one that produced the layout.
+    pub fn measure(layout: &ParagraphLayout, breaker: &mut LineBreaker, thresholds: &QualityThresholds) -> Self {
+        let mut quality = LayoutQuality::default();
+        let space_width = breaker.calculate_text_width(" ");
+        let first_line_indent = layout.properties.indent_first_line * layout.max_width / 1440.0;
+        // A gap is considered to continue when it moves by less than a space
+        let tolerance = space_width.max(f32::EPSILON);
+
+        let mut consecutive = 0;
+        let mut open: Vec<OpenGap> = Vec::new();
+
+        for (index, line) in layout.lines.iter().enumerate() {
+            let Some(line_text) = layout.text.get(line.start..line.end) else {
+                continue;
+            };
+            let content = line_text.trim_end();
+
+            if is_hyphenated(line_text, &line.break_type) {
+                quality.hyphenated_lines += 1;
+                consecutive += 1;
+                quality.max_consecutive_hyphens = quality.max_consecutive_hyphens.max(consecutive);
+            } else {
+                consecutive = 0;
+            }
+
+            // Spaces between words, ignoring leading indentation
+            let leading = content.len() - content.trim_start().len();
+            let gaps: Vec<usize> = content
+                .char_indices()
+                .filter(|&(i, c)| c == ' ' && i >= leading)
+                .map(|(i, _)| i)
+                .collect();
+
+            // The last line of a justified paragraph keeps its natural spacing
+            let justified = layout.properties.alignment == Alignment::Justify && line.break_type != "HardBreak";
+            let mut stretch = 1.0;
+            if justified && !gaps.is_empty() && space_width > 0.0 {
+                let available = if index == 0 {
+                    layout.content_width - first_line_indent
+                } else {
+                    layout.content_width
+                };
+                let natural = breaker.calculate_text_width(content);
+                let extra = (available - natural).max(0.0) / gaps.len() as f32;
+                stretch = (space_width + extra) / space_width;
+                quality.min_space_stretch = Some(quality.min_space_stretch.map_or(stretch, |s: f32| s.min(stretch)));
+                quality.max_space_stretch = Some(quality.max_space_stretch.map_or(stretch, |s: f32| s.max(stretch)));
+            }
+
+            // Centre of each gap, with the spaces before it stretched as laid out
+            let centres: Vec<f32> = gaps
+                .iter()
+                .enumerate()
+                .map(|(n, &gap)| {
+                    let prefix = breaker.calculate_text_width(&content[..gap]);
+                    prefix + n as f32 * space_width * (stretch - 1.0) + space_width * stretch / 2.0
+                })
+                .collect();
+
+            let mut next_open = Vec::with_capacity(centres.len());
+            for x in centres {
+                let continued = open.iter().find(|gap| (gap.x - x).abs() < tolerance);
+                next_open.push(match continued {
+                    Some(gap) => OpenGap {
+                        x,
+                        first_line: gap.first_line,
+                        line_count: gap.line_count + 1,
+                    },
+                    None => OpenGap {
+                        x,
+                        first_line: index,
+                        line_count: 1,
+                    },
+                });
+            }
+            close_gaps(&mut quality.rivers, &open, &next_open, thresholds.min_river_lines);
+            open = next_open;
+        }
+        close_gaps(&mut quality.rivers, &open, &[], thresholds.min_river_lines);
+
+        quality
+    }
+
+    /// Whether any measurement is past the thresholds
+    pub fn is_poor(&self, thresholds: &QualityThresholds) -> bool {
+        self.max_consecutive_hyphens > thresholds.max_consecutive_hyphens
+            || self
+                .max_space_stretch
+                .is_some_and(|stretch| stretch > thresholds.max_space_stretch)
+            || !self.rivers.is_empty()
+    }
+}
+
+/// Whether a line ends with a break inside a word
+fn is_hyphenated(line_text: &str, break_type: &str) -> bool {
+    match break_type {
+        "Hyphenated" => true,
+        "SoftBreak" => matches!(line_text.trim_end().chars().last(), Some('-' | '\u{00AD}' | '\u{2010}')),
+        _ => false,
+    }
+}
+
+/// Record gaps that did not continue onto the next line and ran long enough to be rivers
+fn close_gaps(rivers: &mut Vec<River>, open: &[OpenGap], next_open: &[OpenGap], min_lines: usize) {
+    for gap in open {
+        let continued = next_open
+            .iter()
+            .any(|next| next.first_line == gap.first_line && next.line_count == gap.line_count + 1);
+        if !continued && gap.line_count >= min_lines {
+            rivers.push(River {
+                first_line: gap.first_line,
+                line_count: gap.line_count,
+                x: gap.x,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_layout::{LineLayout, LineLayoutInfo, ParagraphProperties};
+
+    fn manual_layout(text: &str, breaks: &[(usize, usize, &str)]) -> ParagraphLayout {
+        let lines = breaks
+            .iter()
+            .enumerate()
+            .map(|(i, &(start, end, break_type))| LineLayoutInfo {
+                line_number: i,
+                start,
+                end,
+                width: 0.0,
+                break_type: break_type.to_string(),
+                char_count: text[start..end].chars().count(),
+                is_bidi: false,
+                trailing_whitespace: 0.0,
+                offset_x: 0.0,
+                line_height: 16.8,
+            })
+            .collect();
+        ParagraphLayout {
+            text: text.to_string(),
+            max_width: 100.0,
+            content_width: 100.0,
+            lines,
+            total_height: 0.0,
+            base_line_height: 16.8,
+            actual_line_height: 16.8,
+            has_bidi: false,
+            properties: ParagraphProperties::default(),
+        }
+    }
+
+    #[test]
+    fn test_hyphen_ladder_is_poor() {
+        let layout = manual_layout(
+            "co-op-er-ate",
+            &[(0, 3, "SoftBreak"), (3, 6, "SoftBreak"), (6, 9, "Hyphenated"), (9, 12, "HardBreak")],
+        );
+        let thresholds = QualityThresholds::default();
+        let quality = LayoutQuality::measure(&layout, &mut LineBreaker::new(), &thresholds);
+
+        assert_eq!(quality.hyphenated_lines, 3);
+        assert_eq!(quality.max_consecutive_hyphens, 3);
+        assert!(quality.rivers.is_empty());
+        assert!(quality.is_poor(&thresholds));
+    }
+
+    #[test]
+    fn test_aligned_gaps_form_a_river() {
+        let layout = manual_layout(
+            "ab cd ab cd ab cd",
+            &[(0, 6, "SoftBreak"), (6, 12, "SoftBreak"), (12, 17, "HardBreak")],
+        );
+        let quality = LayoutQuality::measure(&layout, &mut LineBreaker::new(), &QualityThresholds::default());
+
+        assert_eq!(quality.rivers.len(), 1);
+        assert_eq!(quality.rivers[0].first_line, 0);
+        assert_eq!(quality.rivers[0].line_count, 3);
+        // Not justified, so no stretch is reported
+        assert_eq!(quality.max_space_stretch, None);
+
+        let strict = QualityThresholds {
+            min_river_lines: 4,
+            ..Default::default()
+        };
+        let quality = LayoutQuality::measure(&layout, &mut LineBreaker::new(), &strict);
+        assert!(quality.rivers.is_empty());
+    }
+
+    #[test]
+    fn test_justified_space_stretch() {
+        let text = "The quick brown fox jumps over the lazy dog while a typographer watches the spacing closely";
+        let mut line_layout = LineLayout::new();
+        let layout = line_layout.layout_paragraph_with_props(
+            text,
+            200.0,
+            ParagraphProperties::with_alignment(Alignment::Justify),
+        );
+        assert!(layout.lines.len() > 1);
+
+        let quality = LayoutQuality::measure(&layout, line_layout.breaker_mut(), &QualityThresholds::default());
+        let min = quality.min_space_stretch.unwrap();
+        let max = quality.max_space_stretch.unwrap();
+        assert!(min >= 1.0);
+        assert!(max >= min);
+    }
+}
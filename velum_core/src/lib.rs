@@ -6,13 +6,119 @@ pub mod find;
 pub mod text_shaping;
 pub mod page_layout;
 pub mod undo_redo;
+pub mod page_setup;
+pub mod paragraph_hash;
+pub mod font_coverage;
+pub mod metrics;
+pub mod modification;
+pub mod document_model;
+pub mod document_sync;
+pub mod font_substitution;
+pub mod edit_locations;
+pub mod paste;
+pub mod html_import;
+pub mod markdown;
+pub mod style_sheet;
+pub mod view_filter;
+pub mod layout_quality;
+pub mod revisions;
+pub mod repagination;
+pub mod incremental_layout;
+pub mod viewport_layout;
+pub mod hit_test;
+pub mod caret_movement;
+pub mod paragraph_edit;
+pub mod document;
+pub mod track_changes;
+pub mod comments;
+pub mod bookmarks;
+pub mod hyperlinks;
+pub mod forms;
+pub mod snippets;
+pub mod numbering;
+pub mod index;
+pub mod captions;
+pub mod mailings;
+pub mod reading_view;
+pub mod focus_mode;
+pub mod headers_footers;
+pub mod document_end;
+pub mod autoformat;
+pub mod math;
+pub mod image;
+pub mod metafile;
+pub mod accessibility;
+pub mod read_aloud;
+pub mod library_index;
+pub mod measurement;
+pub mod font_license;
+pub mod floating;
+pub mod text_wrap;
+pub mod bidi;
+pub mod segmentation;
+pub mod blocks;
+pub mod raster;
+pub mod pdf;
 
-pub use piece_tree::{BufferId, Piece, PieceTree, TextAttributes};
-pub use line_breaking::{BreakType, Line, LineBreaker};
+pub use piece_tree::{
+    AttributeSpan, AttributeState, BufferId, CellPosition, CommonAttributes, DeleteDirection, EditorState,
+    MultiSelection, ParagraphAttributes, Piece, PieceTree, TextAttributes, TreeCorruption,
+};
+pub use line_breaking::{BreakStrategy, BreakType, Line, LineBreaker};
+pub use text_shaping::{FontFeature, ShapedText, ShapingOptions};
 pub use line_layout::{DocumentLayout, LineLayout, ParagraphLayout};
 pub use ooxml::{parse_ooxml, ParsedDocument, OoxmlError};
 pub use find::{SearchOptions, SearchResult, SearchResultSet};
-pub use page_layout::{PageConfig, PageLayout, RenderedPage, RenderedLine, Rect, PaginationConfig};
+pub use page_layout::{PageConfig, PageLayout, PageModel, RenderedPage, RenderedLine, Rect, PaginationConfig, SectionBreak};
+pub use page_setup::{Margins, Orientation, PageSetup, PageSetupError, PaperSize, SectionPageSetup};
+pub use paragraph_hash::{ParagraphHash, ParagraphHashes};
+pub use font_coverage::{CharUsage, CoverageReport, FontRegistry, FontUsage, MissingGlyph};
+pub use metrics::{Metrics, SessionMarker};
+pub use modification::ModificationTracker;
+pub use document_model::{Block, DocumentModel, DocumentModelError, ModelMetadata, DOCUMENT_MODEL_VERSION};
+pub use font_substitution::{FontScope, FontSubstitution, FontSubstitutionReport};
+pub use edit_locations::EditLocations;
+pub use paste::{PastePolicy, PasteReport};
+pub use html_import::import_html;
+pub use markdown::{export_markdown, import_markdown, MarkdownFlavor};
+pub use style_sheet::{NamedStyle, ResolvedStyle, StyleKind, StyleSheet, StyleSheetError};
+pub use view_filter::{Annotation, ContentClass, Decoration, FilteredLayout, OutputTarget, ViewFilter};
+pub use layout_quality::{LayoutQuality, QualityThresholds, River};
+pub use revisions::{Resolution, RevisionError, RevisionSet};
+pub use track_changes::{revision_marks, MarkStyle, RevisionMark, TrackChangesManager};
+pub use comments::{CommentError, CommentManager, CommentThread};
+pub use bookmarks::{Bookmark, BookmarkError, BookmarkRegistry};
+pub use hyperlinks::{HyperlinkError, HyperlinkSet};
+pub use snippets::{Snippet, SnippetError, SnippetInsertion, SnippetLibrary, TabStop};
+pub use numbering::{format_number, LabelSuffix, ListEdit, ListError, ListKind, ListLabel, ListNumbering, NumberingEngine, OutlineScheme};
+pub use index::{DocumentIndex, IndexEntry, IndexError, IndexLine, IndexOptions};
+pub use captions::{Caption, CaptionError, CaptionLabel, CaptionPosition, CaptionSet};
+pub use mailings::{EnvelopeSize, LabelProduct, MailingDocument, MailingError, LABEL_PRODUCTS};
+pub use reading_view::{AnchoredObject, InlineObject, ReadingLayout, ReadingOptions};
+pub use focus_mode::{FocusData, FocusUnit};
+pub use autoformat::{AutoFormatChange, AutoFormatOptions, AutoFormatResult};
+pub use math::{MathNode, MathZones};
+pub use accessibility::{AccessibleNode, AccessiblePage, Role};
+pub use read_aloud::{speech_chunks, Emphasis, EmphasisSpan, Pause, SpeechChunk, DEFAULT_MAX_CHUNK_CHARS};
+pub use library_index::{IndexStatus, LibraryHit, LibraryIndex, LibraryIndexError, LibraryIndexer};
+pub use measurement::{MeasurementError, MeasurementSettings, MeasurementUnit, RulerTick, TableWidth};
+pub use font_license::{EmbeddingPermission, FontLicense, FontLicensePolicy};
+pub use floating::{AnchorBehavior, FloatingError, FloatingObjectSet, PlacedObject};
+pub use bidi::{bidi_class, BidiClass, BidiParagraph, BidiRun, Direction};
+pub use segmentation::{line_break_class, line_break_opportunities, LineBreak, LineBreakClass};
+pub use blocks::{cells, BlockRange, BlockRef, Blocks, CellRef, NoteRef};
+pub use raster::{compare, Canvas, Color, ImageDiff, RasterError};
+pub use pdf::{write_pdf, PdfDocument, PdfError, PdfExport, PdfFontLicense, PdfFonts, PdfOptions, PdfPage};
+pub use headers_footers::{HeaderFooterError, HeaderFooterKind, HeaderFooterManager, HeaderFooterVariant};
+pub use document_end::DocumentEnd;
+pub use document_sync::{DocumentPatch, DocumentSnapshot, DocumentSync, PatchOp, SyncUpdate, SyncedBlock};
+pub use repagination::{PageBoundary, PaginationEvent, PaginationJob, PaginationStatus, Repaginator};
+pub use incremental_layout::{IncrementalLayout, RelayoutStats};
+pub use viewport_layout::{RefineStep, Viewport, ViewportLayout, ViewportPage, ViewportPages};
+pub use hit_test::{Affinity, CaretPosition, HighlightRect, LineCaret};
+pub use caret_movement::{Caret, Movement};
+pub use paragraph_edit::{ParagraphSplit, SplitKind};
+pub use document::{Document, DocumentMetadata};
 pub use undo_redo::{
     Command, CommandError, CommandMetadata, CommandRecord,
     InsertCommand, DeleteCommand,
@@ -22,4 +128,6 @@ pub use undo_redo::{
 
 mod bridge_generated;
 mod api;
+#[cfg(test)]
+mod test_support;
 pub use api::*;
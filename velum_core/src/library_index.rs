@@ -0,0 +1,678 @@
+//! # Library Index Module
+//!
+//! Full-text search across a library of .docx files.
+//!
+//! [`LibraryIndex`] is an inverted index from lowercased words to the documents
+//! and char ranges they occur at. Documents are read with
+//! [`parse_preview`](crate::ooxml::parse_preview), so indexing never runs a
+//! full parse, and each document's modification time and size are kept so that
+//! re-indexing an unchanged file is a metadata check. The index saves to and
+//! loads from a JSON file.
+//!
+//! [`LibraryIndexer`] keeps an index up to date on a worker thread: change
+//! notifications queue paths, the worker re-indexes them one at a time and
+//! saves the index whenever its queue runs empty, and searches read the index
+//! between two files.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::ooxml::{parse_preview, DocumentPreview};
+
+/// Format version of saved indexes; an index saved by another version is rebuilt
+pub const LIBRARY_INDEX_VERSION: u32 = 1;
+
+/// Chars of a document's text kept to show under its search hit
+const EXCERPT_CHARS: usize = 160;
+
+/// Errors maintaining a library index
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum LibraryIndexError {
+    #[error("I/O error: {0}")]
+    Io(String),
+
+    #[error("Cannot read {path}: {reason}")]
+    Unreadable { path: String, reason: String },
+
+    #[error("Invalid index file: {0}")]
+    InvalidStore(String),
+
+    #[error("Index file version {found} is not {expected}")]
+    VersionMismatch { found: u32, expected: u32 },
+}
+
+impl From<std::io::Error> for LibraryIndexError {
+    fn from(e: std::io::Error) -> Self {
+        LibraryIndexError::Io(e.to_string())
+    }
+}
+
+/// A document found by a search
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryHit {
+    pub path: String,
+    pub title: Option<String>,
+    /// Start of the document's text
+    pub excerpt: String,
+    /// Relevance; higher is better
+    pub score: f32,
+    /// Char ranges of the matched words in the document's preview text
+    pub ranges: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedDocument {
+    path: String,
+    title: Option<String>,
+    excerpt: String,
+    /// Seconds since the Unix epoch
+    modified: u64,
+    size: u64,
+    /// Distinct words of the document, to drop its postings when it changes
+    terms: Vec<String>,
+}
+
+/// Where a word occurs in one document: (char start, char length) pairs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Posting {
+    document: u32,
+    ranges: Vec<(u32, u32)>,
+}
+
+/// Score and matched ranges of each document, by ID
+type Matches = HashMap<u32, (f32, Vec<(u32, u32)>)>;
+
+/// Inverted index over the text of many documents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryIndex {
+    version: u32,
+    next_id: u32,
+    documents: HashMap<u32, IndexedDocument>,
+    /// Document ID by path
+    ids: HashMap<String, u32>,
+    /// Postings by word, ordered so the words a prefix starts can be scanned
+    postings: BTreeMap<String, Vec<Posting>>,
+}
+
+impl Default for LibraryIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LibraryIndex {
+    pub fn new() -> Self {
+        LibraryIndex {
+            version: LIBRARY_INDEX_VERSION,
+            next_id: 0,
+            documents: HashMap::new(),
+            ids: HashMap::new(),
+            postings: BTreeMap::new(),
+        }
+    }
+
+    /// Read an index saved with [`save`](Self::save)
+    pub fn load(path: &Path) -> Result<Self, LibraryIndexError> {
+        let json = fs::read_to_string(path)?;
+        let index: LibraryIndex =
+            serde_json::from_str(&json).map_err(|e| LibraryIndexError::InvalidStore(e.to_string()))?;
+        if index.version != LIBRARY_INDEX_VERSION {
+            return Err(LibraryIndexError::VersionMismatch { found: index.version, expected: LIBRARY_INDEX_VERSION });
+        }
+        Ok(index)
+    }
+
+    /// Write the index to `path`, replacing the previous file only once the new one is complete
+    pub fn save(&self, path: &Path) -> Result<(), LibraryIndexError> {
+        let json = serde_json::to_string(self).map_err(|e| LibraryIndexError::InvalidStore(e.to_string()))?;
+        let partial = path.with_extension("partial");
+        fs::write(&partial, json)?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    pub fn document_count(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.ids.contains_key(path)
+    }
+
+    /// Index the .docx at `path` unless it is indexed with the same modification
+    /// time and size; returns whether it was read
+    pub fn index_file(&mut self, path: &Path) -> Result<bool, LibraryIndexError> {
+        let (modified, size) = file_stamp(path)?;
+        let key = path.to_string_lossy();
+        if self.is_current(&key, modified, size) {
+            return Ok(false);
+        }
+        let preview = read_preview(path)?;
+        self.insert(&key, &preview, modified, size);
+        Ok(true)
+    }
+
+    /// Index a .docx held in memory under `path`, replacing what was indexed there
+    pub fn index_bytes(&mut self, path: &str, file_data: &[u8], modified: u64) -> Result<(), LibraryIndexError> {
+        let preview = parse_preview(file_data)
+            .map_err(|e| LibraryIndexError::Unreadable { path: path.to_string(), reason: e.to_string() })?;
+        self.insert(path, &preview, modified, file_data.len() as u64);
+        Ok(())
+    }
+
+    /// Drop a document from the index; returns whether it was indexed
+    pub fn remove(&mut self, path: &str) -> bool {
+        let Some(id) = self.ids.remove(path) else {
+            return false;
+        };
+        if let Some(document) = self.documents.remove(&id) {
+            for term in &document.terms {
+                if let Some(postings) = self.postings.get_mut(term) {
+                    postings.retain(|posting| posting.document != id);
+                    if postings.is_empty() {
+                        self.postings.remove(term);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Indexed paths under the folder `root`
+    pub fn paths_under(&self, root: &Path) -> Vec<String> {
+        self.ids.keys().filter(|path| Path::new(path).starts_with(root)).cloned().collect()
+    }
+
+    /// Documents holding every word of `query`, best first; the last word also
+    /// matches as a prefix, for search as you type
+    pub fn search(&self, query: &str, limit: usize) -> Vec<LibraryHit> {
+        let terms: Vec<String> = words(query).map(|(word, _)| word).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let total = self.documents.len().max(1) as f32;
+        let mut matches: Option<Matches> = None;
+        for (index, term) in terms.iter().enumerate() {
+            let postings: Vec<&Posting> = if index + 1 == terms.len() {
+                self.postings
+                    .range(term.clone()..)
+                    .take_while(|(word, _)| word.starts_with(term.as_str()))
+                    .flat_map(|(_, postings)| postings)
+                    .collect()
+            } else {
+                self.postings.get(term).map(|postings| postings.iter().collect()).unwrap_or_default()
+            };
+
+            let mut found: HashMap<u32, Vec<(u32, u32)>> = HashMap::new();
+            for posting in postings {
+                found.entry(posting.document).or_default().extend(&posting.ranges);
+            }
+            // Rarer words weigh more
+            let weight = (1.0 + total / found.len().max(1) as f32).ln();
+
+            matches = Some(match matches {
+                None => found
+                    .into_iter()
+                    .map(|(id, ranges)| (id, (ranges.len() as f32 * weight, ranges)))
+                    .collect(),
+                Some(mut previous) => {
+                    previous.retain(|id, _| found.contains_key(id));
+                    for (id, (score, ranges)) in previous.iter_mut() {
+                        let more = &found[id];
+                        *score += more.len() as f32 * weight;
+                        ranges.extend(more);
+                    }
+                    previous
+                }
+            });
+        }
+
+        let mut hits: Vec<LibraryHit> = matches
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(id, (score, mut ranges))| {
+                let document = self.documents.get(&id)?;
+                ranges.sort_unstable();
+                ranges.dedup();
+                Some(LibraryHit {
+                    path: document.path.clone(),
+                    title: document.title.clone(),
+                    excerpt: document.excerpt.clone(),
+                    score,
+                    ranges: ranges
+                        .into_iter()
+                        .map(|(start, length)| start as usize..(start + length) as usize)
+                        .collect(),
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+        hits.truncate(limit);
+        hits
+    }
+
+    fn is_current(&self, path: &str, modified: u64, size: u64) -> bool {
+        self.ids
+            .get(path)
+            .and_then(|id| self.documents.get(id))
+            .is_some_and(|document| document.modified == modified && document.size == size)
+    }
+
+    fn insert(&mut self, path: &str, preview: &DocumentPreview, modified: u64, size: u64) {
+        self.remove(path);
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut occurrences: BTreeMap<String, Vec<(u32, u32)>> = BTreeMap::new();
+        for (word, range) in words(&preview.text) {
+            occurrences.entry(word).or_default().push((range.start as u32, range.len() as u32));
+        }
+        let terms: Vec<String> = occurrences.keys().cloned().collect();
+        for (word, ranges) in occurrences {
+            self.postings.entry(word).or_default().push(Posting { document: id, ranges });
+        }
+
+        self.documents.insert(
+            id,
+            IndexedDocument {
+                path: path.to_string(),
+                title: preview.title.clone().filter(|title| !title.trim().is_empty()),
+                excerpt: preview.text.chars().take(EXCERPT_CHARS).collect(),
+                modified,
+                size,
+                terms,
+            },
+        );
+        self.ids.insert(path.to_string(), id);
+    }
+}
+
+/// Lowercased words of `text` with their char ranges
+fn words(text: &str) -> impl Iterator<Item = (String, Range<usize>)> + '_ {
+    let mut chars = 0usize;
+    text.split_word_bounds().filter_map(move |segment| {
+        let start = chars;
+        chars += segment.chars().count();
+        segment
+            .chars()
+            .any(char::is_alphanumeric)
+            .then(|| (segment.to_lowercase(), start..chars))
+    })
+}
+
+/// Modification time in seconds since the Unix epoch and size of a file
+fn file_stamp(path: &Path) -> Result<(u64, u64), LibraryIndexError> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    Ok((modified, metadata.len()))
+}
+
+fn read_preview(path: &Path) -> Result<DocumentPreview, LibraryIndexError> {
+    let unreadable = |reason: String| LibraryIndexError::Unreadable { path: path.to_string_lossy().into_owned(), reason };
+    let file_data = fs::read(path).map_err(|e| unreadable(e.to_string()))?;
+    parse_preview(&file_data).map_err(|e| unreadable(e.to_string()))
+}
+
+/// Whether a file is a .docx the library indexes; Word's "~$" lock files are not
+fn is_indexable(path: &Path) -> bool {
+    let docx = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("docx"));
+    let lock_file = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("~$"));
+    docx && !lock_file
+}
+
+/// Where background indexing stands, for a status line
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IndexStatus {
+    /// Notifications not handled yet
+    pub pending: usize,
+    pub document_count: usize,
+    /// Files that could not be read since the indexer started
+    pub failed: usize,
+    pub last_error: Option<String>,
+}
+
+enum Task {
+    Changed(PathBuf),
+    Removed(PathBuf),
+    /// Index every .docx under a folder and forget those no longer there
+    Folder(PathBuf),
+}
+
+/// State shared between the indexer and its worker
+struct Shared {
+    index: RwLock<LibraryIndex>,
+    /// Where the index is saved, if anywhere
+    store: Option<PathBuf>,
+    pending: AtomicUsize,
+    errors: Mutex<(usize, Option<String>)>,
+}
+
+/// Keeps a [`LibraryIndex`] up to date on a worker thread
+pub struct LibraryIndexer {
+    sender: Option<Sender<Task>>,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl LibraryIndexer {
+    /// Start indexing into the index saved at `store`, or a new one if there is
+    /// none or it cannot be read; None keeps the index in memory only
+    pub fn new(store: Option<PathBuf>) -> Self {
+        let index = store
+            .as_deref()
+            .filter(|path| path.exists())
+            .and_then(|path| {
+                LibraryIndex::load(path)
+                    .map_err(|e| log::warn!("Rebuilding the library index: {}", e))
+                    .ok()
+            })
+            .unwrap_or_default();
+        Self::with_index(index, store)
+    }
+
+    /// Start indexing into `index`
+    pub fn with_index(index: LibraryIndex, store: Option<PathBuf>) -> Self {
+        let shared = Arc::new(Shared {
+            index: RwLock::new(index),
+            store,
+            pending: AtomicUsize::new(0),
+            errors: Mutex::new((0, None)),
+        });
+        let (sender, receiver) = mpsc::channel();
+        let worker = {
+            let shared = Arc::clone(&shared);
+            thread::Builder::new()
+                .name("velum-library-index".to_string())
+                .spawn(move || run_worker(&shared, receiver))
+                .expect("failed to spawn the library index worker")
+        };
+        LibraryIndexer {
+            sender: Some(sender),
+            shared,
+            worker: Some(worker),
+        }
+    }
+
+    /// Index every .docx under `folder`, dropping indexed files that are gone
+    pub fn add_folder(&self, folder: impl Into<PathBuf>) {
+        self.send(Task::Folder(folder.into()));
+    }
+
+    /// A file was created or modified
+    pub fn notify_changed(&self, path: impl Into<PathBuf>) {
+        self.send(Task::Changed(path.into()));
+    }
+
+    /// A file was deleted or moved away
+    pub fn notify_removed(&self, path: impl Into<PathBuf>) {
+        self.send(Task::Removed(path.into()));
+    }
+
+    /// Search the documents indexed so far
+    pub fn search(&self, query: &str, limit: usize) -> Vec<LibraryHit> {
+        self.shared.index.read().unwrap().search(query, limit)
+    }
+
+    pub fn status(&self) -> IndexStatus {
+        let (failed, last_error) = self.shared.errors.lock().unwrap().clone();
+        IndexStatus {
+            pending: self.shared.pending.load(Ordering::Acquire),
+            document_count: self.shared.index.read().unwrap().document_count(),
+            failed,
+            last_error,
+        }
+    }
+
+    fn send(&self, task: Task) {
+        if let Some(sender) = &self.sender {
+            self.shared.pending.fetch_add(1, Ordering::AcqRel);
+            // The worker only stops when the indexer is dropped
+            let _ = sender.send(task);
+        }
+    }
+}
+
+impl Drop for LibraryIndexer {
+    fn drop(&mut self) {
+        // The worker finishes the queue, saves and sees the closed channel
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run_worker(shared: &Shared, receiver: Receiver<Task>) {
+    let mut dirty = false;
+    while let Ok(task) = receiver.recv() {
+        dirty |= handle_task(shared, task);
+        let closed = loop {
+            match receiver.try_recv() {
+                Ok(task) => dirty |= handle_task(shared, task),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+        if dirty {
+            save(shared);
+            dirty = false;
+        }
+        if closed {
+            break;
+        }
+    }
+}
+
+/// Handle one notification; returns whether the index changed
+fn handle_task(shared: &Shared, task: Task) -> bool {
+    let changed = match task {
+        Task::Changed(path) => index_path(shared, &path),
+        Task::Removed(path) => shared.index.write().unwrap().remove(&path.to_string_lossy()),
+        Task::Folder(folder) => {
+            let mut files = Vec::new();
+            collect_documents(&folder, &mut files);
+            let mut changed = false;
+            for gone in shared.index.read().unwrap().paths_under(&folder) {
+                if !Path::new(&gone).exists() {
+                    changed |= shared.index.write().unwrap().remove(&gone);
+                }
+            }
+            for file in files {
+                changed |= index_path(shared, &file);
+            }
+            changed
+        }
+    };
+    shared.pending.fetch_sub(1, Ordering::AcqRel);
+    changed
+}
+
+/// Re-index one file, parsing it without holding the index lock
+fn index_path(shared: &Shared, path: &Path) -> bool {
+    let key = path.to_string_lossy();
+    if !path.exists() {
+        return shared.index.write().unwrap().remove(&key);
+    }
+    if !is_indexable(path) {
+        return false;
+    }
+    let result = file_stamp(path).and_then(|(modified, size)| {
+        if shared.index.read().unwrap().is_current(&key, modified, size) {
+            return Ok(false);
+        }
+        let preview = read_preview(path)?;
+        shared.index.write().unwrap().insert(&key, &preview, modified, size);
+        Ok(true)
+    });
+    result.unwrap_or_else(|e| {
+        log::warn!("Library index: {}", e);
+        let mut errors = shared.errors.lock().unwrap();
+        errors.0 += 1;
+        errors.1 = Some(e.to_string());
+        false
+    })
+}
+
+/// The indexable files under `folder`, recursively
+fn collect_documents(folder: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_documents(&path, files);
+        } else if is_indexable(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn save(shared: &Shared) {
+    if let Some(store) = &shared.store {
+        if let Err(e) = shared.index.read().unwrap().save(store) {
+            log::warn!("Saving the library index failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::zip_fixture;
+    use std::time::{Duration, Instant};
+
+    fn build_docx(paragraphs: &[&str], title: &str) -> Vec<u8> {
+        let body: String = paragraphs.iter().map(|text| format!("<w:p><w:r><w:t>{}</w:t></w:r></w:p>", text)).collect();
+        zip_fixture([
+            ("word/document.xml", format!("<w:document><w:body>{}</w:body></w:document>", body)),
+            ("docProps/core.xml", format!("<cp:coreProperties><dc:title>{}</dc:title></cp:coreProperties>", title)),
+        ])
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("velum-library-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn wait_until(indexer: &LibraryIndexer, done: impl Fn(&IndexStatus) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !done(&indexer.status()) {
+            assert!(Instant::now() < deadline, "indexing did not finish: {:?}", indexer.status());
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_search_ranks_and_reports_ranges() {
+        let mut index = LibraryIndex::new();
+        index.index_bytes("a.docx", &build_docx(&["Budget report", "The budget grew"], "Budget"), 1).unwrap();
+        index.index_bytes("b.docx", &build_docx(&["Travel report"], "Trip"), 1).unwrap();
+        index.index_bytes("c.docx", &build_docx(&["Budgeting tips"], ""), 1).unwrap();
+
+        let hits = index.search("budget", 10);
+        let paths: Vec<&str> = hits.iter().map(|hit| hit.path.as_str()).collect();
+        assert_eq!(paths, ["a.docx", "c.docx"]);
+        assert_eq!(hits[0].ranges, vec![0..6, 18..24]);
+        assert_eq!(hits[0].title.as_deref(), Some("Budget"));
+        assert_eq!(hits[1].title, None);
+
+        // Every word must occur; only the last one matches as a prefix
+        let hits = index.search("report BUD", 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].ranges, vec![0..6, 7..13, 18..24]);
+        assert!(index.search("budg report", 10).is_empty());
+        assert!(index.search("  ", 10).is_empty());
+    }
+
+    #[test]
+    fn test_reindex_and_remove_drop_old_postings() {
+        let mut index = LibraryIndex::new();
+        index.index_bytes("a.docx", &build_docx(&["alpha beta"], ""), 1).unwrap();
+        index.index_bytes("a.docx", &build_docx(&["gamma"], ""), 2).unwrap();
+        assert!(index.search("alpha", 10).is_empty());
+        assert_eq!(index.search("gamma", 10).len(), 1);
+        assert_eq!(index.document_count(), 1);
+
+        assert!(index.remove("a.docx"));
+        assert!(!index.remove("a.docx"));
+        assert!(index.postings.is_empty());
+        assert!(matches!(
+            index.index_bytes("bad.docx", b"not a zip", 1),
+            Err(LibraryIndexError::Unreadable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_index_persists_and_skips_unchanged_files() {
+        let dir = temp_dir("persist");
+        let file = dir.join("notes.docx");
+        fs::write(&file, build_docx(&["persistent words"], "Notes")).unwrap();
+        let store = dir.join("index.json");
+
+        let mut index = LibraryIndex::new();
+        assert!(index.index_file(&file).unwrap());
+        assert!(!index.index_file(&file).unwrap());
+        index.save(&store).unwrap();
+
+        let mut loaded = LibraryIndex::load(&store).unwrap();
+        assert_eq!(loaded.search("persistent", 10)[0].path, file.to_string_lossy());
+        assert!(!loaded.index_file(&file).unwrap());
+
+        fs::write(&store, r#"{"version":0,"next_id":0,"documents":{},"ids":{},"postings":{}}"#).unwrap();
+        assert_eq!(
+            LibraryIndex::load(&store).unwrap_err(),
+            LibraryIndexError::VersionMismatch { found: 0, expected: LIBRARY_INDEX_VERSION }
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_background_indexer_follows_notifications() {
+        let dir = temp_dir("background");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("one.docx"), build_docx(&["first library file"], "")).unwrap();
+        fs::write(dir.join("sub/two.docx"), build_docx(&["second library file"], "")).unwrap();
+        fs::write(dir.join("~$one.docx"), b"lock").unwrap();
+        fs::write(dir.join("broken.docx"), b"not a zip").unwrap();
+        let store = dir.join("index.json");
+
+        let indexer = LibraryIndexer::new(Some(store.clone()));
+        indexer.add_folder(&dir);
+        wait_until(&indexer, |status| status.pending == 0);
+        assert_eq!(indexer.search("library", 10).len(), 2);
+        let status = indexer.status();
+        assert_eq!((status.document_count, status.failed), (2, 1));
+
+        fs::write(dir.join("one.docx"), build_docx(&["rewritten content, longer than before"], "")).unwrap();
+        indexer.notify_changed(dir.join("one.docx"));
+        fs::remove_file(dir.join("sub/two.docx")).unwrap();
+        indexer.notify_removed(dir.join("sub/two.docx"));
+        wait_until(&indexer, |status| status.pending == 0);
+        assert!(indexer.search("library", 10).is_empty());
+        assert_eq!(indexer.search("rewritten", 10).len(), 1);
+
+        drop(indexer);
+        let reopened = LibraryIndexer::new(Some(store));
+        assert_eq!(reopened.status().document_count, 1);
+        drop(reopened);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -6,7 +6,9 @@
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap};
 use std::sync::Arc;
-use crate::text_shaping::TextShaper;
+use serde::{Deserialize, Serialize};
+use crate::segmentation::{self, LineBreakClass};
+use crate::text_shaping::{ShapedText, ShapingOptions, TextShaper};
 
 /// Represents the type of line break
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -77,13 +79,28 @@ const MAX_LOOKAHEAD: usize = 200;
 
 /// Penalties for various line break situations
 const PENALTY_HYPHEN: i32 = 50;
-const PENALTY_HARD: i32 = 100;
 
 /// Demerits multipliers
 const DEMERITS_FLAGGED: f32 = 100.0;
 const DEMERITS_DOUBLE: f32 = 50.0;
 const DEMERITS_HYPHEN: f32 = 30.0;
 
+/// Break opportunities above which total fit falls back to first fit
+const MAX_TOTAL_FIT_BREAKS: usize = 5000;
+
+/// Demerits of a line wider than the maximum, allowed only for a single overlong word
+const DEMERITS_OVERFULL: f32 = 1.0e6;
+
+/// How the breaks of a paragraph are chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum BreakStrategy {
+    /// Settle on breaks while scanning, keeping only a few candidates
+    #[default]
+    FirstFit,
+    /// Choose all breaks of the paragraph together so its lines come out even
+    TotalFit,
+}
+
 /// Line breaker configuration
 #[derive(Debug, Clone)]
 pub struct LineBreakerConfig {
@@ -95,6 +112,10 @@ pub struct LineBreakerConfig {
     pub tab_width: f32,
     /// Word spacing adjustment
     pub word_spacing: f32,
+    /// How breaks are chosen
+    pub break_strategy: BreakStrategy,
+    /// Language and OpenType features text is shaped with
+    pub shaping: ShapingOptions,
 }
 
 impl Default for LineBreakerConfig {
@@ -104,6 +125,8 @@ impl Default for LineBreakerConfig {
             hyphenation_enabled: true,
             tab_width: 40.0,
             word_spacing: 4.0,
+            break_strategy: BreakStrategy::default(),
+            shaping: ShapingOptions::default(),
         }
     }
 }
@@ -208,70 +231,59 @@ impl LineBreaker {
         self.config.hyphenation_enabled = enabled;
     }
 
+    /// Sets how breaks are chosen
+    #[inline]
+    pub fn set_break_strategy(&mut self, strategy: BreakStrategy) {
+        self.config.break_strategy = strategy;
+    }
+
+    /// Sets the language and features text is shaped with
+    #[inline]
+    pub fn set_shaping_options(&mut self, options: ShapingOptions) {
+        self.config.shaping = options;
+    }
+
+    /// Measures and shapes text with `shaper` instead of the system font
+    #[inline]
+    pub fn set_shaper(&mut self, shaper: TextShaper<'static>) {
+        self.shaper = Arc::new(shaper);
+    }
+
     /// Calculates the width of a substring
     fn text_width(&mut self, text: &str) -> f32 {
         self.shaper.measure_width(text)
     }
 
+    /// Shapes text with the breaker's shaper
+    pub fn shape_text(&self, text: &str, options: &ShapingOptions) -> ShapedText {
+        self.shaper.shape_text(text, options)
+    }
+
     /// Clears the width cache (No-op in new engine)
     #[inline]
     pub fn clear_cache(&mut self) {
         // shaper handles caching internally if needed
     }
 
-    /// Checks if a character is a valid break point (after the character)
-    #[inline]
-    fn is_break_after(&self, ch: char) -> bool {
-        matches!(
-            ch,
-            ' ' | '\t' | '-' | '–' | '—' | ';' | ':' | ',' | '.' | '!' | '?' | ')' | ']' | '}'
-        )
-    }
-
     /// Checks if a character can be hyphenated
     #[inline]
     fn can_hyphenate(&self, ch: char) -> bool {
         ch.is_alphabetic() && !ch.is_ascii() || ch.is_ascii_alphabetic()
     }
 
-    /// Checks if a character is CJK
-    #[inline]
-    fn is_cjk(&self, ch: char) -> bool {
-        let code = ch as u32;
-        // CJK Unified Ideographs
-        (code >= 0x4E00 && code <= 0x9FFF)
-        || // CJK Unified Ideographs Extension A
-        (code >= 0x3400 && code <= 0x4DBF)
-        || // CJK Symbols and Punctuation
-        (code >= 0x3000 && code <= 0x303F)
-        || // Hiragana
-        (code >= 0x3040 && code <= 0x309F)
-        || // Katakana
-        (code >= 0x30A0 && code <= 0x30FF)
-    }
-
     /// Gets break points for a line using HarfBuzz shaping
+    ///
+    /// Mandatory breaks inside the text are not forced here; `break_lines`
+    /// splits the text at them first.
     pub(crate) fn get_break_points(&mut self, text: &str) -> Vec<BreakPoint> {
         let mut break_points: Vec<BreakPoint> = Vec::new();
         let len = text.len();
 
-        // 1. Shape the entire text to get accurate glyph positions
-        let (total_width, glyphs) = self.shaper.shape(text);
-
-        // 2. Build a map of char_index -> x_position in pixels
-        let mut char_pos: HashMap<usize, f32> = HashMap::new();
-
-        // For simple ASCII text, estimate position based on accumulated width
-        // HarfBuzz glyphs have cluster indices that map to character positions
-        let mut current_pos_px: f32 = 0.0;
-        for glyph in &glyphs {
-            let char_idx = glyph.cluster as usize;
-            // Only set if not already set (first glyph for this cluster wins)
-            if !char_pos.contains_key(&char_idx) {
-                char_pos.insert(char_idx, current_pos_px);
-            }
-            current_pos_px += glyph.x_advance;
-        }
+        // 1. Shape the entire text; the cluster map gives every char its
+        // share of the glyph advances, ligatures and conjuncts included
+        let shaped = self.shaper.shape_text(text, &self.config.shaping);
+        let total_width = shaped.width;
+        let positions = shaped.char_positions();
 
         // Add start break point
         break_points.push(BreakPoint {
@@ -284,96 +296,45 @@ impl LineBreaker {
             flagged: false,
         });
 
-        // 3. Iterate through characters to find break points
-        let chars: Vec<char> = text.chars().collect();
-        let char_count = chars.len();
-
-        for (char_idx, ch) in chars.iter().enumerate() {
-            // Get current width from char_pos, or estimate if not available
-            let current_width = char_pos.get(&char_idx).copied().unwrap_or_else(|| {
-                // Fallback: estimate width based on character count
-                let estimated_char_width = total_width / char_count.max(1) as f32;
-                estimated_char_width * char_idx as f32
-            });
-
-            // Calculate width after this character (for the next break point)
-            let char_width = char_pos.get(&char_idx).copied()
-                .and_then(|w| char_pos.get(&(char_idx + 1)).copied().map(|next_w| next_w - w))
-                .unwrap_or_else(|| total_width / char_count.max(1) as f32);
-            let width_after = current_width + char_width;
-
-            // Handle CJK characters - each can be a break point
-            if self.is_cjk(*ch) {
-                // Find byte index of next character
-                let next_byte_idx = if char_idx + 1 < char_count {
-                    text.char_indices().nth(char_idx + 1).map(|(i, _)| i).unwrap_or(len)
-                } else {
-                    len
-                };
+        // 2. Break where UAX #14 allows, which covers spaces, hyphens, CJK
+        // and the no-break rules; the end of the text is added below
+        let opportunities = segmentation::line_break_opportunities(text);
+        let mut opportunities = opportunities.iter().map(|(position, _)| *position).peekable();
+        let char_count = text.chars().count();
 
-                break_points.push(BreakPoint {
-                    position: next_byte_idx,
-                    char_offset: char_idx + 1,
-                    width: width_after,
-                    break_type: BreakType::SoftBreak,
-                    is_hyphenated: false,
-                    penalty: 0,
-                    flagged: false,
-                });
+        for (char_idx, (byte_idx, ch)) in text.char_indices().enumerate() {
+            let next_byte_idx = byte_idx + ch.len_utf8();
+            if next_byte_idx == len {
+                break;
+            }
+            while opportunities.next_if(|&position| position < next_byte_idx).is_some() {}
+            if opportunities.peek() != Some(&next_byte_idx) {
                 continue;
             }
 
-            // Handle ASCII/whitespace - allow break after spaces and tabs
-            if *ch == ' ' || *ch == '\t' {
-                let next_byte_idx = if char_idx + 1 < char_count {
-                    text.char_indices().nth(char_idx + 1).map(|(i, _)| i).unwrap_or(len)
-                } else {
-                    len
-                };
-
-                break_points.push(BreakPoint {
-                    position: next_byte_idx,
-                    char_offset: char_idx + 1,
-                    width: width_after,
-                    break_type: BreakType::SoftBreak,
-                    is_hyphenated: false,
-                    penalty: 0,
-                    flagged: false,
-                });
+            // Never break inside a cluster, such as before a combining mark
+            if shaped.cluster_starts.get(char_idx + 1) == Some(&false) {
                 continue;
             }
 
-            // Check if we can break after this character
-            if self.is_break_after(*ch) {
-                let next_byte_idx = if char_idx + 1 < char_count {
-                    text.char_indices().nth(char_idx + 1).map(|(i, _)| i).unwrap_or(len)
-                } else {
-                    len
-                };
-
-                // Calculate penalty based on character
-                let penalty = match *ch {
-                    '-' | '–' | '—' => PENALTY_HYPHEN,
-                    '!' | '?' => PENALTY_HARD,
-                    _ => 0,
-                };
-
-                break_points.push(BreakPoint {
-                    position: next_byte_idx,
-                    char_offset: char_idx + 1,
-                    width: width_after,
-                    break_type: BreakType::SoftBreak,
-                    is_hyphenated: false,
-                    penalty,
-                    flagged: false,
-                });
-            }
+            // Prefer spaces to dashes
+            let penalty = match ch {
+                '-' | '\u{2010}' | '–' | '—' => PENALTY_HYPHEN,
+                _ => 0,
+            };
+
+            break_points.push(BreakPoint {
+                position: next_byte_idx,
+                char_offset: char_idx + 1,
+                width: positions[char_idx + 1],
+                break_type: BreakType::SoftBreak,
+                is_hyphenated: false,
+                penalty,
+                flagged: false,
+            });
         }
 
-        // Add end break point with total width
-        // Use a unique key to avoid deduplication with SoftBreaks at the same position
-        // Store the HardBreak separately and add it after dedup
-        let end_break = BreakPoint {
+        break_points.push(BreakPoint {
             position: len,
             char_offset: char_count,
             width: total_width,
@@ -381,14 +342,7 @@ impl LineBreaker {
             is_hyphenated: false,
             penalty: 0,
             flagged: false,
-        };
-
-        // Remove duplicates (SoftBreaks) but keep HardBreaks
-        break_points.retain(|bp| {
-            // Keep if position is different, or if it's a HardBreak
-            bp.position != len || bp.break_type == BreakType::HardBreak
         });
-        break_points.push(end_break);
 
         // Sort by position
         break_points.sort_by_key(|bp| bp.position);
@@ -438,8 +392,7 @@ impl LineBreaker {
     }
 
     /// Main breaking algorithm - finds optimal breaks using Knuth-Plass
-    fn find_breaks(&mut self, text: &str) -> Vec<BreakPoint> {
-        let break_points = self.get_break_points(text);
+    fn find_breaks(&self, break_points: Vec<BreakPoint>) -> Vec<BreakPoint> {
         if break_points.len() < 2 {
             return break_points;
         }
@@ -570,6 +523,69 @@ impl LineBreaker {
         result
     }
 
+    /// Total-fit breaking: the breaks with the least demerits summed over the paragraph
+    ///
+    /// Each line costs its squared slack, so one very loose line costs more than
+    /// several slightly loose ones; the last line may be short for free. Only
+    /// breaks within a line's width of each other are compared, and paragraphs
+    /// with more than MAX_TOTAL_FIT_BREAKS opportunities fall back to first fit.
+    fn find_total_fit_breaks(&self, break_points: Vec<BreakPoint>) -> Vec<BreakPoint> {
+        if break_points.len() < 2 {
+            return break_points;
+        }
+        if break_points.len() > MAX_TOTAL_FIT_BREAKS {
+            return self.find_breaks(break_points);
+        }
+
+        let max_width = self.config.max_width.max(f32::EPSILON);
+        // Least demerits of the text up to each break, and the break starting its last line
+        let mut best: Vec<(f32, usize)> = vec![(f32::INFINITY, 0); break_points.len()];
+        best[0] = (0.0, 0);
+
+        for (j, current) in break_points.iter().enumerate().skip(1) {
+            let mut penalties = current.penalty as f32;
+            if current.flagged {
+                penalties += DEMERITS_FLAGGED;
+            }
+            if current.is_hyphenated {
+                penalties += DEMERITS_HYPHEN;
+            }
+
+            for i in (0..j).rev() {
+                let previous = &break_points[i];
+                let line_width = current.width - previous.width;
+                // Only a single word too wide for any line may overflow
+                if line_width > max_width && i + 1 < j {
+                    break;
+                }
+                let fit = if line_width > max_width {
+                    DEMERITS_OVERFULL
+                } else if current.break_type == BreakType::HardBreak {
+                    0.0
+                } else {
+                    let slack = (max_width - line_width) / max_width * 100.0;
+                    slack * slack
+                };
+                let mut total = best[i].0 + fit + penalties;
+                if i > 0 && previous.is_hyphenated && current.is_hyphenated {
+                    total += DEMERITS_DOUBLE;
+                }
+                if total < best[j].0 {
+                    best[j] = (total, i);
+                }
+            }
+        }
+
+        let mut result = Vec::new();
+        let mut j = break_points.len() - 1;
+        while j > 0 {
+            result.push(break_points[j].clone());
+            j = best[j].1;
+        }
+        result.reverse();
+        result
+    }
+
     /// Breaks text into lines with optimal breaks
     pub fn break_lines(&mut self, text: &str, max_width: Option<f32>) -> Vec<Line> {
         if text.is_empty() {
@@ -591,21 +607,37 @@ impl LineBreaker {
                 continue;
             }
 
-            let breaks = self.find_breaks(paragraph);
+            // Other mandatory breaks, such as a line separator, end a line
+            // without ending the paragraph
+            let mut segment_start = 0usize;
+            let segments = paragraph.split_inclusive(|c| {
+                matches!(
+                    segmentation::line_break_class(c),
+                    LineBreakClass::MandatoryBreak | LineBreakClass::CarriageReturn | LineBreakClass::NextLine
+                )
+            });
+            for segment in segments {
+                let break_points = self.get_break_points(segment);
+                let breaks = match self.config.break_strategy {
+                    BreakStrategy::FirstFit => self.find_breaks(break_points),
+                    BreakStrategy::TotalFit => self.find_total_fit_breaks(break_points),
+                };
 
-            // Convert break points to lines
-            let mut prev_end = 0usize;
-            for (i, bp) in breaks.iter().enumerate() {
-                let start = if i == 0 { 0 } else { prev_end };
-                let end = bp.position;
+                // Convert break points to lines
+                let mut prev_end = 0usize;
+                for (i, bp) in breaks.iter().enumerate() {
+                    let start = if i == 0 { 0 } else { prev_end };
+                    let end = bp.position;
 
-                if end > start {
-                    let line_text = &paragraph[start..end];
-                    let width = self.text_width(line_text);
-                    lines.push(Line::new(start, end, width, bp.break_type));
-                }
+                    if end > start {
+                        let line_text = &segment[start..end];
+                        let width = self.text_width(line_text);
+                        lines.push(Line::new(segment_start + start, segment_start + end, width, bp.break_type));
+                    }
 
-                prev_end = end;
+                    prev_end = end;
+                }
+                segment_start += segment.len();
             }
         }
 
@@ -685,6 +717,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_no_break_rules() {
+        let mut breaker = LineBreaker::with_width(100.0);
+        // No line starts with closing punctuation or a small kana
+        let text = "这是一个测试文本，用于测试中文分行。ちょっとコーヒー";
+        for line in breaker.break_lines(text, None) {
+            let first = text[line.start..line.end].chars().next().unwrap();
+            assert!(!"，。ょっー".contains(first), "line starts with {:?}", first);
+        }
+
+        // A line separator ends a line without ending the paragraph
+        let lines = breaker.break_lines("one\u{2028}two", Some(1000.0));
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[1].start, lines[1].end), (6, 9));
+    }
+
     #[test]
     fn test_char_width_calculation() {
         let mut breaker = LineBreaker::new();
@@ -753,4 +801,64 @@ mod tests {
             assert!(line.width <= 200.0 + 50.0, "Line width {} exceeds max", line.width);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_break_points_use_cluster_advances() {
+        let mut breaker = LineBreaker::new();
+        let text = "cafe\u{301} مرحبا नमस्ते";
+        let positions = breaker.shape_text(text, &ShapingOptions::default()).char_positions();
+        let break_points = breaker.get_break_points(text);
+        for bp in &break_points {
+            assert!((bp.width - positions[bp.char_offset]).abs() < 0.01, "{:?}", bp);
+        }
+        // Not between the e and its accent
+        assert!(break_points.iter().all(|bp| bp.char_offset != 4));
+    }
+
+    /// Squared slack of every line but the last, the measure total fit minimizes
+    fn raggedness(lines: &[Line], max_width: f32) -> f32 {
+        lines[..lines.len() - 1]
+            .iter()
+            .map(|line| ((max_width - line.width) / max_width * 100.0).powi(2))
+            .sum()
+    }
+
+    #[test]
+    fn test_total_fit_is_more_even() {
+        let text = "Typesetting a paragraph one line at a time can leave a very short line \
+                    followed by a very long one, while looking at the whole paragraph at once \
+                    spreads the white space more evenly over all of its lines instead.";
+        let mut first_fit = LineBreaker::with_width(220.0);
+        let greedy = first_fit.break_lines(text, None);
+        let mut total_fit = LineBreaker::with_width(220.0);
+        total_fit.set_break_strategy(BreakStrategy::TotalFit);
+        let even = total_fit.break_lines(text, None);
+
+        assert!(even.len() > 2);
+        assert_eq!(even.last().map(|line| line.end), Some(text.len()));
+        for line in &even {
+            assert!(line.width <= 220.0 + 1.0, "Line width {} exceeds max", line.width);
+        }
+        assert!(raggedness(&even, 220.0) <= raggedness(&greedy, 220.0) + 1.0);
+    }
+
+    #[test]
+    fn test_total_fit_overlong_word() {
+        let mut breaker = LineBreaker::with_width(40.0);
+        breaker.set_break_strategy(BreakStrategy::TotalFit);
+        let text = "a incomprehensibilities b";
+        let lines = breaker.break_lines(text, None);
+        let words: Vec<&str> = lines.iter().map(|line| text[line.start..line.end].trim_end()).collect();
+        assert_eq!(words, ["a", "incomprehensibilities", "b"]);
+    }
+
+    #[test]
+    fn test_total_fit_falls_back_for_long_paragraphs() {
+        let text = "ab ".repeat(MAX_TOTAL_FIT_BREAKS + 1);
+        let mut first_fit = LineBreaker::with_width(300.0);
+        let mut total_fit = LineBreaker::with_width(300.0);
+        total_fit.set_break_strategy(BreakStrategy::TotalFit);
+        let spans = |lines: Vec<Line>| lines.iter().map(|line| (line.start, line.end)).collect::<Vec<_>>();
+        assert_eq!(spans(total_fit.break_lines(&text, None)), spans(first_fit.break_lines(&text, None)));
+    }
+}
@@ -3,7 +3,10 @@
 //! Provides higher-level text layout functionality including paragraph layout
 //! and bidirectional text support.
 
-use crate::line_breaking::{BreakType, LineBreaker};
+use std::ops::Range;
+use crate::bidi::{self, BidiParagraph};
+use crate::line_breaking::{BreakStrategy, BreakType, Line, LineBreaker};
+use crate::text_shaping::ShapingOptions;
 use serde::{Deserialize, Serialize};
 
 /// Line spacing rule enumeration
@@ -101,6 +104,25 @@ pub struct ParagraphProperties {
     pub line_spacing_rule: LineSpacingRule,
     /// Text alignment
     pub alignment: Alignment,
+    /// How lines are broken; None uses the layout's default
+    #[serde(default)]
+    pub break_strategy: Option<BreakStrategy>,
+    /// Kept on the same page as the first lines of the next paragraph
+    #[serde(default)]
+    pub keep_with_next: bool,
+    /// Kept whole on one page when it fits on one
+    #[serde(default)]
+    pub keep_lines_together: bool,
+    /// Starts a new page
+    #[serde(default)]
+    pub page_break_before: bool,
+    /// Leaves no single first or last line of the paragraph alone on a page
+    #[serde(default = "widow_control_default")]
+    pub widow_control: bool,
+}
+
+fn widow_control_default() -> bool {
+    true
 }
 
 impl Default for ParagraphProperties {
@@ -114,6 +136,11 @@ impl Default for ParagraphProperties {
             line_spacing: 1.0,
             line_spacing_rule: LineSpacingRule::Single,
             alignment: Alignment::default(),
+            break_strategy: None,
+            keep_with_next: false,
+            keep_lines_together: false,
+            page_break_before: false,
+            widow_control: true,
         }
     }
 }
@@ -171,6 +198,7 @@ impl ParagraphProperties {
             line_spacing,
             line_spacing_rule,
             alignment,
+            ..Default::default()
         }
     }
 }
@@ -198,6 +226,45 @@ pub struct ParagraphLayout {
     pub properties: ParagraphProperties,
 }
 
+impl ParagraphLayout {
+    /// Space above the first line, in the same units as the line heights
+    pub fn space_before(&self) -> f32 {
+        self.properties.space_before * self.max_width / 1440.0
+    }
+
+    /// Space below the last line, in the same units as the line heights
+    pub fn space_after(&self) -> f32 {
+        self.properties.space_after * self.max_width / 1440.0
+    }
+
+    /// Byte ranges of line `line_index` in the order they are displayed, left
+    /// to right; ranges read right to left are reversed in their glyphs
+    ///
+    /// Levels are resolved over the whole paragraph, as the bidi algorithm
+    /// needs, then the line is reordered on its own.
+    pub fn visual_order(&self, line_index: usize) -> Vec<(usize, usize)> {
+        let Some(line) = self.lines.get(line_index) else {
+            return Vec::new();
+        };
+        if !self.has_bidi {
+            return vec![(line.start, line.end)];
+        }
+        visual_byte_ranges(&self.text, line.start..line.end)
+    }
+}
+
+/// Byte ranges of `line` of `text` in display order, one per bidi run
+fn visual_byte_ranges(text: &str, line: Range<usize>) -> Vec<(usize, usize)> {
+    let bytes: Vec<usize> = text.char_indices().map(|(byte, _)| byte).chain(std::iter::once(text.len())).collect();
+    let char_of = |byte: usize| bytes.partition_point(|&start| start < byte);
+    let paragraph = BidiParagraph::new(text, None);
+    paragraph
+        .visual_runs(char_of(line.start)..char_of(line.end))
+        .into_iter()
+        .map(|run| (bytes[run.range.start], bytes[run.range.end]))
+        .collect()
+}
+
 /// Complete document layout result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentLayout {
@@ -224,6 +291,8 @@ pub struct LineLayoutConfig {
     pub bidi_enabled: bool,
     /// Trim trailing whitespace
     pub trim_trailing: bool,
+    /// Line breaking for paragraphs that do not choose their own
+    pub break_strategy: BreakStrategy,
 }
 
 impl Default for LineLayoutConfig {
@@ -234,6 +303,7 @@ impl Default for LineLayoutConfig {
             font_size: 14.0,
             bidi_enabled: true,
             trim_trailing: true,
+            break_strategy: BreakStrategy::default(),
         }
     }
 }
@@ -288,6 +358,18 @@ impl LineLayout {
         self.config.bidi_enabled = enabled;
     }
 
+    /// Sets the line breaking used by paragraphs that do not choose their own
+    #[inline]
+    pub fn set_break_strategy(&mut self, strategy: BreakStrategy) {
+        self.config.break_strategy = strategy;
+    }
+
+    /// Sets the language and OpenType features text is shaped with
+    #[inline]
+    pub fn set_shaping_options(&mut self, options: ShapingOptions) {
+        self.breaker.set_shaping_options(options);
+    }
+
     /// Calculates the line height based on spacing rule
     fn calculate_line_height(&self, base_height: f32, props: ParagraphProperties) -> f32 {
         match props.line_spacing_rule {
@@ -300,6 +382,11 @@ impl LineLayout {
         }
     }
 
+    /// Height of a line of a paragraph with `props`
+    pub fn line_height(&self, props: ParagraphProperties) -> f32 {
+        self.calculate_line_height(self.config.line_height * self.config.font_size, props)
+    }
+
     /// Calculates the left offset for a line based on indentation
     fn calculate_line_offset(&self, line_index: usize, props: ParagraphProperties) -> f32 {
         let left_indent = props.indent_left;
@@ -335,12 +422,74 @@ impl LineLayout {
 
         // Set breaker max width to content width
         self.breaker.set_max_width(content_width);
+        self.breaker
+            .set_break_strategy(props.break_strategy.unwrap_or(self.config.break_strategy));
 
         let lines = self.breaker.break_lines(text, None);
+        self.paragraph_from_lines(text, max_width, content_width, props, &lines, &[])
+    }
+
+    /// Layouts a single paragraph with custom properties, each line as wide as
+    /// `band` gives for its index, with how far right of the line's usual start
+    /// it goes
+    ///
+    /// This is how text flows beside floating objects: lines break one at a
+    /// time, so each may get a different width.
+    pub fn layout_paragraph_in_bands(
+        &mut self,
+        text: &str,
+        max_width: f32,
+        props: ParagraphProperties,
+        mut band: impl FnMut(usize) -> (f32, f32),
+    ) -> ParagraphLayout {
+        let twips_to_units = max_width / 1440.0;
+        let content_width = max_width - (props.indent_left + props.indent_right) * twips_to_units;
+        self.breaker
+            .set_break_strategy(props.break_strategy.unwrap_or(self.config.break_strategy));
+
+        let mut lines = Vec::new();
+        let mut shifts = Vec::new();
+        let mut start = 0usize;
+        while start < text.len() {
+            let (shift, width) = band(lines.len());
+            let rest = &text[start..];
+            let Some(line) = self.breaker.break_lines(rest, Some(width)).into_iter().next() else {
+                break;
+            };
+            // A line always takes at least one char, however narrow
+            let end = match line.end {
+                0 => rest.chars().next().map_or(rest.len(), char::len_utf8),
+                end => end,
+            };
+            lines.push(Line::new(start + line.start, start + end, line.width, line.break_type));
+            shifts.push(shift);
+            start += end;
+        }
+        self.breaker.set_max_width(content_width);
+        self.paragraph_from_lines(text, max_width, content_width, props, &lines, &shifts)
+    }
+
+    /// The layout of a paragraph broken into `lines`, each moved right by its
+    /// entry in `shifts` if it has one
+    fn paragraph_from_lines(
+        &mut self,
+        text: &str,
+        max_width: f32,
+        content_width: f32,
+        props: ParagraphProperties,
+        lines: &[Line],
+        shifts: &[f32],
+    ) -> ParagraphLayout {
+        let twips_to_units = max_width / 1440.0;
+        let left_indent_units = props.indent_left * twips_to_units;
         let mut layout_lines = Vec::new();
 
         let mut has_bidi = false;
         let mut char_offset = 0usize;
+        // Levels of each char, for paragraphs where some text may read right to left
+        let bidi = (self.config.bidi_enabled && bidi::needs_bidi(text)).then(|| BidiParagraph::new(text, None));
+        // Byte and char offsets of the end of the last line, to count chars from
+        let mut counted = (0usize, 0usize);
 
         // Calculate base line height
         let base_line_height = self.config.line_height * self.config.font_size;
@@ -369,26 +518,13 @@ impl LineLayout {
             let char_count = line_text.chars().count();
 
             // Check for bidirectional text
-            let is_bidi = if self.config.bidi_enabled {
-                let has_rtl = line_text.chars().any(|c| {
-                    matches!(
-                        c,
-                        '\u{0590}'..='\u{05FF}' |  // Hebrew
-                        '\u{0600}'..='\u{06FF}' |  // Arabic
-                        '\u{0750}'..='\u{077F}' |  // Arabic Supplement
-                        '\u{08A0}'..='\u{08FF}' |  // Arabic Extended-A
-                        '\u{FB50}'..='\u{FDFF}' |  // Arabic Presentation Forms-A
-                        '\u{FE70}'..='\u{FEFF}' |  // Arabic Presentation Forms-B
-                        '\u{10800}'..='\u{10FFF}'  // Private Use Area (some RTL scripts)
-                    )
-                });
-                if has_rtl {
-                    has_bidi = true;
-                }
-                has_rtl
-            } else {
-                false
-            };
+            let is_bidi = bidi.as_ref().is_some_and(|bidi| {
+                let (byte, chars) = counted;
+                let first = chars + text.get(byte..line.start).map_or(0, |gap| gap.chars().count());
+                counted = (line.end, first + char_count);
+                bidi.levels().get(first..first + char_count).is_some_and(|levels| levels.iter().any(|level| level % 2 == 1))
+            });
+            has_bidi |= is_bidi;
 
             // Calculate trailing whitespace
             let trailing_ws = if self.config.trim_trailing {
@@ -404,7 +540,7 @@ impl LineLayout {
             };
 
             // Calculate line offset based on indentation
-            let offset_x = self.calculate_line_offset(i, props);
+            let offset_x = self.calculate_line_offset(i, props) + shifts.get(i).copied().unwrap_or(0.0);
 
             let break_type_str = match line.break_type {
                 BreakType::HardBreak => "HardBreak",
@@ -486,6 +622,36 @@ impl LineLayout {
         }
     }
 
+    /// Layouts a full document with properties for each paragraph, in order
+    /// Paragraphs past the end of `props` use the defaults
+    pub fn layout_document_with_paragraph_props(
+        &mut self,
+        text: &str,
+        max_width: f32,
+        props: &[ParagraphProperties],
+    ) -> DocumentLayout {
+        let mut all_paragraphs = Vec::new();
+        let mut total_width = 0.0f32;
+        let mut total_height = 0.0f32;
+
+        for (i, paragraph) in text.split('\n').enumerate() {
+            let paragraph_props = props.get(i).copied().unwrap_or_default();
+            let layout = self.layout_paragraph_with_props(paragraph, max_width, paragraph_props);
+            for line in &layout.lines {
+                total_width = total_width.max(line.width + line.offset_x);
+            }
+            total_height += layout.total_height;
+            all_paragraphs.push(layout);
+        }
+
+        DocumentLayout {
+            paragraphs: all_paragraphs,
+            total_width,
+            total_height,
+            line_height: self.config.line_height * self.config.font_size,
+        }
+    }
+
     /// Layouts text and returns JSON string
     pub fn layout_to_json(&mut self, text: &str, max_width: f32) -> String {
         let layout = self.layout_document(text, max_width);
@@ -503,16 +669,16 @@ impl LineLayout {
         serde_json::to_string(&layout).unwrap_or_else(|_| "{}".to_string())
     }
 
-    /// Calculates the visual order for a bidirectional line
-    #[allow(dead_code)]
+    /// Calculates the visual order for a bidirectional line: its byte ranges
+    /// in display order, one per run of a direction
     pub fn calculate_visual_order(&self, text: &str) -> Vec<(usize, usize)> {
         if text.is_empty() {
             return Vec::new();
         }
-
-        // Simple implementation - returns the text as-is for LTR
-        // Full bidirectional reordering would require more complex handling
-        vec![(0, text.len())]
+        if !self.config.bidi_enabled || !bidi::needs_bidi(text) {
+            return vec![(0, text.len())];
+        }
+        visual_byte_ranges(text, 0..text.len())
     }
 
     /// Gets the line breaker for direct access
@@ -642,6 +808,22 @@ mod tests {
         let text = "Hello";
         let order = layout.calculate_visual_order(text);
         assert!(!order.is_empty());
+
+        // Hebrew after English is shown as a run of its own; English in a
+        // Hebrew paragraph goes on the left
+        assert_eq!(layout.calculate_visual_order("abc אב"), vec![(0, 4), (4, 8)]);
+        assert_eq!(layout.calculate_visual_order("אב abc"), vec![(5, 8), (0, 5)]);
+    }
+
+    #[test]
+    fn test_bidi_lines() {
+        let mut layout = LineLayout::new();
+        let result = layout.layout_paragraph("Hello שלום 42", 1000.0);
+        assert!(result.has_bidi && result.lines[0].is_bidi);
+        let text = &result.text;
+        let runs: Vec<&str> = result.visual_order(0).iter().map(|&(start, end)| &text[start..end]).collect();
+        assert_eq!(runs, vec!["Hello ", "42", "שלום "]);
+        assert!(!layout.layout_paragraph("Hello 42", 1000.0).has_bidi);
     }
 
     #[test]
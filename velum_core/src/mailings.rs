@@ -0,0 +1,515 @@
+//! # Mailings Module
+//!
+//! Generators for envelopes and label sheets, ready to print.
+//!
+//! An envelope is a page the size of the envelope, fed landscape, with the
+//! return address framed in its top left corner and the delivery address
+//! framed a little left of and below its middle, as Word places them. There
+//! is no POSTNET barcode; postal services print their own.
+//!
+//! A label sheet is a table laid over the paper at the exact pitch of the
+//! label stock: fixed column widths and exact row heights, with empty spacer
+//! columns and rows where the labels have gaps between them. Each label is a
+//! cell holding an address. Sizes are in points, as in [`crate::page_setup`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{
+    Paragraph, ParagraphFrame, ParagraphProperties, Run, Table, TableCell, TableCellProperties, TableProperties,
+    TableRow, TableRowProperties,
+};
+use crate::page_setup::{Margins, Orientation, PaperSize, SectionPageSetup};
+
+const TWIPS_PER_POINT: f32 = 20.0;
+
+/// Points per inch and per millimetre
+const INCH: f32 = 72.0;
+const MM: f32 = 72.0 / 25.4;
+
+/// Text inset from the left and right edges of a label
+const LABEL_INSET: f32 = 4.5;
+
+/// Mailing errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum MailingError {
+    #[error("Unknown envelope size '{0}'")]
+    UnknownEnvelopeSize(String),
+
+    #[error("Unknown label product '{0}'")]
+    UnknownLabel(String),
+
+    #[error("A delivery address is needed")]
+    MissingAddress,
+}
+
+/// Standard envelope sizes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EnvelopeSize {
+    /// US No. 10 business envelope, 9.5 x 4.125 in
+    Number10,
+    /// US No. 9, 8.875 x 3.875 in
+    Number9,
+    /// US Monarch, 7.5 x 3.875 in
+    Monarch,
+    /// DL, 220 x 110 mm
+    DL,
+    /// C5, 229 x 162 mm
+    C5,
+    /// C6, 162 x 114 mm
+    C6,
+    /// Custom size in points, long edge first
+    Custom { width: f32, height: f32 },
+}
+
+impl EnvelopeSize {
+    /// Dimensions (width, height) in points as the envelope is fed, long edge across
+    pub fn dimensions(&self) -> (f32, f32) {
+        let (width, height) = match self {
+            EnvelopeSize::Number10 => (9.5 * INCH, 4.125 * INCH),
+            EnvelopeSize::Number9 => (8.875 * INCH, 3.875 * INCH),
+            EnvelopeSize::Monarch => (7.5 * INCH, 3.875 * INCH),
+            EnvelopeSize::DL => (220.0 * MM, 110.0 * MM),
+            EnvelopeSize::C5 => (229.0 * MM, 162.0 * MM),
+            EnvelopeSize::C6 => (162.0 * MM, 114.0 * MM),
+            EnvelopeSize::Custom { width, height } => (*width, *height),
+        };
+        (width.max(height), width.min(height))
+    }
+
+    /// Look up a preset by name (case-insensitive), e.g. "10", "DL" or "C5"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().trim_start_matches("no.").trim() {
+            "10" | "com10" => Some(EnvelopeSize::Number10),
+            "9" | "com9" => Some(EnvelopeSize::Number9),
+            "monarch" => Some(EnvelopeSize::Monarch),
+            "dl" => Some(EnvelopeSize::DL),
+            "c5" => Some(EnvelopeSize::C5),
+            "c6" => Some(EnvelopeSize::C6),
+            _ => None,
+        }
+    }
+}
+
+/// A label product: the paper, the grid of labels on it and their pitch, in points
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LabelProduct {
+    /// Product number, e.g. "5160"
+    pub name: &'static str,
+    pub description: &'static str,
+    pub paper: PaperSize,
+    pub columns: usize,
+    pub rows: usize,
+    pub label_width: f32,
+    pub label_height: f32,
+    /// From the top edge of the paper to the first row
+    pub top_margin: f32,
+    /// From the left edge of the paper to the first column
+    pub side_margin: f32,
+    /// From the left of one label to the left of the next
+    pub horizontal_pitch: f32,
+    /// From the top of one label to the top of the next
+    pub vertical_pitch: f32,
+}
+
+/// Common Avery label products
+pub const LABEL_PRODUCTS: &[LabelProduct] = &[
+    LabelProduct {
+        name: "5160",
+        description: "Address, 1 x 2-5/8 in, 30 per sheet",
+        paper: PaperSize::Letter,
+        columns: 3,
+        rows: 10,
+        label_width: 2.625 * INCH,
+        label_height: 1.0 * INCH,
+        top_margin: 0.5 * INCH,
+        side_margin: 0.1875 * INCH,
+        horizontal_pitch: 2.75 * INCH,
+        vertical_pitch: 1.0 * INCH,
+    },
+    LabelProduct {
+        name: "5161",
+        description: "Address, 1 x 4 in, 20 per sheet",
+        paper: PaperSize::Letter,
+        columns: 2,
+        rows: 10,
+        label_width: 4.0 * INCH,
+        label_height: 1.0 * INCH,
+        top_margin: 0.5 * INCH,
+        side_margin: 0.15625 * INCH,
+        horizontal_pitch: 4.1875 * INCH,
+        vertical_pitch: 1.0 * INCH,
+    },
+    LabelProduct {
+        name: "5162",
+        description: "Address, 1-1/3 x 4 in, 14 per sheet",
+        paper: PaperSize::Letter,
+        columns: 2,
+        rows: 7,
+        label_width: 4.0 * INCH,
+        label_height: 1.333 * INCH,
+        top_margin: 0.84 * INCH,
+        side_margin: 0.15625 * INCH,
+        horizontal_pitch: 4.1875 * INCH,
+        vertical_pitch: 1.333 * INCH,
+    },
+    LabelProduct {
+        name: "5163",
+        description: "Shipping, 2 x 4 in, 10 per sheet",
+        paper: PaperSize::Letter,
+        columns: 2,
+        rows: 5,
+        label_width: 4.0 * INCH,
+        label_height: 2.0 * INCH,
+        top_margin: 0.5 * INCH,
+        side_margin: 0.15625 * INCH,
+        horizontal_pitch: 4.1875 * INCH,
+        vertical_pitch: 2.0 * INCH,
+    },
+    LabelProduct {
+        name: "5167",
+        description: "Return address, 1/2 x 1-3/4 in, 80 per sheet",
+        paper: PaperSize::Letter,
+        columns: 4,
+        rows: 20,
+        label_width: 1.75 * INCH,
+        label_height: 0.5 * INCH,
+        top_margin: 0.5 * INCH,
+        side_margin: 0.28125 * INCH,
+        horizontal_pitch: 2.0625 * INCH,
+        vertical_pitch: 0.5 * INCH,
+    },
+    LabelProduct {
+        name: "L7160",
+        description: "Address, 63.5 x 38.1 mm, 21 per sheet",
+        paper: PaperSize::A4,
+        columns: 3,
+        rows: 7,
+        label_width: 63.5 * MM,
+        label_height: 38.1 * MM,
+        top_margin: 15.15 * MM,
+        side_margin: 7.25 * MM,
+        horizontal_pitch: 66.04 * MM,
+        vertical_pitch: 38.1 * MM,
+    },
+    LabelProduct {
+        name: "L7163",
+        description: "Address, 99.1 x 38.1 mm, 14 per sheet",
+        paper: PaperSize::A4,
+        columns: 2,
+        rows: 7,
+        label_width: 99.1 * MM,
+        label_height: 38.1 * MM,
+        top_margin: 15.15 * MM,
+        side_margin: 4.65 * MM,
+        horizontal_pitch: 101.6 * MM,
+        vertical_pitch: 38.1 * MM,
+    },
+    LabelProduct {
+        name: "L7651",
+        description: "Mini, 38.1 x 21.2 mm, 65 per sheet",
+        paper: PaperSize::A4,
+        columns: 5,
+        rows: 13,
+        label_width: 38.1 * MM,
+        label_height: 21.2 * MM,
+        top_margin: 10.7 * MM,
+        side_margin: 4.75 * MM,
+        horizontal_pitch: 40.64 * MM,
+        vertical_pitch: 21.2 * MM,
+    },
+];
+
+impl LabelProduct {
+    /// Look up a product by number, with or without an "Avery" prefix
+    pub fn find(name: &str) -> Option<&'static LabelProduct> {
+        let name = name.trim();
+        let name = name
+            .get(..5)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("avery"))
+            .map_or(name, |_| name[5..].trim());
+        LABEL_PRODUCTS.iter().find(|product| product.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn labels_per_sheet(&self) -> usize {
+        self.columns * self.rows
+    }
+}
+
+/// A generated document and the page geometry it prints with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailingDocument {
+    pub model: DocumentModel,
+    pub page: SectionPageSetup,
+}
+
+/// An envelope addressed to `delivery`, from `return_address` if it is not
+/// empty; addresses have one line per "\n"
+pub fn envelope(size: EnvelopeSize, delivery: &str, return_address: &str) -> Result<MailingDocument, MailingError> {
+    if address_lines(delivery).is_empty() {
+        return Err(MailingError::MissingAddress);
+    }
+    let (width, height) = size.dimensions();
+    let margin = 0.375 * INCH;
+    let page_frame = |x: f32, y: f32, frame_width: f32| ParagraphFrame {
+        x: twips(x),
+        y: twips(y),
+        width: Some(twips(frame_width)),
+        height: None,
+        horizontal_anchor: Some("page".to_string()),
+        vertical_anchor: Some("page".to_string()),
+    };
+
+    let mut body = Vec::new();
+    let return_frame = page_frame(margin, margin, width * 0.4);
+    body.extend(address_paragraphs(return_address, Some(&return_frame)).into_iter().map(Block::from));
+    // Word's automatic position: just left of centre, just above the middle
+    let (x, y) = (width * 0.42, height * 0.48);
+    let delivery_frame = page_frame(x, y, width - x - margin);
+    body.extend(address_paragraphs(delivery, Some(&delivery_frame)).into_iter().map(Block::from));
+
+    Ok(MailingDocument {
+        model: DocumentModel {
+            body,
+            ..Default::default()
+        },
+        page: SectionPageSetup {
+            width,
+            height,
+            orientation: Orientation::Landscape,
+            margins: Margins {
+                header: 0.0,
+                footer: 0.0,
+                ..Margins::uniform(margin)
+            },
+        },
+    })
+}
+
+/// Sheets of `product` labels holding `addresses` in order, across then down
+///
+/// With `full_sheet`, the first address fills every label of one sheet.
+/// Labels past the last address are left blank.
+pub fn label_sheets(product: &LabelProduct, addresses: &[String], full_sheet: bool) -> MailingDocument {
+    let addresses: Vec<&str> = if full_sheet {
+        vec![addresses.first().map_or("", String::as_str); product.labels_per_sheet()]
+    } else {
+        addresses.iter().map(String::as_str).collect()
+    };
+    let sheets = addresses.len().div_ceil(product.labels_per_sheet()).max(1);
+    let horizontal_gap = (product.horizontal_pitch - product.label_width).max(0.0);
+    let vertical_gap = (product.vertical_pitch - product.label_height).max(0.0);
+
+    let mut widths = Vec::new();
+    for column in 0..product.columns {
+        if column > 0 && horizontal_gap > 0.0 {
+            widths.push(twips(horizontal_gap) as u32);
+        }
+        widths.push(twips(product.label_width) as u32);
+    }
+    let spacer_row = |widths: &[u32]| TableRow {
+        cells: widths.iter().map(|&width| cell(width, "")).collect(),
+        height: Some(twips(vertical_gap) as u32),
+        properties: exact_height(vertical_gap),
+    };
+
+    let mut rows = Vec::new();
+    let mut labels = addresses.into_iter();
+    for row in 0..sheets * product.rows {
+        // Gaps only fall between rows of a sheet; the next sheet starts at its top margin
+        if row % product.rows > 0 && vertical_gap > 0.0 {
+            rows.push(spacer_row(&widths));
+        }
+        let mut cells = Vec::new();
+        for column in 0..product.columns {
+            if column > 0 && horizontal_gap > 0.0 {
+                cells.push(cell(twips(horizontal_gap) as u32, ""));
+            }
+            cells.push(cell(twips(product.label_width) as u32, labels.next().unwrap_or_default()));
+        }
+        rows.push(TableRow {
+            cells,
+            height: Some(twips(product.label_height) as u32),
+            properties: exact_height(product.label_height),
+        });
+    }
+
+    let table = Table {
+        rows,
+        properties: TableProperties {
+            width: Some(widths.iter().sum()),
+            indent: Some(0),
+            layout: Some("fixed".to_string()),
+            grid_columns: widths.clone(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (width, height) = product.paper.dimensions();
+    let grid_height = product.rows as f32 * product.vertical_pitch - vertical_gap;
+    MailingDocument {
+        model: DocumentModel {
+            body: vec![Block::Table(Box::new(table))],
+            ..Default::default()
+        },
+        page: SectionPageSetup {
+            width,
+            height,
+            orientation: Orientation::Portrait,
+            // The bottom margin leaves exactly one sheet of rows on each page
+            margins: Margins {
+                top: product.top_margin,
+                bottom: (height - product.top_margin - grid_height).max(0.0),
+                left: product.side_margin,
+                right: (width - product.side_margin - product.columns as f32 * product.horizontal_pitch + horizontal_gap)
+                    .max(0.0),
+                header: 0.0,
+                footer: 0.0,
+                gutter: 0.0,
+            },
+        },
+    }
+}
+
+fn twips(points: f32) -> i32 {
+    (points * TWIPS_PER_POINT).round() as i32
+}
+
+/// The non-empty lines of an address, trimmed
+fn address_lines(address: &str) -> Vec<&str> {
+    address.lines().map(str::trim).filter(|line| !line.is_empty()).collect()
+}
+
+/// One paragraph per address line, without space between them
+fn address_paragraphs(address: &str, frame: Option<&ParagraphFrame>) -> Vec<Paragraph> {
+    address_lines(address)
+        .into_iter()
+        .map(|line| Paragraph {
+            text: line.to_string(),
+            runs: vec![Run {
+                text: line.to_string(),
+                ..Default::default()
+            }],
+            properties: ParagraphProperties {
+                spacing_before: Some(0),
+                spacing_after: Some(0),
+                frame: frame.cloned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// A label cell `width` twips wide; an empty one still holds a paragraph, as Word requires
+fn cell(width: u32, address: &str) -> TableCell {
+    let mut paragraphs = address_paragraphs(address, None);
+    for paragraph in paragraphs.iter_mut() {
+        paragraph.properties.indent_left = Some(twips(LABEL_INSET));
+        paragraph.properties.indent_right = Some(twips(LABEL_INSET));
+    }
+    if paragraphs.is_empty() {
+        paragraphs.push(Paragraph::default());
+    }
+    TableCell {
+        paragraphs,
+        width: Some(width),
+        properties: TableCellProperties {
+            width: Some(width),
+            vertical_alignment: Some("center".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn exact_height(points: f32) -> TableRowProperties {
+    TableRowProperties {
+        height: Some(twips(points) as u32),
+        height_rule: Some("exact".to_string()),
+        is_header: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(document: &MailingDocument) -> Vec<(String, i32, i32)> {
+        document
+            .model
+            .paragraphs()
+            .map(|paragraph| {
+                let frame = paragraph.properties.frame.as_ref().unwrap();
+                (paragraph.text.clone(), frame.x, frame.y)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_envelope_frames() {
+        assert_eq!(EnvelopeSize::from_name("No. 10"), Some(EnvelopeSize::Number10));
+        assert_eq!(EnvelopeSize::from_name("dl"), Some(EnvelopeSize::DL));
+        assert_eq!(envelope(EnvelopeSize::DL, " \n ", "").unwrap_err(), MailingError::MissingAddress);
+
+        let document = envelope(EnvelopeSize::Number10, "Ann Lee\n1 Main St\n\nSpringfield", "Bob Day\n2 Elm St").unwrap();
+        assert_eq!((document.page.width, document.page.height), (684.0, 297.0));
+        assert_eq!(document.page.orientation, Orientation::Landscape);
+        // 0.375in from the top left, then 42% across and 48% down
+        assert_eq!(
+            frames(&document),
+            vec![
+                ("Bob Day".to_string(), 540, 540),
+                ("2 Elm St".to_string(), 540, 540),
+                ("Ann Lee".to_string(), 5746, 2851),
+                ("1 Main St".to_string(), 5746, 2851),
+                ("Springfield".to_string(), 5746, 2851),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_label_grid_matches_product() {
+        let product = LabelProduct::find("Avery 5160").unwrap();
+        let addresses: Vec<String> = (1..=31).map(|n| format!("Name {}\nStreet {}", n, n)).collect();
+        let document = label_sheets(product, &addresses, false);
+        let Block::Table(ref table) = document.model.body[0] else {
+            panic!("expected a table");
+        };
+
+        // Two sheets of 10 rows; 3 labels with 2 gap columns between them
+        assert_eq!(table.rows.len(), 20);
+        let widths: Vec<Option<u32>> = table.rows[0].cells.iter().map(|cell| cell.width).collect();
+        assert_eq!(widths, vec![Some(3780), Some(180), Some(3780), Some(180), Some(3780)]);
+        assert!(table.rows.iter().all(|row| row.properties.height == Some(1440)
+            && row.properties.height_rule.as_deref() == Some("exact")));
+        assert_eq!(table.rows[0].cells[2].paragraphs[0].text, "Name 2");
+        assert_eq!(table.rows[10].cells[0].paragraphs[1].text, "Street 31");
+        assert!(table.rows[10].cells[2].paragraphs[0].text.is_empty());
+
+        assert_eq!(document.page.margins.top, 36.0);
+        assert_eq!(document.page.margins.left, 13.5);
+        assert_eq!(document.page.margins.bottom, 36.0);
+        assert!(LabelProduct::find("9999").is_none());
+    }
+
+    #[test]
+    fn test_full_sheet_with_row_gaps() {
+        let mut product = *LabelProduct::find("L7163").unwrap();
+        product.vertical_pitch = product.label_height + 9.0;
+        let document = label_sheets(&product, &["Ann Lee".to_string()], true);
+        let Block::Table(ref table) = document.model.body[0] else {
+            panic!("expected a table");
+        };
+        // 7 label rows with 6 spacer rows between them, every label filled
+        assert_eq!(table.rows.len(), 13);
+        assert_eq!(table.rows[1].properties.height, Some(180));
+        let filled = table
+            .rows
+            .iter()
+            .flat_map(|row| &row.cells)
+            .filter(|cell| cell.paragraphs[0].text == "Ann Lee")
+            .count();
+        assert_eq!(filled, 14);
+    }
+}
@@ -0,0 +1,1963 @@
+//! # Markdown Module
+//!
+//! Converts between Markdown and the [`DocumentModel`], for opening .md files
+//! and the "Save as Markdown" command.
+//!
+//! Import reads CommonMark with the GitHub (GFM) extensions. ATX and setext
+//! headings take the Heading 1–6 styles, emphasis and strong emphasis become
+//! italic and bold runs, and code spans a monospace font. A code block is one
+//! paragraph in that font with line breaks (U+2028), as a pasted pre is, a
+//! block quote is a left indent, and a thematic break an empty paragraph with
+//! a bottom border. Each outermost list becomes a list of its own with the
+//! lists nested in it as its deeper levels. Links, autolinks and reference
+//! links become hyperlinks, and images linked images where they sit in the
+//! text. Tables keep their column alignment, task list items start with a
+//! ballot box, and struck-through text keeps its text only, as runs cannot be
+//! struck through. HTML blocks are imported as pasted HTML.
+//!
+//! Export writes the same constructs back. The flavor decides what has no
+//! CommonMark syntax: GFM writes tables as pipe tables and CommonMark as HTML.
+//! Formatting Markdown cannot express, such as fonts, colors and sizes, is
+//! dropped.
+
+use std::collections::HashMap;
+
+use crate::document_model::{Block, DocumentModel, ModelMetadata};
+use crate::html_import::{decode_entities, import_html};
+use crate::numbering::{ListKind, ListNumbering};
+use crate::ooxml::{
+    DocumentImage, Hyperlink, Paragraph, ParagraphBorder, ParagraphProperties, Run, RunProperties, Style, Table,
+    TableCell, TableRow, TableRowProperties,
+};
+
+/// Line break within a paragraph
+const LINE_BREAK: char = '\u{2028}';
+
+/// Font of code spans and code blocks
+const MONOSPACE_FONT: &str = "Courier New";
+
+/// Fonts whose runs are written as code
+const MONOSPACE_FONTS: [&str; 7] = [
+    "Courier New",
+    "Courier",
+    "Consolas",
+    "Menlo",
+    "Monaco",
+    "Lucida Console",
+    "Source Code Pro",
+];
+
+/// Indent of a block quote, and of each list level, in twips
+const QUOTE_INDENT: i32 = 720;
+
+/// Deepest list level OOXML allows, from 0
+const MAX_LIST_LEVEL: u8 = 8;
+
+/// Font sizes of Heading 1–6, in points
+const HEADING_SIZES: [i32; 6] = [16, 13, 12, 11, 11, 11];
+
+/// Tags starting an HTML block
+const HTML_BLOCKS: [&str; 28] = [
+    "address", "article", "aside", "blockquote", "center", "details", "div", "dl", "fieldset", "figure", "footer",
+    "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "main", "nav", "ol", "p", "pre", "section", "table",
+    "ul",
+];
+
+/// What task list items start with, unchecked and checked
+const TASK_BOXES: [&str; 2] = ["☐ ", "☒ "];
+
+/// Which Markdown export writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkdownFlavor {
+    /// The CommonMark specification; tables are written as HTML
+    CommonMark,
+    /// GitHub Flavored Markdown, with pipe tables and strikethrough
+    #[default]
+    Gfm,
+}
+
+impl MarkdownFlavor {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "commonmark" => Some(MarkdownFlavor::CommonMark),
+            "gfm" | "github" => Some(MarkdownFlavor::Gfm),
+            _ => None,
+        }
+    }
+}
+
+// ==================== Import ====================
+
+/// Read Markdown into a document model, with a list definition for each list
+/// and the styles of the headings used
+pub fn import_markdown(markdown: &str) -> DocumentModel {
+    let lines: Vec<String> = markdown.trim_start_matches('\u{feff}').lines().map(expand_tabs).collect();
+    let mut importer = Importer::new();
+    let lines = importer.take_definitions(&lines);
+    importer.blocks(&lines, &Context::default());
+    importer.finish()
+}
+
+/// Tabs as spaces up to the next multiple of 4 columns
+fn expand_tabs(line: &str) -> String {
+    if !line.contains('\t') {
+        return line.to_string();
+    }
+    let mut expanded = String::with_capacity(line.len());
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let width = 4 - column % 4;
+            expanded.extend(std::iter::repeat_n(' ', width));
+            column += width;
+        } else {
+            expanded.push(c);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+/// Where blocks go: the indent of the quotes and list items they are in, and
+/// the list item they belong to
+#[derive(Debug, Clone, Default)]
+struct Context {
+    indent_left: i32,
+    /// List and level of the enclosing list item
+    list: Option<(String, u8)>,
+}
+
+/// A link target: URL and title
+type LinkTarget = (String, Option<String>);
+
+struct Importer {
+    numbering: ListNumbering,
+    /// Link reference definitions by normalized label
+    definitions: HashMap<String, LinkTarget>,
+    body: Vec<Block>,
+    images: Vec<DocumentImage>,
+    /// Body paragraphs so far, which images and tables are placed by
+    paragraph_count: usize,
+    /// List item whose first paragraph comes next
+    item: Option<(String, u8)>,
+    /// Heading levels used, from 1
+    headings: [bool; 6],
+    title: Option<String>,
+}
+
+/// What a line starts besides a paragraph
+#[derive(Debug)]
+enum BlockStart {
+    Fence(String),
+    Heading(u8),
+    Break,
+    Quote,
+    List(ListMarker),
+    Table(Vec<Option<&'static str>>),
+    Html,
+}
+
+/// A list item's marker
+#[derive(Debug, Clone, Copy)]
+struct ListMarker {
+    ordered: bool,
+    /// '-', '*' or '+' for bullets, '.' or ')' after numbers
+    delimiter: char,
+    /// Number an ordered item starts with
+    start: u32,
+    /// Column the item's content starts at
+    content: usize,
+    /// Whether anything follows the marker on its line
+    has_content: bool,
+}
+
+impl ListMarker {
+    fn continues(&self, other: &ListMarker) -> bool {
+        self.ordered == other.ordered && self.delimiter == other.delimiter
+    }
+}
+
+impl Importer {
+    fn new() -> Self {
+        Importer {
+            numbering: ListNumbering::new(),
+            definitions: HashMap::new(),
+            body: Vec::new(),
+            images: Vec::new(),
+            paragraph_count: 0,
+            item: None,
+            headings: [false; 6],
+            title: None,
+        }
+    }
+
+    /// Remove the link reference definitions, keeping the first of each label
+    fn take_definitions(&mut self, lines: &[String]) -> Vec<String> {
+        let mut kept = Vec::with_capacity(lines.len());
+        let mut fence: Option<String> = None;
+        // A definition cannot interrupt a paragraph
+        let mut after_paragraph = false;
+        for line in lines {
+            if let Some(open) = &fence {
+                if indentation(line) < 4 && is_closing_fence(line.trim_start(), open) {
+                    fence = None;
+                }
+                kept.push(line.clone());
+                continue;
+            }
+            if let Some((open, _)) = opening_fence(line) {
+                fence = Some(open);
+            } else if !after_paragraph {
+                if let Some((label, target)) = link_definition(line) {
+                    self.definitions.entry(label).or_insert(target);
+                    continue;
+                }
+            }
+            after_paragraph = !line.trim().is_empty() && fence.is_none();
+            kept.push(line.clone());
+        }
+        kept
+    }
+
+    fn blocks(&mut self, lines: &[String], context: &Context) {
+        let mut paragraph: Vec<&str> = Vec::new();
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i].as_str();
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() {
+                self.paragraph(&paragraph, context, None);
+                paragraph.clear();
+                i += 1;
+                continue;
+            }
+            if indentation(line) >= 4 {
+                if paragraph.is_empty() {
+                    i = self.indented_code(lines, i, context);
+                } else {
+                    paragraph.push(trimmed);
+                    i += 1;
+                }
+                continue;
+            }
+            if let Some(level) = setext_level(trimmed).filter(|_| !paragraph.is_empty()) {
+                self.paragraph(&paragraph, context, Some(level));
+                paragraph.clear();
+                i += 1;
+                continue;
+            }
+            let Some(start) = block_start(lines, i, !paragraph.is_empty()) else {
+                paragraph.push(trimmed);
+                i += 1;
+                continue;
+            };
+
+            self.paragraph(&paragraph, context, None);
+            paragraph.clear();
+            i = match start {
+                BlockStart::Fence(fence) => self.fenced_code(lines, i, &fence, context),
+                BlockStart::Heading(level) => {
+                    self.paragraph(&[atx_heading_text(trimmed)], context, Some(level));
+                    i + 1
+                }
+                BlockStart::Break => {
+                    let mut rule = Paragraph::default();
+                    rule.properties.border_bottom = Some(ParagraphBorder {
+                        style: "single".to_string(),
+                        size: 6,
+                        space: 1,
+                    });
+                    self.push_paragraph(rule, context, Vec::new());
+                    i + 1
+                }
+                BlockStart::Quote => self.quote(lines, i, context),
+                BlockStart::List(marker) => self.list(lines, i, marker, context),
+                BlockStart::Table(alignments) => self.table(lines, i, &alignments),
+                BlockStart::Html => self.html(lines, i, context),
+            };
+        }
+        self.paragraph(&paragraph, context, None);
+    }
+
+    /// Add the paragraph of `lines`, a heading of `heading` level if given
+    fn paragraph(&mut self, lines: &[&str], context: &Context, heading: Option<u8>) {
+        if lines.is_empty() {
+            return;
+        }
+        let (mut paragraph, images) = parse_inline(lines.join("\n").trim_end(), &self.definitions);
+        if let Some(level) = heading {
+            paragraph.properties.style_id = Some(format!("Heading{}", level));
+            self.headings[usize::from(level) - 1] = true;
+            if level == 1 && self.title.is_none() && !paragraph.text.is_empty() {
+                self.title = Some(paragraph.text.clone());
+            }
+        }
+        self.push_paragraph(paragraph, context, images);
+    }
+
+    /// Add a paragraph, as the first of the pending list item or indented as its context
+    fn push_paragraph(&mut self, mut paragraph: Paragraph, context: &Context, images: Vec<InlineImage>) {
+        match self.item.take() {
+            Some((num_id, level)) => {
+                paragraph.properties.num_id = Some(num_id);
+                paragraph.properties.list_level = Some(level);
+            }
+            None if context.indent_left > 0 => paragraph.properties.indent_left = Some(context.indent_left),
+            None => {}
+        }
+        for image in images {
+            self.images.push(DocumentImage {
+                id: format!("image{}", self.images.len() + 1),
+                path: image.url,
+                title: image.title,
+                alt_description: Some(image.alt).filter(|alt| !alt.is_empty()),
+                is_linked: true,
+                paragraph_index: self.paragraph_count,
+                position: image.position,
+                ..Default::default()
+            });
+        }
+        self.body.push(Block::from(paragraph));
+        self.paragraph_count += 1;
+    }
+
+    fn code_block(&mut self, lines: &[String], context: &Context) {
+        let text = lines.join(&LINE_BREAK.to_string());
+        let mut paragraph = Paragraph {
+            text: text.clone(),
+            ..Default::default()
+        };
+        if !text.is_empty() {
+            paragraph.runs.push(Run {
+                text,
+                properties: RunProperties {
+                    font_name: Some(MONOSPACE_FONT.to_string()),
+                    ..Default::default()
+                },
+            });
+        }
+        self.push_paragraph(paragraph, context, Vec::new());
+    }
+
+    /// Add the code block indented by 4 spaces at line `i`; returns the line after it
+    fn indented_code(&mut self, lines: &[String], i: usize, context: &Context) -> usize {
+        let end = lines[i..]
+            .iter()
+            .position(|line| !line.trim().is_empty() && indentation(line) < 4)
+            .map_or(lines.len(), |length| i + length);
+        let mut code: Vec<String> = lines[i..end].iter().map(|line| strip_indent(line, 4)).collect();
+        while code.last().is_some_and(|line| line.trim().is_empty()) {
+            code.pop();
+        }
+        self.code_block(&code, context);
+        end
+    }
+
+    /// Add the code block opened by `fence` at line `i`; returns the line after it
+    fn fenced_code(&mut self, lines: &[String], i: usize, fence: &str, context: &Context) -> usize {
+        let indent = indentation(&lines[i]);
+        let mut code = Vec::new();
+        let mut j = i + 1;
+        while j < lines.len() {
+            let line = &lines[j];
+            j += 1;
+            if indentation(line) < 4 && is_closing_fence(line.trim_start(), fence) {
+                break;
+            }
+            code.push(strip_indent(line, indent));
+        }
+        self.code_block(&code, context);
+        j
+    }
+
+    /// Add the block quote at line `i`; returns the line after it
+    fn quote(&mut self, lines: &[String], i: usize, context: &Context) -> usize {
+        let mut quoted: Vec<String> = Vec::new();
+        let mut j = i;
+        while j < lines.len() {
+            let line = &lines[j];
+            let trimmed = line.trim_start();
+            if indentation(line) < 4 && trimmed.starts_with('>') {
+                let rest = &trimmed[1..];
+                quoted.push(rest.strip_prefix(' ').unwrap_or(rest).to_string());
+            } else if is_lazy_continuation(lines, j, quoted.last()) {
+                quoted.push(trimmed.to_string());
+            } else {
+                break;
+            }
+            j += 1;
+        }
+        let inner = Context {
+            indent_left: context.indent_left + QUOTE_INDENT,
+            list: None,
+        };
+        self.blocks(&quoted, &inner);
+        j
+    }
+
+    /// Add the list starting with `marker` at line `i`; returns the line after it
+    fn list(&mut self, lines: &[String], i: usize, marker: ListMarker, context: &Context) -> usize {
+        let kind = if marker.ordered { ListKind::Numbered } else { ListKind::Bullet };
+        // A nested list of the same kind is a deeper level of the enclosing one
+        let (num_id, level) = match &context.list {
+            Some((num_id, level)) if self.numbering.kind(num_id) == Some(kind) => {
+                (num_id.clone(), (level + 1).min(MAX_LIST_LEVEL))
+            }
+            Some((_, level)) => (self.numbering.add_list(kind), (level + 1).min(MAX_LIST_LEVEL)),
+            None => (self.numbering.add_list(kind), 0),
+        };
+        let inner = Context {
+            indent_left: context.indent_left + QUOTE_INDENT * (i32::from(level) + 1),
+            list: Some((num_id.clone(), level)),
+        };
+
+        let mut j = i;
+        while let Some(item) = lines.get(j).and_then(|line| list_marker(line)).filter(|item| item.continues(&marker)) {
+            let first = lines[j].get(item.content..).unwrap_or("");
+            let mut item_lines = vec![task_item(first)];
+            j += 1;
+            while j < lines.len() {
+                let line = &lines[j];
+                if line.trim().is_empty() {
+                    // A blank line stays in the item if indented content follows
+                    match lines[j..].iter().position(|line| !line.trim().is_empty()) {
+                        Some(length) if indentation(&lines[j + length]) >= item.content => {
+                            item_lines.extend(std::iter::repeat_n(String::new(), length));
+                            j += length;
+                            continue;
+                        }
+                        _ => break,
+                    }
+                }
+                if indentation(line) >= item.content {
+                    item_lines.push(strip_indent(line, item.content));
+                } else if list_marker(line).is_some() {
+                    // The next item of this list or of one it is in
+                    break;
+                } else if is_lazy_continuation(lines, j, item_lines.last()) {
+                    item_lines.push(line.trim_start().to_string());
+                } else {
+                    break;
+                }
+                j += 1;
+            }
+
+            self.item = Some((num_id.clone(), level));
+            self.blocks(&item_lines, &inner);
+            if self.item.is_some() {
+                self.push_paragraph(Paragraph::default(), &inner, Vec::new());
+            }
+
+            // Blank lines may part the items of a loose list
+            let next = lines[j..].iter().position(|line| !line.trim().is_empty()).map_or(lines.len(), |length| j + length);
+            if lines.get(next).and_then(|line| list_marker(line)).is_some_and(|next| next.continues(&marker)) {
+                j = next;
+            } else {
+                break;
+            }
+        }
+        j
+    }
+
+    /// Add the table whose header row is line `i`; returns the line after it
+    fn table(&mut self, lines: &[String], i: usize, alignments: &[Option<&'static str>]) -> usize {
+        let mut rows = vec![split_row(&lines[i])];
+        let mut j = i + 2;
+        while j < lines.len() && !lines[j].trim().is_empty() && block_start(lines, j, true).is_none() {
+            rows.push(split_row(&lines[j]));
+            j += 1;
+        }
+
+        let rows = rows
+            .iter()
+            .enumerate()
+            .map(|(index, cells)| TableRow {
+                cells: alignments
+                    .iter()
+                    .enumerate()
+                    .map(|(column, alignment)| {
+                        let text = cells.get(column).map_or("", String::as_str);
+                        // Images have no place in a cell; their text stays
+                        let (mut paragraph, _) = parse_inline(text, &self.definitions);
+                        paragraph.properties.alignment = alignment.map(str::to_string);
+                        TableCell {
+                            paragraphs: vec![paragraph],
+                            ..Default::default()
+                        }
+                    })
+                    .collect(),
+                properties: TableRowProperties {
+                    is_header: index == 0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect();
+        self.body.push(Block::Table(Box::new(Table {
+            rows,
+            paragraph_index: self.paragraph_count,
+            ..Default::default()
+        })));
+        j
+    }
+
+    /// Add the HTML block at line `i`, which runs to a blank line; returns the line after it
+    fn html(&mut self, lines: &[String], i: usize, context: &Context) -> usize {
+        let end = lines[i..]
+            .iter()
+            .position(|line| line.trim().is_empty())
+            .map_or(lines.len(), |length| i + length);
+        for block in import_html(&lines[i..end].join("\n"), &mut self.numbering) {
+            match block {
+                Block::Paragraph(paragraph) => self.push_paragraph(*paragraph, context, Vec::new()),
+                Block::Table(mut table) => {
+                    table.paragraph_index = self.paragraph_count;
+                    self.body.push(Block::Table(table));
+                }
+            }
+        }
+        end
+    }
+
+    fn finish(mut self) -> DocumentModel {
+        if !self.body.iter().any(|block| matches!(block, Block::Paragraph(_))) {
+            self.body.push(Block::from(Paragraph::default()));
+        }
+        let styles = (1..=6)
+            .filter(|level| self.headings[level - 1])
+            .map(|level| {
+                let id = format!("Heading{}", level);
+                let style = Style {
+                    id: id.clone(),
+                    name: Some(format!("heading {}", level)),
+                    style_type: "paragraph".to_string(),
+                    paragraph_properties: ParagraphProperties {
+                        spacing_before: Some(if level == 1 { 240 } else { 40 }),
+                        ..Default::default()
+                    },
+                    run_properties: RunProperties {
+                        bold: Some(true),
+                        font_size: Some(HEADING_SIZES[level - 1]),
+                        color: Some("2F5496".to_string()),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                (id, style)
+            })
+            .collect();
+        DocumentModel {
+            metadata: ModelMetadata {
+                title: self.title,
+                ..Default::default()
+            },
+            body: self.body,
+            styles,
+            numbering: self.numbering.to_ooxml(),
+            images: self.images,
+            ..Default::default()
+        }
+    }
+}
+
+/// What line `i` starts besides a paragraph; `in_paragraph` if it would end one
+fn block_start(lines: &[String], i: usize, in_paragraph: bool) -> Option<BlockStart> {
+    let line = lines[i].as_str();
+    if indentation(line) >= 4 {
+        return None;
+    }
+    let trimmed = line.trim_start();
+    if let Some((fence, _)) = opening_fence(line) {
+        return Some(BlockStart::Fence(fence));
+    }
+    if let Some(level) = atx_heading_level(trimmed) {
+        return Some(BlockStart::Heading(level));
+    }
+    if is_thematic_break(trimmed) {
+        return Some(BlockStart::Break);
+    }
+    if trimmed.starts_with('>') {
+        return Some(BlockStart::Quote);
+    }
+    if let Some(marker) = list_marker(line) {
+        // Only items with content, and numbered from 1, can interrupt a paragraph
+        if !in_paragraph || (marker.has_content && (!marker.ordered || marker.start == 1)) {
+            return Some(BlockStart::List(marker));
+        }
+    }
+    if let Some(alignments) = table_alignments(lines, i) {
+        return Some(BlockStart::Table(alignments));
+    }
+    if is_html_block(trimmed) {
+        return Some(BlockStart::Html);
+    }
+    None
+}
+
+/// Whether line `i` continues a paragraph ending with `last` without its marker
+fn is_lazy_continuation(lines: &[String], i: usize, last: Option<&String>) -> bool {
+    !lines[i].trim().is_empty()
+        && last.is_some_and(|last| !last.trim().is_empty() && opening_fence(last).is_none())
+        && block_start(lines, i, true).is_none()
+}
+
+/// Leading spaces of a line
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// A line without up to `count` leading spaces
+fn strip_indent(line: &str, count: usize) -> String {
+    line[indentation(line).min(count)..].to_string()
+}
+
+/// The fence and its indentation if the line opens a fenced code block
+fn opening_fence(line: &str) -> Option<(String, usize)> {
+    let indent = indentation(line);
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let fence_char = rest.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let length = rest.len() - rest.trim_start_matches(fence_char).len();
+    // A backtick fence's info string cannot have backticks, or it would be a code span
+    if length < 3 || (fence_char == '`' && rest[length..].contains('`')) {
+        return None;
+    }
+    Some((rest[..length].to_string(), indent))
+}
+
+fn is_closing_fence(trimmed: &str, fence: &str) -> bool {
+    let fence_char = fence.chars().next().unwrap_or('`');
+    let rest = trimmed.trim_start_matches(fence_char);
+    trimmed.len() - rest.len() >= fence.len() && rest.trim().is_empty()
+}
+
+/// Level of an ATX heading line, as "## Title"
+fn atx_heading_level(trimmed: &str) -> Option<u8> {
+    let level = trimmed.len() - trimmed.trim_start_matches('#').len();
+    let rest = &trimmed[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' '))).then_some(level as u8)
+}
+
+/// Text of an ATX heading line, without the closing #s
+fn atx_heading_text(trimmed: &str) -> &str {
+    let content = trimmed.trim_start_matches('#').trim();
+    let without_closing = content.trim_end_matches('#');
+    if without_closing.is_empty() {
+        ""
+    } else if without_closing.ends_with(' ') {
+        without_closing.trim_end()
+    } else {
+        content
+    }
+}
+
+/// Level of the heading a setext underline (=== or ---) makes of the paragraph above
+fn setext_level(trimmed: &str) -> Option<u8> {
+    let underline = trimmed.trim_end();
+    if !underline.is_empty() && underline.chars().all(|c| c == '=') {
+        Some(1)
+    } else if !underline.is_empty() && underline.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Whether the line is three or more of the same *, - or _, spaces between
+fn is_thematic_break(trimmed: &str) -> bool {
+    let mut marks = trimmed.chars().filter(|c| !c.is_whitespace());
+    let Some(mark) = marks.next().filter(|c| matches!(c, '*' | '-' | '_')) else {
+        return false;
+    };
+    let mut count = 1;
+    for c in marks {
+        if c != mark {
+            return false;
+        }
+        count += 1;
+    }
+    count >= 3
+}
+
+fn list_marker(line: &str) -> Option<ListMarker> {
+    let indent = indentation(line);
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let (marker_length, ordered, delimiter, start) = match rest.chars().next()? {
+        c @ ('-' | '*' | '+') => (1, false, c, 1),
+        _ => {
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let delimiter = rest[digits..].chars().next().filter(|c| matches!(c, '.' | ')'))?;
+            if digits == 0 || digits > 9 {
+                return None;
+            }
+            (digits + 1, true, delimiter, rest[..digits].parse().ok()?)
+        }
+    };
+    let after = &rest[marker_length..];
+    if !after.is_empty() && !after.starts_with(' ') {
+        return None;
+    }
+    let has_content = !after.trim().is_empty();
+    // Content indented 5 or more is code inside the item, one space from the marker
+    let spaces = match after.len() - after.trim_start().len() {
+        spaces if has_content && spaces <= 4 => spaces,
+        _ => 1,
+    };
+    Some(ListMarker {
+        ordered,
+        delimiter,
+        start,
+        content: indent + marker_length + spaces,
+        has_content,
+    })
+}
+
+/// A task list item's "[ ] " or "[x] " as a ballot box
+fn task_item(first_line: &str) -> String {
+    let boxed = [("[ ] ", TASK_BOXES[0]), ("[x] ", TASK_BOXES[1]), ("[X] ", TASK_BOXES[1])];
+    for (marker, ballot_box) in boxed {
+        if let Some(rest) = first_line.strip_prefix(marker) {
+            return format!("{}{}", ballot_box, rest);
+        }
+    }
+    first_line.to_string()
+}
+
+/// Alignments of the columns if line `i` is a table's header row: a row of
+/// cells with a delimiter row such as "| :-- | --: |" under it
+fn table_alignments(lines: &[String], i: usize) -> Option<Vec<Option<&'static str>>> {
+    let header = &lines[i];
+    let delimiter = lines.get(i + 1)?;
+    if !header.contains('|') || indentation(delimiter) > 3 {
+        return None;
+    }
+    let alignments = split_row(delimiter)
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Some("center"),
+                (false, true) => Some("right"),
+                (true, false) => Some("left"),
+                (false, false) => None,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    // A lone "---" under a line is a setext underline
+    let columns_match = split_row(header).len() == alignments.len();
+    (columns_match && (delimiter.contains('|') || alignments.len() > 1)).then_some(alignments)
+}
+
+/// Cells of a table row, split at the pipes that are not escaped
+fn split_row(line: &str) -> Vec<String> {
+    let row = line.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = match row.strip_suffix('|') {
+        Some(stripped) if !stripped.ends_with('\\') => stripped,
+        _ => row,
+    };
+    let mut cells = vec![String::new()];
+    let mut escaped = false;
+    for c in row.chars() {
+        if c == '|' && !escaped {
+            cells.push(String::new());
+        } else if let Some(cell) = cells.last_mut() {
+            cell.push(c);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    cells.iter().map(|cell| cell.trim().to_string()).collect()
+}
+
+fn is_html_block(trimmed: &str) -> bool {
+    let Some(tag) = trimmed.strip_prefix('<') else {
+        return false;
+    };
+    if tag.starts_with("!--") {
+        return true;
+    }
+    let tag = tag.strip_prefix('/').unwrap_or(tag);
+    let name_length = tag.len() - tag.trim_start_matches(|c: char| c.is_ascii_alphanumeric()).len();
+    let after = &tag[name_length..];
+    let name = tag[..name_length].to_ascii_lowercase();
+    HTML_BLOCKS.contains(&name.as_str()) && (after.is_empty() || after.starts_with([' ', '>', '/']))
+}
+
+/// A link reference definition line, as `[label]: /url "title"`
+fn link_definition(line: &str) -> Option<(String, LinkTarget)> {
+    if indentation(line) > 3 {
+        return None;
+    }
+    let rest = line.trim_start().strip_prefix('[')?;
+    let close = rest.find("]:")?;
+    let label = &rest[..close];
+    if label.trim().is_empty() || label.contains(['[', ']']) {
+        return None;
+    }
+    let rest = rest[close + 2..].trim();
+    let (url, rest) = match rest.strip_prefix('<') {
+        Some(bracketed) => {
+            let end = bracketed.find('>')?;
+            (&bracketed[..end], &bracketed[end + 1..])
+        }
+        None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+    };
+    if url.is_empty() && !rest.starts_with('>') && !line.contains("<>") {
+        return None;
+    }
+    let title = rest.trim();
+    let title = match title.chars().next() {
+        None => None,
+        Some(open @ ('"' | '\'' | '(')) => {
+            let close = if open == '(' { ')' } else { open };
+            Some(title.strip_suffix(close).filter(|_| title.len() > 1)?[1..].to_string())
+        }
+        Some(_) => return None,
+    };
+    Some((normalize_label(label), (unescape(url), title.map(|title| unescape(&title)))))
+}
+
+/// Labels match ignoring case and runs of whitespace
+fn normalize_label(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Text with backslash escapes and character references resolved
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next) if c == '\\' && next.is_ascii_punctuation() => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    decode_entities(&unescaped)
+}
+
+// ==================== Inlines ====================
+
+/// Formatting of inline text
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Marks {
+    strong: u8,
+    emphasis: u8,
+    code: bool,
+    /// Index of the link the text is in
+    link: Option<usize>,
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    Text(String),
+    /// A run of *, _ or ~ that may open or close emphasis
+    Delimiter {
+        mark: char,
+        count: usize,
+        can_open: bool,
+        can_close: bool,
+    },
+    /// A "[" or "![" not matched yet; `start` is the char index after it
+    Bracket { image: bool, active: bool, start: usize },
+    Image {
+        alt: String,
+        url: String,
+        title: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+struct Node {
+    kind: NodeKind,
+    marks: Marks,
+}
+
+/// An image in a paragraph, at a char offset into its text
+#[derive(Debug)]
+struct InlineImage {
+    position: usize,
+    alt: String,
+    url: String,
+    title: Option<String>,
+}
+
+struct InlineParser<'a> {
+    chars: Vec<char>,
+    definitions: &'a HashMap<String, LinkTarget>,
+    nodes: Vec<Node>,
+    links: Vec<LinkTarget>,
+    /// Plain text not yet in a node
+    text: String,
+}
+
+/// Parse the inlines of a paragraph's text, its lines joined with "\n"
+fn parse_inline(source: &str, definitions: &HashMap<String, LinkTarget>) -> (Paragraph, Vec<InlineImage>) {
+    let mut parser = InlineParser {
+        chars: source.chars().collect(),
+        definitions,
+        nodes: Vec::new(),
+        links: Vec::new(),
+        text: String::new(),
+    };
+    parser.parse();
+    parser.finish()
+}
+
+impl InlineParser<'_> {
+    fn parse(&mut self) {
+        let mut i = 0;
+        while i < self.chars.len() {
+            let c = self.chars[i];
+            i = match c {
+                '\\' => self.backslash(i),
+                '`' => self.code_span(i),
+                '*' | '_' | '~' => self.delimiter(i),
+                '!' if self.chars.get(i + 1) == Some(&'[') => self.bracket(i, true),
+                '[' => self.bracket(i, false),
+                ']' => self.close_bracket(i),
+                '<' => self.angle(i),
+                '&' => self.entity(i),
+                '\n' => self.line_end(i),
+                'h' | 'w' => self.bare_url(i),
+                _ => {
+                    self.text.push(c);
+                    i + 1
+                }
+            };
+        }
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        if !self.text.is_empty() {
+            let text = std::mem::take(&mut self.text);
+            self.push(NodeKind::Text(text), Marks::default());
+        }
+    }
+
+    fn push(&mut self, kind: NodeKind, marks: Marks) {
+        self.nodes.push(Node { kind, marks });
+    }
+
+    /// Push linked text for a new link to `target`
+    fn push_link(&mut self, text: String, target: LinkTarget) {
+        self.flush();
+        let link = Some(self.links.len());
+        self.links.push(target);
+        self.push(NodeKind::Text(text), Marks { link, ..Default::default() });
+    }
+
+    fn backslash(&mut self, i: usize) -> usize {
+        match self.chars.get(i + 1) {
+            Some('\n') => {
+                self.text.push(LINE_BREAK);
+                i + 2
+            }
+            Some(&next) if next.is_ascii_punctuation() => {
+                self.text.push(next);
+                i + 2
+            }
+            _ => {
+                self.text.push('\\');
+                i + 1
+            }
+        }
+    }
+
+    fn run_length(&self, i: usize) -> usize {
+        self.chars[i..].iter().take_while(|&&c| c == self.chars[i]).count()
+    }
+
+    /// A code span opened by the backticks at `i`, or the backticks as text
+    fn code_span(&mut self, i: usize) -> usize {
+        let length = self.run_length(i);
+        let mut j = i + length;
+        while j < self.chars.len() {
+            if self.chars[j] != '`' {
+                j += 1;
+                continue;
+            }
+            let closing = self.run_length(j);
+            if closing == length {
+                let mut code: String = self.chars[i + length..j]
+                    .iter()
+                    .map(|&c| if c == '\n' { ' ' } else { c })
+                    .collect();
+                if code.len() > 2 && code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty() {
+                    code = code[1..code.len() - 1].to_string();
+                }
+                self.flush();
+                self.push(NodeKind::Text(code), Marks { code: true, ..Default::default() });
+                return j + closing;
+            }
+            j += closing;
+        }
+        self.text.extend(std::iter::repeat_n('`', length));
+        i + length
+    }
+
+    /// A run of delimiters, which may open or close emphasis as the chars
+    /// either side of it allow
+    fn delimiter(&mut self, i: usize) -> usize {
+        let mark = self.chars[i];
+        let count = self.run_length(i);
+        if mark == '~' && count > 2 {
+            self.text.extend(std::iter::repeat_n('~', count));
+            return i + count;
+        }
+        let before = if i == 0 { ' ' } else { self.chars[i - 1] };
+        let after = self.chars.get(i + count).copied().unwrap_or(' ');
+        let is_space = |c: char| c.is_whitespace() || c == LINE_BREAK;
+        let is_punctuation = |c: char| c.is_ascii_punctuation() || (!c.is_alphanumeric() && !is_space(c));
+        let left_flanking = !is_space(after) && (!is_punctuation(after) || is_space(before) || is_punctuation(before));
+        let right_flanking = !is_space(before) && (!is_punctuation(before) || is_space(after) || is_punctuation(after));
+        let (can_open, can_close) = if mark == '_' {
+            (
+                left_flanking && (!right_flanking || is_punctuation(before)),
+                right_flanking && (!left_flanking || is_punctuation(after)),
+            )
+        } else {
+            (left_flanking, right_flanking)
+        };
+        self.flush();
+        let kind = NodeKind::Delimiter {
+            mark,
+            count,
+            can_open,
+            can_close,
+        };
+        self.push(kind, Marks::default());
+        i + count
+    }
+
+    fn bracket(&mut self, i: usize, image: bool) -> usize {
+        let start = i + if image { 2 } else { 1 };
+        self.flush();
+        self.push(
+            NodeKind::Bracket {
+                image,
+                active: true,
+                start,
+            },
+            Marks::default(),
+        );
+        start
+    }
+
+    /// Close the last "[" or "![" at the "]" at `i` into a link or image if a
+    /// target follows, as "(url "title")", "[label]" or a defined link text
+    fn close_bracket(&mut self, i: usize) -> usize {
+        self.flush();
+        let open = self.nodes.iter().rposition(|node| matches!(node.kind, NodeKind::Bracket { .. }));
+        let Some(open) = open else {
+            self.text.push(']');
+            return i + 1;
+        };
+        let NodeKind::Bracket { image, active, start } = self.nodes[open].kind else {
+            unreachable!("rposition found a bracket");
+        };
+        let label: String = self.chars[start..i].iter().collect();
+        let target = if active { self.link_target(i + 1, &label) } else { None };
+        let Some(((url, title), end)) = target else {
+            self.nodes[open].kind = NodeKind::Text(if image { "![" } else { "[" }.to_string());
+            self.text.push(']');
+            return i + 1;
+        };
+
+        process_emphasis(&mut self.nodes, open + 1);
+        if image {
+            let alt = self.nodes.drain(open + 1..).map(|node| node_text(&node.kind)).collect();
+            self.nodes[open] = Node {
+                kind: NodeKind::Image { alt, url, title },
+                marks: Marks::default(),
+            };
+        } else {
+            let link = self.links.len();
+            self.links.push((url, title));
+            for node in &mut self.nodes[open + 1..] {
+                node.marks.link.get_or_insert(link);
+            }
+            self.nodes.remove(open);
+            // Links cannot contain links
+            for node in &mut self.nodes[..open] {
+                if let NodeKind::Bracket { image: false, active, .. } = &mut node.kind {
+                    *active = false;
+                }
+            }
+        }
+        end
+    }
+
+    /// The target of a link whose text `label` ends before char `i`, and the char after it
+    fn link_target(&self, i: usize, label: &str) -> Option<(LinkTarget, usize)> {
+        match self.chars.get(i) {
+            Some('(') => {
+                if let Some(inline) = self.inline_target(i + 1) {
+                    return Some(inline);
+                }
+            }
+            Some('[') => {
+                let close = self.chars[i + 1..].iter().position(|&c| c == ']').map(|length| i + 1 + length)?;
+                let reference: String = self.chars[i + 1..close].iter().collect();
+                let reference = if reference.trim().is_empty() { label } else { &reference };
+                return self.definitions.get(&normalize_label(reference)).map(|target| (target.clone(), close + 1));
+            }
+            _ => {}
+        }
+        self.definitions.get(&normalize_label(label)).map(|target| (target.clone(), i))
+    }
+
+    /// A destination and title in parentheses opened before char `i`
+    fn inline_target(&self, mut i: usize) -> Option<(LinkTarget, usize)> {
+        let chars = &self.chars;
+        let skip_spaces = |mut i: usize| {
+            while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+                i += 1;
+            }
+            i
+        };
+        i = skip_spaces(i);
+        let mut url = String::new();
+        if chars.get(i) == Some(&'<') {
+            let close = chars[i..].iter().position(|&c| c == '>' || c == '\n').map(|length| i + length)?;
+            if chars[close] != '>' {
+                return None;
+            }
+            url = chars[i + 1..close].iter().collect();
+            i = close + 1;
+        } else {
+            let mut depth = 0;
+            while let Some(&c) = chars.get(i) {
+                match c {
+                    '\\' if chars.get(i + 1).is_some_and(char::is_ascii_punctuation) => {
+                        url.push(c);
+                        url.push(chars[i + 1]);
+                        i += 2;
+                        continue;
+                    }
+                    '(' => depth += 1,
+                    ')' if depth == 0 => break,
+                    ')' => depth -= 1,
+                    c if c.is_whitespace() => break,
+                    _ => {}
+                }
+                url.push(c);
+                i += 1;
+            }
+        }
+
+        let after_url = i;
+        i = skip_spaces(i);
+        let mut title = None;
+        if let Some(&open @ ('"' | '\'' | '(')) = chars.get(i).filter(|_| i > after_url) {
+            let close_char = if open == '(' { ')' } else { open };
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != close_char {
+                j += if chars[j] == '\\' { 2 } else { 1 };
+            }
+            if j >= chars.len() {
+                return None;
+            }
+            title = Some(unescape(&chars[i + 1..j].iter().collect::<String>()));
+            i = skip_spaces(j + 1);
+        }
+        (chars.get(i) == Some(&')')).then(|| ((unescape(&url), title), i + 1))
+    }
+
+    /// An autolink such as <https://example.com>, or an inline HTML tag, of
+    /// which line breaks are kept and the rest dropped
+    fn angle(&mut self, i: usize) -> usize {
+        let close = self.chars[i + 1..]
+            .iter()
+            .position(|&c| c == '>' || c == '<' || c == '\n')
+            .map(|length| i + 1 + length)
+            .filter(|&close| self.chars[close] == '>');
+        let Some(close) = close else {
+            self.text.push('<');
+            return i + 1;
+        };
+        let inner: String = self.chars[i + 1..close].iter().collect();
+        if is_uri(&inner) {
+            self.push_link(inner.clone(), (inner, None));
+        } else if !inner.contains(char::is_whitespace) && inner.contains('@') && !inner.starts_with('@') {
+            self.push_link(inner.clone(), (format!("mailto:{}", inner), None));
+        } else if inner.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') {
+            let name = inner.trim_start_matches('/').split([' ', '/']).next().unwrap_or("");
+            if name.eq_ignore_ascii_case("br") {
+                self.text.push(LINE_BREAK);
+            }
+        } else {
+            self.text.push('<');
+            return i + 1;
+        }
+        close + 1
+    }
+
+    fn entity(&mut self, i: usize) -> usize {
+        let end = self.chars[i..].iter().take(33).position(|&c| c == ';').map(|length| i + length + 1);
+        if let Some(end) = end {
+            let reference: String = self.chars[i..end].iter().collect();
+            let decoded = decode_entities(&reference);
+            if decoded != reference {
+                self.text.push_str(&decoded);
+                return end;
+            }
+        }
+        self.text.push('&');
+        i + 1
+    }
+
+    /// A soft line break is a space; two spaces before it make a hard one
+    fn line_end(&mut self, i: usize) -> usize {
+        let hard = self.text.ends_with("  ");
+        let trimmed = self.text.trim_end_matches(' ').len();
+        self.text.truncate(trimmed);
+        self.text.push(if hard { LINE_BREAK } else { ' ' });
+        i + 1
+    }
+
+    /// A GFM extended autolink: a URL starting "http://", "https://" or "www."
+    fn bare_url(&mut self, i: usize) -> usize {
+        let at_word_start = i == 0 || matches!(self.chars[i - 1], ' ' | '\n' | '(' | '*' | '_' | '~');
+        let rest: String = self.chars[i..]
+            .iter()
+            .take_while(|&&c| !c.is_whitespace() && c != '<')
+            .collect();
+        let scheme = ["http://", "https://", "www."].into_iter().find(|scheme| rest.starts_with(scheme));
+        let Some(scheme) = scheme.filter(|_| at_word_start) else {
+            self.text.push(self.chars[i]);
+            return i + 1;
+        };
+        let mut url = rest.trim_end_matches(['?', '!', '.', ',', ':', '*', '_', '~', '\'', '"']).to_string();
+        while url.ends_with(')') && url.matches(')').count() > url.matches('(').count() {
+            url.pop();
+        }
+        if !url[scheme.len()..].contains('.') {
+            self.text.push(self.chars[i]);
+            return i + 1;
+        }
+        let length = url.chars().count();
+        let target = if scheme == "www." { format!("http://{}", url) } else { url.clone() };
+        self.push_link(url, (target, None));
+        i + length
+    }
+
+    fn finish(mut self) -> (Paragraph, Vec<InlineImage>) {
+        process_emphasis(&mut self.nodes, 0);
+        let mut paragraph = Paragraph::default();
+        let mut images = Vec::new();
+        let mut length = 0;
+        for node in self.nodes {
+            let text = match node.kind {
+                NodeKind::Image { alt, url, title } => {
+                    images.push(InlineImage {
+                        position: length,
+                        alt,
+                        url,
+                        title,
+                    });
+                    continue;
+                }
+                kind => node_text(&kind),
+            };
+            if text.is_empty() {
+                continue;
+            }
+            let marks = &node.marks;
+            let properties = RunProperties {
+                bold: (marks.strong > 0).then_some(true),
+                italic: (marks.emphasis > 0).then_some(true),
+                font_name: marks.code.then(|| MONOSPACE_FONT.to_string()),
+                ..Default::default()
+            };
+            let count = text.chars().count();
+            if let Some(link) = marks.link {
+                let (url, title) = &self.links[link];
+                let extends = paragraph.hyperlinks.last_mut().filter(|last| {
+                    last.start + last.length == length && last.tooltip == *title && link_destination(last) == *url
+                });
+                match extends {
+                    Some(last) => last.length += count,
+                    None => paragraph.hyperlinks.push(hyperlink(url, title.clone(), length, count)),
+                }
+            }
+            match paragraph.runs.last_mut().filter(|run| run.properties == properties) {
+                Some(run) => run.text.push_str(&text),
+                None => paragraph.runs.push(Run {
+                    text: text.clone(),
+                    properties,
+                }),
+            }
+            paragraph.text.push_str(&text);
+            length += count;
+        }
+        (paragraph, images)
+    }
+}
+
+/// Text a node shows when it is not a link or image
+fn node_text(kind: &NodeKind) -> String {
+    match kind {
+        NodeKind::Text(text) => text.clone(),
+        NodeKind::Delimiter { mark, count, .. } => std::iter::repeat_n(*mark, *count).collect(),
+        NodeKind::Bracket { image, .. } => if *image { "![" } else { "[" }.to_string(),
+        NodeKind::Image { alt, .. } => alt.clone(),
+    }
+}
+
+/// Match the delimiter runs from node `from` on into emphasis, strong
+/// emphasis and strikethrough, as CommonMark's delimiter algorithm does
+fn process_emphasis(nodes: &mut [Node], from: usize) {
+    let delimiter = |node: &Node| match node.kind {
+        NodeKind::Delimiter {
+            mark,
+            count,
+            can_open,
+            can_close,
+        } => Some((mark, count, can_open, can_close)),
+        _ => None,
+    };
+    let mut closer = from;
+    while closer < nodes.len() {
+        let Some((mark, count, closer_can_open, true)) = delimiter(&nodes[closer]).filter(|d| d.1 > 0) else {
+            closer += 1;
+            continue;
+        };
+        let opener = (from..closer).rev().find(|&opener| match delimiter(&nodes[opener]) {
+            Some((opener_mark, opener_count, true, opener_can_close)) => {
+                // Runs that can both open and close only pair if their lengths
+                // do not add up to a multiple of 3, unless both are multiples
+                let rule_of_three = (opener_can_close || closer_can_open)
+                    && (opener_count + count) % 3 == 0
+                    && !(opener_count % 3 == 0 && count % 3 == 0);
+                opener_mark == mark && opener_count > 0 && !rule_of_three
+            }
+            _ => false,
+        });
+        let Some(opener) = opener else {
+            closer += 1;
+            continue;
+        };
+        let opener_count = delimiter(&nodes[opener]).map_or(0, |d| d.1);
+        let used = match mark {
+            '~' if opener_count != count => {
+                closer += 1;
+                continue;
+            }
+            '~' => count,
+            _ if opener_count >= 2 && count >= 2 => 2,
+            _ => 1,
+        };
+        for node in &mut nodes[opener + 1..closer] {
+            match mark {
+                // Runs have no strikethrough; the text stays as it is
+                '~' => {}
+                _ if used == 2 => node.marks.strong += 1,
+                _ => node.marks.emphasis += 1,
+            }
+            // Delimiters inside can no longer pair with ones outside
+            if let NodeKind::Delimiter { can_open, can_close, .. } = &mut node.kind {
+                *can_open = false;
+                *can_close = false;
+            }
+        }
+        for index in [opener, closer] {
+            if let NodeKind::Delimiter { count, .. } = &mut nodes[index].kind {
+                *count -= used;
+            }
+        }
+    }
+}
+
+fn is_uri(text: &str) -> bool {
+    let Some((scheme, rest)) = text.split_once(':') else {
+        return false;
+    };
+    (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+        && !rest.contains(char::is_whitespace)
+}
+
+/// A hyperlink over chars `start..start + length`; "#name" goes to a bookmark
+fn hyperlink(url: &str, title: Option<String>, start: usize, length: usize) -> Hyperlink {
+    let (url, anchor) = match url.strip_prefix('#') {
+        Some(anchor) => (None, Some(anchor.to_string())),
+        None => (Some(url.to_string()), None),
+    };
+    Hyperlink {
+        url,
+        anchor,
+        tooltip: title,
+        start,
+        length,
+        ..Default::default()
+    }
+}
+
+/// Where a hyperlink goes, as Markdown writes it
+fn link_destination(link: &Hyperlink) -> String {
+    match (&link.url, &link.anchor) {
+        (Some(url), _) => url.clone(),
+        (None, Some(anchor)) => format!("#{}", anchor),
+        (None, None) => String::new(),
+    }
+}
+
+// ==================== Export ====================
+
+/// Write a document model as Markdown of `flavor`
+pub fn export_markdown(model: &DocumentModel, flavor: MarkdownFlavor) -> String {
+    let mut images: HashMap<usize, Vec<&DocumentImage>> = HashMap::new();
+    for image in &model.images {
+        images.entry(image.paragraph_index).or_default().push(image);
+    }
+    let mut writer = MarkdownWriter {
+        flavor,
+        numbering: ListNumbering::from_ooxml(&model.numbering),
+        list_level: None,
+        counters: HashMap::new(),
+        out: String::new(),
+    };
+    let mut index = 0;
+    for block in &model.body {
+        match block {
+            Block::Paragraph(paragraph) => {
+                writer.paragraph(paragraph, images.get(&index).map_or(&[][..], Vec::as_slice));
+                index += 1;
+            }
+            Block::Table(table) => writer.table(table),
+        }
+    }
+    let mut markdown = writer.out.trim_end().to_string();
+    if !markdown.is_empty() {
+        markdown.push('\n');
+    }
+    markdown
+}
+
+struct MarkdownWriter {
+    flavor: MarkdownFlavor,
+    numbering: ListNumbering,
+    /// Level of the last list item, while writing a list
+    list_level: Option<u8>,
+    /// Items so far at each level of the lists being written
+    counters: HashMap<String, Vec<u32>>,
+    out: String,
+}
+
+impl MarkdownWriter {
+    /// Add a block, after a blank line unless it is `tight` against the one before
+    fn block(&mut self, text: &str, tight: bool) {
+        if !self.out.is_empty() && !tight {
+            self.out.push('\n');
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn paragraph(&mut self, paragraph: &Paragraph, images: &[&DocumentImage]) {
+        let properties = &paragraph.properties;
+        let list = properties
+            .num_id
+            .as_deref()
+            .filter(|num_id| *num_id != "0")
+            .and_then(|num_id| Some((num_id, self.numbering.kind(num_id)?)));
+        if let Some((num_id, kind)) = list {
+            let level = properties.list_level.unwrap_or(0).min(MAX_LIST_LEVEL);
+            let counters = self.counters.entry(num_id.to_string()).or_default();
+            counters.resize(usize::from(level) + 1, 0);
+            counters[usize::from(level)] += 1;
+            let marker = match kind {
+                ListKind::Bullet => "-".to_string(),
+                ListKind::Numbered => format!("{}.", counters[usize::from(level)]),
+            };
+            // Four spaces a level nest items under any marker up to "99."
+            let indent = "    ".repeat(usize::from(level));
+            let text = self.inline(paragraph, images, false);
+            let continuation = format!("\n{}{}", indent, " ".repeat(marker.len() + 1));
+            let item = format!("{}{} {}", indent, marker, text.replace('\n', &continuation));
+            self.block(item.trim_end(), self.list_level.is_some());
+            self.list_level = Some(level);
+            return;
+        }
+
+        // A paragraph indented as the list item before it continues that item
+        if let Some(level) = self.list_level {
+            let item_indent = QUOTE_INDENT * (i32::from(level) + 1);
+            if properties.indent_left == Some(item_indent) && !paragraph.text.is_empty() {
+                let indent = "    ".repeat(usize::from(level) + 1);
+                let text = self.inline(paragraph, images, false);
+                self.block(&prefix_lines(&text, &indent), false);
+                return;
+            }
+        }
+        self.list_level = None;
+        self.counters.clear();
+
+        let heading = properties
+            .style_id
+            .as_deref()
+            .and_then(|id| id.strip_prefix("Heading"))
+            .and_then(|level| level.parse::<usize>().ok())
+            .filter(|level| (1..=6).contains(level));
+        let text = if let Some(level) = heading {
+            let text = self.inline(paragraph, images, false).replace("\\\n", " ");
+            format!("{} {}", "#".repeat(level), text).trim_end().to_string()
+        } else if is_code_block(paragraph) {
+            code_block(&paragraph.text)
+        } else if paragraph.text.is_empty() && images.is_empty() {
+            if properties.border_bottom.is_none() {
+                return;
+            }
+            "---".to_string()
+        } else {
+            self.inline(paragraph, images, false)
+        };
+        let depth = usize::try_from(properties.indent_left.unwrap_or(0) / QUOTE_INDENT).unwrap_or(0);
+        self.block(&prefix_lines(&text, &"> ".repeat(depth)), false);
+    }
+
+    fn table(&mut self, table: &Table) {
+        self.list_level = None;
+        self.counters.clear();
+        let columns = table
+            .rows
+            .iter()
+            .map(|row| row.cells.iter().map(grid_span).sum::<usize>())
+            .max()
+            .unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+        let text = match self.flavor {
+            MarkdownFlavor::Gfm => self.pipe_table(table, columns),
+            MarkdownFlavor::CommonMark => html_table(table),
+        };
+        self.block(&text, false);
+    }
+
+    /// A GFM table; its first row is the header, spanned columns are empty cells
+    fn pipe_table(&self, table: &Table, columns: usize) -> String {
+        let mut lines = Vec::new();
+        for (index, row) in table.rows.iter().enumerate() {
+            let mut cells = Vec::with_capacity(columns);
+            for cell in &row.cells {
+                let text = if is_continuation(cell.vertical_merge) {
+                    String::new()
+                } else {
+                    cell.paragraphs
+                        .iter()
+                        .map(|paragraph| self.inline(paragraph, &[], true).replace("\\\n", "<br>"))
+                        .filter(|text| !text.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("<br>")
+                };
+                cells.push(text);
+                cells.extend(std::iter::repeat_n(String::new(), grid_span(cell) - 1));
+            }
+            cells.resize(columns, String::new());
+            lines.push(format!("| {} |", cells.join(" | ")));
+
+            if index == 0 {
+                let mut delimiters: Vec<&str> = Vec::with_capacity(columns);
+                for cell in &row.cells {
+                    let alignment = cell.paragraphs.first().and_then(|p| p.properties.alignment.as_deref());
+                    delimiters.push(match alignment {
+                        Some("center") => ":---:",
+                        Some("right" | "end") => "---:",
+                        Some("left" | "start") => ":---",
+                        _ => "---",
+                    });
+                    delimiters.extend(std::iter::repeat_n("---", grid_span(cell) - 1));
+                }
+                delimiters.resize(columns, "---");
+                lines.push(format!("| {} |", delimiters.join(" | ")));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// A paragraph's text with its formatting, links and images in Markdown
+    fn inline(&self, paragraph: &Paragraph, images: &[&DocumentImage], in_table: bool) -> String {
+        let chars: Vec<char> = paragraph.text.chars().collect();
+        let default = RunProperties::default();
+        let mut run_properties: Vec<&RunProperties> = Vec::with_capacity(chars.len());
+        for run in &paragraph.runs {
+            run_properties.extend(std::iter::repeat_n(&run.properties, run.text.chars().count()));
+        }
+        run_properties.resize(chars.len(), &default);
+
+        // Spans of chars with the same formatting and link, and images between them
+        let mut spans: Vec<Span> = Vec::new();
+        let mut images: Vec<&&DocumentImage> = images.iter().collect();
+        images.sort_by_key(|image| image.position);
+        let mut images = images.into_iter().peekable();
+        for (position, &c) in chars.iter().enumerate() {
+            while let Some(image) = images.next_if(|image| image.position <= position) {
+                spans.push(Span::Image(image));
+            }
+            let properties = run_properties[position];
+            let style = TextStyle {
+                bold: properties.bold == Some(true),
+                italic: properties.italic == Some(true),
+                code: is_monospace(properties),
+                link: paragraph
+                    .hyperlinks
+                    .iter()
+                    .position(|link| (link.start..link.start + link.length).contains(&position)),
+            };
+            match spans.last_mut() {
+                Some(Span::Text(text, last)) if *last == style => text.push(c),
+                _ => spans.push(Span::Text(c.to_string(), style)),
+            }
+        }
+        spans.extend(images.map(|image| Span::Image(image)));
+
+        let mut out = String::new();
+        let mut i = 0;
+        while i < spans.len() {
+            let link = spans[i].link();
+            let end = spans[i..].iter().position(|span| span.link() != link).map_or(spans.len(), |length| i + length);
+            let inner: String = spans[i..end].iter().map(|span| self.span(span, in_table)).collect();
+            match link.map(|link| &paragraph.hyperlinks[link]) {
+                Some(link) => {
+                    out.push_str(&format!("[{}]({}{})", inner, destination(&link_destination(link)), title(link.tooltip.as_deref())));
+                }
+                None => out.push_str(&inner),
+            }
+            i = end;
+        }
+        escape_line_start(&out)
+    }
+
+    fn span(&self, span: &Span, in_table: bool) -> String {
+        let (text, style) = match span {
+            Span::Image(image) => {
+                let alt = image.alt_description.as_deref().unwrap_or_default();
+                return format!(
+                    "![{}]({}{})",
+                    self.escape(alt, in_table),
+                    destination(&image.path),
+                    title(image.title.as_deref())
+                );
+            }
+            Span::Text(text, style) => (text, style),
+        };
+        if style.code {
+            return code_span(text);
+        }
+        let escaped = self.escape(text, in_table);
+        let core = escaped.trim_matches(|c: char| c.is_whitespace());
+        let marker = match (style.bold, style.italic) {
+            _ if core.is_empty() => return escaped,
+            (true, true) => "***",
+            (true, false) => "**",
+            (false, true) => "*",
+            (false, false) => return escaped,
+        };
+        // Emphasis cannot start or end with whitespace
+        let leading = &escaped[..escaped.len() - escaped.trim_start().len()];
+        let trailing = &escaped[escaped.trim_end().len()..];
+        format!("{}{}{}{}{}", leading, marker, core, marker, trailing)
+    }
+
+    /// Text with what Markdown would read as syntax escaped, and line breaks as "\" line ends
+    fn escape(&self, text: &str, in_table: bool) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            let special = match c {
+                '\\' | '`' | '*' | '_' | '[' | ']' | '<' => true,
+                '|' => in_table,
+                '~' => self.flavor == MarkdownFlavor::Gfm,
+                '&' => chars.peek().is_some_and(|next| next.is_alphanumeric() || *next == '#'),
+                _ => false,
+            };
+            if special {
+                escaped.push('\\');
+            }
+            match c {
+                LINE_BREAK | '\n' => escaped.push_str("\\\n"),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    }
+}
+
+/// Chars of a paragraph written one way
+#[derive(Debug)]
+enum Span<'a> {
+    Text(String, TextStyle),
+    Image(&'a DocumentImage),
+}
+
+impl Span<'_> {
+    fn link(&self) -> Option<usize> {
+        match self {
+            Span::Text(_, style) => style.link,
+            Span::Image(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TextStyle {
+    bold: bool,
+    italic: bool,
+    code: bool,
+    /// Index of the paragraph hyperlink over the text
+    link: Option<usize>,
+}
+
+fn is_monospace(properties: &RunProperties) -> bool {
+    properties
+        .font_name
+        .as_deref()
+        .is_some_and(|font| MONOSPACE_FONTS.iter().any(|mono| mono.eq_ignore_ascii_case(font)))
+}
+
+/// Whether a paragraph is all monospace text, as a code block reads back
+fn is_code_block(paragraph: &Paragraph) -> bool {
+    !paragraph.text.is_empty()
+        && !paragraph.runs.is_empty()
+        && paragraph.hyperlinks.is_empty()
+        && paragraph.runs.iter().all(|run| run.text.is_empty() || is_monospace(&run.properties))
+}
+
+/// Code in a fence longer than any run of backticks in it
+fn code_block(text: &str) -> String {
+    let fence = "`".repeat(longest_backtick_run(text).max(2) + 1);
+    let code = text.replace(LINE_BREAK, "\n");
+    format!("{}\n{}\n{}", fence, code, fence)
+}
+
+fn code_span(text: &str) -> String {
+    let ticks = "`".repeat(longest_backtick_run(text) + 1);
+    let text = text.replace(LINE_BREAK, " ");
+    // A space keeps backticks at either end apart from the fence, and is stripped on reading
+    if text.starts_with('`') || text.ends_with('`') || (text.starts_with(' ') && text.ends_with(' ')) {
+        format!("{} {} {}", ticks, text, ticks)
+    } else {
+        format!("{}{}{}", ticks, text, ticks)
+    }
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/// A link destination, in angle brackets if it has spaces or parentheses
+fn destination(url: &str) -> String {
+    if url.is_empty() || url.contains([' ', '(', ')']) {
+        format!("<{}>", url.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        url.to_string()
+    }
+}
+
+fn title(title: Option<&str>) -> String {
+    title.map_or_else(String::new, |title| format!(" \"{}\"", title.replace('"', "\\\"")))
+}
+
+/// Escape what would start a heading, quote, list or setext underline at the start of a paragraph
+fn escape_line_start(text: &str) -> String {
+    if text.starts_with(['#', '>', '+', '-', '=']) {
+        return format!("\\{}", text);
+    }
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && text[digits..].starts_with(['.', ')']) {
+        return format!("{}\\{}", &text[..digits], &text[digits..]);
+    }
+    text.to_string()
+}
+
+/// Each line of `text` after `prefix`, without trailing spaces on empty lines
+fn prefix_lines(text: &str, prefix: &str) -> String {
+    text.split('\n')
+        .map(|line| format!("{}{}", prefix, line).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn grid_span(cell: &TableCell) -> usize {
+    cell.properties.grid_span.map_or(1, |span| span.max(1) as usize)
+}
+
+fn is_continuation(merge: Option<i32>) -> bool {
+    merge.is_some_and(|value| value != 1)
+}
+
+/// A table as HTML, which CommonMark passes through; cells hold plain text
+fn html_table(table: &Table) -> String {
+    let mut html = String::from("<table>\n");
+    for row in &table.rows {
+        let tag = if row.properties.is_header { "th" } else { "td" };
+        html.push_str("<tr>");
+        for cell in row.cells.iter().filter(|cell| !is_continuation(cell.horizontal_merge)) {
+            let text = if is_continuation(cell.vertical_merge) {
+                String::new()
+            } else {
+                cell.paragraphs
+                    .iter()
+                    .map(|paragraph| escape_html(&paragraph.text).replace(LINE_BREAK, "<br>"))
+                    .collect::<Vec<_>>()
+                    .join("<br>")
+            };
+            let span = grid_span(cell);
+            if span > 1 {
+                html.push_str(&format!("<{} colspan=\"{}\">{}</{}>", tag, span, text, tag));
+            } else {
+                html.push_str(&format!("<{}>{}</{}>", tag, text, tag));
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paragraphs(model: &DocumentModel) -> Vec<&Paragraph> {
+        model.paragraphs().collect()
+    }
+
+    #[test]
+    fn test_import_blocks() {
+        let model = import_markdown(
+            "Title\n=====\n\n## Part *one* ##\n\nSome **bold** and _italic_\ntext with `code`.  \nNext line\n\n> Quoted\n> text\n\n---\n\n```rust\nfn main() {}\n\n```\n\n    indented\n",
+        );
+        let paragraphs = paragraphs(&model);
+        let texts: Vec<&str> = paragraphs.iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "Title",
+                "Part one",
+                "Some bold and italic text with code.\u{2028}Next line",
+                "Quoted text",
+                "",
+                "fn main() {}\u{2028}",
+                "indented"
+            ]
+        );
+        assert_eq!(paragraphs[0].properties.style_id.as_deref(), Some("Heading1"));
+        assert_eq!(paragraphs[1].properties.style_id.as_deref(), Some("Heading2"));
+        assert_eq!(model.metadata.title.as_deref(), Some("Title"));
+        assert!(model.styles.contains_key("Heading2"));
+
+        let runs: Vec<(&str, Option<bool>, Option<bool>, bool)> = paragraphs[2]
+            .runs
+            .iter()
+            .map(|run| (run.text.as_str(), run.properties.bold, run.properties.italic, is_monospace(&run.properties)))
+            .collect();
+        assert_eq!(runs[1], ("bold", Some(true), None, false));
+        assert_eq!(runs[3], ("italic", None, Some(true), false));
+        assert_eq!(runs[5], ("code", None, None, true));
+        assert_eq!(paragraphs[3].properties.indent_left, Some(720));
+        assert!(paragraphs[4].properties.border_bottom.is_some());
+        assert!(is_code_block(paragraphs[5]) && is_code_block(paragraphs[6]));
+    }
+
+    #[test]
+    fn test_import_lists_links_and_images() {
+        let model = import_markdown(
+            "- one\n- two\n  1. first\n  2. second\n\n    more of two\n- [x] done\n\nSee [the site][site], <https://a.example> and www.b.example.\n\n![Logo](img/logo.png \"Our logo\") here\n\n[site]: https://example.com \"Example\"\n",
+        );
+        let paragraphs = paragraphs(&model);
+        let items: Vec<(&str, Option<&str>, Option<u8>)> = paragraphs
+            .iter()
+            .map(|p| (p.text.as_str(), p.properties.num_id.as_deref(), p.properties.list_level))
+            .collect();
+        let (bullets, numbers) = (items[0].1, items[2].1);
+        assert!(bullets.is_some() && numbers.is_some() && bullets != numbers);
+        assert_eq!(
+            items[..6],
+            [
+                ("one", bullets, Some(0)),
+                ("two", bullets, Some(0)),
+                ("first", numbers, Some(1)),
+                ("second", numbers, Some(1)),
+                ("more of two", None, None),
+                ("☒ done", bullets, Some(0)),
+            ]
+        );
+        // Indented less than "second", the paragraph continues "two"
+        assert_eq!(paragraphs[4].properties.indent_left, Some(720));
+
+        let links = &paragraphs[6].hyperlinks;
+        let targets: Vec<(Option<&str>, usize, usize)> =
+            links.iter().map(|link| (link.url.as_deref(), link.start, link.length)).collect();
+        assert_eq!(paragraphs[6].text, "See the site, https://a.example and www.b.example.");
+        assert_eq!(
+            targets,
+            [
+                (Some("https://example.com"), 4, 8),
+                (Some("https://a.example"), 14, 17),
+                (Some("http://www.b.example"), 36, 13)
+            ]
+        );
+        assert_eq!(links[0].tooltip.as_deref(), Some("Example"));
+
+        let image = &model.images[0];
+        assert_eq!((image.path.as_str(), image.paragraph_index, image.position), ("img/logo.png", 7, 0));
+        assert_eq!(image.alt_description.as_deref(), Some("Logo"));
+        assert_eq!(paragraphs[7].text, " here");
+    }
+
+    #[test]
+    fn test_import_tables_and_emphasis_edge_cases() {
+        let model = import_markdown(
+            "| Name | Qty |\n| :--- | ---: |\n| a \\| b | **2** |\n\n***both*** snake_case_name ~~gone~~ *a **b** c* 2 * 3 \\*x\\*\n\n<div><b>html</b></div>\n",
+        );
+        let Block::Table(table) = &model.body[0] else {
+            panic!("expected a table");
+        };
+        assert!(table.rows[0].properties.is_header);
+        let cell = |row: usize, column: usize| &table.rows[row].cells[column].paragraphs[0];
+        assert_eq!(cell(1, 0).text, "a | b");
+        assert_eq!(cell(1, 1).properties.alignment.as_deref(), Some("right"));
+        assert_eq!(cell(1, 1).runs[0].properties.bold, Some(true));
+
+        let paragraphs = paragraphs(&model);
+        assert_eq!(paragraphs[0].text, "both snake_case_name gone a b c 2 * 3 *x*");
+        let both = &paragraphs[0].runs[0];
+        assert_eq!((both.text.as_str(), both.properties.bold, both.properties.italic), ("both", Some(true), Some(true)));
+        let b = paragraphs[0].runs.iter().find(|run| run.text == "b").unwrap();
+        assert_eq!((b.properties.bold, b.properties.italic), (Some(true), Some(true)));
+        assert_eq!(paragraphs[1].text, "html");
+        assert_eq!(paragraphs[1].runs[0].properties.bold, Some(true));
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let markdown = "# Report\n\nSome **bold**, *italic* and `code` with [a link](https://example.com \"Site\").\\\nNext line\n\n- one\n- two\n    1. first\n    2. second\n\n> 1\\. not a list\n\n---\n\n```\nlet x = 1;\n```\n\n| Name | Qty |\n| :---: | --- |\n| a \\| b | 2 |\n\n![Logo](media/logo.png)\n";
+        let model = import_markdown(markdown);
+        assert_eq!(export_markdown(&model, MarkdownFlavor::Gfm), markdown);
+
+        let commonmark = export_markdown(&model, MarkdownFlavor::CommonMark);
+        assert!(commonmark.contains("<table>\n<tr><th>Name</th><th>Qty</th></tr>\n<tr><td>a | b</td><td>2</td></tr>\n</table>"));
+        let reread = import_markdown(&commonmark);
+        assert!(matches!(reread.body.iter().filter(|block| matches!(block, Block::Table(_))).count(), 1));
+        assert_eq!(MarkdownFlavor::from_name("CommonMark"), Some(MarkdownFlavor::CommonMark));
+    }
+}
@@ -0,0 +1,925 @@
+//! # Math Module
+//!
+//! Equations typed in the linear format Word's equation editor takes, a subset
+//! of UnicodeMath, and their OMML form.
+//!
+//! The editor text of a math zone is the equation's linear form: `a^2` is a
+//! superscript, `x_i` a subscript, `a/b` a fraction, `√x` or `√(3&x)` a root,
+//! and brackets group. Parentheses round a fraction's or script's argument
+//! only group it, as in UnicodeMath, and `〖…〗` groups without showing. Math
+//! AutoCorrect turns a control word such as `\alpha` or `\sqrt` into its
+//! symbol when a space or operator is typed after it. Every edit builds the
+//! zone's tree anew from its text, and the tree is what `m:oMath` holds in
+//! OOXML; reading `m:oMath` back gives the tree's linear form as the text.
+//!
+//! A zone covers chars of the editor text and grows with typing at either end,
+//! so typing an equation out keeps it in its zone.
+
+use std::ops::Range;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::comments::move_anchor;
+use crate::document_model::{Block, DocumentModel};
+use crate::ooxml::{escape_xml_attr, escape_xml_text, unescape_xml_text, MathZone, Paragraph};
+use crate::piece_tree::PieceTree;
+
+/// Control words Math AutoCorrect replaces, and their symbols
+const CONTROL_WORDS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("phi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+    ("sqrt", "√"),
+    ("infty", "∞"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("cdot", "⋅"),
+    ("le", "≤"),
+    ("ge", "≥"),
+    ("ne", "≠"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("int", "∫"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("in", "∈"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("degree", "°"),
+];
+
+/// Opening brackets and the brackets that close them
+const BRACKETS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('|', '|'), ('⟨', '⟩'), ('〖', '〗')];
+
+/// Grouping that is not shown
+const INVISIBLE_OPEN: char = '〖';
+const INVISIBLE_CLOSE: char = '〗';
+
+static OMML_TOKEN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"<m:t\b[^>]*>([^<]*)</m:t>|<(/?)m:(\w+)\b([^>]*?)(/?)>"#).unwrap());
+static OMML_ATTRIBUTE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"([\w:]+)="([^"]*)""#).unwrap());
+
+/// A node of an equation's tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MathNode {
+    /// Letters, digits and operators, set as they are
+    Text { text: String },
+    Fraction { numerator: Vec<MathNode>, denominator: Vec<MathNode> },
+    /// A base with a subscript, a superscript or both
+    Script {
+        base: Vec<MathNode>,
+        subscript: Option<Vec<MathNode>>,
+        superscript: Option<Vec<MathNode>>,
+    },
+    /// A square root, or a root of `degree`
+    Radical { degree: Option<Vec<MathNode>>, radicand: Vec<MathNode> },
+    /// An expression in visible brackets
+    Delimited { open: char, close: char, body: Vec<MathNode> },
+}
+
+impl MathNode {
+    fn text(text: impl Into<String>) -> Self {
+        MathNode::Text { text: text.into() }
+    }
+
+    /// Written without brackets as a script's base
+    fn is_atom(&self) -> bool {
+        match self {
+            MathNode::Text { text } => {
+                text.chars().count() == 1 || (!text.is_empty() && text.chars().all(|c| c.is_ascii_digit() || c == '.'))
+            }
+            MathNode::Radical { .. } | MathNode::Delimited { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Parse an equation's linear form
+pub fn parse_linear(text: &str) -> Vec<MathNode> {
+    let mut parser = LinearParser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    parser.sequence(None)
+}
+
+/// The linear form of an equation, which parses back to the same tree
+pub fn to_linear(nodes: &[MathNode]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        write_linear(node, &mut out);
+    }
+    out
+}
+
+fn write_linear(node: &MathNode, out: &mut String) {
+    match node {
+        MathNode::Text { text } => out.push_str(text),
+        MathNode::Fraction { numerator, denominator } => {
+            out.push_str(&argument_linear(numerator, true));
+            out.push('/');
+            out.push_str(&argument_linear(denominator, true));
+        }
+        MathNode::Script { base, subscript, superscript } => {
+            match base.as_slice() {
+                [single] if single.is_atom() => write_linear(single, out),
+                _ => {
+                    out.push(INVISIBLE_OPEN);
+                    out.push_str(&to_linear(base));
+                    out.push(INVISIBLE_CLOSE);
+                }
+            }
+            if let Some(subscript) = subscript {
+                out.push('_');
+                out.push_str(&argument_linear(subscript, false));
+            }
+            if let Some(superscript) = superscript {
+                out.push('^');
+                out.push_str(&argument_linear(superscript, false));
+            }
+        }
+        MathNode::Radical { degree: Some(degree), radicand } => {
+            out.push_str(&format!("√({}&{})", to_linear(degree), to_linear(radicand)));
+        }
+        MathNode::Radical { degree: None, radicand } => {
+            out.push('√');
+            out.push_str(&argument_linear(radicand, false));
+        }
+        MathNode::Delimited { open, close, body } => {
+            out.push(*open);
+            out.push_str(&to_linear(body));
+            out.push(*close);
+        }
+    }
+}
+
+/// A fraction's, script's or root's argument: one operand as it is, more in parentheses
+///
+/// A fraction's numerator and denominator may have scripts of their own.
+fn argument_linear(nodes: &[MathNode], scripts: bool) -> String {
+    match nodes {
+        [MathNode::Text { text }] if !text.is_empty() && text.chars().all(char::is_alphanumeric) => text.clone(),
+        [MathNode::Script { .. }] if scripts => to_linear(nodes),
+        [single] if single.is_atom() && !matches!(single, MathNode::Delimited { open: '(', .. }) => to_linear(nodes),
+        _ => format!("({})", to_linear(nodes)),
+    }
+}
+
+struct LinearParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl LinearParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Nodes up to `close`, which is left for the caller, or the end
+    fn sequence(&mut self, close: Option<char>) -> Vec<MathNode> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                None => break,
+                Some(c) if Some(c) == close => break,
+                Some(_) => {
+                    let node = self.fraction(&mut nodes);
+                    nodes.push(node);
+                }
+            }
+        }
+        merge_text(nodes)
+    }
+
+    /// An operand with its scripts, and the fractions it is the numerator of
+    fn fraction(&mut self, before: &mut Vec<MathNode>) -> MathNode {
+        let mut node = self.scripted(before);
+        loop {
+            self.skip_spaces();
+            if self.peek() != Some('/') {
+                return node;
+            }
+            self.pos += 1;
+            self.skip_spaces();
+            let mut denominator = Vec::new();
+            let last = self.scripted(&mut denominator);
+            denominator.push(last);
+            node = MathNode::Fraction {
+                numerator: argument(node),
+                denominator: unwrap_argument(merge_text(denominator)),
+            };
+        }
+    }
+
+    /// An operand with any subscript and superscript; of a run of letters only
+    /// the last takes the scripts, and the ones before it go to `before`
+    fn scripted(&mut self, before: &mut Vec<MathNode>) -> MathNode {
+        let mut base = self.operand();
+        loop {
+            let Some(kind @ ('^' | '_')) = self.peek() else {
+                return base;
+            };
+            self.pos += 1;
+            if let MathNode::Text { text } = &base {
+                if text.chars().count() > 1 && !text.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                    let split = text.char_indices().last().map_or(0, |(i, _)| i);
+                    before.push(MathNode::text(&text[..split]));
+                    base = MathNode::text(&text[split..]);
+                }
+            }
+            let script = argument(self.operand());
+            base = match base {
+                // A second script of the other kind joins the first
+                MathNode::Script { base, subscript: None, superscript } if kind == '_' && superscript.is_some() => {
+                    MathNode::Script { base, subscript: Some(script), superscript }
+                }
+                MathNode::Script { base, subscript, superscript: None } if kind == '^' && subscript.is_some() => {
+                    MathNode::Script { base, subscript, superscript: Some(script) }
+                }
+                base => {
+                    let base = match base {
+                        MathNode::Delimited { open: INVISIBLE_OPEN, body, .. } => body,
+                        base => vec![base],
+                    };
+                    match kind {
+                        '^' => MathNode::Script { base, subscript: None, superscript: Some(script) },
+                        _ => MathNode::Script { base, subscript: Some(script), superscript: None },
+                    }
+                }
+            };
+        }
+    }
+
+    /// A run of letters and digits, a bracketed group, a root, or one other char
+    fn operand(&mut self) -> MathNode {
+        self.skip_spaces();
+        let Some(c) = self.peek() else {
+            return MathNode::text("");
+        };
+        self.pos += 1;
+        if c.is_alphanumeric() || (c == '.' && self.peek().is_some_and(|next| next.is_ascii_digit())) {
+            let mut run = c.to_string();
+            while let Some(next) = self.peek().filter(|next| next.is_alphanumeric() || *next == '.') {
+                run.push(next);
+                self.pos += 1;
+            }
+            return MathNode::text(run);
+        }
+        if c == '\\' {
+            let start = self.pos;
+            while self.peek().is_some_and(|next| next.is_ascii_alphabetic()) {
+                self.pos += 1;
+            }
+            let name: String = self.chars[start..self.pos].iter().collect();
+            return match control_word(&name) {
+                Some("√") => self.radical(),
+                Some(symbol) => MathNode::text(symbol),
+                None => MathNode::text(format!("\\{}", name)),
+            };
+        }
+        if c == '√' {
+            return self.radical();
+        }
+        if let Some(&(open, close)) = BRACKETS.iter().find(|(open, _)| *open == c) {
+            let body = self.sequence(Some(close));
+            if self.peek() == Some(close) {
+                self.pos += 1;
+            }
+            return MathNode::Delimited { open, close, body };
+        }
+        MathNode::text(c)
+    }
+
+    /// The root after a √: `√x`, `√(x+1)`, or `√(n&x)` for a root of degree n
+    fn radical(&mut self) -> MathNode {
+        self.skip_spaces();
+        if self.peek() == Some('(') {
+            let mut depth = 0;
+            let mut amp = None;
+            for (i, &c) in self.chars.iter().enumerate().skip(self.pos) {
+                match c {
+                    '(' => depth += 1,
+                    ')' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            if let Some(amp) = amp {
+                                let sub = |range: Range<usize>| parse_linear(&self.chars[range].iter().collect::<String>());
+                                let degree = sub(self.pos + 1..amp);
+                                let radicand = sub(amp + 1..i);
+                                self.pos = i + 1;
+                                return MathNode::Radical {
+                                    degree: Some(degree),
+                                    radicand,
+                                };
+                            }
+                            break;
+                        }
+                    }
+                    '&' if depth == 1 => amp = Some(i),
+                    _ => {}
+                }
+            }
+        }
+        MathNode::Radical {
+            degree: None,
+            radicand: argument(self.operand()),
+        }
+    }
+}
+
+/// A fraction's, script's or root's argument: parentheses round it only group
+fn argument(node: MathNode) -> Vec<MathNode> {
+    match node {
+        MathNode::Delimited { open: '(' | INVISIBLE_OPEN, body, .. } => body,
+        node => vec![node],
+    }
+}
+
+fn unwrap_argument(nodes: Vec<MathNode>) -> Vec<MathNode> {
+    match <[MathNode; 1]>::try_from(nodes) {
+        Ok([node]) => argument(node),
+        Err(nodes) => nodes,
+    }
+}
+
+/// Joins neighbouring text, and lets invisible groups in a sequence go
+fn merge_text(nodes: Vec<MathNode>) -> Vec<MathNode> {
+    let mut merged: Vec<MathNode> = Vec::with_capacity(nodes.len());
+    let flattened = nodes.into_iter().flat_map(|node| match node {
+        MathNode::Delimited { open: INVISIBLE_OPEN, body, .. } => body,
+        node => vec![node],
+    });
+    for node in flattened {
+        match (merged.last_mut(), node) {
+            (_, MathNode::Text { text }) if text.is_empty() => {}
+            (Some(MathNode::Text { text: last }), MathNode::Text { text }) => last.push_str(&text),
+            (_, node) => merged.push(node),
+        }
+    }
+    merged
+}
+
+fn control_word(name: &str) -> Option<&'static str> {
+    CONTROL_WORDS.iter().find(|(word, _)| *word == name).map(|(_, symbol)| *symbol)
+}
+
+/// Math AutoCorrect for the text of a zone up to the caret
+///
+/// When the char just typed ends a control word, returns how many chars
+/// before the caret to replace and their replacement: a space is taken with
+/// the word, an operator or bracket is kept after the symbol.
+pub fn autocorrect(before_caret: &str) -> Option<(usize, String)> {
+    let mut chars = before_caret.chars().rev();
+    let typed = chars.next()?;
+    if typed.is_alphanumeric() || typed == '\\' {
+        return None;
+    }
+    let name: String = chars.clone().take_while(|c| c.is_ascii_alphabetic()).collect::<Vec<_>>().into_iter().rev().collect();
+    if name.is_empty() || chars.nth(name.len()) != Some('\\') {
+        return None;
+    }
+    let symbol = control_word(&name)?;
+    let replacement = match typed {
+        ' ' => symbol.to_string(),
+        typed => format!("{}{}", symbol, typed),
+    };
+    Some((name.len() + 2, replacement))
+}
+
+/// A control word Math AutoCorrect replaced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MathCorrection {
+    /// Chars replaced: the control word and the char typed after it
+    pub replaced: Range<usize>,
+    pub replacement: String,
+    /// Caret after the replacement
+    pub caret: usize,
+}
+
+/// Math AutoCorrect for the char typed before `caret`, if it is in a math zone
+///
+/// The replacement is an undo step of its own, so undoing it gets the
+/// control word back.
+pub fn correct_as_you_type(tree: &mut PieceTree, zones: &MathZones, caret: usize) -> Option<MathCorrection> {
+    let zone = zones.at(caret)?;
+    // Only the zone's text counts, so a control word starts inside it
+    let zone_start = tree.char_to_byte_offset(zone.start);
+    let before = tree.get_text_range(zone_start, tree.char_to_byte_offset(caret) - zone_start);
+    let (length, replacement) = autocorrect(&before)?;
+    let replaced = caret - length..caret;
+    tree.break_undo_coalescing();
+    tree.transaction(|tree| {
+        let start = tree.char_to_byte_offset(replaced.start);
+        tree.delete(start, tree.char_to_byte_offset(replaced.end) - start);
+        tree.insert(replaced.start, replacement.clone());
+    });
+    tree.break_undo_coalescing();
+    Some(MathCorrection {
+        caret: replaced.start + replacement.chars().count(),
+        replaced,
+        replacement,
+    })
+}
+
+/// An equation's OMML, an `m:oMath` element
+pub fn to_omml(nodes: &[MathNode]) -> String {
+    format!("<m:oMath>{}</m:oMath>", omml_content(nodes))
+}
+
+fn omml_content(nodes: &[MathNode]) -> String {
+    nodes.iter().map(omml_node).collect()
+}
+
+fn omml_node(node: &MathNode) -> String {
+    let part = |name: &str, nodes: &[MathNode]| format!("<m:{0}>{1}</m:{0}>", name, omml_content(nodes));
+    match node {
+        MathNode::Text { text } => {
+            format!(r#"<m:r><m:t xml:space="preserve">{}</m:t></m:r>"#, escape_xml_text(text))
+        }
+        MathNode::Fraction { numerator, denominator } => {
+            format!("<m:f>{}{}</m:f>", part("num", numerator), part("den", denominator))
+        }
+        MathNode::Script { base, subscript: Some(sub), superscript: Some(sup) } => {
+            format!("<m:sSubSup>{}{}{}</m:sSubSup>", part("e", base), part("sub", sub), part("sup", sup))
+        }
+        MathNode::Script { base, subscript: Some(sub), superscript: None } => {
+            format!("<m:sSub>{}{}</m:sSub>", part("e", base), part("sub", sub))
+        }
+        MathNode::Script { base, subscript: None, superscript } => {
+            let sup = superscript.as_deref().unwrap_or_default();
+            format!("<m:sSup>{}{}</m:sSup>", part("e", base), part("sup", sup))
+        }
+        MathNode::Radical { degree: Some(degree), radicand } => {
+            format!("<m:rad>{}{}</m:rad>", part("deg", degree), part("e", radicand))
+        }
+        MathNode::Radical { degree: None, radicand } => format!(
+            r#"<m:rad><m:radPr><m:degHide m:val="1"/></m:radPr><m:deg/>{}</m:rad>"#,
+            part("e", radicand)
+        ),
+        MathNode::Delimited { open, close, body } => {
+            let properties = match (open, close) {
+                ('(', ')') => String::new(),
+                _ => format!(
+                    r#"<m:dPr><m:begChr m:val="{}"/><m:endChr m:val="{}"/></m:dPr>"#,
+                    escape_xml_attr(&open.to_string()),
+                    escape_xml_attr(&close.to_string())
+                ),
+            };
+            format!("<m:d>{}{}</m:d>", properties, part("e", body))
+        }
+    }
+}
+
+/// An OMML element, with the text of its `m:t` children
+#[derive(Default)]
+struct OmmlElement {
+    name: String,
+    attributes: String,
+    children: Vec<OmmlElement>,
+    text: String,
+}
+
+impl OmmlElement {
+    fn child(&self, name: &str) -> Option<&OmmlElement> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn attribute(&self, name: &str) -> Option<String> {
+        OMML_ATTRIBUTE
+            .captures_iter(&self.attributes)
+            .find(|caps| caps[1].strip_prefix("m:") == Some(name))
+            .map(|caps| unescape_xml_text(&caps[2]))
+    }
+
+    /// All the text inside, for structures without a tree node of their own
+    fn all_text(&self) -> String {
+        let mut text = self.text.clone();
+        for child in &self.children {
+            text.push_str(&child.all_text());
+        }
+        text
+    }
+}
+
+/// The tree of an `m:oMath` element, or of the content of one
+///
+/// Structures the tree has no node for keep their text.
+pub fn from_omml(xml: &str) -> Vec<MathNode> {
+    let mut stack = vec![OmmlElement::default()];
+    for caps in OMML_TOKEN.captures_iter(xml) {
+        if let Some(text) = caps.get(1) {
+            if let Some(top) = stack.last_mut() {
+                top.text.push_str(&unescape_xml_text(text.as_str()));
+            }
+            continue;
+        }
+        let element = OmmlElement {
+            name: caps[3].to_string(),
+            attributes: caps[4].to_string(),
+            ..Default::default()
+        };
+        if &caps[2] == "/" {
+            if stack.len() > 1 {
+                let done = stack.pop().unwrap_or_default();
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(done);
+                }
+            }
+        } else if &caps[5] == "/" {
+            if let Some(parent) = stack.last_mut() {
+                parent.children.push(element);
+            }
+        } else {
+            stack.push(element);
+        }
+    }
+    while stack.len() > 1 {
+        let done = stack.pop().unwrap_or_default();
+        if let Some(parent) = stack.last_mut() {
+            parent.children.push(done);
+        }
+    }
+    let root = stack.pop().unwrap_or_default();
+    merge_text(omml_children(&root))
+}
+
+fn omml_children(element: &OmmlElement) -> Vec<MathNode> {
+    element.children.iter().flat_map(omml_tree).collect()
+}
+
+fn omml_tree(element: &OmmlElement) -> Vec<MathNode> {
+    let part = |name: &str| element.child(name).map(|child| merge_text(omml_children(child))).unwrap_or_default();
+    let node = match element.name.as_str() {
+        "oMathPara" | "oMath" | "e" => return omml_children(element),
+        "r" => MathNode::text(element.all_text()),
+        "f" => MathNode::Fraction {
+            numerator: part("num"),
+            denominator: part("den"),
+        },
+        "sSup" => MathNode::Script {
+            base: part("e"),
+            subscript: None,
+            superscript: Some(part("sup")),
+        },
+        "sSub" => MathNode::Script {
+            base: part("e"),
+            subscript: Some(part("sub")),
+            superscript: None,
+        },
+        "sSubSup" => MathNode::Script {
+            base: part("e"),
+            subscript: Some(part("sub")),
+            superscript: Some(part("sup")),
+        },
+        "rad" => {
+            let hidden = element
+                .child("radPr")
+                .and_then(|properties| properties.child("degHide"))
+                .is_some_and(|hide| !matches!(hide.attribute("val").as_deref(), Some("0" | "off" | "false")));
+            let degree = part("deg");
+            MathNode::Radical {
+                degree: (!hidden && !degree.is_empty()).then_some(degree),
+                radicand: part("e"),
+            }
+        }
+        "d" => {
+            let properties = element.child("dPr");
+            let bracket = |name: &str, default: char| {
+                match properties.and_then(|p| p.child(name)).map(|c| c.attribute("val").unwrap_or_default()) {
+                    Some(value) => value.chars().next().unwrap_or(' '),
+                    None => default,
+                }
+            };
+            let body = element
+                .children
+                .iter()
+                .filter(|child| child.name == "e")
+                .map(|child| merge_text(omml_children(child)))
+                .reduce(|mut body, next| {
+                    body.push(MathNode::text(","));
+                    body.extend(next);
+                    body
+                })
+                .unwrap_or_default();
+            MathNode::Delimited {
+                open: bracket("begChr", '('),
+                close: bracket("endChr", ')'),
+                body: merge_text(body),
+            }
+        }
+        // Properties are not content
+        name if name.ends_with("Pr") => return Vec::new(),
+        _ => MathNode::text(element.all_text()),
+    };
+    vec![node]
+}
+
+/// The math zones of a document, in text order
+#[derive(Debug, Clone, Default)]
+pub struct MathZones {
+    zones: Vec<MathZone>,
+}
+
+impl MathZones {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Math zones of `paragraphs`, each given with the char offset it starts
+    /// at, with offsets into the whole text
+    pub fn from_paragraphs<'a>(paragraphs: impl IntoIterator<Item = (usize, &'a Paragraph)>) -> Self {
+        let mut zones = Vec::new();
+        for (paragraph_start, paragraph) in paragraphs {
+            zones.extend(paragraph.math_zones.iter().map(|zone| MathZone {
+                start: paragraph_start + zone.start,
+                length: zone.length,
+            }));
+        }
+        zones.sort_by_key(|zone| zone.start);
+        MathZones { zones }
+    }
+
+    /// Math zones of the model's body paragraphs
+    pub fn from_model(model: &DocumentModel) -> Self {
+        Self::from_paragraphs(model.located_paragraphs())
+    }
+
+    /// Hand the zones to the model's body paragraphs
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let mut paragraph_start = 0;
+        for block in model.body.iter_mut() {
+            let Block::Paragraph(paragraph) = block else {
+                continue;
+            };
+            let length = paragraph.text.chars().count();
+            paragraph.math_zones = paragraph_math_zones(&self.zones, paragraph_start, length);
+            paragraph_start += length + 1;
+        }
+    }
+
+    pub fn zones(&self) -> &[MathZone] {
+        &self.zones
+    }
+
+    /// The zone the caret at `offset` types into; a zone's ends are in it
+    pub fn at(&self, offset: usize) -> Option<&MathZone> {
+        self.zones.iter().find(|zone| zone.start <= offset && offset <= zone.start + zone.length)
+    }
+
+    /// Make chars `range` a math zone, or, if any zone is in it, turn those back into text
+    ///
+    /// An empty range in no zone makes an empty zone to type into. Returns
+    /// true if a zone was made.
+    pub fn toggle(&mut self, range: Range<usize>) -> bool {
+        let touched = |zone: &MathZone| {
+            let end = zone.start + zone.length;
+            match range.is_empty() {
+                true => zone.start <= range.start && range.start <= end,
+                false => zone.start < range.end && range.start < end || (zone.length == 0 && range.contains(&zone.start)),
+            }
+        };
+        let before = self.zones.len();
+        self.zones.retain(|zone| !touched(zone));
+        if self.zones.len() < before {
+            return false;
+        }
+        let index = self.zones.partition_point(|zone| zone.start < range.start);
+        self.zones.insert(
+            index,
+            MathZone {
+                start: range.start,
+                length: range.len(),
+            },
+        );
+        true
+    }
+
+    /// Move the zones past an edit
+    ///
+    /// Unlike [`move_anchor`], text typed at either end of a zone goes into it;
+    /// a zone whose text is all deleted, and not replaced, goes away.
+    pub fn apply_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        self.zones.retain_mut(|zone| {
+            let end = zone.start + zone.length;
+            if zone.length > 0 && removed > 0 && inserted == 0 && offset <= zone.start && end <= offset + removed {
+                return false;
+            }
+            // Where the zone is once the removed text is gone
+            let (start, length) = move_anchor(zone.start, zone.length, offset, removed, 0);
+            let end = start + length;
+            zone.start = if start > offset { start + inserted } else { start };
+            zone.length = if end >= offset { end + inserted } else { end } - zone.start;
+            true
+        });
+    }
+}
+
+/// The parts of `zones` inside the paragraph of `length` chars at `start`, relative to it
+pub(crate) fn paragraph_math_zones(zones: &[MathZone], start: usize, length: usize) -> Vec<MathZone> {
+    let end = start + length;
+    zones
+        .iter()
+        .filter_map(|zone| {
+            let from = zone.start.max(start);
+            let to = (zone.start + zone.length).min(end);
+            let inside = if zone.length == 0 {
+                (start..=end).contains(&zone.start)
+            } else {
+                from < to
+            };
+            inside.then(|| MathZone {
+                start: from - start,
+                length: to.saturating_sub(from),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> MathNode {
+        MathNode::text(text)
+    }
+
+    #[test]
+    fn test_parse_linear_structures() {
+        assert_eq!(
+            parse_linear("a^2+b^2=c^2"),
+            [
+                MathNode::Script { base: vec![text("a")], subscript: None, superscript: Some(vec![text("2")]) },
+                text("+"),
+                MathNode::Script { base: vec![text("b")], subscript: None, superscript: Some(vec![text("2")]) },
+                text("="),
+                MathNode::Script { base: vec![text("c")], subscript: None, superscript: Some(vec![text("2")]) },
+            ]
+        );
+        // Parentheses round a numerator only group it; round a base they show
+        assert_eq!(
+            parse_linear("(a+b)/2"),
+            [MathNode::Fraction { numerator: vec![text("a+b")], denominator: vec![text("2")] }]
+        );
+        let MathNode::Script { base, .. } = &parse_linear("(a+b)^n")[0] else {
+            panic!("expected a script");
+        };
+        assert!(matches!(base[..], [MathNode::Delimited { open: '(', .. }]));
+        // Of a run of letters only the last takes the script
+        assert_eq!(
+            parse_linear("x_i^2 y"),
+            [MathNode::Script {
+                base: vec![text("x")],
+                subscript: Some(vec![text("i")]),
+                superscript: Some(vec![text("2")]),
+            }, text("y")]
+        );
+        assert_eq!(parse_linear("2ab^2")[0], text("2a"));
+        assert_eq!(
+            parse_linear("√(3&x)"),
+            [MathNode::Radical { degree: Some(vec![text("3")]), radicand: vec![text("x")] }]
+        );
+        assert_eq!(parse_linear(r"\sqrt x"), [MathNode::Radical { degree: None, radicand: vec![text("x")] }]);
+        assert_eq!(parse_linear(r"\alpha+\unknown"), [text(r"α+\unknown")]);
+    }
+
+    #[test]
+    fn test_linear_round_trip() {
+        for linear in ["a^2+b^2=c^2", "(a+b)/2", "x_i^2", "√(3&x)+√(x+1)", "e^(iπ)", "(a/b)/c", "[0,1]", "〖a+b〗^2", "1/(1+x^2)", "x^2/y_1"] {
+            let tree = parse_linear(linear);
+            assert_eq!(parse_linear(&to_linear(&tree)), tree, "{}", linear);
+        }
+        assert_eq!(to_linear(&parse_linear("( a + b ) / 2")), "(a+b)/2");
+        assert_eq!(to_linear(&parse_linear("(x^2)/(y_1)")), "x^2/y_1");
+    }
+
+    #[test]
+    fn test_omml_round_trip() {
+        let tree = parse_linear("x=(-b±√(b^2-4ac))/(2a)+[y]_1+√(3&z)");
+        let omml = to_omml(&tree);
+        assert!(omml.starts_with("<m:oMath><m:r>"));
+        assert!(omml.contains("<m:f><m:num>"));
+        assert!(omml.contains(r#"<m:radPr><m:degHide m:val="1"/></m:radPr><m:deg/>"#));
+        assert!(omml.contains(r#"<m:begChr m:val="["/>"#));
+        assert_eq!(from_omml(&omml), tree);
+
+        // Word's own markup, with run properties and a structure without a node
+        let word = r#"<m:oMathPara><m:oMath><m:sSup><m:sSupPr><m:ctrlPr><w:rPr><w:i/></w:rPr></m:ctrlPr></m:sSupPr><m:e><m:r><w:rPr><w:rFonts w:ascii="Cambria Math"/></w:rPr><m:t>e</m:t></m:r></m:e><m:sup><m:r><m:t>x</m:t></m:r></m:sup></m:sSup><m:nary><m:e><m:r><m:t>k</m:t></m:r></m:e></m:nary></m:oMath></m:oMathPara>"#;
+        assert_eq!(to_linear(&from_omml(word)), "e^xk");
+    }
+
+    #[test]
+    fn test_autocorrect_control_words() {
+        assert_eq!(autocorrect(r"x=\alpha "), Some((7, "α".to_string())));
+        assert_eq!(autocorrect(r"\pi/"), Some((4, "π/".to_string())));
+        assert_eq!(autocorrect(r"\sqrt("), Some((6, "√(".to_string())));
+        assert_eq!(autocorrect(r"\alph"), None);
+        assert_eq!(autocorrect(r"\unknown "), None);
+        assert_eq!(autocorrect("alpha "), None);
+    }
+
+    #[test]
+    fn test_zones_follow_edits() {
+        let mut zones = MathZones::new();
+        assert!(zones.toggle(4..4));
+        // Typing into the empty zone fills it
+        zones.apply_edit(4, 0, 3);
+        assert_eq!(zones.zones(), [MathZone { start: 4, length: 3 }]);
+        zones.apply_edit(7, 0, 2);
+        zones.apply_edit(0, 0, 1);
+        assert_eq!(zones.zones(), [MathZone { start: 5, length: 5 }]);
+        zones.apply_edit(6, 2, 0);
+        assert_eq!(zones.at(8), Some(&MathZone { start: 5, length: 3 }));
+
+        // Toggling over a zone turns it back into text
+        assert!(!zones.toggle(6..7));
+        assert!(zones.zones().is_empty());
+        assert!(zones.toggle(0..3));
+        zones.apply_edit(0, 3, 0);
+        assert!(zones.zones().is_empty());
+
+        let zones = [MathZone { start: 2, length: 6 }];
+        assert_eq!(paragraph_math_zones(&zones, 5, 4), [MathZone { start: 0, length: 3 }]);
+    }
+
+    #[test]
+    fn test_correct_as_you_type() {
+        let mut tree = PieceTree::new("Area: ".to_string());
+        let mut zones = MathZones::new();
+        // Outside a zone control words stay
+        tree.insert(6, r"\pi ".to_string());
+        assert_eq!(correct_as_you_type(&mut tree, &zones, 10), None);
+        tree.undo();
+
+        zones.toggle(6..6);
+        let mut caret = 6;
+        for c in r"\pi r^2".chars() {
+            tree.insert(caret, c.to_string());
+            zones.apply_edit(caret, 0, 1);
+            caret += 1;
+            if let Some(correction) = correct_as_you_type(&mut tree, &zones, caret) {
+                assert_eq!(correction, MathCorrection { replaced: 6..10, replacement: "π".to_string(), caret: 7 });
+                zones.apply_edit(6, 4, 1);
+                caret = correction.caret;
+            }
+        }
+        assert_eq!(tree.get_text(), "Area: πr^2");
+        assert_eq!(zones.zones(), [MathZone { start: 6, length: 4 }]);
+        let equation: String = tree.get_text().chars().skip(6).collect();
+        assert_eq!(
+            parse_linear(&equation),
+            [text("π"), MathNode::Script { base: vec![text("r")], subscript: None, superscript: Some(vec![text("2")]) }]
+        );
+
+        // Undo brings the control word back
+        let mut tree = PieceTree::new(r"\sqrt(".to_string());
+        let mut zones = MathZones::new();
+        zones.toggle(0..6);
+        assert!(correct_as_you_type(&mut tree, &zones, 6).is_some());
+        assert_eq!(tree.get_text(), "√(");
+        tree.undo();
+        assert_eq!(tree.get_text(), r"\sqrt(");
+    }
+}
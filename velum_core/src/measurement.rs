@@ -0,0 +1,340 @@
+//! # Measurement Units Module
+//!
+//! The unit the rulers, page setup, indent and table width fields show
+//! lengths in, and the formatting and parsing of dimension strings such as
+//! `2.54 cm`, `1"`, `12 pt`, `6 pi` or `2字符`.
+//!
+//! Lengths are in points, as in page setup; OOXML twips are 1/20 of a point.
+//! A character unit is the char pitch of the document grid, so two chars of
+//! indent line up with two grid columns; without a grid it is the body font
+//! size, as in Word.
+
+use serde::{Deserialize, Serialize};
+
+/// Twips per point
+const TWIPS_PER_POINT: f32 = 20.0;
+
+/// Char pitch without a document grid: 10.5pt, the body size of CJK documents
+pub const DEFAULT_CHAR_PITCH: f32 = 10.5;
+
+/// A unit lengths are shown and entered in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeasurementUnit {
+    #[default]
+    Inches,
+    Centimeters,
+    Millimeters,
+    Points,
+    Picas,
+    /// Grid characters, for CJK documents
+    Characters,
+}
+
+impl MeasurementUnit {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "inches" | "inch" | "in" => Some(MeasurementUnit::Inches),
+            "centimeters" | "centimetres" | "cm" => Some(MeasurementUnit::Centimeters),
+            "millimeters" | "millimetres" | "mm" => Some(MeasurementUnit::Millimeters),
+            "points" | "pt" => Some(MeasurementUnit::Points),
+            "picas" | "pi" => Some(MeasurementUnit::Picas),
+            "characters" | "chars" | "ch" => Some(MeasurementUnit::Characters),
+            _ => None,
+        }
+    }
+
+    /// The unit of a suffix as typed after a number (case-insensitive)
+    fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix.to_lowercase().as_str() {
+            "\"" | "″" | "”" | "in" | "inch" | "inches" | "英寸" => Some(MeasurementUnit::Inches),
+            "cm" | "厘米" | "公分" => Some(MeasurementUnit::Centimeters),
+            "mm" | "毫米" => Some(MeasurementUnit::Millimeters),
+            "pt" | "pts" | "point" | "points" | "磅" => Some(MeasurementUnit::Points),
+            "pi" | "pc" | "pica" | "picas" => Some(MeasurementUnit::Picas),
+            "ch" | "char" | "chars" | "字符" | "字" => Some(MeasurementUnit::Characters),
+            _ => None,
+        }
+    }
+
+    /// How values in this unit are written after the number
+    fn symbol(self) -> &'static str {
+        match self {
+            MeasurementUnit::Inches => "\"",
+            MeasurementUnit::Centimeters => " cm",
+            MeasurementUnit::Millimeters => " mm",
+            MeasurementUnit::Points => " pt",
+            MeasurementUnit::Picas => " pi",
+            MeasurementUnit::Characters => " 字符",
+        }
+    }
+
+    /// Decimals shown, about the precision of a ruler drag in this unit
+    fn decimals(self) -> usize {
+        match self {
+            MeasurementUnit::Millimeters | MeasurementUnit::Points => 1,
+            _ => 2,
+        }
+    }
+
+    /// Ruler ticks: units between numbered ticks, and ticks per numbered interval
+    fn ruler_steps(self) -> (f32, usize) {
+        match self {
+            MeasurementUnit::Inches => (1.0, 8),
+            MeasurementUnit::Centimeters => (1.0, 4),
+            MeasurementUnit::Millimeters => (10.0, 4),
+            MeasurementUnit::Points => (36.0, 6),
+            MeasurementUnit::Picas => (6.0, 6),
+            MeasurementUnit::Characters => (2.0, 2),
+        }
+    }
+}
+
+/// Errors reading a dimension string or setting the document grid
+#[derive(Debug, Clone, thiserror::Error, PartialEq)]
+pub enum MeasurementError {
+    #[error("No value entered")]
+    Empty,
+
+    #[error("'{0}' is not a number")]
+    InvalidNumber(String),
+
+    #[error("Unknown unit '{0}'")]
+    UnknownUnit(String),
+
+    #[error("Invalid document grid: {0}")]
+    InvalidGrid(String),
+}
+
+/// A table or column width as entered
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TableWidth {
+    /// Fixed width in points
+    Fixed(f32),
+    /// Fit the contents
+    Auto,
+    /// Percentage of the text width
+    Percent(f32),
+}
+
+/// A tick mark of a ruler
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RulerTick {
+    /// Distance from the ruler's origin in points
+    pub position: f32,
+    /// Number shown at the tick, in the ruler's unit
+    pub label: Option<String>,
+    /// Half-way between numbered ticks, drawn longer than the others
+    pub major: bool,
+}
+
+/// The preferred unit and the size of a character unit
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MeasurementSettings {
+    pub unit: MeasurementUnit,
+    /// Width of one character unit in points
+    pub char_pitch: f32,
+}
+
+impl Default for MeasurementSettings {
+    fn default() -> Self {
+        MeasurementSettings {
+            unit: MeasurementUnit::default(),
+            char_pitch: DEFAULT_CHAR_PITCH,
+        }
+    }
+}
+
+impl MeasurementSettings {
+    /// Tie the character unit to a document grid of `chars_per_line` chars
+    /// across `content_width` points of text area
+    pub fn set_document_grid(&mut self, content_width: f32, chars_per_line: u32) -> Result<(), MeasurementError> {
+        if chars_per_line == 0 || !content_width.is_finite() || content_width <= 0.0 {
+            return Err(MeasurementError::InvalidGrid(format!(
+                "{} chars across {}pt",
+                chars_per_line, content_width
+            )));
+        }
+        self.char_pitch = content_width / chars_per_line as f32;
+        Ok(())
+    }
+
+    /// Points in one `unit`
+    pub fn points_per_unit(&self, unit: MeasurementUnit) -> f32 {
+        match unit {
+            MeasurementUnit::Inches => 72.0,
+            MeasurementUnit::Centimeters => 72.0 / 2.54,
+            MeasurementUnit::Millimeters => 72.0 / 25.4,
+            MeasurementUnit::Points => 1.0,
+            MeasurementUnit::Picas => 12.0,
+            MeasurementUnit::Characters => self.char_pitch,
+        }
+    }
+
+    /// Show a length in points in the preferred unit, e.g. `1.5"` or `2 字符`
+    pub fn format(&self, points: f32) -> String {
+        self.format_in(points, self.unit)
+    }
+
+    pub fn format_in(&self, points: f32, unit: MeasurementUnit) -> String {
+        let value = points / self.points_per_unit(unit);
+        format!("{}{}", format_number(value, unit.decimals()), unit.symbol())
+    }
+
+    /// Show a length in twips in the preferred unit
+    pub fn format_twips(&self, twips: i32) -> String {
+        self.format(twips as f32 / TWIPS_PER_POINT)
+    }
+
+    /// Read a dimension string into points; a bare number is in the preferred unit
+    ///
+    /// Spaces around the number and unit are ignored, and a comma may stand
+    /// for the decimal point.
+    pub fn parse(&self, text: &str) -> Result<f32, MeasurementError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(MeasurementError::Empty);
+        }
+        let split = text
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || c == ',' || (i == 0 && (c == '-' || c == '+'))))
+            .map_or(text.len(), |(i, _)| i);
+        let (number, suffix) = text.split_at(split);
+        let value: f32 = number
+            .replace(',', ".")
+            .parse()
+            .ok()
+            .filter(|value: &f32| value.is_finite())
+            .ok_or_else(|| MeasurementError::InvalidNumber(number.to_string()))?;
+        let suffix = suffix.trim();
+        let unit = match suffix {
+            "" => self.unit,
+            suffix => MeasurementUnit::from_suffix(suffix).ok_or_else(|| MeasurementError::UnknownUnit(suffix.to_string()))?,
+        };
+        Ok(value * self.points_per_unit(unit))
+    }
+
+    /// Read a dimension string into twips, rounded to the nearest twip
+    pub fn parse_twips(&self, text: &str) -> Result<i32, MeasurementError> {
+        self.parse(text).map(|points| (points * TWIPS_PER_POINT).round() as i32)
+    }
+
+    /// Read a table width: "auto", a percentage of the text width such as
+    /// "50%", or a dimension string
+    pub fn parse_table_width(&self, text: &str) -> Result<TableWidth, MeasurementError> {
+        let text = text.trim();
+        if text.eq_ignore_ascii_case("auto") || text == "自动" {
+            return Ok(TableWidth::Auto);
+        }
+        if let Some(percent) = text.strip_suffix('%') {
+            let percent = percent.trim();
+            return percent
+                .replace(',', ".")
+                .parse::<f32>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .map(TableWidth::Percent)
+                .ok_or_else(|| MeasurementError::InvalidNumber(percent.to_string()));
+        }
+        self.parse(text).map(TableWidth::Fixed)
+    }
+
+    /// Ticks of a ruler `length` points long in the preferred unit, numbered
+    /// from its origin
+    pub fn ruler_ticks(&self, length: f32) -> Vec<RulerTick> {
+        let (numbered, per_number) = self.unit.ruler_steps();
+        let step = numbered * self.points_per_unit(self.unit) / per_number as f32;
+        if !step.is_finite() || step <= 0.0 || !length.is_finite() {
+            return Vec::new();
+        }
+        let count = (length / step + 1e-3).floor().max(0.0) as usize;
+        (0..=count)
+            .map(|n| {
+                let numbered_tick = n % per_number == 0;
+                RulerTick {
+                    position: n as f32 * step,
+                    label: (numbered_tick && n > 0)
+                        .then(|| format_number((n / per_number) as f32 * numbered, 0)),
+                    major: !numbered_tick && per_number % 2 == 0 && n % (per_number / 2) == 0,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A number with at most `decimals` decimals and no trailing zeros
+fn format_number(value: f32, decimals: usize) -> String {
+    let text = format!("{:.*}", decimals, value);
+    let text = if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &text
+    };
+    // Rounding leaves "-0" for small negative values
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dimension_strings() {
+        let settings = MeasurementSettings::default();
+        assert_eq!(settings.parse("2.54 cm"), Ok(72.0));
+        assert_eq!(settings.parse("1\""), Ok(72.0));
+        assert_eq!(settings.parse("12 pt"), Ok(12.0));
+        assert_eq!(settings.parse("1,5 pi"), Ok(18.0));
+        assert_eq!(settings.parse("25.4mm"), Ok(72.0));
+        assert_eq!(settings.parse("2字符"), Ok(21.0));
+        assert_eq!(settings.parse("-0.5"), Ok(-36.0));
+        assert_eq!(settings.parse_twips("2.54 cm"), Ok(1440));
+        assert_eq!(settings.parse("  "), Err(MeasurementError::Empty));
+        assert_eq!(settings.parse("cm"), Err(MeasurementError::InvalidNumber(String::new())));
+        assert_eq!(settings.parse("3 furlongs"), Err(MeasurementError::UnknownUnit("furlongs".to_string())));
+    }
+
+    #[test]
+    fn test_format_in_each_unit() {
+        let mut settings = MeasurementSettings::default();
+        assert_eq!(settings.format(108.0), "1.5\"");
+        assert_eq!(settings.format_in(72.0, MeasurementUnit::Centimeters), "2.54 cm");
+        assert_eq!(settings.format_in(72.0, MeasurementUnit::Millimeters), "25.4 mm");
+        assert_eq!(settings.format_in(18.0, MeasurementUnit::Picas), "1.5 pi");
+        assert_eq!(settings.format_in(-0.001, MeasurementUnit::Points), "0 pt");
+
+        settings.unit = MeasurementUnit::Characters;
+        assert_eq!(settings.format_twips(420), "2 字符");
+        for text in ["2 字符", "0.5 字符", "3.25 字符"] {
+            assert_eq!(settings.format(settings.parse(text).unwrap()), text);
+        }
+    }
+
+    #[test]
+    fn test_character_unit_follows_document_grid() {
+        let mut settings = MeasurementSettings { unit: MeasurementUnit::Characters, ..Default::default() };
+        // A4 with 3.17 cm side margins, gridded to 40 chars a line
+        settings.set_document_grid(415.3, 40).unwrap();
+        assert!((settings.parse("2").unwrap() - 20.765).abs() < 1e-3);
+        assert!(settings.set_document_grid(415.3, 0).is_err());
+
+        assert_eq!(settings.parse_table_width("50 %"), Ok(TableWidth::Percent(50.0)));
+        assert_eq!(settings.parse_table_width("Auto"), Ok(TableWidth::Auto));
+        assert_eq!(settings.parse_table_width("1 in"), Ok(TableWidth::Fixed(72.0)));
+    }
+
+    #[test]
+    fn test_ruler_ticks() {
+        let settings = MeasurementSettings { unit: MeasurementUnit::Centimeters, ..Default::default() };
+        let ticks = settings.ruler_ticks(72.0);
+        // 2.54 cm: ticks every quarter centimeter up to 2.5
+        assert_eq!(ticks.len(), 11);
+        assert_eq!(ticks[4].label.as_deref(), Some("1"));
+        assert!(ticks[2].major && !ticks[1].major && !ticks[4].major);
+        assert_eq!(ticks[0].label, None);
+    }
+}
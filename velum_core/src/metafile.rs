@@ -0,0 +1,1337 @@
+//! # Metafile Module
+//!
+//! Windows metafiles, the EMF and WMF vector pictures Office documents embed,
+//! converted to SVG, which [`ImageData::decode_pixels`] then rasterizes as it
+//! does SVG images.
+//!
+//! The conversion plays the metafile's records as GDI would: pens, brushes
+//! and fonts are created into the object table and selected, the window,
+//! viewport and world transform map logical coordinates to the device, and
+//! shapes are drawn with the pen and brush selected at the time. Lines,
+//! polygons, Béziers, rectangles, ellipses, arcs, paths, text and embedded
+//! bitmaps are drawn; the bitmaps, often all a metafile holds, become PNG
+//! images in the SVG.
+//!
+//! Left out are clipping, raster operations other than copying, pattern
+//! brushes, which fill nothing, and EMF+ records; EMF+ files written by
+//! Office carry the same picture in plain EMF records as well. Hatched
+//! brushes fill solid. Text is written to the SVG but not rasterized, as no
+//! fonts are loaded for it.
+//!
+//! [`ImageData::decode_pixels`]: crate::image::ImageData::decode_pixels
+
+use std::fmt::Write;
+
+use crate::image::Size;
+use crate::ooxml::{data_uri, escape_xml_attr, escape_xml_text};
+
+/// Pixels a device inch, when a metafile does not say
+const DEVICE_DPI: f32 = 96.0;
+
+const EMF_SIGNATURE: &[u8; 4] = b" EMF";
+const WMF_PLACEABLE_KEY: [u8; 4] = [0xD7, 0xCD, 0xC6, 0x9A];
+
+/// Metafile reading errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum MetafileError {
+    #[error("Not an EMF or WMF metafile")]
+    NotAMetafile,
+
+    #[error("Truncated metafile header")]
+    Truncated,
+
+    #[error("Metafile with an empty picture frame")]
+    EmptyFrame,
+}
+
+/// Whether `data` is an EMF, with the EMF header record and signature
+pub fn is_emf(data: &[u8]) -> bool {
+    u32_at(data, 0) == Some(1) && data.get(40..44) == Some(EMF_SIGNATURE)
+}
+
+/// Whether `data` is a WMF, placeable or not
+pub fn is_wmf(data: &[u8]) -> bool {
+    data.starts_with(&WMF_PLACEABLE_KEY)
+        || matches!(u16_at(data, 0), Some(1 | 2))
+            && u16_at(data, 2) == Some(9)
+            && matches!(u16_at(data, 4), Some(0x0100 | 0x0300))
+}
+
+/// The size a metafile is meant to be shown at, in points
+pub fn metafile_size(data: &[u8]) -> Result<Size, MetafileError> {
+    let header = Header::read(data)?;
+    Ok(Size::new(header.size.0, header.size.1))
+}
+
+/// Convert an EMF or WMF to an SVG document of the same picture
+pub fn metafile_to_svg(data: &[u8]) -> Result<String, MetafileError> {
+    let header = Header::read(data)?;
+    let mut player = Player::new(&header);
+    match header.kind {
+        Kind::Emf { .. } => player.play_emf(data, header.records_start),
+        Kind::Wmf => player.play_wmf(data, header.records_start),
+    }
+    Ok(player.into_svg())
+}
+
+// ============================================================================
+// Header
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// Device pixels a millimetre, horizontally and vertically
+    Emf { pixels_per_mm: (f32, f32) },
+    Wmf,
+}
+
+/// A left, top, width, height rectangle
+type Frame = (f32, f32, f32, f32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Header {
+    kind: Kind,
+    /// The picture, in device units for an EMF and logical units for a WMF
+    frame: Frame,
+    /// Size to show the picture at, in points
+    size: (f32, f32),
+    records_start: usize,
+}
+
+impl Header {
+    fn read(data: &[u8]) -> Result<Header, MetafileError> {
+        if is_emf(data) {
+            Self::read_emf(data)
+        } else if is_wmf(data) {
+            Self::read_wmf(data)
+        } else {
+            Err(MetafileError::NotAMetafile)
+        }
+    }
+
+    fn read_emf(data: &[u8]) -> Result<Header, MetafileError> {
+        let rect = |offset| -> Option<(i32, i32, i32, i32)> {
+            Some((i32_at(data, offset)?, i32_at(data, offset + 4)?, i32_at(data, offset + 8)?, i32_at(data, offset + 12)?))
+        };
+        let (Some(bounds), Some(frame), Some(header_size)) = (rect(8), rect(24), u32_at(data, 4)) else {
+            return Err(MetafileError::Truncated);
+        };
+        let device = (i32_at(data, 72), i32_at(data, 76), i32_at(data, 80), i32_at(data, 84));
+        let pixels_per_mm = match device {
+            (Some(cx), Some(cy), Some(mx), Some(my)) if cx > 0 && cy > 0 && mx > 0 && my > 0 => {
+                (cx as f32 / mx as f32, cy as f32 / my as f32)
+            }
+            _ => (DEVICE_DPI / 25.4, DEVICE_DPI / 25.4),
+        };
+
+        // The frame is in hundredths of a millimetre and includes its right and bottom edges
+        let (left, top, right, bottom) = frame;
+        let (frame, size) = match right > left && bottom > top {
+            true => {
+                let mm = |from: i32, to: i32| (to - from) as f32 / 100.0;
+                (
+                    (
+                        left as f32 / 100.0 * pixels_per_mm.0,
+                        top as f32 / 100.0 * pixels_per_mm.1,
+                        mm(left, right) * pixels_per_mm.0,
+                        mm(top, bottom) * pixels_per_mm.1,
+                    ),
+                    (mm(left, right) / 25.4 * 72.0, mm(top, bottom) / 25.4 * 72.0),
+                )
+            }
+            false => {
+                let (left, top, right, bottom) = bounds;
+                let (width, height) = ((right - left + 1) as f32, (bottom - top + 1) as f32);
+                if width <= 1.0 || height <= 1.0 {
+                    return Err(MetafileError::EmptyFrame);
+                }
+                (
+                    (left as f32, top as f32, width, height),
+                    (width / pixels_per_mm.0 / 25.4 * 72.0, height / pixels_per_mm.1 / 25.4 * 72.0),
+                )
+            }
+        };
+        Ok(Header {
+            kind: Kind::Emf { pixels_per_mm },
+            frame,
+            size,
+            records_start: header_size as usize,
+        })
+    }
+
+    fn read_wmf(data: &[u8]) -> Result<Header, MetafileError> {
+        let placeable = data.starts_with(&WMF_PLACEABLE_KEY);
+        let start = if placeable { 22 } else { 0 };
+        let header_words = u16_at(data, start + 2).ok_or(MetafileError::Truncated)?;
+        let records_start = start + usize::from(header_words) * 2;
+
+        if placeable {
+            let (Some(left), Some(top), Some(right), Some(bottom), Some(inch)) =
+                (i16_at(data, 6), i16_at(data, 8), i16_at(data, 10), i16_at(data, 12), u16_at(data, 14))
+            else {
+                return Err(MetafileError::Truncated);
+            };
+            let (width, height) = (f32::from(right) - f32::from(left), f32::from(bottom) - f32::from(top));
+            if width == 0.0 || height == 0.0 || inch == 0 {
+                return Err(MetafileError::EmptyFrame);
+            }
+            let points = 72.0 / f32::from(inch);
+            return Ok(Header {
+                kind: Kind::Wmf,
+                frame: (f32::from(left), f32::from(top), width, height),
+                size: (width.abs() * points, height.abs() * points),
+                records_start,
+            });
+        }
+
+        // Without a placeable header the window set for drawing is the picture
+        let (mut origin, mut extent) = ((0.0, 0.0), None);
+        for (function, record) in wmf_records(data, records_start) {
+            match function {
+                WMF_SETWINDOWORG => origin = (f32_i16(record, 8), f32_i16(record, 6)),
+                WMF_SETWINDOWEXT => extent = Some((f32_i16(record, 8), f32_i16(record, 6))),
+                _ => {}
+            }
+        }
+        let (width, height) = extent.filter(|(w, h)| *w != 0.0 && *h != 0.0).ok_or(MetafileError::EmptyFrame)?;
+        Ok(Header {
+            kind: Kind::Wmf,
+            frame: (origin.0, origin.1, width, height),
+            size: (width.abs() * 72.0 / DEVICE_DPI, height.abs() * 72.0 / DEVICE_DPI),
+            records_start,
+        })
+    }
+}
+
+// ============================================================================
+// Device Context
+// ============================================================================
+
+/// A `COLORREF`, 0x00BBGGRR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rgb(u32);
+
+impl Rgb {
+    const BLACK: Rgb = Rgb(0);
+    const WHITE: Rgb = Rgb(0x00FF_FFFF);
+
+    fn css(self) -> String {
+        let [r, g, b, _] = self.0.to_le_bytes();
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pen {
+    /// None for the null pen
+    color: Option<Rgb>,
+    /// In logical units; 0 is one device pixel
+    width: f32,
+    /// PS_SOLID, PS_DASH, ...
+    style: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Font {
+    /// Cell or char height in logical units
+    height: f32,
+    face: String,
+    bold: bool,
+    italic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum GdiObject {
+    Pen(Pen),
+    /// The brush's color, None for hollow and pattern brushes
+    Brush(Option<Rgb>),
+    Font(Font),
+    /// Palettes, regions and the like, kept only to hold their slot
+    Other,
+}
+
+/// An affine transform: x' = a x + c y + e, y' = b x + d y + f, as SVG's
+/// matrix() and GDI's XFORM have it
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// `first` then `then`
+fn multiply(first: Matrix, then: Matrix) -> Matrix {
+    let [a1, b1, c1, d1, e1, f1] = first;
+    let [a2, b2, c2, d2, e2, f2] = then;
+    [
+        a2 * a1 + c2 * b1,
+        b2 * a1 + d2 * b1,
+        a2 * c1 + c2 * d1,
+        b2 * c1 + d2 * d1,
+        a2 * e1 + c2 * f1 + e2,
+        b2 * e1 + d2 * f1 + f2,
+    ]
+}
+
+/// Mapping modes
+const MM_TEXT: u32 = 1;
+const MM_ISOTROPIC: u32 = 7;
+const MM_ANISOTROPIC: u32 = 8;
+
+/// What drawing records draw with, saved and restored as a whole
+#[derive(Debug, Clone, PartialEq)]
+struct DeviceContext {
+    pen: Pen,
+    brush: Option<Rgb>,
+    font: Option<Font>,
+    text_color: Rgb,
+    text_align: u32,
+    /// ALTERNATE, as opposed to WINDING
+    even_odd: bool,
+    position: (f32, f32),
+    map_mode: u32,
+    window_origin: (f32, f32),
+    window_extent: Option<(f32, f32)>,
+    viewport_origin: (f32, f32),
+    viewport_extent: (f32, f32),
+    world: Matrix,
+}
+
+impl Default for DeviceContext {
+    fn default() -> Self {
+        DeviceContext {
+            pen: Pen {
+                color: Some(Rgb::BLACK),
+                width: 0.0,
+                style: 0,
+            },
+            brush: Some(Rgb::WHITE),
+            font: None,
+            text_color: Rgb::BLACK,
+            text_align: 0,
+            even_odd: true,
+            position: (0.0, 0.0),
+            map_mode: MM_TEXT,
+            window_origin: (0.0, 0.0),
+            window_extent: None,
+            viewport_origin: (0.0, 0.0),
+            viewport_extent: (1.0, 1.0),
+            world: IDENTITY,
+        }
+    }
+}
+
+// ============================================================================
+// Player
+// ============================================================================
+
+/// Plays records into SVG elements
+struct Player {
+    kind: Kind,
+    frame: Frame,
+    /// In points
+    size: (f32, f32),
+    /// Device units of one pixel, the width of a zero-width pen
+    pixel: f32,
+    dc: DeviceContext,
+    saved: Vec<DeviceContext>,
+    objects: Vec<Option<GdiObject>>,
+    /// The path of an open path bracket, or of the last closed one
+    path: String,
+    in_path: bool,
+    elements: String,
+}
+
+impl Player {
+    fn new(header: &Header) -> Self {
+        let pixel = match header.kind {
+            Kind::Emf { .. } => 1.0,
+            Kind::Wmf => header.frame.2.abs() / (header.size.0 / 72.0 * DEVICE_DPI),
+        };
+        let mut dc = DeviceContext::default();
+        if header.kind == Kind::Wmf {
+            dc.window_origin = (header.frame.0, header.frame.1);
+            dc.window_extent = Some((header.frame.2, header.frame.3));
+        }
+        Player {
+            kind: header.kind,
+            frame: header.frame,
+            size: header.size,
+            pixel,
+            dc,
+            saved: Vec::new(),
+            objects: Vec::new(),
+            path: String::new(),
+            in_path: false,
+            elements: String::new(),
+        }
+    }
+
+    /// Logical coordinates to device units, or for a WMF to units of the
+    /// picture frame, by the mapping mode and the window and viewport
+    fn page_matrix(&self) -> Matrix {
+        let dc = &self.dc;
+        let (ox, oy) = dc.window_origin;
+        let (sx, sy) = match self.kind {
+            // The window maps onto the frame the picture was sized by
+            Kind::Wmf => {
+                let (wx, wy) = dc.window_extent.unwrap_or((1.0, 1.0));
+                (self.frame.2 / wx, self.frame.3 / wy)
+            }
+            Kind::Emf { pixels_per_mm } => match dc.map_mode {
+                MM_ISOTROPIC | MM_ANISOTROPIC => {
+                    let (wx, wy) = dc.window_extent.unwrap_or((1.0, 1.0));
+                    let (sx, sy) = (dc.viewport_extent.0 / wx, dc.viewport_extent.1 / wy);
+                    match dc.map_mode == MM_ISOTROPIC {
+                        true => {
+                            let scale = sx.abs().min(sy.abs());
+                            (scale * sx.signum(), scale * sy.signum())
+                        }
+                        false => (sx, sy),
+                    }
+                }
+                MM_TEXT => (1.0, 1.0),
+                mode => {
+                    // Fixed modes count in physical units, y up
+                    let units_per_mm = match mode {
+                        2 => 10.0,
+                        3 => 100.0,
+                        4 => 100.0 / 25.4,
+                        5 => 1000.0 / 25.4,
+                        _ => 1440.0 / 25.4,
+                    };
+                    (pixels_per_mm.0 / units_per_mm, -pixels_per_mm.1 / units_per_mm)
+                }
+            },
+        };
+        let (vx, vy) = match self.kind {
+            Kind::Wmf => (self.frame.0, self.frame.1),
+            Kind::Emf { .. } => dc.viewport_origin,
+        };
+        [sx, 0.0, 0.0, sy, vx - ox * sx, vy - oy * sy].map(|v| if v.is_finite() { v } else { 0.0 })
+    }
+
+    /// Logical coordinates to the SVG's, through the world transform
+    fn matrix(&self) -> Matrix {
+        multiply(self.dc.world, self.page_matrix())
+    }
+
+    fn transform_attribute(&self) -> String {
+        let matrix = self.matrix();
+        match matrix == IDENTITY {
+            true => String::new(),
+            false => format!(" transform=\"matrix({})\"", matrix.map(num).join(" ")),
+        }
+    }
+
+    // ==================== Objects ====================
+
+    /// Put `object` in the lowest free slot, as WMF creation records do
+    fn add_object(&mut self, object: GdiObject) {
+        match self.objects.iter().position(Option::is_none) {
+            Some(slot) => self.objects[slot] = Some(object),
+            None => self.objects.push(Some(object)),
+        }
+    }
+
+    /// Put `object` in slot `index`, as EMF creation records do
+    fn set_object(&mut self, index: u32, object: GdiObject) {
+        let index = index as usize;
+        if index > 0xFFFF {
+            return;
+        }
+        if self.objects.len() <= index {
+            self.objects.resize(index + 1, None);
+        }
+        self.objects[index] = Some(object);
+    }
+
+    fn select_object(&mut self, index: u32) {
+        // Stock objects have the high bit set
+        if index & 0x8000_0000 != 0 {
+            let gray = |level: u32| Some(Rgb(level * 0x0001_0101));
+            match index & 0x7FFF_FFFF {
+                0 => self.dc.brush = Some(Rgb::WHITE),
+                1 => self.dc.brush = gray(0xC0),
+                2 => self.dc.brush = gray(0x80),
+                3 => self.dc.brush = gray(0x40),
+                4 => self.dc.brush = Some(Rgb::BLACK),
+                5 => self.dc.brush = None,
+                6 => self.dc.pen = Pen { color: Some(Rgb::WHITE), width: 0.0, style: 0 },
+                7 => self.dc.pen = Pen { color: Some(Rgb::BLACK), width: 0.0, style: 0 },
+                8 => self.dc.pen.color = None,
+                _ => {}
+            }
+            return;
+        }
+        match self.objects.get(index as usize).cloned().flatten() {
+            Some(GdiObject::Pen(pen)) => self.dc.pen = pen,
+            Some(GdiObject::Brush(brush)) => self.dc.brush = brush,
+            Some(GdiObject::Font(font)) => self.dc.font = Some(font),
+            Some(GdiObject::Other) | None => {}
+        }
+    }
+
+    fn delete_object(&mut self, index: u32) {
+        if let Some(slot) = self.objects.get_mut(index as usize) {
+            *slot = None;
+        }
+    }
+
+    fn restore(&mut self, relative: i32) {
+        // Negative counts back from the last save; positive names a save by number
+        let index = match relative < 0 {
+            true => self.saved.len().checked_sub(relative.unsigned_abs() as usize),
+            false => (relative as usize).checked_sub(1),
+        };
+        if let Some(index) = index.filter(|index| *index < self.saved.len()) {
+            self.dc = self.saved[index].clone();
+            self.saved.truncate(index);
+        }
+    }
+
+    // ==================== Drawing ====================
+
+    /// Draw the figure `d`, filled with the brush if `fill` and outlined
+    /// with the pen, or in a path bracket add it to the path
+    fn shape(&mut self, d: &str, fill: bool) {
+        if self.in_path {
+            self.path.push_str(d);
+            return;
+        }
+        self.draw(d, fill, true);
+    }
+
+    fn draw(&mut self, d: &str, fill: bool, stroke: bool) {
+        let fill = self.dc.brush.filter(|_| fill);
+        let pen = self.dc.pen;
+        let stroke = pen.color.filter(|_| stroke && pen.style & 0xF != 5);
+        if d.is_empty() || fill.is_none() && stroke.is_none() {
+            return;
+        }
+        let mut element = format!("<path d=\"{}\"", d.trim());
+        match fill {
+            Some(color) => {
+                let _ = write!(element, " fill=\"{}\"", color.css());
+                if self.dc.even_odd {
+                    element.push_str(" fill-rule=\"evenodd\"");
+                }
+            }
+            None => element.push_str(" fill=\"none\""),
+        }
+        if let Some(color) = stroke {
+            let matrix = self.matrix();
+            let scale = (matrix[0] * matrix[3] - matrix[1] * matrix[2]).abs().sqrt();
+            let width = match pen.width > 0.0 {
+                true => pen.width,
+                false => self.pixel / scale.max(f32::EPSILON),
+            };
+            let _ = write!(element, " stroke=\"{}\" stroke-width=\"{}\"", color.css(), num(width));
+            let dashes: &[f32] = match pen.style & 0xF {
+                1 => &[3.0, 1.0],
+                2 => &[1.0, 1.0],
+                3 => &[3.0, 1.0, 1.0, 1.0],
+                4 => &[3.0, 1.0, 1.0, 1.0, 1.0, 1.0],
+                _ => &[],
+            };
+            if !dashes.is_empty() {
+                let dashes: Vec<String> = dashes.iter().map(|dash| num(dash * width.max(self.pixel))).collect();
+                let _ = write!(element, " stroke-dasharray=\"{}\"", dashes.join(" "));
+            }
+        }
+        element.push_str(&self.transform_attribute());
+        element.push_str("/>");
+        self.elements.push_str(&element);
+    }
+
+    fn move_to(&mut self, point: (f32, f32)) {
+        self.dc.position = point;
+        if self.in_path {
+            let _ = write!(self.path, "M{} {} ", num(point.0), num(point.1));
+        }
+    }
+
+    /// Lines, or with `bezier` Bézier curves, from the current position
+    /// through `points`, which end at the new position
+    fn draw_to(&mut self, points: &[(f32, f32)], bezier: bool) {
+        let Some(&last) = points.last() else {
+            return;
+        };
+        let start = self.dc.position;
+        let mut d = String::new();
+        if !self.in_path || self.path.is_empty() {
+            let _ = write!(d, "M{} {} ", num(start.0), num(start.1));
+        }
+        push_points(&mut d, points, bezier);
+        self.dc.position = last;
+        match self.in_path {
+            true => self.path.push_str(&d),
+            false => self.draw(&d, false, true),
+        }
+    }
+
+    fn poly(&mut self, points: &[(f32, f32)], closed: bool, bezier: bool) {
+        let Some((&first, rest)) = points.split_first() else {
+            return;
+        };
+        let mut d = format!("M{} {} ", num(first.0), num(first.1));
+        push_points(&mut d, rest, bezier);
+        if closed {
+            d.push_str("Z ");
+        }
+        match closed {
+            true => self.shape(&d, true),
+            false if self.in_path => self.path.push_str(&d),
+            false => self.draw(&d, false, true),
+        }
+    }
+
+    fn rectangle(&mut self, (left, top, right, bottom): (f32, f32, f32, f32), corner: (f32, f32)) {
+        let (rx, ry) = ((corner.0 / 2.0).abs().min((right - left).abs() / 2.0), (corner.1 / 2.0).abs().min((bottom - top).abs() / 2.0));
+        let (left, right) = (left.min(right), left.max(right));
+        let (top, bottom) = (top.min(bottom), top.max(bottom));
+        let d = match rx > 0.0 && ry > 0.0 {
+            false => format!("M{} {} H{} V{} H{} Z ", num(left), num(top), num(right), num(bottom), num(left)),
+            true => {
+                let arc = |x: f32, y: f32| format!("A{} {} 0 0 1 {} {} ", num(rx), num(ry), num(x), num(y));
+                format!(
+                    "M{} {} H{} {}V{} {}H{} {}V{} {}Z ",
+                    num(left + rx),
+                    num(top),
+                    num(right - rx),
+                    arc(right, top + ry),
+                    num(bottom - ry),
+                    arc(right - rx, bottom),
+                    num(left + rx),
+                    arc(left, bottom - ry),
+                    num(top + ry),
+                    arc(left + rx, top),
+                )
+            }
+        };
+        self.shape(&d, true);
+    }
+
+    fn ellipse(&mut self, (left, top, right, bottom): (f32, f32, f32, f32)) {
+        let (rx, ry) = ((right - left).abs() / 2.0, (bottom - top).abs() / 2.0);
+        let (cx, cy) = ((left + right) / 2.0, (top + bottom) / 2.0);
+        let half = |x: f32| format!("A{} {} 0 1 0 {} {} ", num(rx), num(ry), num(x), num(cy));
+        let d = format!("M{} {} {}{}Z ", num(cx - rx), num(cy), half(cx + rx), half(cx - rx));
+        self.shape(&d, true);
+    }
+
+    /// An arc of the ellipse in `rect`, counterclockwise from where the ray
+    /// from its center to `start` crosses it to where the ray to `end` does,
+    /// alone, closed by a chord, or as a pie slice
+    fn arc(&mut self, (left, top, right, bottom): (f32, f32, f32, f32), start: (f32, f32), end: (f32, f32), close: ArcClose) {
+        let (rx, ry) = ((right - left).abs() / 2.0, (bottom - top).abs() / 2.0);
+        let (cx, cy) = ((left + right) / 2.0, (top + bottom) / 2.0);
+        if rx == 0.0 || ry == 0.0 {
+            return;
+        }
+        let angle = |(x, y): (f32, f32)| ((y - cy) * rx).atan2((x - cx) * ry);
+        let on_ellipse = |t: f32| (cx + rx * t.cos(), cy + ry * t.sin());
+        let (from, to) = (angle(start), angle(end));
+        let mut sweep = (from - to).rem_euclid(std::f32::consts::TAU);
+        if sweep == 0.0 {
+            sweep = std::f32::consts::TAU;
+        }
+        let (p0, p1) = (on_ellipse(from), on_ellipse(to));
+        let mut d = match close {
+            ArcClose::Pie => format!("M{} {} L{} {} ", num(cx), num(cy), num(p0.0), num(p0.1)),
+            _ => format!("M{} {} ", num(p0.0), num(p0.1)),
+        };
+        let large = u8::from(sweep > std::f32::consts::PI);
+        if sweep >= std::f32::consts::TAU - 1e-4 {
+            // A full turn, as two halves since an arc's ends must differ
+            let middle = on_ellipse(from - std::f32::consts::PI);
+            let _ = write!(d, "A{} {} 0 1 0 {} {} ", num(rx), num(ry), num(middle.0), num(middle.1));
+        }
+        let _ = write!(d, "A{} {} 0 {} 0 {} {} ", num(rx), num(ry), large, num(p1.0), num(p1.1));
+        match close {
+            ArcClose::Open if self.in_path => self.path.push_str(&d),
+            ArcClose::Open => self.draw(&d, false, true),
+            _ => {
+                d.push_str("Z ");
+                self.shape(&d, true);
+            }
+        }
+    }
+
+    fn text(&mut self, at: (f32, f32), text: &str) {
+        let text = text.trim_end_matches('\0');
+        if text.trim().is_empty() {
+            return;
+        }
+        let font = self.dc.font.clone();
+        let height = font.as_ref().map_or(12.0, |font| font.height.abs().max(1.0));
+        let mut element = format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"{}\" fill=\"{}\"",
+            num(at.0),
+            num(at.1),
+            num(height),
+            self.dc.text_color.css()
+        );
+        if let Some(font) = font.filter(|font| !font.face.is_empty()) {
+            let _ = write!(element, " font-family=\"{}\"", escape_xml_attr(&font.face));
+            if font.bold {
+                element.push_str(" font-weight=\"bold\"");
+            }
+            if font.italic {
+                element.push_str(" font-style=\"italic\"");
+            }
+        }
+        // TA_CENTER, TA_RIGHT; TA_BASELINE, TA_BOTTOM, else the top
+        match self.dc.text_align & 6 {
+            6 => element.push_str(" text-anchor=\"middle\""),
+            2 => element.push_str(" text-anchor=\"end\""),
+            _ => {}
+        }
+        match self.dc.text_align & 24 {
+            24 => {}
+            8 => element.push_str(" dominant-baseline=\"text-after-edge\""),
+            _ => element.push_str(" dominant-baseline=\"text-before-edge\""),
+        }
+        element.push_str(&self.transform_attribute());
+        let _ = write!(element, ">{}</text>", escape_xml_text(text));
+        self.elements.push_str(&element);
+    }
+
+    /// Draw a device-independent bitmap, its header and color table
+    /// `info` and its pixels `bits`, into the rectangle `(x, y, width, height)`
+    fn bitmap(&mut self, (x, y, width, height): (f32, f32, f32, f32), info: &[u8], bits: &[u8]) {
+        let Some(png) = dib_to_png(info, bits) else {
+            return;
+        };
+        let (x, width) = (x.min(x + width), width.abs());
+        let (y, height) = (y.min(y + height), height.abs());
+        let _ = write!(
+            self.elements,
+            "<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" xlink:href=\"{}\"{}/>",
+            num(x),
+            num(y),
+            num(width),
+            num(height),
+            data_uri("image/png", &png),
+            self.transform_attribute()
+        );
+    }
+
+    fn into_svg(self) -> String {
+        let (x, y, width, height) = self.frame;
+        let (x, width) = (x.min(x + width), width.abs());
+        let (y, height) = (y.min(y + height), height.abs());
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+             width=\"{}pt\" height=\"{}pt\" viewBox=\"{} {} {} {}\">{}</svg>",
+            num(self.size.0),
+            num(self.size.1),
+            num(x),
+            num(y),
+            num(width),
+            num(height),
+            self.elements
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArcClose {
+    Open,
+    Chord,
+    Pie,
+}
+
+fn push_points(d: &mut String, points: &[(f32, f32)], bezier: bool) {
+    for (i, (x, y)) in points.iter().enumerate() {
+        let command = match bezier {
+            true if i % 3 == 0 => "C",
+            true => "",
+            false => "L",
+        };
+        let _ = write!(d, "{}{} {} ", command, num(*x), num(*y));
+    }
+}
+
+// ============================================================================
+// EMF
+// ============================================================================
+
+impl Player {
+    fn play_emf(&mut self, data: &[u8], start: usize) {
+        let mut offset = start;
+        while let (Some(kind), Some(size)) = (u32_at(data, offset), u32_at(data, offset + 4)) {
+            let size = size as usize;
+            let Some(record) = data.get(offset..offset + size).filter(|_| size >= 8) else {
+                break;
+            };
+            if kind == 14 {
+                break;
+            }
+            self.emf_record(kind, record);
+            offset += size;
+        }
+    }
+
+    fn emf_record(&mut self, kind: u32, r: &[u8]) -> Option<()> {
+        let point = |offset: usize| Some((i32_at(r, offset)? as f32, i32_at(r, offset + 4)? as f32));
+        let rect = |offset: usize| {
+            Some((i32_at(r, offset)? as f32, i32_at(r, offset + 4)? as f32, i32_at(r, offset + 8)? as f32, i32_at(r, offset + 12)? as f32))
+        };
+        match kind {
+            // EMR_POLYBEZIER, POLYGON, POLYLINE, POLYBEZIERTO, POLYLINETO and their 16-bit forms
+            2..=6 | 85..=89 => {
+                let points = emf_points(r, 28, u32_at(r, 24)? as usize, kind >= 85)?;
+                match kind {
+                    2 | 85 => self.poly(&points, false, true),
+                    3 | 86 => self.poly(&points, true, false),
+                    4 | 87 => self.poly(&points, false, false),
+                    5 | 88 => self.draw_to(&points, true),
+                    _ => self.draw_to(&points, false),
+                }
+            }
+            // EMR_POLYPOLYLINE, POLYPOLYGON and their 16-bit forms
+            7 | 8 | 90 | 91 => {
+                let polygons = u32_at(r, 24)? as usize;
+                let counts: Vec<usize> = (0..polygons).map(|i| u32_at(r, 32 + i * 4).map(|n| n as usize)).collect::<Option<_>>()?;
+                let points = emf_points(r, 32 + polygons * 4, counts.iter().sum(), kind >= 90)?;
+                let closed = matches!(kind, 8 | 91);
+                let mut d = String::new();
+                let mut from = 0;
+                for count in counts {
+                    if let Some((first, rest)) = points[from..from + count].split_first() {
+                        let _ = write!(d, "M{} {} ", num(first.0), num(first.1));
+                        push_points(&mut d, rest, false);
+                        if closed {
+                            d.push_str("Z ");
+                        }
+                    }
+                    from += count;
+                }
+                match closed {
+                    true => self.shape(&d, true),
+                    false if self.in_path => self.path.push_str(&d),
+                    false => self.draw(&d, false, true),
+                }
+            }
+            9 => self.dc.window_extent = Some(point(8)?),
+            10 => self.dc.window_origin = point(8)?,
+            11 => self.dc.viewport_extent = point(8)?,
+            12 => self.dc.viewport_origin = point(8)?,
+            17 => self.dc.map_mode = u32_at(r, 8)?,
+            19 => self.dc.even_odd = u32_at(r, 8)? == 1,
+            22 => self.dc.text_align = u32_at(r, 8)?,
+            24 => self.dc.text_color = Rgb(u32_at(r, 8)?),
+            27 => self.move_to(point(8)?),
+            33 => self.saved.push(self.dc.clone()),
+            34 => self.restore(i32_at(r, 8)?),
+            35 => self.dc.world = xform(r, 8)?,
+            36 => {
+                let matrix = xform(r, 8)?;
+                self.dc.world = match u32_at(r, 32)? {
+                    1 => IDENTITY,
+                    2 => multiply(matrix, self.dc.world),
+                    3 => multiply(self.dc.world, matrix),
+                    4 => matrix,
+                    _ => return None,
+                };
+            }
+            37 => self.select_object(u32_at(r, 8)?),
+            // EMR_CREATEPEN
+            38 => {
+                let style = u32_at(r, 12)?;
+                let pen = Pen {
+                    color: Some(Rgb(u32_at(r, 24)?)).filter(|_| style & 0xF != 5),
+                    width: i32_at(r, 16)? as f32,
+                    style,
+                };
+                self.set_object(u32_at(r, 8)?, GdiObject::Pen(pen));
+            }
+            // EMR_CREATEBRUSHINDIRECT
+            39 => {
+                let brush = Some(Rgb(u32_at(r, 16)?)).filter(|_| matches!(u32_at(r, 12), Some(0 | 2)));
+                self.set_object(u32_at(r, 8)?, GdiObject::Brush(brush));
+            }
+            40 => self.delete_object(u32_at(r, 8)?),
+            42 => self.ellipse(rect(8)?),
+            43 => self.rectangle(rect(8)?, (0.0, 0.0)),
+            44 => self.rectangle(rect(8)?, point(24)?),
+            45..=47 => {
+                let close = [ArcClose::Open, ArcClose::Chord, ArcClose::Pie][kind as usize - 45];
+                self.arc(rect(8)?, point(24)?, point(32)?, close);
+            }
+            54 => {
+                let to = point(8)?;
+                self.draw_to(&[to], false);
+            }
+            // Path brackets
+            59 => {
+                self.in_path = true;
+                self.path.clear();
+            }
+            60 => self.in_path = false,
+            61 if self.in_path => self.path.push_str("Z "),
+            62..=64 => {
+                let path = std::mem::take(&mut self.path);
+                self.in_path = false;
+                self.draw(&path, kind != 64, kind != 62);
+            }
+            68 => {
+                self.in_path = false;
+                self.path.clear();
+            }
+            // EMR_BITBLT, EMR_STRETCHBLT
+            76 | 77 => {
+                let dest = rect(24)?;
+                let (info, bits) = emf_bitmap(r, 84)?;
+                match info.is_empty() {
+                    // Without a source it fills with the brush
+                    true if u32_at(r, 40)? == 0x00F0_0021 => {
+                        let (x, y, w, h) = dest;
+                        let d = format!("M{} {} h{} v{} h{} Z", num(x), num(y), num(w), num(h), num(-w));
+                        self.draw(&d, true, false);
+                    }
+                    true => {}
+                    false => self.bitmap(dest, info, bits),
+                }
+            }
+            // EMR_STRETCHDIBITS
+            81 => {
+                let (x, y) = point(24)?;
+                let (width, height) = point(72)?;
+                let (info, bits) = emf_bitmap(r, 48)?;
+                self.bitmap((x, y, width, height), info, bits);
+            }
+            // EMR_EXTCREATEFONTINDIRECTW
+            82 => {
+                let face: Vec<u16> = (0..32).map_while(|i| u16_at(r, 40 + i * 2).filter(|c| *c != 0)).collect();
+                let font = Font {
+                    height: i32_at(r, 12)? as f32,
+                    face: String::from_utf16_lossy(&face),
+                    bold: i32_at(r, 28)? >= 600,
+                    italic: *r.get(32)? != 0,
+                };
+                self.set_object(u32_at(r, 8)?, GdiObject::Font(font));
+            }
+            // EMR_EXTTEXTOUTW
+            84 => {
+                let at = point(36)?;
+                let (chars, offset) = (u32_at(r, 44)? as usize, u32_at(r, 48)? as usize);
+                let text: Vec<u16> = (0..chars).map(|i| u16_at(r, offset + i * 2)).collect::<Option<_>>()?;
+                self.text(at, &String::from_utf16_lossy(&text));
+            }
+            // EMR_EXTCREATEPEN
+            95 => {
+                let style = u32_at(r, 28)?;
+                let solid = u32_at(r, 36)? == 0;
+                let pen = Pen {
+                    color: Some(Rgb(u32_at(r, 40)?)).filter(|_| solid && style & 0xF != 5),
+                    width: u32_at(r, 32)? as f32,
+                    style,
+                };
+                self.set_object(u32_at(r, 8)?, GdiObject::Pen(pen));
+            }
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+/// `count` points from `offset`, 32-bit or with `short` 16-bit
+fn emf_points(r: &[u8], offset: usize, count: usize, short: bool) -> Option<Vec<(f32, f32)>> {
+    (0..count)
+        .map(|i| match short {
+            true => Some((f32_i16(r, offset + i * 4), f32::from(i16_at(r, offset + i * 4 + 2)?))),
+            false => Some((i32_at(r, offset + i * 8)? as f32, i32_at(r, offset + i * 8 + 4)? as f32)),
+        })
+        .collect()
+}
+
+/// The bitmap header and pixels of a blit record, whose offBmi, cbBmi,
+/// offBits and cbBits are at `offset`
+fn emf_bitmap(r: &[u8], offset: usize) -> Option<(&[u8], &[u8])> {
+    let (info_at, info_size) = (u32_at(r, offset)? as usize, u32_at(r, offset + 4)? as usize);
+    let (bits_at, bits_size) = (u32_at(r, offset + 8)? as usize, u32_at(r, offset + 12)? as usize);
+    Some((r.get(info_at..info_at + info_size)?, r.get(bits_at..bits_at + bits_size)?))
+}
+
+fn xform(r: &[u8], offset: usize) -> Option<Matrix> {
+    let mut matrix = [0.0; 6];
+    for (i, value) in matrix.iter_mut().enumerate() {
+        *value = f32::from_le_bytes(r.get(offset + i * 4..offset + i * 4 + 4)?.try_into().ok()?);
+    }
+    Some(matrix)
+}
+
+// ============================================================================
+// WMF
+// ============================================================================
+
+const WMF_SETWINDOWORG: u16 = 0x020B;
+const WMF_SETWINDOWEXT: u16 = 0x020C;
+
+/// The function and bytes of each WMF record from `start`, up to META_EOF
+fn wmf_records(data: &[u8], start: usize) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut offset = start;
+    std::iter::from_fn(move || {
+        let words = u32_at(data, offset)? as usize;
+        let function = u16_at(data, offset + 4)?;
+        let record = data.get(offset..offset + words.checked_mul(2)?).filter(|_| words >= 3 && function != 0)?;
+        offset += words * 2;
+        Some((function, record))
+    })
+}
+
+impl Player {
+    fn play_wmf(&mut self, data: &[u8], start: usize) {
+        for (function, record) in wmf_records(data, start) {
+            self.wmf_record(function, record);
+        }
+    }
+
+    fn wmf_record(&mut self, function: u16, r: &[u8]) -> Option<()> {
+        let value = |offset: usize| Some(f32::from(i16_at(r, offset)?));
+        // Parameters are stored last first, so y before x and bottom right before top left
+        let point = |offset: usize| Some((value(offset + 2)?, value(offset)?));
+        let rect = |offset: usize| Some((value(offset + 6)?, value(offset + 4)?, value(offset + 2)?, value(offset)?));
+        match function {
+            WMF_SETWINDOWORG => self.dc.window_origin = point(6)?,
+            WMF_SETWINDOWEXT => self.dc.window_extent = Some(point(6)?),
+            0x0106 => self.dc.even_odd = u16_at(r, 6)? == 1,
+            0x012E => self.dc.text_align = u32::from(u16_at(r, 6)?),
+            0x0209 => self.dc.text_color = Rgb(u32_at(r, 6)?),
+            0x001E => self.saved.push(self.dc.clone()),
+            0x0127 => self.restore(i32::from(i16_at(r, 6)?)),
+            0x012D => self.select_object(u32::from(u16_at(r, 6)?)),
+            0x01F0 => self.delete_object(u32::from(u16_at(r, 6)?)),
+            // META_CREATEPENINDIRECT
+            0x02FA => {
+                let style = u32::from(u16_at(r, 6)?);
+                let pen = Pen {
+                    color: Some(Rgb(u32_at(r, 12)?)).filter(|_| style & 0xF != 5),
+                    width: value(8)?,
+                    style,
+                };
+                self.add_object(GdiObject::Pen(pen));
+            }
+            // META_CREATEBRUSHINDIRECT
+            0x02FC => {
+                let brush = Some(Rgb(u32_at(r, 8)?)).filter(|_| matches!(u16_at(r, 6), Some(0 | 2)));
+                self.add_object(GdiObject::Brush(brush));
+            }
+            // META_CREATEFONTINDIRECT, with its face in the system code page
+            0x02FB => {
+                let face: String = r.get(24..)?.iter().take(32).take_while(|c| **c != 0).map(|c| char::from(*c)).collect();
+                let font = Font {
+                    height: value(6)?,
+                    face,
+                    bold: i16_at(r, 14)? >= 600,
+                    italic: *r.get(16)? != 0,
+                };
+                self.add_object(GdiObject::Font(font));
+            }
+            // Pattern brushes fill nothing; palettes and regions only take a slot
+            0x0142 | 0x01F9 => self.add_object(GdiObject::Brush(None)),
+            0x00F7 | 0x06FF => self.add_object(GdiObject::Other),
+            0x0214 => self.move_to(point(6)?),
+            0x0213 => {
+                let to = point(6)?;
+                self.draw_to(&[to], false);
+            }
+            0x041B => self.rectangle(rect(6)?, (0.0, 0.0)),
+            0x061C => {
+                let corner = point(6)?;
+                self.rectangle(rect(10)?, corner);
+            }
+            0x0418 => self.ellipse(rect(6)?),
+            0x0817 | 0x0830 | 0x081A => {
+                let close = match function {
+                    0x0817 => ArcClose::Open,
+                    0x0830 => ArcClose::Chord,
+                    _ => ArcClose::Pie,
+                };
+                self.arc(rect(14)?, point(10)?, point(6)?, close);
+            }
+            0x0324 | 0x0325 => {
+                let points = wmf_points(r, 8, usize::from(u16_at(r, 6)?))?;
+                self.poly(&points, function == 0x0324, false);
+            }
+            // META_POLYPOLYGON
+            0x0538 => {
+                let polygons = usize::from(u16_at(r, 6)?);
+                let counts: Vec<usize> = (0..polygons).map(|i| u16_at(r, 8 + i * 2).map(usize::from)).collect::<Option<_>>()?;
+                let points = wmf_points(r, 8 + polygons * 2, counts.iter().sum())?;
+                let mut d = String::new();
+                let mut from = 0;
+                for count in counts {
+                    if let Some((first, rest)) = points[from..from + count].split_first() {
+                        let _ = write!(d, "M{} {} ", num(first.0), num(first.1));
+                        push_points(&mut d, rest, false);
+                        d.push_str("Z ");
+                    }
+                    from += count;
+                }
+                self.shape(&d, true);
+            }
+            // META_TEXTOUT
+            0x0521 => {
+                let length = usize::from(u16_at(r, 6)?);
+                let text = latin1(r.get(8..8 + length)?);
+                let at = point(8 + length.div_ceil(2) * 2)?;
+                self.text(at, &text);
+            }
+            // META_EXTTEXTOUT
+            0x0A32 => {
+                let length = usize::from(u16_at(r, 10)?);
+                // ETO_OPAQUE and ETO_CLIPPED come with a rectangle
+                let start = if u16_at(r, 12)? & 6 != 0 { 22 } else { 14 };
+                let text = latin1(r.get(start..start + length)?);
+                self.text(point(6)?, &text);
+            }
+            // META_DIBBITBLT, META_DIBSTRETCHBLT, META_STRETCHDIB
+            0x0940 | 0x0B41 | 0x0F43 => {
+                let (dest, dib) = match function {
+                    0x0940 => ((value(20)?, value(18)?, value(16)?, value(14)?), 22),
+                    0x0B41 => ((value(24)?, value(22)?, value(20)?, value(18)?), 26),
+                    _ => ((value(26)?, value(24)?, value(22)?, value(20)?), 28),
+                };
+                let dib = r.get(dib..)?;
+                let bits = dib_bits_offset(dib)?;
+                self.bitmap(dest, &dib[..bits], &dib[bits..]);
+            }
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+fn wmf_points(r: &[u8], offset: usize, count: usize) -> Option<Vec<(f32, f32)>> {
+    (0..count)
+        .map(|i| Some((f32::from(i16_at(r, offset + i * 4)?), f32::from(i16_at(r, offset + i * 4 + 2)?))))
+        .collect()
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|c| char::from(*c)).collect()
+}
+
+// ============================================================================
+// Bitmaps
+// ============================================================================
+
+/// Where a packed DIB's pixels start: after its header and color table
+///
+/// None when the header or color table claims more bytes than the DIB has.
+fn dib_bits_offset(dib: &[u8]) -> Option<usize> {
+    let header = u32_at(dib, 0)? as usize;
+    let offset = if header == 12 {
+        // BITMAPCOREHEADER, three bytes a color
+        let bits = u16_at(dib, 10)?;
+        let colors = if bits <= 8 { 1usize << bits } else { 0 };
+        12 + colors * 3
+    } else {
+        let bits = u16_at(dib, 14)?;
+        let compression = u32_at(dib, 16)?;
+        let used = u32_at(dib, 32)? as usize;
+        let colors = match used {
+            0 if bits <= 8 => 1usize << bits,
+            used => used,
+        };
+        // BI_BITFIELDS masks follow a plain BITMAPINFOHEADER
+        let masks = if header == 40 && compression == 3 { 12 } else { 0 };
+        header.checked_add(masks)?.checked_add(colors.checked_mul(4)?)?
+    };
+    (offset <= dib.len()).then_some(offset)
+}
+
+/// A DIB as PNG, by making it a BMP file for the decoder
+fn dib_to_png(info: &[u8], bits: &[u8]) -> Option<Vec<u8>> {
+    let offset = 14 + info.len();
+    let mut bmp = Vec::with_capacity(offset + bits.len());
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&u32::try_from(offset + bits.len()).ok()?.to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&u32::try_from(offset).ok()?.to_le_bytes());
+    bmp.extend_from_slice(info);
+    bmp.extend_from_slice(bits);
+    let decoded = ::image::load_from_memory_with_format(&bmp, ::image::ImageFormat::Bmp).ok()?;
+    let mut png = Vec::new();
+    decoded.write_to(&mut std::io::Cursor::new(&mut png), ::image::ImageFormat::Png).ok()?;
+    Some(png)
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn i16_at(data: &[u8], offset: usize) -> Option<i16> {
+    u16_at(data, offset).map(|value| value as i16)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn i32_at(data: &[u8], offset: usize) -> Option<i32> {
+    u32_at(data, offset).map(|value| value as i32)
+}
+
+/// A 16-bit value as f32, 0 if missing
+fn f32_i16(data: &[u8], offset: usize) -> f32 {
+    f32::from(i16_at(data, offset).unwrap_or(0))
+}
+
+/// A number with at most three decimals and no trailing zeros
+fn num(value: f32) -> String {
+    let text = format!("{:.3}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WMF record of `function` with 16-bit `params`
+    fn wmf_record(function: u16, params: &[i16]) -> Vec<u8> {
+        let mut record = ((3 + params.len()) as u32).to_le_bytes().to_vec();
+        record.extend_from_slice(&function.to_le_bytes());
+        for param in params {
+            record.extend_from_slice(&param.to_le_bytes());
+        }
+        record
+    }
+
+    fn wmf(records: &[Vec<u8>]) -> Vec<u8> {
+        // Placeable header: a 1000 x 500 unit frame at 1000 units an inch
+        let mut data = WMF_PLACEABLE_KEY.to_vec();
+        for value in [0i16, 0, 0, 1000, 500, 1000] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[0; 6]);
+        for value in [1u16, 9, 0x0300, 0, 0, 0, 0, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for record in records {
+            data.extend_from_slice(record);
+        }
+        data.extend_from_slice(&wmf_record(0, &[]));
+        data
+    }
+
+    /// An EMF record of `kind` with 32-bit `params`
+    fn emf_record(kind: u32, params: &[i32]) -> Vec<u8> {
+        let mut record = kind.to_le_bytes().to_vec();
+        record.extend_from_slice(&(8 + params.len() as u32 * 4).to_le_bytes());
+        for param in params {
+            record.extend_from_slice(&param.to_le_bytes());
+        }
+        record
+    }
+
+    fn emf(records: &[Vec<u8>]) -> Vec<u8> {
+        // Bounds, a 2 x 1 inch frame, and a 96 dpi device
+        let mut header = vec![0, 0, 191, 95, 0, 0, 5080, 2540];
+        header.push(i32::from_le_bytes(*EMF_SIGNATURE));
+        header.extend_from_slice(&[0x10000, 0, 0, 0, 0, 0, 0, 960, 480, 254, 127]);
+        let mut data = emf_record(1, &header);
+        for record in records {
+            data.extend_from_slice(record);
+        }
+        data.extend_from_slice(&emf_record(14, &[0, 0, 0]));
+        data
+    }
+
+    #[test]
+    fn test_wmf_to_svg() {
+        let data = wmf(&[
+            // A red brush and a blue pen 10 units wide
+            wmf_record(0x02FC, &[0, 0x00FF, 0, 0]),
+            wmf_record(0x02FA, &[0, 10, 0, 0, 0x00FF]),
+            wmf_record(0x012D, &[0]),
+            wmf_record(0x012D, &[1]),
+            wmf_record(0x041B, &[400, 900, 100, 100]),
+            wmf_record(0x0324, &[3, 0, 0, 100, 0, 50, 80]),
+        ]);
+        assert!(is_wmf(&data) && !is_emf(&data));
+        assert_eq!(metafile_size(&data).unwrap(), Size::new(72.0, 36.0));
+
+        let svg = metafile_to_svg(&data).unwrap();
+        assert!(svg.contains("viewBox=\"0 0 1000 500\""), "{}", svg);
+        assert!(svg.contains(
+            "<path d=\"M100 100 H900 V400 H100 Z\" fill=\"#ff0000\" fill-rule=\"evenodd\" stroke=\"#0000ff\" stroke-width=\"10\"/>"
+        ));
+        assert!(svg.contains("d=\"M0 0 L100 0 L50 80 Z\""));
+
+        // Rasterized by way of the SVG
+        let image = crate::image::ImageData::decode(data).unwrap();
+        let pixels = image.decode_pixels(Some((100, 100))).unwrap();
+        assert_eq!((pixels.width, pixels.height), (100, 50));
+        assert_eq!(pixels.rgba[(25 * 100 + 50) * 4..][..4], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_emf_to_svg() {
+        let mut text = emf_record(84, &[0, 0, 0, 0, 1, 0, 0, 10, 20, 2, 76, 0, 0, 0, 0, 0, 0]);
+        text.extend_from_slice(&[b'H', 0, b'i', 0]);
+        let size = text.len() as u32;
+        text[4..8].copy_from_slice(&size.to_le_bytes());
+        let data = emf(&[
+            // Anisotropic mapping of a 200 x 100 window onto the 192 x 96 pixel picture
+            emf_record(17, &[8]),
+            emf_record(9, &[200, 100]),
+            emf_record(11, &[192, 96]),
+            emf_record(37, &[0x8000_0005u32 as i32]),
+            emf_record(59, &[]),
+            emf_record(27, &[0, 0]),
+            emf_record(54, &[200, 100]),
+            emf_record(60, &[]),
+            emf_record(64, &[0, 0, 0, 0]),
+            emf_record(42, &[0, 0, 200, 100]),
+            text,
+        ]);
+        assert!(is_emf(&data));
+        assert_eq!(metafile_size(&data).unwrap(), Size::new(144.0, 72.0));
+
+        let svg = metafile_to_svg(&data).unwrap();
+        assert!(svg.contains("width=\"144pt\" height=\"72pt\" viewBox=\"0 0 192 96\""), "{}", svg);
+        // The stroked path, and the ellipse left unfilled by the null brush
+        assert!(svg.contains("<path d=\"M0 0 L200 100\" fill=\"none\" stroke=\"#000000\" stroke-width=\"1.042\" transform=\"matrix(0.96 0 0 0.96 0 0)\"/>"));
+        assert!(svg.contains("<path d=\"M0 50 A100 50 0 1 0 200 50 A100 50 0 1 0 0 50 Z\" fill=\"none\""));
+        assert!(svg.contains(">Hi</text>"));
+
+        assert_eq!(metafile_to_svg(b"not a metafile"), Err(MetafileError::NotAMetafile));
+    }
+
+    #[test]
+    fn test_embedded_bitmap() {
+        // A 2 x 1 24-bit DIB, bottom-up: a red then a green pixel, padded to 4 bytes
+        let mut dib = Vec::new();
+        for value in [40u32, 2, 1] {
+            dib.extend_from_slice(&value.to_le_bytes());
+        }
+        dib.extend_from_slice(&1u16.to_le_bytes());
+        dib.extend_from_slice(&24u16.to_le_bytes());
+        dib.extend_from_slice(&[0; 24]);
+        dib.extend_from_slice(&[0, 0, 255, 0, 255, 0, 0, 0]);
+
+        let mut params = vec![0x0020, 0x00CC, 0, 1, 2, 0, 0, 500, 1000, 0, 0];
+        params.extend(dib.chunks(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])));
+        let data = wmf(&[wmf_record(0x0F43, &params)]);
+        let svg = metafile_to_svg(&data).unwrap();
+        assert!(svg.contains("<image x=\"0\" y=\"0\" width=\"1000\" height=\"500\" preserveAspectRatio=\"none\" xlink:href=\"data:image/png;base64,"));
+        assert_eq!(dib_bits_offset(&dib), Some(40));
+
+        // A color table longer than the record leaves the picture out
+        dib[32..36].copy_from_slice(&1000u32.to_le_bytes());
+        assert_eq!(dib_bits_offset(&dib), None);
+        params.truncate(11);
+        params.extend(dib.chunks(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])));
+        let svg = metafile_to_svg(&wmf(&[wmf_record(0x0F43, &params)])).unwrap();
+        assert!(!svg.contains("<image"));
+    }
+}
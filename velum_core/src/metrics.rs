@@ -0,0 +1,205 @@
+//! # Metrics Module
+//!
+//! Pluggable telemetry sink for production performance monitoring.
+//!
+//! Hosts implement [`Metrics`] and install it with [`install`]. The crate then
+//! reports counters and histograms at key points (document open/save, layout).
+//! With no sink installed every probe is a single relaxed atomic load: no clock
+//! reads, no allocation, no locking.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use once_cell::sync::Lazy;
+
+/// Time to parse a .docx package, in milliseconds
+pub const DOCUMENT_OPEN_MS: &str = "document.open_ms";
+/// Time to serialize a .docx package, in milliseconds
+pub const DOCUMENT_SAVE_MS: &str = "document.save_ms";
+/// Documents opened
+pub const DOCUMENTS_OPENED: &str = "document.opened";
+/// Documents saved
+pub const DOCUMENTS_SAVED: &str = "document.saved";
+/// Pagination time divided by the pages produced, in milliseconds
+pub const LAYOUT_PAGE_MS: &str = "layout.page_ms";
+/// Pages laid out
+pub const PAGES_LAID_OUT: &str = "layout.pages";
+
+/// Session lifecycle markers; a session started without a clean end did not finish crash-free
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionMarker {
+    Started,
+    EndedCleanly,
+}
+
+/// Receiver for the crate's metrics, implemented by the host
+pub trait Metrics: Send + Sync {
+    /// Add `value` to a monotonically increasing counter
+    fn counter(&self, name: &str, value: u64);
+
+    /// Record one observation of a distribution
+    fn histogram(&self, name: &str, value: f64);
+
+    /// Record a session lifecycle marker
+    fn session(&self, _marker: SessionMarker) {}
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SINK: Lazy<RwLock<Option<Arc<dyn Metrics>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Install the metrics sink, replacing any previous one
+pub fn install(sink: Arc<dyn Metrics>) {
+    *SINK.write().unwrap() = Some(sink);
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Remove the metrics sink
+pub fn uninstall() {
+    ENABLED.store(false, Ordering::Release);
+    *SINK.write().unwrap() = None;
+}
+
+/// Check whether a sink is installed
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn with_sink(f: impl FnOnce(&dyn Metrics)) {
+    if let Some(sink) = SINK.read().unwrap().as_ref() {
+        f(sink.as_ref());
+    }
+}
+
+/// Increment a counter
+#[inline]
+pub fn counter(name: &str, value: u64) {
+    if is_enabled() {
+        with_sink(|sink| sink.counter(name, value));
+    }
+}
+
+/// Record a histogram observation
+#[inline]
+pub fn histogram(name: &str, value: f64) {
+    if is_enabled() {
+        with_sink(|sink| sink.histogram(name, value));
+    }
+}
+
+/// Record a session marker
+pub fn session(marker: SessionMarker) {
+    if is_enabled() {
+        with_sink(|sink| sink.session(marker));
+    }
+}
+
+/// Measures elapsed time from creation; only reads the clock when a sink is installed
+pub struct Timer {
+    start: Option<Instant>,
+}
+
+impl Timer {
+    /// Start timing
+    #[inline]
+    pub fn start() -> Self {
+        Timer {
+            start: is_enabled().then(Instant::now),
+        }
+    }
+
+    /// Elapsed milliseconds, or None when metrics are disabled
+    pub fn elapsed_ms(&self) -> Option<f64> {
+        self.start.map(|start| start.elapsed().as_secs_f64() * 1000.0)
+    }
+
+    /// Record the elapsed milliseconds into a histogram
+    pub fn record(self, name: &str) {
+        if let Some(ms) = self.elapsed_ms() {
+            histogram(name, ms);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Sink that keeps everything it receives
+    #[derive(Default)]
+    struct RecordingSink {
+        counters: Mutex<Vec<(String, u64)>>,
+        histograms: Mutex<Vec<(String, f64)>>,
+        sessions: Mutex<Vec<SessionMarker>>,
+    }
+
+    impl Metrics for RecordingSink {
+        fn counter(&self, name: &str, value: u64) {
+            self.counters.lock().unwrap().push((name.to_string(), value));
+        }
+
+        fn histogram(&self, name: &str, value: f64) {
+            self.histograms.lock().unwrap().push((name.to_string(), value));
+        }
+
+        fn session(&self, marker: SessionMarker) {
+            self.sessions.lock().unwrap().push(marker);
+        }
+    }
+
+    /// The sink is global; tests that install one run one at a time
+    static SINK_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_no_sink_is_noop() {
+        let _guard = SINK_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        uninstall();
+        assert!(!is_enabled());
+        counter("x", 1);
+        assert!(Timer::start().elapsed_ms().is_none());
+    }
+
+    #[test]
+    fn test_sink_receives_metrics() {
+        let _guard = SINK_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let sink = Arc::new(RecordingSink::default());
+        install(sink.clone());
+
+        // Other tests may emit real metrics concurrently; use private names
+        counter("test.counter", 1);
+        histogram("test.histogram", 12.5);
+        session(SessionMarker::Started);
+        Timer::start().record("test.timer");
+        uninstall();
+        counter("test.counter", 1);
+
+        let counters = sink.counters.lock().unwrap();
+        let ours: Vec<_> = counters.iter().filter(|(name, _)| name == "test.counter").collect();
+        assert_eq!(ours, vec![&("test.counter".to_string(), 1)]);
+        let histograms = sink.histograms.lock().unwrap();
+        assert!(histograms.contains(&("test.histogram".to_string(), 12.5)));
+        assert!(histograms.iter().any(|(name, _)| name == "test.timer"));
+        assert_eq!(*sink.sessions.lock().unwrap(), vec![SessionMarker::Started]);
+    }
+
+    #[test]
+    fn test_layout_emits_per_page_time() {
+        use crate::line_layout::LineLayout;
+        use crate::page_layout::PageLayout;
+
+        let _guard = SINK_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let sink = Arc::new(RecordingSink::default());
+        install(sink.clone());
+
+        let text = "Paragraph of text.\n".repeat(200);
+        let layout = LineLayout::new().layout_document(&text, 400.0);
+        let pages = PageLayout::new().layout_pages(&layout.paragraphs);
+        uninstall();
+
+        let counters = sink.counters.lock().unwrap();
+        assert!(counters.contains(&(PAGES_LAID_OUT.to_string(), pages.len() as u64)));
+        assert!(sink.histograms.lock().unwrap().iter().any(|(name, _)| name == LAYOUT_PAGE_MS));
+    }
+}
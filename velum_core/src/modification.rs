@@ -0,0 +1,466 @@
+//! # Modification Module
+//!
+//! Dirty-state tracking for the document session.
+//!
+//! The tracker compares a hash of the current document state against the hash
+//! taken at the last save, instead of counting edits. Undoing back to the saved
+//! state, or typing a character and deleting it again, leaves the document
+//! clean. Listeners are told whenever the dirty state flips.
+//!
+//! State that is not plain text is hashed with [`hash_serialized`], which
+//! feeds each value to the hasher as serde walks it rather than formatting it
+//! as JSON first.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use serde::ser::{self, Serialize};
+
+/// Tracks whether the document differs from what was last saved
+#[derive(Default)]
+pub struct ModificationTracker {
+    saved_hash: u64,
+    dirty: bool,
+    listeners: Vec<Box<dyn Fn(bool) + Send + Sync>>,
+}
+
+impl ModificationTracker {
+    /// Create a clean tracker for a document whose state hashes to `saved_hash`
+    pub fn new(saved_hash: u64) -> Self {
+        ModificationTracker {
+            saved_hash,
+            ..Default::default()
+        }
+    }
+
+    /// Check whether the document has unsaved changes
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Hash of the document state at the last save
+    pub fn saved_hash(&self) -> u64 {
+        self.saved_hash
+    }
+
+    /// Register a callback receiving the new dirty state each time it changes
+    pub fn on_dirty_changed<F>(&mut self, callback: F)
+    where
+        F: Fn(bool) + Send + Sync + 'static,
+    {
+        self.listeners.push(Box::new(callback));
+    }
+
+    /// Report that the document changed and now hashes to `hash`
+    ///
+    /// Returns the dirty state.
+    pub fn content_changed(&mut self, hash: u64) -> bool {
+        self.set_dirty(hash != self.saved_hash);
+        self.dirty
+    }
+
+    /// Record that the document was saved (or loaded) in the state hashing to `hash`
+    pub fn mark_saved(&mut self, hash: u64) {
+        self.saved_hash = hash;
+        self.set_dirty(false);
+    }
+
+    fn set_dirty(&mut self, dirty: bool) {
+        if self.dirty != dirty {
+            self.dirty = dirty;
+            for listener in &self.listeners {
+                listener(dirty);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ModificationTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModificationTracker")
+            .field("saved_hash", &self.saved_hash)
+            .field("dirty", &self.dirty)
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+/// Hash `value` into `hasher` field by field
+///
+/// Floats are hashed by their bits and map entries in any order, so a map
+/// hashes the same whatever order it iterates in.
+pub(crate) fn hash_serialized<T: Serialize + ?Sized, H: Hasher>(value: &T, hasher: &mut H) {
+    // Hashing never fails; only a value's own `Serialize` can, and then it is skipped
+    let _ = value.serialize(&mut ValueHasher(hasher));
+}
+
+struct ValueHasher<'a, H>(&'a mut H);
+
+/// A sequence, tuple or struct being hashed, counting its elements
+struct Compound<'b, 'a, H> {
+    inner: &'b mut ValueHasher<'a, H>,
+    len: usize,
+}
+
+/// A map being hashed as the sum of the hashes of its entries
+struct MapHasher<'b, 'a, H> {
+    inner: &'b mut ValueHasher<'a, H>,
+    entry: Option<DefaultHasher>,
+    sum: u64,
+    len: usize,
+}
+
+impl<'b, 'a, H: Hasher> ser::Serializer for &'b mut ValueHasher<'a, H> {
+    type Ok = ();
+    type Error = fmt::Error;
+    type SerializeSeq = Compound<'b, 'a, H>;
+    type SerializeTuple = Compound<'b, 'a, H>;
+    type SerializeTupleStruct = Compound<'b, 'a, H>;
+    type SerializeTupleVariant = Compound<'b, 'a, H>;
+    type SerializeMap = MapHasher<'b, 'a, H>;
+    type SerializeStruct = Compound<'b, 'a, H>;
+    type SerializeStructVariant = Compound<'b, 'a, H>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), fmt::Error> {
+        v.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), fmt::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), fmt::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), fmt::Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), fmt::Error> {
+        v.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<(), fmt::Error> {
+        v.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), fmt::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), fmt::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), fmt::Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), fmt::Error> {
+        v.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), fmt::Error> {
+        v.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), fmt::Error> {
+        v.to_bits().hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), fmt::Error> {
+        v.to_bits().hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), fmt::Error> {
+        v.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), fmt::Error> {
+        v.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), fmt::Error> {
+        v.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), fmt::Error> {
+        0u8.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), fmt::Error> {
+        1u8.hash(self.0);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, index: u32, _variant: &'static str) -> Result<(), fmt::Error> {
+        index.hash(self.0);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), fmt::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), fmt::Error> {
+        index.hash(self.0);
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, fmt::Error> {
+        Ok(Compound { inner: self, len: 0 })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, fmt::Error> {
+        Ok(Compound { inner: self, len: 0 })
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, fmt::Error> {
+        Ok(Compound { inner: self, len: 0 })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, fmt::Error> {
+        index.hash(self.0);
+        Ok(Compound { inner: self, len: 0 })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, fmt::Error> {
+        Ok(MapHasher { inner: self, entry: None, sum: 0, len: 0 })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, fmt::Error> {
+        Ok(Compound { inner: self, len: 0 })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, fmt::Error> {
+        index.hash(self.0);
+        Ok(Compound { inner: self, len: 0 })
+    }
+}
+
+impl<H: Hasher> Compound<'_, '_, H> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        self.len += 1;
+        value.serialize(&mut *self.inner)
+    }
+
+    /// Fields left out, e.g. by `skip_serializing_if`, are told apart by the names of those written
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), fmt::Error> {
+        key.hash(self.inner.0);
+        value.serialize(&mut *self.inner)
+    }
+
+    fn finish(self) -> Result<(), fmt::Error> {
+        self.len.hash(self.inner.0);
+        Ok(())
+    }
+}
+
+impl<H: Hasher> ser::SerializeSeq for Compound<'_, '_, H> {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        self.finish()
+    }
+}
+
+impl<H: Hasher> ser::SerializeTuple for Compound<'_, '_, H> {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        self.finish()
+    }
+}
+
+impl<H: Hasher> ser::SerializeTupleStruct for Compound<'_, '_, H> {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        self.finish()
+    }
+}
+
+impl<H: Hasher> ser::SerializeTupleVariant for Compound<'_, '_, H> {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        self.finish()
+    }
+}
+
+impl<H: Hasher> ser::SerializeStruct for Compound<'_, '_, H> {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), fmt::Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        self.finish()
+    }
+}
+
+impl<H: Hasher> ser::SerializeStructVariant for Compound<'_, '_, H> {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), fmt::Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        self.finish()
+    }
+}
+
+impl<H: Hasher> ser::SerializeMap for MapHasher<'_, '_, H> {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), fmt::Error> {
+        let mut entry = DefaultHasher::new();
+        hash_serialized(key, &mut entry);
+        self.entry = Some(entry);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        let mut entry = self.entry.take().unwrap_or_default();
+        hash_serialized(value, &mut entry);
+        self.sum = self.sum.wrapping_add(entry.finish());
+        self.len += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        self.sum.hash(self.inner.0);
+        self.len.hash(self.inner.0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn recording_tracker(hash: u64) -> (ModificationTracker, Arc<Mutex<Vec<bool>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let mut tracker = ModificationTracker::new(hash);
+        tracker.on_dirty_changed(move |dirty| sink.lock().unwrap().push(dirty));
+        (tracker, events)
+    }
+
+    #[test]
+    fn test_returning_to_saved_state_clears_dirty() {
+        let (mut tracker, events) = recording_tracker(1);
+        assert!(!tracker.is_dirty());
+
+        assert!(tracker.content_changed(2));
+        assert!(tracker.content_changed(3));
+        // e.g. undo back to the saved state
+        assert!(!tracker.content_changed(1));
+
+        assert_eq!(*events.lock().unwrap(), vec![true, false]);
+    }
+
+    #[test]
+    fn test_mark_saved_moves_the_baseline() {
+        let (mut tracker, events) = recording_tracker(1);
+        tracker.content_changed(2);
+        tracker.mark_saved(2);
+        assert!(!tracker.is_dirty());
+        assert_eq!(tracker.saved_hash(), 2);
+
+        // The old saved state is now a modification
+        assert!(tracker.content_changed(1));
+        assert_eq!(*events.lock().unwrap(), vec![true, false, true]);
+    }
+
+    fn hash_of<T: Serialize + ?Sized>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hash_serialized(value, &mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_hash_serialized() {
+        assert_eq!(hash_of(&("a", 1.5f32, Some(2))), hash_of(&("a", 1.5f32, Some(2))));
+        assert_ne!(hash_of(&("a", "bc")), hash_of(&("ab", "c")));
+        assert_ne!(hash_of(&vec![vec![1], vec![]]), hash_of(&vec![vec![], vec![1]]));
+        assert_ne!(hash_of(&Some(0u8)), hash_of(&None::<u8>));
+
+        let map: std::collections::HashMap<String, u32> = (0..50).map(|i| (i.to_string(), i)).collect();
+        let reversed: std::collections::HashMap<String, u32> = (0..50).rev().map(|i| (i.to_string(), i)).collect();
+        assert_eq!(hash_of(&map), hash_of(&reversed));
+    }
+
+    #[test]
+    fn test_no_event_without_state_change() {
+        let (mut tracker, events) = recording_tracker(1);
+        tracker.content_changed(1);
+        tracker.mark_saved(1);
+        assert!(events.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,904 @@
+//! # Numbering Module
+//!
+//! Multilevel list numbering: the labels ("1.1", "Section 1.02", "(iii)") list
+//! paragraphs get from numbering.xml definitions, and outline numbering linked
+//! to the heading styles.
+//!
+//! A paragraph is in a list when its effective formatting, direct or through
+//! its style, has a list ID; its list level picks the level definition. Labels
+//! are computed from all paragraphs in order on every call, so inserting,
+//! deleting, moving or restyling a paragraph renumbers the ones after it.
+//!
+//! A [`NumberingEngine`] does the counting: counters belong to the abstract
+//! definition, so lists sharing one continue each other's numbers as in Word,
+//! while a list overriding a start value counts on its own. A level restarts
+//! after any item of a higher level unless its w:lvlRestart says otherwise.
+//!
+//! List editing works on the paragraphs' direct formatting: turning a bullet
+//! or numbered list on or off, changing list levels, and restarting a list or
+//! continuing the one before. Restarting gives the paragraphs a new list with
+//! start overrides; definitions are only ever added, so undoing the paragraph
+//! changes leaves nothing inconsistent.
+//!
+//! Outline numbering puts the numbering into Heading 1–9 themselves, as Word
+//! does: each heading style refers to the list and a level of it, and each
+//! level names its heading style, so applying a heading style numbers the
+//! paragraph and the link survives a round trip through styles.xml and
+//! numbering.xml.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::line_layout;
+use crate::ooxml::{AbstractNumDef, LevelOverride, ListLevel, NumInstance, Numbering, ParagraphProperties, RunProperties};
+use crate::piece_tree::ParagraphAttributes;
+use crate::style_sheet::{NamedStyle, StyleKind, StyleSheet};
+
+/// Levels of a multilevel list
+pub const LEVEL_COUNT: usize = 9;
+
+/// List ID that takes a paragraph out of the list its style puts it in
+const NO_LIST: &str = "0";
+
+/// Bullets and numbers of the lists list editing creates, by level
+const BULLETS: [&str; 3] = ["•", "◦", "▪"];
+const NUMBER_FORMATS: [&str; 3] = ["decimal", "lowerLetter", "lowerRoman"];
+
+/// Indent of each list level, and the hang its label sits in, in twips
+const LEVEL_INDENT: i32 = 720;
+const LABEL_HANG: i32 = 360;
+
+/// List editing errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ListError {
+    #[error("The paragraphs are not in a list")]
+    NotInList,
+
+    #[error("There is no earlier list of this kind to continue")]
+    NothingToContinue,
+}
+
+/// Kinds of list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListKind {
+    Bullet,
+    Numbered,
+}
+
+impl ListKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "bullet" | "bullets" => Some(ListKind::Bullet),
+            "numbered" | "number" | "numbers" => Some(ListKind::Numbered),
+            _ => None,
+        }
+    }
+}
+
+/// A list editing operation on a run of paragraphs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListEdit {
+    /// Take the paragraphs out of the list if they are all in a list of the
+    /// kind, otherwise make them one
+    Toggle(ListKind),
+    /// Make the paragraphs a list of the kind, keeping their levels
+    Convert(ListKind),
+    /// One level deeper
+    Indent,
+    /// One level shallower
+    Outdent,
+    /// Number the list from its start again, from the first paragraph on
+    Restart,
+    /// Join the list of the same kind before, numbering on from it
+    Continue,
+}
+
+impl ListEdit {
+    /// Parses "bullet"/"numbered" (toggle), "to_bullet"/"to_numbered"
+    /// (convert), "indent", "outdent", "restart" or "continue"
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "indent" | "increase" => Some(ListEdit::Indent),
+            "outdent" | "decrease" => Some(ListEdit::Outdent),
+            "restart" => Some(ListEdit::Restart),
+            "continue" => Some(ListEdit::Continue),
+            _ => match name.strip_prefix("to_") {
+                Some(kind) => ListKind::from_name(kind).map(ListEdit::Convert),
+                None => ListKind::from_name(&name).map(ListEdit::Toggle),
+            },
+        }
+    }
+}
+
+/// Numbering schemes for the heading outline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlineScheme {
+    /// "1", "1.1", "1.1.1", ...
+    Decimal,
+    /// "Article I.", "Section 1.01", "(a)", "(i)", ...; sections use legal
+    /// numbering, so the article number shows in decimal inside them
+    Legal,
+}
+
+impl OutlineScheme {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "decimal" | "outline" => Some(OutlineScheme::Decimal),
+            "legal" => Some(OutlineScheme::Legal),
+            _ => None,
+        }
+    }
+
+    /// Format, level text and legal flag of `level`, from 0
+    fn level(self, level: usize) -> (&'static str, String, bool) {
+        match self {
+            OutlineScheme::Decimal => {
+                let text = (1..=level + 1).map(|n| format!("%{}", n)).collect::<Vec<_>>().join(".");
+                ("decimal", text, false)
+            }
+            OutlineScheme::Legal => {
+                let n = level + 1;
+                match level {
+                    0 => ("upperRoman", "Article %1.".to_string(), false),
+                    1 => ("decimalZero", "Section %1.%2".to_string(), true),
+                    2 | 5 => ("lowerLetter", format!("({})", placeholder(n)), false),
+                    3 | 6 => ("lowerRoman", format!("({})", placeholder(n)), false),
+                    _ => ("decimal", format!("{}.", placeholder(n)), false),
+                }
+            }
+        }
+    }
+}
+
+fn placeholder(level_number: usize) -> String {
+    format!("%{}", level_number)
+}
+
+/// ID of the style for heading `level`, from 1
+fn heading_style_id(level: usize) -> String {
+    format!("Heading{}", level)
+}
+
+/// What separates a list label from the paragraph text (w:suff)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelSuffix {
+    Tab,
+    Space,
+    Nothing,
+}
+
+impl LabelSuffix {
+    fn from_ooxml(value: Option<&str>) -> Self {
+        match value {
+            Some("space") => LabelSuffix::Space,
+            Some("nothing") => LabelSuffix::Nothing,
+            _ => LabelSuffix::Tab,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LabelSuffix::Tab => "\t",
+            LabelSuffix::Space => " ",
+            LabelSuffix::Nothing => "",
+        }
+    }
+}
+
+/// The rendered label of a list paragraph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListLabel {
+    pub num_id: String,
+    pub level: u8,
+    /// "1.2.3", "a)", "Section 1.01" or a bullet such as "•"
+    pub text: String,
+    pub suffix: LabelSuffix,
+    pub is_bullet: bool,
+    /// Indents the level gives its paragraphs, in twips
+    pub indent_left: Option<i32>,
+    pub indent_first_line: Option<i32>,
+    /// Formatting of the label itself, e.g. the bullet's font
+    pub run_properties: RunProperties,
+}
+
+impl ListLabel {
+    /// The label with its suffix, as it precedes the paragraph text
+    pub fn prefix(&self) -> String {
+        format!("{}{}", self.text, self.suffix.as_str())
+    }
+
+    /// Layout properties of the paragraph with this label, from its effective formatting
+    ///
+    /// The level's indents apply where the paragraph sets none. A label in a
+    /// hanging indent is drawn in the hang and tabs to the left indent, so the
+    /// paragraph text starts there on every line.
+    pub fn layout_properties(&self, paragraph: &ParagraphAttributes) -> line_layout::ParagraphProperties {
+        let attributes = ParagraphAttributes {
+            indent_left: paragraph.indent_left.or(self.indent_left),
+            indent_first_line: paragraph.indent_first_line.or(self.indent_first_line),
+            ..paragraph.clone()
+        };
+        let mut properties = attributes.layout_properties();
+        if self.suffix == LabelSuffix::Tab && properties.indent_first_line < 0.0 {
+            properties.indent_first_line = 0.0;
+        }
+        properties
+    }
+}
+
+/// The list definitions of a document
+#[derive(Debug, Clone, Default)]
+pub struct ListNumbering {
+    abstract_nums: Vec<AbstractNumDef>,
+    nums: Vec<NumInstance>,
+}
+
+impl ListNumbering {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List definitions of a parsed document or document model
+    pub fn from_ooxml(numbering: &[Numbering]) -> Self {
+        ListNumbering {
+            abstract_nums: numbering.iter().flat_map(|n| n.abstract_num_defs.iter().cloned()).collect(),
+            nums: numbering.iter().flat_map(|n| n.num_instances.iter().cloned()).collect(),
+        }
+    }
+
+    /// The definitions for a document model; empty when there are none
+    pub fn to_ooxml(&self) -> Vec<Numbering> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        vec![Numbering {
+            abstract_num_defs: self.abstract_nums.clone(),
+            num_instances: self.nums.clone(),
+        }]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.abstract_nums.is_empty() && self.nums.is_empty()
+    }
+
+    /// Definition of `level` of list `num_id`, with the list's overrides applied
+    pub fn level(&self, num_id: &str, level: usize) -> Option<ListLevel> {
+        let num = self.nums.iter().find(|num| num.num_id == num_id)?;
+        let definition = self
+            .abstract_nums
+            .iter()
+            .find(|definition| definition.abstract_num_id == num.abstract_num_id)?;
+        let mut list_level = definition.levels.iter().find(|l| l.level as usize == level)?.clone();
+        if let Some(level_override) = num.overrides.iter().find(|o| o.level as usize == level) {
+            if let Some(start) = level_override.start_value {
+                list_level.start_value = start;
+            }
+            if let Some(ref text) = level_override.text {
+                list_level.text = text.clone();
+            }
+            if let Some(ref format) = level_override.format {
+                list_level.format = format.clone();
+            }
+        }
+        Some(list_level)
+    }
+
+    /// ID of the list the heading styles are linked to
+    pub fn heading_list(&self, styles: &StyleSheet) -> Option<String> {
+        let style = styles.get(&heading_style_id(1))?;
+        style.paragraph.num_id.clone().filter(|id| id != NO_LIST)
+    }
+
+    /// Number the heading styles with `scheme`, or stop numbering them
+    ///
+    /// Replaces any list the headings were linked to. Heading styles the sheet
+    /// lacks are created, based on Normal. Returns the new list's ID.
+    pub fn set_heading_numbering(&mut self, styles: &mut StyleSheet, scheme: Option<OutlineScheme>) -> Option<String> {
+        // Drop the definitions linked to the headings, and the lists using them
+        let linked: Vec<String> = self
+            .abstract_nums
+            .iter()
+            .filter(|definition| {
+                definition
+                    .levels
+                    .iter()
+                    .any(|level| level.style_id.as_deref() == Some(heading_style_id(level.level as usize + 1).as_str()))
+            })
+            .map(|definition| definition.abstract_num_id.clone())
+            .collect();
+        self.abstract_nums.retain(|definition| !linked.contains(&definition.abstract_num_id));
+        self.nums.retain(|num| !linked.contains(&num.abstract_num_id));
+
+        let num_id = scheme.map(|scheme| {
+            let abstract_num_id = next_id(self.abstract_nums.iter().map(|d| d.abstract_num_id.as_str()), 0);
+            let num_id = next_id(self.nums.iter().map(|num| num.num_id.as_str()), 1);
+            self.abstract_nums.push(AbstractNumDef {
+                abstract_num_id: abstract_num_id.clone(),
+                levels: (0..LEVEL_COUNT)
+                    .map(|level| {
+                        let (format, text, is_legal) = scheme.level(level);
+                        ListLevel {
+                            level: level as u32,
+                            format: format.to_string(),
+                            text,
+                            start_value: 1,
+                            style_id: Some(heading_style_id(level + 1)),
+                            is_legal,
+                            restart: None,
+                            suffix: None,
+                            paragraph_properties: ParagraphProperties::default(),
+                            run_properties: RunProperties::default(),
+                        }
+                    })
+                    .collect(),
+            });
+            self.nums.push(NumInstance {
+                num_id: num_id.clone(),
+                abstract_num_id,
+                overrides: Vec::new(),
+            });
+            num_id
+        });
+
+        for level in 0..LEVEL_COUNT {
+            let id = heading_style_id(level + 1);
+            let mut style = match styles.get(&id) {
+                Some(style) => style.clone(),
+                None if num_id.is_none() => continue,
+                None => {
+                    let mut style = NamedStyle::new(id.clone(), StyleKind::Paragraph);
+                    style.name = format!("heading {}", level + 1);
+                    style.based_on = styles.get("Normal").map(|normal| normal.id.clone());
+                    style
+                }
+            };
+            style.paragraph.num_id = num_id.clone();
+            style.paragraph.list_level = num_id.as_ref().map(|_| level as u8);
+            styles.add(style);
+        }
+        num_id
+    }
+
+    /// The list label of each paragraph, None for paragraphs not in a list
+    ///
+    /// `paragraphs` is the effective formatting of every paragraph in order.
+    pub fn labels(&self, paragraphs: &[ParagraphAttributes]) -> Vec<Option<String>> {
+        self.resolve(paragraphs)
+            .into_iter()
+            .map(|label| label.map(|label| label.text))
+            .collect()
+    }
+
+    /// The full label of each paragraph, as [`ListNumbering::labels`]
+    pub fn resolve(&self, paragraphs: &[ParagraphAttributes]) -> Vec<Option<ListLabel>> {
+        let mut engine = NumberingEngine::new(self);
+        paragraphs.iter().map(|paragraph| engine.next(paragraph)).collect()
+    }
+
+    /// Whether list `num_id` has bullets or numbers, by its first level
+    pub fn kind(&self, num_id: &str) -> Option<ListKind> {
+        let level = self.level(num_id, 0)?;
+        Some(if level.format == "bullet" { ListKind::Bullet } else { ListKind::Numbered })
+    }
+
+    /// Add a list of `kind` with a definition of its own; returns its ID
+    pub fn add_list(&mut self, kind: ListKind) -> String {
+        let abstract_num_id = next_id(self.abstract_nums.iter().map(|d| d.abstract_num_id.as_str()), 0);
+        let levels = (0..LEVEL_COUNT)
+            .map(|level| {
+                let (format, text) = match kind {
+                    ListKind::Bullet => ("bullet", BULLETS[level % BULLETS.len()].to_string()),
+                    ListKind::Numbered => (NUMBER_FORMATS[level % NUMBER_FORMATS.len()], format!("{}.", placeholder(level + 1))),
+                };
+                ListLevel {
+                    level: level as u32,
+                    format: format.to_string(),
+                    text,
+                    start_value: 1,
+                    paragraph_properties: ParagraphProperties {
+                        indent_left: Some(LEVEL_INDENT * (level as i32 + 1)),
+                        indent_first_line: Some(-LABEL_HANG),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }
+            })
+            .collect();
+        self.abstract_nums.push(AbstractNumDef {
+            abstract_num_id: abstract_num_id.clone(),
+            levels,
+        });
+        self.add_num(abstract_num_id, Vec::new())
+    }
+
+    fn add_num(&mut self, abstract_num_id: String, overrides: Vec<LevelOverride>) -> String {
+        let num_id = next_id(self.nums.iter().map(|num| num.num_id.as_str()), 1);
+        self.nums.push(NumInstance {
+            num_id: num_id.clone(),
+            abstract_num_id,
+            overrides,
+        });
+        num_id
+    }
+
+    /// Apply `edit` to paragraphs `range`, by index
+    ///
+    /// `paragraphs` is the direct formatting of every paragraph in order; the
+    /// result is the new direct formatting of every paragraph. Restarting and
+    /// continuing also renumber the rest of the list after the range.
+    pub fn edit_paragraphs(
+        &mut self,
+        edit: ListEdit,
+        paragraphs: &[Option<ParagraphAttributes>],
+        range: Range<usize>,
+        styles: &StyleSheet,
+    ) -> Result<Vec<Option<ParagraphAttributes>>, ListError> {
+        let range = range.start.min(paragraphs.len())..range.end.min(paragraphs.len());
+        let effective: Vec<ParagraphAttributes> =
+            paragraphs.iter().map(|direct| styles.effective_paragraph(direct.as_ref())).collect();
+        let lists: Vec<Option<(String, ListKind)>> = effective
+            .iter()
+            .map(|paragraph| {
+                let num_id = paragraph.num_id.as_deref().filter(|id| *id != NO_LIST)?;
+                self.kind(num_id).map(|kind| (num_id.to_string(), kind))
+            })
+            .collect();
+        let list_of = |index: usize| lists[index].clone();
+        let mut result = paragraphs.to_vec();
+        let mut set = |index: usize, num_id: Option<&str>, level: Option<u8>| {
+            let direct = result[index].get_or_insert_with(Default::default);
+            direct.num_id = num_id.map(str::to_string);
+            direct.list_level = level;
+            // A list from the paragraph style is overridden by "no list"
+            let unlisted = direct.num_id.is_none() && styles.effective_paragraph(Some(direct)).num_id.is_some_and(|id| id != NO_LIST);
+            if unlisted {
+                direct.num_id = Some(NO_LIST.to_string());
+            }
+        };
+
+        match edit {
+            ListEdit::Toggle(kind) if !range.is_empty() && range.clone().all(|i| list_of(i).is_some_and(|(_, k)| k == kind)) => {
+                for index in range {
+                    set(index, None, None);
+                }
+            }
+            ListEdit::Toggle(kind) | ListEdit::Convert(kind) => {
+                // Join the list the range starts in or follows, if it is of the kind
+                let joined = range
+                    .clone()
+                    .next()
+                    .and_then(|first| list_of(first).or_else(|| first.checked_sub(1).and_then(list_of)))
+                    .filter(|(_, k)| *k == kind)
+                    .map(|(num_id, _)| num_id);
+                let num_id = match joined {
+                    Some(num_id) => num_id,
+                    None => self.add_list(kind),
+                };
+                for index in range {
+                    let level = effective[index].list_level.filter(|_| list_of(index).is_some()).unwrap_or(0);
+                    set(index, Some(&num_id), Some(level));
+                }
+            }
+            ListEdit::Indent | ListEdit::Outdent => {
+                let listed: Vec<usize> = range.filter(|&i| list_of(i).is_some()).collect();
+                if listed.is_empty() {
+                    return Err(ListError::NotInList);
+                }
+                for index in listed {
+                    let level = effective[index].list_level.unwrap_or(0);
+                    let level = match edit {
+                        ListEdit::Indent => (level + 1).min(LEVEL_COUNT as u8 - 1),
+                        _ => level.saturating_sub(1),
+                    };
+                    let num_id = effective[index].num_id.clone();
+                    set(index, num_id.as_deref(), Some(level));
+                }
+            }
+            ListEdit::Restart | ListEdit::Continue => {
+                let first = range.clone().find(|&i| list_of(i).is_some()).ok_or(ListError::NotInList)?;
+                let (old, kind) = list_of(first).ok_or(ListError::NotInList)?;
+                let num_id = if edit == ListEdit::Restart {
+                    let num = self.nums.iter().find(|num| num.num_id == old).ok_or(ListError::NotInList)?;
+                    let abstract_num_id = num.abstract_num_id.clone();
+                    let overrides = (0..LEVEL_COUNT)
+                        .filter_map(|level| {
+                            let start = self.level(&old, level)?.start_value;
+                            Some(LevelOverride {
+                                level: level as u32,
+                                start_value: Some(start),
+                                ..Default::default()
+                            })
+                        })
+                        .collect();
+                    self.add_num(abstract_num_id, overrides)
+                } else {
+                    (0..first)
+                        .rev()
+                        .filter_map(list_of)
+                        .find(|(num_id, k)| *k == kind && *num_id != old)
+                        .map(|(num_id, _)| num_id)
+                        .ok_or(ListError::NothingToContinue)?
+                };
+                for (index, paragraph) in effective.iter().enumerate().skip(first) {
+                    if paragraph.num_id.as_deref() == Some(old.as_str()) {
+                        set(index, Some(&num_id), paragraph.list_level);
+                    }
+                }
+            }
+        }
+        for direct in result.iter_mut() {
+            if direct.as_ref().is_some_and(|direct| *direct == ParagraphAttributes::default()) {
+                *direct = None;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Whose counters list `num_id` uses: its own if it overrides a start
+    /// value, otherwise its abstract definition's
+    fn counter_key(&self, num_id: &str) -> Option<(bool, &str)> {
+        let num = self.nums.iter().find(|num| num.num_id == num_id)?;
+        Some(match num.overrides.iter().any(|o| o.start_value.is_some()) {
+            true => (true, &num.num_id),
+            false => (false, &num.abstract_num_id),
+        })
+    }
+}
+
+/// Counts list items through the paragraphs of a document, in order
+pub struct NumberingEngine<'a> {
+    numbering: &'a ListNumbering,
+    counters: HashMap<(bool, &'a str), [Option<u32>; LEVEL_COUNT]>,
+}
+
+impl<'a> NumberingEngine<'a> {
+    pub fn new(numbering: &'a ListNumbering) -> Self {
+        NumberingEngine {
+            numbering,
+            counters: HashMap::new(),
+        }
+    }
+
+    /// Count the next paragraph, given its effective formatting, and render its label
+    pub fn next(&mut self, paragraph: &ParagraphAttributes) -> Option<ListLabel> {
+        let num_id = paragraph.num_id.as_deref().filter(|id| *id != NO_LIST)?;
+        let level = (paragraph.list_level.unwrap_or(0) as usize).min(LEVEL_COUNT - 1);
+        let definition = self.numbering.level(num_id, level)?;
+
+        let key = self.numbering.counter_key(num_id)?;
+        let counts = self.counters.entry(key).or_insert([None; LEVEL_COUNT]);
+        counts[level] = Some(counts[level].map_or(definition.start_value, |n| n + 1));
+        for (deeper, count) in counts.iter_mut().enumerate().skip(level + 1) {
+            // w:lvlRestart is the lowest level, from 1, whose items restart this one
+            let restart = self.numbering.level(num_id, deeper).and_then(|l| l.restart);
+            if restart.is_none_or(|restart| restart as usize > level) {
+                *count = None;
+            }
+        }
+
+        let mut text = definition.text.clone();
+        for shown in (0..=level).rev() {
+            let Some(shown_level) = self.numbering.level(num_id, shown) else { continue };
+            // A level that has not appeared yet counts as one before its start
+            let n = counts[shown].unwrap_or(shown_level.start_value.saturating_sub(1));
+            // Legal numbering shows the levels above in decimal, its own in its format
+            let format = if definition.is_legal && shown != level { "decimal" } else { shown_level.format.as_str() };
+            text = text.replace(&placeholder(shown + 1), &format_number(n, format));
+        }
+        let is_bullet = definition.format == "bullet";
+        if is_bullet {
+            text = text.chars().map(bullet_char).collect();
+        }
+
+        Some(ListLabel {
+            num_id: num_id.to_string(),
+            level: level as u8,
+            text,
+            suffix: LabelSuffix::from_ooxml(definition.suffix.as_deref()),
+            is_bullet,
+            indent_left: definition.paragraph_properties.indent_left,
+            indent_first_line: definition.paragraph_properties.indent_first_line,
+            run_properties: definition.run_properties,
+        })
+    }
+}
+
+/// The Unicode bullet for a symbol-font character, which Word stores in the private use area
+fn bullet_char(c: char) -> char {
+    match c as u32 {
+        0xF0B7 => '•',
+        0xF0A7 => '▪',
+        0xF0D8 => '➢',
+        0xF076 => '❖',
+        0xF0FC => '✓',
+        0xF000..=0xF0FF => '•',
+        _ => c,
+    }
+}
+
+/// One more than the largest numeric ID, and at least `first`
+fn next_id<'a>(ids: impl Iterator<Item = &'a str>, first: u32) -> String {
+    ids.filter_map(|id| id.parse::<u32>().ok())
+        .map(|id| id + 1)
+        .max()
+        .unwrap_or(first)
+        .max(first)
+        .to_string()
+}
+
+/// Render `n` in a w:numFmt format; unknown formats render in decimal
+pub fn format_number(n: u32, format: &str) -> String {
+    match format {
+        "decimalZero" if n < 10 => format!("0{}", n),
+        "lowerLetter" => letters(n),
+        "upperLetter" => letters(n).to_uppercase(),
+        "lowerRoman" => roman(n).to_lowercase(),
+        "upperRoman" => roman(n),
+        // The level text is the bullet itself
+        "bullet" | "none" => String::new(),
+        _ => n.to_string(),
+    }
+}
+
+/// a, b, ..., z, aa, bb, ..., as Word counts
+fn letters(n: u32) -> String {
+    if n == 0 {
+        return n.to_string();
+    }
+    let letter = (b'a' + ((n - 1) % 26) as u8) as char;
+    letter.to_string().repeat((n as usize - 1) / 26 + 1)
+}
+
+fn roman(n: u32) -> String {
+    if n == 0 || n >= 4000 {
+        return n.to_string();
+    }
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut rest = n;
+    let mut numeral = String::new();
+    for (value, symbol) in NUMERALS {
+        while rest >= value {
+            numeral.push_str(symbol);
+            rest -= value;
+        }
+    }
+    numeral
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headings(styles: &StyleSheet, levels: &[Option<u8>]) -> Vec<ParagraphAttributes> {
+        levels
+            .iter()
+            .map(|level| {
+                let direct = ParagraphAttributes {
+                    style_id: level.map(|level| heading_style_id(level as usize)),
+                    ..Default::default()
+                };
+                styles.effective_paragraph(Some(&direct))
+            })
+            .collect()
+    }
+
+    fn texts(labels: Vec<Option<String>>) -> Vec<String> {
+        labels.into_iter().map(|label| label.unwrap_or_default()).collect()
+    }
+
+    #[test]
+    fn test_heading_styles_number_their_paragraphs() {
+        let mut styles = StyleSheet::new();
+        styles.add(NamedStyle::new("Normal", StyleKind::Paragraph));
+        let mut numbering = ListNumbering::new();
+        let num_id = numbering.set_heading_numbering(&mut styles, Some(OutlineScheme::Decimal)).unwrap();
+
+        assert_eq!(numbering.heading_list(&styles), Some(num_id));
+        assert_eq!(styles.get("Heading2").unwrap().based_on.as_deref(), Some("Normal"));
+        assert_eq!(styles.find("heading 3").unwrap().paragraph.list_level, Some(2));
+
+        let paragraphs = headings(&styles, &[Some(1), None, Some(2), Some(2), Some(3), Some(1), Some(3)]);
+        assert_eq!(texts(numbering.labels(&paragraphs)), ["1", "", "1.1", "1.2", "1.2.1", "2", "2.0.1"]);
+
+        // Direct formatting can take a heading out of the list
+        let mut paragraphs = paragraphs;
+        paragraphs[0].num_id = Some(NO_LIST.to_string());
+        assert_eq!(texts(numbering.labels(&paragraphs))[..3], ["", "", "0.1"]);
+
+        // Turning numbering off unlinks the styles and drops the definitions
+        assert_eq!(numbering.set_heading_numbering(&mut styles, None), None);
+        assert!(numbering.is_empty());
+        assert!(numbering.labels(&headings(&styles, &[Some(1)]))[0].is_none());
+    }
+
+    #[test]
+    fn test_legal_numbering_shows_levels_in_decimal() {
+        let mut styles = StyleSheet::new();
+        let mut numbering = ListNumbering::new();
+        numbering.set_heading_numbering(&mut styles, Some(OutlineScheme::Decimal));
+        // Replacing the scheme replaces the definitions instead of adding more
+        let num_id = numbering.set_heading_numbering(&mut styles, Some(OutlineScheme::Legal)).unwrap();
+        assert_eq!(numbering.to_ooxml()[0].abstract_num_defs.len(), 1);
+
+        let paragraphs = headings(&styles, &[Some(1), Some(2), Some(1), Some(2), Some(2), Some(3), Some(4)]);
+        assert_eq!(
+            texts(numbering.labels(&paragraphs)),
+            ["Article I.", "Section 1.01", "Article II.", "Section 2.01", "Section 2.02", "(a)", "(i)"]
+        );
+        assert!(numbering.level(&num_id, 1).unwrap().is_legal);
+    }
+
+    /// A list definition "0" with a decimal "%1." level and an "%2)" letter level
+    fn outline_list(nums: Vec<NumInstance>) -> ListNumbering {
+        let level = |level: u32, format: &str, text: &str| ListLevel {
+            level,
+            format: format.to_string(),
+            text: text.to_string(),
+            start_value: 1,
+            paragraph_properties: ParagraphProperties {
+                indent_left: Some(720 * (level as i32 + 1)),
+                indent_first_line: Some(-360),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        ListNumbering::from_ooxml(&[Numbering {
+            abstract_num_defs: vec![AbstractNumDef {
+                abstract_num_id: "0".to_string(),
+                levels: vec![level(0, "decimal", "%1."), level(1, "lowerLetter", "%2)"), level(2, "bullet", "\u{F0B7}")],
+            }],
+            num_instances: nums,
+        }])
+    }
+
+    fn num(num_id: &str, overrides: Vec<LevelOverride>) -> NumInstance {
+        NumInstance {
+            num_id: num_id.to_string(),
+            abstract_num_id: "0".to_string(),
+            overrides,
+        }
+    }
+
+    fn items(items: &[(&str, u8)]) -> Vec<ParagraphAttributes> {
+        items
+            .iter()
+            .map(|&(num_id, level)| ParagraphAttributes {
+                num_id: Some(num_id.to_string()),
+                list_level: Some(level),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lists_share_counters_unless_restarted() {
+        let restart = LevelOverride {
+            level: 0,
+            start_value: Some(1),
+            text: Some("(%1)".to_string()),
+            format: Some("upperRoman".to_string()),
+        };
+        let numbering = outline_list(vec![num("1", Vec::new()), num("2", Vec::new()), num("3", vec![restart])]);
+
+        // "2" continues "1"; "3" restarts at its override with its own format
+        let paragraphs = items(&[("1", 0), ("1", 1), ("1", 2), ("2", 0), ("2", 1), ("3", 0), ("3", 0), ("1", 0)]);
+        assert_eq!(
+            texts(numbering.labels(&paragraphs)),
+            ["1.", "a)", "•", "2.", "a)", "(I)", "(II)", "3."]
+        );
+
+        let bullet = numbering.resolve(&paragraphs)[2].clone().unwrap();
+        assert!(bullet.is_bullet);
+        assert_eq!(bullet.prefix(), "•\t");
+        // The label hangs in the level's indent, so the text starts at the left indent
+        let layout = bullet.layout_properties(&paragraphs[2]);
+        assert_eq!((layout.indent_left, layout.indent_first_line), (2160.0, 0.0));
+    }
+
+    #[test]
+    fn test_level_restart_rules() {
+        let mut numbering = outline_list(vec![num("1", Vec::new())]);
+        numbering.abstract_nums[0].levels[2] = ListLevel {
+            level: 2,
+            format: "decimal".to_string(),
+            text: "%3".to_string(),
+            start_value: 1,
+            // Items of level 1 restart it, items of level 2 do not
+            restart: Some(1),
+            suffix: Some("space".to_string()),
+            ..Default::default()
+        };
+        let paragraphs = items(&[("1", 0), ("1", 2), ("1", 1), ("1", 2), ("1", 0), ("1", 2)]);
+        assert_eq!(texts(numbering.labels(&paragraphs)), ["1.", "1", "a)", "2", "2.", "1"]);
+        assert_eq!(numbering.resolve(&paragraphs)[1].as_ref().unwrap().suffix, LabelSuffix::Space);
+
+        // Never restarting keeps counting through higher levels
+        numbering.abstract_nums[0].levels[2].restart = Some(0);
+        assert_eq!(texts(numbering.labels(&paragraphs)), ["1.", "1", "a)", "2", "2.", "3"]);
+    }
+
+    /// Apply `edit` to `range` and return the labels of all paragraphs
+    fn edit(
+        numbering: &mut ListNumbering,
+        paragraphs: &mut Vec<Option<ParagraphAttributes>>,
+        edit: ListEdit,
+        range: Range<usize>,
+    ) -> Result<Vec<String>, ListError> {
+        let styles = StyleSheet::new();
+        *paragraphs = numbering.edit_paragraphs(edit, paragraphs, range, &styles)?;
+        let effective: Vec<_> = paragraphs.iter().map(|p| styles.effective_paragraph(p.as_ref())).collect();
+        Ok(texts(numbering.labels(&effective)))
+    }
+
+    #[test]
+    fn test_toggle_convert_and_levels() {
+        let mut numbering = ListNumbering::new();
+        let mut paragraphs = vec![None; 4];
+
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Toggle(ListKind::Numbered), 0..3).unwrap(), ["1.", "2.", "3.", ""]);
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Indent, 1..3).unwrap(), ["1.", "a.", "b.", ""]);
+        // Toggling the last paragraph on joins the list just before it
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Toggle(ListKind::Numbered), 3..4).unwrap(), ["1.", "a.", "b.", "2."]);
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Outdent, 2..3).unwrap(), ["1.", "a.", "2.", "3."]);
+
+        // Converting keeps the levels; toggling again takes the paragraphs out
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Convert(ListKind::Bullet), 0..2).unwrap(), ["•", "◦", "1.", "2."]);
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Toggle(ListKind::Bullet), 0..2).unwrap(), ["", "", "1.", "2."]);
+        assert_eq!(paragraphs[0], None);
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Indent, 0..2).unwrap_err(), ListError::NotInList);
+        assert_eq!(numbering.to_ooxml()[0].num_instances.len(), 2);
+    }
+
+    #[test]
+    fn test_restart_and_continue_numbering() {
+        let mut numbering = ListNumbering::new();
+        let mut paragraphs = vec![None; 5];
+        edit(&mut numbering, &mut paragraphs, ListEdit::Toggle(ListKind::Numbered), 0..5).unwrap();
+
+        // Restarting at the third paragraph renumbers the rest of the list
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Restart, 2..3).unwrap(), ["1.", "2.", "1.", "2.", "3."]);
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Continue, 2..3).unwrap(), ["1.", "2.", "3.", "4.", "5."]);
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Continue, 0..1).unwrap_err(), ListError::NothingToContinue);
+
+        // A separate list counts from 1 even though it follows another, until it continues it
+        let num_id = numbering.add_list(ListKind::Numbered);
+        paragraphs.push(Some(ParagraphAttributes {
+            num_id: Some(num_id),
+            ..Default::default()
+        }));
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Indent, 5..6).unwrap()[5], "a.");
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Outdent, 5..6).unwrap()[5], "1.");
+        assert_eq!(edit(&mut numbering, &mut paragraphs, ListEdit::Continue, 5..6).unwrap()[5], "6.");
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(14, "upperRoman"), "XIV");
+        assert_eq!(format_number(1994, "lowerRoman"), "mcmxciv");
+        assert_eq!(format_number(28, "lowerLetter"), "bb");
+        assert_eq!(format_number(3, "upperLetter"), "C");
+        assert_eq!(format_number(7, "decimalZero"), "07");
+        assert_eq!(format_number(12, "ordinalText"), "12");
+    }
+}
@@ -0,0 +1,178 @@
+//! Extended properties (docProps/app.xml)
+//!
+//! The application that saved the document and the statistics Word shows
+//! under File > Info, counted afresh on every save rather than carried over
+//! from the source package. Words, characters and paragraphs are counted in
+//! the body, tables included, as Word does: a word is a run of text between
+//! spaces, except that each CJK ideograph or kana counts as a word of its
+//! own; characters leave out spaces and paragraph marks, and only paragraphs
+//! with text count. Pages and lines depend on layout and come from the
+//! paginator; the editing time adds this session to what the source recorded.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::segmentation::{line_break_class, LineBreakClass};
+
+use super::types::{Paragraph, Table};
+
+/// Version written to AppVersion, in the XX.YYYY form Word expects
+const APP_VERSION: &str = "1.0000";
+
+/// Pages and lines of a finished pagination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutStatistics {
+    pub pages: usize,
+    pub lines: usize,
+}
+
+/// Statistics written to app.xml
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentStatistics {
+    pub pages: Option<usize>,
+    pub lines: Option<usize>,
+    pub words: usize,
+    /// Characters, not counting spaces
+    pub characters: usize,
+    pub characters_with_spaces: usize,
+    /// Paragraphs with text
+    pub paragraphs: usize,
+    /// Minutes spent editing the document, over all sessions
+    pub total_time: u64,
+}
+
+impl DocumentStatistics {
+    /// Counts of body `paragraphs` and `tables`; pages, lines and editing time are left unset
+    pub fn count(paragraphs: &[Paragraph], tables: &[Table]) -> Self {
+        let mut statistics = DocumentStatistics::default();
+        let cells = tables.iter().flat_map(|table| &table.rows).flat_map(|row| &row.cells);
+        for paragraph in paragraphs.iter().chain(cells.flat_map(|cell| &cell.paragraphs)) {
+            statistics.add(&paragraph.text);
+        }
+        statistics
+    }
+
+    fn add(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.paragraphs += 1;
+        let mut in_word = false;
+        for c in text.chars() {
+            self.characters_with_spaces += 1;
+            if c.is_whitespace() {
+                in_word = false;
+                continue;
+            }
+            self.characters += 1;
+            if is_ideographic(c) {
+                self.words += 1;
+                in_word = false;
+            } else if !in_word {
+                self.words += 1;
+                in_word = true;
+            }
+        }
+    }
+}
+
+/// Whether Word counts char `c` as a word by itself
+fn is_ideographic(c: char) -> bool {
+    matches!(line_break_class(c), LineBreakClass::Ideographic)
+        || matches!(c as u32, 0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F)
+}
+
+/// app.xml naming Velum as the application, with `statistics` if given
+pub(super) fn app_properties_xml(statistics: Option<&DocumentStatistics>) -> String {
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+    xml.push_str(r#"<Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/extended-properties">"#);
+    let element = |xml: &mut String, name: &str, value: &str| xml.push_str(&format!("<{0}>{1}</{0}>", name, value));
+    if let Some(statistics) = statistics {
+        element(&mut xml, "TotalTime", &statistics.total_time.to_string());
+        if let Some(pages) = statistics.pages {
+            element(&mut xml, "Pages", &pages.to_string());
+        }
+        element(&mut xml, "Words", &statistics.words.to_string());
+        element(&mut xml, "Characters", &statistics.characters.to_string());
+    }
+    element(&mut xml, "Application", "Velum");
+    if let Some(statistics) = statistics {
+        if let Some(lines) = statistics.lines {
+            element(&mut xml, "Lines", &lines.to_string());
+        }
+        element(&mut xml, "Paragraphs", &statistics.paragraphs.to_string());
+        element(&mut xml, "CharactersWithSpaces", &statistics.characters_with_spaces.to_string());
+    }
+    element(&mut xml, "AppVersion", APP_VERSION);
+    xml.push_str("</Properties>");
+    xml
+}
+
+/// Editing minutes recorded in a source app.xml
+pub(super) fn recorded_total_time(app_xml: &[u8]) -> Option<u64> {
+    static TOTAL_TIME: Lazy<Regex> = Lazy::new(|| Regex::new(r"<(?:\w+:)?TotalTime>\s*(\d+)\s*</").unwrap());
+    let xml = std::str::from_utf8(app_xml).ok()?;
+    TOTAL_TIME.captures(xml)?.get(1)?.as_str().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::{TableCell, TableRow};
+
+    fn paragraph(text: &str) -> Paragraph {
+        Paragraph {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_counts_follow_word() {
+        let table = Table {
+            rows: vec![TableRow {
+                cells: vec![TableCell {
+                    paragraphs: vec![paragraph("Cell text")],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let statistics = DocumentStatistics::count(&[paragraph("Hello,  world!"), paragraph(""), paragraph("日本語 text")], &[table]);
+        assert_eq!(
+            statistics,
+            DocumentStatistics {
+                words: 2 + 4 + 2,
+                characters: 12 + 7 + 8,
+                characters_with_spaces: 14 + 8 + 9,
+                paragraphs: 3,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_app_xml() {
+        let statistics = DocumentStatistics {
+            pages: Some(2),
+            lines: Some(40),
+            words: 300,
+            characters: 1500,
+            characters_with_spaces: 1800,
+            paragraphs: 12,
+            total_time: 15,
+        };
+        let xml = app_properties_xml(Some(&statistics));
+        assert!(xml.contains("<TotalTime>15</TotalTime><Pages>2</Pages><Words>300</Words><Characters>1500</Characters>"));
+        assert!(xml.contains("<Lines>40</Lines><Paragraphs>12</Paragraphs><CharactersWithSpaces>1800</CharactersWithSpaces>"));
+        assert_eq!(recorded_total_time(xml.as_bytes()), Some(15));
+
+        let private = app_properties_xml(None);
+        assert!(private.contains("<Application>Velum</Application>"));
+        assert!(private.contains("<AppVersion>1.0000</AppVersion>"));
+        assert!(!private.contains("Words") && !private.contains("TotalTime"));
+        assert_eq!(recorded_total_time(b"<ep:TotalTime> 7 </ep:TotalTime>"), Some(7));
+    }
+}
@@ -0,0 +1,122 @@
+//! ZIP compression of saved packages
+//!
+//! Which method each entry of a package is written with: XML parts deflate
+//! well, while JPEG, PNG and the like are compressed already and only cost
+//! time to deflate again, so by default they are stored. Zstandard is faster
+//! than deflate at a similar ratio, but Word and most ZIP readers cannot open
+//! it; it is only meant for autosave sidecars that Velum reads back itself.
+
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+
+/// How one kind of part is written to the archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartCompression {
+    /// As is, uncompressed
+    Stored,
+    /// Deflated at the package's `deflate_level`
+    Deflated,
+    /// Zstandard at the package's `zstd_level`; not readable by Word
+    Zstd,
+}
+
+/// Compression of a saved package, by kind of part
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackageCompression {
+    /// XML parts and relationships
+    pub xml: PartCompression,
+    /// Media in a compressed format: JPEG, PNG, GIF, WebP, HD Photo, audio and
+    /// video, and embedded packages that are ZIP archives themselves
+    pub compressed_media: PartCompression,
+    /// Any other binary part: BMP, TIFF, EMF and WMF images, fonts, VBA projects, ...
+    pub other: PartCompression,
+    /// Deflate level, from 0 (fastest) to 9 (smallest)
+    pub deflate_level: i32,
+    /// Zstandard level, from 1 (fastest) to 21 (smallest)
+    pub zstd_level: i32,
+}
+
+impl Default for PackageCompression {
+    fn default() -> Self {
+        PackageCompression {
+            xml: PartCompression::Deflated,
+            compressed_media: PartCompression::Stored,
+            other: PartCompression::Deflated,
+            deflate_level: 9,
+            zstd_level: 3,
+        }
+    }
+}
+
+impl PackageCompression {
+    /// Every part deflated at `deflate_level`, as Word itself saves
+    pub fn deflate_all(deflate_level: i32) -> Self {
+        PackageCompression {
+            compressed_media: PartCompression::Deflated,
+            deflate_level,
+            ..Default::default()
+        }
+    }
+
+    /// Quick to write, for autosave sidecars: parts that compress are
+    /// Zstandard at level 1, compressed media stored
+    pub fn autosave() -> Self {
+        PackageCompression {
+            xml: PartCompression::Zstd,
+            other: PartCompression::Zstd,
+            zstd_level: 1,
+            ..Default::default()
+        }
+    }
+
+    /// How the entry `name` is written
+    pub fn for_entry(&self, name: &str) -> PartCompression {
+        let extension = name.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("xml" | "rels" | "vml") => self.xml,
+            Some(extension) if COMPRESSED_EXTENSIONS.contains(&extension) => self.compressed_media,
+            _ => self.other,
+        }
+    }
+
+    /// ZIP options for the entry `name`
+    pub(super) fn file_options(&self, name: &str) -> FileOptions {
+        let options = FileOptions::default();
+        match self.for_entry(name) {
+            PartCompression::Stored => options.compression_method(CompressionMethod::Stored),
+            PartCompression::Deflated => options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(self.deflate_level.clamp(0, 9))),
+            PartCompression::Zstd => options
+                .compression_method(CompressionMethod::Zstd)
+                .compression_level(Some(self.zstd_level.clamp(1, 21))),
+        }
+    }
+}
+
+/// Extensions of formats that are compressed already
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "jpe", "jfif", "png", "gif", "webp", "wdp", "jxr", "hdp", "mp3", "m4a", "mp4", "m4v", "mov", "zip",
+    "docx", "docm", "dotx", "xlsx", "xlsm", "pptx", "pptm", "emz", "wmz", "svgz",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_by_kind() {
+        let compression = PackageCompression::default();
+        assert_eq!(compression.for_entry("word/document.xml"), PartCompression::Deflated);
+        assert_eq!(compression.for_entry("word/_rels/document.xml.rels"), PartCompression::Deflated);
+        assert_eq!(compression.for_entry("word/media/image1.JPEG"), PartCompression::Stored);
+        assert_eq!(compression.for_entry("word/embeddings/Sheet1.xlsx"), PartCompression::Stored);
+        assert_eq!(compression.for_entry("word/media/image2.emf"), PartCompression::Deflated);
+        assert_eq!(compression.for_entry("word/vbaProject.bin"), PartCompression::Deflated);
+
+        let autosave = PackageCompression::autosave();
+        assert_eq!(autosave.for_entry("[Content_Types].xml"), PartCompression::Zstd);
+        assert_eq!(autosave.for_entry("word/media/image1.png"), PartCompression::Stored);
+        assert_eq!(PackageCompression::deflate_all(6).for_entry("word/media/image1.png"), PartCompression::Deflated);
+    }
+}
@@ -0,0 +1,76 @@
+//! Progress reporting and cancellation for long-running loads and exports
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::error::OoxmlError;
+
+type ProgressFn<P> = dyn Fn(&P) + Send + Sync;
+
+/// Progress and cancellation shared between an operation and whoever started it
+///
+/// `P` is what the operation reports as it goes. Clones share the same
+/// cancellation flag and progress receiver.
+pub struct OperationControl<P> {
+    cancelled: Arc<AtomicBool>,
+    progress: Option<Arc<ProgressFn<P>>>,
+}
+
+impl<P> OperationControl<P> {
+    /// A control that reports nowhere and is not cancelled
+    pub fn new() -> Self {
+        OperationControl { cancelled: Arc::new(AtomicBool::new(false)), progress: None }
+    }
+
+    /// Receive each progress report
+    pub fn with_progress(mut self, progress: impl Fn(&P) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Ask the operation to stop at its next step
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fail once the operation has been cancelled
+    pub(crate) fn check(&self) -> Result<(), OoxmlError> {
+        if self.is_cancelled() {
+            return Err(OoxmlError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Pass a report on to the receiver, if there is one
+    pub(crate) fn report(&self, progress: &P) {
+        if let Some(receiver) = &self.progress {
+            receiver(progress);
+        }
+    }
+}
+
+impl<P> Default for OperationControl<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P> Clone for OperationControl<P> {
+    fn clone(&self) -> Self {
+        OperationControl { cancelled: self.cancelled.clone(), progress: self.progress.clone() }
+    }
+}
+
+impl<P> fmt::Debug for OperationControl<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OperationControl")
+            .field("cancelled", &self.is_cancelled())
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
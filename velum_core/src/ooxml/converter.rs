@@ -59,7 +59,7 @@ pub fn ooxml_to_piece_tree(doc: &WordDocument) -> PieceTree {
 }
 
 /// Convert OOXML RunProperties to PieceTree TextAttributes
-fn convert_run_properties(props: &RunProperties) -> TextAttributes {
+pub(super) fn convert_run_properties(props: &RunProperties) -> TextAttributes {
     let mut attrs = TextAttributes::default();
     
     attrs.bold = props.bold;
@@ -111,6 +111,10 @@ mod tests {
             footnotes: Vec::new(),
             endnotes: Vec::new(),
             numbering: Vec::new(),
+            sections: Vec::new(),
+            comments: Vec::new(),
+            content_controls: Vec::new(),
+            protection: Default::default(),
         };
 
         // Create a paragraph with mixed formatting
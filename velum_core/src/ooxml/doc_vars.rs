@@ -0,0 +1,110 @@
+//! Document Variables
+//! Named string values kept in settings.xml (w:docVars). Word stores them with
+//! the document without displaying them, which makes them the place for
+//! per-document editor state such as the last edit position.
+
+use regex::Regex;
+
+use super::document::unescape_xml_text;
+use super::error::OoxmlError;
+use super::parts::{empty_part, entry_text, insert_before_closing, read_entries, set_part, write_entries};
+use super::serializer::escape_xml_attr;
+
+const SETTINGS_PART: &str = "word/settings.xml";
+
+/// Settings elements that come after w:docVars in schema order
+const FOLLOWING_ELEMENTS: [&str; 8] = [
+    "<w:rsids",
+    "<m:mathPr",
+    "<w:attachedSchema",
+    "<w:themeFontLang",
+    "<w:clrSchemeMapping",
+    "<w:shapeDefaults",
+    "<w:decimalSymbol",
+    "<w:listSeparator",
+];
+
+/// Read document variable `name` from a .docx
+pub fn document_variable(file_data: &[u8], name: &str) -> Result<Option<String>, OoxmlError> {
+    let entries = read_entries(file_data)?;
+    Ok(entry_text(&entries, SETTINGS_PART).and_then(|settings| variable_in_settings(&settings, name)))
+}
+
+/// Set document variable `name` in a .docx, adding settings.xml if the package has none
+pub fn set_document_variable(file_data: &[u8], name: &str, value: &str) -> Result<Vec<u8>, OoxmlError> {
+    let mut entries = read_entries(file_data)?;
+    let settings = entry_text(&entries, SETTINGS_PART).unwrap_or_else(|| empty_part("w:settings"));
+    set_part(&mut entries, SETTINGS_PART, set_variable_in_settings(&settings, name, value), "settings");
+    write_entries(&entries)
+}
+
+fn variable_pattern(name: &str) -> Regex {
+    Regex::new(&format!(
+        r#"<w:docVar\b[^>]*\sw:name="{}"[^>]*/>"#,
+        regex::escape(&escape_xml_attr(name))
+    ))
+    .unwrap()
+}
+
+fn variable_in_settings(settings: &str, name: &str) -> Option<String> {
+    let element = variable_pattern(name).find(settings)?;
+    let value = Regex::new(r#"\sw:val="([^"]*)""#).unwrap().captures(element.as_str())?;
+    Some(unescape_xml_text(&value[1]))
+}
+
+/// `settings` with document variable `name` set to `value`
+pub(super) fn set_variable_in_settings(settings: &str, name: &str, value: &str) -> String {
+    let element = format!(
+        r#"<w:docVar w:name="{}" w:val="{}"/>"#,
+        escape_xml_attr(name),
+        escape_xml_attr(value)
+    );
+    let block = format!("<w:docVars>{}</w:docVars>", element);
+
+    let mut settings = settings.to_string();
+    if let Some(existing) = variable_pattern(name).find(&settings).map(|m| m.range()) {
+        settings.replace_range(existing, &element);
+    } else if settings.contains("</w:docVars>") {
+        insert_before_closing(&mut settings, "w:docVars", &element);
+    } else if let Some(at) = settings.find("<w:docVars/>") {
+        settings.replace_range(at..at + "<w:docVars/>".len(), &block);
+    } else {
+        match FOLLOWING_ELEMENTS.iter().filter_map(|tag| settings.find(tag)).min() {
+            Some(at) => settings.insert_str(at, &block),
+            None => insert_before_closing(&mut settings, "w:settings", &block),
+        }
+    }
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::parts::CONTENT_TYPES_PART;
+
+    #[test]
+    fn test_variables_keep_schema_order_and_update_in_place() {
+        let settings = r#"<w:settings><w:zoom w:percent="100"/><w:rsids><w:rsidRoot w:val="00A1"/></w:rsids></w:settings>"#;
+        let settings = set_variable_in_settings(settings, "Position", "12");
+        assert!(settings.contains(r#"<w:docVars><w:docVar w:name="Position" w:val="12"/></w:docVars><w:rsids>"#));
+
+        let settings = set_variable_in_settings(&settings, "Note", "a \"b\" & c");
+        let settings = set_variable_in_settings(&settings, "Position", "40");
+        assert_eq!(settings.matches("<w:docVar ").count(), 2);
+        assert_eq!(variable_in_settings(&settings, "Position").as_deref(), Some("40"));
+        assert_eq!(variable_in_settings(&settings, "Note").as_deref(), Some("a \"b\" & c"));
+        assert_eq!(variable_in_settings(&settings, "Missing"), None);
+    }
+
+    #[test]
+    fn test_package_without_settings_part() {
+        let types = r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"></Types>"#;
+        let docx = write_entries(&[(CONTENT_TYPES_PART.to_string(), types.as_bytes().to_vec())]).unwrap();
+        assert_eq!(document_variable(&docx, "Position").unwrap(), None);
+
+        let docx = set_document_variable(&docx, "Position", "7").unwrap();
+        assert_eq!(document_variable(&docx, "Position").unwrap().as_deref(), Some("7"));
+        let entries = read_entries(&docx).unwrap();
+        assert!(entry_text(&entries, CONTENT_TYPES_PART).unwrap().contains("settings+xml"));
+    }
+}
@@ -5,11 +5,38 @@ use std::collections::HashMap;
 use super::opc::OpcPackage;
 use super::types::{
     Paragraph, ParagraphProperties, Run, RunProperties, Style, Theme, ThemeFonts,
-    Table, TableRow, TableCell, TableProperties, TableRowProperties,
+    Table, TableRow, TableCell, TableCellProperties, TableProperties, TableRowProperties,
     TableBorders, TableBorder, Header, Footer, Footnote, Endnote, Numbering,
-    AbstractNumDef, ListLevel, NumInstance, DocumentImage,
+    AbstractNumDef, ListLevel, NumInstance, LevelOverride, DocumentImage, Field, NoteKind, NoteReference,
+    Section, HeaderFooterReference, Revision, RevisionKind, Comment, CommentMark, CommentMarkKind,
+    BookmarkMark, BookmarkMarkKind, ContentControl, ContentControlProperties, Hyperlink, ImageAnchor, ImageTransform, MathZone, ParagraphBorder, ParagraphFrame, RelationshipType, SourceRect,
 };
 use super::error::OoxmlError;
+use super::forms::{self, AnchoredFormField, ProtectionMode};
+use super::serializer::resolve_part_name;
+use super::whitespace::read_text;
+use crate::math;
+
+/// A complex field whose result runs on past the end of the body paragraph it starts in
+struct OpenField {
+    /// Index of that paragraph in the body
+    paragraph: usize,
+    instruction: String,
+    /// Char offset where the result starts in that paragraph
+    start: usize,
+}
+
+/// What a paragraph does to complex fields that span paragraphs, and the
+/// drawings in it, which are the body's
+#[derive(Default)]
+pub(super) struct SpanningFields {
+    /// Fields left open at its end: instruction and result start
+    pub(super) opened: Vec<(String, usize)>,
+    /// Where fields left open by earlier paragraphs end in it, innermost first
+    pub(super) closed: Vec<usize>,
+    /// Images drawn in it, with the char offset each sits at
+    pub(super) drawings: Vec<(usize, DocumentImage)>,
+}
 
 /// WordProcessingML document parser
 #[derive(Debug, Clone)]
@@ -38,6 +65,14 @@ pub struct WordDocument {
     pub endnotes: Vec<Endnote>,
     /// Numbering definitions (list styles)
     pub numbering: Vec<Numbering>,
+    /// Sections in body order, each covering a run of paragraphs
+    pub sections: Vec<Section>,
+    /// Comments; their anchors are the comment marks in the paragraphs
+    pub comments: Vec<Comment>,
+    /// Block-level content controls in body order, outermost first
+    pub content_controls: Vec<ContentControl>,
+    /// Editing restriction enforced by settings.xml
+    pub protection: ProtectionMode,
 }
 
 /// Core document properties
@@ -53,10 +88,56 @@ pub struct CoreProperties {
     pub modified: Option<String>,
 }
 
+impl CoreProperties {
+    /// Read the properties Velum shows from docProps/core.xml
+    pub(super) fn parse(xml_str: &str) -> Self {
+        let mut props = CoreProperties::default();
+
+        // Parse title
+        if let Some(caps) = regex::Regex::new(r#"<dc:title[^>]*>([^<]*)</dc:title>"#).unwrap().captures(xml_str) {
+            if let Some(m) = caps.get(1) {
+                props.title = Some(m.as_str().to_string());
+            }
+        }
+        
+        // Parse creator
+        if let Some(caps) = regex::Regex::new(r#"<dc:creator[^>]*>([^<]*)</dc:creator>"#).unwrap().captures(xml_str) {
+            if let Some(m) = caps.get(1) {
+                props.creator = Some(m.as_str().to_string());
+            }
+        }
+        
+        // Parse created
+        if let Some(caps) = regex::Regex::new(r#"<dcterms:created[^>]*>([^<]*)</dcterms:created>"#).unwrap().captures(xml_str) {
+            if let Some(m) = caps.get(1) {
+                props.created = Some(m.as_str().to_string());
+            }
+        }
+        
+        // Parse modified
+        if let Some(caps) = regex::Regex::new(r#"<dcterms:modified[^>]*>([^<]*)</dcterms:modified>"#).unwrap().captures(xml_str) {
+            if let Some(m) = caps.get(1) {
+                props.modified = Some(m.as_str().to_string());
+            }
+        }
+
+        props
+    }
+}
+
 impl WordDocument {
     /// Create a new WordDocument by parsing the OPC package
     pub fn parse(package: &OpcPackage) -> Result<Self, OoxmlError> {
-        let mut document = WordDocument {
+        let mut document = Self::empty();
+        document.parse_main_document(package)?;
+        document.parse_supporting_parts(package)?;
+
+        Ok(document)
+    }
+
+    /// A document with nothing parsed yet
+    pub(super) fn empty() -> Self {
+        WordDocument {
             text: String::new(),
             paragraphs: Vec::new(),
             styles: HashMap::new(),
@@ -69,21 +150,29 @@ impl WordDocument {
             footnotes: Vec::new(),
             endnotes: Vec::new(),
             numbering: Vec::new(),
-        };
-
-        document.parse_main_document(package)?;
-        document.parse_styles(package)?;
-        document.parse_theme(package)?;
-        document.parse_core_properties(package)?;
-        document.parse_numbering(package)?;
-        document.parse_headers_footers(package)?;
-        document.parse_footnotes_endnotes(package)?;
+            sections: Vec::new(),
+            comments: Vec::new(),
+            content_controls: Vec::new(),
+            protection: ProtectionMode::None,
+        }
+    }
 
-        Ok(document)
+    /// Parse every part but the main document body: styles, theme, core
+    /// properties, numbering, headers and footers, notes, comments and settings
+    pub(super) fn parse_supporting_parts(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
+        self.parse_styles(package)?;
+        self.parse_theme(package)?;
+        self.parse_core_properties(package)?;
+        self.parse_numbering(package)?;
+        self.parse_headers_footers(package)?;
+        self.parse_footnotes_endnotes(package)?;
+        self.parse_comments(package);
+        self.parse_settings(package);
+        Ok(())
     }
 
     /// Parse the main document body (word/document.xml)
-    fn parse_main_document(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
+    pub(super) fn parse_main_document(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
         let main_part_name = "/word/document.xml".to_string();
 
         let main_part = package.get_part(&main_part_name)
@@ -91,49 +180,69 @@ impl WordDocument {
 
         let xml_str = String::from_utf8_lossy(&main_part.data);
 
-        // First, extract and parse all tables
-        self.parse_tables(&xml_str, package);
-
-        // Then parse paragraphs (excluding those inside tables)
-        // We need to handle paragraphs outside tables
-        let para_pattern = regex::Regex::new(r#"<w:p[^>]*>(.*?)</w:p>"#).unwrap();
-
-        // Track positions to skip table content
-        let table_pattern = regex::Regex::new(r#"<w:tbl[^>]*>.*?</w:tbl>"#).unwrap();
+        // Paragraphs between tables belong to the body; each table notes how many came before it
+        let para_pattern = regex::Regex::new(r#"(?s)<w:p\b[^>]*>(.*?)</w:p>"#).unwrap();
+        let table_pattern = regex::Regex::new(r#"(?s)<w:tbl\b[^>]*>.*?</w:tbl>"#).unwrap();
         let mut last_end = 0usize;
+        let mut open_fields = Vec::new();
+        // Byte position of each body paragraph element, with the paragraphs before it
+        let mut paragraph_starts = Vec::new();
 
-        for table_cap in table_pattern.captures(&xml_str) {
-            let table_range = match table_cap.get(0) {
-                Some(m) => m.start()..m.end(),
-                None => continue,
-            };
-
-            // Parse paragraphs before this table
-            let before_table = &xml_str[last_end..table_range.start];
-            for para_cap in para_pattern.captures(before_table) {
+        for table_match in table_pattern.find_iter(&xml_str) {
+            let before_table = &xml_str[last_end..table_match.start()];
+            for para_cap in para_pattern.captures_iter(before_table) {
                 if let Some(para_xml) = para_cap.get(1) {
-                    if let Some(para) = self.parse_paragraph(para_xml.as_str()) {
-                        self.paragraphs.push(para);
-                    }
+                    paragraph_starts.push((last_end + para_cap.get(0).unwrap().start(), self.paragraphs.len()));
+                    self.push_body_paragraph(para_xml.as_str(), &mut open_fields);
                 }
             }
-
-            // Skip table content (already parsed)
-            last_end = table_range.end;
+            if let Some(mut table) = Self::parse_table(table_match.as_str()) {
+                table.paragraph_index = self.paragraphs.len();
+                self.tables.push(table);
+            }
+            last_end = table_match.end();
         }
 
-        // Parse paragraphs after last table
+        // Parse paragraphs after last table; the final paragraph mark holds the
+        // end of the document, so the final paragraph stays even when empty
         let after_tables = &xml_str[last_end..];
-        for para_cap in para_pattern.captures(after_tables) {
-            if let Some(para_xml) = para_cap.get(1) {
-                if let Some(para) = self.parse_paragraph(para_xml.as_str()) {
-                    self.paragraphs.push(para);
-                }
+        let mut paragraphs = para_pattern.captures_iter(after_tables).filter_map(|cap| cap.get(1)).peekable();
+        while let Some(para_xml) = paragraphs.next() {
+            let count = self.paragraphs.len();
+            // The group starts right after the element's start tag
+            let tag_start = after_tables[..para_xml.start()].rfind("<w:p").unwrap_or(0);
+            paragraph_starts.push((last_end + tag_start, count));
+            self.push_body_paragraph(para_xml.as_str(), &mut open_fields);
+            if self.paragraphs.len() == count && paragraphs.peek().is_none() && !para_xml.as_str().contains("<w:sectPr") {
+                let mut paragraph = Paragraph::default();
+                Self::parse_paragraph_mark(para_xml.as_str(), &mut paragraph);
+                self.paragraphs.push(paragraph);
             }
         }
 
-        // Parse inline images in the document
-        self.parse_inline_images(&xml_str, package);
+        // A field still open at the end of the body ends with it
+        while let Some(field) = open_fields.pop() {
+            let end = self.paragraphs.last().map_or(0, |p| p.text.chars().count());
+            self.close_field(field, end);
+        }
+
+        // The body-level sectPr follows the last paragraph and covers the rest of the body
+        let body_tail = &xml_str[xml_str.rfind("</w:p>").unwrap_or(0)..];
+        let mut last = body_tail
+            .find("<w:sectPr")
+            .map(|start| Self::parse_section_properties(&body_tail[start..]))
+            .unwrap_or_default();
+        let first_paragraph = self.sections.last().map_or(0, |s| s.first_paragraph + s.paragraph_count);
+        if first_paragraph < self.paragraphs.len() || self.sections.is_empty() {
+            last.first_paragraph = first_paragraph;
+            last.paragraph_count = self.paragraphs.len() - first_paragraph;
+            self.sections.push(last);
+        }
+
+        paragraph_starts.push((xml_str.len(), self.paragraphs.len()));
+        self.parse_content_controls(&xml_str, &paragraph_starts);
+        self.resolve_images(package);
+        self.resolve_hyperlinks(package);
 
         self.text = self.paragraphs
             .iter()
@@ -144,322 +253,847 @@ impl WordDocument {
         Ok(())
     }
 
-    /// Parse a single paragraph from XML
-    fn parse_paragraph(&self, para_xml: &str) -> Option<Paragraph> {
-        let mut paragraph = Paragraph::default();
+    /// Record the body's block-level content controls: w:sdt elements that
+    /// wrap whole body paragraphs. Run-level controls, and controls that only
+    /// hold tables or cells, wrap no body paragraph and are left out.
+    fn parse_content_controls(&mut self, xml: &str, paragraph_starts: &[(usize, usize)]) {
+        let tag_pattern = regex::Regex::new(r#"<(/?)w:sdt\b[^>]*>"#).unwrap();
+        let binding_pattern = regex::Regex::new(r#"<w:dataBinding\b([^>]*)/?>"#).unwrap();
+        // The type is the one type element among the properties; rich text has none
+        let type_patterns: Vec<(&str, regex::Regex)> =
+            ["text", "date", "dropDownList", "comboBox", "picture", "docPartObj", "group"]
+                .into_iter()
+                .map(|kind| (kind, regex::Regex::new(&format!(r#"<w:{}\b"#, kind)).unwrap()))
+                .collect();
+        let paragraphs_before = |position: usize| {
+            paragraph_starts
+                .iter()
+                .find(|(start, _)| *start >= position)
+                .map_or(self.paragraphs.len(), |(_, count)| *count)
+        };
 
-        // Parse runs within paragraph
-        let run_pattern = regex::Regex::new(r#"<w:r[^>]*>(.*?)</w:r>"#).unwrap();
-        for run_cap in run_pattern.captures(para_xml) {
-            let run_xml = match run_cap.get(1) {
-                Some(m) => m.as_str(),
-                None => continue,
+        let mut open = Vec::new();
+        let mut controls = Vec::new();
+        for tag in tag_pattern.captures_iter(xml) {
+            let whole = tag.get(0).unwrap();
+            if tag[1].is_empty() {
+                if !whole.as_str().ends_with("/>") {
+                    open.push(whole.start());
+                }
+                continue;
+            }
+            let Some(start) = open.pop() else {
+                continue;
             };
+            let first_paragraph = paragraphs_before(start);
+            let paragraph_count = paragraphs_before(whole.end()) - first_paragraph;
+            if paragraph_count == 0 {
+                continue;
+            }
+            let element = &xml[start..whole.end()];
+            let properties = element
+                .find("<w:sdtPr")
+                .map(|from| &element[from..element.find("</w:sdtPr>").unwrap_or(element.len())])
+                .unwrap_or("");
+            let value = |name: &str| {
+                regex::Regex::new(&format!(r#"<{}\b([^>]*)/?>"#, name))
+                    .unwrap()
+                    .captures(properties)
+                    .and_then(|caps| Self::attribute(&caps[1], "w:val"))
+            };
+            let sdt_type = type_patterns
+                .iter()
+                .find(|(_, pattern)| pattern.is_match(properties))
+                .map(|(kind, _)| *kind)
+                .or_else(|| properties.contains("<w14:checkbox").then_some("checkbox"))
+                .unwrap_or("richText");
+            controls.push((start, ContentControl {
+                tag: value("w:tag"),
+                alias: value("w:alias"),
+                sdt_type: sdt_type.to_string(),
+                properties: ContentControlProperties {
+                    placeholder_text: None,
+                    data_binding: binding_pattern
+                        .captures(properties)
+                        .and_then(|caps| Self::attribute(&caps[1], "w:xpath")),
+                    color: value("w15:color"),
+                    id: value("w:id"),
+                    is_temporary: properties.contains("<w:temporary"),
+                },
+                content: Vec::new(),
+                first_paragraph,
+                paragraph_count,
+            }));
+        }
+        controls.sort_by_key(|(start, _)| *start);
+        self.content_controls = controls.into_iter().map(|(_, control)| control).collect();
+    }
 
-            let mut run = Run::default();
-
-            // Parse text in run
-            let text_pattern = regex::Regex::new(r#"<w:t[^>]*>([^<]*)</w:t>"#).unwrap();
-            for text_cap in text_pattern.captures(run_xml) {
-                if let Some(text_match) = text_cap.get(1) {
-                    run.text = text_match.as_str().to_string();
-                    break;
-                }
+    /// Give the body's external hyperlinks the URLs their relationships point to
+    fn resolve_hyperlinks(&mut self, package: &OpcPackage) {
+        let Some(relationships) = package.get_relationships("/word/document.xml") else {
+            return;
+        };
+        let cells = self.tables.iter_mut().flat_map(|table| &mut table.rows).flat_map(|row| &mut row.cells);
+        let paragraphs = self.paragraphs.iter_mut().chain(cells.flat_map(|cell| &mut cell.paragraphs));
+        for hyperlink in paragraphs.flat_map(|paragraph| &mut paragraph.hyperlinks) {
+            if let Some(id) = hyperlink.relationship_id.take() {
+                hyperlink.url = relationships.iter().find(|rel| rel.id == id).map(|rel| rel.target.clone());
             }
+        }
+    }
 
-            // Parse run properties
-            let rpr_pattern = regex::Regex::new(r#"<w:rPr[^>]*>(.*?)</w:rPr>"#).unwrap();
-            if let Some(rpr_cap) = rpr_pattern.captures(run_xml) {
-                if let Some(rpr_xml) = rpr_cap.get(1) {
-                    Self::parse_run_properties(rpr_xml.as_str(), &mut run.properties);
+    /// Parse a body paragraph; a sectPr in its properties ends a section with it
+    ///
+    /// `open_fields` are the complex fields earlier paragraphs left open, which
+    /// the paragraph may end or add to.
+    fn push_body_paragraph(&mut self, para_xml: &str, open_fields: &mut Vec<OpenField>) {
+        let (para, spanning) = Self::parse_paragraph_spanning(para_xml, open_fields.len());
+        let Some(para) = para else {
+            // A field ending in an empty paragraph ends with the paragraph before it
+            for _ in spanning.closed {
+                let end = self.paragraphs.last().map_or(0, |p| p.text.chars().count());
+                if let Some(field) = open_fields.pop() {
+                    self.close_field(field, end);
                 }
             }
-
-            if !run.text.is_empty() || !run.properties.is_default() {
-                paragraph.runs.push(run);
+            return;
+        };
+        self.paragraphs.push(para);
+        let paragraph = self.paragraphs.len() - 1;
+        for (position, mut image) in spanning.drawings {
+            image.paragraph_index = paragraph;
+            image.position = position;
+            self.images.push(image);
+        }
+        for end in spanning.closed {
+            if let Some(field) = open_fields.pop() {
+                self.close_field(field, end);
             }
         }
+        open_fields.extend(
+            spanning
+                .opened
+                .into_iter()
+                .map(|(instruction, start)| OpenField { paragraph, instruction, start }),
+        );
 
-        if paragraph.runs.is_empty() {
-            return None;
+        if let Some(start) = para_xml.find("<w:sectPr") {
+            let first_paragraph = self.sections.last().map_or(0, |s| s.first_paragraph + s.paragraph_count);
+            let mut section = Self::parse_section_properties(&para_xml[start..]);
+            section.first_paragraph = first_paragraph;
+            section.paragraph_count = self.paragraphs.len() - first_paragraph;
+            self.sections.push(section);
         }
+    }
 
-        paragraph.text = paragraph.runs
-            .iter()
-            .map(|r| r.text.clone())
-            .collect();
-        Some(paragraph)
+    /// Give the paragraph an open field starts in the field, with a result that
+    /// runs to char `end` of the last body paragraph
+    fn close_field(&mut self, field: OpenField, end: usize) {
+        let last = self.paragraphs.len() - 1;
+        let mut result = Self::char_slice(&self.paragraphs[field.paragraph].text, field.start, usize::MAX);
+        for paragraph in &self.paragraphs[field.paragraph + 1..last] {
+            result.push('\n');
+            result.push_str(&paragraph.text);
+        }
+        if last > field.paragraph {
+            result.push('\n');
+            result.push_str(&Self::char_slice(&self.paragraphs[last].text, 0, end));
+        }
+        let paragraph = &mut self.paragraphs[field.paragraph];
+        paragraph.fields.push(Field::new(&field.instruction, field.start, &result));
+        paragraph.fields.sort_by_key(|f| f.start);
     }
 
-    /// Parse tables from document XML
-    fn parse_tables(&mut self, xml_str: &str, _package: &OpcPackage) {
-        let table_pattern = regex::Regex::new(r#"<w:tbl[^>]*>(.*?)</w:tbl>"#).unwrap();
+    /// Parse section properties from XML starting at a w:sectPr element
+    pub(super) fn parse_section_properties(xml: &str) -> Section {
+        // Only look inside this sectPr, which may be self-closing
+        let end = match (xml.find("/>"), xml.find('>')) {
+            (Some(close), Some(open)) if close + 1 == open => open + 1,
+            _ => xml.find("</w:sectPr>").map_or(xml.len(), |end| end + "</w:sectPr>".len()),
+        };
+        let xml = &xml[..end];
 
-        for table_cap in table_pattern.captures(xml_str) {
-            let table_xml = match table_cap.get(1) {
-                Some(m) => m.as_str(),
-                None => continue,
-            };
+        let attribute = |element: &str, name: &str| {
+            regex::Regex::new(&format!(r#"<w:{}\b[^>]*\sw:{}="([^"]*)""#, element, name))
+                .unwrap()
+                .captures(xml)
+                .map(|caps| unescape_xml_text(&caps[1]))
+        };
+        let twips = |element: &str, name: &str| attribute(element, name).and_then(|v| v.parse::<i32>().ok());
+        let references = |element: &str| {
+            regex::Regex::new(&format!(r#"<w:{}\b([^>]*)/?>"#, element))
+                .unwrap()
+                .captures_iter(xml)
+                .filter_map(|caps| {
+                    let attr = |name: &str| {
+                        regex::Regex::new(&format!(r#"\b{}="([^"]*)""#, name))
+                            .unwrap()
+                            .captures(&caps[1])
+                            .map(|c| c[1].to_string())
+                    };
+                    Some(HeaderFooterReference {
+                        kind: attr("w:type").unwrap_or_else(|| "default".to_string()),
+                        id: attr("r:id")?,
+                    })
+                })
+                .collect()
+        };
 
-            let mut table = Table::default();
-            table.properties = self.parse_table_properties(table_xml);
+        Section {
+            page_width: twips("pgSz", "w"),
+            page_height: twips("pgSz", "h"),
+            landscape: attribute("pgSz", "orient").as_deref() == Some("landscape"),
+            margin_top: twips("pgMar", "top"),
+            margin_right: twips("pgMar", "right"),
+            margin_bottom: twips("pgMar", "bottom"),
+            margin_left: twips("pgMar", "left"),
+            header_distance: twips("pgMar", "header"),
+            footer_distance: twips("pgMar", "footer"),
+            gutter: twips("pgMar", "gutter"),
+            columns: attribute("cols", "num").and_then(|v| v.parse().ok()).unwrap_or(1).max(1),
+            column_space: twips("cols", "space"),
+            title_page: regex::Regex::new(r#"<w:titlePg\b([^>]*)/?>"#)
+                .unwrap()
+                .captures(xml)
+                .is_some_and(|caps| Self::toggle_value(&caps[1])),
+            header_references: references("headerReference"),
+            footer_references: references("footerReference"),
+            other_children: Self::unmodeled_section_children(xml),
+            ..Default::default()
+        }
+    }
 
-            // Parse table rows
-            let row_pattern = regex::Regex::new(r#"<w:tr[^>]*>(.*?)</w:tr>"#).unwrap();
-            for row_cap in row_pattern.captures(table_xml) {
-                let row_xml = match row_cap.get(1) {
-                    Some(m) => m.as_str(),
-                    None => continue,
-                };
+    /// Children of a whole w:sectPr element that [`Section`] has no fields for, as XML
+    fn unmodeled_section_children(xml: &str) -> Vec<String> {
+        const MODELED: &[&str] = &["headerReference", "footerReference", "pgSz", "pgMar", "cols", "titlePg"];
+        let mut rest = match (xml.find('>'), xml.rfind("</w:sectPr>")) {
+            (Some(open), Some(close)) if open < close => &xml[open + 1..close],
+            _ => return Vec::new(),
+        };
+        let mut children = Vec::new();
+        while let Some(start) = rest.find("<w:") {
+            let Some(tag_end) = rest[start..].find('>').map(|end| start + end + 1) else {
+                break;
+            };
+            let name: String = rest[start + 3..].chars().take_while(|c| c.is_ascii_alphanumeric()).collect();
+            let end = if rest[..tag_end].ends_with("/>") {
+                tag_end
+            } else {
+                let close = format!("</w:{}>", name);
+                rest[tag_end..].find(&close).map_or(rest.len(), |end| tag_end + end + close.len())
+            };
+            if !MODELED.contains(&name.as_str()) {
+                children.push(rest[start..end].to_string());
+            }
+            rest = &rest[end..];
+        }
+        children
+    }
+
+    /// Parse a single paragraph from XML
+    pub(super) fn parse_paragraph(para_xml: &str) -> Option<Paragraph> {
+        Self::parse_paragraph_spanning(para_xml, 0).0
+    }
 
-                let mut row = TableRow::default();
-                row.properties = self.parse_table_row_properties(row_xml);
+    /// Parse a paragraph that may end `carried` fields left open by the ones
+    /// before it, or leave fields open itself
+    ///
+    /// Only fields whose result has started are left open; such a field is not
+    /// in the paragraph's `fields` until it is closed.
+    pub(super) fn parse_paragraph_spanning(para_xml: &str, carried: usize) -> (Option<Paragraph>, SpanningFields) {
+        let mut spanning = SpanningFields::default();
+        let mut paragraph = Paragraph::default();
 
-                // Parse table cells
-                let cell_pattern = regex::Regex::new(r#"<w:tc[^>]*>(.*?)</w:tc>"#).unwrap();
-                for cell_cap in cell_pattern.captures(row_xml) {
-                    let cell_xml = match cell_cap.get(1) {
-                        Some(m) => m.as_str(),
-                        None => continue,
+        // A paragraph format change nests the old w:pPr inside the current one
+        let ppr_change_pattern = regex::Regex::new(r#"(?s)<w:pPrChange\b([^>]*)>(.*?)</w:pPrChange>"#).unwrap();
+        let ppr_change = ppr_change_pattern
+            .captures(para_xml)
+            .map(|caps| (caps[1].to_string(), caps[2].to_string()));
+        let para_xml = &*ppr_change_pattern.replace(para_xml, "");
+
+        // Runs, simple-field, revision, comment range, bookmark and hyperlink boundaries,
+        // equations, and the properties and content end of content controls, in document order
+        let token_pattern = regex::Regex::new(
+            r#"(?s)<w:fldSimple\b([^>]*?)(/?)>|</w:fldSimple>|<w:r\b[^>]*>(.*?)</w:r>|<w:(ins|del)\b([^>]*?)(/?)>|</w:(?:ins|del)>|<w:commentRange(Start|End)\b([^>]*?)/?>|<w:bookmark(Start|End)\b([^>]*?)/?>|<w:hyperlink\b([^>]*?)(/?)>|</w:hyperlink>|<m:oMath\b[^>]*>(.*?)</m:oMath>|<w:sdtPr\b[^>]*>(.*?)</w:sdtPr>|</w:sdtContent>"#,
+        ).unwrap();
+        // Deleted runs keep their text in w:delText
+        let rpr_change_pattern = regex::Regex::new(r#"(?s)<w:rPrChange\b([^>]*)>(.*?)</w:rPrChange>"#).unwrap();
+        let instr_pattern = regex::Regex::new(r#"<w:instrText[^>]*>([^<]*)</w:instrText>"#).unwrap();
+        let fld_char_pattern = regex::Regex::new(r#"<w:fldChar\b[^>]*w:fldCharType="(\w+)""#).unwrap();
+        let ff_data_pattern = regex::Regex::new(r#"(?s)<w:ffData>(.*?)</w:ffData>"#).unwrap();
+        let instr_attr_pattern = regex::Regex::new(r#"w:instr="([^"]*)""#).unwrap();
+        let rpr_pattern = regex::Regex::new(r#"(?s)<w:rPr[^>]*>(.*?)</w:rPr>"#).unwrap();
+        let note_ref_pattern = regex::Regex::new(
+            r#"<w:(footnote|endnote)Reference\b[^>]*w:id="([^"]*)""#,
+        ).unwrap();
+        let comment_ref_pattern = regex::Regex::new(r#"<w:commentReference\b[^>]*w:id="([^"]*)""#).unwrap();
+        let drawing_pattern = regex::Regex::new(r#"(?s)<w:drawing\b.*?</w:drawing>"#).unwrap();
+
+        // Open complex fields: instruction so far, where the result started,
+        // and the w:ffData of a legacy form field
+        let mut complex_fields: Vec<(String, Option<usize>, Option<String>)> = Vec::new();
+        // Open content control: its properties and where its content started
+        let mut open_control: Option<(String, usize)> = None;
+        let mut simple_field: Option<(String, usize)> = None;
+        // Open w:ins or w:del: the revision so far
+        let mut open_revision: Option<Revision> = None;
+        let mut open_hyperlink: Option<Hyperlink> = None;
+        let mut char_len = 0usize;
+
+        for token in token_pattern.captures_iter(para_xml) {
+            let whole = token.get(0).map_or("", |m| m.as_str());
+
+            if let Some(element) = token.get(4) {
+                // Self-closing marks only flag the paragraph mark itself as changed
+                if &token[6] != "/" {
+                    let kind = if element.as_str() == "ins" {
+                        RevisionKind::Insertion
+                    } else {
+                        RevisionKind::Deletion
                     };
+                    open_revision = Some(Self::revision(kind, &token[5], char_len));
+                }
+                continue;
+            }
+
+            if let Some(boundary) = token.get(7) {
+                let kind = if boundary.as_str() == "Start" {
+                    CommentMarkKind::RangeStart
+                } else {
+                    CommentMarkKind::RangeEnd
+                };
+                paragraph.comment_marks.push(CommentMark {
+                    kind,
+                    id: Self::attribute(&token[8], "w:id").unwrap_or_default(),
+                    position: char_len,
+                });
+                continue;
+            }
 
-                    let cell = self.parse_table_cell(cell_xml);
-                    row.cells.push(cell);
+            if let Some(boundary) = token.get(9) {
+                let kind = if boundary.as_str() == "Start" {
+                    BookmarkMarkKind::Start
+                } else {
+                    BookmarkMarkKind::End
+                };
+                paragraph.bookmark_marks.push(BookmarkMark {
+                    kind,
+                    id: Self::attribute(&token[10], "w:id").unwrap_or_default(),
+                    name: Self::attribute(&token[10], "w:name"),
+                    position: char_len,
+                });
+                continue;
+            }
+
+            if let Some(attributes) = token.get(11) {
+                let attributes = attributes.as_str();
+                if &token[12] != "/" {
+                    open_hyperlink = Some(Hyperlink {
+                        relationship_id: Self::attribute(attributes, "r:id"),
+                        anchor: Self::attribute(attributes, "w:anchor"),
+                        tooltip: Self::attribute(attributes, "w:tooltip"),
+                        start: char_len,
+                        ..Default::default()
+                    });
                 }
+                continue;
+            }
 
-                if !row.cells.is_empty() {
-                    table.rows.push(row);
+            if whole == "</w:hyperlink>" {
+                if let Some(mut hyperlink) = open_hyperlink.take() {
+                    hyperlink.length = char_len - hyperlink.start;
+                    if hyperlink.length > 0 {
+                        paragraph.hyperlinks.push(hyperlink);
+                    }
                 }
+                continue;
             }
 
-            if !table.rows.is_empty() {
-                self.tables.push(table);
+            // An equation's text is its linear form
+            if let Some(omml) = token.get(13) {
+                let linear = math::to_linear(&math::from_omml(omml.as_str()));
+                let length = linear.chars().count();
+                paragraph.math_zones.push(MathZone { start: char_len, length });
+                if length > 0 {
+                    char_len += length;
+                    paragraph.text.push_str(&linear);
+                    paragraph.runs.push(Run {
+                        text: linear,
+                        ..Default::default()
+                    });
+                }
+                continue;
             }
-        }
-    }
 
-    /// Parse table properties from XML
-    fn parse_table_properties(&self, table_xml: &str) -> TableProperties {
-        let mut props = TableProperties::default();
+            if let Some(props) = token.get(14) {
+                open_control = Some((props.as_str().to_string(), char_len));
+                continue;
+            }
 
-        // Parse table width
-        if let Some(caps) = regex::Regex::new(r#"<w:tblW[^>]*w:w="([^"]*)""#).unwrap().captures(table_xml) {
-            if let Some(m) = caps.get(1) {
-                props.width = m.as_str().parse().ok();
+            if whole == "</w:sdtContent>" {
+                if let Some((props, start)) = open_control.take() {
+                    let text = Self::char_slice(&paragraph.text, start, char_len);
+                    if let Some(field) = forms::control_field(&props, &text) {
+                        paragraph.form_fields.push(AnchoredFormField {
+                            field,
+                            start,
+                            length: char_len - start,
+                            placeholder: forms::is_showing_placeholder(&props),
+                        });
+                    }
+                }
+                continue;
             }
-        }
 
-        // Parse table alignment
-        if let Some(caps) = regex::Regex::new(r#"<w:jc[^>]*w:val="([^"]*)""#).unwrap().captures(table_xml) {
-            if let Some(m) = caps.get(1) {
-                props.alignment = Some(m.as_str().to_string());
+            if whole == "</w:ins>" || whole == "</w:del>" {
+                if let Some(mut revision) = open_revision.take() {
+                    revision.length = char_len - revision.start;
+                    paragraph.revisions.push(revision);
+                }
+                continue;
             }
-        }
 
-        // Parse table indent
-        if let Some(caps) = regex::Regex::new(r#"<w:tblInd[^>]*w:w="([^"]*)""#).unwrap().captures(table_xml) {
-            if let Some(m) = caps.get(1) {
-                props.indent = m.as_str().parse().ok();
+            if whole.starts_with("<w:fldSimple") {
+                let instruction = instr_attr_pattern
+                    .captures(&token[1])
+                    .map(|c| unescape_xml_text(&c[1]))
+                    .unwrap_or_default();
+                if &token[2] == "/" {
+                    paragraph.fields.push(Field::new(&instruction, char_len, ""));
+                } else {
+                    simple_field = Some((instruction, char_len));
+                }
+                continue;
             }
-        }
 
-        // Parse table layout
-        if let Some(caps) = regex::Regex::new(r#"<w:tblLayout[^>]*w:type="([^"]*)""#).unwrap().captures(table_xml) {
-            if let Some(m) = caps.get(1) {
-                props.layout = Some(m.as_str().to_string());
+            if whole == "</w:fldSimple>" {
+                if let Some((instruction, start)) = simple_field.take() {
+                    let result = Self::char_slice(&paragraph.text, start, char_len);
+                    paragraph.fields.push(Field::new(&instruction, start, &result));
+                }
+                continue;
             }
-        }
 
-        // Parse table borders
-        props.borders = self.parse_table_borders(table_xml);
+            let run_xml = token.get(3).map_or("", |m| m.as_str());
+
+            if let Some(fld_char) = fld_char_pattern.captures(run_xml) {
+                match &fld_char[1] {
+                    "begin" => complex_fields.push((
+                        String::new(),
+                        None,
+                        ff_data_pattern.captures(run_xml).map(|caps| caps[1].to_string()),
+                    )),
+                    "separate" => {
+                        if let Some(field) = complex_fields.last_mut() {
+                            field.1 = Some(char_len);
+                        }
+                    }
+                    "end" => {
+                        if let Some((instruction, start, ff_data)) = complex_fields.pop() {
+                            let start = start.unwrap_or(char_len);
+                            let result = Self::char_slice(&paragraph.text, start, char_len);
+                            match ff_data {
+                                Some(ff_data) => paragraph.form_fields.push(AnchoredFormField {
+                                    field: forms::legacy_field(&ff_data, &instruction, &result),
+                                    start,
+                                    length: char_len - start,
+                                    placeholder: false,
+                                }),
+                                None => paragraph.fields.push(Field::new(&instruction, start, &result)),
+                            }
+                        } else if spanning.closed.len() < carried {
+                            spanning.closed.push(char_len);
+                        }
+                    }
+                    _ => {}
+                }
+            }
 
-        props
-    }
+            for instr_cap in instr_pattern.captures_iter(run_xml) {
+                if let Some(field) = complex_fields.last_mut() {
+                    field.0.push_str(&unescape_xml_text(&instr_cap[1]));
+                }
+            }
 
-    /// Parse table borders from XML
-    fn parse_table_borders(&self, table_xml: &str) -> TableBorders {
-        let mut borders = TableBorders::default();
-
-        // Helper to parse individual border
-        let parse_border = |xml: &str, tag: &str| -> Option<TableBorder> {
-            // Simplified border parsing
-            if let Some(caps) = regex::Regex::new(&format!(r#"<w:{}[^>]*w:val="([^"]*)"[^>]*w:sz="([^"]*)"[^>]*w:fill="([^"]*)""#, tag)).unwrap().captures(xml) {
-                return Some(TableBorder {
-                    style: caps.get(1).map(|m| m.as_str().to_string()),
-                    size: caps.get(2).and_then(|m| m.as_str().parse().ok()),
-                    color: caps.get(3).map(|m| {
-                        let color = m.as_str().to_string();
-                        if color.is_empty() { None } else { Some(color) }
-                    }).unwrap_or(None),
+            for note_cap in note_ref_pattern.captures_iter(run_xml) {
+                let kind = if &note_cap[1] == "footnote" {
+                    NoteKind::Footnote
+                } else {
+                    NoteKind::Endnote
+                };
+                paragraph.note_references.push(NoteReference {
+                    kind,
+                    id: note_cap[2].to_string(),
+                    position: char_len,
                 });
             }
-            None
-        };
 
-        borders.top = parse_border(table_xml, "top");
-        borders.bottom = parse_border(table_xml, "bottom");
-        borders.left = parse_border(table_xml, "left");
-        borders.right = parse_border(table_xml, "right");
-        borders.inside_horizontal = parse_border(table_xml, "insideH");
-        borders.inside_vertical = parse_border(table_xml, "insideV");
+            if let Some(drawing) = drawing_pattern.find(run_xml) {
+                spanning.drawings.extend(Self::parse_drawing(drawing.as_str()).map(|image| (char_len, image)));
+            }
 
-        borders
-    }
+            for comment_cap in comment_ref_pattern.captures_iter(run_xml) {
+                paragraph.comment_marks.push(CommentMark {
+                    kind: CommentMarkKind::Reference,
+                    id: comment_cap[1].to_string(),
+                    position: char_len,
+                });
+            }
 
-    /// Parse table row properties from XML
-    fn parse_table_row_properties(&self, row_xml: &str) -> TableRowProperties {
-        let mut props = TableRowProperties::default();
+            // Parse text in run
+            let mut run = Run {
+                text: read_text(run_xml, true),
+                ..Default::default()
+            };
 
-        // Parse row height
-        if let Some(caps) = regex::Regex::new(r#"<w:trHeight[^>]*w:h="([^"]*)""#).unwrap().captures(row_xml) {
-            if let Some(m) = caps.get(1) {
-                props.height = m.as_str().parse().ok();
+            // Parse run properties, setting aside the formatting a tracked change replaced
+            let format_change = rpr_change_pattern.captures(run_xml).map(|caps| {
+                let mut revision = Self::revision(RevisionKind::RunFormat, &caps[1], char_len);
+                let mut previous = RunProperties::default();
+                Self::parse_run_properties(&caps[2], &mut previous);
+                revision.previous_run_properties = Some(previous);
+                revision
+            });
+            let run_xml = &*rpr_change_pattern.replace(run_xml, "");
+            if let Some(rpr_cap) = rpr_pattern.captures(run_xml) {
+                if let Some(rpr_xml) = rpr_cap.get(1) {
+                    Self::parse_run_properties(rpr_xml.as_str(), &mut run.properties);
+                }
             }
-        }
 
-        // Parse height rule
-        if let Some(caps) = regex::Regex::new(r#"<w:trHeight[^>]*w:hrule="([^"]*)""#).unwrap().captures(row_xml) {
-            if let Some(m) = caps.get(1) {
-                props.height_rule = Some(m.as_str().to_string());
+            if !run.text.is_empty() || !run.properties.is_default() {
+                if let Some(mut revision) = format_change {
+                    revision.length = run.text.chars().count();
+                    paragraph.revisions.push(revision);
+                }
+                char_len += run.text.chars().count();
+                paragraph.text.push_str(&run.text);
+                paragraph.runs.push(run);
             }
         }
 
-        // Check if header row
-        props.is_header = row_xml.contains("<w:tblHeader");
+        spanning.opened = complex_fields
+            .into_iter()
+            .filter_map(|(instruction, start, _)| Some((instruction, start?)))
+            .collect();
+        if paragraph.runs.is_empty()
+            && paragraph.note_references.is_empty()
+            && paragraph.math_zones.is_empty()
+            && paragraph.form_fields.is_empty()
+            && spanning.drawings.is_empty()
+        {
+            return (None, spanning);
+        }
 
-        props
-    }
+        Self::parse_paragraph_mark(para_xml, &mut paragraph);
+        if let Some((attributes, previous_xml)) = ppr_change {
+            let mut revision = Self::revision(RevisionKind::ParagraphFormat, &attributes, 0);
+            revision.length = char_len;
+            let mut previous = ParagraphProperties::default();
+            Self::parse_paragraph_properties(&previous_xml, &mut previous);
+            revision.previous_paragraph_properties = Some(previous);
+            paragraph.revisions.push(revision);
+        }
+        paragraph.revisions.sort_by_key(|revision| revision.start);
 
-    /// Parse table cell from XML
-    fn parse_table_cell(&self, cell_xml: &str) -> TableCell {
-        let mut cell = TableCell::default();
+        paragraph.fields.sort_by_key(|f| f.start);
+        (Some(paragraph), spanning)
+    }
 
-        // Parse cell width
-        if let Some(caps) = regex::Regex::new(r#"<w:tcW[^>]*w:w="([^"]*)""#).unwrap().captures(cell_xml) {
-            if let Some(m) = caps.get(1) {
-                cell.width = m.as_str().parse().ok();
-            }
+    /// Give a paragraph the properties in the w:pPr its content starts with,
+    /// and the formatting of its mark (w:pPr/w:rPr)
+    fn parse_paragraph_mark(para_xml: &str, paragraph: &mut Paragraph) {
+        // A format change nests the old w:pPr, whose end would end this one early
+        let para_xml = regex::Regex::new(r#"(?s)<w:pPrChange\b.*?</w:pPrChange>"#).unwrap().replace(para_xml, "");
+        let Some(ppr_cap) = regex::Regex::new(r#"(?s)^\s*<w:pPr>(.*?)</w:pPr>"#).unwrap().captures(&para_xml) else {
+            return;
+        };
+        Self::parse_paragraph_properties(&ppr_cap[1], &mut paragraph.properties);
+        let mark_xml = regex::Regex::new(r#"(?s)<w:rPrChange\b.*?</w:rPrChange>"#).unwrap().replace(&ppr_cap[1], "");
+        if let Some(rpr_cap) = regex::Regex::new(r#"(?s)<w:rPr>(.*?)</w:rPr>"#).unwrap().captures(&mark_xml) {
+            let mut mark = RunProperties::default();
+            Self::parse_run_properties(&rpr_cap[1], &mut mark);
+            paragraph.mark_properties = (!mark.is_default()).then_some(mark);
         }
+    }
 
-        // Parse vertical merge
-        if let Some(caps) = regex::Regex::new(r#"<w:vMerge[^>]*w:val="([^"]*)""#).unwrap().captures(cell_xml) {
-            if let Some(m) = caps.get(1) {
-                cell.vertical_merge = Some(match m.as_str() {
-                    "restart" => 1,
-                    "continue" => -1,
-                    _ => 0,
-                });
-            }
+    /// A revision starting at `start` from the attributes of its w:ins, w:del or w:*PrChange element
+    fn revision(kind: RevisionKind, attributes: &str, start: usize) -> Revision {
+        Revision {
+            id: Self::attribute(attributes, "w:id").unwrap_or_default(),
+            kind,
+            author: Self::attribute(attributes, "w:author"),
+            date: Self::attribute(attributes, "w:date"),
+            start,
+            length: 0,
+            previous_run_properties: None,
+            previous_paragraph_properties: None,
         }
+    }
 
-        // Parse horizontal merge
-        if let Some(caps) = regex::Regex::new(r#"<w:hMerge[^>]*w:val="([^"]*)""#).unwrap().captures(cell_xml) {
-            if let Some(m) = caps.get(1) {
-                cell.horizontal_merge = Some(match m.as_str() {
-                    "restart" => 1,
-                    "continue" => -1,
-                    _ => 0,
-                });
-            }
-        }
+    /// Value of the attribute with qualified `name` in an element's attribute text
+    pub(super) fn attribute(attributes: &str, name: &str) -> Option<String> {
+        regex::Regex::new(&format!(r#"\b{}="([^"]*)""#, regex::escape(name)))
+            .unwrap()
+            .captures(attributes)
+            .map(|caps| unescape_xml_text(&caps[1]))
+    }
 
-        // Parse paragraphs in cell
-        let para_pattern = regex::Regex::new(r#"<w:p[^>]*>(.*?)</w:p>"#).unwrap();
-        for para_cap in para_pattern.captures(cell_xml) {
-            if let Some(para_xml) = para_cap.get(1) {
-                if let Some(para) = self.parse_paragraph(para_xml.as_str()) {
-                    cell.paragraphs.push(para);
-                }
+    /// Take chars [start, end) of a string
+    fn char_slice(text: &str, start: usize, end: usize) -> String {
+        text.chars().skip(start).take(end.saturating_sub(start)).collect()
+    }
+
+    /// Parse a table from its w:tbl element; None if it has no cells
+    pub(super) fn parse_table(table_xml: &str) -> Option<Table> {
+        let row_pattern = regex::Regex::new(r#"(?s)<w:tr\b[^>]*>(.*?)</w:tr>"#).unwrap();
+        let cell_pattern = regex::Regex::new(r#"(?s)<w:tc\b[^>]*>(.*?)</w:tc>"#).unwrap();
+
+        let mut table = Table {
+            properties: Self::parse_table_properties(table_xml),
+            ..Default::default()
+        };
+        for row_cap in row_pattern.captures_iter(table_xml) {
+            let row_xml = &row_cap[1];
+            let cells: Vec<TableCell> = cell_pattern
+                .captures_iter(row_xml)
+                .map(|cell_cap| Self::parse_table_cell(&cell_cap[1]))
+                .collect();
+            if !cells.is_empty() {
+                let properties = Self::parse_table_row_properties(row_xml);
+                table.rows.push(TableRow {
+                    cells,
+                    height: properties.height,
+                    properties,
+                });
             }
         }
+        (!table.rows.is_empty()).then_some(table)
+    }
 
-        cell
+    /// The content of the first `element` in `xml`, if it has any
+    fn element_content<'a>(xml: &'a str, element: &str) -> Option<&'a str> {
+        let open = format!("<w:{}>", element);
+        let start = xml.find(&open)? + open.len();
+        let end = xml[start..].find(&format!("</w:{}>", element))?;
+        Some(&xml[start..start + end])
     }
 
-    /// Parse inline images from document XML
-    fn parse_inline_images(&mut self, xml_str: &str, package: &OpcPackage) {
-        // Parse drawing elements with inline pictures
-        let drawing_pattern = regex::Regex::new(
-            r#"<w:drawing[^>]*>.*?<wp:inline[^>]*>.*?(?:<wp:blipFill>.*?<a:blip[^>]*r:embed="([^"]*)"[^>]*>.*?</wp:blipFill>).*?</wp:inline>.*?</w:drawing>"#
-        ).unwrap();
+    /// Value of attribute `name` of the first `element` in `xml`
+    fn element_attribute(xml: &str, element: &str, name: &str) -> Option<String> {
+        let caps = regex::Regex::new(&format!(r#"<w:{}\b([^>]*)>"#, element)).unwrap().captures(xml)?;
+        Self::attribute(&caps[1], &format!("w:{}", name))
+    }
 
-        for cap in drawing_pattern.captures(xml_str) {
-            if let Some(embed_id) = cap.get(1) {
-                let embed_id = embed_id.as_str().to_string();
+    /// Parse table properties from the w:tblPr and w:tblGrid of a table
+    fn parse_table_properties(table_xml: &str) -> TableProperties {
+        let grid_columns = Self::element_content(table_xml, "tblGrid")
+            .map(|grid| {
+                regex::Regex::new(r#"<w:gridCol\b[^>]*\sw:w="(\d+)""#)
+                    .unwrap()
+                    .captures_iter(grid)
+                    .filter_map(|caps| caps[1].parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let Some(xml) = Self::element_content(table_xml, "tblPr") else {
+            return TableProperties {
+                grid_columns,
+                ..Default::default()
+            };
+        };
 
-                // Look up the image in relationships
-                let image = self.resolve_image_reference(package, &embed_id);
-                if let Some(img) = image {
-                    self.images.push(img);
-                }
-            }
+        TableProperties {
+            width: Self::element_attribute(xml, "tblW", "w").and_then(|v| v.parse().ok()),
+            alignment: Self::element_attribute(xml, "jc", "val"),
+            borders: Self::element_content(xml, "tblBorders").map(Self::parse_table_borders).unwrap_or_default(),
+            indent: Self::element_attribute(xml, "tblInd", "w").and_then(|v| v.parse().ok()),
+            layout: Self::element_attribute(xml, "tblLayout", "type"),
+            grid_columns,
         }
     }
 
-    /// Resolve image reference from relationships
-    fn resolve_image_reference(&self, package: &OpcPackage, embed_id: &str) -> Option<DocumentImage> {
-        // Get document relationships
-        let doc_rels_part = "/word/_rels/document.xml.rels";
+    /// Parse the content of a w:tblBorders element
+    fn parse_table_borders(xml: &str) -> TableBorders {
+        let border = |edge: &str| {
+            let style = Self::element_attribute(xml, edge, "val")?;
+            Some(TableBorder {
+                style: Some(style),
+                size: Self::element_attribute(xml, edge, "sz").and_then(|v| v.parse().ok()),
+                color: Self::element_attribute(xml, edge, "color").filter(|color| !color.is_empty()),
+            })
+        };
+        TableBorders {
+            top: border("top"),
+            bottom: border("bottom"),
+            left: border("left").or_else(|| border("start")),
+            right: border("right").or_else(|| border("end")),
+            inside_horizontal: border("insideH"),
+            inside_vertical: border("insideV"),
+        }
+    }
 
-        let rels_part = if let Some(part) = package.get_part(doc_rels_part) {
-            part
-        } else {
-            return None;
+    /// Parse table row properties from the w:trPr of a row
+    fn parse_table_row_properties(row_xml: &str) -> TableRowProperties {
+        let Some(xml) = Self::element_content(row_xml, "trPr") else {
+            return TableRowProperties::default();
         };
+        TableRowProperties {
+            height: Self::element_attribute(xml, "trHeight", "val").and_then(|v| v.parse().ok()),
+            height_rule: Self::element_attribute(xml, "trHeight", "hRule"),
+            is_header: regex::Regex::new(r#"<w:tblHeader\b([^>]*)/?>"#)
+                .unwrap()
+                .captures(xml)
+                .is_some_and(|caps| Self::toggle_value(&caps[1])),
+        }
+    }
 
-        let rels_xml = String::from_utf8_lossy(&rels_part.data);
+    /// Parse a table cell from the content of its w:tc element
+    fn parse_table_cell(cell_xml: &str) -> TableCell {
+        // A merge without a value continues the merged cell
+        let merge = |element: &str| {
+            regex::Regex::new(&format!(r#"<w:{}\b([^>]*)/?>"#, element))
+                .unwrap()
+                .captures(cell_xml)
+                .map(|caps| match Self::attribute(&caps[1], "w:val").as_deref() {
+                    Some("restart") => 1,
+                    _ => -1,
+                })
+        };
+        let properties = match Self::element_content(cell_xml, "tcPr") {
+            Some(xml) => TableCellProperties {
+                width: Self::element_attribute(xml, "tcW", "w").and_then(|v| v.parse().ok()),
+                vertical_alignment: Self::element_attribute(xml, "vAlign", "val"),
+                text_direction: Self::element_attribute(xml, "textDirection", "val"),
+                shading_color: Self::element_attribute(xml, "shd", "fill").filter(|fill| fill != "auto"),
+                grid_span: Self::element_attribute(xml, "gridSpan", "val").and_then(|v| v.parse().ok()),
+            },
+            None => TableCellProperties::default(),
+        };
 
-        // Find the relationship with this ID
-        // Escape special regex characters in embed_id
-        let escaped_id = regex::escape(embed_id);
-        let rel_pattern = format!(
-            r#"<Relationship[^>]*Id="{}"[^>]*Target="([^"]*)""#,
-            escaped_id
-        );
+        let para_pattern = regex::Regex::new(r#"(?s)<w:p\b[^>]*>(.*?)</w:p>"#).unwrap();
+        TableCell {
+            paragraphs: para_pattern
+                .captures_iter(cell_xml)
+                .filter_map(|caps| Self::parse_paragraph(&caps[1]))
+                .collect(),
+            width: properties.width,
+            vertical_merge: merge("vMerge"),
+            horizontal_merge: merge("hMerge"),
+            properties,
+        }
+    }
 
-        if let Some(caps) = regex::Regex::new(&rel_pattern).unwrap().captures(&rels_xml) {
-            if let Some(target) = caps.get(1) {
-                let target = target.as_str().to_string();
-                let image_path = format!("/word/{}", target);
-
-                // Get the image part
-                if let Some(image_part) = package.get_part(&image_path) {
-                    let _content_type = &image_part.content_type;
-
-                    return Some(DocumentImage {
-                        id: embed_id.to_string(),
-                        path: target,
-                        original_width: None,
-                        original_height: None,
-                        desired_width: None,
-                        desired_height: None,
-                        scale_x: None,
-                        scale_y: None,
-                        title: None,
-                        alt_description: None,
-                        is_linked: false,
-                    });
-                }
+    /// An image from a w:drawing element: its relationship, size, description
+    /// and, for a floating image, its anchor
+    fn parse_drawing(xml: &str) -> Option<DocumentImage> {
+        let blip = regex::Regex::new(r#"<a:blip\b[^>]*\br:(embed|link)="([^"]*)""#).unwrap().captures(xml)?;
+        let attributes = |element: &str| {
+            regex::Regex::new(&format!(r#"<{}\b([^>]*)>"#, element))
+                .unwrap()
+                .captures(xml)
+                .map(|caps| caps[1].to_string())
+        };
+        let extent = attributes("wp:extent").unwrap_or_default();
+        let emus = |name: &str| Self::attribute(&extent, name).and_then(|v| v.parse().ok());
+        let doc_pr = attributes("wp:docPr").unwrap_or_default();
+
+        let anchor = attributes("wp:anchor").map(|anchor| {
+            let position = |axis: &str| {
+                regex::Regex::new(&format!(
+                    r#"(?s)<wp:position{}\b[^>]*relativeFrom="([^"]*)"[^>]*>.*?<wp:posOffset>(-?\d+)</wp:posOffset>"#,
+                    axis
+                ))
+                .unwrap()
+                .captures(xml)
+                .map(|caps| (caps[1].to_string(), caps[2].parse().unwrap_or(0)))
+            };
+            let defaults = ImageAnchor::default();
+            let (horizontal_relative_to, horizontal_offset) =
+                position("H").unwrap_or((defaults.horizontal_relative_to, defaults.horizontal_offset));
+            let (vertical_relative_to, vertical_offset) =
+                position("V").unwrap_or((defaults.vertical_relative_to, defaults.vertical_offset));
+            let wrap = regex::Regex::new(r#"<wp:wrap(Square|Tight|Through|TopAndBottom|None)\b"#)
+                .unwrap()
+                .captures(xml)
+                .map(|caps| {
+                    let name = &caps[1];
+                    name[..1].to_lowercase() + &name[1..]
+                })
+                .unwrap_or(defaults.wrap);
+            ImageAnchor {
+                horizontal_offset,
+                horizontal_relative_to,
+                vertical_offset,
+                vertical_relative_to,
+                wrap,
+                behind_text: Self::attribute(&anchor, "behindDoc").is_some_and(|v| matches!(v.as_str(), "1" | "true" | "on")),
+                z_order: Self::attribute(&anchor, "relativeHeight").and_then(|v| v.parse().ok()).unwrap_or(0),
+                allow_overlap: Self::attribute(&anchor, "allowOverlap").is_some_and(|v| matches!(v.as_str(), "1" | "true" | "on")),
             }
-        }
+        });
+
+        // DrawingML gives percentages in thousandths and angles in 60000ths of a degree
+        let value = |attributes: &Option<String>, name: &str, default: f32| {
+            attributes.as_deref().and_then(|a| Self::attribute(a, name)).and_then(|v| v.parse::<f32>().ok()).unwrap_or(default)
+        };
+        let src_rect = attributes("a:srcRect");
+        let xfrm = attributes("a:xfrm");
+        let lum = attributes("a:lum");
+        let flag = |name: &str| xfrm.as_deref().and_then(|a| Self::attribute(a, name)).is_some_and(|v| matches!(v.as_str(), "1" | "true"));
+        let transform = ImageTransform {
+            crop: src_rect.is_some().then(|| SourceRect {
+                top: value(&src_rect, "t", 0.0) / 1000.0,
+                left: value(&src_rect, "l", 0.0) / 1000.0,
+                bottom: value(&src_rect, "b", 0.0) / 1000.0,
+                right: value(&src_rect, "r", 0.0) / 1000.0,
+            }),
+            rotation: value(&xfrm, "rot", 0.0) / 60_000.0,
+            flip_horizontal: flag("flipH"),
+            flip_vertical: flag("flipV"),
+            brightness: value(&lum, "bright", 0.0) / 1000.0,
+            contrast: value(&lum, "contrast", 0.0) / 1000.0,
+            transparency: 100.0 - value(&attributes("a:alphaModFix"), "amt", 100_000.0) / 1000.0,
+        };
+
+        Some(DocumentImage {
+            id: blip[2].to_string(),
+            desired_width: emus("cx"),
+            desired_height: emus("cy"),
+            title: Self::attribute(&doc_pr, "title"),
+            alt_description: Self::attribute(&doc_pr, "descr"),
+            is_linked: &blip[1] == "link",
+            anchor,
+            transform,
+            ..Default::default()
+        })
+    }
 
-        None
+    /// Give the body's images the package paths their relationships point to,
+    /// dropping any whose relationship is missing
+    fn resolve_images(&mut self, package: &OpcPackage) {
+        let relationships = package.get_relationships("/word/document.xml").cloned().unwrap_or_default();
+        self.images.retain_mut(|image| {
+            let Some(rel) = relationships.iter().find(|rel| rel.id == image.id) else {
+                return false;
+            };
+            image.path = rel.target.clone();
+            image.is_linked = rel.target_mode.as_deref() == Some("External");
+            true
+        });
     }
 
     /// Parse run properties from XML
     fn parse_run_properties(xml: &str, props: &mut RunProperties) {
-        // Bold
-        if let Some(caps) = regex::Regex::new(r#"<w:b[^>]*val="([^"]*)""#).unwrap().captures(xml) {
-            if let Some(m) = caps.get(1) {
-                props.bold = Some(m.as_str() != "0");
-            }
+        // Bold; a bare <w:b/> turns it on
+        if let Some(caps) = regex::Regex::new(r#"<w:b(\s[^>]*)?/?>"#).unwrap().captures(xml) {
+            props.bold = Some(Self::toggle_value(caps.get(1).map_or("", |m| m.as_str())));
         }
         
         // Italic
-        if let Some(caps) = regex::Regex::new(r#"<w:i[^>]*val="([^"]*)""#).unwrap().captures(xml) {
-            if let Some(m) = caps.get(1) {
-                props.italic = Some(m.as_str() != "0");
-            }
+        if let Some(caps) = regex::Regex::new(r#"<w:i(\s[^>]*)?/?>"#).unwrap().captures(xml) {
+            props.italic = Some(Self::toggle_value(caps.get(1).map_or("", |m| m.as_str())));
         }
         
         // Underline
@@ -493,8 +1127,83 @@ impl WordDocument {
         }
     }
 
+    /// Whether the attributes of a toggle property such as <w:b w:val="0"/> turn it on
+    fn toggle_value(attributes: &str) -> bool {
+        match regex::Regex::new(r#"w:val="([^"]*)""#).unwrap().captures(attributes) {
+            Some(caps) => !matches!(&caps[1], "0" | "false" | "off"),
+            None => true,
+        }
+    }
+
+    /// Parse paragraph properties from the content of a w:pPr element
+    fn parse_paragraph_properties(xml: &str, props: &mut ParagraphProperties) {
+        let attribute = |element: &str, name: &str| {
+            regex::Regex::new(&format!(r#"<w:{}\b[^>]*\sw:{}="([^"]*)""#, element, name))
+                .unwrap()
+                .captures(xml)
+                .map(|caps| unescape_xml_text(&caps[1]))
+        };
+        let twips = |element: &str, names: &[&str]| {
+            names.iter().find_map(|name| attribute(element, name)).and_then(|v| v.parse::<i32>().ok())
+        };
+
+        props.style_id = attribute("pStyle", "val");
+        props.alignment = attribute("jc", "val");
+        props.indent_left = twips("ind", &["left", "start"]);
+        props.indent_right = twips("ind", &["right", "end"]);
+        // A hanging indent is a negative first-line indent
+        props.indent_first_line = twips("ind", &["firstLine"]).or_else(|| twips("ind", &["hanging"]).map(|v| -v));
+        props.spacing_before = twips("spacing", &["before"]);
+        props.spacing_after = twips("spacing", &["after"]);
+        // Only auto spacing is in 240ths of a line; exact and at-least spacing are in twips
+        if attribute("spacing", "lineRule").is_none_or(|rule| rule == "auto") {
+            props.spacing_line = twips("spacing", &["line"]);
+        }
+        props.num_id = attribute("numId", "val");
+        props.list_level = attribute("ilvl", "val").and_then(|v| v.parse().ok());
+        let toggle = |element: &str| {
+            regex::Regex::new(&format!(r"<w:{}\b([^>]*?)/?>", element))
+                .unwrap()
+                .captures(xml)
+                .map(|caps| Self::toggle_value(&caps[1]))
+        };
+        props.keep_next = toggle("keepNext");
+        props.keep_lines = toggle("keepLines");
+        props.page_break_before = toggle("pageBreakBefore");
+        props.widow_control = toggle("widowControl");
+        if xml.contains("<w:framePr") {
+            props.frame = Some(ParagraphFrame {
+                x: twips("framePr", &["x"]).unwrap_or(0),
+                y: twips("framePr", &["y"]).unwrap_or(0),
+                width: twips("framePr", &["w"]),
+                height: twips("framePr", &["h"]),
+                horizontal_anchor: attribute("framePr", "hAnchor"),
+                vertical_anchor: attribute("framePr", "vAnchor"),
+            });
+        }
+        // Only the borders' own bottom line; a section's page borders have one too
+        let borders = regex::Regex::new(r"(?s)<w:pBdr>(.*?)</w:pBdr>").unwrap();
+        if let Some(caps) = borders.captures(xml) {
+            let bottom = regex::Regex::new(r"<w:bottom\b([^>]*)/?>").unwrap();
+            let value = |attributes: &str, name: &str| {
+                regex::Regex::new(&format!(r#"\sw:{}="([^"]*)""#, name))
+                    .unwrap()
+                    .captures(attributes)
+                    .map(|caps| caps[1].to_string())
+            };
+            props.border_bottom = bottom.captures(&caps[1]).and_then(|bottom| {
+                let style = value(&bottom[1], "val").filter(|style| style != "none" && style != "nil")?;
+                Some(ParagraphBorder {
+                    style,
+                    size: value(&bottom[1], "sz").and_then(|v| v.parse().ok()).unwrap_or(4),
+                    space: value(&bottom[1], "space").and_then(|v| v.parse().ok()).unwrap_or(0),
+                })
+            });
+        }
+    }
+
     /// Parse styles (word/styles.xml)
-    fn parse_styles(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
+    pub(super) fn parse_styles(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
         let styles_part_name = "/word/styles.xml";
         
         let styles_part = if let Some(part) = package.get_part(styles_part_name) {
@@ -505,54 +1214,69 @@ impl WordDocument {
 
         let xml_str = String::from_utf8_lossy(&styles_part.data);
         
-        // Parse style elements
-        let style_pattern = regex::Regex::new(
-            r#"<w:style[^>]*w:styleId="([^"]*)"[^>]*w:type="([^"]*)"[^>]*>(.*?)</w:style>"#
-        ).unwrap();
+        // Parse style elements; attributes may come in any order
+        let style_pattern = regex::Regex::new(r#"(?s)<w:style\b([^>]*)>(.*?)</w:style>"#).unwrap();
+        let id_pattern = regex::Regex::new(r#"\sw:styleId="([^"]*)""#).unwrap();
+        let type_pattern = regex::Regex::new(r#"\sw:type="([^"]*)""#).unwrap();
+        let default_pattern = regex::Regex::new(r#"\sw:default="(1|true|on)""#).unwrap();
+        let ppr_pattern = regex::Regex::new(r#"(?s)<w:pPr>(.*?)</w:pPr>"#).unwrap();
+        let rpr_pattern = regex::Regex::new(r#"(?s)<w:rPr>(.*?)</w:rPr>"#).unwrap();
+        let name_pattern = regex::Regex::new(r#"<w:name[^>]*w:val="([^"]*)""#).unwrap();
+        let based_on_pattern = regex::Regex::new(r#"<w:basedOn[^>]*w:val="([^"]*)""#).unwrap();
+        let next_pattern = regex::Regex::new(r#"<w:next\b[^>]*w:val="([^"]*)""#).unwrap();
         
-        for cap in style_pattern.captures(&xml_str) {
-            let style_id = match cap.get(1) {
-                Some(m) => m.as_str().to_string(),
+        for cap in style_pattern.captures_iter(&xml_str) {
+            let tag = cap.get(1).map_or("", |m| m.as_str());
+            let style_xml = cap.get(2).map_or("", |m| m.as_str());
+
+            let style_id = match id_pattern.captures(tag) {
+                Some(id_cap) => unescape_xml_text(&id_cap[1]),
                 None => continue,
             };
             
-            let style_type = match cap.get(2) {
-                Some(m) => m.as_str().to_string(),
+            let style_type = match type_pattern.captures(tag) {
+                Some(type_cap) => type_cap[1].to_string(),
                 None => "paragraph".to_string(),
             };
             
-            let style_xml = match cap.get(3) {
-                Some(m) => m.as_str(),
-                None => "",
-            };
-            
             let mut style = Style {
                 id: style_id.clone(),
                 name: None,
                 style_type,
                 based_on: None,
+                next: None,
                 paragraph_properties: ParagraphProperties::default(),
                 run_properties: RunProperties::default(),
-                is_default: false,
+                is_default: default_pattern.is_match(tag),
             };
             
             // Get style name
-            if let Some(name_cap) = regex::Regex::new(r#"<w:name[^>]*w:val="([^"]*)""#).unwrap().captures(style_xml) {
+            if let Some(name_cap) = name_pattern.captures(style_xml) {
                 if let Some(m) = name_cap.get(1) {
                     style.name = Some(m.as_str().to_string());
                 }
             }
             
             // Get basedOn
-            if let Some(based_cap) = regex::Regex::new(r#"<w:basedOn[^>]*w:val="([^"]*)""#).unwrap().captures(style_xml) {
+            if let Some(based_cap) = based_on_pattern.captures(style_xml) {
                 if let Some(m) = based_cap.get(1) {
                     style.based_on = Some(m.as_str().to_string());
                 }
             }
+
+            // Get next
+            if let Some(next_cap) = next_pattern.captures(style_xml) {
+                if let Some(m) = next_cap.get(1) {
+                    style.next = Some(m.as_str().to_string());
+                }
+            }
             
-            // Check if default
-            if regex::Regex::new(r#"w:default="1""#).unwrap().is_match(style_xml) {
-                style.is_default = true;
+            if let Some(ppr_cap) = ppr_pattern.captures(style_xml) {
+                Self::parse_paragraph_properties(&ppr_cap[1], &mut style.paragraph_properties);
+            }
+
+            if let Some(rpr_cap) = rpr_pattern.captures(style_xml) {
+                Self::parse_run_properties(&rpr_cap[1], &mut style.run_properties);
             }
             
             self.styles.insert(style_id, style);
@@ -562,7 +1286,7 @@ impl WordDocument {
     }
 
     /// Parse theme (word/theme/theme1.xml)
-    fn parse_theme(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
+    pub(super) fn parse_theme(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
         let theme_part_names = ["/word/theme/theme1.xml", "/word/theme/theme.xml", "/word/themes/theme1.xml"];
         
         let theme_part = theme_part_names.iter()
@@ -589,7 +1313,7 @@ impl WordDocument {
     }
 
     /// Parse core properties (docProps/core.xml)
-    fn parse_core_properties(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
+    pub(super) fn parse_core_properties(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
         let core_part_name = "/docProps/core.xml";
         
         let core_part = if let Some(part) = package.get_part(core_part_name) {
@@ -598,43 +1322,12 @@ impl WordDocument {
             return Ok(());
         };
 
-        let xml_str = String::from_utf8_lossy(&core_part.data);
-        let mut props = CoreProperties::default();
-
-        // Parse title
-        if let Some(caps) = regex::Regex::new(r#"<dc:title[^>]*>([^<]*)</dc:title>"#).unwrap().captures(&xml_str) {
-            if let Some(m) = caps.get(1) {
-                props.title = Some(m.as_str().to_string());
-            }
-        }
-        
-        // Parse creator
-        if let Some(caps) = regex::Regex::new(r#"<dc:creator[^>]*>([^<]*)</dc:creator>"#).unwrap().captures(&xml_str) {
-            if let Some(m) = caps.get(1) {
-                props.creator = Some(m.as_str().to_string());
-            }
-        }
-        
-        // Parse created
-        if let Some(caps) = regex::Regex::new(r#"<dcterms:created[^>]*>([^<]*)</dcterms:created>"#).unwrap().captures(&xml_str) {
-            if let Some(m) = caps.get(1) {
-                props.created = Some(m.as_str().to_string());
-            }
-        }
-        
-        // Parse modified
-        if let Some(caps) = regex::Regex::new(r#"<dcterms:modified[^>]*>([^<]*)</dcterms:modified>"#).unwrap().captures(&xml_str) {
-            if let Some(m) = caps.get(1) {
-                props.modified = Some(m.as_str().to_string());
-            }
-        }
-
-        self.core_properties = Some(props);
+        self.core_properties = Some(CoreProperties::parse(&String::from_utf8_lossy(&core_part.data)));
         Ok(())
     }
 
     /// Parse numbering definitions (word/numbering.xml)
-    fn parse_numbering(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
+    pub(super) fn parse_numbering(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
         let numbering_part_name = "/word/numbering.xml";
 
         let numbering_part = if let Some(part) = package.get_part(numbering_part_name) {
@@ -648,98 +1341,90 @@ impl WordDocument {
 
         // Parse abstract numbering definitions
         let abstract_num_pattern = regex::Regex::new(
-            r#"<w:abstractNum[^>]*w:abstractNumId="([^"]*)"[^>]*>(.*?)</w:abstractNum>"#
+            r#"(?s)<w:abstractNum\b[^>]*w:abstractNumId="([^"]*)"[^>]*>(.*?)</w:abstractNum>"#
         ).unwrap();
+        let lvl_pattern = regex::Regex::new(r#"(?s)<w:lvl\b[^>]*w:ilvl="([^"]*)"[^>]*>(.*?)</w:lvl>"#).unwrap();
+        let legal_pattern = regex::Regex::new(r#"<w:isLgl\b([^>]*)/?>"#).unwrap();
+        let ppr_pattern = regex::Regex::new(r#"(?s)<w:pPr>(.*?)</w:pPr>"#).unwrap();
+        let rpr_pattern = regex::Regex::new(r#"(?s)<w:rPr>(.*?)</w:rPr>"#).unwrap();
+        let value_in = |xml: &str, element: &str| {
+            regex::Regex::new(&format!(r#"<w:{}\b[^>]*w:val="([^"]*)""#, element))
+                .unwrap()
+                .captures(xml)
+                .map(|caps| unescape_xml_text(&caps[1]))
+        };
 
-        for cap in abstract_num_pattern.captures(&xml_str) {
-            let abstract_num_id = match cap.get(1) {
-                Some(m) => m.as_str().to_string(),
-                None => continue,
-            };
-
-            let abstract_num_xml = match cap.get(2) {
-                Some(m) => m.as_str(),
-                None => continue,
-            };
-
+        for cap in abstract_num_pattern.captures_iter(&xml_str) {
             let mut abstract_num = AbstractNumDef {
-                abstract_num_id,
+                abstract_num_id: cap[1].to_string(),
                 levels: Vec::new(),
             };
 
             // Parse list levels (lvl)
-            let lvl_pattern = regex::Regex::new(r#"<w:lvl[^>]*w:ilvl="([^"]*)"[^>]*>(.*?)</w:lvl>"#).unwrap();
-            for lvl_cap in lvl_pattern.captures(abstract_num_xml) {
-                let level_idx = match lvl_cap.get(1) {
-                    Some(m) => m.as_str().parse().unwrap_or(0),
-                    None => 0,
-                };
-
-                let lvl_xml = match lvl_cap.get(2) {
-                    Some(m) => m.as_str(),
-                    None => continue,
-                };
-
-                let mut level = ListLevel {
-                    level: level_idx,
-                    format: String::new(),
-                    text: String::new(),
-                    start_value: 1,
-                    paragraph_properties: ParagraphProperties::default(),
-                    run_properties: RunProperties::default(),
-                };
-
-                // Parse format
-                if let Some(caps) = regex::Regex::new(r#"<w:numFmt[^>]*w:val="([^"]*)""#).unwrap().captures(lvl_xml) {
-                    if let Some(m) = caps.get(1) {
-                        level.format = m.as_str().to_string();
-                    }
+            for lvl_cap in lvl_pattern.captures_iter(&cap[2]) {
+                let lvl_xml = lvl_cap.get(2).map_or("", |m| m.as_str());
+                let value = |element: &str| value_in(lvl_xml, element);
+
+                let is_legal = legal_pattern
+                    .captures(lvl_xml)
+                    .is_some_and(|caps| !matches!(Self::attribute(&caps[1], "w:val").as_deref(), Some("0" | "false")));
+                let mut paragraph_properties = ParagraphProperties::default();
+                if let Some(caps) = ppr_pattern.captures(lvl_xml) {
+                    Self::parse_paragraph_properties(&caps[1], &mut paragraph_properties);
                 }
-
-                // Parse text
-                if let Some(caps) = regex::Regex::new(r#"<w:lvlText[^>]*w:val="([^"]*)""#).unwrap().captures(lvl_xml) {
-                    if let Some(m) = caps.get(1) {
-                        level.text = m.as_str().to_string();
-                    }
-                }
-
-                // Parse start value
-                if let Some(caps) = regex::Regex::new(r#"<w:startOverride[^>]*w:val="([^"]*)""#).unwrap().captures(lvl_xml) {
-                    if let Some(m) = caps.get(1) {
-                        level.start_value = m.as_str().parse().unwrap_or(1);
-                    }
+                let mut run_properties = RunProperties::default();
+                if let Some(caps) = rpr_pattern.captures(lvl_xml) {
+                    Self::parse_run_properties(&caps[1], &mut run_properties);
                 }
 
-                abstract_num.levels.push(level);
+                abstract_num.levels.push(ListLevel {
+                    level: lvl_cap[1].parse().unwrap_or(0),
+                    format: value("numFmt").unwrap_or_default(),
+                    text: value("lvlText").unwrap_or_default(),
+                    start_value: value("start").and_then(|v| v.parse().ok()).unwrap_or(1),
+                    style_id: value("pStyle"),
+                    is_legal,
+                    restart: value("lvlRestart").and_then(|v| v.parse().ok()),
+                    suffix: value("suff"),
+                    paragraph_properties,
+                    run_properties,
+                });
             }
 
             numbering.abstract_num_defs.push(abstract_num);
         }
 
         // Parse numbering instances
-        let num_pattern = regex::Regex::new(r#"<w:num[^>]*w:numId="([^"]*)"[^>]*>(.*?)</w:num>"#).unwrap();
-        for cap in num_pattern.captures(&xml_str) {
-            let num_id = match cap.get(1) {
-                Some(m) => m.as_str().to_string(),
-                None => continue,
-            };
-
-            let num_xml = match cap.get(2) {
-                Some(m) => m.as_str(),
-                None => continue,
-            };
-
+        let num_pattern = regex::Regex::new(r#"(?s)<w:num\b[^>]*w:numId="([^"]*)"[^>]*>(.*?)</w:num>"#).unwrap();
+        let abstract_id_pattern = regex::Regex::new(r#"<w:abstractNumId[^>]*w:val="([^"]*)""#).unwrap();
+        let override_pattern =
+            regex::Regex::new(r#"(?s)<w:lvlOverride\b[^>]*w:ilvl="([^"]*)"[^>]*>(.*?)</w:lvlOverride>"#).unwrap();
+        let start_override_pattern = regex::Regex::new(r#"<w:startOverride\b[^>]*w:val="([^"]*)""#).unwrap();
+
+        for cap in num_pattern.captures_iter(&xml_str) {
+            let num_xml = cap.get(2).map_or("", |m| m.as_str());
             let mut num_instance = NumInstance {
-                num_id,
+                num_id: cap[1].to_string(),
                 abstract_num_id: String::new(),
                 overrides: Vec::new(),
             };
 
             // Parse abstract num ID reference
-            if let Some(caps) = regex::Regex::new(r#"<w:abstractNumId[^>]*w:val="([^"]*)""#).unwrap().captures(num_xml) {
-                if let Some(m) = caps.get(1) {
-                    num_instance.abstract_num_id = m.as_str().to_string();
-                }
+            if let Some(caps) = abstract_id_pattern.captures(num_xml) {
+                num_instance.abstract_num_id = caps[1].to_string();
+            }
+
+            for override_cap in override_pattern.captures_iter(num_xml) {
+                // A w:lvl inside the override replaces the level's format and text
+                let override_xml = &override_cap[2];
+                num_instance.overrides.push(LevelOverride {
+                    level: override_cap[1].parse().unwrap_or(0),
+                    start_value: start_override_pattern
+                        .captures(override_xml)
+                        .and_then(|caps| caps[1].parse().ok()),
+                    text: value_in(override_xml, "lvlText"),
+                    format: value_in(override_xml, "numFmt"),
+                });
             }
 
             numbering.num_instances.push(num_instance);
@@ -750,171 +1435,74 @@ impl WordDocument {
     }
 
     /// Parse headers and footers
-    fn parse_headers_footers(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
-        // Get document relationships to find header/footer references
-        let doc_rels_part = "/word/_rels/document.xml.rels";
-
-        let rels_part = if let Some(part) = package.get_part(doc_rels_part) {
-            part
-        } else {
+    ///
+    /// Each part is read through the main document's relationships; its type
+    /// is the kind of the first section reference to it.
+    pub(super) fn parse_headers_footers(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
+        let Some(relationships) = package.get_relationships("/word/document.xml") else {
             return Ok(());
         };
 
-        let rels_xml = String::from_utf8_lossy(&rels_part.data);
-
-        // Header pattern in relationships
-        let header_pattern = regex::Regex::new(
-            r#"<Relationship[^>]*Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/header"[^>]*Id="([^"]*)"[^>]*Target="([^"]*)""#
-        ).unwrap();
-
-        for cap in header_pattern.captures(&rels_xml) {
-            let header_id = match cap.get(1) {
-                Some(m) => m.as_str().to_string(),
-                None => continue,
+        for rel in relationships {
+            let is_header = match rel.relationship_type {
+                RelationshipType::Header => true,
+                RelationshipType::Footer => false,
+                _ => continue,
             };
-
-            let target = match cap.get(2) {
-                Some(m) => m.as_str().to_string(),
-                None => continue,
+            let Some(part) = package.get_part(&resolve_part_name("/word/", &rel.target)) else {
+                continue;
             };
-
-            let header_part_name = format!("/word/{}", target);
-            if let Some(header_part) = package.get_part(&header_part_name) {
-                let header_xml = String::from_utf8_lossy(&header_part.data);
-                let paragraphs = self.parse_header_footer_content(&header_xml);
-
-                let header = Header {
-                    id: header_id,
-                    header_type: Self::determine_header_type(&target),
+            let paragraphs = self.parse_header_footer_content(&String::from_utf8_lossy(&part.data));
+            let kind = self
+                .sections
+                .iter()
+                .flat_map(|section| if is_header { &section.header_references } else { &section.footer_references })
+                .find(|reference| reference.id == rel.id)
+                .map_or_else(|| "default".to_string(), |reference| reference.kind.clone());
+
+            if is_header {
+                self.headers.push(Header {
+                    id: rel.id.clone(),
+                    header_type: kind,
                     paragraphs,
                     images: Vec::new(),
-                };
-                self.headers.push(header);
-            }
-        }
-
-        // Footer pattern in relationships
-        let footer_pattern = regex::Regex::new(
-            r#"<Relationship[^>]*Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/footer"[^>]*Id="([^"]*)"[^>]*Target="([^"]*)""#
-        ).unwrap();
-
-        for cap in footer_pattern.captures(&rels_xml) {
-            let footer_id = match cap.get(1) {
-                Some(m) => m.as_str().to_string(),
-                None => continue,
-            };
-
-            let target = match cap.get(2) {
-                Some(m) => m.as_str().to_string(),
-                None => continue,
-            };
-
-            let footer_part_name = format!("/word/{}", target);
-            if let Some(footer_part) = package.get_part(&footer_part_name) {
-                let footer_xml = String::from_utf8_lossy(&footer_part.data);
-                let paragraphs = self.parse_header_footer_content(&footer_xml);
-
-                let footer = Footer {
-                    id: footer_id,
-                    footer_type: Self::determine_footer_type(&target),
+                });
+            } else {
+                self.footers.push(Footer {
+                    id: rel.id.clone(),
+                    footer_type: kind,
                     paragraphs,
                     images: Vec::new(),
-                };
-                self.footers.push(footer);
+                });
             }
         }
 
         Ok(())
     }
 
-    /// Determine header type from target filename
-    fn determine_header_type(target: &str) -> String {
-        if target.contains("header1") {
-            "first".to_string()
-        } else if target.contains("even") {
-            "even".to_string()
-        } else {
-            "default".to_string()
-        }
-    }
-
-    /// Determine footer type from target filename
-    fn determine_footer_type(target: &str) -> String {
-        if target.contains("footer1") {
-            "first".to_string()
-        } else if target.contains("even") {
-            "even".to_string()
-        } else {
-            "default".to_string()
-        }
-    }
-
     /// Parse content from header/footer XML
     fn parse_header_footer_content(&self, xml_str: &str) -> Vec<Paragraph> {
-        let mut paragraphs = Vec::new();
-
-        let para_pattern = regex::Regex::new(r#"<w:p[^>]*>(.*?)</w:p>"#).unwrap();
-        for para_cap in para_pattern.captures(xml_str) {
-            if let Some(para_xml) = para_cap.get(1) {
-                if let Some(para) = self.parse_paragraph(para_xml.as_str()) {
-                    paragraphs.push(para);
-                }
-            }
-        }
-
-        paragraphs
+        let para_pattern = regex::Regex::new(r#"(?s)<w:p\b[^>]*>(.*?)</w:p>"#).unwrap();
+        para_pattern
+            .captures_iter(xml_str)
+            .filter_map(|para_cap| Self::parse_paragraph(para_cap.get(1)?.as_str()))
+            .collect()
     }
 
     /// Parse footnotes and endnotes
-    fn parse_footnotes_endnotes(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
+    pub(super) fn parse_footnotes_endnotes(&mut self, package: &OpcPackage) -> Result<(), OoxmlError> {
         // Parse footnotes
         let footnote_part_names = ["/word/footnotes.xml", "/word/footnote.xml"];
 
         for part_name in footnote_part_names.iter() {
             if let Some(footnote_part) = package.get_part(part_name) {
                 let xml_str = String::from_utf8_lossy(&footnote_part.data);
-
-                let footnote_pattern = regex::Regex::new(
-                    r#"<w:footnote[^>]*w:id="([^"]*)"[^>]*>(.*?)</w:footnote>"#
-                ).unwrap();
-
-                for cap in footnote_pattern.captures(&xml_str) {
-                    let footnote_id = match cap.get(1) {
-                        Some(m) => m.as_str().to_string(),
-                        None => continue,
-                    };
-
-                    let footnote_xml = match cap.get(2) {
-                        Some(m) => m.as_str(),
-                        None => continue,
-                    };
-
-                    let mut footnote = Footnote {
-                        id: footnote_id,
-                        footnote_type: None,
-                        paragraphs: Vec::new(),
-                    };
-
-                    // Determine footnote type
-                    if footnote_xml.contains("<w:footnoteRef") {
-                        footnote.footnote_type = Some("normal".to_string());
-                    } else if footnote_xml.contains("<w:separator") {
-                        footnote.footnote_type = Some("separator".to_string());
-                    } else if footnote_xml.contains("<w:continuationSeparator") {
-                        footnote.footnote_type = Some("continuationSeparator".to_string());
-                    }
-
-                    // Parse paragraphs in footnote
-                    let para_pattern = regex::Regex::new(r#"<w:p[^>]*>(.*?)</w:p>"#).unwrap();
-                    for para_cap in para_pattern.captures(footnote_xml) {
-                        if let Some(para_xml) = para_cap.get(1) {
-                            if let Some(para) = self.parse_paragraph(para_xml.as_str()) {
-                                footnote.paragraphs.push(para);
-                            }
-                        }
-                    }
-
-                    self.footnotes.push(footnote);
+                for (id, note_type, paragraphs) in self.parse_notes(&xml_str, "footnote") {
+                    self.footnotes.push(Footnote {
+                        id,
+                        footnote_type: note_type,
+                        paragraphs,
+                    });
                 }
             }
         }
@@ -925,53 +1513,123 @@ impl WordDocument {
         for part_name in endnote_part_names.iter() {
             if let Some(endnote_part) = package.get_part(part_name) {
                 let xml_str = String::from_utf8_lossy(&endnote_part.data);
+                for (id, note_type, paragraphs) in self.parse_notes(&xml_str, "endnote") {
+                    self.endnotes.push(Endnote {
+                        id,
+                        endnote_type: note_type,
+                        paragraphs,
+                    });
+                }
+            }
+        }
 
-                let endnote_pattern = regex::Regex::new(
-                    r#"<w:endnote[^>]*w:id="([^"]*)"[^>]*>(.*?)</w:endnote>"#
-                ).unwrap();
+        Ok(())
+    }
 
-                for cap in endnote_pattern.captures(&xml_str) {
-                    let endnote_id = match cap.get(1) {
-                        Some(m) => m.as_str().to_string(),
-                        None => continue,
-                    };
+    /// Read the editing restriction from settings.xml
+    pub(super) fn parse_settings(&mut self, package: &OpcPackage) {
+        if let Some(part) = package.get_part("/word/settings.xml") {
+            self.protection = forms::parse_protection(&String::from_utf8_lossy(&part.data));
+        }
+    }
 
-                    let endnote_xml = match cap.get(2) {
-                        Some(m) => m.as_str(),
-                        None => continue,
-                    };
+    /// Parse comments.xml, with reply threads and done flags from commentsExtended.xml
+    pub(super) fn parse_comments(&mut self, package: &OpcPackage) {
+        let Some(part) = package.get_part("/word/comments.xml") else {
+            return;
+        };
+        let xml_str = String::from_utf8_lossy(&part.data);
+        let comment_pattern = regex::Regex::new(r#"(?s)<w:comment\b([^>]*)>(.*?)</w:comment>"#).unwrap();
+        let para_pattern = regex::Regex::new(r#"(?s)<w:p\b([^>]*)>(.*?)</w:p>"#).unwrap();
+
+        // Threads refer to comments by the paraId of their last paragraph
+        let mut para_ids: HashMap<String, usize> = HashMap::new();
+        for cap in comment_pattern.captures_iter(&xml_str) {
+            let mut comment = Comment {
+                id: Self::attribute(&cap[1], "w:id").unwrap_or_default(),
+                author: Self::attribute(&cap[1], "w:author"),
+                initials: Self::attribute(&cap[1], "w:initials"),
+                date: Self::attribute(&cap[1], "w:date"),
+                ..Default::default()
+            };
+            for para_cap in para_pattern.captures_iter(&cap[2]) {
+                if let Some(para_id) = Self::attribute(&para_cap[1], "w14:paraId") {
+                    para_ids.insert(para_id, self.comments.len());
+                }
+                comment.paragraphs.extend(Self::parse_paragraph(&para_cap[2]));
+            }
+            self.comments.push(comment);
+        }
 
-                    let mut endnote = Endnote {
-                        id: endnote_id,
-                        endnote_type: None,
-                        paragraphs: Vec::new(),
-                    };
+        let Some(extended) = package.get_part("/word/commentsExtended.xml") else {
+            return;
+        };
+        let xml_str = String::from_utf8_lossy(&extended.data);
+        let comment_ex_pattern = regex::Regex::new(r#"<w15:commentEx\b([^>]*?)/?>"#).unwrap();
+        for cap in comment_ex_pattern.captures_iter(&xml_str) {
+            let index = Self::attribute(&cap[1], "w15:paraId").and_then(|id| para_ids.get(&id).copied());
+            let Some(index) = index else {
+                continue;
+            };
+            let parent = Self::attribute(&cap[1], "w15:paraIdParent")
+                .and_then(|id| para_ids.get(&id))
+                .map(|&parent| self.comments[parent].id.clone());
+            let comment = &mut self.comments[index];
+            comment.parent_id = parent;
+            comment.done = Self::attribute(&cap[1], "w15:done").is_some_and(|done| matches!(done.as_str(), "1" | "true" | "on"));
+        }
+    }
 
-                    // Determine endnote type
-                    if endnote_xml.contains("<w:endnoteRef") {
-                        endnote.endnote_type = Some("normal".to_string());
-                    } else if endnote_xml.contains("<w:separator") {
-                        endnote.endnote_type = Some("separator".to_string());
-                    } else if endnote_xml.contains("<w:continuationSeparator") {
-                        endnote.endnote_type = Some("continuationSeparator".to_string());
-                    }
+    /// Parse the `w:footnote` or `w:endnote` elements of a notes part
+    ///
+    /// Returns (id, type, paragraphs) for each note.
+    fn parse_notes(&self, xml_str: &str, element: &str) -> Vec<(String, Option<String>, Vec<Paragraph>)> {
+        let note_pattern = regex::Regex::new(&format!(
+            r#"(?s)<w:{element}\b([^>]*)>(.*?)</w:{element}>"#
+        )).unwrap();
+        let id_pattern = regex::Regex::new(r#"w:id="([^"]*)""#).unwrap();
+        let para_pattern = regex::Regex::new(r#"(?s)<w:p\b[^>]*>(.*?)</w:p>"#).unwrap();
+
+        let mut notes = Vec::new();
+        for cap in note_pattern.captures_iter(xml_str) {
+            let Some(id) = id_pattern.captures(&cap[1]).map(|c| c[1].to_string()) else {
+                continue;
+            };
+            let note_xml = &cap[2];
+
+            // Determine note type
+            let note_type = if note_xml.contains(&format!("<w:{element}Ref")) {
+                Some("normal".to_string())
+            } else if note_xml.contains("<w:separator") {
+                Some("separator".to_string())
+            } else if note_xml.contains("<w:continuationSeparator") {
+                Some("continuationSeparator".to_string())
+            } else {
+                None
+            };
 
-                    // Parse paragraphs in endnote
-                    for para_cap in endnote_pattern.captures(endnote_xml) {
-                        if let Some(para_xml) = para_cap.get(1) {
-                            if let Some(para) = self.parse_paragraph(para_xml.as_str()) {
-                                endnote.paragraphs.push(para);
-                            }
-                        }
-                    }
+            // Parse paragraphs in note
+            let paragraphs = para_pattern
+                .captures_iter(note_xml)
+                .filter_map(|para_cap| Self::parse_paragraph(&para_cap[1]))
+                .collect();
 
-                    self.endnotes.push(endnote);
-                }
-            }
+            notes.push((id, note_type, paragraphs));
         }
+        notes
+    }
+}
 
-        Ok(())
+/// Resolve the predefined XML entities in text or attribute content
+pub(crate) fn unescape_xml_text(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
     }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
 }
 
 impl RunProperties {
@@ -986,3 +1644,253 @@ impl RunProperties {
             && self.background_color.is_none()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::types::FieldKind;
+    use crate::page_layout::SectionBreak;
+
+    fn parse(para_xml: &str) -> Paragraph {
+        WordDocument::parse_paragraph(para_xml).unwrap()
+    }
+
+    #[test]
+    fn test_parse_paragraph_all_runs() {
+        let para = parse(r#"<w:r><w:rPr><w:b w:val="1"/></w:rPr><w:t>Hello</w:t></w:r>
+            <w:r><w:t xml:space="preserve"> big &amp; </w:t><w:t>bold</w:t></w:r>"#);
+        assert_eq!(para.runs.len(), 2);
+        assert_eq!(para.text, "Hello big & bold");
+        assert_eq!(para.runs[0].properties.bold, Some(true));
+    }
+
+    #[test]
+    fn test_parse_complex_field() {
+        let para = parse(r#"<w:r><w:t xml:space="preserve">Dear </w:t></w:r>
+            <w:r><w:fldChar w:fldCharType="begin"/></w:r>
+            <w:r><w:instrText xml:space="preserve"> MERGEFIELD FirstName </w:instrText></w:r>
+            <w:r><w:fldChar w:fldCharType="separate"/></w:r>
+            <w:r><w:t>«FirstName»</w:t></w:r>
+            <w:r><w:fldChar w:fldCharType="end"/></w:r>
+            <w:r><w:t>,</w:t></w:r>"#);
+        assert_eq!(para.text, "Dear «FirstName»,");
+        assert_eq!(para.fields.len(), 1);
+        let field = &para.fields[0];
+        assert_eq!(field.kind, FieldKind::MergeField);
+        assert_eq!(field.argument(), Some("FirstName"));
+        assert_eq!(field.start, 5);
+        assert_eq!(field.length, 11);
+        assert_eq!(field.result, "«FirstName»");
+    }
+
+    #[test]
+    fn test_parse_simple_field() {
+        let para = parse(r#"<w:r><w:t xml:space="preserve">Page </w:t></w:r>
+            <w:fldSimple w:instr=" PAGE "><w:r><w:t>3</w:t></w:r></w:fldSimple>"#);
+        assert_eq!(para.text, "Page 3");
+        assert_eq!(para.fields.len(), 1);
+        assert_eq!(para.fields[0].kind, FieldKind::Page);
+        assert_eq!(para.fields[0].start, 5);
+        assert_eq!(para.fields[0].result, "3");
+    }
+
+    #[test]
+    fn test_parse_form_fields_over_their_text() {
+        let para = parse(r#"<w:r><w:t xml:space="preserve">Name: </w:t></w:r>
+            <w:r><w:fldChar w:fldCharType="begin"><w:ffData><w:name w:val="Name"/><w:textInput/></w:ffData></w:fldChar></w:r>
+            <w:r><w:instrText xml:space="preserve"> FORMTEXT </w:instrText></w:r>
+            <w:r><w:fldChar w:fldCharType="separate"/></w:r>
+            <w:r><w:t>Ada</w:t></w:r>
+            <w:r><w:fldChar w:fldCharType="end"/></w:r>
+            <w:r><w:fldChar w:fldCharType="begin"><w:ffData><w:name w:val="Agree"/><w:checkBox><w:default w:val="1"/></w:checkBox></w:ffData></w:fldChar></w:r>
+            <w:r><w:instrText xml:space="preserve"> FORMCHECKBOX </w:instrText></w:r>
+            <w:r><w:fldChar w:fldCharType="end"/></w:r>
+            <w:sdt><w:sdtPr><w:tag w:val="City"/><w:showingPlcHdr/><w:text/></w:sdtPr>
+            <w:sdtContent><w:r><w:t>Enter a city</w:t></w:r></w:sdtContent></w:sdt>"#);
+        assert_eq!(para.text, "Name: AdaEnter a city");
+        assert!(para.fields.is_empty());
+        let spans: Vec<(&str, usize, usize, bool)> = para
+            .form_fields
+            .iter()
+            .map(|anchored| (anchored.field.name.as_str(), anchored.start, anchored.length, anchored.placeholder))
+            .collect();
+        assert_eq!(spans, vec![("Name", 6, 3, false), ("Agree", 9, 0, false), ("City", 9, 12, true)]);
+        assert_eq!(para.form_fields[0].field.value, "Ada");
+        assert!(para.form_fields[1].field.checked);
+    }
+
+    #[test]
+    fn test_unescape_xml_text() {
+        assert_eq!(unescape_xml_text("a &lt;b&gt; &amp;amp;"), "a <b> &amp;");
+        assert_eq!(unescape_xml_text("plain"), "plain");
+    }
+
+    #[test]
+    fn test_parse_paragraph_properties() {
+        let para = parse(r#"<w:pPr><w:pStyle w:val="Heading1"/><w:keepNext/><w:keepLines w:val="1"/><w:widowControl w:val="0"/><w:numPr><w:ilvl w:val="2"/><w:numId w:val="5"/></w:numPr>
+            <w:spacing w:before="240" w:line="360" w:lineRule="auto"/><w:ind w:left="720" w:hanging="360"/><w:jc w:val="center"/></w:pPr>
+            <w:r><w:rPr><w:b/><w:i w:val="0"/></w:rPr><w:t>Title</w:t></w:r>"#);
+        let props = &para.properties;
+        assert_eq!(props.style_id.as_deref(), Some("Heading1"));
+        assert_eq!((props.num_id.as_deref(), props.list_level), (Some("5"), Some(2)));
+        assert_eq!((props.spacing_before, props.spacing_line), (Some(240), Some(360)));
+        assert_eq!((props.indent_left, props.indent_first_line), (Some(720), Some(-360)));
+        assert_eq!(props.alignment.as_deref(), Some("center"));
+        assert_eq!((props.keep_next, props.keep_lines, props.page_break_before, props.widow_control), (Some(true), Some(true), None, Some(false)));
+        assert_eq!((para.runs[0].properties.bold, para.runs[0].properties.italic), (Some(true), Some(false)));
+    }
+
+    #[test]
+    fn test_parse_sections() {
+        let xml = r#"<w:document><w:body>
+            <w:p><w:r><w:t>Cover</w:t></w:r></w:p>
+            <w:p><w:pPr><w:sectPr><w:headerReference w:type="first" r:id="rId8"/><w:footerReference r:id="rId9" w:type="default"/>
+                <w:pgSz w:w="15840" w:h="12240" w:orient="landscape"/><w:pgMar w:top="720" w:right="720" w:bottom="720" w:left="1440" w:header="360" w:footer="360" w:gutter="0"/>
+                <w:cols w:num="2" w:space="720"/><w:titlePg/></w:sectPr></w:pPr><w:r><w:t>Wide</w:t></w:r></w:p>
+            <w:p><w:r><w:t>Body</w:t></w:r></w:p>
+            <w:sectPr><w:type w:val="evenPage"/><w:pgSz w:w="11906" w:h="16838"/></w:sectPr>
+        </w:body></w:document>"#;
+        let mut package = OpcPackage::default();
+        package.parts.insert(
+            "/word/document.xml".to_string(),
+            super::super::types::PackagePart {
+                name: "/word/document.xml".to_string(),
+                content_type: super::super::types::ContentType::MainDocument,
+                data: xml.as_bytes().to_vec(),
+            },
+        );
+
+        let document = WordDocument::parse(&package).unwrap();
+        assert_eq!(document.sections.len(), 2);
+
+        let first = &document.sections[0];
+        assert_eq!((first.first_paragraph, first.paragraph_count), (0, 2));
+        assert!(first.landscape && first.title_page);
+        assert_eq!((first.columns, first.column_space), (2, Some(720)));
+        assert_eq!(first.margin_left, Some(1440));
+        assert_eq!(first.header_references, vec![HeaderFooterReference { kind: "first".to_string(), id: "rId8".to_string() }]);
+        assert_eq!(first.footer_references[0].kind, "default");
+        let setup = first.page_setup();
+        assert_eq!((setup.width, setup.height), (792.0, 612.0));
+        assert_eq!(setup.margins.left, 72.0);
+
+        let last = &document.sections[1];
+        assert_eq!((last.first_paragraph, last.paragraph_count), (2, 1));
+        assert!(!last.landscape && !last.title_page);
+        assert_eq!((last.page_width, last.columns), (Some(11906), 1));
+        assert_eq!((first.break_type(), last.break_type()), (SectionBreak::NextPage, SectionBreak::EvenPage));
+    }
+
+    #[test]
+    fn test_parse_document_end() {
+        let xml = r#"<w:document><w:body>
+            <w:p><w:pPr><w:rPr><w:i/></w:rPr></w:pPr><w:r><w:t>Body</w:t></w:r></w:p>
+            <w:p><w:r><w:t></w:t></w:r></w:p>
+            <w:p><w:pPr><w:jc w:val="center"/><w:rPr><w:b/><w:sz w:val="28"/></w:rPr></w:pPr></w:p>
+            <w:sectPr><w:footnotePr><w:numFmt w:val="lowerRoman"/></w:footnotePr><w:pgSz w:w="11906" w:h="16838"/>
+                <w:pgNumType w:start="5"/><w:cols w:space="708"/><w:docGrid w:linePitch="360"/></w:sectPr>
+        </w:body></w:document>"#;
+        let mut package = OpcPackage::default();
+        package.parts.insert(
+            "/word/document.xml".to_string(),
+            super::super::types::PackagePart {
+                name: "/word/document.xml".to_string(),
+                content_type: super::super::types::ContentType::MainDocument,
+                data: xml.as_bytes().to_vec(),
+            },
+        );
+
+        // Empty paragraphs are dropped, but not the final one, whose mark ends the document
+        let document = WordDocument::parse(&package).unwrap();
+        assert_eq!(document.paragraphs.len(), 2);
+        assert_eq!(document.text, "Body\n");
+        assert_eq!(document.paragraphs[0].mark_properties.as_ref().and_then(|mark| mark.italic), Some(true));
+        let last = &document.paragraphs[1];
+        assert_eq!(last.properties.alignment.as_deref(), Some("center"));
+        let mark = last.mark_properties.as_ref().unwrap();
+        assert_eq!((mark.bold, mark.font_size), (Some(true), Some(14)));
+
+        let section = &document.sections[0];
+        assert_eq!((section.first_paragraph, section.paragraph_count), (0, 2));
+        assert_eq!(section.column_space, Some(708));
+        assert_eq!(
+            section.other_children,
+            vec![
+                r#"<w:footnotePr><w:numFmt w:val="lowerRoman"/></w:footnotePr>"#.to_string(),
+                r#"<w:pgNumType w:start="5"/>"#.to_string(),
+                r#"<w:docGrid w:linePitch="360"/>"#.to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_content_controls() {
+        let xml = r#"<w:document><w:body>
+            <w:p><w:r><w:t>Intro</w:t></w:r></w:p>
+            <w:sdt><w:sdtPr><w:alias w:val="Applicant"/><w:tag w:val="applicant"/><w:id w:val="42"/></w:sdtPr><w:sdtContent>
+                <w:p><w:r><w:t>Name</w:t></w:r></w:p>
+                <w:sdt><w:sdtPr><w:tag w:val="born"/><w:date w:fullDate="2024-01-31T00:00:00Z"><w:dateFormat w:val="d.M.yyyy"/></w:date></w:sdtPr>
+                    <w:sdtContent><w:p><w:r><w:t>31.1.2024</w:t></w:r></w:p></w:sdtContent></w:sdt>
+            </w:sdtContent></w:sdt>
+            <w:p><w:sdt><w:sdtPr><w:tag w:val="inline"/><w:text/></w:sdtPr><w:sdtContent><w:r><w:t>Run</w:t></w:r></w:sdtContent></w:sdt></w:p>
+            <w:sectPr/>
+        </w:body></w:document>"#;
+        let mut package = OpcPackage::default();
+        package.parts.insert(
+            "/word/document.xml".to_string(),
+            super::super::types::PackagePart {
+                name: "/word/document.xml".to_string(),
+                content_type: super::super::types::ContentType::MainDocument,
+                data: xml.as_bytes().to_vec(),
+            },
+        );
+
+        // The run-level control inside the last paragraph is not a block
+        let document = WordDocument::parse(&package).unwrap();
+        assert_eq!(document.paragraphs.len(), 4);
+        let controls: Vec<(Option<&str>, &str, usize, usize)> = document
+            .content_controls
+            .iter()
+            .map(|c| (c.tag.as_deref(), c.sdt_type.as_str(), c.first_paragraph, c.paragraph_count))
+            .collect();
+        assert_eq!(controls, vec![(Some("applicant"), "richText", 1, 2), (Some("born"), "date", 2, 1)]);
+        let applicant = &document.content_controls[0];
+        assert_eq!(applicant.alias.as_deref(), Some("Applicant"));
+        assert_eq!(applicant.properties.id.as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn test_parse_revisions() {
+        let para = parse(r#"<w:pPr><w:jc w:val="center"/><w:pPrChange w:id="4" w:author="Bo"><w:pPr><w:jc w:val="left"/></w:pPr></w:pPrChange></w:pPr>
+            <w:r><w:t xml:space="preserve">Keep </w:t></w:r>
+            <w:ins w:id="1" w:author="Ann" w:date="2024-05-01T10:00:00Z"><w:r><w:t>new</w:t></w:r></w:ins>
+            <w:del w:id="2" w:author="Ann"><w:r><w:delText xml:space="preserve"> old</w:delText></w:r></w:del>
+            <w:r><w:rPr><w:b/><w:rPrChange w:id="3" w:author="Bo"><w:rPr><w:i/></w:rPr></w:rPrChange></w:rPr><w:t>!</w:t></w:r>"#);
+
+        // Deleted text stays in the text until the deletion is accepted
+        assert_eq!(para.text, "Keep new old!");
+        assert_eq!(para.properties.alignment.as_deref(), Some("center"));
+        assert_eq!(para.runs[3].properties.bold, Some(true));
+        assert_eq!(para.runs[3].properties.italic, None);
+
+        let revisions: Vec<(&str, RevisionKind, usize, usize)> = para
+            .revisions
+            .iter()
+            .map(|r| (r.id.as_str(), r.kind, r.start, r.length))
+            .collect();
+        assert_eq!(
+            revisions,
+            vec![
+                ("4", RevisionKind::ParagraphFormat, 0, 13),
+                ("1", RevisionKind::Insertion, 5, 3),
+                ("2", RevisionKind::Deletion, 8, 4),
+                ("3", RevisionKind::RunFormat, 12, 1),
+            ]
+        );
+        assert_eq!(para.revisions[1].author.as_deref(), Some("Ann"));
+        assert_eq!(para.revisions[1].date.as_deref(), Some("2024-05-01T10:00:00Z"));
+        assert_eq!(para.revisions[3].previous_run_properties.as_ref().unwrap().italic, Some(true));
+        let previous = para.revisions[0].previous_paragraph_properties.as_ref().unwrap();
+        assert_eq!(previous.alignment.as_deref(), Some("left"));
+    }
+}
@@ -31,4 +31,13 @@ pub enum OoxmlError {
     
     #[error("Unsupported content type: {0}")]
     UnsupportedContentType(String),
+
+    #[error("Cancelled")]
+    Cancelled,
+
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(super::LimitViolation),
+
+    #[error("Form error: {0}")]
+    Form(#[from] super::FormError),
 }
@@ -0,0 +1,185 @@
+//! Snapshot-based export with progress reporting and cancellation
+//!
+//! Exporters read a [`TextSnapshot`] instead of the live piece tree, so the
+//! document lock is only held for the instant it takes to snapshot, and
+//! editing can continue while a long export runs on another thread.
+
+use super::control::OperationControl;
+use super::error::OoxmlError;
+use super::opc::OpcPackage;
+use super::serializer::{snapshot_to_word_document, DocxSerializer, ExportContent, ExportOptions, HtmlExport};
+use crate::piece_tree::TextSnapshot;
+
+/// Progress and cancellation shared between an export and whoever started it;
+/// progress is reported as a fraction from 0.0 to 1.0
+pub type ExportControl = OperationControl<f32>;
+
+impl ExportControl {
+    /// Report progress; fails once the export has been cancelled
+    pub fn step(&self, fraction: f32) -> Result<(), OoxmlError> {
+        self.check()?;
+        self.report(&fraction.clamp(0.0, 1.0));
+        Ok(())
+    }
+}
+
+/// Export a snapshot to .docx bytes along with its tracked changes, comments,
+/// bookmarks, styles, lists and paragraph formatting
+pub fn export_snapshot_docx(
+    snapshot: &TextSnapshot,
+    content: &ExportContent,
+    options: Option<ExportOptions>,
+    control: &ExportControl,
+) -> Result<Vec<u8>, OoxmlError> {
+    let document = snapshot_to_word_document(snapshot, content, control)?;
+    let serializer = DocxSerializer::new(OpcPackage::default(), document).with_media(&content.media);
+    serializer
+        .export_docx_with_control(options, control)
+        .map(|(data, _)| data)
+}
+
+/// Export a snapshot to .docx bytes as [`export_snapshot_docx`] does, over
+/// `package`, the package the document was opened from
+///
+/// The source parts the export does not write itself are carried over, and
+/// the entries of parts that did not change are copied from the package's
+/// archive, as [`DocxSerializer::export_docx_incremental`] does.
+pub fn export_snapshot_docx_incremental(
+    snapshot: &TextSnapshot,
+    content: &ExportContent,
+    package: OpcPackage,
+    options: Option<ExportOptions>,
+    control: &ExportControl,
+) -> Result<Vec<u8>, OoxmlError> {
+    let document = snapshot_to_word_document(snapshot, content, control)?;
+    let serializer = DocxSerializer::new(package, document).with_media(&content.media);
+    serializer
+        .export_docx_incremental(options, control)
+        .map(|(data, _)| data)
+}
+
+/// Export a snapshot as one HTML page, as [`export_snapshot_docx`] does to .docx
+pub fn export_snapshot_html(
+    snapshot: &TextSnapshot,
+    content: &ExportContent,
+    options: Option<ExportOptions>,
+    control: &ExportControl,
+) -> Result<HtmlExport, OoxmlError> {
+    let document = snapshot_to_word_document(snapshot, content, control)?;
+    DocxSerializer::new(OpcPackage::default(), document)
+        .with_media(&content.media)
+        .export_html_with_control(options, control)
+}
+
+/// Export a snapshot as plain text, as [`export_snapshot_docx`] does to .docx
+pub fn export_snapshot_text(
+    snapshot: &TextSnapshot,
+    content: &ExportContent,
+    options: Option<ExportOptions>,
+    control: &ExportControl,
+) -> Result<String, OoxmlError> {
+    let document = snapshot_to_word_document(snapshot, content, control)?;
+    DocxSerializer::new(OpcPackage::default(), document)
+        .with_media(&content.media)
+        .export_text_with_control(options, control)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece_tree::{PieceTree, TextAttributes};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    fn large_tree() -> PieceTree {
+        let mut tree = PieceTree::new(
+            (0..20_000)
+                .map(|i| format!("Paragraph {} of the export test.", i))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        let bold = TextAttributes {
+            bold: Some(true),
+            ..Default::default()
+        };
+        tree.insert_with_attrs(0, "Title ".to_string(), Some(bold));
+        tree
+    }
+
+    fn document_xml(docx: &[u8]) -> String {
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(docx)).unwrap();
+        let mut xml = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("word/document.xml").unwrap(), &mut xml).unwrap();
+        xml
+    }
+
+    #[test]
+    fn test_edits_during_export_do_not_leak_into_it() {
+        let mut tree = large_tree();
+        let snapshot = tree.snapshot();
+        let expected = export_snapshot_docx(&snapshot, &ExportContent::default(), None, &ExportControl::new()).unwrap();
+
+        let edits = Arc::new(AtomicUsize::new(0));
+        let done = AtomicBool::new(false);
+        let waiting_edits = edits.clone();
+        // Hold the export at its first step until edits are under way
+        let control = ExportControl::new().with_progress(move |_| {
+            while waiting_edits.load(Ordering::Relaxed) < 500 {
+                std::thread::yield_now();
+            }
+        });
+
+        let exported = std::thread::scope(|scope| {
+            let exporter = scope.spawn(|| {
+                let result = export_snapshot_docx(&snapshot, &ExportContent::default(), None, &control);
+                done.store(true, Ordering::Relaxed);
+                result
+            });
+
+            let mut i = 0usize;
+            while !done.load(Ordering::Relaxed) || i < 500 {
+                let offset = (i * 7_919) % tree.char_count();
+                tree.insert(offset, "EDIT\n".to_string());
+                if i.is_multiple_of(2) {
+                    tree.delete(tree.char_to_byte_offset(offset / 2), 3);
+                }
+                i += 1;
+                edits.store(i, Ordering::Relaxed);
+            }
+            exporter.join().unwrap()
+        });
+
+        assert!(edits.load(Ordering::Relaxed) >= 500);
+        assert_ne!(tree.get_text(), snapshot.get_text());
+        assert_eq!(document_xml(&exported.unwrap()), document_xml(&expected));
+    }
+
+    #[test]
+    fn test_progress_is_reported_to_completion() {
+        let fractions = Arc::new(Mutex::new(Vec::new()));
+        let sink = fractions.clone();
+        let control = ExportControl::new().with_progress(move |&f| sink.lock().unwrap().push(f));
+
+        export_snapshot_docx(&large_tree().snapshot(), &ExportContent::default(), None, &control).unwrap();
+
+        let fractions = fractions.lock().unwrap();
+        assert!(fractions.len() > 10);
+        assert!(fractions.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(fractions.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn test_cancel_stops_export() {
+        let control = ExportControl::new();
+        let handle = control.clone();
+        let control = control.with_progress(move |&f| {
+            if f >= 0.25 {
+                handle.cancel();
+            }
+        });
+
+        let result = export_snapshot_docx(&large_tree().snapshot(), &ExportContent::default(), None, &control);
+        assert!(matches!(result, Err(OoxmlError::Cancelled)));
+        assert!(control.is_cancelled());
+    }
+}
@@ -0,0 +1,431 @@
+//! Feature Analysis
+//! Quickly scans a package for Word features and reports how well Velum supports them,
+//! so the host can warn about possible fidelity loss before the user starts editing.
+
+use std::io::{Cursor, Read};
+use cfb::CompoundFile;
+use zip::ZipArchive;
+
+use super::error::OoxmlError;
+
+/// Magic bytes of a Compound File Binary container (used by encrypted .docx and legacy .doc)
+const CFB_SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// Word features that can be detected in a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum DocumentFeature {
+    TrackedChanges,
+    Comments,
+    Equations,
+    SmartArt,
+    Charts,
+    Macros,
+    Encryption,
+    FormFields,
+    ContentControls,
+    EmbeddedObjects,
+    TextBoxes,
+    Footnotes,
+    Endnotes,
+    HeadersFooters,
+    Tables,
+    Images,
+    Fields,
+    EmbeddedFonts,
+    CustomXml,
+    /// A Word 97-2003 binary document rather than a package
+    LegacyFormat,
+}
+
+/// How well Velum handles a detected feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub enum SupportLevel {
+    /// Read and written without loss
+    Full,
+    /// Read, but some properties are lost when editing or saving
+    Partial,
+    /// Ignored; the content is dropped or flattened
+    Unsupported,
+    /// The document cannot be opened at all
+    Blocking,
+}
+
+impl DocumentFeature {
+    /// Velum's current support level for this feature
+    ///
+    /// Custom XML and embedded fonts are parts a save carries unchanged, and
+    /// fields are read with their results; the body of a chart, diagram,
+    /// embedded object or text box is still dropped or flattened.
+    pub fn support_level(&self) -> SupportLevel {
+        match self {
+            DocumentFeature::Tables
+            | DocumentFeature::Images
+            | DocumentFeature::Footnotes
+            | DocumentFeature::Endnotes
+            | DocumentFeature::HeadersFooters
+            | DocumentFeature::ContentControls
+            | DocumentFeature::Macros
+            | DocumentFeature::FormFields
+            | DocumentFeature::Equations
+            | DocumentFeature::TrackedChanges
+            | DocumentFeature::Comments
+            | DocumentFeature::Fields
+            | DocumentFeature::CustomXml
+            | DocumentFeature::EmbeddedFonts
+            | DocumentFeature::LegacyFormat => SupportLevel::Partial,
+            DocumentFeature::SmartArt
+            | DocumentFeature::Charts
+            | DocumentFeature::EmbeddedObjects
+            | DocumentFeature::TextBoxes => SupportLevel::Unsupported,
+            DocumentFeature::Encryption => SupportLevel::Blocking,
+        }
+    }
+
+    /// Short human-readable description for UI warnings
+    pub fn description(&self) -> &'static str {
+        match self {
+            DocumentFeature::TrackedChanges => "Tracked changes (revisions)",
+            DocumentFeature::Comments => "Comments",
+            DocumentFeature::Equations => "Equations",
+            DocumentFeature::SmartArt => "SmartArt diagrams",
+            DocumentFeature::Charts => "Charts",
+            DocumentFeature::Macros => "Macros (VBA)",
+            DocumentFeature::Encryption => "Password encryption",
+            DocumentFeature::FormFields => "Legacy form fields",
+            DocumentFeature::ContentControls => "Content controls",
+            DocumentFeature::EmbeddedObjects => "Embedded OLE objects",
+            DocumentFeature::TextBoxes => "Text boxes",
+            DocumentFeature::Footnotes => "Footnotes",
+            DocumentFeature::Endnotes => "Endnotes",
+            DocumentFeature::HeadersFooters => "Headers and footers",
+            DocumentFeature::Tables => "Tables",
+            DocumentFeature::Images => "Images",
+            DocumentFeature::Fields => "Fields",
+            DocumentFeature::EmbeddedFonts => "Embedded fonts",
+            DocumentFeature::CustomXml => "Custom XML data",
+            DocumentFeature::LegacyFormat => "Word 97-2003 binary format",
+        }
+    }
+}
+
+/// A feature found in the package
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FeatureUsage {
+    pub feature: DocumentFeature,
+    pub support: SupportLevel,
+    /// Number of occurrences (elements or parts) found
+    pub occurrences: usize,
+    pub description: String,
+}
+
+/// Result of a feature scan
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FeatureReport {
+    pub features: Vec<FeatureUsage>,
+}
+
+impl FeatureReport {
+    /// Check whether a feature was detected
+    pub fn uses(&self, feature: DocumentFeature) -> bool {
+        self.features.iter().any(|f| f.feature == feature)
+    }
+
+    /// Get the usage entry for a feature
+    pub fn get(&self, feature: DocumentFeature) -> Option<&FeatureUsage> {
+        self.features.iter().find(|f| f.feature == feature)
+    }
+
+    /// Worst support level among the detected features
+    pub fn worst_support(&self) -> SupportLevel {
+        self.features
+            .iter()
+            .map(|f| f.support)
+            .max()
+            .unwrap_or(SupportLevel::Full)
+    }
+
+    /// True if editing this document may lose content
+    pub fn has_fidelity_risk(&self) -> bool {
+        self.worst_support() >= SupportLevel::Unsupported
+    }
+
+    fn record(&mut self, feature: DocumentFeature, occurrences: usize) {
+        if occurrences == 0 {
+            return;
+        }
+        if let Some(existing) = self.features.iter_mut().find(|f| f.feature == feature) {
+            existing.occurrences += occurrences;
+            return;
+        }
+        self.features.push(FeatureUsage {
+            feature,
+            support: feature.support_level(),
+            occurrences,
+            description: feature.description().to_string(),
+        });
+    }
+}
+
+/// Scan a package and report which Word features it uses
+///
+/// Only part names and raw XML are inspected; nothing is fully parsed, so this is
+/// much cheaper than `parse_ooxml`. Encrypted documents and Word 97-2003 files
+/// (which are CFB containers, not ZIP archives) are reported rather than rejected.
+pub fn analyze_features(file_data: &[u8]) -> Result<FeatureReport, OoxmlError> {
+    let mut report = FeatureReport::default();
+
+    if file_data.starts_with(&CFB_SIGNATURE) {
+        let compound = CompoundFile::open(Cursor::new(file_data))?;
+        if compound.is_stream("EncryptionInfo") && compound.is_stream("EncryptedPackage") {
+            report.record(DocumentFeature::Encryption, 1);
+        } else if compound.is_stream("WordDocument") {
+            report.record(DocumentFeature::LegacyFormat, 1);
+        } else {
+            return Err(OoxmlError::ParseError(
+                "compound file is neither an encrypted package nor a Word document".to_string(),
+            ));
+        }
+        return Ok(report);
+    }
+
+    let mut archive = ZipArchive::new(Cursor::new(file_data))?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().trim_start_matches('/').to_string();
+
+        scan_part_name(&name, &mut report);
+
+        if name.starts_with("word/") && name.ends_with(".xml") && !name.contains("/_rels/") {
+            let mut xml = String::new();
+            if file.read_to_string(&mut xml).is_ok() {
+                scan_part_xml(&name, &xml, &mut report);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Detect features from the presence of well-known parts
+fn scan_part_name(name: &str, report: &mut FeatureReport) {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+
+    if file_name == "vbaProject.bin" {
+        report.record(DocumentFeature::Macros, 1);
+    } else if name.starts_with("word/diagrams/") && file_name.starts_with("data") {
+        report.record(DocumentFeature::SmartArt, 1);
+    } else if name.starts_with("word/charts/") && file_name.starts_with("chart") && !name.contains("/_rels/") {
+        report.record(DocumentFeature::Charts, 1);
+    } else if name.starts_with("word/embeddings/") {
+        report.record(DocumentFeature::EmbeddedObjects, 1);
+    } else if name.starts_with("word/fonts/") {
+        report.record(DocumentFeature::EmbeddedFonts, 1);
+    } else if name.starts_with("customXml/item") && !file_name.starts_with("itemProps") && !name.contains("/_rels/") {
+        report.record(DocumentFeature::CustomXml, 1);
+    } else if name.starts_with("word/header") || name.starts_with("word/footer") {
+        report.record(DocumentFeature::HeadersFooters, 1);
+    } else if name.starts_with("word/media/") {
+        report.record(DocumentFeature::Images, 1);
+    }
+}
+
+/// Detect features from element usage inside a WordprocessingML part
+fn scan_part_xml(name: &str, xml: &str, report: &mut FeatureReport) {
+    match name {
+        "word/comments.xml" => report.record(DocumentFeature::Comments, count_elements(xml, "w:comment")),
+        "word/footnotes.xml" => report.record(DocumentFeature::Footnotes, count_user_notes(xml, "w:footnote")),
+        "word/endnotes.xml" => report.record(DocumentFeature::Endnotes, count_user_notes(xml, "w:endnote")),
+        _ => {}
+    }
+
+    if name.starts_with("word/comments") || name.starts_with("word/styles") || name.starts_with("word/numbering") {
+        return;
+    }
+
+    report.record(
+        DocumentFeature::TrackedChanges,
+        count_elements(xml, "w:ins")
+            + count_elements(xml, "w:del")
+            + count_elements(xml, "w:moveFrom")
+            + count_elements(xml, "w:moveTo")
+            + count_elements(xml, "w:rPrChange")
+            + count_elements(xml, "w:pPrChange"),
+    );
+    report.record(DocumentFeature::Equations, count_elements(xml, "m:oMath"));
+    report.record(DocumentFeature::FormFields, count_elements(xml, "w:ffData"));
+    report.record(DocumentFeature::ContentControls, count_elements(xml, "w:sdt"));
+    report.record(DocumentFeature::EmbeddedObjects, count_elements(xml, "w:object"));
+    report.record(
+        DocumentFeature::TextBoxes,
+        count_elements(xml, "wps:txbx") + count_elements(xml, "v:textbox"),
+    );
+    report.record(DocumentFeature::Tables, count_elements(xml, "w:tbl"));
+    report.record(
+        DocumentFeature::Fields,
+        count_elements(xml, "w:fldSimple") + count_field_begins(xml),
+    );
+}
+
+/// Count opening tags of an element, ignoring elements that merely share a prefix
+fn count_elements(xml: &str, tag: &str) -> usize {
+    let needle = format!("<{}", tag);
+    xml.match_indices(&needle)
+        .filter(|(idx, _)| {
+            matches!(
+                xml.as_bytes().get(idx + needle.len()),
+                Some(b' ') | Some(b'>') | Some(b'/') | Some(b'\n') | Some(b'\r') | Some(b'\t')
+            )
+        })
+        .count()
+}
+
+/// Count complex fields (each starts with a fldChar of type "begin")
+fn count_field_begins(xml: &str) -> usize {
+    xml.matches(r#"w:fldCharType="begin""#).count()
+}
+
+/// Count footnotes/endnotes, skipping the separator notes every document carries
+fn count_user_notes(xml: &str, tag: &str) -> usize {
+    let total = count_elements(xml, tag);
+    let separators = xml.matches(r#"w:type="separator""#).count()
+        + xml.matches(r#"w:type="continuationSeparator""#).count()
+        + xml.matches(r#"w:type="continuationNotice""#).count();
+    total.saturating_sub(separators)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::zip_fixture;
+
+    #[test]
+    fn test_plain_document_has_no_risk() {
+        let doc = br#"<w:document><w:body><w:p><w:r><w:t>Hello</w:t></w:r></w:p></w:body></w:document>"#;
+        let data = zip_fixture([("word/document.xml", doc)]);
+
+        let report = analyze_features(&data).unwrap();
+        assert!(report.features.is_empty());
+        assert!(!report.has_fidelity_risk());
+        assert_eq!(report.worst_support(), SupportLevel::Full);
+    }
+
+    #[test]
+    fn test_detects_tracked_changes_and_equations() {
+        let doc = br#"<w:document><w:body><w:p>
+            <w:ins w:id="1" w:author="A"><w:r><w:t>new</w:t></w:r></w:ins>
+            <w:del w:id="2" w:author="A"><w:r><w:delText>old</w:delText></w:r></w:del>
+            <m:oMathPara><m:oMath><m:r><m:t>x</m:t></m:r></m:oMath></m:oMathPara>
+        </w:p></w:body></w:document>"#;
+        let data = zip_fixture([("word/document.xml", doc)]);
+
+        let report = analyze_features(&data).unwrap();
+        assert_eq!(report.get(DocumentFeature::TrackedChanges).unwrap().occurrences, 2);
+        // Insertions, deletions and formatting changes round-trip, moves do not
+        assert_eq!(report.get(DocumentFeature::TrackedChanges).unwrap().support, SupportLevel::Partial);
+        // m:oMathPara must not be counted as an equation of its own
+        assert_eq!(report.get(DocumentFeature::Equations).unwrap().occurrences, 1);
+        assert_eq!(report.worst_support(), SupportLevel::Partial);
+        assert!(!report.has_fidelity_risk());
+    }
+
+    #[test]
+    fn test_detects_parts() {
+        let data = zip_fixture([
+            ("word/document.xml", b"<w:document/>".as_slice()),
+            ("word/vbaProject.bin", b"\x00\x01"),
+            ("word/diagrams/data1.xml", b"<dgm:dataModel/>"),
+            ("word/charts/chart1.xml", b"<c:chartSpace/>"),
+            ("word/media/image1.png", b"\x89PNG"),
+            ("word/header1.xml", b"<w:hdr/>"),
+            ("word/fonts/font1.odttf", b"\x00"),
+            ("customXml/item1.xml", b"<data/>"),
+        ]);
+
+        let report = analyze_features(&data).unwrap();
+        assert!(report.uses(DocumentFeature::Macros));
+        assert!(report.uses(DocumentFeature::SmartArt));
+        assert!(report.uses(DocumentFeature::Charts));
+        assert!(report.uses(DocumentFeature::Images));
+        assert!(report.uses(DocumentFeature::HeadersFooters));
+        assert!(!report.uses(DocumentFeature::Comments));
+        // Kept through a save, unlike the chart itself
+        assert_eq!(report.get(DocumentFeature::EmbeddedFonts).unwrap().support, SupportLevel::Partial);
+        assert_eq!(report.get(DocumentFeature::CustomXml).unwrap().support, SupportLevel::Partial);
+        assert_eq!(report.get(DocumentFeature::Charts).unwrap().support, SupportLevel::Unsupported);
+    }
+
+    #[test]
+    fn test_detects_forms_and_fields() {
+        let doc = br#"<w:document><w:body><w:p>
+            <w:r><w:fldChar w:fldCharType="begin"><w:ffData><w:name w:val="Text1"/></w:ffData></w:fldChar></w:r>
+            <w:r><w:instrText> FORMTEXT </w:instrText></w:r>
+            <w:r><w:fldChar w:fldCharType="end"/></w:r>
+            <w:sdt><w:sdtPr/><w:sdtContent/></w:sdt>
+        </w:p></w:body></w:document>"#;
+        let data = zip_fixture([("word/document.xml", doc)]);
+
+        let report = analyze_features(&data).unwrap();
+        assert_eq!(report.get(DocumentFeature::FormFields).unwrap().occurrences, 1);
+        assert_eq!(report.get(DocumentFeature::Fields).unwrap().occurrences, 1);
+        assert_eq!(report.get(DocumentFeature::Fields).unwrap().support, SupportLevel::Partial);
+        // w:sdtPr and w:sdtContent are not separate controls
+        assert_eq!(report.get(DocumentFeature::ContentControls).unwrap().occurrences, 1);
+    }
+
+    #[test]
+    fn test_footnote_separators_ignored() {
+        let notes = br#"<w:footnotes>
+            <w:footnote w:type="separator" w:id="-1"/>
+            <w:footnote w:type="continuationSeparator" w:id="0"/>
+            <w:footnote w:id="1"><w:p/></w:footnote>
+        </w:footnotes>"#;
+        let data = zip_fixture([("word/document.xml", b"<w:document/>".as_slice()), ("word/footnotes.xml", notes)]);
+
+        let report = analyze_features(&data).unwrap();
+        assert_eq!(report.get(DocumentFeature::Footnotes).unwrap().occurrences, 1);
+    }
+
+    #[test]
+    fn test_comments_partly_supported() {
+        let comments = br#"<w:comments>
+            <w:comment w:id="0" w:author="A"><w:p><w:r><w:t>Source?</w:t></w:r></w:p></w:comment>
+            <w:comment w:id="1" w:author="B"><w:p><w:r><w:t>Added</w:t></w:r></w:p></w:comment>
+        </w:comments>"#;
+        let data = zip_fixture([("word/document.xml", b"<w:document/>".as_slice()), ("word/comments.xml", comments)]);
+
+        let report = analyze_features(&data).unwrap();
+        let usage = report.get(DocumentFeature::Comments).unwrap();
+        // Bodies, ranges and threads round-trip; author identities (people.xml) do not
+        assert_eq!((usage.occurrences, usage.support), (2, SupportLevel::Partial));
+        assert!(!report.has_fidelity_risk());
+    }
+
+    fn compound_file(streams: &[&str]) -> Vec<u8> {
+        let mut compound = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        for name in streams {
+            std::io::Write::write_all(&mut compound.create_stream(name).unwrap(), &[0u8; 64]).unwrap();
+        }
+        compound.flush().unwrap();
+        compound.into_inner().into_inner()
+    }
+
+    #[test]
+    fn test_encrypted_package() {
+        let report = analyze_features(&compound_file(&["EncryptionInfo", "EncryptedPackage"])).unwrap();
+        assert!(report.uses(DocumentFeature::Encryption));
+        assert_eq!(report.worst_support(), SupportLevel::Blocking);
+
+        // A .doc is a compound file too, but opens
+        let report = analyze_features(&compound_file(&["WordDocument", "1Table"])).unwrap();
+        assert!(!report.uses(DocumentFeature::Encryption));
+        assert_eq!(report.get(DocumentFeature::LegacyFormat).unwrap().support, SupportLevel::Partial);
+
+        assert!(analyze_features(&compound_file(&["Workbook"])).is_err());
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        assert!(analyze_features(b"not a package").is_err());
+    }
+}
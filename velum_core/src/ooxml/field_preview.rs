@@ -0,0 +1,290 @@
+//! Field Preview
+//! Display-only substitution of field results (merge fields, page numbers, dates...)
+//! with sample or record data. The document itself is never modified; the preview
+//! produces a separate text plus an offset map back to the source.
+
+use std::collections::{HashMap, HashSet};
+
+use super::types::{Field, FieldKind};
+
+/// Preview settings: which field kinds are substituted and with what data
+#[derive(Debug, Clone, Default)]
+pub struct FieldPreview {
+    enabled: HashSet<FieldKind>,
+    record: HashMap<String, String>,
+    samples: HashMap<FieldKind, String>,
+    page_number: Option<usize>,
+    page_count: Option<usize>,
+    date: Option<String>,
+}
+
+/// A substituted field in the preview text
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PreviewSpan {
+    pub kind: FieldKind,
+    /// Char offset of the field result in the source text
+    pub source_start: usize,
+    /// Length of the field result in the source text
+    pub source_length: usize,
+    /// Char offset of the substituted value in the preview text
+    pub start: usize,
+    /// Length of the substituted value
+    pub length: usize,
+}
+
+/// Preview text with the spans that were substituted
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PreviewText {
+    pub text: String,
+    pub spans: Vec<PreviewSpan>,
+}
+
+impl FieldPreview {
+    /// Create a preview with every field kind disabled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable or disable substitution for a field kind
+    pub fn set_enabled(&mut self, kind: FieldKind, enabled: bool) {
+        if enabled {
+            self.enabled.insert(kind);
+        } else {
+            self.enabled.remove(&kind);
+        }
+    }
+
+    /// Flip substitution for a field kind, returning the new state
+    pub fn toggle(&mut self, kind: FieldKind) -> bool {
+        let enabled = !self.is_enabled(kind);
+        self.set_enabled(kind, enabled);
+        enabled
+    }
+
+    /// Check whether a field kind is substituted
+    pub fn is_enabled(&self, kind: FieldKind) -> bool {
+        self.enabled.contains(&kind)
+    }
+
+    /// Set the data record used for merge fields (field name -> value)
+    pub fn set_record(&mut self, record: HashMap<String, String>) {
+        self.record = record;
+    }
+
+    /// Set sample text shown for a field kind when no better data is available
+    pub fn set_sample(&mut self, kind: FieldKind, text: &str) {
+        self.samples.insert(kind, text.to_string());
+    }
+
+    /// Set the page values used for PAGE / NUMPAGES fields
+    pub fn set_page_info(&mut self, page_number: usize, page_count: usize) {
+        self.page_number = Some(page_number);
+        self.page_count = Some(page_count);
+    }
+
+    /// Set the text shown for date fields (defaults to today's date)
+    pub fn set_date(&mut self, date: &str) {
+        self.date = Some(date.to_string());
+    }
+
+    /// Value to display for a field, or None to keep its cached result
+    pub fn preview_value(&self, field: &Field) -> Option<String> {
+        if !self.is_enabled(field.kind) {
+            return None;
+        }
+
+        let value = match field.kind {
+            FieldKind::MergeField => {
+                let name = field.argument().unwrap_or_default();
+                // Field names are matched case-insensitively, like Word does
+                let value = self
+                    .record
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.clone());
+                Some(value.unwrap_or_else(|| format!("\u{00AB}{}\u{00BB}", name)))
+            }
+            FieldKind::Page => self.page_number.map(|n| n.to_string()),
+            FieldKind::NumPages => self.page_count.map(|n| n.to_string()),
+            FieldKind::Date => Some(
+                self.date
+                    .clone()
+                    .unwrap_or_else(|| chrono::Local::now().format("%-m/%-d/%Y").to_string()),
+            ),
+            _ => None,
+        };
+
+        value.or_else(|| self.samples.get(&field.kind).cloned())
+    }
+
+    /// Build the preview of `text`, substituting the enabled fields
+    ///
+    /// Field offsets are char offsets into `text`. Nested or overlapping fields are
+    /// only substituted once, by the outermost (first) field.
+    pub fn apply(&self, text: &str, fields: &[Field]) -> PreviewText {
+        let chars: Vec<char> = text.chars().collect();
+        let mut sorted: Vec<&Field> = fields.iter().collect();
+        sorted.sort_by_key(|f| (f.start, std::cmp::Reverse(f.length)));
+
+        let mut preview = PreviewText::default();
+        let mut source_pos = 0usize;
+        let mut preview_len = 0usize;
+
+        for field in sorted {
+            if field.start < source_pos || field.start + field.length > chars.len() {
+                continue;
+            }
+            let Some(value) = self.preview_value(field) else {
+                continue;
+            };
+
+            let unchanged: String = chars[source_pos..field.start].iter().collect();
+            preview_len += field.start - source_pos;
+            preview.text.push_str(&unchanged);
+
+            let length = value.chars().count();
+            preview.spans.push(PreviewSpan {
+                kind: field.kind,
+                source_start: field.start,
+                source_length: field.length,
+                start: preview_len,
+                length,
+            });
+            preview.text.push_str(&value);
+            preview_len += length;
+            source_pos = field.start + field.length;
+        }
+
+        let rest: String = chars[source_pos.min(chars.len())..].iter().collect();
+        preview.text.push_str(&rest);
+        preview
+    }
+}
+
+impl PreviewText {
+    /// Map a char offset in the preview text back to the source text
+    ///
+    /// Offsets inside a substituted value map to the start of the field.
+    pub fn to_source_offset(&self, offset: usize) -> usize {
+        let mut delta: isize = 0;
+        for span in &self.spans {
+            if offset < span.start {
+                break;
+            }
+            if offset < span.start + span.length {
+                return span.source_start;
+            }
+            delta += span.source_length as isize - span.length as isize;
+        }
+        (offset as isize + delta).max(0) as usize
+    }
+
+    /// Map a char offset in the source text to the preview text
+    ///
+    /// Offsets inside a substituted field map to the start of its value.
+    pub fn to_preview_offset(&self, offset: usize) -> usize {
+        let mut delta: isize = 0;
+        for span in &self.spans {
+            if offset < span.source_start {
+                break;
+            }
+            if offset < span.source_start + span.source_length {
+                return span.start;
+            }
+            delta += span.length as isize - span.source_length as isize;
+        }
+        (offset as isize + delta).max(0) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn letter() -> (String, Vec<Field>) {
+        let text = "Dear «FirstName», page 1 of 1".to_string();
+        let fields = vec![
+            Field::new("MERGEFIELD FirstName", 5, "«FirstName»"),
+            Field::new("PAGE", 23, "1"),
+            Field::new("NUMPAGES", 28, "1"),
+        ];
+        (text, fields)
+    }
+
+    #[test]
+    fn test_disabled_preview_keeps_text() {
+        let (text, fields) = letter();
+        let preview = FieldPreview::new().apply(&text, &fields);
+        assert_eq!(preview.text, text);
+        assert!(preview.spans.is_empty());
+    }
+
+    #[test]
+    fn test_merge_field_record() {
+        let (text, fields) = letter();
+        let mut preview = FieldPreview::new();
+        preview.set_enabled(FieldKind::MergeField, true);
+        preview.set_record(HashMap::from([("firstname".to_string(), "Ada".to_string())]));
+
+        let result = preview.apply(&text, &fields);
+        assert_eq!(result.text, "Dear Ada, page 1 of 1");
+        assert_eq!(result.spans.len(), 1);
+        assert_eq!(result.spans[0].start, 5);
+        assert_eq!(result.spans[0].length, 3);
+        // Source text is untouched
+        assert_eq!(text, "Dear «FirstName», page 1 of 1");
+    }
+
+    #[test]
+    fn test_toggle_per_kind() {
+        let (text, fields) = letter();
+        let mut preview = FieldPreview::new();
+        preview.set_page_info(4, 12);
+        assert!(preview.toggle(FieldKind::NumPages));
+
+        let result = preview.apply(&text, &fields);
+        assert_eq!(result.text, "Dear «FirstName», page 1 of 12");
+
+        assert!(!preview.toggle(FieldKind::NumPages));
+        assert_eq!(preview.apply(&text, &fields).text, text);
+    }
+
+    #[test]
+    fn test_samples_and_dates() {
+        let mut preview = FieldPreview::new();
+        preview.set_enabled(FieldKind::Toc, true);
+        preview.set_enabled(FieldKind::Date, true);
+        preview.set_sample(FieldKind::Toc, "[Table of Contents]");
+        preview.set_date("1/2/2025");
+
+        let fields = vec![Field::new("TOC \\o \"1-3\"", 0, ""), Field::new("DATE", 1, "x")];
+        let result = preview.apply("\nx", &fields);
+        assert_eq!(result.text, "[Table of Contents]\n1/2/2025");
+    }
+
+    #[test]
+    fn test_missing_merge_data_shows_placeholder() {
+        let mut preview = FieldPreview::new();
+        preview.set_enabled(FieldKind::MergeField, true);
+        let fields = vec![Field::new("MERGEFIELD City", 0, "")];
+        assert_eq!(preview.apply("", &fields).text, "«City»");
+    }
+
+    #[test]
+    fn test_offset_mapping() {
+        let (text, fields) = letter();
+        let mut preview = FieldPreview::new();
+        preview.set_enabled(FieldKind::MergeField, true);
+        preview.set_record(HashMap::from([("FirstName".to_string(), "Ada".to_string())]));
+        let result = preview.apply(&text, &fields);
+
+        // "Dear " is unchanged
+        assert_eq!(result.to_source_offset(2), 2);
+        // Inside the substituted value maps to the field start
+        assert_eq!(result.to_source_offset(6), 5);
+        // After the field, offsets shift by the length difference (11 -> 3)
+        assert_eq!(result.to_source_offset(8), 16);
+        assert_eq!(result.to_preview_offset(16), 8);
+        assert_eq!(result.to_preview_offset(10), 5);
+    }
+}
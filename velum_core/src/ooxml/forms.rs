@@ -0,0 +1,1260 @@
+//! Form Fields
+//! Legacy form fields (FORMTEXT / FORMCHECKBOX / FORMDROPDOWN) and their modern
+//! content-control equivalents, with protection-aware value editing and tab order.
+//! Filled values are written back into document.xml in place, so a host can
+//! complete a form without touching OOXML.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use chrono::NaiveDate;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::document::unescape_xml_text;
+use super::error::OoxmlError;
+use super::parts::{entry_text, insert_before_closing, read_entries, set_part, write_entries};
+use super::serializer::{escape_xml_attr, escape_xml_text};
+
+const DOCUMENT_PART: &str = "word/document.xml";
+const SETTINGS_PART: &str = "word/settings.xml";
+
+/// Date format of date content controls that do not name one
+const DEFAULT_DATE_FORMAT: &str = "M/d/yyyy";
+
+/// Settings elements that come after w:documentProtection in schema order
+const SETTINGS_AFTER_PROTECTION: [&str; 20] = [
+    "<w:autoFormatOverride",
+    "<w:styleLockTheme",
+    "<w:styleLockQFSet",
+    "<w:defaultTabStop",
+    "<w:autoHyphenation",
+    "<w:consecutiveHyphenLimit",
+    "<w:hyphenationZone",
+    "<w:doNotHyphenateCaps",
+    "<w:showEnvelope",
+    "<w:summaryLength",
+    "<w:clickAndTypeStyle",
+    "<w:defaultTableStyle",
+    "<w:evenAndOddHeaders",
+    "<w:bookFold",
+    "<w:characterSpacingControl",
+    "<w:compat",
+    "<w:docVars",
+    "<w:rsids",
+    "<m:mathPr",
+    "<w:themeFontLang",
+];
+
+static PROTECTION: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w:documentProtection\b[^>]*>"#).unwrap());
+static FIELD_BEGIN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?s)<w:fldChar\b[^>]*w:fldCharType="begin"[^>]*>(.*?)</w:fldChar>"#).unwrap());
+static FIELD_SEPARATE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"<w:fldChar\b[^>]*w:fldCharType="separate"[^>]*/?>"#).unwrap());
+static FIELD_END: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w:fldChar\b[^>]*w:fldCharType="end"[^>]*/?>"#).unwrap());
+static LIST_ENTRY: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w:listEntry\b[^>]*w:val="([^"]*)""#).unwrap());
+static LEGACY_CHECKED: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w:checked\b[^>]*/>"#).unwrap());
+static LEGACY_RESULT: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w:result\b[^>]*/>"#).unwrap());
+static CONTENT_CONTROL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?s)<w:sdt>\s*<w:sdtPr>(.*?)</w:sdtPr>.*?<w:sdtContent>(.*?)</w:sdtContent>"#).unwrap()
+});
+static LIST_ITEM: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w:listItem\b[^>]*>"#).unwrap());
+static DATE_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w:date\b[^>]*>"#).unwrap());
+static FULL_DATE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"\sw:fullDate="[^"]*""#).unwrap());
+static SHOWING_PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w:showingPlcHdr\b[^>]*/>"#).unwrap());
+static CONTROL_CHECKED: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w14:checked\b[^>]*/>"#).unwrap());
+static EMPTY_PARAGRAPH: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<w:p\b([^>]*)/>"#).unwrap());
+static TEXT_ELEMENT: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?s)<w:t(?:\s[^>]*)?(?:/>|>.*?</w:t>)"#).unwrap());
+static TEXT_CONTENT: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?s)<w:t(?:\s[^>]*)?>(.*?)</w:t>"#).unwrap());
+static START_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<([\w:]+)(?:\s[^>]*)?/?>"#).unwrap());
+static ATTRIBUTE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"([\w:]+)="([^"]*)""#).unwrap());
+
+/// Kind of form field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FormFieldKind {
+    Text,
+    Checkbox,
+    DropDown,
+    Date,
+}
+
+/// Where a form field came from in the document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FormFieldSource {
+    /// Complex field with w:ffData
+    Legacy,
+    /// Structured document tag (w:sdt)
+    ContentControl,
+}
+
+/// Document protection mode from settings.xml (w:documentProtection)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum ProtectionMode {
+    #[default]
+    None,
+    ReadOnly,
+    Comments,
+    TrackedChanges,
+    /// Only form fields may be edited
+    Forms,
+}
+
+impl ProtectionMode {
+    fn from_edit_value(value: &str) -> Self {
+        match value {
+            "readOnly" => ProtectionMode::ReadOnly,
+            "comments" => ProtectionMode::Comments,
+            "trackedChanges" => ProtectionMode::TrackedChanges,
+            "forms" => ProtectionMode::Forms,
+            _ => ProtectionMode::None,
+        }
+    }
+
+    fn edit_value(&self) -> Option<&'static str> {
+        match self {
+            ProtectionMode::None => None,
+            ProtectionMode::ReadOnly => Some("readOnly"),
+            ProtectionMode::Comments => Some("comments"),
+            ProtectionMode::TrackedChanges => Some("trackedChanges"),
+            ProtectionMode::Forms => Some("forms"),
+        }
+    }
+
+    /// The w:documentProtection element of settings.xml enforcing this mode
+    pub fn to_xml(&self) -> Option<String> {
+        self.edit_value()
+            .map(|edit| format!(r#"<w:documentProtection w:edit="{}" w:enforcement="1"/>"#, edit))
+    }
+}
+
+/// A single fillable field
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FormField {
+    /// Bookmark name (legacy) or tag/alias (content control)
+    pub name: String,
+    /// Title shown to the user (content control w:alias)
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Tag identifying the control to programs (content control w:tag)
+    #[serde(default)]
+    pub tag: Option<String>,
+    pub kind: FormFieldKind,
+    pub source: FormFieldSource,
+    /// Current text value (text, date and drop-down fields)
+    pub value: String,
+    /// Current state (checkbox fields)
+    pub checked: bool,
+    /// Allowed entries (drop-down fields)
+    pub options: Vec<String>,
+    /// Text shown for each entry of `options`, where it differs from the entry
+    /// (content control list items with a w:displayText)
+    #[serde(default)]
+    pub option_labels: Vec<String>,
+    /// Maximum text length, if limited
+    pub max_length: Option<usize>,
+    /// Disabled fields are skipped in tab order and cannot be filled
+    pub enabled: bool,
+    pub help_text: Option<String>,
+    /// Date format (date fields)
+    pub date_format: Option<String>,
+    /// Date the value stands for (date fields)
+    #[serde(default)]
+    pub date: Option<NaiveDate>,
+}
+
+impl FormField {
+    /// An enabled field of `kind` with no value
+    pub fn new(name: &str, kind: FormFieldKind, source: FormFieldSource) -> Self {
+        FormField {
+            name: name.to_string(),
+            title: None,
+            tag: None,
+            kind,
+            source,
+            value: String::new(),
+            checked: false,
+            options: Vec::new(),
+            option_labels: Vec::new(),
+            max_length: None,
+            enabled: true,
+            help_text: None,
+            date_format: None,
+            date: None,
+        }
+    }
+
+    /// Text shown for the drop-down entry `option`
+    pub fn option_label<'a>(&'a self, option: &'a str) -> &'a str {
+        self.options
+            .iter()
+            .position(|o| o == option)
+            .and_then(|index| self.option_labels.get(index))
+            .map_or(option, String::as_str)
+    }
+
+    /// The text the field shows in the document: its value, the label of its
+    /// drop-down entry, or a content control checkbox's glyph; a legacy
+    /// checkbox shows none
+    pub fn text(&self) -> String {
+        match (self.kind, self.source) {
+            (FormFieldKind::Checkbox, FormFieldSource::Legacy) => String::new(),
+            (FormFieldKind::Checkbox, FormFieldSource::ContentControl) => {
+                (if self.checked { "\u{2612}" } else { "\u{2610}" }).to_string()
+            }
+            (FormFieldKind::DropDown, _) => self.option_label(&self.value).to_string(),
+            (FormFieldKind::Text | FormFieldKind::Date, _) => self.value.clone(),
+        }
+    }
+
+    /// The field once `text` was typed into it: text and date fields take it
+    /// as their value, the others keep theirs
+    pub fn with_text(&self, text: &str) -> FormField {
+        let mut field = self.clone();
+        match field.kind {
+            FormFieldKind::Text => field.value = text.to_string(),
+            FormFieldKind::Date => {
+                field.value = text.to_string();
+                field.date = parse_date(text, field.date_format.as_deref());
+            }
+            FormFieldKind::Checkbox | FormFieldKind::DropDown => {}
+        }
+        field
+    }
+
+    /// Serialize as what the field was read from: a legacy field or a content control
+    pub fn to_xml(&self) -> String {
+        match self.source {
+            FormFieldSource::Legacy => self.to_legacy_xml(),
+            FormFieldSource::ContentControl => self.to_sdt_xml(),
+        }
+    }
+
+    /// Serialize as a legacy complex field (fldChar begin/separate/end with w:ffData)
+    pub fn to_legacy_xml(&self) -> String {
+        let mut ff_data = format!(r#"<w:ffData><w:name w:val="{}"/>"#, escape_xml_attr(&self.name));
+        ff_data.push_str(if self.enabled { "<w:enabled/>" } else { r#"<w:enabled w:val="0"/>"# });
+        if let Some(ref help) = self.help_text {
+            ff_data.push_str(&format!(r#"<w:helpText w:type="text" w:val="{}"/>"#, escape_xml_attr(help)));
+        }
+
+        let instruction = match self.kind {
+            FormFieldKind::Checkbox => {
+                ff_data.push_str(&format!(
+                    r#"<w:checkBox><w:sizeAuto/><w:default w:val="{}"/></w:checkBox>"#,
+                    if self.checked { 1 } else { 0 }
+                ));
+                "FORMCHECKBOX"
+            }
+            FormFieldKind::DropDown => {
+                ff_data.push_str("<w:ddList>");
+                if let Some(index) = self.options.iter().position(|o| *o == self.value) {
+                    ff_data.push_str(&format!(r#"<w:result w:val="{}"/>"#, index));
+                }
+                for option in &self.options {
+                    ff_data.push_str(&format!(r#"<w:listEntry w:val="{}"/>"#, escape_xml_attr(option)));
+                }
+                ff_data.push_str("</w:ddList>");
+                "FORMDROPDOWN"
+            }
+            FormFieldKind::Text | FormFieldKind::Date => {
+                ff_data.push_str("<w:textInput>");
+                if self.kind == FormFieldKind::Date {
+                    ff_data.push_str(r#"<w:type w:val="date"/>"#);
+                }
+                if let Some(max) = self.max_length {
+                    ff_data.push_str(&format!(r#"<w:maxLength w:val="{}"/>"#, max));
+                }
+                if let Some(ref format) = self.date_format {
+                    ff_data.push_str(&format!(r#"<w:format w:val="{}"/>"#, escape_xml_attr(format)));
+                }
+                ff_data.push_str("</w:textInput>");
+                "FORMTEXT"
+            }
+        };
+        ff_data.push_str("</w:ffData>");
+
+        let mut xml = format!(
+            r#"<w:r><w:fldChar w:fldCharType="begin">{}</w:fldChar></w:r><w:r><w:instrText xml:space="preserve"> {} </w:instrText></w:r>"#,
+            ff_data, instruction
+        );
+        if self.kind != FormFieldKind::Checkbox {
+            xml.push_str(r#"<w:r><w:fldChar w:fldCharType="separate"/></w:r>"#);
+            xml.push_str(&format!(
+                r#"<w:r><w:t xml:space="preserve">{}</w:t></w:r>"#,
+                escape_xml_text(&self.value)
+            ));
+        }
+        xml.push_str(r#"<w:r><w:fldChar w:fldCharType="end"/></w:r>"#);
+        xml
+    }
+
+    /// Serialize as the equivalent run-level content control (w:sdt)
+    pub fn to_sdt_xml(&self) -> String {
+        self.sdt_xml(None)
+    }
+
+    /// The content control showing `placeholder`, or its value when there is none
+    fn sdt_xml(&self, placeholder: Option<&str>) -> String {
+        let mut props = format!(
+            r#"<w:sdtPr><w:alias w:val="{}"/><w:tag w:val="{}"/>"#,
+            escape_xml_attr(self.title.as_deref().unwrap_or(&self.name)),
+            escape_xml_attr(self.tag.as_deref().unwrap_or(&self.name))
+        );
+        if !self.enabled {
+            props.push_str(r#"<w:lock w:val="sdtContentLocked"/>"#);
+        }
+
+        let content = match self.kind {
+            FormFieldKind::Checkbox => {
+                let state = if self.checked { 1 } else { 0 };
+                props.push_str(&format!(
+                    r#"<w14:checkbox><w14:checked w14:val="{}"/><w14:checkedState w14:val="2612"/><w14:uncheckedState w14:val="2610"/></w14:checkbox>"#,
+                    state
+                ));
+                (if self.checked { "\u{2612}" } else { "\u{2610}" }).to_string()
+            }
+            FormFieldKind::DropDown => {
+                props.push_str("<w:dropDownList>");
+                for option in &self.options {
+                    props.push_str(&format!(
+                        r#"<w:listItem w:displayText="{}" w:value="{}"/>"#,
+                        escape_xml_attr(self.option_label(option)),
+                        escape_xml_attr(option)
+                    ));
+                }
+                props.push_str("</w:dropDownList>");
+                self.option_label(&self.value).to_string()
+            }
+            FormFieldKind::Date => {
+                match self.date {
+                    Some(date) => props.push_str(&format!(r#"<w:date w:fullDate="{}T00:00:00Z">"#, date)),
+                    None => props.push_str("<w:date>"),
+                }
+                if let Some(ref format) = self.date_format {
+                    props.push_str(&format!(r#"<w:dateFormat w:val="{}"/>"#, escape_xml_attr(format)));
+                }
+                props.push_str("</w:date>");
+                self.value.clone()
+            }
+            FormFieldKind::Text => {
+                props.push_str("<w:text/>");
+                self.value.clone()
+            }
+        };
+        let content = match placeholder {
+            Some(text) => {
+                props.push_str("<w:showingPlcHdr/>");
+                text.to_string()
+            }
+            None => content,
+        };
+        props.push_str("</w:sdtPr>");
+
+        format!(
+            r#"<w:sdt>{}<w:sdtContent><w:r><w:t xml:space="preserve">{}</w:t></w:r></w:sdtContent></w:sdt>"#,
+            props,
+            escape_xml_text(&content)
+        )
+    }
+}
+
+/// A form field at chars of a text, which hold what the field shows
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AnchoredFormField {
+    #[serde(flatten)]
+    pub field: FormField,
+    /// Char offset of the field's text in the containing text
+    pub start: usize,
+    /// Length of the field's text in chars
+    pub length: usize,
+    /// Whether the text is the placeholder a content control shows while it
+    /// has no value
+    #[serde(default)]
+    pub placeholder: bool,
+}
+
+impl AnchoredFormField {
+    /// Serialize the field, which shows `text`, as what it was read from
+    pub fn to_xml(&self, text: &str) -> String {
+        match self.field.source {
+            FormFieldSource::ContentControl if self.placeholder => self.field.sdt_xml(Some(text)),
+            _ => self.field.to_xml(),
+        }
+    }
+}
+
+/// Errors raised when filling form fields
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum FormError {
+    #[error("Form field not found: {0}")]
+    NotFound(String),
+
+    #[error("Form field is disabled: {0}")]
+    Disabled(String),
+
+    #[error("Form field has a different type: {0}")]
+    WrongKind(String),
+
+    #[error("Invalid value for form field {0}: {1}")]
+    InvalidValue(String, String),
+
+    #[error("Document is protected against editing")]
+    Protected,
+}
+
+/// All form fields of a document, in document order
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FormFieldSet {
+    pub fields: Vec<FormField>,
+    pub protection: ProtectionMode,
+}
+
+impl FormFieldSet {
+    /// Parse form fields from document.xml and the protection mode from settings.xml
+    pub fn parse(document_xml: &str, settings_xml: Option<&str>) -> Self {
+        FormFieldSet {
+            fields: find_fields(document_xml).into_iter().map(|(_, field)| field).collect(),
+            protection: settings_xml.map(parse_protection).unwrap_or_default(),
+        }
+    }
+
+    /// Get a field by name
+    pub fn get(&self, name: &str) -> Option<&FormField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Whether text outside form fields may be edited
+    pub fn allows_free_editing(&self) -> bool {
+        self.protection == ProtectionMode::None
+    }
+
+    /// Enabled fields in tab order (document order)
+    pub fn tab_order(&self) -> Vec<&FormField> {
+        self.fields.iter().filter(|f| f.enabled).collect()
+    }
+
+    /// Field that follows `current` in tab order, wrapping around at the end
+    pub fn next_field(&self, current: &str) -> Option<&FormField> {
+        let order = self.tab_order();
+        let index = order.iter().position(|f| f.name == current)?;
+        order.get((index + 1) % order.len()).copied()
+    }
+
+    /// Field that precedes `current` in tab order, wrapping around at the start
+    pub fn previous_field(&self, current: &str) -> Option<&FormField> {
+        let order = self.tab_order();
+        let index = order.iter().position(|f| f.name == current)?;
+        order.get((index + order.len() - 1) % order.len()).copied()
+    }
+
+    /// Set the value of a text, date or drop-down field
+    ///
+    /// Drop-down fields take one of their entries or its label. Date fields take
+    /// an ISO date (2024-03-05) or a date in their own format, and store it in
+    /// their format; an empty value clears them.
+    pub fn set_value(&mut self, name: &str, value: &str) -> Result<(), FormError> {
+        let field = self.editable_field(name)?;
+        let invalid = || FormError::InvalidValue(name.to_string(), value.to_string());
+        match field.kind {
+            FormFieldKind::Checkbox => return Err(FormError::WrongKind(name.to_string())),
+            FormFieldKind::DropDown => {
+                let index = field
+                    .options
+                    .iter()
+                    .position(|o| o == value)
+                    .or_else(|| field.option_labels.iter().position(|l| l == value))
+                    .ok_or_else(invalid)?;
+                field.value = field.options[index].clone();
+                return Ok(());
+            }
+            FormFieldKind::Date if value.trim().is_empty() => {
+                field.date = None;
+            }
+            FormFieldKind::Date => {
+                let date = parse_date(value, field.date_format.as_deref()).ok_or_else(invalid)?;
+                field.value = format_date(date, field.date_format.as_deref());
+                field.date = Some(date);
+                return Ok(());
+            }
+            FormFieldKind::Text => {
+                if let Some(max) = field.max_length {
+                    if value.chars().count() > max {
+                        return Err(invalid());
+                    }
+                }
+            }
+        }
+        field.value = value.to_string();
+        Ok(())
+    }
+
+    /// Set any field from text: checkboxes take "true" or "false" ("1", "0",
+    /// "yes", "no" and "x" also do), other fields their value as in `set_value`
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), FormError> {
+        if self.get(name).map(|f| f.kind) != Some(FormFieldKind::Checkbox) {
+            return self.set_value(name, value);
+        }
+        let checked = match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" | "x" => true,
+            "false" | "0" | "no" | "off" | "" => false,
+            _ => return Err(FormError::InvalidValue(name.to_string(), value.to_string())),
+        };
+        self.set_checked(name, checked)
+    }
+
+    /// Set several fields by name, each as `set` does; if any value is refused
+    /// the first error (by field name) is returned and no field changes
+    pub fn fill(&mut self, values: &HashMap<String, String>) -> Result<(), FormError> {
+        let mut names: Vec<&String> = values.keys().collect();
+        names.sort();
+
+        let mut filled = self.clone();
+        for name in names {
+            filled.set(name, &values[name])?;
+        }
+        *self = filled;
+        Ok(())
+    }
+
+    /// Write the fields' values into `document_xml`, the document the set was parsed from
+    ///
+    /// Only fields whose value changed are rewritten, keeping their formatting;
+    /// content controls that showed their placeholder text show the value instead.
+    pub fn write_values(&self, document_xml: &str) -> String {
+        let mut xml = document_xml.to_string();
+        // From the end, so earlier ranges stay valid
+        for ((range, original), field) in find_fields(document_xml).into_iter().zip(&self.fields).rev() {
+            if original.name != field.name || original.kind != field.kind || original == *field {
+                continue;
+            }
+            let updated = match field.source {
+                FormFieldSource::Legacy => write_legacy_value(&xml[range.clone()], field),
+                FormFieldSource::ContentControl => write_control_value(&xml[range.clone()], field),
+            };
+            xml.replace_range(range, &updated);
+        }
+        xml
+    }
+
+    /// Set the state of a checkbox field
+    pub fn set_checked(&mut self, name: &str, checked: bool) -> Result<(), FormError> {
+        let field = self.editable_field(name)?;
+        if field.kind != FormFieldKind::Checkbox {
+            return Err(FormError::WrongKind(name.to_string()));
+        }
+        field.checked = checked;
+        Ok(())
+    }
+
+    /// Serialize the protection setting for settings.xml
+    pub fn protection_xml(&self) -> Option<String> {
+        self.protection.to_xml()
+    }
+
+    fn editable_field(&mut self, name: &str) -> Result<&mut FormField, FormError> {
+        // Only forms protection leaves fields fillable
+        if !matches!(self.protection, ProtectionMode::None | ProtectionMode::Forms) {
+            return Err(FormError::Protected);
+        }
+        let field = self
+            .fields
+            .iter_mut()
+            .find(|f| f.name == name)
+            .ok_or_else(|| FormError::NotFound(name.to_string()))?;
+        if !field.enabled {
+            return Err(FormError::Disabled(name.to_string()));
+        }
+        Ok(field)
+    }
+}
+
+/// Read the form fields of a .docx
+pub fn form_fields(file_data: &[u8]) -> Result<FormFieldSet, OoxmlError> {
+    let entries = read_entries(file_data)?;
+    let document = entry_text(&entries, DOCUMENT_PART)
+        .ok_or_else(|| OoxmlError::MissingRequiredPart(DOCUMENT_PART.to_string()))?;
+    Ok(FormFieldSet::parse(&document, entry_text(&entries, SETTINGS_PART).as_deref()))
+}
+
+/// Fill form fields of a .docx by name, as `FormFieldSet::fill` does
+/// Returns the completed package and its fields
+pub fn fill_form(file_data: &[u8], values: &HashMap<String, String>) -> Result<(Vec<u8>, FormFieldSet), OoxmlError> {
+    let mut entries = read_entries(file_data)?;
+    let document = entry_text(&entries, DOCUMENT_PART)
+        .ok_or_else(|| OoxmlError::MissingRequiredPart(DOCUMENT_PART.to_string()))?;
+    let mut fields = FormFieldSet::parse(&document, entry_text(&entries, SETTINGS_PART).as_deref());
+    fields.fill(values)?;
+
+    set_part(&mut entries, DOCUMENT_PART, fields.write_values(&document), "document.main");
+    Ok((write_entries(&entries)?, fields))
+}
+
+/// Form fields of document.xml with their byte ranges, in document order
+fn find_fields(document_xml: &str) -> Vec<(Range<usize>, FormField)> {
+    let mut found = parse_legacy_fields(document_xml);
+    found.extend(parse_content_controls(document_xml));
+    found.sort_by_key(|(range, _)| range.start);
+    found
+}
+
+/// Read w:documentProtection from settings.xml; only enforced protection counts
+pub(super) fn parse_protection(settings_xml: &str) -> ProtectionMode {
+    let Some(element) = PROTECTION.find(settings_xml) else {
+        return ProtectionMode::None;
+    };
+    let element = element.as_str();
+
+    let enforced = attribute(element, "w:enforcement")
+        .map(|v| v == "1" || v == "true" || v == "on")
+        .unwrap_or(false);
+    if !enforced {
+        return ProtectionMode::None;
+    }
+    attribute(element, "w:edit")
+        .map(|v| ProtectionMode::from_edit_value(&v))
+        .unwrap_or_default()
+}
+
+/// `settings` enforcing `protection` in place of any w:documentProtection it has
+pub(super) fn set_protection_in_settings(settings: &str, protection: ProtectionMode) -> String {
+    let mut settings = PROTECTION.replace(settings, "").into_owned();
+    if let Some(element) = protection.to_xml() {
+        match SETTINGS_AFTER_PROTECTION.iter().filter_map(|tag| settings.find(tag)).min() {
+            Some(at) => settings.insert_str(at, &element),
+            None => insert_before_closing(&mut settings, "w:settings", &element),
+        }
+    }
+    settings
+}
+
+/// Parse legacy form fields, returning each with the bytes from its begin to its end fldChar
+fn parse_legacy_fields(xml: &str) -> Vec<(Range<usize>, FormField)> {
+    let mut fields = Vec::new();
+    for cap in FIELD_BEGIN.captures_iter(xml) {
+        let whole = cap.get(0).unwrap();
+        let ff_data = &cap[1];
+        if !ff_data.contains("<w:ffData") {
+            continue;
+        }
+
+        let rest = &xml[whole.end()..];
+        let (field_body, field_end) = match FIELD_END.find(rest) {
+            Some(m) => (&rest[..m.start()], whole.end() + m.end()),
+            None => (rest, xml.len()),
+        };
+        let result_text = FIELD_SEPARATE
+            .find(field_body)
+            .map(|m| collect_text(&field_body[m.end()..]))
+            .unwrap_or_default();
+
+        fields.push((whole.start()..field_end, legacy_field(ff_data, field_body, &result_text)));
+    }
+    fields
+}
+
+/// The legacy form field of a begin fldChar's w:ffData, whose instruction
+/// (FORMTEXT, FORMCHECKBOX or FORMDROPDOWN) `instruction` holds and whose result is `result`
+pub(super) fn legacy_field(ff_data: &str, instruction: &str, result: &str) -> FormField {
+    let kind = if instruction.contains("FORMCHECKBOX") || ff_data.contains("<w:checkBox") {
+        FormFieldKind::Checkbox
+    } else if instruction.contains("FORMDROPDOWN") || ff_data.contains("<w:ddList") {
+        FormFieldKind::DropDown
+    } else if ff_data.contains(r#"<w:type w:val="date""#) {
+        FormFieldKind::Date
+    } else {
+        FormFieldKind::Text
+    };
+
+    let name = element_val(ff_data, "w:name").unwrap_or_default();
+    let mut field = FormField::new(&name, kind, FormFieldSource::Legacy);
+    field.enabled = element_val(ff_data, "w:enabled").map(|v| is_on(&v)).unwrap_or(true);
+    field.help_text = element_val(ff_data, "w:helpText");
+    field.max_length = element_val(ff_data, "w:maxLength").and_then(|v| v.parse().ok());
+    field.date_format = element_val(ff_data, "w:format");
+
+    match kind {
+        FormFieldKind::Checkbox => {
+            // w:checked overrides w:default
+            field.checked = element_val(ff_data, "w:checked")
+                .or_else(|| element_val(ff_data, "w:default"))
+                .map(|v| is_on(&v))
+                .unwrap_or(false);
+        }
+        FormFieldKind::DropDown => {
+            field.options = LIST_ENTRY.captures_iter(ff_data).map(|c| unescape_xml_text(&c[1])).collect();
+            let selected = element_val(ff_data, "w:result")
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0);
+            field.value = field.options.get(selected).cloned().unwrap_or_default();
+        }
+        FormFieldKind::Text | FormFieldKind::Date => {
+            field.value = result.to_string();
+        }
+    }
+    if kind == FormFieldKind::Date {
+        field.date = parse_date(&field.value, field.date_format.as_deref());
+    }
+    field
+}
+
+/// Parse fillable content controls, returning each with the bytes from w:sdt to the end of its content
+fn parse_content_controls(xml: &str) -> Vec<(Range<usize>, FormField)> {
+    CONTENT_CONTROL
+        .captures_iter(xml)
+        .filter_map(|cap| Some((cap.get(0).unwrap().range(), control_field(&cap[1], &collect_text(&cap[2]))?)))
+        .collect()
+}
+
+/// The form field of a content control with the properties `props`
+/// (w:sdtPr) showing `text`; None for rich text, pictures, building blocks
+/// and other controls that are not form fields
+pub(super) fn control_field(props: &str, text: &str) -> Option<FormField> {
+    let kind = if props.contains("<w14:checkbox") {
+        FormFieldKind::Checkbox
+    } else if props.contains("<w:dropDownList") || props.contains("<w:comboBox") {
+        FormFieldKind::DropDown
+    } else if props.contains("<w:date") {
+        FormFieldKind::Date
+    } else if props.contains("<w:text") {
+        FormFieldKind::Text
+    } else {
+        return None;
+    };
+
+    let name = element_val(props, "w:tag")
+        .or_else(|| element_val(props, "w:alias"))
+        .unwrap_or_default();
+    let mut field = FormField::new(&name, kind, FormFieldSource::ContentControl);
+    field.title = element_val(props, "w:alias");
+    field.tag = element_val(props, "w:tag");
+    field.enabled = !props.contains("sdtContentLocked");
+
+    let showing_placeholder = is_showing_placeholder(props);
+    match kind {
+        FormFieldKind::Checkbox => {
+            field.checked = element_val(props, "w14:checked").map(|v| is_on(&v)).unwrap_or(false);
+        }
+        FormFieldKind::DropDown => {
+            let items: Vec<(String, String)> = LIST_ITEM
+                .find_iter(props)
+                .filter_map(|m| {
+                    let value = attribute(m.as_str(), "w:value");
+                    let label = attribute(m.as_str(), "w:displayText");
+                    let value = value.or_else(|| label.clone())?;
+                    Some((label.unwrap_or_else(|| value.clone()), value))
+                })
+                .collect();
+            if items.iter().any(|(label, value)| label != value) {
+                field.option_labels = items.iter().map(|(label, _)| label.clone()).collect();
+            }
+            field.options = items.into_iter().map(|(_, value)| value).collect();
+            if !showing_placeholder {
+                // The content shows the entry's label
+                field.value = field
+                    .option_labels
+                    .iter()
+                    .position(|label| label == text)
+                    .map_or_else(|| text.to_string(), |index| field.options[index].clone());
+            }
+        }
+        FormFieldKind::Date => {
+            field.date_format = element_val(props, "w:dateFormat");
+            let full_date = DATE_TAG.find(props).and_then(|tag| attribute(tag.as_str(), "w:fullDate"));
+            if !showing_placeholder {
+                field.value = text.to_string();
+                field.date = full_date
+                    .and_then(|date| NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok())
+                    .or_else(|| parse_date(&field.value, field.date_format.as_deref()));
+            }
+        }
+        FormFieldKind::Text => {
+            if !showing_placeholder {
+                field.value = text.to_string();
+            }
+        }
+    }
+    Some(field)
+}
+
+/// Whether a content control with the properties `props` shows its placeholder text
+pub(super) fn is_showing_placeholder(props: &str) -> bool {
+    props.contains("<w:showingPlcHdr")
+}
+
+/// Write a legacy field's value into its XML, from the begin to the end fldChar
+fn write_legacy_value(xml: &str, field: &FormField) -> String {
+    let mut xml = xml.to_string();
+    match field.kind {
+        FormFieldKind::Checkbox => {
+            // w:checked overrides w:default, so it is all that needs to change
+            xml = LEGACY_CHECKED.replace(&xml, "").into_owned();
+            let checked = format!(r#"<w:checked w:val="{}"/>"#, u8::from(field.checked));
+            if let Some(at) = xml.find("</w:checkBox>") {
+                xml.insert_str(at, &checked);
+            } else {
+                xml = xml.replacen("<w:checkBox/>", &format!("<w:checkBox>{}</w:checkBox>", checked), 1);
+            }
+            return xml;
+        }
+        FormFieldKind::DropDown => {
+            xml = LEGACY_RESULT.replace(&xml, "").into_owned();
+            if let (Some(at), Some(index)) = (xml.find("<w:ddList>"), field.options.iter().position(|o| *o == field.value)) {
+                xml.insert_str(at + "<w:ddList>".len(), &format!(r#"<w:result w:val="{}"/>"#, index));
+            }
+        }
+        FormFieldKind::Text | FormFieldKind::Date => {}
+    }
+
+    // The result text runs between the separate and the end fldChar
+    let Some(result_start) = FIELD_SEPARATE.find(&xml).map(|m| m.end()) else {
+        return xml;
+    };
+    let result = match replace_text(&xml[result_start..], &field.value) {
+        Some(result) => result,
+        None => {
+            // No result runs yet: add one after the run of the separate fldChar
+            let mut result = xml[result_start..].to_string();
+            let at = result.find("</w:r>").map_or(0, |at| at + "</w:r>".len());
+            result.insert_str(at, &text_run(&field.value));
+            result
+        }
+    };
+    xml.truncate(result_start);
+    xml.push_str(&result);
+    xml
+}
+
+/// Write a content control's value into its XML, from w:sdt to the end of w:sdtContent
+fn write_control_value(xml: &str, field: &FormField) -> String {
+    let Some(content_start) = xml.find("<w:sdtContent>").map(|at| at + "<w:sdtContent>".len()) else {
+        return xml.to_string();
+    };
+    let content_end = xml.rfind("</w:sdtContent>").unwrap_or(xml.len()).max(content_start);
+    let mut props = SHOWING_PLACEHOLDER.replace(&xml[..content_start], "").into_owned();
+
+    let text = match field.kind {
+        FormFieldKind::Checkbox => {
+            let state = if field.checked { "w14:checkedState" } else { "w14:uncheckedState" };
+            let glyph = start_tag(&props, state)
+                .and_then(|tag| attribute(tag, "w14:val"))
+                .and_then(|code| u32::from_str_radix(&code, 16).ok())
+                .and_then(char::from_u32)
+                .unwrap_or(if field.checked { '\u{2612}' } else { '\u{2610}' });
+            props = CONTROL_CHECKED
+                .replace(&props, format!(r#"<w14:checked w14:val="{}"/>"#, u8::from(field.checked)).as_str())
+                .into_owned();
+            glyph.to_string()
+        }
+        FormFieldKind::DropDown => field.option_label(&field.value).to_string(),
+        FormFieldKind::Date => {
+            if let Some(tag) = DATE_TAG.find(&props).map(|m| m.range()) {
+                let mut date = FULL_DATE.replace(&props[tag.clone()], "").into_owned();
+                if let Some(value) = field.date {
+                    date.insert_str("<w:date".len(), &format!(r#" w:fullDate="{}T00:00:00Z""#, value));
+                }
+                props.replace_range(tag, &date);
+            }
+            field.value.clone()
+        }
+        FormFieldKind::Text => field.value.clone(),
+    };
+
+    // The value is no longer placeholder text
+    let content = xml[content_start..content_end].replace(r#"<w:rStyle w:val="PlaceholderText"/>"#, "");
+    let content = replace_text(&content, &text).unwrap_or_else(|| {
+        if let Some(at) = content.find("</w:p>") {
+            format!("{}{}{}", &content[..at], text_run(&text), &content[at..])
+        } else if EMPTY_PARAGRAPH.is_match(&content) {
+            EMPTY_PARAGRAPH
+                .replace(&content, |caps: &regex::Captures| format!("<w:p{}>{}</w:p>", &caps[1], text_run(&text)))
+                .into_owned()
+        } else {
+            format!("{}{}", content, text_run(&text))
+        }
+    });
+    format!("{}{}{}", props, content, &xml[content_end..])
+}
+
+/// Put `text` in the first w:t of a fragment and drop the others, keeping
+/// their runs and formatting; None when the fragment has no w:t
+fn replace_text(xml: &str, text: &str) -> Option<String> {
+    TEXT_ELEMENT.find(xml)?;
+    let mut first = true;
+    let replaced = TEXT_ELEMENT.replace_all(xml, |_: &regex::Captures| {
+        if std::mem::take(&mut first) {
+            format!(r#"<w:t xml:space="preserve">{}</w:t>"#, escape_xml_text(text))
+        } else {
+            String::new()
+        }
+    });
+    Some(replaced.into_owned())
+}
+
+fn text_run(text: &str) -> String {
+    format!(r#"<w:r><w:t xml:space="preserve">{}</w:t></w:r>"#, escape_xml_text(text))
+}
+
+/// Parse a date typed as ISO (2024-03-05) or in a Word date format (M/d/yyyy when none)
+fn parse_date(text: &str, format: Option<&str>) -> Option<NaiveDate> {
+    let text = text.trim();
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(text, &date_pattern(format.unwrap_or(DEFAULT_DATE_FORMAT))))
+        .ok()
+}
+
+/// Write a date in a Word date format (M/d/yyyy when none)
+fn format_date(date: NaiveDate, format: Option<&str>) -> String {
+    // Time parts of the format show midnight
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    midnight.format(&date_pattern(format.unwrap_or(DEFAULT_DATE_FORMAT))).to_string()
+}
+
+/// Translate a Word date format such as "dddd, MMMM d, yyyy" to a chrono pattern
+fn date_pattern(format: &str) -> String {
+    let chars: Vec<char> = format.chars().collect();
+    let mut pattern = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let run = chars[i..].iter().take_while(|&&next| next == c).count();
+        let specifier = match (c, run) {
+            ('d', 1) => "%-d",
+            ('d', 2) => "%d",
+            ('d', 3) => "%a",
+            ('d', _) => "%A",
+            ('M', 1) => "%-m",
+            ('M', 2) => "%m",
+            ('M', 3) => "%b",
+            ('M', _) => "%B",
+            ('y', 1 | 2) => "%y",
+            ('y', _) => "%Y",
+            ('H', 1) => "%-H",
+            ('H', _) => "%H",
+            ('h', 1) => "%-I",
+            ('h', _) => "%I",
+            ('m', 1) => "%-M",
+            ('m', _) => "%M",
+            ('s', 1) => "%-S",
+            ('s', _) => "%S",
+            _ => "",
+        };
+        if !specifier.is_empty() {
+            pattern.push_str(specifier);
+            i += run;
+        } else if chars[i..].starts_with(&['A', 'M', '/', 'P', 'M']) {
+            pattern.push_str("%p");
+            i += 5;
+        } else if c == '\'' {
+            // Quoted literal text
+            let literal: String = chars[i + 1..].iter().take_while(|&&next| next != '\'').collect();
+            pattern.push_str(&literal.replace('%', "%%"));
+            i += literal.chars().count() + 2;
+        } else {
+            if c == '%' {
+                pattern.push('%');
+            }
+            pattern.push(c);
+            i += 1;
+        }
+    }
+    pattern
+}
+
+/// Concatenate the w:t text of a fragment
+fn collect_text(xml: &str) -> String {
+    TEXT_CONTENT.captures_iter(xml).map(|c| unescape_xml_text(&c[1])).collect()
+}
+
+/// Value of the w:val (or namespace-specific val) attribute of the first `element`
+fn element_val(xml: &str, element: &str) -> Option<String> {
+    let tag = start_tag(xml, element)?;
+    let prefix = element.split(':').next().unwrap_or("w");
+    // Presence without a value means "on" for toggle elements
+    Some(attribute(tag, &format!("{}:val", prefix)).unwrap_or_else(|| "1".to_string()))
+}
+
+/// The first start tag of `element` in `xml`
+fn start_tag<'a>(xml: &'a str, element: &str) -> Option<&'a str> {
+    START_TAG
+        .captures_iter(xml)
+        .find(|c| &c[1] == element)
+        .and_then(|c| c.get(0))
+        .map(|m| m.as_str())
+}
+
+/// Value of an attribute within a single start tag
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    ATTRIBUTE
+        .captures_iter(tag)
+        .find(|c| &c[1] == name)
+        .map(|c| unescape_xml_text(&c[2]))
+}
+
+fn is_on(value: &str) -> bool {
+    !matches!(value, "0" | "false" | "off")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY_FORM: &str = r#"<w:body><w:p>
+        <w:r><w:fldChar w:fldCharType="begin"><w:ffData><w:name w:val="FullName"/><w:enabled/><w:textInput><w:maxLength w:val="10"/></w:textInput></w:ffData></w:fldChar></w:r>
+        <w:r><w:instrText xml:space="preserve"> FORMTEXT </w:instrText></w:r>
+        <w:r><w:fldChar w:fldCharType="separate"/></w:r>
+        <w:r><w:t>Jane</w:t></w:r>
+        <w:r><w:fldChar w:fldCharType="end"/></w:r>
+        <w:r><w:fldChar w:fldCharType="begin"><w:ffData><w:name w:val="Agree"/><w:checkBox><w:sizeAuto/><w:default w:val="0"/><w:checked/></w:checkBox></w:ffData></w:fldChar></w:r>
+        <w:r><w:instrText> FORMCHECKBOX </w:instrText></w:r>
+        <w:r><w:fldChar w:fldCharType="end"/></w:r>
+        <w:r><w:fldChar w:fldCharType="begin"><w:ffData><w:name w:val="Color"/><w:enabled w:val="0"/><w:ddList><w:result w:val="1"/><w:listEntry w:val="Red"/><w:listEntry w:val="Blue"/></w:ddList></w:ffData></w:fldChar></w:r>
+        <w:r><w:instrText> FORMDROPDOWN </w:instrText></w:r>
+        <w:r><w:fldChar w:fldCharType="end"/></w:r>
+    </w:p></w:body>"#;
+
+    const SDT_FORM: &str = r#"<w:body><w:p>
+        <w:sdt><w:sdtPr><w:alias w:val="City"/><w:tag w:val="city"/><w:text/></w:sdtPr><w:sdtContent><w:r><w:t>Paris</w:t></w:r></w:sdtContent></w:sdt>
+        <w:sdt><w:sdtPr><w:tag w:val="size"/><w:dropDownList><w:listItem w:displayText="Small" w:value="S"/><w:listItem w:displayText="Large" w:value="L"/></w:dropDownList></w:sdtPr><w:sdtContent><w:r><w:t>S</w:t></w:r></w:sdtContent></w:sdt>
+        <w:sdt><w:sdtPr><w:tag w:val="news"/><w14:checkbox><w14:checked w14:val="1"/></w14:checkbox></w:sdtPr><w:sdtContent><w:r><w:t>X</w:t></w:r></w:sdtContent></w:sdt>
+        <w:sdt><w:sdtPr><w:tag w:val="notes"/><w:richText/></w:sdtPr><w:sdtContent><w:p/></w:sdtContent></w:sdt>
+    </w:p></w:body>"#;
+
+    #[test]
+    fn test_parse_legacy_fields() {
+        let set = FormFieldSet::parse(LEGACY_FORM, None);
+        assert_eq!(set.fields.len(), 3);
+
+        let name = set.get("FullName").unwrap();
+        assert_eq!(name.kind, FormFieldKind::Text);
+        assert_eq!(name.value, "Jane");
+        assert_eq!(name.max_length, Some(10));
+
+        let agree = set.get("Agree").unwrap();
+        assert_eq!(agree.kind, FormFieldKind::Checkbox);
+        assert!(agree.checked);
+
+        let color = set.get("Color").unwrap();
+        assert_eq!(color.options, vec!["Red", "Blue"]);
+        assert_eq!(color.value, "Blue");
+        assert!(!color.enabled);
+    }
+
+    #[test]
+    fn test_parse_content_controls() {
+        let set = FormFieldSet::parse(SDT_FORM, None);
+        // The rich text control is not a form field
+        assert_eq!(set.fields.len(), 3);
+        assert_eq!(set.get("city").unwrap().value, "Paris");
+        assert_eq!(set.get("size").unwrap().options, vec!["S", "L"]);
+        assert!(set.get("news").unwrap().checked);
+        assert!(set.fields.iter().all(|f| f.source == FormFieldSource::ContentControl));
+    }
+
+    #[test]
+    fn test_tab_order_skips_disabled() {
+        let set = FormFieldSet::parse(LEGACY_FORM, None);
+        let order: Vec<&str> = set.tab_order().iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(order, vec!["FullName", "Agree"]);
+        assert_eq!(set.next_field("Agree").unwrap().name, "FullName");
+        assert_eq!(set.previous_field("FullName").unwrap().name, "Agree");
+    }
+
+    #[test]
+    fn test_set_values_with_validation() {
+        let mut set = FormFieldSet::parse(LEGACY_FORM, None);
+        set.set_value("FullName", "John").unwrap();
+        assert_eq!(set.get("FullName").unwrap().value, "John");
+
+        assert!(matches!(set.set_value("FullName", "A very long name"), Err(FormError::InvalidValue(_, _))));
+        assert_eq!(set.set_value("Agree", "x"), Err(FormError::WrongKind("Agree".to_string())));
+        assert_eq!(set.set_value("Color", "Red"), Err(FormError::Disabled("Color".to_string())));
+        assert_eq!(set.set_checked("Missing", true), Err(FormError::NotFound("Missing".to_string())));
+
+        set.set_checked("Agree", false).unwrap();
+        assert!(!set.get("Agree").unwrap().checked);
+    }
+
+    #[test]
+    fn test_protection_enforcement() {
+        let forms = r#"<w:settings><w:documentProtection w:edit="forms" w:enforcement="1"/></w:settings>"#;
+        let mut set = FormFieldSet::parse(SDT_FORM, Some(forms));
+        assert_eq!(set.protection, ProtectionMode::Forms);
+        assert!(!set.allows_free_editing());
+        assert!(set.set_value("city", "Rome").is_ok());
+
+        let read_only = r#"<w:settings><w:documentProtection w:edit="readOnly" w:enforcement="1"/></w:settings>"#;
+        let mut set = FormFieldSet::parse(SDT_FORM, Some(read_only));
+        assert_eq!(set.set_value("city", "Rome"), Err(FormError::Protected));
+
+        // Protection that is not enforced is ignored
+        let unenforced = r#"<w:settings><w:documentProtection w:edit="forms"/></w:settings>"#;
+        assert_eq!(FormFieldSet::parse(SDT_FORM, Some(unenforced)).protection, ProtectionMode::None);
+    }
+
+    #[test]
+    fn test_legacy_round_trip() {
+        let set = FormFieldSet::parse(LEGACY_FORM, None);
+        let xml: String = set.fields.iter().map(|f| f.to_legacy_xml()).collect();
+        let reparsed = FormFieldSet::parse(&xml, None);
+        assert_eq!(reparsed.fields, set.fields);
+    }
+
+    #[test]
+    fn test_sdt_round_trip() {
+        let mut set = FormFieldSet::parse(LEGACY_FORM, None);
+        set.set_value("FullName", "A & B").unwrap();
+        let xml: String = set.fields.iter().map(|f| f.to_sdt_xml()).collect();
+        let reparsed = FormFieldSet::parse(&xml, None);
+
+        assert_eq!(reparsed.fields.len(), 3);
+        assert_eq!(reparsed.get("FullName").unwrap().value, "A & B");
+        assert!(reparsed.get("Agree").unwrap().checked);
+        assert_eq!(reparsed.get("Color").unwrap().value, "Blue");
+        assert!(!reparsed.get("Color").unwrap().enabled);
+    }
+
+    const FILLABLE_FORM: &str = r#"<w:body>
+        <w:p><w:sdt><w:sdtPr><w:alias w:val="Full name"/><w:tag w:val="name"/><w:showingPlcHdr/><w:text/></w:sdtPr><w:sdtContent><w:r><w:rPr><w:rStyle w:val="PlaceholderText"/></w:rPr><w:t>Click to enter</w:t></w:r></w:sdtContent></w:sdt></w:p>
+        <w:sdt><w:sdtPr><w:tag w:val="start"/><w:date w:fullDate="2024-01-02T00:00:00Z"><w:dateFormat w:val="d MMMM yyyy"/></w:date></w:sdtPr><w:sdtContent><w:p><w:pPr><w:jc w:val="center"/></w:pPr><w:r><w:rPr><w:b/></w:rPr><w:t>2 January</w:t></w:r><w:r><w:t xml:space="preserve"> 2024</w:t></w:r></w:p></w:sdtContent></w:sdt>
+        <w:p><w:sdt><w:sdtPr><w:tag w:val="size"/><w:dropDownList><w:listItem w:displayText="Small" w:value="S"/><w:listItem w:displayText="Large" w:value="L"/></w:dropDownList></w:sdtPr><w:sdtContent><w:r><w:t>Small</w:t></w:r></w:sdtContent></w:sdt>
+        <w:sdt><w:sdtPr><w:tag w:val="news"/><w14:checkbox><w14:checked w14:val="0"/><w14:checkedState w14:val="2612"/><w14:uncheckedState w14:val="2610"/></w14:checkbox></w:sdtPr><w:sdtContent><w:r><w:t>☐</w:t></w:r></w:sdtContent></w:sdt></w:p>
+    </w:body>"#;
+
+    #[test]
+    fn test_content_control_titles_labels_and_dates() {
+        let set = FormFieldSet::parse(FILLABLE_FORM, None);
+        let name = set.get("name").unwrap();
+        assert_eq!(name.title.as_deref(), Some("Full name"));
+        assert_eq!(name.tag.as_deref(), Some("name"));
+        // Placeholder text is not a value
+        assert_eq!(name.value, "");
+
+        let start = set.get("start").unwrap();
+        assert_eq!(start.value, "2 January 2024");
+        assert_eq!(start.date, NaiveDate::from_ymd_opt(2024, 1, 2));
+
+        let size = set.get("size").unwrap();
+        assert_eq!(size.options, vec!["S", "L"]);
+        assert_eq!(size.option_labels, vec!["Small", "Large"]);
+        assert_eq!(size.value, "S");
+        assert_eq!(size.option_label("L"), "Large");
+    }
+
+    #[test]
+    fn test_date_values() {
+        assert_eq!(date_pattern("dddd, MMMM d, yyyy"), "%A, %B %-d, %Y");
+        assert_eq!(date_pattern("dd.MM.yy 'at' HH:mm"), "%d.%m.%y at %H:%M");
+        assert_eq!(date_pattern("h:mm AM/PM"), "%-I:%M %p");
+
+        let mut set = FormFieldSet::parse(FILLABLE_FORM, None);
+        set.set_value("start", "2025-03-05").unwrap();
+        assert_eq!(set.get("start").unwrap().value, "5 March 2025");
+        set.set_value("start", "7 April 2025").unwrap();
+        assert_eq!(set.get("start").unwrap().date, NaiveDate::from_ymd_opt(2025, 4, 7));
+
+        for invalid in ["2025-02-30", "04/07/2025", "soon"] {
+            assert_eq!(
+                set.set_value("start", invalid),
+                Err(FormError::InvalidValue("start".to_string(), invalid.to_string()))
+            );
+        }
+        set.set_value("start", "").unwrap();
+        assert_eq!(set.get("start").unwrap().date, None);
+
+        assert_eq!(parse_date("3/14/2024", None), NaiveDate::from_ymd_opt(2024, 3, 14));
+        assert_eq!(format_date(NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(), Some("dddd d/M")), "Monday 4/3");
+    }
+
+    #[test]
+    fn test_fill_checks_every_value_first() {
+        let mut set = FormFieldSet::parse(FILLABLE_FORM, None);
+        let values = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+
+        let refused = values(&[("name", "Ada"), ("size", "Medium")]);
+        assert_eq!(
+            set.fill(&refused),
+            Err(FormError::InvalidValue("size".to_string(), "Medium".to_string()))
+        );
+        assert_eq!(set.get("name").unwrap().value, "");
+
+        assert_eq!(set.fill(&values(&[("missing", "x")])), Err(FormError::NotFound("missing".to_string())));
+        assert!(matches!(set.fill(&values(&[("news", "maybe")])), Err(FormError::InvalidValue(_, _))));
+
+        set.fill(&values(&[("name", "Ada"), ("size", "Large"), ("news", "yes")])).unwrap();
+        assert_eq!(set.get("name").unwrap().value, "Ada");
+        assert_eq!(set.get("size").unwrap().value, "L");
+        assert!(set.get("news").unwrap().checked);
+    }
+
+    #[test]
+    fn test_write_values_into_content_controls() {
+        let mut set = FormFieldSet::parse(FILLABLE_FORM, None);
+        set.set("name", "Ada <Lovelace>").unwrap();
+        set.set("start", "2025-12-10").unwrap();
+        set.set("size", "L").unwrap();
+        set.set("news", "true").unwrap();
+
+        let xml = set.write_values(FILLABLE_FORM);
+        assert!(!xml.contains("showingPlcHdr") && !xml.contains("PlaceholderText"));
+        assert!(xml.contains(r#"<w:date w:fullDate="2025-12-10T00:00:00Z">"#));
+        assert!(xml.contains(r#"<w:t xml:space="preserve">Large</w:t>"#));
+        assert!(xml.contains(r#"<w14:checked w14:val="1"/>"#) && xml.contains('\u{2612}'));
+        // Formatting of the first run stays; the second run is emptied
+        assert!(xml.contains(r#"<w:jc w:val="center"/></w:pPr><w:r><w:rPr><w:b/></w:rPr><w:t xml:space="preserve">10 December 2025</w:t></w:r><w:r></w:r>"#));
+
+        let reparsed = FormFieldSet::parse(&xml, None);
+        assert_eq!(reparsed.fields, set.fields);
+        // Unchanged fields are left as they are
+        assert_eq!(FormFieldSet::parse(FILLABLE_FORM, None).write_values(FILLABLE_FORM), FILLABLE_FORM);
+    }
+
+    #[test]
+    fn test_write_values_into_legacy_fields() {
+        let mut set = FormFieldSet::parse(LEGACY_FORM, None);
+        set.set("FullName", "Bo").unwrap();
+        set.set("Agree", "false").unwrap();
+
+        let xml = set.write_values(LEGACY_FORM);
+        assert!(xml.contains(r#"<w:default w:val="0"/><w:checked w:val="0"/></w:checkBox>"#));
+        let reparsed = FormFieldSet::parse(&xml, None);
+        assert_eq!(reparsed.get("FullName").unwrap().value, "Bo");
+        assert!(!reparsed.get("Agree").unwrap().checked);
+        assert_eq!(reparsed.get("Color").unwrap().value, "Blue");
+
+        // A drop-down keeps its selection in w:result
+        let mut color = FormField::new("Color", FormFieldKind::DropDown, FormFieldSource::Legacy);
+        color.options = vec!["Red".to_string(), "Blue".to_string()];
+        let xml = format!("<w:p>{}</w:p>", color.to_legacy_xml());
+        color.value = "Blue".to_string();
+        let set = FormFieldSet { fields: vec![color], protection: ProtectionMode::None };
+        let filled = FormFieldSet::parse(&set.write_values(&xml), None);
+        assert_eq!(filled.get("Color").unwrap().value, "Blue");
+    }
+
+    #[test]
+    fn test_fill_form_package() {
+        let document = format!(r#"<?xml version="1.0" encoding="UTF-8"?><w:document>{}</w:document>"#, FILLABLE_FORM);
+        let docx = write_entries(&[(DOCUMENT_PART.to_string(), document.into_bytes())]).unwrap();
+
+        let values = HashMap::from([("name".to_string(), "Grace".to_string())]);
+        let (filled, fields) = fill_form(&docx, &values).unwrap();
+        assert_eq!(fields.get("name").unwrap().value, "Grace");
+        assert_eq!(form_fields(&filled).unwrap(), fields);
+
+        let refused = HashMap::from([("size".to_string(), "XL".to_string())]);
+        assert!(matches!(fill_form(&docx, &refused), Err(OoxmlError::Form(FormError::InvalidValue(_, _)))));
+    }
+
+    #[test]
+    fn test_protection_xml() {
+        let mut set = FormFieldSet::default();
+        assert!(set.protection_xml().is_none());
+        set.protection = ProtectionMode::Forms;
+        assert_eq!(
+            set.protection_xml().unwrap(),
+            r#"<w:documentProtection w:edit="forms" w:enforcement="1"/>"#
+        );
+    }
+}
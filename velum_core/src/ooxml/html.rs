@@ -0,0 +1,972 @@
+//! HTML export
+//!
+//! [`DocxSerializer::export_html`](super::DocxSerializer::export_html) writes
+//! a document as one standalone page for pasting into web editors: a style
+//! sheet made from the document's styles, direct formatting as inline CSS,
+//! headings, lists as nested ul/ol, tables with their merged cells, images as
+//! data URIs or links to files beside the page, and footnotes and endnotes as
+//! numbered links to a list of notes at the end. Text deleted in tracked
+//! changes is left out; comments, headers and footers are not written.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+use super::error::OoxmlError;
+use super::export::ExportControl;
+use super::lazy::heading_level;
+use super::serializer::{escape_xml_attr, escape_xml_text, WordDocument, PROGRESS_INTERVAL};
+use super::types::{
+    DocumentImage, NoteKind, Paragraph, ParagraphProperties, RevisionKind, RunProperties, Table, TableBorder,
+    TableCell, TableRow,
+};
+
+/// EMUs per CSS pixel
+const EMU_PER_PX: u32 = 9525;
+
+/// Links of nested styles followed before giving up on a cycle
+const MAX_STYLE_DEPTH: usize = 16;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Where the page finds an image, and the size it is drawn at in EMUs
+#[derive(Debug, Clone)]
+pub(super) struct HtmlImage {
+    pub src: String,
+    pub extent: Option<(u32, u32)>,
+}
+
+/// Write `document` as an HTML page; `images` are by the path of their
+/// [`DocumentImage`]. Without `include_styles`, the page has direct formatting only
+pub(super) fn document_html(
+    document: &WordDocument,
+    images: &HashMap<String, HtmlImage>,
+    include_styles: bool,
+    control: &ExportControl,
+) -> Result<String, OoxmlError> {
+    let mut drawings: HashMap<usize, Vec<(usize, &DocumentImage)>> = HashMap::new();
+    for image in &document.images {
+        drawings.entry(image.paragraph_index).or_default().push((image.position, image));
+    }
+
+    let mut writer = HtmlWriter {
+        document,
+        images,
+        drawings,
+        include_styles,
+        notes: Vec::new(),
+        lists: Vec::new(),
+        out: String::new(),
+    };
+    writer.head();
+    writer.body(control)?;
+    Ok(writer.out)
+}
+
+/// A list open around the paragraphs being written, with its last item
+struct OpenList {
+    level: u8,
+    num_id: String,
+    tag: &'static str,
+}
+
+struct HtmlWriter<'a> {
+    document: &'a WordDocument,
+    images: &'a HashMap<String, HtmlImage>,
+    /// Images by body paragraph, with the char offsets they sit at
+    drawings: HashMap<usize, Vec<(usize, &'a DocumentImage)>>,
+    include_styles: bool,
+    /// Notes in the order they are referenced; a note's number is its index + 1
+    notes: Vec<(NoteKind, String)>,
+    /// Lists open, outermost first
+    lists: Vec<OpenList>,
+    out: String,
+}
+
+impl<'a> HtmlWriter<'a> {
+    fn head(&mut self) {
+        let properties = self.document.core_properties.as_ref();
+        let title = properties.and_then(|props| props.title.as_deref()).unwrap_or_default();
+        self.out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        let _ = writeln!(self.out, "<title>{}</title>", escape_xml_text(title));
+        if let Some(author) = properties.and_then(|props| props.creator.as_deref()) {
+            let _ = writeln!(self.out, "<meta name=\"author\" content=\"{}\">", escape_xml_attr(author));
+        }
+        self.out.push_str("<style>\n");
+        self.out.push_str(&self.style_sheet());
+        self.out.push_str("</style>\n</head>\n");
+    }
+
+    /// Rules for the body, tables and notes, and a class for each style with
+    /// what it inherits folded in, as classes do not inherit from each other
+    fn style_sheet(&self) -> String {
+        let styles = &self.document.styles;
+        let mut css = String::new();
+
+        let mut body = Vec::new();
+        if let Some(theme) = &self.document.theme {
+            if !theme.fonts.minor_font.is_empty() {
+                body.push(("font-family", css_font(&theme.fonts.minor_font)));
+            }
+        }
+        let default = styles.values().find(|style| style.is_default && style.style_type == "paragraph");
+        if let Some(default) = default.filter(|_| self.include_styles) {
+            merge(&mut body, self.style_declarations(&default.id));
+        }
+        let _ = writeln!(css, "body {{ {}}}", declarations(&body));
+        css.push_str("table { border-collapse: collapse; }\n");
+        css.push_str("td, th { padding: 0 5.4pt; vertical-align: top; }\n");
+        css.push_str(".notes { font-size: smaller; }\n");
+        if !self.include_styles {
+            return css;
+        }
+
+        let mut ids: Vec<&String> = styles.keys().collect();
+        ids.sort();
+        for id in ids {
+            let rules = self.style_declarations(id);
+            if !rules.is_empty() {
+                let _ = writeln!(css, ".{} {{ {}}}", style_class(id), declarations(&rules));
+            }
+        }
+        css
+    }
+
+    /// Declarations of a style and the styles it is based on, nearest last
+    fn style_declarations(&self, id: &str) -> Vec<(&'static str, String)> {
+        let mut chain = Vec::new();
+        let mut next = Some(id);
+        while let Some(style) = next.and_then(|id| self.document.styles.get(id)) {
+            if chain.len() == MAX_STYLE_DEPTH {
+                break;
+            }
+            chain.push(style);
+            next = style.based_on.as_deref();
+        }
+        let mut rules = Vec::new();
+        for style in chain.into_iter().rev() {
+            merge(&mut rules, paragraph_declarations(&style.paragraph_properties));
+            merge(&mut rules, run_declarations(&style.run_properties));
+        }
+        rules
+    }
+
+    fn body(&mut self, control: &ExportControl) -> Result<(), OoxmlError> {
+        self.out.push_str("<body>\n");
+
+        // Tables go before the paragraph they precede, or after the last
+        let mut tables: Vec<&Table> = self.document.tables.iter().collect();
+        tables.sort_by_key(|table| table.paragraph_index);
+        let mut tables = tables.into_iter().peekable();
+
+        let total = self.document.paragraphs.len().max(1) as f32;
+        for (i, para) in self.document.paragraphs.iter().enumerate() {
+            if i % PROGRESS_INTERVAL == 0 {
+                control.step(0.5 + 0.5 * i as f32 / total)?;
+            }
+            while let Some(table) = tables.next_if(|table| table.paragraph_index <= i) {
+                self.close_lists(0);
+                self.table(table);
+            }
+            let drawings = self.drawings.remove(&i).unwrap_or_default();
+            self.body_paragraph(para, &drawings);
+        }
+        self.close_lists(0);
+        for table in tables {
+            self.table(table);
+        }
+
+        self.notes_list();
+        self.out.push_str("</body>\n</html>\n");
+        control.step(1.0)
+    }
+
+    /// Write a body paragraph, as a list item if it is in a list
+    fn body_paragraph(&mut self, para: &Paragraph, drawings: &[(usize, &DocumentImage)]) {
+        let Some((num_id, level, tag, attributes)) = self.list_item(&para.properties) else {
+            self.close_lists(0);
+            self.paragraph(para, drawings);
+            return;
+        };
+
+        // Close deeper lists and a different list at this level, then the
+        // item before at this level, or open lists down to it
+        self.close_lists(self.lists.iter().take_while(|list| list.level <= level).count());
+        if self.lists.last().is_some_and(|list| list.level == level && (list.num_id != num_id || list.tag != tag)) {
+            self.close_lists(self.lists.len() - 1);
+        }
+        match self.lists.last() {
+            Some(list) if list.level == level => self.out.push_str("</li>\n"),
+            _ => loop {
+                let opened = self.lists.last().map_or(0, |list| list.level + 1);
+                // A level skipped over gets an item of its own to hold the deeper list
+                let skipped = opened < level;
+                let _ = write!(self.out, "<{}{}>", tag, if skipped { "" } else { attributes.as_str() });
+                if skipped {
+                    self.out.push_str("<li>");
+                }
+                self.lists.push(OpenList { level: opened, num_id: num_id.clone(), tag });
+                if !skipped {
+                    break;
+                }
+            },
+        }
+        let inline = self.inline(para, drawings);
+        let _ = write!(self.out, "<li{}>{}", paragraph_attributes(&para.properties), inline);
+    }
+
+    /// The list a paragraph is an item of: its list ID, level, the element the
+    /// list is written as and that element's attributes
+    fn list_item(&self, props: &ParagraphProperties) -> Option<(String, u8, &'static str, String)> {
+        let num_id = props.num_id.as_deref().filter(|id| *id != "0")?;
+        let level = props.list_level.unwrap_or(0);
+        let list_level = self
+            .document
+            .numbering
+            .iter()
+            .find_map(|numbering| {
+                let instance = numbering.num_instances.iter().find(|num| num.num_id == num_id)?;
+                numbering
+                    .abstract_num_defs
+                    .iter()
+                    .find(|def| def.abstract_num_id == instance.abstract_num_id)
+            })
+            .and_then(|def| def.levels.iter().find(|l| l.level == u32::from(level)));
+
+        let format = list_level.map_or("bullet", |l| l.format.as_str());
+        if matches!(format, "bullet" | "none" | "") {
+            return Some((num_id.to_string(), level, "ul", String::new()));
+        }
+        let mut attributes = match format {
+            "lowerLetter" => String::from(r#" type="a""#),
+            "upperLetter" => String::from(r#" type="A""#),
+            "lowerRoman" => String::from(r#" type="i""#),
+            "upperRoman" => String::from(r#" type="I""#),
+            _ => String::new(),
+        };
+        if let Some(start) = list_level.map(|l| l.start_value).filter(|&start| start != 1) {
+            let _ = write!(attributes, r#" start="{}""#, start);
+        }
+        Some((num_id.to_string(), level, "ol", attributes))
+    }
+
+    /// Close open lists, and the items open in them, until `keep` are left
+    fn close_lists(&mut self, keep: usize) {
+        while self.lists.len() > keep {
+            let list = self.lists.pop().unwrap();
+            let _ = writeln!(self.out, "</li></{}>", list.tag);
+        }
+    }
+
+    /// Write a paragraph as a heading or p
+    fn paragraph(&mut self, para: &Paragraph, drawings: &[(usize, &DocumentImage)]) {
+        let tag = match para.properties.style_id.as_deref().and_then(heading_level) {
+            Some(level) => format!("h{}", level.min(6)),
+            None => "p".to_string(),
+        };
+        let mut inline = self.inline(para, drawings);
+        if inline.is_empty() {
+            // Keep the empty line an empty paragraph makes
+            inline.push_str("<br>");
+        }
+        let _ = writeln!(self.out, "<{0}{1}>{2}</{0}>", tag, paragraph_attributes(&para.properties), inline);
+    }
+
+    /// The runs of a paragraph with its links, bookmarks, images and note
+    /// references, leaving out deleted text
+    fn inline(&mut self, para: &Paragraph, drawings: &[(usize, &DocumentImage)]) -> String {
+        let mut marks: Vec<(usize, String)> = para
+            .bookmark_marks
+            .iter()
+            .filter_map(|mark| Some((mark.position, format!(r#"<a id="{}"></a>"#, escape_xml_attr(mark.name.as_deref()?)))))
+            .collect();
+        for &(position, image) in drawings {
+            if let Some(html) = self.image(image) {
+                marks.push((position, html));
+            }
+        }
+        for reference in &para.note_references {
+            if let Some(html) = self.note_reference(reference.kind, &reference.id) {
+                marks.push((reference.position, html));
+            }
+        }
+        marks.sort_by_key(|&(position, _)| position);
+
+        let deleted: Vec<Range<usize>> = para
+            .revisions
+            .iter()
+            .filter(|revision| revision.kind == RevisionKind::Deletion && revision.length > 0)
+            .map(|revision| revision.start..revision.start + revision.length)
+            .collect();
+        let links: Vec<(Range<usize>, String)> = para
+            .hyperlinks
+            .iter()
+            .filter(|link| link.length > 0)
+            .filter_map(|link| {
+                let href = match (&link.url, &link.anchor) {
+                    (Some(url), Some(anchor)) => format!("{}#{}", url, anchor),
+                    (Some(url), None) => url.clone(),
+                    (None, Some(anchor)) => format!("#{}", anchor),
+                    (None, None) => return None,
+                };
+                let mut tag = format!(r#"<a href="{}""#, escape_xml_attr(&href));
+                if let Some(tooltip) = &link.tooltip {
+                    let _ = write!(tag, r#" title="{}""#, escape_xml_attr(tooltip));
+                }
+                tag.push('>');
+                Some((link.start..link.start + link.length, tag))
+            })
+            .collect();
+
+        let mut html = String::new();
+        let mut open_link: Option<usize> = None;
+        let mut run_start = 0;
+        let mut pending_marks = marks.iter().peekable();
+        for run in &para.runs {
+            let chars: Vec<usize> = run.text.char_indices().map(|(i, _)| i).collect();
+            let run_end = run_start + chars.len();
+            let mut cuts = vec![run_start, run_end];
+            let range_cuts = deleted.iter().chain(links.iter().map(|(range, _)| range)).flat_map(|range| [range.start, range.end]);
+            for cut in range_cuts.chain(marks.iter().map(|&(position, _)| position)) {
+                if cut > run_start && cut < run_end {
+                    cuts.push(cut);
+                }
+            }
+            cuts.sort_unstable();
+            cuts.dedup();
+
+            let byte = |offset: usize| chars.get(offset - run_start).copied().unwrap_or(run.text.len());
+            for span in cuts.windows(2) {
+                while let Some((_, mark)) = pending_marks.next_if(|&&(position, _)| position <= span[0]) {
+                    html.push_str(mark);
+                }
+                if deleted.iter().any(|range| range.contains(&span[0])) {
+                    continue;
+                }
+                let link = links.iter().position(|(range, _)| range.contains(&span[0]));
+                if link != open_link {
+                    if open_link.is_some() {
+                        html.push_str("</a>");
+                    }
+                    if let Some(link) = link {
+                        html.push_str(&links[link].1);
+                    }
+                    open_link = link;
+                }
+                html.push_str(&run_html(&run.properties, &run.text[byte(span[0])..byte(span[1])]));
+            }
+            run_start = run_end;
+        }
+        if open_link.is_some() {
+            html.push_str("</a>");
+        }
+        for (_, mark) in pending_marks {
+            html.push_str(mark);
+        }
+        html
+    }
+
+    fn image(&self, image: &DocumentImage) -> Option<String> {
+        let found = self.images.get(&image.path)?;
+        let alt = image.alt_description.as_deref().or(image.title.as_deref()).unwrap_or_default();
+        let mut html = format!(r#"<img src="{}" alt="{}""#, escape_xml_attr(&found.src), escape_xml_attr(alt));
+        if let Some(title) = &image.title {
+            let _ = write!(html, r#" title="{}""#, escape_xml_attr(title));
+        }
+        if let Some((cx, cy)) = found.extent {
+            let _ = write!(html, r#" width="{}" height="{}""#, (cx / EMU_PER_PX).max(1), (cy / EMU_PER_PX).max(1));
+        }
+        html.push('>');
+        Some(html)
+    }
+
+    /// A link to the note in the notes list, numbering the note
+    fn note_reference(&mut self, kind: NoteKind, id: &str) -> Option<String> {
+        self.note_paragraphs(kind, id)?;
+        let number = match self.notes.iter().position(|(k, i)| *k == kind && i == id) {
+            Some(index) => index + 1,
+            None => {
+                self.notes.push((kind, id.to_string()));
+                self.notes.len()
+            }
+        };
+        Some(format!(r##"<sup><a href="#note-{0}" id="note-ref-{0}">{0}</a></sup>"##, number))
+    }
+
+    /// Paragraphs of a footnote or endnote, unless it is a separator
+    fn note_paragraphs(&self, kind: NoteKind, id: &str) -> Option<&'a [Paragraph]> {
+        let document = self.document;
+        let (paragraphs, note_type) = match kind {
+            NoteKind::Footnote => document
+                .footnotes
+                .iter()
+                .find(|note| note.id == id)
+                .map(|note| (&note.paragraphs, &note.footnote_type))?,
+            NoteKind::Endnote => document
+                .endnotes
+                .iter()
+                .find(|note| note.id == id)
+                .map(|note| (&note.paragraphs, &note.endnote_type))?,
+        };
+        match note_type.as_deref() {
+            None | Some("normal") => Some(paragraphs),
+            Some(_) => None,
+        }
+    }
+
+    /// The notes referenced, in order, each linking back to its reference
+    fn notes_list(&mut self) {
+        if self.notes.is_empty() {
+            return;
+        }
+        self.out.push_str("<section class=\"notes\">\n<hr>\n<ol>\n");
+        // Notes may reference further notes, which join the end of the list
+        let mut index = 0;
+        while let Some((kind, id)) = self.notes.get(index).cloned() {
+            index += 1;
+            let _ = write!(self.out, "<li id=\"note-{}\">", index);
+            let paragraphs = self.note_paragraphs(kind, &id).unwrap_or_default();
+            for (n, para) in paragraphs.iter().enumerate() {
+                let mut inline = self.inline(para, &[]);
+                if n + 1 == paragraphs.len() {
+                    let _ = write!(inline, r##" <a href="#note-ref-{}">&#8617;</a>"##, index);
+                }
+                let _ = write!(self.out, "<p{}>{}</p>", paragraph_attributes(&para.properties), inline);
+            }
+            self.out.push_str("</li>\n");
+        }
+        self.out.push_str("</ol>\n</section>\n");
+    }
+
+    fn table(&mut self, table: &Table) {
+        let props = &table.properties;
+        let borders = &props.borders;
+        let mut rules = Vec::new();
+        if let Some(width) = props.width.filter(|&width| width > 0) {
+            rules.push(("width", points(width as i32)));
+        }
+        match props.alignment.as_deref() {
+            Some("center") => rules.push(("margin", "0 auto".to_string())),
+            Some("right" | "end") => rules.push(("margin-left", "auto".to_string())),
+            _ => {
+                if let Some(indent) = props.indent.filter(|&indent| indent != 0) {
+                    rules.push(("margin-left", points(indent)));
+                }
+            }
+        }
+        for (property, border) in [
+            ("border-top", &borders.top),
+            ("border-left", &borders.left),
+            ("border-bottom", &borders.bottom),
+            ("border-right", &borders.right),
+        ] {
+            if let Some(css) = border.as_ref().map(border_css) {
+                rules.push((property, css));
+            }
+        }
+        let _ = writeln!(self.out, "<table{}>", style_attribute(&rules));
+
+        let mut cell_rules = Vec::new();
+        if let Some(css) = borders.inside_horizontal.as_ref().map(border_css) {
+            cell_rules.push(("border-top", css.clone()));
+            cell_rules.push(("border-bottom", css));
+        }
+        if let Some(css) = borders.inside_vertical.as_ref().map(border_css) {
+            cell_rules.push(("border-left", css.clone()));
+            cell_rules.push(("border-right", css));
+        }
+
+        let columns: Vec<Vec<usize>> = table.rows.iter().map(grid_columns).collect();
+        for (r, row) in table.rows.iter().enumerate() {
+            self.out.push_str("<tr>");
+            let tag = if row.properties.is_header { "th" } else { "td" };
+            for (c, cell) in row.cells.iter().enumerate() {
+                if is_continuation(cell.horizontal_merge) || is_continuation(cell.vertical_merge) {
+                    continue;
+                }
+                let column = columns[r][c];
+                let mut attributes = String::new();
+                let colspan: u32 = span(cell)
+                    + row.cells[c + 1..]
+                        .iter()
+                        .take_while(|next| is_continuation(next.horizontal_merge))
+                        .map(span)
+                        .sum::<u32>();
+                if colspan > 1 {
+                    let _ = write!(attributes, r#" colspan="{}""#, colspan);
+                }
+                if cell.vertical_merge.is_some() {
+                    let rowspan = 1 + table.rows[r + 1..]
+                        .iter()
+                        .zip(&columns[r + 1..])
+                        .take_while(|(below, starts)| {
+                            starts
+                                .iter()
+                                .position(|&start| start == column)
+                                .is_some_and(|index| is_continuation(below.cells[index].vertical_merge))
+                        })
+                        .count();
+                    if rowspan > 1 {
+                        let _ = write!(attributes, r#" rowspan="{}""#, rowspan);
+                    }
+                }
+                let mut rules = cell_rules.clone();
+                merge(&mut rules, cell_declarations(cell));
+                attributes.push_str(&style_attribute(&rules));
+
+                let _ = write!(self.out, "<{}{}>", tag, attributes);
+                for para in &cell.paragraphs {
+                    let inline = self.inline(para, &[]);
+                    let _ = write!(self.out, "<p{}>{}</p>", paragraph_attributes(&para.properties), inline);
+                }
+                let _ = write!(self.out, "</{}>", tag);
+            }
+            self.out.push_str("</tr>\n");
+        }
+        self.out.push_str("</table>\n");
+    }
+}
+
+/// Grid column each cell of a row starts at
+pub(super) fn grid_columns(row: &TableRow) -> Vec<usize> {
+    row.cells
+        .iter()
+        .scan(0usize, |column, cell| {
+            let start = *column;
+            *column += span(cell) as usize;
+            Some(start)
+        })
+        .collect()
+}
+
+pub(super) fn span(cell: &TableCell) -> u32 {
+    cell.properties.grid_span.unwrap_or(1).max(1)
+}
+
+/// Whether a merge value marks a cell merged into the one before it
+pub(super) fn is_continuation(merge: Option<i32>) -> bool {
+    merge.is_some_and(|value| value != 1)
+}
+
+fn cell_declarations(cell: &TableCell) -> Vec<(&'static str, String)> {
+    let props = &cell.properties;
+    let mut rules = Vec::new();
+    if let Some(width) = props.width.or(cell.width).filter(|&width| width > 0) {
+        rules.push(("width", points(width as i32)));
+    }
+    if let Some(fill) = props.shading_color.as_deref().and_then(css_color) {
+        rules.push(("background-color", fill));
+    }
+    match props.vertical_alignment.as_deref() {
+        Some("center") => rules.push(("vertical-align", "middle".to_string())),
+        Some("bottom") => rules.push(("vertical-align", "bottom".to_string())),
+        _ => {}
+    }
+    rules
+}
+
+/// The class and style attributes of a paragraph element
+fn paragraph_attributes(props: &ParagraphProperties) -> String {
+    let mut attributes = String::new();
+    if let Some(id) = &props.style_id {
+        let _ = write!(attributes, r#" class="{}""#, style_class(id));
+    }
+    attributes.push_str(&style_attribute(&paragraph_declarations(props)));
+    attributes
+}
+
+/// Text of a run, in a span carrying its formatting if it has any
+fn run_html(props: &RunProperties, text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' | '\u{000B}' => escaped.push_str("<br>"),
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    let rules = run_declarations(props);
+    if rules.is_empty() || escaped.is_empty() {
+        return escaped;
+    }
+    format!("<span{}>{}</span>", style_attribute(&rules), escaped)
+}
+
+fn paragraph_declarations(props: &ParagraphProperties) -> Vec<(&'static str, String)> {
+    let mut rules = Vec::new();
+    let align = match props.alignment.as_deref() {
+        Some("left" | "start") => Some("left"),
+        Some("center") => Some("center"),
+        Some("right" | "end") => Some("right"),
+        Some("both" | "distribute" | "justify") => Some("justify"),
+        _ => None,
+    };
+    if let Some(align) = align {
+        rules.push(("text-align", align.to_string()));
+    }
+    for (property, value) in [
+        ("margin-top", props.spacing_before),
+        ("margin-bottom", props.spacing_after),
+        ("margin-left", props.indent_left),
+        ("margin-right", props.indent_right),
+        ("text-indent", props.indent_first_line),
+    ] {
+        if let Some(value) = value {
+            rules.push((property, points(value)));
+        }
+    }
+    // Line spacing is in 240ths of a line
+    if let Some(line) = props.spacing_line.filter(|&line| line > 0) {
+        rules.push(("line-height", format_number(line as f32 / 240.0)));
+    }
+    if let Some(border) = &props.border_bottom {
+        rules.push((
+            "border-bottom",
+            format!("{}pt {} currentColor", format_number(border.size as f32 / 8.0), border_style(&border.style)),
+        ));
+        rules.push(("padding-bottom", format!("{}pt", border.space)));
+    }
+    rules
+}
+
+fn run_declarations(props: &RunProperties) -> Vec<(&'static str, String)> {
+    let mut rules = Vec::new();
+    if let Some(bold) = props.bold {
+        rules.push(("font-weight", if bold { "bold" } else { "normal" }.to_string()));
+    }
+    if let Some(italic) = props.italic {
+        rules.push(("font-style", if italic { "italic" } else { "normal" }.to_string()));
+    }
+    match props.underline.as_deref() {
+        Some("none") => rules.push(("text-decoration", "none".to_string())),
+        Some(underline) => {
+            let style = match underline {
+                "double" => " double",
+                "wave" | "wavyHeavy" | "wavyDouble" => " wavy",
+                "dotted" | "dottedHeavy" => " dotted",
+                u if u.starts_with("dash") => " dashed",
+                _ => "",
+            };
+            rules.push(("text-decoration", format!("underline{}", style)));
+        }
+        None => {}
+    }
+    if let Some(size) = props.font_size.filter(|&size| size > 0) {
+        rules.push(("font-size", format!("{}pt", size)));
+    }
+    if let Some(name) = props.font_name.as_deref().filter(|name| !name.is_empty()) {
+        rules.push(("font-family", css_font(name)));
+    }
+    if let Some(color) = props.color.as_deref().and_then(css_color) {
+        rules.push(("color", color));
+    }
+    if let Some(color) = props.background_color.as_deref().and_then(css_color) {
+        rules.push(("background-color", color));
+    }
+    rules
+}
+
+/// Replace declarations of the same property, keep order otherwise
+fn merge(rules: &mut Vec<(&'static str, String)>, more: Vec<(&'static str, String)>) {
+    for (property, value) in more {
+        rules.retain(|(existing, _)| *existing != property);
+        rules.push((property, value));
+    }
+}
+
+fn declarations(rules: &[(&'static str, String)]) -> String {
+    rules.iter().map(|(property, value)| format!("{}: {}; ", property, value)).collect()
+}
+
+fn style_attribute(rules: &[(&'static str, String)]) -> String {
+    if rules.is_empty() {
+        return String::new();
+    }
+    format!(r#" style="{}""#, escape_xml_attr(declarations(rules).trim_end()))
+}
+
+/// Class of the elements in a style; IDs may hold any chars, classes may not
+fn style_class(id: &str) -> String {
+    let name: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("s-{}", name)
+}
+
+fn css_font(name: &str) -> String {
+    format!("\"{}\"", name.replace(['"', '\\'], ""))
+}
+
+/// A CSS color for a hex RGB or highlight color name; "auto" has none
+fn css_color(value: &str) -> Option<String> {
+    if value.len() == 6 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some(format!("#{}", value.to_ascii_uppercase()));
+    }
+    if value.eq_ignore_ascii_case("auto") || value.is_empty() || !value.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some(value.to_ascii_lowercase())
+}
+
+fn border_css(border: &TableBorder) -> String {
+    let style = border.style.as_deref().unwrap_or("single");
+    if matches!(style, "nil" | "none") {
+        return "none".to_string();
+    }
+    let width = border.size.map_or(0.5, |size| size as f32 / 8.0);
+    let color = border.color.as_deref().and_then(css_color).unwrap_or_else(|| "currentColor".to_string());
+    format!("{}pt {} {}", format_number(width), border_style(style), color)
+}
+
+/// The CSS border style closest to a Word one
+fn border_style(style: &str) -> &'static str {
+    match style {
+        "nil" | "none" => "none",
+        "double" | "triple" => "double",
+        "dotted" => "dotted",
+        s if s.starts_with("dash") => "dashed",
+        _ => "solid",
+    }
+}
+
+/// Twips as CSS points
+fn points(twips: i32) -> String {
+    format!("{}pt", format_number(twips as f32 / 20.0))
+}
+
+/// A number with at most two decimals and no trailing zeros
+fn format_number(value: f32) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// A data URI holding `data`, for images embedded in the page
+pub(crate) fn data_uri(mime_type: &str, data: &[u8]) -> String {
+    let mut uri = format!("data:{};base64,", mime_type);
+    uri.reserve(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                uri.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                uri.push('=');
+            }
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::serializer::{DocxSerializer, ExportFormat, ExportOptions, HtmlImages};
+    use super::super::types::{
+        AbstractNumDef, Footnote, Hyperlink, ListLevel, NoteReference, NumInstance, Numbering, Revision, Run, Style,
+        TableCellProperties,
+    };
+    use super::super::OpcPackage;
+    use super::*;
+    use crate::image::ImageCache;
+
+    fn paragraph(text: &str) -> Paragraph {
+        Paragraph {
+            text: text.to_string(),
+            runs: vec![Run { text: text.to_string(), ..Default::default() }],
+            ..Default::default()
+        }
+    }
+
+    fn list_paragraph(text: &str, level: u8) -> Paragraph {
+        let mut para = paragraph(text);
+        para.properties.num_id = Some("1".to_string());
+        para.properties.list_level = Some(level);
+        para
+    }
+
+    fn html(document: WordDocument) -> String {
+        DocxSerializer::new(OpcPackage::default(), document).export_html(None).unwrap().html
+    }
+
+    fn body(html: &str) -> &str {
+        let start = html.find("<body>\n").unwrap() + "<body>\n".len();
+        &html[start..html.find("</body>").unwrap()]
+    }
+
+    #[test]
+    fn test_styles_and_formatting_become_css() {
+        let mut styles = HashMap::new();
+        let mut normal = Style { id: "Normal".to_string(), style_type: "paragraph".to_string(), is_default: true, ..Default::default() };
+        normal.run_properties.font_size = Some(11);
+        let mut heading = Style {
+            id: "Heading1".to_string(),
+            style_type: "paragraph".to_string(),
+            based_on: Some("Normal".to_string()),
+            ..Default::default()
+        };
+        heading.run_properties.bold = Some(true);
+        heading.paragraph_properties.spacing_before = Some(240);
+        styles.insert(normal.id.clone(), normal);
+        styles.insert(heading.id.clone(), heading);
+
+        let mut title = paragraph("Intro");
+        title.properties.style_id = Some("Heading1".to_string());
+        let mut text = paragraph("");
+        text.text = "Read <this> not that here".to_string();
+        text.properties.alignment = Some("both".to_string());
+        text.runs = vec![
+            Run { text: "Read ".to_string(), ..Default::default() },
+            Run {
+                text: "<this>".to_string(),
+                properties: RunProperties { italic: Some(true), color: Some("ff0000".to_string()), ..Default::default() },
+            },
+            Run { text: " not that here".to_string(), ..Default::default() },
+        ];
+        text.revisions.push(Revision {
+            id: "1".to_string(),
+            kind: RevisionKind::Deletion,
+            author: None,
+            date: None,
+            start: 11,
+            length: 9,
+            previous_run_properties: None,
+            previous_paragraph_properties: None,
+        });
+        text.hyperlinks.push(Hyperlink { url: Some("https://example.com/?a=1&b=2".to_string()), start: 21, length: 4, ..Default::default() });
+
+        let html = html(WordDocument { paragraphs: vec![title, text, paragraph("")], styles, ..Default::default() });
+        assert!(html.contains("body { font-size: 11pt; }"));
+        assert!(html.contains(".s-Heading1 { font-size: 11pt; margin-top: 12pt; font-weight: bold; }"));
+        assert_eq!(
+            body(&html),
+            concat!(
+                "<h1 class=\"s-Heading1\">Intro</h1>\n",
+                "<p style=\"text-align: justify;\">Read <span style=\"font-style: italic; color: #FF0000;\">&lt;this&gt;</span>",
+                " <a href=\"https://example.com/?a=1&amp;b=2\">here</a></p>\n",
+                "<p><br></p>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_lists_nest_by_level() {
+        let levels = vec![
+            ListLevel { level: 0, format: "decimal".to_string(), start_value: 3, ..Default::default() },
+            ListLevel { level: 1, format: "bullet".to_string(), ..Default::default() },
+        ];
+        let numbering = Numbering {
+            abstract_num_defs: vec![AbstractNumDef { abstract_num_id: "0".to_string(), levels }],
+            num_instances: vec![NumInstance { num_id: "1".to_string(), abstract_num_id: "0".to_string(), overrides: Vec::new() }],
+        };
+        let paragraphs = vec![
+            list_paragraph("One", 0),
+            list_paragraph("Sub", 1),
+            list_paragraph("Two", 0),
+            paragraph("After"),
+        ];
+        let html = html(WordDocument { paragraphs, numbering: vec![numbering], ..Default::default() });
+        assert_eq!(
+            body(&html),
+            concat!(
+                "<ol start=\"3\"><li>One<ul><li>Sub</li></ul>\n",
+                "</li>\n<li>Two</li></ol>\n",
+                "<p>After</p>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_tables_keep_merged_cells() {
+        let cell = |text: &str, vertical_merge: Option<i32>, grid_span: Option<u32>| TableCell {
+            paragraphs: vec![paragraph(text)],
+            vertical_merge,
+            properties: TableCellProperties { grid_span, ..Default::default() },
+            ..Default::default()
+        };
+        let table = Table {
+            rows: vec![
+                TableRow { cells: vec![cell("Tall", Some(1), None), cell("Wide", None, Some(2))], ..Default::default() },
+                TableRow {
+                    cells: vec![cell("", Some(0), None), cell("B", None, None), cell("C", None, None)],
+                    ..Default::default()
+                },
+            ],
+            paragraph_index: 1,
+            ..Default::default()
+        };
+        let html = html(WordDocument {
+            paragraphs: vec![paragraph("Before"), paragraph("After")],
+            tables: vec![table],
+            ..Default::default()
+        });
+        assert_eq!(
+            body(&html),
+            concat!(
+                "<p>Before</p>\n<table>\n",
+                "<tr><td rowspan=\"2\"><p>Tall</p></td><td colspan=\"2\"><p>Wide</p></td></tr>\n",
+                "<tr><td><p>B</p></td><td><p>C</p></td></tr>\n",
+                "</table>\n<p>After</p>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_notes_and_images() {
+        let mut cache = ImageCache::new();
+        let png = vec![
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R',
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01,
+        ];
+        cache.load("media/logo.png".to_string(), png.clone()).unwrap();
+        let image = DocumentImage {
+            path: "media/logo.png".to_string(),
+            desired_width: Some(952_500),
+            desired_height: Some(476_250),
+            alt_description: Some("Logo".to_string()),
+            position: 3,
+            ..Default::default()
+        };
+        let mut text = paragraph("See logo");
+        text.note_references.push(NoteReference { kind: NoteKind::Footnote, id: "2".to_string(), position: 8 });
+        let document = WordDocument {
+            paragraphs: vec![text],
+            images: vec![image],
+            footnotes: vec![
+                Footnote { id: "0".to_string(), footnote_type: Some("separator".to_string()), paragraphs: Vec::new() },
+                Footnote { id: "2".to_string(), footnote_type: None, paragraphs: vec![paragraph("A note.")] },
+            ],
+            ..Default::default()
+        };
+        let serializer = DocxSerializer::new(OpcPackage::default(), document).with_image_cache(&cache);
+
+        let embedded = serializer.export_html(None).unwrap();
+        assert!(embedded.images.is_empty());
+        assert_eq!(
+            body(&embedded.html),
+            concat!(
+                "<p>See<img src=\"data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAIAAAAB\" alt=\"Logo\" width=\"100\" height=\"50\"> logo",
+                "<sup><a href=\"#note-1\" id=\"note-ref-1\">1</a></sup></p>\n",
+                "<section class=\"notes\">\n<hr>\n<ol>\n",
+                "<li id=\"note-1\"><p>A note. <a href=\"#note-ref-1\">&#8617;</a></p></li>\n",
+                "</ol>\n</section>\n",
+            )
+        );
+
+        let options = ExportOptions { format: ExportFormat::Html, html_images: HtmlImages::Link, ..Default::default() };
+        let linked = serializer.export_html(Some(options.clone())).unwrap();
+        assert!(linked.html.contains(r#"<img src="media/logo.png" alt="Logo" width="100" height="50">"#));
+        assert_eq!(linked.images.len(), 1);
+        assert_eq!(linked.images[0].data, png);
+        assert_eq!(serializer.export_docx(Some(options)).unwrap(), linked.html.into_bytes());
+    }
+
+    #[test]
+    fn test_data_uri_padding() {
+        assert_eq!(data_uri("text/plain", b"Ma"), "data:text/plain;base64,TWE=");
+        assert_eq!(data_uri("text/plain", b"M"), "data:text/plain;base64,TQ==");
+        assert_eq!(data_uri("text/plain", b"Man"), "data:text/plain;base64,TWFu");
+    }
+}
@@ -0,0 +1,597 @@
+//! Outline-first partial loading for long documents
+//!
+//! [`LazyDocument`] scans document.xml once for paragraph boundaries, plain
+//! text, headings and section breaks. That is enough to show the outline and
+//! lay out the first page. Run-level parsing (formatting, fields, note
+//! references) happens per chunk of paragraphs the first time a range inside
+//! the chunk is accessed, usually because it scrolled into the viewport, and
+//! the parsed runs are then applied to the piece tree as styled pieces.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::converter::convert_run_properties;
+use super::document::WordDocument;
+use super::error::OoxmlError;
+use super::forms::{parse_protection, ProtectionMode};
+use super::opc::OpcPackage;
+use super::types::{Paragraph, RunProperties, Section};
+use super::whitespace::read_text;
+use crate::piece_tree::{BufferId, Piece, PieceTree, TextAttributes};
+
+/// Paragraphs per chunk unless configured otherwise
+pub const DEFAULT_CHUNK_PARAGRAPHS: usize = 64;
+
+/// How a document is split for deferred parsing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LazyLoadOptions {
+    /// Paragraphs parsed together when any one of them is accessed
+    pub chunk_paragraphs: usize,
+}
+
+impl Default for LazyLoadOptions {
+    fn default() -> Self {
+        LazyLoadOptions {
+            chunk_paragraphs: DEFAULT_CHUNK_PARAGRAPHS,
+        }
+    }
+}
+
+/// A heading in the document outline
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutlineEntry {
+    /// Outline level, 1 for top-level headings
+    pub level: u8,
+    pub text: String,
+    /// Paragraph index
+    pub paragraph: usize,
+    /// Char offset of the heading in the document text
+    pub offset: usize,
+}
+
+/// A run of paragraphs sharing one section's page setup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionSpan {
+    pub first_paragraph: usize,
+    pub paragraph_count: usize,
+    /// Char offset of the section start
+    pub offset: usize,
+}
+
+/// Position and style of a paragraph, known without parsing its runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParagraphSpan {
+    /// Char offset of the paragraph start
+    pub start: usize,
+    /// Length in chars, excluding the paragraph break
+    pub length: usize,
+    /// Paragraph style ID
+    pub style: Option<String>,
+}
+
+struct Chunk {
+    paragraphs: Range<usize>,
+    /// Byte range of the chunk in the document text, including paragraph breaks
+    bytes: Range<usize>,
+    loaded: bool,
+}
+
+/// A document whose structure is parsed and whose runs are parsed on demand
+pub struct LazyDocument {
+    xml: String,
+    /// Byte range of each paragraph's inner XML
+    paragraph_xml: Vec<Range<usize>>,
+    /// Byte offset of each paragraph in `text`
+    paragraph_bytes: Vec<usize>,
+    paragraphs: Vec<ParagraphSpan>,
+    parsed: Vec<Option<Paragraph>>,
+    chunks: Vec<Chunk>,
+    outline: Vec<OutlineEntry>,
+    sections: Vec<SectionSpan>,
+    /// Properties of each of `sections`
+    section_properties: Vec<Section>,
+    /// Plain text, paragraphs joined by '\n'
+    text: String,
+    /// Editing restriction from settings.xml
+    protection: ProtectionMode,
+}
+
+impl LazyDocument {
+    /// Scan the structure of a .docx package without parsing runs
+    pub fn open(file_data: &[u8], options: LazyLoadOptions) -> Result<Self, OoxmlError> {
+        Self::from_package(&OpcPackage::new(file_data)?, options)
+    }
+
+    /// Scan the structure of the document in `package` without parsing runs
+    pub fn from_package(package: &OpcPackage, options: LazyLoadOptions) -> Result<Self, OoxmlError> {
+        let main_part_name = "/word/document.xml";
+        let main_part = package
+            .get_part(main_part_name)
+            .ok_or_else(|| OoxmlError::PartNotFound(main_part_name.to_string()))?;
+        let xml = String::from_utf8_lossy(&main_part.data).into_owned();
+        let mut document = Self::from_document_xml(xml, options);
+        if let Some(part) = package.get_part("/word/settings.xml") {
+            document.protection = parse_protection(&String::from_utf8_lossy(&part.data));
+        }
+        Ok(document)
+    }
+
+    /// Scan the structure of a document.xml string
+    pub fn from_document_xml(xml: String, options: LazyLoadOptions) -> Self {
+        let para_pattern = regex::Regex::new(r#"(?s)<w:p\b[^>]*?/>|<w:p\b[^>]*>(.*?)</w:p>"#).unwrap();
+        let style_pattern = regex::Regex::new(r#"<w:pStyle\b[^>]*w:val="([^"]*)""#).unwrap();
+        let outline_pattern = regex::Regex::new(r#"<w:outlineLvl\b[^>]*w:val="(\d+)""#).unwrap();
+
+        let mut document = LazyDocument {
+            xml: String::new(),
+            paragraph_xml: Vec::new(),
+            paragraph_bytes: Vec::new(),
+            paragraphs: Vec::new(),
+            parsed: Vec::new(),
+            chunks: Vec::new(),
+            outline: Vec::new(),
+            sections: Vec::new(),
+            section_properties: Vec::new(),
+            text: String::new(),
+            protection: ProtectionMode::None,
+        };
+        let mut char_offset = 0usize;
+        let mut section_start = 0usize;
+        // Where the last paragraph ends, for the body-level sectPr after it
+        let mut body_end = 0usize;
+
+        for para_cap in para_pattern.captures_iter(&xml) {
+            let inner = para_cap.get(1).map_or_else(
+                || {
+                    let end = para_cap.get(0).map_or(0, |m| m.end());
+                    end..end
+                },
+                |m| m.range(),
+            );
+            let para_xml = &xml[inner.clone()];
+            let index = document.paragraphs.len();
+            body_end = para_cap.get(0).map_or(body_end, |m| m.end());
+
+            if index > 0 {
+                document.text.push('\n');
+                char_offset += 1;
+            }
+
+            let text = read_text(para_xml, false);
+            let length = text.chars().count();
+            let style = style_pattern.captures(para_xml).map(|c| c[1].to_string());
+
+            let level = outline_pattern
+                .captures(para_xml)
+                .and_then(|c| c[1].parse::<u8>().ok())
+                .filter(|level| *level < 9)
+                .map(|level| level + 1)
+                .or_else(|| style.as_deref().and_then(heading_level));
+            if let Some(level) = level {
+                if !text.trim().is_empty() {
+                    document.outline.push(OutlineEntry {
+                        level,
+                        text: text.trim().to_string(),
+                        paragraph: index,
+                        offset: char_offset,
+                    });
+                }
+            }
+
+            // A sectPr inside a paragraph ends the section with that paragraph
+            if let Some(at) = para_xml.find("<w:sectPr") {
+                document.section_properties.push(WordDocument::parse_section_properties(&para_xml[at..]));
+                document.sections.push(SectionSpan {
+                    first_paragraph: section_start,
+                    paragraph_count: index + 1 - section_start,
+                    offset: document.paragraphs.get(section_start).map_or(char_offset, |p| p.start),
+                });
+                section_start = index + 1;
+            }
+
+            document.paragraph_xml.push(inner);
+            document.paragraph_bytes.push(document.text.len());
+            document.paragraphs.push(ParagraphSpan {
+                start: char_offset,
+                length,
+                style,
+            });
+            document.text.push_str(&text);
+            char_offset += length;
+        }
+
+        // The body-level sectPr covers the paragraphs after the last section break
+        if section_start < document.paragraphs.len() || document.sections.is_empty() {
+            let properties = xml[body_end..]
+                .find("<w:sectPr")
+                .map(|at| WordDocument::parse_section_properties(&xml[body_end + at..]))
+                .unwrap_or_default();
+            document.section_properties.push(properties);
+            document.sections.push(SectionSpan {
+                first_paragraph: section_start,
+                paragraph_count: document.paragraphs.len() - section_start,
+                offset: document.paragraphs.get(section_start).map_or(char_offset, |p| p.start),
+            });
+        }
+
+        let chunk_size = options.chunk_paragraphs.max(1);
+        let count = document.paragraphs.len();
+        for first in (0..count).step_by(chunk_size) {
+            let end = (first + chunk_size).min(count);
+            let byte_end = document
+                .paragraph_bytes
+                .get(end)
+                .copied()
+                .unwrap_or(document.text.len());
+            document.chunks.push(Chunk {
+                paragraphs: first..end,
+                bytes: document.paragraph_bytes[first]..byte_end,
+                loaded: false,
+            });
+        }
+
+        document.parsed = vec![None; count];
+        document.xml = xml;
+        document
+    }
+
+    /// Plain text of the whole document
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Paragraph positions, including empty paragraphs
+    pub fn paragraphs(&self) -> &[ParagraphSpan] {
+        &self.paragraphs
+    }
+
+    /// Headings in document order
+    pub fn outline(&self) -> &[OutlineEntry] {
+        &self.outline
+    }
+
+    /// Sections in document order
+    pub fn sections(&self) -> &[SectionSpan] {
+        &self.sections
+    }
+
+    /// Page geometry, columns and header and footer references of each section
+    pub fn section_properties(&self) -> &[Section] {
+        &self.section_properties
+    }
+
+    /// Editing restriction the document enforces
+    pub fn protection(&self) -> ProtectionMode {
+        self.protection
+    }
+
+    /// Number of chunks the paragraphs are split into
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Number of chunks whose runs have been parsed
+    pub fn loaded_chunk_count(&self) -> usize {
+        self.chunks.iter().filter(|c| c.loaded).count()
+    }
+
+    /// Check whether a paragraph's runs have been parsed
+    pub fn is_loaded(&self, paragraph: usize) -> bool {
+        self.parsed.get(paragraph).is_some_and(Option::is_some)
+    }
+
+    /// Fully parsed paragraph, parsing its chunk if needed
+    pub fn paragraph(&mut self, index: usize) -> Option<&Paragraph> {
+        if index >= self.paragraphs.len() {
+            return None;
+        }
+        let chunk = index / self.chunk_size();
+        self.load_chunk(chunk);
+        self.parsed[index].as_ref()
+    }
+
+    /// Run properties of the last paragraph's mark, parsing its chunk if needed
+    pub fn final_mark(&mut self) -> Option<&RunProperties> {
+        let last = self.paragraphs.len().checked_sub(1)?;
+        self.paragraph(last)?.mark_properties.as_ref()
+    }
+
+    /// Piece tree over the plain text; chunks already parsed are styled
+    pub fn piece_tree(&self) -> PieceTree {
+        let pieces = self
+            .chunks
+            .iter()
+            .filter(|chunk| !chunk.bytes.is_empty())
+            .map(|chunk| {
+                let char_length = self.text[chunk.bytes.clone()].chars().count();
+                Piece::new(chunk.bytes.start, chunk.bytes.len(), BufferId::ORIGINAL, char_length)
+            })
+            .collect();
+        let mut tree = PieceTree::from_loaded_data(pieces, vec![self.text.clone()]);
+
+        let loaded: Vec<usize> = (0..self.chunks.len()).filter(|&i| self.chunks[i].loaded).collect();
+        self.apply_styles(&mut tree, &loaded);
+        tree
+    }
+
+    /// Parse the chunks covering chars [start, end) of `tree` and style them in place
+    ///
+    /// `tree` must come from [`LazyDocument::piece_tree`]; it may have been edited
+    /// since, since chunks are located through the pieces that still show the
+    /// original text. Returns the number of chunks parsed by this call; nothing
+    /// is parsed when `tree` was built from a different document.
+    pub fn load_range(&mut self, tree: &mut PieceTree, start: usize, end: usize) -> usize {
+        if !self.owns(tree) {
+            return 0;
+        }
+        let mut newly_loaded = Vec::new();
+        for bytes in original_bytes_in(tree, start, end.max(start + 1)) {
+            let first = self.chunks.partition_point(|c| c.bytes.end <= bytes.start);
+            for index in first..self.chunks.len() {
+                if self.chunks[index].bytes.start >= bytes.end.max(bytes.start + 1) {
+                    break;
+                }
+                if self.load_chunk(index) {
+                    newly_loaded.push(index);
+                }
+            }
+        }
+
+        self.apply_styles(tree, &newly_loaded);
+        newly_loaded.len()
+    }
+
+    /// Parse every remaining chunk and style `tree`
+    pub fn load_all(&mut self, tree: &mut PieceTree) -> usize {
+        if !self.owns(tree) {
+            return 0;
+        }
+        let newly_loaded: Vec<usize> = (0..self.chunks.len()).filter(|&i| self.load_chunk(i)).collect();
+        self.apply_styles(tree, &newly_loaded);
+        newly_loaded.len()
+    }
+
+    /// Check whether `tree`'s original buffer is this document's text
+    fn owns(&self, tree: &PieceTree) -> bool {
+        tree.buffers.first().map(|b| &**b) == Some(self.text.as_str())
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.chunks.first().map_or(1, |c| c.paragraphs.len().max(1))
+    }
+
+    /// Parse the runs of one chunk; returns false if it was already parsed
+    fn load_chunk(&mut self, index: usize) -> bool {
+        let Some(chunk) = self.chunks.get_mut(index) else {
+            return false;
+        };
+        if chunk.loaded {
+            return false;
+        }
+        chunk.loaded = true;
+
+        for paragraph in chunk.paragraphs.clone() {
+            let para_xml = &self.xml[self.paragraph_xml[paragraph].clone()];
+            self.parsed[paragraph] = Some(WordDocument::parse_paragraph(para_xml).unwrap_or_default());
+        }
+        true
+    }
+
+    /// Split the unstyled original-text pieces of `tree` along the runs of the given chunks
+    fn apply_styles(&self, tree: &mut PieceTree, chunks: &[usize]) {
+        let mut styled: Vec<(Range<usize>, TextAttributes)> = Vec::new();
+        for &chunk in chunks {
+            for paragraph in self.chunks[chunk].paragraphs.clone() {
+                let Some(parsed) = &self.parsed[paragraph] else {
+                    continue;
+                };
+                let start = self.paragraph_bytes[paragraph];
+                // Both scans read the same <w:t> elements; skip the paragraph if they disagree
+                if self.text.get(start..start + parsed.text.len()) != Some(parsed.text.as_str()) {
+                    continue;
+                }
+                let mut cursor = start;
+                for run in &parsed.runs {
+                    if !run.text.is_empty() {
+                        styled.push((cursor..cursor + run.text.len(), convert_run_properties(&run.properties)));
+                        cursor += run.text.len();
+                    }
+                }
+            }
+        }
+        if styled.is_empty() {
+            return;
+        }
+        styled.sort_by_key(|(range, _)| range.start);
+
+        let buffer = &tree.buffers[0];
+        let mut pieces = Vec::with_capacity(tree.pieces.len() + styled.len());
+        for piece in &tree.pieces {
+            if !piece.buffer_id.is_original() || piece.attributes.is_some() {
+                pieces.push(piece.clone());
+                continue;
+            }
+
+            let end = piece.start + piece.length;
+            let mut cursor = piece.start;
+            let mut push = |range: Range<usize>, attributes: Option<TextAttributes>| {
+                let char_length = buffer[range.clone()].chars().count();
+                pieces.push(Piece::new_with_attrs(range.start, range.len(), BufferId::ORIGINAL, char_length, attributes));
+            };
+
+            let first = styled.partition_point(|(range, _)| range.end <= cursor);
+            for (range, attributes) in &styled[first..] {
+                if range.start >= end {
+                    break;
+                }
+                if range.start > cursor {
+                    push(cursor..range.start, None);
+                }
+                let styled_end = range.end.min(end);
+                push(range.start.max(cursor)..styled_end, Some(attributes.clone()));
+                cursor = styled_end;
+            }
+            if cursor < end {
+                push(cursor..end, None);
+            }
+        }
+        tree.set_pieces(pieces);
+    }
+}
+
+/// Byte ranges of the original buffer shown at chars [start, end) of `tree`
+fn original_bytes_in(tree: &PieceTree, start: usize, end: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut piece_start = 0usize;
+
+    for piece in &tree.pieces {
+        let piece_end = piece_start + piece.piece_char_length;
+        if piece_end > start && piece_start < end && piece.buffer_id.is_original() {
+            let text = &tree.buffers[0][piece.start..piece.start + piece.length];
+            let byte_at = |chars: usize| text.char_indices().nth(chars).map_or(text.len(), |(i, _)| i);
+            let from = byte_at(start.saturating_sub(piece_start));
+            let to = byte_at(end.min(piece_end) - piece_start);
+            ranges.push(piece.start + from..piece.start + to);
+        }
+        if piece_start >= end {
+            break;
+        }
+        piece_start = piece_end;
+    }
+    ranges
+}
+
+/// Outline level of a heading style ID ("Heading2", "heading 2", "Title")
+pub(super) fn heading_level(style: &str) -> Option<u8> {
+    if style == "Title" {
+        return Some(1);
+    }
+    let rest = style.to_ascii_lowercase().strip_prefix("heading")?.trim().to_string();
+    if rest.is_empty() {
+        return Some(1);
+    }
+    rest.parse::<u8>().ok().filter(|level| (1..=9).contains(level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paragraph(style: Option<&str>, runs: &str) -> String {
+        let ppr = style.map_or(String::new(), |s| format!(r#"<w:pPr><w:pStyle w:val="{}"/></w:pPr>"#, s));
+        format!("<w:p>{}{}</w:p>", ppr, runs)
+    }
+
+    fn plain(text: &str) -> String {
+        format!("<w:r><w:t>{}</w:t></w:r>", text)
+    }
+
+    fn bold(text: &str) -> String {
+        format!(r#"<w:r><w:rPr><w:b w:val="1"/></w:rPr><w:t>{}</w:t></w:r>"#, text)
+    }
+
+    fn sample() -> String {
+        let mut body = String::new();
+        body.push_str(&paragraph(Some("Heading1"), &plain("Intro")));
+        for i in 0..4 {
+            body.push_str(&paragraph(None, &format!("{}{}", plain("Para "), bold(&i.to_string()))));
+        }
+        body.push_str(r#"<w:p><w:pPr><w:sectPr/></w:pPr></w:p>"#);
+        body.push_str(&paragraph(Some("heading 2"), &plain("Details")));
+        body.push_str("<w:p/>");
+        body.push_str(&paragraph(None, &plain("A &amp; B")));
+        let landscape = r#"<w:sectPr><w:pgSz w:w="16838" w:h="11906" w:orient="landscape"/></w:sectPr>"#;
+        format!("<w:document><w:body>{}{}</w:body></w:document>", body, landscape)
+    }
+
+    fn open() -> LazyDocument {
+        LazyDocument::from_document_xml(sample(), LazyLoadOptions { chunk_paragraphs: 3 })
+    }
+
+    #[test]
+    fn test_structure_is_scanned_eagerly() {
+        let document = open();
+        assert_eq!(document.text(), "Intro\nPara 0\nPara 1\nPara 2\nPara 3\n\nDetails\n\nA & B");
+        assert_eq!(document.paragraphs().len(), 9);
+        assert_eq!(document.paragraphs()[6].start, 35);
+        assert_eq!(document.paragraphs()[6].style.as_deref(), Some("heading 2"));
+
+        let outline: Vec<(u8, &str, usize)> = document
+            .outline()
+            .iter()
+            .map(|e| (e.level, e.text.as_str(), e.offset))
+            .collect();
+        assert_eq!(outline, vec![(1, "Intro", 0), (2, "Details", 35)]);
+
+        let sections = document.sections();
+        assert_eq!(sections.len(), 2);
+        assert_eq!((sections[0].first_paragraph, sections[0].paragraph_count), (0, 6));
+        assert_eq!((sections[1].first_paragraph, sections[1].offset), (6, 35));
+        let properties = document.section_properties();
+        assert_eq!(properties.len(), 2);
+        assert!(!properties[0].landscape);
+        assert!(properties[1].landscape);
+        assert_eq!(properties[1].page_width, Some(16838));
+
+        assert_eq!(document.chunk_count(), 3);
+        assert_eq!(document.loaded_chunk_count(), 0);
+    }
+
+    #[test]
+    fn test_paragraph_access_parses_its_chunk() {
+        let mut document = open();
+        let para = document.paragraph(4).unwrap();
+        assert_eq!(para.runs.len(), 2);
+        assert_eq!(para.runs[1].properties.bold, Some(true));
+        assert!(document.is_loaded(3));
+        assert!(!document.is_loaded(2));
+        assert_eq!(document.loaded_chunk_count(), 1);
+        assert!(document.paragraph(9).is_none());
+    }
+
+    #[test]
+    fn test_load_range_styles_piece_tree() {
+        let mut document = open();
+        let mut tree = document.piece_tree();
+        assert_eq!(tree.get_text(), document.text());
+        assert!(tree.pieces.iter().all(|p| p.attributes.is_none()));
+
+        // "Para 1" is in the first chunk
+        assert_eq!(document.load_range(&mut tree, 13, 19), 1);
+        assert_eq!(document.load_range(&mut tree, 13, 19), 0);
+        assert_eq!(tree.get_text(), document.text());
+        let bold_pieces: Vec<String> = tree
+            .pieces
+            .iter()
+            .filter(|p| p.attributes.as_ref().and_then(|a| a.bold) == Some(true))
+            .map(|p| tree.buffers[0][p.start..p.start + p.length].to_string())
+            .collect();
+        assert_eq!(bold_pieces, vec!["0", "1"]);
+        assert_eq!(tree.total_char_count, document.text().chars().count());
+    }
+
+    #[test]
+    fn test_load_range_after_edit() {
+        let mut document = open();
+        let mut tree = document.piece_tree();
+        tree.insert(0, "New\n".to_string());
+
+        // Chars 4.. now show the original text; "Para 3" was at 27, now 31
+        assert_eq!(document.load_range(&mut tree, 31, 37), 1);
+        assert!(document.is_loaded(4));
+        assert_eq!(tree.get_text(), format!("New\n{}", document.text()));
+
+        assert_eq!(document.load_all(&mut tree), 2);
+        assert_eq!(document.loaded_chunk_count(), 3);
+        assert_eq!(tree.get_text(), format!("New\n{}", document.text()));
+    }
+
+    #[test]
+    fn test_foreign_tree_is_left_alone() {
+        let mut document = open();
+        let mut tree = PieceTree::new("Something else".to_string());
+        assert_eq!(document.load_range(&mut tree, 0, 5), 0);
+        assert_eq!(document.loaded_chunk_count(), 0);
+    }
+}
@@ -0,0 +1,1274 @@
+//! Binary Word 97-2003 (.doc) import
+//!
+//! A .doc is an OLE compound file. Its WordDocument stream opens with the FIB,
+//! which locates everything else in the 0Table or 1Table stream: the piece
+//! table mapping character positions to text in WordDocument, the bin tables
+//! of the FKP pages holding character (CHPX) and paragraph (PAPX) formatting,
+//! the style sheet and the font table. Inline pictures live in the Data stream.
+//!
+//! [`parse_doc`] reads the body of Word 97 and later files into the same
+//! [`WordDocument`] a .docx is parsed into. Headers, notes, comments, tables,
+//! lists, field codes, floating shapes and the property changes of fast-saved
+//! pieces are not read; fields keep their result text.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek};
+
+use cfb::CompoundFile;
+
+use super::document::{CoreProperties, WordDocument};
+use super::opc::OpcPackage;
+use super::types::{DocumentImage, Paragraph, ParagraphProperties, Run, RunProperties, Style};
+use super::{parsed_document, ParsedDocument};
+
+/// First bytes of every OLE compound file
+const CFB_SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// wIdent of a Word binary FIB
+const FIB_IDENT: u16 = 0xA5EC;
+/// Lowest nFib of the Word 97 format; Word 6 and 95 files are older
+const WORD97_NFIB: u16 = 0x00C0;
+const FIB_ENCRYPTED: u16 = 0x0100;
+const FIB_TABLE_ONE: u16 = 0x0200;
+
+// Indexes of the (fc, lcb) pairs in FibRgFcLcb97
+const FC_STSHF: usize = 1;
+const FC_PLCF_BTE_CHPX: usize = 12;
+const FC_PLCF_BTE_PAPX: usize = 13;
+const FC_STTBF_FFN: usize = 15;
+const FC_CLX: usize = 33;
+
+const FKP_SIZE: usize = 512;
+/// Istd of the style a paragraph without one has
+const ISTD_NORMAL: u16 = 0;
+/// Istd of the style a run without one has
+const ISTD_DEFAULT_PARAGRAPH_FONT: u16 = 10;
+
+// Character sprms
+const SPRM_C_F_DATA: u16 = 0x0806;
+const SPRM_C_F_BOLD: u16 = 0x0835;
+const SPRM_C_F_ITALIC: u16 = 0x0836;
+const SPRM_C_F_SPEC: u16 = 0x0855;
+const SPRM_C_HIGHLIGHT: u16 = 0x2A0C;
+const SPRM_C_KUL: u16 = 0x2A3E;
+const SPRM_C_ICO: u16 = 0x2A42;
+const SPRM_C_HPS: u16 = 0x4A43;
+const SPRM_C_RG_FTC0: u16 = 0x4A4F;
+const SPRM_C_PIC_LOCATION: u16 = 0x6A03;
+const SPRM_C_CV: u16 = 0x6870;
+
+// Paragraph sprms
+const SPRM_P_JC80: u16 = 0x2403;
+const SPRM_P_FTTP: u16 = 0x2417;
+const SPRM_P_JC: u16 = 0x2461;
+const SPRM_P_DYA_LINE: u16 = 0x6412;
+const SPRM_P_DXA_RIGHT80: u16 = 0x840E;
+const SPRM_P_DXA_LEFT80: u16 = 0x840F;
+const SPRM_P_DXA_LEFT1_80: u16 = 0x8411;
+const SPRM_P_DXA_RIGHT: u16 = 0x845D;
+const SPRM_P_DXA_LEFT: u16 = 0x845E;
+const SPRM_P_DXA_LEFT1: u16 = 0x8460;
+const SPRM_P_DYA_BEFORE: u16 = 0xA413;
+const SPRM_P_DYA_AFTER: u16 = 0xA414;
+/// Sprms whose operand is longer than the one length byte can say
+const SPRM_T_DEF_TABLE: u16 = 0xD608;
+const SPRM_P_CHG_TABS: u16 = 0xC615;
+
+// Special characters of the text
+const CHAR_PICTURE: u32 = 0x01;
+const CHAR_CELL_END: u32 = 0x07;
+const CHAR_TAB: u32 = 0x09;
+const CHAR_LINE_BREAK: u32 = 0x0B;
+const CHAR_SECTION_END: u32 = 0x0C;
+const CHAR_PARAGRAPH_END: u32 = 0x0D;
+const CHAR_FIELD_BEGIN: u32 = 0x13;
+const CHAR_FIELD_SEPARATOR: u32 = 0x14;
+const CHAR_FIELD_END: u32 = 0x15;
+const CHAR_NON_BREAKING_HYPHEN: u32 = 0x1E;
+const CHAR_OPTIONAL_HYPHEN: u32 = 0x1F;
+
+// OfficeArt records around an inline picture's BLIP
+const RECORD_SP_CONTAINER: u16 = 0xF004;
+const RECORD_BSE: u16 = 0xF007;
+const RECORD_BLIP_EMF: u16 = 0xF01A;
+const RECORD_BLIP_WMF: u16 = 0xF01B;
+const RECORD_BLIP_PICT: u16 = 0xF01C;
+const RECORD_BLIP_JPEG: u16 = 0xF01D;
+const RECORD_BLIP_PNG: u16 = 0xF01E;
+const RECORD_BLIP_DIB: u16 = 0xF01F;
+const RECORD_BLIP_TIFF: u16 = 0xF029;
+const RECORD_BLIP_JPEG_CMYK: u16 = 0xF02A;
+/// mfpf.mm of a picture whose file name follows the PICF
+const MM_SHAPE_FILE: u16 = 0x0066;
+
+/// EMUs per twip, as DocumentImage sizes are in EMUs
+const EMU_PER_TWIP: u32 = 635;
+
+/// Colors of the 16-entry ico palette, from index 1; 0 is auto
+const ICO_COLORS: [&str; 16] = [
+    "000000", "0000FF", "00FFFF", "00FF00", "FF00FF", "FF0000", "FFFF00", "FFFFFF",
+    "000080", "008080", "008000", "800080", "800000", "808000", "808080", "C0C0C0",
+];
+
+/// Code points of bytes 0x80-0x9F in Windows-1252, which compressed text is in
+const CP1252_HIGH: [u16; 32] = [
+    0x20AC, 0x0081, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039, 0x0152,
+    0x008D, 0x017D, 0x008F, 0x0090, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0x02DC, 0x2122,
+    0x0161, 0x203A, 0x0153, 0x009D, 0x017E, 0x0178,
+];
+
+/// Errors reading a .doc
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum LegacyDocError {
+    #[error("Not an OLE compound file: {0}")]
+    Container(String),
+
+    #[error("Missing stream: {0}")]
+    MissingStream(String),
+
+    #[error("Not a Word document")]
+    NotWordDocument,
+
+    #[error("Unsupported Word version (nFib {0:#06x}); Word 97 and later files can be read")]
+    UnsupportedVersion(u16),
+
+    #[error("The document is encrypted")]
+    Encrypted,
+
+    #[error("Truncated {0}")]
+    Truncated(&'static str),
+}
+
+/// A .doc read into the model a .docx is parsed into
+#[derive(Debug, Clone)]
+pub struct LegacyDocument {
+    /// Body paragraphs with their runs, styles, images and summary properties
+    pub word_document: WordDocument,
+    /// The structure the UI reads, as [`parse_ooxml`](super::parse_ooxml) returns for a .docx
+    pub document: ParsedDocument,
+    /// Inline pictures, keyed by the `path` of their [`DocumentImage`]
+    pub media: HashMap<String, Vec<u8>>,
+}
+
+/// Whether `file_data` is an OLE compound file, as .doc files are
+pub fn is_legacy_doc(file_data: &[u8]) -> bool {
+    file_data.starts_with(&CFB_SIGNATURE)
+}
+
+/// Read a Word 97-2003 binary document
+pub fn parse_doc(file_data: &[u8]) -> Result<LegacyDocument, LegacyDocError> {
+    let timer = crate::metrics::Timer::start();
+
+    let mut compound =
+        CompoundFile::open(Cursor::new(file_data)).map_err(|e| LegacyDocError::Container(e.to_string()))?;
+    let word = read_stream(&mut compound, "WordDocument")?
+        .ok_or_else(|| LegacyDocError::MissingStream("WordDocument".to_string()))?;
+    let fib = Fib::parse(&word)?;
+    let table_name = if fib.table_one { "1Table" } else { "0Table" };
+    let table = read_stream(&mut compound, table_name)?
+        .ok_or_else(|| LegacyDocError::MissingStream(table_name.to_string()))?;
+    let data = read_stream(&mut compound, "Data")?.unwrap_or_default();
+
+    let clx = fib.table_slice(&table, FC_CLX).ok_or(LegacyDocError::Truncated("piece table"))?;
+    let text = read_text(&word, &parse_clx(clx)?, fib.ccp_text);
+
+    let fonts = fib.table_slice(&table, FC_STTBF_FFN).map(parse_font_table).unwrap_or_default();
+    let styles = fib
+        .table_slice(&table, FC_STSHF)
+        .map(|stsh| parse_style_sheet(stsh, &fonts))
+        .unwrap_or_default();
+    let character_runs: Vec<FcRun<CharacterFormat>> = fib
+        .table_slice(&table, FC_PLCF_BTE_CHPX)
+        .map(|plc| read_fkp_runs(&word, plc, |page, index| parse_chpx(page, index, &fonts)))
+        .unwrap_or_default();
+    let paragraph_runs: Vec<FcRun<ParagraphFormat>> = fib
+        .table_slice(&table, FC_PLCF_BTE_PAPX)
+        .map(|plc| read_fkp_runs(&word, plc, parse_papx))
+        .unwrap_or_default();
+
+    let mut word_document = WordDocument::empty();
+    let mut media = HashMap::new();
+    let mut body = BodyBuilder::default();
+    for &(code, fc) in &text {
+        let format = find_run(&character_runs, fc);
+        match code {
+            CHAR_FIELD_BEGIN => body.fields.push(false),
+            CHAR_FIELD_SEPARATOR => {
+                if let Some(in_result) = body.fields.last_mut() {
+                    *in_result = true;
+                }
+            }
+            CHAR_FIELD_END => {
+                body.fields.pop();
+            }
+            // A field's instruction is not part of the text
+            _ if body.fields.contains(&false) => {}
+            CHAR_PARAGRAPH_END | CHAR_CELL_END | CHAR_SECTION_END => {
+                let format = find_run(&paragraph_runs, fc);
+                body.end_paragraph(format, &styles);
+            }
+            CHAR_PICTURE => {
+                let Some(location) = format.filter(|f| f.special && !f.form_data).and_then(|f| f.picture) else {
+                    continue;
+                };
+                if let Some(picture) = read_picture(&data, location as usize) {
+                    let index = word_document.images.len() + 1;
+                    let path = format!("media/image{}.{}", index, picture.extension);
+                    word_document.images.push(DocumentImage {
+                        id: format!("image{}", index),
+                        path: path.clone(),
+                        original_width: Some(picture.width_twips * EMU_PER_TWIP),
+                        original_height: Some(picture.height_twips * EMU_PER_TWIP),
+                        scale_x: Some(picture.scale_x),
+                        scale_y: Some(picture.scale_y),
+                        paragraph_index: body.paragraphs.len(),
+                        position: body.paragraph.text.chars().count() + body.run_text.chars().count(),
+                        ..Default::default()
+                    });
+                    media.insert(path, picture.bytes);
+                }
+            }
+            CHAR_TAB => body.push('\t', format),
+            CHAR_LINE_BREAK => body.push('\u{2028}', format),
+            CHAR_NON_BREAKING_HYPHEN => body.push('\u{2011}', format),
+            CHAR_OPTIONAL_HYPHEN => body.push('\u{00AD}', format),
+            // Note and comment reference marks, drawn objects and other controls
+            code if code < 0x20 => {}
+            code => body.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER), format),
+        }
+    }
+    if !body.paragraph.text.is_empty() || !body.run_text.is_empty() {
+        body.end_paragraph(None, &styles);
+    }
+
+    word_document.paragraphs = body.paragraphs;
+    word_document.text = word_document
+        .paragraphs
+        .iter()
+        .map(|p| p.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    word_document.styles = styles.into_iter().flatten().map(|style| (style.id.clone(), style)).collect();
+    word_document.core_properties = read_stream(&mut compound, "\u{5}SummaryInformation")?
+        .and_then(|stream| parse_summary_information(&stream));
+
+    let mut document = parsed_document(&OpcPackage::default(), word_document.clone());
+    document.has_macros = compound.is_storage("Macros");
+
+    timer.record(crate::metrics::DOCUMENT_OPEN_MS);
+    crate::metrics::counter(crate::metrics::DOCUMENTS_OPENED, 1);
+
+    Ok(LegacyDocument { word_document, document, media })
+}
+
+fn read_stream<F: Read + Seek>(compound: &mut CompoundFile<F>, name: &str) -> Result<Option<Vec<u8>>, LegacyDocError> {
+    if !compound.is_stream(name) {
+        return Ok(None);
+    }
+    let mut buffer = Vec::new();
+    compound
+        .open_stream(name)
+        .and_then(|mut stream| stream.read_to_end(&mut buffer))
+        .map_err(|e| LegacyDocError::Container(e.to_string()))?;
+    Ok(Some(buffer))
+}
+
+fn u8_at(data: &[u8], offset: usize) -> Option<u8> {
+    data.get(offset).copied()
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset.checked_add(2)?)?.try_into().ok()?))
+}
+
+fn i16_at(data: &[u8], offset: usize) -> Option<i16> {
+    u16_at(data, offset).map(|value| value as i16)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset.checked_add(4)?)?.try_into().ok()?))
+}
+
+/// UTF-16LE text up to its first null, or the end of `data`
+fn utf16_until_null(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn cp1252_char(byte: u8) -> u32 {
+    match byte {
+        0x80..=0x9F => CP1252_HIGH[(byte - 0x80) as usize] as u32,
+        _ => byte as u32,
+    }
+}
+
+/// The parts of the File Information Block the importer needs
+struct Fib {
+    /// Whether the table stream is 1Table rather than 0Table
+    table_one: bool,
+    /// Length of the main document text in characters
+    ccp_text: u32,
+    /// (offset, length) pairs locating the structures in the table stream
+    fc_lcb: Vec<(u32, u32)>,
+}
+
+impl Fib {
+    fn parse(word: &[u8]) -> Result<Self, LegacyDocError> {
+        const TRUNCATED: LegacyDocError = LegacyDocError::Truncated("FIB");
+        if u16_at(word, 0).ok_or(TRUNCATED)? != FIB_IDENT {
+            return Err(LegacyDocError::NotWordDocument);
+        }
+        let n_fib = u16_at(word, 2).ok_or(TRUNCATED)?;
+        if n_fib < WORD97_NFIB {
+            return Err(LegacyDocError::UnsupportedVersion(n_fib));
+        }
+        let flags = u16_at(word, 0x0A).ok_or(TRUNCATED)?;
+        if flags & FIB_ENCRYPTED != 0 {
+            return Err(LegacyDocError::Encrypted);
+        }
+
+        // FibBase is followed by three counted arrays: fibRgW, fibRgLw and fibRgFcLcb
+        let csw = u16_at(word, 32).ok_or(TRUNCATED)? as usize;
+        let rg_lw = 34 + csw * 2;
+        let cslw = u16_at(word, rg_lw).ok_or(TRUNCATED)? as usize;
+        let ccp_text = u32_at(word, rg_lw + 2 + 3 * 4).ok_or(TRUNCATED)?;
+        let rg_fc_lcb = rg_lw + 2 + cslw * 4;
+        let count = u16_at(word, rg_fc_lcb).ok_or(TRUNCATED)? as usize;
+        let fc_lcb = (0..count)
+            .map(|index| {
+                let offset = rg_fc_lcb + 2 + index * 8;
+                Some((u32_at(word, offset)?, u32_at(word, offset + 4)?))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(TRUNCATED)?;
+
+        Ok(Fib { table_one: flags & FIB_TABLE_ONE != 0, ccp_text, fc_lcb })
+    }
+
+    /// The structure at `index` of FibRgFcLcb in the table stream, if present
+    fn table_slice<'a>(&self, table: &'a [u8], index: usize) -> Option<&'a [u8]> {
+        let &(fc, lcb) = self.fc_lcb.get(index)?;
+        if lcb == 0 {
+            return None;
+        }
+        table.get(fc as usize..(fc as usize).checked_add(lcb as usize)?)
+    }
+}
+
+/// A piece of the document text: characters `cp_start..cp_end` are stored at
+/// byte `fc` of the WordDocument stream
+struct TextPiece {
+    cp_start: u32,
+    cp_end: u32,
+    fc: u32,
+    /// One byte of Windows-1252 per character rather than UTF-16
+    compressed: bool,
+}
+
+/// Read the piece table (PlcPcd) out of the Clx, skipping the property modifiers before it
+fn parse_clx(clx: &[u8]) -> Result<Vec<TextPiece>, LegacyDocError> {
+    const TRUNCATED: LegacyDocError = LegacyDocError::Truncated("piece table");
+    let mut pos = 0;
+    while u8_at(clx, pos) == Some(0x01) {
+        let size = i16_at(clx, pos + 1).ok_or(TRUNCATED)?;
+        pos += 3 + size.max(0) as usize;
+    }
+    if u8_at(clx, pos) != Some(0x02) {
+        return Err(TRUNCATED);
+    }
+    let lcb = u32_at(clx, pos + 1).ok_or(TRUNCATED)? as usize;
+    let plc = clx.get(pos + 5..pos + 5 + lcb).ok_or(TRUNCATED)?;
+
+    // n + 1 character positions followed by n 8-byte piece descriptors
+    let count = lcb.saturating_sub(4) / 12;
+    (0..count)
+        .map(|index| {
+            let cp_start = u32_at(plc, index * 4)?;
+            let cp_end = u32_at(plc, index * 4 + 4)?;
+            let fc = u32_at(plc, (count + 1) * 4 + index * 8 + 2)?;
+            let compressed = fc & 0x4000_0000 != 0;
+            let fc = fc & 0x3FFF_FFFF;
+            Some(TextPiece { cp_start, cp_end, fc: if compressed { fc / 2 } else { fc }, compressed })
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or(TRUNCATED)
+}
+
+/// The main document text as (code point, stream offset) pairs; control
+/// characters keep their codes
+fn read_text(word: &[u8], pieces: &[TextPiece], ccp_text: u32) -> Vec<(u32, u32)> {
+    let mut text = Vec::new();
+    for piece in pieces {
+        let end = piece.cp_end.min(ccp_text);
+        if piece.cp_start >= end {
+            continue;
+        }
+        // No piece holds more characters than the stream has bytes
+        let length = (end - piece.cp_start).min(word.len() as u32);
+        if piece.compressed {
+            for index in 0..length {
+                let fc = piece.fc + index;
+                match u8_at(word, fc as usize) {
+                    Some(byte) => text.push((cp1252_char(byte), fc)),
+                    None => return text,
+                }
+            }
+        } else {
+            let units = (0..length).map_while(|index| u16_at(word, (piece.fc + index * 2) as usize));
+            let mut fc = piece.fc;
+            for decoded in char::decode_utf16(units) {
+                let code = decoded.map_or(char::REPLACEMENT_CHARACTER as u32, u32::from);
+                text.push((code, fc));
+                fc += if code > 0xFFFF { 4 } else { 2 };
+            }
+        }
+    }
+    text
+}
+
+/// Formatting applied to the WordDocument bytes `start..end`
+struct FcRun<T> {
+    start: u32,
+    end: u32,
+    format: T,
+}
+
+fn find_run<T>(runs: &[FcRun<T>], fc: u32) -> Option<&T> {
+    let index = runs.partition_point(|run| run.end <= fc);
+    runs.get(index).filter(|run| run.start <= fc).map(|run| &run.format)
+}
+
+/// Collect the runs of every FKP page a bin table (PlcBteChpx or PlcBtePapx)
+/// lists, reading each run's formatting with `parse`
+fn read_fkp_runs<T>(word: &[u8], plc: &[u8], parse: impl Fn(&[u8], usize) -> T) -> Vec<FcRun<T>> {
+    let count = plc.len().saturating_sub(4) / 8;
+    let mut runs = Vec::new();
+    for index in 0..count {
+        let Some(pn) = u32_at(plc, (count + 1) * 4 + index * 4) else {
+            break;
+        };
+        let start = (pn & 0x003F_FFFF) as usize * FKP_SIZE;
+        let Some(page) = word.get(start..start + FKP_SIZE) else {
+            continue;
+        };
+        let crun = page[FKP_SIZE - 1] as usize;
+        for run in 0..crun {
+            let (Some(start), Some(end)) = (u32_at(page, run * 4), u32_at(page, run * 4 + 4)) else {
+                break;
+            };
+            runs.push(FcRun { start, end, format: parse(page, run) });
+        }
+    }
+    runs.sort_by_key(|run| run.start);
+    runs
+}
+
+/// What a CHPX says about its characters
+#[derive(Debug, Clone, Default)]
+struct CharacterFormat {
+    properties: RunProperties,
+    /// sprmCFSpec: the control characters are special ones, such as pictures
+    special: bool,
+    /// sprmCFData: a picture character holds form field data instead
+    form_data: bool,
+    /// sprmCPicLocation: offset of the picture in the Data stream
+    picture: Option<u32>,
+}
+
+fn parse_chpx(page: &[u8], run: usize, fonts: &[String]) -> CharacterFormat {
+    let crun = page[FKP_SIZE - 1] as usize;
+    let offset = u8_at(page, (crun + 1) * 4 + run).unwrap_or(0) as usize * 2;
+    let mut format = CharacterFormat::default();
+    if offset == 0 {
+        return format;
+    }
+    let size = u8_at(page, offset).unwrap_or(0) as usize;
+    if let Some(grpprl) = page.get(offset + 1..offset + 1 + size) {
+        apply_character_sprms(&mut format, grpprl, fonts);
+    }
+    format
+}
+
+fn apply_character_sprms(format: &mut CharacterFormat, grpprl: &[u8], fonts: &[String]) {
+    let properties = &mut format.properties;
+    for (sprm, operand) in Sprms(grpprl) {
+        let byte = operand.first().copied().unwrap_or(0);
+        match sprm {
+            SPRM_C_F_BOLD => properties.bold = toggle(byte),
+            SPRM_C_F_ITALIC => properties.italic = toggle(byte),
+            SPRM_C_KUL => properties.underline = Some(underline_name(byte).to_string()),
+            SPRM_C_HPS => properties.font_size = u16_at(operand, 0).map(i32::from),
+            SPRM_C_RG_FTC0 => {
+                properties.font_name = u16_at(operand, 0).and_then(|ftc| fonts.get(ftc as usize)).cloned()
+            }
+            SPRM_C_ICO => properties.color = ico_color(byte),
+            SPRM_C_CV => {
+                // COLORREF: red, green, blue, then 0xFF for auto
+                properties.color = match operand {
+                    [_, _, _, 0xFF] => None,
+                    [red, green, blue, _] => Some(format!("{:02X}{:02X}{:02X}", red, green, blue)),
+                    _ => properties.color.take(),
+                }
+            }
+            SPRM_C_HIGHLIGHT => properties.background_color = ico_color(byte),
+            SPRM_C_F_SPEC => format.special = byte != 0,
+            SPRM_C_F_DATA => format.form_data = byte != 0,
+            SPRM_C_PIC_LOCATION => format.picture = u32_at(operand, 0),
+            _ => {}
+        }
+    }
+}
+
+/// A ToggleOperand; "opposite of the style's value" is taken as on
+fn toggle(operand: u8) -> Option<bool> {
+    match operand {
+        0 => Some(false),
+        1 | 0x81 => Some(true),
+        _ => None,
+    }
+}
+
+fn underline_name(kul: u8) -> &'static str {
+    match kul {
+        0 => "none",
+        2 => "words",
+        3 => "double",
+        4 => "dotted",
+        6 => "thick",
+        7 => "dash",
+        9 => "dotDash",
+        10 => "dotDotDash",
+        11 => "wave",
+        _ => "single",
+    }
+}
+
+fn ico_color(ico: u8) -> Option<String> {
+    ICO_COLORS.get((ico as usize).checked_sub(1)?).map(|color| color.to_string())
+}
+
+/// What a PAPX says about its paragraph
+#[derive(Debug, Clone, Default)]
+struct ParagraphFormat {
+    istd: u16,
+    properties: ParagraphProperties,
+    /// sprmPFTtp: the paragraph mark ends a table row rather than a paragraph
+    row_end: bool,
+}
+
+fn parse_papx(page: &[u8], run: usize) -> ParagraphFormat {
+    let cpara = page[FKP_SIZE - 1] as usize;
+    // Each BxPap is the PAPX's word offset followed by a 12-byte PHE
+    let offset = u8_at(page, (cpara + 1) * 4 + run * 13).unwrap_or(0) as usize * 2;
+    let mut format = ParagraphFormat::default();
+    if offset == 0 {
+        return format;
+    }
+    let papx = match u8_at(page, offset) {
+        Some(0) => {
+            let size = u8_at(page, offset + 1).unwrap_or(0) as usize * 2;
+            page.get(offset + 2..offset + 2 + size)
+        }
+        Some(cb) => page.get(offset + 1..offset + cb as usize * 2),
+        None => None,
+    };
+    if let Some(papx) = papx {
+        format.istd = u16_at(papx, 0).unwrap_or(ISTD_NORMAL);
+        apply_paragraph_sprms(&mut format, papx.get(2..).unwrap_or_default());
+    }
+    format
+}
+
+fn apply_paragraph_sprms(format: &mut ParagraphFormat, grpprl: &[u8]) {
+    let properties = &mut format.properties;
+    for (sprm, operand) in Sprms(grpprl) {
+        let value = i16_at(operand, 0).map(i32::from);
+        match sprm {
+            SPRM_P_JC80 | SPRM_P_JC => {
+                properties.alignment = match operand.first() {
+                    Some(0) => Some("left".to_string()),
+                    Some(1) => Some("center".to_string()),
+                    Some(2) => Some("right".to_string()),
+                    Some(3) => Some("both".to_string()),
+                    Some(4) => Some("distribute".to_string()),
+                    _ => properties.alignment.take(),
+                }
+            }
+            SPRM_P_DXA_LEFT80 | SPRM_P_DXA_LEFT => properties.indent_left = value,
+            SPRM_P_DXA_RIGHT80 | SPRM_P_DXA_RIGHT => properties.indent_right = value,
+            SPRM_P_DXA_LEFT1_80 | SPRM_P_DXA_LEFT1 => properties.indent_first_line = value,
+            SPRM_P_DYA_BEFORE => properties.spacing_before = u16_at(operand, 0).map(i32::from),
+            SPRM_P_DYA_AFTER => properties.spacing_after = u16_at(operand, 0).map(i32::from),
+            // LSPD: the line height, in 240ths of a line when the second field is set
+            SPRM_P_DYA_LINE => properties.spacing_line = value,
+            SPRM_P_FTTP => format.row_end = operand.first() == Some(&1),
+            _ => {}
+        }
+    }
+}
+
+/// The (sprm, operand) pairs of a grpprl
+struct Sprms<'a>(&'a [u8]);
+
+impl<'a> Iterator for Sprms<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sprm = u16_at(self.0, 0)?;
+        let rest = &self.0[2..];
+        // spra, the top three bits, gives the operand size
+        let (skip, size) = match sprm >> 13 {
+            0 | 1 => (0, 1),
+            2 | 4 | 5 => (0, 2),
+            3 => (0, 4),
+            7 => (0, 3),
+            _ if sprm == SPRM_T_DEF_TABLE => (2, (u16_at(rest, 0)? as usize).saturating_sub(1)),
+            // A tab change too long for its length byte is not worth decoding
+            _ if sprm == SPRM_P_CHG_TABS && rest.first() == Some(&0xFF) => return None,
+            _ => (1, *rest.first()? as usize),
+        };
+        let operand = rest.get(skip..skip + size)?;
+        self.0 = &rest[skip + size..];
+        Some((sprm, operand))
+    }
+}
+
+/// Font names by ftc, from the SttbfFfn
+fn parse_font_table(sttb: &[u8]) -> Vec<String> {
+    // Each FFN: ffid, weight, charset, alternate name index, PANOSE and
+    // FONTSIGNATURE come before the null-terminated UTF-16 name
+    const FFN_NAME: usize = 39;
+    let count = u16_at(sttb, 0).unwrap_or(0) as usize;
+    let mut fonts = Vec::with_capacity(count);
+    let mut pos = 4;
+    for _ in 0..count {
+        let Some(size) = u8_at(sttb, pos) else {
+            break;
+        };
+        let ffn = sttb.get(pos + 1..pos + 1 + size as usize).unwrap_or_default();
+        fonts.push(utf16_until_null(ffn.get(FFN_NAME..).unwrap_or_default()));
+        pos += 1 + size as usize;
+    }
+    fonts
+}
+
+/// Styles by istd, from the STSH; empty slots are None
+fn parse_style_sheet(stsh: &[u8], fonts: &[String]) -> Vec<Option<Style>> {
+    let Some(stshi_size) = u16_at(stsh, 0).map(usize::from) else {
+        return Vec::new();
+    };
+    let count = u16_at(stsh, 2).unwrap_or(0) as usize;
+    let base_size = u16_at(stsh, 4).unwrap_or(10) as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut pos = 2 + stshi_size;
+    for _ in 0..count {
+        let Some(size) = u16_at(stsh, pos).map(usize::from) else {
+            break;
+        };
+        let std = stsh.get(pos + 2..pos + 2 + size).unwrap_or_default();
+        entries.push(parse_style(std, base_size, fonts));
+        pos += 2 + size;
+    }
+
+    // Styles name their parent by istd
+    let ids: Vec<Option<String>> = entries.iter().map(|entry| entry.as_ref().map(|(style, _)| style.id.clone())).collect();
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(istd, entry)| {
+            entry.map(|(mut style, base)| {
+                style.based_on = ids.get(base as usize).cloned().flatten();
+                style.is_default = istd == ISTD_NORMAL as usize || istd == ISTD_DEFAULT_PARAGRAPH_FONT as usize;
+                style
+            })
+        })
+        .collect()
+}
+
+/// A style and the istd of its parent from an STD
+fn parse_style(std: &[u8], base_size: usize, fonts: &[String]) -> Option<(Style, u16)> {
+    let kind = u16_at(std, 2)?;
+    let style_type = match kind & 0x000F {
+        1 => "paragraph",
+        2 => "character",
+        3 => "table",
+        4 => "numbering",
+        _ => return None,
+    };
+    let upx_count = (u16_at(std, 4)? & 0x000F) as usize;
+    let name_length = u16_at(std, base_size)? as usize;
+    let name = utf16_until_null(std.get(base_size + 2..base_size + 2 + name_length * 2)?);
+
+    let mut style = Style {
+        id: style_id(&name),
+        name: Some(name),
+        style_type: style_type.to_string(),
+        is_default: false,
+        ..Default::default()
+    };
+
+    // The UPXs follow the null-terminated name, each starting on an even offset
+    let mut pos = base_size + 2 + (name_length + 1) * 2;
+    let mut upxs = Vec::with_capacity(upx_count);
+    for _ in 0..upx_count {
+        pos += pos % 2;
+        let Some(size) = u16_at(std, pos).map(usize::from) else {
+            break;
+        };
+        upxs.push(std.get(pos + 2..pos + 2 + size).unwrap_or_default());
+        pos += 2 + size;
+    }
+    let character_upx = match style_type {
+        "paragraph" => {
+            if let Some(papx) = upxs.first() {
+                let mut format = ParagraphFormat::default();
+                apply_paragraph_sprms(&mut format, papx.get(2..).unwrap_or_default());
+                style.paragraph_properties = format.properties;
+            }
+            upxs.get(1)
+        }
+        "character" => upxs.first(),
+        _ => None,
+    };
+    if let Some(chpx) = character_upx {
+        let mut format = CharacterFormat::default();
+        apply_character_sprms(&mut format, chpx, fonts);
+        style.run_properties = format.properties;
+    }
+    Some((style, (kind >> 4) & 0x0FFF))
+}
+
+/// The style ID Word gives a style named `name` in a .docx: "heading 1" is "Heading1"
+fn style_id(name: &str) -> String {
+    name.split_whitespace()
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        })
+        .collect()
+}
+
+/// An inline picture's image data and size
+struct Picture {
+    bytes: Vec<u8>,
+    extension: &'static str,
+    width_twips: u32,
+    height_twips: u32,
+    /// Scale in percent
+    scale_x: f32,
+    scale_y: f32,
+}
+
+/// Read the picture at `offset` of the Data stream: a PICF header, then the
+/// shape and the BLIP store entry holding the image
+fn read_picture(data: &[u8], offset: usize) -> Option<Picture> {
+    let size = u32_at(data, offset)? as usize;
+    let header_size = u16_at(data, offset + 4)? as usize;
+    let end = offset.checked_add(size)?.min(data.len());
+    let mut pos = offset + header_size;
+    if u16_at(data, offset + 6)? == MM_SHAPE_FILE {
+        pos += 1 + u8_at(data, pos)? as usize;
+    }
+
+    while pos + 8 <= end {
+        let record_type = u16_at(data, pos + 2)?;
+        let record_size = u32_at(data, pos + 4)? as usize;
+        let body = pos + 8;
+        let blip = match record_type {
+            RECORD_SP_CONTAINER => None,
+            // An FBSE's fixed fields end with the length of its name, then the BLIP
+            RECORD_BSE => read_blip(data, body + 36 + u8_at(data, body + 33)? as usize),
+            _ => read_blip(data, pos),
+        };
+        if let Some((bytes, extension)) = blip {
+            return Some(Picture {
+                bytes,
+                extension,
+                width_twips: i16_at(data, offset + 28)?.max(0) as u32,
+                height_twips: i16_at(data, offset + 30)?.max(0) as u32,
+                scale_x: u16_at(data, offset + 32)? as f32 / 10.0,
+                scale_y: u16_at(data, offset + 34)? as f32 / 10.0,
+            });
+        }
+        pos = body.checked_add(record_size)?;
+    }
+    None
+}
+
+/// The image in the BLIP record at `pos`, with its file extension
+fn read_blip(data: &[u8], pos: usize) -> Option<(Vec<u8>, &'static str)> {
+    let instance = u16_at(data, pos)? >> 4;
+    let record_type = u16_at(data, pos + 2)?;
+    let record_size = u32_at(data, pos + 4)? as usize;
+    let record = data.get(pos + 8..pos + 8 + record_size)?;
+    // An odd instance carries a second 16-byte UID
+    let uids = if instance & 1 == 1 { 32 } else { 16 };
+
+    let (extension, header) = match record_type {
+        RECORD_BLIP_JPEG | RECORD_BLIP_JPEG_CMYK => ("jpeg", uids + 1),
+        RECORD_BLIP_PNG => ("png", uids + 1),
+        RECORD_BLIP_DIB => ("bmp", uids + 1),
+        RECORD_BLIP_TIFF => ("tiff", uids + 1),
+        RECORD_BLIP_EMF | RECORD_BLIP_WMF | RECORD_BLIP_PICT => {
+            // The metafile header ends with its compression: 0xFE is none, 0 is deflate
+            let header = uids + 34;
+            if u8_at(record, header - 2)? != 0xFE {
+                log::warn!("Skipping a compressed metafile picture in a .doc");
+                return None;
+            }
+            let extension = match record_type {
+                RECORD_BLIP_EMF => "emf",
+                RECORD_BLIP_WMF => "wmf",
+                _ => "pict",
+            };
+            (extension, header)
+        }
+        _ => return None,
+    };
+    let image = record.get(header..)?;
+    Some(if extension == "bmp" { (bitmap_file(image)?, extension) } else { (image.to_vec(), extension) })
+}
+
+/// A .bmp file of a device-independent bitmap, which lacks the file header
+fn bitmap_file(dib: &[u8]) -> Option<Vec<u8>> {
+    let info_size = u32_at(dib, 0)?;
+    let bit_count = u16_at(dib, 14)?;
+    let used_colors = u32_at(dib, 32).unwrap_or(0);
+    let palette = if used_colors != 0 {
+        used_colors
+    } else if bit_count <= 8 {
+        1 << bit_count
+    } else {
+        0
+    };
+    let pixels = info_size.saturating_add(palette.saturating_mul(4)).saturating_add(14);
+
+    let mut file = Vec::with_capacity(14 + dib.len());
+    file.extend_from_slice(b"BM");
+    file.extend_from_slice(&(14 + dib.len() as u32).to_le_bytes());
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&pixels.to_le_bytes());
+    file.extend_from_slice(dib);
+    Some(file)
+}
+
+/// Title, author and dates from the SummaryInformation property set
+fn parse_summary_information(stream: &[u8]) -> Option<CoreProperties> {
+    const PID_CODEPAGE: u32 = 1;
+    const PID_TITLE: u32 = 2;
+    const PID_SUBJECT: u32 = 3;
+    const PID_AUTHOR: u32 = 4;
+    const PID_KEYWORDS: u32 = 5;
+    const PID_COMMENTS: u32 = 6;
+    const PID_LAST_AUTHOR: u32 = 8;
+    const PID_CREATED: u32 = 12;
+    const PID_SAVED: u32 = 13;
+    const VT_I2: u32 = 2;
+    const VT_LPSTR: u32 = 30;
+    const VT_FILETIME: u32 = 64;
+
+    // The first section's offset follows the 28-byte header and its FMTID
+    let section = u32_at(stream, 44)? as usize;
+    let count = u32_at(stream, section + 4)? as usize;
+    let mut codepage = 1252;
+    let mut values = HashMap::new();
+    for index in 0..count.min(256) {
+        let pid = u32_at(stream, section + 8 + index * 8)?;
+        let property = section + u32_at(stream, section + 12 + index * 8)? as usize;
+        match u32_at(stream, property)? {
+            VT_I2 if pid == PID_CODEPAGE => codepage = u16_at(stream, property + 4)?,
+            VT_LPSTR => {
+                let length = u32_at(stream, property + 4)? as usize;
+                let bytes = stream.get(property + 8..property + 8 + length)?;
+                let bytes = bytes.split(|&byte| byte == 0).next().unwrap_or_default();
+                let value = if codepage == 65001 {
+                    String::from_utf8_lossy(bytes).into_owned()
+                } else {
+                    bytes.iter().map(|&byte| char::from_u32(cp1252_char(byte)).unwrap_or('?')).collect()
+                };
+                values.insert(pid, value);
+            }
+            VT_FILETIME => {
+                // 100ns intervals since 1601
+                let ticks = u32_at(stream, property + 4)? as u64 | (u32_at(stream, property + 8)? as u64) << 32;
+                let seconds = (ticks / 10_000_000) as i64 - 11_644_473_600;
+                if let Some(time) = chrono::DateTime::from_timestamp(seconds, 0) {
+                    values.insert(pid, time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut take = |pid| values.remove(&pid).filter(|value: &String| !value.is_empty());
+    Some(CoreProperties {
+        title: take(PID_TITLE),
+        subject: take(PID_SUBJECT),
+        creator: take(PID_AUTHOR),
+        keywords: take(PID_KEYWORDS),
+        description: take(PID_COMMENTS),
+        last_modified_by: take(PID_LAST_AUTHOR),
+        created: take(PID_CREATED),
+        modified: take(PID_SAVED),
+    })
+}
+
+/// Paragraphs being assembled from the text, one run per CHPX
+#[derive(Default)]
+struct BodyBuilder {
+    paragraphs: Vec<Paragraph>,
+    paragraph: Paragraph,
+    run_text: String,
+    run_format: Option<RunProperties>,
+    /// For each open field, whether its separator has been passed
+    fields: Vec<bool>,
+}
+
+impl BodyBuilder {
+    fn push(&mut self, ch: char, format: Option<&CharacterFormat>) {
+        let properties = format.map(|format| &format.properties);
+        if !self.run_text.is_empty() && self.run_format.as_ref() != properties {
+            self.end_run();
+        }
+        if self.run_text.is_empty() {
+            self.run_format = properties.cloned();
+        }
+        self.run_text.push(ch);
+    }
+
+    fn end_run(&mut self) {
+        if self.run_text.is_empty() {
+            return;
+        }
+        let text = std::mem::take(&mut self.run_text);
+        self.paragraph.text.push_str(&text);
+        self.paragraph.runs.push(Run { text, properties: self.run_format.take().unwrap_or_default() });
+    }
+
+    fn end_paragraph(&mut self, format: Option<&ParagraphFormat>, styles: &[Option<Style>]) {
+        self.end_run();
+        let mut paragraph = std::mem::take(&mut self.paragraph);
+        if let Some(format) = format {
+            // A row-end mark has no text of its own
+            if format.row_end && paragraph.text.is_empty() {
+                return;
+            }
+            paragraph.properties = format.properties.clone();
+            if format.istd != ISTD_NORMAL {
+                paragraph.properties.style_id =
+                    styles.get(format.istd as usize).cloned().flatten().map(|style| style.id);
+            }
+        }
+        self.paragraphs.push(paragraph);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::ops::Range;
+
+    /// Where the test documents keep their text in WordDocument
+    const TEXT_FC: usize = 1024;
+    const CHPX_PAGE: usize = 4;
+    const PAPX_PAGE: usize = 5;
+
+    /// Formatting of a byte range of the text
+    struct Formatted {
+        range: Range<usize>,
+        istd: u16,
+        grpprl: Vec<u8>,
+    }
+
+    fn sprm(sprm: u16, operand: &[u8]) -> Vec<u8> {
+        let mut bytes = sprm.to_le_bytes().to_vec();
+        bytes.extend_from_slice(operand);
+        bytes
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    /// An FKP page of `runs`, each property set stored by `store` from the page's end
+    fn fkp(runs: &[Formatted], entry_size: usize, store: impl Fn(&Formatted) -> Vec<u8>) -> Vec<u8> {
+        let mut page = vec![0u8; FKP_SIZE];
+        let count = runs.len();
+        for (index, run) in runs.iter().enumerate() {
+            page[index * 4..index * 4 + 4].copy_from_slice(&((TEXT_FC + run.range.start) as u32).to_le_bytes());
+        }
+        let end = (TEXT_FC + runs.last().unwrap().range.end) as u32;
+        page[count * 4..count * 4 + 4].copy_from_slice(&end.to_le_bytes());
+
+        let mut free = FKP_SIZE - 1;
+        for (index, run) in runs.iter().enumerate() {
+            let stored = store(run);
+            free = (free - stored.len()) & !1;
+            page[free..free + stored.len()].copy_from_slice(&stored);
+            page[(count + 1) * 4 + index * entry_size] = (free / 2) as u8;
+        }
+        page[FKP_SIZE - 1] = count as u8;
+        page
+    }
+
+    fn bin_table(length: usize, page: usize) -> Vec<u8> {
+        [TEXT_FC as u32, (TEXT_FC + length) as u32, page as u32].iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn font_table(names: &[&str]) -> Vec<u8> {
+        let mut sttb = (names.len() as u16).to_le_bytes().to_vec();
+        sttb.extend_from_slice(&[0, 0]);
+        for name in names {
+            let mut ffn = vec![0u8; 39];
+            ffn.extend(utf16(name));
+            ffn.extend_from_slice(&[0, 0]);
+            sttb.push(ffn.len() as u8);
+            sttb.extend(ffn);
+        }
+        sttb
+    }
+
+    /// A style sheet of Normal and a bold "heading 1" based on it
+    fn style_sheet() -> Vec<u8> {
+        let mut stsh = 18u16.to_le_bytes().to_vec();
+        let mut stshi = vec![0u8; 18];
+        stshi[0..2].copy_from_slice(&2u16.to_le_bytes());
+        stshi[2..4].copy_from_slice(&10u16.to_le_bytes());
+        stsh.extend(stshi);
+
+        for (name, base, chpx) in [("Normal", 0x0FFFu16, Vec::new()), ("heading 1", 0, sprm(SPRM_C_F_BOLD, &[1]))] {
+            let mut std = vec![0u8; 10];
+            std[2..4].copy_from_slice(&(1 | base << 4).to_le_bytes());
+            std[4..6].copy_from_slice(&2u16.to_le_bytes());
+            std.extend((name.len() as u16).to_le_bytes());
+            std.extend(utf16(name));
+            std.extend_from_slice(&[0, 0]);
+            for upx in [ISTD_NORMAL.to_le_bytes().to_vec(), chpx] {
+                std.extend((upx.len() as u16).to_le_bytes());
+                std.extend(&upx);
+                if upx.len() % 2 == 1 {
+                    std.push(0);
+                }
+            }
+            stsh.extend((std.len() as u16).to_le_bytes());
+            stsh.extend(std);
+        }
+        stsh
+    }
+
+    /// A compound file with one-byte-per-char `text` formatted by `chpx` and `papx`
+    fn build_doc(text: &[u8], chpx: &[Formatted], papx: &[Formatted], data: &[u8], summary: Option<Vec<u8>>) -> Vec<u8> {
+        let mut table = Vec::new();
+        let mut fc_lcb = vec![(0u32, 0u32); 93];
+        let mut place = |table: &mut Vec<u8>, index: usize, bytes: Vec<u8>| {
+            fc_lcb[index] = (table.len() as u32, bytes.len() as u32);
+            table.extend(bytes);
+        };
+
+        let mut clx = vec![0x02];
+        clx.extend(16u32.to_le_bytes());
+        clx.extend(0u32.to_le_bytes());
+        clx.extend((text.len() as u32).to_le_bytes());
+        clx.extend_from_slice(&[0, 0]);
+        clx.extend(((TEXT_FC as u32 * 2) | 0x4000_0000).to_le_bytes());
+        clx.extend_from_slice(&[0, 0]);
+        place(&mut table, FC_CLX, clx);
+        place(&mut table, FC_PLCF_BTE_CHPX, bin_table(text.len(), CHPX_PAGE));
+        place(&mut table, FC_PLCF_BTE_PAPX, bin_table(text.len(), PAPX_PAGE));
+        place(&mut table, FC_STTBF_FFN, font_table(&["Times New Roman", "Arial"]));
+        place(&mut table, FC_STSHF, style_sheet());
+
+        let mut word = vec![0u8; (PAPX_PAGE + 1) * FKP_SIZE];
+        word[0..2].copy_from_slice(&FIB_IDENT.to_le_bytes());
+        word[2..4].copy_from_slice(&0x00C1u16.to_le_bytes());
+        word[0x0A..0x0C].copy_from_slice(&FIB_TABLE_ONE.to_le_bytes());
+        word[32..34].copy_from_slice(&14u16.to_le_bytes());
+        let rg_lw = 34 + 28;
+        word[rg_lw..rg_lw + 2].copy_from_slice(&22u16.to_le_bytes());
+        word[rg_lw + 14..rg_lw + 18].copy_from_slice(&(text.len() as u32).to_le_bytes());
+        let rg_fc_lcb = rg_lw + 2 + 88;
+        word[rg_fc_lcb..rg_fc_lcb + 2].copy_from_slice(&93u16.to_le_bytes());
+        for (index, (fc, lcb)) in fc_lcb.iter().enumerate() {
+            let offset = rg_fc_lcb + 2 + index * 8;
+            word[offset..offset + 4].copy_from_slice(&fc.to_le_bytes());
+            word[offset + 4..offset + 8].copy_from_slice(&lcb.to_le_bytes());
+        }
+        word[TEXT_FC..TEXT_FC + text.len()].copy_from_slice(text);
+        let chpx_page = fkp(chpx, 1, |run| {
+            let mut stored = vec![run.grpprl.len() as u8];
+            stored.extend(&run.grpprl);
+            stored
+        });
+        let papx_page = fkp(papx, 13, |run| {
+            let mut stored = run.istd.to_le_bytes().to_vec();
+            stored.extend(&run.grpprl);
+            if stored.len() % 2 == 1 {
+                stored.insert(0, stored.len().div_ceil(2) as u8);
+            } else {
+                stored.splice(0..0, [0, (stored.len() / 2) as u8]);
+            }
+            stored
+        });
+        word[CHPX_PAGE * FKP_SIZE..(CHPX_PAGE + 1) * FKP_SIZE].copy_from_slice(&chpx_page);
+        word[PAPX_PAGE * FKP_SIZE..(PAPX_PAGE + 1) * FKP_SIZE].copy_from_slice(&papx_page);
+
+        let mut compound = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        let mut streams = vec![("WordDocument", word), ("1Table", table), ("Data", data.to_vec())];
+        if let Some(summary) = summary {
+            streams.push(("\u{5}SummaryInformation", summary));
+        }
+        for (name, bytes) in streams {
+            compound.create_stream(name).unwrap().write_all(&bytes).unwrap();
+        }
+        compound.flush().unwrap();
+        compound.into_inner().into_inner()
+    }
+
+    fn plain(range: Range<usize>) -> Formatted {
+        Formatted { range, istd: ISTD_NORMAL, grpprl: Vec::new() }
+    }
+
+    #[test]
+    fn test_text_and_formatting() {
+        let text = b"Title\rHello \x93bold\x94 text\r\x13 PAGE \x141\x15 of 2\r";
+        let chpx = [
+            plain(0..12),
+            Formatted {
+                range: 12..18,
+                istd: 0,
+                grpprl: [sprm(SPRM_C_F_BOLD, &[1]), sprm(SPRM_C_HPS, &28u16.to_le_bytes()), sprm(SPRM_C_RG_FTC0, &1u16.to_le_bytes())].concat(),
+            },
+            Formatted { range: 18..24, istd: 0, grpprl: sprm(SPRM_C_CV, &[0x12, 0x34, 0x56, 0]) },
+            plain(24..text.len()),
+        ];
+        let papx = [
+            Formatted { range: 0..6, istd: 1, grpprl: Vec::new() },
+            Formatted {
+                range: 6..24,
+                istd: 0,
+                grpprl: [sprm(SPRM_P_JC80, &[1]), sprm(SPRM_P_DXA_LEFT80, &720i16.to_le_bytes())].concat(),
+            },
+            plain(24..text.len()),
+        ];
+        let file = build_doc(text, &chpx, &papx, &[], None);
+        assert!(is_legacy_doc(&file));
+
+        let legacy = parse_doc(&file).unwrap();
+        assert_eq!(legacy.document.text, "Title\nHello \u{201C}bold\u{201D} text\n1 of 2");
+        assert_eq!(legacy.document.paragraph_count, 3);
+
+        let paragraphs = &legacy.word_document.paragraphs;
+        assert_eq!(paragraphs[0].properties.style_id.as_deref(), Some("Heading1"));
+        assert_eq!(paragraphs[1].properties.alignment.as_deref(), Some("center"));
+        assert_eq!(paragraphs[1].properties.indent_left, Some(720));
+        let runs: Vec<&str> = paragraphs[1].runs.iter().map(|run| run.text.as_str()).collect();
+        assert_eq!(runs, ["Hello ", "\u{201C}bold\u{201D}", " text"]);
+        let bold = &paragraphs[1].runs[1].properties;
+        assert_eq!((bold.bold, bold.font_size, bold.font_name.as_deref()), (Some(true), Some(28), Some("Arial")));
+        assert_eq!(paragraphs[1].runs[2].properties.color.as_deref(), Some("123456"));
+
+        let heading = &legacy.document.styles["Heading1"];
+        assert_eq!(heading.based_on.as_deref(), Some("Normal"));
+        assert_eq!(heading.run_properties.bold, Some(true));
+        assert!(legacy.document.styles["Normal"].is_default);
+    }
+
+    #[test]
+    fn test_inline_picture() {
+        let png = b"\x89PNG\r\n\x1a\nfake image";
+        let mut blip = 0x6E0u16.wrapping_shl(4).to_le_bytes().to_vec();
+        blip.extend(RECORD_BLIP_PNG.to_le_bytes());
+        blip.extend(((16 + 1 + png.len()) as u32).to_le_bytes());
+        blip.extend([0u8; 17]);
+        blip.extend(png);
+        let mut bse = vec![0x02, 0x00];
+        bse.extend(RECORD_BSE.to_le_bytes());
+        bse.extend(((36 + blip.len()) as u32).to_le_bytes());
+        bse.extend([0u8; 36]);
+        bse.extend(blip);
+
+        let mut data = vec![0u8; 8];
+        let mut picf = vec![0u8; 0x44];
+        picf[0..4].copy_from_slice(&((0x44 + 8 + bse.len()) as u32).to_le_bytes());
+        picf[4..6].copy_from_slice(&0x44u16.to_le_bytes());
+        picf[6..8].copy_from_slice(&0x64u16.to_le_bytes());
+        picf[28..30].copy_from_slice(&1440i16.to_le_bytes());
+        picf[30..32].copy_from_slice(&720i16.to_le_bytes());
+        picf[32..34].copy_from_slice(&500u16.to_le_bytes());
+        picf[34..36].copy_from_slice(&500u16.to_le_bytes());
+        data.extend(picf);
+        data.extend([0x0F, 0x00]);
+        data.extend(RECORD_SP_CONTAINER.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(bse);
+
+        let text = b"See \x01 here\r";
+        let picture = [sprm(SPRM_C_F_SPEC, &[1]), sprm(SPRM_C_PIC_LOCATION, &8u32.to_le_bytes())].concat();
+        let chpx = [plain(0..4), Formatted { range: 4..5, istd: 0, grpprl: picture }, plain(5..text.len())];
+        let file = build_doc(text, &chpx, &[plain(0..text.len())], &data, None);
+
+        let legacy = parse_doc(&file).unwrap();
+        assert_eq!(legacy.document.text, "See  here");
+        let image = &legacy.document.images[0];
+        assert_eq!((image.path.as_str(), image.paragraph_index, image.position), ("media/image1.png", 0, 4));
+        assert_eq!(image.original_width, Some(1440 * EMU_PER_TWIP));
+        assert_eq!(image.extent(), Some((1440 * EMU_PER_TWIP / 2, 720 * EMU_PER_TWIP / 2)));
+        assert_eq!(legacy.media["media/image1.png"], png);
+    }
+
+    #[test]
+    fn test_summary_information() {
+        let title = b"Quarterly report\0";
+        let mut summary = vec![0u8; 48];
+        summary[44..48].copy_from_slice(&48u32.to_le_bytes());
+        let mut section = Vec::new();
+        section.extend(0u32.to_le_bytes());
+        section.extend(2u32.to_le_bytes());
+        section.extend([2u32, 24, 12, 24 + 8 + 20].iter().flat_map(|v| v.to_le_bytes()));
+        section.extend(30u32.to_le_bytes());
+        section.extend((title.len() as u32).to_le_bytes());
+        section.extend(title);
+        section.resize(24 + 8 + 20, 0);
+        // 2024-01-02T03:04:05Z
+        let ticks = (1_704_164_645u64 + 11_644_473_600) * 10_000_000;
+        section.extend(64u32.to_le_bytes());
+        section.extend(ticks.to_le_bytes());
+        summary.extend(section);
+
+        let file = build_doc(b"Body\r", &[plain(0..5)], &[plain(0..5)], &[], Some(summary));
+        let document = parse_doc(&file).unwrap().document;
+        assert_eq!(document.title.as_deref(), Some("Quarterly report"));
+        assert_eq!(document.created_at.as_deref(), Some("2024-01-02T03:04:05Z"));
+        assert_eq!(document.author, None);
+    }
+
+    #[test]
+    fn test_rejected_files() {
+        assert!(matches!(parse_doc(b"not a compound file"), Err(LegacyDocError::Container(_))));
+
+        let file = build_doc(b"Body\r", &[plain(0..5)], &[plain(0..5)], &[], None);
+        let mut compound = CompoundFile::open(Cursor::new(&file)).unwrap();
+        let mut word = Vec::new();
+        compound.open_stream("WordDocument").unwrap().read_to_end(&mut word).unwrap();
+        drop(compound);
+
+        let rewrite = |patch: &dyn Fn(&mut Vec<u8>)| {
+            let mut patched = word.clone();
+            patch(&mut patched);
+            let mut compound = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+            compound.create_stream("WordDocument").unwrap().write_all(&patched).unwrap();
+            compound.flush().unwrap();
+            parse_doc(&compound.into_inner().into_inner())
+        };
+        assert_eq!(rewrite(&|word| word[2] = 0x68).unwrap_err(), LegacyDocError::UnsupportedVersion(0x68));
+        assert_eq!(rewrite(&|word| word[0x0B] |= 0x01).unwrap_err(), LegacyDocError::Encrypted);
+        assert_eq!(rewrite(&|_| {}).unwrap_err(), LegacyDocError::MissingStream("1Table".to_string()));
+        assert_eq!(rewrite(&|word| word.truncate(40)).unwrap_err(), LegacyDocError::Truncated("FIB"));
+    }
+}
@@ -0,0 +1,357 @@
+//! Size limits for opening untrusted or oversized documents
+//!
+//! Hosts on low-memory devices set [`DocumentLimits`] and open documents with
+//! [`parse_ooxml_with_limits`]. Each limit is checked where the data first
+//! appears: part sizes before a ZIP entry is decompressed, XML nesting before
+//! document.xml is parsed, characters and images once the body is. Under
+//! [`LimitPolicy::Fail`] the first exceeded limit is an
+//! [`OoxmlError::LimitExceeded`]; under [`LimitPolicy::Truncate`] the document
+//! is cut down to fit and every cut is reported as a [`LimitViolation`].
+
+use std::fmt;
+use std::io::Cursor;
+
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use super::document::WordDocument;
+use super::error::OoxmlError;
+use super::opc::OpcPackage;
+use super::streaming::{Event, PullParser};
+use super::{parsed_document, ParsedDocument};
+
+const MAIN_PART: &str = "/word/document.xml";
+
+/// What to do when a document exceeds a limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitPolicy {
+    /// Refuse to open the document
+    #[default]
+    Fail,
+    /// Open as much as fits and report what was cut
+    Truncate,
+}
+
+/// Upper bounds on what opening a document may allocate; None is unbounded
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentLimits {
+    /// Chars of body text, paragraph breaks included
+    #[serde(default)]
+    pub max_chars: Option<usize>,
+    /// Images in the body
+    #[serde(default)]
+    pub max_images: Option<usize>,
+    /// Element nesting depth of document.xml
+    #[serde(default)]
+    pub max_nesting_depth: Option<usize>,
+    /// Uncompressed bytes of any one package part
+    #[serde(default)]
+    pub max_part_size: Option<u64>,
+    #[serde(default)]
+    pub policy: LimitPolicy,
+}
+
+/// Which limit was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitKind {
+    Characters,
+    Images,
+    NestingDepth,
+    PartSize,
+}
+
+/// A limit a document exceeded: the error under [`LimitPolicy::Fail`], a
+/// warning about what was cut under [`LimitPolicy::Truncate`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitViolation {
+    pub kind: LimitKind,
+    pub limit: u64,
+    /// The document's value, or at least this much when it was not read to the end
+    pub found: u64,
+    /// The part concerned, for part sizes
+    #[serde(default)]
+    pub part: Option<String>,
+}
+
+impl fmt::Display for LimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            LimitKind::Characters => "characters",
+            LimitKind::Images => "images",
+            LimitKind::NestingDepth => "levels of XML nesting",
+            LimitKind::PartSize => "bytes",
+        };
+        match &self.part {
+            Some(part) => write!(f, "{} has {} {}, over the limit of {}", part, self.found, what, self.limit),
+            None => write!(f, "Document has {} {}, over the limit of {}", self.found, what, self.limit),
+        }
+    }
+}
+
+impl DocumentLimits {
+    /// Fail with `violation`, or record it as a warning when truncating
+    fn exceeded(&self, violation: LimitViolation, warnings: &mut Vec<LimitViolation>) -> Result<(), OoxmlError> {
+        match self.policy {
+            LimitPolicy::Fail => Err(OoxmlError::LimitExceeded(violation)),
+            LimitPolicy::Truncate => {
+                log::warn!("Truncating document: {}", violation);
+                warnings.push(violation);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parse a .docx within `limits`
+///
+/// Under [`LimitPolicy::Truncate`], oversized XML parts are cut at the size
+/// limit, dropping the elements left incomplete, while oversized binary parts
+/// are left out; elements nested deeper than allowed are dropped; the body
+/// ends with the last paragraph that fits in the char limit; images past the
+/// image limit are dropped. The cuts are in the result's `limit_warnings`.
+pub fn parse_ooxml_with_limits(file_data: &[u8], limits: &DocumentLimits) -> Result<ParsedDocument, OoxmlError> {
+    let timer = crate::metrics::Timer::start();
+    let mut warnings = Vec::new();
+    let mut package = read_package(file_data, limits, &mut warnings)?;
+
+    if let Some(max_depth) = limits.max_nesting_depth {
+        if let Some(part) = package.parts.get_mut(MAIN_PART) {
+            let (pruned, depth) = prune_nesting(&part.data, max_depth)?;
+            if depth > max_depth {
+                limits.exceeded(
+                    LimitViolation { kind: LimitKind::NestingDepth, limit: max_depth as u64, found: depth as u64, part: None },
+                    &mut warnings,
+                )?;
+                part.data = pruned;
+            }
+        }
+    }
+
+    let mut word_doc = WordDocument::parse(&package)?;
+    if let Some(max_chars) = limits.max_chars {
+        let chars = word_doc.text.chars().count();
+        if chars > max_chars {
+            limits.exceeded(
+                LimitViolation { kind: LimitKind::Characters, limit: max_chars as u64, found: chars as u64, part: None },
+                &mut warnings,
+            )?;
+            truncate_body(&mut word_doc, max_chars);
+        }
+    }
+    if let Some(max_images) = limits.max_images {
+        if word_doc.images.len() > max_images {
+            limits.exceeded(
+                LimitViolation {
+                    kind: LimitKind::Images,
+                    limit: max_images as u64,
+                    found: word_doc.images.len() as u64,
+                    part: None,
+                },
+                &mut warnings,
+            )?;
+            word_doc.images.truncate(max_images);
+        }
+    }
+
+    let mut document = parsed_document(&package, word_doc);
+    document.limit_warnings = warnings;
+
+    timer.record(crate::metrics::DOCUMENT_OPEN_MS);
+    crate::metrics::counter(crate::metrics::DOCUMENTS_OPENED, 1);
+    Ok(document)
+}
+
+/// Read the package, checking each part's uncompressed size before reading it
+fn read_package(
+    file_data: &[u8],
+    limits: &DocumentLimits,
+    warnings: &mut Vec<LimitViolation>,
+) -> Result<OpcPackage, OoxmlError> {
+    let Some(max_size) = limits.max_part_size else {
+        return OpcPackage::new(file_data);
+    };
+
+    let mut archive = ZipArchive::new(Cursor::new(file_data))?;
+    let mut oversized = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+        if entry.size() > max_size {
+            oversized.push((format!("/{}", entry.name().trim_start_matches('/')), entry.size()));
+        }
+    }
+    for (part, size) in &oversized {
+        let violation =
+            LimitViolation { kind: LimitKind::PartSize, limit: max_size, found: *size, part: Some(part.clone()) };
+        // The content types and relationships hold the package together and cannot be cut
+        if part.ends_with(".rels") || part == "/[Content_Types].xml" {
+            return Err(OoxmlError::LimitExceeded(violation));
+        }
+        limits.exceeded(violation, warnings)?;
+    }
+
+    let mut package = OpcPackage::read_parts(&mut archive, |name| !oversized.iter().any(|(part, _)| part == name))?;
+    // What fits of an oversized XML part still parses; a cut binary part is useless
+    for (name, _) in oversized.iter().filter(|(name, _)| name.ends_with(".xml")) {
+        if let Some(part) = package.read_part_prefix(&mut archive, name, max_size)? {
+            package.parts.insert(name.clone(), part);
+        }
+    }
+    Ok(package)
+}
+
+/// Drop the elements of `xml` nested deeper than `max_depth`, returning the
+/// pruned XML and the deepest nesting found
+fn prune_nesting(xml: &[u8], max_depth: usize) -> Result<(Vec<u8>, usize), OoxmlError> {
+    let mut parser = PullParser::new(xml);
+    let mut pruned = Vec::with_capacity(xml.len());
+    let mut depth = 0usize;
+    let mut deepest = 0usize;
+
+    while let Some(event) = parser.next_event()? {
+        let inside = match event {
+            Event::Start { empty, .. } => {
+                let inside = depth < max_depth;
+                if !empty {
+                    depth += 1;
+                }
+                deepest = deepest.max(depth + usize::from(empty));
+                inside
+            }
+            Event::End { .. } => {
+                depth = depth.saturating_sub(1);
+                depth < max_depth
+            }
+            Event::Other => depth <= max_depth,
+        };
+        if inside {
+            pruned.extend_from_slice(parser.raw());
+        }
+    }
+    Ok((pruned, deepest))
+}
+
+/// Keep the body paragraphs that fit in `max_chars`, with the tables, images
+/// and sections among them
+fn truncate_body(word_doc: &mut WordDocument, max_chars: usize) {
+    let mut chars = 0usize;
+    let mut kept = 0usize;
+    for (index, paragraph) in word_doc.paragraphs.iter().enumerate() {
+        let length = paragraph.text.chars().count() + usize::from(index > 0);
+        if chars + length > max_chars {
+            break;
+        }
+        chars += length;
+        kept += 1;
+    }
+
+    word_doc.paragraphs.truncate(kept);
+    word_doc.tables.retain(|table| table.paragraph_index <= kept);
+    word_doc.images.retain(|image| image.paragraph_index < kept);
+    word_doc.sections.retain(|section| section.first_paragraph < kept.max(1));
+    if let Some(last) = word_doc.sections.last_mut() {
+        last.paragraph_count = kept - last.first_paragraph.min(kept);
+    }
+    word_doc.text = word_doc
+        .paragraphs
+        .iter()
+        .map(|p| p.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::minimal_docx;
+    use zip::CompressionMethod;
+
+    fn build_docx(body: &str, media: &[(&str, usize)]) -> Vec<u8> {
+        let rels = r#"<Relationships><Relationship Id="rIdImg" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/image1.png"/></Relationships>"#;
+        let media: Vec<(&str, String)> = media.iter().map(|&(name, size)| (name, "\0".repeat(size))).collect();
+        let mut extra_parts = vec![("word/_rels/document.xml.rels", rels)];
+        extra_parts.extend(media.iter().map(|(name, data)| (*name, data.as_str())));
+        minimal_docx(body, &extra_parts, CompressionMethod::Deflated)
+    }
+
+    fn paragraphs(count: usize) -> String {
+        (0..count).map(|i| format!("<w:p><w:r><w:t>Paragraph {}</w:t></w:r></w:p>", i)).collect()
+    }
+
+    fn image_paragraph() -> &'static str {
+        r#"<w:p><w:r><w:drawing><wp:inline><wp:extent cx="1" cy="1"/><a:blip r:embed="rIdImg"/></wp:inline></w:drawing></w:r></w:p>"#
+    }
+
+    #[test]
+    fn test_char_limit_fails_or_truncates_at_a_paragraph() {
+        let file = build_docx(&paragraphs(10), &[]);
+        let mut limits = DocumentLimits { max_chars: Some(40), ..Default::default() };
+
+        let error = parse_ooxml_with_limits(&file, &limits).unwrap_err();
+        assert!(matches!(
+            error,
+            OoxmlError::LimitExceeded(LimitViolation { kind: LimitKind::Characters, limit: 40, found: 119, .. })
+        ));
+
+        limits.policy = LimitPolicy::Truncate;
+        let document = parse_ooxml_with_limits(&file, &limits).unwrap();
+        assert_eq!(document.text, "Paragraph 0\nParagraph 1\nParagraph 2");
+        assert_eq!(document.sections[0].paragraph_count, 3);
+        assert_eq!(document.limit_warnings.len(), 1);
+        assert_eq!(document.limit_warnings[0].kind, LimitKind::Characters);
+    }
+
+    #[test]
+    fn test_image_and_part_size_limits() {
+        let body = format!("{}{}{}", image_paragraph(), image_paragraph(), paragraphs(1));
+        let file = build_docx(&body, &[("word/media/image1.png", 4096)]);
+        let limits = DocumentLimits {
+            max_images: Some(1),
+            max_part_size: Some(1024),
+            policy: LimitPolicy::Truncate,
+            ..Default::default()
+        };
+
+        let document = parse_ooxml_with_limits(&file, &limits).unwrap();
+        assert_eq!(document.images.len(), 1);
+        let kinds: Vec<(LimitKind, Option<&str>)> =
+            document.limit_warnings.iter().map(|w| (w.kind, w.part.as_deref())).collect();
+        assert_eq!(
+            kinds,
+            vec![(LimitKind::PartSize, Some("/word/media/image1.png")), (LimitKind::Images, None)]
+        );
+        assert!(document.text.ends_with("Paragraph 0"));
+    }
+
+    #[test]
+    fn test_oversized_document_xml_keeps_what_fits() {
+        let file = build_docx(&paragraphs(50), &[]);
+        let limits = DocumentLimits { max_part_size: Some(300), policy: LimitPolicy::Truncate, ..Default::default() };
+
+        let document = parse_ooxml_with_limits(&file, &limits).unwrap();
+        assert!(document.paragraph_count > 0 && document.paragraph_count < 50);
+        assert!(document.text.starts_with("Paragraph 0\nParagraph 1"));
+        assert_eq!(document.limit_warnings[0].part.as_deref(), Some(MAIN_PART));
+    }
+
+    #[test]
+    fn test_deep_nesting_is_pruned() {
+        let deep = format!("{}{}", "<w:sdt><w:sdtContent>".repeat(20), "</w:sdtContent></w:sdt>".repeat(20));
+        let body = format!("{}{}", paragraphs(2), deep);
+        let file = build_docx(&body, &[]);
+
+        let (pruned, depth) = prune_nesting(format!("<a>{}</a>", deep).as_bytes(), 5).unwrap();
+        assert_eq!(depth, 41);
+        assert!(std::str::from_utf8(&pruned).unwrap().matches("<w:sdt>").count() == 2);
+
+        let strict = DocumentLimits { max_nesting_depth: Some(10), ..Default::default() };
+        assert!(matches!(
+            parse_ooxml_with_limits(&file, &strict),
+            Err(OoxmlError::LimitExceeded(LimitViolation { kind: LimitKind::NestingDepth, found: 42, .. }))
+        ));
+        let lenient = DocumentLimits { policy: LimitPolicy::Truncate, ..strict };
+        let document = parse_ooxml_with_limits(&file, &lenient).unwrap();
+        assert_eq!(document.text, "Paragraph 0\nParagraph 1");
+    }
+}
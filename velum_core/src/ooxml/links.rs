@@ -0,0 +1,524 @@
+//! Link Audit
+//! Checks hyperlinks and cross-references for integrity: internal links and
+//! REF/PAGEREF fields must point at existing bookmarks, relationships must resolve
+//! to parts in the package, and external URLs can be ping-tested through a fetcher
+//! supplied by the host (the core never does network I/O itself).
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::document::unescape_xml_text;
+use super::error::OoxmlError;
+use super::opc::OpcPackage;
+use super::types::{Relationship, RelationshipType};
+
+/// Host-provided URL checker used to ping-test external links
+pub trait LinkFetcher {
+    /// Request `url` and return the HTTP status code, or a description of the failure
+    fn fetch_status(&mut self, url: &str) -> Result<u16, String>;
+}
+
+impl<F: FnMut(&str) -> Result<u16, String>> LinkFetcher for F {
+    fn fetch_status(&mut self, url: &str) -> Result<u16, String> {
+        self(url)
+    }
+}
+
+/// Kind of link that was checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkKind {
+    /// `w:hyperlink` with an external relationship target
+    ExternalHyperlink,
+    /// `w:hyperlink` with a `w:anchor` bookmark target
+    InternalHyperlink,
+    /// REF / PAGEREF / NOTEREF field, or HYPERLINK field with `\l`
+    CrossReference,
+    /// Relationship of the main document part
+    Relationship,
+}
+
+/// What is wrong with a link
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LinkIssue {
+    /// The bookmark the link points to does not exist
+    MissingBookmark(String),
+    /// The relationship ID is not declared in document.xml.rels
+    MissingRelationship(String),
+    /// The relationship target is not a part in the package
+    BrokenTarget(String),
+    /// The external URL could not be reached or returned an error status
+    Unreachable { status: Option<u16>, reason: String },
+}
+
+/// Suggested repair for a finding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FixAction {
+    /// Point the link at a heading; `bookmark` is None when one must be created
+    RebindToHeading {
+        paragraph: usize,
+        heading: String,
+        bookmark: Option<String>,
+    },
+    /// Keep the text but remove the link
+    RemoveLink,
+    /// Drop the dangling relationship
+    RemoveRelationship(String),
+}
+
+/// A broken or suspicious link
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkFinding {
+    pub kind: LinkKind,
+    /// Paragraph index in the document body (None for relationship-level findings)
+    pub paragraph: Option<usize>,
+    /// Display text of the link
+    pub text: String,
+    /// Bookmark name, relationship ID, or URL the link points to
+    pub target: String,
+    pub issue: LinkIssue,
+    pub fixes: Vec<FixAction>,
+}
+
+/// Result of a link audit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LinkAuditReport {
+    /// Number of links and cross-references examined
+    pub links_checked: usize,
+    /// External URLs that were ping-tested
+    pub urls_checked: usize,
+    pub findings: Vec<LinkFinding>,
+}
+
+impl LinkAuditReport {
+    /// Check whether every link is intact
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// A heading paragraph that links can be rebound to
+struct Heading {
+    paragraph: usize,
+    text: String,
+    bookmark: Option<String>,
+}
+
+/// A link found in the body, before it is checked
+struct LinkRef {
+    kind: LinkKind,
+    paragraph: usize,
+    text: String,
+    target: String,
+}
+
+/// Audit hyperlinks, cross-references and relationships of a .docx package
+///
+/// External URLs are only ping-tested when a fetcher is given; each distinct URL
+/// is fetched once.
+pub fn audit_links(
+    file_data: &[u8],
+    fetcher: Option<&mut dyn LinkFetcher>,
+) -> Result<LinkAuditReport, OoxmlError> {
+    let package = OpcPackage::new(file_data)?;
+    let document_xml = package
+        .get_part("/word/document.xml")
+        .map(|part| String::from_utf8_lossy(&part.data).into_owned())
+        .ok_or_else(|| OoxmlError::MissingRequiredPart("/word/document.xml".to_string()))?;
+    let relationships: Vec<Relationship> = package
+        .get_relationships("/word/document.xml")
+        .cloned()
+        .unwrap_or_default();
+
+    let (links, bookmarks, headings) = scan_body(&document_xml);
+    let rels_by_id: HashMap<&str, &Relationship> =
+        relationships.iter().map(|rel| (rel.id.as_str(), rel)).collect();
+
+    let mut report = LinkAuditReport {
+        links_checked: links.len(),
+        ..Default::default()
+    };
+    let mut external: Vec<(LinkRef, String)> = Vec::new();
+
+    for link in links {
+        match link.kind {
+            LinkKind::ExternalHyperlink => match rels_by_id.get(link.target.as_str()) {
+                None => report.findings.push(finding(
+                    &link,
+                    LinkIssue::MissingRelationship(link.target.clone()),
+                    vec![FixAction::RemoveLink],
+                )),
+                Some(rel) if is_external(rel) => {
+                    let url = rel.target.clone();
+                    external.push((link, url));
+                }
+                Some(rel) => {
+                    if resolve_part(&rel.target).is_some_and(|part| package.get_part(&part).is_none()) {
+                        report.findings.push(finding(
+                            &link,
+                            LinkIssue::BrokenTarget(rel.target.clone()),
+                            vec![FixAction::RemoveLink],
+                        ));
+                    }
+                }
+            },
+            _ => {
+                if !bookmarks.contains(&link.target) {
+                    let mut fixes: Vec<FixAction> = nearest_heading(&headings, &link)
+                        .map(|heading| FixAction::RebindToHeading {
+                            paragraph: heading.paragraph,
+                            heading: heading.text.clone(),
+                            bookmark: heading.bookmark.clone(),
+                        })
+                        .into_iter()
+                        .collect();
+                    fixes.push(FixAction::RemoveLink);
+                    report.findings.push(finding(
+                        &link,
+                        LinkIssue::MissingBookmark(link.target.clone()),
+                        fixes,
+                    ));
+                }
+            }
+        }
+    }
+
+    // Internal relationships whose target part is gone (hyperlinks were reported above)
+    for rel in &relationships {
+        if is_external(rel) || rel.relationship_type == RelationshipType::Hyperlink {
+            continue;
+        }
+        if let Some(part) = resolve_part(&rel.target) {
+            if package.get_part(&part).is_none() {
+                report.findings.push(LinkFinding {
+                    kind: LinkKind::Relationship,
+                    paragraph: None,
+                    text: String::new(),
+                    target: rel.id.clone(),
+                    issue: LinkIssue::BrokenTarget(rel.target.clone()),
+                    fixes: vec![FixAction::RemoveRelationship(rel.id.clone())],
+                });
+            }
+        }
+    }
+
+    if let Some(fetcher) = fetcher {
+        let mut results: HashMap<String, Result<u16, String>> = HashMap::new();
+        for (link, url) in &external {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                continue;
+            }
+            let result = results
+                .entry(url.clone())
+                .or_insert_with(|| fetcher.fetch_status(url))
+                .clone();
+            let issue = match result {
+                Ok(status) if status < 400 => continue,
+                Ok(status) => LinkIssue::Unreachable {
+                    status: Some(status),
+                    reason: format!("HTTP {}", status),
+                },
+                Err(reason) => LinkIssue::Unreachable { status: None, reason },
+            };
+            let mut finding = finding(link, issue, vec![FixAction::RemoveLink]);
+            finding.target = url.clone();
+            report.findings.push(finding);
+        }
+        report.urls_checked = results.len();
+    }
+
+    Ok(report)
+}
+
+fn finding(link: &LinkRef, issue: LinkIssue, fixes: Vec<FixAction>) -> LinkFinding {
+    LinkFinding {
+        kind: link.kind,
+        paragraph: Some(link.paragraph),
+        text: link.text.clone(),
+        target: link.target.clone(),
+        issue,
+        fixes,
+    }
+}
+
+fn is_external(rel: &Relationship) -> bool {
+    rel.target_mode.as_deref() == Some("External")
+        || (rel.relationship_type == RelationshipType::Hyperlink && rel.target.contains("://"))
+}
+
+/// Resolve a relationship target of word/document.xml to an absolute part name
+fn resolve_part(target: &str) -> Option<String> {
+    if target.contains("://") || target.starts_with('#') {
+        return None;
+    }
+    let path = match target.strip_prefix('/') {
+        Some(absolute) => absolute.to_string(),
+        None => format!("word/{}", target),
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+/// Prefer a heading whose text matches the link text, else the closest heading before the link
+fn nearest_heading<'a>(headings: &'a [Heading], link: &LinkRef) -> Option<&'a Heading> {
+    let text = link.text.trim();
+    if !text.is_empty() {
+        if let Some(heading) = headings.iter().find(|h| h.text.trim().eq_ignore_ascii_case(text)) {
+            return Some(heading);
+        }
+    }
+    headings
+        .iter()
+        .rev()
+        .find(|h| h.paragraph <= link.paragraph)
+        .or_else(|| headings.first())
+}
+
+/// Collect links, bookmark names and headings from document.xml
+fn scan_body(xml: &str) -> (Vec<LinkRef>, HashSet<String>, Vec<Heading>) {
+    let para_pattern = regex::Regex::new(r#"(?s)<w:p\b[^>]*>(.*?)</w:p>"#).unwrap();
+    let style_pattern = regex::Regex::new(r#"<w:pStyle\b[^>]*w:val="([^"]*)""#).unwrap();
+    let outline_pattern = regex::Regex::new(r#"<w:outlineLvl\b"#).unwrap();
+    let bookmark_pattern = regex::Regex::new(r#"<w:bookmarkStart\b[^>]*w:name="([^"]*)""#).unwrap();
+    let hyperlink_pattern = regex::Regex::new(r#"(?s)<w:hyperlink\b([^>]*)>(.*?)</w:hyperlink>"#).unwrap();
+    let rid_pattern = regex::Regex::new(r#"\br:id="([^"]*)""#).unwrap();
+    let anchor_pattern = regex::Regex::new(r#"w:anchor="([^"]*)""#).unwrap();
+    let text_pattern = regex::Regex::new(r#"<w:t(?:\s[^>]*)?>([^<]*)</w:t>"#).unwrap();
+    let instr_pattern = regex::Regex::new(
+        r#"<w:instrText[^>]*>([^<]*)</w:instrText>|<w:fldSimple\b[^>]*w:instr="([^"]*)""#,
+    ).unwrap();
+
+    let collect_text = |fragment: &str| -> String {
+        text_pattern
+            .captures_iter(fragment)
+            .map(|c| unescape_xml_text(&c[1]))
+            .collect()
+    };
+
+    let mut links = Vec::new();
+    let mut bookmarks = HashSet::new();
+    let mut headings = Vec::new();
+
+    for (paragraph, para_cap) in para_pattern.captures_iter(xml).enumerate() {
+        let para_xml = &para_cap[1];
+        let text = collect_text(para_xml);
+
+        let paragraph_bookmarks: Vec<String> = bookmark_pattern
+            .captures_iter(para_xml)
+            .map(|c| unescape_xml_text(&c[1]))
+            .collect();
+
+        let is_heading = style_pattern
+            .captures(para_xml)
+            .is_some_and(|c| c[1].to_ascii_lowercase().starts_with("heading") || &c[1] == "Title")
+            || outline_pattern.is_match(para_xml);
+        if is_heading && !text.trim().is_empty() {
+            headings.push(Heading {
+                paragraph,
+                text: text.trim().to_string(),
+                // Word's own heading bookmarks (_Toc...) are hidden; any bookmark will do
+                bookmark: paragraph_bookmarks.first().cloned(),
+            });
+        }
+        bookmarks.extend(paragraph_bookmarks);
+
+        for link_cap in hyperlink_pattern.captures_iter(para_xml) {
+            let attributes = &link_cap[1];
+            let link_text = collect_text(&link_cap[2]);
+            if let Some(anchor) = anchor_pattern.captures(attributes) {
+                links.push(LinkRef {
+                    kind: LinkKind::InternalHyperlink,
+                    paragraph,
+                    text: link_text,
+                    target: unescape_xml_text(&anchor[1]),
+                });
+            } else if let Some(rid) = rid_pattern.captures(attributes) {
+                links.push(LinkRef {
+                    kind: LinkKind::ExternalHyperlink,
+                    paragraph,
+                    text: link_text,
+                    target: rid[1].to_string(),
+                });
+            }
+        }
+
+        // Complex field instructions may be split over several instrText runs
+        let instruction: String = instr_pattern
+            .captures_iter(para_xml)
+            .map(|c| {
+                let raw = c.get(1).or_else(|| c.get(2)).map_or("", |m| m.as_str());
+                format!("{} ", unescape_xml_text(raw))
+            })
+            .collect();
+        for target in cross_reference_targets(&instruction) {
+            links.push(LinkRef {
+                kind: LinkKind::CrossReference,
+                paragraph,
+                text: text.clone(),
+                target,
+            });
+        }
+    }
+
+    (links, bookmarks, headings)
+}
+
+/// Bookmark targets of REF/PAGEREF/NOTEREF fields and HYPERLINK \l fields
+fn cross_reference_targets(instructions: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    let words: Vec<&str> = instructions.split_whitespace().collect();
+    for (index, word) in words.iter().enumerate() {
+        let keyword = word.to_ascii_uppercase();
+        match keyword.as_str() {
+            "REF" | "PAGEREF" | "NOTEREF" => {
+                if let Some(target) = words.get(index + 1) {
+                    targets.push(target.trim_matches('"').to_string());
+                }
+            }
+            "HYPERLINK" => {
+                let rest = &words[index + 1..];
+                if let Some(position) = rest.iter().position(|w| *w == "\\l") {
+                    if let Some(target) = rest.get(position + 1) {
+                        targets.push(target.trim_matches('"').to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::zip_fixture;
+
+    const CONTENT_TYPES: &[u8] = br#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+    <Default Extension="xml" ContentType="application/xml"/>
+    <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+    <Default Extension="png" ContentType="image/png"/>
+</Types>"#;
+
+    const RELS: &[u8] = br#"<Relationships>
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="https://example.com/ok" TargetMode="External"/>
+<Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="https://example.com/gone" TargetMode="External"/>
+<Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/missing.png"/>
+<Relationship Id="rId4" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/image1.png"/>
+</Relationships>"#;
+
+    const DOCUMENT: &[u8] = br#"<w:document><w:body>
+<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:bookmarkStart w:id="0" w:name="_Toc1"/><w:r><w:t>Introduction</w:t></w:r><w:bookmarkEnd w:id="0"/></w:p>
+<w:p><w:hyperlink w:anchor="_Toc1"><w:r><w:t>see intro</w:t></w:r></w:hyperlink></w:p>
+<w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>Results</w:t></w:r></w:p>
+<w:p><w:hyperlink w:anchor="_Toc99"><w:r><w:t>Results</w:t></w:r></w:hyperlink></w:p>
+<w:p><w:r><w:fldChar w:fldCharType="begin"/></w:r><w:r><w:instrText xml:space="preserve"> PAGEREF _Ref42 \h </w:instrText></w:r><w:r><w:fldChar w:fldCharType="end"/></w:r></w:p>
+<w:p><w:hyperlink r:id="rId1"><w:r><w:t>ok</w:t></w:r></w:hyperlink><w:hyperlink r:id="rId2"><w:r><w:t>gone</w:t></w:r></w:hyperlink><w:hyperlink r:id="rId9"><w:r><w:t>dangling</w:t></w:r></w:hyperlink></w:p>
+</w:body></w:document>"#;
+
+    fn build_docx() -> Vec<u8> {
+        zip_fixture([
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("word/document.xml", DOCUMENT),
+            ("word/_rels/document.xml.rels", RELS),
+            ("word/media/image1.png", b"\x89PNG".as_slice()),
+        ])
+    }
+
+    #[test]
+    fn test_missing_bookmarks_suggest_headings() {
+        let report = audit_links(&build_docx(), None).unwrap();
+        assert_eq!(report.links_checked, 6);
+
+        let anchor = report
+            .findings
+            .iter()
+            .find(|f| f.target == "_Toc99")
+            .unwrap();
+        assert_eq!(anchor.kind, LinkKind::InternalHyperlink);
+        // Rebinds to the heading with the same text, which has no bookmark yet
+        assert_eq!(
+            anchor.fixes[0],
+            FixAction::RebindToHeading {
+                paragraph: 2,
+                heading: "Results".to_string(),
+                bookmark: None,
+            }
+        );
+
+        let pageref = report.findings.iter().find(|f| f.target == "_Ref42").unwrap();
+        assert_eq!(pageref.kind, LinkKind::CrossReference);
+        assert_eq!(pageref.issue, LinkIssue::MissingBookmark("_Ref42".to_string()));
+        // Nearest heading before paragraph 4
+        assert!(matches!(&pageref.fixes[0], FixAction::RebindToHeading { paragraph: 2, .. }));
+    }
+
+    #[test]
+    fn test_relationship_findings() {
+        let report = audit_links(&build_docx(), None).unwrap();
+
+        let dangling = report.findings.iter().find(|f| f.target == "rId9").unwrap();
+        assert_eq!(dangling.issue, LinkIssue::MissingRelationship("rId9".to_string()));
+
+        let image = report.findings.iter().find(|f| f.target == "rId3").unwrap();
+        assert_eq!(image.kind, LinkKind::Relationship);
+        assert_eq!(image.fixes, vec![FixAction::RemoveRelationship("rId3".to_string())]);
+        assert!(!report.findings.iter().any(|f| f.target == "rId4"));
+
+        // Without a fetcher external links are not tested
+        assert_eq!(report.urls_checked, 0);
+        assert_eq!(report.findings.len(), 4);
+    }
+
+    #[test]
+    fn test_external_links_use_host_fetcher() {
+        let mut requested = Vec::new();
+        let mut fetcher = |url: &str| {
+            requested.push(url.to_string());
+            if url.ends_with("/gone") {
+                Ok(404)
+            } else {
+                Ok(200)
+            }
+        };
+        let report = audit_links(&build_docx(), Some(&mut fetcher)).unwrap();
+        assert_eq!(report.urls_checked, 2);
+
+        let gone = report
+            .findings
+            .iter()
+            .find(|f| f.target == "https://example.com/gone")
+            .unwrap();
+        assert_eq!(gone.text, "gone");
+        assert_eq!(
+            gone.issue,
+            LinkIssue::Unreachable {
+                status: Some(404),
+                reason: "HTTP 404".to_string()
+            }
+        );
+        assert_eq!(gone.fixes, vec![FixAction::RemoveLink]);
+        assert_eq!(requested.len(), 2);
+    }
+
+    #[test]
+    fn test_helpers() {
+        assert_eq!(resolve_part("media/a.png").as_deref(), Some("/word/media/a.png"));
+        assert_eq!(resolve_part("../customXml/item1.xml").as_deref(), Some("/customXml/item1.xml"));
+        assert_eq!(resolve_part("https://x.org"), None);
+        assert_eq!(
+            cross_reference_targets(r#"HYPERLINK \l "Intro" REF _Ref1 \h"#),
+            vec!["Intro".to_string(), "_Ref1".to_string()]
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Parallel document open with progress reporting and cancellation
+//!
+//! [`parse_ooxml_parallel`] reads the XML parts of a package first, then parses
+//! the body, styles, numbering and the other supporting parts on threads of
+//! their own while the remaining (media and other binary) parts are
+//! decompressed by a few more. Each finished step is reported to the
+//! [`LoadControl`], which can also cancel the load. [`parse_ooxml_async`] runs
+//! the whole load on a background thread and hands back a [`LoadJob`].
+
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{JoinHandle, ScopedJoinHandle};
+
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use super::control::OperationControl;
+use super::document::WordDocument;
+use super::error::OoxmlError;
+use super::opc::OpcPackage;
+use super::types::PackagePart;
+use super::{parsed_document, ParsedDocument};
+
+/// Upper bound on the threads decompressing binary parts
+const MAX_MEDIA_THREADS: usize = 4;
+
+/// A unit of work of a document load
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum LoadStep {
+    /// The archive directory, content types, relationships and XML parts
+    Package,
+    /// word/document.xml
+    Body,
+    Styles,
+    Numbering,
+    Theme,
+    CoreProperties,
+    HeadersFooters,
+    Notes,
+    Comments,
+    Settings,
+    /// A media or other binary part, by part name
+    Media { part: String },
+}
+
+/// Progress after a step of a load has finished
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoadProgress {
+    #[serde(flatten)]
+    pub step: LoadStep,
+    /// Steps finished so far, this one included
+    pub completed: usize,
+    /// Steps the load has in all; known once the package has been read
+    pub total: usize,
+}
+
+/// Progress and cancellation shared between a load and whoever started it
+///
+/// Progress is reported after each finished step, from any of the load's
+/// threads, one step at a time.
+pub type LoadControl = OperationControl<LoadProgress>;
+
+/// Counts finished steps across threads and reports them in order of completion
+struct Progress<'a> {
+    control: &'a LoadControl,
+    completed: AtomicUsize,
+    total: usize,
+    /// Serializes reports so `completed` only ever grows between them
+    report: Mutex<()>,
+}
+
+impl Progress<'_> {
+    fn finish(&self, step: LoadStep) -> Result<(), OoxmlError> {
+        self.control.check()?;
+        let _report = self.report.lock().unwrap();
+        let completed = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+        self.control.report(&LoadProgress { step, completed, total: self.total });
+        Ok(())
+    }
+}
+
+/// A parsed document and its package, media parts included
+#[derive(Debug, Clone)]
+pub struct LoadedDocument {
+    pub document: ParsedDocument,
+    /// Body paragraphs with their runs, styles, lists, sections and comments
+    pub word_document: WordDocument,
+    pub package: OpcPackage,
+}
+
+/// Whether a part is read up front with the XML parts
+fn is_xml_part(name: &str) -> bool {
+    name.ends_with(".xml")
+}
+
+/// Parse a .docx on several threads, reporting each finished step to `control`
+///
+/// The result is the same as [`parse_ooxml`](super::parse_ooxml)'s; fails with
+/// `OoxmlError::Cancelled` once `control` is cancelled.
+pub fn parse_ooxml_parallel(file_data: &[u8], control: &LoadControl) -> Result<LoadedDocument, OoxmlError> {
+    let timer = crate::metrics::Timer::start();
+    control.check()?;
+
+    let mut archive = ZipArchive::new(Cursor::new(file_data))?;
+    let mut package = OpcPackage::read_parts(&mut archive, is_xml_part)?;
+    let binary_parts: Vec<String> = archive
+        .file_names()
+        .map(|name| format!("/{}", name.trim_start_matches('/')))
+        .filter(|name| !is_xml_part(name) && !name.ends_with(".rels") && !name.ends_with('/'))
+        .collect();
+
+    const XML_STEPS: usize = 9;
+    let progress = Progress {
+        control,
+        completed: AtomicUsize::new(0),
+        total: 1 + XML_STEPS + binary_parts.len(),
+        report: Mutex::new(()),
+    };
+    progress.finish(LoadStep::Package)?;
+
+    let threads = binary_parts.len().clamp(1, MAX_MEDIA_THREADS);
+    let chunk_size = binary_parts.len().div_ceil(threads).max(1);
+
+    let (document, media) = std::thread::scope(|scope| {
+        let package = &package;
+        let progress = &progress;
+
+        let media: Vec<_> = binary_parts
+            .chunks(chunk_size)
+            .map(|names| {
+                scope.spawn(move || -> Result<Vec<PackagePart>, OoxmlError> {
+                    // Each thread reads through its own view of the archive
+                    let mut archive = ZipArchive::new(Cursor::new(file_data))?;
+                    let mut parts = Vec::with_capacity(names.len());
+                    for name in names {
+                        progress.control.check()?;
+                        if let Some(part) = package.read_part(&mut archive, name)? {
+                            parts.push(part);
+                        }
+                        progress.finish(LoadStep::Media { part: name.clone() })?;
+                    }
+                    Ok(parts)
+                })
+            })
+            .collect();
+
+        // Each step fills its own fields of an empty document
+        let step = |step: LoadStep, parse: fn(&mut WordDocument, &OpcPackage) -> Result<(), OoxmlError>| {
+            scope.spawn(move || -> Result<WordDocument, OoxmlError> {
+                progress.control.check()?;
+                let mut document = WordDocument::empty();
+                parse(&mut document, package)?;
+                progress.finish(step)?;
+                Ok(document)
+            })
+        };
+        let body = step(LoadStep::Body, WordDocument::parse_main_document);
+        let styles = step(LoadStep::Styles, WordDocument::parse_styles);
+        let numbering = step(LoadStep::Numbering, WordDocument::parse_numbering);
+        let theme = step(LoadStep::Theme, WordDocument::parse_theme);
+        let core = step(LoadStep::CoreProperties, WordDocument::parse_core_properties);
+        let headers = step(LoadStep::HeadersFooters, WordDocument::parse_headers_footers);
+        let notes = step(LoadStep::Notes, WordDocument::parse_footnotes_endnotes);
+        let comments = step(LoadStep::Comments, |document, package| {
+            document.parse_comments(package);
+            Ok(())
+        });
+        let settings = step(LoadStep::Settings, |document, package| {
+            document.parse_settings(package);
+            Ok(())
+        });
+
+        let join = |handle: ScopedJoinHandle<'_, Result<WordDocument, OoxmlError>>| {
+            handle.join().unwrap_or_else(|_| Err(OoxmlError::ParseError("document load thread panicked".to_string())))
+        };
+        let document = (|| {
+            let mut document = join(body)?;
+            document.styles = join(styles)?.styles;
+            document.numbering = join(numbering)?.numbering;
+            document.theme = join(theme)?.theme;
+            document.core_properties = join(core)?.core_properties;
+            let headers = join(headers)?;
+            document.headers = headers.headers;
+            document.footers = headers.footers;
+            let notes = join(notes)?;
+            document.footnotes = notes.footnotes;
+            document.endnotes = notes.endnotes;
+            document.comments = join(comments)?.comments;
+            document.protection = join(settings)?.protection;
+            Ok::<_, OoxmlError>(document)
+        })();
+
+        let mut parts = Vec::new();
+        let mut media_result = Ok(());
+        for handle in media {
+            match handle.join() {
+                Ok(Ok(chunk)) => parts.extend(chunk),
+                Ok(Err(e)) => media_result = media_result.and(Err(e)),
+                Err(_) => {
+                    media_result = media_result.and(Err(OoxmlError::ParseError("media load thread panicked".to_string())))
+                }
+            }
+        }
+        (document, media_result.map(|()| parts))
+    });
+
+    control.check()?;
+    let document = document?;
+    for part in media? {
+        package.parts.insert(part.name.clone(), part);
+    }
+    let word_document = document;
+    let document = parsed_document(&package, word_document.clone());
+
+    timer.record(crate::metrics::DOCUMENT_OPEN_MS);
+    crate::metrics::counter(crate::metrics::DOCUMENTS_OPENED, 1);
+
+    Ok(LoadedDocument { document, word_document, package })
+}
+
+/// A document load running on a background thread
+pub struct LoadJob {
+    control: LoadControl,
+    handle: JoinHandle<Result<LoadedDocument, OoxmlError>>,
+}
+
+impl LoadJob {
+    /// Ask the load to stop; [`LoadJob::wait`] then fails with `OoxmlError::Cancelled`
+    pub fn cancel(&self) {
+        self.control.cancel();
+    }
+
+    /// Whether the load has finished, successfully or not
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Block until the load has finished
+    pub fn wait(self) -> Result<LoadedDocument, OoxmlError> {
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(OoxmlError::ParseError("document load thread panicked".to_string())))
+    }
+}
+
+/// Start parsing a .docx on a background thread
+///
+/// Progress goes to `control` from the load's threads; cancel through either
+/// `control` or the returned job. The package keeps the archive, as one made
+/// by [`OpcPackage::open`] does.
+pub fn parse_ooxml_async(file_data: Vec<u8>, control: LoadControl) -> LoadJob {
+    let job_control = control.clone();
+    let file_data: Arc<[u8]> = file_data.into();
+    let handle = std::thread::spawn(move || {
+        let mut loaded = parse_ooxml_parallel(&file_data, &job_control)?;
+        loaded.package.keep_source(file_data);
+        Ok(loaded)
+    });
+    LoadJob { control, handle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::minimal_docx;
+    use std::sync::Arc;
+    use zip::CompressionMethod;
+
+    fn build_docx(paragraphs: usize, images: usize) -> Vec<u8> {
+        let body: String = (0..paragraphs)
+            .map(|i| format!("<w:p><w:r><w:t>Paragraph {}</w:t></w:r></w:p>", i))
+            .collect();
+        let styles = r#"<w:styles><w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/></w:style></w:styles>"#;
+        let media: Vec<String> = (0..images).map(|i| format!("word/media/image{}.png", i)).collect();
+        let data = "x".repeat(64);
+        let mut extra_parts = vec![("word/styles.xml", styles)];
+        extra_parts.extend(media.iter().map(|name| (name.as_str(), data.as_str())));
+        minimal_docx(&body, &extra_parts, CompressionMethod::Deflated)
+    }
+
+    #[test]
+    fn test_parallel_load_matches_sequential_parse() {
+        let file = build_docx(50, 6);
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let control = LoadControl::new().with_progress(move |p| sink.lock().unwrap().push(p.clone()));
+
+        let loaded = parse_ooxml_parallel(&file, &control).unwrap();
+        let sequential = crate::ooxml::parse_ooxml(&file).unwrap();
+        assert_eq!(loaded.document.text, sequential.text);
+        assert_eq!(loaded.document.paragraph_count, 50);
+        assert!(loaded.document.styles.contains_key("Heading1"));
+        assert_eq!(loaded.package.get_part("/word/media/image5.png").map(|p| p.data.len()), Some(64));
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1 + 9 + 6);
+        assert_eq!(reports[0].step, LoadStep::Package);
+        assert!(reports.iter().enumerate().all(|(i, p)| p.completed == i + 1 && p.total == 16));
+        assert!(reports.iter().any(|p| p.step == LoadStep::Body));
+        assert!(reports.iter().any(|p| p.step == LoadStep::Media { part: "/word/media/image3.png".to_string() }));
+    }
+
+    #[test]
+    fn test_async_load_can_be_cancelled() {
+        let file = build_docx(10, 40);
+        let control = LoadControl::new();
+        let handle = control.clone();
+        // Cancel from inside the load, once the package has been read
+        let control = control.with_progress(move |p| {
+            if p.step == LoadStep::Package {
+                handle.cancel();
+            }
+        });
+
+        let job = parse_ooxml_async(file.clone(), control.clone());
+        assert!(matches!(job.wait(), Err(OoxmlError::Cancelled)));
+        assert!(control.is_cancelled());
+
+        let job = parse_ooxml_async(file, LoadControl::new());
+        let loaded = job.wait().unwrap();
+        assert_eq!(loaded.document.paragraph_count, 10);
+    }
+}
@@ -6,7 +6,7 @@
 //!
 //! # Example
 //!
-//! ```rust
+//! ```rust,no_run
 //! use velum_core::ooxml::{parse_ooxml, ParsedDocument};
 //!
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -23,20 +23,70 @@ mod opc;
 mod document;
 mod converter;
 mod serializer;
+mod app_properties;
+mod compression;
+mod control;
+mod export;
+mod html;
+mod text;
+mod features;
+mod forms;
+mod field_preview;
+mod notes;
+mod links;
+mod lazy;
+mod loader;
+mod limits;
+mod legacy_doc;
+mod odf;
+mod whitespace;
+mod streaming;
+mod preview;
+mod parts;
+mod organizer;
+mod doc_vars;
 
 pub use error::OoxmlError;
 pub use converter::ooxml_to_piece_tree;
+pub use app_properties::{DocumentStatistics, LayoutStatistics};
+pub use compression::{PackageCompression, PartCompression};
 pub use serializer::{
     DocxSerializer,
+    ExportContent,
     ExportOptions,
     ExportFormat,
+    HtmlExport,
+    HtmlImages,
+    MacroPolicy,
     piece_tree_to_word_document,
+    snapshot_to_word_document,
 };
+pub use control::OperationControl;
+pub use export::{export_snapshot_docx, export_snapshot_docx_incremental, export_snapshot_html, export_snapshot_text, ExportControl};
+pub(crate) use html::data_uri;
+pub(crate) use serializer::{escape_xml_attr, escape_xml_text, resolve_part_name};
+pub(crate) use document::unescape_xml_text;
+pub use text::{LineEnding, NoteText, PlainTextOptions, TableText};
 pub use types::{
     ContentType,
+    MathZone,
     Paragraph,
+    ParagraphBorder,
+    ParagraphFrame,
     ParagraphProperties,
+    Field,
+    FieldKind,
+    NoteKind,
+    NoteReference,
     Relationship,
+    Revision,
+    RevisionKind,
+    Comment,
+    CommentMark,
+    CommentMarkKind,
+    BookmarkMark,
+    BookmarkMarkKind,
+    Hyperlink,
     RelationshipType,
     Run,
     RunProperties,
@@ -45,6 +95,8 @@ pub use types::{
     ThemeFonts,
     PackagePart,
     DocumentImage,
+    ImageAnchor,
+    ImageTransform,
     BlipFill,
     SourceRect,
     DocumentAnchor,
@@ -61,6 +113,9 @@ pub use types::{
     // Header/Footer types
     Header,
     Footer,
+    // Section types
+    Section,
+    HeaderFooterReference,
     // Footnote/Endnote types
     Footnote,
     Endnote,
@@ -76,6 +131,20 @@ pub use types::{
 };
 pub use opc::OpcPackage;
 pub use document::WordDocument;
+pub use field_preview::{FieldPreview, PreviewSpan, PreviewText};
+pub use lazy::{LazyDocument, LazyLoadOptions, OutlineEntry, ParagraphSpan, SectionSpan, DEFAULT_CHUNK_PARAGRAPHS};
+pub use streaming::StreamingDocument;
+pub use preview::{parse_preview, DocumentPreview};
+pub use legacy_doc::{is_legacy_doc, parse_doc, LegacyDocError, LegacyDocument};
+pub use odf::{export_odt, is_odt, parse_odt, OdfError, OdtDocument};
+pub use limits::{parse_ooxml_with_limits, DocumentLimits, LimitKind, LimitPolicy, LimitViolation};
+pub use loader::{parse_ooxml_async, parse_ooxml_parallel, LoadControl, LoadJob, LoadProgress, LoadStep, LoadedDocument};
+pub use links::{audit_links, FixAction, LinkAuditReport, LinkFetcher, LinkFinding, LinkIssue, LinkKind};
+pub use notes::{NoteIndex, NotePreview};
+pub use forms::{fill_form, form_fields, AnchoredFormField, FormError, FormField, FormFieldKind, FormFieldSet, FormFieldSource, ProtectionMode};
+pub use features::{analyze_features, DocumentFeature, FeatureReport, FeatureUsage, SupportLevel};
+pub use doc_vars::{document_variable, set_document_variable};
+pub use organizer::{import_style_parts, import_styles, list_styles, StyleConflictPolicy, StyleImportReport, StyleParts, StyleSummary};
 
 /// Serializable document structure for UI consumption
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -143,6 +212,54 @@ pub struct ParsedDocument {
     /// Numbering definitions (list styles)
     #[serde(default)]
     pub numbering: Vec<Numbering>,
+
+    /// Whether the package carries a VBA project (never executed)
+    #[serde(default)]
+    pub has_macros: bool,
+
+    /// Form fields (legacy and content controls) and document protection
+    #[serde(default)]
+    pub forms: FormFieldSet,
+
+    /// Fields with char offsets into `text`
+    #[serde(default)]
+    pub fields: Vec<Field>,
+
+    /// Footnote/endnote reference marks with char offsets into `text`
+    #[serde(default)]
+    pub note_references: Vec<NoteReference>,
+
+    /// Section properties: page geometry, columns and header/footer references
+    #[serde(default)]
+    pub sections: Vec<Section>,
+
+    /// Tracked changes with char offsets into `text`
+    #[serde(default)]
+    pub revisions: Vec<Revision>,
+
+    /// Comments, anchored by char offsets into `text`
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+
+    /// Bookmarks, with char offsets into `text`
+    #[serde(default)]
+    pub bookmarks: Vec<crate::bookmarks::Bookmark>,
+
+    /// Hyperlinks of the body, with char offsets into `text`
+    #[serde(default)]
+    pub hyperlinks: Vec<Hyperlink>,
+
+    /// Limits the document exceeded and how it was cut to fit, when opened
+    /// with [`parse_ooxml_with_limits`] under [`LimitPolicy::Truncate`]
+    #[serde(default)]
+    pub limit_warnings: Vec<LimitViolation>,
+}
+
+impl ParsedDocument {
+    /// Index for resolving note references to their footnote/endnote bodies
+    pub fn note_index(&self) -> NoteIndex<'_> {
+        NoteIndex::new(&self.note_references, &self.footnotes, &self.endnotes)
+    }
 }
 
 impl Default for ParsedDocument {
@@ -165,6 +282,16 @@ impl Default for ParsedDocument {
             footnotes: Vec::new(),
             endnotes: Vec::new(),
             numbering: Vec::new(),
+            has_macros: false,
+            forms: FormFieldSet::default(),
+            fields: Vec::new(),
+            note_references: Vec::new(),
+            sections: Vec::new(),
+            revisions: Vec::new(),
+            comments: Vec::new(),
+            bookmarks: Vec::new(),
+            hyperlinks: Vec::new(),
+            limit_warnings: Vec::new(),
         }
     }
 }
@@ -190,12 +317,23 @@ impl Default for ParsedDocument {
 /// - XML parsing fails
 /// - Content types are invalid
 pub fn parse_ooxml(file_data: &[u8]) -> Result<ParsedDocument, OoxmlError> {
+    let timer = crate::metrics::Timer::start();
+
     // Parse the OPC package
     let package = OpcPackage::new(file_data)?;
     
     // Parse the Word document
     let word_doc = WordDocument::parse(&package)?;
-    
+    let document = parsed_document(&package, word_doc);
+
+    timer.record(crate::metrics::DOCUMENT_OPEN_MS);
+    crate::metrics::counter(crate::metrics::DOCUMENTS_OPENED, 1);
+
+    Ok(document)
+}
+
+/// Gather a parsed Word document and its package into the structure the UI reads
+fn parsed_document(package: &OpcPackage, word_doc: WordDocument) -> ParsedDocument {
     // Calculate statistics
     let char_count = word_doc.text.chars().count();
     let word_count = word_doc.text.split_whitespace().count();
@@ -212,7 +350,46 @@ pub fn parse_ooxml(file_data: &[u8]) -> Result<ParsedDocument, OoxmlError> {
         (None, None, None, None)
     };
     
-    Ok(ParsedDocument {
+    let forms = package
+        .get_part("/word/document.xml")
+        .map(|part| {
+            let settings = package
+                .get_part("/word/settings.xml")
+                .map(|settings| String::from_utf8_lossy(&settings.data).into_owned());
+            FormFieldSet::parse(&String::from_utf8_lossy(&part.data), settings.as_deref())
+        })
+        .unwrap_or_default();
+
+    // Paragraph-relative field, note and revision offsets become offsets into the joined text
+    let mut fields = Vec::new();
+    let mut note_references = Vec::new();
+    let mut revisions = Vec::new();
+    let mut paragraph_start = 0usize;
+    for paragraph in &word_doc.paragraphs {
+        for field in &paragraph.fields {
+            let mut field = field.clone();
+            field.start += paragraph_start;
+            fields.push(field);
+        }
+        for reference in &paragraph.note_references {
+            let mut reference = reference.clone();
+            reference.position += paragraph_start;
+            note_references.push(reference);
+        }
+        for revision in &paragraph.revisions {
+            let mut revision = revision.clone();
+            revision.start += paragraph_start;
+            revisions.push(revision);
+        }
+        paragraph_start += paragraph.text.chars().count() + 1;
+    }
+    let located = || crate::document_model::located(&word_doc.paragraphs);
+    let mut comments = word_doc.comments;
+    crate::comments::anchor_comments(&mut comments, located());
+    let bookmarks = crate::bookmarks::BookmarkRegistry::from_paragraphs(located()).bookmarks().to_vec();
+    let hyperlinks = crate::hyperlinks::HyperlinkSet::from_paragraphs(located()).hyperlinks().to_vec();
+
+    ParsedDocument {
         text: word_doc.text,
         styles: word_doc.styles,
         paragraph_count: word_doc.paragraphs.len(),
@@ -230,7 +407,17 @@ pub fn parse_ooxml(file_data: &[u8]) -> Result<ParsedDocument, OoxmlError> {
         footnotes: word_doc.footnotes,
         endnotes: word_doc.endnotes,
         numbering: word_doc.numbering,
-    })
+        has_macros: package.has_macros(),
+        forms,
+        fields,
+        note_references,
+        sections: word_doc.sections,
+        revisions,
+        comments,
+        bookmarks,
+        hyperlinks,
+        limit_warnings: Vec::new(),
+    }
 }
 
 /// Parse OOXML document from file path
@@ -278,6 +465,7 @@ pub fn document_from_json(json: &str) -> Result<ParsedDocument, OoxmlError> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::zip_fixture;
     use std::fs;
     use std::path::PathBuf;
 
@@ -334,6 +522,16 @@ mod tests {
             footnotes: Vec::new(),
             endnotes: Vec::new(),
             numbering: Vec::new(),
+            has_macros: false,
+            forms: FormFieldSet::default(),
+            fields: Vec::new(),
+            note_references: Vec::new(),
+            sections: Vec::new(),
+            revisions: Vec::new(),
+            comments: Vec::new(),
+            bookmarks: Vec::new(),
+            hyperlinks: Vec::new(),
+            limit_warnings: Vec::new(),
         };
 
         let json = document_to_json(&doc).unwrap();
@@ -404,6 +602,8 @@ mod tests {
             ("image/webp", ContentType::ImageWebP),
             ("image/tiff", ContentType::ImageTiff),
             ("image/svg+xml", ContentType::ImageSvg),
+            ("image/x-emf", ContentType::ImageEmf),
+            ("image/x-wmf", ContentType::ImageWmf),
         ];
 
         for (input, expected) in variants {
@@ -477,6 +677,16 @@ mod tests {
             footnotes: Vec::new(),
             endnotes: Vec::new(),
             numbering: Vec::new(),
+            has_macros: false,
+            forms: FormFieldSet::default(),
+            fields: Vec::new(),
+            note_references: Vec::new(),
+            sections: Vec::new(),
+            revisions: Vec::new(),
+            comments: Vec::new(),
+            bookmarks: Vec::new(),
+            hyperlinks: Vec::new(),
+            limit_warnings: Vec::new(),
         };
 
         assert_eq!(doc.text, "Test content");
@@ -498,4 +708,107 @@ mod tests {
         let result = document_to_json(&doc);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_parse_ooxml_detects_macros() {
+        let content_types = br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+    <Default Extension="xml" ContentType="application/xml"/>
+    <Override PartName="/word/document.xml" ContentType="application/vnd.ms-word.document.macroEnabled.main+xml"/>
+    <Override PartName="/word/vbaProject.bin" ContentType="application/vnd.ms-office.vbaProject"/>
+</Types>"#;
+        let document = br#"<w:document><w:body><w:p><w:r><w:t>Macro doc</w:t></w:r></w:p></w:body></w:document>"#;
+
+        let data = zip_fixture([
+            ("[Content_Types].xml", content_types.as_slice()),
+            ("word/document.xml", document),
+            ("word/vbaProject.bin", &[0xD0, 0xCF, 0x11, 0xE0]),
+        ]);
+
+        let parsed = parse_ooxml(&data).unwrap();
+        assert!(parsed.has_macros);
+        assert!(parsed.text.contains("Macro doc"));
+    }
+
+    #[test]
+    fn test_parse_ooxml_note_preview() {
+        let content_types = br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+    <Default Extension="xml" ContentType="application/xml"/>
+</Types>"#;
+        let document = br#"<w:document><w:body>
+<w:p><w:r><w:t>Intro</w:t></w:r></w:p>
+<w:p><w:r><w:t>Claim</w:t></w:r><w:r><w:rPr><w:rStyle w:val="FootnoteReference"/></w:rPr><w:footnoteReference w:id="1"/></w:r><w:r><w:t> continues</w:t></w:r></w:p>
+</w:body></w:document>"#;
+        let footnotes = br#"<w:footnotes>
+<w:footnote w:type="separator" w:id="-1"><w:p><w:r><w:separator/></w:r></w:p></w:footnote>
+<w:footnote w:id="1"><w:p><w:r><w:footnoteRef/></w:r><w:r><w:t xml:space="preserve"> Source: </w:t></w:r><w:r><w:rPr><w:i w:val="1"/></w:rPr><w:t>Annual Report</w:t></w:r></w:p></w:footnote>
+</w:footnotes>"#;
+
+        let data = zip_fixture([
+            ("[Content_Types].xml", content_types.as_slice()),
+            ("word/document.xml", document),
+            ("word/footnotes.xml", footnotes),
+        ]);
+
+        let parsed = parse_ooxml(&data).unwrap();
+        assert_eq!(parsed.note_references.len(), 1);
+        let position = parsed.note_references[0].position;
+        assert_eq!(position, "Intro\nClaim".chars().count());
+
+        let index = parsed.note_index();
+        let preview = index.preview_at(position, 100).unwrap();
+        assert_eq!(preview.number, 1);
+        assert_eq!(preview.text, "Source: Annual Report");
+        assert_eq!(preview.runs[1].properties.italic, Some(true));
+        assert_eq!(index.references_to(NoteKind::Footnote, "1").len(), 1);
+    }
+
+    #[test]
+    fn test_parse_ooxml_comments() {
+        let content_types = br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+    <Default Extension="xml" ContentType="application/xml"/>
+</Types>"#;
+        let document = br#"<w:document><w:body>
+<w:p><w:r><w:t>Intro</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">The </w:t></w:r><w:commentRangeStart w:id="3"/><w:r><w:t>claim</w:t></w:r><w:commentRangeEnd w:id="3"/><w:r><w:rPr><w:rStyle w:val="CommentReference"/></w:rPr><w:commentReference w:id="3"/></w:r><w:r><w:t xml:space="preserve"> holds</w:t></w:r><w:r><w:commentReference w:id="5"/></w:r></w:p>
+</w:body></w:document>"#;
+        let comments = br#"<w:comments>
+<w:comment w:id="3" w:author="Ann" w:date="2024-05-01T10:00:00Z" w:initials="A"><w:p w14:paraId="1A2B3C4D" w14:textId="77777777" w:rsidR="00AB"><w:r><w:annotationRef/></w:r><w:r><w:t>Source?</w:t></w:r></w:p></w:comment>
+<w:comment w:id="4" w:author="Bob"><w:p w14:paraId="2B3C4D5E"><w:r><w:annotationRef/></w:r><w:r><w:t>Added</w:t></w:r></w:p></w:comment>
+<w:comment w:id="5" w:author="Bob"><w:p w14:paraId="3C4D5E6F"><w:r><w:t>Point</w:t></w:r></w:p></w:comment>
+</w:comments>"#;
+        let extended = br#"<w15:commentsEx>
+<w15:commentEx w15:paraId="1A2B3C4D" w15:done="1"/>
+<w15:commentEx w15:paraId="2B3C4D5E" w15:paraIdParent="1A2B3C4D" w15:done="0"/>
+</w15:commentsEx>"#;
+
+        let data = zip_fixture([
+            ("[Content_Types].xml", content_types.as_slice()),
+            ("word/document.xml", document),
+            ("word/comments.xml", comments),
+            ("word/commentsExtended.xml", extended),
+        ]);
+
+        let parsed = parse_ooxml(&data).unwrap();
+        let read: Vec<(&str, Option<&str>, bool, usize, usize)> = parsed
+            .comments
+            .iter()
+            .map(|c| (c.id.as_str(), c.parent_id.as_deref(), c.done, c.start, c.length))
+            .collect();
+        let claim = "Intro\nThe ".chars().count();
+        assert_eq!(
+            read,
+            vec![
+                ("3", None, true, claim, 5),
+                // A reply without marks of its own shares its thread's anchor
+                ("4", Some("3"), false, claim, 5),
+                // Only a reference mark: anchored at it
+                ("5", None, false, claim + 11, 0),
+            ]
+        );
+        assert_eq!(parsed.comments[0].text(), "Source?");
+        assert_eq!(parsed.comments[0].initials.as_deref(), Some("A"));
+    }
 }
@@ -0,0 +1,256 @@
+//! Note Previews
+//! Hover-card content for footnote/endnote references and the reverse lookup
+//! from a note back to every reference mark that points to it.
+
+use serde::{Deserialize, Serialize};
+
+use super::types::{Endnote, Footnote, NoteKind, NoteReference, Paragraph, Run};
+
+/// Flattened, styled content of a note for display next to its reference
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotePreview {
+    pub kind: NoteKind,
+    pub id: String,
+    /// Display number (1-based, in order of first reference)
+    pub number: usize,
+    /// Runs of the note body; paragraphs are separated by "\n" runs
+    pub runs: Vec<Run>,
+    /// Plain text of `runs`
+    pub text: String,
+    /// Whether the content was cut at the length limit
+    pub truncated: bool,
+}
+
+/// Resolves references to note bodies
+pub struct NoteIndex<'a> {
+    references: &'a [NoteReference],
+    footnotes: &'a [Footnote],
+    endnotes: &'a [Endnote],
+}
+
+impl<'a> NoteIndex<'a> {
+    /// Create an index over document-level references and the note parts
+    pub fn new(references: &'a [NoteReference], footnotes: &'a [Footnote], endnotes: &'a [Endnote]) -> Self {
+        NoteIndex {
+            references,
+            footnotes,
+            endnotes,
+        }
+    }
+
+    /// Reference mark at a char position, if any
+    pub fn reference_at(&self, position: usize) -> Option<&'a NoteReference> {
+        self.references.iter().find(|r| r.position == position)
+    }
+
+    /// All references pointing to a note, in document order
+    pub fn references_to(&self, kind: NoteKind, id: &str) -> Vec<&'a NoteReference> {
+        self.references
+            .iter()
+            .filter(|r| r.kind == kind && r.id == id)
+            .collect()
+    }
+
+    /// Display number of a note: its rank among distinct notes of the same kind
+    pub fn number_of(&self, kind: NoteKind, id: &str) -> Option<usize> {
+        let mut seen: Vec<&str> = Vec::new();
+        for reference in self.references.iter().filter(|r| r.kind == kind) {
+            if !seen.contains(&reference.id.as_str()) {
+                seen.push(&reference.id);
+            }
+            if reference.id == id {
+                return Some(seen.len());
+            }
+        }
+        None
+    }
+
+    /// Preview of the note referenced at `position`, cut to `max_chars` characters
+    pub fn preview_at(&self, position: usize, max_chars: usize) -> Option<NotePreview> {
+        let reference = self.reference_at(position)?;
+        self.preview(reference.kind, &reference.id, max_chars)
+    }
+
+    /// Preview of a note by kind and ID, cut to `max_chars` characters
+    pub fn preview(&self, kind: NoteKind, id: &str, max_chars: usize) -> Option<NotePreview> {
+        let paragraphs = match kind {
+            NoteKind::Footnote => &self.footnotes.iter().find(|n| n.id == id)?.paragraphs,
+            NoteKind::Endnote => &self.endnotes.iter().find(|n| n.id == id)?.paragraphs,
+        };
+
+        let (runs, truncated) = flatten(paragraphs, max_chars);
+        Some(NotePreview {
+            kind,
+            id: id.to_string(),
+            number: self.number_of(kind, id).unwrap_or(0),
+            text: runs.iter().map(|r| r.text.as_str()).collect(),
+            runs,
+            truncated,
+        })
+    }
+}
+
+/// Join note paragraphs into one run list, keeping at most `max_chars` characters
+fn flatten(paragraphs: &[Paragraph], max_chars: usize) -> (Vec<Run>, bool) {
+    let mut runs: Vec<Run> = Vec::new();
+    let mut remaining = max_chars;
+
+    let texts = paragraphs.iter().filter(|p| !p.runs.is_empty());
+    for (index, paragraph) in texts.enumerate() {
+        if index > 0 {
+            if remaining == 0 {
+                return (runs, true);
+            }
+            runs.push(Run {
+                text: "\n".to_string(),
+                ..Default::default()
+            });
+            remaining -= 1;
+        }
+
+        for run in &paragraph.runs {
+            // The body starts with the note number mark followed by a space
+            let text = if runs.is_empty() { run.text.trim_start() } else { run.text.as_str() };
+            if text.is_empty() {
+                continue;
+            }
+
+            let length = text.chars().count();
+            if length > remaining {
+                let cut: String = text.chars().take(remaining).collect();
+                if !cut.is_empty() {
+                    runs.push(Run {
+                        text: cut,
+                        properties: run.properties.clone(),
+                    });
+                }
+                return (runs, true);
+            }
+            runs.push(Run {
+                text: text.to_string(),
+                properties: run.properties.clone(),
+            });
+            remaining -= length;
+        }
+    }
+
+    (runs, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::RunProperties;
+
+    fn paragraph(runs: &[(&str, bool)]) -> Paragraph {
+        Paragraph {
+            text: runs.iter().map(|(t, _)| *t).collect(),
+            runs: runs
+                .iter()
+                .map(|(text, bold)| Run {
+                    text: text.to_string(),
+                    properties: RunProperties {
+                        bold: bold.then_some(true),
+                        ..Default::default()
+                    },
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn reference(kind: NoteKind, id: &str, position: usize) -> NoteReference {
+        NoteReference {
+            kind,
+            id: id.to_string(),
+            position,
+        }
+    }
+
+    fn fixture() -> (Vec<NoteReference>, Vec<Footnote>, Vec<Endnote>) {
+        let references = vec![
+            reference(NoteKind::Footnote, "2", 10),
+            reference(NoteKind::Endnote, "1", 20),
+            reference(NoteKind::Footnote, "3", 30),
+            reference(NoteKind::Footnote, "2", 40),
+        ];
+        let footnotes = vec![
+            Footnote {
+                id: "2".to_string(),
+                footnote_type: Some("normal".to_string()),
+                paragraphs: vec![
+                    paragraph(&[(" See ", false), ("Smith", true), (" 2019.", false)]),
+                    paragraph(&[("Second paragraph.", false)]),
+                ],
+            },
+            Footnote {
+                id: "3".to_string(),
+                footnote_type: Some("normal".to_string()),
+                paragraphs: vec![paragraph(&[("Third.", false)])],
+            },
+        ];
+        let endnotes = vec![Endnote {
+            id: "1".to_string(),
+            endnote_type: Some("normal".to_string()),
+            paragraphs: vec![paragraph(&[("End.", false)])],
+        }];
+        (references, footnotes, endnotes)
+    }
+
+    #[test]
+    fn test_preview_at_reference() {
+        let (references, footnotes, endnotes) = fixture();
+        let index = NoteIndex::new(&references, &footnotes, &endnotes);
+
+        let preview = index.preview_at(10, 200).unwrap();
+        assert_eq!(preview.kind, NoteKind::Footnote);
+        assert_eq!(preview.number, 1);
+        assert_eq!(preview.text, "See Smith 2019.\nSecond paragraph.");
+        assert_eq!(preview.runs[1].properties.bold, Some(true));
+        assert!(!preview.truncated);
+
+        assert!(index.preview_at(11, 200).is_none());
+    }
+
+    #[test]
+    fn test_preview_length_limit() {
+        let (references, footnotes, endnotes) = fixture();
+        let index = NoteIndex::new(&references, &footnotes, &endnotes);
+
+        let preview = index.preview_at(10, 7).unwrap();
+        assert_eq!(preview.text, "See Smi");
+        assert!(preview.truncated);
+        assert_eq!(preview.runs.len(), 2);
+
+        // Exactly the first paragraph: the separator is what gets cut
+        let preview = index.preview_at(10, 15).unwrap();
+        assert_eq!(preview.text, "See Smith 2019.");
+        assert!(preview.truncated);
+    }
+
+    #[test]
+    fn test_numbering_by_kind() {
+        let (references, footnotes, endnotes) = fixture();
+        let index = NoteIndex::new(&references, &footnotes, &endnotes);
+
+        assert_eq!(index.preview_at(30, 100).unwrap().number, 2);
+        assert_eq!(index.preview_at(40, 100).unwrap().number, 1);
+        let endnote = index.preview_at(20, 100).unwrap();
+        assert_eq!(endnote.number, 1);
+        assert_eq!(endnote.text, "End.");
+    }
+
+    #[test]
+    fn test_reverse_lookup() {
+        let (references, footnotes, endnotes) = fixture();
+        let index = NoteIndex::new(&references, &footnotes, &endnotes);
+
+        let positions: Vec<usize> = index
+            .references_to(NoteKind::Footnote, "2")
+            .iter()
+            .map(|r| r.position)
+            .collect();
+        assert_eq!(positions, vec![10, 40]);
+        assert!(index.references_to(NoteKind::Endnote, "2").is_empty());
+    }
+}
@@ -0,0 +1,2370 @@
+//! OpenDocument Text (.odt) import and export
+//!
+//! An .odt is a zip package whose first entry, stored uncompressed, is its
+//! mimetype. content.xml holds the body and the automatic styles its
+//! paragraphs, spans and cells are formatted with, styles.xml the named styles
+//! and the page layout, meta.xml the title and author, and Pictures/ the images.
+//!
+//! [`parse_odt`] reads an .odt into the same [`WordDocument`] a .docx is
+//! parsed into: headings, paragraphs, lists, tables with their spanned cells,
+//! spans, links, images, footnotes and endnotes. An automatic style is direct
+//! formatting over the named style it is based on. Sections and indexes keep
+//! their paragraphs and fields their text; comments, tracked changes, text
+//! boxes, headers and footers are not read.
+//!
+//! [`export_odt`] writes a document model back out as an .odt for LibreOffice
+//! and other ODF applications. Text deleted in tracked changes is left out.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io::{Cursor, Read, Write};
+use std::ops::Range;
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use super::document::{CoreProperties, WordDocument};
+use super::lazy::heading_level;
+use super::opc::OpcPackage;
+use super::serializer::{escape_xml_attr, escape_xml_text};
+use super::types::{
+    AbstractNumDef, DocumentImage, Endnote, Footnote, Hyperlink, ListLevel, NoteKind, NoteReference, NumInstance,
+    Numbering, Paragraph, ParagraphBorder, ParagraphProperties, RevisionKind, Run, RunProperties, Section, Style,
+    Table, TableCell, TableCellProperties, TableRow, TableRowProperties,
+};
+use super::{parsed_document, ParsedDocument};
+use crate::document_model::{Block, DocumentModel};
+use crate::html_import::decode_entities;
+use crate::image::{ImageCache, ImageFormat};
+
+/// Mimetype of an OpenDocument text document
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.text";
+
+/// ODF version written
+const ODF_VERSION: &str = "1.3";
+
+/// Namespaces of the elements and attributes written to content.xml and styles.xml
+const NAMESPACES: &str = concat!(
+    r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" "#,
+    r#"xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" "#,
+    r#"xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" "#,
+    r#"xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" "#,
+    r#"xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0" "#,
+    r#"xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" "#,
+    r#"xmlns:svg="urn:oasis:names:tc:opendocument:xmlns:svg-compatible:1.0" "#,
+    r#"xmlns:xlink="http://www.w3.org/1999/xlink""#,
+);
+
+/// Line break within a paragraph
+const LINE_BREAK: char = '\u{2028}';
+
+const EMU_PER_TWIP: u32 = 635;
+
+/// Links of parent styles followed before giving up on a cycle
+const MAX_STYLE_DEPTH: usize = 16;
+
+/// Deepest list level, from 0
+const MAX_LIST_LEVEL: u8 = 8;
+
+/// Most times a repeated row, cell or column is read
+const MAX_REPEAT: usize = 256;
+
+/// Block elements whose content is not part of the text flow
+const SKIPPED_BLOCKS: [&str; 4] = ["draw:frame", "office:annotation", "office:forms", "text:tracked-changes"];
+
+/// Errors reading an .odt or writing one
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum OdfError {
+    #[error("Package error: {0}")]
+    Package(String),
+
+    #[error("Not an OpenDocument text document ({0})")]
+    NotText(String),
+
+    #[error("Missing part: {0}")]
+    MissingPart(String),
+}
+
+impl From<zip::result::ZipError> for OdfError {
+    fn from(error: zip::result::ZipError) -> Self {
+        OdfError::Package(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for OdfError {
+    fn from(error: std::io::Error) -> Self {
+        OdfError::Package(error.to_string())
+    }
+}
+
+/// An .odt read into the model a .docx is parsed into
+#[derive(Debug, Clone)]
+pub struct OdtDocument {
+    /// Body paragraphs and tables with their styles, lists, images, notes and properties
+    pub word_document: WordDocument,
+    /// The structure the UI reads, as [`parse_ooxml`](super::parse_ooxml) returns for a .docx
+    pub document: ParsedDocument,
+    /// Embedded images, keyed by the `path` of their [`DocumentImage`]
+    pub media: HashMap<String, Vec<u8>>,
+}
+
+/// Whether `file_data` is an OpenDocument text package: a zip whose first
+/// entry is the stored mimetype
+pub fn is_odt(file_data: &[u8]) -> bool {
+    // The first local file header is 30 bytes, then the name and the data
+    file_data.starts_with(b"PK\x03\x04")
+        && file_data.get(30..38) == Some(b"mimetype".as_slice())
+        && file_data.get(38..).is_some_and(|rest| rest.starts_with(MIMETYPE.as_bytes()))
+}
+
+/// Read an OpenDocument text document
+pub fn parse_odt(file_data: &[u8]) -> Result<OdtDocument, OdfError> {
+    let timer = crate::metrics::Timer::start();
+
+    let mut archive = ZipArchive::new(Cursor::new(file_data))?;
+    if let Some(mimetype) = read_entry(&mut archive, "mimetype") {
+        let mimetype = String::from_utf8_lossy(&mimetype).trim().to_string();
+        if mimetype != MIMETYPE {
+            return Err(OdfError::NotText(mimetype));
+        }
+    }
+    let content = read_entry(&mut archive, "content.xml").ok_or_else(|| OdfError::MissingPart("content.xml".to_string()))?;
+    let content = parse_xml(&String::from_utf8_lossy(&content));
+    let styles = read_entry(&mut archive, "styles.xml")
+        .map(|data| parse_xml(&String::from_utf8_lossy(&data)))
+        .unwrap_or_default();
+    let meta = read_entry(&mut archive, "meta.xml")
+        .map(|data| parse_xml(&String::from_utf8_lossy(&data)))
+        .unwrap_or_default();
+    let content = content.child("office:document-content").unwrap_or(&content);
+    let styles = styles.child("office:document-styles").unwrap_or(&styles);
+
+    let mut reader = OdtReader::default();
+    reader.read_styles(styles, false);
+    reader.read_styles(content, true);
+    let mut body = Flow {
+        body: true,
+        ..Default::default()
+    };
+    if let Some(text) = content.child("office:body").and_then(|body| body.child("office:text")) {
+        reader.blocks(text, &mut body);
+    }
+
+    let mut word_document = WordDocument::empty();
+    word_document.text = body.paragraphs.iter().map(|p| p.text.as_str()).collect::<Vec<_>>().join("\n");
+    word_document.sections = page_section(styles, body.paragraphs.len()).into_iter().collect();
+    word_document.paragraphs = body.paragraphs;
+    word_document.tables = body.tables;
+    word_document.styles = reader.named_styles();
+    if !reader.numbering.num_instances.is_empty() {
+        word_document.numbering = vec![std::mem::take(&mut reader.numbering)];
+    }
+    word_document.footnotes = reader.footnotes;
+    word_document.endnotes = reader.endnotes;
+    word_document.core_properties = meta
+        .child("office:document-meta")
+        .and_then(|meta| meta.child("office:meta"))
+        .map(core_properties);
+
+    let mut media = HashMap::new();
+    for image in reader.images.iter().filter(|image| !image.is_linked) {
+        if let Some(data) = read_entry(&mut archive, &image.path) {
+            media.insert(image.path.clone(), data);
+        }
+    }
+    word_document.images = reader.images;
+
+    let document = parsed_document(&OpcPackage::default(), word_document.clone());
+
+    timer.record(crate::metrics::DOCUMENT_OPEN_MS);
+    crate::metrics::counter(crate::metrics::DOCUMENTS_OPENED, 1);
+
+    Ok(OdtDocument { word_document, document, media })
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Option<Vec<u8>> {
+    let mut entry = archive.by_name(name).ok()?;
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+// ============================================
+// XML
+// ============================================
+
+/// An XML element with its children
+#[derive(Debug, Default)]
+struct Element {
+    /// Qualified name, e.g. "text:p"
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.name == name)
+    }
+
+    /// Text of the element and everything in it
+    fn text(&self) -> String {
+        self.children
+            .iter()
+            .map(|node| match node {
+                Node::Element(element) => element.text(),
+                Node::Text(text) => text.clone(),
+            })
+            .collect()
+    }
+
+    /// Times a row, cell or column is repeated, by the attribute `name`
+    fn repeat(&self, name: &str) -> usize {
+        self.attribute(name).and_then(|count| count.parse().ok()).unwrap_or(1).clamp(1, MAX_REPEAT)
+    }
+}
+
+/// Parse XML into a tree under a nameless root; malformed markup is read as far as it makes sense
+fn parse_xml(xml: &str) -> Element {
+    let mut stack = vec![Element::default()];
+    let mut rest = xml;
+    while let Some(open) = rest.find('<') {
+        push_text(&mut stack, decode_entities(&rest[..open]));
+        rest = &rest[open..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            push_text(&mut stack, cdata[..end].to_string());
+            rest = cdata.get(end + 3..).unwrap_or("");
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            // An end tag closes what is open inside its element too
+            if let Some(depth) = stack.iter().rposition(|element| element.name == name.trim()).filter(|&depth| depth > 0) {
+                while stack.len() > depth {
+                    close_element(&mut stack);
+                }
+            }
+            continue;
+        }
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let element = Element {
+            name: tag[..name_end].to_string(),
+            attributes: parse_attributes(&tag[name_end..]),
+            children: Vec::new(),
+        };
+        if self_closing {
+            stack.last_mut().expect("the root is never closed").children.push(Node::Element(element));
+        } else {
+            stack.push(element);
+        }
+    }
+    push_text(&mut stack, decode_entities(rest));
+    while stack.len() > 1 {
+        close_element(&mut stack);
+    }
+    stack.pop().expect("the root is never closed")
+}
+
+fn push_text(stack: &mut [Element], text: String) {
+    if !text.is_empty() {
+        stack.last_mut().expect("the root is never closed").children.push(Node::Text(text));
+    }
+}
+
+fn close_element(stack: &mut Vec<Element>) {
+    let element = stack.pop().expect("the root is never closed");
+    stack.last_mut().expect("the root is never closed").children.push(Node::Element(element));
+}
+
+/// Byte offset of the '>' ending the tag `tag` starts, outside attribute values
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '>') => return Some(i),
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if open == c => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_attributes(source: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = source;
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].trim().to_string();
+        let after = rest[equals + 1..].trim_start();
+        let Some(quote) = after.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+            break;
+        };
+        let value = &after[1..];
+        let end = value.find(quote).unwrap_or(value.len());
+        attributes.push((name, decode_entities(&value[..end])));
+        rest = value.get(end + 1..).unwrap_or("");
+    }
+    attributes
+}
+
+// ============================================
+// Reading
+// ============================================
+
+/// A style:style of styles.xml or content.xml
+#[derive(Debug, Clone, Default)]
+struct OdfStyle {
+    parent: Option<String>,
+    display_name: Option<String>,
+    /// "paragraph", "text", "table-cell", "table-column", ...
+    family: String,
+    /// Whether it is an automatic style, which is direct formatting
+    automatic: bool,
+    paragraph: ParagraphProperties,
+    text: RunProperties,
+    cell: TableCellProperties,
+    /// Width of a table column in twips
+    column_width: Option<i32>,
+}
+
+/// Paragraphs and tables of the body, a table cell or a note
+#[derive(Debug, Default)]
+struct Flow {
+    paragraphs: Vec<Paragraph>,
+    tables: Vec<Table>,
+    /// Whether this is the body; elsewhere tables are flattened into their
+    /// cells' paragraphs and images are not read
+    body: bool,
+}
+
+/// A paragraph being read
+struct InlineText {
+    paragraph: Paragraph,
+    /// Chars in the paragraph so far
+    length: usize,
+    /// Whether the last char was whitespace, which collapses whitespace after it
+    space: bool,
+    /// Index of the body paragraph, for images; None outside the body
+    body_index: Option<usize>,
+}
+
+impl InlineText {
+    fn push(&mut self, text: &str, properties: &RunProperties) {
+        if text.is_empty() {
+            return;
+        }
+        self.length += text.chars().count();
+        self.paragraph.text.push_str(text);
+        match self.paragraph.runs.last_mut() {
+            Some(run) if run.properties == *properties => run.text.push_str(text),
+            _ => self.paragraph.runs.push(Run {
+                text: text.to_string(),
+                properties: properties.clone(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct OdtReader {
+    /// Styles by their ODF name; content.xml's automatic styles shadow styles.xml's
+    styles: HashMap<String, OdfStyle>,
+    /// Properties of style:default-style for paragraphs
+    default_style: Option<OdfStyle>,
+    /// Font families by font face name
+    fonts: HashMap<String, String>,
+    /// Levels of each text:list-style, by name
+    list_styles: HashMap<String, Vec<ListLevel>>,
+    numbering: Numbering,
+    /// Abstract numbering ID of each list style used
+    list_definitions: HashMap<String, String>,
+    /// Last list instance of each list style, for lists continuing its numbering
+    last_lists: HashMap<String, String>,
+    images: Vec<DocumentImage>,
+    footnotes: Vec<Footnote>,
+    endnotes: Vec<Endnote>,
+}
+
+impl OdtReader {
+    /// Read the font faces, named styles, automatic styles and list styles of
+    /// the root of content.xml or styles.xml
+    fn read_styles(&mut self, root: &Element, content: bool) {
+        if let Some(fonts) = root.child("office:font-face-decls") {
+            for font in fonts.elements() {
+                if let (Some(name), Some(family)) = (font.attribute("style:name"), font.attribute("svg:font-family")) {
+                    self.fonts.insert(name.to_string(), font_family(family));
+                }
+            }
+        }
+        for (container, automatic) in [("office:styles", false), ("office:automatic-styles", true)] {
+            // styles.xml's automatic styles format its headers, footers and page layouts
+            if automatic && !content {
+                continue;
+            }
+            let Some(container) = root.child(container) else {
+                continue;
+            };
+            for element in container.elements() {
+                match element.name.as_str() {
+                    "style:style" => {
+                        if let Some(name) = element.attribute("style:name") {
+                            let style = self.read_style(element, automatic);
+                            self.styles.insert(name.to_string(), style);
+                        }
+                    }
+                    "style:default-style" if element.attribute("style:family") == Some("paragraph") => {
+                        self.default_style = Some(self.read_style(element, false));
+                    }
+                    "text:list-style" => {
+                        if let Some(name) = element.attribute("style:name") {
+                            self.list_styles.insert(name.to_string(), list_levels(element));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn read_style(&self, element: &Element, automatic: bool) -> OdfStyle {
+        let mut style = OdfStyle {
+            parent: element.attribute("style:parent-style-name").map(str::to_string),
+            display_name: element.attribute("style:display-name").map(str::to_string),
+            family: element.attribute("style:family").unwrap_or_default().to_string(),
+            automatic,
+            ..Default::default()
+        };
+        for properties in element.elements() {
+            match properties.name.as_str() {
+                "style:paragraph-properties" => style.paragraph = paragraph_properties(properties),
+                "style:text-properties" => style.text = run_properties(properties, &self.fonts),
+                "style:table-cell-properties" => {
+                    style.cell = TableCellProperties {
+                        shading_color: properties.attribute("fo:background-color").and_then(hex_color),
+                        vertical_alignment: properties.attribute("style:vertical-align").and_then(|align| match align {
+                            "top" | "bottom" => Some(align.to_string()),
+                            "middle" => Some("center".to_string()),
+                            _ => None,
+                        }),
+                        ..Default::default()
+                    }
+                }
+                "style:table-column-properties" => {
+                    style.column_width = properties.attribute("style:column-width").and_then(twips);
+                }
+                _ => {}
+            }
+        }
+        style
+    }
+
+    /// The named paragraph and text styles as Word styles
+    fn named_styles(&self) -> HashMap<String, Style> {
+        let mut styles = HashMap::new();
+        for (name, style) in &self.styles {
+            let style_type = match style.family.as_str() {
+                "paragraph" => "paragraph",
+                "text" => "character",
+                _ => continue,
+            };
+            if style.automatic {
+                continue;
+            }
+            let mut paragraph_properties = style.paragraph.clone();
+            let mut run_properties = style.text.clone();
+            let is_default = name == "Standard";
+            // The default style's properties are what the Standard style starts from
+            if let Some(default) = self.default_style.as_ref().filter(|_| is_default) {
+                paragraph_properties = overlay_paragraph(&default.paragraph, &paragraph_properties);
+                run_properties = overlay_run(&default.text, &run_properties);
+            }
+            let id = style_id(name);
+            styles.insert(
+                id.clone(),
+                Style {
+                    id,
+                    name: Some(style.display_name.clone().unwrap_or_else(|| decode_style_name(name))),
+                    style_type: style_type.to_string(),
+                    based_on: style.parent.as_deref().map(style_id),
+                    next: None,
+                    paragraph_properties,
+                    run_properties,
+                    is_default,
+                },
+            );
+        }
+        styles
+    }
+
+    /// The style ID, direct paragraph properties and direct run properties of
+    /// a paragraph with the style named `name`
+    fn paragraph_style(&self, name: &str) -> (Option<String>, ParagraphProperties, RunProperties) {
+        match self.styles.get(name) {
+            Some(style) if style.automatic => {
+                (style.parent.as_deref().map(style_id), style.paragraph.clone(), style.text.clone())
+            }
+            _ => (Some(style_id(name)), ParagraphProperties::default(), RunProperties::default()),
+        }
+    }
+
+    /// Run properties of a text style with those of the styles it is based on
+    fn text_style(&self, name: &str) -> RunProperties {
+        let mut chain = Vec::new();
+        let mut next = Some(name);
+        while let Some(style) = next.and_then(|name| self.styles.get(name)) {
+            if chain.len() == MAX_STYLE_DEPTH {
+                break;
+            }
+            chain.push(style);
+            next = style.parent.as_deref();
+        }
+        chain.iter().rev().fold(RunProperties::default(), |properties, style| overlay_run(&properties, &style.text))
+    }
+
+    /// Read the paragraphs, lists, tables and sections in `parent`
+    fn blocks(&mut self, parent: &Element, flow: &mut Flow) {
+        for element in parent.elements() {
+            match element.name.as_str() {
+                "text:p" | "text:h" => {
+                    let paragraph = self.paragraph(element, None, flow.body.then_some(flow.paragraphs.len()));
+                    flow.paragraphs.push(paragraph);
+                }
+                "text:list" => self.list(element, 0, None, flow),
+                "table:table" => self.table(element, flow),
+                name if SKIPPED_BLOCKS.contains(&name) || name.ends_with("-decls") || name.ends_with("-source") => {}
+                // Sections, indexes and other containers
+                _ => self.blocks(element, flow),
+            }
+        }
+    }
+
+    fn paragraph(&mut self, element: &Element, list: Option<(&str, u8)>, body_index: Option<usize>) -> Paragraph {
+        let (style_id, properties, run_properties) = element
+            .attribute("text:style-name")
+            .map(|name| self.paragraph_style(name))
+            .unwrap_or_default();
+        let mut paragraph = Paragraph {
+            properties: ParagraphProperties { style_id, ..properties },
+            ..Default::default()
+        };
+        if element.name == "text:h" && paragraph.properties.style_id.as_deref().and_then(heading_level).is_none() {
+            let level = element.attribute("text:outline-level").and_then(|level| level.parse().ok()).unwrap_or(1);
+            paragraph.properties.style_id = Some(format!("Heading{}", level.clamp(1, 9)));
+        }
+        if let Some((num_id, level)) = list {
+            paragraph.properties.num_id = Some(num_id.to_string());
+            paragraph.properties.list_level = Some(level);
+        }
+
+        let mut text = InlineText {
+            paragraph,
+            length: 0,
+            space: true,
+            body_index,
+        };
+        self.inline(element, &run_properties, &mut text);
+        text.paragraph
+    }
+
+    /// Read the text of `parent` into a paragraph, collapsing whitespace as ODF does
+    fn inline(&mut self, parent: &Element, properties: &RunProperties, text: &mut InlineText) {
+        for node in &parent.children {
+            let element = match node {
+                Node::Text(content) => {
+                    let mut collapsed = String::with_capacity(content.len());
+                    for c in content.chars() {
+                        if matches!(c, ' ' | '\t' | '\n' | '\r') {
+                            if !text.space {
+                                collapsed.push(' ');
+                                text.space = true;
+                            }
+                        } else {
+                            collapsed.push(c);
+                            text.space = false;
+                        }
+                    }
+                    text.push(&collapsed, properties);
+                    continue;
+                }
+                Node::Element(element) => element,
+            };
+            match element.name.as_str() {
+                "text:s" => {
+                    text.push(&" ".repeat(element.repeat("text:c")), properties);
+                    text.space = false;
+                }
+                "text:tab" => {
+                    text.push("\t", properties);
+                    text.space = false;
+                }
+                "text:line-break" => {
+                    text.push(&LINE_BREAK.to_string(), properties);
+                    text.space = false;
+                }
+                "text:span" => {
+                    let span = match element.attribute("text:style-name") {
+                        Some(name) => overlay_run(properties, &self.text_style(name)),
+                        None => properties.clone(),
+                    };
+                    self.inline(element, &span, text);
+                }
+                "text:a" => {
+                    let start = text.length;
+                    self.inline(element, properties, text);
+                    if let Some(href) = element.attribute("xlink:href").filter(|_| text.length > start) {
+                        let (url, anchor) = match href.strip_prefix('#') {
+                            Some(anchor) => (None, Some(anchor.to_string())),
+                            None => (Some(href.to_string()), None),
+                        };
+                        text.paragraph.hyperlinks.push(Hyperlink {
+                            url,
+                            anchor,
+                            tooltip: element.attribute("office:title").map(str::to_string),
+                            start,
+                            length: text.length - start,
+                            ..Default::default()
+                        });
+                    }
+                }
+                "text:note" => self.note(element, text),
+                "draw:frame" => self.frame(element, text),
+                "office:annotation" | "text:ruby-text" => {}
+                // Fields, bookmarks, changes and other marks keep the text in them
+                _ => self.inline(element, properties, text),
+            }
+        }
+    }
+
+    fn note(&mut self, element: &Element, text: &mut InlineText) {
+        let Some(body) = element.child("text:note-body") else {
+            return;
+        };
+        let mut flow = Flow::default();
+        self.blocks(body, &mut flow);
+        let kind = match element.attribute("text:note-class") {
+            Some("endnote") => NoteKind::Endnote,
+            _ => NoteKind::Footnote,
+        };
+        let id = match kind {
+            NoteKind::Footnote => {
+                let id = (self.footnotes.len() + 1).to_string();
+                self.footnotes.push(Footnote {
+                    id: id.clone(),
+                    footnote_type: None,
+                    paragraphs: flow.paragraphs,
+                });
+                id
+            }
+            NoteKind::Endnote => {
+                let id = (self.endnotes.len() + 1).to_string();
+                self.endnotes.push(Endnote {
+                    id: id.clone(),
+                    endnote_type: None,
+                    paragraphs: flow.paragraphs,
+                });
+                id
+            }
+        };
+        text.paragraph.note_references.push(NoteReference {
+            kind,
+            id,
+            position: text.length,
+        });
+    }
+
+    /// An image in a draw:frame, drawn where the frame is in the text
+    fn frame(&mut self, frame: &Element, text: &mut InlineText) {
+        let (Some(paragraph_index), Some(image)) = (text.body_index, frame.child("draw:image")) else {
+            return;
+        };
+        let Some(href) = image.attribute("xlink:href").filter(|href| !href.is_empty()) else {
+            return;
+        };
+        let size = |name: &str| frame.attribute(name).and_then(twips).map(|twips| twips.max(0) as u32 * EMU_PER_TWIP);
+        let description = |name: &str| frame.child(name).map(Element::text).filter(|text| !text.is_empty());
+        self.images.push(DocumentImage {
+            id: format!("image{}", self.images.len() + 1),
+            path: href.to_string(),
+            desired_width: size("svg:width"),
+            desired_height: size("svg:height"),
+            title: description("svg:title"),
+            alt_description: description("svg:desc"),
+            // Embedded images are at paths inside the package
+            is_linked: href.contains(':') || href.starts_with('/') || href.starts_with("../"),
+            paragraph_index,
+            position: text.length,
+            ..Default::default()
+        });
+    }
+
+    fn list(&mut self, list: &Element, level: u8, num_id: Option<String>, flow: &mut Flow) {
+        let num_id = num_id.unwrap_or_else(|| self.list_instance(list));
+        for item in list.elements() {
+            // Only an item's first paragraph has its number; a list header has none
+            let mut numbered = item.name == "text:list-item";
+            if !numbered && item.name != "text:list-header" {
+                continue;
+            }
+            for child in item.elements() {
+                match child.name.as_str() {
+                    "text:p" | "text:h" => {
+                        let body_index = flow.body.then_some(flow.paragraphs.len());
+                        let paragraph = self.paragraph(child, numbered.then_some((num_id.as_str(), level)), body_index);
+                        flow.paragraphs.push(paragraph);
+                        numbered = false;
+                    }
+                    "text:list" => self.list(child, (level + 1).min(MAX_LIST_LEVEL), Some(num_id.clone()), flow),
+                    "table:table" => self.table(child, flow),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// The list instance a top-level list is numbered with: the last of its
+    /// style when it continues that one's numbering, or else a new one
+    fn list_instance(&mut self, list: &Element) -> String {
+        let style = list.attribute("text:style-name").unwrap_or_default().to_string();
+        let continues =
+            list.attribute("text:continue-numbering") == Some("true") || list.attribute("text:continue-list").is_some();
+        if let Some(num_id) = self.last_lists.get(&style).filter(|_| continues) {
+            return num_id.clone();
+        }
+
+        let abstract_num_id = match self.list_definitions.get(&style) {
+            Some(id) => id.clone(),
+            None => {
+                let id = (self.numbering.abstract_num_defs.len() + 1).to_string();
+                let levels = self.list_styles.get(&style).cloned().unwrap_or_else(bullet_levels);
+                self.numbering.abstract_num_defs.push(AbstractNumDef {
+                    abstract_num_id: id.clone(),
+                    levels,
+                });
+                self.list_definitions.insert(style.clone(), id.clone());
+                id
+            }
+        };
+        let num_id = (self.numbering.num_instances.len() + 1).to_string();
+        self.numbering.num_instances.push(NumInstance {
+            num_id: num_id.clone(),
+            abstract_num_id,
+            overrides: Vec::new(),
+        });
+        self.last_lists.insert(style, num_id.clone());
+        num_id
+    }
+
+    fn table(&mut self, element: &Element, flow: &mut Flow) {
+        let mut widths = Vec::new();
+        table_columns(element, &mut widths, &self.styles);
+        let mut table = Table {
+            paragraph_index: flow.paragraphs.len(),
+            ..Default::default()
+        };
+        if widths.iter().all(Option::is_some) {
+            table.properties.grid_columns = widths.into_iter().flatten().map(|width| width.max(0) as u32).collect();
+        }
+
+        let mut rows = Vec::new();
+        table_rows(element, false, &mut rows);
+        // Cells spanning rows, by grid column: the rows still covered and the columns spanned
+        let mut row_spans: Vec<Option<(usize, usize)>> = Vec::new();
+        for (row_element, is_header) in rows {
+            for _ in 0..row_element.repeat("table:number-rows-repeated") {
+                let mut row = TableRow {
+                    properties: TableRowProperties {
+                        is_header,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                let mut column = 0;
+                // Covered cells of the last cell's column span
+                let mut covered = 0;
+                for cell_element in row_element.elements() {
+                    for _ in 0..cell_element.repeat("table:number-columns-repeated") {
+                        match cell_element.name.as_str() {
+                            "table:table-cell" => {
+                                let columns = cell_element.repeat("table:number-columns-spanned");
+                                let rows = cell_element.repeat("table:number-rows-spanned");
+                                let mut cell = self.cell(cell_element);
+                                cell.properties.grid_span = (columns > 1).then_some(columns as u32);
+                                if rows > 1 {
+                                    cell.vertical_merge = Some(1);
+                                    if row_spans.len() <= column {
+                                        row_spans.resize(column + 1, None);
+                                    }
+                                    row_spans[column] = Some((rows - 1, columns));
+                                }
+                                row.cells.push(cell);
+                                covered = columns - 1;
+                            }
+                            "table:covered-table-cell" if covered > 0 => covered -= 1,
+                            "table:covered-table-cell" => {
+                                if let Some(span) = row_spans.get_mut(column) {
+                                    if let Some((rows, columns)) = *span {
+                                        row.cells.push(TableCell {
+                                            vertical_merge: Some(-1),
+                                            properties: TableCellProperties {
+                                                grid_span: (columns > 1).then_some(columns as u32),
+                                                ..Default::default()
+                                            },
+                                            ..Default::default()
+                                        });
+                                        *span = (rows > 1).then_some((rows - 1, columns));
+                                        covered = columns - 1;
+                                    }
+                                }
+                            }
+                            _ => continue,
+                        }
+                        column += 1;
+                    }
+                }
+                table.rows.push(row);
+            }
+        }
+
+        if flow.body {
+            flow.tables.push(table);
+        } else {
+            let cells = table.rows.into_iter().flat_map(|row| row.cells);
+            flow.paragraphs.extend(cells.flat_map(|cell| cell.paragraphs));
+        }
+    }
+
+    fn cell(&mut self, element: &Element) -> TableCell {
+        let mut flow = Flow::default();
+        self.blocks(element, &mut flow);
+        TableCell {
+            paragraphs: flow.paragraphs,
+            properties: element
+                .attribute("table:style-name")
+                .and_then(|name| self.styles.get(name))
+                .map(|style| style.cell.clone())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Widths of the columns of a table, None where a column's style sets none
+fn table_columns(element: &Element, widths: &mut Vec<Option<i32>>, styles: &HashMap<String, OdfStyle>) {
+    for child in element.elements() {
+        match child.name.as_str() {
+            "table:table-column" => {
+                let width = child
+                    .attribute("table:style-name")
+                    .and_then(|name| styles.get(name))
+                    .and_then(|style| style.column_width);
+                widths.extend(std::iter::repeat_n(width, child.repeat("table:number-columns-repeated")));
+            }
+            "table:table-columns" | "table:table-header-columns" | "table:table-column-group" => {
+                table_columns(child, widths, styles)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Rows of a table, with whether each is a header row
+fn table_rows<'e>(element: &'e Element, header: bool, rows: &mut Vec<(&'e Element, bool)>) {
+    for child in element.elements() {
+        match child.name.as_str() {
+            "table:table-row" => rows.push((child, header)),
+            "table:table-header-rows" => table_rows(child, true, rows),
+            "table:table-rows" | "table:table-row-group" => table_rows(child, header, rows),
+            _ => {}
+        }
+    }
+}
+
+/// Levels of a text:list-style
+fn list_levels(style: &Element) -> Vec<ListLevel> {
+    style
+        .elements()
+        .filter_map(|level_style| {
+            let level = level_style.attribute("text:level")?.parse::<u32>().ok()?.checked_sub(1)?;
+            if level > u32::from(MAX_LIST_LEVEL) {
+                return None;
+            }
+            let (format, text, start_value) = match level_style.name.as_str() {
+                "text:list-level-style-number" => {
+                    let format = match level_style.attribute("style:num-format").unwrap_or_default() {
+                        "" => "none",
+                        "a" => "lowerLetter",
+                        "A" => "upperLetter",
+                        "i" => "lowerRoman",
+                        "I" => "upperRoman",
+                        _ => "decimal",
+                    };
+                    let shown = level_style
+                        .attribute("text:display-levels")
+                        .and_then(|levels| levels.parse::<u32>().ok())
+                        .unwrap_or(1)
+                        .clamp(1, level + 1);
+                    let numbers = match format {
+                        "none" => String::new(),
+                        _ => (level + 2 - shown..=level + 1).map(|l| format!("%{}", l)).collect::<Vec<_>>().join("."),
+                    };
+                    let prefix = level_style.attribute("style:num-prefix").unwrap_or_default();
+                    let suffix = level_style.attribute("style:num-suffix").unwrap_or_default();
+                    let start = level_style.attribute("text:start-value").and_then(|start| start.parse().ok()).unwrap_or(1);
+                    (format, format!("{}{}{}", prefix, numbers, suffix), start)
+                }
+                "text:list-level-style-bullet" => {
+                    ("bullet", level_style.attribute("text:bullet-char").unwrap_or("\u{2022}").to_string(), 1)
+                }
+                "text:list-level-style-image" => ("bullet", "\u{2022}".to_string(), 1),
+                _ => return None,
+            };
+            let mut paragraph_properties = ParagraphProperties::default();
+            let alignment = level_style
+                .child("style:list-level-properties")
+                .and_then(|properties| properties.child("style:list-level-label-alignment"));
+            if let Some(alignment) = alignment {
+                paragraph_properties.indent_left = alignment.attribute("fo:margin-left").and_then(twips);
+                paragraph_properties.indent_first_line = alignment.attribute("fo:text-indent").and_then(twips);
+            }
+            Some(ListLevel {
+                level,
+                format: format.to_string(),
+                text,
+                start_value,
+                paragraph_properties,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Levels of a list whose style is missing
+fn bullet_levels() -> Vec<ListLevel> {
+    (0..=u32::from(MAX_LIST_LEVEL))
+        .map(|level| ListLevel {
+            level,
+            format: "bullet".to_string(),
+            text: "\u{2022}".to_string(),
+            start_value: 1,
+            paragraph_properties: ParagraphProperties {
+                indent_left: Some(720 * (level as i32 + 1)),
+                indent_first_line: Some(-360),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn paragraph_properties(properties: &Element) -> ParagraphProperties {
+    let length = |name: &str| properties.attribute(name).and_then(twips);
+    ParagraphProperties {
+        alignment: properties.attribute("fo:text-align").and_then(|align| match align {
+            "start" | "left" => Some("left".to_string()),
+            "end" | "right" => Some("right".to_string()),
+            "center" => Some("center".to_string()),
+            "justify" => Some("both".to_string()),
+            _ => None,
+        }),
+        indent_left: length("fo:margin-left"),
+        indent_right: length("fo:margin-right"),
+        indent_first_line: length("fo:text-indent"),
+        spacing_before: length("fo:margin-top"),
+        spacing_after: length("fo:margin-bottom"),
+        // Proportional line height, which Word gives in 240ths of a line
+        spacing_line: properties
+            .attribute("fo:line-height")
+            .and_then(|height| height.strip_suffix('%'))
+            .and_then(|percent| percent.trim().parse::<f32>().ok())
+            .map(|percent| (percent * 2.4).round() as i32),
+        border_bottom: properties
+            .attribute("fo:border-bottom")
+            .or_else(|| properties.attribute("fo:border"))
+            .and_then(|border| {
+                let padding = properties.attribute("fo:padding-bottom").or_else(|| properties.attribute("fo:padding"));
+                paragraph_border(border, padding)
+            }),
+        ..Default::default()
+    }
+}
+
+/// A border such as "0.5pt solid #000000", with the padding between it and the text
+fn paragraph_border(border: &str, padding: Option<&str>) -> Option<ParagraphBorder> {
+    let mut parts = border.split_whitespace();
+    let width = twips(parts.next()?)?;
+    let style = match parts.next()? {
+        "none" | "hidden" => return None,
+        "double" => "double",
+        "dotted" => "dotted",
+        "dashed" => "dashed",
+        _ => "single",
+    };
+    Some(ParagraphBorder {
+        style: style.to_string(),
+        // Eighths of a point
+        size: (width as f32 * 0.4).round().max(0.0) as u32,
+        space: padding.and_then(twips).map_or(0, |padding| (padding / 20).max(0) as u32),
+    })
+}
+
+fn run_properties(properties: &Element, fonts: &HashMap<String, String>) -> RunProperties {
+    RunProperties {
+        bold: properties
+            .attribute("fo:font-weight")
+            .map(|weight| weight == "bold" || weight.parse::<u32>().is_ok_and(|weight| weight >= 600)),
+        italic: properties.attribute("fo:font-style").map(|style| matches!(style, "italic" | "oblique")),
+        underline: properties.attribute("style:text-underline-style").map(|style| {
+            match (style, properties.attribute("style:text-underline-type")) {
+                ("none", _) => "none",
+                (_, Some("double")) => "double",
+                ("dotted", _) => "dotted",
+                ("dash", _) => "dash",
+                ("wave", _) => "wave",
+                _ => "single",
+            }
+            .to_string()
+        }),
+        // Half-points
+        font_size: properties.attribute("fo:font-size").and_then(twips).map(|size| (size as f32 / 10.0).round() as i32),
+        font_name: properties
+            .attribute("style:font-name")
+            .map(|name| fonts.get(name).cloned().unwrap_or_else(|| name.to_string()))
+            .or_else(|| properties.attribute("fo:font-family").map(font_family)),
+        color: properties.attribute("fo:color").and_then(hex_color),
+        background_color: properties.attribute("fo:background-color").and_then(hex_color),
+    }
+}
+
+/// The first family of a font family list, unquoted
+fn font_family(families: &str) -> String {
+    families.split(',').next().unwrap_or_default().trim().trim_matches(['\'', '"']).to_string()
+}
+
+/// RRGGBB of a "#rrggbb" color
+fn hex_color(value: &str) -> Option<String> {
+    let hex = value.strip_prefix('#')?;
+    (hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit())).then(|| hex.to_ascii_uppercase())
+}
+
+/// A length such as "2.5cm" or "12pt" in twips
+fn twips(length: &str) -> Option<i32> {
+    let length = length.trim();
+    let unit = length.find(|c: char| c.is_ascii_alphabetic())?;
+    let value: f32 = length[..unit].parse().ok()?;
+    let per_unit = match &length[unit..] {
+        "in" => 1440.0,
+        "cm" => 1440.0 / 2.54,
+        "mm" => 144.0 / 2.54,
+        "pt" => 20.0,
+        "pc" => 240.0,
+        "px" => 15.0,
+        _ => return None,
+    };
+    Some((value * per_unit).round() as i32)
+}
+
+/// The Word style ID of the ODF style named `name`: its name without the
+/// spaces and punctuation, and Normal for Standard
+fn style_id(name: &str) -> String {
+    if name == "Standard" {
+        return "Normal".to_string();
+    }
+    let id: String = decode_style_name(name).chars().filter(|c| c.is_alphanumeric()).collect();
+    if id.is_empty() {
+        name.to_string()
+    } else {
+        id
+    }
+}
+
+/// Undo the _XX_ escapes of characters a style name cannot hold, as in "Heading_20_1"
+fn decode_style_name(name: &str) -> String {
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(start) = rest.find('_') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let escaped = rest[1..].find('_').and_then(|end| {
+            let hex = &rest[1..end + 1];
+            let c = u32::from_str_radix(hex, 16).ok().filter(|_| !hex.is_empty()).and_then(char::from_u32)?;
+            Some((c, end + 2))
+        });
+        match escaped {
+            Some((c, length)) => {
+                decoded.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('_');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn overlay_run(base: &RunProperties, over: &RunProperties) -> RunProperties {
+    RunProperties {
+        bold: over.bold.or(base.bold),
+        italic: over.italic.or(base.italic),
+        underline: over.underline.clone().or_else(|| base.underline.clone()),
+        font_size: over.font_size.or(base.font_size),
+        font_name: over.font_name.clone().or_else(|| base.font_name.clone()),
+        color: over.color.clone().or_else(|| base.color.clone()),
+        background_color: over.background_color.clone().or_else(|| base.background_color.clone()),
+    }
+}
+
+fn overlay_paragraph(base: &ParagraphProperties, over: &ParagraphProperties) -> ParagraphProperties {
+    ParagraphProperties {
+        alignment: over.alignment.clone().or_else(|| base.alignment.clone()),
+        indent_left: over.indent_left.or(base.indent_left),
+        indent_right: over.indent_right.or(base.indent_right),
+        indent_first_line: over.indent_first_line.or(base.indent_first_line),
+        spacing_before: over.spacing_before.or(base.spacing_before),
+        spacing_after: over.spacing_after.or(base.spacing_after),
+        spacing_line: over.spacing_line.or(base.spacing_line),
+        border_bottom: over.border_bottom.clone().or_else(|| base.border_bottom.clone()),
+        ..over.clone()
+    }
+}
+
+/// The page size, margins and columns of the Standard master page
+fn page_section(styles: &Element, paragraph_count: usize) -> Option<Section> {
+    let masters = styles.child("office:master-styles")?;
+    let master = masters
+        .elements()
+        .find(|master| master.attribute("style:name") == Some("Standard"))
+        .or_else(|| masters.child("style:master-page"))?;
+    let layout_name = master.attribute("style:page-layout-name")?;
+    let layout = styles
+        .child("office:automatic-styles")?
+        .elements()
+        .find(|layout| layout.name == "style:page-layout" && layout.attribute("style:name") == Some(layout_name))?;
+    let properties = layout.child("style:page-layout-properties")?;
+    let length = |name: &str| properties.attribute(name).and_then(twips);
+    let columns = properties.child("style:columns");
+    Some(Section {
+        paragraph_count,
+        page_width: length("fo:page-width"),
+        page_height: length("fo:page-height"),
+        landscape: properties.attribute("style:print-orientation") == Some("landscape"),
+        margin_top: length("fo:margin-top"),
+        margin_right: length("fo:margin-right"),
+        margin_bottom: length("fo:margin-bottom"),
+        margin_left: length("fo:margin-left"),
+        columns: columns
+            .and_then(|columns| columns.attribute("fo:column-count"))
+            .and_then(|count| count.parse().ok())
+            .filter(|&count| count > 0)
+            .unwrap_or(1),
+        column_space: columns.and_then(|columns| columns.attribute("fo:column-gap")).and_then(twips),
+        ..Default::default()
+    })
+}
+
+fn core_properties(meta: &Element) -> CoreProperties {
+    let text = |name: &str| meta.child(name).map(|element| element.text().trim().to_string()).filter(|text| !text.is_empty());
+    CoreProperties {
+        title: text("dc:title"),
+        subject: text("dc:subject"),
+        creator: text("meta:initial-creator").or_else(|| text("dc:creator")),
+        keywords: text("meta:keyword"),
+        description: text("dc:description"),
+        last_modified_by: text("dc:creator"),
+        created: text("meta:creation-date"),
+        modified: text("dc:date"),
+    }
+}
+
+// ============================================
+// Writing
+// ============================================
+
+/// Write a document model as an .odt; `media` has the embedded images'
+/// data by the path of their [`DocumentImage`]. Images without data are left out
+pub fn export_odt(model: &DocumentModel, media: &HashMap<String, Vec<u8>>) -> Result<Vec<u8>, OdfError> {
+    let mut drawings: HashMap<usize, Vec<&DocumentImage>> = HashMap::new();
+    for image in &model.images {
+        drawings.entry(image.paragraph_index).or_default().push(image);
+    }
+    let mut writer = OdtWriter {
+        model,
+        media,
+        drawings,
+        automatic: AutomaticStyles::default(),
+        pictures: HashMap::new(),
+        files: Vec::new(),
+        list_depth: 0,
+        list_id: None,
+        lists_started: HashSet::new(),
+        frames: 0,
+        tables: 0,
+        footnotes: 0,
+        endnotes: 0,
+        in_note: false,
+        out: String::new(),
+    };
+    writer.body();
+    let content = writer.content_xml();
+    let styles = writer.styles_xml();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // The mimetype goes first and uncompressed, so the type shows at a fixed offset
+    zip.start_file("mimetype", FileOptions::default().compression_method(CompressionMethod::Stored))?;
+    zip.write_all(MIMETYPE.as_bytes())?;
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut entries = vec![
+        ("content.xml", content.into_bytes()),
+        ("styles.xml", styles.into_bytes()),
+        ("meta.xml", meta_xml(model).into_bytes()),
+    ];
+    entries.push(("META-INF/manifest.xml", manifest_xml(&writer.files).into_bytes()));
+    for (name, data) in entries {
+        zip.start_file(name, deflated)?;
+        zip.write_all(&data)?;
+    }
+    for (name, data, _) in &writer.files {
+        zip.start_file(name.as_str(), FileOptions::default().compression_method(CompressionMethod::Stored))?;
+        zip.write_all(data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Automatic styles made for the direct formatting written
+#[derive(Debug, Default)]
+struct AutomaticStyles {
+    xml: String,
+    /// Names of the styles written, by their family, parent and properties
+    names: HashMap<String, String>,
+    /// Styles written with each name prefix
+    counts: HashMap<&'static str, usize>,
+}
+
+impl AutomaticStyles {
+    /// The name of the style with `properties`, writing it if it is new
+    fn add(&mut self, family: &str, prefix: &'static str, parent: Option<&str>, properties: String) -> String {
+        let key = format!("{}\u{0}{}\u{0}{}", family, parent.unwrap_or_default(), properties);
+        if let Some(name) = self.names.get(&key) {
+            return name.clone();
+        }
+        let count = self.counts.entry(prefix).or_default();
+        *count += 1;
+        let name = format!("{}{}", prefix, count);
+        let _ = write!(self.xml, r#"<style:style style:name="{}" style:family="{}""#, name, family);
+        if let Some(parent) = parent {
+            let _ = write!(self.xml, r#" style:parent-style-name="{}""#, escape_xml_attr(parent));
+        }
+        let _ = write!(self.xml, ">{}</style:style>", properties);
+        self.names.insert(key, name.clone());
+        name
+    }
+}
+
+struct OdtWriter<'a> {
+    model: &'a DocumentModel,
+    media: &'a HashMap<String, Vec<u8>>,
+    /// Images by body paragraph
+    drawings: HashMap<usize, Vec<&'a DocumentImage>>,
+    automatic: AutomaticStyles,
+    /// Package paths of the images written, by image path
+    pictures: HashMap<&'a str, String>,
+    /// Package path, data and mimetype of each image written
+    files: Vec<(String, Vec<u8>, &'static str)>,
+    /// Lists open, each with an item open in it
+    list_depth: usize,
+    /// List ID of the outermost list open
+    list_id: Option<String>,
+    /// Lists written so far; one opened again continues their numbering
+    lists_started: HashSet<String>,
+    frames: usize,
+    tables: usize,
+    footnotes: usize,
+    endnotes: usize,
+    /// Whether a note is being written, in which note references are not
+    in_note: bool,
+    out: String,
+}
+
+impl<'a> OdtWriter<'a> {
+    fn body(&mut self) {
+        let model = self.model;
+        let mut paragraph_index = 0;
+        for block in &model.body {
+            match block {
+                Block::Paragraph(paragraph) => {
+                    let drawings = self.drawings.remove(&paragraph_index).unwrap_or_default();
+                    self.body_paragraph(paragraph, &drawings);
+                    paragraph_index += 1;
+                }
+                Block::Table(table) => {
+                    self.close_lists(0);
+                    self.table(table);
+                }
+            }
+        }
+        self.close_lists(0);
+    }
+
+    /// Write a paragraph, as a list item if it is in a list
+    fn body_paragraph(&mut self, paragraph: &'a Paragraph, drawings: &[&'a DocumentImage]) {
+        let properties = &paragraph.properties;
+        let Some(num_id) = properties.num_id.as_deref().filter(|id| *id != "0") else {
+            self.close_lists(0);
+            self.paragraph(paragraph, drawings);
+            return;
+        };
+        let level = usize::from(properties.list_level.unwrap_or(0).min(MAX_LIST_LEVEL));
+        if self.list_id.as_deref() != Some(num_id) {
+            self.close_lists(0);
+        }
+        self.close_lists(level + 1);
+        if self.list_depth == level + 1 {
+            self.out.push_str("</text:list-item><text:list-item>");
+        }
+        while self.list_depth < level + 1 {
+            if self.list_depth == 0 {
+                let continues = !self.lists_started.insert(num_id.to_string());
+                let _ = write!(self.out, r#"<text:list text:style-name="{}""#, list_style_name(num_id));
+                if continues {
+                    self.out.push_str(r#" text:continue-numbering="true""#);
+                }
+                self.out.push('>');
+                self.list_id = Some(num_id.to_string());
+            } else {
+                self.out.push_str("<text:list>");
+            }
+            self.out.push_str("<text:list-item>");
+            self.list_depth += 1;
+        }
+        self.paragraph(paragraph, drawings);
+    }
+
+    /// Close open lists, and the items open in them, until `keep` are left
+    fn close_lists(&mut self, keep: usize) {
+        while self.list_depth > keep {
+            self.out.push_str("</text:list-item></text:list>");
+            self.list_depth -= 1;
+        }
+        if self.list_depth == 0 {
+            self.list_id = None;
+        }
+    }
+
+    /// Write a paragraph as a heading or text:p
+    fn paragraph(&mut self, paragraph: &'a Paragraph, drawings: &[&'a DocumentImage]) {
+        let properties = &paragraph.properties;
+        let parent = properties.style_id.as_deref().map(|id| self.style_name(id));
+        let attributes = paragraph_attributes(properties);
+        let style = match attributes.is_empty() {
+            true => parent,
+            false => Some(self.automatic.add(
+                "paragraph",
+                "P",
+                parent.as_deref(),
+                format!("<style:paragraph-properties{}/>", attributes),
+            )),
+        };
+        let heading = properties.style_id.as_deref().and_then(heading_level);
+        let tag = if heading.is_some() { "text:h" } else { "text:p" };
+        let _ = write!(self.out, "<{}", tag);
+        if let Some(style) = style {
+            let _ = write!(self.out, r#" text:style-name="{}""#, escape_xml_attr(&style));
+        }
+        if let Some(level) = heading {
+            let _ = write!(self.out, r#" text:outline-level="{}""#, level);
+        }
+        self.out.push('>');
+        self.inline(paragraph, drawings);
+        let _ = write!(self.out, "</{}>", tag);
+    }
+
+    /// Write the runs of a paragraph with its links, notes and images, leaving
+    /// out text deleted in tracked changes
+    fn inline(&mut self, paragraph: &'a Paragraph, drawings: &[&'a DocumentImage]) {
+        let chars: Vec<char> = paragraph.runs.iter().flat_map(|run| run.text.chars()).collect();
+        let length = chars.len();
+        let deleted: Vec<Range<usize>> = paragraph
+            .revisions
+            .iter()
+            .filter(|revision| revision.kind == RevisionKind::Deletion)
+            .map(|revision| revision.start..revision.start + revision.length)
+            .collect();
+
+        // Offsets where a run, link or deletion starts or ends, or a note or image sits
+        let mut runs = Vec::new();
+        let mut breaks = vec![0, length];
+        let mut offset = 0;
+        for run in &paragraph.runs {
+            runs.push((offset, &run.properties));
+            offset += run.text.chars().count();
+            breaks.push(offset);
+        }
+        for link in &paragraph.hyperlinks {
+            breaks.extend([link.start, link.start + link.length]);
+        }
+        breaks.extend(deleted.iter().flat_map(|range| [range.start, range.end]));
+        breaks.extend(paragraph.note_references.iter().map(|reference| reference.position));
+        breaks.extend(drawings.iter().map(|image| image.position));
+        breaks.retain(|&offset| offset <= length);
+        breaks.sort_unstable();
+        breaks.dedup();
+
+        let mut link_end = None;
+        let mut space = true;
+        for (i, &at) in breaks.iter().enumerate() {
+            if link_end.is_some_and(|end| end <= at) {
+                self.out.push_str("</text:a>");
+                link_end = None;
+            }
+            for reference in paragraph.note_references.iter().filter(|reference| reference.position == at) {
+                self.note(reference.kind, &reference.id);
+            }
+            for image in drawings.iter().filter(|image| image.position == at) {
+                self.frame(image);
+            }
+            let link = paragraph.hyperlinks.iter().find(|link| link.start == at && link.length > 0);
+            if let Some(link) = link.filter(|_| link_end.is_none() && at < length) {
+                let href = match (&link.url, &link.anchor) {
+                    (Some(url), _) => url.clone(),
+                    (None, Some(anchor)) => format!("#{}", anchor),
+                    (None, None) => String::new(),
+                };
+                let _ = write!(self.out, r#"<text:a xlink:type="simple" xlink:href="{}""#, escape_xml_attr(&href));
+                if let Some(tooltip) = &link.tooltip {
+                    let _ = write!(self.out, r#" office:title="{}""#, escape_xml_attr(tooltip));
+                }
+                self.out.push('>');
+                link_end = Some(link.start + link.length);
+            }
+
+            let Some(&end) = breaks.get(i + 1) else {
+                break;
+            };
+            if deleted.iter().any(|range| range.contains(&at)) {
+                continue;
+            }
+            let properties = runs.iter().rev().find(|(start, _)| *start <= at).map(|(_, properties)| *properties);
+            let style = properties.filter(|properties| **properties != RunProperties::default()).map(|properties| {
+                let attributes = text_attributes(properties);
+                self.automatic.add("text", "T", None, format!("<style:text-properties{}/>", attributes))
+            });
+            if let Some(style) = &style {
+                let _ = write!(self.out, r#"<text:span text:style-name="{}">"#, style);
+            }
+            write_text(&mut self.out, &chars[at..end], &mut space);
+            if style.is_some() {
+                self.out.push_str("</text:span>");
+            }
+        }
+        if link_end.is_some() {
+            self.out.push_str("</text:a>");
+        }
+    }
+
+    fn note(&mut self, kind: NoteKind, id: &str) {
+        let model = self.model;
+        let paragraphs = match kind {
+            NoteKind::Footnote => model.footnotes.iter().find(|note| note.id == id).map(|note| &note.paragraphs),
+            NoteKind::Endnote => model.endnotes.iter().find(|note| note.id == id).map(|note| &note.paragraphs),
+        };
+        let Some(paragraphs) = paragraphs.filter(|_| !self.in_note) else {
+            return;
+        };
+        let (class, prefix, number) = match kind {
+            NoteKind::Footnote => {
+                self.footnotes += 1;
+                ("footnote", "ftn", self.footnotes)
+            }
+            NoteKind::Endnote => {
+                self.endnotes += 1;
+                ("endnote", "edn", self.endnotes)
+            }
+        };
+        let _ = write!(
+            self.out,
+            r#"<text:note text:id="{}{}" text:note-class="{}"><text:note-citation>{}</text:note-citation><text:note-body>"#,
+            prefix, number, class, number
+        );
+        self.in_note = true;
+        for paragraph in paragraphs {
+            self.paragraph(paragraph, &[]);
+        }
+        self.in_note = false;
+        self.out.push_str("</text:note-body></text:note>");
+    }
+
+    /// Write an image as a frame in the text, with its data in Pictures/
+    fn frame(&mut self, image: &'a DocumentImage) {
+        let data = self.media.get(&image.path);
+        let href = match (image.is_linked, data) {
+            (true, _) => image.path.clone(),
+            (false, Some(data)) => self.picture(&image.path, data),
+            (false, None) => return,
+        };
+        let extent = image.extent().or_else(|| {
+            let data = data?.clone();
+            ImageCache::new().load(image.path.clone(), data).ok().map(|data| data.dimensions.to_emu())
+        });
+        self.frames += 1;
+        let _ = write!(self.out, r#"<draw:frame draw:name="Image{}" text:anchor-type="as-char""#, self.frames);
+        if let Some((width, height)) = extent {
+            let length = |emu: u32| length((emu / EMU_PER_TWIP) as i32);
+            let _ = write!(self.out, r#" svg:width="{}" svg:height="{}""#, length(width), length(height));
+        }
+        let _ = write!(
+            self.out,
+            r#"><draw:image xlink:href="{}" xlink:type="simple" xlink:show="embed" xlink:actuate="onLoad"/>"#,
+            escape_xml_attr(&href)
+        );
+        if let Some(title) = &image.title {
+            let _ = write!(self.out, "<svg:title>{}</svg:title>", escape_xml_text(title));
+        }
+        if let Some(description) = &image.alt_description {
+            let _ = write!(self.out, "<svg:desc>{}</svg:desc>", escape_xml_text(description));
+        }
+        self.out.push_str("</draw:frame>");
+    }
+
+    /// The package path an image's data is written to, once per image path
+    fn picture(&mut self, path: &'a str, data: &[u8]) -> String {
+        if let Some(picture) = self.pictures.get(path) {
+            return picture.clone();
+        }
+        let format = ImageFormat::from_magic_bytes(data);
+        let extension = match format {
+            ImageFormat::Unknown => path.rsplit_once('.').map_or("bin", |(_, extension)| extension),
+            format => format.extension(),
+        };
+        let picture = format!("Pictures/image{}.{}", self.files.len() + 1, extension);
+        self.files.push((picture.clone(), data.to_vec(), format.mime_type()));
+        self.pictures.insert(path, picture.clone());
+        picture
+    }
+
+    fn table(&mut self, table: &'a Table) {
+        self.tables += 1;
+        let _ = write!(self.out, r#"<table:table table:name="Table{}">"#, self.tables);
+        let columns = table
+            .rows
+            .iter()
+            .map(|row| row.cells.iter().map(span).sum::<usize>())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let widths = &table.properties.grid_columns;
+        if widths.len() == columns && widths.iter().all(|&width| width > 0) {
+            for &width in widths {
+                let properties = format!(r#"<style:table-column-properties style:column-width="{}"/>"#, length(width as i32));
+                let style = self.automatic.add("table-column", "co", None, properties);
+                let _ = write!(self.out, r#"<table:table-column table:style-name="{}"/>"#, style);
+            }
+        } else {
+            let _ = write!(self.out, r#"<table:table-column table:number-columns-repeated="{}"/>"#, columns);
+        }
+
+        let headers = table.rows.iter().take_while(|row| row.properties.is_header).count();
+        for index in 0..table.rows.len() {
+            if index == 0 && headers > 0 {
+                self.out.push_str("<table:table-header-rows>");
+            }
+            self.table_row(table, index);
+            if index + 1 == headers {
+                self.out.push_str("</table:table-header-rows>");
+            }
+        }
+        self.out.push_str("</table:table>");
+    }
+
+    /// Write a row; merged cells are written as spans over covered cells
+    fn table_row(&mut self, table: &'a Table, index: usize) {
+        let row = &table.rows[index];
+        self.out.push_str("<table:table-row>");
+        let mut column = 0;
+        for (i, cell) in row.cells.iter().enumerate() {
+            let width = span(cell);
+            if is_continuation(cell.vertical_merge) || is_continuation(cell.horizontal_merge) {
+                self.out.push_str(&"<table:covered-table-cell/>".repeat(width));
+                column += width;
+                continue;
+            }
+            let mut columns = width;
+            if cell.horizontal_merge == Some(1) {
+                let merged = row.cells[i + 1..].iter().take_while(|next| is_continuation(next.horizontal_merge));
+                columns += merged.map(span).sum::<usize>();
+            }
+            let rows = match cell.vertical_merge {
+                Some(1) => {
+                    let below = table.rows[index + 1..].iter().take_while(|below| {
+                        cell_at(below, column).is_some_and(|below| is_continuation(below.vertical_merge))
+                    });
+                    1 + below.count()
+                }
+                _ => 1,
+            };
+
+            self.out.push_str("<table:table-cell");
+            if let Some(style) = self.cell_style(&cell.properties) {
+                let _ = write!(self.out, r#" table:style-name="{}""#, style);
+            }
+            if columns > 1 {
+                let _ = write!(self.out, r#" table:number-columns-spanned="{}""#, columns);
+            }
+            if rows > 1 {
+                let _ = write!(self.out, r#" table:number-rows-spanned="{}""#, rows);
+            }
+            self.out.push_str(r#" office:value-type="string">"#);
+            if cell.paragraphs.is_empty() {
+                self.out.push_str("<text:p/>");
+            }
+            for paragraph in &cell.paragraphs {
+                self.body_paragraph(paragraph, &[]);
+            }
+            self.close_lists(0);
+            self.out.push_str("</table:table-cell>");
+            self.out.push_str(&"<table:covered-table-cell/>".repeat(width - 1));
+            column += width;
+        }
+        self.out.push_str("</table:table-row>");
+    }
+
+    fn cell_style(&mut self, properties: &TableCellProperties) -> Option<String> {
+        let mut attributes = String::new();
+        if let Some(color) = properties.shading_color.as_deref().and_then(|color| hex_color(&format!("#{}", color))) {
+            let _ = write!(attributes, r##" fo:background-color="#{}""##, color);
+        }
+        let alignment = properties.vertical_alignment.as_deref().and_then(|alignment| match alignment {
+            "top" | "bottom" => Some(alignment),
+            "center" => Some("middle"),
+            _ => None,
+        });
+        if let Some(alignment) = alignment {
+            let _ = write!(attributes, r#" style:vertical-align="{}""#, alignment);
+        }
+        (!attributes.is_empty()).then(|| {
+            let properties = format!("<style:table-cell-properties{}/>", attributes);
+            self.automatic.add("table-cell", "ce", None, properties)
+        })
+    }
+
+    /// The ODF name of the style with ID `id`; the default paragraph style is Standard
+    fn style_name(&self, id: &str) -> String {
+        match self.model.styles.get(id) {
+            Some(style) if style.is_default && style.style_type == "paragraph" => "Standard".to_string(),
+            _ if id == "Normal" => "Standard".to_string(),
+            _ => encode_style_name(id),
+        }
+    }
+
+    fn content_xml(&mut self) -> String {
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = write!(
+            xml,
+            r#"<office:document-content {} office:version="{}"><office:automatic-styles>"#,
+            NAMESPACES, ODF_VERSION
+        );
+        xml.push_str(&self.automatic.xml);
+        for numbering in &self.model.numbering {
+            for instance in &numbering.num_instances {
+                let definition = numbering
+                    .abstract_num_defs
+                    .iter()
+                    .find(|definition| definition.abstract_num_id == instance.abstract_num_id);
+                xml.push_str(&list_style_xml(&instance.num_id, definition.map_or(&[], |d| d.levels.as_slice())));
+            }
+        }
+        xml.push_str("</office:automatic-styles><office:body><office:text>");
+        xml.push_str(&self.out);
+        xml.push_str("</office:text></office:body></office:document-content>");
+        xml
+    }
+
+    /// The named paragraph and character styles, and the page layout of the last section
+    fn styles_xml(&self) -> String {
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = write!(xml, r#"<office:document-styles {} office:version="{}"><office:styles>"#, NAMESPACES, ODF_VERSION);
+        let mut ids: Vec<&String> = self.model.styles.keys().collect();
+        ids.sort();
+        for id in ids {
+            let style = &self.model.styles[id];
+            let family = match style.style_type.as_str() {
+                "paragraph" => "paragraph",
+                "character" => "text",
+                _ => continue,
+            };
+            let _ = write!(
+                xml,
+                r#"<style:style style:name="{}" style:display-name="{}" style:family="{}""#,
+                escape_xml_attr(&self.style_name(id)),
+                escape_xml_attr(style.name.as_deref().unwrap_or(id)),
+                family
+            );
+            if let Some(parent) = &style.based_on {
+                let _ = write!(xml, r#" style:parent-style-name="{}""#, escape_xml_attr(&self.style_name(parent)));
+            }
+            xml.push('>');
+            let paragraph = paragraph_attributes(&style.paragraph_properties);
+            if family == "paragraph" && !paragraph.is_empty() {
+                let _ = write!(xml, "<style:paragraph-properties{}/>", paragraph);
+            }
+            let text = text_attributes(&style.run_properties);
+            if !text.is_empty() {
+                let _ = write!(xml, "<style:text-properties{}/>", text);
+            }
+            xml.push_str("</style:style>");
+        }
+        xml.push_str("</office:styles>");
+
+        xml.push_str(r#"<office:automatic-styles><style:page-layout style:name="pm1"><style:page-layout-properties"#);
+        if let Some(section) = self.model.sections.last() {
+            for (name, value) in [
+                ("fo:page-width", section.page_width),
+                ("fo:page-height", section.page_height),
+                ("fo:margin-top", section.margin_top),
+                ("fo:margin-right", section.margin_right),
+                ("fo:margin-bottom", section.margin_bottom),
+                ("fo:margin-left", section.margin_left),
+            ] {
+                if let Some(value) = value {
+                    let _ = write!(xml, r#" {}="{}""#, name, length(value));
+                }
+            }
+            if section.landscape {
+                xml.push_str(r#" style:print-orientation="landscape""#);
+            }
+            xml.push('>');
+            if section.columns > 1 {
+                let _ = write!(xml, r#"<style:columns fo:column-count="{}""#, section.columns);
+                if let Some(space) = section.column_space {
+                    let _ = write!(xml, r#" fo:column-gap="{}""#, length(space));
+                }
+                xml.push_str("/>");
+            }
+            xml.push_str("</style:page-layout-properties>");
+        } else {
+            xml.push_str("/>");
+        }
+        xml.push_str("</style:page-layout></office:automatic-styles>");
+        xml.push_str(r#"<office:master-styles><style:master-page style:name="Standard" style:page-layout-name="pm1"/></office:master-styles>"#);
+        xml.push_str("</office:document-styles>");
+        xml
+    }
+}
+
+fn span(cell: &TableCell) -> usize {
+    cell.properties.grid_span.unwrap_or(1).max(1) as usize
+}
+
+/// Whether a merge value marks a cell merged into the one before it
+fn is_continuation(merge: Option<i32>) -> bool {
+    merge.is_some_and(|value| value != 1)
+}
+
+/// The cell of `row` starting at grid column `column`
+fn cell_at(row: &TableRow, column: usize) -> Option<&TableCell> {
+    let mut start = 0;
+    for cell in &row.cells {
+        if start == column {
+            return Some(cell);
+        }
+        start += span(cell);
+    }
+    None
+}
+
+/// Write text, with spaces XML whitespace handling would collapse as text:s,
+/// tabs as text:tab and line breaks as text:line-break; `space` is whether
+/// the text written before ends in whitespace
+fn write_text(out: &mut String, text: &[char], space: &mut bool) {
+    let mut spaces = 0;
+    let flush = |out: &mut String, spaces: &mut usize| {
+        match *spaces {
+            0 => {}
+            1 => out.push_str("<text:s/>"),
+            count => {
+                let _ = write!(out, r#"<text:s text:c="{}"/>"#, count);
+            }
+        }
+        *spaces = 0;
+    };
+    for &c in text {
+        match c {
+            ' ' if *space => spaces += 1,
+            ' ' => {
+                out.push(' ');
+                *space = true;
+            }
+            '\t' => {
+                flush(out, &mut spaces);
+                out.push_str("<text:tab/>");
+                *space = true;
+            }
+            LINE_BREAK | '\n' | '\u{b}' => {
+                flush(out, &mut spaces);
+                out.push_str("<text:line-break/>");
+                *space = true;
+            }
+            // Other control chars cannot be in XML
+            c if c < ' ' => {}
+            c => {
+                flush(out, &mut spaces);
+                match c {
+                    '&' => out.push_str("&amp;"),
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    c => out.push(c),
+                }
+                *space = false;
+            }
+        }
+    }
+    flush(out, &mut spaces);
+}
+
+/// Attributes of style:paragraph-properties for paragraph properties
+fn paragraph_attributes(properties: &ParagraphProperties) -> String {
+    let mut attributes = String::new();
+    let alignment = properties.alignment.as_deref().and_then(|alignment| match alignment {
+        "left" | "start" => Some("start"),
+        "right" | "end" => Some("end"),
+        "center" => Some("center"),
+        "both" | "justify" | "distribute" => Some("justify"),
+        _ => None,
+    });
+    if let Some(alignment) = alignment {
+        let _ = write!(attributes, r#" fo:text-align="{}""#, alignment);
+    }
+    for (name, value) in [
+        ("fo:margin-left", properties.indent_left),
+        ("fo:margin-right", properties.indent_right),
+        ("fo:text-indent", properties.indent_first_line),
+        ("fo:margin-top", properties.spacing_before),
+        ("fo:margin-bottom", properties.spacing_after),
+    ] {
+        if let Some(value) = value {
+            let _ = write!(attributes, r#" {}="{}""#, name, length(value));
+        }
+    }
+    if let Some(line) = properties.spacing_line.filter(|&line| line > 0) {
+        let _ = write!(attributes, r#" fo:line-height="{}%""#, format_number(line as f32 / 2.4));
+    }
+    if let Some(border) = &properties.border_bottom {
+        let style = match border.style.as_str() {
+            "double" => "double",
+            "dotted" => "dotted",
+            "dashed" => "dashed",
+            _ => "solid",
+        };
+        let _ = write!(
+            attributes,
+            r##" fo:border-bottom="{}pt {} #000000" fo:padding-bottom="{}pt""##,
+            format_number(border.size as f32 / 8.0),
+            style,
+            border.space
+        );
+    }
+    attributes
+}
+
+/// Attributes of style:text-properties for run properties
+fn text_attributes(properties: &RunProperties) -> String {
+    let mut attributes = String::new();
+    if let Some(bold) = properties.bold {
+        let _ = write!(attributes, r#" fo:font-weight="{}""#, if bold { "bold" } else { "normal" });
+    }
+    if let Some(italic) = properties.italic {
+        let _ = write!(attributes, r#" fo:font-style="{}""#, if italic { "italic" } else { "normal" });
+    }
+    match properties.underline.as_deref() {
+        None => {}
+        Some("none") => attributes.push_str(r#" style:text-underline-style="none""#),
+        Some(underline) => {
+            let style = match underline {
+                "dotted" => "dotted",
+                "dash" => "dash",
+                "wave" => "wave",
+                _ => "solid",
+            };
+            let _ = write!(
+                attributes,
+                r#" style:text-underline-style="{}" style:text-underline-width="auto" style:text-underline-color="font-color""#,
+                style
+            );
+            if underline == "double" {
+                attributes.push_str(r#" style:text-underline-type="double""#);
+            }
+        }
+    }
+    if let Some(size) = properties.font_size {
+        let _ = write!(attributes, r#" fo:font-size="{}pt""#, format_number(size as f32 / 2.0));
+    }
+    if let Some(font) = &properties.font_name {
+        let _ = write!(attributes, r#" fo:font-family="{}""#, escape_xml_attr(&format!("'{}'", font)));
+    }
+    if let Some(color) = properties.color.as_deref().and_then(|color| hex_color(&format!("#{}", color))) {
+        let _ = write!(attributes, r##" fo:color="#{}""##, color);
+    }
+    if let Some(color) = properties.background_color.as_deref().and_then(|color| hex_color(&format!("#{}", color))) {
+        let _ = write!(attributes, r##" fo:background-color="#{}""##, color);
+    }
+    attributes
+}
+
+/// A text:list-style for the list `num_id` with the levels of its definition
+fn list_style_xml(num_id: &str, levels: &[ListLevel]) -> String {
+    let mut xml = format!(r#"<text:list-style style:name="{}">"#, list_style_name(num_id));
+    for level in levels.iter().filter(|level| level.level <= u32::from(MAX_LIST_LEVEL)) {
+        let number = level.level + 1;
+        if level.format == "bullet" {
+            let bullet = level.text.chars().next().unwrap_or('\u{2022}');
+            let _ = write!(
+                xml,
+                r#"<text:list-level-style-bullet text:level="{}" text:bullet-char="{}">"#,
+                number,
+                escape_xml_attr(&bullet.to_string())
+            );
+        } else {
+            // "%1.%2." is two levels shown between no prefix and a "." suffix
+            let prefix = level.text.split('%').next().unwrap_or_default();
+            let suffix = level.text.rsplit_once(|c: char| c.is_ascii_digit()).map_or("", |(_, suffix)| suffix);
+            let shown = level.text.matches('%').count().max(1);
+            let format = match level.format.as_str() {
+                "none" => "",
+                "lowerLetter" => "a",
+                "upperLetter" => "A",
+                "lowerRoman" => "i",
+                "upperRoman" => "I",
+                _ => "1",
+            };
+            let _ = write!(
+                xml,
+                r#"<text:list-level-style-number text:level="{}" style:num-format="{}" style:num-prefix="{}" style:num-suffix="{}" text:start-value="{}""#,
+                number,
+                format,
+                escape_xml_attr(prefix),
+                escape_xml_attr(suffix),
+                level.start_value.max(1)
+            );
+            if shown > 1 {
+                let _ = write!(xml, r#" text:display-levels="{}""#, shown);
+            }
+            xml.push('>');
+        }
+        let indent = level.paragraph_properties.indent_left.unwrap_or(720 * number as i32);
+        let first_line = level.paragraph_properties.indent_first_line.unwrap_or(-360);
+        let _ = write!(
+            xml,
+            r#"<style:list-level-properties text:list-level-position-and-space-mode="label-alignment"><style:list-level-label-alignment text:label-followed-by="listtab" text:list-tab-stop-position="{}" fo:text-indent="{}" fo:margin-left="{}"/></style:list-level-properties>"#,
+            length(indent),
+            length(first_line),
+            length(indent)
+        );
+        xml.push_str(if level.format == "bullet" {
+            "</text:list-level-style-bullet>"
+        } else {
+            "</text:list-level-style-number>"
+        });
+    }
+    xml.push_str("</text:list-style>");
+    xml
+}
+
+fn list_style_name(num_id: &str) -> String {
+    format!("L{}", encode_style_name(num_id))
+}
+
+/// A style name with the characters it cannot hold as _XX_ escapes
+fn encode_style_name(id: &str) -> String {
+    let mut name = String::with_capacity(id.len());
+    for c in id.chars() {
+        if c.is_alphanumeric() || c == '-' || c == '.' {
+            name.push(c);
+        } else {
+            let _ = write!(name, "_{:x}_", c as u32);
+        }
+    }
+    name
+}
+
+fn meta_xml(model: &DocumentModel) -> String {
+    let metadata = &model.metadata;
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = write!(
+        xml,
+        r#"<office:document-meta xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0" office:version="{}"><office:meta><meta:generator>Velum</meta:generator>"#,
+        ODF_VERSION
+    );
+    for (element, value) in [
+        ("dc:title", &metadata.title),
+        ("meta:initial-creator", &metadata.author),
+        ("dc:creator", &metadata.author),
+        ("meta:creation-date", &metadata.created),
+        ("dc:date", &metadata.modified),
+    ] {
+        if let Some(value) = value {
+            let _ = write!(xml, "<{}>{}</{}>", element, escape_xml_text(value), element);
+        }
+    }
+    xml.push_str("</office:meta></office:document-meta>");
+    xml
+}
+
+fn manifest_xml(files: &[(String, Vec<u8>, &'static str)]) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = write!(
+        xml,
+        r#"<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="{0}"><manifest:file-entry manifest:full-path="/" manifest:version="{0}" manifest:media-type="{1}"/>"#,
+        ODF_VERSION, MIMETYPE
+    );
+    let parts = ["content.xml", "styles.xml", "meta.xml"].map(|part| (part, "text/xml"));
+    let pictures = files.iter().map(|(path, _, mime_type)| (path.as_str(), *mime_type));
+    for (path, media_type) in parts.into_iter().chain(pictures) {
+        let _ = write!(
+            xml,
+            r#"<manifest:file-entry manifest:full-path="{}" manifest:media-type="{}"/>"#,
+            escape_xml_attr(path),
+            media_type
+        );
+    }
+    xml.push_str("</manifest:manifest>");
+    xml
+}
+
+/// A length in twips in points
+fn length(twips: i32) -> String {
+    format!("{}pt", format_number(twips as f32 / 20.0))
+}
+
+fn format_number(value: f32) -> String {
+    let formatted = format!("{:.2}", value);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An .odt of the given content.xml body and styles.xml office:styles
+    fn odt(body: &str, automatic_styles: &str, styles: &str) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("mimetype", FileOptions::default().compression_method(CompressionMethod::Stored)).unwrap();
+        zip.write_all(MIMETYPE.as_bytes()).unwrap();
+        zip.start_file("content.xml", FileOptions::default()).unwrap();
+        write!(
+            zip,
+            r#"<?xml version="1.0" encoding="UTF-8"?><office:document-content {}><office:automatic-styles>{}</office:automatic-styles><office:body><office:text>{}</office:text></office:body></office:document-content>"#,
+            NAMESPACES, automatic_styles, body
+        )
+        .unwrap();
+        zip.start_file("styles.xml", FileOptions::default()).unwrap();
+        write!(zip, r#"<office:document-styles {}><office:styles>{}</office:styles></office:document-styles>"#, NAMESPACES, styles).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn paragraph(text: &str) -> Paragraph {
+        Paragraph {
+            text: text.to_string(),
+            runs: vec![Run {
+                text: text.to_string(),
+                properties: RunProperties::default(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_styles_spans_and_whitespace() {
+        let styles = concat!(
+            r#"<style:style style:name="Heading_20_1" style:display-name="Heading 1" style:family="paragraph">"#,
+            r#"<style:text-properties fo:font-size="16pt" fo:font-weight="bold"/></style:style>"#,
+            r#"<style:style style:name="Emphasis" style:family="text"><style:text-properties fo:font-style="italic"/></style:style>"#,
+        );
+        let automatic = concat!(
+            r#"<style:style style:name="P1" style:family="paragraph" style:parent-style-name="Standard">"#,
+            r#"<style:paragraph-properties fo:text-align="center" fo:margin-top="0.5in" fo:line-height="150%"/></style:style>"#,
+            r##"<style:style style:name="T1" style:family="text" style:parent-style-name="Emphasis"><style:text-properties fo:color="#ff0000"/></style:style>"##,
+        );
+        let body = concat!(
+            r#"<text:h text:style-name="Heading_20_1" text:outline-level="1">Title</text:h>"#,
+            "<text:p text:style-name=\"P1\">\n  One  <text:span text:style-name=\"T1\">red &amp; italic</text:span>",
+            r#"<text:s text:c="2"/>two<text:tab/>three<text:line-break/>four</text:p>"#,
+            r#"<text:h text:outline-level="2">Second</text:h>"#,
+        );
+        let parsed = parse_odt(&odt(body, automatic, styles)).unwrap();
+        let document = &parsed.word_document;
+
+        assert_eq!(document.paragraphs.len(), 3);
+        assert_eq!(document.paragraphs[0].properties.style_id.as_deref(), Some("Heading1"));
+        assert_eq!(document.paragraphs[2].properties.style_id.as_deref(), Some("Heading2"));
+        let heading = &document.styles["Heading1"];
+        assert_eq!(heading.name.as_deref(), Some("Heading 1"));
+        assert_eq!((heading.run_properties.font_size, heading.run_properties.bold), (Some(32), Some(true)));
+
+        let body = &document.paragraphs[1];
+        assert_eq!(body.text, "One red & italic  two\tthree\u{2028}four");
+        assert_eq!(body.properties.style_id.as_deref(), Some("Normal"));
+        assert_eq!(body.properties.alignment.as_deref(), Some("center"));
+        assert_eq!((body.properties.spacing_before, body.properties.spacing_line), (Some(720), Some(360)));
+        let red = &body.runs[1];
+        assert_eq!(red.text, "red & italic");
+        assert_eq!((red.properties.italic, red.properties.color.as_deref()), (Some(true), Some("FF0000")));
+        assert_eq!(document.text, "Title\nOne red & italic  two\tthree\u{2028}four\nSecond");
+    }
+
+    #[test]
+    fn test_parse_lists_tables_images_and_notes() {
+        let automatic = concat!(
+            r#"<text:list-style style:name="L1"><text:list-level-style-number text:level="1" style:num-format="1" style:num-suffix="."/>"#,
+            r#"<text:list-level-style-number text:level="2" style:num-format="a" style:num-suffix=")" text:display-levels="2"/></text:list-style>"#,
+        );
+        let body = concat!(
+            r#"<text:list text:style-name="L1"><text:list-item><text:p>First</text:p>"#,
+            r#"<text:list><text:list-item><text:p>Nested</text:p></text:list-item></text:list></text:list-item></text:list>"#,
+            r#"<text:p>Between</text:p>"#,
+            r#"<text:list text:style-name="L1" text:continue-numbering="true"><text:list-item><text:p>Second</text:p></text:list-item></text:list>"#,
+            r#"<table:table><table:table-column table:number-columns-repeated="3"/>"#,
+            r#"<table:table-header-rows><table:table-row><table:table-cell table:number-columns-spanned="2"><text:p>Wide</text:p></table:table-cell>"#,
+            r#"<table:covered-table-cell/><table:table-cell table:number-rows-spanned="2"><text:p>Tall</text:p></table:table-cell></table:table-row></table:table-header-rows>"#,
+            r#"<table:table-row><table:table-cell><text:p>A</text:p></table:table-cell><table:table-cell><text:p>B</text:p></table:table-cell><table:covered-table-cell/></table:table-row>"#,
+            r#"</table:table>"#,
+            r#"<text:p>See<text:note text:id="ftn1" text:note-class="footnote"><text:note-citation>1</text:note-citation>"#,
+            r#"<text:note-body><text:p>The note</text:p></text:note-body></text:note> <text:a xlink:href="https://example.com">this</text:a>"#,
+            r#"<draw:frame svg:width="1in" svg:height="0.5in"><draw:image xlink:href="Pictures/a.png"/><svg:desc>A picture</svg:desc></draw:frame></text:p>"#,
+        );
+        let parsed = parse_odt(&odt(body, automatic, "")).unwrap();
+        let document = &parsed.word_document;
+
+        let lists: Vec<_> = document.paragraphs[..4]
+            .iter()
+            .map(|p| (p.text.as_str(), p.properties.num_id.as_deref(), p.properties.list_level))
+            .collect();
+        assert_eq!(
+            lists,
+            vec![("First", Some("1"), Some(0)), ("Nested", Some("1"), Some(1)), ("Between", None, None), ("Second", Some("1"), Some(0))]
+        );
+        let levels = &document.numbering[0].abstract_num_defs[0].levels;
+        assert_eq!((levels[0].format.as_str(), levels[0].text.as_str()), ("decimal", "%1."));
+        assert_eq!((levels[1].format.as_str(), levels[1].text.as_str()), ("lowerLetter", "%1.%2)"));
+
+        let table = &document.tables[0];
+        assert_eq!(table.paragraph_index, 4);
+        assert!(table.rows[0].properties.is_header);
+        assert_eq!(table.rows[0].cells.len(), 2);
+        assert_eq!(table.rows[0].cells[0].properties.grid_span, Some(2));
+        assert_eq!(table.rows[0].cells[1].vertical_merge, Some(1));
+        assert_eq!(table.rows[1].cells.len(), 3);
+        assert_eq!(table.rows[1].cells[2].vertical_merge, Some(-1));
+
+        let paragraph = &document.paragraphs[4];
+        assert_eq!(paragraph.text, "See this");
+        assert_eq!(paragraph.note_references[0].position, 3);
+        assert_eq!(document.footnotes[0].paragraphs[0].text, "The note");
+        assert_eq!(paragraph.hyperlinks[0].url.as_deref(), Some("https://example.com"));
+        assert_eq!((paragraph.hyperlinks[0].start, paragraph.hyperlinks[0].length), (4, 4));
+        let image = &document.images[0];
+        assert_eq!((image.paragraph_index, image.position), (4, 8));
+        assert_eq!(image.extent(), Some((914_400, 457_200)));
+        assert_eq!(image.alt_description.as_deref(), Some("A picture"));
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let mut heading = paragraph("Report");
+        heading.properties.style_id = Some("Heading1".to_string());
+        let mut body = paragraph("");
+        body.runs = vec![
+            Run {
+                text: "  Bold".to_string(),
+                properties: RunProperties {
+                    bold: Some(true),
+                    ..Default::default()
+                },
+            },
+            Run {
+                text: " and  plain<&> text with a note".to_string(),
+                properties: RunProperties::default(),
+            },
+        ];
+        body.text = body.runs.iter().map(|run| run.text.as_str()).collect();
+        body.properties.alignment = Some("both".to_string());
+        body.properties.indent_left = Some(720);
+        body.note_references.push(NoteReference {
+            kind: NoteKind::Footnote,
+            id: "7".to_string(),
+            position: 6,
+        });
+        body.hyperlinks.push(Hyperlink {
+            anchor: Some("top".to_string()),
+            start: 8,
+            length: 3,
+            ..Default::default()
+        });
+        let mut item = paragraph("Item");
+        item.properties.num_id = Some("3".to_string());
+        item.properties.list_level = Some(0);
+        let cell = |text: &str, vertical_merge| TableCell {
+            paragraphs: vec![paragraph(text)],
+            vertical_merge,
+            ..Default::default()
+        };
+        let table = Table {
+            rows: vec![
+                TableRow {
+                    cells: vec![cell("tall", Some(1)), cell("b", None)],
+                    ..Default::default()
+                },
+                TableRow {
+                    cells: vec![cell("", Some(-1)), cell("d", None)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let mut model = DocumentModel {
+            body: vec![
+                Block::from(heading),
+                Block::from(body),
+                Block::from(item),
+                Block::Table(Box::new(table)),
+                Block::from(paragraph("")),
+            ],
+            footnotes: vec![Footnote {
+                id: "7".to_string(),
+                footnote_type: None,
+                paragraphs: vec![paragraph("Noted")],
+            }],
+            numbering: vec![Numbering {
+                abstract_num_defs: vec![AbstractNumDef {
+                    abstract_num_id: "0".to_string(),
+                    levels: vec![ListLevel {
+                        level: 0,
+                        format: "decimal".to_string(),
+                        text: "%1.".to_string(),
+                        start_value: 1,
+                        ..Default::default()
+                    }],
+                }],
+                num_instances: vec![NumInstance {
+                    num_id: "3".to_string(),
+                    abstract_num_id: "0".to_string(),
+                    overrides: Vec::new(),
+                }],
+            }],
+            images: vec![DocumentImage {
+                id: "image1".to_string(),
+                path: "media/image1.png".to_string(),
+                desired_width: Some(635_000),
+                desired_height: Some(317_500),
+                paragraph_index: 2,
+                position: 4,
+                ..Default::default()
+            }],
+            sections: vec![Section {
+                page_width: Some(16838),
+                page_height: Some(11906),
+                landscape: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        model.metadata.title = Some("Quarterly".to_string());
+        model.styles.insert(
+            "Heading1".to_string(),
+            Style {
+                id: "Heading1".to_string(),
+                name: Some("heading 1".to_string()),
+                style_type: "paragraph".to_string(),
+                run_properties: RunProperties {
+                    font_size: Some(28),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let png = b"\x89PNG\r\n\x1a\n not really".to_vec();
+        let media = HashMap::from([("media/image1.png".to_string(), png.clone())]);
+
+        let data = export_odt(&model, &media).unwrap();
+        assert!(is_odt(&data));
+        let parsed = parse_odt(&data).unwrap();
+        let document = &parsed.word_document;
+
+        let texts: Vec<&str> = document.paragraphs.iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(texts, vec!["Report", "  Bold and  plain<&> text with a note", "Item", ""]);
+        assert_eq!(document.paragraphs[0].properties.style_id.as_deref(), Some("Heading1"));
+        assert_eq!(document.styles["Heading1"].run_properties.font_size, Some(28));
+
+        let body = &document.paragraphs[1];
+        assert_eq!(body.runs[0].properties.bold, Some(true));
+        assert_eq!(body.properties.alignment.as_deref(), Some("both"));
+        assert_eq!(body.properties.indent_left, Some(720));
+        assert_eq!(body.note_references[0].position, 6);
+        assert_eq!(document.footnotes[0].paragraphs[0].text, "Noted");
+        assert_eq!(body.hyperlinks[0].anchor.as_deref(), Some("top"));
+        assert_eq!((body.hyperlinks[0].start, body.hyperlinks[0].length), (8, 3));
+
+        assert_eq!(document.paragraphs[2].properties.num_id.as_deref(), Some("1"));
+        assert_eq!(document.numbering[0].abstract_num_defs[0].levels[0].text, "%1.");
+
+        let table = &document.tables[0];
+        assert_eq!(table.paragraph_index, 3);
+        assert_eq!(table.rows[0].cells[0].vertical_merge, Some(1));
+        assert_eq!(table.rows[1].cells[0].vertical_merge, Some(-1));
+        assert_eq!(table.rows[1].cells[1].paragraphs[0].text, "d");
+
+        let image = &document.images[0];
+        assert_eq!((image.paragraph_index, image.position), (2, 4));
+        assert_eq!(image.extent(), Some((635_000, 317_500)));
+        assert_eq!(parsed.media[&image.path], png);
+
+        let section = &document.sections[0];
+        assert_eq!((section.page_width, section.page_height, section.landscape), (Some(16838), Some(11906), true));
+        assert_eq!(parsed.document.title.as_deref(), Some("Quarterly"));
+    }
+
+    #[test]
+    fn test_not_text_document() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("mimetype", FileOptions::default().compression_method(CompressionMethod::Stored)).unwrap();
+        zip.write_all(b"application/vnd.oasis.opendocument.spreadsheet").unwrap();
+        let data = zip.finish().unwrap().into_inner();
+        assert!(!is_odt(&data));
+        assert!(matches!(parse_odt(&data), Err(OdfError::NotText(_))));
+        assert!(matches!(parse_odt(b"not a zip"), Err(OdfError::Package(_))));
+    }
+}
@@ -1,9 +1,11 @@
 //! OPC (Open Packaging Conventions) Package Reader
 //! Reads and parses ZIP-based Office Open XML documents (.docx, .xlsx, .pptx)
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::io::{Cursor, Read, Seek};
-use zip::result::ZipResult;
+use std::sync::Arc;
+use zip::result::{ZipError, ZipResult};
 use zip::ZipArchive;
 
 use super::error::OoxmlError;
@@ -20,6 +22,37 @@ pub struct OpcPackage {
     pub root_relationships: Vec<Relationship>,
     /// Relationships indexed by source part name
     pub relationships: HashMap<String, Vec<Relationship>>,
+    /// The archive the package was read from
+    source: SourceArchive,
+    /// Parts replaced or marked changed since the package was read
+    dirty: HashSet<String>,
+}
+
+/// A ZIP entry of the archive a package was read from
+#[derive(Debug, Clone)]
+struct SourceEntry {
+    /// Entry name as stored, which may differ from the part name by a leading slash
+    name: String,
+    crc32: u32,
+    size: u64,
+}
+
+/// The archive a package was read from, for copying unchanged entries on save
+#[derive(Clone, Default)]
+struct SourceArchive {
+    /// The archive bytes, kept only by [`OpcPackage::open`]
+    data: Option<Arc<[u8]>>,
+    /// Every entry by part name, [Content_Types].xml and the relationships included
+    entries: HashMap<String, SourceEntry>,
+}
+
+impl fmt::Debug for SourceArchive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceArchive")
+            .field("bytes", &self.data.as_ref().map(|data| data.len()))
+            .field("entries", &self.entries.len())
+            .finish()
+    }
 }
 
 impl OpcPackage {
@@ -28,12 +61,7 @@ impl OpcPackage {
         let reader = Cursor::new(file_data);
         let mut archive = ZipArchive::new(reader)?;
 
-        let mut package = OpcPackage {
-            parts: HashMap::new(),
-            content_types: HashMap::new(),
-            root_relationships: Vec::new(),
-            relationships: HashMap::new(),
-        };
+        let mut package = OpcPackage::default();
 
         // Parse [Content_Types].xml
         package.parse_content_types(&mut archive)?;
@@ -45,11 +73,40 @@ impl OpcPackage {
         package.parse_all_relationships(&mut archive)?;
 
         // Extract all parts from the archive
-        package.extract_parts(&mut archive)?;
+        package.extract_parts(&mut archive, |_| true)?;
+
+        Ok(package)
+    }
+
+    /// Read the content types, the relationships and the parts `include` accepts,
+    /// leaving every other part compressed in `archive`
+    pub(crate) fn read_parts<R: Read + Seek>(
+        archive: &mut ZipArchive<R>,
+        include: impl Fn(&str) -> bool,
+    ) -> Result<Self, OoxmlError> {
+        let mut package = OpcPackage::default();
+        package.parse_content_types(archive)?;
+        package.parse_root_relationships(archive)?;
+        package.parse_all_relationships(archive)?;
+        package.extract_parts(archive, include)?;
+        Ok(package)
+    }
 
+    /// Create a package from ZIP file data, keeping the archive so that
+    /// [`DocxSerializer::export_docx_incremental`](super::DocxSerializer::export_docx_incremental)
+    /// can copy the entries of unchanged parts instead of compressing them again
+    pub fn open(file_data: Arc<[u8]>) -> Result<Self, OoxmlError> {
+        let mut package = Self::new(&file_data)?;
+        package.keep_source(file_data);
         Ok(package)
     }
 
+    /// Keep `file_data`, the archive the package was read from, as
+    /// [`OpcPackage::open`] does
+    pub(crate) fn keep_source(&mut self, file_data: Arc<[u8]>) {
+        self.source.data = Some(file_data);
+    }
+
     /// Helper function to read file content from archive (tries multiple path variants)
     fn read_file_from_archive<R: Read + Seek>(
         archive: &mut ZipArchive<R>,
@@ -125,20 +182,34 @@ impl OpcPackage {
         let mut relationships = Vec::new();
         
         // <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/>
-        let rel_pattern = regex::Regex::new(r#"<Relationship\s+Id="([^"]+)"\s+Type="([^"]+)"\s+Target="([^"]+)"\s*/>"#).unwrap();
+        // Attribute order is not fixed, and external targets carry TargetMode="External"
+        let rel_pattern = regex::Regex::new(r#"<Relationship\s([^>]*?)/?>"#).unwrap();
+        let attr_pattern = regex::Regex::new(r#"(\w+)="([^"]*)""#).unwrap();
         
         for cap in rel_pattern.captures_iter(&xml_str) {
-            let id = cap[1].to_string();
-            let type_uri = cap[2].to_string();
-            let target = cap[3].to_string();
-            
-            let rel_type = RelationshipType::from_string(&type_uri);
-            
+            let mut id = None;
+            let mut type_uri = None;
+            let mut target = None;
+            let mut target_mode = None;
+            for attr in attr_pattern.captures_iter(&cap[1]) {
+                let value = Some(super::document::unescape_xml_text(&attr[2]));
+                match &attr[1] {
+                    "Id" => id = value,
+                    "Type" => type_uri = value,
+                    "Target" => target = value,
+                    "TargetMode" => target_mode = value,
+                    _ => {}
+                }
+            }
+
+            let (Some(id), Some(type_uri), Some(target)) = (id, type_uri, target) else {
+                continue;
+            };
             relationships.push(Relationship {
                 id,
-                relationship_type: rel_type,
+                relationship_type: RelationshipType::from_string(&type_uri),
                 target,
-                target_mode: None,
+                target_mode,
             });
         }
 
@@ -147,34 +218,23 @@ impl OpcPackage {
 
     /// Parse relationships files for each part
     fn parse_all_relationships<R: Read + Seek>(&mut self, archive: &mut ZipArchive<R>) -> ZipResult<()> {
-        // Find all .rels files by looking at the content types
-        let mut rel_files: Vec<String> = self.content_types.keys()
-            .filter(|name| name.ends_with(".rels"))
-            .cloned()
+        // Every "<dir>/_rels/<name>.rels" entry except the package-level _rels/.rels
+        let rel_files: Vec<String> = archive
+            .file_names()
+            .map(|name| name.trim_start_matches('/'))
+            .filter(|name| name.ends_with(".rels") && *name != "_rels/.rels" && name.contains("_rels/"))
+            .map(str::to_string)
             .collect();
 
-        // Also check for word/_rels/*.rels files
-        for part_name in self.content_types.keys().cloned().collect::<Vec<_>>() {
-            if part_name.starts_with("word/") && !part_name.ends_with(".rels") {
-                // This is a part that might have relationships
-                let rel_path = format!("{}/_rels/{}.rels", 
-                    part_name.rsplit_once('/').map_or("", |(p, _)| p),
-                    part_name.rsplit('/').next().unwrap_or(&part_name));
-                if !rel_files.contains(&rel_path) {
-                    rel_files.push(rel_path);
-                }
-            }
-        }
-
         for rel_file in rel_files {
             if let Some(xml_data) = Self::read_file_from_archive(archive, &[&rel_file]) {
                 let relationships = Self::parse_relationships_xml(&xml_data);
                 if !relationships.is_empty() {
-                    // Store relationships keyed by the source part (derive from .rels path)
-                    let source_part = rel_file
-                        .strip_suffix(".rels")
-                        .unwrap_or(&rel_file)
-                        .replace("/_rels/", "/");
+                    // Store relationships keyed by the source part: word/_rels/document.xml.rels -> /word/document.xml
+                    let Some((dir, file)) = rel_file.rsplit_once("_rels/") else {
+                        continue;
+                    };
+                    let source_part = format!("/{}{}", dir, file.strip_suffix(".rels").unwrap_or(file));
                     self.relationships.insert(source_part, relationships);
                 }
             }
@@ -183,26 +243,37 @@ impl OpcPackage {
         Ok(())
     }
 
-    /// Extract all parts from the archive
-    fn extract_parts<R: Read + Seek>(&mut self, archive: &mut ZipArchive<R>) -> ZipResult<()> {
+    /// Extract the parts `include` accepts from the archive
+    fn extract_parts<R: Read + Seek>(
+        &mut self,
+        archive: &mut ZipArchive<R>,
+        include: impl Fn(&str) -> bool,
+    ) -> ZipResult<()> {
         for i in 0..archive.len() {
             let mut file = archive.by_index(i)?;
             let name = file.name().to_string();
-            
+            self.source.entries.insert(
+                format!("/{}", name.trim_start_matches('/')),
+                SourceEntry { name: name.clone(), crc32: file.crc32(), size: file.size() },
+            );
+
             // Skip special files
             if name.starts_with('_') || name == "[Content_Types].xml" {
                 continue;
             }
 
-            // Get content type from our map
-            let content_type = self.content_types.get(&name).cloned();
+            // Part names are absolute ("/word/document.xml"), zip entry names are not
+            let part_name = format!("/{}", name.trim_start_matches('/'));
+            if !include(&part_name) {
+                continue;
+            }
 
-            if let Some(ct) = content_type {
+            if let Some(ct) = self.content_type_of(&part_name) {
                 let mut data = Vec::new();
                 file.read_to_end(&mut data)?;
 
-                self.parts.insert(name.clone(), PackagePart {
-                    name: name.clone(),
+                self.parts.insert(part_name.clone(), PackagePart {
+                    name: part_name,
                     content_type: ct,
                     data,
                 });
@@ -212,11 +283,88 @@ impl OpcPackage {
         Ok(())
     }
 
+    /// Content type of a part from its Override, falling back to the extension Default
+    fn content_type_of(&self, part_name: &str) -> Option<ContentType> {
+        self.content_types.get(part_name).cloned().or_else(|| {
+            let extension = part_name.rsplit_once('.').map(|(_, ext)| ext)?;
+            self.content_types.get(&format!("/{}", extension)).cloned()
+        })
+    }
+
+    /// Read part `part_name` from `archive` without adding it to the package;
+    /// None if the archive has no such entry or its content type is unknown
+    pub(crate) fn read_part<R: Read + Seek>(
+        &self,
+        archive: &mut ZipArchive<R>,
+        part_name: &str,
+    ) -> ZipResult<Option<PackagePart>> {
+        self.read_part_prefix(archive, part_name, u64::MAX)
+    }
+
+    /// Like [`read_part`](Self::read_part), reading at most `max_bytes` of the part
+    pub(crate) fn read_part_prefix<R: Read + Seek>(
+        &self,
+        archive: &mut ZipArchive<R>,
+        part_name: &str,
+        max_bytes: u64,
+    ) -> ZipResult<Option<PackagePart>> {
+        let Some(content_type) = self.content_type_of(part_name) else {
+            return Ok(None);
+        };
+        let file = match archive.by_name(part_name.trim_start_matches('/')) {
+            Ok(file) => file,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut data = Vec::with_capacity(file.size().min(max_bytes) as usize);
+        file.take(max_bytes).read_to_end(&mut data)?;
+        Ok(Some(PackagePart { name: part_name.to_string(), content_type, data }))
+    }
+
     /// Get a part by name
     pub fn get_part(&self, name: &str) -> Option<&PackagePart> {
         self.parts.get(name)
     }
 
+    /// Add or replace a part, marking it changed
+    pub fn set_part(&mut self, part: PackagePart) {
+        self.dirty.insert(part.name.clone());
+        self.parts.insert(part.name.clone(), part);
+    }
+
+    /// Mark part `name` changed, so an incremental save writes it anew
+    pub fn mark_dirty(&mut self, name: &str) {
+        self.dirty.insert(name.to_string());
+    }
+
+    /// Whether part `name` was replaced or marked changed since the package was read
+    pub fn is_dirty(&self, name: &str) -> bool {
+        self.dirty.contains(name)
+    }
+
+    /// The archive the package was opened from, if it was kept
+    pub(crate) fn source_archive(&self) -> Option<ZipArchive<Cursor<Arc<[u8]>>>> {
+        let data = self.source.data.clone()?;
+        ZipArchive::new(Cursor::new(data)).ok()
+    }
+
+    /// Name of the source archive entry of part `name`, if the archive was
+    /// kept and the part is not dirty
+    pub(crate) fn source_entry(&self, name: &str) -> Option<&str> {
+        if self.source.data.is_none() || self.is_dirty(name) {
+            return None;
+        }
+        self.source.entries.get(name).map(|entry| entry.name.as_str())
+    }
+
+    /// Name of the source archive entry holding exactly `data` as part `name`:
+    /// the part is not dirty and the entry's size and CRC-32 match
+    pub(crate) fn unchanged_entry(&self, name: &str, data: &[u8]) -> Option<&str> {
+        self.source_entry(name)?;
+        let entry = self.source.entries.get(name)?;
+        (entry.size == data.len() as u64 && entry.crc32 == crc32fast::hash(data)).then_some(entry.name.as_str())
+    }
+
     /// Get content type for a part
     pub fn get_content_type(&self, name: &str) -> Option<ContentType> {
         self.content_types.get(name).cloned()
@@ -226,11 +374,37 @@ impl OpcPackage {
     pub fn get_relationships(&self, source: &str) -> Option<&Vec<Relationship>> {
         self.relationships.get(source)
     }
+
+    /// Check whether the package carries a VBA project
+    ///
+    /// The project is only ever detected and carried as opaque bytes; it is never executed.
+    pub fn has_macros(&self) -> bool {
+        self.parts.values().any(|part| {
+            part.content_type == ContentType::VbaProject || part.name.ends_with("/vbaProject.bin")
+        })
+    }
+
+    /// Get all macro-related parts (the VBA project, its metadata and its relationships)
+    pub fn macro_parts(&self) -> Vec<&PackagePart> {
+        let mut parts: Vec<&PackagePart> = self
+            .parts
+            .values()
+            .filter(|part| {
+                matches!(part.content_type, ContentType::VbaProject | ContentType::VbaData)
+                    || part.name.ends_with("/vbaProject.bin")
+                    || part.name.ends_with("/vbaData.xml")
+                    || part.name.ends_with("/_rels/vbaProject.bin.rels")
+            })
+            .collect();
+        parts.sort_by(|a, b| a.name.cmp(&b.name));
+        parts
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::zip_fixture;
 
     #[test]
     fn test_parse_relationships_xml() {
@@ -245,4 +419,93 @@ mod tests {
         assert_eq!(relationships[0].id, "rId1");
         assert_eq!(relationships[0].target, "word/document.xml");
     }
+
+    #[test]
+    fn test_parse_relationships_any_attribute_order() {
+        let xml = r#"<Relationships>
+    <Relationship Target="https://example.com/" TargetMode="External" Id="rId7" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" />
+</Relationships>"#;
+
+        let relationships = OpcPackage::parse_relationships_xml(xml.as_bytes());
+        assert_eq!(relationships.len(), 1);
+        assert_eq!(relationships[0].id, "rId7");
+        assert_eq!(relationships[0].relationship_type, RelationshipType::Hyperlink);
+        assert_eq!(relationships[0].target_mode.as_deref(), Some("External"));
+    }
+
+    const CONTENT_TYPES: &[u8] = br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+    <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+    <Default Extension="png" ContentType="image/png"/>
+    <Override PartName="/word/document.xml" ContentType="application/vnd.ms-word.document.macroEnabled.main+xml"/>
+    <Override PartName="/word/vbaProject.bin" ContentType="application/vnd.ms-office.vbaProject"/>
+</Types>"#;
+
+    #[test]
+    fn test_parts_keyed_by_absolute_name() {
+        let data = zip_fixture([
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("word/document.xml", b"<w:document/>"),
+            ("word/media/image1.png", b"\x89PNG"),
+        ]);
+
+        let package = OpcPackage::new(&data).unwrap();
+        let main = package.get_part("/word/document.xml").unwrap();
+        assert_eq!(main.content_type, ContentType::MacroEnabledDocument);
+        // Content type resolved through the extension Default
+        let image = package.get_part("/word/media/image1.png").unwrap();
+        assert_eq!(image.content_type, ContentType::ImagePng);
+    }
+
+    #[test]
+    fn test_part_relationships_keyed_by_source() {
+        let rels = br#"<Relationships><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/image1.png"/></Relationships>"#;
+        let data = zip_fixture([
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("word/document.xml", b"<w:document/>"),
+            ("word/_rels/document.xml.rels", rels),
+        ]);
+
+        let package = OpcPackage::new(&data).unwrap();
+        let relationships = package.get_relationships("/word/document.xml").unwrap();
+        assert_eq!(relationships[0].target, "media/image1.png");
+    }
+
+    #[test]
+    fn test_relationship_targets_name_parts() {
+        let rels = br#"<Relationships><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/image1.png"/></Relationships>"#;
+        let data = zip_fixture([
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("word/document.xml", b"<w:document/>".as_slice()),
+            ("word/_rels/document.xml.rels", rels),
+            ("word/media/image1.png", b"\x89PNG"),
+        ]);
+
+        // A target resolves to an absolute part name, which is what a part is found by
+        let package = OpcPackage::new(&data).unwrap();
+        let target = &package.get_relationships("/word/document.xml").unwrap()[0].target;
+        let image = package.get_part(&crate::ooxml::resolve_part_name("/word/", target)).unwrap();
+        assert_eq!(image.name, "/word/media/image1.png");
+    }
+
+    #[test]
+    fn test_macro_detection() {
+        let data = zip_fixture([
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("word/document.xml", b"<w:document/>"),
+            ("word/vbaProject.bin", b"\xD0\xCF\x11\xE0"),
+        ]);
+
+        let package = OpcPackage::new(&data).unwrap();
+        assert!(package.has_macros());
+        let parts = package.macro_parts();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].name, "/word/vbaProject.bin");
+
+        let plain = zip_fixture([
+            ("[Content_Types].xml", CONTENT_TYPES),
+            ("word/document.xml", b"<w:document/>"),
+        ]);
+        assert!(!OpcPackage::new(&plain).unwrap().has_macros());
+    }
 }
@@ -0,0 +1,542 @@
+//! Style Organizer
+//! Copies style definitions from another document or template into a package,
+//! as Word's Organizer does. Chosen styles bring along the styles they are
+//! based on, linked to or followed by when the target lacks them, the list
+//! definitions they number with, and their latent style settings. Definitions
+//! are copied as XML, so properties the parser does not model survive.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use super::document::unescape_xml_text;
+use super::error::OoxmlError;
+use super::parts::{empty_part, entry_text, insert_before_closing, read_entries, set_part, write_entries};
+use super::serializer::escape_xml_attr;
+
+const STYLES_PART: &str = "word/styles.xml";
+const NUMBERING_PART: &str = "word/numbering.xml";
+
+/// What to do when a chosen style's ID already exists in the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StyleConflictPolicy {
+    /// Replace the target's definition
+    Overwrite,
+    /// Import under a new ID and name
+    Rename,
+    /// Keep the target's definition
+    Skip,
+}
+
+impl StyleConflictPolicy {
+    /// Look up a policy by name (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "overwrite" => Some(StyleConflictPolicy::Overwrite),
+            "rename" => Some(StyleConflictPolicy::Rename),
+            "skip" => Some(StyleConflictPolicy::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// A style defined in a styles part, for choosing what to import
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StyleSummary {
+    pub id: String,
+    pub name: Option<String>,
+    /// paragraph, character, table or numbering
+    pub style_type: Option<String>,
+    pub based_on: Option<String>,
+}
+
+/// What an import changed in the target
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StyleImportReport {
+    /// Chosen styles now in the target, by target ID
+    pub imported: Vec<String>,
+    /// Chosen styles that replaced a definition with the same ID
+    pub overwritten: Vec<String>,
+    /// Chosen styles imported under a new ID, from source ID to target ID
+    pub renamed: BTreeMap<String, String>,
+    /// Chosen styles left out because the target has the ID and the policy is Skip
+    pub skipped: Vec<String>,
+    /// Chosen IDs the source does not define
+    pub missing: Vec<String>,
+    /// Styles the chosen ones depend on, copied because the target lacked them
+    pub dependencies: Vec<String>,
+    /// List definitions copied for numbered styles
+    pub numbering_definitions: usize,
+    /// Latent style exceptions copied
+    pub latent_exceptions: usize,
+}
+
+/// Styles and list definitions parts of a package, as XML
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StyleParts {
+    pub styles: Option<String>,
+    pub numbering: Option<String>,
+}
+
+impl StyleParts {
+    /// Read the parts from a .docx
+    pub fn from_docx(file_data: &[u8]) -> Result<Self, OoxmlError> {
+        let entries = read_entries(file_data)?;
+        Ok(StyleParts {
+            styles: entry_text(&entries, STYLES_PART),
+            numbering: entry_text(&entries, NUMBERING_PART),
+        })
+    }
+}
+
+/// Styles defined in a .docx
+pub fn list_styles(file_data: &[u8]) -> Result<Vec<StyleSummary>, OoxmlError> {
+    let styles = StyleParts::from_docx(file_data)?.styles.unwrap_or_default();
+    Ok(elements(&styles, "w:style")
+        .into_iter()
+        .filter_map(|range| {
+            let xml = &styles[range];
+            Some(StyleSummary {
+                id: attribute(xml, "w:styleId")?,
+                name: child_value(xml, "w:name"),
+                style_type: attribute(xml, "w:type"),
+                based_on: child_value(xml, "w:basedOn"),
+            })
+        })
+        .collect())
+}
+
+/// Copy the styles with IDs `style_ids` from `source_docx` into `target_docx`
+///
+/// Returns the rewritten target package. Parts the import needs and the target
+/// lacks (styles.xml, numbering.xml) are created.
+pub fn import_styles(
+    target_docx: &[u8],
+    source_docx: &[u8],
+    style_ids: &[String],
+    policy: StyleConflictPolicy,
+) -> Result<(Vec<u8>, StyleImportReport), OoxmlError> {
+    let source = StyleParts::from_docx(source_docx)?;
+    let mut entries = read_entries(target_docx)?;
+    let mut target = StyleParts {
+        styles: entry_text(&entries, STYLES_PART),
+        numbering: entry_text(&entries, NUMBERING_PART),
+    };
+
+    let report = import_style_parts(&mut target, &source, style_ids, policy);
+
+    if let Some(styles) = target.styles {
+        set_part(&mut entries, STYLES_PART, styles, "styles");
+    }
+    if let Some(numbering) = target.numbering {
+        set_part(&mut entries, NUMBERING_PART, numbering, "numbering");
+    }
+    Ok((write_entries(&entries)?, report))
+}
+
+/// Copy the styles with IDs `style_ids` from `source` into `target`
+pub fn import_style_parts(
+    target: &mut StyleParts,
+    source: &StyleParts,
+    style_ids: &[String],
+    policy: StyleConflictPolicy,
+) -> StyleImportReport {
+    let mut report = StyleImportReport::default();
+    let source_styles_xml = source.styles.as_deref().unwrap_or_default();
+    let source_styles: HashMap<String, &str> = elements(source_styles_xml, "w:style")
+        .into_iter()
+        .filter_map(|range| {
+            let xml = &source_styles_xml[range];
+            Some((attribute(xml, "w:styleId")?, xml))
+        })
+        .collect();
+    let target_styles = target.styles.get_or_insert_with(|| empty_part("w:styles"));
+    let target_ids: HashSet<String> = elements(target_styles, "w:style")
+        .into_iter()
+        .filter_map(|range| attribute(&target_styles[range], "w:styleId"))
+        .collect();
+
+    // Chosen styles first, so a chosen style reached as a dependency follows the policy
+    let mut plan: Vec<String> = Vec::new();
+    let mut renames: HashMap<String, String> = HashMap::new();
+    let mut visited: HashSet<String> = HashSet::new();
+    for id in style_ids {
+        if !visited.insert(id.clone()) {
+            continue;
+        }
+        if !source_styles.contains_key(id) {
+            report.missing.push(id.clone());
+            continue;
+        }
+        if target_ids.contains(id) {
+            match policy {
+                StyleConflictPolicy::Skip => {
+                    report.skipped.push(id.clone());
+                    continue;
+                }
+                StyleConflictPolicy::Overwrite => report.overwritten.push(id.clone()),
+                StyleConflictPolicy::Rename => {
+                    let new_id = (1..)
+                        .map(|n| format!("{}_{}", id, n))
+                        .find(|candidate| !target_ids.contains(candidate) && !source_styles.contains_key(candidate))
+                        .expect("unbounded candidates");
+                    report.renamed.insert(id.clone(), new_id.clone());
+                    renames.insert(id.clone(), new_id);
+                }
+            }
+        }
+        report.imported.push(renames.get(id).unwrap_or(id).clone());
+        plan.push(id.clone());
+    }
+
+    let mut pending = plan.clone();
+    while let Some(id) = pending.pop() {
+        for element in ["w:basedOn", "w:link", "w:next"] {
+            let Some(dependency) = child_value(source_styles[&id], element) else {
+                continue;
+            };
+            if target_ids.contains(&dependency)
+                || !source_styles.contains_key(&dependency)
+                || !visited.insert(dependency.clone())
+            {
+                continue;
+            }
+            report.dependencies.push(dependency.clone());
+            plan.push(dependency.clone());
+            pending.push(dependency);
+        }
+    }
+
+    // Rewrite the copies for the target
+    let mut list_ids: HashMap<String, Option<String>> = HashMap::new();
+    let mut copies: Vec<(String, String)> = Vec::with_capacity(plan.len());
+    for id in &plan {
+        let mut xml = remove_open_tag_attribute(source_styles[id], "w:default");
+        for element in ["w:basedOn", "w:link", "w:next"] {
+            if let Some(renamed) = child_value(&xml, element).and_then(|dep| renames.get(&dep)) {
+                xml = set_child_value(&xml, element, renamed);
+            }
+        }
+        if let Some(new_id) = renames.get(id) {
+            xml = set_attribute(&xml, "w:styleId", new_id);
+            if let Some(name) = child_value(&xml, "w:name") {
+                let suffix = &new_id[id.len()..];
+                xml = set_child_value(&xml, "w:name", &format!("{}{}", name, suffix));
+            }
+        }
+        if let Some(num_id) = child_value(&xml, "w:numId").filter(|num_id| num_id != "0") {
+            let copied = list_ids.entry(num_id.clone()).or_insert_with(|| {
+                let copied = source
+                    .numbering
+                    .as_deref()
+                    .and_then(|numbering| copy_list_definition(numbering, &mut target.numbering, &num_id));
+                report.numbering_definitions += usize::from(copied.is_some());
+                copied
+            });
+            if let Some(target_num_id) = copied {
+                xml = set_child_value(&xml, "w:numId", target_num_id);
+            }
+        }
+        copies.push((renames.get(id).unwrap_or(id).clone(), xml));
+    }
+
+    let mut styles = target.styles.take().unwrap_or_default();
+    for id in plan.iter().filter(|id| !renames.contains_key(*id)) {
+        if let Some(name) = child_value(source_styles[id], "w:name") {
+            if copy_latent_exception(source_styles_xml, &mut styles, &name) {
+                report.latent_exceptions += 1;
+            }
+        }
+    }
+
+    // Overwrite in place, back to front so earlier ranges stay valid; append the rest
+    let mut existing: HashMap<String, Range<usize>> = elements(&styles, "w:style")
+        .into_iter()
+        .filter_map(|range| Some((attribute(&styles[range.clone()], "w:styleId")?, range)))
+        .collect();
+    let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+    let mut additions = String::new();
+    for (id, xml) in copies {
+        match existing.remove(&id) {
+            Some(range) => {
+                // The target keeps its own default styles
+                let xml = match attribute(&styles[range.clone()], "w:default") {
+                    Some(default) => xml.replacen("<w:style", &format!("<w:style w:default=\"{}\"", default), 1),
+                    None => xml,
+                };
+                replacements.push((range, xml));
+            }
+            None => additions.push_str(&xml),
+        }
+    }
+    replacements.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    for (range, xml) in replacements {
+        styles.replace_range(range, &xml);
+    }
+    insert_before_closing(&mut styles, "w:styles", &additions);
+    target.styles = Some(styles);
+
+    report
+}
+
+/// Copy list `num_id` and its abstract definition under fresh IDs; returns the new list ID
+fn copy_list_definition(source: &str, target: &mut Option<String>, num_id: &str) -> Option<String> {
+    let num = element_with_attribute(source, "w:num", "w:numId", num_id)?;
+    let abstract_id = child_value(num, "w:abstractNumId")?;
+    let abstract_num = element_with_attribute(source, "w:abstractNum", "w:abstractNumId", &abstract_id)?;
+
+    let target = target.get_or_insert_with(|| empty_part("w:numbering"));
+    let new_abstract_id = next_id(target, "w:abstractNum", "w:abstractNumId", 0);
+    let new_num_id = next_id(target, "w:num", "w:numId", 1);
+
+    // nsid identifies a list across documents; Word assigns a new one to the copy
+    let nsid = Regex::new(r#"<w:nsid\b[^>]*/>"#).unwrap();
+    let abstract_num = nsid.replace(abstract_num, "");
+    let abstract_num = set_attribute(&abstract_num, "w:abstractNumId", &new_abstract_id);
+    let num = set_attribute(num, "w:numId", &new_num_id);
+    let num = set_child_value(&num, "w:abstractNumId", &new_abstract_id);
+
+    // Schema order: abstractNum elements, then num elements, then numIdMacAtCleanup
+    let cleanup = target.find("<w:numIdMacAtCleanup");
+    let first_num = Regex::new(r#"<w:num\b"#).unwrap().find(target).map(|m| m.start());
+    match cleanup {
+        Some(at) => target.insert_str(at, &num),
+        None => insert_before_closing(target, "w:numbering", &num),
+    }
+    match first_num.or(cleanup) {
+        Some(at) => target.insert_str(at, &abstract_num),
+        None => insert_before_closing(target, "w:numbering", &abstract_num),
+    }
+    Some(new_num_id)
+}
+
+/// Copy the latent style exception for style `name`, replacing the target's; false if the source has none
+fn copy_latent_exception(source: &str, target: &mut String, name: &str) -> bool {
+    let pattern = Regex::new(&format!(
+        r#"<w:lsdException\b[^>]*\bw:name="{}"[^>]*/>"#,
+        regex::escape(&escape_xml_attr(name))
+    ))
+    .unwrap();
+    let Some(exception) = pattern.find(source).map(|m| m.as_str().to_string()) else {
+        return false;
+    };
+
+    if let Some(existing) = pattern.find(target).map(|m| m.range()) {
+        target.replace_range(existing, &exception);
+    } else if target.contains("</w:latentStyles>") {
+        insert_before_closing(target, "w:latentStyles", &exception);
+    } else {
+        // Schema order: docDefaults, latentStyles, then the styles
+        let block = format!("<w:latentStyles>{}</w:latentStyles>", exception);
+        match elements(target, "w:style").first() {
+            Some(first) => target.insert_str(first.start, &block),
+            None => insert_before_closing(target, "w:styles", &block),
+        }
+    }
+    true
+}
+
+/// Byte ranges of every `element` element, self-closing or not
+fn elements(xml: &str, element: &str) -> Vec<Range<usize>> {
+    let element = regex::escape(element);
+    Regex::new(&format!(r#"(?s)<{0}\b[^>]*?(?:/>|>.*?</{0}>)"#, element))
+        .unwrap()
+        .find_iter(xml)
+        .map(|m| m.range())
+        .collect()
+}
+
+fn element_with_attribute<'a>(xml: &'a str, element: &str, name: &str, value: &str) -> Option<&'a str> {
+    elements(xml, element)
+        .into_iter()
+        .map(|range| &xml[range])
+        .find(|element| attribute(element, name).as_deref() == Some(value))
+}
+
+/// One more than the largest numeric `name` attribute on `element`s, or `first`
+fn next_id(xml: &str, element: &str, name: &str, first: u32) -> String {
+    elements(xml, element)
+        .into_iter()
+        .filter_map(|range| attribute(&xml[range], name)?.parse::<u32>().ok())
+        .max()
+        .map_or(first, |max| max + 1)
+        .to_string()
+}
+
+fn open_tag(xml: &str) -> &str {
+    &xml[..xml.find('>').map_or(xml.len(), |end| end + 1)]
+}
+
+fn attribute_pattern(name: &str) -> Regex {
+    Regex::new(&format!(r#"(\s{}=")([^"]*)(")"#, regex::escape(name))).unwrap()
+}
+
+/// Attribute `name` of the element's open tag
+fn attribute(xml: &str, name: &str) -> Option<String> {
+    attribute_pattern(name)
+        .captures(open_tag(xml))
+        .map(|caps| unescape_xml_text(&caps[2]))
+}
+
+fn set_attribute(xml: &str, name: &str, value: &str) -> String {
+    let tag_end = open_tag(xml).len();
+    let tag = attribute_pattern(name).replacen(&xml[..tag_end], 1, |caps: &Captures| {
+        format!("{}{}{}", &caps[1], escape_xml_attr(value), &caps[3])
+    });
+    format!("{}{}", tag, &xml[tag_end..])
+}
+
+fn remove_open_tag_attribute(xml: &str, name: &str) -> String {
+    let tag_end = open_tag(xml).len();
+    let tag = attribute_pattern(name).replacen(&xml[..tag_end], 1, "");
+    format!("{}{}", tag, &xml[tag_end..])
+}
+
+fn child_value_pattern(element: &str) -> Regex {
+    Regex::new(&format!(r#"(<{}\b[^>]*\sw:val=")([^"]*)(")"#, regex::escape(element))).unwrap()
+}
+
+/// `w:val` of the first `element` inside `xml`
+fn child_value(xml: &str, element: &str) -> Option<String> {
+    child_value_pattern(element)
+        .captures(xml)
+        .map(|caps| unescape_xml_text(&caps[2]))
+}
+
+fn set_child_value(xml: &str, element: &str, value: &str) -> String {
+    child_value_pattern(element)
+        .replacen(xml, 1, |caps: &Captures| {
+            format!("{}{}{}", &caps[1], escape_xml_attr(value), &caps[3])
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::parts::{CONTENT_TYPES_PART, DOCUMENT_RELS_PART};
+
+    const SOURCE_STYLES: &str = r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:latentStyles w:defQFormat="0"><w:lsdException w:name="heading 1" w:uiPriority="9" w:qFormat="1"/><w:lsdException w:name="Title" w:uiPriority="10"/></w:latentStyles>
+<w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/><w:rPr><w:sz w:val="22"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:basedOn w:val="Normal"/><w:next w:val="Body"/><w:link w:val="Heading1Char"/><w:pPr><w:numPr><w:numId w:val="3"/></w:numPr></w:pPr><w:rPr><w:b/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Body"><w:name w:val="Body"/><w:basedOn w:val="Normal"/></w:style>
+<w:style w:type="character" w:customStyle="1" w:styleId="Heading1Char"><w:name w:val="Heading 1 Char"/><w:link w:val="Heading1"/></w:style>
+</w:styles>"#;
+
+    const SOURCE_NUMBERING: &str = r#"<w:numbering xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:abstractNum w:abstractNumId="7"><w:nsid w:val="1A2B3C4D"/><w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/><w:lvlText w:val="%1."/></w:lvl></w:abstractNum>
+<w:num w:numId="3"><w:abstractNumId w:val="7"/></w:num>
+</w:numbering>"#;
+
+    const TARGET_STYLES: &str = r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/></w:style>
+<w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:rPr><w:i/></w:rPr></w:style>
+</w:styles>"#;
+
+    const TARGET_NUMBERING: &str = r#"<w:numbering xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:abstractNum w:abstractNumId="0"><w:lvl w:ilvl="0"/></w:abstractNum>
+<w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num>
+</w:numbering>"#;
+
+    fn parts(styles: &str, numbering: Option<&str>) -> StyleParts {
+        StyleParts {
+            styles: Some(styles.to_string()),
+            numbering: numbering.map(str::to_string),
+        }
+    }
+
+    fn style<'a>(styles: &'a str, id: &str) -> &'a str {
+        element_with_attribute(styles, "w:style", "w:styleId", id).unwrap()
+    }
+
+    #[test]
+    fn test_overwrite_brings_dependencies_lists_and_latent_settings() {
+        let source = parts(SOURCE_STYLES, Some(SOURCE_NUMBERING));
+        let mut target = parts(TARGET_STYLES, Some(TARGET_NUMBERING));
+
+        let report = import_style_parts(&mut target, &source, &["Heading1".to_string()], StyleConflictPolicy::Overwrite);
+        assert_eq!(report.imported, ["Heading1"]);
+        assert_eq!(report.overwritten, ["Heading1"]);
+        // Normal exists in the target and is left alone
+        let mut dependencies = report.dependencies.clone();
+        dependencies.sort();
+        assert_eq!(dependencies, ["Body", "Heading1Char"]);
+        assert_eq!(report.numbering_definitions, 1);
+        assert_eq!(report.latent_exceptions, 1);
+
+        let styles = target.styles.unwrap();
+        let heading = style(&styles, "Heading1");
+        assert!(heading.contains("<w:b/>") && !heading.contains("<w:i/>"));
+        assert_eq!(child_value(heading, "w:numId").as_deref(), Some("2"));
+        assert!(!style(&styles, "Normal").contains("w:sz"));
+        assert_eq!(elements(&styles, "w:style").len(), 4);
+        assert!(styles.contains(r#"<w:latentStyles><w:lsdException w:name="heading 1""#));
+        assert!(!styles.contains(r#"w:name="Title""#));
+
+        let numbering = target.numbering.unwrap();
+        let num = element_with_attribute(&numbering, "w:num", "w:numId", "2").unwrap();
+        assert_eq!(child_value(num, "w:abstractNumId").as_deref(), Some("1"));
+        let abstract_num = element_with_attribute(&numbering, "w:abstractNum", "w:abstractNumId", "1").unwrap();
+        assert!(abstract_num.contains("%1.") && !abstract_num.contains("w:nsid"));
+        // Abstract definitions stay ahead of the lists
+        assert!(numbering.find(r#"w:abstractNumId="1""#) < numbering.find(r#"<w:num w:numId="1""#));
+    }
+
+    #[test]
+    fn test_rename_rewrites_references() {
+        let source = parts(SOURCE_STYLES, None);
+        let mut target = parts(TARGET_STYLES, None);
+        let chosen = ["Heading1".to_string(), "Heading1Char".to_string(), "Missing".to_string()];
+
+        let report = import_style_parts(&mut target, &source, &chosen, StyleConflictPolicy::Rename);
+        assert_eq!(report.renamed.get("Heading1").map(String::as_str), Some("Heading1_1"));
+        assert_eq!(report.imported, ["Heading1_1", "Heading1Char"]);
+        assert_eq!(report.missing, ["Missing"]);
+        // The source has no list definitions to copy; renamed styles get no latent settings
+        assert_eq!(report.numbering_definitions, 0);
+        assert_eq!(report.latent_exceptions, 0);
+        assert!(target.numbering.is_none());
+
+        let styles = target.styles.unwrap();
+        let renamed = style(&styles, "Heading1_1");
+        assert_eq!(child_value(renamed, "w:name").as_deref(), Some("heading 1_1"));
+        assert_eq!(child_value(style(&styles, "Heading1Char"), "w:link").as_deref(), Some("Heading1_1"));
+        assert!(style(&styles, "Heading1").contains("<w:i/>"));
+    }
+
+    #[test]
+    fn test_skip_keeps_target_definition() {
+        let source = parts(SOURCE_STYLES, Some(SOURCE_NUMBERING));
+        let mut target = parts(TARGET_STYLES, None);
+
+        let report = import_style_parts(&mut target, &source, &["Normal".to_string(), "Heading1".to_string()], StyleConflictPolicy::Skip);
+        assert_eq!(report.skipped, ["Normal", "Heading1"]);
+        assert!(report.imported.is_empty() && report.dependencies.is_empty());
+        assert_eq!(target.styles.as_deref(), Some(TARGET_STYLES));
+    }
+
+    #[test]
+    fn test_import_into_package_without_numbering_part() {
+        fn docx(parts: &[(&str, &str)]) -> Vec<u8> {
+            let entries: Vec<_> = parts.iter().map(|(n, d)| (n.to_string(), d.as_bytes().to_vec())).collect();
+            write_entries(&entries).unwrap()
+        }
+        let types = r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"></Types>"#;
+        let rels = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#;
+        let source = docx(&[(STYLES_PART, SOURCE_STYLES), (NUMBERING_PART, SOURCE_NUMBERING)]);
+        let target = docx(&[(CONTENT_TYPES_PART, types), (DOCUMENT_RELS_PART, rels), (STYLES_PART, TARGET_STYLES)]);
+
+        let (output, report) = import_styles(&target, &source, &["Body".to_string(), "Heading1".to_string()], StyleConflictPolicy::Overwrite).unwrap();
+        assert_eq!(report.numbering_definitions, 1);
+
+        let entries = read_entries(&output).unwrap();
+        assert!(entry_text(&entries, NUMBERING_PART).unwrap().contains(r#"<w:num w:numId="1">"#));
+        assert!(entry_text(&entries, CONTENT_TYPES_PART).unwrap().contains("/word/numbering.xml"));
+        assert!(entry_text(&entries, DOCUMENT_RELS_PART).unwrap().contains(r#"Id="rId2""#));
+        let ids: Vec<_> = list_styles(&output).unwrap().into_iter().map(|s| s.id).collect();
+        assert_eq!(ids, ["Normal", "Heading1", "Body", "Heading1Char"]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Package Parts
+//! Whole-package rewrites for commands that edit a few XML parts of a .docx in
+//! place: read the archive entries, replace or add parts, and write it back.
+//! Parts that are added get a content type override and a relationship from
+//! the main document.
+
+use std::io::{Cursor, Read, Write};
+
+use regex::Regex;
+use zip::{ZipArchive, ZipWriter};
+
+use super::compression::PackageCompression;
+use super::error::OoxmlError;
+
+pub(super) const CONTENT_TYPES_PART: &str = "[Content_Types].xml";
+pub(super) const DOCUMENT_RELS_PART: &str = "word/_rels/document.xml.rels";
+const W_NAMESPACE: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+
+/// Insert `content` before the last `</element>`, or append it when there is none
+pub(super) fn insert_before_closing(xml: &mut String, element: &str, content: &str) {
+    let closing = format!("</{}>", element);
+    match xml.rfind(&closing) {
+        Some(at) => xml.insert_str(at, content),
+        None => xml.push_str(content),
+    }
+}
+
+/// An empty part with root element `root` in the WordprocessingML namespace
+pub(super) fn empty_part(root: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<{0} xmlns:w=\"{1}\"></{0}>",
+        root, W_NAMESPACE
+    )
+}
+
+/// Zip entries in archive order, with leading slashes stripped from names
+pub(super) fn read_entries(file_data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, OoxmlError> {
+    let mut archive = ZipArchive::new(Cursor::new(file_data))?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        entries.push((file.name().trim_start_matches('/').to_string(), data));
+    }
+    Ok(entries)
+}
+
+/// Zip `entries`, compressed as a default save would
+pub(super) fn write_entries(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>, OoxmlError> {
+    let compression = PackageCompression::default();
+    let mut buffer = Cursor::new(Vec::new());
+    {
+        let mut zip = ZipWriter::new(&mut buffer);
+        for (name, data) in entries {
+            zip.start_file(name.as_str(), compression.file_options(name))?;
+            zip.write_all(data)?;
+        }
+        zip.finish()?;
+    }
+    Ok(buffer.into_inner())
+}
+
+pub(super) fn entry_text(entries: &[(String, Vec<u8>)], name: &str) -> Option<String> {
+    entries
+        .iter()
+        .find(|(entry, _)| entry == name)
+        .map(|(_, data)| String::from_utf8_lossy(data).into_owned())
+}
+
+/// Replace a part's XML, registering the part if the package lacks it
+pub(super) fn set_part(entries: &mut Vec<(String, Vec<u8>)>, name: &str, xml: String, kind: &str) {
+    if let Some(entry) = entries.iter_mut().find(|(entry, _)| entry == name) {
+        entry.1 = xml.into_bytes();
+        return;
+    }
+    entries.push((name.to_string(), xml.into_bytes()));
+
+    let override_xml = format!(
+        "<Override PartName=\"/{}\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.{}+xml\"/>",
+        name, kind
+    );
+    if let Some(entry) = entries.iter_mut().find(|(entry, _)| entry == CONTENT_TYPES_PART) {
+        let mut types = String::from_utf8_lossy(&entry.1).into_owned();
+        insert_before_closing(&mut types, "Types", &override_xml);
+        entry.1 = types.into_bytes();
+    }
+
+    let mut rels = entry_text(entries, DOCUMENT_RELS_PART).unwrap_or_else(|| {
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\"></Relationships>".to_string()
+    });
+    let next_rel = Regex::new(r#"\sId="rId(\d+)""#)
+        .unwrap()
+        .captures_iter(&rels)
+        .filter_map(|caps| caps[1].parse::<u32>().ok())
+        .max()
+        .map_or(1, |max| max + 1);
+    let relationship = format!(
+        "<Relationship Id=\"rId{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/{}\" Target=\"{}\"/>",
+        next_rel,
+        kind,
+        name.trim_start_matches("word/")
+    );
+    insert_before_closing(&mut rels, "Relationships", &relationship);
+    match entries.iter_mut().find(|(entry, _)| entry == DOCUMENT_RELS_PART) {
+        Some(entry) => entry.1 = rels.into_bytes(),
+        None => entries.push((DOCUMENT_RELS_PART.to_string(), rels.into_bytes())),
+    }
+}
+
@@ -0,0 +1,191 @@
+//! Text, outline and metadata of a .docx without a full parse
+//!
+//! File browsers and search indexing need a document's words, headings and
+//! title, not its styles, numbering, tables or media. [`parse_preview`] reads
+//! only two parts of the package: document.xml, tokenized by the streaming
+//! pull parser without building paragraphs or runs, and docProps/core.xml.
+//! Like [`LazyDocument`](super::LazyDocument), it counts and reads every w:p,
+//! table cells included.
+
+use std::io::{BufReader, Read, Seek};
+
+use serde::{Deserialize, Serialize};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use super::document::{unescape_xml_text, CoreProperties, WordDocument};
+use super::error::OoxmlError;
+use super::lazy::{heading_level, OutlineEntry};
+use super::streaming::{Event, PullParser};
+
+const MAIN_PART: &str = "word/document.xml";
+const CORE_PART: &str = "docProps/core.xml";
+
+/// What a preview or a search index shows of a document
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentPreview {
+    /// Plain text, paragraphs joined by '\n'
+    pub text: String,
+    /// Headings, by outline level or heading style
+    pub outline: Vec<OutlineEntry>,
+    pub paragraph_count: usize,
+    pub char_count: usize,
+    pub word_count: usize,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub created_at: Option<String>,
+    pub modified_at: Option<String>,
+}
+
+/// Read the plain text, outline and core properties of a .docx
+pub fn parse_preview(file_data: &[u8]) -> Result<DocumentPreview, OoxmlError> {
+    let mut archive = ZipArchive::new(std::io::Cursor::new(file_data))?;
+    let mut preview = read_body(&mut archive)?;
+
+    match archive.by_name(CORE_PART) {
+        Ok(mut entry) => {
+            let mut xml = String::new();
+            entry.read_to_string(&mut xml)?;
+            let props = CoreProperties::parse(&xml);
+            preview.title = props.title;
+            preview.author = props.creator;
+            preview.created_at = props.created;
+            preview.modified_at = props.modified;
+        }
+        Err(ZipError::FileNotFound) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    preview.char_count = preview.text.chars().count();
+    preview.word_count = preview.text.split_whitespace().count();
+    Ok(preview)
+}
+
+/// Stream document.xml, keeping the text of w:t elements and the outline
+/// level of each paragraph
+fn read_body<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<DocumentPreview, OoxmlError> {
+    let entry = match archive.by_name(MAIN_PART) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Err(OoxmlError::PartNotFound(format!("/{}", MAIN_PART))),
+        Err(e) => return Err(e.into()),
+    };
+    let mut parser = PullParser::new(BufReader::new(entry));
+    let mut preview = DocumentPreview::default();
+    let mut char_offset = 0usize;
+
+    // Paragraphs in text boxes nest inside the paragraph that anchors them and
+    // count as part of it
+    let mut paragraph_depth = 0usize;
+    let mut in_text = false;
+    let mut paragraph = String::new();
+    let mut style: Option<String> = None;
+    let mut outline_level: Option<u8> = None;
+
+    while let Some(event) = parser.next_event()? {
+        match event {
+            Event::Start { name, empty } => match name.as_str() {
+                "w:p" if paragraph_depth == 0 && empty => {
+                    end_paragraph(&mut preview, &mut char_offset, String::new(), None);
+                }
+                "w:p" if !empty => paragraph_depth += 1,
+                "w:t" => in_text = !empty,
+                "w:pStyle" if paragraph_depth == 1 => {
+                    style = WordDocument::attribute(&String::from_utf8_lossy(parser.raw()), "w:val");
+                }
+                "w:outlineLvl" if paragraph_depth == 1 => {
+                    outline_level = WordDocument::attribute(&String::from_utf8_lossy(parser.raw()), "w:val")
+                        .and_then(|level| level.parse::<u8>().ok())
+                        .filter(|level| *level < 9)
+                        .map(|level| level + 1);
+                }
+                _ => {}
+            },
+            Event::End { name } => match name.as_str() {
+                "w:t" => in_text = false,
+                "w:p" if paragraph_depth > 0 => {
+                    paragraph_depth -= 1;
+                    if paragraph_depth == 0 {
+                        let level = outline_level.take().or_else(|| style.take().as_deref().and_then(heading_level));
+                        style = None;
+                        end_paragraph(&mut preview, &mut char_offset, std::mem::take(&mut paragraph), level);
+                    }
+                }
+                _ => {}
+            },
+            Event::Other if in_text => paragraph.push_str(&unescape_xml_text(&String::from_utf8_lossy(parser.raw()))),
+            Event::Other => {}
+        }
+    }
+    Ok(preview)
+}
+
+fn end_paragraph(preview: &mut DocumentPreview, char_offset: &mut usize, text: String, level: Option<u8>) {
+    let index = preview.paragraph_count;
+    if index > 0 {
+        preview.text.push('\n');
+        *char_offset += 1;
+    }
+    if let Some(level) = level.filter(|_| !text.trim().is_empty()) {
+        preview.outline.push(OutlineEntry {
+            level,
+            text: text.trim().to_string(),
+            paragraph: index,
+            offset: *char_offset,
+        });
+    }
+    *char_offset += text.chars().count();
+    preview.text.push_str(&text);
+    preview.paragraph_count += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::minimal_docx;
+    use zip::CompressionMethod;
+
+    fn build_docx(body: &str, core: Option<&str>) -> Vec<u8> {
+        let mut extra_parts: Vec<(&str, &str)> = core.map(|core| (CORE_PART, core)).into_iter().collect();
+        // Never read by the preview
+        extra_parts.push(("word/styles.xml", "<w:styles><unterminated"));
+        minimal_docx(body, &extra_parts, CompressionMethod::Deflated)
+    }
+
+    #[test]
+    fn test_preview_reads_text_outline_and_metadata() {
+        let body = concat!(
+            r#"<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Introduction</w:t></w:r></w:p>"#,
+            r#"<w:p><w:r><w:t xml:space="preserve">Fish &amp; </w:t></w:r><w:r><w:t>chips</w:t></w:r></w:p>"#,
+            r#"<w:p/>"#,
+            r#"<w:tbl><w:tr><w:tc><w:p><w:r><w:t>Cell</w:t></w:r></w:p></w:tc></w:tr></w:tbl>"#,
+            r#"<w:p><w:pPr><w:outlineLvl w:val="1"/></w:pPr><w:r><w:t>Details</w:t></w:r></w:p>"#,
+        );
+        let core = r#"<cp:coreProperties><dc:title>Menu</dc:title><dc:creator>Sam</dc:creator></cp:coreProperties>"#;
+        let preview = parse_preview(&build_docx(body, Some(core))).unwrap();
+
+        assert_eq!(preview.text, "Introduction\nFish & chips\n\nCell\nDetails");
+        assert_eq!(preview.paragraph_count, 5);
+        assert_eq!(preview.word_count, 6);
+        assert_eq!(
+            preview.outline,
+            vec![
+                OutlineEntry { level: 1, text: "Introduction".to_string(), paragraph: 0, offset: 0 },
+                OutlineEntry { level: 2, text: "Details".to_string(), paragraph: 4, offset: 32 },
+            ]
+        );
+        assert_eq!((preview.title.as_deref(), preview.author.as_deref()), (Some("Menu"), Some("Sam")));
+    }
+
+    #[test]
+    fn test_text_box_paragraphs_join_their_anchor() {
+        let body = concat!(
+            r#"<w:p><w:r><w:t>Before </w:t></w:r><w:r><w:drawing><w:txbxContent>"#,
+            r#"<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>boxed</w:t></w:r></w:p>"#,
+            r#"</w:txbxContent></w:drawing></w:r><w:r><w:t> after</w:t></w:r></w:p>"#,
+        );
+        let preview = parse_preview(&build_docx(body, None)).unwrap();
+        assert_eq!(preview.text, "Before boxed after");
+        assert!(preview.outline.is_empty());
+        assert_eq!(preview.title, None);
+    }
+}
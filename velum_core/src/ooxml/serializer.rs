@@ -6,21 +6,80 @@
 
 use std::collections::HashMap;
 use std::io::{Cursor, Write};
-use zip::write::FileOptions;
+use std::ops::Range;
+use std::sync::Arc;
 use zip::ZipWriter;
 
 use super::error::OoxmlError;
+use super::export::ExportControl;
+use super::html::{data_uri, document_html, HtmlImage};
+use super::app_properties::{app_properties_xml, recorded_total_time, DocumentStatistics, LayoutStatistics};
+use super::compression::PackageCompression;
+use super::doc_vars::set_variable_in_settings;
+use super::forms::{set_protection_in_settings, AnchoredFormField, ProtectionMode};
 use super::opc::OpcPackage;
+use super::text::{document_text, PlainTextOptions};
 use super::types::{
-    ContentType, Paragraph, ParagraphProperties, Relationship, RelationshipType,
-    Run, RunProperties, Style, Theme, ThemeFonts,
+    BookmarkMark, BookmarkMarkKind, Comment, CommentMark, CommentMarkKind, ContentType, DocumentImage, Endnote, Field, Footer, Footnote, Header, Hyperlink, MathZone, Numbering,
+    PackagePart, Paragraph, ParagraphFrame, ParagraphProperties, Relationship, RelationshipType, Revision, RevisionKind, Run, RunProperties,
+    Section, Style, Table, TableBorder, TableCell, TableRow, Theme, ThemeFonts,
 };
-use crate::piece_tree::{PieceTree, TextAttributes};
+use super::whitespace::{run_content, whitespace_lint};
+use crate::bookmarks::{bookmark_marks, paragraph_bookmark_marks, Bookmark};
+use crate::comments::{comment_marks, paragraph_comment_marks};
+use crate::document_model::paragraph_properties;
+use crate::forms::paragraph_form_fields;
+use crate::hyperlinks::paragraph_hyperlinks;
+use crate::image::{ImageCache, ImageData, ImageFormat};
+use crate::index::paragraph_fields;
+use crate::math::{self, paragraph_math_zones};
+use crate::metrics;
+use crate::page_setup::SectionPageSetup;
+use crate::piece_tree::{ParagraphAttributes, PieceTree, TextAttributes, TextSnapshot};
+
+/// Pieces or paragraphs processed between progress reports
+pub(super) const PROGRESS_INTERVAL: usize = 256;
+
+/// Width shared by a table's columns when neither it nor its cells give one:
+/// the text width of a Letter page with one-inch margins, in twips
+const DEFAULT_TABLE_WIDTH: u32 = 9360;
+
+/// Settings elements that come after w:evenAndOddHeaders in schema order
+const SETTINGS_AFTER_EVEN_AND_ODD: [&str; 14] = [
+    "<w:bookFold",
+    "<w:drawingGrid",
+    "<w:displayHorizontalDrawingGridEvery",
+    "<w:displayVerticalDrawingGridEvery",
+    "<w:doNotUseMarginsForDrawingGridOrigin",
+    "<w:doNotShadeFormData",
+    "<w:noPunctuationKerning",
+    "<w:characterSpacingControl",
+    "<w:compat",
+    "<w:docVars",
+    "<w:rsids",
+    "<m:mathPr",
+    "<w:themeFontLang",
+    "<w:clrSchemeMapping",
+];
+
+/// Parts the serializer always writes itself, never copied from the source package
+const REGENERATED_PARTS: &[&str] = &[
+    "/word/document.xml",
+    "/word/_rels/document.xml.rels",
+    "/word/styles.xml",
+    "/word/theme/theme1.xml",
+    "/word/comments.xml",
+    "/word/commentsExtended.xml",
+    "/docProps/core.xml",
+    "/docProps/app.xml",
+];
 
 /// DOCX 序列化器
 pub struct DocxSerializer {
     package: OpcPackage,
     document: WordDocument,
+    /// Loaded image data by image path, written ahead of the source package's media
+    media: HashMap<String, Arc<ImageData>>,
 }
 
 /// 导出选项
@@ -30,6 +89,30 @@ pub struct ExportOptions {
     pub include_images: bool,
     pub include_styles: bool,
     pub include_theme: bool,
+    pub macro_policy: MacroPolicy,
+    /// Page size and margins written to the body's `w:sectPr`
+    pub page_setup: Option<SectionPageSetup>,
+    /// Carry forward source parts Velum does not regenerate (custom XML,
+    /// embedded fonts, settings, ...) along with the relationships to them
+    pub preserve_unknown_parts: bool,
+    /// Where HTML exports put images
+    pub html_images: HtmlImages,
+    /// How plain text exports write lines, tables, notes and lists
+    pub plain_text: PlainTextOptions,
+    /// Write word, character, paragraph, page and line counts and the editing
+    /// time to docProps/app.xml; turned off for privacy, only the application
+    /// name is written
+    pub document_statistics: bool,
+    /// Pages and lines of the latest pagination, left out of app.xml if unknown
+    pub layout_statistics: Option<LayoutStatistics>,
+    /// Minutes spent editing since the document was opened, added to the
+    /// editing time the source package records
+    pub editing_minutes: u64,
+    /// How the ZIP entries of .docx and .docm exports are compressed
+    pub compression: PackageCompression,
+    /// Document variables by name, written to w:docVars in settings.xml over
+    /// any of the same name the source settings have
+    pub document_variables: Vec<(String, String)>,
 }
 
 /// 导出格式
@@ -38,12 +121,38 @@ pub enum ExportFormat {
     Docx,
     Docm,
     FlatOxml,
+    /// One standalone HTML page, see [`DocxSerializer::export_html`]
+    Html,
+    /// UTF-8 plain text, see [`DocxSerializer::export_text`]
+    PlainText,
+}
+
+/// Where an HTML export puts the document's images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HtmlImages {
+    /// In the page, as base64 data URIs
+    #[default]
+    Embed,
+    /// In files the page links to under media/, returned alongside it
+    Link,
+}
+
+/// 宏处理策略
+///
+/// VBA projects are only ever carried as opaque bytes; Velum never executes them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MacroPolicy {
+    /// Keep the VBA project and save with the macro-enabled (.docm) content type
+    #[default]
+    Preserve,
+    /// Drop the VBA project and report a warning
+    Strip,
 }
 
 /// Represents an image to be embedded in the document
 #[derive(Debug, Clone)]
 pub struct ExportImage {
-    /// Unique ID for referencing the image
+    /// ID of the relationship the drawings refer to the image by
     pub id: String,
     /// File path within the media folder
     pub path: String,
@@ -53,6 +162,17 @@ pub struct ExportImage {
     pub mime_type: String,
 }
 
+/// A document exported as HTML
+#[derive(Debug, Clone)]
+pub struct HtmlExport {
+    pub html: String,
+    /// Image files for [`HtmlImages::Link`], to be written to the media folder
+    /// beside the page; empty when images are embedded
+    pub images: Vec<ExportImage>,
+    /// Warnings about content that could not be written
+    pub warnings: Vec<String>,
+}
+
 /// Serialized part to be written to the ZIP archive
 #[derive(Debug, Clone)]
 pub struct SerializedPart {
@@ -77,6 +197,11 @@ pub struct SerializedDocument {
     pub images: Vec<ExportImage>,
     /// Content types map
     pub content_types: HashMap<String, ContentType>,
+    /// Warnings about content that could not be written
+    pub warnings: Vec<String>,
+    /// Source parts carried over unchanged that an incremental save copies
+    /// from the source archive instead of writing from `parts` and `images`
+    pub copied: Vec<String>,
 }
 
 impl Default for ExportOptions {
@@ -86,6 +211,16 @@ impl Default for ExportOptions {
             include_images: true,
             include_styles: true,
             include_theme: true,
+            macro_policy: MacroPolicy::Preserve,
+            page_setup: None,
+            preserve_unknown_parts: true,
+            html_images: HtmlImages::Embed,
+            plain_text: PlainTextOptions::default(),
+            document_statistics: true,
+            layout_statistics: None,
+            editing_minutes: 0,
+            compression: PackageCompression::default(),
+            document_variables: Vec::new(),
         }
     }
 }
@@ -93,14 +228,170 @@ impl Default for ExportOptions {
 impl DocxSerializer {
     /// Create a new serializer from an OPC package
     pub fn new(package: OpcPackage, document: WordDocument) -> Self {
-        DocxSerializer { package, document }
+        DocxSerializer {
+            package,
+            document,
+            media: HashMap::new(),
+        }
+    }
+
+    /// Take the data of the document's images from `cache`; images it does not
+    /// hold are copied from the source package
+    pub fn with_image_cache(mut self, cache: &ImageCache) -> Self {
+        for image in &self.document.images {
+            if let Some(data) = cache.get(&image.path) {
+                self.media.insert(image.path.clone(), data);
+            }
+        }
+        self
+    }
+
+    /// Take the data of the document's images from `media`, by image path, as
+    /// [`Self::with_image_cache`] does from a cache
+    pub fn with_media(mut self, media: &HashMap<String, Arc<ImageData>>) -> Self {
+        for image in &self.document.images {
+            if let Some(data) = media.get(&image.path) {
+                self.media.insert(image.path.clone(), Arc::clone(data));
+            }
+        }
+        self
     }
 
     /// Export the document to DOCX format bytes
     pub fn export_docx(&self, options: Option<ExportOptions>) -> Result<Vec<u8>, OoxmlError> {
+        self.export_docx_with_warnings(options).map(|(data, _)| data)
+    }
+
+    /// Export the document to DOCX format bytes, also returning warnings about dropped content
+    pub fn export_docx_with_warnings(
+        &self,
+        options: Option<ExportOptions>,
+    ) -> Result<(Vec<u8>, Vec<String>), OoxmlError> {
+        self.export_docx_with_control(options, &ExportControl::new())
+    }
+
+    /// Export with progress reporting; fails with `OoxmlError::Cancelled` once cancelled
+    ///
+    /// Progress runs from 0.5 to 1.0 here: the first half is left for building
+    /// the document from a snapshot.
+    pub fn export_docx_with_control(
+        &self,
+        options: Option<ExportOptions>,
+        control: &ExportControl,
+    ) -> Result<(Vec<u8>, Vec<String>), OoxmlError> {
+        self.export(options, control, false)
+    }
+
+    /// Export as [`DocxSerializer::export_docx_with_control`], copying the ZIP
+    /// entries of the parts that come out byte for byte as they were from the
+    /// archive the package was opened from, compressed data and all
+    ///
+    /// Parts carried over from the package (its media, macros and parts the
+    /// export does not write itself) are copied without comparing them.
+    /// Only a package made by [`OpcPackage::open`] keeps its archive; for any
+    /// other the whole document is compressed as usual. Parts the package
+    /// marks dirty are always written anew.
+    pub fn export_docx_incremental(
+        &self,
+        options: Option<ExportOptions>,
+        control: &ExportControl,
+    ) -> Result<(Vec<u8>, Vec<String>), OoxmlError> {
+        self.export(options, control, true)
+    }
+
+    fn export(
+        &self,
+        options: Option<ExportOptions>,
+        control: &ExportControl,
+        incremental: bool,
+    ) -> Result<(Vec<u8>, Vec<String>), OoxmlError> {
+        let timer = metrics::Timer::start();
+        let options = options.unwrap_or_default();
+        if options.format == ExportFormat::Html {
+            let export = self.export_html_with_control(Some(options), control)?;
+            timer.record(metrics::DOCUMENT_SAVE_MS);
+            return Ok((export.html.into_bytes(), export.warnings));
+        }
+        if options.format == ExportFormat::PlainText {
+            let text = self.export_text_with_control(Some(options), control)?;
+            timer.record(metrics::DOCUMENT_SAVE_MS);
+            return Ok((text.into_bytes(), Vec::new()));
+        }
+        let compression = options.compression;
+        let serialized = self.serialize(options, control, incremental)?;
+        control.step(0.9)?;
+        let data = self.package_to_zip(&serialized, &compression, incremental)?;
+        control.step(1.0)?;
+        timer.record(metrics::DOCUMENT_SAVE_MS);
+        metrics::counter(metrics::DOCUMENTS_SAVED, 1);
+        Ok((data, serialized.warnings))
+    }
+
+    /// Export the document as one HTML page, with CSS from its styles and
+    /// formatting and its images embedded or linked as `html_images` says
+    ///
+    /// Only `include_images`, `include_styles` and `html_images` of the options apply.
+    pub fn export_html(&self, options: Option<ExportOptions>) -> Result<HtmlExport, OoxmlError> {
+        self.export_html_with_control(options, &ExportControl::new())
+    }
+
+    /// Export as HTML with progress reporting; fails with `OoxmlError::Cancelled` once cancelled
+    ///
+    /// Progress runs from 0.5 to 1.0, as for [`DocxSerializer::export_docx_with_control`].
+    pub fn export_html_with_control(
+        &self,
+        options: Option<ExportOptions>,
+        control: &ExportControl,
+    ) -> Result<HtmlExport, OoxmlError> {
+        let options = options.unwrap_or_default();
+        let mut warnings = Vec::new();
+        let (images, _, image_ids) = match options.include_images {
+            true => self.media(&mut warnings),
+            false => Default::default(),
+        };
+
+        let mut sources = HashMap::new();
+        for image in &self.document.images {
+            let Some(id) = image_ids.get(&image.path) else {
+                continue;
+            };
+            let src = match images.iter().find(|export| &export.id == id) {
+                Some(export) if options.html_images == HtmlImages::Embed => data_uri(&export.mime_type, &export.data),
+                Some(export) => format!("media/{}", export.path),
+                // Linked images keep their target
+                None => image.path.clone(),
+            };
+            let extent = image
+                .extent()
+                .or_else(|| self.media.get(&image.path).map(|data| data.dimensions.to_emu()));
+            sources.insert(image.path.clone(), HtmlImage { src, extent });
+        }
+
+        let html = document_html(&self.document, &sources, options.include_styles, control)?;
+        let images = match options.html_images {
+            HtmlImages::Embed => Vec::new(),
+            HtmlImages::Link => images,
+        };
+        Ok(HtmlExport { html, images, warnings })
+    }
+
+    /// Export the document as plain text, as the `plain_text` options say
+    ///
+    /// Only `plain_text` of the options applies.
+    pub fn export_text(&self, options: Option<ExportOptions>) -> Result<String, OoxmlError> {
+        self.export_text_with_control(options, &ExportControl::new())
+    }
+
+    /// Export as plain text with progress reporting; fails with `OoxmlError::Cancelled` once cancelled
+    ///
+    /// Progress runs from 0.5 to 1.0, as for [`DocxSerializer::export_docx_with_control`].
+    pub fn export_text_with_control(
+        &self,
+        options: Option<ExportOptions>,
+        control: &ExportControl,
+    ) -> Result<String, OoxmlError> {
         let options = options.unwrap_or_default();
-        let serialized = self.serialize(options.clone())?;
-        self.package_to_zip(&serialized, options)
+        document_text(&self.document, &options.plain_text, control)
     }
 
     /// Export the document to a file
@@ -114,12 +405,28 @@ impl DocxSerializer {
         Ok(())
     }
 
-    /// Serialize the document to an intermediate representation
-    fn serialize(&self, options: ExportOptions) -> Result<SerializedDocument, OoxmlError> {
+    /// Serialize the document to an intermediate representation, leaving the
+    /// source parts it carries over to be `copied` when `incremental`
+    fn serialize(
+        &self,
+        options: ExportOptions,
+        control: &ExportControl,
+        incremental: bool,
+    ) -> Result<SerializedDocument, OoxmlError> {
         let mut parts = Vec::new();
-        let images = Vec::new();
         let mut content_types = HashMap::new();
         let mut root_relationships = Vec::new();
+        let mut warnings = Vec::new();
+
+        let (mut images, image_relationships, image_ids) = match options.include_images {
+            true => self.media(&mut warnings),
+            false => Default::default(),
+        };
+        for image in &images {
+            content_types.insert(format!("/word/media/{}", image.path), ContentType::from_string(&image.mime_type));
+        }
+        // Source parts written as they were
+        let mut carried: Vec<String> = Vec::new();
 
         // Generate root relationships
         root_relationships.push(Relationship {
@@ -152,13 +459,116 @@ impl DocxSerializer {
             target_mode: None,
         });
 
-        // Serialize main document
-        let document_part = self.serialize_document(&self.document)?;
-        parts.push(document_part);
+        // Serialize main document, drawing the images that have a relationship
+        let drawings = self.drawings(&image_ids);
+        let mut document_part = self.serialize_document(&self.document, &options, control, &drawings)?;
+        document_part.relationships.extend(image_relationships);
+
+        // Carry the VBA project forward untouched, or drop it if asked to
+        let macro_parts = self.package.macro_parts();
+        let keep_macros = !macro_parts.is_empty() && options.macro_policy == MacroPolicy::Preserve;
+        if keep_macros {
+            carried.extend(macro_parts.iter().map(|part| part.name.clone()));
+            for part in macro_parts {
+                if part.content_type != ContentType::Relationships {
+                    content_types.insert(part.name.clone(), part.content_type.clone());
+                }
+                parts.push(SerializedPart {
+                    path: part.name.clone(),
+                    content_type: part.content_type.clone(),
+                    data: part.data.clone(),
+                    relationships: Vec::new(),
+                });
+            }
+            document_part.relationships.push(Relationship {
+                id: "rIdVbaProject".to_string(),
+                relationship_type: RelationshipType::VbaProject,
+                target: "vbaProject.bin".to_string(),
+                target_mode: None,
+            });
+        } else if !macro_parts.is_empty() {
+            log::warn!("Stripping VBA project from exported document");
+            warnings.push("The document's macros (VBA project) were removed on save".to_string());
+        }
+
+        if !self.document.comments.is_empty() {
+            for part in self.serialize_comments(&self.document.comments)? {
+                content_types.insert(part.path.clone(), part.content_type.clone());
+                let (relationship_type, id) = if part.content_type == ContentType::Comments {
+                    (RelationshipType::Comments, "rIdComments")
+                } else {
+                    (RelationshipType::CommentsExtended, "rIdCommentsExtended")
+                };
+                document_part.relationships.push(Relationship {
+                    id: id.to_string(),
+                    relationship_type,
+                    target: part.path["/word/".len()..].to_string(),
+                    target_mode: None,
+                });
+                parts.push(part);
+            }
+        }
+
+        if !self.document.numbering.is_empty() {
+            let part = self.serialize_numbering(&self.document.numbering);
+            content_types.insert(part.path.clone(), part.content_type.clone());
+            document_part.relationships.push(Relationship {
+                id: "rIdNumbering".to_string(),
+                relationship_type: RelationshipType::Numbering,
+                target: "numbering.xml".to_string(),
+                target_mode: None,
+            });
+            parts.push(part);
+        }
+
+        for (part, relationship) in self.serialize_headers_footers(&self.document)? {
+            content_types.insert(part.path.clone(), part.content_type.clone());
+            document_part.relationships.push(relationship);
+            parts.push(part);
+        }
+
+        let source_settings = self
+            .package
+            .get_part("/word/settings.xml")
+            .map(|part| String::from_utf8_lossy(&part.data).into_owned());
+        // Protection the source has gives way to the document's
+        let protection_changed = self.document.protection != ProtectionMode::None
+            || source_settings.as_deref().is_some_and(|settings| settings.contains("<w:documentProtection"));
+        if self.document.even_and_odd_headers || !options.document_variables.is_empty() || protection_changed {
+            // The source settings are kept, with the flag, variables and protection added
+            let mut settings = source_settings.unwrap_or_else(|| EMPTY_SETTINGS.to_string());
+            if self.document.even_and_odd_headers {
+                settings = settings_with_even_and_odd_headers(settings);
+            }
+            for (name, value) in &options.document_variables {
+                settings = set_variable_in_settings(&settings, name, value);
+            }
+            if protection_changed {
+                settings = set_protection_in_settings(&settings, self.document.protection);
+            }
+            content_types.insert("/word/settings.xml".to_string(), ContentType::Settings);
+            document_part.relationships.push(Relationship {
+                id: "rIdSettings".to_string(),
+                relationship_type: RelationshipType::Settings,
+                target: "settings.xml".to_string(),
+                target_mode: None,
+            });
+            parts.push(SerializedPart {
+                path: "/word/settings.xml".to_string(),
+                content_type: ContentType::Settings,
+                data: settings.into_bytes(),
+                relationships: Vec::new(),
+            });
+        }
+
+        if keep_macros || options.format == ExportFormat::Docm {
+            document_part.content_type = ContentType::MacroEnabledDocument;
+        }
         content_types.insert(
             "/word/document.xml".to_string(),
-            ContentType::MainDocument,
+            document_part.content_type.clone(),
         );
+        parts.push(document_part);
 
         // Serialize styles if requested
         if options.include_styles {
@@ -179,7 +589,7 @@ impl DocxSerializer {
         );
 
         // Serialize app properties
-        let app_part = Self::serialize_app_properties();
+        let app_part = self.serialize_app_properties(&options);
         parts.push(app_part);
         content_types.insert(
             "/docProps/app.xml".to_string(),
@@ -198,30 +608,289 @@ impl DocxSerializer {
             }
         }
 
+        // Text that other applications would strip or collapse the spaces of
+        for part in &parts {
+            let count = whitespace_lint(&String::from_utf8_lossy(&part.data));
+            if count > 0 {
+                warnings.push(format!("{} text elements in {} may lose their spaces when opened", count, part.path));
+            }
+        }
+
+        if options.preserve_unknown_parts {
+            let preserved = self.preserve_source_parts(&mut parts, &images, &mut content_types);
+            carried.extend(preserved.iter().cloned());
+            document_part_relationships(&mut parts, self.preserved_relationships("/word/document.xml", "/word/", &preserved));
+            for mut rel in self.preserved_relationships("", "/", &preserved) {
+                if root_relationships.iter().any(|existing| existing.id == rel.id) {
+                    // Nothing refers to package-level ids, so a clash is settled by renaming
+                    rel.id = (1..)
+                        .map(|n| format!("rId{}", n))
+                        .find(|id| root_relationships.iter().all(|existing| &existing.id != id))
+                        .unwrap_or_default();
+                }
+                root_relationships.push(rel);
+            }
+        }
+
         // Add default content types
         content_types.insert("/rels".to_string(), ContentType::Relationships);
         content_types.insert(".rels".to_string(), ContentType::Relationships);
 
+        let mut copied = Vec::new();
+        if incremental {
+            let mut copy = |name: &str| match self.package.source_entry(name) {
+                Some(_) => {
+                    copied.push(name.to_string());
+                    false
+                }
+                None => true,
+            };
+            parts.retain(|part| !carried.contains(&part.path) || copy(&part.path));
+            // Images whose data the package gave, under their own names
+            images.retain(|image| {
+                let name = format!("/word/media/{}", image.path);
+                let from_source = self
+                    .document
+                    .images
+                    .iter()
+                    .any(|source| !self.media.contains_key(&source.path) && resolve_part_name("/word/", &source.path) == name);
+                !from_source || copy(&name)
+            });
+        }
+
         Ok(SerializedDocument {
             parts,
             root_relationships,
             images,
             content_types,
+            warnings,
+            copied,
         })
     }
 
+    /// Copy every source part the export does not write itself, returning their names
+    fn preserve_source_parts(
+        &self,
+        parts: &mut Vec<SerializedPart>,
+        images: &[ExportImage],
+        content_types: &mut HashMap<String, ContentType>,
+    ) -> Vec<String> {
+        let macro_parts: Vec<&str> = self.package.macro_parts().iter().map(|part| part.name.as_str()).collect();
+        let mut source_parts: Vec<&PackagePart> = self
+            .package
+            .parts
+            .values()
+            .filter(|part| {
+                !REGENERATED_PARTS.contains(&part.name.as_str())
+                    && !macro_parts.contains(&part.name.as_str())
+                    && !parts.iter().any(|written| written.path == part.name)
+                    && !images.iter().any(|image| format!("/word/media/{}", image.path) == part.name)
+            })
+            .collect();
+        source_parts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut preserved = Vec::with_capacity(source_parts.len());
+        for part in source_parts {
+            if part.content_type != ContentType::Relationships {
+                content_types.insert(part.name.clone(), part.content_type.clone());
+            }
+            parts.push(SerializedPart {
+                path: part.name.clone(),
+                content_type: part.content_type.clone(),
+                data: part.data.clone(),
+                relationships: Vec::new(),
+            });
+            preserved.push(part.name.clone());
+        }
+        preserved
+    }
+
+    /// Source relationships of `source` that point at preserved parts or outside the package
+    ///
+    /// An empty `source` means the package-level relationships; `base` is the
+    /// folder their relative targets start from.
+    fn preserved_relationships(&self, source: &str, base: &str, preserved: &[String]) -> Vec<Relationship> {
+        let relationships = if source.is_empty() {
+            Some(&self.package.root_relationships)
+        } else {
+            self.package.get_relationships(source)
+        };
+        relationships
+            .into_iter()
+            .flatten()
+            .filter(|rel| {
+                let external = rel.target_mode.as_deref() == Some("External");
+                // External targets are only meaningful from content parts, not the package root
+                (external && !source.is_empty())
+                    || (!external && preserved.contains(&resolve_part_name(base, &rel.target)))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The media parts of the document's images, and the image relationships of
+    /// the main document part, one per image path, with their IDs by path
+    ///
+    /// An embedded image's data comes from the image cache, or else from the
+    /// source package; one found in neither is left out with a warning. Linked
+    /// images only get an external relationship.
+    fn media(&self, warnings: &mut Vec<String>) -> (Vec<ExportImage>, Vec<Relationship>, HashMap<String, String>) {
+        let mut images: Vec<ExportImage> = Vec::new();
+        let mut relationships: Vec<Relationship> = Vec::new();
+        let mut ids: HashMap<String, String> = HashMap::new();
+        let mut done: Vec<&str> = Vec::new();
+        for image in &self.document.images {
+            if done.contains(&image.path.as_str()) {
+                continue;
+            }
+            done.push(&image.path);
+            let id = format!("rIdImage{}", relationships.len() + 1);
+            if image.is_linked {
+                ids.insert(image.path.clone(), id.clone());
+                relationships.push(Relationship {
+                    id,
+                    relationship_type: RelationshipType::Image,
+                    target: image.path.clone(),
+                    target_mode: Some("External".to_string()),
+                });
+                continue;
+            }
+            let source = self.package.get_part(&resolve_part_name("/word/", &image.path));
+            let (data, format, mime_type) = match (self.media.get(&image.path), source) {
+                (Some(cached), _) => (cached.data.clone(), cached.format, cached.format.mime_type().to_string()),
+                (None, Some(part)) => (
+                    part.data.clone(),
+                    ImageFormat::from_magic_bytes(&part.data),
+                    part.content_type.as_str().to_string(),
+                ),
+                (None, None) => {
+                    warnings.push(format!("The image {} was left out: its data is not loaded", image.path));
+                    continue;
+                }
+            };
+            let path = self.media_name(&image.path, format, &images);
+            ids.insert(image.path.clone(), id.clone());
+            relationships.push(Relationship {
+                id: id.clone(),
+                relationship_type: RelationshipType::Image,
+                target: format!("media/{}", path),
+                target_mode: None,
+            });
+            images.push(ExportImage { id, path, data, mime_type });
+        }
+        (images, relationships, ids)
+    }
+
+    /// Name in word/media of the image at `path`: its own name if it is there
+    /// already, else the first free imageN with the extension of its format
+    fn media_name(&self, path: &str, format: ImageFormat, images: &[ExportImage]) -> String {
+        if let Some(name) = path.strip_prefix("media/").filter(|name| !name.contains('/')) {
+            if images.iter().all(|image| image.path != name) {
+                return name.to_string();
+            }
+        }
+        let extension = match format {
+            ImageFormat::Unknown => path.rsplit_once('.').map_or("bin", |(_, extension)| extension),
+            format => format.extension(),
+        };
+        (1..)
+            .map(|n| format!("image{}.{}", n, extension))
+            .find(|name| {
+                images.iter().all(|image| &image.path != name)
+                    && self.package.get_part(&format!("/word/media/{}", name)).is_none()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Each image's drawing by body paragraph, with the char offset it sits at
+    fn drawings(&self, image_ids: &HashMap<String, String>) -> HashMap<usize, Vec<(usize, String)>> {
+        let mut drawings: HashMap<usize, Vec<(usize, String)>> = HashMap::new();
+        for (n, image) in self.document.images.iter().enumerate() {
+            let Some(id) = image_ids.get(&image.path) else {
+                continue;
+            };
+            let extent = image
+                .extent()
+                .or_else(|| self.media.get(&image.path).map(|data| data.dimensions.to_emu()))
+                .unwrap_or((DEFAULT_IMAGE_EXTENT, DEFAULT_IMAGE_EXTENT));
+            drawings
+                .entry(image.paragraph_index)
+                .or_default()
+                .push((image.position, drawing_xml(image, id, extent, n + 1)));
+        }
+        for marks in drawings.values_mut() {
+            marks.sort_by_key(|&(position, _)| position);
+        }
+        drawings
+    }
+
     /// Serialize the main document body
-    fn serialize_document(&self, document: &WordDocument) -> Result<SerializedPart, OoxmlError> {
+    fn serialize_document(
+        &self,
+        document: &WordDocument,
+        options: &ExportOptions,
+        control: &ExportControl,
+        drawings: &HashMap<usize, Vec<(usize, String)>>,
+    ) -> Result<SerializedPart, OoxmlError> {
         let mut body = String::new();
 
         // Document header
         body.push_str(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
-        body.push_str(r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">"#);
+        body.push_str(r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:m="http://schemas.openxmlformats.org/officeDocument/2006/math" xmlns:wp="http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing">"#);
         body.push_str(r#"<w:body>"#);
 
-        // Serialize each paragraph
-        for para in &document.paragraphs {
-            body.push_str(&self.serialize_paragraph(para)?);
+        // External hyperlink targets are relationships, one per URL
+        let cells = document.tables.iter().flat_map(|table| &table.rows).flat_map(|row| &row.cells);
+        let relationships = hyperlink_relationships(document.paragraphs.iter().chain(cells.flat_map(|cell| &cell.paragraphs)));
+        let link_ids: HashMap<&str, &str> = relationships
+            .iter()
+            .map(|rel| (rel.target.as_str(), rel.id.as_str()))
+            .collect();
+
+        // Sections before the last end with their last paragraph
+        let (last_section, earlier_sections) = match document.sections.split_last() {
+            Some((last, earlier)) => (Some(last), earlier),
+            None => (None, &[][..]),
+        };
+        let section_ends: HashMap<usize, &Section> = earlier_sections
+            .iter()
+            .filter(|section| section.paragraph_count > 0)
+            .map(|section| (section.first_paragraph + section.paragraph_count - 1, section))
+            .collect();
+
+        // Tables go before the paragraph they precede, or after the last
+        let mut tables: Vec<&Table> = document.tables.iter().collect();
+        tables.sort_by_key(|table| table.paragraph_index);
+        let mut tables = tables.into_iter().peekable();
+
+        // Serialize each paragraph, carrying the ends of fields that run on past theirs
+        let total = document.paragraphs.len().max(1) as f32;
+        let mut open_fields = Vec::new();
+        for (i, para) in document.paragraphs.iter().enumerate() {
+            if i % PROGRESS_INTERVAL == 0 {
+                control.step(0.5 + 0.4 * i as f32 / total)?;
+            }
+            while let Some(table) = tables.next_if(|table| table.paragraph_index <= i) {
+                body.push_str(&self.serialize_table(table, &link_ids)?);
+            }
+            let drawings = drawings.get(&i).map_or(&[][..], Vec::as_slice);
+            let mut xml = self.serialize_paragraph(para, &link_ids, drawings, &mut open_fields)?;
+            if let Some(section) = section_ends.get(&i) {
+                let sect_pr = section_properties_xml(Some(section), None);
+                match xml.find("</w:pPr>") {
+                    Some(at) => xml.insert_str(at, &sect_pr),
+                    None => xml.insert_str("<w:p>".len(), &format!("<w:pPr>{}</w:pPr>", sect_pr)),
+                }
+            }
+            body.push_str(&xml);
+        }
+        for table in tables {
+            body.push_str(&self.serialize_table(table, &link_ids)?);
+        }
+
+        // Section properties for the final section
+        if options.page_setup.is_some() || last_section.is_some() {
+            body.push_str(&section_properties_xml(last_section, options.page_setup.as_ref()));
         }
 
         // End document body
@@ -232,22 +901,196 @@ impl DocxSerializer {
             path: "/word/document.xml".to_string(),
             content_type: ContentType::MainDocument,
             data: body.into_bytes(),
-            relationships: Vec::new(),
+            relationships,
         })
     }
 
-    /// Serialize a single paragraph
-    fn serialize_paragraph(&self, para: &Paragraph) -> Result<String, OoxmlError> {
+    /// Serialize a table with its grid, properties and the paragraphs of each cell
+    fn serialize_table(&self, table: &Table, link_ids: &HashMap<&str, &str>) -> Result<String, OoxmlError> {
+        let props = &table.properties;
+        let mut xml = String::from("<w:tbl><w:tblPr>");
+        match props.width {
+            Some(width) => xml.push_str(&format!(r#"<w:tblW w:w="{}" w:type="dxa"/>"#, width)),
+            None => xml.push_str(r#"<w:tblW w:w="0" w:type="auto"/>"#),
+        }
+        if let Some(ref alignment) = props.alignment {
+            xml.push_str(&format!(r#"<w:jc w:val="{}"/>"#, escape_xml_attr(alignment)));
+        }
+        if let Some(indent) = props.indent {
+            xml.push_str(&format!(r#"<w:tblInd w:w="{}" w:type="dxa"/>"#, indent));
+        }
+        let borders = &props.borders;
+        let edges = [
+            ("top", &borders.top),
+            ("left", &borders.left),
+            ("bottom", &borders.bottom),
+            ("right", &borders.right),
+            ("insideH", &borders.inside_horizontal),
+            ("insideV", &borders.inside_vertical),
+        ];
+        if edges.iter().any(|(_, border)| border.is_some()) {
+            xml.push_str("<w:tblBorders>");
+            for (edge, border) in edges {
+                if let Some(border) = border {
+                    xml.push_str(&table_border_xml(edge, border));
+                }
+            }
+            xml.push_str("</w:tblBorders>");
+        }
+        if let Some(ref layout) = props.layout {
+            xml.push_str(&format!(r#"<w:tblLayout w:type="{}"/>"#, escape_xml_attr(layout)));
+        }
+        xml.push_str("</w:tblPr><w:tblGrid>");
+        for width in table_grid(table) {
+            xml.push_str(&format!(r#"<w:gridCol w:w="{}"/>"#, width));
+        }
+        xml.push_str("</w:tblGrid>");
+
+        for row in &table.rows {
+            xml.push_str("<w:tr>");
+            let height = row.properties.height.or(row.height);
+            if height.is_some() || row.properties.is_header {
+                xml.push_str("<w:trPr>");
+                if let Some(height) = height {
+                    xml.push_str(&format!(r#"<w:trHeight w:val="{}""#, height));
+                    if let Some(ref rule) = row.properties.height_rule {
+                        xml.push_str(&format!(r#" w:hRule="{}""#, escape_xml_attr(rule)));
+                    }
+                    xml.push_str("/>");
+                }
+                if row.properties.is_header {
+                    xml.push_str("<w:tblHeader/>");
+                }
+                xml.push_str("</w:trPr>");
+            }
+            for cell in &row.cells {
+                xml.push_str(&self.serialize_table_cell(cell, link_ids)?);
+            }
+            xml.push_str("</w:tr>");
+        }
+        xml.push_str("</w:tbl>");
+        Ok(xml)
+    }
+
+    /// Serialize a table cell; a cell needs a paragraph even when empty
+    fn serialize_table_cell(&self, cell: &TableCell, link_ids: &HashMap<&str, &str>) -> Result<String, OoxmlError> {
+        let props = &cell.properties;
+        let mut xml = String::from("<w:tc><w:tcPr>");
+        match props.width.or(cell.width) {
+            Some(width) => xml.push_str(&format!(r#"<w:tcW w:w="{}" w:type="dxa"/>"#, width)),
+            None => xml.push_str(r#"<w:tcW w:w="0" w:type="auto"/>"#),
+        }
+        if let Some(span) = props.grid_span.filter(|&span| span > 1) {
+            xml.push_str(&format!(r#"<w:gridSpan w:val="{}"/>"#, span));
+        }
+        match cell.horizontal_merge {
+            Some(1) => xml.push_str(r#"<w:hMerge w:val="restart"/>"#),
+            Some(_) => xml.push_str("<w:hMerge/>"),
+            None => {}
+        }
+        match cell.vertical_merge {
+            Some(1) => xml.push_str(r#"<w:vMerge w:val="restart"/>"#),
+            Some(_) => xml.push_str("<w:vMerge/>"),
+            None => {}
+        }
+        if let Some(ref fill) = props.shading_color {
+            xml.push_str(&format!(r#"<w:shd w:val="clear" w:color="auto" w:fill="{}"/>"#, escape_xml_attr(fill)));
+        }
+        if let Some(ref direction) = props.text_direction {
+            xml.push_str(&format!(r#"<w:textDirection w:val="{}"/>"#, escape_xml_attr(direction)));
+        }
+        if let Some(ref alignment) = props.vertical_alignment {
+            xml.push_str(&format!(r#"<w:vAlign w:val="{}"/>"#, escape_xml_attr(alignment)));
+        }
+        xml.push_str("</w:tcPr>");
+        for para in &cell.paragraphs {
+            // Fields do not run on out of a cell
+            xml.push_str(&self.serialize_paragraph(para, link_ids, &[], &mut Vec::new())?);
+        }
+        if cell.paragraphs.is_empty() {
+            xml.push_str("<w:p></w:p>");
+        }
+        xml.push_str("</w:tc>");
+        Ok(xml)
+    }
+
+    /// Serialize a single paragraph; `link_ids` are the relationship IDs of hyperlink URLs
+    ///
+    /// `open_fields` holds where the fields earlier paragraphs left open end,
+    /// as char offsets from the start of this paragraph; it is updated for the next one.
+    fn serialize_paragraph(
+        &self,
+        para: &Paragraph,
+        link_ids: &HashMap<&str, &str>,
+        drawings: &[(usize, String)],
+        open_fields: &mut Vec<usize>,
+    ) -> Result<String, OoxmlError> {
         let mut xml = String::new();
 
         xml.push_str("<w:p>");
 
-        // Serialize paragraph properties
-        xml.push_str(&self.serialize_paragraph_properties(&para.properties));
+        // Serialize paragraph properties, the mark's formatting last
+        let mut properties = self.serialize_paragraph_properties(&para.properties);
+        let mark = para.mark_properties.as_ref().map(|props| self.serialize_run_properties(props)).unwrap_or_default();
+        if !mark.is_empty() {
+            match properties.strip_suffix("</w:pPr>") {
+                Some(start) => properties = format!("{}{}</w:pPr>", start, mark),
+                None => properties = format!("<w:pPr>{}</w:pPr>", mark),
+            }
+        }
+        xml.push_str(&properties);
+
+        // Equations are written as OMML in place of their linear text, and
+        // form fields as the legacy field or content control they came from in
+        // place of the text they show; they and the drawings are objects
+        // written at their positions
+        let split = (!para.math_zones.is_empty() || !para.form_fields.is_empty()).then(|| without_objects(para, drawings));
+        let (para, objects) = match &split {
+            Some((stripped, objects)) => (stripped, objects.as_slice()),
+            None => (para, drawings),
+        };
 
         // Serialize runs
-        for run in &para.runs {
-            xml.push_str(&self.serialize_run(run)?);
+        let marked: Vec<&Revision> = para
+            .revisions
+            .iter()
+            .filter(|r| matches!(r.kind, RevisionKind::Insertion | RevisionKind::Deletion) && r.length > 0)
+            .collect();
+        if marked.is_empty()
+            && para.comment_marks.is_empty()
+            && para.bookmark_marks.is_empty()
+            && para.hyperlinks.is_empty()
+            && para.fields.is_empty()
+            && open_fields.is_empty()
+            && objects.is_empty()
+        {
+            for run in &para.runs {
+                xml.push_str(&self.serialize_run(run)?);
+            }
+        } else {
+            // Where several marks fall together, fields end first (inner ones
+            // before outer ones) and start last
+            let length = para.text.chars().count();
+            let own_ends = para.fields.iter().filter(|f| f.length > 0).map(|f| f.start + f.length);
+            let (ends, carried): (Vec<usize>, Vec<usize>) = open_fields.drain(..).chain(own_ends).partition(|&end| end <= length);
+            *open_fields = carried.into_iter().map(|end| end - length - 1).collect();
+            let mut marks: Vec<(usize, String)> = ends
+                .into_iter()
+                .rev()
+                .map(|end| (end, FIELD_END_XML.to_string()))
+                .chain(para.bookmark_marks.iter().map(|mark| (mark.position, bookmark_mark_xml(mark))))
+                .chain(para.comment_marks.iter().map(|mark| (mark.position, comment_mark_xml(mark))))
+                .chain(para.fields.iter().map(|field| (field.start, field_start_xml(field))))
+                .chain(objects.iter().cloned())
+                .collect();
+            marks.sort_by_key(|&(position, _)| position);
+            let links: Vec<(Range<usize>, String)> = para
+                .hyperlinks
+                .iter()
+                .filter(|link| link.length > 0)
+                .map(|link| (link.start..link.start + link.length, hyperlink_start_tag(link, link_ids)))
+                .collect();
+            xml.push_str(&self.serialize_marked_runs(&para.runs, &marked, &marks, &links));
         }
 
         xml.push_str("</w:p>");
@@ -255,6 +1098,87 @@ impl DocxSerializer {
         Ok(xml)
     }
 
+    /// Serialize runs split where tracked insertions and deletions start and end,
+    /// wrapping the revised parts in w:ins / w:del, with comment and bookmark
+    /// marks (XML by position, sorted) written where they fall
+    ///
+    /// `links` are the hyperlinks' ranges with their start tags; a hyperlink
+    /// holds the revisions inside it, so revisions are closed at its ends.
+    fn serialize_marked_runs(
+        &self,
+        runs: &[Run],
+        marked: &[&Revision],
+        marks: &[(usize, String)],
+        links: &[(Range<usize>, String)],
+    ) -> String {
+        let mut xml = String::new();
+        let mut open: Option<&Revision> = None;
+        let mut open_link: Option<usize> = None;
+        let mut run_start = 0;
+        let mut pending_marks = marks.iter().peekable();
+
+        for run in runs {
+            let chars: Vec<usize> = run.text.char_indices().map(|(i, _)| i).collect();
+            let run_end = run_start + chars.len();
+            let mut cuts = vec![run_start, run_end];
+            let revision_cuts = marked.iter().flat_map(|revision| [revision.start, revision.start + revision.length]);
+            let link_cuts = links.iter().flat_map(|(range, _)| [range.start, range.end]);
+            for cut in revision_cuts.chain(link_cuts).chain(marks.iter().map(|&(position, _)| position)) {
+                if cut > run_start && cut < run_end {
+                    cuts.push(cut);
+                }
+            }
+            cuts.sort_unstable();
+            cuts.dedup();
+
+            let byte = |offset: usize| chars.get(offset - run_start).copied().unwrap_or(run.text.len());
+            for span in cuts.windows(2) {
+                while let Some((_, mark)) = pending_marks.next_if(|&&(position, _)| position <= span[0]) {
+                    xml.push_str(mark);
+                }
+                let link = links.iter().position(|(range, _)| range.contains(&span[0]));
+                if link != open_link {
+                    if let Some(previous) = open.take() {
+                        xml.push_str(revision_end_tag(previous));
+                    }
+                    if open_link.is_some() {
+                        xml.push_str("</w:hyperlink>");
+                    }
+                    if let Some(link) = link {
+                        xml.push_str(&links[link].1);
+                    }
+                    open_link = link;
+                }
+                let revision = marked
+                    .iter()
+                    .copied()
+                    .find(|r| r.start <= span[0] && span[0] < r.start + r.length);
+                if !open.zip(revision).is_some_and(|(a, b)| std::ptr::eq(a, b)) {
+                    if let Some(previous) = open {
+                        xml.push_str(revision_end_tag(previous));
+                    }
+                    if let Some(revision) = revision {
+                        xml.push_str(&revision_start_tag(revision));
+                    }
+                    open = revision;
+                }
+                let deleted = revision.is_some_and(|r| r.kind == RevisionKind::Deletion);
+                xml.push_str(&self.serialize_run_text(&run.properties, &run.text[byte(span[0])..byte(span[1])], deleted));
+            }
+            run_start = run_end;
+        }
+        if let Some(previous) = open {
+            xml.push_str(revision_end_tag(previous));
+        }
+        if open_link.is_some() {
+            xml.push_str("</w:hyperlink>");
+        }
+        for (_, mark) in pending_marks {
+            xml.push_str(mark);
+        }
+        xml
+    }
+
     /// Serialize paragraph properties
     fn serialize_paragraph_properties(&self, props: &ParagraphProperties) -> String {
         let mut xml = String::new();
@@ -266,9 +1190,57 @@ impl DocxSerializer {
             || props.spacing_after.is_some()
             || props.spacing_line.is_some()
             || props.alignment.is_some()
+            || props.style_id.is_some()
+            || props.num_id.is_some()
+            || props.list_level.is_some()
+            || props.frame.is_some()
+            || props.border_bottom.is_some()
+            || props.keep_next.is_some()
+            || props.keep_lines.is_some()
+            || props.page_break_before.is_some()
+            || props.widow_control.is_some()
         {
             xml.push_str("<w:pPr>");
 
+            if let Some(ref style_id) = props.style_id {
+                xml.push_str(&format!(r#"<w:pStyle w:val="{}"/>"#, escape_xml_attr(style_id)));
+            }
+
+            let toggle = |xml: &mut String, element: &str, value: Option<bool>| match value {
+                Some(true) => xml.push_str(&format!("<w:{}/>", element)),
+                Some(false) => xml.push_str(&format!(r#"<w:{} w:val="0"/>"#, element)),
+                None => {}
+            };
+            toggle(&mut xml, "keepNext", props.keep_next);
+            toggle(&mut xml, "keepLines", props.keep_lines);
+            toggle(&mut xml, "pageBreakBefore", props.page_break_before);
+
+            if let Some(ref frame) = props.frame {
+                xml.push_str(&frame_xml(frame));
+            }
+
+            toggle(&mut xml, "widowControl", props.widow_control);
+
+            if props.num_id.is_some() || props.list_level.is_some() {
+                xml.push_str("<w:numPr>");
+                if let Some(level) = props.list_level {
+                    xml.push_str(&format!(r#"<w:ilvl w:val="{}"/>"#, level));
+                }
+                if let Some(ref num_id) = props.num_id {
+                    xml.push_str(&format!(r#"<w:numId w:val="{}"/>"#, escape_xml_attr(num_id)));
+                }
+                xml.push_str("</w:numPr>");
+            }
+
+            if let Some(ref border) = props.border_bottom {
+                xml.push_str(&format!(
+                    r#"<w:pBdr><w:bottom w:val="{}" w:sz="{}" w:space="{}" w:color="auto"/></w:pBdr>"#,
+                    escape_xml_attr(&border.style),
+                    border.size,
+                    border.space
+                ));
+            }
+
             if let Some(ref align) = props.alignment {
                 xml.push_str(&format!(r#"<w:jc w:val="{}"/>"#, escape_xml_attr(align)));
             }
@@ -305,24 +1277,24 @@ impl DocxSerializer {
 
     /// Serialize a run
     fn serialize_run(&self, run: &Run) -> Result<String, OoxmlError> {
+        Ok(self.serialize_run_text(&run.properties, &run.text, false))
+    }
+
+    /// Serialize a run of text; deleted text goes in w:delText
+    fn serialize_run_text(&self, properties: &RunProperties, text: &str, deleted: bool) -> String {
         let mut xml = String::new();
 
         xml.push_str("<w:r>");
 
         // Serialize run properties
-        xml.push_str(&self.serialize_run_properties(&run.properties));
+        xml.push_str(&self.serialize_run_properties(properties));
 
-        // Serialize text
-        if !run.text.is_empty() {
-            xml.push_str(&format!(
-                "<w:t>{}</w:t>",
-                escape_xml_text(&run.text)
-            ));
-        }
+        // Serialize text, with its spaces, tabs and line breaks kept
+        xml.push_str(&run_content(text, deleted));
 
         xml.push_str("</w:r>");
 
-        Ok(xml)
+        xml
     }
 
     /// Serialize run properties
@@ -374,33 +1346,229 @@ impl DocxSerializer {
         xml
     }
 
-    /// Serialize styles
-    fn serialize_styles(&self, styles: &HashMap<String, Style>) -> Result<SerializedPart, OoxmlError> {
-        let mut xml = String::new();
+    /// Serialize comment bodies to comments.xml and their threads to commentsExtended.xml
+    fn serialize_comments(&self, comments: &[Comment]) -> Result<Vec<SerializedPart>, OoxmlError> {
+        // commentsExtended refers to a comment by the paraId of its last paragraph
+        let para_id = |index: usize| format!("{:08X}", index + 1);
 
+        let mut xml = String::new();
         xml.push_str(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
-        xml.push_str(
-            r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">"#,
-        );
-
-        // Serialize each style
-        for (_, style) in styles {
-            xml.push_str(&self.serialize_style(style)?);
+        xml.push_str(r#"<w:comments xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:w14="http://schemas.microsoft.com/office/word/2010/wordml">"#);
+        for (index, comment) in comments.iter().enumerate() {
+            xml.push_str(&format!(
+                r#"<w:comment w:id="{}" w:author="{}""#,
+                escape_xml_attr(&comment.id),
+                escape_xml_attr(comment.author.as_deref().unwrap_or("Unknown"))
+            ));
+            if let Some(ref date) = comment.date {
+                xml.push_str(&format!(r#" w:date="{}""#, escape_xml_attr(date)));
+            }
+            if let Some(ref initials) = comment.initials {
+                xml.push_str(&format!(r#" w:initials="{}""#, escape_xml_attr(initials)));
+            }
+            xml.push('>');
+
+            let empty = [Paragraph::default()];
+            let paragraphs = if comment.paragraphs.is_empty() { &empty[..] } else { &comment.paragraphs[..] };
+            for (n, paragraph) in paragraphs.iter().enumerate() {
+                if n + 1 == paragraphs.len() {
+                    xml.push_str(&format!(r#"<w:p w14:paraId="{}">"#, para_id(index)));
+                } else {
+                    xml.push_str("<w:p>");
+                }
+                xml.push_str(&self.serialize_paragraph_properties(&paragraph.properties));
+                if n == 0 {
+                    xml.push_str("<w:r><w:annotationRef/></w:r>");
+                }
+                for run in &paragraph.runs {
+                    xml.push_str(&self.serialize_run(run)?);
+                }
+                xml.push_str("</w:p>");
+            }
+            xml.push_str("</w:comment>");
         }
-
-        // Add default styles if none exist
-        if styles.is_empty() {
-            xml.push_str(&self.get_default_styles());
+        xml.push_str("</w:comments>");
+
+        let mut extended = String::new();
+        extended.push_str(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+        extended.push_str(r#"<w15:commentsEx xmlns:w15="http://schemas.microsoft.com/office/word/2012/wordml">"#);
+        for (index, comment) in comments.iter().enumerate() {
+            extended.push_str(&format!(r#"<w15:commentEx w15:paraId="{}""#, para_id(index)));
+            let parent = comment
+                .parent_id
+                .as_deref()
+                .and_then(|parent| comments.iter().position(|c| c.id == parent));
+            if let Some(parent) = parent {
+                extended.push_str(&format!(r#" w15:paraIdParent="{}""#, para_id(parent)));
+            }
+            extended.push_str(&format!(r#" w15:done="{}"/>"#, u8::from(comment.done)));
         }
+        extended.push_str("</w15:commentsEx>");
+
+        Ok(vec![
+            SerializedPart {
+                path: "/word/comments.xml".to_string(),
+                content_type: ContentType::Comments,
+                data: xml.into_bytes(),
+                relationships: Vec::new(),
+            },
+            SerializedPart {
+                path: "/word/commentsExtended.xml".to_string(),
+                content_type: ContentType::CommentsExtended,
+                data: extended.into_bytes(),
+                relationships: Vec::new(),
+            },
+        ])
+    }
 
-        xml.push_str("</w:styles>");
+    /// Serialize headers to header{n}.xml and footers to footer{n}.xml, each
+    /// with the main document's relationship to it
+    fn serialize_headers_footers(&self, document: &WordDocument) -> Result<Vec<(SerializedPart, Relationship)>, OoxmlError> {
+        let headers = document.headers.iter().map(|header| ("header", &header.id, &header.paragraphs));
+        let footers = document.footers.iter().map(|footer| ("footer", &footer.id, &footer.paragraphs));
+        let mut numbers: HashMap<&str, usize> = HashMap::new();
+        let mut parts = Vec::new();
+        for (name, id, paragraphs) in headers.chain(footers) {
+            let number = numbers.entry(name).or_default();
+            *number += 1;
+            let (root, content_type, relationship_type) = if name == "header" {
+                ("w:hdr", ContentType::Header, RelationshipType::Header)
+            } else {
+                ("w:ftr", ContentType::Footer, RelationshipType::Footer)
+            };
 
-        Ok(SerializedPart {
-            path: "/word/styles.xml".to_string(),
-            content_type: ContentType::Styles,
-            data: xml.into_bytes(),
-            relationships: Vec::new(),
-        })
+            let mut xml = String::new();
+            xml.push_str(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+            xml.push_str(&format!(
+                r#"<{} xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+                root
+            ));
+            // A header or footer holds at least one paragraph
+            let empty = [Paragraph::default()];
+            let paragraphs = if paragraphs.is_empty() { &empty[..] } else { &paragraphs[..] };
+            let mut open_fields = Vec::new();
+            for paragraph in paragraphs {
+                xml.push_str(&self.serialize_paragraph(paragraph, &HashMap::new(), &[], &mut open_fields)?);
+            }
+            xml.push_str(&format!("</{}>", root));
+
+            let file = format!("{}{}.xml", name, number);
+            parts.push((
+                SerializedPart {
+                    path: format!("/word/{}", file),
+                    content_type,
+                    data: xml.into_bytes(),
+                    relationships: Vec::new(),
+                },
+                Relationship {
+                    id: id.clone(),
+                    relationship_type,
+                    target: file,
+                    target_mode: None,
+                },
+            ));
+        }
+        Ok(parts)
+    }
+
+    /// Serialize list definitions to numbering.xml, all abstract definitions first
+    fn serialize_numbering(&self, numbering: &[Numbering]) -> SerializedPart {
+        let mut xml = String::new();
+        xml.push_str(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+        xml.push_str(r#"<w:numbering xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">"#);
+
+        for definition in numbering.iter().flat_map(|n| &n.abstract_num_defs) {
+            xml.push_str(&format!(r#"<w:abstractNum w:abstractNumId="{}">"#, escape_xml_attr(&definition.abstract_num_id)));
+            xml.push_str(r#"<w:multiLevelType w:val="multilevel"/>"#);
+            for level in &definition.levels {
+                xml.push_str(&format!(r#"<w:lvl w:ilvl="{}">"#, level.level));
+                xml.push_str(&format!(r#"<w:start w:val="{}"/>"#, level.start_value));
+                if !level.format.is_empty() {
+                    xml.push_str(&format!(r#"<w:numFmt w:val="{}"/>"#, escape_xml_attr(&level.format)));
+                }
+                if let Some(restart) = level.restart {
+                    xml.push_str(&format!(r#"<w:lvlRestart w:val="{}"/>"#, restart));
+                }
+                if let Some(ref style_id) = level.style_id {
+                    xml.push_str(&format!(r#"<w:pStyle w:val="{}"/>"#, escape_xml_attr(style_id)));
+                }
+                if level.is_legal {
+                    xml.push_str("<w:isLgl/>");
+                }
+                if let Some(ref suffix) = level.suffix {
+                    xml.push_str(&format!(r#"<w:suff w:val="{}"/>"#, escape_xml_attr(suffix)));
+                }
+                xml.push_str(&format!(r#"<w:lvlText w:val="{}"/>"#, escape_xml_attr(&level.text)));
+                xml.push_str(&self.serialize_paragraph_properties(&level.paragraph_properties));
+                xml.push_str(&self.serialize_run_properties(&level.run_properties));
+                xml.push_str("</w:lvl>");
+            }
+            xml.push_str("</w:abstractNum>");
+        }
+
+        for num in numbering.iter().flat_map(|n| &n.num_instances) {
+            xml.push_str(&format!(r#"<w:num w:numId="{}">"#, escape_xml_attr(&num.num_id)));
+            xml.push_str(&format!(r#"<w:abstractNumId w:val="{}"/>"#, escape_xml_attr(&num.abstract_num_id)));
+            for level in &num.overrides {
+                if level.start_value.is_none() && level.text.is_none() && level.format.is_none() {
+                    continue;
+                }
+                xml.push_str(&format!(r#"<w:lvlOverride w:ilvl="{}">"#, level.level));
+                if let Some(start) = level.start_value {
+                    xml.push_str(&format!(r#"<w:startOverride w:val="{}"/>"#, start));
+                }
+                if level.text.is_some() || level.format.is_some() {
+                    xml.push_str(&format!(r#"<w:lvl w:ilvl="{}">"#, level.level));
+                    if let Some(ref format) = level.format {
+                        xml.push_str(&format!(r#"<w:numFmt w:val="{}"/>"#, escape_xml_attr(format)));
+                    }
+                    if let Some(ref text) = level.text {
+                        xml.push_str(&format!(r#"<w:lvlText w:val="{}"/>"#, escape_xml_attr(text)));
+                    }
+                    xml.push_str("</w:lvl>");
+                }
+                xml.push_str("</w:lvlOverride>");
+            }
+            xml.push_str("</w:num>");
+        }
+
+        xml.push_str("</w:numbering>");
+
+        SerializedPart {
+            path: "/word/numbering.xml".to_string(),
+            content_type: ContentType::Numbering,
+            data: xml.into_bytes(),
+            relationships: Vec::new(),
+        }
+    }
+
+    /// Serialize styles
+    fn serialize_styles(&self, styles: &HashMap<String, Style>) -> Result<SerializedPart, OoxmlError> {
+        let mut xml = String::new();
+
+        xml.push_str(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+        xml.push_str(
+            r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">"#,
+        );
+
+        // Serialize each style
+        for (_, style) in styles {
+            xml.push_str(&self.serialize_style(style)?);
+        }
+
+        // Add default styles if none exist
+        if styles.is_empty() {
+            xml.push_str(&self.get_default_styles());
+        }
+
+        xml.push_str("</w:styles>");
+
+        Ok(SerializedPart {
+            path: "/word/styles.xml".to_string(),
+            content_type: ContentType::Styles,
+            data: xml.into_bytes(),
+            relationships: Vec::new(),
+        })
     }
 
     /// Serialize a single style
@@ -427,6 +1595,11 @@ impl DocxSerializer {
             xml.push_str(&format!(r#"<w:basedOn w:val="{}"/>"#, escape_xml_attr(based_on)));
         }
 
+        // Next paragraph's style
+        if let Some(ref next) = style.next {
+            xml.push_str(&format!(r#"<w:next w:val="{}"/>"#, escape_xml_attr(next)));
+        }
+
         // Paragraph properties
         xml.push_str(&self.serialize_paragraph_properties(&style.paragraph_properties));
 
@@ -535,19 +1708,26 @@ impl DocxSerializer {
         }
     }
 
-    /// Serialize app properties
-    fn serialize_app_properties() -> SerializedPart {
-        let xml = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-        <Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/extended-properties">
-            <Application>Velum</Application>
-            <AppVersion>1.0</AppVersion>
-        </Properties>"#
-            .to_string();
+    /// Serialize app properties, with statistics of the document being saved
+    fn serialize_app_properties(&self, options: &ExportOptions) -> SerializedPart {
+        let statistics = options.document_statistics.then(|| {
+            let recorded = self
+                .package
+                .get_part("/docProps/app.xml")
+                .and_then(|part| recorded_total_time(&part.data))
+                .unwrap_or(0);
+            DocumentStatistics {
+                pages: options.layout_statistics.map(|layout| layout.pages),
+                lines: options.layout_statistics.map(|layout| layout.lines),
+                total_time: recorded + options.editing_minutes,
+                ..DocumentStatistics::count(&self.document.paragraphs, &self.document.tables)
+            }
+        });
 
         SerializedPart {
             path: "/docProps/app.xml".to_string(),
             content_type: ContentType::AppProperties,
-            data: xml.into_bytes(),
+            data: app_properties_xml(statistics.as_ref()).into_bytes(),
             relationships: Vec::new(),
         }
     }
@@ -606,49 +1786,70 @@ impl DocxSerializer {
     }
 
     /// Package the serialized document into a ZIP archive
+    ///
+    /// When `incremental`, entries the source archive holds unchanged are
+    /// copied from it rather than compressed again.
     fn package_to_zip(
         &self,
         serialized: &SerializedDocument,
-        _options: ExportOptions,
+        compression: &PackageCompression,
+        incremental: bool,
     ) -> Result<Vec<u8>, OoxmlError> {
         let mut writer = Cursor::new(Vec::new());
+        let mut source = incremental.then(|| self.package.source_archive()).flatten();
+        let mut copied = 0;
         {
             let mut zip = ZipWriter::new(&mut writer);
 
-            let zip_options = FileOptions::default()
-                .compression_method(zip::CompressionMethod::Deflated)
-                .compression_level(Some(9));
+            let mut write = |zip: &mut ZipWriter<&mut Cursor<Vec<u8>>>, name: &str, data: &[u8]| -> Result<(), OoxmlError> {
+                let entry = source.as_ref().and_then(|_| self.package.unchanged_entry(&format!("/{}", name), data));
+                if let (Some(entry), Some(archive)) = (entry, source.as_mut()) {
+                    zip.raw_copy_file_rename(archive.by_name(entry)?, name)?;
+                    copied += 1;
+                } else {
+                    zip.start_file(name, compression.file_options(name))?;
+                    zip.write_all(data)?;
+                }
+                Ok(())
+            };
 
             // Write [Content_Types].xml
             let content_types_xml = self.generate_content_types_xml(&serialized.content_types);
-            zip.start_file("[Content_Types].xml", zip_options)?;
-            zip.write_all(&content_types_xml)?;
+            write(&mut zip, "[Content_Types].xml", &content_types_xml)?;
 
             // Write root relationships
             let rels_xml = self.generate_relationships_xml(&serialized.root_relationships, "");
-            zip.start_file("_rels/.rels", zip_options)?;
-            zip.write_all(&rels_xml)?;
+            write(&mut zip, "_rels/.rels", &rels_xml)?;
 
             // Write document relationships
             let doc_rels = self.generate_document_relationships(serialized);
-            zip.start_file("word/_rels/document.xml.rels", zip_options)?;
-            zip.write_all(&doc_rels)?;
+            write(&mut zip, "word/_rels/document.xml.rels", &doc_rels)?;
 
             // Write all parts
             for part in &serialized.parts {
-                zip.start_file(&part.path[1..], zip_options)?; // Remove leading slash
-                zip.write_all(&part.data)?;
+                write(&mut zip, &part.path[1..], &part.data)?; // Remove leading slash
             }
 
             // Write images if any
             for image in &serialized.images {
-                zip.start_file(&image.path, zip_options)?;
-                zip.write_all(&image.data)?;
+                write(&mut zip, &format!("word/media/{}", image.path), &image.data)?;
+            }
+
+            // Copy the source parts carried over, entry for entry
+            for name in &serialized.copied {
+                let (Some(entry), Some(archive)) = (self.package.source_entry(name), source.as_mut()) else {
+                    return Err(OoxmlError::PartNotFound(name.clone()));
+                };
+                zip.raw_copy_file_rename(archive.by_name(entry)?, &name[1..])?;
+                copied += 1;
             }
 
             // Finish ZIP
             zip.finish()?;
         }
+        if incremental {
+            log::debug!("Incremental save copied {} unchanged entries", copied);
+        }
 
         Ok(writer.into_inner())
     }
@@ -669,25 +1870,12 @@ impl DocxSerializer {
         xml.push_str(r#"<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>"#);
         xml.push_str(r#"<Default Extension="xml" ContentType="application/xml"/>"#);
 
-        // Override types
+        // Override types, in name order so that an unchanged package gives the same XML
+        let mut content_types: Vec<_> = content_types.iter().collect();
+        content_types.sort_by_key(|(part_name, _)| part_name.as_str());
         for (part_name, content_type) in content_types {
             if part_name.starts_with("/") {
-                let type_str = match content_type {
-                    ContentType::MainDocument => "application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml",
-                    ContentType::Styles => "application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml",
-                    ContentType::Theme => "application/vnd.openxmlformats-officedocument.theme+xml",
-                    ContentType::Settings => "application/vnd.openxmlformats-officedocument.wordprocessingml.settings+xml",
-                    ContentType::CoreProperties => "application/vnd.openxmlformats-package.core-properties+xml",
-                    ContentType::AppProperties => "application/vnd.openxmlformats-officedocument.extended-properties+xml",
-                    ContentType::Numbering => "application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml",
-                    ContentType::WebSettings => "application/vnd.openxmlformats-officedocument.wordprocessingml.webSettings+xml",
-                    ContentType::ImagePng => "image/png",
-                    ContentType::ImageJpeg => "image/jpeg",
-                    ContentType::ImageGif => "image/gif",
-                    ContentType::ImageBmp => "image/bmp",
-                    ContentType::ImageSvg => "image/svg+xml",
-                    _ => "application/xml",
-                };
+                let type_str = content_type.as_str();
                 xml.push_str(&format!(
                     r#"<Override PartName="{}" ContentType="{}"/>"#,
                     part_name, type_str
@@ -727,14 +1915,27 @@ impl DocxSerializer {
                 RelationshipType::Settings => "http://schemas.openxmlformats.org/officeDocument/2006/relationships/settings".to_string(),
                 RelationshipType::CoreProperties => "http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties".to_string(),
                 RelationshipType::Image => "http://schemas.openxmlformats.org/officeDocument/2006/relationships/image".to_string(),
+                RelationshipType::VbaProject => "http://schemas.microsoft.com/office/2006/relationships/vbaProject".to_string(),
+                RelationshipType::Hyperlink => "http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink".to_string(),
+                RelationshipType::Comments => "http://schemas.openxmlformats.org/officeDocument/2006/relationships/comments".to_string(),
+                RelationshipType::CommentsExtended => "http://schemas.microsoft.com/office/2011/relationships/commentsExtended".to_string(),
+                RelationshipType::Numbering => "http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering".to_string(),
+                RelationshipType::Header => "http://schemas.openxmlformats.org/officeDocument/2006/relationships/header".to_string(),
+                RelationshipType::Footer => "http://schemas.openxmlformats.org/officeDocument/2006/relationships/footer".to_string(),
                 RelationshipType::Unknown(uri) => uri.clone(),
                 _ => "http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument".to_string(),
             };
+            let target_mode = rel
+                .target_mode
+                .as_ref()
+                .map(|mode| format!(r#" TargetMode="{}""#, escape_xml_attr(mode)))
+                .unwrap_or_default();
             xml.push_str(&format!(
-                r#"<Relationship Id="{}" Type="{}" Target="{}"/>"#,
+                r#"<Relationship Id="{}" Type="{}" Target="{}"{}/>"#,
                 escape_xml_attr(&rel.id),
                 type_uri,
-                escape_xml_attr(&target)
+                escape_xml_attr(&target),
+                target_mode
             ));
         }
 
@@ -752,64 +1953,639 @@ impl DocxSerializer {
             relationships.push(Relationship {
                 id: "rIdTheme".to_string(),
                 relationship_type: RelationshipType::Theme,
-                target: "../theme/theme1.xml".to_string(),
+                target: "theme/theme1.xml".to_string(),
                 target_mode: None,
             });
         }
 
-        // Add image relationships
-        for (i, image) in serialized.images.iter().enumerate() {
+        // Add relationships declared by the main document part itself
+        if let Some(document_part) = serialized.parts.iter().find(|p| p.path == "/word/document.xml") {
+            relationships.extend(document_part.relationships.iter().cloned());
+        }
+
+        // Targets are relative to the word/ folder the .rels file describes
+        self.generate_relationships_xml(&relationships, "")
+    }
+}
+
+/// Add carried-forward relationships to the main document part, skipping ids already taken
+fn document_part_relationships(parts: &mut [SerializedPart], relationships: Vec<Relationship>) {
+    let Some(document_part) = parts.iter_mut().find(|part| part.path == "/word/document.xml") else {
+        return;
+    };
+    for rel in relationships {
+        // The regenerated body may reference its own ids, so those win
+        if document_part.relationships.iter().all(|existing| existing.id != rel.id) {
+            document_part.relationships.push(rel);
+        }
+    }
+}
+
+/// Children of w:sectPr in the order the schema requires them
+const SECTION_CHILDREN: &[&str] = &[
+    "headerReference",
+    "footerReference",
+    "footnotePr",
+    "endnotePr",
+    "type",
+    "pgSz",
+    "pgMar",
+    "paperSrc",
+    "pgBorders",
+    "lnNumType",
+    "pgNumType",
+    "cols",
+    "formProt",
+    "vAlign",
+    "noEndnote",
+    "titlePg",
+    "textDirection",
+    "bidi",
+    "rtlGutter",
+    "docGrid",
+    "printerSettings",
+    "sectPrChange",
+];
+
+/// A section's w:sectPr: its header and footer references, the page setup
+/// given or its own, its columns, whether it has a different first page and
+/// the children it was read with that Velum does not model
+fn section_properties_xml(section: Option<&Section>, setup: Option<&SectionPageSetup>) -> String {
+    let mut children: Vec<(&str, String)> = Vec::new();
+    if let Some(section) = section {
+        let references = [("headerReference", &section.header_references), ("footerReference", &section.footer_references)];
+        for (element, references) in references {
+            for reference in references {
+                let xml = format!(
+                    r#"<w:{} w:type="{}" r:id="{}"/>"#,
+                    element,
+                    escape_xml_attr(&reference.kind),
+                    escape_xml_attr(&reference.id)
+                );
+                children.push((element, xml));
+            }
+        }
+    }
+    let own_setup = section.filter(|section| section.has_page_setup()).map(Section::page_setup);
+    if let Some(setup) = setup.copied().or(own_setup) {
+        // w:pgSz and w:pgMar
+        children.push(("pgSz", setup.to_sect_pr_xml()));
+    }
+    if let Some(section) = section {
+        if section.columns > 1 || section.column_space.is_some() {
+            let space = section.column_space.map(|space| format!(r#" w:space="{}""#, space)).unwrap_or_default();
+            children.push(("cols", format!(r#"<w:cols w:num="{}"{}/>"#, section.columns, space)));
+        }
+        if section.title_page {
+            children.push(("titlePg", "<w:titlePg/>".to_string()));
+        }
+        for child in &section.other_children {
+            let name = child.trim_start_matches("<w:").split(|c: char| !c.is_ascii_alphanumeric()).next().unwrap_or("");
+            children.push((name, child.clone()));
+        }
+    }
+    children.sort_by_key(|(name, _)| SECTION_CHILDREN.iter().position(|known| known == name).unwrap_or(SECTION_CHILDREN.len()));
+
+    let mut xml = String::from("<w:sectPr>");
+    for (_, child) in children {
+        xml.push_str(&child);
+    }
+    xml.push_str("</w:sectPr>");
+    xml
+}
+
+/// settings.xml for exports whose source has none
+const EMPTY_SETTINGS: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<w:settings xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"></w:settings>"#
+);
+
+/// `settings` with w:evenAndOddHeaders set
+fn settings_with_even_and_odd_headers(mut settings: String) -> String {
+    if settings.contains("<w:evenAndOddHeaders") {
+        return settings;
+    }
+    let at = SETTINGS_AFTER_EVEN_AND_ODD
+        .iter()
+        .filter_map(|tag| settings.find(tag))
+        .min()
+        .or_else(|| settings.rfind("</w:settings>"));
+    match at {
+        Some(at) => settings.insert_str(at, "<w:evenAndOddHeaders/>"),
+        // A self-closing w:settings
+        None => {
+            if let Some(at) = settings.rfind("/>") {
+                settings.replace_range(at..at + 2, "><w:evenAndOddHeaders/></w:settings>");
+            }
+        }
+    }
+    settings
+}
+
+/// Resolve a relationship target against the folder of its source part
+pub(crate) fn resolve_part_name(base: &str, target: &str) -> String {
+    let path = if target.starts_with('/') {
+        target.to_string()
+    } else {
+        format!("{}{}", base, target)
+    };
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+/// Opening w:ins or w:del tag of a tracked change
+fn revision_start_tag(revision: &Revision) -> String {
+    let element = if revision.kind == RevisionKind::Deletion { "w:del" } else { "w:ins" };
+    let mut tag = format!(
+        r#"<{} w:id="{}" w:author="{}""#,
+        element,
+        escape_xml_attr(&revision.id),
+        escape_xml_attr(revision.author.as_deref().unwrap_or("Unknown"))
+    );
+    if let Some(ref date) = revision.date {
+        tag.push_str(&format!(r#" w:date="{}""#, escape_xml_attr(date)));
+    }
+    tag.push('>');
+    tag
+}
+
+/// A comment range boundary, or the run holding a comment reference
+fn comment_mark_xml(mark: &CommentMark) -> String {
+    let id = escape_xml_attr(&mark.id);
+    match mark.kind {
+        CommentMarkKind::RangeStart => format!(r#"<w:commentRangeStart w:id="{}"/>"#, id),
+        CommentMarkKind::RangeEnd => format!(r#"<w:commentRangeEnd w:id="{}"/>"#, id),
+        CommentMarkKind::Reference => format!(r#"<w:r><w:commentReference w:id="{}"/></w:r>"#, id),
+    }
+}
+
+/// A bookmark start or end
+fn bookmark_mark_xml(mark: &BookmarkMark) -> String {
+    let id = escape_xml_attr(&mark.id);
+    match mark.kind {
+        BookmarkMarkKind::Start => format!(
+            r#"<w:bookmarkStart w:id="{}" w:name="{}"/>"#,
+            id,
+            escape_xml_attr(mark.name.as_deref().unwrap_or_default())
+        ),
+        BookmarkMarkKind::End => format!(r#"<w:bookmarkEnd w:id="{}"/>"#, id),
+    }
+}
+
+/// A paragraph's w:framePr
+fn frame_xml(frame: &ParagraphFrame) -> String {
+    let mut xml = String::from("<w:framePr");
+    if let Some(width) = frame.width {
+        xml.push_str(&format!(r#" w:w="{}""#, width));
+    }
+    if let Some(height) = frame.height {
+        xml.push_str(&format!(r#" w:h="{}" w:hRule="atLeast""#, height));
+    }
+    xml.push_str(r#" w:wrap="around""#);
+    if let Some(ref anchor) = frame.horizontal_anchor {
+        xml.push_str(&format!(r#" w:hAnchor="{}""#, escape_xml_attr(anchor)));
+    }
+    if let Some(ref anchor) = frame.vertical_anchor {
+        xml.push_str(&format!(r#" w:vAnchor="{}""#, escape_xml_attr(anchor)));
+    }
+    xml.push_str(&format!(r#" w:x="{}" w:y="{}"/>"#, frame.x, frame.y));
+    xml
+}
+
+/// The runs starting a complex field up to its result; a field without a result
+/// is ended straight away, as XE fields are
+fn field_start_xml(field: &Field) -> String {
+    format!(
+        r#"<w:r><w:fldChar w:fldCharType="begin"/></w:r><w:r><w:instrText xml:space="preserve"> {} </w:instrText></w:r>{}"#,
+        escape_xml_text(&field.instruction),
+        if field.length == 0 { FIELD_END_XML } else { FIELD_SEPARATE_XML }
+    )
+}
+
+const FIELD_SEPARATE_XML: &str = r#"<w:r><w:fldChar w:fldCharType="separate"/></w:r>"#;
+const FIELD_END_XML: &str = r#"<w:r><w:fldChar w:fldCharType="end"/></w:r>"#;
+
+/// The paragraph without the text of its math zones and form fields, and the
+/// OMML of each zone's equation and the XML of each field with the
+/// `drawings`, at the positions they take in what is left
+fn without_objects(para: &Paragraph, drawings: &[(usize, String)]) -> (Paragraph, Vec<(usize, String)>) {
+    let zones: Vec<MathZone> = para
+        .math_zones
+        .iter()
+        .copied()
+        .chain(para.form_fields.iter().map(|field| MathZone { start: field.start, length: field.length }))
+        .collect();
+    let in_zone = |i: usize| zones.iter().any(|zone| (zone.start..zone.start + zone.length).contains(&i));
+    let map = |position: usize| {
+        position
+            - zones
+                .iter()
+                .map(|zone| position.min(zone.start + zone.length).saturating_sub(zone.start))
+                .sum::<usize>()
+    };
+    let keep = |text: &str, start: usize| -> String {
+        text.chars().enumerate().filter(|&(i, _)| !in_zone(start + i)).map(|(_, c)| c).collect()
+    };
+
+    let mut stripped = para.clone();
+    stripped.math_zones = Vec::new();
+    stripped.form_fields = Vec::new();
+    stripped.text = keep(&para.text, 0);
+    let mut run_start = 0;
+    stripped.runs = para
+        .runs
+        .iter()
+        .filter_map(|run| {
+            let text = keep(&run.text, run_start);
+            run_start += run.text.chars().count();
+            (!text.is_empty()).then(|| Run {
+                text,
+                properties: run.properties.clone(),
+            })
+        })
+        .collect();
+    for revision in &mut stripped.revisions {
+        let end = map(revision.start + revision.length);
+        revision.start = map(revision.start);
+        revision.length = end - revision.start;
+    }
+    for link in &mut stripped.hyperlinks {
+        let end = map(link.start + link.length);
+        link.start = map(link.start);
+        link.length = end - link.start;
+    }
+    for field in &mut stripped.fields {
+        let end = map(field.start + field.length);
+        field.start = map(field.start);
+        field.length = end - field.start;
+    }
+    for mark in &mut stripped.comment_marks {
+        mark.position = map(mark.position);
+    }
+    for mark in &mut stripped.bookmark_marks {
+        mark.position = map(mark.position);
+    }
+    for reference in &mut stripped.note_references {
+        reference.position = map(reference.position);
+    }
+
+    let shown = |start: usize, length: usize| -> String { para.text.chars().skip(start).take(length).collect() };
+    let mut objects: Vec<(usize, String)> = para
+        .math_zones
+        .iter()
+        .map(|zone| {
+            let linear = shown(zone.start, zone.length);
+            (map(zone.start), math::to_omml(&math::parse_linear(&linear)))
+        })
+        .chain(para.form_fields.iter().map(|field| (map(field.start), field.to_xml(&shown(field.start, field.length)))))
+        .chain(drawings.iter().map(|(position, xml)| (map(*position), xml.clone())))
+        .collect();
+    objects.sort_by_key(|&(position, _)| position);
+    (stripped, objects)
+}
+
+/// Size an image is drawn at when neither it nor its data gives one: an inch square
+const DEFAULT_IMAGE_EXTENT: u32 = 914_400;
+
+/// A run drawing `image` through relationship `id` at `extent` EMUs, inline or
+/// anchored; `n` numbers the drawing in the document
+fn drawing_xml(image: &DocumentImage, id: &str, (cx, cy): (u32, u32), n: usize) -> String {
+    let name = image.path.rsplit('/').next().unwrap_or_default();
+    let mut doc_pr = format!(r#"<wp:docPr id="{}" name="Picture {}""#, n, n);
+    if let Some(ref description) = image.alt_description {
+        doc_pr.push_str(&format!(r#" descr="{}""#, escape_xml_attr(description)));
+    }
+    if let Some(ref title) = image.title {
+        doc_pr.push_str(&format!(r#" title="{}""#, escape_xml_attr(title)));
+    }
+    doc_pr.push_str("/>");
+    // DrawingML gives percentages in thousandths and angles in 60000ths of a degree
+    let transform = &image.transform;
+    let thousandths = |percent: f32| (percent * 1000.0).round() as i64;
+    let mut effects = String::new();
+    if transform.transparency != 0.0 {
+        effects.push_str(&format!(r#"<a:alphaModFix amt="{}"/>"#, thousandths(100.0 - transform.transparency)));
+    }
+    if transform.brightness != 0.0 || transform.contrast != 0.0 {
+        effects.push_str(&format!(
+            r#"<a:lum bright="{}" contrast="{}"/>"#,
+            thousandths(transform.brightness),
+            thousandths(transform.contrast)
+        ));
+    }
+    let reference = match image.is_linked {
+        true => format!(r#"r:link="{}""#, escape_xml_attr(id)),
+        false => format!(r#"r:embed="{}""#, escape_xml_attr(id)),
+    };
+    let mut blip = match effects.is_empty() {
+        true => format!("<a:blip {}/>", reference),
+        false => format!("<a:blip {}>{}</a:blip>", reference, effects),
+    };
+    if let Some(crop) = transform.crop {
+        blip.push_str(&format!(
+            r#"<a:srcRect l="{}" t="{}" r="{}" b="{}"/>"#,
+            thousandths(crop.left),
+            thousandths(crop.top),
+            thousandths(crop.right),
+            thousandths(crop.bottom)
+        ));
+    }
+    let mut xfrm = String::new();
+    if transform.rotation != 0.0 {
+        xfrm.push_str(&format!(r#" rot="{}""#, (transform.rotation * 60_000.0).round() as i64));
+    }
+    if transform.flip_horizontal {
+        xfrm.push_str(r#" flipH="1""#);
+    }
+    if transform.flip_vertical {
+        xfrm.push_str(r#" flipV="1""#);
+    }
+    let graphic = format!(
+        concat!(
+            r#"<wp:cNvGraphicFramePr><a:graphicFrameLocks xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" noChangeAspect="1"/></wp:cNvGraphicFramePr>"#,
+            r#"<a:graphic xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main"><a:graphicData uri="http://schemas.openxmlformats.org/drawingml/2006/picture">"#,
+            r#"<pic:pic xmlns:pic="http://schemas.openxmlformats.org/drawingml/2006/picture"><pic:nvPicPr><pic:cNvPr id="{n}" name="{name}"/><pic:cNvPicPr/></pic:nvPicPr>"#,
+            r#"<pic:blipFill>{blip}<a:stretch><a:fillRect/></a:stretch></pic:blipFill>"#,
+            r#"<pic:spPr><a:xfrm{xfrm}><a:off x="0" y="0"/><a:ext cx="{cx}" cy="{cy}"/></a:xfrm><a:prstGeom prst="rect"><a:avLst/></a:prstGeom></pic:spPr>"#,
+            r#"</pic:pic></a:graphicData></a:graphic>"#
+        ),
+        n = n,
+        name = escape_xml_attr(name),
+        blip = blip,
+        xfrm = xfrm,
+        cx = cx,
+        cy = cy,
+    );
+    let extent = format!(r#"<wp:extent cx="{}" cy="{}"/><wp:effectExtent l="0" t="0" r="0" b="0"/>"#, cx, cy);
+    let drawing = match image.anchor {
+        None => format!(r#"<wp:inline distT="0" distB="0" distL="0" distR="0">{}{}{}</wp:inline>"#, extent, doc_pr, graphic),
+        Some(ref anchor) => {
+            let wrap = match anchor.wrap.as_str() {
+                "none" => "<wp:wrapNone/>".to_string(),
+                "topAndBottom" => "<wp:wrapTopAndBottom/>".to_string(),
+                wrap @ ("tight" | "through") => {
+                    let element = if wrap == "tight" { "wrapTight" } else { "wrapThrough" };
+                    format!(
+                        concat!(
+                            r#"<wp:{0} wrapText="bothSides"><wp:wrapPolygon edited="0"><wp:start x="0" y="0"/>"#,
+                            r#"<wp:lineTo x="0" y="21600"/><wp:lineTo x="21600" y="21600"/><wp:lineTo x="21600" y="0"/>"#,
+                            r#"<wp:lineTo x="0" y="0"/></wp:wrapPolygon></wp:{0}>"#
+                        ),
+                        element
+                    )
+                }
+                _ => r#"<wp:wrapSquare wrapText="bothSides"/>"#.to_string(),
+            };
+            format!(
+                concat!(
+                    r#"<wp:anchor distT="0" distB="0" distL="114300" distR="114300" simplePos="0" relativeHeight="{}" behindDoc="{}" locked="0" layoutInCell="1" allowOverlap="{}">"#,
+                    r#"<wp:simplePos x="0" y="0"/>"#,
+                    r#"<wp:positionH relativeFrom="{}"><wp:posOffset>{}</wp:posOffset></wp:positionH>"#,
+                    r#"<wp:positionV relativeFrom="{}"><wp:posOffset>{}</wp:posOffset></wp:positionV>"#,
+                    r#"{}{}{}{}</wp:anchor>"#
+                ),
+                // Objects without a stacking order stack in document order
+                if anchor.z_order > 0 { anchor.z_order as usize } else { n },
+                u8::from(anchor.behind_text),
+                u8::from(anchor.allow_overlap),
+                escape_xml_attr(&anchor.horizontal_relative_to),
+                anchor.horizontal_offset,
+                escape_xml_attr(&anchor.vertical_relative_to),
+                anchor.vertical_offset,
+                extent,
+                wrap,
+                doc_pr,
+                graphic
+            )
+        }
+    };
+    format!("<w:r><w:drawing>{}</w:drawing></w:r>", drawing)
+}
+
+/// Start tag of a hyperlink; `link_ids` are the relationship IDs of the URLs
+fn hyperlink_start_tag(link: &Hyperlink, link_ids: &HashMap<&str, &str>) -> String {
+    let mut tag = String::from("<w:hyperlink");
+    if let Some(id) = link.url.as_deref().and_then(|url| link_ids.get(url)) {
+        tag.push_str(&format!(r#" r:id="{}""#, escape_xml_attr(id)));
+    }
+    if let Some(ref anchor) = link.anchor {
+        tag.push_str(&format!(r#" w:anchor="{}""#, escape_xml_attr(anchor)));
+    }
+    if let Some(ref tooltip) = link.tooltip {
+        tag.push_str(&format!(r#" w:tooltip="{}""#, escape_xml_attr(tooltip)));
+    }
+    tag.push_str(r#" w:history="1">"#);
+    tag
+}
+
+/// External relationships for the hyperlink URLs of `paragraphs`, one per URL in order of use
+fn hyperlink_relationships<'a>(paragraphs: impl IntoIterator<Item = &'a Paragraph>) -> Vec<Relationship> {
+    let mut relationships: Vec<Relationship> = Vec::new();
+    for url in paragraphs.into_iter().flat_map(|p| &p.hyperlinks).filter_map(|link| link.url.as_deref()) {
+        if relationships.iter().all(|rel| rel.target != url) {
             relationships.push(Relationship {
-                id: format!("rIdImage{}", i + 1),
-                relationship_type: RelationshipType::Image,
-                target: format!("media/{}", image.path),
-                target_mode: None,
+                id: format!("rIdLink{}", relationships.len() + 1),
+                relationship_type: RelationshipType::Hyperlink,
+                target: url.to_string(),
+                target_mode: Some("External".to_string()),
             });
         }
+    }
+    relationships
+}
+
+/// A table border edge, e.g. `<w:top .../>`
+fn table_border_xml(edge: &str, border: &TableBorder) -> String {
+    format!(
+        r#"<w:{} w:val="{}" w:sz="{}" w:space="0" w:color="{}"/>"#,
+        edge,
+        escape_xml_attr(border.style.as_deref().unwrap_or("single")),
+        border.size.unwrap_or(4),
+        escape_xml_attr(border.color.as_deref().unwrap_or("auto"))
+    )
+}
+
+/// Widths of a table's grid columns: its own grid, or one worked out from its cells
+///
+/// Without a grid, the row spanning the most columns gives each column the
+/// width of its cell, shared evenly by the columns a cell spans; cells without
+/// a width share what the table width leaves over.
+fn table_grid(table: &Table) -> Vec<u32> {
+    if !table.properties.grid_columns.is_empty() {
+        return table.properties.grid_columns.clone();
+    }
+    let span = |cell: &TableCell| cell.properties.grid_span.unwrap_or(1).max(1) as usize;
+    let Some(widest) = table.rows.iter().max_by_key(|row: &&TableRow| row.cells.iter().map(span).sum::<usize>()) else {
+        return Vec::new();
+    };
+    let columns: usize = widest.cells.iter().map(span).sum();
+    let width = |cell: &TableCell| cell.properties.width.or(cell.width);
+    let known: u32 = widest.cells.iter().filter_map(width).sum();
+    let unknown: usize = widest.cells.iter().filter(|cell| width(cell).is_none()).map(span).sum();
+    let share = match unknown {
+        0 => 0,
+        n => table.properties.width.unwrap_or(DEFAULT_TABLE_WIDTH).saturating_sub(known) / n as u32,
+    };
+    widest
+        .cells
+        .iter()
+        .flat_map(|cell| {
+            let each = width(cell).map_or(share, |width| width / span(cell) as u32);
+            std::iter::repeat_n(each, span(cell))
+        })
+        .take(columns)
+        .collect()
+}
 
-        self.generate_relationships_xml(&relationships, "word")
+fn revision_end_tag(revision: &Revision) -> &'static str {
+    if revision.kind == RevisionKind::Deletion {
+        "</w:del>"
+    } else {
+        "</w:ins>"
     }
 }
 
 /// Convert PieceTree to WordDocument for serialization
 pub fn piece_tree_to_word_document(tree: &PieceTree) -> WordDocument {
+    // Without a cancellable control the conversion cannot fail
+    snapshot_to_word_document(&tree.snapshot(), &ExportContent::default(), &ExportControl::new()).unwrap_or_default()
+}
+
+/// What an editor export writes besides the text of its snapshot
+///
+/// Revisions, comments, bookmarks, hyperlinks, fields, math zones and form
+/// fields have char offsets into the snapshot's text.
+#[derive(Debug, Clone, Default)]
+pub struct ExportContent {
+    /// Tracked changes
+    pub revisions: Vec<Revision>,
+    pub comments: Vec<Comment>,
+    pub bookmarks: Vec<Bookmark>,
+    pub hyperlinks: Vec<Hyperlink>,
+    /// Fields, whose results may run on into later paragraphs
+    pub fields: Vec<Field>,
+    /// Named styles by ID; without any the default styles are written
+    pub styles: HashMap<String, Style>,
+    /// List definitions
+    pub numbering: Vec<Numbering>,
+    /// Direct formatting of the snapshot's paragraphs, by paragraph index
+    pub paragraphs: Vec<Option<ParagraphAttributes>>,
+    pub headers: Vec<Header>,
+    pub footers: Vec<Footer>,
+    /// Header and footer references of each section
+    pub sections: Vec<Section>,
+    /// Whether even pages have headers and footers of their own
+    pub even_and_odd_headers: bool,
+    /// Tables, each placed before the paragraph of its `paragraph_index`
+    pub tables: Vec<Table>,
+    /// Equations, whose text is their linear form
+    pub math_zones: Vec<MathZone>,
+    /// Form fields over the text they show
+    pub form_fields: Vec<AnchoredFormField>,
+    /// Editing restriction, written to settings.xml
+    pub protection: ProtectionMode,
+    /// Images, each drawn at `position` in the paragraph of its `paragraph_index`
+    pub images: Vec<DocumentImage>,
+    /// Loaded image data by image path, for images no source package holds
+    pub media: HashMap<String, Arc<ImageData>>,
+    /// Formatting of the final paragraph mark
+    pub final_mark: Option<RunProperties>,
+}
+
+/// Convert a snapshot to WordDocument, reporting progress from 0.0 to 0.5
+///
+/// Each paragraph gets its formatting and the revision parts, comment marks,
+/// bookmark marks, hyperlink parts and math zones inside it, and the fields
+/// and form fields starting in it.
+pub fn snapshot_to_word_document(
+    snapshot: &TextSnapshot,
+    content: &ExportContent,
+    control: &ExportControl,
+) -> Result<WordDocument, OoxmlError> {
+    let revisions = &content.revisions;
+    let marks = comment_marks(&content.comments);
+    let bookmark_marks = bookmark_marks(&content.bookmarks);
+    let properties = |index: usize| {
+        content
+            .paragraphs
+            .get(index)
+            .and_then(Option::as_ref)
+            .map(paragraph_properties)
+            .unwrap_or_default()
+    };
     let mut paragraphs = Vec::new();
     let mut current_para = Paragraph::default();
+    let mut paragraph_start = 0;
+    let mut paragraph_index = 0;
+    let total = snapshot.pieces().len().max(1) as f32;
 
     // Process all pieces
-    for piece in &tree.pieces {
-        let buffer_idx = PieceTree::buffer_idx(&piece.buffer_id);
-        if let Some(buffer) = tree.buffers.get(buffer_idx) {
-            let piece_text = if piece.start + piece.length <= buffer.len() {
-                &buffer[piece.start..piece.start + piece.length]
-            } else {
-                ""
-            };
+    for (i, piece) in snapshot.pieces().iter().enumerate() {
+        if i % PROGRESS_INTERVAL == 0 {
+            control.step(0.5 * i as f32 / total)?;
+        }
 
-            // Split by newlines to create paragraphs
-            for part in piece_text.split('\n') {
-                if !current_para.text.is_empty() {
-                    paragraphs.push(current_para.clone());
+        // Split by newlines to create paragraphs; a piece boundary continues the paragraph
+        for (n, part) in snapshot.piece_text(piece).split('\n').enumerate() {
+            if n > 0 {
+                let mut finished = std::mem::take(&mut current_para);
+                let length = finished.text.chars().count();
+                if !finished.text.is_empty() {
+                    finished.properties = properties(paragraph_index);
+                    finished.revisions = paragraph_revisions(revisions, paragraph_start, length);
+                    finished.comment_marks = paragraph_comment_marks(&marks, paragraph_start, length);
+                    finished.bookmark_marks = paragraph_bookmark_marks(&bookmark_marks, paragraph_start, length);
+                    finished.hyperlinks = paragraph_hyperlinks(&content.hyperlinks, paragraph_start, length);
+                    finished.fields = paragraph_fields(&content.fields, paragraph_start, length);
+                    finished.math_zones = paragraph_math_zones(&content.math_zones, paragraph_start, length);
+                    finished.form_fields = paragraph_form_fields(&content.form_fields, paragraph_start, length);
+                    paragraphs.push(finished);
                 }
-                current_para = Paragraph::default();
-                current_para.text = part.to_string();
-
-                // Create run with piece attributes
-                let mut run = Run::default();
-                run.text = part.to_string();
+                paragraph_start += length + 1;
+                paragraph_index += 1;
+            }
+            if part.is_empty() {
+                continue;
+            }
 
-                // Convert TextAttributes to RunProperties
-                if let Some(ref attrs) = piece.attributes {
-                    run.properties = convert_attrs_to_run_props(attrs);
-                }
+            // Create run with piece attributes
+            let mut run = Run {
+                text: part.to_string(),
+                ..Default::default()
+            };
 
-                current_para.runs.push(run);
+            // Convert TextAttributes to RunProperties
+            if let Some(ref attrs) = piece.attributes {
+                run.properties = convert_attrs_to_run_props(attrs);
             }
+
+            current_para.text.push_str(part);
+            current_para.runs.push(run);
         }
     }
 
-    // Add last paragraph if not empty
-    if !current_para.text.is_empty() || !current_para.runs.is_empty() {
+    // Add last paragraph if not empty, or if its mark carries formatting
+    let final_formatted = content.final_mark.is_some() || content.paragraphs.get(paragraph_index).is_some_and(Option::is_some);
+    if !current_para.text.is_empty() || !current_para.runs.is_empty() || final_formatted {
+        let length = current_para.text.chars().count();
+        current_para.properties = properties(paragraph_index);
+        current_para.mark_properties = content.final_mark.clone();
+        current_para.revisions = paragraph_revisions(revisions, paragraph_start, length);
+        current_para.comment_marks = paragraph_comment_marks(&marks, paragraph_start, length);
+        current_para.bookmark_marks = paragraph_bookmark_marks(&bookmark_marks, paragraph_start, length);
+        current_para.hyperlinks = paragraph_hyperlinks(&content.hyperlinks, paragraph_start, length);
+        current_para.fields = paragraph_fields(&content.fields, paragraph_start, length);
+        current_para.math_zones = paragraph_math_zones(&content.math_zones, paragraph_start, length);
+        current_para.form_fields = paragraph_form_fields(&content.form_fields, paragraph_start, length);
         paragraphs.push(current_para);
     }
 
@@ -820,13 +2596,47 @@ pub fn piece_tree_to_word_document(tree: &PieceTree) -> WordDocument {
         .collect::<Vec<_>>()
         .join("\n");
 
-    WordDocument {
+    Ok(WordDocument {
         text,
         paragraphs,
-        styles: HashMap::new(),
+        styles: content.styles.clone(),
         theme: Some(create_default_theme()),
         core_properties: Some(CoreProperties::default()),
-    }
+        comments: content.comments.clone(),
+        numbering: content.numbering.clone(),
+        headers: content.headers.clone(),
+        footers: content.footers.clone(),
+        sections: content.sections.clone(),
+        even_and_odd_headers: content.even_and_odd_headers,
+        protection: content.protection,
+        tables: content.tables.clone(),
+        images: content.images.clone(),
+        footnotes: Vec::new(),
+        endnotes: Vec::new(),
+    })
+}
+
+/// The parts of `revisions` inside the paragraph of `length` chars at `start`, relative to it
+fn paragraph_revisions(revisions: &[Revision], start: usize, length: usize) -> Vec<Revision> {
+    let end = start + length;
+    revisions
+        .iter()
+        .filter_map(|revision| {
+            let from = revision.start.max(start);
+            let to = (revision.start + revision.length).min(end);
+            // Paragraph format changes cover their paragraph without covering its text
+            let inside = if revision.kind == RevisionKind::ParagraphFormat {
+                (start..=end).contains(&revision.start)
+            } else {
+                from < to
+            };
+            inside.then(|| Revision {
+                start: from - start,
+                length: to.saturating_sub(from),
+                ..revision.clone()
+            })
+        })
+        .collect()
 }
 
 /// Convert TextAttributes to RunProperties
@@ -876,17 +2686,41 @@ pub struct WordDocument {
     pub styles: HashMap<String, Style>,
     pub theme: Option<Theme>,
     pub core_properties: Option<CoreProperties>,
+    /// Comments; their anchors are the comment marks in the paragraphs
+    pub comments: Vec<Comment>,
+    /// List definitions, written to numbering.xml when there are any
+    pub numbering: Vec<Numbering>,
+    /// Headers and footers, written to header{n}.xml and footer{n}.xml; their
+    /// IDs are the relationship IDs the sections refer to them by
+    pub headers: Vec<Header>,
+    pub footers: Vec<Footer>,
+    /// Header and footer references and first-page flag of each section, in
+    /// body order; the last goes in the body's w:sectPr, the others at the end
+    /// of their last paragraph
+    pub sections: Vec<Section>,
+    /// Whether even pages have headers and footers of their own (w:evenAndOddHeaders in settings.xml)
+    pub even_and_odd_headers: bool,
+    /// Editing restriction (w:documentProtection in settings.xml)
+    pub protection: ProtectionMode,
+    /// Tables, each written before the body paragraph of its `paragraph_index`,
+    /// or after the last
+    pub tables: Vec<Table>,
+    /// Images, each drawn in the body paragraph of its `paragraph_index`
+    pub images: Vec<DocumentImage>,
+    /// Notes the paragraphs' note references point to; only HTML exports write them
+    pub footnotes: Vec<Footnote>,
+    pub endnotes: Vec<Endnote>,
 }
 
 /// Escape special XML characters in text content
-fn escape_xml_text(text: &str) -> String {
+pub(crate) fn escape_xml_text(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
 }
 
 /// Escape special XML characters in attribute values
-fn escape_xml_attr(attr: &str) -> String {
+pub(crate) fn escape_xml_attr(attr: &str) -> String {
     escape_xml_text(attr)
         .replace('\"', "&quot;")
         .replace('\'', "&apos;")
@@ -895,8 +2729,10 @@ fn escape_xml_attr(attr: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::types::PackagePart;
     use std::fs;
     use std::path::PathBuf;
+    use zip::write::FileOptions;
 
     #[test]
     fn test_serialize_empty_document() {
@@ -904,6 +2740,7 @@ mod tests {
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
         let result = serializer.export_docx(None);
@@ -924,12 +2761,14 @@ mod tests {
                 text: "Hello World".to_string(),
                 properties: RunProperties::default(),
             }],
+            ..Default::default()
         };
         doc.paragraphs.push(para);
 
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
         let result = serializer.export_docx(None);
@@ -957,12 +2796,14 @@ mod tests {
             text: "Bold and Italic".to_string(),
             properties: ParagraphProperties::default(),
             runs: vec![run],
+            ..Default::default()
         };
         doc.paragraphs.push(para);
 
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
         let result = serializer.export_docx(None);
@@ -980,8 +2821,9 @@ mod tests {
         style.style_type = "paragraph".to_string();
         style.run_properties.bold = Some(true);
         style.run_properties.font_size = Some(32);
+        style.next = Some("Normal".to_string());
 
-        doc.styles.insert("Heading1".to_string(), style);
+        doc.styles.insert("Heading1".to_string(), style.clone());
 
         let para = Paragraph {
             text: "Heading".to_string(),
@@ -990,6 +2832,7 @@ mod tests {
                 text: "Heading".to_string(),
                 properties: RunProperties::default(),
             }],
+            ..Default::default()
         };
         doc.paragraphs.push(para);
 
@@ -998,13 +2841,22 @@ mod tests {
             include_images: true,
             include_styles: true,
             include_theme: true,
+            macro_policy: MacroPolicy::Preserve,
+            page_setup: None,
+            preserve_unknown_parts: true,
+            html_images: HtmlImages::Embed,
+            plain_text: PlainTextOptions::default(),
+            ..Default::default()
         };
 
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
+        let xml = serializer.serialize_style(&style).unwrap();
+        assert!(xml.contains(r#"<w:next w:val="Normal"/>"#));
         let result = serializer.export_docx(Some(options));
         assert!(result.is_ok());
     }
@@ -1022,6 +2874,7 @@ mod tests {
                     text: format!("Paragraph {}", i),
                     properties: RunProperties::default(),
                 }],
+                ..Default::default()
             };
             doc.paragraphs.push(para);
         }
@@ -1029,6 +2882,7 @@ mod tests {
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
         let result = serializer.export_docx(None);
@@ -1049,12 +2903,14 @@ mod tests {
                 text: "Special chars: <>&\"'".to_string(),
                 properties: RunProperties::default(),
             }],
+            ..Default::default()
         };
         doc.paragraphs.push(para);
 
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
         let result = serializer.export_docx(None);
@@ -1071,11 +2927,18 @@ mod tests {
             include_images: false,
             include_styles: false,
             include_theme: false,
+            macro_policy: MacroPolicy::Preserve,
+            page_setup: None,
+            preserve_unknown_parts: true,
+            html_images: HtmlImages::Embed,
+            plain_text: PlainTextOptions::default(),
+            ..Default::default()
         };
 
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
         let result = serializer.export_docx(Some(options));
@@ -1087,6 +2950,7 @@ mod tests {
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: WordDocument::default(),
+            media: HashMap::new(),
         };
 
         let mut content_types = HashMap::new();
@@ -1120,12 +2984,12 @@ mod tests {
         tree.insert(0, "Bold".to_string());
 
         // Apply bold formatting by modifying the piece
-        if let Some(piece) = tree.pieces.first_mut() {
-            piece.attributes = Some(TextAttributes {
-                bold: Some(true),
-                ..Default::default()
-            });
-        }
+        let mut pieces = tree.pieces.to_vec();
+        pieces[0].attributes = Some(TextAttributes {
+            bold: Some(true),
+            ..Default::default()
+        });
+        tree.set_pieces(pieces);
 
         let doc = piece_tree_to_word_document(&tree);
         assert!(!doc.paragraphs.is_empty());
@@ -1143,6 +3007,7 @@ mod tests {
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
         let temp_path = PathBuf::from("/tmp/test_export.docx");
@@ -1165,6 +3030,7 @@ mod tests {
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
         let result = serializer.export_docx(Some(options));
@@ -1178,6 +3044,7 @@ mod tests {
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: WordDocument::default(),
+            media: HashMap::new(),
         };
 
         let relationships = vec![
@@ -1219,6 +3086,7 @@ mod tests {
                     text: format!("This is paragraph {}.", i),
                     properties: RunProperties::default(),
                 }],
+                ..Default::default()
             };
             doc.paragraphs.push(para);
         }
@@ -1227,6 +3095,7 @@ mod tests {
         let serializer = DocxSerializer {
             package: OpcPackage::new(&[]).unwrap_or_default(),
             document: doc,
+            media: HashMap::new(),
         };
 
         let result = serializer.export_docx(None);
@@ -1235,4 +3104,1108 @@ mod tests {
         // Check that data is non-empty (100 paragraphs should produce substantial output)
         assert!(!data.is_empty(), "Exported DOCX should not be empty");
     }
+
+    fn macro_package() -> OpcPackage {
+        let mut package = OpcPackage::default();
+        package.parts.insert(
+            "/word/vbaProject.bin".to_string(),
+            PackagePart {
+                name: "/word/vbaProject.bin".to_string(),
+                content_type: ContentType::VbaProject,
+                data: vec![0xD0, 0xCF, 0x11, 0xE0],
+            },
+        );
+        package
+    }
+
+    fn read_zip_entry(data: &[u8], name: &str) -> Option<Vec<u8>> {
+        use std::io::Read;
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).ok()?;
+        let mut file = archive.by_name(name).ok()?;
+        let mut out = Vec::new();
+        file.read_to_end(&mut out).ok()?;
+        Some(out)
+    }
+
+    #[test]
+    fn test_macros_preserved_by_default() {
+        let serializer = DocxSerializer {
+            package: macro_package(),
+            document: WordDocument::default(),
+            media: HashMap::new(),
+        };
+
+        let (data, warnings) = serializer.export_docx_with_warnings(None).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(read_zip_entry(&data, "word/vbaProject.bin").unwrap(), vec![0xD0, 0xCF, 0x11, 0xE0]);
+
+        let content_types = String::from_utf8(read_zip_entry(&data, "[Content_Types].xml").unwrap()).unwrap();
+        assert!(content_types.contains("application/vnd.ms-word.document.macroEnabled.main+xml"));
+        assert!(content_types.contains("application/vnd.ms-office.vbaProject"));
+
+        let rels = String::from_utf8(read_zip_entry(&data, "word/_rels/document.xml.rels").unwrap()).unwrap();
+        assert!(rels.contains("relationships/vbaProject"));
+        assert!(rels.contains(r#"Target="vbaProject.bin""#));
+    }
+
+    #[test]
+    fn test_macros_stripped_with_warning() {
+        let serializer = DocxSerializer {
+            package: macro_package(),
+            document: WordDocument::default(),
+            media: HashMap::new(),
+        };
+        let options = ExportOptions {
+            macro_policy: MacroPolicy::Strip,
+            page_setup: None,
+            ..Default::default()
+        };
+
+        let (data, warnings) = serializer.export_docx_with_warnings(Some(options)).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(read_zip_entry(&data, "word/vbaProject.bin").is_none());
+
+        let content_types = String::from_utf8(read_zip_entry(&data, "[Content_Types].xml").unwrap()).unwrap();
+        assert!(!content_types.contains("macroEnabled"));
+    }
+
+    #[test]
+    fn test_docm_format_without_macros() {
+        let serializer = DocxSerializer {
+            package: OpcPackage::default(),
+            document: WordDocument::default(),
+            media: HashMap::new(),
+        };
+        let options = ExportOptions {
+            format: ExportFormat::Docm,
+            ..Default::default()
+        };
+
+        let (data, warnings) = serializer.export_docx_with_warnings(Some(options)).unwrap();
+        assert!(warnings.is_empty());
+        let content_types = String::from_utf8(read_zip_entry(&data, "[Content_Types].xml").unwrap()).unwrap();
+        assert!(content_types.contains("macroEnabled"));
+    }
+
+    fn package_with_extensions() -> OpcPackage {
+        let mut package = OpcPackage::default();
+        for (name, content_type, data) in [
+            ("/customXml/item1.xml", ContentType::CustomXml, b"<data/>".to_vec()),
+            ("/customXml/itemProps1.xml", ContentType::CustomXmlProperties, b"<ds:datastoreItem/>".to_vec()),
+            ("/word/fonts/font1.odttf", ContentType::Unknown("application/vnd.openxmlformats-officedocument.obfuscatedFont".to_string()), vec![1, 2, 3]),
+            ("/word/document.xml", ContentType::MainDocument, b"<w:document/>".to_vec()),
+        ] {
+            package.parts.insert(name.to_string(), PackagePart { name: name.to_string(), content_type, data });
+        }
+        package.relationships.insert(
+            "/word/document.xml".to_string(),
+            vec![
+                Relationship {
+                    id: "rId9".to_string(),
+                    relationship_type: RelationshipType::CustomXml,
+                    target: "../customXml/item1.xml".to_string(),
+                    target_mode: None,
+                },
+                Relationship {
+                    id: "rId10".to_string(),
+                    relationship_type: RelationshipType::Unknown("http://schemas.openxmlformats.org/officeDocument/2006/relationships/font".to_string()),
+                    target: "fonts/font1.odttf".to_string(),
+                    target_mode: None,
+                },
+                Relationship {
+                    id: "rId11".to_string(),
+                    relationship_type: RelationshipType::Styles,
+                    target: "styles.xml".to_string(),
+                    target_mode: None,
+                },
+            ],
+        );
+        package
+    }
+
+    #[test]
+    fn test_unknown_parts_survive_save() {
+        let serializer = DocxSerializer::new(package_with_extensions(), WordDocument::default());
+        let data = serializer.export_docx(None).unwrap();
+
+        assert_eq!(read_zip_entry(&data, "customXml/item1.xml").unwrap(), b"<data/>");
+        assert_eq!(read_zip_entry(&data, "word/fonts/font1.odttf").unwrap(), vec![1, 2, 3]);
+        // The body is regenerated, not copied
+        assert_ne!(read_zip_entry(&data, "word/document.xml").unwrap(), b"<w:document/>");
+
+        let content_types = String::from_utf8(read_zip_entry(&data, "[Content_Types].xml").unwrap()).unwrap();
+        assert!(content_types.contains("application/vnd.openxmlformats-officedocument.obfuscatedFont"));
+        assert!(content_types.contains("customXmlProperties+xml"));
+
+        let rels = String::from_utf8(read_zip_entry(&data, "word/_rels/document.xml.rels").unwrap()).unwrap();
+        assert!(rels.contains(r#"Id="rId9""#) && rels.contains(r#"Target="../customXml/item1.xml""#));
+        assert!(rels.contains(r#"Id="rId10""#));
+        // Styles are written by the serializer, so the source relationship to them is not carried
+        assert!(!rels.contains(r#"Id="rId11""#));
+
+        // A second round trip keeps them too
+        let reopened = OpcPackage::new(&data).unwrap();
+        let data = DocxSerializer::new(reopened, WordDocument::default()).export_docx(None).unwrap();
+        assert_eq!(read_zip_entry(&data, "word/fonts/font1.odttf").unwrap(), vec![1, 2, 3]);
+        let rels = String::from_utf8(read_zip_entry(&data, "word/_rels/document.xml.rels").unwrap()).unwrap();
+        assert!(rels.contains(r#"Id="rId10""#));
+    }
+
+    #[test]
+    fn test_unknown_parts_dropped_without_preservation() {
+        let serializer = DocxSerializer::new(package_with_extensions(), WordDocument::default());
+        let options = ExportOptions {
+            preserve_unknown_parts: false,
+            ..Default::default()
+        };
+        let data = serializer.export_docx(Some(options)).unwrap();
+
+        assert!(read_zip_entry(&data, "customXml/item1.xml").is_none());
+        let rels = String::from_utf8(read_zip_entry(&data, "word/_rels/document.xml.rels").unwrap()).unwrap();
+        assert!(!rels.contains(r#"Id="rId9""#));
+    }
+
+    #[test]
+    fn test_statistics_written_to_app_xml() {
+        let mut package = OpcPackage::default();
+        let stale = br#"<Properties><TotalTime>10</TotalTime><Words>9999</Words></Properties>"#.to_vec();
+        package.parts.insert(
+            "/docProps/app.xml".to_string(),
+            PackagePart { name: "/docProps/app.xml".to_string(), content_type: ContentType::AppProperties, data: stale },
+        );
+        let mut document = WordDocument::default();
+        for text in ["Two words", "", "And three more"] {
+            document.paragraphs.push(Paragraph { text: text.to_string(), ..Default::default() });
+        }
+        let serializer = DocxSerializer::new(package, document);
+
+        let options = ExportOptions {
+            layout_statistics: Some(LayoutStatistics { pages: 1, lines: 3 }),
+            editing_minutes: 5,
+            ..Default::default()
+        };
+        let data = serializer.export_docx(Some(options)).unwrap();
+        let app = String::from_utf8(read_zip_entry(&data, "docProps/app.xml").unwrap()).unwrap();
+        assert!(app.contains("<TotalTime>15</TotalTime><Pages>1</Pages><Words>5</Words><Characters>20</Characters>"));
+        assert!(app.contains("<Lines>3</Lines><Paragraphs>2</Paragraphs><CharactersWithSpaces>23</CharactersWithSpaces>"));
+
+        let options = ExportOptions { document_statistics: false, ..Default::default() };
+        let data = serializer.export_docx(Some(options)).unwrap();
+        let app = String::from_utf8(read_zip_entry(&data, "docProps/app.xml").unwrap()).unwrap();
+        assert!(!app.contains("Words") && !app.contains("TotalTime"));
+    }
+
+    #[test]
+    fn test_page_setup_written_to_sect_pr() {
+        use crate::page_setup::{Orientation, PageSetup, PaperSize};
+
+        let mut setup = PageSetup::new();
+        setup.set_page_size(0, PaperSize::Letter).unwrap();
+        setup.set_orientation(0, Orientation::Landscape).unwrap();
+
+        let serializer = DocxSerializer {
+            package: OpcPackage::default(),
+            document: WordDocument::default(),
+            media: HashMap::new(),
+        };
+        let options = ExportOptions {
+            page_setup: setup.section(0).copied(),
+            ..Default::default()
+        };
+
+        let data = serializer.export_docx(Some(options)).unwrap();
+        let document = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(document.contains(r#"<w:sectPr><w:pgSz w:w="15840" w:h="12240" w:orient="landscape"/>"#));
+        assert!(document.ends_with("</w:sectPr></w:body></w:document>"));
+    }
+
+    #[test]
+    fn test_document_end_survives_editing_at_the_end() {
+        use crate::line_layout::Alignment;
+
+        let mut tree = PieceTree::new("Body\nEnd".to_string());
+        let centered = ParagraphAttributes {
+            alignment: Some(Alignment::Center),
+            ..Default::default()
+        };
+        tree.set_paragraph_attributes(6..6, Some(&centered));
+        // Empty the final paragraph, then type after it and delete that again
+        tree.delete(5, 3);
+        tree.insert(5, "more".to_string());
+        tree.delete(5, 4);
+
+        let content = ExportContent {
+            paragraphs: vec![None, Some(centered)],
+            final_mark: Some(RunProperties {
+                bold: Some(true),
+                ..Default::default()
+            }),
+            sections: vec![Section {
+                page_width: Some(11906),
+                page_height: Some(16838),
+                columns: 2,
+                column_space: Some(720),
+                title_page: true,
+                other_children: vec![
+                    r#"<w:docGrid w:linePitch="360"/>"#.to_string(),
+                    r#"<w:pgNumType w:start="5"/>"#.to_string(),
+                    r#"<w:footnotePr><w:numFmt w:val="lowerRoman"/></w:footnotePr>"#.to_string(),
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+
+        // The emptied final paragraph stays, with its formatting and its mark's
+        assert!(xml.contains(r#"<w:p><w:pPr><w:jc w:val="center"/><w:rPr><w:b w:val="1"/></w:rPr></w:pPr></w:p><w:sectPr>"#));
+        // The final section keeps everything it was read with, in schema order
+        assert!(xml.ends_with(concat!(
+            r#"<w:sectPr><w:footnotePr><w:numFmt w:val="lowerRoman"/></w:footnotePr>"#,
+            r#"<w:pgSz w:w="11906" w:h="16838"/><w:pgMar w:top="1440" w:right="1440" w:bottom="1440" w:left="1440" w:header="720" w:footer="720" w:gutter="0"/>"#,
+            r#"<w:pgNumType w:start="5"/><w:cols w:num="2" w:space="720"/><w:titlePg/><w:docGrid w:linePitch="360"/>"#,
+            "</w:sectPr></w:body></w:document>"
+        )));
+
+        let reread = super::super::document::WordDocument::parse(&OpcPackage::new(&data).unwrap()).unwrap();
+        assert_eq!(reread.text, "Body\n");
+        let end = reread.paragraphs.last().unwrap();
+        assert_eq!(end.properties.alignment.as_deref(), Some("center"));
+        assert_eq!(end.mark_properties.as_ref().and_then(|mark| mark.bold), Some(true));
+        assert_eq!(reread.sections.len(), 1);
+        assert_eq!(reread.sections[0].other_children, content.sections[0].other_children.iter().rev().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_revisions_written_as_ins_and_del() {
+        let revision = |id: &str, kind: RevisionKind, start: usize, length: usize| Revision {
+            id: id.to_string(),
+            kind,
+            author: Some("Ann & Bob".to_string()),
+            date: Some("2026-01-02T03:04:05Z".to_string()),
+            start,
+            length,
+            previous_run_properties: None,
+            previous_paragraph_properties: None,
+        };
+        // Offsets are into the whole text; the second paragraph starts at 22
+        let revisions = vec![
+            revision("1", RevisionKind::Insertion, 6, 6),
+            revision("2", RevisionKind::Deletion, 12, 4),
+            revision("3", RevisionKind::Insertion, 22, 3),
+        ];
+        let tree = PieceTree::new("Hello brave new world\nOld text".to_string());
+        let content = ExportContent {
+            revisions,
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        assert_eq!(document.paragraphs[1].revisions[0].start, 0);
+
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(
+            r#"<w:r><w:t xml:space="preserve">Hello </w:t></w:r><w:ins w:id="1" w:author="Ann &amp; Bob" w:date="2026-01-02T03:04:05Z"><w:r><w:t xml:space="preserve">brave </w:t></w:r></w:ins>"#
+        ));
+        assert!(xml.contains(r#"<w:del w:id="2" w:author="Ann &amp; Bob" w:date="2026-01-02T03:04:05Z"><w:r><w:delText xml:space="preserve">new </w:delText></w:r></w:del><w:r><w:t>world</w:t></w:r>"#));
+
+        // Read back, the revisions cover the same text
+        let parsed = crate::ooxml::parse_ooxml(&data).unwrap();
+        let read: Vec<(RevisionKind, usize, usize)> =
+            parsed.revisions.iter().map(|r| (r.kind, r.start, r.length)).collect();
+        assert_eq!(
+            read,
+            vec![(RevisionKind::Insertion, 6, 6), (RevisionKind::Deletion, 12, 4), (RevisionKind::Insertion, 22, 3)]
+        );
+        assert_eq!(parsed.revisions[0].author.as_deref(), Some("Ann & Bob"));
+    }
+
+    #[test]
+    fn test_comments_round_trip() {
+        let mut comments = crate::comments::CommentManager::new();
+        // "brave new world" runs into the second paragraph, which starts at 22
+        let first = comments.add(6..25, "Ann Baker", "Which world?\nSecond line");
+        let reply = comments.reply(&first, "Bob", "This one").unwrap();
+        comments.set_resolved(&first, true).unwrap();
+        let point = comments.add(30..30, "Bob", "Add more");
+
+        let tree = PieceTree::new("Hello brave new world\nOld text".to_string());
+        let content = ExportContent {
+            comments: comments.comments().to_vec(),
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:r><w:t xml:space="preserve">Hello </w:t></w:r><w:commentRangeStart w:id="0"/><w:commentRangeStart w:id="1"/><w:r><w:t>brave new world</w:t></w:r>"#));
+        assert!(xml.contains(r#"<w:r><w:t>Old</w:t></w:r><w:commentRangeEnd w:id="0"/><w:commentRangeEnd w:id="1"/><w:r><w:commentReference w:id="0"/></w:r>"#));
+        let content_types = String::from_utf8(read_zip_entry(&data, "[Content_Types].xml").unwrap()).unwrap();
+        assert!(content_types.contains("wordprocessingml.comments+xml"));
+        let rels = String::from_utf8(read_zip_entry(&data, "word/_rels/document.xml.rels").unwrap()).unwrap();
+        assert!(rels.contains(r#"Target="commentsExtended.xml""#));
+
+        let parsed = crate::ooxml::parse_ooxml(&data).unwrap();
+        let read: Vec<(&str, Option<&str>, bool, usize, usize)> = parsed
+            .comments
+            .iter()
+            .map(|c| (c.id.as_str(), c.parent_id.as_deref(), c.done, c.start, c.length))
+            .collect();
+        assert_eq!(
+            read,
+            vec![
+                (first.as_str(), None, true, 6, 19),
+                (reply.as_str(), Some(first.as_str()), true, 6, 19),
+                (point.as_str(), None, false, 30, 0),
+            ]
+        );
+        assert_eq!(parsed.comments[0].text(), "Which world?\nSecond line");
+        assert_eq!(parsed.comments[0].initials.as_deref(), Some("AB"));
+    }
+
+    #[test]
+    fn test_bookmarks_round_trip() {
+        let mut bookmarks = crate::bookmarks::BookmarkRegistry::new();
+        // "world\nOld" spans the paragraph break at 11
+        bookmarks.insert("Span", 6..15).unwrap();
+        bookmarks.insert("_Ref1", 20..20).unwrap();
+
+        let tree = PieceTree::new("Hello world\nOld text".to_string());
+        let content = ExportContent {
+            bookmarks: bookmarks.bookmarks().to_vec(),
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:r><w:t xml:space="preserve">Hello </w:t></w:r><w:bookmarkStart w:id="0" w:name="Span"/><w:r><w:t>world</w:t></w:r>"#));
+        assert!(xml.contains(r#"<w:r><w:t>Old</w:t></w:r><w:bookmarkEnd w:id="0"/>"#));
+        assert!(xml.contains(r#"<w:bookmarkStart w:id="1" w:name="_Ref1"/><w:bookmarkEnd w:id="1"/></w:p>"#));
+
+        let parsed = crate::ooxml::parse_ooxml(&data).unwrap();
+        assert_eq!(parsed.bookmarks, bookmarks.bookmarks());
+    }
+
+    #[test]
+    fn test_theme_relationship_names_the_theme_part() {
+        let tree = PieceTree::new("Hello".to_string());
+        let document = snapshot_to_word_document(&tree.snapshot(), &ExportContent::default(), &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+
+        // The target is relative to word/, where the document part is
+        let package = OpcPackage::new(&data).unwrap();
+        let theme = package
+            .get_relationships("/word/document.xml")
+            .and_then(|relationships| relationships.iter().find(|r| r.relationship_type == RelationshipType::Theme))
+            .unwrap();
+        let part = package.get_part(&resolve_part_name("/word/", &theme.target)).unwrap();
+        assert_eq!(part.name, "/word/theme/theme1.xml");
+    }
+
+    #[test]
+    fn test_headers_footers_round_trip() {
+        use crate::headers_footers::{HeaderFooterKind, HeaderFooterManager, HeaderFooterVariant};
+
+        let mut manager = HeaderFooterManager::new();
+        manager.story_mut(0, HeaderFooterKind::Header, HeaderFooterVariant::Default).insert(0, "Annual report".to_string());
+        manager.story_mut(0, HeaderFooterKind::Header, HeaderFooterVariant::Even).insert(0, "Even page".to_string());
+        manager.story_mut(0, HeaderFooterKind::Footer, HeaderFooterVariant::First).insert(0, "Cover".to_string());
+        manager.set_different_first_page(0, true);
+        manager.set_different_odd_even(true);
+
+        let (headers, footers, sections) = manager.to_ooxml();
+        let content = ExportContent {
+            headers,
+            footers,
+            sections,
+            even_and_odd_headers: manager.different_odd_even(),
+            ..Default::default()
+        };
+        let tree = PieceTree::new("Body".to_string());
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document.clone()).export_docx(None).unwrap();
+
+        let header = String::from_utf8(read_zip_entry(&data, "word/header1.xml").unwrap()).unwrap();
+        assert!(header.contains("<w:hdr ") && header.contains("<w:t>Annual report</w:t>"));
+        let footer = String::from_utf8(read_zip_entry(&data, "word/footer1.xml").unwrap()).unwrap();
+        assert!(footer.contains("<w:ftr ") && footer.contains("<w:t>Cover</w:t>"));
+        let rels = String::from_utf8(read_zip_entry(&data, "word/_rels/document.xml.rels").unwrap()).unwrap();
+        assert!(rels.contains(r#"relationships/header" Target="header1.xml""#));
+        assert!(rels.contains(r#"relationships/footer" Target="footer1.xml""#));
+        let types = String::from_utf8(read_zip_entry(&data, "[Content_Types].xml").unwrap()).unwrap();
+        assert!(types.contains(r#"PartName="/word/footer1.xml""#));
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:headerReference w:type="even" r:id="#));
+        assert!(xml.contains(r#"<w:footerReference w:type="first" r:id="#));
+        assert!(xml.contains("<w:titlePg/></w:sectPr>"));
+        let settings = String::from_utf8(read_zip_entry(&data, "word/settings.xml").unwrap()).unwrap();
+        assert!(settings.contains("<w:evenAndOddHeaders/>"));
+
+        // Document variables go into the same settings, whatever the compression
+        let options = ExportOptions {
+            document_variables: vec![("LastEdit".to_string(), "3".to_string())],
+            compression: PackageCompression { xml: crate::ooxml::PartCompression::Stored, ..Default::default() },
+            ..Default::default()
+        };
+        let stored = DocxSerializer::new(OpcPackage::default(), document).export_docx(Some(options)).unwrap();
+        let settings = String::from_utf8(read_zip_entry(&stored, "word/settings.xml").unwrap()).unwrap();
+        assert!(settings.contains(r#"<w:evenAndOddHeaders/><w:docVars><w:docVar w:name="LastEdit" w:val="3"/></w:docVars>"#));
+        assert_eq!(crate::ooxml::document_variable(&stored, "LastEdit").unwrap().as_deref(), Some("3"));
+        let mut archive = zip::ZipArchive::new(Cursor::new(&stored)).unwrap();
+        assert_eq!(archive.by_name("word/settings.xml").unwrap().compression(), zip::CompressionMethod::Stored);
+
+        // Reading it back gives every variant to the same section
+        let parsed = crate::ooxml::parse_ooxml(&data).unwrap();
+        let read = HeaderFooterManager::from_ooxml(&parsed.headers, &parsed.footers, &parsed.sections);
+        let text = |kind, variant| read.story(0, kind, variant).map(PieceTree::get_text);
+        assert_eq!(text(HeaderFooterKind::Header, HeaderFooterVariant::Default).as_deref(), Some("Annual report"));
+        assert_eq!(text(HeaderFooterKind::Header, HeaderFooterVariant::Even).as_deref(), Some("Even page"));
+        assert_eq!(text(HeaderFooterKind::Footer, HeaderFooterVariant::First).as_deref(), Some("Cover"));
+        assert!(read.different_first_page(0));
+    }
+
+    #[test]
+    fn test_heading_numbering_round_trip() {
+        use crate::numbering::{ListNumbering, OutlineScheme};
+        use crate::style_sheet::StyleSheet;
+
+        let mut styles = StyleSheet::new();
+        let mut numbering = ListNumbering::new();
+        let num_id = numbering.set_heading_numbering(&mut styles, Some(OutlineScheme::Legal)).unwrap();
+        let heading = |level: u8| {
+            Some(ParagraphAttributes {
+                style_id: Some(format!("Heading{}", level)),
+                ..Default::default()
+            })
+        };
+
+        let tree = PieceTree::new("Intro\nDefinitions\nTerms".to_string());
+        let content = ExportContent {
+            styles: styles.to_ooxml_styles(),
+            numbering: numbering.to_ooxml(),
+            paragraphs: vec![None, heading(1), heading(2)],
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:p><w:r><w:t>Intro</w:t></w:r></w:p><w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr>"#));
+        let numbering_xml = String::from_utf8(read_zip_entry(&data, "word/numbering.xml").unwrap()).unwrap();
+        assert!(numbering_xml.contains(r#"<w:numFmt w:val="decimalZero"/><w:pStyle w:val="Heading2"/><w:isLgl/><w:lvlText w:val="Section %1.%2"/>"#));
+        let rels = String::from_utf8(read_zip_entry(&data, "word/_rels/document.xml.rels").unwrap()).unwrap();
+        assert!(rels.contains(r#"relationships/numbering" Target="numbering.xml""#));
+
+        // The styles still link the headings to the list after reading it back
+        let parsed = crate::ooxml::parse_ooxml(&data).unwrap();
+        let styles = StyleSheet::from_ooxml_styles(&parsed.styles);
+        let numbering = ListNumbering::from_ooxml(&parsed.numbering);
+        assert_eq!(numbering.heading_list(&styles), Some(num_id));
+        let paragraphs: Vec<ParagraphAttributes> = [None, heading(1), heading(2)]
+            .iter()
+            .map(|direct| styles.effective_paragraph(direct.as_ref()))
+            .collect();
+        assert_eq!(numbering.labels(&paragraphs), [None, Some("Article I.".to_string()), Some("Section 1.01".to_string())]);
+    }
+
+    #[test]
+    fn test_table_round_trip() {
+        use super::super::types::{TableBorders, TableCellProperties, TableProperties, TableRowProperties};
+
+        let cell = |text: &str, properties: TableCellProperties, vertical_merge: Option<i32>| TableCell {
+            paragraphs: match text {
+                "" => Vec::new(),
+                text => vec![Paragraph {
+                    text: text.to_string(),
+                    runs: vec![Run {
+                        text: text.to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+            },
+            vertical_merge,
+            properties,
+            ..Default::default()
+        };
+        let header = TableRow {
+            cells: vec![cell(
+                "Quarter",
+                TableCellProperties {
+                    grid_span: Some(2),
+                    shading_color: Some("D9E2F3".to_string()),
+                    ..Default::default()
+                },
+                None,
+            )],
+            properties: TableRowProperties {
+                height: Some(400),
+                height_rule: Some("atLeast".to_string()),
+                is_header: true,
+            },
+            ..Default::default()
+        };
+        let q1 = TableCellProperties {
+            width: Some(2000),
+            vertical_alignment: Some("center".to_string()),
+            ..Default::default()
+        };
+        let row = |cells| TableRow {
+            cells,
+            ..Default::default()
+        };
+        let table = Table {
+            rows: vec![
+                header,
+                row(vec![cell("Q1", q1, Some(1)), cell("Sales", Default::default(), None)]),
+                row(vec![cell("", Default::default(), Some(-1)), cell("Costs", Default::default(), None)]),
+            ],
+            properties: TableProperties {
+                width: Some(6000),
+                alignment: Some("center".to_string()),
+                borders: TableBorders {
+                    top: Some(TableBorder {
+                        style: Some("single".to_string()),
+                        size: Some(8),
+                        color: Some("FF0000".to_string()),
+                    }),
+                    inside_horizontal: Some(TableBorder {
+                        style: Some("dotted".to_string()),
+                        size: Some(4),
+                        color: None,
+                    }),
+                    ..Default::default()
+                },
+                indent: Some(120),
+                layout: Some("fixed".to_string()),
+                grid_columns: vec![2000, 4000],
+            },
+            paragraph_index: 1,
+        };
+
+        let tree = PieceTree::new("Before\nAfter".to_string());
+        let content = ExportContent {
+            tables: vec![table],
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:t>Before</w:t></w:r></w:p><w:tbl><w:tblPr><w:tblW w:w="6000" w:type="dxa"/><w:jc w:val="center"/>"#));
+        assert!(xml.contains(r#"<w:tblBorders><w:top w:val="single" w:sz="8" w:space="0" w:color="FF0000"/><w:insideH w:val="dotted" w:sz="4" w:space="0" w:color="auto"/></w:tblBorders>"#));
+        assert!(xml.contains(r#"<w:tblGrid><w:gridCol w:w="2000"/><w:gridCol w:w="4000"/></w:tblGrid>"#));
+        assert!(xml.contains(r#"<w:trPr><w:trHeight w:val="400" w:hRule="atLeast"/><w:tblHeader/></w:trPr>"#));
+        assert!(xml.contains(r#"<w:gridSpan w:val="2"/><w:shd w:val="clear" w:color="auto" w:fill="D9E2F3"/>"#));
+        assert!(xml.contains(r#"<w:vMerge w:val="restart"/><w:vAlign w:val="center"/>"#));
+        // A merged-away cell still has a paragraph
+        assert!(xml.contains(r#"<w:vMerge/></w:tcPr><w:p></w:p></w:tc>"#));
+        assert!(xml.contains(r#"</w:tbl><w:p><w:r><w:t>After</w:t>"#));
+
+        let parsed = crate::ooxml::document::WordDocument::parse(&OpcPackage::new(&data).unwrap()).unwrap();
+        assert_eq!(parsed.paragraphs.len(), 2);
+        let table = &parsed.tables[0];
+        assert_eq!(table.paragraph_index, 1);
+        let props = &table.properties;
+        assert_eq!((props.width, props.indent, props.alignment.as_deref()), (Some(6000), Some(120), Some("center")));
+        assert_eq!(props.layout.as_deref(), Some("fixed"));
+        assert_eq!(props.grid_columns, [2000, 4000]);
+        let top = props.borders.top.as_ref().unwrap();
+        assert_eq!((top.style.as_deref(), top.size, top.color.as_deref()), (Some("single"), Some(8), Some("FF0000")));
+        assert_eq!(props.borders.inside_horizontal.as_ref().and_then(|b| b.style.as_deref()), Some("dotted"));
+        assert!(props.borders.left.is_none());
+
+        assert_eq!(table.rows.len(), 3);
+        let header = &table.rows[0];
+        assert!(header.properties.is_header);
+        assert_eq!((header.properties.height, header.properties.height_rule.as_deref()), (Some(400), Some("atLeast")));
+        assert_eq!(header.cells[0].properties.grid_span, Some(2));
+        assert_eq!(header.cells[0].properties.shading_color.as_deref(), Some("D9E2F3"));
+        assert_eq!(header.cells[0].paragraphs[0].text, "Quarter");
+        let (q1, continued) = (&table.rows[1].cells[0], &table.rows[2].cells[0]);
+        assert_eq!((q1.vertical_merge, q1.width), (Some(1), Some(2000)));
+        assert_eq!(q1.properties.vertical_alignment.as_deref(), Some("center"));
+        assert_eq!(continued.vertical_merge, Some(-1));
+        assert!(continued.paragraphs.is_empty());
+        assert_eq!(table.rows[2].cells[1].paragraphs[0].text, "Costs");
+
+        // The model keeps the table between the paragraphs
+        let model = crate::document_model::DocumentModel::from_word_document(&parsed);
+        let kinds: Vec<bool> = model.body.iter().map(|block| matches!(block, crate::document_model::Block::Table(_))).collect();
+        assert_eq!(kinds, [false, true, false]);
+    }
+
+    #[test]
+    fn test_table_grid_from_cells() {
+        let cell = |width: Option<u32>, grid_span: Option<u32>| TableCell {
+            properties: super::super::types::TableCellProperties {
+                width,
+                grid_span,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut table = Table {
+            rows: vec![
+                TableRow {
+                    cells: vec![cell(None, Some(3))],
+                    ..Default::default()
+                },
+                TableRow {
+                    cells: vec![cell(Some(3000), Some(2)), cell(None, None)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        // The widest row decides; the cell without a width gets the rest of the default width
+        assert_eq!(table_grid(&table), [1500, 1500, DEFAULT_TABLE_WIDTH - 3000]);
+        table.properties.width = Some(5000);
+        assert_eq!(table_grid(&table), [1500, 1500, 2000]);
+    }
+
+    #[test]
+    fn test_paragraph_border_round_trip() {
+        use super::super::types::ParagraphBorder;
+
+        let border = ParagraphBorder {
+            style: "double".to_string(),
+            size: 6,
+            space: 1,
+        };
+        let tree = PieceTree::new("Title\n".to_string());
+        let content = ExportContent {
+            paragraphs: vec![
+                Some(ParagraphAttributes {
+                    border_bottom: Some(border.clone()),
+                    ..Default::default()
+                }),
+                None,
+            ],
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:pBdr><w:bottom w:val="double" w:sz="6" w:space="1" w:color="auto"/></w:pBdr>"#));
+
+        let tree = crate::document_model::DocumentModel::from_docx(&data).unwrap().to_piece_tree();
+        assert_eq!(tree.paragraph_attributes(0).and_then(|a| a.border_bottom.as_ref()), Some(&border));
+        assert!(tree.paragraph_attributes(1).is_none());
+    }
+
+    #[test]
+    fn test_pagination_properties_round_trip() {
+        let tree = PieceTree::new("Heading\nBody".to_string());
+        let attributes = ParagraphAttributes {
+            keep_with_next: Some(true),
+            page_break_before: Some(true),
+            widow_control: Some(false),
+            ..Default::default()
+        };
+        let content = ExportContent {
+            paragraphs: vec![Some(attributes.clone()), None],
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:pPr><w:keepNext/><w:pageBreakBefore/><w:widowControl w:val="0"/></w:pPr>"#));
+
+        let tree = crate::document_model::DocumentModel::from_docx(&data).unwrap().to_piece_tree();
+        assert_eq!(tree.paragraph_attributes(0), Some(&attributes));
+        let layout = attributes.layout_properties();
+        assert!(layout.keep_with_next && layout.page_break_before && !layout.widow_control && !layout.keep_lines_together);
+    }
+
+    #[test]
+    fn test_list_level_details_round_trip() {
+        use super::super::types::{AbstractNumDef, LevelOverride, ListLevel, NumInstance};
+
+        let level = ListLevel {
+            level: 1,
+            format: "lowerLetter".to_string(),
+            text: "%2)".to_string(),
+            start_value: 1,
+            restart: Some(0),
+            suffix: Some("space".to_string()),
+            paragraph_properties: ParagraphProperties {
+                indent_left: Some(1440),
+                indent_first_line: Some(-360),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let numbering = Numbering {
+            abstract_num_defs: vec![AbstractNumDef {
+                abstract_num_id: "0".to_string(),
+                levels: vec![level],
+            }],
+            num_instances: vec![NumInstance {
+                num_id: "4".to_string(),
+                abstract_num_id: "0".to_string(),
+                overrides: vec![LevelOverride {
+                    level: 1,
+                    start_value: Some(3),
+                    text: Some("(%2)".to_string()),
+                    format: Some("upperLetter".to_string()),
+                }],
+            }],
+        };
+
+        let tree = PieceTree::new("Item".to_string());
+        let content = ExportContent {
+            numbering: vec![numbering],
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+        let xml = String::from_utf8(read_zip_entry(&data, "word/numbering.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:numFmt w:val="lowerLetter"/><w:lvlRestart w:val="0"/><w:suff w:val="space"/>"#));
+        assert!(xml.contains(r#"<w:lvlOverride w:ilvl="1"><w:startOverride w:val="3"/><w:lvl w:ilvl="1"><w:numFmt w:val="upperLetter"/><w:lvlText w:val="(%2)"/></w:lvl></w:lvlOverride>"#));
+
+        let parsed = crate::ooxml::parse_ooxml(&data).unwrap();
+        let level = &parsed.numbering[0].abstract_num_defs[0].levels[0];
+        assert_eq!((level.restart, level.suffix.as_deref()), (Some(0), Some("space")));
+        assert_eq!(level.paragraph_properties.indent_first_line, Some(-360));
+        let list = crate::numbering::ListNumbering::from_ooxml(&parsed.numbering);
+        let item = ParagraphAttributes {
+            num_id: Some("4".to_string()),
+            list_level: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(list.labels(&[item]), [Some("(C)".to_string())]);
+    }
+
+    #[test]
+    fn test_hyperlinks_round_trip() {
+        let mut links = crate::hyperlinks::HyperlinkSet::new();
+        links.insert(6..11, Some("https://example.com/?a=1&b=2"), None, Some("Example")).unwrap();
+        links.insert(12..15, None, Some("Terms"), None).unwrap();
+        links.insert(16..20, Some("https://example.com/?a=1&b=2"), None, None).unwrap();
+        // A deletion running out of the first link is closed where the link ends
+        let deletion = Revision {
+            id: "1".to_string(),
+            kind: RevisionKind::Deletion,
+            author: Some("Ann".to_string()),
+            date: None,
+            start: 9,
+            length: 3,
+            previous_run_properties: None,
+            previous_paragraph_properties: None,
+        };
+
+        let tree = PieceTree::new("Hello world\nOld text".to_string());
+        let content = ExportContent {
+            revisions: vec![deletion],
+            hyperlinks: links.hyperlinks().to_vec(),
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:hyperlink r:id="rIdLink1" w:tooltip="Example" w:history="1"><w:r><w:t>wor</w:t></w:r><w:del w:id="1" w:author="Ann"><w:r><w:delText>ld</w:delText></w:r></w:del></w:hyperlink>"#));
+        assert!(xml.contains(r#"<w:hyperlink w:anchor="Terms" w:history="1"><w:r><w:t>Old</w:t></w:r></w:hyperlink>"#));
+        let rels = String::from_utf8(read_zip_entry(&data, "word/_rels/document.xml.rels").unwrap()).unwrap();
+        // Both links to the URL share one relationship
+        assert_eq!(rels.matches("relationships/hyperlink").count(), 1);
+        assert!(rels.contains(r#"Target="https://example.com/?a=1&amp;b=2" TargetMode="External""#));
+
+        let parsed = crate::ooxml::parse_ooxml(&data).unwrap();
+        assert_eq!(parsed.hyperlinks, links.hyperlinks());
+    }
+
+    #[test]
+    fn test_whitespace_round_trip() {
+        let text = "  two  leading\n \n\tTab\tand line\u{2028}break \ntrailing   ";
+        let tree = PieceTree::new(text.to_string());
+        let document = snapshot_to_word_document(&tree.snapshot(), &ExportContent::default(), &ExportControl::new()).unwrap();
+        let (data, warnings) = DocxSerializer::new(OpcPackage::default(), document).export_docx_with_warnings(None).unwrap();
+        assert!(warnings.is_empty());
+
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:t xml:space="preserve">  two  leading</w:t>"#));
+        assert!(xml.contains(r#"<w:r><w:t xml:space="preserve"> </w:t></w:r>"#));
+        assert!(xml.contains(r#"<w:tab/><w:t>Tab</w:t><w:tab/><w:t>and line</w:t><w:br/><w:t xml:space="preserve">break </w:t>"#));
+
+        let parsed = crate::ooxml::parse_ooxml(&data).unwrap();
+        assert_eq!(parsed.text, text);
+    }
+
+    #[test]
+    fn test_math_zones_round_trip() {
+        let mut zones = crate::math::MathZones::new();
+        zones.toggle(5..10);
+        zones.toggle(16..22);
+        let mut links = crate::hyperlinks::HyperlinkSet::new();
+        links.insert(11..15, None, Some("End"), None).unwrap();
+
+        let tree = PieceTree::new("Area x^2/2 done\n√(3&y)".to_string());
+        let content = ExportContent {
+            hyperlinks: links.hyperlinks().to_vec(),
+            math_zones: zones.zones().to_vec(),
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        // The linear text is not written, only the equation
+        assert!(!xml.contains("x^2"));
+        assert!(xml.contains(r#"<w:t xml:space="preserve">Area </w:t></w:r><m:oMath><m:f><m:num><m:sSup>"#));
+        assert!(xml.contains(r#"</m:oMath><w:r><w:t xml:space="preserve"> </w:t></w:r><w:hyperlink w:anchor="End""#));
+        assert!(xml.contains("<m:rad><m:deg><m:r>"));
+
+        let model = crate::document_model::DocumentModel::from_docx(&data).unwrap();
+        assert_eq!(model.paragraphs().map(|p| p.text.as_str()).collect::<Vec<_>>(), ["Area x^2/2 done", "√(3&y)"]);
+        assert_eq!(crate::math::MathZones::from_model(&model).zones(), zones.zones());
+        assert_eq!(crate::hyperlinks::HyperlinkSet::from_model(&model).hyperlinks(), links.hyperlinks());
+    }
+
+    #[test]
+    fn test_index_fields_round_trip() {
+        let text = "Apples and pears\nIndex\nApple, 1\nFruit\nPear, 1\nThe end";
+        let mut index = crate::index::DocumentIndex::new();
+        index.mark(6, "Apple", None).unwrap();
+        index.mark(16, "Fruit:Pear", None).unwrap();
+        // The index runs over three paragraphs
+        index.set_field("INDEX", 23..45, "Apple, 1\nFruit\nPear, 1");
+
+        let tree = PieceTree::new(text.to_string());
+        let content = ExportContent {
+            fields: index.fields(),
+            ..Default::default()
+        };
+        let document = snapshot_to_word_document(&tree.snapshot(), &content, &ExportControl::new()).unwrap();
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert_eq!(xml.matches(r#"w:fldCharType="begin""#).count(), 3);
+        assert_eq!(xml.matches(r#"w:fldCharType="separate""#).count(), 1);
+        assert_eq!(xml.matches(r#"w:fldCharType="end""#).count(), 3);
+        assert!(xml.contains(r#"<w:t>Pear, 1</w:t></w:r><w:r><w:fldChar w:fldCharType="end"/></w:r></w:p>"#));
+
+        let parsed = crate::ooxml::parse_ooxml(&data).unwrap();
+        assert_eq!(parsed.text, text);
+        assert_eq!(parsed.fields, index.fields());
+    }
+
+    #[test]
+    fn test_paragraph_frame_round_trip() {
+        let envelope =
+            crate::mailings::envelope(crate::mailings::EnvelopeSize::DL, "Ann Lee\n1 Main St", "").unwrap();
+        let document = WordDocument {
+            text: "Ann Lee\n1 Main St".to_string(),
+            paragraphs: envelope.model.paragraphs().cloned().collect(),
+            ..Default::default()
+        };
+
+        let data = DocxSerializer::new(OpcPackage::default(), document).export_docx(None).unwrap();
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:framePr w:w="6694" w:wrap="around" w:hAnchor="page" w:vAnchor="page" w:x="5238" w:y="2993"/>"#));
+
+        let parsed = crate::ooxml::document::WordDocument::parse(&OpcPackage::new(&data).unwrap()).unwrap();
+        let frame = parsed.paragraphs[0].properties.frame.as_ref();
+        assert_eq!(frame, envelope.model.paragraphs().next().unwrap().properties.frame.as_ref());
+    }
+
+    #[test]
+    fn test_images_round_trip() {
+        let mut cache = ImageCache::new();
+        let png = vec![
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R',
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01,
+        ];
+        cache.load("media/logo.png".to_string(), png.clone()).unwrap();
+        let transform = crate::ooxml::types::ImageTransform {
+            crop: Some(crate::ooxml::types::SourceRect { left: 10.0, top: 0.0, right: 12.5, bottom: 5.0 }),
+            rotation: 90.0,
+            flip_horizontal: true,
+            brightness: 20.0,
+            contrast: -10.0,
+            transparency: 40.0,
+            ..Default::default()
+        };
+        let inline = DocumentImage {
+            path: "media/logo.png".to_string(),
+            desired_width: Some(1_828_800),
+            desired_height: Some(914_400),
+            alt_description: Some("Logo & mark".to_string()),
+            paragraph_index: 0,
+            position: 4,
+            transform: transform.clone(),
+            ..Default::default()
+        };
+        let anchored = DocumentImage {
+            paragraph_index: 1,
+            position: 0,
+            desired_width: None,
+            desired_height: None,
+            anchor: Some(crate::ooxml::types::ImageAnchor {
+                horizontal_offset: 457_200,
+                horizontal_relative_to: "page".to_string(),
+                wrap: "tight".to_string(),
+                z_order: 251_659_264,
+                allow_overlap: true,
+                ..Default::default()
+            }),
+            transform: Default::default(),
+            ..inline.clone()
+        };
+        let document = WordDocument {
+            text: "Our logo\nSee".to_string(),
+            paragraphs: ["Our logo", "See"]
+                .iter()
+                .map(|text| Paragraph { text: text.to_string(), runs: vec![Run { text: text.to_string(), ..Default::default() }], ..Default::default() })
+                .collect(),
+            images: vec![inline, anchored],
+            ..Default::default()
+        };
+
+        let data = DocxSerializer::new(OpcPackage::default(), document)
+            .with_image_cache(&cache)
+            .export_docx(None)
+            .unwrap();
+        assert_eq!(read_zip_entry(&data, "word/media/logo.png").unwrap(), png);
+        let types = String::from_utf8(read_zip_entry(&data, "[Content_Types].xml").unwrap()).unwrap();
+        assert!(types.contains(r#"PartName="/word/media/logo.png" ContentType="image/png""#));
+        let rels = String::from_utf8(read_zip_entry(&data, "word/_rels/document.xml.rels").unwrap()).unwrap();
+        assert_eq!(rels.matches(r#"Target="media/logo.png""#).count(), 1);
+        let xml = String::from_utf8(read_zip_entry(&data, "word/document.xml").unwrap()).unwrap();
+        assert!(xml.contains(r#"<w:t xml:space="preserve">Our </w:t></w:r><w:r><w:drawing><wp:inline"#));
+        assert!(xml.contains(r#"descr="Logo &amp; mark""#));
+        assert!(xml.contains(r#"<wp:positionH relativeFrom="page"><wp:posOffset>457200</wp:posOffset></wp:positionH>"#));
+        // The anchored image has no size of its own and takes its data's
+        assert!(xml.contains(r#"<wp:extent cx="19050" cy="9525"/>"#));
+
+        let parsed = crate::ooxml::document::WordDocument::parse(&OpcPackage::new(&data).unwrap()).unwrap();
+        assert_eq!(parsed.images.len(), 2);
+        assert_eq!((parsed.images[0].paragraph_index, parsed.images[0].position), (0, 4));
+        assert_eq!(parsed.images[0].extent(), Some((1_828_800, 914_400)));
+        assert_eq!(parsed.images[0].alt_description.as_deref(), Some("Logo & mark"));
+        assert_eq!(parsed.images[0].path, "media/logo.png");
+        assert!(xml.contains(r#"<a:lum bright="20000" contrast="-10000"/></a:blip><a:srcRect l="10000" t="0" r="12500" b="5000"/>"#));
+        assert!(xml.contains(r#"<a:xfrm rot="5400000" flipH="1">"#));
+        assert_eq!(parsed.images[0].transform, transform);
+        assert_eq!(parsed.images[1].transform, Default::default());
+        let anchor = parsed.images[1].anchor.as_ref().unwrap();
+        assert_eq!((anchor.horizontal_offset, anchor.horizontal_relative_to.as_str()), (457_200, "page"));
+        assert_eq!((anchor.wrap.as_str(), anchor.z_order), ("tight", 251_659_264));
+        assert!(xml.contains(r#"layoutInCell="1" allowOverlap="1">"#));
+        assert!(anchor.allow_overlap);
+        assert_eq!(parsed.paragraphs[0].text, "Our logo");
+    }
+
+    #[test]
+    fn test_incremental_save_copies_unchanged_entries() {
+        let mut cache = ImageCache::new();
+        let mut png = vec![
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R',
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01,
+        ];
+        png.extend((0..4096u32).map(|i| (i * 7 % 251) as u8));
+        cache.load("media/photo.png".to_string(), png.clone()).unwrap();
+        let document = WordDocument {
+            text: "Photo".to_string(),
+            paragraphs: vec![Paragraph { text: "Photo".to_string(), runs: vec![Run { text: "Photo".to_string(), ..Default::default() }], ..Default::default() }],
+            images: vec![DocumentImage { path: "media/photo.png".to_string(), ..Default::default() }],
+            ..Default::default()
+        };
+        let saved = DocxSerializer::new(OpcPackage::default(), document).with_image_cache(&cache).export_docx(None).unwrap();
+
+        // Store the entries uncompressed, which a rewrite would deflate
+        let mut source = Cursor::new(Vec::new());
+        {
+            let mut archive = zip::ZipArchive::new(Cursor::new(&saved)).unwrap();
+            let mut zip = ZipWriter::new(&mut source);
+            for i in 0..archive.len() {
+                let mut file = archive.by_index(i).unwrap();
+                let mut data = Vec::new();
+                std::io::Read::read_to_end(&mut file, &mut data).unwrap();
+                zip.start_file(file.name(), FileOptions::default().compression_method(zip::CompressionMethod::Stored)).unwrap();
+                zip.write_all(&data).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        let source: Arc<[u8]> = source.into_inner().into();
+
+        let methods = |data: &[u8]| -> HashMap<String, zip::CompressionMethod> {
+            let mut archive = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+            (0..archive.len())
+                .map(|i| {
+                    let file = archive.by_index(i).unwrap();
+                    (file.name().to_string(), file.compression())
+                })
+                .collect()
+        };
+        let save = |package: OpcPackage| {
+            let document = crate::ooxml::document::WordDocument::parse(&package).unwrap();
+            let document = WordDocument {
+                text: document.text,
+                paragraphs: document.paragraphs,
+                images: document.images,
+                ..Default::default()
+            };
+            let (data, _) = DocxSerializer::new(package, document)
+                .export_docx_incremental(None, &ExportControl::new())
+                .unwrap();
+            data
+        };
+
+        let data = save(OpcPackage::open(source.clone()).unwrap());
+        let written = methods(&data);
+        assert_eq!(written["word/media/photo.png"], zip::CompressionMethod::Stored);
+        assert_eq!(written["[Content_Types].xml"], zip::CompressionMethod::Stored);
+        assert_eq!(read_zip_entry(&data, "word/media/photo.png").unwrap(), png);
+        let parsed = crate::ooxml::document::WordDocument::parse(&OpcPackage::new(&data).unwrap()).unwrap();
+        assert_eq!((parsed.text.as_str(), parsed.images.len()), ("Photo", 1));
+
+        // A dirty part, or a package without its archive, is compressed anew
+        let mut package = OpcPackage::open(source.clone()).unwrap();
+        package.mark_dirty("/word/document.xml");
+        assert_eq!(methods(&save(package))["word/document.xml"], zip::CompressionMethod::Deflated);
+        assert_eq!(methods(&save(OpcPackage::new(&source).unwrap()))["word/document.xml"], zip::CompressionMethod::Deflated);
+    }
+
+    #[test]
+    fn test_compression_by_part_kind() {
+        let mut cache = ImageCache::new();
+        let mut png = vec![
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, b'I', b'H', b'D', b'R',
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01,
+        ];
+        png.extend((0..4096u32).map(|i| (i * 7 % 251) as u8));
+        cache.load("media/photo.png".to_string(), png.clone()).unwrap();
+        let document = WordDocument {
+            text: "Photo".to_string(),
+            paragraphs: vec![Paragraph { text: "Photo".to_string(), runs: vec![Run { text: "Photo".to_string(), ..Default::default() }], ..Default::default() }],
+            images: vec![DocumentImage { path: "media/photo.png".to_string(), ..Default::default() }],
+            ..Default::default()
+        };
+        let serializer = DocxSerializer::new(OpcPackage::default(), document).with_image_cache(&cache);
+        let methods = |compression: PackageCompression| {
+            let data = serializer.export_docx(Some(ExportOptions { compression, ..Default::default() })).unwrap();
+            let mut archive = zip::ZipArchive::new(Cursor::new(&data)).unwrap();
+            let mut method = |name: &str| archive.by_name(name).unwrap().compression();
+            let methods = (method("word/document.xml"), method("word/media/photo.png"));
+            (data, methods)
+        };
+
+        let (_, default) = methods(PackageCompression::default());
+        assert_eq!(default, (zip::CompressionMethod::Deflated, zip::CompressionMethod::Stored));
+        let (_, deflated) = methods(PackageCompression::deflate_all(1));
+        assert_eq!(deflated, (zip::CompressionMethod::Deflated, zip::CompressionMethod::Deflated));
+
+        // An autosave sidecar reads back like any package
+        let (sidecar, autosave) = methods(PackageCompression::autosave());
+        assert_eq!(autosave, (zip::CompressionMethod::Zstd, zip::CompressionMethod::Stored));
+        let package = OpcPackage::new(&sidecar).unwrap();
+        let parsed = crate::ooxml::document::WordDocument::parse(&package).unwrap();
+        assert_eq!((parsed.text.as_str(), parsed.images.len()), ("Photo", 1));
+        assert_eq!(read_zip_entry(&sidecar, "word/media/photo.png").unwrap(), png);
+    }
 }
@@ -0,0 +1,563 @@
+//! Streaming import for very large documents
+//!
+//! [`parse_ooxml`](super::parse_ooxml) extracts every part of the package and
+//! holds document.xml as one string while parsing it. [`StreamingDocument`]
+//! instead reads the package from any `Read + Seek` source: only the small XML
+//! parts (styles, numbering, headers, notes...) are extracted, document.xml is
+//! decompressed and tokenized by a pull parser one buffer at a time, and each
+//! body paragraph is parsed and appended to the piece tree under construction
+//! as soon as its end tag is read, then dropped unless something is anchored
+//! in it (see [`StreamingDocument::anchored_paragraphs`]). Media parts stay
+//! compressed in the archive until [`StreamingDocument::media`] reads one.
+//!
+//! Fields whose result spans several paragraphs are not tracked across them;
+//! fields inside one paragraph are parsed as usual.
+
+use std::io::{BufRead, BufReader, Read, Seek};
+
+use zip::ZipArchive;
+
+use super::converter::convert_run_properties;
+use super::document::WordDocument;
+use super::error::OoxmlError;
+use super::opc::OpcPackage;
+use super::types::{DocumentImage, FieldKind, Paragraph, Relationship, RunProperties, Section, Table};
+use crate::piece_tree::{BufferId, Piece, PieceTree};
+
+const MAIN_PART: &str = "/word/document.xml";
+
+/// A token of the XML stream; its raw text is [`PullParser::raw`]
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Event {
+    Start { name: String, empty: bool },
+    End { name: String },
+    /// Character data, comments, CDATA, processing instructions and declarations
+    Other,
+}
+
+/// A minimal pull parser over buffered XML that never holds more than one token
+pub(super) struct PullParser<R> {
+    reader: R,
+    raw: Vec<u8>,
+}
+
+impl<R: BufRead> PullParser<R> {
+    pub(super) fn new(reader: R) -> Self {
+        PullParser { reader, raw: Vec::new() }
+    }
+
+    /// Raw bytes of the last token
+    pub(super) fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Read the next token, or None at the end of the input
+    pub(super) fn next_event(&mut self) -> Result<Option<Event>, OoxmlError> {
+        self.raw.clear();
+        let buffer = self.reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+        if buffer[0] != b'<' {
+            self.read_text()?;
+            return Ok(Some(Event::Other));
+        }
+
+        self.read_markup()?;
+        let raw = &self.raw;
+        if raw.starts_with(b"<!") || raw.starts_with(b"<?") {
+            return Ok(Some(Event::Other));
+        }
+        let closing = raw.starts_with(b"</");
+        let name_start = if closing { 2 } else { 1 };
+        let name_end = raw[name_start..]
+            .iter()
+            .position(|&b| b.is_ascii_whitespace() || b == b'/' || b == b'>')
+            .map_or(raw.len(), |i| name_start + i);
+        let name = String::from_utf8_lossy(&raw[name_start..name_end]).into_owned();
+        Ok(Some(if closing {
+            Event::End { name }
+        } else {
+            Event::Start { name, empty: raw.ends_with(b"/>") }
+        }))
+    }
+
+    /// Read character data up to the next '<'
+    fn read_text(&mut self) -> Result<(), OoxmlError> {
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(());
+            }
+            match buffer.iter().position(|&b| b == b'<') {
+                Some(end) => {
+                    self.raw.extend_from_slice(&buffer[..end]);
+                    self.reader.consume(end);
+                    return Ok(());
+                }
+                None => {
+                    let length = buffer.len();
+                    self.raw.extend_from_slice(buffer);
+                    self.reader.consume(length);
+                }
+            }
+        }
+    }
+
+    /// Read a tag, comment, CDATA section or processing instruction
+    ///
+    /// A '>' only ends a tag outside quoted attribute values.
+    fn read_markup(&mut self) -> Result<(), OoxmlError> {
+        loop {
+            if self.reader.read_until(b'>', &mut self.raw)? == 0 {
+                return Err(OoxmlError::ParseError("unterminated markup in document.xml".to_string()));
+            }
+            let raw = &self.raw;
+            let complete = if raw.starts_with(b"<!--") {
+                raw.ends_with(b"-->") && raw.len() >= 7
+            } else if raw.starts_with(b"<![CDATA[") {
+                raw.ends_with(b"]]>")
+            } else if raw.starts_with(b"<?") {
+                raw.ends_with(b"?>")
+            } else {
+                Self::outside_quotes(raw)
+            };
+            if complete {
+                return Ok(());
+            }
+        }
+    }
+
+    fn outside_quotes(raw: &[u8]) -> bool {
+        let mut quote = None;
+        for &byte in raw {
+            match quote {
+                Some(q) if byte == q => quote = None,
+                None if byte == b'"' || byte == b'\'' => quote = Some(byte),
+                _ => {}
+            }
+        }
+        quote.is_none()
+    }
+}
+
+/// A body element whose XML is being collected until its end tag
+struct Capture {
+    name: String,
+    depth: usize,
+    xml: Vec<u8>,
+}
+
+/// Builds the piece tree and body structures one element at a time
+struct BodyBuilder<'a> {
+    relationships: &'a [Relationship],
+    text: String,
+    pieces: Vec<Piece>,
+    paragraph_count: usize,
+    tables: Vec<Table>,
+    images: Vec<DocumentImage>,
+    sections: Vec<Section>,
+    /// Chars of text so far, paragraph separators included
+    char_count: usize,
+    anchored: Vec<(usize, Paragraph)>,
+    /// The last paragraph, if not kept in `anchored`, for a caption after it
+    previous: Option<(usize, Paragraph)>,
+    /// Whether the last paragraph was a caption, whose object may come next
+    after_caption: bool,
+    final_mark: Option<RunProperties>,
+    on_paragraph: &'a mut dyn FnMut(usize, &Paragraph),
+}
+
+impl BodyBuilder<'_> {
+    fn finish_element(&mut self, name: &str, xml: &str) {
+        match name {
+            "w:p" => self.push_paragraph(xml),
+            "w:tbl" => {
+                if let Some(mut table) = WordDocument::parse_table(xml) {
+                    table.paragraph_index = self.paragraph_count;
+                    self.tables.push(table);
+                }
+            }
+            _ => {
+                // The body-level sectPr covers the paragraphs after the last section break
+                let mut section = WordDocument::parse_section_properties(xml);
+                self.end_section(&mut section);
+                if section.paragraph_count > 0 || self.sections.is_empty() {
+                    self.sections.push(section);
+                }
+            }
+        }
+    }
+
+    fn push_paragraph(&mut self, xml: &str) {
+        let inner = match (xml.find('>'), xml.rfind("</w:p>")) {
+            (Some(open), Some(close)) if open < close => &xml[open + 1..close],
+            _ => "",
+        };
+        let (paragraph, spanning) = WordDocument::parse_paragraph_spanning(inner, 0);
+        // Empty paragraphs are lines of the piece tree like any other
+        let mut paragraph = paragraph.unwrap_or_default();
+        let index = self.paragraph_count;
+
+        if index > 0 {
+            self.pieces.push(Piece::new(self.text.len(), 1, BufferId::ORIGINAL, 1));
+            self.text.push('\n');
+            self.char_count += 1;
+        }
+        let start = self.char_count;
+        for run in paragraph.runs.iter().filter(|run| !run.text.is_empty()) {
+            let attributes = convert_run_properties(&run.properties);
+            let char_length = run.text.chars().count();
+            self.pieces.push(Piece::new_with_attrs(
+                self.text.len(),
+                run.text.len(),
+                BufferId::ORIGINAL,
+                char_length,
+                Some(attributes),
+            ));
+            self.text.push_str(&run.text);
+            self.char_count += char_length;
+        }
+
+        for (position, mut image) in spanning.drawings {
+            let Some(rel) = self.relationships.iter().find(|rel| rel.id == image.id) else {
+                continue;
+            };
+            image.path = rel.target.clone();
+            image.is_linked = rel.target_mode.as_deref() == Some("External");
+            image.paragraph_index = index;
+            image.position = position;
+            self.images.push(image);
+        }
+        for hyperlink in &mut paragraph.hyperlinks {
+            if let Some(id) = hyperlink.relationship_id.take() {
+                hyperlink.url = self.relationships.iter().find(|rel| rel.id == id).map(|rel| rel.target.clone());
+            }
+        }
+
+        self.paragraph_count += 1;
+        (self.on_paragraph)(index, &paragraph);
+
+        // A sectPr inside the paragraph ends a section with it
+        if let Some(start) = inner.find("<w:sectPr") {
+            let mut section = WordDocument::parse_section_properties(&inner[start..]);
+            self.end_section(&mut section);
+            self.sections.push(section);
+        }
+
+        self.keep_anchored(start, paragraph);
+    }
+
+    /// Keep `paragraph`, starting at char `start`, if something is anchored
+    /// in it or it is next to a caption; its runs are in the piece tree already
+    fn keep_anchored(&mut self, start: usize, mut paragraph: Paragraph) {
+        let caption = paragraph.fields.iter().any(|field| field.kind == FieldKind::Seq);
+        let anchored = !paragraph.fields.is_empty()
+            || !paragraph.revisions.is_empty()
+            || !paragraph.comment_marks.is_empty()
+            || !paragraph.bookmark_marks.is_empty()
+            || !paragraph.hyperlinks.is_empty()
+            || !paragraph.math_zones.is_empty()
+            || !paragraph.form_fields.is_empty();
+        self.final_mark = paragraph.mark_properties.take();
+        paragraph.runs = Vec::new();
+
+        let previous = self.previous.take();
+        if caption {
+            self.anchored.extend(previous);
+        }
+        if anchored || self.after_caption {
+            self.anchored.push((start, paragraph));
+        } else {
+            self.previous = Some((start, paragraph));
+        }
+        self.after_caption = caption;
+    }
+
+    /// Make `section` cover the paragraphs since the previous one ended
+    fn end_section(&self, section: &mut Section) {
+        let first_paragraph = self.sections.last().map_or(0, |s| s.first_paragraph + s.paragraph_count);
+        section.first_paragraph = first_paragraph;
+        section.paragraph_count = self.paragraph_count - first_paragraph;
+    }
+}
+
+/// A .docx read without materializing its body XML or media
+///
+/// Everything but the body comes from the supporting parts as usual, in
+/// [`StreamingDocument::document`], whose `paragraphs` and `text` stay empty:
+/// the body is in [`StreamingDocument::piece_tree`].
+pub struct StreamingDocument<R> {
+    archive: ZipArchive<R>,
+    document: WordDocument,
+    tree: PieceTree,
+    paragraph_count: usize,
+    anchored: Vec<(usize, Paragraph)>,
+    final_mark: Option<RunProperties>,
+}
+
+impl<R: Read + Seek> StreamingDocument<R> {
+    /// Read a .docx package from `reader`
+    pub fn open(reader: R) -> Result<Self, OoxmlError> {
+        Self::open_with(reader, |_, _| {})
+    }
+
+    /// Read a .docx package from `reader`, passing each body paragraph and its
+    /// index to `on_paragraph` as soon as it is parsed, e.g. to report progress
+    pub fn open_with(reader: R, mut on_paragraph: impl FnMut(usize, &Paragraph)) -> Result<Self, OoxmlError> {
+        let mut archive = ZipArchive::new(reader)?;
+        let package = OpcPackage::read_parts(&mut archive, |name| name.ends_with(".xml") && name != MAIN_PART)?;
+        let mut document = WordDocument::empty();
+        document.parse_supporting_parts(&package)?;
+
+        let relationships = package.get_relationships(MAIN_PART).cloned().unwrap_or_default();
+        let mut builder = BodyBuilder {
+            relationships: &relationships,
+            text: String::new(),
+            pieces: Vec::new(),
+            paragraph_count: 0,
+            tables: Vec::new(),
+            images: Vec::new(),
+            sections: Vec::new(),
+            char_count: 0,
+            anchored: Vec::new(),
+            previous: None,
+            after_caption: false,
+            final_mark: None,
+            on_paragraph: &mut on_paragraph,
+        };
+
+        {
+            let entry = match archive.by_name(MAIN_PART.trim_start_matches('/')) {
+                Ok(entry) => entry,
+                Err(zip::result::ZipError::FileNotFound) => {
+                    return Err(OoxmlError::PartNotFound(MAIN_PART.to_string()));
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let mut parser = PullParser::new(BufReader::new(entry));
+            let mut depth = 0usize;
+            let mut capture: Option<Capture> = None;
+
+            while let Some(event) = parser.next_event()? {
+                match event {
+                    Event::Start { name, empty } => {
+                        if capture.is_none() && matches!(name.as_str(), "w:p" | "w:tbl" | "w:sectPr") {
+                            capture = Some(Capture { name, depth, xml: Vec::new() });
+                        }
+                        if let Some(capture) = &mut capture {
+                            capture.xml.extend_from_slice(parser.raw());
+                        }
+                        if !empty {
+                            depth += 1;
+                        }
+                    }
+                    Event::End { .. } => {
+                        depth = depth.saturating_sub(1);
+                        if let Some(capture) = &mut capture {
+                            capture.xml.extend_from_slice(parser.raw());
+                        }
+                    }
+                    Event::Other => {
+                        if let Some(capture) = &mut capture {
+                            capture.xml.extend_from_slice(parser.raw());
+                        }
+                    }
+                }
+
+                if capture.as_ref().is_some_and(|c| c.depth == depth) {
+                    let Capture { name, xml, .. } = capture.take().unwrap();
+                    builder.finish_element(&name, &String::from_utf8_lossy(&xml));
+                }
+            }
+        }
+
+        if builder.sections.is_empty()
+            || builder.sections.last().is_some_and(|s| s.first_paragraph + s.paragraph_count < builder.paragraph_count)
+        {
+            let mut section = Section::default();
+            builder.end_section(&mut section);
+            builder.sections.push(section);
+        }
+
+        let BodyBuilder { text, pieces, paragraph_count, tables, images, sections, anchored, final_mark, .. } = builder;
+        document.tables = tables;
+        document.images = images;
+        document.sections = sections;
+
+        Ok(StreamingDocument {
+            archive,
+            document,
+            tree: PieceTree::from_loaded_data(pieces, vec![text]),
+            paragraph_count,
+            anchored,
+            final_mark,
+        })
+    }
+
+    /// Styles, numbering, headers and footers, notes and comments, plus the
+    /// body's tables, images and sections
+    pub fn document(&self) -> &WordDocument {
+        &self.document
+    }
+
+    /// The body text with run formatting, one line per paragraph
+    pub fn piece_tree(&self) -> &PieceTree {
+        &self.tree
+    }
+
+    /// Take the piece tree, leaving an empty one
+    pub fn take_piece_tree(&mut self) -> PieceTree {
+        std::mem::replace(&mut self.tree, PieceTree::new(String::new()))
+    }
+
+    /// Number of body paragraphs, empty ones included
+    pub fn paragraph_count(&self) -> usize {
+        self.paragraph_count
+    }
+
+    /// The body paragraphs that fields, revisions, comments, bookmarks,
+    /// hyperlinks or math are anchored in, and those next to a caption, each
+    /// with the char offset it starts at; their runs are left out
+    pub fn anchored_paragraphs(&self) -> &[(usize, Paragraph)] {
+        &self.anchored
+    }
+
+    /// Run properties of the last paragraph's mark
+    pub fn final_mark(&self) -> Option<&RunProperties> {
+        self.final_mark.as_ref()
+    }
+
+    /// Read a media part from the archive, e.g. an image's `path` relative to word/
+    /// ("media/image1.png") or an absolute part name ("/word/media/image1.png")
+    ///
+    /// Nothing is cached; every call decompresses the entry again.
+    pub fn media(&mut self, path: &str) -> Result<Vec<u8>, OoxmlError> {
+        let name = match path.strip_prefix('/') {
+            Some(absolute) => absolute.to_string(),
+            None => format!("word/{}", path),
+        };
+        let mut entry = match self.archive.by_name(&name) {
+            Ok(entry) => entry,
+            Err(zip::result::ZipError::FileNotFound) => return Err(OoxmlError::PartNotFound(format!("/{}", name))),
+            Err(e) => return Err(e.into()),
+        };
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::minimal_docx;
+    use std::io::Cursor;
+    use zip::CompressionMethod;
+
+    const DOCUMENT_RELS: &str = r#"<Relationships><Relationship Id="rIdImg" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/image1.png"/></Relationships>"#;
+
+    fn build_docx(body: &str) -> Vec<u8> {
+        let extra_parts = [("word/_rels/document.xml.rels", DOCUMENT_RELS), ("word/media/image1.png", "PNGDATA")];
+        minimal_docx(body, &extra_parts, CompressionMethod::Deflated)
+    }
+
+    #[test]
+    fn test_pull_parser_splits_tokens_across_buffers() {
+        let xml = r#"<?xml version="1.0"?><!-- a > b --><w:p w:x="1 > 0"><w:t>A &amp; B</w:t><w:br/></w:p>"#;
+        // A tiny buffer makes every token straddle refills
+        let mut parser = PullParser::new(BufReader::with_capacity(3, xml.as_bytes()));
+        let mut tokens = Vec::new();
+        while let Some(event) = parser.next_event().unwrap() {
+            tokens.push((event, String::from_utf8(parser.raw().to_vec()).unwrap()));
+        }
+
+        let start = |name: &str, empty| Event::Start { name: name.to_string(), empty };
+        let end = |name: &str| Event::End { name: name.to_string() };
+        assert_eq!(
+            tokens,
+            vec![
+                (Event::Other, r#"<?xml version="1.0"?>"#.to_string()),
+                (Event::Other, "<!-- a > b -->".to_string()),
+                (start("w:p", false), r#"<w:p w:x="1 > 0">"#.to_string()),
+                (start("w:t", false), "<w:t>".to_string()),
+                (Event::Other, "A &amp; B".to_string()),
+                (end("w:t"), "</w:t>".to_string()),
+                (start("w:br", true), "<w:br/>".to_string()),
+                (end("w:p"), "</w:p>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_streaming_open_builds_body_and_loads_media_lazily() {
+        let drawing = r#"<w:r><w:drawing><wp:inline><wp:extent cx="100" cy="200"/><wp:docPr id="1" descr="Logo"/><a:blip r:embed="rIdImg"/></wp:inline></w:drawing></w:r>"#;
+        let body = format!(
+            concat!(
+                r#"<w:p><w:r><w:t>Plain </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>bold</w:t></w:r></w:p>"#,
+                r#"<w:p/>"#,
+                r#"<w:tbl><w:tr><w:tc><w:p><w:r><w:t>Cell</w:t></w:r></w:p></w:tc></w:tr></w:tbl>"#,
+                r#"<w:p><w:pPr><w:sectPr><w:pgSz w:w="12240" w:h="15840"/></w:sectPr></w:pPr>{}</w:p>"#,
+                r#"<w:p><w:r><w:t>Last</w:t></w:r></w:p>"#,
+                r#"<w:sectPr><w:pgSz w:w="15840" w:h="12240" w:orient="landscape"/></w:sectPr>"#,
+            ),
+            drawing
+        );
+        let file = build_docx(&body);
+
+        let mut seen = Vec::new();
+        let mut streamed = StreamingDocument::open_with(Cursor::new(file), |index, paragraph: &Paragraph| {
+            seen.push((index, paragraph.text.clone()));
+        })
+        .unwrap();
+
+        assert_eq!(streamed.paragraph_count(), 4);
+        assert_eq!(seen.len(), 4);
+        assert_eq!(seen[0], (0, "Plain bold".to_string()));
+        assert_eq!(streamed.piece_tree().get_text(), "Plain bold\n\n\nLast");
+        assert!(streamed.piece_tree().get_attributes_at(7).is_some_and(|a| a.bold == Some(true)));
+
+        let document = streamed.document();
+        assert!(document.paragraphs.is_empty());
+        assert_eq!(document.tables.len(), 1);
+        assert_eq!(document.tables[0].paragraph_index, 2);
+        let sections: Vec<(usize, usize, bool)> = document
+            .sections
+            .iter()
+            .map(|s| (s.first_paragraph, s.paragraph_count, s.landscape))
+            .collect();
+        assert_eq!(sections, vec![(0, 3, false), (3, 1, true)]);
+
+        assert_eq!(document.images.len(), 1);
+        let image = document.images[0].clone();
+        assert_eq!((image.path.as_str(), image.paragraph_index), ("media/image1.png", 2));
+        assert_eq!(image.alt_description.as_deref(), Some("Logo"));
+
+        assert_eq!(streamed.media(&image.path).unwrap(), b"PNGDATA");
+        assert_eq!(streamed.media("/word/media/image1.png").unwrap(), b"PNGDATA");
+        assert!(matches!(streamed.media("media/missing.png"), Err(OoxmlError::PartNotFound(_))));
+    }
+
+    #[test]
+    fn test_streaming_open_keeps_anchored_paragraphs_and_caption_neighbours() {
+        let body = concat!(
+            r#"<w:p><w:r><w:t>Intro</w:t></w:r></w:p>"#,
+            r#"<w:p><w:r><w:t>Picture</w:t></w:r></w:p>"#,
+            r#"<w:p><w:r><w:t xml:space="preserve">Figure </w:t></w:r><w:fldSimple w:instr=" SEQ Figure \* ARABIC "><w:r><w:t>1</w:t></w:r></w:fldSimple></w:p>"#,
+            r#"<w:p><w:r><w:t>After</w:t></w:r></w:p>"#,
+            r#"<w:p><w:r><w:t>Plain</w:t></w:r></w:p>"#,
+            r#"<w:p><w:bookmarkStart w:id="0" w:name="Here"/><w:r><w:t>Marked</w:t></w:r><w:bookmarkEnd w:id="0"/></w:p>"#,
+            r#"<w:p><w:pPr><w:rPr><w:b/></w:rPr></w:pPr><w:r><w:t>End</w:t></w:r></w:p>"#,
+        );
+        let streamed = StreamingDocument::open(Cursor::new(build_docx(body))).unwrap();
+
+        let kept: Vec<(usize, &str)> = streamed
+            .anchored_paragraphs()
+            .iter()
+            .map(|(start, paragraph)| (*start, paragraph.text.as_str()))
+            .collect();
+        assert_eq!(kept, vec![(6, "Picture"), (14, "Figure 1"), (23, "After"), (35, "Marked")]);
+        assert!(streamed.anchored_paragraphs().iter().all(|(_, paragraph)| paragraph.runs.is_empty()));
+        assert_eq!(streamed.final_mark().and_then(|mark| mark.bold), Some(true));
+    }
+}
@@ -0,0 +1,491 @@
+//! Plain text export
+//!
+//! [`DocxSerializer::export_text`](super::DocxSerializer::export_text) writes
+//! a document as plain text, one line per paragraph or one per line of its
+//! layout at a given width. List items keep their numbers and bullets, tables
+//! become aligned columns or tab-separated cells, and footnotes and endnotes
+//! are written where they are referenced or numbered and listed at the end,
+//! as [`PlainTextOptions`] say. Text deleted in tracked changes is left out;
+//! images, comments, headers and footers are not written.
+
+use serde::{Deserialize, Serialize};
+
+use super::error::OoxmlError;
+use super::export::ExportControl;
+use super::html::{grid_columns, is_continuation, span};
+use super::serializer::{WordDocument, PROGRESS_INTERVAL};
+use super::types::{NoteKind, Paragraph, RevisionKind, Table};
+use crate::line_layout::LineLayout;
+use crate::numbering::{ListNumbering, NumberingEngine};
+use crate::piece_tree::ParagraphAttributes;
+
+/// Line break within a paragraph
+const LINE_BREAK: char = '\u{2028}';
+
+/// Spaces between the columns of a table
+const COLUMN_GAP: usize = 2;
+
+/// What ends each line of a plain text export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+/// How a plain text export writes tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TableText {
+    /// One line per row, cells padded with spaces so the columns line up
+    #[default]
+    Columns,
+    /// One line per row, cells separated by tabs
+    Tabs,
+}
+
+/// Where a plain text export writes footnotes and endnotes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteText {
+    /// Numbered "[1]" where referenced and listed after the body
+    #[default]
+    Appended,
+    /// In brackets where referenced
+    Inline,
+}
+
+/// Options of [`ExportFormat::PlainText`](super::ExportFormat::PlainText) exports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlainTextOptions {
+    pub line_ending: LineEnding,
+    /// Break paragraphs into lines where layout at this width in points
+    /// breaks them; None writes each paragraph on one line. Tables are not wrapped
+    pub wrap_width: Option<f32>,
+    pub tables: TableText,
+    pub notes: NoteText,
+    /// Start list items with their number or bullet
+    pub list_markers: bool,
+}
+
+impl Default for PlainTextOptions {
+    fn default() -> Self {
+        PlainTextOptions {
+            line_ending: LineEnding::Lf,
+            wrap_width: None,
+            tables: TableText::Columns,
+            notes: NoteText::Appended,
+            list_markers: true,
+        }
+    }
+}
+
+/// Write `document` as plain text
+pub(super) fn document_text(
+    document: &WordDocument,
+    options: &PlainTextOptions,
+    control: &ExportControl,
+) -> Result<String, OoxmlError> {
+    let numbering = ListNumbering::from_ooxml(&document.numbering);
+    let mut writer = TextWriter {
+        document,
+        options,
+        numbering: NumberingEngine::new(&numbering),
+        layout: LineLayout::new(),
+        notes: Vec::new(),
+        lines: Vec::new(),
+    };
+
+    // Tables go before the paragraph they precede, or after the last
+    let mut tables: Vec<&Table> = document.tables.iter().collect();
+    tables.sort_by_key(|table| table.paragraph_index);
+    let mut tables = tables.into_iter().peekable();
+
+    let total = document.paragraphs.len().max(1) as f32;
+    for (i, para) in document.paragraphs.iter().enumerate() {
+        if i % PROGRESS_INTERVAL == 0 {
+            control.step(0.5 + 0.5 * i as f32 / total)?;
+        }
+        while let Some(table) = tables.next_if(|table| table.paragraph_index <= i) {
+            writer.table(table);
+        }
+        let text = writer.paragraph_text(para, false);
+        writer.push_wrapped(&text);
+    }
+    for table in tables {
+        writer.table(table);
+    }
+    writer.notes_list();
+    control.step(1.0)?;
+
+    let ending = options.line_ending.as_str();
+    let mut text = writer.lines.join(ending);
+    text.push_str(ending);
+    Ok(text)
+}
+
+struct TextWriter<'a> {
+    document: &'a WordDocument,
+    options: &'a PlainTextOptions,
+    numbering: NumberingEngine<'a>,
+    layout: LineLayout,
+    /// Notes referenced so far, numbered by their place here
+    notes: Vec<(NoteKind, String)>,
+    lines: Vec<String>,
+}
+
+impl<'a> TextWriter<'a> {
+    /// The text of a paragraph with its list label and note references,
+    /// leaving out deleted text. Notes written inline have no references of
+    /// their own
+    fn paragraph_text(&mut self, para: &Paragraph, in_note: bool) -> String {
+        let mut text = String::new();
+        if !in_note {
+            let attributes = ParagraphAttributes {
+                num_id: para.properties.num_id.clone(),
+                list_level: para.properties.list_level,
+                ..Default::default()
+            };
+            let label = self.numbering.next(&attributes);
+            if let Some(label) = label.filter(|_| self.options.list_markers) {
+                text.push_str(&label.prefix());
+            }
+        }
+
+        let mut marks: Vec<(usize, String)> = Vec::new();
+        if !(in_note && self.options.notes == NoteText::Inline) {
+            for reference in &para.note_references {
+                if let Some(mark) = self.note_reference(reference.kind, &reference.id) {
+                    marks.push((reference.position, mark));
+                }
+            }
+        }
+        marks.sort_by_key(|&(position, _)| position);
+        let mut marks = marks.into_iter().peekable();
+
+        let deleted: Vec<_> = para
+            .revisions
+            .iter()
+            .filter(|revision| revision.kind == RevisionKind::Deletion && revision.length > 0)
+            .map(|revision| revision.start..revision.start + revision.length)
+            .collect();
+        for (offset, c) in para.text.chars().enumerate() {
+            while let Some((_, mark)) = marks.next_if(|&(position, _)| position <= offset) {
+                text.push_str(&mark);
+            }
+            if !deleted.iter().any(|range| range.contains(&offset)) {
+                text.push(c);
+            }
+        }
+        for (_, mark) in marks {
+            text.push_str(&mark);
+        }
+        text
+    }
+
+    /// The note's text in brackets, or its number in brackets to list it at the end
+    fn note_reference(&mut self, kind: NoteKind, id: &str) -> Option<String> {
+        let paragraphs = self.note_paragraphs(kind, id)?;
+        if self.options.notes == NoteText::Inline {
+            let text: Vec<String> = paragraphs.iter().map(|para| self.paragraph_text(para, true)).collect();
+            return Some(format!("[{}]", text.join(" ")));
+        }
+        let number = match self.notes.iter().position(|(k, i)| *k == kind && i == id) {
+            Some(index) => index + 1,
+            None => {
+                self.notes.push((kind, id.to_string()));
+                self.notes.len()
+            }
+        };
+        Some(format!("[{}]", number))
+    }
+
+    /// Paragraphs of a footnote or endnote, unless it is a separator
+    fn note_paragraphs(&self, kind: NoteKind, id: &str) -> Option<&'a [Paragraph]> {
+        let document = self.document;
+        let (paragraphs, note_type) = match kind {
+            NoteKind::Footnote => document
+                .footnotes
+                .iter()
+                .find(|note| note.id == id)
+                .map(|note| (&note.paragraphs, &note.footnote_type))?,
+            NoteKind::Endnote => document
+                .endnotes
+                .iter()
+                .find(|note| note.id == id)
+                .map(|note| (&note.paragraphs, &note.endnote_type))?,
+        };
+        match note_type.as_deref() {
+            None | Some("normal") => Some(paragraphs),
+            Some(_) => None,
+        }
+    }
+
+    /// The notes referenced, in order, after a blank line
+    fn notes_list(&mut self) {
+        if self.notes.is_empty() {
+            return;
+        }
+        self.lines.push(String::new());
+        // Notes may reference further notes, which join the end of the list
+        let mut index = 0;
+        while let Some((kind, id)) = self.notes.get(index).cloned() {
+            index += 1;
+            let paragraphs = self.note_paragraphs(kind, &id).unwrap_or_default();
+            for (n, para) in paragraphs.iter().enumerate() {
+                let mut text = self.paragraph_text(para, true);
+                if n == 0 {
+                    text.insert_str(0, &format!("[{}] ", index));
+                }
+                self.push_wrapped(&text);
+            }
+        }
+    }
+
+    /// Add the lines of a paragraph's text: one per line break in it, each
+    /// broken where the layout breaks it if wrapping
+    fn push_wrapped(&mut self, text: &str) {
+        for segment in text.split(LINE_BREAK) {
+            let Some(width) = self.options.wrap_width.filter(|_| !segment.trim().is_empty()) else {
+                self.lines.push(segment.to_string());
+                continue;
+            };
+            let layout = self.layout.layout_paragraph(segment, width);
+            if layout.lines.is_empty() {
+                self.lines.push(segment.to_string());
+            }
+            for line in &layout.lines {
+                let line_text = segment.get(line.start..line.end).unwrap_or_default();
+                self.lines.push(line_text.trim_end().to_string());
+            }
+        }
+    }
+
+    /// Add a line for each row of the table
+    fn table(&mut self, table: &Table) {
+        // Cells by the grid column they start at, the columns they span and their text
+        let mut rows: Vec<Vec<(usize, usize, String)>> = Vec::new();
+        for row in &table.rows {
+            let mut cells: Vec<(usize, usize, String)> = Vec::new();
+            for (cell, column) in row.cells.iter().zip(grid_columns(row)) {
+                let columns = span(cell) as usize;
+                if is_continuation(cell.horizontal_merge) {
+                    if let Some(last) = cells.last_mut() {
+                        last.1 += columns;
+                        continue;
+                    }
+                }
+                let text = if is_continuation(cell.vertical_merge) {
+                    String::new()
+                } else {
+                    let texts: Vec<String> = cell.paragraphs.iter().map(|para| self.paragraph_text(para, false)).collect();
+                    texts.join(" ").replace(['\t', LINE_BREAK], " ")
+                };
+                cells.push((column, columns, text));
+            }
+            rows.push(cells);
+        }
+
+        match self.options.tables {
+            TableText::Tabs => {
+                for cells in rows {
+                    // A tab for each grid column from one cell to the next, so spanned columns stay empty
+                    let mut line = String::new();
+                    let mut start = 0;
+                    for (column, _, text) in cells {
+                        line.push_str(&"\t".repeat(column - start));
+                        line.push_str(&text);
+                        start = column;
+                    }
+                    self.lines.push(line);
+                }
+            }
+            TableText::Columns => {
+                let count = rows.iter().flatten().map(|&(column, columns, _)| column + columns).max().unwrap_or(0);
+                let mut widths = vec![0; count];
+                let mut spanning = Vec::new();
+                for &(column, columns, ref text) in rows.iter().flatten() {
+                    let width = text.chars().count();
+                    if columns == 1 {
+                        widths[column] = widths[column].max(width);
+                    } else {
+                        spanning.push((column, columns, width));
+                    }
+                }
+                // Cells spanning columns widen the last of them if they do not fit
+                for (column, columns, width) in spanning {
+                    let range = column..column + columns;
+                    let available = widths[range.clone()].iter().sum::<usize>() + COLUMN_GAP * (columns - 1);
+                    if width > available {
+                        widths[range.end - 1] += width - available;
+                    }
+                }
+
+                for cells in rows {
+                    let mut line = String::new();
+                    let mut next = 0;
+                    for (column, columns, text) in cells {
+                        let skipped = widths[next..column].iter().map(|width| width + COLUMN_GAP).sum::<usize>();
+                        let gap = if column > 0 { COLUMN_GAP } else { 0 };
+                        let width = widths[column..column + columns].iter().sum::<usize>() + COLUMN_GAP * (columns - 1);
+                        line.push_str(&" ".repeat(skipped + gap));
+                        line.push_str(&text);
+                        line.push_str(&" ".repeat(width - text.chars().count()));
+                        next = column + columns;
+                    }
+                    self.lines.push(line.trim_end().to_string());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::serializer::{DocxSerializer, ExportFormat, ExportOptions};
+    use super::super::types::{
+        AbstractNumDef, Footnote, ListLevel, NoteReference, NumInstance, Numbering, Revision, Run, TableCell,
+        TableCellProperties, TableRow,
+    };
+    use super::super::OpcPackage;
+    use super::*;
+
+    fn paragraph(text: &str) -> Paragraph {
+        Paragraph {
+            text: text.to_string(),
+            runs: vec![Run { text: text.to_string(), ..Default::default() }],
+            ..Default::default()
+        }
+    }
+
+    fn text(document: WordDocument, plain_text: PlainTextOptions) -> String {
+        let options = ExportOptions { plain_text, ..Default::default() };
+        DocxSerializer::new(OpcPackage::default(), document).export_text(Some(options)).unwrap()
+    }
+
+    fn cell(text: &str) -> TableCell {
+        TableCell { paragraphs: vec![paragraph(text)], ..Default::default() }
+    }
+
+    #[test]
+    fn test_lists_breaks_and_line_endings() {
+        let numbering = Numbering {
+            abstract_num_defs: vec![AbstractNumDef {
+                abstract_num_id: "1".to_string(),
+                levels: vec![ListLevel {
+                    level: 0,
+                    format: "decimal".to_string(),
+                    text: "%1.".to_string(),
+                    start_value: 1,
+                    ..Default::default()
+                }],
+            }],
+            num_instances: vec![NumInstance { num_id: "1".to_string(), abstract_num_id: "1".to_string(), ..Default::default() }],
+        };
+        let item = |text: &str| {
+            let mut para = paragraph(text);
+            para.properties.num_id = Some("1".to_string());
+            para
+        };
+        let mut edited = paragraph("Keep this gone");
+        edited.revisions.push(Revision {
+            id: "1".to_string(),
+            kind: RevisionKind::Deletion,
+            author: None,
+            date: None,
+            start: 4,
+            length: 5,
+            previous_run_properties: None,
+            previous_paragraph_properties: None,
+        });
+        let document = || WordDocument {
+            paragraphs: vec![paragraph("Title"), item("First"), item("Second"), paragraph("a\u{2028}b"), edited.clone()],
+            numbering: vec![numbering.clone()],
+            ..Default::default()
+        };
+
+        assert_eq!(text(document(), PlainTextOptions::default()), "Title\n1.\tFirst\n2.\tSecond\na\nb\nKeep gone\n");
+        let options = PlainTextOptions { line_ending: LineEnding::CrLf, list_markers: false, ..Default::default() };
+        assert_eq!(text(document(), options), "Title\r\nFirst\r\nSecond\r\na\r\nb\r\nKeep gone\r\n");
+
+        // The export format writes the same text
+        let options = ExportOptions { format: ExportFormat::PlainText, ..Default::default() };
+        let bytes = DocxSerializer::new(OpcPackage::default(), document()).export_docx(Some(options)).unwrap();
+        assert_eq!(bytes, b"Title\n1.\tFirst\n2.\tSecond\na\nb\nKeep gone\n");
+    }
+
+    #[test]
+    fn test_wrapping_at_layout_lines() {
+        let words = "alpha beta gamma delta epsilon zeta eta theta iota kappa";
+        let document = WordDocument { paragraphs: vec![paragraph(words), paragraph("")], ..Default::default() };
+        let wrapped = text(document, PlainTextOptions { wrap_width: Some(100.0), ..Default::default() });
+        let lines: Vec<&str> = wrapped.lines().collect();
+        assert!(lines.len() > 2, "{:?}", lines);
+        assert_eq!(lines.last(), Some(&""));
+        assert_eq!(lines[..lines.len() - 1].join(" "), words);
+        assert!(lines.iter().all(|line| !line.ends_with(' ')));
+    }
+
+    #[test]
+    fn test_tables_as_columns_or_tabs() {
+        let wide = TableCell {
+            properties: TableCellProperties { grid_span: Some(2), ..Default::default() },
+            ..cell("Spanning both")
+        };
+        let table = Table {
+            rows: vec![
+                TableRow { cells: vec![cell("Name"), cell("Qty"), cell("Note")], ..Default::default() },
+                TableRow { cells: vec![cell("Apples"), cell("3"), cell("")], ..Default::default() },
+                TableRow { cells: vec![wide, cell("x")], ..Default::default() },
+            ],
+            paragraph_index: 1,
+            ..Default::default()
+        };
+        let document = || WordDocument {
+            paragraphs: vec![paragraph("Before"), paragraph("After")],
+            tables: vec![table.clone()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            text(document(), PlainTextOptions::default()),
+            concat!("Before\n", "Name    Qty    Note\n", "Apples  3\n", "Spanning both  x\n", "After\n")
+        );
+        let options = PlainTextOptions { tables: TableText::Tabs, ..Default::default() };
+        assert_eq!(
+            text(document(), options),
+            "Before\nName\tQty\tNote\nApples\t3\t\nSpanning both\t\tx\nAfter\n"
+        );
+    }
+
+    #[test]
+    fn test_notes_appended_or_inline() {
+        let mut body = paragraph("See here.");
+        body.note_references.push(NoteReference { kind: NoteKind::Footnote, id: "2".to_string(), position: 8 });
+        let document = || WordDocument {
+            paragraphs: vec![body.clone()],
+            footnotes: vec![
+                Footnote { id: "0".to_string(), footnote_type: Some("separator".to_string()), paragraphs: Vec::new() },
+                Footnote { id: "2".to_string(), footnote_type: None, paragraphs: vec![paragraph("A note.")] },
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(text(document(), PlainTextOptions::default()), "See here[1].\n\n[1] A note.\n");
+        let options = PlainTextOptions { notes: NoteText::Inline, ..Default::default() };
+        assert_eq!(text(document(), options), "See here[A note.].\n");
+    }
+}
@@ -1,11 +1,19 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use super::forms::AnchoredFormField;
+
 /// Content types defined in [Content_Types].xml
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ContentType {
     /// Main document body (word/document.xml)
     MainDocument,
+    /// Macro-enabled main document body (.docm)
+    MacroEnabledDocument,
+    /// VBA project storage (word/vbaProject.bin)
+    VbaProject,
+    /// VBA project metadata (word/vbaData.xml)
+    VbaData,
     /// Document styles (word/styles.xml)
     Styles,
     /// Theme colors and fonts (word/theme/theme1.xml)
@@ -20,8 +28,18 @@ pub enum ContentType {
     WebSettings,
     /// Numbering definitions (word/numbering.xml)
     Numbering,
-    /// Custom XML properties
+    /// Comments (word/comments.xml)
+    Comments,
+    /// Comment threading and done state (word/commentsExtended.xml)
+    CommentsExtended,
+    /// Header (word/header1.xml, ...)
+    Header,
+    /// Footer (word/footer1.xml, ...)
+    Footer,
+    /// Custom XML data (customXml/item1.xml)
     CustomXml,
+    /// Custom XML data store properties (customXml/itemProps1.xml)
+    CustomXmlProperties,
     /// Thumbnail image
     Thumbnail,
     /// Relationships file
@@ -40,6 +58,10 @@ pub enum ContentType {
     ImageTiff,
     /// SVG image
     ImageSvg,
+    /// EMF vector picture
+    ImageEmf,
+    /// WMF vector picture
+    ImageWmf,
     /// Unknown content type
     Unknown(String),
 }
@@ -49,6 +71,9 @@ impl ContentType {
     pub fn from_string(s: &str) -> Self {
         match s {
             "application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml" => ContentType::MainDocument,
+            "application/vnd.ms-word.document.macroEnabled.main+xml" => ContentType::MacroEnabledDocument,
+            "application/vnd.ms-office.vbaProject" => ContentType::VbaProject,
+            "application/vnd.ms-word.vbaData+xml" => ContentType::VbaData,
             "application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml" => ContentType::Styles,
             "application/vnd.openxmlformats-officedocument.wordprocessingml.theme+xml" => ContentType::Theme,
             "application/vnd.openxmlformats-officedocument.wordprocessingml.settings+xml" => ContentType::Settings,
@@ -56,7 +81,12 @@ impl ContentType {
             "application/vnd.openxmlformats-officedocument.extended-properties+xml" => ContentType::AppProperties,
             "application/vnd.openxmlformats-officedocument.wordprocessingml.webSettings+xml" => ContentType::WebSettings,
             "application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml" => ContentType::Numbering,
-            "application/xml" | "application/vnd.openxmlformats-officedocument.customXmlProperties+xml" => ContentType::CustomXml,
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.comments+xml" => ContentType::Comments,
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.commentsExtended+xml" => ContentType::CommentsExtended,
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.header+xml" => ContentType::Header,
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.footer+xml" => ContentType::Footer,
+            "application/xml" => ContentType::CustomXml,
+            "application/vnd.openxmlformats-officedocument.customXmlProperties+xml" => ContentType::CustomXmlProperties,
             "application/vnd.openxmlformats-package.relationships+xml" => ContentType::Relationships,
             // Image types
             "image/png" => ContentType::ImagePng,
@@ -66,10 +96,47 @@ impl ContentType {
             "image/webp" => ContentType::ImageWebP,
             "image/tiff" | "image/tif" => ContentType::ImageTiff,
             "image/svg+xml" => ContentType::ImageSvg,
+            "image/x-emf" | "image/emf" => ContentType::ImageEmf,
+            "image/x-wmf" | "image/wmf" => ContentType::ImageWmf,
             _ => ContentType::Unknown(s.to_string()),
         }
     }
 
+    /// The content type string written to [Content_Types].xml
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContentType::MainDocument => "application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml",
+            ContentType::MacroEnabledDocument => "application/vnd.ms-word.document.macroEnabled.main+xml",
+            ContentType::VbaProject => "application/vnd.ms-office.vbaProject",
+            ContentType::VbaData => "application/vnd.ms-word.vbaData+xml",
+            ContentType::Styles => "application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml",
+            ContentType::Theme => "application/vnd.openxmlformats-officedocument.theme+xml",
+            ContentType::Settings => "application/vnd.openxmlformats-officedocument.wordprocessingml.settings+xml",
+            ContentType::CoreProperties => "application/vnd.openxmlformats-package.core-properties+xml",
+            ContentType::AppProperties => "application/vnd.openxmlformats-officedocument.extended-properties+xml",
+            ContentType::WebSettings => "application/vnd.openxmlformats-officedocument.wordprocessingml.webSettings+xml",
+            ContentType::Numbering => "application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml",
+            ContentType::Comments => "application/vnd.openxmlformats-officedocument.wordprocessingml.comments+xml",
+            ContentType::CommentsExtended => "application/vnd.openxmlformats-officedocument.wordprocessingml.commentsExtended+xml",
+            ContentType::Header => "application/vnd.openxmlformats-officedocument.wordprocessingml.header+xml",
+            ContentType::Footer => "application/vnd.openxmlformats-officedocument.wordprocessingml.footer+xml",
+            ContentType::CustomXml => "application/xml",
+            ContentType::CustomXmlProperties => "application/vnd.openxmlformats-officedocument.customXmlProperties+xml",
+            ContentType::Relationships => "application/vnd.openxmlformats-package.relationships+xml",
+            ContentType::ImagePng => "image/png",
+            ContentType::ImageJpeg | ContentType::Thumbnail => "image/jpeg",
+            ContentType::ImageGif => "image/gif",
+            ContentType::ImageBmp => "image/bmp",
+            ContentType::ImageWebP => "image/webp",
+            ContentType::ImageTiff => "image/tiff",
+            ContentType::ImageSvg => "image/svg+xml",
+            ContentType::ImageEmf => "image/x-emf",
+            ContentType::ImageWmf => "image/x-wmf",
+            // Types Velum does not know are written back exactly as they were read
+            ContentType::Unknown(content_type) => content_type,
+        }
+    }
+
     /// Check if this is an image content type
     pub fn is_image(&self) -> bool {
         matches!(self,
@@ -79,7 +146,9 @@ impl ContentType {
             ContentType::ImageBmp |
             ContentType::ImageWebP |
             ContentType::ImageTiff |
-            ContentType::ImageSvg
+            ContentType::ImageSvg |
+            ContentType::ImageEmf |
+            ContentType::ImageWmf
         )
     }
 
@@ -94,6 +163,8 @@ impl ContentType {
             ContentType::AppProperties => Some("/docProps/app.xml"),
             ContentType::WebSettings => Some("/word/webSettings.xml"),
             ContentType::Numbering => Some("/word/numbering.xml"),
+            ContentType::Comments => Some("/word/comments.xml"),
+            ContentType::CommentsExtended => Some("/word/commentsExtended.xml"),
             _ => None,
         }
     }
@@ -120,6 +191,20 @@ pub enum RelationshipType {
     OfficeDocument,
     /// Image relationship
     Image,
+    /// VBA project relationship (macro-enabled documents)
+    VbaProject,
+    /// Hyperlink relationship (usually external)
+    Hyperlink,
+    /// Comments relationship
+    Comments,
+    /// Comment threading relationship
+    CommentsExtended,
+    /// Numbering definitions relationship
+    Numbering,
+    /// Header relationship
+    Header,
+    /// Footer relationship
+    Footer,
     /// Unknown relationship type
     Unknown(String),
 }
@@ -136,6 +221,13 @@ impl RelationshipType {
             "http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" => RelationshipType::CoreProperties,
             "http://schemas.openxmlformats.org/officeDocument/2006/relationships/customXml" => RelationshipType::CustomXml,
             "http://schemas.openxmlformats.org/package/2006/relationships/metadata/thumbnail" => RelationshipType::Thumbnail,
+            "http://schemas.microsoft.com/office/2006/relationships/vbaProject" => RelationshipType::VbaProject,
+            "http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" => RelationshipType::Hyperlink,
+            "http://schemas.openxmlformats.org/officeDocument/2006/relationships/comments" => RelationshipType::Comments,
+            "http://schemas.microsoft.com/office/2011/relationships/commentsExtended" => RelationshipType::CommentsExtended,
+            "http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering" => RelationshipType::Numbering,
+            "http://schemas.openxmlformats.org/officeDocument/2006/relationships/header" => RelationshipType::Header,
+            "http://schemas.openxmlformats.org/officeDocument/2006/relationships/footer" => RelationshipType::Footer,
             // Image relationships
             rel if rel.contains("relationships/image") => RelationshipType::Image,
             _ => RelationshipType::Unknown(s.to_string()),
@@ -179,8 +271,47 @@ pub struct Paragraph {
     pub text: String,
     /// Paragraph properties (indentation, alignment, etc.)
     pub properties: ParagraphProperties,
+    /// Formatting of the paragraph mark (w:pPr/w:rPr); the final paragraph's
+    /// holds for the end of the document
+    #[serde(default)]
+    pub mark_properties: Option<RunProperties>,
     /// List of runs in this paragraph
     pub runs: Vec<Run>,
+    /// Fields whose results appear in this paragraph's text
+    #[serde(default)]
+    pub fields: Vec<Field>,
+    /// Footnote and endnote references in this paragraph
+    #[serde(default)]
+    pub note_references: Vec<NoteReference>,
+    /// Tracked changes with char offsets into this paragraph's text
+    #[serde(default)]
+    pub revisions: Vec<Revision>,
+    /// Comment range boundaries and reference marks in this paragraph
+    #[serde(default)]
+    pub comment_marks: Vec<CommentMark>,
+    /// Bookmark boundaries in this paragraph
+    #[serde(default)]
+    pub bookmark_marks: Vec<BookmarkMark>,
+    /// Hyperlinks with char offsets into this paragraph's text
+    #[serde(default)]
+    pub hyperlinks: Vec<Hyperlink>,
+    /// Equations (m:oMath) with char offsets into this paragraph's text, which
+    /// holds their linear form
+    #[serde(default)]
+    pub math_zones: Vec<MathZone>,
+    /// Form fields (legacy fields and fillable content controls) with char
+    /// offsets into this paragraph's text, which holds what they show
+    #[serde(default)]
+    pub form_fields: Vec<AnchoredFormField>,
+}
+
+/// Chars of a paragraph's text that are an equation in linear format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MathZone {
+    /// Char offset of the equation's first char
+    pub start: usize,
+    /// Length of the equation's text in chars
+    pub length: usize,
 }
 
 /// Properties of a paragraph
@@ -200,6 +331,62 @@ pub struct ParagraphProperties {
     pub spacing_after: Option<i32>,
     /// Line spacing
     pub spacing_line: Option<i32>,
+    /// Paragraph style ID
+    #[serde(default)]
+    pub style_id: Option<String>,
+    /// ID of the list (w:num) the paragraph belongs to; "0" removes an inherited list
+    #[serde(default)]
+    pub num_id: Option<String>,
+    /// Level in the paragraph's list, from 0
+    #[serde(default)]
+    pub list_level: Option<u8>,
+    /// Frame placing the paragraph apart from the text flow
+    #[serde(default)]
+    pub frame: Option<ParagraphFrame>,
+    /// Line below the paragraph (w:pBdr/w:bottom)
+    #[serde(default)]
+    pub border_bottom: Option<ParagraphBorder>,
+    /// Kept on the page of the next paragraph (w:keepNext)
+    #[serde(default)]
+    pub keep_next: Option<bool>,
+    /// Kept on one page (w:keepLines)
+    #[serde(default)]
+    pub keep_lines: Option<bool>,
+    /// Starts on a new page (w:pageBreakBefore)
+    #[serde(default)]
+    pub page_break_before: Option<bool>,
+    /// Widow and orphan control (w:widowControl)
+    #[serde(default)]
+    pub widow_control: Option<bool>,
+}
+
+/// A paragraph border line
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ParagraphBorder {
+    /// Line style (w:val): "single", "double", "thick", "dotted", "wave", "triple", ...
+    pub style: String,
+    /// Width in eighths of a point
+    pub size: u32,
+    /// Gap to the text in points
+    pub space: u32,
+}
+
+/// Position and size of a text frame (w:framePr); consecutive paragraphs with
+/// the same frame share it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParagraphFrame {
+    /// Horizontal position in twips from `horizontal_anchor`
+    pub x: i32,
+    /// Vertical position in twips from `vertical_anchor`
+    pub y: i32,
+    /// Width in twips; None fits the text
+    pub width: Option<i32>,
+    /// Minimum height in twips; None fits the text
+    pub height: Option<i32>,
+    /// What `x` is measured from: "page", "margin" or "text" (Word's default)
+    pub horizontal_anchor: Option<String>,
+    /// What `y` is measured from: "page", "margin" or "text" (Word's default)
+    pub vertical_anchor: Option<String>,
 }
 
 /// Represents a run of text with common formatting
@@ -211,8 +398,215 @@ pub struct Run {
     pub properties: RunProperties,
 }
 
+/// Kind of a Word field, taken from the first word of its instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FieldKind {
+    MergeField,
+    Page,
+    NumPages,
+    Toc,
+    Date,
+    Ref,
+    PageRef,
+    NoteRef,
+    Seq,
+    FormField,
+    /// XE: marks an index entry
+    IndexEntry,
+    /// INDEX: the index built from the XE fields
+    Index,
+    /// STYLEREF: text or number of the nearest paragraph in a style
+    StyleRef,
+    Other,
+}
+
+impl FieldKind {
+    /// Classify a field instruction such as "MERGEFIELD FirstName \* MERGEFORMAT"
+    pub fn from_instruction(instruction: &str) -> Self {
+        let keyword = instruction.split_whitespace().next().unwrap_or("").to_uppercase();
+        match keyword.as_str() {
+            "MERGEFIELD" => FieldKind::MergeField,
+            "PAGE" => FieldKind::Page,
+            "NUMPAGES" | "SECTIONPAGES" => FieldKind::NumPages,
+            "TOC" => FieldKind::Toc,
+            "DATE" | "TIME" | "CREATEDATE" | "SAVEDATE" | "PRINTDATE" => FieldKind::Date,
+            "REF" => FieldKind::Ref,
+            "PAGEREF" => FieldKind::PageRef,
+            "NOTEREF" => FieldKind::NoteRef,
+            "SEQ" => FieldKind::Seq,
+            "FORMTEXT" | "FORMCHECKBOX" | "FORMDROPDOWN" => FieldKind::FormField,
+            "XE" => FieldKind::IndexEntry,
+            "INDEX" => FieldKind::Index,
+            "STYLEREF" => FieldKind::StyleRef,
+            _ => FieldKind::Other,
+        }
+    }
+}
+
+/// A simple or complex field within a paragraph
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    /// Field instruction (e.g. "MERGEFIELD FirstName")
+    pub instruction: String,
+    /// Field kind derived from the instruction
+    pub kind: FieldKind,
+    /// Char offset of the field result in the containing text
+    pub start: usize,
+    /// Length of the field result in chars; a result that runs on into later
+    /// paragraphs, as an index does, counts a "\n" for each paragraph break
+    pub length: usize,
+    /// Cached result as last computed by the document's producer
+    pub result: String,
+}
+
+impl Field {
+    /// Create a field from its instruction and cached result
+    pub fn new(instruction: &str, start: usize, result: &str) -> Self {
+        let instruction = instruction.trim().to_string();
+        Field {
+            kind: FieldKind::from_instruction(&instruction),
+            instruction,
+            start,
+            length: result.chars().count(),
+            result: result.to_string(),
+        }
+    }
+
+    /// First argument of the instruction, without quotes (e.g. the merge field name)
+    pub fn argument(&self) -> Option<&str> {
+        let rest = self.instruction.trim_start().split_once(char::is_whitespace)?.1.trim_start();
+        if let Some(quoted) = rest.strip_prefix('"') {
+            return quoted.split('"').next();
+        }
+        rest.split_whitespace().next().filter(|arg| !arg.starts_with('\\'))
+    }
+}
+
+/// Kind of note a reference points to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NoteKind {
+    Footnote,
+    Endnote,
+}
+
+/// Kind of tracked change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RevisionKind {
+    /// Inserted text (w:ins)
+    Insertion,
+    /// Deleted text (w:del), still present in the text until accepted
+    Deletion,
+    /// Changed run formatting (w:rPrChange)
+    RunFormat,
+    /// Changed paragraph formatting (w:pPrChange)
+    ParagraphFormat,
+}
+
+/// A tracked change and the text it covers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    /// Revision ID (w:id)
+    pub id: String,
+    pub kind: RevisionKind,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// W3CDTF timestamp of the change
+    #[serde(default)]
+    pub date: Option<String>,
+    /// Char offset of the revised text in the containing text
+    pub start: usize,
+    /// Length of the revised text in chars
+    pub length: usize,
+    /// Run formatting before a run format change
+    #[serde(default)]
+    pub previous_run_properties: Option<RunProperties>,
+    /// Paragraph formatting before a paragraph format change
+    #[serde(default)]
+    pub previous_paragraph_properties: Option<ParagraphProperties>,
+}
+
+/// A footnote or endnote reference mark in the text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteReference {
+    pub kind: NoteKind,
+    /// ID of the note in footnotes.xml / endnotes.xml
+    pub id: String,
+    /// Char offset of the reference mark in the containing text
+    pub position: usize,
+}
+
+/// What a comment mark in the text is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CommentMarkKind {
+    /// w:commentRangeStart
+    RangeStart,
+    /// w:commentRangeEnd
+    RangeEnd,
+    /// w:commentReference, the comment's mark after its range
+    Reference,
+}
+
+/// A comment range boundary or reference mark in the text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommentMark {
+    pub kind: CommentMarkKind,
+    /// ID of the comment in comments.xml
+    pub id: String,
+    /// Char offset of the mark in the containing text
+    pub position: usize,
+}
+
+/// Which end of a bookmark a mark is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum BookmarkMarkKind {
+    /// w:bookmarkStart, which carries the name
+    Start,
+    /// w:bookmarkEnd
+    End,
+}
+
+/// A bookmark boundary in the text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookmarkMark {
+    pub kind: BookmarkMarkKind,
+    /// ID pairing the start with its end (w:id)
+    pub id: String,
+    /// Bookmark name (w:name), on the start only
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Char offset of the mark in the containing text
+    pub position: usize,
+}
+
+/// A hyperlink (w:hyperlink) over part of the text
+///
+/// It goes to an external `url` or to the bookmark named `anchor`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hyperlink {
+    /// External target, from the relationship the link refers to
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Bookmark inside the document (w:anchor)
+    #[serde(default)]
+    pub anchor: Option<String>,
+    /// Text shown when hovering over the link (w:tooltip)
+    #[serde(default)]
+    pub tooltip: Option<String>,
+    /// Whether the link has been followed, so it shows in the FollowedHyperlink
+    /// style; Word does not keep this in the file
+    #[serde(default)]
+    pub visited: bool,
+    /// Relationship ID (r:id) of `url` as read, until it is resolved
+    #[serde(default, skip_serializing)]
+    pub relationship_id: Option<String>,
+    /// Char offset of the linked text in the containing text
+    pub start: usize,
+    /// Length of the linked text in chars
+    pub length: usize,
+}
+
 /// Properties of a run (text formatting)
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct RunProperties {
     /// Bold formatting
     pub bold: Option<bool>,
@@ -241,6 +635,9 @@ pub struct Style {
     pub style_type: String,
     /// Style ID of the parent style
     pub based_on: Option<String>,
+    /// Style ID of the paragraph started by Enter at the end of one of this style
+    #[serde(default)]
+    pub next: Option<String>,
     /// Paragraph properties
     pub paragraph_properties: ParagraphProperties,
     /// Run properties
@@ -296,6 +693,78 @@ pub struct DocumentImage {
     pub alt_description: Option<String>,
     /// Whether the image is linked rather than embedded
     pub is_linked: bool,
+    /// Body paragraph the image is drawn in
+    #[serde(default)]
+    pub paragraph_index: usize,
+    /// Char offset in that paragraph's text where the drawing sits
+    #[serde(default)]
+    pub position: usize,
+    /// Placement of a floating image (wp:anchor); an image without one is inline
+    #[serde(default)]
+    pub anchor: Option<ImageAnchor>,
+    /// Crop, rotation, flips and picture adjustments
+    #[serde(default)]
+    pub transform: ImageTransform,
+}
+
+impl DocumentImage {
+    /// Size the image is drawn at in EMUs: the desired size, or the original
+    /// one scaled, if known
+    pub fn extent(&self) -> Option<(u32, u32)> {
+        let scaled = |original: Option<u32>, scale: Option<f32>| {
+            original.map(|size| (size as f32 * scale.unwrap_or(100.0) / 100.0).round() as u32)
+        };
+        let width = self.desired_width.or_else(|| scaled(self.original_width, self.scale_x))?;
+        let height = self.desired_height.or_else(|| scaled(self.original_height, self.scale_y))?;
+        Some((width, height))
+    }
+}
+
+/// Where a floating image sits and how text wraps around it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageAnchor {
+    /// Horizontal offset in EMUs from what `horizontal_relative_to` names
+    pub horizontal_offset: i64,
+    /// "column", "page", "margin" or "character" (wp:positionH relativeFrom)
+    pub horizontal_relative_to: String,
+    /// Vertical offset in EMUs from what `vertical_relative_to` names
+    pub vertical_offset: i64,
+    /// "paragraph", "page", "margin" or "line" (wp:positionV relativeFrom)
+    pub vertical_relative_to: String,
+    /// Text wrapping: "square", "tight", "through", "topAndBottom" or "none"
+    pub wrap: String,
+    /// Whether an image text does not wrap around goes behind the text
+    pub behind_text: bool,
+    /// Stacking order among floating objects, higher in front (wp:anchor relativeHeight)
+    #[serde(default)]
+    pub z_order: u32,
+    /// Whether the image may overlap other floating objects instead of being
+    /// moved off them (wp:anchor allowOverlap)
+    #[serde(default)]
+    pub allow_overlap: bool,
+}
+
+impl Default for ImageAnchor {
+    fn default() -> Self {
+        ImageAnchor {
+            horizontal_offset: 0,
+            horizontal_relative_to: "column".to_string(),
+            vertical_offset: 0,
+            vertical_relative_to: "paragraph".to_string(),
+            wrap: "square".to_string(),
+            behind_text: false,
+            z_order: 0,
+            allow_overlap: false,
+        }
+    }
+}
+
+impl ImageAnchor {
+    /// Whether text wraps around the image, so other such images are kept
+    /// from overlapping it
+    pub fn wraps_text(&self) -> bool {
+        self.wrap != "none" && !self.behind_text
+    }
 }
 
 /// Blip fill properties for images
@@ -311,8 +780,9 @@ pub struct BlipFill {
     pub source_rect: Option<SourceRect>,
 }
 
-/// Source rectangle for partial image
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// Source rectangle for partial image: how much of the picture is cut off
+/// each edge, in percent of its size (a:srcRect)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
 pub struct SourceRect {
     /// Top offset
     pub top: f32,
@@ -324,6 +794,57 @@ pub struct SourceRect {
     pub right: f32,
 }
 
+/// How an image's picture is cropped, turned and adjusted when drawn
+///
+/// The picture is cropped first; the frame it fills is then flipped and
+/// turned about its center, so the image keeps its place and size.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImageTransform {
+    /// Part of the picture cut off (pic:blipFill a:srcRect)
+    pub crop: Option<SourceRect>,
+    /// Clockwise rotation in degrees (a:xfrm rot)
+    pub rotation: f32,
+    /// Mirrored left to right (a:xfrm flipH)
+    pub flip_horizontal: bool,
+    /// Mirrored top to bottom (a:xfrm flipV)
+    pub flip_vertical: bool,
+    /// Brightness change in percent, -100 to 100 (a:lum bright)
+    pub brightness: f32,
+    /// Contrast change in percent, -100 to 100 (a:lum contrast)
+    pub contrast: f32,
+    /// Transparency in percent, from 0 opaque to 100 invisible (a:alphaModFix)
+    pub transparency: f32,
+}
+
+impl ImageTransform {
+    /// The transform with each value in range: rotation from 0 to 360
+    /// degrees, adjustments within their bounds, and crops that leave some
+    /// of the picture
+    pub fn normalized(&self) -> Self {
+        let crop = self.crop.map(|crop| {
+            let side = |value: f32| value.clamp(0.0, 99.0);
+            let (left, top) = (side(crop.left), side(crop.top));
+            SourceRect { left, top, right: crop.right.clamp(0.0, 99.0 - left), bottom: crop.bottom.clamp(0.0, 99.0 - top) }
+        });
+        ImageTransform {
+            crop: crop.filter(|crop| *crop != SourceRect::default()),
+            rotation: self.rotation.rem_euclid(360.0),
+            flip_horizontal: self.flip_horizontal,
+            flip_vertical: self.flip_vertical,
+            brightness: self.brightness.clamp(-100.0, 100.0),
+            contrast: self.contrast.clamp(-100.0, 100.0),
+            transparency: self.transparency.clamp(0.0, 100.0),
+        }
+    }
+
+    /// Share of the picture's width and height left after cropping
+    pub fn visible_share(&self) -> (f32, f32) {
+        let crop = self.crop.unwrap_or_default();
+        ((100.0 - crop.left - crop.right) / 100.0, (100.0 - crop.top - crop.bottom) / 100.0)
+    }
+}
+
 /// Document anchor for positioning floating elements
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentAnchor {
@@ -365,6 +886,9 @@ pub struct Table {
     pub rows: Vec<TableRow>,
     /// Table properties (width, alignment, borders, etc.)
     pub properties: TableProperties,
+    /// Body paragraphs before the table
+    #[serde(default)]
+    pub paragraph_index: usize,
 }
 
 /// Table row in a table
@@ -406,6 +930,9 @@ pub struct TableProperties {
     pub indent: Option<i32>,
     /// Table layout type (fixed, auto)
     pub layout: Option<String>,
+    /// Widths of the grid columns cells span, in twips (w:tblGrid)
+    #[serde(default)]
+    pub grid_columns: Vec<u32>,
 }
 
 /// Table row properties
@@ -430,6 +957,9 @@ pub struct TableCellProperties {
     pub text_direction: Option<String>,
     /// Shading/background color
     pub shading_color: Option<String>,
+    /// Grid columns the cell spans (w:gridSpan)
+    #[serde(default)]
+    pub grid_span: Option<u32>,
 }
 
 /// Table borders
@@ -490,6 +1020,150 @@ pub struct Footer {
     pub images: Vec<DocumentImage>,
 }
 
+// ============================================
+// Section types
+// ============================================
+
+/// A header or footer used by a section (w:headerReference / w:footerReference)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeaderFooterReference {
+    /// Which pages it applies to (default, first, even)
+    pub kind: String,
+    /// Relationship ID of the header or footer part
+    pub id: String,
+}
+
+/// Section properties (w:sectPr) and the paragraphs they cover
+///
+/// Lengths are in twips, as stored; unset values fall back to Word's defaults
+/// when converted with [`Section::page_setup`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    /// Index of the section's first body paragraph
+    pub first_paragraph: usize,
+    /// Number of body paragraphs in the section
+    pub paragraph_count: usize,
+    /// Page width in twips
+    pub page_width: Option<i32>,
+    /// Page height in twips
+    pub page_height: Option<i32>,
+    /// Whether w:pgSz declares landscape orientation
+    pub landscape: bool,
+    pub margin_top: Option<i32>,
+    pub margin_right: Option<i32>,
+    pub margin_bottom: Option<i32>,
+    pub margin_left: Option<i32>,
+    /// Distance from the top edge to the header
+    pub header_distance: Option<i32>,
+    /// Distance from the bottom edge to the footer
+    pub footer_distance: Option<i32>,
+    pub gutter: Option<i32>,
+    /// Number of text columns
+    pub columns: u32,
+    /// Space between columns in twips
+    pub column_space: Option<i32>,
+    /// Whether the first page uses its own header and footer (w:titlePg)
+    pub title_page: bool,
+    pub header_references: Vec<HeaderFooterReference>,
+    pub footer_references: Vec<HeaderFooterReference>,
+    /// Children of the w:sectPr Velum does not model (w:pgNumType, w:docGrid,
+    /// w:type, ...), as read, so they are written back unchanged
+    #[serde(default)]
+    pub other_children: Vec<String>,
+}
+
+impl Default for Section {
+    fn default() -> Self {
+        Section {
+            first_paragraph: 0,
+            paragraph_count: 0,
+            page_width: None,
+            page_height: None,
+            landscape: false,
+            margin_top: None,
+            margin_right: None,
+            margin_bottom: None,
+            margin_left: None,
+            header_distance: None,
+            footer_distance: None,
+            gutter: None,
+            columns: 1,
+            column_space: None,
+            title_page: false,
+            header_references: Vec::new(),
+            footer_references: Vec::new(),
+            other_children: Vec::new(),
+        }
+    }
+}
+
+impl Section {
+    /// How the section starts, from the w:type kept among its other children
+    pub fn break_type(&self) -> crate::page_layout::SectionBreak {
+        let value = self.other_children.iter().find_map(|child| {
+            let rest = child.trim_start().strip_prefix("<w:type")?;
+            let value = rest.split_once("w:val=\"")?.1;
+            value.split_once('"').map(|(value, _)| value.to_string())
+        });
+        value.map_or_else(Default::default, |value| crate::page_layout::SectionBreak::from_ooxml(&value))
+    }
+
+    /// Page geometry in points, with defaults for anything the section leaves unset
+    pub fn page_setup(&self) -> crate::page_setup::SectionPageSetup {
+        use crate::page_setup::{Orientation, SectionPageSetup};
+
+        let defaults = SectionPageSetup::default();
+        let points = |twips: Option<i32>, default: f32| twips.map_or(default, |t| t as f32 / 20.0);
+        let margins = &defaults.margins;
+        let landscape = self.landscape
+            || matches!((self.page_width, self.page_height), (Some(w), Some(h)) if w > h);
+        let (width, height) = match (self.page_width, self.page_height) {
+            (Some(_), Some(_)) => (points(self.page_width, 0.0), points(self.page_height, 0.0)),
+            // Without a size the default paper is turned to match the orientation
+            _ if landscape => (defaults.height, defaults.width),
+            _ => (defaults.width, defaults.height),
+        };
+
+        SectionPageSetup {
+            width,
+            height,
+            orientation: if landscape { Orientation::Landscape } else { Orientation::Portrait },
+            margins: crate::page_setup::Margins {
+                top: points(self.margin_top, margins.top),
+                bottom: points(self.margin_bottom, margins.bottom),
+                left: points(self.margin_left, margins.left),
+                right: points(self.margin_right, margins.right),
+                header: points(self.header_distance, margins.header),
+                footer: points(self.footer_distance, margins.footer),
+                gutter: points(self.gutter, margins.gutter),
+            },
+        }
+    }
+
+    /// Whether the section sets its page size, orientation or any margin
+    pub fn has_page_setup(&self) -> bool {
+        self.landscape
+            || [
+                self.page_width,
+                self.page_height,
+                self.margin_top,
+                self.margin_right,
+                self.margin_bottom,
+                self.margin_left,
+                self.header_distance,
+                self.footer_distance,
+                self.gutter,
+            ]
+            .iter()
+            .any(Option::is_some)
+    }
+
+    /// Space between columns in points (Word's default is half an inch)
+    pub fn column_gap(&self) -> f32 {
+        self.column_space.map_or(36.0, |twips| twips as f32 / 20.0)
+    }
+}
+
 // ============================================
 // Footnote/Endnote types
 // ============================================
@@ -516,6 +1190,49 @@ pub struct Endnote {
     pub paragraphs: Vec<Paragraph>,
 }
 
+// ============================================
+// Comment types
+// ============================================
+
+/// A comment and the range of text it is anchored to
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Comment {
+    /// Comment ID (w:id)
+    pub id: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub initials: Option<String>,
+    /// W3CDTF timestamp of the comment
+    #[serde(default)]
+    pub date: Option<String>,
+    /// Paragraphs of the comment body
+    pub paragraphs: Vec<Paragraph>,
+    /// ID of the comment this one replies to
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Whether the comment was marked done
+    #[serde(default)]
+    pub done: bool,
+    /// Char offset of the commented text in the containing text
+    #[serde(default)]
+    pub start: usize,
+    /// Length of the commented text in chars
+    #[serde(default)]
+    pub length: usize,
+}
+
+impl Comment {
+    /// Plain text of the body, paragraphs separated by "\n"
+    pub fn text(&self) -> String {
+        self.paragraphs
+            .iter()
+            .map(|paragraph| paragraph.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
 // ============================================
 // Numbering types
 // ============================================
@@ -549,6 +1266,19 @@ pub struct ListLevel {
     pub text: String,
     /// Starting value
     pub start_value: u32,
+    /// Paragraph style linked to this level (w:pStyle), e.g. a heading style
+    #[serde(default)]
+    pub style_id: Option<String>,
+    /// Legal numbering (w:isLgl): the numbers of all levels in the label are decimal
+    #[serde(default)]
+    pub is_legal: bool,
+    /// Level (from 1) whose items restart this level (w:lvlRestart); 0 never restarts
+    /// it, None restarts it after any item of a higher level
+    #[serde(default)]
+    pub restart: Option<u32>,
+    /// What follows the label (w:suff): "tab" (None), "space" or "nothing"
+    #[serde(default)]
+    pub suffix: Option<String>,
     /// Paragraph properties for this level
     pub paragraph_properties: ParagraphProperties,
     /// Run properties for this level
@@ -575,6 +1305,9 @@ pub struct LevelOverride {
     pub start_value: Option<u32>,
     /// Override text
     pub text: Option<String>,
+    /// Override format
+    #[serde(default)]
+    pub format: Option<String>,
 }
 
 // ============================================
@@ -592,8 +1325,14 @@ pub struct ContentControl {
     pub sdt_type: String,
     /// Properties
     pub properties: ContentControlProperties,
-    /// Content (paragraphs)
+    /// Content (paragraphs); empty for body controls, whose paragraphs stay in the body
     pub content: Vec<Paragraph>,
+    /// Index of the first body paragraph inside a body control
+    #[serde(default)]
+    pub first_paragraph: usize,
+    /// Number of body paragraphs inside a body control
+    #[serde(default)]
+    pub paragraph_count: usize,
 }
 
 /// Content control properties
@@ -638,6 +1377,27 @@ mod tests {
         assert_eq!(part.data.len(), 17);
     }
 
+    #[test]
+    fn test_field_kind_from_instruction() {
+        assert_eq!(FieldKind::from_instruction(" MERGEFIELD Name "), FieldKind::MergeField);
+        assert_eq!(FieldKind::from_instruction("page \\* Arabic"), FieldKind::Page);
+        assert_eq!(FieldKind::from_instruction("TOC \\o \"1-3\""), FieldKind::Toc);
+        assert_eq!(FieldKind::from_instruction("AUTHOR"), FieldKind::Other);
+    }
+
+    #[test]
+    fn test_field_argument() {
+        let field = Field::new(" MERGEFIELD FirstName \\* MERGEFORMAT ", 0, "«FirstName»");
+        assert_eq!(field.argument(), Some("FirstName"));
+        assert_eq!(field.length, 11);
+
+        let quoted = Field::new(r#"MERGEFIELD "Last Name""#, 0, "");
+        assert_eq!(quoted.argument(), Some("Last Name"));
+
+        let bare = Field::new("PAGE \\* MERGEFORMAT", 0, "1");
+        assert_eq!(bare.argument(), None);
+    }
+
     #[test]
     fn test_paragraph_default() {
         let para = Paragraph::default();
@@ -781,6 +1541,10 @@ mod tests {
             format: "bullet".to_string(),
             text: "·".to_string(),
             start_value: 1,
+            style_id: None,
+            is_legal: false,
+            restart: None,
+            suffix: None,
             paragraph_properties: ParagraphProperties::default(),
             run_properties: RunProperties::default(),
         };
@@ -804,6 +1568,7 @@ mod tests {
             level: 0,
             start_value: Some(5),
             text: Some("5.".to_string()),
+            format: None,
         };
         assert_eq!(override_.level, 0);
         assert_eq!(override_.start_value, Some(5));
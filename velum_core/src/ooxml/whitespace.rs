@@ -0,0 +1,152 @@
+//! Whitespace in WordprocessingML run text
+//!
+//! Readers strip the leading and trailing spaces of a w:t, and may collapse
+//! the spaces inside it, unless it has xml:space="preserve". Tabs and line
+//! breaks are not text at all but w:tab and w:br elements between the w:t
+//! segments of a run. [`run_content`] writes run text that way and
+//! [`read_text`] reads it back, so spaces, tabs and line breaks (U+2028)
+//! survive a round trip; [`whitespace_lint`] checks written XML for text
+//! that would still lose its spaces.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::document::unescape_xml_text;
+use super::serializer::escape_xml_text;
+
+/// Line break within a paragraph
+const LINE_BREAK: char = '\u{2028}';
+
+/// w:t and w:delText, run tabs and breaks; tab stops in w:tabs have attributes, run tabs none
+static RUN_CONTENT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"<w:(t|delText)(?:\s[^>]*)?>([^<]*)</w:(?:t|delText)>|<w:(tab)\s*/>|<w:(?:br|cr)\b([^>]*)/>"#).unwrap()
+});
+
+/// Text elements with their attributes and escaped text
+static TEXT_ELEMENT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"<w:(?:t|delText|instrText)(\s[^>]*)?>([^<]*)</w:(?:t|delText|instrText)>"#).unwrap());
+
+/// The content of a w:r for `text`: w:t segments, or w:delText for deleted
+/// text, with w:tab for tabs and w:br for line breaks. Characters XML cannot
+/// hold are left out
+pub(super) fn run_content(text: &str, deleted: bool) -> String {
+    let element = if deleted { "w:delText" } else { "w:t" };
+    let mut xml = String::new();
+    let mut segment = String::new();
+    let flush = |xml: &mut String, segment: &mut String| {
+        if segment.is_empty() {
+            return;
+        }
+        if needs_preserve(segment) {
+            xml.push_str(&format!(r#"<{0} xml:space="preserve">{1}</{0}>"#, element, escape_xml_text(segment)));
+        } else {
+            xml.push_str(&format!("<{0}>{1}</{0}>", element, escape_xml_text(segment)));
+        }
+        segment.clear();
+    };
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                flush(&mut xml, &mut segment);
+                xml.push_str("<w:tab/>");
+            }
+            '\n' | '\u{b}' | LINE_BREAK => {
+                flush(&mut xml, &mut segment);
+                xml.push_str("<w:br/>");
+            }
+            c if is_unwritable(c) => {}
+            c => segment.push(c),
+        }
+    }
+    flush(&mut xml, &mut segment);
+    xml
+}
+
+/// Whether readers would change the spaces of `text` in a w:t without xml:space="preserve"
+fn needs_preserve(text: &str) -> bool {
+    let is_space = |c: char| matches!(c, ' ' | '\t' | '\n' | '\r');
+    text.starts_with(is_space)
+        || text.ends_with(is_space)
+        || text.chars().zip(text.chars().skip(1)).any(|(a, b)| is_space(a) && is_space(b))
+        || text.contains(['\t', '\n', '\r'])
+}
+
+/// Characters XML 1.0 cannot hold, other than those written as elements
+fn is_unwritable(c: char) -> bool {
+    (c < ' ' && !matches!(c, '\t' | '\n' | '\u{b}')) || matches!(c, '\u{fffe}' | '\u{ffff}')
+}
+
+/// The text of the runs in `xml` in order: w:t as written, w:tab as tabs and
+/// line breaks as U+2028; deleted text (w:delText) too if `deleted`. Page
+/// and column breaks are not text and are left out
+pub(super) fn read_text(xml: &str, deleted: bool) -> String {
+    let mut text = String::new();
+    for caps in RUN_CONTENT.captures_iter(xml) {
+        if let Some(element) = caps.get(1) {
+            if deleted || element.as_str() == "t" {
+                text.push_str(&unescape_xml_text(&caps[2]));
+            }
+        } else if caps.get(3).is_some() {
+            text.push('\t');
+        } else {
+            let attributes = caps.get(4).map_or("", |m| m.as_str());
+            if !attributes.contains(r#"w:type="page""#) && !attributes.contains(r#"w:type="column""#) {
+                text.push(LINE_BREAK);
+            }
+        }
+    }
+    text
+}
+
+/// Text elements in `xml` whose spaces readers would strip or collapse, as
+/// they lack xml:space="preserve"
+pub(super) fn whitespace_lint(xml: &str) -> usize {
+    TEXT_ELEMENT
+        .captures_iter(xml)
+        .filter(|caps| {
+            let preserved = caps.get(1).is_some_and(|attributes| attributes.as_str().contains(r#"xml:space="preserve""#));
+            !preserved && needs_preserve(&caps[2])
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_content_preserves_spaces() {
+        assert_eq!(run_content("plain", false), "<w:t>plain</w:t>");
+        assert_eq!(run_content(" lead", false), r#"<w:t xml:space="preserve"> lead</w:t>"#);
+        assert_eq!(run_content("trail ", true), r#"<w:delText xml:space="preserve">trail </w:delText>"#);
+        assert_eq!(run_content("a  b", false), r#"<w:t xml:space="preserve">a  b</w:t>"#);
+        assert_eq!(run_content("   ", false), r#"<w:t xml:space="preserve">   </w:t>"#);
+        assert_eq!(run_content("a\tb\u{2028} c", false), r#"<w:t>a</w:t><w:tab/><w:t>b</w:t><w:br/><w:t xml:space="preserve"> c</w:t>"#);
+        assert_eq!(run_content("\t\t", false), "<w:tab/><w:tab/>");
+        assert_eq!(run_content("x\u{1}<y>", false), "<w:t>x&lt;y&gt;</w:t>");
+    }
+
+    #[test]
+    fn test_read_text_round_trip() {
+        for text in ["  two  spaces  ", " ", "\ttab\t", "multi\u{2028}  line", "a \t b", "<&>"] {
+            assert_eq!(read_text(&run_content(text, false), false), text);
+            assert_eq!(read_text(&run_content(text, true), true), text);
+        }
+        assert_eq!(read_text(&run_content("gone", true), false), "");
+    }
+
+    #[test]
+    fn test_read_text_breaks_and_tab_stops() {
+        let xml = r#"<w:pPr><w:tabs><w:tab w:val="left" w:pos="720"/></w:tabs></w:pPr>
+            <w:r><w:t>a</w:t><w:br/><w:t>b</w:t><w:br w:type="page"/><w:cr/><w:tab/><w:t xml:space="preserve"> c </w:t></w:r>"#;
+        assert_eq!(read_text(xml, false), "a\u{2028}b\u{2028}\t c ");
+    }
+
+    #[test]
+    fn test_whitespace_lint() {
+        let xml = r#"<w:r><w:t>ok</w:t><w:t>lost </w:t><w:t xml:space="preserve"> kept</w:t></w:r>
+            <w:r><w:instrText> PAGE </w:instrText><w:delText>a  b</w:delText></w:r>"#;
+        assert_eq!(whitespace_lint(xml), 3);
+        assert_eq!(whitespace_lint(&run_content(" all\tfine  ", false)), 0);
+    }
+}
@@ -3,12 +3,18 @@
 //! Implements document pagination engine with support for:
 //! - Configurable page sizes and margins
 //! - Widow/orphan control
+//! - Keep with next, keep lines together and page break before
 //! - Multi-column layouts
 //! - Cross-page paragraph breaking
+//! - Sections starting on the next page, even or odd page, next column or
+//!   continuously, each with its own page geometry
 
-use crate::line_layout::ParagraphLayout;
+use crate::line_layout::{LineLayout, ParagraphLayout};
+use crate::metrics;
+use crate::ooxml::Section;
+use crate::text_wrap::{Exclusions, LineSpace};
 use serde::{Deserialize, Serialize};
-use std::cmp::min;
+use std::ops::Range;
 
 /// Represents a rectangle in 2D space
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
@@ -43,6 +49,12 @@ impl Rect {
     pub fn right(&self) -> f32 {
         self.x + self.width
     }
+
+    /// Checks if this rect and `other` overlap; touching edges do not
+    #[inline]
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right() && other.x < self.right() && self.y < other.bottom() && other.y < self.bottom()
+    }
 }
 
 /// Represents a rendered line with position information
@@ -69,7 +81,7 @@ pub struct RenderedLine {
 }
 
 /// Page size and margin configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PageConfig {
     /// Page width in points (default A4: 595.35pt)
     pub width: f32,
@@ -188,6 +200,12 @@ pub struct Page {
     pub continued_on: Option<usize>,
     /// Previous page number indicator (for continuation)
     pub continued_from: Option<usize>,
+    /// Section whose page geometry the page has
+    #[serde(default)]
+    pub section: usize,
+    /// Left blank for a section to start on an even or odd page
+    #[serde(default)]
+    pub blank: bool,
 }
 
 /// Configuration for pagination control
@@ -271,6 +289,14 @@ impl PageLayout {
         }
     }
 
+    /// Creates a page layout with a document section's page geometry and columns
+    pub fn for_section(section: &Section) -> Self {
+        let mut layout = PageLayout::with_page_config(section.page_setup().page_config());
+        layout.set_columns(section.columns);
+        layout.set_column_gap(section.column_gap());
+        layout
+    }
+
     /// Sets the number of columns
     #[inline]
     pub fn set_columns(&mut self, columns: u32) {
@@ -289,9 +315,9 @@ impl PageLayout {
         self.config.enable_widow_orphan = enabled;
     }
 
-    /// Gets a single column's width
+    /// Gets a single column's width, the width paragraphs are laid out at
     #[inline]
-    fn column_width(&self) -> f32 {
+    pub fn column_width(&self) -> f32 {
         if self.config.columns <= 1 {
             self.page_config.content_width()
         } else {
@@ -306,24 +332,6 @@ impl PageLayout {
         self.config.line_height * self.config.font_size
     }
 
-    /// Calculates the content height available on a page
-    #[inline]
-    fn available_content_height(&self) -> f32 {
-        self.page_config.content_height()
-    }
-
-    /// Performs the first pass: collects all paragraphs and calculates total height
-    fn first_pass_collect(&self, paragraphs: &[ParagraphLayout]) -> Vec<(usize, f32)> {
-        let mut paragraph_heights = Vec::with_capacity(paragraphs.len());
-
-        for (idx, para) in paragraphs.iter().enumerate() {
-            let height = self.calculate_paragraph_height(para);
-            paragraph_heights.push((idx, height));
-        }
-
-        paragraph_heights
-    }
-
     /// Calculates the height needed for a paragraph
     fn calculate_paragraph_height(&self, para: &ParagraphLayout) -> f32 {
         if para.lines.is_empty() {
@@ -332,374 +340,703 @@ impl PageLayout {
         para.total_height
     }
 
-    /// Performs the second pass: allocates paragraphs to pages
-    fn second_pass_layout(&mut self, paragraphs: &[ParagraphLayout], paragraph_heights: &[(usize, f32)]) -> Vec<Page> {
-        let mut pages = Vec::new();
-        let column_width = self.column_width();
-        let line_height = self.actual_line_height();
-        let available_height = self.available_content_height();
+    /// Height that must fit under paragraph `index` for it to stay on the
+    /// page of the next paragraphs it is kept with: theirs, up to the first
+    /// lines of the first one not kept with its next, or up to `end`
+    fn keep_height(&self, paragraphs: &[ParagraphLayout], index: usize, end: usize) -> f32 {
+        let mut height = 0.0;
+        for (offset, para) in paragraphs[index..end].iter().enumerate() {
+            if para.properties.keep_with_next && index + offset + 1 < end {
+                height += self.calculate_paragraph_height(para);
+                continue;
+            }
+            let first_lines = match self.config.enable_widow_orphan && para.properties.widow_control {
+                true => self.config.min_lines_orphan.max(1) as usize,
+                false => 1,
+            };
+            let lines: f32 = line_heights(para).iter().take(first_lines).sum();
+            return height + para.space_before() + lines;
+        }
+        height
+    }
 
-        let mut current_page = Page {
-            page_index: 0,
-            lines: Vec::new(),
-            content_bounds: Rect::new(
-                self.page_config.margin_left,
-                self.page_config.margin_top,
-                self.page_config.content_width(),
-                available_height,
-            ),
-            header_region: self.page_config.header_region(),
-            footer_region: self.page_config.footer_region(),
-            column: 0,
-            continued_on: None,
-            continued_from: None,
-        };
+    /// Main method: converts paragraph layouts to pages
+    pub fn layout_pages(&mut self, paragraphs: &[ParagraphLayout]) -> Vec<Page> {
+        self.layout_sections(paragraphs, &[]).pages
+    }
 
-        let mut current_y = 0.0f32;
-        let mut current_column = 0u32;
-        let mut current_x = 0.0f32;
+    /// Lays out `paragraphs` on pages with the geometry, columns and start
+    /// of each of `sections`, or all on pages of this layout's own geometry
+    /// when there are none
+    ///
+    /// Paragraphs break across columns and pages where they have to, moved
+    /// on as a whole or in part to honor page breaks before them, keeping
+    /// them with the next paragraph or their lines together, and widow and
+    /// orphan control. A paragraph kept with the next ones that together
+    /// could never fit on a page is broken as if it were not.
+    pub fn layout_sections(&mut self, paragraphs: &[ParagraphLayout], sections: &[Section]) -> PageModel {
+        self.paginate(paragraphs, sections, None, &mut |paginator, index, para, keep_height| {
+            paginator.place(index, para, keep_height)
+        })
+    }
 
-        for (orig_idx, para_height) in paragraph_heights {
-            let para = &paragraphs[*orig_idx];
+    /// Lays out `paragraphs` as `layout_sections` does, flowing their text
+    /// around `exclusions`; see [`crate::text_wrap`]
+    ///
+    /// A paragraph that may run beside a zone is broken into lines again with
+    /// `line_layout`, each as wide as the room beside the zones where it goes,
+    /// and replaced in `paragraphs`. Such paragraphs are placed a line at a
+    /// time, so they are neither kept together or with the next nor kept from
+    /// leaving widows and orphans.
+    pub fn layout_sections_around(
+        &mut self,
+        paragraphs: &mut [ParagraphLayout],
+        sections: &[Section],
+        exclusions: &Exclusions,
+        line_layout: &mut LineLayout,
+    ) -> PageModel {
+        if exclusions.is_empty() {
+            return self.layout_sections(paragraphs, sections);
+        }
+        let mut rebroken = Vec::new();
+        let model = self.paginate(paragraphs, sections, None, &mut |paginator, index, para, keep_height| {
+            match paginator.runs_beside(exclusions, para) {
+                true => rebroken.push((index, paginator.place_around(index, para, exclusions, line_layout))),
+                false => paginator.place(index, para, keep_height),
+            }
+        });
+        for (index, para) in rebroken {
+            paragraphs[index] = para;
+        }
+        model
+    }
 
-            // Check if paragraph fits on current page
-            if current_y + para_height > available_height || current_column >= self.config.columns {
-                // Start a new page
-                if !current_page.lines.is_empty() {
-                    pages.push(current_page);
-                }
+    /// Lays out `paragraphs` as `layout_sections` does, keeping the pages of
+    /// `previous` up to paragraph `from`
+    ///
+    /// `previous` must be this layout's model of the same paragraphs and
+    /// sections before `from`, and the paragraphs before `from` must not be
+    /// kept with `from`; pagination picks up where `previous` placed `from`.
+    /// Falls back to laying out every paragraph when `previous` does not
+    /// know where that was, e.g. after deserializing it.
+    pub fn relayout_sections(
+        &mut self,
+        paragraphs: &[ParagraphLayout],
+        sections: &[Section],
+        previous: &PageModel,
+        from: usize,
+    ) -> PageModel {
+        self.paginate(paragraphs, sections, Some((previous, from)), &mut |paginator, index, para, keep_height| {
+            paginator.place(index, para, keep_height)
+        })
+    }
 
-                current_page = Page {
-                    page_index: pages.len(),
-                    lines: Vec::new(),
-                    content_bounds: Rect::new(
-                        self.page_config.margin_left,
-                        self.page_config.margin_top,
-                        self.page_config.content_width(),
-                        available_height,
-                    ),
-                    header_region: self.page_config.header_region(),
-                    footer_region: self.page_config.footer_region(),
-                    column: 0,
-                    continued_on: None,
-                    continued_from: None,
+    /// Places each paragraph with `place`
+    fn paginate(
+        &mut self,
+        paragraphs: &[ParagraphLayout],
+        sections: &[Section],
+        resume: Option<(&PageModel, usize)>,
+        place: &mut dyn FnMut(&mut Paginator, usize, &ParagraphLayout, f32),
+    ) -> PageModel {
+        self.paragraph_count = paragraphs.len();
+        if paragraphs.is_empty() {
+            self.pages = Vec::new();
+            return PageModel::default();
+        }
+
+        let timer = metrics::Timer::start();
+        let plans = self.section_plans(sections, paragraphs.len());
+        let resumed = resume.and_then(|(previous, from)| Paginator::resume(&self.config, &plans, previous, from));
+        let (mut paginator, from) = match resumed {
+            Some((paginator, from)) => (paginator, from),
+            None => (Paginator::new(&self.config, &plans), 0),
+        };
+        let first_section = paginator.section;
+        for (section, plan) in plans.iter().enumerate().skip(first_section) {
+            if section > first_section || from == 0 {
+                paginator.start_section(section);
+            }
+            for index in plan.paragraphs.clone().filter(|&index| index >= from) {
+                let para = &paragraphs[index];
+                let keep_height = match para.properties.keep_with_next {
+                    true => self.keep_height(paragraphs, index, plan.paragraphs.end),
+                    false => 0.0,
                 };
-                current_y = 0.0;
-                current_column = 0;
-                current_x = 0.0;
+                place(&mut paginator, index, para, keep_height);
             }
-
-            // Layout paragraph lines
-            self.layout_paragraph_to_page(
-                para,
-                *orig_idx,
-                &mut current_page,
-                &mut current_y,
-                &mut current_column,
-                &mut current_x,
-                column_width,
-                line_height,
-            );
         }
+        let model = paginator.finish();
 
-        // Add the last page if it has content
-        if !current_page.lines.is_empty() {
-            pages.push(current_page);
+        self.pages = model.pages.clone();
+        if let Some(ms) = timer.elapsed_ms() {
+            metrics::counter(metrics::PAGES_LAID_OUT, model.pages.len() as u64);
+            metrics::histogram(metrics::LAYOUT_PAGE_MS, ms / model.pages.len().max(1) as f64);
         }
+        model
+    }
 
-        pages
+    /// The paragraphs, geometry and start of each section; the first starts
+    /// at the first paragraph and the last runs to the end whatever they say
+    fn section_plans(&self, sections: &[Section], paragraph_count: usize) -> Vec<SectionPlan> {
+        if sections.is_empty() {
+            return vec![SectionPlan {
+                paragraphs: 0..paragraph_count,
+                page_config: self.page_config.clone(),
+                columns: self.config.columns.max(1),
+                column_gap: self.config.column_gap,
+                start: SectionBreak::NextPage,
+            }];
+        }
+        let mut plans: Vec<SectionPlan> = Vec::with_capacity(sections.len());
+        for (index, section) in sections.iter().enumerate() {
+            let start = match index {
+                0 => 0,
+                _ => section.first_paragraph.clamp(plans[index - 1].paragraphs.start, paragraph_count),
+            };
+            if let Some(previous) = plans.last_mut() {
+                previous.paragraphs.end = start;
+            }
+            let layout = PageLayout::for_section(section);
+            plans.push(SectionPlan {
+                paragraphs: start..paragraph_count,
+                page_config: layout.page_config,
+                columns: layout.config.columns,
+                column_gap: layout.config.column_gap,
+                start: section.break_type(),
+            });
+        }
+        plans
     }
 
-    /// Layouts a single paragraph onto a page, handling column wrapping
-    fn layout_paragraph_to_page(
-        &self,
-        para: &ParagraphLayout,
-        para_index: usize,
-        page: &mut Page,
-        current_y: &mut f32,
-        current_column: &mut u32,
-        current_x: &mut f32,
-        column_width: f32,
-        line_height: f32,
-    ) {
-        let available_height = self.available_content_height();
-
-        for (line_idx, line_info) in para.lines.iter().enumerate() {
-            let line_height_actual = line_height;
-
-            // Check if line fits in current column
-            if *current_y + line_height_actual > available_height {
-                // Move to next column
-                *current_column += 1;
-                if *current_column >= self.config.columns {
-                    // Need new page - handled in caller
-                    break;
+    /// Gets the page number for a given character offset
+    pub fn get_page_for_offset(&self, offset: usize, paragraphs: &[ParagraphLayout]) -> Option<usize> {
+        let mut char_count = 0usize;
+
+        for (page_idx, page) in self.pages.iter().enumerate() {
+            for line in &page.lines {
+                let para = paragraphs.get(line.paragraph_index)?;
+                let line_text = para.text.get(line.start..line.end)?;
+
+                if char_count + line_text.len() > offset {
+                    return Some(page_idx);
                 }
-                *current_y = 0.0;
-                *current_x = if *current_column == 0 {
-                    0.0
-                } else {
-                    self.page_config.content_width() - (self.config.columns - *current_column) as f32 * (column_width + self.config.column_gap)
-                };
+
+                char_count += line_text.len();
             }
+        }
 
-            let rendered_line = RenderedLine {
-                line_index: page.lines.len(),
-                paragraph_index: para_index,
-                source_line_index: line_idx,
-                y: *current_y,
-                height: line_height_actual,
-                x: *current_x,
-                width: line_info.width.min(column_width),
-                start: line_info.start,
-                end: line_info.end,
-            };
+        None
+    }
 
-            page.lines.push(rendered_line);
-            *current_y += line_height_actual;
-        }
+    /// Gets the total number of pages
+    #[inline]
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
     }
+}
 
-    /// Applies widow/orphan control to adjust page breaks
-    fn apply_widow_orphan(&mut self, pages: &mut Vec<Page>) {
-        if !self.config.enable_widow_orphan || pages.len() < 2 {
-            return;
+/// How a section starts (w:sectPr/w:type)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SectionBreak {
+    /// On a new page
+    #[default]
+    NextPage,
+    /// On the same page, under the previous section
+    Continuous,
+    /// On the next even page, after a blank page if need be
+    EvenPage,
+    /// On the next odd page, after a blank page if need be
+    OddPage,
+    /// In the next column
+    NextColumn,
+}
+
+impl SectionBreak {
+    /// The break a w:type value names; unknown values start a new page, as in Word
+    pub fn from_ooxml(value: &str) -> Self {
+        match value {
+            "continuous" => SectionBreak::Continuous,
+            "evenPage" => SectionBreak::EvenPage,
+            "oddPage" => SectionBreak::OddPage,
+            "nextColumn" => SectionBreak::NextColumn,
+            _ => SectionBreak::NextPage,
         }
+    }
+}
 
-        let min_widow = self.config.min_lines_widow as usize;
-        let min_orphan = self.config.min_lines_orphan as usize;
-
-        // Process from first to last page
-        let mut i = 0usize;
-        while i + 1 < pages.len() {
-            // Use indices instead of borrowing twice
-            let last_para_idx = pages[i].lines.last().map(|l| l.paragraph_index);
-            let first_para_idx = pages[i + 1].lines.first().map(|l| l.paragraph_index);
-
-            if last_para_idx == first_para_idx && last_para_idx.is_some() {
-                let para_idx = last_para_idx.unwrap();
-
-                // Count lines of this paragraph on each page
-                let current_para_lines: usize = pages[i].lines
-                    .iter()
-                    .filter(|l| l.paragraph_index == para_idx)
-                    .count();
-
-                let next_para_lines: usize = pages[i + 1].lines
-                    .iter()
-                    .filter(|l| l.paragraph_index == para_idx)
-                    .count();
-
-                // Widow: paragraph's last line(s) on new page alone
-                if next_para_lines <= min_widow && i > 0 {
-                    // Move lines from current page to next page
-                    self.move_lines_to_next_page(pages, i, min_widow);
-                }
-                // Orphan: paragraph's first line(s) on current page alone
-                else if current_para_lines <= min_orphan && i + 1 < pages.len() - 1 {
-                    // Move lines from next page to current page
-                    self.move_lines_from_next_page(pages, i, min_orphan);
-                }
-            }
+/// The pages of a document, each drawn with the geometry of its section;
+/// what both the renderer and the PDF exporter draw
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PageModel {
+    pub pages: Vec<Page>,
+    /// Page geometry of each section, by [`Page::section`]
+    pub sections: Vec<PageConfig>,
+    /// Paginator state before each paragraph, and after the last
+    #[serde(skip)]
+    pub(crate) placements: Vec<Placement>,
+}
 
-            i += 1;
-        }
+impl PageModel {
+    /// Size and margins of `page`
+    pub fn page_config<'a>(&'a self, page: &Page) -> &'a PageConfig {
+        &self.sections[page.section]
     }
 
-    /// Moves lines to the next page to fix widow
-    fn move_lines_to_next_page(&mut self, pages: &mut Vec<Page>, page_idx: usize, _min_lines: usize) {
-        if page_idx + 1 >= pages.len() {
-            return;
-        }
+    /// Gets the total number of pages
+    #[inline]
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
 
-        let current_page_lines_count = pages[page_idx].lines.len();
-        let next_page_lines_count = pages[page_idx + 1].lines.len();
+    /// Index of the page the first line of paragraph `index` is on
+    pub fn page_of_paragraph(&self, index: usize) -> Option<usize> {
+        self.pages.iter().position(|page| page.lines.iter().any(|line| line.paragraph_index == index))
+    }
 
-        if current_page_lines_count == 0 || next_page_lines_count == 0 {
-            return;
-        }
+    /// Index of the page paragraph `index` was placed on, whether or not it
+    /// has lines there; None for a model without placements
+    pub fn page_placed_on(&self, index: usize) -> Option<usize> {
+        self.placements.get(index).map(|placement| placement.page)
+    }
+}
 
-        // Find the paragraph boundary in current page
-        if let Some(para_idx) = pages[page_idx].lines.last().map(|l| l.paragraph_index) {
-            // Count lines of this paragraph
-            let para_line_indices: Vec<usize> = pages[page_idx].lines
-                .iter()
-                .enumerate()
-                .filter(|(_, l)| l.paragraph_index == para_idx)
-                .map(|(idx, _)| idx)
-                .collect();
-
-            let para_line_count = para_line_indices.len();
-
-            if para_line_count > 2 {
-                // Move some lines to next page
-                let lines_to_move = para_line_count - 2; // Keep at least 2 lines
-                let move_indices: Vec<usize> = para_line_indices[..lines_to_move].to_vec();
-
-                // Calculate base Y for next page
-                let next_base_y = pages[page_idx + 1].lines
-                    .iter()
-                    .map(|l| l.y + l.height)
-                    .fold(0.0, f32::max);
-
-                // Move lines to next page
-                for (offset, &line_idx) in move_indices.iter().enumerate().rev() {
-                    let line = pages[page_idx].lines.remove(line_idx);
-                    let mut new_line = line;
-                    new_line.y = next_base_y + (offset as f32 * self.actual_line_height());
-                    pages[page_idx + 1].lines.insert(0, new_line);
-                }
+/// Where the paginator stood before it placed a paragraph, to pick up from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Placement {
+    /// Index of the page being filled; the pages before it are finished
+    page: usize,
+    /// Section whose geometry the page has
+    page_section: usize,
+    blank: bool,
+    continued_from: Option<usize>,
+    /// Section being laid out
+    section: usize,
+    columns: u32,
+    column_gap: f32,
+    column: u32,
+    region_top: f32,
+    y: f32,
+    bottom: f32,
+    column_used: bool,
+    forced: bool,
+}
 
-                // Update continuation markers
-                pages[page_idx].continued_on = Some(page_idx + 1);
-                pages[page_idx + 1].continued_from = Some(page_idx);
-            }
-        }
+/// Paragraphs of a section and how they are laid out
+#[derive(Debug, Clone)]
+struct SectionPlan {
+    paragraphs: Range<usize>,
+    page_config: PageConfig,
+    columns: u32,
+    column_gap: f32,
+    start: SectionBreak,
+}
+
+/// Height of each line of a paragraph; one line's worth for a paragraph without lines
+fn line_heights(para: &ParagraphLayout) -> Vec<f32> {
+    if para.lines.is_empty() {
+        return vec![para.actual_line_height];
     }
+    para.lines
+        .iter()
+        .map(|line| if line.line_height > 0.0 { line.line_height } else { para.actual_line_height })
+        .collect()
+}
 
-    /// Moves lines from next page to current page to fix orphan
-    fn move_lines_from_next_page(&mut self, pages: &mut Vec<Page>, page_idx: usize, _min_lines: usize) {
-        if page_idx + 1 >= pages.len() {
-            return;
-        }
+/// Pages filled so far and the place the next line goes
+struct Paginator<'a> {
+    config: &'a PaginationConfig,
+    plans: &'a [SectionPlan],
+    pages: Vec<Page>,
+    page: Page,
+    /// Columns of the section being laid out, which a continuous section
+    /// changes partway down the page
+    columns: u32,
+    column_gap: f32,
+    column: u32,
+    /// Top of the current columns: below the previous section on a continuous break
+    region_top: f32,
+    y: f32,
+    /// Lowest point of any column on the page
+    bottom: f32,
+    /// Whether a line is in the current column
+    column_used: bool,
+    /// Whether the column began with a break asked for, which keeps the
+    /// space before its first paragraph
+    forced: bool,
+    /// Section being laid out
+    section: usize,
+    placements: Vec<Placement>,
+}
 
-        if pages[page_idx].lines.is_empty() || pages[page_idx + 1].lines.is_empty() {
-            return;
+impl<'a> Paginator<'a> {
+    fn new(config: &'a PaginationConfig, plans: &'a [SectionPlan]) -> Self {
+        Paginator {
+            config,
+            plans,
+            pages: Vec::new(),
+            page: Self::empty_page(0, 0, &plans[0]),
+            columns: plans[0].columns,
+            column_gap: plans[0].column_gap,
+            column: 0,
+            region_top: 0.0,
+            y: 0.0,
+            bottom: 0.0,
+            column_used: false,
+            forced: true,
+            section: 0,
+            placements: Vec::new(),
         }
+    }
 
-        if let Some(para_idx) = pages[page_idx + 1].lines.first().map(|l| l.paragraph_index) {
-            let para_line_indices: Vec<usize> = pages[page_idx + 1].lines
-                .iter()
-                .enumerate()
-                .filter(|(_, l)| l.paragraph_index == para_idx)
-                .map(|(idx, _)| idx)
-                .collect();
-
-            let para_line_count = para_line_indices.len();
-
-            if para_line_count > 2 {
-                // Calculate new Y position for moved lines
-                let last_y = pages[page_idx].lines
-                    .iter()
-                    .map(|l| l.y + l.height)
-                    .fold(0.0, f32::max);
-
-                // Move some lines to current page
-                let lines_to_keep = para_line_count - 2; // Keep at least 2 on next page
-                let move_indices: Vec<usize> = para_line_indices[lines_to_keep..].to_vec();
-
-                for (offset, &line_idx) in move_indices.iter().enumerate() {
-                    let adjusted_idx = line_idx.saturating_sub(offset);
-                    if adjusted_idx < pages[page_idx + 1].lines.len() {
-                        let line = pages[page_idx + 1].lines.remove(adjusted_idx);
-                        let mut new_line = line;
-                        new_line.y = last_y + (offset as f32 * self.actual_line_height());
-                        pages[page_idx].lines.push(new_line);
-                    }
-                }
-
-                pages[page_idx].continued_on = Some(page_idx + 1);
-                pages[page_idx + 1].continued_from = Some(page_idx);
-            }
+    /// The paginator as it stood before placing paragraph `from` in
+    /// `previous`, with that paragraph and the ones after it taken off its
+    /// page; also returns `from`
+    fn resume(config: &'a PaginationConfig, plans: &'a [SectionPlan], previous: &PageModel, from: usize) -> Option<(Self, usize)> {
+        let state = *previous.placements.get(from)?;
+        if from == 0 || state.section >= plans.len() || state.page_section >= plans.len() {
+            return None;
         }
+        let pages = previous.pages.get(..state.page)?.to_vec();
+        let mut page = Self::empty_page(state.page, state.page_section, &plans[state.page_section]);
+        page.blank = state.blank;
+        page.continued_from = state.continued_from;
+        if let Some(old) = previous.pages.get(state.page) {
+            page.lines = old.lines.iter().filter(|line| line.paragraph_index < from).cloned().collect();
+        }
+        let paginator = Paginator {
+            config,
+            plans,
+            pages,
+            page,
+            columns: state.columns,
+            column_gap: state.column_gap,
+            column: state.column,
+            region_top: state.region_top,
+            y: state.y,
+            bottom: state.bottom,
+            column_used: state.column_used,
+            forced: state.forced,
+            section: state.section,
+            placements: previous.placements[..from].to_vec(),
+        };
+        Some((paginator, from))
     }
 
-    /// Main method: converts paragraph layouts to pages
-    pub fn layout_pages(&mut self, paragraphs: &[ParagraphLayout]) -> Vec<Page> {
-        self.paragraph_count = paragraphs.len();
+    fn placement(&self) -> Placement {
+        Placement {
+            page: self.page.page_index,
+            page_section: self.page.section,
+            blank: self.page.blank,
+            continued_from: self.page.continued_from,
+            section: self.section,
+            columns: self.columns,
+            column_gap: self.column_gap,
+            column: self.column,
+            region_top: self.region_top,
+            y: self.y,
+            bottom: self.bottom,
+            column_used: self.column_used,
+            forced: self.forced,
+        }
+    }
 
-        if paragraphs.is_empty() {
-            return Vec::new();
+    fn empty_page(page_index: usize, section: usize, plan: &SectionPlan) -> Page {
+        let config = &plan.page_config;
+        Page {
+            page_index,
+            lines: Vec::new(),
+            content_bounds: Rect::new(config.margin_left, config.margin_top, config.content_width(), config.content_height()),
+            header_region: config.header_region(),
+            footer_region: config.footer_region(),
+            column: plan.columns,
+            continued_on: None,
+            continued_from: None,
+            section,
+            blank: false,
         }
+    }
 
-        // First pass: collect paragraph heights
-        let paragraph_heights = self.first_pass_collect(paragraphs);
+    fn page_config(&self) -> &PageConfig {
+        &self.plans[self.page.section].page_config
+    }
 
-        // Second pass: layout paragraphs to pages
-        let mut pages = self.second_pass_layout(paragraphs, &paragraph_heights);
+    fn column_width(&self) -> f32 {
+        let gaps = self.column_gap * (self.columns - 1) as f32;
+        (self.page_config().content_width() - gaps) / self.columns as f32
+    }
 
-        // Third pass: apply widow/orphan control
-        self.apply_widow_orphan(&mut pages);
+    fn column_height(&self) -> f32 {
+        self.page_config().content_height() - self.region_top
+    }
 
-        // Apply column adjustments
-        self.apply_column_adjustments(&mut pages);
+    fn page_is_empty(&self) -> bool {
+        self.page.lines.is_empty() && self.bottom <= 0.0
+    }
 
-        self.pages = pages.clone();
-        pages
+    /// Finishes the page unless nothing is on it, and starts one of `section`
+    fn new_page(&mut self, section: usize) {
+        let plan = &self.plans[section];
+        if self.page_is_empty() && !self.page.blank {
+            self.page = Self::empty_page(self.page.page_index, section, plan);
+        } else {
+            let index = self.pages.len() + 1;
+            let page = std::mem::replace(&mut self.page, Self::empty_page(index, section, plan));
+            self.pages.push(page);
+        }
+        self.columns = plan.columns;
+        self.column_gap = plan.column_gap;
+        self.column = 0;
+        self.region_top = 0.0;
+        self.y = 0.0;
+        self.bottom = 0.0;
+        self.column_used = false;
+        self.forced = false;
     }
 
-    /// Adjusts line positions for multi-column layout
-    fn apply_column_adjustments(&self, pages: &mut Vec<Page>) {
-        let column_width = self.column_width();
-        let column_gap = self.config.column_gap;
-
-        for page in pages.iter_mut() {
-            // Group line indices by column
-            let mut column_line_indices: Vec<Vec<usize>> = vec![Vec::new(); self.config.columns as usize];
-
-            for (line_idx, line) in page.lines.iter().enumerate() {
-                // Determine which column this line belongs to based on X position
-                let col_idx = min(
-                    ((line.x + column_width / 2.0) / (column_width + column_gap)).floor() as usize,
-                    self.config.columns as usize - 1,
-                );
-                if col_idx < column_line_indices.len() {
-                    column_line_indices[col_idx].push(line_idx);
+    /// Moves on to the next column, or the first of a new page after the last
+    fn next_column(&mut self) {
+        if self.column + 1 < self.columns {
+            self.column += 1;
+            self.y = self.region_top;
+            self.column_used = false;
+            self.forced = false;
+        } else {
+            self.new_page(self.page.section);
+        }
+    }
+
+    fn start_section(&mut self, section: usize) {
+        let plan = &self.plans[section];
+        self.section = section;
+        if section == 0 {
+            return;
+        }
+        match plan.start {
+            SectionBreak::NextPage => self.new_page(section),
+            SectionBreak::Continuous => {
+                // The page keeps its geometry; the new columns start under the old ones
+                self.region_top = self.bottom;
+                self.y = self.bottom;
+                self.column = 0;
+                self.columns = plan.columns;
+                self.column_gap = plan.column_gap;
+                self.column_used = false;
+            }
+            SectionBreak::EvenPage | SectionBreak::OddPage => {
+                self.new_page(section);
+                let even = (self.page.page_index + 1).is_multiple_of(2);
+                if even != (plan.start == SectionBreak::EvenPage) {
+                    self.page.blank = true;
+                    self.new_page(section);
                 }
             }
+            SectionBreak::NextColumn => {
+                if self.column_used {
+                    self.next_column();
+                }
+                self.columns = plan.columns;
+                self.column_gap = plan.column_gap;
+            }
+        }
+        self.forced = true;
+    }
 
-            // Recalculate Y positions for each column independently
-            let available_height = self.available_content_height();
-            for (col_idx, line_indices) in column_line_indices.iter().enumerate() {
-                let mut y_offset = 0.0f32;
-                let base_x = if col_idx == 0 {
-                    0.0
-                } else {
-                    col_idx as f32 * (column_width + column_gap)
-                };
+    /// Lays out paragraph `index`, which needs `keep_height` more under it
+    /// to stay with the paragraphs it is kept with
+    fn place(&mut self, index: usize, para: &ParagraphLayout, keep_height: f32) {
+        self.placements.push(self.placement());
+        let properties = &para.properties;
+        if properties.page_break_before && !self.page_is_empty() {
+            self.new_page(self.page.section);
+            self.forced = true;
+        }
 
-                for &line_idx in line_indices {
-                    if line_idx < page.lines.len() {
-                        page.lines[line_idx].x = base_x;
-                        page.lines[line_idx].y = y_offset;
-                        y_offset += page.lines[line_idx].height;
-
-                        // Check if line exceeds page height
-                        if y_offset > available_height {
-                            // This shouldn't happen, but handle gracefully
-                            page.lines[line_idx].height = (available_height - y_offset + page.lines[line_idx].height).max(self.actual_line_height());
-                            y_offset = available_height;
-                        }
+        let heights = line_heights(para);
+        let whole: f32 = para.space_before() + heights.iter().sum::<f32>();
+        let room = |paginator: &Self| paginator.page_config().content_height() - paginator.y;
+        let moves_on = |needed: f32, paginator: &Self| {
+            paginator.column_used && needed > room(paginator) && needed <= paginator.column_height()
+        };
+        if (properties.keep_lines_together && moves_on(whole, self))
+            || (properties.keep_with_next && moves_on(whole + keep_height, self))
+        {
+            self.next_column();
+        }
+        if self.column_used || self.forced {
+            self.y += para.space_before();
+        }
+
+        let widow_control = self.config.enable_widow_orphan && properties.widow_control;
+        let min_orphan = self.config.min_lines_orphan.max(1) as usize;
+        let min_widow = self.config.min_lines_widow.max(1) as usize;
+        let count = heights.len();
+        let mut from = 0;
+        while from < count {
+            let mut fit = 0;
+            let mut height = 0.0;
+            for line_height in &heights[from..] {
+                if height + line_height > room(self) {
+                    break;
+                }
+                height += line_height;
+                fit += 1;
+            }
+            let rest = count - from;
+            let mut take = fit.min(rest);
+            if take < rest && widow_control {
+                if rest - take < min_widow {
+                    take = rest.saturating_sub(min_widow);
+                }
+                if from == 0 && take < min_orphan {
+                    take = 0;
+                }
+            }
+            if take == 0 && !self.column_used {
+                // Nothing would be gained by moving on: break where the lines run out
+                take = fit.clamp(1, rest);
+            }
+            self.push_lines(index, para, &heights, from..from + take);
+            from += take;
+            if from < count {
+                let page = self.page.page_index;
+                self.next_column();
+                if self.page.page_index != page && take > 0 {
+                    if let Some(previous) = self.pages.last_mut() {
+                        previous.continued_on = Some(self.page.page_index);
                     }
+                    self.page.continued_from = Some(page);
                 }
             }
         }
+        self.y += para.space_after();
+        self.bottom = self.bottom.max(self.y);
     }
 
-    /// Gets the page number for a given character offset
-    pub fn get_page_for_offset(&self, offset: usize, paragraphs: &[ParagraphLayout]) -> Option<usize> {
-        let mut char_count = 0usize;
+    /// Whether paragraph `para`, placed next, may run beside a zone of `exclusions`
+    fn runs_beside(&self, exclusions: &Exclusions, para: &ParagraphLayout) -> bool {
+        let pages = (para.total_height / self.column_height().max(1.0)).ceil() as usize + 1;
+        let top = self.page.content_bounds.y + self.y;
+        !para.text.is_empty() && exclusions.any_from(self.page.page_index, top, self.page.page_index + pages)
+    }
 
-        for (page_idx, page) in self.pages.iter().enumerate() {
-            for line in &page.lines {
-                let para = paragraphs.get(line.paragraph_index)?;
-                let line_text = para.text.get(line.start..line.end)?;
+    /// Lays out paragraph `index` as `place` does, breaking it into lines
+    /// again with `line_layout` one at a time, each as wide as `exclusions`
+    /// leave room for where it goes; returns the paragraph as broken
+    fn place_around(
+        &mut self,
+        index: usize,
+        para: &ParagraphLayout,
+        exclusions: &Exclusions,
+        line_layout: &mut LineLayout,
+    ) -> ParagraphLayout {
+        self.placements.push(self.placement());
+        if para.properties.page_break_before && !self.page_is_empty() {
+            self.new_page(self.page.section);
+            self.forced = true;
+        }
+        if self.column_used || self.forced {
+            self.y += para.space_before();
+        }
 
-                if char_count + line_text.len() > offset {
-                    return Some(page_idx);
+        let height = para.actual_line_height;
+        let indent = para.properties.indent_left * para.max_width / 1440.0;
+        // Page and position on it of each line, filled in once the lines are broken
+        let mut slots: Vec<(usize, usize)> = Vec::new();
+        let layout = line_layout.layout_paragraph_in_bands(&para.text, para.max_width, para.properties, |line_index| loop {
+            if self.y + height > self.page_config().content_height() && (self.column_used || self.y > self.region_top) {
+                let page = self.page.page_index;
+                self.next_column();
+                if self.page.page_index != page && !slots.is_empty() {
+                    if let Some(previous) = self.pages.last_mut() {
+                        previous.continued_on = Some(self.page.page_index);
+                    }
+                    self.page.continued_from = Some(page);
+                }
+                continue;
+            }
+            let bounds = self.page.content_bounds;
+            let x = self.column_x();
+            let left = bounds.x + x + indent;
+            match exclusions.line_space(self.page.page_index, bounds.y + self.y, height, left, left + para.content_width) {
+                LineSpace::Free { shift, width } => {
+                    slots.push((self.page.page_index, self.page.lines.len()));
+                    self.page.lines.push(RenderedLine {
+                        line_index: self.page.lines.len(),
+                        paragraph_index: index,
+                        source_line_index: line_index,
+                        y: self.y,
+                        height,
+                        x,
+                        width: 0.0,
+                        start: 0,
+                        end: 0,
+                    });
+                    self.y += height;
+                    self.column_used = true;
+                    self.bottom = self.bottom.max(self.y);
+                    break (shift, width);
                 }
+                LineSpace::Below(y) => self.y = y - bounds.y,
+            }
+        });
 
-                char_count += line_text.len();
+        let column_width = self.column_width();
+        for ((page, position), info) in slots.into_iter().zip(&layout.lines) {
+            let page = match page == self.page.page_index {
+                true => &mut self.page,
+                false => &mut self.pages[page],
+            };
+            if let Some(line) = page.lines.get_mut(position) {
+                line.width = info.width.min(column_width);
+                line.start = info.start;
+                line.end = info.end;
             }
         }
+        self.y += para.space_after();
+        self.bottom = self.bottom.max(self.y);
+        layout
+    }
 
-        None
+    /// Left of the current column from the left of the page's content
+    fn column_x(&self) -> f32 {
+        self.column as f32 * (self.column_width() + self.column_gap)
     }
 
-    /// Gets the total number of pages
-    #[inline]
-    pub fn page_count(&self) -> usize {
-        self.pages.len()
+    fn push_lines(&mut self, index: usize, para: &ParagraphLayout, heights: &[f32], lines: Range<usize>) {
+        let column_width = self.column_width();
+        let x = self.column_x();
+        for line_index in lines {
+            let height = heights[line_index];
+            if let Some(line) = para.lines.get(line_index) {
+                self.page.lines.push(RenderedLine {
+                    line_index: self.page.lines.len(),
+                    paragraph_index: index,
+                    source_line_index: line_index,
+                    y: self.y,
+                    height,
+                    x,
+                    width: line.width.min(column_width),
+                    start: line.start,
+                    end: line.end,
+                });
+            }
+            self.y += height;
+            self.column_used = true;
+        }
+        self.bottom = self.bottom.max(self.y);
+    }
+
+    fn finish(mut self) -> PageModel {
+        self.placements.push(self.placement());
+        if !self.page_is_empty() || self.page.blank {
+            self.pages.push(self.page);
+        }
+        PageModel {
+            pages: self.pages,
+            sections: self.plans.iter().map(|plan| plan.page_config.clone()).collect(),
+            placements: self.placements,
+        }
     }
 }
 
@@ -740,6 +1077,25 @@ mod tests {
     use super::*;
     use crate::line_layout::{LineLayoutInfo, LineLayout, ParagraphLayout, ParagraphProperties, LineSpacingRule, Alignment};
 
+    #[test]
+    fn test_page_layout_for_section() {
+        let section = Section {
+            page_width: Some(12240),
+            page_height: Some(15840),
+            margin_left: Some(1800),
+            margin_right: Some(1800),
+            columns: 2,
+            column_space: Some(360),
+            ..Default::default()
+        };
+
+        let layout = PageLayout::for_section(&section);
+        assert_eq!((layout.page_config.width, layout.page_config.height), (612.0, 792.0));
+        assert_eq!(layout.page_config.content_width(), 432.0);
+        assert_eq!(layout.config.columns, 2);
+        assert_eq!(layout.column_width(), (432.0 - 18.0) / 2.0);
+    }
+
     fn create_test_paragraphs() -> Vec<ParagraphLayout> {
         // Create test paragraphs without using LineLayout (to avoid HarfBuzz issues)
         vec![
@@ -1222,6 +1578,216 @@ mod tests {
         assert_eq!(config.height, 792.0);
     }
 
+    /// A page with room for ten 10pt lines
+    fn ten_line_page() -> PageLayout {
+        PageLayout::with_page_config(PageConfig {
+            width: 200.0,
+            height: 120.0,
+            margin_top: 10.0,
+            margin_bottom: 10.0,
+            margin_left: 10.0,
+            margin_right: 10.0,
+            header_height: 0.0,
+            footer_height: 0.0,
+        })
+    }
+
+    fn lines(count: usize, properties: ParagraphProperties) -> ParagraphLayout {
+        ParagraphLayout {
+            text: "x".repeat(count),
+            max_width: 180.0,
+            content_width: 180.0,
+            lines: (0..count)
+                .map(|i| LineLayoutInfo { line_number: i, start: i, end: i + 1, width: 100.0, break_type: "SoftBreak".to_string(), char_count: 1, is_bidi: false, trailing_whitespace: 0.0, offset_x: 0.0, line_height: 10.0 })
+                .collect(),
+            total_height: count as f32 * 10.0,
+            base_line_height: 10.0,
+            actual_line_height: 10.0,
+            has_bidi: false,
+            properties,
+        }
+    }
+
+    /// Lines of paragraph `index` on each page
+    fn lines_per_page(pages: &[Page], index: usize) -> Vec<usize> {
+        pages.iter().map(|page| page.lines.iter().filter(|line| line.paragraph_index == index).count()).collect()
+    }
+
+    #[test]
+    fn test_widows_and_orphans_move_lines() {
+        let plain = ParagraphProperties::default();
+        // One line would be left at the bottom: the paragraph moves on whole
+        let pages = ten_line_page().layout_pages(&[lines(9, plain), lines(3, plain)]);
+        assert_eq!(lines_per_page(&pages, 1), [0, 3]);
+        // One line would go on alone: another goes with it
+        let pages = ten_line_page().layout_pages(&[lines(7, plain), lines(4, plain)]);
+        assert_eq!(lines_per_page(&pages, 1), [2, 2]);
+        assert_eq!((pages[0].continued_on, pages[1].continued_from), (Some(1), Some(0)));
+        assert_eq!(pages[1].lines[0].y, 0.0);
+
+        let free = ParagraphProperties { widow_control: false, ..plain };
+        let pages = ten_line_page().layout_pages(&[lines(9, plain), lines(3, free)]);
+        assert_eq!(lines_per_page(&pages, 1), [1, 2]);
+        let mut layout = ten_line_page();
+        layout.set_widow_orphan(false);
+        assert_eq!(lines_per_page(&layout.layout_pages(&[lines(7, plain), lines(4, plain)]), 1), [3, 1]);
+    }
+
+    #[test]
+    fn test_keeps_and_page_break_before() {
+        let plain = ParagraphProperties::default();
+        // The heading fits, but not with the first two lines of what follows it
+        let heading = ParagraphProperties { keep_with_next: true, ..plain };
+        let pages = ten_line_page().layout_pages(&[lines(8, plain), lines(1, heading), lines(4, plain)]);
+        assert_eq!((lines_per_page(&pages, 1), lines_per_page(&pages, 2)), (vec![0, 1], vec![0, 4]));
+        // Kept with more than a page holds, it breaks as usual
+        let pages = ten_line_page().layout_pages(&[lines(8, plain), lines(6, heading), lines(4, heading), lines(4, plain)]);
+        assert_eq!(lines_per_page(&pages, 1), [2, 4, 0]);
+
+        let together = ParagraphProperties { keep_lines_together: true, ..plain };
+        let pages = ten_line_page().layout_pages(&[lines(6, plain), lines(5, together)]);
+        assert_eq!(lines_per_page(&pages, 1), [0, 5]);
+        let pages = ten_line_page().layout_pages(&[lines(6, plain), lines(14, together)]);
+        assert_eq!(lines_per_page(&pages, 1), [4, 10]);
+
+        let break_before = ParagraphProperties { page_break_before: true, ..plain };
+        let pages = ten_line_page().layout_pages(&[lines(2, break_before), lines(2, plain), lines(2, break_before)]);
+        assert_eq!((pages.len(), lines_per_page(&pages, 2)), (2, vec![0, 2]));
+    }
+
+    #[test]
+    fn test_section_breaks() {
+        let section = |first_paragraph: usize, kind: &str, columns: u32| Section {
+            first_paragraph,
+            paragraph_count: 1,
+            columns,
+            other_children: vec![format!(r#"<w:type w:val="{}"/>"#, kind)],
+            ..Default::default()
+        };
+        let sections = [
+            section(0, "nextPage", 1),
+            section(1, "continuous", 2),
+            section(2, "oddPage", 1),
+            section(3, "evenPage", 1),
+        ];
+        let plain = ParagraphProperties::default();
+        let paragraphs = [lines(2, plain), lines(2, plain), lines(1, plain), lines(1, plain)];
+        let model = PageLayout::new().layout_sections(&paragraphs, &sections);
+
+        // The continuous section goes on the first page, under the first, in two columns
+        assert_eq!(model.page_count(), 4);
+        let first = &model.pages[0];
+        assert_eq!(lines_per_page(&model.pages, 1), [2, 0, 0, 0]);
+        let continuous: Vec<&RenderedLine> = first.lines.iter().filter(|line| line.paragraph_index == 1).collect();
+        assert_eq!(continuous[0].y, 20.0);
+        assert!(continuous[0].width <= PageLayout::for_section(&sections[1]).column_width());
+        // Page 2 is left blank for the odd page section to start on page 3; page 4 is even
+        assert!(model.pages[1].blank && model.pages[1].lines.is_empty());
+        assert_eq!((model.page_of_paragraph(2), model.page_of_paragraph(3)), (Some(2), Some(3)));
+        assert_eq!((model.pages[2].section, model.pages[3].section), (2, 3));
+        let letter = PageLayout::for_section(&sections[2]).page_config;
+        assert_eq!(model.page_config(&model.pages[2]).height, letter.height);
+    }
+
+    #[test]
+    fn test_relayout_picks_up_where_paragraph_was_placed() {
+        // Pages of 200 by 120 points with 10 point margins
+        let section = |first_paragraph: usize, kind: &str, columns: u32| Section {
+            first_paragraph,
+            columns,
+            page_width: Some(4000),
+            page_height: Some(2400),
+            margin_top: Some(200),
+            margin_bottom: Some(200),
+            margin_left: Some(200),
+            margin_right: Some(200),
+            other_children: vec![format!(r#"<w:type w:val="{}"/>"#, kind)],
+            ..Default::default()
+        };
+        let sections = [section(0, "nextPage", 1), section(30, "continuous", 2), section(60, "oddPage", 1)];
+        let plain = ParagraphProperties::default();
+        let mut paragraphs: Vec<ParagraphLayout> = (0..90).map(|i| lines(1 + i % 5, plain)).collect();
+        let mut layout = ten_line_page();
+        let mut previous = layout.layout_sections(&paragraphs, &sections);
+        // Section, blank and continued-from of each page, with where its lines go
+        type PageSummary = (usize, bool, Option<usize>, Vec<(usize, usize, f32, f32)>);
+        let summary = |model: &PageModel| -> Vec<PageSummary> {
+            model
+                .pages
+                .iter()
+                .map(|page| {
+                    let lines = page.lines.iter().map(|l| (l.paragraph_index, l.source_line_index, l.x, l.y)).collect();
+                    (page.section, page.blank, page.continued_from, lines)
+                })
+                .collect()
+        };
+
+        // A paragraph in each section grows
+        for from in [12, 45, 75] {
+            paragraphs[from] = lines(9, plain);
+            let full = layout.layout_sections(&paragraphs, &sections);
+            let resumed = layout.relayout_sections(&paragraphs, &sections, &previous, from);
+            assert_eq!(summary(&resumed), summary(&full), "relayout from paragraph {}", from);
+            assert_eq!(resumed.placements, full.placements);
+            assert!(resumed.page_placed_on(from).unwrap() > 0);
+            previous = full;
+        }
+
+        // Without placements, as after deserializing, everything is laid out
+        let stripped = PageModel { placements: Vec::new(), ..previous.clone() };
+        let resumed = layout.relayout_sections(&paragraphs, &sections, &stripped, 45);
+        assert_eq!(summary(&resumed), summary(&layout.layout_sections(&paragraphs, &sections)));
+    }
+
+    #[test]
+    fn test_text_flows_around_exclusions() {
+        use crate::image::{Rect as ImageRect, WrapDistance, WrapPolygon};
+        use crate::text_shaping::TextShaper;
+        use crate::text_wrap::{ExclusionZone, WrapMode};
+
+        let mut line_layout = LineLayout::new();
+        line_layout.breaker_mut().set_shaper(TextShaper::without_font());
+        let text = "Many words in a row. ".repeat(80);
+        let plain = ParagraphProperties::default();
+        let mut paragraphs = vec![line_layout.layout_paragraph_with_props(&text, 468.0, plain)];
+        let unwrapped = paragraphs[0].lines.len();
+
+        // A square image at the top left of the content, and one wrapped top and bottom below it
+        let zone = |mode, y: f32, width: f32| ExclusionZone {
+            page_index: 0,
+            mode,
+            polygon: WrapPolygon::from_rect(ImageRect::new(72.0, y, width, 100.0), WrapDistance::default()),
+        };
+        let mut exclusions = Exclusions::new();
+        exclusions.push(zone(WrapMode::Around, 72.0, 200.0));
+        exclusions.push(zone(WrapMode::TopAndBottom, 250.0, 100.0));
+        let mut layout = PageLayout::with_page_config(PageConfig::letter());
+        let model = layout.layout_sections_around(&mut paragraphs, &[], &exclusions, &mut line_layout);
+
+        let para = &paragraphs[0];
+        assert!(para.lines.len() > unwrapped);
+        assert_eq!(para.lines.last().unwrap().end, text.len());
+        for line in &model.pages[0].lines {
+            let info = &para.lines[line.source_line_index];
+            let top = 72.0 + line.y;
+            // Lines beside the first image go right of it, shortened
+            if top < 172.0 {
+                assert_eq!(info.offset_x, 200.0);
+                assert!(info.width <= 268.0);
+            } else {
+                assert_eq!(info.offset_x, 0.0);
+            }
+            // None go in the band of the second
+            assert!(top + line.height <= 250.0 || top >= 350.0);
+            assert_eq!((line.start, line.end), (info.start, info.end));
+        }
+
+        // Without zones the paragraphs are laid out as ever
+        let mut plain_paragraphs = vec![line_layout.layout_paragraph_with_props(&text, 468.0, plain)];
+        layout.layout_sections_around(&mut plain_paragraphs, &[], &Exclusions::new(), &mut line_layout);
+        assert_eq!(plain_paragraphs[0].lines.len(), unwrapped);
+    }
+
     #[test]
     fn test_page_layout_info() {
         let page_layout = PageLayout::new();
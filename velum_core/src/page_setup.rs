@@ -0,0 +1,450 @@
+//! # Page Setup Module
+//!
+//! Editable page geometry per section:
+//! - Paper size presets and custom sizes
+//! - Portrait/landscape orientation
+//! - Margins validated against printer-safe minimums
+//! - Serialization to `w:pgSz` / `w:pgMar` in `w:sectPr`
+//!
+//! All values are in points, like `PageConfig`; OOXML stores twips (1pt = 20 twips).
+
+use serde::{Deserialize, Serialize};
+
+use crate::ooxml::Section;
+use crate::page_layout::PageConfig;
+
+/// Twips per point
+const TWIPS_PER_POINT: f32 = 20.0;
+
+/// Default printer-safe minimum margin (0.25 inch)
+pub const DEFAULT_MIN_MARGIN: f32 = 18.0;
+
+/// Smallest and largest page edge Word accepts (0.1 inch to 22 inches)
+const MIN_PAGE_EDGE: f32 = 7.2;
+const MAX_PAGE_EDGE: f32 = 1584.0;
+
+/// Smallest usable text area edge
+const MIN_CONTENT_EDGE: f32 = 36.0;
+
+/// Standard paper sizes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PaperSize {
+    A3,
+    A4,
+    A5,
+    B5,
+    Letter,
+    Legal,
+    Tabloid,
+    Executive,
+    /// Custom portrait size in points
+    Custom { width: f32, height: f32 },
+}
+
+impl PaperSize {
+    /// Portrait dimensions (width, height) in points
+    pub fn dimensions(&self) -> (f32, f32) {
+        match self {
+            PaperSize::A3 => (841.9, 1190.55),
+            PaperSize::A4 => (595.35, 841.89),
+            PaperSize::A5 => (419.55, 595.35),
+            PaperSize::B5 => (498.9, 708.65),
+            PaperSize::Letter => (612.0, 792.0),
+            PaperSize::Legal => (612.0, 1008.0),
+            PaperSize::Tabloid => (792.0, 1224.0),
+            PaperSize::Executive => (522.0, 756.0),
+            PaperSize::Custom { width, height } => (*width, *height),
+        }
+    }
+
+    /// Look up a preset by name (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "a3" => Some(PaperSize::A3),
+            "a4" => Some(PaperSize::A4),
+            "a5" => Some(PaperSize::A5),
+            "b5" => Some(PaperSize::B5),
+            "letter" => Some(PaperSize::Letter),
+            "legal" => Some(PaperSize::Legal),
+            "tabloid" | "ledger" => Some(PaperSize::Tabloid),
+            "executive" => Some(PaperSize::Executive),
+            _ => None,
+        }
+    }
+}
+
+/// Page orientation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    Landscape,
+}
+
+/// Page margins in points
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Margins {
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+    /// Distance from the top edge to the header
+    pub header: f32,
+    /// Distance from the bottom edge to the footer
+    pub footer: f32,
+    pub gutter: f32,
+}
+
+impl Default for Margins {
+    fn default() -> Self {
+        Margins {
+            top: 72.0,
+            bottom: 72.0,
+            left: 72.0,
+            right: 72.0,
+            header: 36.0,
+            footer: 36.0,
+            gutter: 0.0,
+        }
+    }
+}
+
+impl Margins {
+    /// Same margin on all four sides, default header/footer distances
+    pub fn uniform(margin: f32) -> Self {
+        Margins {
+            top: margin,
+            bottom: margin,
+            left: margin,
+            right: margin,
+            ..Default::default()
+        }
+    }
+}
+
+/// Page geometry of one section
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SectionPageSetup {
+    /// Page width in points (already swapped for landscape)
+    pub width: f32,
+    /// Page height in points (already swapped for landscape)
+    pub height: f32,
+    pub orientation: Orientation,
+    pub margins: Margins,
+}
+
+impl Default for SectionPageSetup {
+    fn default() -> Self {
+        let (width, height) = PaperSize::A4.dimensions();
+        SectionPageSetup {
+            width,
+            height,
+            orientation: Orientation::Portrait,
+            margins: Margins::default(),
+        }
+    }
+}
+
+impl SectionPageSetup {
+    /// Page configuration used by the paginator
+    pub fn page_config(&self) -> PageConfig {
+        PageConfig {
+            width: self.width,
+            height: self.height,
+            margin_top: self.margins.top,
+            margin_bottom: self.margins.bottom,
+            margin_left: self.margins.left + self.margins.gutter,
+            margin_right: self.margins.right,
+            header_height: 0.0,
+            footer_height: 0.0,
+        }
+    }
+
+    /// `section` with this page size, orientation and margins
+    pub fn apply_to(&self, section: Section) -> Section {
+        let twips = |points: f32| Some((points * TWIPS_PER_POINT).round() as i32);
+        Section {
+            page_width: twips(self.width),
+            page_height: twips(self.height),
+            landscape: self.orientation == Orientation::Landscape,
+            margin_top: twips(self.margins.top),
+            margin_right: twips(self.margins.right),
+            margin_bottom: twips(self.margins.bottom),
+            margin_left: twips(self.margins.left),
+            header_distance: twips(self.margins.header),
+            footer_distance: twips(self.margins.footer),
+            gutter: twips(self.margins.gutter),
+            ..section
+        }
+    }
+
+    /// Serialize the page size and margins as `w:sectPr` children
+    pub fn to_sect_pr_xml(&self) -> String {
+        let twips = |points: f32| (points * TWIPS_PER_POINT).round() as i32;
+        let mut xml = format!(r#"<w:pgSz w:w="{}" w:h="{}""#, twips(self.width), twips(self.height));
+        if self.orientation == Orientation::Landscape {
+            xml.push_str(r#" w:orient="landscape""#);
+        }
+        xml.push_str("/>");
+        xml.push_str(&format!(
+            r#"<w:pgMar w:top="{}" w:right="{}" w:bottom="{}" w:left="{}" w:header="{}" w:footer="{}" w:gutter="{}"/>"#,
+            twips(self.margins.top),
+            twips(self.margins.right),
+            twips(self.margins.bottom),
+            twips(self.margins.left),
+            twips(self.margins.header),
+            twips(self.margins.footer),
+            twips(self.margins.gutter),
+        ));
+        xml
+    }
+}
+
+/// Page setup validation errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum PageSetupError {
+    #[error("Section {0} does not exist")]
+    SectionOutOfRange(usize),
+
+    #[error("Invalid page size {0}x{1}pt")]
+    InvalidPageSize(f32, f32),
+
+    #[error("{side} margin {value}pt is below the printer-safe minimum of {minimum}pt")]
+    MarginTooSmall { side: String, value: f32, minimum: f32 },
+
+    #[error("Margins leave no room for text")]
+    ContentAreaTooSmall,
+}
+
+/// Page setup for all sections of a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageSetup {
+    pub sections: Vec<SectionPageSetup>,
+    /// Printer-safe minimum margin in points
+    pub min_margin: f32,
+    /// First section whose geometry changed since the last repagination
+    #[serde(skip)]
+    dirty_from: Option<usize>,
+}
+
+impl Default for PageSetup {
+    fn default() -> Self {
+        PageSetup {
+            sections: vec![SectionPageSetup::default()],
+            min_margin: DEFAULT_MIN_MARGIN,
+            dirty_from: None,
+        }
+    }
+}
+
+impl PageSetup {
+    /// Create a setup with a single default (A4) section
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The geometry of each of the `sections` read from a file; a single
+    /// default section when there are none
+    pub fn from_sections(sections: &[Section]) -> Self {
+        if sections.is_empty() {
+            return Self::default();
+        }
+        PageSetup {
+            sections: sections.iter().map(Section::page_setup).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Get a section's geometry
+    pub fn section(&self, section: usize) -> Option<&SectionPageSetup> {
+        self.sections.get(section)
+    }
+
+    /// Set the paper size, keeping the section's current orientation
+    pub fn set_page_size(&mut self, section: usize, size: PaperSize) -> Result<(), PageSetupError> {
+        let (width, height) = size.dimensions();
+        if !(MIN_PAGE_EDGE..=MAX_PAGE_EDGE).contains(&width) || !(MIN_PAGE_EDGE..=MAX_PAGE_EDGE).contains(&height) {
+            return Err(PageSetupError::InvalidPageSize(width, height));
+        }
+
+        let min_margin = self.min_margin;
+        let setup = self.section_mut(section)?;
+        let mut updated = *setup;
+        let (short, long) = (width.min(height), width.max(height));
+        (updated.width, updated.height) = match updated.orientation {
+            Orientation::Portrait => (short, long),
+            Orientation::Landscape => (long, short),
+        };
+        validate(&updated, min_margin)?;
+        *setup = updated;
+        self.mark_dirty(section);
+        Ok(())
+    }
+
+    /// Switch orientation, swapping width and height when it changes
+    pub fn set_orientation(&mut self, section: usize, orientation: Orientation) -> Result<(), PageSetupError> {
+        let min_margin = self.min_margin;
+        let setup = self.section_mut(section)?;
+        if setup.orientation == orientation {
+            return Ok(());
+        }
+        let mut updated = *setup;
+        updated.orientation = orientation;
+        std::mem::swap(&mut updated.width, &mut updated.height);
+        validate(&updated, min_margin)?;
+        *setup = updated;
+        self.mark_dirty(section);
+        Ok(())
+    }
+
+    /// Set the margins of a section
+    pub fn set_margins(&mut self, section: usize, margins: Margins) -> Result<(), PageSetupError> {
+        let min_margin = self.min_margin;
+        let setup = self.section_mut(section)?;
+        let mut updated = *setup;
+        updated.margins = margins;
+        validate(&updated, min_margin)?;
+        *setup = updated;
+        self.mark_dirty(section);
+        Ok(())
+    }
+
+    /// Take the first section that needs repagination, clearing the request
+    pub fn take_repagination(&mut self) -> Option<usize> {
+        self.dirty_from.take()
+    }
+
+    fn section_mut(&mut self, section: usize) -> Result<&mut SectionPageSetup, PageSetupError> {
+        self.sections
+            .get_mut(section)
+            .ok_or(PageSetupError::SectionOutOfRange(section))
+    }
+
+    fn mark_dirty(&mut self, section: usize) {
+        self.dirty_from = Some(self.dirty_from.map_or(section, |s| s.min(section)));
+    }
+}
+
+/// Check margins against the printer-safe minimum and the remaining text area
+fn validate(setup: &SectionPageSetup, min_margin: f32) -> Result<(), PageSetupError> {
+    let margins = &setup.margins;
+    for (side, value) in [
+        ("Top", margins.top),
+        ("Bottom", margins.bottom),
+        ("Left", margins.left),
+        ("Right", margins.right),
+    ] {
+        if value < min_margin {
+            return Err(PageSetupError::MarginTooSmall {
+                side: side.to_string(),
+                value,
+                minimum: min_margin,
+            });
+        }
+    }
+
+    let config = setup.page_config();
+    if config.content_width() < MIN_CONTENT_EDGE || config.content_height() < MIN_CONTENT_EDGE {
+        return Err(PageSetupError::ContentAreaTooSmall);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_a4_portrait() {
+        let setup = PageSetup::new();
+        let section = setup.section(0).unwrap();
+        assert_eq!(section.orientation, Orientation::Portrait);
+        assert!(section.height > section.width);
+        assert_eq!(section.page_config().content_width(), 595.35 - 144.0);
+    }
+
+    #[test]
+    fn test_set_page_size_preset() {
+        let mut setup = PageSetup::new();
+        setup.set_page_size(0, PaperSize::from_name("Letter").unwrap()).unwrap();
+        let section = setup.section(0).unwrap();
+        assert_eq!((section.width, section.height), (612.0, 792.0));
+        assert_eq!(setup.take_repagination(), Some(0));
+        assert_eq!(setup.take_repagination(), None);
+    }
+
+    #[test]
+    fn test_orientation_swaps_dimensions() {
+        let mut setup = PageSetup::new();
+        setup.set_orientation(0, Orientation::Landscape).unwrap();
+        let section = *setup.section(0).unwrap();
+        assert!(section.width > section.height);
+
+        // Changing paper size keeps landscape
+        setup.set_page_size(0, PaperSize::Letter).unwrap();
+        let section = setup.section(0).unwrap();
+        assert_eq!((section.width, section.height), (792.0, 612.0));
+    }
+
+    #[test]
+    fn test_margin_validation() {
+        let mut setup = PageSetup::new();
+        let result = setup.set_margins(0, Margins::uniform(10.0));
+        assert!(matches!(result, Err(PageSetupError::MarginTooSmall { .. })));
+        // Rejected changes leave the section untouched
+        assert_eq!(setup.section(0).unwrap().margins, Margins::default());
+        assert_eq!(setup.take_repagination(), None);
+
+        let result = setup.set_margins(0, Margins::uniform(290.0));
+        assert_eq!(result, Err(PageSetupError::ContentAreaTooSmall));
+
+        setup.set_margins(0, Margins::uniform(36.0)).unwrap();
+        assert_eq!(setup.section(0).unwrap().margins.left, 36.0);
+    }
+
+    #[test]
+    fn test_invalid_section_and_size() {
+        let mut setup = PageSetup::new();
+        assert_eq!(
+            setup.set_orientation(3, Orientation::Landscape),
+            Err(PageSetupError::SectionOutOfRange(3))
+        );
+        assert!(matches!(
+            setup.set_page_size(0, PaperSize::Custom { width: 0.0, height: 100.0 }),
+            Err(PageSetupError::InvalidPageSize(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_repagination_tracks_first_dirty_section() {
+        let mut setup = PageSetup::new();
+        setup.sections.push(SectionPageSetup::default());
+        setup.set_orientation(1, Orientation::Landscape).unwrap();
+        setup.set_margins(0, Margins::uniform(54.0)).unwrap();
+        assert_eq!(setup.take_repagination(), Some(0));
+    }
+
+    #[test]
+    fn test_sect_pr_xml() {
+        let mut setup = PageSetup::new();
+        setup.set_page_size(0, PaperSize::Letter).unwrap();
+        setup.set_orientation(0, Orientation::Landscape).unwrap();
+        let xml = setup.section(0).unwrap().to_sect_pr_xml();
+        assert!(xml.contains(r#"<w:pgSz w:w="15840" w:h="12240" w:orient="landscape"/>"#));
+        assert!(xml.contains(r#"w:top="1440""#));
+        assert!(xml.contains(r#"w:header="720""#));
+    }
+
+    #[test]
+    fn test_apply_to_keeps_the_section_columns() {
+        let mut setup = PageSetup::new();
+        setup.set_orientation(0, Orientation::Landscape).unwrap();
+        let section = setup.section(0).unwrap().apply_to(Section { columns: 2, ..Default::default() });
+        assert_eq!(section.columns, 2);
+        let (applied, original) = (section.page_setup(), setup.section(0).unwrap());
+        assert_eq!(applied.orientation, Orientation::Landscape);
+        assert!((applied.width - original.width).abs() < 0.05);
+        assert!((applied.height - original.height).abs() < 0.05);
+        assert_eq!(applied.margins, original.margins);
+    }
+}
@@ -0,0 +1,259 @@
+//! # Paragraph Edit Module
+//!
+//! Splitting a paragraph with Enter and joining two by deleting the mark
+//! between them, with the paragraph formatting Word gives the result:
+//!
+//! - A split paragraph's halves both keep its formatting, list membership
+//!   included, so a list goes on with a new item. Only at the end of a
+//!   paragraph whose style names a different next style, e.g. a heading
+//!   followed by body text, does the new paragraph start out in that style
+//!   with no direct formatting.
+//! - Enter in an empty list item does not split it: it moves up a level, or
+//!   out of the list from the top level.
+//! - A joined paragraph keeps the first paragraph's formatting, unless the
+//!   first was empty; then it keeps the second's, as its text is all there is.
+//!
+//! Each operation is one undo step of its own.
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::numbering::{ListEdit, ListNumbering};
+use crate::piece_tree::{ParagraphAttributes, PieceTree};
+use crate::style_sheet::{StyleKind, StyleSheet};
+
+/// What Enter did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitKind {
+    /// A paragraph break went in
+    Split,
+    /// An empty list item moved up a level
+    Outdented,
+    /// An empty top-level list item left its list
+    LeftList,
+}
+
+/// The outcome of Enter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ParagraphSplit {
+    pub kind: SplitKind,
+    /// Index of the paragraph the caret is in afterwards
+    pub paragraph: usize,
+    /// Char offset for the caret afterwards
+    pub caret: usize,
+}
+
+/// Chars of paragraph `index`, without its paragraph mark
+pub fn paragraph_range(tree: &PieceTree, index: usize) -> Range<usize> {
+    let start = tree.get_offset_at_line(index + 1);
+    let end = match index + 1 < tree.paragraph_count() {
+        true => tree.get_offset_at_line(index + 2) - 1,
+        false => tree.total_char_count,
+    };
+    start..end
+}
+
+/// Press Enter at char `position`
+pub fn split_paragraph(
+    tree: &mut PieceTree,
+    styles: &StyleSheet,
+    numbering: &mut ListNumbering,
+    position: usize,
+) -> ParagraphSplit {
+    let position = position.min(tree.total_char_count);
+    let index = tree.paragraph_index_at(position);
+    let range = paragraph_range(tree, index);
+    let direct = tree.paragraph_attributes(index).cloned();
+    let effective = styles.effective_paragraph(direct.as_ref());
+
+    if range.is_empty() {
+        if let Some(kind) = effective.num_id.as_deref().and_then(|num_id| numbering.kind(num_id)) {
+            let (edit, kind) = match effective.list_level.unwrap_or(0) {
+                0 => (ListEdit::Toggle(kind), SplitKind::LeftList),
+                _ => (ListEdit::Outdent, SplitKind::Outdented),
+            };
+            let paragraphs: Vec<_> = (0..tree.paragraph_count()).map(|i| tree.paragraph_attributes(i).cloned()).collect();
+            if let Ok(after) = numbering.edit_paragraphs(edit, &paragraphs, index..index + 1, styles) {
+                tree.break_undo_coalescing();
+                tree.set_paragraph_attributes(range.clone(), after[index].as_ref());
+                tree.break_undo_coalescing();
+                return ParagraphSplit {
+                    kind,
+                    paragraph: index,
+                    caret: position,
+                };
+            }
+        }
+    }
+
+    // Only the end of a paragraph starts one in the style's next style
+    let style = direct
+        .as_ref()
+        .and_then(|direct| styles.get(direct.style_id.as_deref()?))
+        .or_else(|| styles.default_style(StyleKind::Paragraph));
+    let next_style = style
+        .filter(|_| position == range.end)
+        .and_then(|style| style.next.clone().filter(|next| *next != style.id && styles.get(next).is_some()));
+
+    tree.break_undo_coalescing();
+    tree.transaction(|tree| {
+        tree.insert(position, "\n".to_string());
+        if let Some(next) = next_style {
+            let attributes = ParagraphAttributes {
+                style_id: Some(next),
+                ..Default::default()
+            };
+            tree.set_paragraph_attributes(position + 1..position + 1, Some(&attributes));
+        }
+    });
+    tree.break_undo_coalescing();
+    ParagraphSplit {
+        kind: SplitKind::Split,
+        paragraph: index + 1,
+        caret: position + 1,
+    }
+}
+
+/// Join paragraph `index` with the one after it, deleting the paragraph mark
+/// between them with `delete`, which may only mark it deleted
+///
+/// Returns None if there is no paragraph after it.
+pub fn merge_with_next<R>(
+    tree: &mut PieceTree,
+    index: usize,
+    delete: impl FnOnce(&mut PieceTree, Range<usize>) -> R,
+) -> Option<R> {
+    if index + 1 >= tree.paragraph_count() {
+        return None;
+    }
+    let range = paragraph_range(tree, index);
+    let mark = range.end..range.end + 1;
+    let first = tree.paragraph_attributes(index).cloned();
+    let second = tree.paragraph_attributes(index + 1).cloned();
+    if !range.is_empty() || first == second {
+        return Some(delete(tree, mark));
+    }
+
+    let count = tree.paragraph_count();
+    tree.break_undo_coalescing();
+    let result = tree.transaction(|tree| {
+        tree.set_paragraph_attributes(range.clone(), second.as_ref());
+        let result = delete(tree, mark);
+        // A tracked deletion may only mark the paragraph mark, leaving the paragraphs apart
+        if tree.paragraph_count() == count {
+            tree.set_paragraph_attributes(range.clone(), first.as_ref());
+        }
+        result
+    });
+    tree.break_undo_coalescing();
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_layout::Alignment;
+    use crate::numbering::ListKind;
+    use crate::style_sheet::NamedStyle;
+
+    fn styles() -> StyleSheet {
+        let mut styles = StyleSheet::new();
+        let mut normal = NamedStyle::new("Normal", StyleKind::Paragraph);
+        normal.is_default = true;
+        styles.add(normal);
+        let mut heading = NamedStyle::new("Heading1", StyleKind::Paragraph);
+        heading.next = Some("Normal".to_string());
+        styles.add(heading);
+        styles
+    }
+
+    fn attributes(style: Option<&str>, alignment: Option<Alignment>) -> Option<ParagraphAttributes> {
+        Some(ParagraphAttributes {
+            style_id: style.map(str::to_string),
+            alignment,
+            ..Default::default()
+        })
+    }
+
+    fn delete(tree: &mut PieceTree, range: Range<usize>) -> bool {
+        let start = tree.char_to_byte_offset(range.start);
+        tree.delete(start, tree.char_to_byte_offset(range.end) - start)
+    }
+
+    #[test]
+    fn test_split_keeps_formatting_except_after_next_style() {
+        let styles = styles();
+        let mut numbering = ListNumbering::new();
+        let mut tree = PieceTree::new("Title\nBody".to_string());
+        let heading = attributes(Some("Heading1"), Some(Alignment::Center));
+        tree.load_paragraph_attributes(vec![heading.clone(), None]);
+
+        // Mid-paragraph both halves are headings
+        let split = split_paragraph(&mut tree, &styles, &mut numbering, 2);
+        assert_eq!((split.kind, split.paragraph, split.caret), (SplitKind::Split, 1, 3));
+        assert_eq!(tree.get_text(), "Ti\ntle\nBody");
+        assert_eq!(tree.paragraph_attributes_in(0..tree.total_char_count), [heading.clone(), heading.clone(), None]);
+
+        // At the end, the next paragraph is in the heading's next style
+        split_paragraph(&mut tree, &styles, &mut numbering, 6);
+        assert_eq!(tree.paragraph_attributes(2).cloned(), attributes(Some("Normal"), None));
+        assert_eq!(tree.paragraph_attributes(1).cloned(), heading);
+
+        // Each split is one undo step
+        assert!(tree.undo());
+        assert_eq!(tree.get_text(), "Ti\ntle\nBody");
+        assert_eq!(tree.paragraph_count(), 3);
+    }
+
+    #[test]
+    fn test_enter_in_empty_list_item_leaves_the_list() {
+        let styles = styles();
+        let mut numbering = ListNumbering::new();
+        let num_id = numbering.add_list(ListKind::Bullet);
+        let item = |level| {
+            Some(ParagraphAttributes {
+                num_id: Some(num_id.clone()),
+                list_level: Some(level),
+                ..Default::default()
+            })
+        };
+        let mut tree = PieceTree::new("One\n".to_string());
+        tree.load_paragraph_attributes(vec![item(0), item(1)]);
+
+        // A new item continues the list
+        let split = split_paragraph(&mut tree, &styles, &mut numbering, 3);
+        assert_eq!(split.kind, SplitKind::Split);
+        assert_eq!(tree.paragraph_attributes(1).cloned(), item(0));
+
+        // Enter in an empty item moves it up a level, then out of the list
+        let split = split_paragraph(&mut tree, &styles, &mut numbering, 5);
+        assert_eq!((split.kind, split.paragraph, split.caret), (SplitKind::Outdented, 2, 5));
+        assert_eq!(tree.paragraph_attributes(2).cloned(), item(0));
+        assert_eq!(split_paragraph(&mut tree, &styles, &mut numbering, 5).kind, SplitKind::LeftList);
+        assert_eq!(tree.paragraph_attributes(2), None);
+        assert_eq!(tree.get_text(), "One\n\n");
+    }
+
+    #[test]
+    fn test_merge_keeps_first_formatting_unless_empty() {
+        let left = attributes(None, Some(Alignment::Left));
+        let right = attributes(None, Some(Alignment::Right));
+        let mut tree = PieceTree::new("A\nB\n\nC".to_string());
+        tree.load_paragraph_attributes(vec![left.clone(), right.clone(), left.clone(), right.clone()]);
+
+        assert_eq!(merge_with_next(&mut tree, 0, delete), Some(true));
+        assert_eq!(tree.get_text(), "AB\n\nC");
+        assert_eq!(tree.paragraph_attributes_in(0..tree.total_char_count), [left.clone(), left.clone(), right.clone()]);
+
+        // An empty first paragraph gives way to the second, in one undo step
+        assert_eq!(merge_with_next(&mut tree, 1, delete), Some(true));
+        assert_eq!(tree.get_text(), "AB\nC");
+        assert_eq!(tree.paragraph_attributes(1).cloned(), right);
+        assert!(tree.undo());
+        assert_eq!(tree.paragraph_attributes_in(0..tree.total_char_count), [left.clone(), left, right]);
+
+        assert_eq!(merge_with_next(&mut tree, 2, delete), None);
+    }
+}
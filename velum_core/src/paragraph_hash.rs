@@ -0,0 +1,389 @@
+//! # Paragraph Hash Module
+//!
+//! Stable per-paragraph content hashes over text and resolved formatting.
+//!
+//! Hashes use 64-bit FNV-1a over a canonical encoding, so they are identical
+//! across runs, platforms and crate versions, and do not depend on how the
+//! piece tree happens to split a paragraph into pieces. Downstream caches
+//! (layout, spellcheck, thumbnails) and delta sync can key off them.
+
+use serde::{Deserialize, Serialize};
+
+use crate::piece_tree::{ParagraphAttributes, PieceTree, TextAttributes};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Hash and position of one paragraph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParagraphHash {
+    /// Char offset of the paragraph start
+    pub start: usize,
+    /// Length in chars, excluding the paragraph break
+    pub length: usize,
+    pub hash: u64,
+}
+
+/// Per-paragraph hashes of a piece tree, kept up to date edit by edit
+#[derive(Debug, Clone, Default)]
+pub struct ParagraphHashes {
+    entries: Vec<ParagraphHash>,
+    /// False until built, and after an edit that was not reported
+    valid: bool,
+}
+
+impl ParagraphHashes {
+    /// Hash every paragraph of the tree
+    pub fn build(tree: &PieceTree) -> Self {
+        let mut hashes = ParagraphHashes::default();
+        hashes.rebuild(tree);
+        hashes
+    }
+
+    /// Recompute all hashes from scratch
+    pub fn rebuild(&mut self, tree: &PieceTree) {
+        self.entries = hash_range(tree, 0, tree.total_char_count);
+        self.valid = true;
+    }
+
+    /// Mark the hashes stale after an edit whose range is unknown
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+
+    /// Check whether the hashes reflect the tree
+    pub fn is_valid(&self) -> bool {
+        self.valid
+    }
+
+    /// Rebuild only if stale
+    pub fn ensure_valid(&mut self, tree: &PieceTree) {
+        if !self.valid {
+            self.rebuild(tree);
+        }
+    }
+
+    /// Update after `removed` chars at `offset` were replaced by `inserted` chars
+    ///
+    /// `tree` must already contain the edit. Only the paragraphs touched by the
+    /// edit are rehashed; later paragraphs are shifted.
+    pub fn apply_edit(&mut self, tree: &PieceTree, offset: usize, removed: usize, inserted: usize) {
+        if !self.valid || self.entries.is_empty() {
+            self.rebuild(tree);
+            return;
+        }
+
+        let old_end = offset + removed;
+        let first = self.paragraph_index_at(offset);
+        let last = self.paragraph_index_at(old_end);
+
+        let region_start = self.entries[first].start;
+        let old_region_end = self.entries[last].start + self.entries[last].length;
+        let new_region_end = (old_region_end + inserted).saturating_sub(removed);
+
+        let replacement = hash_range(tree, region_start, new_region_end);
+        let delta = inserted as isize - removed as isize;
+        for entry in &mut self.entries[last + 1..] {
+            entry.start = (entry.start as isize + delta) as usize;
+        }
+        self.entries.splice(first..=last, replacement);
+    }
+
+    /// Paragraph hashes in document order
+    pub fn entries(&self) -> &[ParagraphHash] {
+        &self.entries
+    }
+
+    /// Hash values in document order
+    pub fn hashes(&self) -> Vec<u64> {
+        self.entries.iter().map(|e| e.hash).collect()
+    }
+
+    /// Indices of paragraphs whose hash does not appear in `previous`
+    ///
+    /// A sync peer holding `previous` only needs these paragraphs; the others
+    /// can be matched by hash.
+    pub fn changed_since(&self, previous: &[u64]) -> Vec<usize> {
+        let known: std::collections::HashSet<u64> = previous.iter().copied().collect();
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| !known.contains(&e.hash))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Index of the paragraph containing a char offset (a break belongs to the paragraph it ends)
+    fn paragraph_index_at(&self, offset: usize) -> usize {
+        self.entries
+            .partition_point(|e| e.start + e.length < offset)
+            .min(self.entries.len() - 1)
+    }
+}
+
+/// Hash the paragraphs covering chars [start, end), where start is a paragraph start
+fn hash_range(tree: &PieceTree, start: usize, end: usize) -> Vec<ParagraphHash> {
+    let mut entries = Vec::new();
+    let mut hasher = ParagraphHasher::new();
+    let mut paragraph = tree.paragraph_index_at(start);
+    let mut paragraph_start = start;
+    let mut position = start;
+
+    for (text, attributes) in styled_chars(tree, start, end) {
+        for ch in text.chars() {
+            if ch == '\n' {
+                entries.push(ParagraphHash {
+                    start: paragraph_start,
+                    length: position - paragraph_start,
+                    hash: hasher.finish(tree.paragraph_attributes(paragraph)),
+                });
+                hasher = ParagraphHasher::new();
+                paragraph += 1;
+                paragraph_start = position + 1;
+            } else {
+                hasher.push(ch, attributes);
+            }
+            position += 1;
+        }
+    }
+
+    entries.push(ParagraphHash {
+        start: paragraph_start,
+        length: position - paragraph_start,
+        hash: hasher.finish(tree.paragraph_attributes(paragraph)),
+    });
+    entries
+}
+
+/// Text slices of chars [start, end) with the attributes of their pieces
+fn styled_chars(tree: &PieceTree, start: usize, end: usize) -> Vec<(&str, Option<&TextAttributes>)> {
+    let mut slices = Vec::new();
+    let mut piece_start = 0usize;
+
+    for piece in &tree.pieces {
+        let piece_end = piece_start + piece.piece_char_length;
+        if piece_end <= start {
+            piece_start = piece_end;
+            continue;
+        }
+        if piece_start >= end {
+            break;
+        }
+
+        let buffer = tree
+            .buffers
+            .get(PieceTree::buffer_idx(&piece.buffer_id))
+            .and_then(|b| b.get(piece.start..piece.start + piece.length))
+            .unwrap_or("");
+        let skip = start.saturating_sub(piece_start);
+        let take = end.min(piece_end) - piece_start.max(start);
+        let byte_start = buffer.char_indices().nth(skip).map_or(buffer.len(), |(i, _)| i);
+        let byte_end = buffer[byte_start..]
+            .char_indices()
+            .nth(take)
+            .map_or(buffer.len(), |(i, _)| byte_start + i);
+        slices.push((&buffer[byte_start..byte_end], piece.attributes.as_ref()));
+
+        piece_start = piece_end;
+    }
+    slices
+}
+
+/// FNV-1a over chars, the formatting runs they belong to and the paragraph formatting
+struct ParagraphHasher {
+    state: u64,
+    current: Option<TextAttributes>,
+}
+
+impl ParagraphHasher {
+    fn new() -> Self {
+        ParagraphHasher {
+            state: FNV_OFFSET,
+            current: None,
+        }
+    }
+
+    fn push(&mut self, ch: char, attributes: Option<&TextAttributes>) {
+        // Missing attributes resolve to the defaults; only run changes are hashed,
+        // so splitting a run across pieces does not change the hash
+        let default = TextAttributes::default();
+        let attributes = attributes.unwrap_or(&default);
+        if self.current.as_ref() != Some(attributes) {
+            self.write(&[0xFF]);
+            self.write_attributes(attributes);
+            self.current = Some(attributes.clone());
+        }
+        let mut buffer = [0u8; 4];
+        self.write(ch.encode_utf8(&mut buffer).as_bytes());
+    }
+
+    fn write_attributes(&mut self, attributes: &TextAttributes) {
+        let flag = |value: Option<bool>| match value {
+            None => 0u8,
+            Some(false) => 1,
+            Some(true) => 2,
+        };
+        self.write(&[
+            flag(attributes.bold),
+            flag(attributes.italic),
+            flag(attributes.underline),
+        ]);
+        self.write(&attributes.font_size.map_or(0, |size| size as u32 + 1).to_le_bytes());
+        for value in [&attributes.font_family, &attributes.foreground, &attributes.background] {
+            match value {
+                Some(text) => {
+                    self.write(&(text.len() as u32 + 1).to_le_bytes());
+                    self.write(text.as_bytes());
+                }
+                None => self.write(&0u32.to_le_bytes()),
+            }
+        }
+    }
+
+    fn write_paragraph_attributes(&mut self, attributes: &ParagraphAttributes) {
+        self.write(&[0xFE, attributes.alignment.map_or(0, |alignment| alignment as u8 + 1)]);
+        for value in [
+            attributes.indent_left,
+            attributes.indent_right,
+            attributes.indent_first_line,
+            attributes.space_before,
+            attributes.space_after,
+        ] {
+            self.write_optional(value.map(i32::to_le_bytes).as_ref().map(|bytes| &bytes[..]));
+        }
+        let line_spacing = attributes.line_spacing.map(|spacing| spacing.to_bits().to_le_bytes());
+        self.write_optional(line_spacing.as_ref().map(|bytes| &bytes[..]));
+        self.write_optional(attributes.style_id.as_deref().map(str::as_bytes));
+        self.write_optional(attributes.num_id.as_deref().map(str::as_bytes));
+        self.write(&[attributes.list_level.map_or(0, |level| level.saturating_add(1))]);
+        let border = attributes.border_bottom.as_ref().map(|border| {
+            let mut bytes = border.style.as_bytes().to_vec();
+            bytes.extend_from_slice(&border.size.to_le_bytes());
+            bytes.extend_from_slice(&border.space.to_le_bytes());
+            bytes
+        });
+        self.write_optional(border.as_deref());
+    }
+
+    /// Presence flag and length, then the bytes, so neighbouring values cannot run together
+    fn write_optional(&mut self, value: Option<&[u8]>) {
+        match value {
+            Some(bytes) => {
+                self.write(&(bytes.len() as u32 + 1).to_le_bytes());
+                self.write(bytes);
+            }
+            None => self.write(&0u32.to_le_bytes()),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= *byte as u64;
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Hash of the paragraph; unformatted paragraphs hash their runs only
+    fn finish(mut self, paragraph: Option<&ParagraphAttributes>) -> u64 {
+        if let Some(attributes) = paragraph {
+            self.write_paragraph_attributes(attributes);
+        }
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bold() -> Option<TextAttributes> {
+        Some(TextAttributes {
+            bold: Some(true),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_paragraph_boundaries() {
+        let tree = PieceTree::new("One\nTwo\n\nFour".to_string());
+        let hashes = ParagraphHashes::build(&tree);
+        let entries = hashes.entries();
+        assert_eq!(entries.len(), 4);
+        assert_eq!((entries[1].start, entries[1].length), (4, 3));
+        assert_eq!((entries[2].start, entries[2].length), (8, 0));
+        assert_eq!(entries[3].start, 9);
+    }
+
+    #[test]
+    fn test_hash_is_stable_and_content_based() {
+        let a = ParagraphHashes::build(&PieceTree::new("Same\nOther\nSame".to_string()));
+        let hashes = a.hashes();
+        assert_eq!(hashes[0], hashes[2]);
+        assert_ne!(hashes[0], hashes[1]);
+        // Fixed algorithm: the value never changes between builds or versions
+        assert_eq!(ParagraphHashes::build(&PieceTree::new(String::new())).hashes(), vec![FNV_OFFSET]);
+    }
+
+    #[test]
+    fn test_formatting_changes_hash() {
+        let mut plain = PieceTree::empty();
+        plain.insert(0, "Hello".to_string());
+        let mut styled = PieceTree::empty();
+        styled.insert_with_attrs(0, "Hello".to_string(), bold());
+        assert_ne!(
+            ParagraphHashes::build(&plain).hashes(),
+            ParagraphHashes::build(&styled).hashes()
+        );
+    }
+
+    #[test]
+    fn test_piece_splits_do_not_change_hash() {
+        let whole = PieceTree::new("Hello world".to_string());
+        let mut split = PieceTree::empty();
+        split.insert(0, "world".to_string());
+        split.insert(0, "Hello ".to_string());
+        assert_eq!(split.get_text(), "Hello world");
+        assert_eq!(
+            ParagraphHashes::build(&whole).hashes(),
+            ParagraphHashes::build(&split).hashes()
+        );
+    }
+
+    #[test]
+    fn test_incremental_matches_rebuild() {
+        let mut tree = PieceTree::new("Alpha\nBeta\nGamma".to_string());
+        let mut hashes = ParagraphHashes::build(&tree);
+        let before = hashes.hashes();
+
+        // Edit inside the second paragraph
+        tree.insert(8, "XX".to_string());
+        hashes.apply_edit(&tree, 8, 0, 2);
+        assert_eq!(hashes.entries(), ParagraphHashes::build(&tree).entries());
+        assert_eq!(hashes.hashes()[0], before[0]);
+        assert_eq!(hashes.hashes()[2], before[2]);
+        assert_eq!(hashes.changed_since(&before), vec![1]);
+
+        // Split a paragraph
+        tree.insert(2, "\n".to_string());
+        hashes.apply_edit(&tree, 2, 0, 1);
+        assert_eq!(hashes.entries(), ParagraphHashes::build(&tree).entries());
+        assert_eq!(hashes.entries().len(), 4);
+
+        // Join paragraphs by deleting a break
+        tree.delete(2, 1);
+        hashes.apply_edit(&tree, 2, 1, 0);
+        assert_eq!(hashes.entries(), ParagraphHashes::build(&tree).entries());
+        assert_eq!(hashes.entries().len(), 3);
+    }
+
+    #[test]
+    fn test_invalidate_rebuilds_on_demand() {
+        let mut tree = PieceTree::new("One\nTwo".to_string());
+        let mut hashes = ParagraphHashes::build(&tree);
+        tree.insert(0, "Zero\n".to_string());
+        hashes.invalidate();
+        assert!(!hashes.is_valid());
+        hashes.ensure_valid(&tree);
+        assert_eq!(hashes.entries().len(), 3);
+    }
+}
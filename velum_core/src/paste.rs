@@ -0,0 +1,394 @@
+//! # Paste Module
+//!
+//! Structure-aware paste of document fragments.
+//!
+//! Pasting list items into a list, or rows into a table, should extend the
+//! structure at the destination instead of leaving a second one beside or
+//! inside it. Under [`PastePolicy::Merge`]:
+//!
+//! - Pasted list items join the destination list, so its numbering continues
+//!   through them. Their levels move so the outermost pasted level lands on
+//!   the destination paragraph's level, keeping the nesting below it.
+//! - A pasted table following a table becomes rows of that table. Rows are
+//!   padded with empty cells to its column count; cells beyond it are folded
+//!   into the last cell so no text is lost. Cell widths follow the destination.
+//!
+//! The piece tree holds running text only, so pasting into it turns table rows
+//! into paragraphs with tab-separated cells.
+
+use serde::{Deserialize, Serialize};
+
+use crate::document_model::{paragraph_attributes, paragraph_properties, text_attributes, Block, DocumentModel};
+use crate::ooxml::{Paragraph, ParagraphProperties, Run, Table, TableCell, TableRow};
+use crate::piece_tree::PieceTree;
+
+/// Deepest list level OOXML allows, from 0
+const MAX_LIST_LEVEL: u8 = 8;
+
+/// How pasted lists and tables meet the structure at the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PastePolicy {
+    /// Continue the destination list or table
+    #[default]
+    Merge,
+    /// Insert the fragment as it is, as a separate list or table
+    KeepSource,
+    /// Insert the text only, with table cells separated by tabs
+    TextOnly,
+}
+
+impl PastePolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "merge" => Some(PastePolicy::Merge),
+            "keep_source" | "keep" => Some(PastePolicy::KeepSource),
+            "text_only" | "text" => Some(PastePolicy::TextOnly),
+            _ => None,
+        }
+    }
+}
+
+/// What a paste did to the fragment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PasteReport {
+    /// Blocks inserted as they were, after merging
+    pub blocks: usize,
+    /// Pasted list items moved into the destination list
+    pub list_items: usize,
+    /// Pasted rows appended to the destination table
+    pub rows: usize,
+    /// Empty cells added to pasted rows
+    pub padded_cells: usize,
+    /// Surplus cells of pasted rows folded into their last cell
+    pub folded_cells: usize,
+}
+
+/// Paste `fragment` into the body of `model` before block `at`
+///
+/// The block before `at` is the destination the fragment merges with.
+pub fn paste_into_model(model: &mut DocumentModel, at: usize, fragment: Vec<Block>, policy: PastePolicy) -> PasteReport {
+    let at = at.min(model.body.len());
+    let mut fragment = match policy {
+        PastePolicy::TextOnly => plain_paragraphs(&fragment).into_iter().map(Block::from).collect(),
+        _ => fragment,
+    };
+    let mut report = PasteReport::default();
+
+    if policy == PastePolicy::Merge && at > 0 {
+        match (&mut model.body[at - 1], fragment.first()) {
+            (Block::Table(destination), Some(Block::Table(_))) => {
+                if let Block::Table(pasted) = fragment.remove(0) {
+                    append_rows(destination, pasted.rows, &mut report);
+                }
+            }
+            (Block::Paragraph(destination), _) => {
+                let items = fragment.iter_mut().map_while(|block| match block {
+                    Block::Paragraph(paragraph) => Some(paragraph.as_mut()),
+                    Block::Table(_) => None,
+                });
+                report.list_items = join_list(items, &destination.properties);
+            }
+            _ => {}
+        }
+    }
+
+    report.blocks = fragment.len();
+    model.body.splice(at..at, fragment);
+    report
+}
+
+/// Paste `fragment` into the tree at char `offset` as one undo step
+///
+/// As in Word, the last pasted paragraph joins the text after the caret and
+/// keeps the destination paragraph's formatting; the others bring their own.
+pub fn paste_into_tree(tree: &mut PieceTree, offset: usize, fragment: &[Block], policy: PastePolicy) -> PasteReport {
+    let offset = offset.min(tree.char_count());
+    let mut paragraphs = match policy {
+        PastePolicy::TextOnly => plain_paragraphs(fragment),
+        _ => running_paragraphs(fragment),
+    };
+    let mut report = PasteReport {
+        blocks: fragment.len(),
+        ..Default::default()
+    };
+    if paragraphs.is_empty() {
+        return report;
+    }
+
+    if policy == PastePolicy::Merge {
+        let destination = tree
+            .paragraph_attributes_at(offset)
+            .map(|attributes| paragraph_properties(&attributes))
+            .unwrap_or_default();
+        report.list_items = join_list(paragraphs.iter_mut(), &destination);
+    }
+
+    tree.transaction(|tree| {
+        let mut at = offset;
+        let mut starts = Vec::with_capacity(paragraphs.len());
+        for (i, paragraph) in paragraphs.iter().enumerate() {
+            if i > 0 {
+                tree.insert(at, "\n".to_string());
+                at += 1;
+            }
+            starts.push(at);
+            for run in paragraph_runs(paragraph) {
+                // A line break inside a run would split the paragraph
+                let text = run.text.replace('\n', " ");
+                let length = text.chars().count();
+                tree.insert_with_attrs(at, text, text_attributes(&run.properties));
+                at += length;
+            }
+        }
+
+        if policy != PastePolicy::TextOnly {
+            for (start, paragraph) in starts.iter().zip(&paragraphs).take(paragraphs.len() - 1) {
+                let attributes = paragraph_attributes(&paragraph.properties);
+                tree.set_paragraph_attributes(*start..*start, attributes.as_ref());
+            }
+        }
+    });
+    report
+}
+
+/// List level of a paragraph in a list
+fn list_level(properties: &ParagraphProperties) -> Option<u8> {
+    match properties.num_id.as_deref() {
+        Some("0") => None,
+        Some(_) => Some(properties.list_level.unwrap_or(0)),
+        None => properties.list_level,
+    }
+}
+
+/// Moves the leading list items of `paragraphs` into the list of `destination`
+///
+/// Returns how many items moved; none do unless `destination` is in a list.
+fn join_list<'a>(paragraphs: impl Iterator<Item = &'a mut Paragraph>, destination: &ParagraphProperties) -> usize {
+    let Some(destination_level) = list_level(destination) else {
+        return 0;
+    };
+    let mut items: Vec<&mut Paragraph> = paragraphs
+        .map_while(|paragraph| list_level(&paragraph.properties).map(|_| paragraph))
+        .collect();
+    let Some(outermost) = items.iter().filter_map(|item| list_level(&item.properties)).min() else {
+        return 0;
+    };
+
+    for item in &mut items {
+        let level = list_level(&item.properties).unwrap_or(outermost) - outermost;
+        item.properties.list_level = Some(destination_level.saturating_add(level).min(MAX_LIST_LEVEL));
+        item.properties.num_id = destination.num_id.clone();
+    }
+    items.len()
+}
+
+/// Appends `rows` to `table`, fitted to its last row
+fn append_rows(table: &mut Table, rows: Vec<TableRow>, report: &mut PasteReport) {
+    let template = table.rows.last().cloned().filter(|row| !row.cells.is_empty());
+    for mut row in rows {
+        if let Some(template) = &template {
+            fit_row(&mut row, template, report);
+        }
+        table.rows.push(row);
+        report.rows += 1;
+    }
+}
+
+/// Gives `row` the column count and cell widths of `template`
+fn fit_row(row: &mut TableRow, template: &TableRow, report: &mut PasteReport) {
+    let columns = template.cells.len();
+    if row.cells.len() > columns {
+        let surplus = row.cells.split_off(columns);
+        report.folded_cells += surplus.len();
+        if let Some(last) = row.cells.last_mut() {
+            last.paragraphs.extend(surplus.into_iter().flat_map(|cell| cell.paragraphs));
+        }
+    }
+    while row.cells.len() < columns {
+        row.cells.push(TableCell {
+            paragraphs: vec![Paragraph::default()],
+            ..Default::default()
+        });
+        report.padded_cells += 1;
+    }
+    for (cell, column) in row.cells.iter_mut().zip(&template.cells) {
+        cell.width = column.width;
+        cell.properties.width = column.properties.width;
+    }
+}
+
+/// Runs of a paragraph; a paragraph with text but no runs is one plain run
+pub(crate) fn paragraph_runs(paragraph: &Paragraph) -> Vec<Run> {
+    if paragraph.runs.is_empty() && !paragraph.text.is_empty() {
+        vec![Run {
+            text: paragraph.text.clone(),
+            properties: Default::default(),
+        }]
+    } else {
+        paragraph.runs.clone()
+    }
+}
+
+/// Paragraphs of a fragment with each table row as one paragraph of tab-separated cells
+pub(crate) fn running_paragraphs(fragment: &[Block]) -> Vec<Paragraph> {
+    let mut paragraphs = Vec::new();
+    for block in fragment {
+        match block {
+            Block::Paragraph(paragraph) => paragraphs.push(paragraph.as_ref().clone()),
+            Block::Table(table) => paragraphs.extend(table.rows.iter().map(row_paragraph)),
+        }
+    }
+    paragraphs
+}
+
+fn row_paragraph(row: &TableRow) -> Paragraph {
+    let mut runs = Vec::new();
+    for (i, cell) in row.cells.iter().enumerate() {
+        if i > 0 {
+            runs.push(plain_run("\t"));
+        }
+        for (j, paragraph) in cell.paragraphs.iter().enumerate() {
+            if j > 0 {
+                runs.push(plain_run(" "));
+            }
+            runs.extend(paragraph_runs(paragraph));
+        }
+    }
+    Paragraph {
+        text: runs.iter().map(|run| run.text.as_str()).collect(),
+        runs,
+        ..Default::default()
+    }
+}
+
+/// Running paragraphs stripped of all formatting
+fn plain_paragraphs(fragment: &[Block]) -> Vec<Paragraph> {
+    running_paragraphs(fragment)
+        .into_iter()
+        .map(|paragraph| {
+            let text: String = paragraph_runs(&paragraph).iter().map(|run| run.text.as_str()).collect();
+            Paragraph {
+                runs: vec![plain_run(&text)],
+                text,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+fn plain_run(text: &str) -> Run {
+    Run {
+        text: text.to_string(),
+        properties: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece_tree::ParagraphAttributes;
+
+    fn item(text: &str, num_id: &str, level: u8) -> Block {
+        Block::from(Paragraph {
+            text: text.to_string(),
+            properties: ParagraphProperties {
+                num_id: Some(num_id.to_string()),
+                list_level: Some(level),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    fn table(rows: &[&[&str]], width: u32) -> Block {
+        let rows = rows
+            .iter()
+            .map(|cells| TableRow {
+                cells: cells
+                    .iter()
+                    .map(|text| TableCell {
+                        paragraphs: vec![Paragraph {
+                            text: text.to_string(),
+                            ..Default::default()
+                        }],
+                        width: Some(width),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .collect();
+        Block::Table(Box::new(Table {
+            rows,
+            ..Default::default()
+        }))
+    }
+
+    fn properties(block: &Block) -> (Option<&str>, Option<u8>) {
+        match block {
+            Block::Paragraph(paragraph) => (paragraph.properties.num_id.as_deref(), paragraph.properties.list_level),
+            Block::Table(_) => panic!("expected a paragraph"),
+        }
+    }
+
+    #[test]
+    fn test_list_items_continue_destination_list() {
+        let mut model = DocumentModel {
+            body: vec![item("one", "4", 1), item("after", "4", 1)],
+            ..Default::default()
+        };
+        let fragment = vec![item("a", "9", 2), item("b", "9", 3), item("tail", "0", 0)];
+        let report = paste_into_model(&mut model, 1, fragment.clone(), PastePolicy::Merge);
+
+        assert_eq!(report.list_items, 2);
+        assert_eq!(properties(&model.body[1]), (Some("4"), Some(1)));
+        assert_eq!(properties(&model.body[2]), (Some("4"), Some(2)));
+        assert_eq!(properties(&model.body[3]), (Some("0"), Some(0)));
+
+        let report = paste_into_model(&mut model, 1, fragment, PastePolicy::KeepSource);
+        assert_eq!(report.list_items, 0);
+        assert_eq!(properties(&model.body[1]), (Some("9"), Some(2)));
+    }
+
+    #[test]
+    fn test_rows_are_appended_and_fitted() {
+        let mut model = DocumentModel {
+            body: vec![table(&[&["h1", "h2", "h3"]], 2000)],
+            ..Default::default()
+        };
+        let fragment = vec![table(&[&["a"], &["b", "c", "d", "e"]], 500)];
+        let report = paste_into_model(&mut model, 1, fragment, PastePolicy::Merge);
+
+        assert_eq!((report.rows, report.padded_cells, report.folded_cells, report.blocks), (2, 2, 1, 0));
+        let Block::Table(table) = &model.body[0] else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.rows.len(), 3);
+        assert!(table.rows.iter().all(|row| row.cells.len() == 3));
+        assert!(table.rows[1].cells.iter().all(|cell| cell.width == Some(2000)));
+        let last: Vec<&str> = table.rows[2].cells[2].paragraphs.iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(last, ["d", "e"]);
+    }
+
+    #[test]
+    fn test_paste_into_tree() {
+        let mut tree = PieceTree::new("Intro\nItem".to_string());
+        let list_item = ParagraphAttributes {
+            list_level: Some(0),
+            ..Default::default()
+        };
+        tree.apply_paragraph_attributes(6..6, &list_item);
+
+        let fragment = vec![item("x", "2", 1), item("y", "2", 2), table(&[&["c1", "c2"]], 100)];
+        let report = paste_into_tree(&mut tree, 10, &fragment, PastePolicy::Merge);
+        assert_eq!(report.list_items, 2);
+        assert_eq!(tree.get_text(), "Intro\nItemx\ny\nc1\tc2");
+        let levels: Vec<_> = tree.paragraph_attributes_in(0..20).iter().map(|a| a.as_ref()?.list_level).collect();
+        assert_eq!(levels, [None, Some(0), Some(1), Some(0)]);
+
+        assert!(tree.undo());
+        assert_eq!(tree.get_text(), "Intro\nItem");
+        assert_eq!(tree.paragraph_attributes_in(0..10), [None, Some(list_item)]);
+    }
+}
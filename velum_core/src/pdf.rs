@@ -0,0 +1,2265 @@
+//! # PDF Module
+//!
+//! Writes paginated layout as PDF, for printing and sharing without other tools.
+//!
+//! [`page_from_layout`] turns a page of [`PageLayout`](crate::page_layout::PageLayout)
+//! output into a [`PdfPage`]: each laid-out line with the text of its
+//! paragraph cut into formatted runs, the links over them and the anchors
+//! named in them. Headers, footers and footnotes are stories laid out apart
+//! and placed on the page, images go in boxes and table borders are rules.
+//! [`write_pdf`] then writes the pages with the fonts of a [`PdfFonts`]
+//! embedded as subsets of the glyphs used, images as XObjects, links to URLs
+//! and to anchors, an outline of the document and its metadata.
+//!
+//! Coordinates are in points from the top left of the page, as in layout.
+//! Text is drawn a glyph per char with the font's advances, without shaping
+//! or justification.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use crate::font_license::{substitution_suggestions, FontLicense, FontLicensePolicy};
+use crate::image::ImageFormat;
+use crate::line_layout::ParagraphLayout;
+use crate::page_layout::{Page, PageConfig, Rect};
+
+/// Font size of text without a style, in points
+pub const DEFAULT_FONT_SIZE: f32 = 12.0;
+
+/// Fraction of a line's height above its baseline
+const BASELINE_RATIO: f32 = 0.8;
+
+/// Space between the body and the footnote separator, and the separator and the notes
+const FOOTNOTE_GAP: f32 = 6.0;
+
+/// Glyph space units per em, in which PDF font metrics are given
+const GLYPH_UNITS: f32 = 1000.0;
+
+/// Errors reading the fonts and images a PDF embeds
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum PdfError {
+    #[error("Font error: {0}")]
+    Font(String),
+
+    #[error("Image error: {0}")]
+    Image(String),
+}
+
+/// An RGB color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PdfColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl PdfColor {
+    pub const BLACK: PdfColor = PdfColor { r: 0, g: 0, b: 0 };
+
+    /// A color from "#RRGGBB" or "RRGGBB"
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(PdfColor { r: channel(0)?, g: channel(2)?, b: channel(4)? })
+    }
+
+    /// The color as operands of rg and RG
+    fn operands(self) -> String {
+        let channel = |value: u8| number(f32::from(value) / 255.0);
+        format!("{} {} {}", channel(self.r), channel(self.g), channel(self.b))
+    }
+}
+
+/// How a run of text is drawn
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdfTextStyle {
+    /// Font family; None for the first font registered
+    pub font: Option<String>,
+    /// Size in points
+    pub size: f32,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub color: PdfColor,
+}
+
+impl Default for PdfTextStyle {
+    fn default() -> Self {
+        PdfTextStyle {
+            font: None,
+            size: DEFAULT_FONT_SIZE,
+            bold: false,
+            italic: false,
+            underline: false,
+            color: PdfColor::BLACK,
+        }
+    }
+}
+
+/// Where a link goes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PdfLinkTarget {
+    Uri(String),
+    /// An anchor of the document, by name
+    Anchor(String),
+}
+
+/// Formatting and link of a byte range of a paragraph's text
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfSpan {
+    pub range: Range<usize>,
+    pub style: PdfTextStyle,
+    pub link: Option<PdfLinkTarget>,
+}
+
+/// How the text of a laid-out paragraph is drawn
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParagraphContent {
+    /// Formatting and links over byte ranges of the text, in order; text
+    /// outside them has the default style
+    pub spans: Vec<PdfSpan>,
+    /// Anchors named at byte offsets of the text, for links and the outline to go to
+    pub anchors: Vec<(usize, String)>,
+}
+
+/// Text in one style along a line
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfRun {
+    pub text: String,
+    pub style: PdfTextStyle,
+    pub link: Option<PdfLinkTarget>,
+}
+
+/// A line of text: its runs one after another from `x` along `baseline`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfLine {
+    pub x: f32,
+    pub baseline: f32,
+    pub runs: Vec<PdfRun>,
+}
+
+/// A line drawn from one point to another, such as a table border
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PdfRule {
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+    pub width: f32,
+    pub color: PdfColor,
+}
+
+/// An image, by the ID it is given to [`write_pdf`] under, drawn into a box
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfImagePlacement {
+    pub image: String,
+    pub rect: Rect,
+}
+
+/// A named place on a page
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfAnchor {
+    pub name: String,
+    pub y: f32,
+}
+
+/// Where a story goes in the region it is placed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoryAnchor {
+    /// Its first line at the top, as a header
+    Top,
+    /// Its last line at the bottom, as a footer
+    Bottom,
+}
+
+/// Everything drawn on one page
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PdfPage {
+    pub width: f32,
+    pub height: f32,
+    pub lines: Vec<PdfLine>,
+    pub images: Vec<PdfImagePlacement>,
+    pub rules: Vec<PdfRule>,
+    pub anchors: Vec<PdfAnchor>,
+}
+
+/// An entry of the outline (PDF bookmarks), going to an anchor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PdfOutlineItem {
+    pub title: String,
+    /// Depth, from 1; an item goes under the nearest item before it of a lower level
+    pub level: u8,
+    pub anchor: String,
+}
+
+/// The document information dictionary and catalog language
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdfMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Vec<String>,
+    /// Application the document was made in
+    pub creator: Option<String>,
+    /// Natural language of the text, e.g. "en-US"
+    pub language: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Pages to write with the outline and metadata
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PdfDocument {
+    pub pages: Vec<PdfPage>,
+    pub outline: Vec<PdfOutlineItem>,
+    pub metadata: PdfMetadata,
+}
+
+/// How [`write_pdf`] writes
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfOptions {
+    /// Compress page contents and fonts
+    pub compress: bool,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions { compress: true }
+    }
+}
+
+/// A written PDF
+#[derive(Debug, Clone)]
+pub struct PdfExport {
+    pub data: Vec<u8>,
+    /// Content that could not be written as asked
+    pub warnings: Vec<String>,
+}
+
+impl PdfPage {
+    pub fn new(width: f32, height: f32) -> Self {
+        PdfPage { width, height, ..Default::default() }
+    }
+
+    /// Draw the lines of `paragraphs`, laid out to the region's width, one
+    /// under another from the top or bottom of `region`
+    pub fn place_story(&mut self, region: Rect, paragraphs: &[ParagraphLayout], content: &[ParagraphContent], anchor: StoryAnchor) {
+        let mut top = match anchor {
+            StoryAnchor::Top => region.y,
+            StoryAnchor::Bottom => region.bottom() - story_height(paragraphs),
+        };
+        for (index, paragraph) in paragraphs.iter().enumerate() {
+            if paragraph.lines.is_empty() {
+                top += paragraph.actual_line_height;
+            }
+            for info in &paragraph.lines {
+                self.push_line(paragraph, content.get(index), info.start..info.end, region.x + info.offset_x, top, info.line_height);
+                top += info.line_height;
+            }
+        }
+    }
+
+    /// Draw footnotes at the bottom of the body area, under a separator a
+    /// third of its width; the body's layout must have left room for them.
+    /// Returns the height they take
+    pub fn place_footnotes(&mut self, body: Rect, notes: &[ParagraphLayout], content: &[ParagraphContent]) -> f32 {
+        if notes.is_empty() {
+            return 0.0;
+        }
+        let height = story_height(notes) + 2.0 * FOOTNOTE_GAP;
+        let separator = body.bottom() - height + FOOTNOTE_GAP;
+        self.rules.push(PdfRule {
+            from: (body.x, separator),
+            to: (body.x + body.width / 3.0, separator),
+            width: 0.5,
+            color: PdfColor::BLACK,
+        });
+        self.place_story(body, notes, content, StoryAnchor::Bottom);
+        height
+    }
+
+    /// Draw the borders of a table's grid with its top left at (`x`, `y`)
+    pub fn place_table_grid(&mut self, x: f32, y: f32, column_widths: &[f32], row_heights: &[f32], width: f32, color: PdfColor) {
+        let right = x + column_widths.iter().sum::<f32>();
+        let bottom = y + row_heights.iter().sum::<f32>();
+        let mut rule = |from, to| self.rules.push(PdfRule { from, to, width, color });
+        let mut top = y;
+        rule((x, top), (right, top));
+        for height in row_heights {
+            top += height;
+            rule((x, top), (right, top));
+        }
+        let mut left = x;
+        rule((left, y), (left, bottom));
+        for column in column_widths {
+            left += column;
+            rule((left, y), (left, bottom));
+        }
+    }
+
+    /// Draw the byte `range` of a paragraph's text as a line whose top is at `top`
+    fn push_line(&mut self, paragraph: &ParagraphLayout, content: Option<&ParagraphContent>, range: Range<usize>, x: f32, top: f32, height: f32) {
+        let text = &paragraph.text;
+        let end = range.end.min(text.len());
+        let start = range.start.min(end);
+        if let Some(content) = content {
+            let last = end == text.len();
+            for (offset, name) in &content.anchors {
+                if (start..end).contains(offset) || (last && *offset == end) {
+                    self.anchors.push(PdfAnchor { name: name.clone(), y: top });
+                }
+            }
+        }
+
+        let mut cuts = vec![start, end];
+        for span in content.map(|content| content.spans.as_slice()).unwrap_or_default() {
+            cuts.extend([span.range.start, span.range.end].into_iter().filter(|cut| (start + 1..end).contains(cut)));
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        let mut runs = Vec::new();
+        for piece in cuts.windows(2) {
+            let Some(run_text) = text.get(piece[0]..piece[1]) else {
+                continue;
+            };
+            let run_text = run_text.trim_end_matches(['\n', '\r']);
+            if run_text.is_empty() {
+                continue;
+            }
+            let span = content.and_then(|content| content.spans.iter().find(|span| span.range.contains(&piece[0])));
+            runs.push(PdfRun {
+                text: run_text.to_string(),
+                style: span.map(|span| span.style.clone()).unwrap_or_default(),
+                link: span.and_then(|span| span.link.clone()),
+            });
+        }
+        if !runs.is_empty() {
+            self.lines.push(PdfLine { x, baseline: top + height * BASELINE_RATIO, runs });
+        }
+    }
+}
+
+/// Height of paragraphs laid out one under another
+fn story_height(paragraphs: &[ParagraphLayout]) -> f32 {
+    paragraphs
+        .iter()
+        .map(|paragraph| match paragraph.lines.is_empty() {
+            true => paragraph.actual_line_height,
+            false => paragraph.lines.iter().map(|line| line.line_height).sum(),
+        })
+        .sum()
+}
+
+/// A page of the layout with the text of its lines; `paragraphs` are the
+/// paragraph layouts it was made from and `content` how each is drawn
+pub fn page_from_layout(page: &Page, config: &PageConfig, paragraphs: &[ParagraphLayout], content: &[ParagraphContent]) -> PdfPage {
+    let mut pdf_page = PdfPage::new(config.width, config.height);
+    let bounds = page.content_bounds;
+    for line in &page.lines {
+        let Some(paragraph) = paragraphs.get(line.paragraph_index) else {
+            continue;
+        };
+        let offset_x = paragraph.lines.get(line.source_line_index).map_or(0.0, |info| info.offset_x);
+        pdf_page.push_line(
+            paragraph,
+            content.get(line.paragraph_index),
+            line.start..line.end,
+            bounds.x + line.x + offset_x,
+            bounds.y + line.y,
+            line.height,
+        );
+    }
+    pdf_page
+}
+
+// ============================================
+// Fonts
+// ============================================
+
+/// A TrueType or OpenType font, with what a PDF needs to know of it
+#[derive(Debug, Clone)]
+struct OpenTypeFont {
+    data: Vec<u8>,
+    /// Offset and length of each table
+    tables: HashMap<[u8; 4], (usize, usize)>,
+    postscript_name: String,
+    units_per_em: u16,
+    ascent: i16,
+    descent: i16,
+    cap_height: i16,
+    bbox: [i16; 4],
+    italic_angle: f32,
+    fixed_pitch: bool,
+    /// Embedding permissions from OS/2 fsType
+    license: FontLicense,
+    /// Outlines in a CFF table rather than glyf, which is embedded whole
+    cff: bool,
+    num_glyphs: u16,
+    long_loca: bool,
+    advances: Vec<u16>,
+    glyphs: HashMap<char, u16>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+impl OpenTypeFont {
+    fn parse(data: Vec<u8>) -> Result<Self, PdfError> {
+        let truncated = |what: &str| PdfError::Font(format!("truncated {}", what));
+        // A collection: its first font
+        let start = if data.starts_with(b"ttcf") {
+            read_u32(&data, 12).ok_or_else(|| truncated("font collection"))? as usize
+        } else {
+            0
+        };
+        let version = read_u32(&data, start).ok_or_else(|| truncated("font header"))?;
+        let cff = match &version.to_be_bytes() {
+            [0, 1, 0, 0] | b"true" => false,
+            b"OTTO" => true,
+            _ => return Err(PdfError::Font("not a TrueType or OpenType font".to_string())),
+        };
+        let count = read_u16(&data, start + 4).ok_or_else(|| truncated("font header"))? as usize;
+        let mut tables = HashMap::new();
+        for index in 0..count {
+            let record = start + 12 + 16 * index;
+            let tag: [u8; 4] = data.get(record..record + 4).and_then(|tag| tag.try_into().ok()).ok_or_else(|| truncated("table directory"))?;
+            let offset = read_u32(&data, record + 8).ok_or_else(|| truncated("table directory"))? as usize;
+            let length = read_u32(&data, record + 12).ok_or_else(|| truncated("table directory"))? as usize;
+            if offset.checked_add(length).is_none_or(|end| end > data.len()) {
+                return Err(truncated(&String::from_utf8_lossy(&tag)));
+            }
+            tables.insert(tag, (offset, length));
+        }
+
+        let table = |tag: &[u8; 4]| -> Result<&[u8], PdfError> {
+            let &(offset, length) = tables
+                .get(tag)
+                .ok_or_else(|| PdfError::Font(format!("no {} table", String::from_utf8_lossy(tag))))?;
+            Ok(&data[offset..offset + length])
+        };
+        let head = table(b"head")?;
+        let hhea = table(b"hhea")?;
+        let maxp = table(b"maxp")?;
+        let hmtx = table(b"hmtx")?;
+        let units_per_em = read_u16(head, 18).filter(|&upem| upem > 0).ok_or_else(|| truncated("head"))?;
+        let bbox = [
+            read_i16(head, 36).unwrap_or(0),
+            read_i16(head, 38).unwrap_or(0),
+            read_i16(head, 40).unwrap_or(0),
+            read_i16(head, 42).unwrap_or(0),
+        ];
+        let long_loca = read_i16(head, 50).unwrap_or(0) == 1;
+        let ascent = read_i16(hhea, 4).ok_or_else(|| truncated("hhea"))?;
+        let descent = read_i16(hhea, 6).ok_or_else(|| truncated("hhea"))?;
+        let metrics = read_u16(hhea, 34).ok_or_else(|| truncated("hhea"))? as usize;
+        let num_glyphs = read_u16(maxp, 4).ok_or_else(|| truncated("maxp"))?;
+
+        let mut advances = Vec::with_capacity(num_glyphs as usize);
+        let mut last = 0;
+        for glyph in 0..num_glyphs as usize {
+            if glyph < metrics {
+                last = read_u16(hmtx, 4 * glyph).ok_or_else(|| truncated("hmtx"))?;
+            }
+            advances.push(last);
+        }
+
+        let os2 = table(b"OS/2").ok();
+        let license = os2.and_then(|os2| read_u16(os2, 8)).map(FontLicense::from_fs_type).unwrap_or_default();
+        let cap_height = os2
+            .filter(|os2| read_u16(os2, 0).unwrap_or(0) >= 2)
+            .and_then(|os2| read_i16(os2, 88))
+            .unwrap_or((f32::from(ascent) * 0.7) as i16);
+        let post = table(b"post").ok();
+        let italic_angle = post.and_then(|post| read_u32(post, 4)).map_or(0.0, |fixed| fixed as i32 as f32 / 65536.0);
+        let fixed_pitch = post.and_then(|post| read_u32(post, 12)).is_some_and(|fixed| fixed != 0);
+
+        let glyphs = read_cmap(table(b"cmap")?).ok_or_else(|| PdfError::Font("no Unicode cmap subtable".to_string()))?;
+        let postscript_name = table(b"name").ok().and_then(postscript_name).unwrap_or_else(|| "Font".to_string());
+        if !cff {
+            table(b"loca")?;
+            table(b"glyf")?;
+        }
+
+        Ok(OpenTypeFont {
+            data,
+            tables,
+            postscript_name,
+            units_per_em,
+            ascent,
+            descent,
+            cap_height,
+            bbox,
+            italic_angle,
+            fixed_pitch,
+            license,
+            cff,
+            num_glyphs,
+            long_loca,
+            advances,
+            glyphs,
+        })
+    }
+
+    fn table(&self, tag: &[u8; 4]) -> Option<&[u8]> {
+        let &(offset, length) = self.tables.get(tag)?;
+        self.data.get(offset..offset + length)
+    }
+
+    /// Glyph of `c`, if the font has one
+    fn glyph(&self, c: char) -> Option<u16> {
+        self.glyphs.get(&c).copied().filter(|&glyph| glyph != 0 && glyph < self.num_glyphs)
+    }
+
+    /// A font unit value in glyph space units
+    fn scaled(&self, value: i32) -> i32 {
+        (value as f32 * GLYPH_UNITS / f32::from(self.units_per_em)).round() as i32
+    }
+
+    /// Advance of a glyph in glyph space units
+    fn advance(&self, glyph: u16) -> f32 {
+        let advance = self.advances.get(glyph as usize).copied().unwrap_or(0);
+        f32::from(advance) * GLYPH_UNITS / f32::from(self.units_per_em)
+    }
+
+    /// Byte range of a glyph's outline in glyf
+    fn glyph_range(&self, glyph: u16) -> Option<Range<usize>> {
+        let loca = self.table(b"loca")?;
+        let index = glyph as usize;
+        let (start, end) = if self.long_loca {
+            (read_u32(loca, 4 * index)? as usize, read_u32(loca, 4 * index + 4)? as usize)
+        } else {
+            (2 * read_u16(loca, 2 * index)? as usize, 2 * read_u16(loca, 2 * index + 2)? as usize)
+        };
+        (start <= end).then_some(start..end)
+    }
+
+    /// The font with the outlines of `glyphs` and the glyphs their
+    /// composites are made of, and empty outlines for every other glyph, so
+    /// glyph IDs stay the same. Only what a PDF reader uses is kept
+    fn subset(&self, glyphs: &BTreeSet<u16>) -> Vec<u8> {
+        let glyf = self.table(b"glyf").unwrap_or_default();
+        let mut keep: BTreeSet<u16> = glyphs.clone();
+        keep.insert(0);
+        let mut pending: Vec<u16> = keep.iter().copied().collect();
+        while let Some(glyph) = pending.pop() {
+            let outline = self.glyph_range(glyph).and_then(|range| glyf.get(range)).unwrap_or_default();
+            for component in composite_components(outline) {
+                if component < self.num_glyphs && keep.insert(component) {
+                    pending.push(component);
+                }
+            }
+        }
+
+        let mut new_glyf = Vec::new();
+        let mut new_loca = Vec::with_capacity(4 * (self.num_glyphs as usize + 1));
+        for glyph in 0..self.num_glyphs {
+            new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+            if keep.contains(&glyph) {
+                if let Some(outline) = self.glyph_range(glyph).and_then(|range| glyf.get(range)) {
+                    new_glyf.extend_from_slice(outline);
+                    new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+                }
+            }
+        }
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+        let mut head = self.table(b"head").unwrap_or_default().to_vec();
+        if head.len() >= 52 {
+            head[8..12].copy_from_slice(&[0; 4]);
+            head[50..52].copy_from_slice(&1i16.to_be_bytes());
+        }
+        let mut tables = vec![
+            (*b"head", head),
+            (*b"loca", new_loca),
+            (*b"glyf", new_glyf),
+        ];
+        for tag in [b"hhea", b"hmtx", b"maxp", b"cvt ", b"fpgm", b"prep"] {
+            if let Some(table) = self.table(tag) {
+                tables.push((*tag, table.to_vec()));
+            }
+        }
+        sfnt(tables)
+    }
+}
+
+/// Glyphs a composite glyph's outline is made of; none for a simple glyph
+fn composite_components(outline: &[u8]) -> Vec<u16> {
+    const ARGS_ARE_WORDS: u16 = 0x0001;
+    const HAVE_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const HAVE_XY_SCALE: u16 = 0x0040;
+    const HAVE_TWO_BY_TWO: u16 = 0x0080;
+
+    let mut components = Vec::new();
+    if read_i16(outline, 0).is_none_or(|contours| contours >= 0) {
+        return components;
+    }
+    let mut offset = 10;
+    while let (Some(flags), Some(glyph)) = (read_u16(outline, offset), read_u16(outline, offset + 2)) {
+        components.push(glyph);
+        offset += 4 + if flags & ARGS_ARE_WORDS != 0 { 4 } else { 2 };
+        if flags & HAVE_SCALE != 0 {
+            offset += 2;
+        } else if flags & HAVE_XY_SCALE != 0 {
+            offset += 4;
+        } else if flags & HAVE_TWO_BY_TWO != 0 {
+            offset += 8;
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    components
+}
+
+/// Chars to glyphs from the best Unicode subtable of a cmap: format 12 for
+/// all of Unicode, else format 4 for the Basic Multilingual Plane
+fn read_cmap(cmap: &[u8]) -> Option<HashMap<char, u16>> {
+    let count = read_u16(cmap, 2)? as usize;
+    let mut best: Option<(u8, usize)> = None;
+    for index in 0..count {
+        let record = 4 + 8 * index;
+        let platform = read_u16(cmap, record)?;
+        let encoding = read_u16(cmap, record + 2)?;
+        let offset = read_u32(cmap, record + 4)? as usize;
+        let format = read_u16(cmap, offset).unwrap_or(0);
+        let rank = match (platform, encoding, format) {
+            (3, 10, 12) | (0, 4, 12) | (0, 6, 12) => 3,
+            (3, 1, 4) | (0, 3, 4) => 2,
+            (0, _, 4) => 1,
+            _ => continue,
+        };
+        if best.is_none_or(|(best_rank, _)| rank > best_rank) {
+            best = Some((rank, offset));
+        }
+    }
+    let (_, offset) = best?;
+    let subtable = cmap.get(offset..)?;
+    let mut glyphs = HashMap::new();
+    match read_u16(subtable, 0)? {
+        12 => {
+            let groups = read_u32(subtable, 12)? as usize;
+            for group in 0..groups {
+                let record = 16 + 12 * group;
+                let (start, end, first) = (read_u32(subtable, record)?, read_u32(subtable, record + 4)?, read_u32(subtable, record + 8)?);
+                for code in start..=end.min(0x10FFFF) {
+                    if let (Some(c), Ok(glyph)) = (char::from_u32(code), u16::try_from(first + (code - start))) {
+                        glyphs.insert(c, glyph);
+                    }
+                }
+            }
+        }
+        _ => {
+            let segments = read_u16(subtable, 6)? as usize / 2;
+            let ends = 14;
+            let starts = ends + 2 * segments + 2;
+            let deltas = starts + 2 * segments;
+            let range_offsets = deltas + 2 * segments;
+            for segment in 0..segments {
+                let end = read_u16(subtable, ends + 2 * segment)?;
+                let start = read_u16(subtable, starts + 2 * segment)?;
+                let delta = read_u16(subtable, deltas + 2 * segment)?;
+                let range_offset = read_u16(subtable, range_offsets + 2 * segment)? as usize;
+                if start > end {
+                    continue;
+                }
+                for code in start..=end {
+                    if code == 0xFFFF {
+                        break;
+                    }
+                    let glyph = if range_offset == 0 {
+                        code.wrapping_add(delta)
+                    } else {
+                        let at = range_offsets + 2 * segment + range_offset + 2 * (code - start) as usize;
+                        match read_u16(subtable, at) {
+                            Some(0) | None => 0,
+                            Some(glyph) => glyph.wrapping_add(delta),
+                        }
+                    };
+                    if let Some(c) = char::from_u32(u32::from(code)) {
+                        glyphs.insert(c, glyph);
+                    }
+                }
+            }
+        }
+    }
+    Some(glyphs)
+}
+
+/// The PostScript name (name ID 6) from a name table
+fn postscript_name(name: &[u8]) -> Option<String> {
+    let count = read_u16(name, 2)? as usize;
+    let strings = read_u16(name, 4)? as usize;
+    for index in 0..count {
+        let record = 6 + 12 * index;
+        let (platform, name_id) = (read_u16(name, record)?, read_u16(name, record + 6)?);
+        if name_id != 6 {
+            continue;
+        }
+        let length = read_u16(name, record + 8)? as usize;
+        let offset = strings + read_u16(name, record + 10)? as usize;
+        let bytes = name.get(offset..offset + length)?;
+        let text = if platform == 3 || platform == 0 {
+            let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            bytes.iter().map(|&b| b as char).collect()
+        };
+        let text: String = text.chars().filter(|c| c.is_ascii_graphic() && !"[](){}<>/%#".contains(*c)).collect();
+        if !text.is_empty() {
+            return Some(text);
+        }
+    }
+    None
+}
+
+/// An sfnt font file of `tables`, with its checksums
+fn sfnt(mut tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    fn checksum(data: &[u8]) -> u32 {
+        data.chunks(4).fold(0u32, |sum, chunk| {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            sum.wrapping_add(u32::from_be_bytes(word))
+        })
+    }
+
+    tables.sort_by_key(|(tag, _)| *tag);
+    let count = tables.len() as u16;
+    let power = if count == 0 { 0 } else { 15 - count.leading_zeros() as u16 };
+    let search_range = 16 * (1u16 << power);
+    let mut font = Vec::new();
+    font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    for value in [count, search_range, power, (16 * count).saturating_sub(search_range)] {
+        font.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+    for (tag, data) in &tables {
+        if tag == b"head" {
+            head_offset = Some(offset);
+        }
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&checksum(data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in &tables {
+        font.extend_from_slice(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    if let Some(head) = head_offset.filter(|head| font.len() >= head + 12) {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+        font[head + 8..head + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    font
+}
+
+struct RegisteredFont {
+    family: String,
+    bold: bool,
+    italic: bool,
+    font: OpenTypeFont,
+}
+
+/// A registered font's embedding permissions, and what to use instead if it
+/// may not be embedded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PdfFontLicense {
+    pub family: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub postscript_name: String,
+    pub license: FontLicense,
+    /// Whether the font is embedded under the current policy
+    pub embedded: bool,
+    /// Fonts to use instead, for fonts whose license keeps them out of documents
+    pub suggestions: Vec<String>,
+}
+
+/// Fonts text can be drawn in, by family and style
+///
+/// Text is drawn in the registered font of its family and style, or the
+/// family's font closest in style; chars it has no glyph for are drawn in the
+/// first other font that has one. Without any font registered, text is drawn
+/// in the standard Helvetica fonts, which readers supply but which only
+/// cover Latin-1.
+///
+/// Fonts whose license does not allow embedding are left out under
+/// [`FontLicensePolicy::Refuse`], and their text drawn as if they were not
+/// registered; fonts that may not be subset are embedded whole.
+#[derive(Default)]
+pub struct PdfFonts {
+    fonts: Vec<RegisteredFont>,
+    policy: FontLicensePolicy,
+}
+
+impl PdfFonts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a TrueType or OpenType font file (or the first font of a
+    /// collection) for a family and style, replacing any registered for them
+    pub fn register(&mut self, family: &str, bold: bool, italic: bool, data: Vec<u8>) -> Result<(), PdfError> {
+        let font = OpenTypeFont::parse(data)?;
+        self.fonts
+            .retain(|registered| !(registered.family.eq_ignore_ascii_case(family) && registered.bold == bold && registered.italic == italic));
+        self.fonts.push(RegisteredFont { family: family.to_string(), bold, italic, font });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty()
+    }
+
+    pub fn license_policy(&self) -> FontLicensePolicy {
+        self.policy
+    }
+
+    pub fn set_license_policy(&mut self, policy: FontLicensePolicy) {
+        self.policy = policy;
+    }
+
+    /// The embedding permissions of every registered font
+    pub fn licenses(&self) -> Vec<PdfFontLicense> {
+        (0..self.fonts.len())
+            .map(|index| {
+                let registered = &self.fonts[index];
+                let license = registered.font.license;
+                PdfFontLicense {
+                    family: registered.family.clone(),
+                    bold: registered.bold,
+                    italic: registered.italic,
+                    postscript_name: registered.font.postscript_name.clone(),
+                    license,
+                    embedded: self.embeddable(index),
+                    suggestions: match license.allows_outline_embedding() {
+                        true => Vec::new(),
+                        false => self.suggestions(&registered.family),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the font at `index` may be embedded under the policy
+    fn embeddable(&self, index: usize) -> bool {
+        self.policy == FontLicensePolicy::Warn || self.fonts[index].font.license.allows_outline_embedding()
+    }
+
+    /// Fonts to use instead of `family`: metric-compatible ones, then the
+    /// registered families that may be embedded
+    fn suggestions(&self, family: &str) -> Vec<String> {
+        let available = (0..self.fonts.len())
+            .filter(|&index| self.fonts[index].font.license.allows_outline_embedding())
+            .map(|index| self.fonts[index].family.as_str());
+        substitution_suggestions(family, available)
+    }
+
+    /// Registered fonts by how close they are to `style`: its family, then its
+    /// boldness and slant
+    fn by_closeness(&self, style: &PdfTextStyle) -> Vec<usize> {
+        let family = style.font.as_deref().filter(|family| self.fonts.iter().any(|f| f.family.eq_ignore_ascii_case(family)));
+        let family = family.unwrap_or(&self.fonts[0].family);
+        let distance = |font: &RegisteredFont| {
+            let other_family = !font.family.eq_ignore_ascii_case(family);
+            (other_family, font.bold != style.bold, font.italic != style.italic)
+        };
+        let mut order: Vec<usize> = (0..self.fonts.len()).collect();
+        order.sort_by_key(|&index| distance(&self.fonts[index]));
+        order
+    }
+
+    /// The font `style` asks for, if it is registered but may not be embedded
+    fn refused_for(&self, style: &PdfTextStyle) -> Option<usize> {
+        if self.fonts.is_empty() {
+            return None;
+        }
+        let closest = self.by_closeness(style)[0];
+        (!self.embeddable(closest)).then_some(closest)
+    }
+
+    /// The font `c` is drawn in with `style`
+    fn font_for(&self, style: &PdfTextStyle, c: char) -> FontChoice {
+        if self.fonts.is_empty() {
+            return FontChoice::Standard(StandardFont::for_style(style));
+        }
+        let order: Vec<usize> = self.by_closeness(style).into_iter().filter(|&index| self.embeddable(index)).collect();
+        let Some(&closest) = order.first() else {
+            return FontChoice::Standard(StandardFont::for_style(style));
+        };
+        let covering = order.iter().copied().find(|&index| self.fonts[index].font.glyph(c).is_some());
+        FontChoice::Embedded(covering.unwrap_or(closest))
+    }
+
+    /// Glyph code of `c` in a font and its advance in glyph space units
+    fn encode(&self, choice: FontChoice, c: char) -> (u16, f32) {
+        match choice {
+            FontChoice::Embedded(index) => {
+                let font = &self.fonts[index].font;
+                let glyph = font.glyph(c).unwrap_or(0);
+                (glyph, font.advance(glyph))
+            }
+            FontChoice::Standard(_) => {
+                let code = win_ansi(c).unwrap_or(b'?');
+                (u16::from(code), helvetica_width(code))
+            }
+        }
+    }
+}
+
+/// Which font a piece of text is drawn in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum FontChoice {
+    /// A registered font, by index
+    Embedded(usize),
+    Standard(StandardFont),
+}
+
+/// The Helvetica family of the standard fonts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum StandardFont {
+    Regular,
+    Bold,
+    Oblique,
+    BoldOblique,
+}
+
+impl StandardFont {
+    fn for_style(style: &PdfTextStyle) -> Self {
+        match (style.bold, style.italic) {
+            (false, false) => StandardFont::Regular,
+            (true, false) => StandardFont::Bold,
+            (false, true) => StandardFont::Oblique,
+            (true, true) => StandardFont::BoldOblique,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            StandardFont::Regular => "Helvetica",
+            StandardFont::Bold => "Helvetica-Bold",
+            StandardFont::Oblique => "Helvetica-Oblique",
+            StandardFont::BoldOblique => "Helvetica-BoldOblique",
+        }
+    }
+}
+
+/// Widths of Helvetica's printable ASCII glyphs, space to tilde
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833,
+    722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556,
+    556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334,
+    260, 334, 584,
+];
+
+/// Width of a WinAnsi code in Helvetica; the bold and oblique fonts are
+/// measured as the regular one, and glyphs beyond ASCII at a typical width
+fn helvetica_width(code: u8) -> f32 {
+    match code {
+        32..=126 => f32::from(HELVETICA_WIDTHS[(code - 32) as usize]),
+        _ => 556.0,
+    }
+}
+
+/// The WinAnsiEncoding code of `c`, if it has one
+fn win_ansi(c: char) -> Option<u8> {
+    const SPECIALS: [(char, u8); 27] = [
+        ('€', 0x80), ('‚', 0x82), ('ƒ', 0x83), ('„', 0x84), ('…', 0x85), ('†', 0x86), ('‡', 0x87), ('ˆ', 0x88),
+        ('‰', 0x89), ('Š', 0x8A), ('‹', 0x8B), ('Œ', 0x8C), ('Ž', 0x8E), ('‘', 0x91), ('’', 0x92), ('“', 0x93),
+        ('”', 0x94), ('•', 0x95), ('–', 0x96), ('—', 0x97), ('˜', 0x98), ('™', 0x99), ('š', 0x9A), ('›', 0x9B),
+        ('œ', 0x9C), ('ž', 0x9E), ('Ÿ', 0x9F),
+    ];
+    match c as u32 {
+        0x20..=0x7E | 0xA0..=0xFF => Some(c as u8),
+        _ => SPECIALS.iter().find(|(special, _)| *special == c).map(|&(_, code)| code),
+    }
+}
+
+// ============================================
+// Images
+// ============================================
+
+/// An image as a PDF image XObject
+#[derive(Debug, Clone)]
+struct ImageXObject {
+    width: u32,
+    height: u32,
+    color_space: String,
+    bits: u8,
+    filter: &'static str,
+    decode_parms: Option<String>,
+    decode: Option<&'static str>,
+    data: Vec<u8>,
+    /// Alpha channel, 8-bit gray compressed with Flate
+    alpha: Option<Vec<u8>>,
+}
+
+fn read_image(data: &[u8]) -> Result<ImageXObject, PdfError> {
+    match ImageFormat::from_magic_bytes(data) {
+        ImageFormat::Jpeg => read_jpeg(data),
+        ImageFormat::Png => read_png(data),
+        ImageFormat::Unknown => Err(PdfError::Image("Unknown images are not supported".to_string())),
+        _ => read_pixels(data),
+    }
+}
+
+/// Vector pictures are rasterized at this many pixels an inch
+const VECTOR_DPI: f32 = 300.0;
+
+/// Other formats are decoded, vector pictures rasterized, and written as
+/// RGB pixels with their alpha as a soft mask
+fn read_pixels(data: &[u8]) -> Result<ImageXObject, PdfError> {
+    let image = crate::image::ImageData::decode(data.to_vec()).map_err(|e| PdfError::Image(e.to_string()))?;
+    // Image dimensions are pixels at 96 dpi
+    let fit = image.format.is_vector().then(|| {
+        let scale = VECTOR_DPI / 96.0;
+        ((image.dimensions.width * scale).ceil() as u32, (image.dimensions.height * scale).ceil() as u32)
+    });
+    let pixels = image.decode_pixels(fit).map_err(|e| PdfError::Image(e.to_string()))?;
+    let mut color = Vec::with_capacity(pixels.rgba.len() / 4 * 3);
+    let mut alpha = Vec::with_capacity(pixels.rgba.len() / 4);
+    for pixel in pixels.rgba.chunks_exact(4) {
+        color.extend_from_slice(&pixel[..3]);
+        alpha.push(pixel[3]);
+    }
+    Ok(ImageXObject {
+        width: pixels.width,
+        height: pixels.height,
+        color_space: "/DeviceRGB".to_string(),
+        bits: 8,
+        filter: "/FlateDecode",
+        decode_parms: None,
+        decode: None,
+        data: deflate(&color),
+        alpha: alpha.iter().any(|a| *a < 255).then(|| deflate(&alpha)),
+    })
+}
+
+/// A JPEG is written as it is, for readers to decode
+fn read_jpeg(data: &[u8]) -> Result<ImageXObject, PdfError> {
+    let mut offset = 2;
+    while offset + 4 <= data.len() {
+        if data[offset] != 0xFF {
+            offset += 1;
+            continue;
+        }
+        let marker = data[offset + 1];
+        if marker == 0xFF || marker == 0xD8 || (0xD0..=0xD7).contains(&marker) {
+            offset += if marker == 0xFF { 1 } else { 2 };
+            continue;
+        }
+        let length = read_u16(data, offset + 2).unwrap_or(0) as usize;
+        let frame = matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if frame {
+            let segment = data.get(offset + 4..offset + 2 + length).unwrap_or_default();
+            let (Some(height), Some(width), Some(&components)) = (read_u16(segment, 1), read_u16(segment, 3), segment.get(5)) else {
+                break;
+            };
+            let (color_space, decode) = match components {
+                1 => ("/DeviceGray", None),
+                3 => ("/DeviceRGB", None),
+                // Adobe CMYK JPEGs are stored inverted
+                4 => ("/DeviceCMYK", Some("[1 0 1 0 1 0 1 0]")),
+                n => return Err(PdfError::Image(format!("JPEG with {} components", n))),
+            };
+            return Ok(ImageXObject {
+                width: u32::from(width),
+                height: u32::from(height),
+                color_space: color_space.to_string(),
+                bits: 8,
+                filter: "/DCTDecode",
+                decode_parms: None,
+                decode,
+                data: data.to_vec(),
+                alpha: None,
+            });
+        }
+        offset += 2 + length;
+    }
+    Err(PdfError::Image("JPEG without a frame header".to_string()))
+}
+
+/// A PNG without alpha is written as its compressed data, which PDF's Flate
+/// predictors decode; one with alpha is decoded to split off the alpha as a soft mask
+fn read_png(data: &[u8]) -> Result<ImageXObject, PdfError> {
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    let mut offset = 8;
+    while let Some(length) = read_u32(data, offset) {
+        let length = length as usize;
+        let kind = data.get(offset + 4..offset + 8).unwrap_or_default();
+        let chunk = data.get(offset + 8..offset + 8 + length).ok_or_else(|| PdfError::Image("truncated PNG".to_string()))?;
+        match kind {
+            b"IHDR" => header = Some(chunk.to_vec()),
+            b"PLTE" => palette = chunk.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {}
+        }
+        offset += 12 + length;
+    }
+    let header = header.ok_or_else(|| PdfError::Image("PNG without a header".to_string()))?;
+    let (Some(width), Some(height), Some(&bits), Some(&color_type), Some(&interlace)) =
+        (read_u32(&header, 0), read_u32(&header, 4), header.get(8), header.get(9), header.get(12))
+    else {
+        return Err(PdfError::Image("truncated PNG header".to_string()));
+    };
+    if interlace != 0 {
+        return Err(PdfError::Image("interlaced PNG images are not supported".to_string()));
+    }
+
+    let (colors, color_space) = match color_type {
+        0 | 4 => (1, "/DeviceGray".to_string()),
+        2 | 6 => (3, "/DeviceRGB".to_string()),
+        3 => {
+            let entries = palette.len() / 3;
+            if entries == 0 {
+                return Err(PdfError::Image("PNG without a palette".to_string()));
+            }
+            (1, format!("[/Indexed /DeviceRGB {} <{}>]", entries - 1, hex(&palette[..entries * 3])))
+        }
+        other => return Err(PdfError::Image(format!("PNG color type {}", other))),
+    };
+    if matches!(color_type, 0 | 2 | 3) {
+        return Ok(ImageXObject {
+            width,
+            height,
+            color_space,
+            bits,
+            filter: "/FlateDecode",
+            decode_parms: Some(format!(
+                "<< /Predictor 15 /Colors {} /BitsPerComponent {} /Columns {} >>",
+                colors, bits, width
+            )),
+            decode: None,
+            data: compressed,
+            alpha: None,
+        });
+    }
+
+    // Gray or RGB with alpha, 8 or 16 bits a sample
+    let bytes = usize::from(bits / 8).max(1);
+    let pixel = (colors + 1) * bytes;
+    let mut raw = Vec::new();
+    ZlibDecoder::new(compressed.as_slice())
+        .read_to_end(&mut raw)
+        .map_err(|e| PdfError::Image(format!("PNG data: {}", e)))?;
+    let pixels = unfilter(&raw, width as usize * pixel, height as usize, pixel)?;
+    let mut color = Vec::with_capacity(pixels.len() / pixel * colors);
+    let mut alpha = Vec::with_capacity(pixels.len() / pixel);
+    for sample in pixels.chunks_exact(pixel) {
+        // Of 16-bit samples, the high bytes
+        for channel in 0..colors {
+            color.push(sample[channel * bytes]);
+        }
+        alpha.push(sample[colors * bytes]);
+    }
+    Ok(ImageXObject {
+        width,
+        height,
+        color_space,
+        bits: 8,
+        filter: "/FlateDecode",
+        decode_parms: None,
+        decode: None,
+        data: deflate(&color),
+        alpha: Some(deflate(&alpha)),
+    })
+}
+
+/// PNG scanlines with their filters undone
+pub(crate) fn unfilter(raw: &[u8], stride: usize, rows: usize, pixel: usize) -> Result<Vec<u8>, PdfError> {
+    let mut pixels = vec![0u8; stride * rows];
+    for row in 0..rows {
+        let line = raw.get(row * (stride + 1)..(row + 1) * (stride + 1)).ok_or_else(|| PdfError::Image("truncated PNG data".to_string()))?;
+        let (filter, line) = (line[0], &line[1..]);
+        let (done, current) = pixels.split_at_mut(row * stride);
+        let previous = if row == 0 { None } else { Some(&done[(row - 1) * stride..]) };
+        let current = &mut current[..stride];
+        for i in 0..stride {
+            let left = if i >= pixel { current[i - pixel] } else { 0 };
+            let up = previous.map_or(0, |previous| previous[i]);
+            let up_left = if i >= pixel { previous.map_or(0, |previous| previous[i - pixel]) } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                other => return Err(PdfError::Image(format!("PNG filter type {}", other))),
+            };
+            current[i] = line[i].wrapping_add(predicted);
+        }
+    }
+    Ok(pixels)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let (to_left, to_up, to_up_left) = (
+        (estimate - i16::from(left)).abs(),
+        (estimate - i16::from(up)).abs(),
+        (estimate - i16::from(up_left)).abs(),
+    );
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+// ============================================
+// Writing
+// ============================================
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing to a Vec cannot fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/// A number as PDF writes it: at most two decimals, without trailing zeros
+fn number(value: f32) -> String {
+    let text = format!("{:.2}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" { "0".to_string() } else { text.to_string() }
+}
+
+/// A literal string of bytes, with delimiters and unprintable bytes escaped
+fn literal(bytes: &[u8]) -> String {
+    let mut text = String::from("(");
+    for &byte in bytes {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                text.push('\\');
+                text.push(byte as char);
+            }
+            0x20..=0x7E => text.push(byte as char),
+            _ => {
+                let _ = write!(text, "\\{:03o}", byte);
+            }
+        }
+    }
+    text.push(')');
+    text
+}
+
+/// A text string: literal if printable ASCII, else UTF-16 with a byte order mark
+fn text_string(text: &str) -> String {
+    if text.chars().all(|c| (' '..='~').contains(&c)) {
+        literal(text.as_bytes())
+    } else {
+        let units: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        format!("<FEFF{}>", hex(&units))
+    }
+}
+
+/// A name object, with bytes other than regular characters as #xx
+fn name(text: &str) -> String {
+    let mut name = String::from("/");
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.+".contains(&byte) {
+            name.push(byte as char);
+        } else {
+            let _ = write!(name, "#{:02X}", byte);
+        }
+    }
+    name
+}
+
+fn date(time: &DateTime<Utc>) -> String {
+    time.format("(D:%Y%m%d%H%M%SZ)").to_string()
+}
+
+/// Objects of a PDF file, written out with their cross-reference table
+struct ObjectWriter {
+    objects: Vec<Vec<u8>>,
+    compress: bool,
+}
+
+impl ObjectWriter {
+    /// Number a new object, to be set later
+    fn reserve(&mut self) -> usize {
+        self.objects.push(Vec::new());
+        self.objects.len()
+    }
+
+    fn set(&mut self, id: usize, body: impl Into<Vec<u8>>) {
+        self.objects[id - 1] = body.into();
+    }
+
+    fn add(&mut self, body: impl Into<Vec<u8>>) -> usize {
+        let id = self.reserve();
+        self.set(id, body);
+        id
+    }
+
+    /// A stream object; `entries` are its dictionary's besides the length and filter
+    fn add_stream(&mut self, entries: &str, data: Vec<u8>, compress: bool) -> usize {
+        let (filter, data) = match compress && self.compress {
+            true => (" /Filter /FlateDecode", deflate(&data)),
+            false => ("", data),
+        };
+        let mut body = format!("<< {}{} /Length {} >>\nstream\n", entries, filter, data.len()).into_bytes();
+        body.extend_from_slice(&data);
+        body.extend_from_slice(b"\nendstream");
+        self.add(body)
+    }
+
+    fn finish(self, root: usize, info: usize) -> Vec<u8> {
+        let mut file = b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(self.objects.len());
+        for (index, body) in self.objects.iter().enumerate() {
+            offsets.push(file.len());
+            file.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+            file.extend_from_slice(body);
+            file.extend_from_slice(b"\nendobj\n");
+        }
+        let mut hasher = DefaultHasher::new();
+        file.hash(&mut hasher);
+        let first = hasher.finish();
+        first.hash(&mut hasher);
+        let id = format!("{:016X}{:016X}", first, hasher.finish());
+
+        let xref = file.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(table, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R /ID [<{id}> <{id}>] >>\nstartxref\n{}\n%%EOF\n",
+            self.objects.len() + 1,
+            root,
+            info,
+            xref,
+            id = id
+        );
+        file.extend_from_slice(table.as_bytes());
+        file
+    }
+}
+
+/// What the pages use of the fonts
+#[derive(Default)]
+struct FontUsage {
+    /// Resource number of each font used, in order of first use
+    resources: BTreeMap<FontChoice, usize>,
+    /// Glyphs of each font used, with the char each stands for
+    glyphs: HashMap<FontChoice, BTreeMap<u16, char>>,
+    /// Registered fonts text asked for that may not be embedded
+    refused: BTreeSet<usize>,
+}
+
+impl FontUsage {
+    fn resource(&mut self, choice: FontChoice) -> usize {
+        let next = self.resources.len() + 1;
+        *self.resources.entry(choice).or_insert(next)
+    }
+}
+
+/// A link annotation's box, in PDF coordinates, and where it goes
+struct LinkArea {
+    rect: [f32; 4],
+    target: PdfLinkTarget,
+}
+
+/// The content stream of a page and its links
+fn page_content(page: &PdfPage, fonts: &PdfFonts, usage: &mut FontUsage, images: &HashMap<String, usize>) -> (String, Vec<LinkArea>) {
+    let flip = |y: f32| page.height - y;
+    let mut content = String::new();
+    let mut links = Vec::new();
+
+    for rule in &page.rules {
+        let _ = writeln!(
+            content,
+            "q {} w {} RG {} {} m {} {} l S Q",
+            number(rule.width),
+            rule.color.operands(),
+            number(rule.from.0),
+            number(flip(rule.from.1)),
+            number(rule.to.0),
+            number(flip(rule.to.1))
+        );
+    }
+    for placement in &page.images {
+        let Some(&resource) = images.get(&placement.image) else {
+            continue;
+        };
+        let rect = placement.rect;
+        let _ = writeln!(
+            content,
+            "q {} 0 0 {} {} {} cm /Im{} Do Q",
+            number(rect.width),
+            number(rect.height),
+            number(rect.x),
+            number(flip(rect.bottom())),
+            resource
+        );
+    }
+
+    for line in &page.lines {
+        let mut x = line.x;
+        let baseline = flip(line.baseline);
+        for run in &line.runs {
+            let start = x;
+            let size = run.style.size;
+            if let Some(index) = fonts.refused_for(&run.style) {
+                usage.refused.insert(index);
+            }
+            // Pieces of the run by the font each char is drawn in
+            let mut pieces: Vec<(FontChoice, String, f32)> = Vec::new();
+            for c in run.text.chars().filter(|c| !c.is_control() || *c == '\t') {
+                let c = if c == '\t' { ' ' } else { c };
+                let choice = fonts.font_for(&run.style, c);
+                let (code, advance) = fonts.encode(choice, c);
+                if let FontChoice::Embedded(_) = choice {
+                    usage.glyphs.entry(choice).or_default().entry(code).or_insert(c);
+                }
+                let code = match choice {
+                    FontChoice::Embedded(_) => format!("{:04X}", code),
+                    FontChoice::Standard(_) => format!("{:02X}", code),
+                };
+                let width = advance * size / GLYPH_UNITS;
+                match pieces.last_mut() {
+                    Some((last, codes, piece_width)) if *last == choice => {
+                        codes.push_str(&code);
+                        *piece_width += width;
+                    }
+                    _ => pieces.push((choice, code, width)),
+                }
+            }
+            for (choice, codes, width) in pieces {
+                let resource = usage.resource(choice);
+                let _ = writeln!(
+                    content,
+                    "BT /F{} {} Tf {} rg {} {} Td <{}> Tj ET",
+                    resource,
+                    number(size),
+                    run.style.color.operands(),
+                    number(x),
+                    number(baseline),
+                    codes
+                );
+                x += width;
+            }
+            if run.style.underline && x > start {
+                let _ = writeln!(
+                    content,
+                    "{} rg {} {} {} {} re f",
+                    run.style.color.operands(),
+                    number(start),
+                    number(baseline - size * 0.12),
+                    number(x - start),
+                    number((size * 0.05).max(0.5))
+                );
+            }
+            if let Some(target) = &run.link {
+                links.push(LinkArea {
+                    rect: [start, baseline - size * 0.25, x, baseline + size * 0.9],
+                    target: target.clone(),
+                });
+            }
+        }
+    }
+    (content, links)
+}
+
+/// The font dictionary of a registered font, embedding the glyphs used
+fn embedded_font(writer: &mut ObjectWriter, font: &OpenTypeFont, glyphs: &BTreeMap<u16, char>) -> usize {
+    let used: BTreeSet<u16> = glyphs.keys().copied().collect();
+    let mut hasher = DefaultHasher::new();
+    font.postscript_name.hash(&mut hasher);
+    used.hash(&mut hasher);
+    let mut tag_source = hasher.finish();
+    let tag: String = (0..6)
+        .map(|_| {
+            let letter = (b'A' + (tag_source % 26) as u8) as char;
+            tag_source /= 26;
+            letter
+        })
+        .collect();
+
+    // Fonts that may not be subset are embedded whole
+    let whole = font.cff || font.license.no_subsetting;
+    let (file, subtype, font_file) = if font.cff {
+        (writer.add_stream("/Subtype /OpenType", font.data.clone(), true), "/CIDFontType0", "/FontFile3")
+    } else if whole {
+        (writer.add_stream("", font.data.clone(), true), "/CIDFontType2", "/FontFile2")
+    } else {
+        (writer.add_stream("", font.subset(&used), true), "/CIDFontType2", "/FontFile2")
+    };
+    let base_font = match whole {
+        true => name(&font.postscript_name),
+        false => name(&format!("{}+{}", tag, font.postscript_name)),
+    };
+
+    let mut flags = 32;
+    if font.fixed_pitch {
+        flags |= 1;
+    }
+    if font.italic_angle != 0.0 {
+        flags |= 64;
+    }
+    let bbox: Vec<String> = font.bbox.iter().map(|&value| font.scaled(i32::from(value)).to_string()).collect();
+    let descriptor = writer.add(format!(
+        "<< /Type /FontDescriptor /FontName {} /Flags {} /FontBBox [{}] /ItalicAngle {} /Ascent {} /Descent {} /CapHeight {} /StemV 80 {} {} 0 R >>",
+        base_font,
+        flags,
+        bbox.join(" "),
+        number(font.italic_angle),
+        font.scaled(i32::from(font.ascent)),
+        font.scaled(i32::from(font.descent)),
+        font.scaled(i32::from(font.cap_height)),
+        font_file,
+        file
+    ));
+
+    let widths: Vec<String> = used.iter().map(|&glyph| format!("{} [{}]", glyph, number(font.advance(glyph)))).collect();
+    let cid_to_gid = if font.cff { "" } else { " /CIDToGIDMap /Identity" };
+    let descendant = writer.add(format!(
+        "<< /Type /Font /Subtype {} /BaseFont {} /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> /FontDescriptor {} 0 R /W [{}]{} >>",
+        subtype,
+        base_font,
+        descriptor,
+        widths.join(" "),
+        cid_to_gid
+    ));
+    let to_unicode = writer.add_stream("", to_unicode(glyphs).into_bytes(), true);
+    writer.add(format!(
+        "<< /Type /Font /Subtype /Type0 /BaseFont {} /Encoding /Identity-H /DescendantFonts [{} 0 R] /ToUnicode {} 0 R >>",
+        base_font, descendant, to_unicode
+    ))
+}
+
+/// A ToUnicode CMap from glyph codes to the chars they stand for, so text can be copied and searched
+fn to_unicode(glyphs: &BTreeMap<u16, char>) -> String {
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+         /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+         1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+    );
+    let entries: Vec<(&u16, &char)> = glyphs.iter().collect();
+    for block in entries.chunks(100) {
+        let _ = writeln!(cmap, "{} beginbfchar", block.len());
+        for (glyph, c) in block {
+            let mut units = [0u16; 2];
+            let units: Vec<u8> = c.encode_utf16(&mut units).iter().flat_map(|unit| unit.to_be_bytes()).collect();
+            let _ = writeln!(cmap, "<{:04X}> <{}>", glyph, hex(&units));
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    cmap
+}
+
+/// Write `document` as a PDF file; `images` are the data of the images its
+/// pages place, by ID. Images that cannot be read are left out with a warning
+pub fn write_pdf(document: &PdfDocument, fonts: &PdfFonts, images: &HashMap<String, Vec<u8>>, options: &PdfOptions) -> PdfExport {
+    let mut writer = ObjectWriter { objects: Vec::new(), compress: options.compress };
+    let mut warnings = Vec::new();
+    let catalog = writer.reserve();
+    let page_tree = writer.reserve();
+    let page_ids: Vec<usize> = document.pages.iter().map(|_| writer.reserve()).collect();
+
+    // Images, written once however many times they are placed
+    let mut image_resources: HashMap<String, usize> = HashMap::new();
+    let mut image_objects: Vec<(usize, usize)> = Vec::new();
+    for placement in document.pages.iter().flat_map(|page| &page.images) {
+        if image_resources.contains_key(&placement.image) {
+            continue;
+        }
+        let Some(data) = images.get(&placement.image) else {
+            warnings.push(format!("Image {} was not found and is left out", placement.image));
+            image_resources.insert(placement.image.clone(), 0);
+            continue;
+        };
+        let image = match read_image(data) {
+            Ok(image) => image,
+            Err(e) => {
+                warnings.push(format!("Image {} is left out: {}", placement.image, e));
+                image_resources.insert(placement.image.clone(), 0);
+                continue;
+            }
+        };
+        let mut entries = format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace {} /BitsPerComponent {} /Filter {}",
+            image.width, image.height, image.color_space, image.bits, image.filter
+        );
+        if let Some(parms) = &image.decode_parms {
+            let _ = write!(entries, " /DecodeParms {}", parms);
+        }
+        if let Some(decode) = image.decode {
+            let _ = write!(entries, " /Decode {}", decode);
+        }
+        if let Some(alpha) = image.alpha {
+            let mask = writer.add_stream(
+                &format!(
+                    "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent 8 /Filter /FlateDecode",
+                    image.width, image.height
+                ),
+                alpha,
+                false,
+            );
+            let _ = write!(entries, " /SMask {} 0 R", mask);
+        }
+        let object = writer.add_stream(&entries, image.data, false);
+        let resource = image_objects.len() + 1;
+        image_objects.push((resource, object));
+        image_resources.insert(placement.image.clone(), resource);
+    }
+    image_resources.retain(|_, resource| *resource != 0);
+
+    // Page contents, collecting the glyphs used
+    let mut usage = FontUsage::default();
+    let mut contents = Vec::with_capacity(document.pages.len());
+    for page in &document.pages {
+        let (content, links) = page_content(page, fonts, &mut usage, &image_resources);
+        let stream = writer.add_stream("", content.into_bytes(), true);
+        contents.push((stream, links));
+    }
+    if fonts.is_empty() && !usage.resources.is_empty() {
+        warnings.push("No fonts were registered; text uses the standard Helvetica fonts, not embedded".to_string());
+    }
+    for &index in &usage.refused {
+        let registered = &fonts.fonts[index];
+        let restriction = registered.font.license.restriction().unwrap_or_default();
+        let mut warning = format!("Font {} is not embedded as {}; its text uses other fonts", registered.family, restriction);
+        let suggestions = fonts.suggestions(&registered.family);
+        if !suggestions.is_empty() {
+            let _ = write!(warning, ". Consider replacing it with {}", suggestions.join(", "));
+        }
+        warnings.push(warning);
+    }
+
+    // Anchors, by name: the first place each is named
+    let mut anchors: HashMap<&str, (usize, f32)> = HashMap::new();
+    for (index, page) in document.pages.iter().enumerate() {
+        for anchor in &page.anchors {
+            anchors.entry(anchor.name.as_str()).or_insert((index, page.height - anchor.y));
+        }
+    }
+    let destination = |anchor: &str| {
+        anchors.get(anchor).map(|&(page, y)| format!("[{} 0 R /XYZ null {} null]", page_ids[page], number(y)))
+    };
+
+    let mut font_entries = String::new();
+    for (&choice, &resource) in &usage.resources {
+        let object = match choice {
+            FontChoice::Embedded(index) => {
+                let registered = &fonts.fonts[index];
+                if let Some(restriction) = registered.font.license.restriction() {
+                    warnings.push(format!("Font {} is embedded though {}", registered.family, restriction));
+                }
+                let glyphs = usage.glyphs.get(&choice).cloned().unwrap_or_default();
+                embedded_font(&mut writer, &fonts.fonts[index].font, &glyphs)
+            }
+            FontChoice::Standard(font) => writer.add(format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                font.name()
+            )),
+        };
+        let _ = write!(font_entries, " /F{} {} 0 R", resource, object);
+    }
+    let image_entries: String = image_objects.iter().map(|(resource, object)| format!(" /Im{} {} 0 R", resource, object)).collect();
+    let resources = writer.add(format!("<< /Font <<{} >> /XObject <<{} >> >>", font_entries, image_entries));
+
+    for ((page, &id), (content, links)) in document.pages.iter().zip(&page_ids).zip(contents) {
+        let mut annotations = Vec::new();
+        for link in links {
+            let action = match &link.target {
+                PdfLinkTarget::Uri(uri) => format!("<< /S /URI /URI {} >>", literal(uri.as_bytes())),
+                PdfLinkTarget::Anchor(anchor) if anchors.contains_key(anchor.as_str()) => {
+                    format!("<< /S /GoTo /D {} >>", literal(anchor.as_bytes()))
+                }
+                PdfLinkTarget::Anchor(anchor) => {
+                    warnings.push(format!("Link to missing anchor {} is left out", anchor));
+                    continue;
+                }
+            };
+            let rect: Vec<String> = link.rect.iter().map(|&value| number(value)).collect();
+            annotations.push(writer.add(format!(
+                "<< /Type /Annot /Subtype /Link /Rect [{}] /Border [0 0 0] /A {} >>",
+                rect.join(" "),
+                action
+            )));
+        }
+        let mut body = format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources {} 0 R /Contents {} 0 R",
+            page_tree,
+            number(page.width),
+            number(page.height),
+            resources,
+            content
+        );
+        if !annotations.is_empty() {
+            let refs: Vec<String> = annotations.iter().map(|id| format!("{} 0 R", id)).collect();
+            let _ = write!(body, " /Annots [{}]", refs.join(" "));
+        }
+        body.push_str(" >>");
+        writer.set(id, body);
+    }
+    let kids: Vec<String> = page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+    writer.set(page_tree, format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), page_ids.len()));
+
+    let mut catalog_body = format!("<< /Type /Catalog /Pages {} 0 R", page_tree);
+    if !anchors.is_empty() {
+        // Name trees are sorted by name
+        let mut names: Vec<&&str> = anchors.keys().collect();
+        names.sort_unstable();
+        let entries: Vec<String> = names
+            .into_iter()
+            .filter_map(|anchor| Some(format!("{} {}", literal(anchor.as_bytes()), destination(anchor)?)))
+            .collect();
+        let dests = writer.add(format!("<< /Names [{}] >>", entries.join(" ")));
+        let _ = write!(catalog_body, " /Names << /Dests {} 0 R >>", dests);
+    }
+    let outline: Vec<(&PdfOutlineItem, String)> = document
+        .outline
+        .iter()
+        .filter_map(|item| match destination(&item.anchor) {
+            Some(dest) => Some((item, dest)),
+            None => {
+                warnings.push(format!("Outline entry {} goes to a missing anchor and is left out", item.title));
+                None
+            }
+        })
+        .collect();
+    if !outline.is_empty() {
+        let root = write_outline(&mut writer, &outline);
+        let _ = write!(catalog_body, " /Outlines {} 0 R /PageMode /UseOutlines", root);
+    }
+    if let Some(language) = &document.metadata.language {
+        let _ = write!(catalog_body, " /Lang {}", text_string(language));
+    }
+    catalog_body.push_str(" >>");
+    writer.set(catalog, catalog_body);
+
+    let info = writer.add(info_dictionary(&document.metadata));
+    PdfExport { data: writer.finish(catalog, info), warnings }
+}
+
+/// The outline's items as a tree by level; returns the outline dictionary
+fn write_outline(writer: &mut ObjectWriter, items: &[(&PdfOutlineItem, String)]) -> usize {
+    let root = writer.reserve();
+    let ids: Vec<usize> = items.iter().map(|_| writer.reserve()).collect();
+    // Parent of each item: the nearest item before it of a lower level, or the root
+    let mut parents: Vec<Option<usize>> = Vec::with_capacity(items.len());
+    let mut stack: Vec<usize> = Vec::new();
+    for (index, (item, _)) in items.iter().enumerate() {
+        while stack.last().is_some_and(|&open| items[open].0.level >= item.level) {
+            stack.pop();
+        }
+        parents.push(stack.last().copied());
+        stack.push(index);
+    }
+    let children = |parent: Option<usize>| -> Vec<usize> { (0..items.len()).filter(|&index| parents[index] == parent).collect() };
+    let descendants = |index: usize| {
+        let mut count = 0;
+        let mut next = index + 1;
+        while next < items.len() && items[next].0.level > items[index].0.level {
+            count += 1;
+            next += 1;
+        }
+        count
+    };
+
+    for (index, (item, dest)) in items.iter().enumerate() {
+        let siblings = children(parents[index]);
+        let position = siblings.iter().position(|&sibling| sibling == index).unwrap_or(0);
+        let mut body = format!(
+            "<< /Title {} /Parent {} 0 R /Dest {}",
+            text_string(&item.title),
+            parents[index].map_or(root, |parent| ids[parent]),
+            dest
+        );
+        if position > 0 {
+            let _ = write!(body, " /Prev {} 0 R", ids[siblings[position - 1]]);
+        }
+        if let Some(&next) = siblings.get(position + 1) {
+            let _ = write!(body, " /Next {} 0 R", ids[next]);
+        }
+        let own = children(Some(index));
+        if let (Some(first), Some(last)) = (own.first(), own.last()) {
+            let _ = write!(body, " /First {} 0 R /Last {} 0 R /Count {}", ids[*first], ids[*last], descendants(index));
+        }
+        body.push_str(" >>");
+        writer.set(ids[index], body);
+    }
+    let top = children(None);
+    writer.set(
+        root,
+        format!(
+            "<< /Type /Outlines /First {} 0 R /Last {} 0 R /Count {} >>",
+            ids[top[0]],
+            ids[top[top.len() - 1]],
+            items.len()
+        ),
+    );
+    root
+}
+
+fn info_dictionary(metadata: &PdfMetadata) -> String {
+    let mut info = String::from("<<");
+    for (key, value) in [
+        ("Title", &metadata.title),
+        ("Author", &metadata.author),
+        ("Subject", &metadata.subject),
+        ("Creator", &metadata.creator),
+    ] {
+        if let Some(value) = value {
+            let _ = write!(info, " /{} {}", key, text_string(value));
+        }
+    }
+    if !metadata.keywords.is_empty() {
+        let _ = write!(info, " /Keywords {}", text_string(&metadata.keywords.join(", ")));
+    }
+    info.push_str(" /Producer (Velum)");
+    if let Some(created) = &metadata.created {
+        let _ = write!(info, " /CreationDate {}", date(created));
+    }
+    if let Some(modified) = &metadata.modified {
+        let _ = write!(info, " /ModDate {}", date(modified));
+    }
+    info.push_str(" >>");
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_layout::{LineLayoutInfo, ParagraphProperties};
+    use crate::page_layout::PageLayout;
+
+    fn paragraph(text: &str, lines: &[(usize, usize)]) -> ParagraphLayout {
+        ParagraphLayout {
+            text: text.to_string(),
+            max_width: 400.0,
+            content_width: 400.0,
+            lines: lines
+                .iter()
+                .enumerate()
+                .map(|(line_number, &(start, end))| LineLayoutInfo {
+                    line_number,
+                    start,
+                    end,
+                    width: 100.0,
+                    break_type: "SoftBreak".to_string(),
+                    char_count: end - start,
+                    is_bidi: false,
+                    trailing_whitespace: 0.0,
+                    offset_x: 0.0,
+                    line_height: 14.4,
+                })
+                .collect(),
+            total_height: 14.4 * lines.len() as f32,
+            base_line_height: 12.0,
+            actual_line_height: 14.4,
+            has_bidi: false,
+            properties: ParagraphProperties::default(),
+        }
+    }
+
+    /// A TrueType font with glyphs for "A" (a square), "B" (a composite of
+    /// the square) and "C" (no outline), 1000 units per em, with OS/2 `fs_type`
+    fn test_font(fs_type: u16) -> Vec<u8> {
+        let mut head = vec![0u8; 54];
+        head[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        head[40..42].copy_from_slice(&600i16.to_be_bytes());
+        head[42..44].copy_from_slice(&700i16.to_be_bytes());
+        let mut hhea = vec![0u8; 36];
+        hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[34..36].copy_from_slice(&4u16.to_be_bytes());
+        let mut maxp = vec![0u8; 6];
+        maxp[0..4].copy_from_slice(&0x0000_5000u32.to_be_bytes());
+        maxp[4..6].copy_from_slice(&4u16.to_be_bytes());
+        let hmtx: Vec<u8> = [500u16, 600, 650, 700].iter().flat_map(|advance| [advance.to_be_bytes(), [0, 0]].concat()).collect();
+
+        // Glyph 1: one contour of four points; glyph 2: glyph 1 offset by (10, 10)
+        let mut square = Vec::new();
+        for value in [1i16, 0, 0, 600, 700, 3, 0] {
+            square.extend_from_slice(&value.to_be_bytes());
+        }
+        square.extend_from_slice(&[1, 1, 1, 1]);
+        for value in [0i16, 600, 0, -600, 0, 0, 700, 0] {
+            square.extend_from_slice(&value.to_be_bytes());
+        }
+        square.resize(square.len().next_multiple_of(4), 0);
+        let mut composite = Vec::new();
+        for value in [-1i16, 0, 0, 600, 700, 0x0002, 1] {
+            composite.extend_from_slice(&value.to_be_bytes());
+        }
+        composite.extend_from_slice(&[10, 10, 0, 0]);
+        let glyf = [square.clone(), composite.clone()].concat();
+        let loca: Vec<u8> = [0u16, 0, square.len() as u16 / 2, glyf.len() as u16 / 2, glyf.len() as u16 / 2]
+            .iter()
+            .flat_map(|offset| offset.to_be_bytes())
+            .collect();
+
+        // Format 4: A-C to glyphs 1-3 by delta, and the closing segment
+        let mut cmap = Vec::new();
+        for value in [0u16, 1, 3, 1, 0, 12] {
+            cmap.extend_from_slice(&value.to_be_bytes());
+        }
+        for value in [4u16, 32, 0, 4, 4, 1, 0, b'C' as u16, 0xFFFF, 0, b'A' as u16, 0xFFFF] {
+            cmap.extend_from_slice(&value.to_be_bytes());
+        }
+        for value in [(1i32 - b'A' as i32) as u16, 1, 0, 0] {
+            cmap.extend_from_slice(&value.to_be_bytes());
+        }
+
+        sfnt(vec![
+            (*b"head", head),
+            (*b"hhea", hhea),
+            (*b"maxp", maxp),
+            (*b"hmtx", hmtx),
+            (*b"loca", loca),
+            (*b"glyf", glyf),
+            (*b"cmap", cmap),
+            (*b"OS/2", [[0u8; 8].as_slice(), &fs_type.to_be_bytes()].concat()),
+        ])
+    }
+
+    fn png(color_type: u8, pixels: &[u8], width: u32) -> Vec<u8> {
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+            chunk.extend_from_slice(kind);
+            chunk.extend_from_slice(data);
+            chunk.extend_from_slice(&[0; 4]);
+            chunk
+        };
+        let mut header = width.to_be_bytes().to_vec();
+        let channels = if color_type == 6 { 4 } else { 3 };
+        let rows = pixels.len() as u32 / (width * channels);
+        header.extend_from_slice(&rows.to_be_bytes());
+        header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+        let mut raw = Vec::new();
+        for row in pixels.chunks(width as usize * channels as usize) {
+            // Sub filter on every row
+            raw.push(1);
+            for (i, &byte) in row.iter().enumerate() {
+                let left = if i >= channels as usize { row[i - channels as usize] } else { 0 };
+                raw.push(byte.wrapping_sub(left));
+            }
+        }
+        [
+            b"\x89PNG\r\n\x1a\n".to_vec(),
+            chunk(b"IHDR", &header),
+            chunk(b"IDAT", &deflate(&raw)),
+            chunk(b"IEND", &[]),
+        ]
+        .concat()
+    }
+
+    fn text(export: &PdfExport) -> String {
+        String::from_utf8_lossy(&export.data).into_owned()
+    }
+
+    #[test]
+    fn test_pages_from_layout() {
+        let bold = PdfTextStyle { bold: true, ..Default::default() };
+        let paragraphs = vec![paragraph("Hello bold world", &[(0, 11), (11, 16)]), paragraph("Second", &[(0, 6)])];
+        let content = vec![
+            ParagraphContent {
+                spans: vec![PdfSpan { range: 6..10, style: bold.clone(), link: Some(PdfLinkTarget::Anchor("second".to_string())) }],
+                anchors: Vec::new(),
+            },
+            ParagraphContent { spans: Vec::new(), anchors: vec![(0, "second".to_string())] },
+        ];
+        let config = PageConfig::letter();
+        let mut layout = PageLayout::with_page_config(config.clone());
+        let pages = layout.layout_pages(&paragraphs);
+        let page = page_from_layout(&pages[0], &config, &paragraphs, &content);
+
+        assert_eq!((page.width, page.height), (612.0, 792.0));
+        let runs: Vec<Vec<(&str, bool)>> = page
+            .lines
+            .iter()
+            .map(|line| line.runs.iter().map(|run| (run.text.as_str(), run.style.bold)).collect())
+            .collect();
+        assert_eq!(runs, vec![vec![("Hello ", false), ("bold", true), (" ", false)], vec![("world", false)], vec![("Second", false)]]);
+        assert_eq!(page.lines[0].x, 72.0);
+        assert!((page.lines[1].baseline - page.lines[0].baseline - 14.4).abs() < 0.01);
+        assert_eq!(page.anchors.len(), 1);
+        assert_eq!(page.anchors[0].name, "second");
+        assert!((page.anchors[0].y - (page.lines[2].baseline - 14.4 * BASELINE_RATIO)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_stories_and_footnotes() {
+        let mut page = PdfPage::new(612.0, 792.0);
+        let header = vec![paragraph("Header", &[(0, 6)])];
+        page.place_story(Rect::new(72.0, 36.0, 468.0, 36.0), &header, &[], StoryAnchor::Top);
+        let footer = vec![paragraph("Page 1", &[(0, 6)])];
+        page.place_story(Rect::new(72.0, 720.0, 468.0, 36.0), &footer, &[], StoryAnchor::Bottom);
+        let body = Rect::new(72.0, 72.0, 468.0, 648.0);
+        let height = page.place_footnotes(body, &[paragraph("1 A note.", &[(0, 9)])], &[]);
+
+        let baselines: Vec<(String, f32)> = page.lines.iter().map(|line| (line.runs[0].text.clone(), line.baseline)).collect();
+        assert_eq!(baselines[0], ("Header".to_string(), 36.0 + 14.4 * BASELINE_RATIO));
+        assert_eq!(baselines[1].0, "Page 1");
+        assert!((baselines[1].1 - (756.0 - 14.4 + 14.4 * BASELINE_RATIO)).abs() < 0.01);
+        assert!((height - (14.4 + 2.0 * FOOTNOTE_GAP)).abs() < 0.01);
+        assert!((baselines[2].1 - (720.0 - 14.4 + 14.4 * BASELINE_RATIO)).abs() < 0.01);
+        let separator = page.rules[0];
+        assert_eq!((separator.from.0, separator.to.0), (72.0, 72.0 + 156.0));
+        assert!((separator.from.1 - (720.0 - height + FOOTNOTE_GAP)).abs() < 0.01);
+
+        page.place_table_grid(72.0, 100.0, &[100.0, 50.0], &[20.0], 0.5, PdfColor::BLACK);
+        assert_eq!(page.rules.len(), 1 + 2 + 3);
+    }
+
+    #[test]
+    fn test_font_subset_keeps_glyph_ids() {
+        let font = OpenTypeFont::parse(test_font(0)).unwrap();
+        assert_eq!((font.glyph('A'), font.glyph('B'), font.glyph('C'), font.glyph('D')), (Some(1), Some(2), Some(3), None));
+        assert_eq!(font.advance(2), 650.0);
+
+        // The composite brings in the glyph it is made of; the subset has no
+        // cmap, so put the original back to read it again
+        let data = font.subset(&BTreeSet::from([2]));
+        assert!(OpenTypeFont::parse(data.clone()).is_err());
+        let mut tables: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+        for index in 0..read_u16(&data, 4).unwrap() as usize {
+            let record = 12 + 16 * index;
+            let tag: [u8; 4] = data[record..record + 4].try_into().unwrap();
+            let offset = read_u32(&data, record + 8).unwrap() as usize;
+            let length = read_u32(&data, record + 12).unwrap() as usize;
+            tables.push((tag, data[offset..offset + length].to_vec()));
+        }
+        let tags: Vec<&[u8; 4]> = tables.iter().map(|(tag, _)| tag).collect();
+        assert_eq!(tags, [b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp"]);
+        tables.push((*b"cmap", font.table(b"cmap").unwrap().to_vec()));
+        let reparsed = OpenTypeFont::parse(sfnt(tables)).unwrap();
+        assert!(reparsed.long_loca);
+        let outline = |glyph| reparsed.glyph_range(glyph).unwrap().len();
+        assert_eq!((outline(0), outline(1), outline(2), outline(3)), (0, 36, 20, 0));
+        assert_eq!(reparsed.advance(3), 700.0);
+    }
+
+    #[test]
+    fn test_write_embeds_subset_fonts_and_links() {
+        let mut fonts = PdfFonts::new();
+        fonts.register("Test", false, false, test_font(0)).unwrap();
+        assert!(fonts.register("Broken", false, false, b"not a font".to_vec()).is_err());
+
+        let mut page = PdfPage::new(200.0, 100.0);
+        page.lines.push(PdfLine {
+            x: 10.0,
+            baseline: 20.0,
+            runs: vec![
+                PdfRun { text: "AB".to_string(), style: PdfTextStyle { font: Some("Test".to_string()), ..Default::default() }, link: None },
+                PdfRun {
+                    text: "A".to_string(),
+                    style: PdfTextStyle { underline: true, color: PdfColor::from_hex("#FF0000").unwrap(), ..Default::default() },
+                    link: Some(PdfLinkTarget::Uri("https://example.com/(x)".to_string())),
+                },
+            ],
+        });
+        page.anchors.push(PdfAnchor { name: "top".to_string(), y: 0.0 });
+        let document = PdfDocument {
+            pages: vec![page],
+            outline: vec![
+                PdfOutlineItem { title: "Top".to_string(), level: 1, anchor: "top".to_string() },
+                PdfOutlineItem { title: "Gone".to_string(), level: 1, anchor: "missing".to_string() },
+            ],
+            metadata: PdfMetadata { title: Some("Café".to_string()), author: Some("Ann".to_string()), ..Default::default() },
+        };
+        let export = write_pdf(&document, &fonts, &HashMap::new(), &PdfOptions { compress: false });
+        let pdf = text(&export);
+
+        assert!(pdf.starts_with("%PDF-1.7\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        // "AB" then "A" after 12pt * 0.6 + 12pt * 0.65
+        assert!(pdf.contains("BT /F1 12 Tf 0 0 0 rg 10 80 Td <00010002> Tj ET"), "{}", pdf);
+        assert!(pdf.contains("BT /F1 12 Tf 1 0 0 rg 25 80 Td <0001> Tj ET"));
+        assert!(pdf.contains("/Subtype /CIDFontType2"));
+        assert!(pdf.contains("/W [1 [600] 2 [650]]"));
+        assert!(pdf.contains("<0001> <0041>\n<0002> <0042>"));
+        assert!(pdf.contains(r"/URI (https://example.com/\(x\))"));
+        assert!(pdf.contains("/Names [(top) [3 0 R /XYZ null 100 null]]"));
+        assert!(pdf.contains("/Title (Top)"));
+        assert!(pdf.contains("/Title <FEFF00430061006600E9>"));
+        assert!(pdf.contains("/Author (Ann)"));
+        assert_eq!(export.warnings, vec!["Outline entry Gone goes to a missing anchor and is left out".to_string()]);
+
+        // Every cross-reference entry points at its object
+        let xref = pdf.rfind("xref\n").unwrap();
+        for (index, entry) in pdf[xref..].lines().skip(3).take_while(|line| line.ends_with(" n ")).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", index + 1)));
+        }
+    }
+
+    #[test]
+    fn test_font_licenses() {
+        let mut fonts = PdfFonts::new();
+        fonts.register("Calibri", false, false, test_font(0x0002)).unwrap();
+        fonts.register("Open", false, false, test_font(0x0100)).unwrap();
+        let licenses = fonts.licenses();
+        assert!(!licenses[0].embedded);
+        assert_eq!(licenses[0].suggestions, vec!["Carlito".to_string(), "Open".to_string()]);
+        assert!(licenses[1].embedded && licenses[1].license.no_subsetting);
+
+        let mut page = PdfPage::new(100.0, 100.0);
+        let style = PdfTextStyle { font: Some("Calibri".to_string()), ..Default::default() };
+        page.lines.push(PdfLine { x: 0.0, baseline: 50.0, runs: vec![PdfRun { text: "AB".to_string(), style, link: None }] });
+        let document = PdfDocument { pages: vec![page], ..Default::default() };
+
+        // Refused: the text is drawn in the other font, embedded whole as it may not be subset
+        let export = write_pdf(&document, &fonts, &HashMap::new(), &PdfOptions { compress: false });
+        assert_eq!(
+            export.warnings,
+            vec!["Font Calibri is not embedded as its license does not allow embedding; its text uses other fonts. \
+                  Consider replacing it with Carlito, Open"
+                .to_string()]
+        );
+        let pdf = text(&export);
+        assert_eq!(pdf.matches("/Subtype /Type0").count(), 1);
+        assert!(pdf.contains("/BaseFont /Font /"), "a whole font has no subset tag");
+
+        // Warned: embedded anyway
+        fonts.set_license_policy(FontLicensePolicy::Warn);
+        let export = write_pdf(&document, &fonts, &HashMap::new(), &PdfOptions { compress: false });
+        assert_eq!(export.warnings, vec!["Font Calibri is embedded though its license does not allow embedding".to_string()]);
+        assert!(text(&export).contains("+Font"));
+
+        // Refused with nothing else registered: the standard fonts
+        let mut restricted = PdfFonts::new();
+        restricted.register("Calibri", false, false, test_font(0x0002)).unwrap();
+        let pdf = text(&write_pdf(&document, &restricted, &HashMap::new(), &PdfOptions { compress: false }));
+        assert!(pdf.contains("/BaseFont /Helvetica "));
+    }
+
+    #[test]
+    fn test_standard_fonts_without_registered_fonts() {
+        let mut page = PdfPage::new(100.0, 100.0);
+        page.lines.push(PdfLine {
+            x: 0.0,
+            baseline: 50.0,
+            runs: vec![PdfRun { text: "Hé—漢".to_string(), style: PdfTextStyle { bold: true, ..Default::default() }, link: None }],
+        });
+        let document = PdfDocument { pages: vec![page], ..Default::default() };
+        let export = write_pdf(&document, &PdfFonts::new(), &HashMap::new(), &PdfOptions::default());
+        assert_eq!(export.warnings.len(), 1);
+
+        let export = write_pdf(&document, &PdfFonts::new(), &HashMap::new(), &PdfOptions { compress: false });
+        let pdf = text(&export);
+        assert!(pdf.contains("/BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding"));
+        assert!(pdf.contains("<48E9973F> Tj"));
+    }
+
+    #[test]
+    fn test_images() {
+        let opaque = png(2, &[255, 0, 0, 0, 255, 0], 2);
+        let translucent = png(6, &[255, 0, 0, 128, 0, 0, 255, 255], 2);
+        let jpeg = [
+            vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0],
+            vec![0xFF, 0xC0, 0, 11, 8, 0, 3, 0, 4, 3, 1, 0x22, 0],
+            vec![0xFF, 0xD9],
+        ]
+        .concat();
+
+        let image = read_image(&opaque).unwrap();
+        assert_eq!((image.width, image.height, image.color_space.as_str()), (2, 1, "/DeviceRGB"));
+        assert_eq!(image.decode_parms.as_deref(), Some("<< /Predictor 15 /Colors 3 /BitsPerComponent 8 /Columns 2 >>"));
+
+        let image = read_image(&translucent).unwrap();
+        let inflate = |data: &[u8]| {
+            let mut out = Vec::new();
+            ZlibDecoder::new(data).read_to_end(&mut out).unwrap();
+            out
+        };
+        assert_eq!(inflate(&image.data), [255, 0, 0, 0, 0, 255]);
+        assert_eq!(inflate(image.alpha.as_ref().unwrap()), [128, 255]);
+
+        let image = read_image(&jpeg).unwrap();
+        assert_eq!((image.width, image.height, image.filter), (4, 3, "/DCTDecode"));
+        assert!(read_image(b"GIF89a....").is_err());
+
+        // Other formats are decoded to pixels, and vector pictures rasterized
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="96" height="48"><rect width="48" height="48" fill="red"/></svg>"#;
+        let image = read_image(svg).unwrap();
+        assert_eq!((image.width, image.height, image.color_space.as_str()), (300, 150, "/DeviceRGB"));
+        assert_eq!(inflate(&image.data)[..3], [255, 0, 0]);
+        assert_eq!(inflate(image.alpha.as_ref().unwrap())[299], 0);
+
+        let mut page = PdfPage::new(100.0, 100.0);
+        for image in ["photo", "photo", "broken"] {
+            page.images.push(PdfImagePlacement { image: image.to_string(), rect: Rect::new(10.0, 10.0, 40.0, 30.0) });
+        }
+        let images = HashMap::from([("photo".to_string(), translucent), ("broken".to_string(), b"BM".to_vec())]);
+        let export = write_pdf(&PdfDocument { pages: vec![page], ..Default::default() }, &PdfFonts::new(), &images, &PdfOptions { compress: false });
+        let pdf = text(&export);
+        assert_eq!(pdf.matches("/Subtype /Image").count(), 2);
+        assert!(pdf.contains("/SMask"));
+        assert_eq!(pdf.matches("q 40 0 0 30 10 60 cm /Im1 Do Q").count(), 2);
+        assert_eq!(export.warnings, vec!["Image broken is left out: Image error: Invalid image dimensions (zero or negative)".to_string()]);
+    }
+
+    #[test]
+    fn test_outline_tree() {
+        let mut page = PdfPage::new(100.0, 100.0);
+        for name in ["a", "b", "c", "d"] {
+            page.anchors.push(PdfAnchor { name: name.to_string(), y: 10.0 });
+        }
+        let item = |title: &str, level| PdfOutlineItem { title: title.to_string(), level, anchor: title.to_string() };
+        let document = PdfDocument { pages: vec![page], outline: vec![item("a", 1), item("b", 2), item("c", 2), item("d", 1)], ..Default::default() };
+        let pdf = text(&write_pdf(&document, &PdfFonts::new(), &HashMap::new(), &PdfOptions { compress: false }));
+
+        let object = |title: &str| {
+            let at = pdf.find(&format!("/Title ({})", title)).unwrap();
+            let start = pdf[..at].rfind(" 0 obj").unwrap();
+            let id_start = pdf[..start].rfind('\n').unwrap() + 1;
+            (pdf[id_start..start].to_string(), pdf[at..pdf[at..].find(">>").unwrap() + at].to_string())
+        };
+        let (a, a_body) = object("a");
+        let (b, b_body) = object("b");
+        let (c, c_body) = object("c");
+        let (d, _) = object("d");
+        assert!(a_body.contains(&format!("/Next {} 0 R /First {} 0 R /Last {} 0 R /Count 2", d, b, c)), "{}", a_body);
+        assert!(b_body.contains(&format!("/Parent {} 0 R", a)) && b_body.contains(&format!("/Next {} 0 R", c)));
+        assert!(c_body.contains(&format!("/Prev {} 0 R", b)));
+        assert!(pdf.contains("/Type /Outlines") && pdf.contains("/Count 4 >>"));
+    }
+}
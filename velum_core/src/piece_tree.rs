@@ -1,12 +1,27 @@
 use serde::{Serialize, Deserialize};
 use crate::find::{SearchOptions, SearchResult, SearchResultSet, search, find_all_in_text};
 use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
 use log::trace;
 
+mod btree;
+mod history;
+mod multi_selection;
+mod paragraphs;
+
+pub use btree::{Iter as PieceIter, PieceBTree, PieceSummary};
+pub use history::{Change, HistoryMemoryUsage, HistoryNodeId, HistoryNodeInfo, SavedHistory};
+pub use multi_selection::{DeleteDirection, MultiSelection};
+pub use paragraphs::ParagraphAttributes;
+use history::{History, Route, UndoGroup};
+use paragraphs::ParagraphTable;
+
+/// Longest piece created from new text; lookups scan at most one piece, so this bounds their cost
+const MAX_PIECE_BYTES: usize = 16 * 1024;
+
 /// Represents which buffer a piece comes from
 /// -1 means original buffer (index 0), other values are buffer indices
-const MAX_UNDO_DEPTH: usize = 100;
-
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct BufferId(pub isize);
 
@@ -59,6 +74,93 @@ impl TextAttributes {
     pub fn new() -> Self {
         TextAttributes::default()
     }
+
+    /// These attributes with every field set in `other` taken from it
+    pub fn merged_with(&self, other: &TextAttributes) -> TextAttributes {
+        TextAttributes {
+            bold: other.bold.or(self.bold),
+            italic: other.italic.or(self.italic),
+            underline: other.underline.or(self.underline),
+            font_size: other.font_size.or(self.font_size),
+            font_family: other.font_family.clone().or_else(|| self.font_family.clone()),
+            foreground: other.foreground.clone().or_else(|| self.foreground.clone()),
+            background: other.background.clone().or_else(|| self.background.clone()),
+        }
+    }
+}
+
+/// Whether an attribute is the same across a range of text
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "state", content = "value", rename_all = "snake_case")]
+pub enum AttributeState<T> {
+    /// Every char has this value (for flags: every char has it on)
+    All(T),
+    /// No char has the attribute set (for flags: on)
+    None,
+    /// Chars differ
+    Mixed,
+}
+
+impl<T: PartialEq> AttributeState<T> {
+    fn of(value: Option<T>) -> Self {
+        value.map_or(AttributeState::None, AttributeState::All)
+    }
+
+    fn combine(self, other: Self) -> Self {
+        if self == other {
+            self
+        } else {
+            AttributeState::Mixed
+        }
+    }
+}
+
+/// Per-attribute state of a range, e.g. for toolbar buttons
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommonAttributes {
+    pub bold: AttributeState<bool>,
+    pub italic: AttributeState<bool>,
+    pub underline: AttributeState<bool>,
+    pub font_size: AttributeState<u16>,
+    pub font_family: AttributeState<String>,
+    pub foreground: AttributeState<String>,
+    pub background: AttributeState<String>,
+}
+
+impl CommonAttributes {
+    fn of(attributes: Option<&TextAttributes>) -> Self {
+        let attributes = attributes.cloned().unwrap_or_default();
+        let flag = |on: Option<bool>| AttributeState::of(on.filter(|on| *on));
+        CommonAttributes {
+            bold: flag(attributes.bold),
+            italic: flag(attributes.italic),
+            underline: flag(attributes.underline),
+            font_size: AttributeState::of(attributes.font_size),
+            font_family: AttributeState::of(attributes.font_family),
+            foreground: AttributeState::of(attributes.foreground),
+            background: AttributeState::of(attributes.background),
+        }
+    }
+
+    fn combine(self, other: Self) -> Self {
+        CommonAttributes {
+            bold: self.bold.combine(other.bold),
+            italic: self.italic.combine(other.italic),
+            underline: self.underline.combine(other.underline),
+            font_size: self.font_size.combine(other.font_size),
+            font_family: self.font_family.combine(other.font_family),
+            foreground: self.foreground.combine(other.foreground),
+            background: self.background.combine(other.background),
+        }
+    }
+}
+
+/// Attributes of a run of consecutive chars, as recorded for undo
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AttributeSpan {
+    /// Length in chars
+    pub length: usize,
+    pub attributes: Option<TextAttributes>,
 }
 
 /// Represents a piece of text from a buffer
@@ -112,7 +214,7 @@ impl Piece {
 }
 
 /// Represents a text selection with anchor and active positions
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Selection {
     /// The anchor position (where selection started, stays fixed during shift+arrow)
     pub anchor: usize,
@@ -164,40 +266,99 @@ impl Default for Selection {
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Change {
-    Insert {
-        offset: usize,
-        length: usize,
-    },
-    Delete {
-        offset: usize,
-        text: String,
-    },
+/// Table cell holding the caret
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellPosition {
+    pub table: usize,
+    pub row: usize,
+    pub column: usize,
+}
+
+/// Editor state captured with every undo step and restored with the text
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditorState {
+    /// The primary selection first, then any additional carets
+    pub selections: Vec<Selection>,
+    /// Char offset of the first visible character
+    pub scroll_anchor: Option<usize>,
+    pub active_cell: Option<CellPosition>,
+}
+
+/// Moves a char offset past an edit that replaced `removed` chars at `at` with `inserted` chars
+fn translate_offset(offset: usize, at: usize, removed: usize, inserted: usize) -> usize {
+    if offset <= at {
+        offset
+    } else if offset < at + removed {
+        at
+    } else {
+        offset - removed + inserted
+    }
+}
+
+/// A broken invariant of a piece tree, as found by [`PieceTree::validate`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TreeCorruption {
+    #[error("Piece {piece} refers to missing buffer {buffer}")]
+    MissingBuffer { piece: usize, buffer: usize },
+
+    #[error("Piece {piece} spans bytes {start}..{end} of a {buffer_length}-byte buffer")]
+    OutOfBounds { piece: usize, start: usize, end: usize, buffer_length: usize },
+
+    #[error("Piece {piece} is empty")]
+    EmptyPiece { piece: usize },
+
+    #[error("Piece {piece} starts or ends inside a char")]
+    SplitChar { piece: usize },
+
+    #[error("Piece {piece} records {recorded} chars but holds {actual}")]
+    CharLength { piece: usize, recorded: usize, actual: usize },
+
+    #[error("Piece {piece} records {recorded} line breaks but holds {actual}")]
+    LineBreaks { piece: usize, recorded: usize, actual: usize },
+
+    #[error("Piece {piece} has malformed attributes: {reason}")]
+    Attributes { piece: usize, reason: &'static str },
+
+    #[error("Cached piece counts disagree with the pieces")]
+    Summaries,
+
+    #[error("Total {measure} is {recorded} but the pieces add up to {actual}")]
+    Total { measure: &'static str, recorded: usize, actual: usize },
+
+    #[error("{recorded} paragraph entries for {actual} paragraphs")]
+    Paragraphs { recorded: usize, actual: usize },
 }
 
 /// Main Piece Tree data structure
 pub struct PieceTree {
-    /// All pieces in the document
-    pub pieces: Vec<Piece>,
-    /// Map of buffer IDs to their content
-    pub buffers: Vec<String>,
+    /// All pieces in the document, in order
+    pub pieces: PieceBTree,
+    /// Map of buffer IDs to their content; buffers are never modified once
+    /// added, only emptied when neither the text nor the history refers to them
+    pub buffers: Vec<Arc<str>>,
     /// Total character count
     pub total_char_count: usize,
     /// Total byte length
     pub total_length: usize,
+    /// Attributes of each paragraph, kept in step with the line breaks
+    paragraphs: ParagraphTable,
     /// Next buffer index to assign (0 is original, starts from 1 for adds)
     next_buffer_index: isize,
-    /// Undo stack
-    undo_stack: Vec<Change>,
-    /// Redo stack
-    redo_stack: Vec<Change>,
+    /// Undo history tree
+    history: History,
+    /// Bytes of buffers only history checkpoints refer to, as of the last release
+    retained_bytes: usize,
+    /// History evictions when buffers were last released
+    released_at: usize,
     /// Whether we are currently undoing or redoing
     is_undoing_redoing: bool,
     /// Current text selection
     pub selection: Selection,
-    /// Saved selection for undo/redo
-    saved_selection: Option<Selection>,
+    /// Carets besides the primary selection
+    extra_selections: Vec<Selection>,
+    /// Char offset of the first visible character, as reported by the host
+    scroll_anchor: Option<usize>,
+    active_cell: Option<CellPosition>,
 }
 
 impl PieceTree {
@@ -209,46 +370,57 @@ impl PieceTree {
         let length = content.len();
         let char_length = content.chars().count();
 
-        // Initial buffer
-        let buffers = vec![content];
+        // Pieces covering the whole initial buffer
+        let pieces = chunk_piece(Piece::new(0, length, BufferId::ORIGINAL, char_length), content.as_bytes());
+        let buffers = vec![Arc::from(content)];
 
-        // Single piece covering the whole buffer
-        let piece = Piece::new(0, length, BufferId::ORIGINAL, char_length);
+        let paragraph_count = pieces.iter().map(|(_, line_breaks)| line_breaks).sum::<usize>() + 1;
 
         PieceTree {
-            pieces: vec![piece],
+            pieces: PieceBTree::from_pieces(pieces),
             buffers,
             total_char_count: char_length,
             total_length: length,
+            paragraphs: ParagraphTable::new(paragraph_count),
             next_buffer_index: 1,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            history: History::default(),
+            retained_bytes: 0,
+            released_at: 0,
             is_undoing_redoing: false,
             selection: Selection::default(),
-            saved_selection: None,
+            extra_selections: Vec::new(),
+            scroll_anchor: None,
+            active_cell: None,
         }
     }
 
     /// Creates an empty PieceTree
     pub fn empty() -> Self {
         PieceTree {
-            pieces: Vec::new(),
-            buffers: vec![String::new()],
+            pieces: PieceBTree::new(),
+            buffers: vec![Arc::from("")],
             total_char_count: 0,
             total_length: 0,
+            paragraphs: ParagraphTable::new(1),
             next_buffer_index: 1,  // First insert should use BufferId(1), referencing buffers[1]
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            history: History::default(),
+            retained_bytes: 0,
+            released_at: 0,
             is_undoing_redoing: false,
             selection: Selection::default(),
-            saved_selection: None,
+            extra_selections: Vec::new(),
+            scroll_anchor: None,
+            active_cell: None,
         }
     }
 
     /// Creates a new PieceTree from pre-loaded data (e.g. from OOXML)
     pub fn from_loaded_data(pieces: Vec<Piece>, buffers: Vec<String>) -> Self {
-        let total_char_count = pieces.iter().map(|p| p.piece_char_length).sum();
-        let total_length = pieces.iter().map(|p| p.length).sum();
+        let buffers: Vec<Arc<str>> = buffers.into_iter().map(Arc::from).collect();
+        let pieces = Self::index_pieces(pieces, &buffers);
+        let total_char_count = pieces.summary().chars;
+        let total_length = pieces.summary().bytes;
+        let paragraphs = ParagraphTable::new(pieces.summary().line_breaks + 1);
 
         let next_buffer_index = if buffers.len() > 1 {
             buffers.len() as isize
@@ -261,12 +433,16 @@ impl PieceTree {
             buffers,
             total_char_count,
             total_length,
+            paragraphs,
             next_buffer_index,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            history: History::default(),
+            retained_bytes: 0,
+            released_at: 0,
             is_undoing_redoing: false,
             selection: Selection::default(),
-            saved_selection: None,
+            extra_selections: Vec::new(),
+            scroll_anchor: None,
+            active_cell: None,
         }
     }
 
@@ -285,6 +461,79 @@ impl PieceTree {
         buffer_id.to_index()
     }
 
+    /// Builds the piece index, counting each piece's line breaks
+    fn index_pieces(pieces: Vec<Piece>, buffers: &[Arc<str>]) -> PieceBTree {
+        PieceBTree::from_pieces(pieces.into_iter().map(|piece| {
+            let line_breaks = count_line_breaks(Self::bytes_of(buffers, &piece));
+            (piece, line_breaks)
+        }))
+    }
+
+    /// Replaces all pieces, e.g. after changing attributes piece by piece
+    pub fn set_pieces(&mut self, pieces: Vec<Piece>) {
+        self.pieces = Self::index_pieces(pieces, &self.buffers);
+        self.total_char_count = self.pieces.summary().chars;
+        self.total_length = self.pieces.summary().bytes;
+        self.paragraphs.resize(self.pieces.summary().line_breaks + 1);
+    }
+
+    /// Text bytes a piece refers to
+    fn bytes_of<'a>(buffers: &'a [Arc<str>], piece: &Piece) -> &'a [u8] {
+        buffers
+            .get(Self::buffer_idx(&piece.buffer_id))
+            .and_then(|b| b.as_bytes().get(piece.start..piece.end()))
+            .unwrap_or(&[])
+    }
+
+    fn piece_bytes(&self, piece: &Piece) -> &[u8] {
+        Self::bytes_of(&self.buffers, piece)
+    }
+
+    /// Bytes [from, to) of a piece as a new piece, with its line break count
+    fn sub_piece(&self, piece: &Piece, from: usize, to: usize) -> (Piece, usize) {
+        let bytes = &self.piece_bytes(piece)[from..to];
+        let sub = Piece::new_with_attrs(
+            piece.start + from,
+            to - from,
+            piece.buffer_id,
+            count_chars(bytes),
+            piece.attributes.clone(),
+        );
+        (sub, count_line_breaks(bytes))
+    }
+
+    /// Takes an immutable snapshot of the current content
+    ///
+    /// Pieces are shared copy-on-write and buffers are append-only, so this only
+    /// copies pointers, and later edits never show up in the snapshot.
+    pub fn snapshot(&self) -> TextSnapshot {
+        TextSnapshot {
+            pieces: self.pieces.clone(),
+            buffers: self.buffers.clone(),
+        }
+    }
+
+    /// Converts a byte offset to a character offset in O(log n)
+    pub fn byte_to_char_offset(&self, byte_offset: usize) -> usize {
+        match self.pieces.find_byte(byte_offset) {
+            Some((idx, before)) => {
+                let bytes = self.piece_bytes(&self.pieces[idx]);
+                before.chars + count_chars(&bytes[..byte_offset - before.bytes])
+            }
+            None => self.total_char_count,
+        }
+    }
+
+    /// Converts a character offset to a byte offset in O(log n)
+    pub fn char_to_byte_offset(&self, char_offset: usize) -> usize {
+        match self.pieces.find_char(char_offset) {
+            Some((idx, before)) => {
+                before.bytes + byte_of_char(self.piece_bytes(&self.pieces[idx]), char_offset - before.chars)
+            }
+            None => self.total_length,
+        }
+    }
+
     // ==================== Selection Management ====================
 
     /// Sets the selection with anchor and active positions
@@ -302,6 +551,37 @@ impl PieceTree {
         self.selection.active = offset;
     }
 
+    /// Captures the selections, scroll anchor and active table cell
+    pub fn editor_state(&self) -> EditorState {
+        EditorState {
+            selections: std::iter::once(self.selection)
+                .chain(self.extra_selections.iter().copied())
+                .collect(),
+            scroll_anchor: self.scroll_anchor,
+            active_cell: self.active_cell,
+        }
+    }
+
+    /// Restores editor state, e.g. as reported by the host or after undo
+    pub fn set_editor_state(&mut self, state: EditorState) {
+        let mut selections = state.selections.into_iter();
+        self.selection = selections.next().unwrap_or_default();
+        self.extra_selections = selections.collect();
+        self.scroll_anchor = state.scroll_anchor;
+        self.active_cell = state.active_cell;
+    }
+
+    /// Keeps additional carets and the scroll anchor on the same text across an edit
+    fn translate_view(&mut self, at: usize, removed: usize, inserted: usize) {
+        for selection in &mut self.extra_selections {
+            selection.anchor = translate_offset(selection.anchor, at, removed, inserted);
+            selection.active = translate_offset(selection.active, at, removed, inserted);
+        }
+        if let Some(anchor) = self.scroll_anchor.as_mut() {
+            *anchor = translate_offset(*anchor, at, removed, inserted);
+        }
+    }
+
     /// Clears the selection by collapsing to the end of the document
     pub fn clear_selection(&mut self) {
         let max_pos = self.total_char_count.max(self.total_length);
@@ -338,6 +618,87 @@ impl PieceTree {
         self.get_text_range(start, end - start)
     }
 
+    // ==================== Multiple Selections ====================
+
+    /// The primary selection and the additional ones
+    pub fn multi_selection(&self) -> MultiSelection {
+        MultiSelection::from_state(&self.editor_state())
+    }
+
+    /// Replaces every selection; inside a transaction, they are also the
+    /// selections its undo step restores on redo
+    pub fn set_multi_selection(&mut self, selections: &MultiSelection) {
+        let mut ordered = selections.ordered().into_iter();
+        self.selection = ordered.next().unwrap_or_default();
+        self.extra_selections = ordered.collect();
+        if self.history.in_transaction() {
+            self.record_state_after();
+        }
+    }
+
+    /// Selections of columns `anchor.1` to `active.1` of every paragraph from
+    /// `anchor.0` to `active.0`, as a block selection covers them: each clipped
+    /// to its paragraph, with the one in the active paragraph primary
+    pub fn column_selection(&self, anchor: (usize, usize), active: (usize, usize)) -> MultiSelection {
+        let last = self.paragraph_count().saturating_sub(1);
+        let (anchor_paragraph, active_paragraph) = (anchor.0.min(last), active.0.min(last));
+        let paragraphs = anchor_paragraph.min(active_paragraph)..=anchor_paragraph.max(active_paragraph);
+        let selections = paragraphs
+            .map(|index| {
+                let start = self.get_offset_at_line(index + 1);
+                let end = match index < last {
+                    true => self.get_offset_at_line(index + 2) - 1,
+                    false => self.total_char_count,
+                };
+                let column = |column: usize| (start + column).min(end);
+                Selection::new(column(anchor.1), column(active.1))
+            })
+            .collect();
+        let primary = active_paragraph.saturating_sub(anchor_paragraph.min(active_paragraph));
+        MultiSelection::from_selections(selections, primary)
+    }
+
+    /// Types `text` at every selection, replacing selected text, as one undo step
+    pub fn insert_at_selections(&mut self, text: &str) -> MultiSelection {
+        let inserted = text.chars().count();
+        self.edit_selections(None, |tree, range| {
+            tree.delete_chars(range.start, range.len());
+            tree.insert(range.start, text.to_string());
+            inserted
+        })
+    }
+
+    /// Deletes the selected text, and at each caret the char in `direction`, as one undo step
+    pub fn delete_at_selections(&mut self, direction: DeleteDirection) -> MultiSelection {
+        self.edit_selections(Some(direction), |tree, range| {
+            tree.delete_chars(range.start, range.len());
+            0
+        })
+    }
+
+    /// Runs `edit` at the range of every selection in one undo step, leaving a
+    /// caret after the chars it returns it put in
+    fn edit_selections(
+        &mut self,
+        direction: Option<DeleteDirection>,
+        mut edit: impl FnMut(&mut Self, Range<usize>) -> usize,
+    ) -> MultiSelection {
+        let selections = self.multi_selection();
+        let ranges = selections.edit_ranges(direction, self.total_char_count);
+        self.break_undo_coalescing();
+        let after = self.transaction(|tree| {
+            let after = selections.edit_each(&ranges, |range| {
+                let before = tree.total_char_count as isize;
+                let inserted = edit(tree, range);
+                (inserted, tree.total_char_count as isize - before)
+            });
+            tree.set_multi_selection(&after);
+            after
+        });
+        self.break_undo_coalescing();
+        after
+    }
+
     // ==================== Insertion ====================
 
     /// Inserts text at the specified character offset (without attributes)
@@ -360,198 +721,85 @@ impl PieceTree {
         let max_offset = self.total_char_count;
         let char_offset = std::cmp::min(char_offset, max_offset);
 
-        // Record change for undo
-        if !self.is_undoing_redoing {
-            // Save current selection for undo
-            self.saved_selection = Some(self.selection);
-            self.undo_stack.push(Change::Insert {
+        // Record change for undo; a single character may be typing
+        let mut chars = text.chars();
+        let typed = chars.next().filter(|_| chars.next().is_none());
+        self.record_change(
+            Change::Insert {
                 offset: char_offset,
-                length: byte_count,
-            });
-            if self.undo_stack.len() > MAX_UNDO_DEPTH {
-                self.undo_stack.remove(0);
-            }
-            self.redo_stack.clear();
-        }
+                text: text.clone(),
+            },
+            typed,
+        );
 
         trace!("insert: char_offset={}, text='{}' ({} bytes, {} chars)",
                   char_offset, text, byte_count, char_count);
 
+        let paragraph = self.paragraph_index_at(char_offset);
+        let line_breaks = count_line_breaks(text.as_bytes());
+
         // Add the new text to buffers
         let new_buffer_id = self.next_buffer_id();
-        self.buffers.push(text.clone());
+        self.buffers.push(Arc::from(text.as_str()));
 
-        if self.pieces.is_empty() {
-            // Empty document - create first piece
-            let piece = Piece::new_with_attrs(0, byte_count, new_buffer_id, char_count, attributes);
-            self.pieces.push(piece);
-            self.total_char_count += char_count;
-            self.total_length += byte_count;
-            // Move selection after inserted text
-            if !self.is_undoing_redoing {
-                self.move_selection_to(char_offset + char_count);
-            }
-            return true;
-        }
+        let new_pieces = chunk_piece(
+            Piece::new_with_attrs(0, byte_count, new_buffer_id, char_count, attributes),
+            text.as_bytes(),
+        );
 
-        // Find position to insert using character-based API
-        let (piece_idx, byte_offset_in_piece) = match self.find_piece_and_byte_offset_from_char(char_offset) {
-            Some(result) => result,
+        match self.pieces.find_char(char_offset) {
             None => {
-                // Insert at end
-                let piece = Piece::new_with_attrs(0, byte_count, new_buffer_id, char_count, attributes);
-                self.pieces.push(piece);
-                self.total_char_count += char_count;
-                self.total_length += byte_count;
-                // Move selection after inserted text
-                if !self.is_undoing_redoing {
-                    self.move_selection_to(char_offset + char_count);
+                // Empty document - create first pieces
+                self.insert_pieces(0, new_pieces);
+            }
+            Some((piece_idx, before)) => {
+                let piece = self.pieces[piece_idx].clone();
+                let char_offset_in_piece = char_offset - before.chars;
+                trace!("piece_idx={}, char_offset_in_piece={}", piece_idx, char_offset_in_piece);
+
+                if char_offset_in_piece == 0 {
+                    // Insert at the beginning of this piece
+                    self.insert_pieces(piece_idx, new_pieces);
+                    trace!("insert at beginning");
+                } else if char_offset_in_piece >= piece.piece_char_length {
+                    // Insert at the end of this piece
+                    self.insert_pieces(piece_idx + 1, new_pieces);
+                    trace!("insert at end");
+                } else {
+                    // Split the piece at the char boundary and insert in the middle
+                    let split = byte_of_char(self.piece_bytes(&piece), char_offset_in_piece);
+                    let (left, left_breaks) = self.sub_piece(&piece, 0, split);
+                    let (right, right_breaks) = self.sub_piece(&piece, split, piece.length);
+
+                    // Insert: left piece (unchanged idx) + new pieces + right piece
+                    let right_idx = piece_idx + 1 + new_pieces.len();
+                    self.pieces.replace(piece_idx, left, left_breaks);
+                    self.insert_pieces(piece_idx + 1, new_pieces);
+                    self.pieces.insert(right_idx, right, right_breaks);
+                    trace!("insert middle");
                 }
-                return true;
             }
-        };
-
-        trace!("piece_idx={}, byte_offset_in_piece={}", piece_idx, byte_offset_in_piece);
-
-        let piece = &mut self.pieces[piece_idx];
-
-        if byte_offset_in_piece == 0 {
-            // Insert at the beginning of this piece
-            let new_piece = Piece::new_with_attrs(0, byte_count, new_buffer_id, char_count, attributes);
-            self.pieces.insert(piece_idx, new_piece);
-            trace!("insert at beginning");
-        } else if byte_offset_in_piece == piece.length {
-            // Insert at the end of this piece
-            let new_piece = Piece::new_with_attrs(0, byte_count, new_buffer_id, char_count, attributes);
-            self.pieces.insert(piece_idx + 1, new_piece);
-            trace!("insert at end");
-        } else {
-            // Split the piece and insert in the middle
-            // byte_offset_in_piece is the character offset within the piece where we split
-
-            // Capture original values before updating
-            let original_piece_start = piece.start;
-            let original_piece_length = piece.length;
-
-            // Calculate left and right pieces using char_indices for correct UTF-8 handling
-            let piece_buffer_idx = Self::buffer_idx(&piece.buffer_id);
-            let piece_buffer = &self.buffers[piece_buffer_idx];
-            let original_piece_text = &piece_buffer[original_piece_start..original_piece_start + original_piece_length];
-
-            // Get all characters in the piece
-            let chars: Vec<char> = original_piece_text.chars().collect();
-            let total_chars_in_piece = chars.len();
-
-            // Clamp byte_offset_in_piece to valid range
-            let split_char_idx = byte_offset_in_piece.min(total_chars_in_piece);
-
-            // left_piece: first split_char_idx characters
-            let left_chars: String = chars[..split_char_idx].iter().collect();
-            let left_byte_count = left_chars.len();
-            let left_char_count = left_chars.chars().count();
-
-            // right_piece: remaining characters
-            let right_chars: String = chars[split_char_idx..].iter().collect();
-            let right_piece_byte_length = right_chars.len();
-            let right_piece_char_length = right_chars.chars().count();
-
-            trace!("left='{}' ({} bytes, {} chars)", left_chars, left_byte_count, left_char_count);
-            trace!("right='{}' ({} bytes, {} chars)", right_chars, right_piece_byte_length, right_piece_char_length);
-
-            // Update left piece
-            piece.length = left_byte_count;
-            piece.piece_char_length = left_char_count;
-
-            // Create right piece
-            let right_piece = Piece::new_with_attrs(
-                original_piece_start + left_byte_count,
-                right_piece_byte_length,
-                piece.buffer_id,
-                right_piece_char_length,
-                piece.attributes.clone(),
-            );
-
-            // Create the new piece to insert
-            let new_piece = Piece::new_with_attrs(0, byte_count, new_buffer_id, char_count, attributes);
-
-            // Insert: left piece (unchanged idx) + new piece (idx+1) + right piece (idx+2)
-            self.pieces.insert(piece_idx + 1, new_piece);
-            self.pieces.insert(piece_idx + 2, right_piece);
-
-            trace!("insert middle");
         }
 
         self.total_char_count += char_count;
         self.total_length += byte_count;
+        self.paragraphs.split(paragraph, line_breaks);
+        self.translate_view(char_offset, 0, char_count);
 
         // Move selection after inserted text
         if !self.is_undoing_redoing {
             self.move_selection_to(char_offset + char_count);
+            self.record_state_after();
         }
 
         true
     }
 
-    /// Finds the piece and byte offset for a given character position (character-based API)
-    /// Returns (piece_index, byte_offset_within_piece)
-    /// Returns None if char_offset is beyond the document
-    fn find_piece_and_byte_offset_from_char(&self, char_offset: usize) -> Option<(usize, usize)> {
-        if char_offset == 0 {
-            if !self.pieces.is_empty() {
-                return Some((0, 0));
-            }
-            return None;
-        }
-
-        let mut accumulated_chars = 0usize;
-
-        for (idx, piece) in self.pieces.iter().enumerate() {
-            let piece_end_chars = accumulated_chars + piece.piece_char_length;
-
-            if char_offset < accumulated_chars {
-                accumulated_chars = piece_end_chars;
-                continue;
-            }
-
-            if char_offset < piece_end_chars {
-                // The character is within this piece
-                let char_offset_in_piece = char_offset - accumulated_chars;
-
-                // Find the byte offset for this character position
-                let piece_buffer_idx = Self::buffer_idx(&piece.buffer_id);
-                if let Some(buffer) = self.buffers.get(piece_buffer_idx) {
-                    let piece_text = &buffer[piece.start..piece.start + piece.length];
-
-                    // Find the byte offset of the char_offset_in_piece-th character
-                    let byte_offset: usize = if char_offset_in_piece == 0 {
-                        0
-                    } else {
-                        piece_text.char_indices()
-                            .nth(char_offset_in_piece - 1)
-                            .map(|(byte_idx, c)| byte_idx + c.len_utf8())
-                            .unwrap_or(piece.length)
-                    };
-
-                    return Some((idx, byte_offset));
-                }
-            }
-
-            if char_offset == piece_end_chars {
-                // At the boundary - return end of this piece
-                return Some((idx, piece.length));
-            }
-
-            accumulated_chars = piece_end_chars;
-        }
-
-        // Handle inserting at the exact end of the document
-        if accumulated_chars == char_offset && !self.pieces.is_empty() {
-            let last_idx = self.pieces.len() - 1;
-            let last_piece = &self.pieces[last_idx];
-            return Some((last_idx, last_piece.length));
+    /// Inserts pieces with their line break counts at consecutive indices
+    fn insert_pieces(&mut self, index: usize, pieces: Vec<(Piece, usize)>) {
+        for (i, (piece, line_breaks)) in pieces.into_iter().enumerate() {
+            self.pieces.insert(index + i, piece, line_breaks);
         }
-
-        None
     }
 
     // ==================== Deletion ====================
@@ -568,88 +816,84 @@ impl PieceTree {
             return false;
         }
 
+        let char_start = self.byte_to_char_offset(offset);
+        let char_end = self.byte_to_char_offset(end_offset);
+        // A byte offset inside a char would leave pieces holding part of it;
+        // both ends move to the next char boundary, as the char offsets do
+        let offset = self.char_to_byte_offset(char_start);
+        let end_offset = self.char_to_byte_offset(char_end);
+        if offset == end_offset {
+            return false;
+        }
+
         // Record change for undo
         if !self.is_undoing_redoing {
-            // Save current selection for undo
-            self.saved_selection = Some(self.selection);
-            let deleted_text = self.get_text_range(offset, length);
-            self.undo_stack.push(Change::Delete {
-                offset,
-                text: deleted_text,
-            });
-            if self.undo_stack.len() > MAX_UNDO_DEPTH {
-                self.undo_stack.remove(0);
-            }
-            self.redo_stack.clear();
+            let deleted_text = self.get_text_range(offset, end_offset - offset);
+            self.record_change(
+                Change::Delete {
+                    offset: char_start,
+                    text: deleted_text,
+                },
+                None,
+            );
         }
 
+        let paragraph = self.paragraph_index_at(char_start);
         let mut deleted_chars = 0;
         let mut deleted_bytes = 0;
-        let mut new_pieces = Vec::new();
+        let mut deleted_line_breaks = 0;
 
-        let mut current_offset = 0usize;
+        let (mut idx, before) = match self.pieces.find_byte(offset) {
+            Some(found) => found,
+            None => return false,
+        };
+        let mut piece_start = before.bytes;
 
-        for piece in &self.pieces {
-            let piece_start = current_offset;
-            let piece_end = current_offset + piece.length;
+        // Only the pieces overlapping the range are visited
+        while piece_start < end_offset && idx < self.pieces.len() {
+            let piece = self.pieces[idx].clone();
+            let piece_end = piece_start + piece.length;
 
-            if piece_end <= offset {
-                // Piece is entirely before delete range
-                new_pieces.push(piece.clone());
-                current_offset = piece_end;
-                continue;
-            }
+            let delete_start_in_piece = offset.saturating_sub(piece_start);
+            let delete_end_in_piece = end_offset.min(piece_end) - piece_start;
 
-            if piece_start >= end_offset {
-                // Piece is entirely after delete range
-                new_pieces.push(piece.clone());
-                continue;
-            }
+            let deleted = &self.piece_bytes(&piece)[delete_start_in_piece..delete_end_in_piece];
+            deleted_bytes += deleted.len();
+            deleted_chars += count_chars(deleted);
+            deleted_line_breaks += count_line_breaks(deleted);
 
-            // This piece overlaps with the delete range
-            let delete_start_in_piece = if offset > piece_start { offset - piece_start } else { 0 };
-            let delete_end_in_piece = if end_offset < piece_end { end_offset - piece_start } else { piece.length };
-
-            deleted_bytes += delete_end_in_piece - delete_start_in_piece;
-            deleted_chars += delete_end_in_piece - delete_start_in_piece;
-
-            if delete_start_in_piece > 0 {
-                // Keep left part
-                let left_piece = Piece::new_with_attrs(
-                    piece.start,
-                    delete_start_in_piece,
-                    piece.buffer_id,
-                    delete_start_in_piece,
-                    piece.attributes.clone(),
-                );
-                new_pieces.push(left_piece);
-            }
+            // Keep the left and right parts
+            let left = (delete_start_in_piece > 0).then(|| self.sub_piece(&piece, 0, delete_start_in_piece));
+            let right = (delete_end_in_piece < piece.length)
+                .then(|| self.sub_piece(&piece, delete_end_in_piece, piece.length));
 
-            if delete_end_in_piece < piece.length {
-                // Keep right part - calculate correct start position
-                let right_start = piece.start + delete_end_in_piece;
-                let right_length = piece.length - delete_end_in_piece;
-                let right_piece = Piece::new_with_attrs(
-                    right_start,
-                    right_length,
-                    piece.buffer_id,
-                    right_length,
-                    piece.attributes.clone(),
-                );
-                new_pieces.push(right_piece);
+            match (left, right) {
+                (None, None) => {
+                    self.pieces.remove(idx);
+                }
+                (Some((part, breaks)), None) | (None, Some((part, breaks))) => {
+                    self.pieces.replace(idx, part, breaks);
+                    idx += 1;
+                }
+                (Some((left, left_breaks)), Some((right, right_breaks))) => {
+                    self.pieces.replace(idx, left, left_breaks);
+                    self.pieces.insert(idx + 1, right, right_breaks);
+                    idx += 2;
+                }
             }
 
-            current_offset = piece_end;
+            piece_start = piece_end;
         }
 
-        self.pieces = new_pieces;
         self.total_char_count = self.total_char_count.saturating_sub(deleted_chars);
         self.total_length = self.total_length.saturating_sub(deleted_bytes);
+        self.paragraphs.join(paragraph, deleted_line_breaks);
+        self.translate_view(char_start, char_end - char_start, 0);
 
-        // Adjust selection after delete
+        // Adjust selection after delete; selections are char offsets
         if !self.is_undoing_redoing {
-            let delete_start = offset;
-            let delete_end = end_offset;
+            let delete_start = char_start;
+            let delete_end = char_end;
 
             // If selection is entirely after deleted range, shift it left
             if self.selection.start() >= delete_end {
@@ -666,11 +910,283 @@ impl PieceTree {
                 self.selection.active = self.selection.active.saturating_sub(shift.min(self.selection.active));
             }
             // If selection is entirely before deleted range, no adjustment needed
+            self.record_state_after();
         }
 
         true
     }
 
+    // ==================== Formatting ====================
+
+    /// Sets the given attributes on the chars in `range`, keeping fields the attributes leave unset
+    /// Returns false if the range is empty
+    pub fn apply_attributes(&mut self, range: Range<usize>, attributes: &TextAttributes) -> bool {
+        self.restyle(range, |old| {
+            Some(old.map_or_else(|| attributes.clone(), |old| old.merged_with(attributes)))
+        })
+    }
+
+    /// Removes all attributes from the chars in `range`
+    /// Returns false if the range is empty
+    pub fn clear_attributes(&mut self, range: Range<usize>) -> bool {
+        self.restyle(range, |_| None)
+    }
+
+    /// Attributes of the chars in `range`, one span per run of equal attributes
+    pub fn attribute_spans(&self, range: Range<usize>) -> Vec<AttributeSpan> {
+        let start = range.start.min(self.total_char_count);
+        let end = range.end.min(self.total_char_count);
+        let mut spans: Vec<AttributeSpan> = Vec::new();
+        let Some((idx, before)) = self.pieces.find_char(start).filter(|_| start < end) else {
+            return spans;
+        };
+
+        let mut piece_start = before.chars;
+        for piece in self.pieces.iter_from(idx) {
+            if piece_start >= end {
+                break;
+            }
+            let length = (piece_start + piece.piece_char_length).min(end) - piece_start.max(start);
+            piece_start += piece.piece_char_length;
+            if length == 0 {
+                continue;
+            }
+            match spans.last_mut() {
+                Some(span) if span.attributes == piece.attributes => span.length += length,
+                _ => spans.push(AttributeSpan {
+                    length,
+                    attributes: piece.attributes.clone(),
+                }),
+            }
+        }
+        spans
+    }
+
+    /// Attributes of the char at `offset`; past the end, those of the last char
+    pub fn get_attributes_at(&self, offset: usize) -> Option<TextAttributes> {
+        let offset = offset.min(self.total_char_count.saturating_sub(1));
+        let (idx, before) = self.pieces.find_char(offset)?;
+        // find_char returns the piece ending at a boundary; the char there starts the next one
+        let idx = if offset - before.chars >= self.pieces[idx].piece_char_length { idx + 1 } else { idx };
+        self.pieces.get(idx)?.attributes.clone()
+    }
+
+    /// Whether each attribute is on, off or mixed across `range`
+    ///
+    /// An empty range reports the attributes at its position, as for a caret.
+    pub fn get_common_attributes(&self, range: Range<usize>) -> CommonAttributes {
+        self.attribute_spans(range.clone())
+            .iter()
+            .map(|span| CommonAttributes::of(span.attributes.as_ref()))
+            .reduce(CommonAttributes::combine)
+            .unwrap_or_else(|| CommonAttributes::of(self.get_attributes_at(range.start).as_ref()))
+    }
+
+    /// Restyles each piece of `range` with `style` applied to its current attributes
+    fn restyle(
+        &mut self,
+        range: Range<usize>,
+        style: impl Fn(Option<&TextAttributes>) -> Option<TextAttributes>,
+    ) -> bool {
+        let start = range.start.min(self.total_char_count);
+        let end = range.end.min(self.total_char_count);
+        if start >= end {
+            return false;
+        }
+
+        let before = self.attribute_spans(start..end);
+        let mut after: Vec<AttributeSpan> = Vec::with_capacity(before.len());
+        for span in &before {
+            let attributes = style(span.attributes.as_ref());
+            match after.last_mut() {
+                Some(last) if last.attributes == attributes => last.length += span.length,
+                _ => after.push(AttributeSpan {
+                    length: span.length,
+                    attributes,
+                }),
+            }
+        }
+
+        if before == after {
+            return true;
+        }
+        self.record_change(Change::Format { offset: start, before, after: after.clone() }, None);
+        self.set_attribute_spans(start, &after);
+        self.record_state_after();
+        true
+    }
+
+    /// Gives consecutive spans of chars from `offset` the spans' attributes
+    fn set_attribute_spans(&mut self, offset: usize, spans: &[AttributeSpan]) {
+        let end = offset + spans.iter().map(|span| span.length).sum::<usize>();
+        let first = self.split_at_char(offset);
+        let mut span_start = offset;
+        for span in spans {
+            let from = self.split_at_char(span_start);
+            let to = self.split_at_char(span_start + span.length);
+            for idx in from..to {
+                let mut piece = self.pieces[idx].clone();
+                piece.attributes = span.attributes.clone();
+                let line_breaks = self.pieces.line_breaks_at(idx);
+                self.pieces.replace(idx, piece, line_breaks);
+            }
+            span_start += span.length;
+        }
+        let last = self.split_at_char(end);
+        self.merge_pieces(first.saturating_sub(1), last);
+    }
+
+    /// Splits the piece containing char `offset` there; returns the index of the piece starting at it
+    fn split_at_char(&mut self, offset: usize) -> usize {
+        let Some((idx, before)) = self.pieces.find_char(offset) else {
+            return 0;
+        };
+        let piece = self.pieces[idx].clone();
+        let offset_in_piece = offset - before.chars;
+        if offset_in_piece == 0 {
+            return idx;
+        }
+        if offset_in_piece >= piece.piece_char_length {
+            return idx + 1;
+        }
+
+        let split = byte_of_char(self.piece_bytes(&piece), offset_in_piece);
+        let (left, left_breaks) = self.sub_piece(&piece, 0, split);
+        let (right, right_breaks) = self.sub_piece(&piece, split, piece.length);
+        self.pieces.replace(idx, left, left_breaks);
+        self.pieces.insert(idx + 1, right, right_breaks);
+        idx + 1
+    }
+
+    /// Joins neighbours among pieces `from..=to` that continue each other in the same buffer with equal attributes
+    fn merge_pieces(&mut self, from: usize, to: usize) {
+        let mut idx = from;
+        let mut to = to.min(self.pieces.len().saturating_sub(1));
+        while idx < to {
+            let (left, right) = (&self.pieces[idx], &self.pieces[idx + 1]);
+            let joins = left.buffer_id == right.buffer_id
+                && left.end() == right.start
+                && left.attributes == right.attributes
+                && left.length + right.length <= MAX_PIECE_BYTES;
+            if !joins {
+                idx += 1;
+                continue;
+            }
+
+            let mut merged = left.clone();
+            merged.length += right.length;
+            merged.piece_char_length += right.piece_char_length;
+            let line_breaks = self.pieces.line_breaks_at(idx) + self.pieces.line_breaks_at(idx + 1);
+            self.pieces.replace(idx, merged, line_breaks);
+            self.pieces.remove(idx + 1);
+            to -= 1;
+        }
+    }
+
+    // ==================== Paragraph Formatting ====================
+
+    /// Number of paragraphs; an empty document has one
+    pub fn paragraph_count(&self) -> usize {
+        self.paragraphs.len()
+    }
+
+    /// Index of the paragraph containing char `offset`, from 0
+    pub fn paragraph_index_at(&self, offset: usize) -> usize {
+        self.get_line_at_offset(offset) - 1
+    }
+
+    /// Indices of the paragraphs with chars in `range`; an empty range gives the paragraph it is in
+    pub fn paragraphs_in(&self, range: Range<usize>) -> Range<usize> {
+        let first = self.paragraph_index_at(range.start);
+        let last = if range.end > range.start {
+            self.paragraph_index_at(range.end - 1)
+        } else {
+            first
+        };
+        first..last + 1
+    }
+
+    /// Gives freshly loaded content its paragraph attributes, one per paragraph, without an undo step
+    pub fn load_paragraph_attributes(&mut self, attributes: Vec<Option<ParagraphAttributes>>) {
+        self.paragraphs.set(0, &attributes);
+    }
+
+    /// Attributes of paragraph `index`; None if it has none or does not exist
+    pub fn paragraph_attributes(&self, index: usize) -> Option<&ParagraphAttributes> {
+        self.paragraphs.get(index)
+    }
+
+    /// Attributes of the paragraph containing char `offset`
+    pub fn paragraph_attributes_at(&self, offset: usize) -> Option<ParagraphAttributes> {
+        self.paragraph_attributes(self.paragraph_index_at(offset)).cloned()
+    }
+
+    /// Attributes of each paragraph `range` touches, in order
+    pub fn paragraph_attributes_in(&self, range: Range<usize>) -> Vec<Option<ParagraphAttributes>> {
+        self.paragraphs.slice(self.paragraphs_in(range))
+    }
+
+    /// Sets the given attributes on every paragraph `range` touches, keeping fields the attributes leave unset
+    /// Returns false if no paragraph changed
+    pub fn apply_paragraph_attributes(&mut self, range: Range<usize>, attributes: &ParagraphAttributes) -> bool {
+        self.reformat_paragraphs(range, |old| {
+            Some(old.map_or_else(|| attributes.clone(), |old| old.merged_with(attributes)))
+        })
+    }
+
+    /// Removes the attributes of every paragraph `range` touches
+    /// Returns false if no paragraph changed
+    pub fn clear_paragraph_attributes(&mut self, range: Range<usize>) -> bool {
+        self.reformat_paragraphs(range, |_| None)
+    }
+
+    /// Replaces the attributes of every paragraph `range` touches; None removes them
+    /// Returns false if no paragraph changed
+    pub fn set_paragraph_attributes(&mut self, range: Range<usize>, attributes: Option<&ParagraphAttributes>) -> bool {
+        self.reformat_paragraphs(range, |_| attributes.cloned())
+    }
+
+    /// Gives each paragraph `range` touches `style` applied to its current attributes, as one undo step
+    fn reformat_paragraphs(
+        &mut self,
+        range: Range<usize>,
+        style: impl Fn(Option<&ParagraphAttributes>) -> Option<ParagraphAttributes>,
+    ) -> bool {
+        self.reformat_each_paragraph(range, |_, attributes| style(attributes))
+    }
+
+    /// Gives each paragraph `range` touches the attributes `style` returns for its
+    /// index and current attributes, as one undo step
+    /// Returns false if no paragraph changed
+    pub fn reformat_each_paragraph(
+        &mut self,
+        range: Range<usize>,
+        mut style: impl FnMut(usize, Option<&ParagraphAttributes>) -> Option<ParagraphAttributes>,
+    ) -> bool {
+        let paragraphs = self.paragraphs_in(range);
+        let before = self.paragraphs.slice(paragraphs.clone());
+        let after: Vec<_> = before
+            .iter()
+            .enumerate()
+            .map(|(i, attributes)| style(paragraphs.start + i, attributes.as_ref()))
+            .collect();
+        if before == after {
+            return false;
+        }
+
+        self.record_change(
+            Change::ParagraphFormat {
+                first: paragraphs.start,
+                before,
+                after: after.clone(),
+            },
+            None,
+        );
+        self.paragraphs.set(paragraphs.start, &after);
+        self.record_state_after();
+        true
+    }
+
     // ==================== Text Retrieval ====================
 
     /// Gets the full text content
@@ -684,11 +1200,16 @@ impl PieceTree {
             return String::new();
         }
 
+        let (first, before) = match self.pieces.find_byte(offset) {
+            Some(found) => found,
+            None => return String::new(),
+        };
+
         let mut result = String::with_capacity(length);
-        let mut current_offset = 0usize;
+        let mut current_offset = before.bytes;
         let end_offset = offset + length;
 
-        for piece in &self.pieces {
+        for piece in self.pieces.iter_from(first) {
             let piece_start = current_offset;
             let piece_end = current_offset + piece.length;
 
@@ -721,128 +1242,330 @@ impl PieceTree {
 
     // ==================== Undo/Redo ====================
 
-    /// Undoes the last change
+    /// Undoes the current undo step, restoring the editor state from before it
+    ///
+    /// The step stays in the history tree, so redo or a jump can return to it.
     pub fn undo(&mut self) -> bool {
-        if let Some(change) = self.undo_stack.pop() {
-            self.is_undoing_redoing = true;
-            let redo_change = match change {
-                Change::Insert { offset, length } => {
-                    let deleted_text = self.get_text_range(offset, length);
-                    self.delete(offset, length);
-                    Change::Delete {
-                        offset,
-                        text: deleted_text,
-                    }
-                }
-                Change::Delete { offset, text } => {
-                    let length = text.len();
-                    self.insert(offset, text);
-                    Change::Insert { offset, length }
-                }
-            };
-            self.redo_stack.push(redo_change);
-            self.is_undoing_redoing = false;
-            // Restore selection
-            if let Some(saved_sel) = self.saved_selection {
-                self.selection = saved_sel;
+        self.end_all_transactions();
+        match self.history.step_back(&self.pieces, &self.paragraphs) {
+            Some(group) => {
+                self.revert_group(&group);
+                self.set_editor_state(group.state_before);
+                true
             }
-            return true;
+            None => false,
         }
-        false
     }
 
-    /// Redoes the last undone change
+    /// Redoes the most recently undone step of the current branch, restoring the editor state from after it
     pub fn redo(&mut self) -> bool {
-        if let Some(change) = self.redo_stack.pop() {
-            self.is_undoing_redoing = true;
-            let undo_change = match change {
-                Change::Insert { offset, length } => {
-                    let deleted_text = self.get_text_range(offset, length);
-                    self.delete(offset, length);
-                    Change::Delete {
-                        offset,
-                        text: deleted_text,
-                    }
+        self.end_all_transactions();
+        match self.history.step_forward(&self.pieces, &self.paragraphs) {
+            Some(group) => {
+                self.apply_group(&group);
+                self.set_editor_state(group.state_after);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the document to the state of any node in the history tree
+    ///
+    /// Returns false if the node does not exist.
+    pub fn jump_to_history(&mut self, target: HistoryNodeId) -> bool {
+        self.end_all_transactions();
+        let Some(route) = self.history.route_to(target) else {
+            return false;
+        };
+
+        match route {
+            Route::Replay { up, down } => {
+                for id in up {
+                    let group = self.history.group(id).clone();
+                    self.revert_group(&group);
                 }
-                Change::Delete { offset, text } => {
-                    let length = text.len();
-                    self.insert(offset, text);
-                    Change::Insert { offset, length }
+                self.apply_groups(&down);
+            }
+            Route::Checkpoint { checkpoint, down } => {
+                self.total_char_count = checkpoint.pieces.summary().chars;
+                self.total_length = checkpoint.pieces.summary().bytes;
+                self.pieces = checkpoint.pieces;
+                self.paragraphs = checkpoint.paragraphs;
+                self.apply_groups(&down);
+            }
+        }
+
+        self.history.set_current(target);
+        let state = self.history.group(target).state_after.clone();
+        self.set_editor_state(state);
+        true
+    }
+
+    /// The history node the document is currently at
+    pub fn current_history_node(&self) -> HistoryNodeId {
+        self.history.current()
+    }
+
+    /// All nodes of the history tree, oldest first
+    pub fn history_nodes(&self) -> Vec<HistoryNodeInfo> {
+        self.history.nodes()
+    }
+
+    /// Tips of every branch in the history tree, oldest first
+    pub fn history_branches(&self) -> Vec<HistoryNodeId> {
+        self.history.branch_tips()
+    }
+
+    /// Estimated memory the history holds, with its budget
+    pub fn history_memory_usage(&self) -> HistoryMemoryUsage {
+        HistoryMemoryUsage {
+            text_bytes: self.retained_bytes,
+            ..self.history.memory_usage()
+        }
+    }
+
+    /// Holds the history and the text buffers kept for it to `max_bytes`,
+    /// dropping the oldest steps past it, or lifts the limit with None; the
+    /// step limits apply either way
+    pub fn set_history_memory_budget(&mut self, max_bytes: Option<usize>) {
+        self.history.set_max_bytes(max_bytes);
+        self.enforce_history_budget();
+    }
+
+    /// Releases buffers nothing refers to once history steps were dropped,
+    /// then drops the oldest steps while the history and the buffers only it
+    /// keeps are over budget
+    ///
+    /// Counting the buffers takes a pass over the pieces of the text and of
+    /// every checkpoint, so it is only done with a budget set or after steps
+    /// were dropped.
+    fn enforce_history_budget(&mut self) {
+        let budgeted = self.history.memory_usage().budget.is_some();
+        if budgeted || self.history.evictions() != self.released_at {
+            self.release_buffers();
+        }
+        while self.history.over_budget(self.retained_bytes) && self.history.drop_oldest() {
+            self.release_buffers();
+        }
+    }
+
+    /// Empties the buffers neither the text nor a history checkpoint refers to
+    /// and counts the bytes of those only checkpoints refer to
+    ///
+    /// Buffer IDs stay as they are; snapshots hold buffers of their own.
+    fn release_buffers(&mut self) {
+        let mut live = vec![false; self.buffers.len()];
+        for piece in self.pieces.iter() {
+            if let Some(used) = live.get_mut(Self::buffer_idx(&piece.buffer_id)) {
+                *used = true;
+            }
+        }
+        let mut kept = live.clone();
+        for checkpoint in self.history.checkpoints() {
+            for piece in checkpoint.pieces.iter() {
+                if let Some(used) = kept.get_mut(Self::buffer_idx(&piece.buffer_id)) {
+                    *used = true;
                 }
-            };
-            self.undo_stack.push(undo_change);
-            self.is_undoing_redoing = false;
-            // Restore selection
-            if let Some(saved_sel) = self.saved_selection {
-                self.selection = saved_sel;
             }
-            return true;
         }
-        false
+        self.retained_bytes = 0;
+        for (index, buffer) in self.buffers.iter_mut().enumerate() {
+            match (live[index], kept[index]) {
+                (true, _) => {}
+                (false, true) => self.retained_bytes += buffer.len(),
+                (false, false) if !buffer.is_empty() => *buffer = Arc::from(""),
+                (false, false) => {}
+            }
+        }
+        self.released_at = self.history.evictions();
     }
 
-    /// Returns true if there are undoable changes available
-    pub fn can_undo(&self) -> bool {
-        !self.undo_stack.is_empty()
+    /// The history tree in a serializable form
+    pub fn save_history(&self) -> SavedHistory {
+        self.history.save()
     }
 
-    /// Returns true if there are redoable changes available
-    pub fn can_redo(&self) -> bool {
-        !self.redo_stack.is_empty()
+    /// Replaces the history tree with a saved one
+    ///
+    /// The text must already be in the state of the saved current node.
+    /// Returns false, leaving the history unchanged, if the saved tree is malformed.
+    pub fn restore_history(&mut self, saved: SavedHistory) -> bool {
+        match History::restore(saved, &self.pieces, &self.paragraphs) {
+            Some(mut history) => {
+                history.set_max_bytes(self.history.memory_usage().budget);
+                self.history = history;
+                self.enforce_history_budget();
+                true
+            }
+            None => false,
+        }
     }
 
-    // ==================== Navigation ====================
+    /// Starts grouping edits into one undo step; transactions nest
+    pub fn begin_transaction(&mut self) {
+        let state = self.editor_state();
+        self.history.begin_transaction(state, &self.pieces, &self.paragraphs);
+    }
 
-    /// Moves to the specified character position and returns (line, column)
-    pub fn move_to(&self, char_offset: usize) -> (usize, usize) {
-        if self.pieces.is_empty() {
-            return (1, 1);
+    /// Ends a transaction; the outermost one records its edits as a single undo step
+    pub fn end_transaction(&mut self) {
+        self.history.end_transaction();
+        if !self.history.in_transaction() {
+            self.enforce_history_budget();
         }
+    }
 
-        let mut line = 1usize;
-        let mut column = 1usize;
-        let mut char_count = 0usize;
+    /// Whether a transaction is open, so edits wait to become an undo step
+    pub fn in_transaction(&self) -> bool {
+        self.history.in_transaction()
+    }
 
-        for piece in &self.pieces {
-            let buffer_idx = Self::buffer_idx(&piece.buffer_id);
-            if let Some(buffer) = self.buffers.get(buffer_idx) {
-                let piece_text = if piece.start + piece.length <= buffer.len() {
-                    &buffer[piece.start..piece.start + piece.length]
-                } else {
-                    ""
-                };
+    /// Runs `edit` inside a transaction
+    pub fn transaction<R>(&mut self, edit: impl FnOnce(&mut Self) -> R) -> R {
+        self.begin_transaction();
+        let result = edit(self);
+        self.end_transaction();
+        result
+    }
 
-                for c in piece_text.chars() {
-                    if char_count >= char_offset {
-                        return (line, column);
-                    }
+    /// Makes the next typed character start a new undo step, e.g. after the caret was moved
+    pub fn break_undo_coalescing(&mut self) {
+        self.history.break_coalescing();
+    }
 
-                    if c == '\n' {
-                        line += 1;
-                        column = 1;
-                    } else {
-                        column += 1;
-                    }
-                    char_count += 1;
+    /// Records an undo step that leaves the text as it is, for a change made
+    /// beside it, e.g. to a comment anchored to it; inside a transaction the
+    /// transaction's step takes it
+    pub fn record_empty_step(&mut self) {
+        let state = self.editor_state();
+        self.history.record_empty(state, &self.pieces, &self.paragraphs);
+        if !self.history.in_transaction() {
+            self.enforce_history_budget();
+        }
+    }
+
+    fn end_all_transactions(&mut self) {
+        self.history.end_all_transactions();
+    }
+
+    /// Adds a change to the open transaction, the current typing run, or a new undo step
+    fn record_change(&mut self, change: Change, typed: Option<char>) {
+        if self.is_undoing_redoing {
+            return;
+        }
+        let state = self.editor_state();
+        self.history.record(change, typed, state, &self.pieces, &self.paragraphs);
+    }
+
+    /// Notes the editor state after the change just recorded
+    fn record_state_after(&mut self) {
+        let state = self.editor_state();
+        self.history.record_state_after(state);
+        if !self.history.in_transaction() {
+            self.enforce_history_budget();
+        }
+    }
+
+    /// Reverts a group's changes, newest first
+    fn revert_group(&mut self, group: &UndoGroup) {
+        self.is_undoing_redoing = true;
+        for change in group.changes.iter().rev() {
+            match change {
+                Change::Insert { offset, text } => self.delete_chars(*offset, text.chars().count()),
+                Change::Delete { offset, text } => {
+                    self.insert(*offset, text.clone());
+                }
+                Change::Format { offset, before, .. } => self.set_attribute_spans(*offset, before),
+                Change::ParagraphFormat { first, before, .. } => self.paragraphs.set(*first, before),
+            }
+        }
+        self.is_undoing_redoing = false;
+    }
+
+    /// Reapplies a group's changes, oldest first
+    fn apply_group(&mut self, group: &UndoGroup) {
+        self.is_undoing_redoing = true;
+        for change in &group.changes {
+            match change {
+                Change::Insert { offset, text } => {
+                    self.insert(*offset, text.clone());
                 }
+                Change::Delete { offset, text } => self.delete_chars(*offset, text.chars().count()),
+                Change::Format { offset, after, .. } => self.set_attribute_spans(*offset, after),
+                Change::ParagraphFormat { first, after, .. } => self.paragraphs.set(*first, after),
             }
         }
+        self.is_undoing_redoing = false;
+    }
+
+    fn apply_groups(&mut self, ids: &[HistoryNodeId]) {
+        for id in ids {
+            let group = self.history.group(*id).clone();
+            self.apply_group(&group);
+        }
+    }
+
+    fn delete_chars(&mut self, offset: usize, length: usize) {
+        let start = self.char_to_byte_offset(offset);
+        let end = self.char_to_byte_offset(offset + length);
+        self.delete(start, end - start);
+    }
+
+    /// Returns true if there are undoable changes available
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    /// Returns true if there are redoable changes available
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    // ==================== Navigation ====================
 
-        // If we get here, char_offset is beyond the text
-        let full_text = self.get_text();
-        if full_text.is_empty() {
+    /// Moves to the specified character position and returns (line, column)
+    pub fn move_to(&self, char_offset: usize) -> (usize, usize) {
+        if self.pieces.is_empty() {
             return (1, 1);
         }
 
-        line = full_text.chars().filter(|&c| c == '\n').count() + 1;
+        let char_offset = char_offset.min(self.total_char_count);
+        let line = self.get_line_at_offset(char_offset);
+        let column = char_offset - self.line_start(line).unwrap_or(0) + 1;
+        (line, column)
+    }
+
+    /// Gets the line (1-indexed) containing a character offset in O(log n)
+    /// Offsets past the end map to the last line
+    pub fn get_line_at_offset(&self, char_offset: usize) -> usize {
+        let char_offset = char_offset.min(self.total_char_count);
+        let line_breaks_before = match self.pieces.find_char(char_offset) {
+            Some((idx, before)) => {
+                let bytes = self.piece_bytes(&self.pieces[idx]);
+                before.line_breaks + count_line_breaks(&bytes[..byte_of_char(bytes, char_offset - before.chars)])
+            }
+            None => 0,
+        };
+        line_breaks_before + 1
+    }
 
-        if let Some(last_newline_pos) = full_text.rfind('\n') {
-            column = full_text[last_newline_pos + 1..].chars().count() + 1;
-        } else {
-            column = full_text.chars().count() + 1;
+    /// Char offset where a line (1-indexed) starts, or None past the last line
+    fn line_start(&self, line_number: usize) -> Option<usize> {
+        if line_number <= 1 {
+            return Some(0);
         }
 
-        (line, column)
+        // The line starts after the (line_number - 1)th line break
+        let (idx, before) = self.pieces.find_line_break(line_number - 1)?;
+        let bytes = self.piece_bytes(&self.pieces[idx]);
+        let break_pos = bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == b'\n')
+            .nth(line_number - 2 - before.line_breaks)
+            .map(|(i, _)| i)?;
+        Some(before.chars + count_chars(&bytes[..break_pos]) + 1)
     }
 
     /// Gets the content of a specific line (1-indexed)
@@ -851,39 +1574,19 @@ impl PieceTree {
             return None;
         }
 
-        let mut current_line = 1usize;
-        let mut current_line_content = String::new();
-
-        for piece in &self.pieces {
-            let buffer_idx = Self::buffer_idx(&piece.buffer_id);
-            if let Some(buffer) = self.buffers.get(buffer_idx) {
-                let piece_text = if piece.start + piece.length <= buffer.len() {
-                    &buffer[piece.start..piece.start + piece.length]
-                } else {
-                    ""
-                };
-
-                for c in piece_text.chars() {
-                    if c == '\n' {
-                        if current_line == line_number {
-                            return Some(current_line_content);
-                        }
-                        current_line += 1;
-                        current_line_content.clear();
-                    } else {
-                        if current_line == line_number {
-                            current_line_content.push(c);
-                        }
-                    }
-                }
+        let start = self.char_to_byte_offset(self.line_start(line_number)?);
+        match self.line_start(line_number + 1) {
+            Some(next) => {
+                // Up to, not including, the line break
+                let end = self.char_to_byte_offset(next - 1);
+                Some(self.get_text_range(start, end - start))
+            }
+            None => {
+                // The last line is only reported when it has content
+                let content = self.get_text_range(start, self.total_length - start);
+                (!content.is_empty()).then_some(content)
             }
         }
-
-        if current_line == line_number && !current_line_content.is_empty() {
-            return Some(current_line_content);
-        }
-
-        None
     }
 
     /// Gets the line count
@@ -892,52 +1595,18 @@ impl PieceTree {
             return 0;
         }
 
-        let full_text = self.get_text();
-        if full_text.is_empty() {
-            return 1;
-        }
-
-        full_text.chars().filter(|&c| c == '\n').count() + 1
+        self.pieces.summary().line_breaks + 1
     }
 
     /// Gets the character offset for the start of a specific line (1-indexed)
     /// Returns 0 if the line number is invalid
     pub fn get_offset_at_line(&self, line_number: usize) -> usize {
-        if line_number == 0 || line_number == 1 {
-            return 0;
-        }
-
-        if self.pieces.is_empty() {
+        if line_number == 0 {
             return 0;
         }
 
-        let mut current_line = 1usize;
-        let mut char_count = 0usize;
-
-        for piece in &self.pieces {
-            let buffer_idx = Self::buffer_idx(&piece.buffer_id);
-            if let Some(buffer) = self.buffers.get(buffer_idx) {
-                let piece_text = if piece.start + piece.length <= buffer.len() {
-                    &buffer[piece.start..piece.start + piece.length]
-                } else {
-                    ""
-                };
-
-                for c in piece_text.chars() {
-                    if current_line == line_number {
-                        return char_count;
-                    }
-
-                    if c == '\n' {
-                        current_line += 1;
-                    }
-                    char_count += 1;
-                }
-            }
-        }
-
         // If line_number is beyond the document, return the total length
-        char_count
+        self.line_start(line_number).unwrap_or(self.total_char_count)
     }
 
     /// Gets total character count
@@ -966,7 +1635,7 @@ impl PieceTree {
     }
 
     /// Gets all pieces (for debugging)
-    pub fn get_all_pieces(&self) -> &Vec<Piece> {
+    pub fn get_all_pieces(&self) -> &PieceBTree {
         &self.pieces
     }
 
@@ -1006,11 +1675,11 @@ impl PieceTree {
             let matched_text = result.matched_text.clone();
             let matched_len = matched_text.len();
 
-            // Delete the matched text
-            self.delete(result.start, matched_len);
-
-            // Insert the replacement
-            self.insert(result.start, options.replace.clone());
+            // Delete the matched text and insert the replacement as one undo step
+            self.transaction(|tree| {
+                tree.delete(result.start, matched_len);
+                tree.insert(result.start, options.replace.clone());
+            });
 
             true
         } else {
@@ -1031,21 +1700,22 @@ impl PieceTree {
             return 0;
         }
 
-        // Work backwards to preserve positions
-        let mut replacements = 0;
-        for result in results.results.iter().rev() {
-            let matched_len = result.matched_text.len();
+        // Work backwards to preserve positions; undone as one step
+        self.transaction(|tree| {
+            let mut replacements = 0;
+            for result in results.results.iter().rev() {
+                let matched_len = result.matched_text.len();
 
-            // Delete the matched text
-            self.delete(result.start, matched_len);
+                // Delete the matched text
+                tree.delete(result.start, matched_len);
 
-            // Insert the replacement
-            self.insert(result.start, options.replace.clone());
+                // Insert the replacement
+                tree.insert(result.start, options.replace.clone());
 
-            replacements += 1;
-        }
-
-        replacements
+                replacements += 1;
+            }
+            replacements
+        })
     }
 
     /// Searches for text with options, returns JSON result for FFI
@@ -1090,6 +1760,83 @@ impl PieceTree {
         }
     }
 
+    // ==================== Consistency ====================
+
+    /// Check the tree's invariants, reporting the first one broken
+    ///
+    /// Every piece must be a non-empty slice of its buffer on char boundaries,
+    /// with the char and line break counts of that slice and well-formed
+    /// attributes; cached counts and totals must add up, and there must be one
+    /// paragraph entry per paragraph. This is O(n) in the number of pieces, for
+    /// tests and `debug_assert!`s rather than every edit.
+    pub fn validate(&self) -> Result<(), TreeCorruption> {
+        let mut chars = 0usize;
+        let mut bytes = 0usize;
+        let mut line_breaks = 0usize;
+
+        for (index, piece) in self.pieces.iter().enumerate() {
+            let buffer_index = Self::buffer_idx(&piece.buffer_id);
+            let buffer = self.buffers.get(buffer_index).ok_or(TreeCorruption::MissingBuffer {
+                piece: index,
+                buffer: buffer_index,
+            })?;
+            if piece.end() > buffer.len() {
+                return Err(TreeCorruption::OutOfBounds {
+                    piece: index,
+                    start: piece.start,
+                    end: piece.end(),
+                    buffer_length: buffer.len(),
+                });
+            }
+            if piece.length == 0 {
+                return Err(TreeCorruption::EmptyPiece { piece: index });
+            }
+            let text = buffer
+                .get(piece.start..piece.end())
+                .ok_or(TreeCorruption::SplitChar { piece: index })?;
+
+            let actual = text.chars().count();
+            if piece.piece_char_length != actual {
+                return Err(TreeCorruption::CharLength { piece: index, recorded: piece.piece_char_length, actual });
+            }
+            let actual = count_line_breaks(text.as_bytes());
+            let recorded = self.pieces.line_breaks_at(index);
+            if recorded != actual {
+                return Err(TreeCorruption::LineBreaks { piece: index, recorded, actual });
+            }
+            if let Some(attributes) = &piece.attributes {
+                if let Some(reason) = malformed_attributes(attributes) {
+                    return Err(TreeCorruption::Attributes { piece: index, reason });
+                }
+            }
+
+            chars += piece.piece_char_length;
+            bytes += piece.length;
+            line_breaks += actual;
+        }
+
+        if !self.pieces.is_consistent() {
+            return Err(TreeCorruption::Summaries);
+        }
+        let summary = self.pieces.summary();
+        for (measure, recorded, actual) in [
+            ("pieces", summary.pieces, self.pieces.iter().count()),
+            ("chars", summary.chars, chars),
+            ("bytes", summary.bytes, bytes),
+            ("line breaks", summary.line_breaks, line_breaks),
+            ("chars", self.total_char_count, chars),
+            ("bytes", self.total_length, bytes),
+        ] {
+            if recorded != actual {
+                return Err(TreeCorruption::Total { measure, recorded, actual });
+            }
+        }
+        if self.paragraphs.len() != line_breaks + 1 {
+            return Err(TreeCorruption::Paragraphs { recorded: self.paragraphs.len(), actual: line_breaks + 1 });
+        }
+        Ok(())
+    }
+
     /// Debug: prints tree structure
     pub fn debug_print(&self) {
         println!("PieceTree with {} pieces, {} chars, {} bytes",
@@ -1109,6 +1856,124 @@ impl PieceTree {
     }
 }
 
+/// Read-only view of a piece tree's content at one point in time
+///
+/// Snapshots are `Send + Sync`, so exports and other long reads can run on
+/// another thread while editing continues.
+#[derive(Debug, Clone)]
+pub struct TextSnapshot {
+    pieces: PieceBTree,
+    buffers: Vec<Arc<str>>,
+}
+
+impl TextSnapshot {
+    /// Pieces in document order
+    pub fn pieces(&self) -> &PieceBTree {
+        &self.pieces
+    }
+
+    /// Total character count
+    pub fn char_count(&self) -> usize {
+        self.pieces.summary().chars
+    }
+
+    /// Total byte length
+    pub fn len(&self) -> usize {
+        self.pieces.summary().bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    /// Text of one piece
+    pub fn piece_text(&self, piece: &Piece) -> &str {
+        self.buffers
+            .get(PieceTree::buffer_idx(&piece.buffer_id))
+            .and_then(|b| b.get(piece.start..piece.end()))
+            .unwrap_or("")
+    }
+
+    /// Text and attributes of each piece in document order
+    pub fn runs(&self) -> impl Iterator<Item = (&str, Option<&TextAttributes>)> {
+        self.pieces
+            .iter()
+            .map(|piece| (self.piece_text(piece), piece.attributes.as_ref()))
+    }
+
+    /// Full text content
+    pub fn get_text(&self) -> String {
+        let mut text = String::with_capacity(self.len());
+        for (run, _) in self.runs() {
+            text.push_str(run);
+        }
+        text
+    }
+}
+
+/// Why attributes are malformed: a zero font size, an empty font family or a
+/// color that is neither "auto" nor hex RGB or ARGB, with or without a leading '#'
+fn malformed_attributes(attributes: &TextAttributes) -> Option<&'static str> {
+    let color_ok = |color: &String| {
+        let value = color.strip_prefix('#').unwrap_or(color);
+        value == "auto" || (matches!(value.len(), 6 | 8) && value.bytes().all(|b| b.is_ascii_hexdigit()))
+    };
+    if attributes.font_size == Some(0) {
+        Some("font size 0")
+    } else if attributes.font_family.as_deref().is_some_and(str::is_empty) {
+        Some("empty font family")
+    } else if !attributes.foreground.iter().all(color_ok) {
+        Some("foreground is not a color")
+    } else if !attributes.background.iter().all(color_ok) {
+        Some("background is not a color")
+    } else {
+        None
+    }
+}
+
+/// Number of '\n' bytes
+fn count_line_breaks(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|b| **b == b'\n').count()
+}
+
+/// Splits a piece of new text into pieces of at most MAX_PIECE_BYTES, on char boundaries
+fn chunk_piece(piece: Piece, bytes: &[u8]) -> Vec<(Piece, usize)> {
+    let mut pieces = Vec::with_capacity(bytes.len() / MAX_PIECE_BYTES + 1);
+    let mut from = 0;
+    while from < bytes.len() {
+        let mut to = (from + MAX_PIECE_BYTES).min(bytes.len());
+        while to < bytes.len() && (bytes[to] & 0xC0) == 0x80 {
+            to -= 1;
+        }
+        let chunk = &bytes[from..to];
+        let sub = Piece::new_with_attrs(
+            piece.start + from,
+            to - from,
+            piece.buffer_id,
+            count_chars(chunk),
+            piece.attributes.clone(),
+        );
+        pieces.push((sub, count_line_breaks(chunk)));
+        from = to;
+    }
+    pieces
+}
+
+/// Number of chars that start in a byte slice; safe on slices that split a char
+fn count_chars(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|b| (**b & 0xC0) != 0x80).count()
+}
+
+/// Byte offset where the `chars`th char of a slice starts, or the slice length
+fn byte_of_char(bytes: &[u8], chars: usize) -> usize {
+    bytes
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| (**b & 0xC0) != 0x80)
+        .nth(chars)
+        .map_or(bytes.len(), |(i, _)| i)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1286,6 +2151,541 @@ mod tests {
         assert_eq!(pt.get_text(), "AAA CCC");
     }
 
+    #[test]
+    fn test_piece_tree_matches_string_model() {
+        let mut pt = PieceTree::new("Start\n".to_string());
+        let mut model = String::from("Start\n");
+        let snippets = ["ab", "\n", "世界", "x\ny", "é"];
+
+        // Deterministic pseudo-random edits
+        let mut seed = 42usize;
+        let mut next = |bound: usize| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345) % (1 << 31);
+            seed % bound.max(1)
+        };
+        for step in 0..600 {
+            let char_len = model.chars().count();
+            if step % 3 == 2 && char_len > 0 {
+                // Delete takes byte offsets on char boundaries
+                let start = model.char_indices().nth(next(char_len)).unwrap().0;
+                let end = model[start..].char_indices().nth(1 + next(3)).map_or(model.len(), |(i, _)| start + i);
+                pt.delete(start, end - start);
+                model.replace_range(start..end, "");
+            } else {
+                let at = next(char_len + 1);
+                let snippet = snippets[next(snippets.len())];
+                pt.insert(at, snippet.to_string());
+                let byte = model.char_indices().nth(at).map_or(model.len(), |(i, _)| i);
+                model.insert_str(byte, snippet);
+            }
+
+            assert_eq!(pt.get_text(), model);
+            assert_eq!(pt.char_count(), model.chars().count());
+            assert_eq!(pt.len(), model.len());
+        }
+
+        let lines: Vec<&str> = model.split('\n').collect();
+        assert_eq!(pt.get_line_count(), lines.len());
+        let mut line_start = 0;
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(pt.get_offset_at_line(i + 1), line_start);
+            assert_eq!(pt.move_to(line_start), (i + 1, 1));
+            if i + 1 < lines.len() || !line.is_empty() {
+                assert_eq!(pt.get_line(i + 1).as_deref(), Some(*line));
+            }
+            line_start += line.chars().count() + 1;
+        }
+
+        let mut line = 1;
+        for (offset, ch) in model.chars().enumerate() {
+            assert_eq!(pt.get_line_at_offset(offset), line);
+            if ch == '\n' {
+                line += 1;
+            }
+        }
+        assert_eq!(pt.get_line_at_offset(usize::MAX), lines.len());
+    }
+
+    #[test]
+    fn test_piece_tree_large_document_edits() {
+        let text = "0123456789abcdefghi\n".repeat(50_000);
+        let mut pt = PieceTree::new(text);
+        assert_eq!(pt.char_count(), 1_000_000);
+
+        for i in 0..5_000 {
+            let offset = (i * 7_919) % pt.char_count();
+            pt.insert(offset, "+".to_string());
+            let delete_at = pt.char_to_byte_offset((i * 104_729) % pt.char_count());
+            pt.delete(delete_at, 1);
+        }
+
+        assert_eq!(pt.char_count(), 1_000_000);
+        assert!(pt.piece_count() > 5_000);
+        assert_eq!(pt.get_text().len(), 1_000_000);
+        assert_eq!(pt.pieces.summary().chars, pt.char_count());
+    }
+
+    #[test]
+    fn test_undo_coalesces_typing_by_word() {
+        let mut pt = PieceTree::empty();
+        for (i, ch) in "hello world".chars().enumerate() {
+            pt.insert(i, ch.to_string());
+        }
+
+        assert!(pt.undo());
+        assert_eq!(pt.get_text(), "hello ");
+        assert!(pt.undo());
+        assert_eq!(pt.get_text(), "");
+        assert!(!pt.can_undo());
+
+        assert!(pt.redo());
+        assert!(pt.redo());
+        assert_eq!(pt.get_text(), "hello world");
+        assert_eq!(pt.selection, Selection::new(11, 11));
+    }
+
+    #[test]
+    fn test_undo_coalescing_breaks() {
+        let mut pt = PieceTree::new("ab".to_string());
+        pt.insert(2, "c".to_string());
+        // Not adjacent to the previous insert
+        pt.insert(0, "x".to_string());
+        pt.insert(1, "y".to_string());
+        pt.break_undo_coalescing();
+        pt.insert(2, "z".to_string());
+        // Pastes are never coalesced
+        pt.insert(3, "pasted".to_string());
+
+        let mut states = Vec::new();
+        while pt.undo() {
+            states.push(pt.get_text());
+        }
+        assert_eq!(states, ["xyzabc", "xyabc", "abc", "ab"]);
+    }
+
+    #[test]
+    fn test_transaction_is_one_undo_step() {
+        let mut pt = PieceTree::new("a-b-c-d".to_string());
+        pt.set_selection(0, 1);
+        let options = SearchOptions {
+            query: "-".to_string(),
+            replace: "+".to_string(),
+            wrap_around: false,
+            ..Default::default()
+        };
+        assert_eq!(pt.replace_all(&options), 3);
+        assert_eq!(pt.get_text(), "a+b+c+d");
+
+        assert!(pt.undo());
+        assert_eq!(pt.get_text(), "a-b-c-d");
+        assert_eq!(pt.selection, Selection::new(0, 1));
+        assert!(!pt.can_undo());
+
+        assert!(pt.redo());
+        assert_eq!(pt.get_text(), "a+b+c+d");
+    }
+
+    #[test]
+    fn test_nested_transactions_and_multibyte_undo() {
+        let mut pt = PieceTree::new("你好".to_string());
+        pt.begin_transaction();
+        pt.insert(1, "世界".to_string());
+        pt.transaction(|tree| {
+            let at = tree.char_to_byte_offset(0);
+            tree.delete(at, "你".len());
+        });
+        // Still open: nothing to undo yet
+        assert!(!pt.can_undo());
+        pt.end_transaction();
+        assert_eq!(pt.get_text(), "世界好");
+        let after = pt.selection;
+
+        assert!(pt.undo());
+        assert_eq!(pt.get_text(), "你好");
+        assert!(pt.redo());
+        assert_eq!(pt.get_text(), "世界好");
+        assert_eq!(pt.selection, after);
+    }
+
+    #[test]
+    fn test_undo_restores_full_editor_state() {
+        let mut pt = PieceTree::new("one two three".to_string());
+        let before = EditorState {
+            selections: vec![Selection::new(3, 3), Selection::new(7, 7)],
+            scroll_anchor: Some(4),
+            active_cell: Some(CellPosition { table: 0, row: 1, column: 2 }),
+        };
+        pt.set_editor_state(before.clone());
+
+        pt.insert(0, ">> ".to_string());
+        // Additional carets and the scroll anchor follow the text
+        let after = pt.editor_state();
+        assert_eq!(after.selections[1], Selection::new(10, 10));
+        assert_eq!(after.scroll_anchor, Some(7));
+
+        pt.set_editor_state(EditorState::default());
+        assert!(pt.undo());
+        assert_eq!(pt.get_text(), "one two three");
+        assert_eq!(pt.editor_state(), before);
+
+        assert!(pt.redo());
+        assert_eq!(pt.editor_state(), after);
+    }
+
+    #[test]
+    fn test_typing_and_deleting_at_every_selection() {
+        let mut pt = PieceTree::new("one two three".to_string());
+        let mut selections = MultiSelection::new(Selection::new(3, 3));
+        selections.add(Selection::new(4, 7));
+        selections.add(Selection::new(13, 13));
+        // A caret touching a range merges into it
+        selections.add(Selection::new(7, 7));
+        assert_eq!(selections.selections(), [Selection::new(3, 3), Selection::new(4, 7), Selection::new(13, 13)]);
+        pt.set_multi_selection(&selections);
+        let before = pt.editor_state();
+        assert_eq!(before.selections[0], Selection::new(4, 7));
+
+        let after = pt.insert_at_selections("!");
+        assert_eq!(pt.get_text(), "one! ! three!");
+        assert_eq!(after.selections(), [Selection::new(4, 4), Selection::new(6, 6), Selection::new(13, 13)]);
+        assert_eq!(pt.selection, Selection::new(6, 6));
+
+        pt.delete_at_selections(DeleteDirection::Backward);
+        assert_eq!(pt.get_text(), "one  three");
+        pt.delete_at_selections(DeleteDirection::Forward);
+        assert_eq!(pt.get_text(), "onethree");
+        assert_eq!(pt.multi_selection().selections(), [Selection::new(3, 3), Selection::new(8, 8)]);
+
+        // Each edit is one undo step, restoring every caret
+        assert!(pt.undo());
+        assert!(pt.undo());
+        assert_eq!(pt.editor_state(), after_state(&after));
+        assert!(pt.undo());
+        assert_eq!(pt.get_text(), "one two three");
+        assert_eq!(pt.editor_state(), before);
+        assert!(pt.redo());
+        assert_eq!(pt.multi_selection(), after);
+
+        fn after_state(selections: &MultiSelection) -> EditorState {
+            EditorState {
+                selections: selections.ordered(),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[test]
+    fn test_delete_translates_extra_carets() {
+        let mut pt = PieceTree::new("héllo wörld".to_string());
+        pt.set_editor_state(EditorState {
+            selections: vec![Selection::new(0, 0), Selection::new(2, 2), Selection::new(8, 9)],
+            scroll_anchor: Some(10),
+            active_cell: None,
+        });
+
+        // Delete "éllo " (chars 1..6)
+        let start = pt.char_to_byte_offset(1);
+        let end = pt.char_to_byte_offset(6);
+        pt.delete(start, end - start);
+
+        let state = pt.editor_state();
+        assert_eq!(state.selections[1..], [Selection::new(1, 1), Selection::new(3, 4)]);
+        assert_eq!(state.scroll_anchor, Some(5));
+    }
+
+    #[test]
+    fn test_delete_before_the_selection_shifts_it_by_chars() {
+        let mut pt = PieceTree::new("日本語 text".to_string());
+        pt.set_selection(4, 8);
+
+        // Delete "日本" (chars 0..2, bytes 0..6)
+        pt.delete(0, pt.char_to_byte_offset(2));
+        assert_eq!(pt.get_selection_range(), (2, 6));
+
+        // A deletion overlapping the selection collapses it to the deletion's start
+        pt.delete(pt.char_to_byte_offset(1), pt.char_to_byte_offset(4) - pt.char_to_byte_offset(1));
+        assert_eq!(pt.get_selection_range(), (1, 1));
+    }
+
+    #[test]
+    fn test_apply_attributes_splits_and_merges_pieces() {
+        let mut pt = PieceTree::new("héllo wörld".to_string());
+        let bold = TextAttributes { bold: Some(true), ..Default::default() };
+        let italic = TextAttributes { italic: Some(true), ..Default::default() };
+
+        assert!(pt.apply_attributes(1..8, &bold));
+        assert_eq!(pt.piece_count(), 3);
+        assert!(pt.apply_attributes(6..11, &italic));
+        let bold_italic = TextAttributes { bold: Some(true), italic: Some(true), ..Default::default() };
+        assert_eq!(
+            pt.attribute_spans(0..11),
+            vec![
+                AttributeSpan { length: 1, attributes: None },
+                AttributeSpan { length: 5, attributes: Some(bold.clone()) },
+                AttributeSpan { length: 2, attributes: Some(bold_italic) },
+                AttributeSpan { length: 3, attributes: Some(italic) },
+            ]
+        );
+
+        // Clearing everything joins the pieces of the original buffer again
+        assert!(pt.clear_attributes(0..11));
+        assert_eq!(pt.piece_count(), 1);
+        assert_eq!(pt.get_text(), "héllo wörld");
+        assert!(!pt.apply_attributes(4..4, &bold));
+    }
+
+    #[test]
+    fn test_attribute_queries() {
+        let mut pt = PieceTree::new("plain bold".to_string());
+        let bold = TextAttributes { bold: Some(true), font_size: Some(12), ..Default::default() };
+        pt.apply_attributes(6..10, &bold);
+
+        assert_eq!(pt.get_attributes_at(5), None);
+        assert_eq!(pt.get_attributes_at(6), Some(bold.clone()));
+        assert_eq!(pt.get_attributes_at(99), Some(bold));
+
+        let common = pt.get_common_attributes(6..10);
+        assert_eq!(common.bold, AttributeState::All(true));
+        assert_eq!(common.italic, AttributeState::None);
+        assert_eq!(common.font_size, AttributeState::All(12));
+
+        let common = pt.get_common_attributes(4..8);
+        assert_eq!(common.bold, AttributeState::Mixed);
+        assert_eq!(common.font_size, AttributeState::Mixed);
+        assert_eq!(common.font_family, AttributeState::None);
+
+        // A caret reports the char at its position
+        assert_eq!(pt.get_common_attributes(7..7).bold, AttributeState::All(true));
+        assert_eq!(
+            serde_json::to_value(&pt.get_common_attributes(0..3).bold).unwrap(),
+            serde_json::json!({ "state": "none" })
+        );
+    }
+
+    #[test]
+    fn test_formatting_is_undoable() {
+        let mut pt = PieceTree::new("one two".to_string());
+        let bold = TextAttributes { bold: Some(true), ..Default::default() };
+        pt.apply_attributes(0..3, &bold);
+        pt.insert(7, " three".to_string());
+        pt.clear_attributes(1..2);
+        let formatted = pt.attribute_spans(0..13);
+
+        assert!(pt.undo());
+        assert_eq!(pt.attribute_spans(0..3), vec![AttributeSpan { length: 3, attributes: Some(bold) }]);
+        assert!(pt.undo());
+        assert!(pt.undo());
+        assert_eq!(pt.attribute_spans(0..7), vec![AttributeSpan { length: 7, attributes: None }]);
+        assert_eq!(pt.piece_count(), 1);
+
+        while pt.redo() {}
+        assert_eq!(pt.get_text(), "one two three");
+        assert_eq!(pt.attribute_spans(0..13), formatted);
+    }
+
+    #[test]
+    fn test_paragraph_attributes_follow_paragraph_breaks() {
+        use crate::line_layout::Alignment;
+
+        let mut pt = PieceTree::new("one\ntwo\nthree".to_string());
+        let centered = ParagraphAttributes { alignment: Some(Alignment::Center), ..Default::default() };
+        let indented = ParagraphAttributes { indent_left: Some(720), ..Default::default() };
+
+        // A range ending right after a break leaves the next paragraph alone
+        assert_eq!(pt.paragraphs_in(0..4), 0..1);
+        assert!(pt.apply_paragraph_attributes(2..6, &centered));
+        assert!(!pt.apply_paragraph_attributes(5..5, &centered));
+        assert!(pt.apply_paragraph_attributes(5..5, &indented));
+        let centered_indented = centered.merged_with(&indented);
+        assert_eq!(
+            pt.paragraph_attributes_in(0..13),
+            vec![Some(centered.clone()), Some(centered_indented.clone()), None]
+        );
+
+        // Enter continues the paragraph's formatting; joining keeps the first paragraph's
+        pt.insert(6, "\n".to_string());
+        assert_eq!(pt.paragraph_count(), 4);
+        assert_eq!(pt.paragraph_attributes(2), Some(&centered_indented));
+        let second_break = pt.char_to_byte_offset(3);
+        pt.delete(second_break, 1);
+        assert_eq!(pt.paragraph_attributes_in(0..14), vec![Some(centered.clone()), Some(centered_indented.clone()), None]);
+
+        assert!(pt.undo());
+        assert!(pt.undo());
+        assert_eq!(pt.paragraph_count(), 3);
+        assert!(pt.undo());
+        assert_eq!(pt.paragraph_attributes_at(5), Some(centered.clone()));
+        assert!(pt.redo());
+        assert_eq!(pt.paragraph_attributes_at(5), Some(centered_indented));
+        assert!(pt.jump_to_history(0));
+        assert_eq!(pt.paragraph_attributes_in(0..13), vec![None, None, None]);
+    }
+
+    #[test]
+    fn test_editing_after_undo_keeps_redo_branch() {
+        let mut pt = PieceTree::new("base".to_string());
+        pt.insert(4, " one".to_string());
+        let one = pt.current_history_node();
+        assert!(pt.undo());
+        pt.insert(4, " two".to_string());
+        let two = pt.current_history_node();
+        assert!(!pt.can_redo());
+        assert_eq!(pt.history_branches(), vec![one, two]);
+
+        assert!(pt.jump_to_history(one));
+        assert_eq!(pt.get_text(), "base one");
+        // Redo now follows the branch jumped to
+        assert!(pt.undo());
+        assert!(pt.redo());
+        assert_eq!(pt.get_text(), "base one");
+
+        assert!(pt.jump_to_history(two));
+        assert_eq!(pt.get_text(), "base two");
+        assert!(!pt.jump_to_history(999));
+    }
+
+    #[test]
+    fn test_jump_between_deep_branches_uses_checkpoints() {
+        let mut pt = PieceTree::new(String::new());
+        for i in 0..40 {
+            pt.insert(pt.char_count(), format!("a{}\n", i));
+        }
+        let left = pt.current_history_node();
+        let left_text = pt.get_text();
+        for _ in 0..35 {
+            pt.undo();
+        }
+        for i in 0..40 {
+            pt.insert(pt.char_count(), format!("b{}\n", i));
+        }
+        let right_text = pt.get_text();
+
+        assert!(pt.jump_to_history(left));
+        assert_eq!(pt.get_text(), left_text);
+        assert_eq!(pt.char_count(), left_text.chars().count());
+        assert!(pt.jump_to_history(pt.history_branches()[1]));
+        assert_eq!(pt.get_text(), right_text);
+
+        // Undo keeps working from a restored checkpoint
+        while pt.undo() {}
+        assert_eq!(pt.get_text(), "");
+    }
+
+    #[test]
+    fn test_history_memory_budget_drops_oldest_steps() {
+        let mut pt = PieceTree::new(String::new());
+        for c in ['a', 'b', 'c'] {
+            pt.insert(pt.char_count(), c.to_string().repeat(100_000));
+        }
+        pt.undo();
+        pt.insert(pt.char_count(), "!".to_string());
+        let usage = pt.history_memory_usage();
+        assert!(usage.bytes > 300_000);
+        assert_eq!((usage.budget, usage.steps), (None, 5));
+
+        // The first paste is the oldest step
+        pt.set_history_memory_budget(Some(250_000));
+        let usage = pt.history_memory_usage();
+        assert!(usage.bytes <= 250_000);
+        assert_eq!((usage.budget, usage.steps), (Some(250_000), 4));
+
+        // Then the second, older than the abandoned third, and then the third
+        pt.set_history_memory_budget(Some(50_000));
+        assert!(pt.history_memory_usage().bytes <= 50_000);
+        assert_eq!(pt.history_branches().len(), 1);
+        assert!(pt.undo());
+        assert!(!pt.undo());
+        assert_eq!(pt.char_count(), 200_000);
+
+        // Lifting the budget keeps what is left, and new steps are kept again
+        pt.set_history_memory_budget(None);
+        pt.insert(0, "x".repeat(100_000));
+        assert!(pt.history_memory_usage().bytes > 100_000);
+        assert_eq!(pt.history_memory_usage().steps, 3);
+    }
+
+    #[test]
+    fn test_history_budget_counts_and_releases_buffers() {
+        let mut pt = PieceTree::new(String::new());
+        let buffered = |pt: &PieceTree| pt.buffers.iter().map(|buffer| buffer.len()).sum::<usize>();
+        pt.insert(0, "a".repeat(100_000));
+        for _ in 0..15 {
+            pt.insert(pt.char_count(), "bb".to_string());
+        }
+        // Leaving the 16th step checkpoints it, and the checkpoint is then the
+        // only thing referring to the pasted text
+        pt.delete(0, pt.total_length);
+        assert_eq!(buffered(&pt), 100_030);
+
+        pt.set_history_memory_budget(Some(10_000_000));
+        let usage = pt.history_memory_usage();
+        assert_eq!(usage.text_bytes, 100_030);
+        assert_eq!(usage.steps, 18);
+
+        // Dropping the paste step frees its copy of the text, but not the buffer
+        pt.set_history_memory_budget(Some(250_000));
+        let usage = pt.history_memory_usage();
+        assert!(usage.bytes + usage.text_bytes <= 250_000);
+        assert_eq!(usage.text_bytes, 100_030);
+        assert!(pt.undo());
+        assert_eq!(pt.char_count(), 100_030);
+        assert!(pt.redo());
+
+        // Dropping the checkpoint releases the buffers, the one undo put the
+        // text back in too
+        pt.set_history_memory_budget(Some(150_000));
+        let usage = pt.history_memory_usage();
+        assert!(usage.bytes + usage.text_bytes <= 150_000);
+        assert_eq!(usage.text_bytes, 0);
+        assert_eq!(buffered(&pt), 0);
+        assert!(!pt.undo());
+
+        pt.insert(0, "c".to_string());
+        assert_eq!(pt.get_text(), "c");
+        assert!(pt.validate().is_ok());
+    }
+
+    #[test]
+    fn test_save_and_restore_history() {
+        let mut pt = PieceTree::new("x".to_string());
+        pt.insert(1, "y".to_string());
+        pt.insert(2, "\nz".to_string());
+        pt.undo();
+        pt.insert(2, "w".to_string());
+        let json = serde_json::to_string(&pt.save_history()).unwrap();
+
+        let mut restored = PieceTree::new(pt.get_text());
+        assert!(restored.restore_history(serde_json::from_str(&json).unwrap()));
+        assert_eq!(restored.history_nodes(), pt.history_nodes());
+        assert!(restored.jump_to_history(pt.history_branches()[0]));
+        assert_eq!(restored.get_text(), "xy\nz");
+        while restored.undo() {}
+        assert_eq!(restored.get_text(), "x");
+
+        let mut saved = pt.save_history();
+        saved.current = 42;
+        assert!(!restored.restore_history(saved));
+    }
+
+    #[test]
+    fn test_piece_tree_line_queries_on_large_text() {
+        // Multi-byte chars straddle the chunk boundaries
+        let text = "第一行 line\n".repeat(40_000);
+        let pt = PieceTree::new(text.clone());
+        assert!(pt.piece_count() > 1);
+        assert!(pt.pieces.iter().all(|p| p.length <= MAX_PIECE_BYTES));
+        assert_eq!(pt.get_text(), text);
+
+        let line_chars = "第一行 line\n".chars().count();
+        assert_eq!(pt.get_line_count(), 40_001);
+        for line in [1, 2, 1_234, 20_000, 40_000] {
+            let start = (line - 1) * line_chars;
+            assert_eq!(pt.get_offset_at_line(line), start);
+            assert_eq!(pt.get_line_at_offset(start + 3), line);
+            assert_eq!(pt.get_line(line).as_deref(), Some("第一行 line"));
+        }
+    }
+
     // ==================== Selection Tests ====================
 
     #[test]
@@ -1439,4 +2839,133 @@ mod tests {
         assert_eq!(pt.get_selection_anchor(), 11); // end of text
         assert_eq!(pt.get_selection_active(), 11);
     }
+
+    #[test]
+    fn test_validate_reports_corruption() {
+        let tree = PieceTree::new("héllo\nworld".to_string());
+        assert_eq!(tree.validate(), Ok(()));
+
+        let wrong_count = PieceTree::from_loaded_data(vec![Piece::new(0, 6, BufferId::ORIGINAL, 6)], vec!["héllo".to_string()]);
+        assert_eq!(
+            wrong_count.validate(),
+            Err(TreeCorruption::CharLength { piece: 0, recorded: 6, actual: 5 })
+        );
+        let split = PieceTree::from_loaded_data(vec![Piece::new(0, 2, BufferId::ORIGINAL, 2)], vec!["héllo".to_string()]);
+        assert_eq!(split.validate(), Err(TreeCorruption::SplitChar { piece: 0 }));
+
+        let mut tree = PieceTree::new("abc".to_string());
+        tree.apply_attributes(0..1, &TextAttributes { foreground: Some("red".to_string()), ..Default::default() });
+        assert!(matches!(tree.validate(), Err(TreeCorruption::Attributes { piece: 0, .. })));
+    }
+
+    #[test]
+    fn test_delete_inside_a_char_keeps_whole_chars() {
+        let mut tree = PieceTree::new("héllo".to_string());
+        // Byte 2 is inside 'é'; the range moves to the boundary after it
+        assert!(tree.delete(2, 2));
+        assert_eq!(tree.get_text(), "hélo");
+        assert_eq!(tree.validate(), Ok(()));
+        assert!(!tree.delete(2, 1));
+        assert!(tree.undo());
+        assert_eq!(tree.get_text(), "héllo");
+    }
+
+    /// An edit of a randomized operation sequence
+    #[derive(Debug, Clone)]
+    enum Edit {
+        /// Char offset, wrapped to the text
+        Insert { at: usize, text: String },
+        /// Byte range, wrapped to the text; may start or end inside a char
+        Delete { at: usize, length: usize },
+        /// Char range, wrapped to the text
+        Format { at: usize, length: usize, bold: bool },
+        /// Undo, checking that redo restores the text
+        UndoRedo,
+    }
+
+    use quickcheck::{Arbitrary, TestResult};
+
+    impl Arbitrary for Edit {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            let samples = ["\n", "é", "日本語", "🎉", "a\nb", "x"];
+            match u8::arbitrary(g) % 7 {
+                0..=2 => Edit::Insert {
+                    at: usize::arbitrary(g),
+                    text: if bool::arbitrary(g) {
+                        String::arbitrary(g)
+                    } else {
+                        g.choose(&samples).unwrap().to_string()
+                    },
+                },
+                3 | 4 => Edit::Delete { at: usize::arbitrary(g), length: usize::arbitrary(g) % 12 },
+                5 => Edit::Format { at: usize::arbitrary(g), length: usize::arbitrary(g) % 12, bold: bool::arbitrary(g) },
+                _ => Edit::UndoRedo,
+            }
+        }
+    }
+
+    /// Apply the edits to a piece tree and a plain String, validating the tree after each
+    fn edits_match_string_model(initial: String, edits: Vec<Edit>) -> TestResult {
+        let mut tree = PieceTree::new(initial.clone());
+        let mut model = initial.clone();
+        let ceil_boundary = |text: &str, mut byte: usize| {
+            while !text.is_char_boundary(byte) {
+                byte += 1;
+            }
+            byte
+        };
+
+        for edit in edits {
+            match edit {
+                Edit::Insert { at, text } => {
+                    let at = at % (model.chars().count() + 1);
+                    let byte = model.char_indices().nth(at).map_or(model.len(), |(i, _)| i);
+                    tree.insert(at, text.clone());
+                    model.insert_str(byte, &text);
+                }
+                Edit::Delete { at, length } => {
+                    let at = at % (model.len() + 1);
+                    let length = length.min(model.len() - at);
+                    tree.delete(at, length);
+                    let (start, end) = (ceil_boundary(&model, at), ceil_boundary(&model, at + length));
+                    model.replace_range(start..end, "");
+                }
+                Edit::Format { at, length, bold } => {
+                    let chars = model.chars().count();
+                    let at = at % (chars + 1);
+                    let bold = TextAttributes { bold: Some(bold), ..Default::default() };
+                    tree.apply_attributes(at..(at + length).min(chars), &bold);
+                }
+                Edit::UndoRedo => {
+                    if tree.undo() {
+                        if let Err(e) = tree.validate() {
+                            return TestResult::error(format!("invalid tree after undo: {}", e));
+                        }
+                        if !tree.redo() {
+                            return TestResult::error("redo failed after undo");
+                        }
+                    }
+                }
+            }
+            if let Err(e) = tree.validate() {
+                return TestResult::error(e.to_string());
+            }
+            if tree.get_text() != model {
+                return TestResult::error(format!("text {:?} differs from model {:?}", tree.get_text(), model));
+            }
+        }
+
+        while tree.undo() {}
+        if let Err(e) = tree.validate() {
+            return TestResult::error(format!("invalid tree after undoing everything: {}", e));
+        }
+        TestResult::from_bool(tree.get_text() == initial)
+    }
+
+    #[test]
+    fn test_random_edits_match_string_model() {
+        quickcheck::QuickCheck::new()
+            .tests(200)
+            .quickcheck(edits_match_string_model as fn(String, Vec<Edit>) -> TestResult);
+    }
 }
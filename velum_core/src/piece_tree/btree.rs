@@ -0,0 +1,593 @@
+//! B+ tree of pieces
+//!
+//! Pieces live in the leaves in document order. Every node caches the
+//! [`PieceSummary`] of its subtree (piece, char, byte and line break counts), so
+//! locating a piece by any of those measures, inserting and removing are all
+//! O(log n) in the number of pieces.
+//!
+//! Nodes are shared copy-on-write, so cloning a tree is O(1) and an edit only
+//! copies the nodes on its path while another clone is alive.
+
+use std::ops::{AddAssign, Index, SubAssign};
+use std::sync::Arc;
+
+use super::Piece;
+
+/// Maximum entries in a leaf and children of an internal node
+const MAX_ENTRIES: usize = 32;
+/// Nodes below this size are merged with a sibling
+const MIN_ENTRIES: usize = MAX_ENTRIES / 2;
+
+/// Aggregate counts over a run of pieces
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PieceSummary {
+    pub pieces: usize,
+    pub chars: usize,
+    pub bytes: usize,
+    pub line_breaks: usize,
+}
+
+impl AddAssign for PieceSummary {
+    fn add_assign(&mut self, other: PieceSummary) {
+        self.pieces += other.pieces;
+        self.chars += other.chars;
+        self.bytes += other.bytes;
+        self.line_breaks += other.line_breaks;
+    }
+}
+
+impl SubAssign for PieceSummary {
+    fn sub_assign(&mut self, other: PieceSummary) {
+        self.pieces -= other.pieces;
+        self.chars -= other.chars;
+        self.bytes -= other.bytes;
+        self.line_breaks -= other.line_breaks;
+    }
+}
+
+/// A piece with the number of '\n' in its text, which the piece itself cannot see
+#[derive(Debug, Clone)]
+struct Entry {
+    piece: Piece,
+    line_breaks: usize,
+}
+
+impl Entry {
+    fn summary(&self) -> PieceSummary {
+        PieceSummary {
+            pieces: 1,
+            chars: self.piece.piece_char_length,
+            bytes: self.piece.length,
+            line_breaks: self.line_breaks,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Leaf(Vec<Entry>),
+    Internal(Vec<Arc<Node>>),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    summary: PieceSummary,
+    kind: Kind,
+}
+
+impl Node {
+    fn new(kind: Kind) -> Self {
+        let mut node = Node {
+            summary: PieceSummary::default(),
+            kind,
+        };
+        node.recompute();
+        node
+    }
+
+    fn len(&self) -> usize {
+        match &self.kind {
+            Kind::Leaf(entries) => entries.len(),
+            Kind::Internal(children) => children.len(),
+        }
+    }
+
+    fn recompute(&mut self) {
+        let mut summary = PieceSummary::default();
+        match &self.kind {
+            Kind::Leaf(entries) => entries.iter().for_each(|e| summary += e.summary()),
+            Kind::Internal(children) => children.iter().for_each(|c| summary += c.summary),
+        }
+        self.summary = summary;
+    }
+
+    /// Summary recomputed from the leaves and the height of the subtree, or None
+    /// if a cached summary disagrees or sibling subtrees differ in height
+    fn verified(&self) -> Option<(PieceSummary, usize)> {
+        let mut summary = PieceSummary::default();
+        let height = match &self.kind {
+            Kind::Leaf(entries) => {
+                entries.iter().for_each(|e| summary += e.summary());
+                0
+            }
+            Kind::Internal(children) => {
+                let mut height = None;
+                for child in children {
+                    let (child_summary, child_height) = child.verified()?;
+                    if *height.get_or_insert(child_height) != child_height {
+                        return None;
+                    }
+                    summary += child_summary;
+                }
+                height.map_or(1, |h| h + 1)
+            }
+        };
+        (summary == self.summary).then_some((summary, height))
+    }
+
+    /// Move the upper half into a new right sibling
+    fn split(&mut self) -> Node {
+        let right = match &mut self.kind {
+            Kind::Leaf(entries) => Kind::Leaf(entries.split_off(entries.len() / 2)),
+            Kind::Internal(children) => Kind::Internal(children.split_off(children.len() / 2)),
+        };
+        self.recompute();
+        Node::new(right)
+    }
+
+    /// Append a right sibling of the same height
+    fn absorb(&mut self, other: Arc<Node>) {
+        match (&mut self.kind, Arc::unwrap_or_clone(other).kind) {
+            (Kind::Leaf(entries), Kind::Leaf(more)) => entries.extend(more),
+            (Kind::Internal(children), Kind::Internal(more)) => children.extend(more),
+            _ => unreachable!("siblings have the same height"),
+        }
+        self.recompute();
+    }
+
+    /// Child holding piece `index` and the number of pieces before it
+    fn child_at(children: &[Arc<Node>], index: usize) -> (usize, usize) {
+        let mut before = 0;
+        for (i, child) in children.iter().enumerate() {
+            if index < before + child.summary.pieces {
+                return (i, before);
+            }
+            before += child.summary.pieces;
+        }
+        // Appending: the end of the last child
+        let last = children.len() - 1;
+        (last, before - children[last].summary.pieces)
+    }
+
+    fn get(&self, index: usize) -> &Entry {
+        match &self.kind {
+            Kind::Leaf(entries) => &entries[index],
+            Kind::Internal(children) => {
+                let (i, before) = Self::child_at(children, index);
+                children[i].get(index - before)
+            }
+        }
+    }
+
+    /// Insert an entry; returns a new right sibling if this node overflowed
+    fn insert(&mut self, index: usize, entry: Entry) -> Option<Node> {
+        let added = entry.summary();
+        let overflow = match &mut self.kind {
+            Kind::Leaf(entries) => {
+                entries.insert(index, entry);
+                entries.len() > MAX_ENTRIES
+            }
+            Kind::Internal(children) => {
+                let (i, before) = Self::child_at(children, index);
+                if let Some(right) = Arc::make_mut(&mut children[i]).insert(index - before, entry) {
+                    children.insert(i + 1, Arc::new(right));
+                }
+                children.len() > MAX_ENTRIES
+            }
+        };
+        self.summary += added;
+        overflow.then(|| self.split())
+    }
+
+    fn remove(&mut self, index: usize) -> Entry {
+        let entry = match &mut self.kind {
+            Kind::Leaf(entries) => entries.remove(index),
+            Kind::Internal(children) => {
+                let (i, before) = Self::child_at(children, index);
+                let entry = Arc::make_mut(&mut children[i]).remove(index - before);
+                if children[i].len() < MIN_ENTRIES && children.len() > 1 {
+                    Self::rebalance(children, i);
+                }
+                entry
+            }
+        };
+        self.summary -= entry.summary();
+        entry
+    }
+
+    fn replace(&mut self, index: usize, entry: Entry) -> Entry {
+        let added = entry.summary();
+        let old = match &mut self.kind {
+            Kind::Leaf(entries) => std::mem::replace(&mut entries[index], entry),
+            Kind::Internal(children) => {
+                let (i, before) = Self::child_at(children, index);
+                Arc::make_mut(&mut children[i]).replace(index - before, entry)
+            }
+        };
+        self.summary -= old.summary();
+        self.summary += added;
+        old
+    }
+
+    /// Merge an underfull child with a neighbour, splitting again if that overflows
+    fn rebalance(children: &mut Vec<Arc<Node>>, i: usize) {
+        let left = if i + 1 < children.len() { i } else { i - 1 };
+        let right = children.remove(left + 1);
+        let merged = Arc::make_mut(&mut children[left]);
+        merged.absorb(right);
+        if merged.len() > MAX_ENTRIES {
+            let split = merged.split();
+            children.insert(left + 1, Arc::new(split));
+        }
+    }
+}
+
+/// Pieces in document order, stored in a B+ tree with subtree aggregates
+#[derive(Debug, Clone)]
+pub struct PieceBTree {
+    root: Arc<Node>,
+}
+
+impl Default for PieceBTree {
+    fn default() -> Self {
+        PieceBTree {
+            root: Arc::new(Node::new(Kind::Leaf(Vec::new()))),
+        }
+    }
+}
+
+impl PieceBTree {
+    /// Create an empty tree
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a tree in O(n) from pieces and their line break counts
+    pub(crate) fn from_pieces(pieces: impl IntoIterator<Item = (Piece, usize)>) -> Self {
+        let entries: Vec<Entry> = pieces
+            .into_iter()
+            .map(|(piece, line_breaks)| Entry { piece, line_breaks })
+            .collect();
+        if entries.len() <= MAX_ENTRIES {
+            return PieceBTree {
+                root: Arc::new(Node::new(Kind::Leaf(entries))),
+            };
+        }
+
+        let mut level: Vec<Arc<Node>> = chunk_evenly(entries)
+            .into_iter()
+            .map(|entries| Arc::new(Node::new(Kind::Leaf(entries))))
+            .collect();
+        while level.len() > 1 {
+            level = chunk_evenly(level)
+                .into_iter()
+                .map(|children| Arc::new(Node::new(Kind::Internal(children))))
+                .collect();
+        }
+        PieceBTree {
+            root: level.pop().unwrap(),
+        }
+    }
+
+    /// Aggregates over all pieces
+    pub fn summary(&self) -> PieceSummary {
+        self.root.summary
+    }
+
+    /// Whether every node's cached summary matches its subtree and all leaves are at one depth
+    pub(crate) fn is_consistent(&self) -> bool {
+        self.root.verified().is_some()
+    }
+
+    /// Number of pieces
+    pub fn len(&self) -> usize {
+        self.root.summary.pieces
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Piece> {
+        (index < self.len()).then(|| &self.root.get(index).piece)
+    }
+
+    pub fn first(&self) -> Option<&Piece> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&Piece> {
+        self.len().checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    /// Line breaks in the piece at `index`
+    pub fn line_breaks_at(&self, index: usize) -> usize {
+        self.root.get(index).line_breaks
+    }
+
+    /// Iterate pieces in document order
+    pub fn iter(&self) -> Iter<'_> {
+        self.iter_from(0)
+    }
+
+    /// Iterate pieces starting at piece `index`
+    pub fn iter_from(&self, index: usize) -> Iter<'_> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            leaf: [].iter(),
+        };
+        if index >= self.len() {
+            return iter;
+        }
+
+        let mut node: &Node = &self.root;
+        let mut index = index;
+        loop {
+            match &node.kind {
+                Kind::Leaf(entries) => {
+                    iter.leaf = entries[index..].iter();
+                    return iter;
+                }
+                Kind::Internal(children) => {
+                    let (i, before) = Node::child_at(children, index);
+                    iter.stack.push((children.as_slice(), i + 1));
+                    node = &children[i];
+                    index -= before;
+                }
+            }
+        }
+    }
+
+    /// Copy the pieces into a Vec
+    pub fn to_vec(&self) -> Vec<Piece> {
+        self.iter().cloned().collect()
+    }
+
+    pub(crate) fn insert(&mut self, index: usize, piece: Piece, line_breaks: usize) {
+        let index = index.min(self.len());
+        if let Some(right) = Arc::make_mut(&mut self.root).insert(index, Entry { piece, line_breaks }) {
+            let left = self.root.clone();
+            self.root = Arc::new(Node::new(Kind::Internal(vec![left, Arc::new(right)])));
+        }
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) -> Piece {
+        let entry = Arc::make_mut(&mut self.root).remove(index);
+        // Collapse a root that is left with a single child
+        loop {
+            let only_child = match &self.root.kind {
+                Kind::Internal(children) if children.len() == 1 => children[0].clone(),
+                _ => break,
+            };
+            self.root = only_child;
+        }
+        entry.piece
+    }
+
+    pub(crate) fn replace(&mut self, index: usize, piece: Piece, line_breaks: usize) -> Piece {
+        Arc::make_mut(&mut self.root).replace(index, Entry { piece, line_breaks }).piece
+    }
+
+    /// First piece whose end, measured by `measure`, reaches `target`
+    ///
+    /// With `inclusive` a piece ending exactly at `target` matches; otherwise the
+    /// piece must extend past it. Returns the piece index and the aggregates of
+    /// all pieces before it.
+    fn find(&self, target: usize, measure: fn(&PieceSummary) -> usize, inclusive: bool) -> Option<(usize, PieceSummary)> {
+        let reaches = |end: usize| if inclusive { target <= end } else { target < end };
+        if self.is_empty() || !reaches(measure(&self.root.summary)) {
+            return None;
+        }
+
+        let mut before = PieceSummary::default();
+        let mut node: &Node = &self.root;
+        loop {
+            match &node.kind {
+                Kind::Leaf(entries) => {
+                    for entry in entries {
+                        let summary = entry.summary();
+                        if reaches(measure(&before) + measure(&summary)) {
+                            return Some((before.pieces, before));
+                        }
+                        before += summary;
+                    }
+                    return None;
+                }
+                Kind::Internal(children) => {
+                    let child = children
+                        .iter()
+                        .find(|child| {
+                            let found = reaches(measure(&before) + measure(&child.summary));
+                            if !found {
+                                before += child.summary;
+                            }
+                            found
+                        })?;
+                    node = child;
+                }
+            }
+        }
+    }
+
+    /// Piece containing char `offset`, or ending at it; with the aggregates before it
+    pub fn find_char(&self, offset: usize) -> Option<(usize, PieceSummary)> {
+        self.find(offset, |s| s.chars, true)
+    }
+
+    /// Piece containing byte `offset`; with the aggregates before it
+    pub fn find_byte(&self, offset: usize) -> Option<(usize, PieceSummary)> {
+        self.find(offset, |s| s.bytes, false)
+    }
+
+    /// Piece containing the `n`th line break (1-based); with the aggregates before it
+    pub fn find_line_break(&self, n: usize) -> Option<(usize, PieceSummary)> {
+        if n == 0 {
+            return None;
+        }
+        self.find(n, |s| s.line_breaks, true)
+    }
+}
+
+impl Index<usize> for PieceBTree {
+    type Output = Piece;
+
+    fn index(&self, index: usize) -> &Piece {
+        self.get(index).expect("piece index out of range")
+    }
+}
+
+impl<'a> IntoIterator for &'a PieceBTree {
+    type Item = &'a Piece;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over pieces in document order
+pub struct Iter<'a> {
+    /// Internal nodes being walked and the next child to visit in each
+    stack: Vec<(&'a [Arc<Node>], usize)>,
+    leaf: std::slice::Iter<'a, Entry>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Piece;
+
+    fn next(&mut self) -> Option<&'a Piece> {
+        loop {
+            if let Some(entry) = self.leaf.next() {
+                return Some(&entry.piece);
+            }
+
+            let (siblings, next) = self.stack.pop()?;
+            if next >= siblings.len() {
+                continue;
+            }
+            self.stack.push((siblings, next + 1));
+
+            // Descend to the leftmost leaf of the next subtree
+            let mut node: &Node = &siblings[next];
+            loop {
+                match &node.kind {
+                    Kind::Leaf(entries) => {
+                        self.leaf = entries.iter();
+                        break;
+                    }
+                    Kind::Internal(children) => {
+                        self.stack.push((children.as_slice(), 1));
+                        node = &children[0];
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Split items into groups of at most MAX_ENTRIES with sizes as equal as possible
+fn chunk_evenly<T>(items: Vec<T>) -> Vec<Vec<T>> {
+    let groups = items.len().div_ceil(MAX_ENTRIES).max(1);
+    let base = items.len() / groups;
+    let extra = items.len() % groups;
+
+    let mut items = items.into_iter();
+    (0..groups)
+        .map(|g| items.by_ref().take(base + usize::from(g < extra)).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece_tree::BufferId;
+
+    fn piece(n: usize) -> (Piece, usize) {
+        (Piece::new(n, n % 3 + 1, BufferId(1), n % 3 + 1), n % 2)
+    }
+
+    fn check(tree: &PieceBTree, expected: &[(Piece, usize)]) {
+        assert_eq!(tree.len(), expected.len());
+        let pieces: Vec<Piece> = expected.iter().map(|(p, _)| p.clone()).collect();
+        assert_eq!(tree.to_vec(), pieces);
+        let chars: usize = pieces.iter().map(|p| p.piece_char_length).sum();
+        assert_eq!(tree.summary().chars, chars);
+        let breaks: usize = expected.iter().map(|(_, b)| b).sum();
+        assert_eq!(tree.summary().line_breaks, breaks);
+    }
+
+    #[test]
+    fn test_insert_remove_match_vec() {
+        let mut tree = PieceBTree::new();
+        let mut model: Vec<(Piece, usize)> = Vec::new();
+
+        // Deterministic pseudo-random positions
+        let mut seed = 7usize;
+        for n in 0..2000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345) % (1 << 31);
+            let index = seed % (model.len() + 1);
+            let (piece, breaks) = piece(n);
+            tree.insert(index, piece.clone(), breaks);
+            model.insert(index, (piece, breaks));
+        }
+        check(&tree, &model);
+        assert!(tree.iter_from(1500).eq(model[1500..].iter().map(|(p, _)| p)));
+
+        for _ in 0..1900 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345) % (1 << 31);
+            let index = seed % model.len();
+            assert_eq!(tree.remove(index), model.remove(index).0);
+        }
+        check(&tree, &model);
+    }
+
+    #[test]
+    fn test_clone_is_unaffected_by_edits() {
+        let pieces: Vec<(Piece, usize)> = (0..500).map(piece).collect();
+        let mut tree = PieceBTree::from_pieces(pieces.clone());
+        let snapshot = tree.clone();
+
+        for n in 0..200 {
+            let (piece, breaks) = piece(1000 + n);
+            tree.insert(n * 2, piece, breaks);
+            tree.remove(n * 3 % tree.len());
+        }
+        check(&snapshot, &pieces);
+        assert_ne!(tree.to_vec(), snapshot.to_vec());
+    }
+
+    #[test]
+    fn test_find_by_measure() {
+        let pieces: Vec<(Piece, usize)> = (0..100).map(piece).collect();
+        let tree = PieceBTree::from_pieces(pieces.clone());
+        check(&tree, &pieces);
+
+        let mut chars = 0;
+        let mut bytes = 0;
+        for (i, (p, _)) in pieces.iter().enumerate() {
+            // A char offset at a boundary belongs to the piece that ends there
+            assert_eq!(tree.find_char(chars + p.piece_char_length).map(|(i, _)| i), Some(i));
+            // A byte offset belongs to the piece that starts there
+            let (index, before) = tree.find_byte(bytes).unwrap();
+            assert_eq!((index, before.bytes), (i, bytes));
+            chars += p.piece_char_length;
+            bytes += p.length;
+        }
+        assert!(tree.find_char(chars + 1).is_none());
+        assert!(tree.find_byte(bytes).is_none());
+
+        // Odd pieces hold one line break each
+        let (index, before) = tree.find_line_break(3).unwrap();
+        assert_eq!((index, before.line_breaks), (5, 2));
+    }
+}
@@ -0,0 +1,700 @@
+//! Branching undo history
+//!
+//! Undo steps form a tree: each node holds the edits leading from its
+//! parent's state to its own. Undo moves to the parent and redo to the child
+//! visited last, so editing after an undo starts a new branch instead of
+//! discarding the redo steps. Any node can be jumped to directly.
+//!
+//! Nodes a multiple of `CHECKPOINT_INTERVAL` steps deep keep a copy of the
+//! piece index and paragraph attributes once the tree has been in their state.
+//! The piece index copy is an O(1) copy-on-write clone, and buffers are
+//! append-only, so restoring it is exact; a jump replays at most `CHECKPOINT_INTERVAL` steps past the nearest
+//! checkpoint instead of walking the whole path between two branches.
+//!
+//! Besides the step limits, the history can be held to a byte budget. Each
+//! step is charged for the text its edits carry, which is the only copy of
+//! deleted text, for its attribute records and editor states, and for its
+//! checkpoint's copy of the piece index and paragraph attributes. The tree
+//! adds the text buffers only checkpoints still refer to. Over the budget,
+//! the oldest step goes first, whether it is the oldest step of the current
+//! chain or the tip of an abandoned branch, and the tree then releases the
+//! buffers nothing refers to any more.
+
+use std::collections::{BTreeMap, HashSet};
+use std::mem::size_of;
+
+use serde::{Deserialize, Serialize};
+
+use super::paragraphs::{ParagraphAttributes, ParagraphTable};
+use super::{AttributeSpan, EditorState, Piece, PieceBTree, Selection};
+
+/// Identifier of an undo step in the history tree
+pub type HistoryNodeId = usize;
+
+/// Longest undo chain kept; older steps are dropped
+const MAX_UNDO_DEPTH: usize = 100;
+/// Most steps kept across all branches; the oldest abandoned branch tips go first
+const MAX_HISTORY_NODES: usize = 1000;
+/// Depth interval between checkpoints
+const CHECKPOINT_INTERVAL: usize = 16;
+
+/// A recorded edit; offsets are in chars
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    Insert {
+        offset: usize,
+        text: String,
+    },
+    Delete {
+        offset: usize,
+        text: String,
+    },
+    /// Attributes of the chars from `offset` changed from `before` to `after`
+    Format {
+        offset: usize,
+        before: Vec<AttributeSpan>,
+        after: Vec<AttributeSpan>,
+    },
+    /// Attributes of the paragraphs from index `first` changed from `before` to `after`
+    ParagraphFormat {
+        first: usize,
+        before: Vec<Option<ParagraphAttributes>>,
+        after: Vec<Option<ParagraphAttributes>>,
+    },
+}
+
+impl Change {
+    /// Bytes the change holds: its text or attribute records
+    fn byte_cost(&self) -> usize {
+        size_of::<Change>()
+            + match self {
+                Change::Insert { text, .. } | Change::Delete { text, .. } => text.len(),
+                Change::Format { before, after, .. } => (before.len() + after.len()) * size_of::<AttributeSpan>(),
+                Change::ParagraphFormat { before, after, .. } => {
+                    (before.len() + after.len()) * size_of::<Option<ParagraphAttributes>>()
+                }
+            }
+    }
+}
+
+/// Edits undone and redone as one step, with the editor state on either side
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct UndoGroup {
+    pub(super) changes: Vec<Change>,
+    pub(super) state_before: EditorState,
+    pub(super) state_after: EditorState,
+    /// Last character typed while the group is a run of typing that more typing may join
+    #[serde(skip)]
+    last_typed: Option<char>,
+}
+
+impl UndoGroup {
+    fn new(state: EditorState) -> Self {
+        UndoGroup {
+            changes: Vec::new(),
+            state_before: state.clone(),
+            state_after: state,
+            last_typed: None,
+        }
+    }
+
+    fn byte_cost(&self) -> usize {
+        let selections = self.state_before.selections.len() + self.state_after.selections.len();
+        size_of::<UndoGroup>()
+            + selections * size_of::<Selection>()
+            + self.changes.iter().map(Change::byte_cost).sum::<usize>()
+    }
+
+    /// Whether typing `typed` as `change` continues this group
+    ///
+    /// Typing coalesces word by word: a new group starts with the first
+    /// character typed after whitespace, and at every line break.
+    fn continues_typing(&self, change: &Change, typed: Option<char>) -> bool {
+        let (Some(previous), Some(typed)) = (self.last_typed, typed) else {
+            return false;
+        };
+        let adjacent = match (self.changes.last(), change) {
+            (Some(Change::Insert { offset, text }), Change::Insert { offset: next, .. }) => {
+                offset + text.chars().count() == *next
+            }
+            _ => false,
+        };
+        let starts_word = previous.is_whitespace() && !typed.is_whitespace();
+        adjacent && typed != '\n' && !starts_word
+    }
+}
+
+/// How much memory the history holds, for diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryMemoryUsage {
+    /// Estimated bytes held by all steps and checkpoints
+    pub bytes: usize,
+    /// Bytes of text buffers kept only for checkpoints, as of the last time
+    /// unreferenced buffers were released; counted against the budget too
+    pub text_bytes: usize,
+    /// The byte budget, if one is set
+    pub budget: Option<usize>,
+    /// Steps kept across all branches, the oldest kept state included
+    pub steps: usize,
+}
+
+/// One step in the history tree, as listed for the UI
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryNodeInfo {
+    pub id: HistoryNodeId,
+    /// None for the oldest state still kept
+    pub parent: Option<HistoryNodeId>,
+    pub children: Vec<HistoryNodeId>,
+    /// Steps from the oldest kept state
+    pub depth: usize,
+    /// Number of edits in the step
+    pub change_count: usize,
+}
+
+/// History tree in a form that can be stored next to the document
+///
+/// It must be restored into a tree whose text is in the state of `current`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedHistory {
+    pub root: HistoryNodeId,
+    pub current: HistoryNodeId,
+    nodes: Vec<SavedNode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedNode {
+    id: HistoryNodeId,
+    parent: Option<HistoryNodeId>,
+    redo_child: Option<HistoryNodeId>,
+    group: UndoGroup,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    parent: Option<HistoryNodeId>,
+    children: Vec<HistoryNodeId>,
+    /// Child that redo moves to: the one created or visited last
+    redo_child: Option<HistoryNodeId>,
+    depth: usize,
+    /// Edits from the parent's state; empty for the root
+    group: UndoGroup,
+    checkpoint: Option<Checkpoint>,
+    /// Bytes charged for the group and checkpoint, as of the last accounting
+    bytes: usize,
+}
+
+impl Node {
+    fn new(parent: Option<HistoryNodeId>, depth: usize, group: UndoGroup) -> Self {
+        Node {
+            parent,
+            children: Vec::new(),
+            redo_child: None,
+            depth,
+            group,
+            checkpoint: None,
+            bytes: 0,
+        }
+    }
+
+    fn byte_cost(&self) -> usize {
+        size_of::<Node>()
+            + self.children.len() * size_of::<HistoryNodeId>()
+            + self.group.byte_cost()
+            + self.checkpoint.as_ref().map_or(0, Checkpoint::byte_cost)
+    }
+}
+
+/// How to get from the current node to another one
+pub(super) enum Route {
+    /// Revert the `up` steps in order, then apply the `down` steps in order
+    Replay {
+        up: Vec<HistoryNodeId>,
+        down: Vec<HistoryNodeId>,
+    },
+    /// Restore a checkpoint, then apply the `down` steps in order
+    Checkpoint {
+        checkpoint: Checkpoint,
+        down: Vec<HistoryNodeId>,
+    },
+}
+
+/// Content of the tree at a node: the piece index and the paragraph attributes
+#[derive(Debug, Clone)]
+pub(super) struct Checkpoint {
+    pub(super) pieces: PieceBTree,
+    pub(super) paragraphs: ParagraphTable,
+}
+
+impl Checkpoint {
+    /// Bytes of the copies, counted in full though the piece index shares
+    /// nodes with the live tree until either changes
+    fn byte_cost(&self) -> usize {
+        self.pieces.len() * size_of::<Piece>() + self.paragraphs.len() * size_of::<Option<ParagraphAttributes>>()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct History {
+    nodes: BTreeMap<HistoryNodeId, Node>,
+    root: HistoryNodeId,
+    current: HistoryNodeId,
+    next_id: HistoryNodeId,
+    /// Edits of the open transaction
+    transaction: Option<UndoGroup>,
+    /// Nesting depth of begin_transaction calls
+    transaction_depth: usize,
+    /// Sum of the nodes' `bytes`
+    bytes: usize,
+    /// Most bytes the nodes may hold, if limited
+    max_bytes: Option<usize>,
+    /// Steps dropped so far, so the tree knows when to release buffers
+    evictions: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(0, Node::new(None, 0, UndoGroup::new(EditorState::default())));
+        History {
+            nodes,
+            root: 0,
+            current: 0,
+            next_id: 1,
+            transaction: None,
+            transaction_depth: 0,
+            bytes: 0,
+            max_bytes: None,
+            evictions: 0,
+        }
+    }
+}
+
+impl History {
+    pub(super) fn current(&self) -> HistoryNodeId {
+        self.current
+    }
+
+    pub(super) fn can_undo(&self) -> bool {
+        self.node(self.current).parent.is_some()
+    }
+
+    pub(super) fn can_redo(&self) -> bool {
+        self.node(self.current).redo_child.is_some()
+    }
+
+    pub(super) fn group(&self, id: HistoryNodeId) -> &UndoGroup {
+        &self.node(id).group
+    }
+
+    /// Adds a change to the open transaction, the current typing run, or a new step
+    ///
+    /// Called before the change is applied, while `pieces` and `paragraphs` still reflect the current node.
+    pub(super) fn record(
+        &mut self,
+        change: Change,
+        typed: Option<char>,
+        state: EditorState,
+        pieces: &PieceBTree,
+        paragraphs: &ParagraphTable,
+    ) {
+        if let Some(group) = self.transaction.as_mut() {
+            group.changes.push(change);
+            return;
+        }
+
+        let current = self.current;
+        let node = self.node_mut(current);
+        if node.parent.is_some() && node.children.is_empty() && node.group.continues_typing(&change, typed) {
+            node.group.changes.push(change);
+            node.group.last_typed = typed;
+            self.account(current);
+            self.enforce_budget();
+            return;
+        }
+
+        self.checkpoint_if_due(current, pieces, paragraphs);
+        let mut group = UndoGroup::new(state);
+        group.changes.push(change);
+        group.last_typed = typed;
+        self.push(group);
+    }
+
+    /// Adds a step with no changes, for one made beside the text, unless a
+    /// transaction is open to take it
+    pub(super) fn record_empty(&mut self, state: EditorState, pieces: &PieceBTree, paragraphs: &ParagraphTable) {
+        if self.transaction.is_some() {
+            return;
+        }
+        self.checkpoint_if_due(self.current, pieces, paragraphs);
+        self.push(UndoGroup::new(state));
+    }
+
+    /// Notes the editor state after the change just recorded
+    pub(super) fn record_state_after(&mut self, state: EditorState) {
+        let current = self.current;
+        match self.transaction.as_mut() {
+            Some(group) => group.state_after = state,
+            None => self.node_mut(current).group.state_after = state,
+        }
+    }
+
+    pub(super) fn begin_transaction(&mut self, state: EditorState, pieces: &PieceBTree, paragraphs: &ParagraphTable) {
+        if self.transaction_depth == 0 {
+            self.checkpoint_if_due(self.current, pieces, paragraphs);
+            self.transaction = Some(UndoGroup::new(state));
+        }
+        self.transaction_depth += 1;
+    }
+
+    pub(super) fn in_transaction(&self) -> bool {
+        self.transaction_depth > 0
+    }
+
+    pub(super) fn end_transaction(&mut self) {
+        if self.transaction_depth == 0 {
+            return;
+        }
+        self.transaction_depth -= 1;
+        if self.transaction_depth == 0 {
+            if let Some(group) = self.transaction.take().filter(|g| !g.changes.is_empty()) {
+                self.push(group);
+            }
+        }
+    }
+
+    pub(super) fn end_all_transactions(&mut self) {
+        while self.transaction_depth > 0 {
+            self.end_transaction();
+        }
+    }
+
+    pub(super) fn break_coalescing(&mut self) {
+        let current = self.current;
+        self.node_mut(current).group.last_typed = None;
+    }
+
+    /// Moves to the parent and returns the step to revert
+    pub(super) fn step_back(&mut self, pieces: &PieceBTree, paragraphs: &ParagraphTable) -> Option<UndoGroup> {
+        let id = self.current;
+        let parent = self.node(id).parent?;
+        self.checkpoint_if_due(id, pieces, paragraphs);
+        let node = self.node_mut(id);
+        node.group.last_typed = None;
+        let group = node.group.clone();
+        self.node_mut(parent).redo_child = Some(id);
+        self.current = parent;
+        Some(group)
+    }
+
+    /// Moves to the redo child and returns the step to apply
+    pub(super) fn step_forward(&mut self, pieces: &PieceBTree, paragraphs: &ParagraphTable) -> Option<UndoGroup> {
+        let child = self.node(self.current).redo_child?;
+        self.checkpoint_if_due(self.current, pieces, paragraphs);
+        self.current = child;
+        Some(self.node(child).group.clone())
+    }
+
+    /// Cheapest way from the current node to `target`, or None if it does not exist
+    pub(super) fn route_to(&self, target: HistoryNodeId) -> Option<Route> {
+        if !self.nodes.contains_key(&target) {
+            return None;
+        }
+
+        let from_current = self.ancestors(self.current);
+        let from_target = self.ancestors(target);
+        let on_current_path: HashSet<_> = from_current.iter().copied().collect();
+        let common = from_target.iter().position(|id| on_current_path.contains(id))?;
+
+        let up: Vec<_> = from_current.iter().copied().take_while(|id| *id != from_target[common]).collect();
+        let down: Vec<_> = from_target[..common].iter().rev().copied().collect();
+
+        let checkpoint = from_target
+            .iter()
+            .position(|id| self.node(*id).checkpoint.is_some())
+            .filter(|distance| *distance < up.len() + down.len());
+        Some(match checkpoint {
+            Some(distance) => Route::Checkpoint {
+                checkpoint: self.node(from_target[distance]).checkpoint.clone().expect("checked above"),
+                down: from_target[..distance].iter().rev().copied().collect(),
+            },
+            None => Route::Replay { up, down },
+        })
+    }
+
+    /// Makes `target` current; redo from its ancestors now follows this branch
+    pub(super) fn set_current(&mut self, target: HistoryNodeId) {
+        self.break_coalescing();
+        let mut id = target;
+        while let Some(parent) = self.node(id).parent {
+            self.node_mut(parent).redo_child = Some(id);
+            id = parent;
+        }
+        self.current = target;
+    }
+
+    pub(super) fn memory_usage(&self) -> HistoryMemoryUsage {
+        HistoryMemoryUsage {
+            bytes: self.bytes,
+            text_bytes: 0,
+            budget: self.max_bytes,
+            steps: self.nodes.len(),
+        }
+    }
+
+    /// Limits the bytes the history holds, dropping the oldest steps now if it is over
+    pub(super) fn set_max_bytes(&mut self, max_bytes: Option<usize>) {
+        self.max_bytes = max_bytes;
+        self.enforce_budget();
+    }
+
+    /// Whether the steps and `text_bytes` of buffers kept for them are over the budget
+    pub(super) fn over_budget(&self, text_bytes: usize) -> bool {
+        self.max_bytes.is_some_and(|max| self.bytes + text_bytes > max)
+    }
+
+    /// Number of steps dropped since the history was created
+    pub(super) fn evictions(&self) -> usize {
+        self.evictions
+    }
+
+    /// Copies of the piece index the history keeps
+    pub(super) fn checkpoints(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.nodes.values().filter_map(|node| node.checkpoint.as_ref())
+    }
+
+    /// Drops the oldest step, of the current chain or an abandoned branch;
+    /// false if only the current state is left
+    pub(super) fn drop_oldest(&mut self) -> bool {
+        let path = self.ancestors(self.current);
+        let oldest_on_path = path.len().checked_sub(2).map(|i| path[i]);
+        match (self.oldest_abandoned_tip(), oldest_on_path) {
+            (Some(tip), Some(step)) if tip < step => self.drop_tip(tip),
+            (_, Some(_)) => self.drop_root(),
+            (Some(tip), None) => self.drop_tip(tip),
+            (None, None) => return false,
+        }
+        true
+    }
+
+    pub(super) fn nodes(&self) -> Vec<HistoryNodeInfo> {
+        self.nodes
+            .iter()
+            .map(|(id, node)| HistoryNodeInfo {
+                id: *id,
+                parent: node.parent,
+                children: node.children.clone(),
+                depth: node.depth,
+                change_count: node.group.changes.len(),
+            })
+            .collect()
+    }
+
+    /// Tips of all branches, oldest first
+    pub(super) fn branch_tips(&self) -> Vec<HistoryNodeId> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.children.is_empty())
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    pub(super) fn save(&self) -> SavedHistory {
+        SavedHistory {
+            root: self.root,
+            current: self.current,
+            nodes: self
+                .nodes
+                .iter()
+                .map(|(id, node)| SavedNode {
+                    id: *id,
+                    parent: node.parent,
+                    redo_child: node.redo_child,
+                    group: node.group.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Rebuilds a saved history; None if it is not a single tree containing `current`
+    pub(super) fn restore(saved: SavedHistory, pieces: &PieceBTree, paragraphs: &ParagraphTable) -> Option<History> {
+        let mut nodes: BTreeMap<HistoryNodeId, Node> = BTreeMap::new();
+        for saved_node in saved.nodes {
+            let mut node = Node::new(saved_node.parent, 0, saved_node.group);
+            node.redo_child = saved_node.redo_child;
+            if nodes.insert(saved_node.id, node).is_some() {
+                return None;
+            }
+        }
+        if nodes.get(&saved.root)?.parent.is_some() {
+            return None;
+        }
+
+        // Link children and compute depths from the root; every node must be reachable
+        let links: Vec<_> = nodes.iter().filter_map(|(id, node)| Some((node.parent?, *id))).collect();
+        for (parent, child) in links {
+            nodes.get_mut(&parent)?.children.push(child);
+        }
+        let mut stack = vec![saved.root];
+        let mut reached = 0;
+        while let Some(id) = stack.pop() {
+            reached += 1;
+            let node = nodes.get(&id)?;
+            let (depth, children) = (node.depth + 1, node.children.clone());
+            for child in children {
+                nodes.get_mut(&child)?.depth = depth;
+                stack.push(child);
+            }
+        }
+        if reached != nodes.len() || !nodes.contains_key(&saved.current) {
+            return None;
+        }
+
+        nodes.get_mut(&saved.current)?.checkpoint = Some(Checkpoint {
+            pieces: pieces.clone(),
+            paragraphs: paragraphs.clone(),
+        });
+        let next_id = nodes.keys().next_back().map_or(0, |id| id + 1);
+        let mut history = History {
+            nodes,
+            root: saved.root,
+            current: saved.current,
+            next_id,
+            transaction: None,
+            transaction_depth: 0,
+            bytes: 0,
+            max_bytes: None,
+            evictions: 0,
+        };
+        let ids: Vec<_> = history.nodes.keys().copied().collect();
+        for id in ids {
+            history.account(id);
+        }
+        Some(history)
+    }
+
+    /// Charges node `id` for what it holds now
+    fn account(&mut self, id: HistoryNodeId) {
+        let node = self.node_mut(id);
+        let (before, after) = (node.bytes, node.byte_cost());
+        node.bytes = after;
+        self.bytes = self.bytes + after - before;
+    }
+
+    /// Removes node `id`, which must have no children left
+    fn remove(&mut self, id: HistoryNodeId) -> Option<Node> {
+        let node = self.nodes.remove(&id)?;
+        self.bytes -= node.bytes;
+        self.evictions += 1;
+        Some(node)
+    }
+
+    fn node(&self, id: HistoryNodeId) -> &Node {
+        &self.nodes[&id]
+    }
+
+    fn node_mut(&mut self, id: HistoryNodeId) -> &mut Node {
+        self.nodes.get_mut(&id).expect("history node exists")
+    }
+
+    /// `id` and its ancestors, up to the root
+    fn ancestors(&self, id: HistoryNodeId) -> Vec<HistoryNodeId> {
+        std::iter::successors(Some(id), |id| self.node(*id).parent).collect()
+    }
+
+    /// Keeps a copy of the content for a node the tree is currently in
+    fn checkpoint_if_due(&mut self, id: HistoryNodeId, pieces: &PieceBTree, paragraphs: &ParagraphTable) {
+        let node = self.node_mut(id);
+        if node.depth.is_multiple_of(CHECKPOINT_INTERVAL) && node.checkpoint.is_none() {
+            node.checkpoint = Some(Checkpoint {
+                pieces: pieces.clone(),
+                paragraphs: paragraphs.clone(),
+            });
+            self.account(id);
+        }
+    }
+
+    /// Adds a step as a child of the current node and moves to it
+    fn push(&mut self, group: UndoGroup) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let parent = self.current;
+        let depth = self.node(parent).depth + 1;
+        let parent_node = self.node_mut(parent);
+        parent_node.children.push(id);
+        parent_node.redo_child = Some(id);
+        self.account(parent);
+        self.nodes.insert(id, Node::new(Some(parent), depth, group));
+        self.account(id);
+        self.current = id;
+        self.prune();
+    }
+
+    fn prune(&mut self) {
+        // Drop the oldest step of the current chain once it is too deep
+        if self.node(self.current).depth > MAX_UNDO_DEPTH {
+            self.drop_root();
+        }
+
+        // Then the oldest abandoned branch tips
+        while self.nodes.len() > MAX_HISTORY_NODES {
+            let Some(leaf) = self.oldest_abandoned_tip() else {
+                break;
+            };
+            self.drop_tip(leaf);
+        }
+
+        self.enforce_budget();
+    }
+
+    /// Drops the oldest steps, of the current chain or abandoned branches,
+    /// until the history is within its byte budget or only the current state is left
+    fn enforce_budget(&mut self) {
+        while self.over_budget(0) && self.drop_oldest() {}
+    }
+
+    /// Makes the root's child on the current path the root, dropping the
+    /// root's step and every branch that leaves the path there
+    fn drop_root(&mut self) {
+        let path = self.ancestors(self.current);
+        let new_root = path[path.len() - 2];
+        let mut keep = HashSet::new();
+        let mut stack = vec![new_root];
+        while let Some(id) = stack.pop() {
+            keep.insert(id);
+            stack.extend(self.node(id).children.iter().copied());
+        }
+        let dropped: Vec<_> = self.nodes.keys().copied().filter(|id| !keep.contains(id)).collect();
+        for id in dropped {
+            self.remove(id);
+        }
+        for node in self.nodes.values_mut() {
+            node.depth -= 1;
+        }
+        let root = self.node_mut(new_root);
+        root.parent = None;
+        root.group = UndoGroup::new(root.group.state_after.clone());
+        self.account(new_root);
+        self.root = new_root;
+    }
+
+    /// The oldest tip of a branch the current node is not on
+    fn oldest_abandoned_tip(&self) -> Option<HistoryNodeId> {
+        let on_current_path: HashSet<_> = self.ancestors(self.current).into_iter().collect();
+        self.nodes
+            .iter()
+            .find(|(id, node)| node.children.is_empty() && !on_current_path.contains(id))
+            .map(|(id, _)| *id)
+    }
+
+    fn drop_tip(&mut self, leaf: HistoryNodeId) {
+        let parent = self.remove(leaf).and_then(|node| node.parent);
+        if let Some(parent) = parent {
+            let parent_node = self.node_mut(parent);
+            parent_node.children.retain(|child| *child != leaf);
+            if parent_node.redo_child == Some(leaf) {
+                parent_node.redo_child = parent_node.children.last().copied();
+            }
+            self.account(parent);
+        }
+    }
+}
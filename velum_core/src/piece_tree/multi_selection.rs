@@ -0,0 +1,156 @@
+//! Multiple selections
+//!
+//! Besides the primary selection the editor may hold any number of other
+//! carets and ranges, added with Ctrl+click or made by a column selection.
+//! Typing and deleting apply at all of them at once, as one undo step that
+//! restores every caret, since each step captures the [`EditorState`].
+//!
+//! Selections are kept in document order and never overlap: ones that
+//! overlap, and a caret touching a range, merge into one keeping the
+//! direction of the first.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::{EditorState, Selection};
+
+/// What deleting at a caret takes out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteDirection {
+    /// The char before the caret, as Backspace
+    Backward,
+    /// The char after the caret, as Delete
+    Forward,
+}
+
+/// All selections of the editor, one of them primary
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MultiSelection {
+    selections: Vec<Selection>,
+    primary: usize,
+}
+
+impl MultiSelection {
+    /// A lone selection
+    pub fn new(selection: Selection) -> Self {
+        MultiSelection {
+            selections: vec![selection],
+            primary: 0,
+        }
+    }
+
+    /// Selections in any order, with the one at index `primary` primary
+    pub fn from_selections(mut selections: Vec<Selection>, primary: usize) -> Self {
+        if selections.is_empty() {
+            selections.push(Selection::default());
+        }
+        let primary = selections.get(primary).copied().unwrap_or(selections[0]);
+        selections.sort_by_key(|selection| (selection.start(), selection.end()));
+
+        let mut merged: Vec<Selection> = Vec::with_capacity(selections.len());
+        let mut index = 0;
+        for selection in selections {
+            match merged.last_mut() {
+                Some(last) if overlap(last, &selection) => {
+                    let (start, end) = (last.start(), last.end().max(selection.end()));
+                    *last = match last.anchor <= last.active {
+                        true => Selection::new(start, end),
+                        false => Selection::new(end, start),
+                    };
+                }
+                _ => merged.push(selection),
+            }
+            if selection == primary {
+                index = merged.len() - 1;
+            }
+        }
+        MultiSelection {
+            selections: merged,
+            primary: index,
+        }
+    }
+
+    /// The selections of an editor state, whose first is primary
+    pub fn from_state(state: &EditorState) -> Self {
+        MultiSelection::from_selections(state.selections.clone(), 0)
+    }
+
+    /// Adds `selection` and makes it primary, merging it with any it overlaps
+    pub fn add(&mut self, selection: Selection) {
+        let mut selections = std::mem::take(&mut self.selections);
+        selections.push(selection);
+        let primary = selections.len() - 1;
+        *self = MultiSelection::from_selections(selections, primary);
+    }
+
+    /// The selections in document order
+    pub fn selections(&self) -> &[Selection] {
+        &self.selections
+    }
+
+    pub fn primary(&self) -> Selection {
+        self.selections[self.primary]
+    }
+
+    /// The selections with the primary first, as an [`EditorState`] holds them
+    pub fn ordered(&self) -> Vec<Selection> {
+        std::iter::once(self.primary())
+            .chain(self.selections.iter().enumerate().filter(|(i, _)| *i != self.primary).map(|(_, s)| *s))
+            .collect()
+    }
+
+    /// Chars each selection's edit replaces: a range selection's own chars,
+    /// and at a caret nothing, or with `direction` the char beside it, in a
+    /// text of `total` chars
+    pub fn edit_ranges(&self, direction: Option<DeleteDirection>, total: usize) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::with_capacity(self.selections.len());
+        for (i, selection) in self.selections.iter().enumerate() {
+            let (start, end) = (selection.start(), selection.end().min(total));
+            let range = match direction {
+                _ if start < end => start..end,
+                Some(DeleteDirection::Backward) => {
+                    let previous = ranges.last().map_or(0, |range| range.end);
+                    start.saturating_sub(1).max(previous).min(start)..start
+                }
+                Some(DeleteDirection::Forward) => {
+                    let next = self.selections.get(i + 1).map_or(total, |next| next.start());
+                    start..(start + 1).min(next).min(total).max(start)
+                }
+                None => start..start,
+            };
+            ranges.push(range);
+        }
+        ranges
+    }
+
+    /// Runs `edit` on the range of each selection, the last first so the
+    /// offsets of those before stay put, and returns a caret after each edit
+    ///
+    /// `edit` replaces the chars in a range and returns the chars it put in
+    /// before where the caret goes, with how many chars longer the text got.
+    pub fn edit_each(
+        &self,
+        ranges: &[Range<usize>],
+        mut edit: impl FnMut(Range<usize>) -> (usize, isize),
+    ) -> MultiSelection {
+        let outcomes: Vec<(usize, isize)> = ranges.iter().rev().map(|range| edit(range.clone())).collect();
+        let mut shift = 0isize;
+        let carets = ranges
+            .iter()
+            .zip(outcomes.iter().rev())
+            .map(|(range, (inserted, grown))| {
+                let caret = (range.start + inserted).saturating_add_signed(shift);
+                shift += grown;
+                Selection::new(caret, caret)
+            })
+            .collect();
+        MultiSelection::from_selections(carets, self.primary)
+    }
+}
+
+/// Whether `next`, starting no earlier than `last`, merges into it
+fn overlap(last: &Selection, next: &Selection) -> bool {
+    next.start() < last.end() || next.start() == last.end() && (last.is_empty() || next.is_empty())
+}
@@ -0,0 +1,181 @@
+//! Paragraph formatting
+//!
+//! Run formatting lives on the pieces; paragraph formatting is kept beside
+//! them, one entry per paragraph, where a paragraph ends at each line break.
+//! Inserting line breaks splits a paragraph and the new paragraphs continue its
+//! formatting, as pressing Enter does. Deleting line breaks joins paragraphs
+//! into the first one, which keeps its formatting.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::line_breaking::BreakStrategy;
+use crate::line_layout::{Alignment, LineSpacingRule, ParagraphProperties};
+use crate::ooxml::ParagraphBorder;
+
+/// Formatting of a whole paragraph
+///
+/// Lengths are in twips (1/20 of a point), as in OOXML. None leaves a value to
+/// the paragraph style.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ParagraphAttributes {
+    pub alignment: Option<Alignment>,
+    pub indent_left: Option<i32>,
+    pub indent_right: Option<i32>,
+    /// Relative to the left indent; negative for a hanging indent
+    pub indent_first_line: Option<i32>,
+    pub space_before: Option<i32>,
+    pub space_after: Option<i32>,
+    /// Multiple of single line spacing, e.g. 1.5
+    pub line_spacing: Option<f32>,
+    pub style_id: Option<String>,
+    /// ID of the list (w:num) the paragraph is in; "0" takes it out of its style's list
+    #[serde(default)]
+    pub num_id: Option<String>,
+    /// Level in the paragraph's list, from 0
+    pub list_level: Option<u8>,
+    /// Line drawn below the paragraph
+    #[serde(default)]
+    pub border_bottom: Option<ParagraphBorder>,
+    /// Line breaking; editor-only, not written to OOXML
+    #[serde(default)]
+    pub break_strategy: Option<BreakStrategy>,
+    /// Kept on the page of the next paragraph
+    #[serde(default)]
+    pub keep_with_next: Option<bool>,
+    /// Kept on one page
+    #[serde(default)]
+    pub keep_lines_together: Option<bool>,
+    /// Starts on a new page
+    #[serde(default)]
+    pub page_break_before: Option<bool>,
+    /// Widow and orphan control
+    #[serde(default)]
+    pub widow_control: Option<bool>,
+}
+
+impl ParagraphAttributes {
+    /// These attributes with every field set in `other` taken from it
+    pub fn merged_with(&self, other: &ParagraphAttributes) -> ParagraphAttributes {
+        ParagraphAttributes {
+            alignment: other.alignment.or(self.alignment),
+            indent_left: other.indent_left.or(self.indent_left),
+            indent_right: other.indent_right.or(self.indent_right),
+            indent_first_line: other.indent_first_line.or(self.indent_first_line),
+            space_before: other.space_before.or(self.space_before),
+            space_after: other.space_after.or(self.space_after),
+            line_spacing: other.line_spacing.or(self.line_spacing),
+            style_id: other.style_id.clone().or_else(|| self.style_id.clone()),
+            num_id: other.num_id.clone().or_else(|| self.num_id.clone()),
+            list_level: other.list_level.or(self.list_level),
+            border_bottom: other.border_bottom.clone().or_else(|| self.border_bottom.clone()),
+            break_strategy: other.break_strategy.or(self.break_strategy),
+            keep_with_next: other.keep_with_next.or(self.keep_with_next),
+            keep_lines_together: other.keep_lines_together.or(self.keep_lines_together),
+            page_break_before: other.page_break_before.or(self.page_break_before),
+            widow_control: other.widow_control.or(self.widow_control),
+        }
+    }
+
+    /// Layout properties, with defaults for the unset fields
+    pub fn layout_properties(&self) -> ParagraphProperties {
+        let defaults = ParagraphProperties::default();
+        ParagraphProperties {
+            indent_left: self.indent_left.map_or(defaults.indent_left, |v| v as f32),
+            indent_right: self.indent_right.map_or(defaults.indent_right, |v| v as f32),
+            indent_first_line: self.indent_first_line.map_or(defaults.indent_first_line, |v| v as f32),
+            space_before: self.space_before.map_or(defaults.space_before, |v| v as f32),
+            space_after: self.space_after.map_or(defaults.space_after, |v| v as f32),
+            line_spacing: self.line_spacing.unwrap_or(defaults.line_spacing),
+            line_spacing_rule: match self.line_spacing {
+                Some(_) => LineSpacingRule::Multiple,
+                None => defaults.line_spacing_rule,
+            },
+            alignment: self.alignment.unwrap_or(defaults.alignment),
+            break_strategy: self.break_strategy,
+            keep_with_next: self.keep_with_next.unwrap_or(defaults.keep_with_next),
+            keep_lines_together: self.keep_lines_together.unwrap_or(defaults.keep_lines_together),
+            page_break_before: self.page_break_before.unwrap_or(defaults.page_break_before),
+            widow_control: self.widow_control.unwrap_or(defaults.widow_control),
+        }
+    }
+}
+
+/// Attributes of every paragraph of a tree, in order
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct ParagraphTable {
+    paragraphs: Vec<Option<ParagraphAttributes>>,
+}
+
+impl ParagraphTable {
+    /// A table of `count` unformatted paragraphs; there is always at least one
+    pub(super) fn new(count: usize) -> Self {
+        ParagraphTable {
+            paragraphs: vec![None; count.max(1)],
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.paragraphs.len()
+    }
+
+    pub(super) fn get(&self, index: usize) -> Option<&ParagraphAttributes> {
+        self.paragraphs.get(index)?.as_ref()
+    }
+
+    pub(super) fn slice(&self, range: Range<usize>) -> Vec<Option<ParagraphAttributes>> {
+        let end = range.end.min(self.paragraphs.len());
+        self.paragraphs[range.start.min(end)..end].to_vec()
+    }
+
+    /// Gives consecutive paragraphs from `first` the given attributes
+    pub(super) fn set(&mut self, first: usize, attributes: &[Option<ParagraphAttributes>]) {
+        for (slot, attributes) in self.paragraphs.iter_mut().skip(first).zip(attributes) {
+            *slot = attributes.clone();
+        }
+    }
+
+    /// Paragraph `index` was split by `breaks` new line breaks
+    pub(super) fn split(&mut self, index: usize, breaks: usize) {
+        let index = index.min(self.paragraphs.len() - 1);
+        let attributes = self.paragraphs[index].clone();
+        self.paragraphs
+            .splice(index + 1..index + 1, std::iter::repeat_n(attributes, breaks));
+    }
+
+    /// The `breaks` line breaks after paragraph `index` were deleted
+    pub(super) fn join(&mut self, index: usize, breaks: usize) {
+        let end = (index + 1 + breaks).min(self.paragraphs.len());
+        self.paragraphs.drain((index + 1).min(end)..end);
+    }
+
+    /// Match a tree whose pieces were replaced wholesale, keeping what still fits
+    pub(super) fn resize(&mut self, count: usize) {
+        self.paragraphs.resize(count.max(1), None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn centered() -> Option<ParagraphAttributes> {
+        Some(ParagraphAttributes {
+            alignment: Some(Alignment::Center),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let mut table = ParagraphTable::new(2);
+        table.set(1, &[centered()]);
+        table.split(1, 2);
+        assert_eq!(table.slice(0..4), [None, centered(), centered(), centered()]);
+
+        table.set(2, &[None]);
+        table.join(1, 2);
+        assert_eq!(table.slice(0..10), [None, centered()]);
+    }
+}
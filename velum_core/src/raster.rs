@@ -0,0 +1,466 @@
+//! # Raster Module
+//!
+//! Headless rendering of laid-out pages into RGBA pixels, PNG encoding and
+//! decoding, and perceptual comparison of two renderings.
+//!
+//! Pages are drawn from the display list PDF export writes, a [`PdfPage`].
+//! The rasterizer is for checking layout, not for showing documents: text is
+//! drawn as one box per glyph, sized from the char's class (x-height,
+//! ascender, descender, wide) and advanced by the widths
+//! [`TextShaper::without_font`](crate::text_shaping::TextShaper::without_font)
+//! estimates, and images as framed placeholders. A rendering shows where
+//! lines, words, rules and images land and is the same on every machine:
+//! edges are antialiased by exact area coverage, with no font rasterizing or
+//! platform maths involved.
+//!
+//! Renderings are compared the way pixelmatch does: each pixel's color
+//! difference is measured in YIQ space, where it tracks what the eye sees,
+//! and pixels past a threshold count as changed.
+
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::page_layout::Rect;
+use crate::pdf::{unfilter, PdfColor, PdfPage};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Largest YIQ delta between two colors
+const MAX_YIQ_DELTA: f32 = 35_215.0;
+
+/// Rasterizing and comparison errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RasterError {
+    #[error("Invalid PNG: {0}")]
+    InvalidPng(String),
+
+    #[error("Unsupported PNG: {0}")]
+    UnsupportedPng(String),
+
+    #[error("Size mismatch: expected {expected:?}, got {actual:?}")]
+    SizeMismatch { expected: (u32, u32), actual: (u32, u32) },
+}
+
+/// Straight-alpha RGBA color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b, a: 255 }
+    }
+}
+
+impl From<PdfColor> for Color {
+    fn from(color: PdfColor) -> Self {
+        Color::rgb(color.r, color.g, color.b)
+    }
+}
+
+/// An RGBA image being drawn on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    /// Rows top to bottom, four bytes a pixel
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// A canvas filled with `background`
+    pub fn new(width: u32, height: u32, background: Color) -> Self {
+        let pixels = [background.r, background.g, background.b, background.a].repeat(width as usize * height as usize);
+        Canvas { width, height, pixels }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        Some(Color { r: self.pixels[i], g: self.pixels[i + 1], b: self.pixels[i + 2], a: self.pixels[i + 3] })
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[i..i + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+    }
+
+    /// Composite `color` over the pixel, scaled by `coverage` in 0..=1
+    fn blend_pixel(&mut self, x: u32, y: u32, color: Color, coverage: f32) {
+        let alpha = color.a as f32 / 255.0 * coverage;
+        if alpha <= 0.0 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let dst_alpha = self.pixels[i + 3] as f32 / 255.0;
+        let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+        for (channel, src) in [color.r, color.g, color.b].into_iter().enumerate() {
+            let dst = self.pixels[i + channel] as f32;
+            let value = (src as f32 * alpha + dst * dst_alpha * (1.0 - alpha)) / out_alpha;
+            self.pixels[i + channel] = value.round().clamp(0.0, 255.0) as u8;
+        }
+        self.pixels[i + 3] = (out_alpha * 255.0).round() as u8;
+    }
+
+    /// Fill a rectangle in pixels; partly covered pixels get the covered share
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        if rect.is_empty() {
+            return;
+        }
+        let (left, top) = (rect.x.max(0.0), rect.y.max(0.0));
+        let (right, bottom) = (rect.right().min(self.width as f32), rect.bottom().min(self.height as f32));
+        if left >= right || top >= bottom {
+            return;
+        }
+        for y in top.floor() as u32..bottom.ceil() as u32 {
+            let cover_y = bottom.min(y as f32 + 1.0) - top.max(y as f32);
+            for x in left.floor() as u32..right.ceil() as u32 {
+                let cover_x = right.min(x as f32 + 1.0) - left.max(x as f32);
+                self.blend_pixel(x, y, color, cover_x * cover_y);
+            }
+        }
+    }
+
+    /// Outline a rectangle with lines `width` pixels wide, inside its edges
+    pub fn stroke_rect(&mut self, rect: Rect, width: f32, color: Color) {
+        let width = width.min(rect.width / 2.0).min(rect.height / 2.0);
+        let inner_height = rect.height - 2.0 * width;
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, width), color);
+        self.fill_rect(Rect::new(rect.x, rect.bottom() - width, rect.width, width), color);
+        self.fill_rect(Rect::new(rect.x, rect.y + width, width, inner_height), color);
+        self.fill_rect(Rect::new(rect.right() - width, rect.y + width, width, inner_height), color);
+    }
+
+    /// A page drawn on white at `scale` pixels a point
+    pub fn render_page(page: &PdfPage, scale: f32) -> Self {
+        let mut canvas = Canvas::new((page.width * scale).ceil() as u32, (page.height * scale).ceil() as u32, Color::WHITE);
+        canvas.draw_page(page, scale);
+        canvas
+    }
+
+    /// Draw a page given in points, at `scale` pixels a point
+    pub fn draw_page(&mut self, page: &PdfPage, scale: f32) {
+        let scaled = |x: f32, y: f32, width: f32, height: f32| Rect::new(x * scale, y * scale, width * scale, height * scale);
+        for line in &page.lines {
+            let mut pen = line.x;
+            for run in &line.runs {
+                let (size, color) = (run.style.size, Color::from(run.style.color));
+                let start = pen;
+                for c in run.text.chars() {
+                    let advance = glyph_advance(c, size);
+                    if let Some((top, bottom)) = glyph_extent(c) {
+                        let ink = if run.style.bold { 0.9 } else { 0.7 };
+                        let glyph = scaled(pen + advance * (1.0 - ink) / 2.0, line.baseline + top * size, advance * ink, (bottom - top) * size);
+                        self.fill_rect(glyph, color);
+                    }
+                    pen += advance;
+                }
+                if run.style.underline {
+                    self.fill_rect(scaled(start, line.baseline + size * 0.1, pen - start, size * 0.05), color);
+                }
+            }
+        }
+        for rule in &page.rules {
+            self.draw_rule(rule.from, rule.to, rule.width, rule.color.into(), scale);
+        }
+        for image in &page.images {
+            // A placeholder frame: where the image goes is what layout decides
+            let frame = scaled(image.rect.x, image.rect.y, image.rect.width, image.rect.height);
+            self.fill_rect(frame, Color::rgb(0xDD, 0xDD, 0xDD));
+            self.stroke_rect(frame, scale.max(1.0), Color::rgb(0x88, 0x88, 0x88));
+        }
+    }
+
+    /// Draw a straight line in points; slanted lines are drawn as a run of squares
+    fn draw_rule(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: Color, scale: f32) {
+        let width = width.max(1.0 / scale);
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        if dx == 0.0 || dy == 0.0 {
+            let (left, top) = (from.0.min(to.0) - width / 2.0, from.1.min(to.1) - width / 2.0);
+            let rect = Rect::new(left, top, dx.abs() + width, dy.abs() + width);
+            self.fill_rect(Rect::new(rect.x * scale, rect.y * scale, rect.width * scale, rect.height * scale), color);
+            return;
+        }
+        let steps = (length * scale).ceil().max(1.0) as u32;
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let (x, y) = (from.0 + dx * t - width / 2.0, from.1 + dy * t - width / 2.0);
+            self.fill_rect(Rect::new(x * scale, y * scale, width * scale, width * scale), color);
+        }
+    }
+
+    /// Encode as an 8-bit RGBA PNG
+    pub fn to_png(&self) -> Vec<u8> {
+        let row_bytes = self.width as usize * 4;
+        let mut raw = Vec::with_capacity((row_bytes + 1) * self.height as usize);
+        for row in self.pixels.chunks(row_bytes.max(1)).take(self.height as usize) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&raw).expect("writing to a Vec cannot fail");
+        let data = encoder.finish().expect("writing to a Vec cannot fail");
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        // 8 bits a channel, RGBA, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        write_chunk(&mut png, b"IDAT", &data);
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    /// Decode an 8-bit, non-interlaced RGB or RGBA PNG
+    pub fn from_png(png: &[u8]) -> Result<Self, RasterError> {
+        let invalid = |message: &str| RasterError::InvalidPng(message.to_string());
+        if !png.starts_with(&PNG_SIGNATURE) {
+            return Err(invalid("missing signature"));
+        }
+
+        let mut header = None;
+        let mut data = Vec::new();
+        let mut rest = &png[PNG_SIGNATURE.len()..];
+        while rest.len() >= 12 {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let kind = &rest[4..8];
+            let body = rest.get(8..8 + length).ok_or_else(|| invalid("truncated chunk"))?;
+            let crc = rest.get(8 + length..12 + length).ok_or_else(|| invalid("truncated chunk"))?;
+            if crc32fast::hash(&rest[4..8 + length]).to_be_bytes() != crc {
+                return Err(invalid("chunk CRC mismatch"));
+            }
+            match kind {
+                b"IHDR" if body.len() == 13 => header = Some(body.to_vec()),
+                b"IDAT" => data.extend_from_slice(body),
+                b"IEND" => break,
+                _ => {}
+            }
+            rest = &rest[12 + length..];
+        }
+
+        let header = header.ok_or_else(|| invalid("missing IHDR"))?;
+        let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let (bit_depth, color_type, interlace) = (header[8], header[9], header[12]);
+        let channels = match color_type {
+            2 => 3,
+            6 => 4,
+            _ => return Err(RasterError::UnsupportedPng(format!("color type {}", color_type))),
+        };
+        if bit_depth != 8 || interlace != 0 {
+            return Err(RasterError::UnsupportedPng(format!("bit depth {}, interlace {}", bit_depth, interlace)));
+        }
+
+        let mut raw = Vec::new();
+        ZlibDecoder::new(data.as_slice()).read_to_end(&mut raw).map_err(|e| invalid(&e.to_string()))?;
+        let row_bytes = width as usize * channels;
+
+        let raw = unfilter(&raw, row_bytes, height as usize, channels).map_err(|e| invalid(&e.to_string()))?;
+        let pixels = match channels {
+            4 => raw,
+            _ => raw.chunks(3).flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]).collect(),
+        };
+        Ok(Canvas { width, height, pixels })
+    }
+}
+
+/// How two renderings of the same page differ
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    /// Pixels whose difference passes the threshold
+    pub differing: usize,
+    pub total: usize,
+    /// Largest difference of any pixel, from 0 (same) to 1; black against white is about 0.97
+    pub max_delta: f32,
+    /// The expected image faded, with differing pixels in red
+    pub image: Canvas,
+}
+
+impl ImageDiff {
+    /// Share of the pixels that differ
+    pub fn ratio(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.differing as f32 / self.total as f32
+        }
+    }
+}
+
+/// Compare two renderings; a pixel differs when its perceived color
+/// difference, 0 to 1, is above `threshold` (pixelmatch uses 0.1)
+pub fn compare(expected: &Canvas, actual: &Canvas, threshold: f32) -> Result<ImageDiff, RasterError> {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Err(RasterError::SizeMismatch {
+            expected: (expected.width, expected.height),
+            actual: (actual.width, actual.height),
+        });
+    }
+
+    let mut image = Canvas::new(expected.width, expected.height, Color::WHITE);
+    let mut differing = 0;
+    let mut max_delta = 0.0f32;
+    for y in 0..expected.height {
+        for x in 0..expected.width {
+            let (a, b) = (expected.pixel(x, y).unwrap(), actual.pixel(x, y).unwrap());
+            let delta = color_delta(a, b);
+            max_delta = max_delta.max(delta);
+            if delta > threshold {
+                differing += 1;
+                image.set_pixel(x, y, Color::rgb(255, 0, 0));
+            } else {
+                let (luma, _, _) = yiq(a);
+                let faded = (255.0 - (255.0 - luma) * 0.1).round() as u8;
+                image.set_pixel(x, y, Color::rgb(faded, faded, faded));
+            }
+        }
+    }
+    Ok(ImageDiff { differing, total: (expected.width * expected.height) as usize, max_delta, image })
+}
+
+/// Perceived difference between two colors, 0 to 1
+fn color_delta(a: Color, b: Color) -> f32 {
+    let (y1, i1, q1) = yiq(a);
+    let (y2, i2, q2) = yiq(b);
+    let (y, i, q) = (y1 - y2, i1 - i2, q1 - q2);
+    ((0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / MAX_YIQ_DELTA).sqrt()
+}
+
+/// YIQ components of a color composited over white
+fn yiq(color: Color) -> (f32, f32, f32) {
+    let alpha = color.a as f32 / 255.0;
+    let over_white = |channel: u8| 255.0 + (channel as f32 - 255.0) * alpha;
+    let (r, g, b) = (over_white(color.r), over_white(color.g), over_white(color.b));
+    (
+        r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_3,
+        r * 0.595_977_9 - g * 0.274_176_4 - b * 0.321_801_5,
+        r * 0.211_470_2 - g * 0.522_617_2 + b * 0.311_147,
+    )
+}
+
+/// Width of a glyph box, as the fontless shaper estimates it
+fn glyph_advance(c: char, font_size: f32) -> f32 {
+    if c.is_ascii() {
+        font_size * 0.5
+    } else {
+        font_size
+    }
+}
+
+/// Top and bottom of a glyph box in ems from the baseline, up negative;
+/// None for chars that leave no ink
+fn glyph_extent(c: char) -> Option<(f32, f32)> {
+    if c.is_whitespace() || c.is_control() {
+        return None;
+    }
+    let top = match c {
+        'a'..='z' if !"bdfhklt".contains(c) => -0.5,
+        '.' | ',' | ':' | ';' => -0.15,
+        _ if c.is_ascii_punctuation() => -0.6,
+        _ if c.is_ascii() => -0.7,
+        // Wide glyphs fill most of the em
+        _ => -0.8,
+    };
+    let bottom = if "gjpqy,;".contains(c) || !c.is_ascii() { 0.2 } else { 0.0 };
+    Some((top, bottom))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32fast::hash(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_rect_coverage() {
+        let mut canvas = Canvas::new(4, 2, Color::WHITE);
+        canvas.fill_rect(Rect::new(0.5, 0.0, 2.0, 1.0), Color::BLACK);
+
+        assert_eq!(canvas.pixel(0, 0), Some(Color::rgb(128, 128, 128)));
+        assert_eq!(canvas.pixel(1, 0), Some(Color::BLACK));
+        assert_eq!(canvas.pixel(2, 0), Some(Color::rgb(128, 128, 128)));
+        assert_eq!(canvas.pixel(3, 0), Some(Color::WHITE));
+        assert_eq!(canvas.pixel(1, 1), Some(Color::WHITE));
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let mut canvas = Canvas::new(7, 5, Color::WHITE);
+        canvas.fill_rect(Rect::new(1.0, 1.0, 3.5, 2.0), Color::rgb(200, 30, 60));
+        canvas.fill_rect(Rect::new(3.0, 2.0, 4.0, 3.0), Color { a: 100, ..Color::BLACK });
+
+        let png = canvas.to_png();
+        assert!(png.starts_with(&PNG_SIGNATURE));
+        assert_eq!(Canvas::from_png(&png), Ok(canvas));
+        assert!(matches!(Canvas::from_png(&png[..20]), Err(RasterError::InvalidPng(_))));
+    }
+
+    #[test]
+    fn test_compare_counts_visible_changes() {
+        let expected = Canvas::new(10, 10, Color::WHITE);
+        let mut actual = expected.clone();
+        actual.fill_rect(Rect::new(0.0, 0.0, 2.0, 1.0), Color::BLACK);
+        // Off-white is below what the eye notices at the default threshold
+        actual.fill_rect(Rect::new(5.0, 5.0, 1.0, 1.0), Color::rgb(250, 250, 250));
+
+        let diff = compare(&expected, &actual, 0.1).unwrap();
+        assert_eq!(diff.differing, 2);
+        assert!((diff.ratio() - 0.02).abs() < 1e-6);
+        assert!(diff.max_delta > 0.95);
+        assert_eq!(diff.image.pixel(0, 0), Some(Color::rgb(255, 0, 0)));
+
+        let smaller = Canvas::new(5, 10, Color::WHITE);
+        assert!(matches!(compare(&expected, &smaller, 0.1), Err(RasterError::SizeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_draw_text_as_glyph_boxes() {
+        use crate::pdf::{PdfLine, PdfRule, PdfRun, PdfTextStyle};
+
+        let mut page = PdfPage::new(40.0, 20.0);
+        page.lines.push(PdfLine {
+            x: 0.0,
+            baseline: 10.0,
+            runs: vec![PdfRun {
+                text: "ab c".to_string(),
+                style: PdfTextStyle { size: 10.0, ..Default::default() },
+                link: None,
+            }],
+        });
+        page.rules.push(PdfRule { from: (0.0, 15.0), to: (40.0, 15.0), width: 1.0, color: PdfColor::BLACK });
+        let canvas = Canvas::render_page(&page, 1.0);
+        assert_eq!((canvas.width, canvas.height), (40, 20));
+
+        // 'a' has x-height ink, 'b' ascends, the space leaves none
+        assert_eq!(canvas.pixel(2, 6), Some(Color::BLACK));
+        assert_eq!(canvas.pixel(2, 4), Some(Color::WHITE));
+        assert_eq!(canvas.pixel(7, 4), Some(Color::BLACK));
+        assert_eq!(canvas.pixel(12, 8), Some(Color::WHITE));
+        assert_eq!(canvas.pixel(17, 8), Some(Color::BLACK));
+        // The rule is centered on its line, half a pixel each side
+        assert_eq!(canvas.pixel(20, 14), Some(Color::rgb(128, 128, 128)));
+    }
+}
@@ -0,0 +1,369 @@
+//! # Read Aloud Module
+//!
+//! The body cut into chunks a text-to-speech engine can say one at a time,
+//! each with the range of text to highlight while it is spoken.
+//!
+//! A chunk is a sentence (UAX #29), or a piece of one cut at a line break
+//! opportunity when the sentence is longer than the engine takes. Chunks
+//! carry hints for SSML: bold text is said with strong emphasis and italic
+//! text with moderate emphasis, and the pause after a chunk is longer at the
+//! end of a paragraph and longest after a heading. Page furniture is left
+//! out: headers, footers and notes are not body text, and the results of
+//! page number, table of contents and index fields are skipped.
+//!
+//! Ranges are char offsets into the body text, paragraphs joined with "\n".
+//! Tables are not part of that text and are not read.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::accessibility::heading_level;
+use crate::document_model::DocumentModel;
+use crate::ooxml::{FieldKind, Paragraph};
+use crate::segmentation;
+use crate::style_sheet::StyleSheet;
+
+/// Longest chunk by default, in chars; engines take a few thousand, but
+/// shorter chunks start speaking sooner
+pub const DEFAULT_MAX_CHUNK_CHARS: usize = 500;
+
+/// How strongly a span is stressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Emphasis {
+    /// Italic text
+    Moderate,
+    /// Bold text
+    Strong,
+}
+
+/// An emphasized span of a chunk's text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmphasisSpan {
+    /// Char offsets into the chunk's text
+    pub range: Range<usize>,
+    pub level: Emphasis,
+}
+
+/// Pause after a chunk, from shortest to longest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pause {
+    /// Inside a sentence cut for length
+    None,
+    Sentence,
+    Paragraph,
+    Heading,
+}
+
+impl Pause {
+    /// SSML break strength
+    fn strength(self) -> Option<&'static str> {
+        match self {
+            Pause::None => None,
+            Pause::Sentence => Some("medium"),
+            Pause::Paragraph => Some("strong"),
+            Pause::Heading => Some("x-strong"),
+        }
+    }
+}
+
+/// One piece of text to speak
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpeechChunk {
+    /// What to say
+    pub text: String,
+    /// Chars of the body text to highlight
+    pub range: Range<usize>,
+    /// Ranges of the body text inside `range` left out of `text`
+    pub skipped: Vec<Range<usize>>,
+    /// Level of the heading the chunk belongs to, from 1
+    pub heading: Option<u8>,
+    pub emphasis: Vec<EmphasisSpan>,
+    pub pause_after: Pause,
+}
+
+impl SpeechChunk {
+    /// Char offset into the body text of char `offset` of the chunk's text,
+    /// for highlighting the word the engine reports it is saying
+    pub fn document_offset(&self, offset: usize) -> usize {
+        let mut position = self.range.start + offset;
+        for skipped in &self.skipped {
+            if skipped.start <= position {
+                position += skipped.len();
+            }
+        }
+        position.min(self.range.end)
+    }
+
+    /// The chunk as SSML, without the enclosing <speak> element
+    pub fn to_ssml(&self) -> String {
+        let mut ssml = String::new();
+        let mut spans = self.emphasis.iter().peekable();
+        let mut open: Option<&EmphasisSpan> = None;
+        for (index, c) in self.text.chars().enumerate() {
+            if open.is_some_and(|span| span.range.end == index) {
+                ssml.push_str("</emphasis>");
+                open = None;
+            }
+            if let Some(span) = spans.next_if(|span| span.range.start == index) {
+                let level = match span.level {
+                    Emphasis::Moderate => "moderate",
+                    Emphasis::Strong => "strong",
+                };
+                ssml.push_str(&format!("<emphasis level=\"{}\">", level));
+                open = Some(span);
+            }
+            match c {
+                '&' => ssml.push_str("&amp;"),
+                '<' => ssml.push_str("&lt;"),
+                '>' => ssml.push_str("&gt;"),
+                '"' => ssml.push_str("&quot;"),
+                '\'' => ssml.push_str("&apos;"),
+                c => ssml.push(c),
+            }
+        }
+        if open.is_some() {
+            ssml.push_str("</emphasis>");
+        }
+        if let Some(strength) = self.pause_after.strength() {
+            ssml.push_str(&format!("<break strength=\"{}\"/>", strength));
+        }
+        ssml
+    }
+}
+
+/// The body of `model` as speech chunks in reading order
+///
+/// `styles` are the document's and decide the headings; chunks are at most
+/// `max_chars` chars long, unless a single word is longer.
+pub fn speech_chunks(model: &DocumentModel, styles: &StyleSheet, max_chars: usize) -> Vec<SpeechChunk> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    for paragraph in model.paragraphs() {
+        let heading = heading_level(styles, paragraph.properties.style_id.as_deref());
+        let first = chunks.len();
+        paragraph_chunks(paragraph, offset, heading, max_chars, &mut chunks);
+        if let Some(last) = chunks[first..].last_mut() {
+            last.pause_after = if heading.is_some() { Pause::Heading } else { Pause::Paragraph };
+        }
+        offset += paragraph.text.chars().count() + 1;
+    }
+    chunks
+}
+
+/// Whether the result of a field of `kind` is page furniture rather than text
+fn is_furniture(kind: FieldKind) -> bool {
+    matches!(
+        kind,
+        FieldKind::Page | FieldKind::NumPages | FieldKind::PageRef | FieldKind::Toc | FieldKind::IndexEntry | FieldKind::Index
+    )
+}
+
+/// Chunks of one paragraph, which starts at char `offset` of the body text
+fn paragraph_chunks(paragraph: &Paragraph, offset: usize, heading: Option<u8>, max_chars: usize, chunks: &mut Vec<SpeechChunk>) {
+    let text = &paragraph.text;
+    let chars: Vec<char> = text.chars().collect();
+
+    let mut spoken = vec![true; chars.len()];
+    for field in paragraph.fields.iter().filter(|field| is_furniture(field.kind)) {
+        let end = (field.start + field.length).min(chars.len());
+        for flag in &mut spoken[field.start.min(end)..end] {
+            *flag = false;
+        }
+    }
+
+    let mut emphasis: Vec<Option<Emphasis>> = Vec::with_capacity(chars.len());
+    for run in &paragraph.runs {
+        let level = if run.properties.bold == Some(true) {
+            Some(Emphasis::Strong)
+        } else if run.properties.italic == Some(true) {
+            Some(Emphasis::Moderate)
+        } else {
+            None
+        };
+        emphasis.extend(std::iter::repeat_n(level, run.text.chars().count()));
+    }
+    emphasis.resize(chars.len(), None);
+
+    // Byte offset of each char, and of the end, to map segment bounds to chars
+    let bytes: Vec<usize> = text.char_indices().map(|(i, _)| i).chain(std::iter::once(text.len())).collect();
+    let char_at = |byte: usize| bytes.partition_point(|&b| b < byte);
+
+    for (start, sentence) in text.split_sentence_bound_indices() {
+        let pieces = split_long(sentence, max_chars);
+        let last = pieces.len().saturating_sub(1);
+        for (index, piece) in pieces.into_iter().enumerate() {
+            let range = char_at(start + piece.start)..char_at(start + piece.end);
+            let pause = if index == last { Pause::Sentence } else { Pause::None };
+            if let Some(chunk) = chunk(&chars, &spoken, &emphasis, range, offset, heading, pause) {
+                chunks.push(chunk);
+            }
+        }
+    }
+}
+
+/// A sentence cut at line break opportunities into byte ranges of at most
+/// `max_chars` chars, unless a single word is longer
+fn split_long(sentence: &str, max_chars: usize) -> Vec<Range<usize>> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    if sentence.chars().count() <= max_chars {
+        pieces.push(start..sentence.len());
+        return pieces;
+    }
+    let mut last_break = None;
+    for (position, _) in segmentation::line_break_opportunities(sentence) {
+        if sentence[start..position].trim_end().chars().count() > max_chars {
+            if let Some(end) = last_break.filter(|&end| end > start) {
+                pieces.push(start..end);
+                start = end;
+            }
+        }
+        last_break = Some(position);
+    }
+    pieces.push(start..sentence.len());
+    pieces
+}
+
+/// The chunk of paragraph chars `range`, or None if nothing in it is spoken
+fn chunk(
+    chars: &[char],
+    spoken: &[bool],
+    emphasis: &[Option<Emphasis>],
+    range: Range<usize>,
+    offset: usize,
+    heading: Option<u8>,
+    pause_after: Pause,
+) -> Option<SpeechChunk> {
+    let start = range.start + chars[range.clone()].iter().take_while(|c| c.is_whitespace()).count();
+    let end = range.end - chars[start..range.end].iter().rev().take_while(|c| c.is_whitespace()).count();
+    if !(start..end).any(|i| spoken[i] && !chars[i].is_whitespace()) {
+        return None;
+    }
+
+    let mut text = String::new();
+    let mut skipped: Vec<Range<usize>> = Vec::new();
+    let mut spans: Vec<EmphasisSpan> = Vec::new();
+    let mut length = 0;
+    for i in start..end {
+        if !spoken[i] {
+            match skipped.last_mut() {
+                Some(last) if last.end == offset + i => last.end += 1,
+                _ => skipped.push(offset + i..offset + i + 1),
+            }
+            continue;
+        }
+        text.push(chars[i]);
+        if let Some(level) = emphasis[i] {
+            match spans.last_mut() {
+                Some(span) if span.level == level && span.range.end == length => span.range.end += 1,
+                _ => spans.push(EmphasisSpan { range: length..length + 1, level }),
+            }
+        }
+        length += 1;
+    }
+
+    Some(SpeechChunk {
+        text,
+        range: offset + start..offset + end,
+        skipped,
+        heading,
+        emphasis: spans,
+        pause_after,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_model::Block;
+    use crate::ooxml::{Field, Run, RunProperties};
+
+    fn paragraph(runs: &[(&str, Option<bool>, Option<bool>)], style: Option<&str>) -> Block {
+        let mut paragraph = Paragraph::default();
+        for (text, bold, italic) in runs {
+            paragraph.text.push_str(text);
+            paragraph.runs.push(Run {
+                text: text.to_string(),
+                properties: RunProperties { bold: *bold, italic: *italic, ..Default::default() },
+            });
+        }
+        paragraph.properties.style_id = style.map(str::to_string);
+        Block::from(paragraph)
+    }
+
+    #[test]
+    fn test_sentences_and_pauses() {
+        let model = DocumentModel {
+            body: vec![
+                paragraph(&[("Intro", None, None)], Some("Heading1")),
+                paragraph(&[("It is ", None, None), ("very", Some(true), None), (" good. ", None, None), ("Really", None, Some(true)), ("!", None, None)], None),
+                paragraph(&[("  ", None, None)], None),
+                paragraph(&[("Fish & chips <3", None, None)], None),
+            ],
+            ..Default::default()
+        };
+        let chunks = speech_chunks(&model, &StyleSheet::new(), DEFAULT_MAX_CHUNK_CHARS);
+        let summary: Vec<(&str, Range<usize>, Option<u8>, Pause)> =
+            chunks.iter().map(|c| (c.text.as_str(), c.range.clone(), c.heading, c.pause_after)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Intro", 0..5, Some(1), Pause::Heading),
+                ("It is very good.", 6..22, None, Pause::Sentence),
+                ("Really!", 23..30, None, Pause::Paragraph),
+                ("Fish & chips <3", 34..49, None, Pause::Paragraph),
+            ]
+        );
+        assert_eq!(chunks[1].emphasis, vec![EmphasisSpan { range: 6..10, level: Emphasis::Strong }]);
+        assert_eq!(
+            chunks[2].to_ssml(),
+            "<emphasis level=\"moderate\">Really</emphasis>!<break strength=\"strong\"/>"
+        );
+        assert_eq!(chunks[3].to_ssml(), "Fish &amp; chips &lt;3<break strength=\"strong\"/>");
+    }
+
+    #[test]
+    fn test_page_furniture_is_skipped() {
+        let Block::Paragraph(mut footer_like) = paragraph(&[("See page 12 for more.", None, None)], None) else {
+            unreachable!()
+        };
+        footer_like.fields.push(Field::new("PAGEREF _Toc1 \\h", 9, "12"));
+        let Block::Paragraph(mut number_only) = paragraph(&[("3", None, None)], None) else {
+            unreachable!()
+        };
+        number_only.fields.push(Field::new("PAGE", 0, "3"));
+        let model = DocumentModel {
+            body: vec![Block::Paragraph(number_only), Block::Paragraph(footer_like)],
+            ..Default::default()
+        };
+
+        let chunks = speech_chunks(&model, &StyleSheet::new(), DEFAULT_MAX_CHUNK_CHARS);
+        assert_eq!(chunks.len(), 1);
+        let chunk = &chunks[0];
+        assert_eq!(chunk.text, "See page  for more.");
+        assert_eq!(chunk.range, 2..23);
+        assert_eq!(chunk.skipped, vec![11..13]);
+        // "for" is after the skipped page number
+        assert_eq!(chunk.document_offset(10), 14);
+        assert_eq!(chunk.document_offset(3), 5);
+    }
+
+    #[test]
+    fn test_long_sentences_are_cut_between_words() {
+        let model = DocumentModel {
+            body: vec![paragraph(&[("one two three four five six.", None, None)], None)],
+            ..Default::default()
+        };
+        let chunks = speech_chunks(&model, &StyleSheet::new(), 10);
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, vec!["one two", "three four", "five six."]);
+        assert_eq!(chunks[0].pause_after, Pause::None);
+        assert_eq!(chunks[1].range, 8..18);
+        assert_eq!(chunks[2].pause_after, Pause::Paragraph);
+    }
+}
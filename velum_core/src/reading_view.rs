@@ -0,0 +1,216 @@
+//! # Reading View Module
+//!
+//! Read mode: the document reflowed to a screen instead of laid out on pages.
+//!
+//! Section page sizes and margins are ignored; text runs in one column as
+//! wide as the viewport less some padding, with no page breaks, and a font
+//! scale enlarges everything, wrapping lines sooner. Indents keep their size
+//! in points, scaled with the text, but never take more than a quarter of the
+//! column each, so deeply indented text stays readable on a phone.
+//!
+//! Floating objects lose their page position and text wrapping: each is drawn
+//! inline above the paragraph it is anchored to, shrunk to the column width
+//! if it is wider. Only the layout changes; the document itself is untouched.
+
+use serde::{Deserialize, Serialize};
+
+use crate::line_breaking::BreakStrategy;
+use crate::line_layout::{LineLayout, ParagraphProperties};
+use crate::page_layout::{Rect, RenderedLine};
+
+/// Twips per point
+const TWIPS_PER_POINT: f32 = 20.0;
+
+/// Line layout scales indents and spacing as if 1440 twips were the column width
+const LAYOUT_TWIPS_PER_COLUMN: f32 = 1440.0;
+
+/// Largest fraction of the column one indent may take
+const MAX_INDENT_FRACTION: f32 = 0.25;
+
+/// How to reflow the document
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReadingOptions {
+    /// Width of the screen area in points
+    pub viewport_width: f32,
+    /// Size of the text relative to the document's, e.g. 1.25
+    pub font_scale: f32,
+    /// Space left and right of the column, in points
+    pub padding: f32,
+}
+
+impl Default for ReadingOptions {
+    fn default() -> Self {
+        ReadingOptions {
+            viewport_width: 360.0,
+            font_scale: 1.0,
+            padding: 16.0,
+        }
+    }
+}
+
+/// A floating object, such as a picture or text box, and the paragraph it is anchored to
+///
+/// Its position on the page and how text wraps around it do not matter here.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnchoredObject {
+    pub id: String,
+    pub paragraph_index: usize,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Where an object is drawn in the reading layout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InlineObject {
+    pub id: String,
+    pub rect: Rect,
+}
+
+/// The document reflowed to the viewport, in points from its top left corner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadingLayout {
+    pub options: ReadingOptions,
+    /// Width of the text column
+    pub column_width: f32,
+    /// Lines in order; `line_index` counts through the whole document
+    pub lines: Vec<RenderedLine>,
+    /// Floating objects, now in line with the text
+    pub objects: Vec<InlineObject>,
+    /// Height of everything laid out
+    pub height: f32,
+}
+
+/// Reflow `text` for reading
+///
+/// `props` gives each paragraph's properties, in order; `objects` are drawn
+/// inline in the order given.
+pub fn layout(
+    text: &str,
+    props: &[ParagraphProperties],
+    objects: &[AnchoredObject],
+    options: ReadingOptions,
+    break_strategy: BreakStrategy,
+) -> ReadingLayout {
+    let scale = if options.font_scale > 0.0 { options.font_scale } else { 1.0 };
+    let column_width = (options.viewport_width - 2.0 * options.padding).max(1.0);
+    // Lines are broken at the size the text would have unscaled, then everything is scaled up
+    let layout_width = column_width / scale;
+
+    let mut line_layout = LineLayout::new();
+    line_layout.set_break_strategy(break_strategy);
+    let mut lines = Vec::new();
+    let mut inline_objects = Vec::new();
+    let mut y = 0.0;
+    for (paragraph_index, paragraph) in text.split('\n').enumerate() {
+        for object in objects.iter().filter(|object| object.paragraph_index == paragraph_index) {
+            // Shrunk to the column if it is wider, keeping its aspect
+            let fit = if object.width > column_width { column_width / object.width } else { 1.0 };
+            let rect = Rect::new(options.padding, y, object.width * fit, object.height * fit);
+            y += rect.height;
+            inline_objects.push(InlineObject {
+                id: object.id.clone(),
+                rect,
+            });
+        }
+
+        let original = props.get(paragraph_index).copied().unwrap_or_default();
+        let (left, first_line, right) = indents(&original, layout_width);
+        let reading_props = ParagraphProperties {
+            indent_left: left * LAYOUT_TWIPS_PER_COLUMN / layout_width,
+            indent_right: right * LAYOUT_TWIPS_PER_COLUMN / layout_width,
+            ..original
+        };
+        let paragraph_layout = line_layout.layout_paragraph_with_props(paragraph, layout_width, reading_props);
+
+        y += original.space_before / TWIPS_PER_POINT * scale;
+        for (source_line_index, line) in paragraph_layout.lines.iter().enumerate() {
+            let indent = if source_line_index == 0 { (left + first_line).max(0.0) } else { left };
+            lines.push(RenderedLine {
+                line_index: lines.len(),
+                paragraph_index,
+                source_line_index,
+                y,
+                height: line.line_height * scale,
+                x: options.padding + indent * scale,
+                width: line.width * scale,
+                start: line.start,
+                end: line.end,
+            });
+            y += line.line_height * scale;
+        }
+        y += original.space_after / TWIPS_PER_POINT * scale;
+    }
+
+    ReadingLayout {
+        options,
+        column_width,
+        lines,
+        objects: inline_objects,
+        height: y,
+    }
+}
+
+/// Left, first-line and right indents of a paragraph in points, limited to fit `column_width`
+fn indents(props: &ParagraphProperties, column_width: f32) -> (f32, f32, f32) {
+    let limit = column_width * MAX_INDENT_FRACTION;
+    let left = (props.indent_left / TWIPS_PER_POINT).clamp(0.0, limit);
+    let right = (props.indent_right / TWIPS_PER_POINT).clamp(0.0, limit);
+    // A hanging first line may not reach left of the column
+    let first_line = (props.indent_first_line / TWIPS_PER_POINT).clamp(-left, limit);
+    (left, first_line, right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reflows_to_viewport_and_scales() {
+        let text = "The quick brown fox jumps over the lazy dog and keeps running through the field";
+        let narrow = ReadingOptions {
+            viewport_width: 200.0,
+            ..Default::default()
+        };
+        let normal = layout(text, &[], &[], narrow, BreakStrategy::default());
+        let wide = layout(text, &[], &[], ReadingOptions { viewport_width: 2000.0, ..narrow }, BreakStrategy::default());
+        let larger = layout(text, &[], &[], ReadingOptions { font_scale: 2.0, ..narrow }, BreakStrategy::default());
+
+        assert_eq!(normal.column_width, 168.0);
+        assert_eq!(wide.lines.len(), 1);
+        assert!(normal.lines.len() > 1);
+        // Larger text wraps sooner, with lines as much taller
+        assert!(larger.lines.len() > normal.lines.len());
+        assert_eq!(larger.lines[1].y, larger.lines[0].height);
+        assert_eq!(larger.lines[0].height, normal.lines[0].height * 2.0);
+    }
+
+    #[test]
+    fn test_indents_are_kept_in_points_and_limited() {
+        let props = [
+            ParagraphProperties::with_indent(720.0, 0.0, -360.0),
+            ParagraphProperties::with_indent(14400.0, 0.0, 0.0),
+        ];
+        let reading = layout("First\nSecond", &props, &[], ReadingOptions::default(), BreakStrategy::default());
+        // 36pt left with an 18pt hang; a 720pt indent is cut to a quarter of the 328pt column
+        assert_eq!(reading.lines[0].x, 16.0 + 18.0);
+        assert_eq!(reading.lines[1].x, 16.0 + 82.0);
+    }
+
+    #[test]
+    fn test_floating_objects_become_inline() {
+        let objects = [AnchoredObject {
+            id: "chart".to_string(),
+            paragraph_index: 1,
+            width: 656.0,
+            height: 200.0,
+        }];
+        let reading = layout("Intro\nAfter", &[], &objects, ReadingOptions::default(), BreakStrategy::default());
+
+        // Shrunk to the column, keeping its aspect, between the two paragraphs
+        let height = reading.lines[0].height;
+        assert_eq!(reading.objects[0].rect, Rect::new(16.0, height, 328.0, 100.0));
+        assert_eq!(reading.lines[1].y, height + 100.0);
+        assert_eq!(reading.height, height * 2.0 + 100.0);
+    }
+}
@@ -0,0 +1,496 @@
+//! # Repagination Module
+//!
+//! Background pagination that never holds the document lock.
+//!
+//! Callers submit a [`PaginationJob`] built from a [`TextSnapshot`] and keep
+//! editing; a worker thread lays the snapshot out and reports progress as
+//! [`PaginationEvent`]s. Each job gets a generation number, and a job is
+//! abandoned as soon as a newer one is submitted, so only the latest state of
+//! the document is ever paginated to the end.
+//!
+//! Paragraph layouts are cached by content between jobs, so after an edit only
+//! the changed paragraphs are shaped again. The page holding the visible offset
+//! is published as soon as the text up to it has been laid out, before the
+//! rest of the document, so "Page X of Y" can update while the total is still
+//! being counted.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use serde::Serialize;
+
+use crate::line_breaking::BreakStrategy;
+use crate::line_layout::{LineLayout, ParagraphLayout, ParagraphProperties};
+use crate::ooxml::Section;
+use crate::page_layout::{Page, PageLayout};
+use crate::piece_tree::TextSnapshot;
+
+/// Chars of the text one page holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PageBoundary {
+    pub page_index: usize,
+    /// Char offset of the first char on the page
+    pub start: usize,
+    /// Char offset just past the last char on the page
+    pub end: usize,
+}
+
+/// Progress reported by the repaginator
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PaginationEvent {
+    /// The page holding the visible offset is laid out, ahead of the rest
+    VisiblePageReady { generation: u64, page: PageBoundary },
+    /// Pages from `first_page` on moved; `pages` lists them all up to the last page
+    BoundariesChanged {
+        generation: u64,
+        first_page: usize,
+        pages: Vec<PageBoundary>,
+    },
+    /// The total page count changed
+    PageCountChanged {
+        generation: u64,
+        previous: usize,
+        page_count: usize,
+    },
+    /// Every page of this generation is laid out
+    Finished { generation: u64, page_count: usize },
+}
+
+/// What to paginate
+#[derive(Debug, Clone)]
+pub struct PaginationJob {
+    pub snapshot: TextSnapshot,
+    /// Layout properties of each paragraph, in order; missing ones use the defaults
+    pub paragraph_props: Vec<ParagraphProperties>,
+    /// Page geometry and columns, for all paragraphs when there are no `sections`
+    pub page_layout: PageLayout,
+    /// Sections the paragraphs are laid out in, as by [`PageLayout::layout_sections`]
+    pub sections: Vec<Section>,
+    pub break_strategy: BreakStrategy,
+}
+
+/// Where pagination stands, for a status bar
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PaginationStatus {
+    /// Generation the boundaries belong to
+    pub generation: u64,
+    /// Page count of the latest finished generation
+    pub page_count: usize,
+    /// Line count of the latest finished generation
+    pub line_count: usize,
+    /// Whether the latest submitted job has finished
+    pub complete: bool,
+    /// Pages known so far; only up to the visible page while a job runs
+    pub pages: Vec<PageBoundary>,
+}
+
+impl PaginationStatus {
+    /// Index of the page holding char `offset`
+    ///
+    /// Page ends are exclusive, so an offset where a line carries over to the
+    /// next page is on that page. The end of a page's last paragraph, like
+    /// the end of the text, stays on the page it ends.
+    pub fn page_of(&self, offset: usize) -> Option<usize> {
+        if offset > self.pages.last()?.end {
+            return None;
+        }
+        let index = self.pages.partition_point(|page| page.start <= offset).saturating_sub(1);
+        Some(self.pages[index].page_index)
+    }
+}
+
+type Listener = Box<dyn Fn(&PaginationEvent) + Send>;
+
+/// State shared between the repaginator and its worker
+struct Shared {
+    /// Generation of the newest submitted job
+    latest: AtomicU64,
+    visible_offset: AtomicUsize,
+    status: Mutex<PaginationStatus>,
+}
+
+impl Shared {
+    fn is_superseded(&self, generation: u64) -> bool {
+        self.latest.load(Ordering::Acquire) != generation
+    }
+}
+
+/// Paginates document snapshots on a worker thread
+pub struct Repaginator {
+    sender: Option<Sender<(u64, PaginationJob)>>,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Repaginator {
+    /// Start the worker; `listener` is called on the worker thread for each event
+    pub fn new(listener: impl Fn(&PaginationEvent) + Send + 'static) -> Self {
+        let shared = Arc::new(Shared {
+            latest: AtomicU64::new(0),
+            visible_offset: AtomicUsize::new(0),
+            status: Mutex::new(PaginationStatus {
+                complete: true,
+                ..Default::default()
+            }),
+        });
+        let (sender, receiver) = mpsc::channel();
+        let worker = {
+            let shared = Arc::clone(&shared);
+            thread::Builder::new()
+                .name("velum-repagination".to_string())
+                .spawn(move || Worker::new(shared, Box::new(listener)).run(receiver))
+                .expect("failed to spawn the repagination worker")
+        };
+        Repaginator {
+            sender: Some(sender),
+            shared,
+            worker: Some(worker),
+        }
+    }
+
+    /// Paginate a snapshot, abandoning any job still running; returns its generation
+    pub fn submit(&self, job: PaginationJob) -> u64 {
+        let generation = {
+            // Under the status lock, so a finishing older job cannot mark this one complete
+            let mut status = self.shared.status.lock().unwrap();
+            status.complete = false;
+            self.shared.latest.fetch_add(1, Ordering::AcqRel) + 1
+        };
+        if let Some(sender) = &self.sender {
+            // The worker only stops when the repaginator is dropped
+            let _ = sender.send((generation, job));
+        }
+        generation
+    }
+
+    /// Char offset the user is looking at; its page is laid out first
+    pub fn set_visible_offset(&self, offset: usize) {
+        self.shared.visible_offset.store(offset, Ordering::Relaxed);
+    }
+
+    pub fn status(&self) -> PaginationStatus {
+        self.shared.status.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Repaginator {
+    fn drop(&mut self) {
+        // Abandon the running job, then let the worker see the closed channel
+        self.shared.latest.fetch_add(1, Ordering::AcqRel);
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The worker thread's side
+struct Worker {
+    shared: Arc<Shared>,
+    listener: Listener,
+    line_layout: LineLayout,
+    /// Paragraph layouts of the last job, by content
+    cache: HashMap<u64, ParagraphLayout>,
+    /// Pages of the last finished job
+    pages: Vec<PageBoundary>,
+}
+
+impl Worker {
+    fn new(shared: Arc<Shared>, listener: Listener) -> Self {
+        Worker {
+            shared,
+            listener,
+            line_layout: LineLayout::new(),
+            cache: HashMap::new(),
+            pages: Vec::new(),
+        }
+    }
+
+    fn run(mut self, receiver: Receiver<(u64, PaginationJob)>) {
+        while let Ok(mut next) = receiver.recv() {
+            // Skip straight to the newest job
+            while let Ok(newer) = receiver.try_recv() {
+                next = newer;
+            }
+            let (generation, job) = next;
+            if !self.shared.is_superseded(generation) {
+                self.paginate(generation, &job);
+            }
+        }
+    }
+
+    /// Lay out a job, returning early once it is superseded
+    fn paginate(&mut self, generation: u64, job: &PaginationJob) {
+        let text = job.snapshot.get_text();
+        let content_width = job.page_layout.column_width();
+        let content_height = job.page_layout.page_config.content_height();
+        self.line_layout.set_break_strategy(job.break_strategy);
+
+        let mut layouts = Vec::new();
+        let mut used = HashMap::new();
+        let mut paragraph_start = 0;
+        // Height laid out past the visible paragraph, until its page is published
+        let mut after_visible: Option<f32> = None;
+        let mut visible_published = false;
+
+        let paragraphs: Vec<&str> = text.split('\n').collect();
+        for (index, paragraph) in paragraphs.iter().enumerate() {
+            if self.shared.is_superseded(generation) {
+                self.cache.extend(used);
+                return;
+            }
+            let props = job.paragraph_props.get(index).copied().unwrap_or_default();
+            let key = paragraph_key(paragraph, &props, content_width, job.break_strategy);
+            let layout = match self.cache.remove(&key) {
+                Some(layout) => layout,
+                None => self.line_layout.layout_paragraph_with_props(paragraph, content_width, props),
+            };
+            let height = layout.total_height;
+            used.insert(key, layout.clone());
+            layouts.push(layout);
+
+            let paragraph_end = paragraph_start + paragraph.chars().count();
+            if !visible_published {
+                let visible = self.shared.visible_offset.load(Ordering::Relaxed);
+                if let Some(after) = after_visible.as_mut() {
+                    *after += height;
+                } else if visible <= paragraph_end {
+                    after_visible = Some(0.0);
+                }
+                // A page's worth of text past the visible paragraph settles its page
+                let settled = after_visible.is_some_and(|after| after >= content_height);
+                if settled && index + 1 < paragraphs.len() {
+                    self.publish_visible_page(generation, job, &layouts, visible);
+                    visible_published = true;
+                }
+            }
+            paragraph_start = paragraph_end + 1;
+        }
+        self.cache = used;
+
+        let pages = job.page_layout.clone().layout_sections(&layouts, &job.sections).pages;
+        let boundaries = page_boundaries(&pages, &layouts);
+        if self.shared.is_superseded(generation) {
+            return;
+        }
+
+        let first_changed = boundaries
+            .iter()
+            .zip(&self.pages)
+            .position(|(new, old)| new != old)
+            .unwrap_or(boundaries.len().min(self.pages.len()));
+        if first_changed < boundaries.len().max(self.pages.len()) {
+            self.emit(PaginationEvent::BoundariesChanged {
+                generation,
+                first_page: first_changed,
+                pages: boundaries[first_changed.min(boundaries.len())..].to_vec(),
+            });
+        }
+        let previous = self.pages.len();
+        if boundaries.len() != previous {
+            self.emit(PaginationEvent::PageCountChanged {
+                generation,
+                previous,
+                page_count: boundaries.len(),
+            });
+        }
+
+        {
+            let mut status = self.shared.status.lock().unwrap();
+            *status = PaginationStatus {
+                generation,
+                page_count: boundaries.len(),
+                line_count: layouts.iter().map(|layout| layout.lines.len()).sum(),
+                complete: !self.shared.is_superseded(generation),
+                pages: boundaries.clone(),
+            };
+        }
+        self.pages = boundaries;
+        self.emit(PaginationEvent::Finished {
+            generation,
+            page_count: self.pages.len(),
+        });
+    }
+
+    /// Paginate the paragraphs laid out so far and publish the page holding `visible`
+    fn publish_visible_page(&mut self, generation: u64, job: &PaginationJob, layouts: &[ParagraphLayout], visible: usize) {
+        let pages = job.page_layout.clone().layout_sections(layouts, &job.sections).pages;
+        let mut boundaries = page_boundaries(&pages, layouts);
+        let Some(index) = boundaries.iter().position(|page| visible <= page.end) else {
+            return;
+        };
+        boundaries.truncate(index + 1);
+        let page = boundaries[index].clone();
+        {
+            let mut status = self.shared.status.lock().unwrap();
+            status.generation = generation;
+            status.page_count = status.page_count.max(boundaries.len());
+            status.pages = boundaries;
+        }
+        self.emit(PaginationEvent::VisiblePageReady { generation, page });
+    }
+
+    fn emit(&self, event: PaginationEvent) {
+        (self.listener)(&event);
+    }
+}
+
+/// Cache key of a paragraph's layout
+fn paragraph_key(text: &str, props: &ParagraphProperties, width: f32, strategy: BreakStrategy) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    format!("{:?}", props).hash(&mut hasher);
+    width.to_bits().hash(&mut hasher);
+    strategy.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Char range of each page, from its first line to its last
+fn page_boundaries(pages: &[Page], paragraphs: &[ParagraphLayout]) -> Vec<PageBoundary> {
+    let mut starts = Vec::with_capacity(paragraphs.len());
+    let mut start = 0;
+    for paragraph in paragraphs {
+        starts.push(start);
+        start += paragraph.text.chars().count() + 1;
+    }
+    let char_offset = |paragraph: usize, byte: usize| {
+        let text = &paragraphs[paragraph].text;
+        starts[paragraph] + text.get(..byte).map_or(0, |prefix| prefix.chars().count())
+    };
+
+    let mut boundaries = Vec::with_capacity(pages.len());
+    for page in pages {
+        // Empty paragraphs have no lines and belong to no page
+        let (Some(first), Some(last)) = (page.lines.first(), page.lines.last()) else {
+            continue;
+        };
+        boundaries.push(PageBoundary {
+            page_index: page.page_index,
+            start: char_offset(first.paragraph_index, first.start),
+            end: char_offset(last.paragraph_index, last.end),
+        });
+    }
+    boundaries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_layout::PageConfig;
+    use crate::piece_tree::PieceTree;
+    use std::time::Duration;
+
+    fn job(text: &str) -> PaginationJob {
+        PaginationJob {
+            snapshot: PieceTree::new(text.to_string()).snapshot(),
+            paragraph_props: Vec::new(),
+            page_layout: PageLayout::with_page_config(PageConfig::a4()),
+            sections: Vec::new(),
+            break_strategy: BreakStrategy::FirstFit,
+        }
+    }
+
+    fn long_text(paragraphs: usize) -> String {
+        (0..paragraphs)
+            .map(|i| format!("Paragraph {} of a document long enough to need several pages.", i))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Events up to and including the Finished event of `generation`
+    fn events_until(receiver: &Receiver<PaginationEvent>, generation: u64) -> Vec<PaginationEvent> {
+        let mut events = Vec::new();
+        loop {
+            let event = receiver.recv_timeout(Duration::from_secs(60)).expect("pagination timed out");
+            let done = matches!(event, PaginationEvent::Finished { generation: g, .. } if g == generation);
+            events.push(event);
+            if done {
+                return events;
+            }
+        }
+    }
+
+    fn repaginator() -> (Repaginator, Receiver<PaginationEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        let repaginator = Repaginator::new(move |event| {
+            let _ = sender.send(event.clone());
+        });
+        (repaginator, receiver)
+    }
+
+    #[test]
+    fn test_page_count_events() {
+        let (repaginator, receiver) = repaginator();
+        let text = long_text(200);
+        let generation = repaginator.submit(job(&text));
+        let events = events_until(&receiver, generation);
+
+        let status = repaginator.status();
+        assert!(status.complete);
+        assert!(status.page_count > 1);
+        assert!(events.contains(&PaginationEvent::PageCountChanged {
+            generation,
+            previous: 0,
+            page_count: status.page_count,
+        }));
+        // The pages cover the text in order
+        assert_eq!(status.pages[0].start, 0);
+        assert_eq!(status.pages.last().unwrap().end, text.chars().count());
+        assert!(status.pages.windows(2).all(|pair| pair[0].end <= pair[1].start));
+        assert_eq!(status.page_of(text.chars().count()), Some(status.page_count - 1));
+
+        // Paginating the same text again moves no page
+        let again = repaginator.submit(job(&text));
+        let events = events_until(&receiver, again);
+        assert!(!events.iter().any(|event| matches!(
+            event,
+            PaginationEvent::BoundariesChanged { .. } | PaginationEvent::PageCountChanged { .. }
+        )));
+    }
+
+    #[test]
+    fn test_page_of_boundaries() {
+        let page = |page_index, start, end| PageBoundary { page_index, start, end };
+        // A paragraph carries over from the first page to the second, which
+        // ends with its paragraph
+        let status = PaginationStatus { pages: vec![page(0, 0, 10), page(1, 10, 20), page(2, 21, 30)], ..Default::default() };
+        let pages: Vec<Option<usize>> = [0, 9, 10, 19, 20, 21, 30, 31].iter().map(|&offset| status.page_of(offset)).collect();
+        assert_eq!(pages, [Some(0), Some(0), Some(1), Some(1), Some(1), Some(2), Some(2), None]);
+        assert_eq!(PaginationStatus::default().page_of(0), None);
+    }
+
+    #[test]
+    fn test_visible_page_comes_first() {
+        let (repaginator, receiver) = repaginator();
+        let text = long_text(400);
+        repaginator.set_visible_offset(text.chars().count() / 4);
+        let generation = repaginator.submit(job(&text));
+        let events = events_until(&receiver, generation);
+
+        let PaginationEvent::VisiblePageReady { page, .. } = &events[0] else {
+            panic!("expected the visible page first, got {:?}", events[0]);
+        };
+        assert!(page.page_index > 0);
+        assert!((page.start..=page.end).contains(&(text.chars().count() / 4)));
+        // The final pagination agrees with the early one
+        assert_eq!(repaginator.status().pages[page.page_index], *page);
+    }
+
+    #[test]
+    fn test_newer_job_supersedes() {
+        let (repaginator, receiver) = repaginator();
+        repaginator.submit(job(&long_text(400)));
+        let latest = repaginator.submit(job("Short"));
+        let events = events_until(&receiver, latest);
+
+        // Whatever the first job got to report, the last word is the newest job's
+        assert_eq!(events.last(), Some(&PaginationEvent::Finished { generation: latest, page_count: 1 }));
+        assert_eq!(repaginator.status().pages, vec![PageBoundary { page_index: 0, start: 0, end: 5 }]);
+    }
+}
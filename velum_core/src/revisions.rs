@@ -0,0 +1,365 @@
+//! # Revisions Module
+//!
+//! Tracked changes (w:ins, w:del, w:rPrChange, w:pPrChange) over editor text.
+//!
+//! Revised text stays in the piece tree until the revision is resolved:
+//! deleted text is still shown (as struck-through markup) and only removed
+//! when the deletion is accepted, and inserted text is removed when the
+//! insertion is rejected. Rejecting a format change restores the formatting
+//! recorded with it. Offsets are chars into the whole text and move with
+//! later edits.
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::comments::move_anchor;
+use crate::document_model::{paragraph_attributes, text_attributes, Block, DocumentModel};
+use crate::ooxml::{Paragraph, Revision, RevisionKind};
+use crate::piece_tree::PieceTree;
+
+/// Revision errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RevisionError {
+    #[error("Revision {0} does not exist")]
+    NotFound(String),
+}
+
+/// What resolving a revision did to the document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Resolution {
+    /// Only the revision mark was dropped
+    Unchanged,
+    /// These chars were deleted
+    Removed(Range<usize>),
+    /// The formatting of these chars was restored
+    Restyled(Range<usize>),
+}
+
+/// Pending tracked changes of a document
+#[derive(Debug, Clone, Default)]
+pub struct RevisionSet {
+    /// Sorted by start
+    revisions: Vec<Revision>,
+}
+
+impl RevisionSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Revisions of the model's body paragraphs, with offsets into the text `to_piece_tree` builds
+    pub fn from_model(model: &DocumentModel) -> Self {
+        Self::from_paragraphs(model.located_paragraphs())
+    }
+
+    /// Revisions of `paragraphs`, each given with the char offset it starts
+    /// at, with offsets into the whole text
+    pub fn from_paragraphs<'a>(paragraphs: impl IntoIterator<Item = (usize, &'a Paragraph)>) -> Self {
+        let mut revisions = Vec::new();
+        for (paragraph_start, paragraph) in paragraphs {
+            revisions.extend(paragraph.revisions.iter().map(|revision| Revision {
+                start: revision.start + paragraph_start,
+                ..revision.clone()
+            }));
+        }
+        revisions.sort_by_key(|revision| revision.start);
+        RevisionSet { revisions }
+    }
+
+    /// Hand the revisions back to the model's paragraphs, with paragraph-relative offsets
+    pub fn add_to_model(&self, model: &mut DocumentModel) {
+        let mut paragraph_start = 0;
+        let mut pending = self.revisions.iter().peekable();
+        for block in model.body.iter_mut() {
+            let Block::Paragraph(paragraph) = block else {
+                continue;
+            };
+            let paragraph_end = paragraph_start + paragraph.text.chars().count();
+            while let Some(revision) = pending.next_if(|revision| revision.start <= paragraph_end) {
+                paragraph.revisions.push(Revision {
+                    start: revision.start - paragraph_start,
+                    length: revision.length.min(paragraph_end - revision.start),
+                    ..revision.clone()
+                });
+            }
+            paragraph_start = paragraph_end + 1;
+        }
+    }
+
+    pub fn revisions(&self) -> &[Revision] {
+        &self.revisions
+    }
+
+    pub fn len(&self) -> usize {
+        self.revisions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.revisions.is_empty()
+    }
+
+    /// Add a revision made in the editor
+    ///
+    /// It joins a touching or overlapping insertion or deletion of the same kind
+    /// by the same author, so typing a word records one insertion; an insertion
+    /// inside a deletion splits the deletion around it.
+    pub fn record(&mut self, revision: Revision) {
+        let start = revision.start;
+        let end = start + revision.length;
+        if revision.kind == RevisionKind::Insertion {
+            // The tails must not take the new revision's ID either
+            let first_free = revision.id.parse::<u64>().map_or(0, |id| id + 1);
+            self.split_deletions(start, end, self.next_id_number().max(first_free));
+        }
+
+        let joinable = matches!(revision.kind, RevisionKind::Insertion | RevisionKind::Deletion);
+        let existing = self.revisions.iter_mut().find(|existing| {
+            joinable
+                && existing.kind == revision.kind
+                && existing.author == revision.author
+                && existing.start <= end
+                && start <= existing.start + existing.length
+        });
+        match existing {
+            Some(existing) => {
+                let joined_start = existing.start.min(start);
+                existing.length = (existing.start + existing.length).max(end) - joined_start;
+                existing.start = joined_start;
+            }
+            None => self.revisions.push(revision),
+        }
+        self.revisions.sort_by_key(|revision| revision.start);
+    }
+
+    /// An ID no revision has yet
+    pub fn next_id(&self) -> String {
+        self.next_id_number().to_string()
+    }
+
+    fn next_id_number(&self) -> u64 {
+        self.revisions
+            .iter()
+            .filter_map(|revision| revision.id.parse::<u64>().ok())
+            .max()
+            .map_or(1, |id| id + 1)
+    }
+
+    /// Cut chars [start, end) out of the deletions they are strictly inside,
+    /// numbering the split-off tails from `next_id`
+    fn split_deletions(&mut self, start: usize, end: usize, mut next_id: u64) {
+        let mut tails = Vec::new();
+        for deletion in self.revisions.iter_mut() {
+            let deletion_end = deletion.start + deletion.length;
+            if deletion.kind == RevisionKind::Deletion && deletion.start < start && end < deletion_end {
+                tails.push(Revision {
+                    id: next_id.to_string(),
+                    start: end,
+                    length: deletion_end - end,
+                    ..deletion.clone()
+                });
+                next_id += 1;
+                deletion.length = start - deletion.start;
+            }
+        }
+        self.revisions.extend(tails);
+    }
+
+    /// Move the revisions past an edit, see [`move_anchor`]
+    ///
+    /// A revision whose text is all deleted goes away, except paragraph format
+    /// changes, which cover the paragraph.
+    pub fn apply_edit(&mut self, offset: usize, removed: usize, inserted: usize) {
+        self.revisions.retain_mut(|revision| {
+            let emptied = revision.length > 0;
+            (revision.start, revision.length) = move_anchor(revision.start, revision.length, offset, removed, inserted);
+            !(emptied && revision.length == 0) || revision.kind == RevisionKind::ParagraphFormat
+        });
+    }
+
+    /// Accept a revision: keep an insertion or format change, carry out a deletion
+    pub fn accept(&mut self, tree: &mut PieceTree, id: &str) -> Result<Resolution, RevisionError> {
+        let revision = self.take(id)?;
+        let range = revision.start..revision.start + revision.length;
+        Ok(match revision.kind {
+            RevisionKind::Deletion => self.remove_text(tree, range),
+            _ => Resolution::Unchanged,
+        })
+    }
+
+    /// Reject a revision: remove an insertion, keep deleted text, restore replaced formatting
+    pub fn reject(&mut self, tree: &mut PieceTree, id: &str) -> Result<Resolution, RevisionError> {
+        let revision = self.take(id)?;
+        let range = revision.start..revision.start + revision.length;
+        Ok(match revision.kind {
+            RevisionKind::Insertion => self.remove_text(tree, range),
+            RevisionKind::Deletion => Resolution::Unchanged,
+            RevisionKind::RunFormat => {
+                let previous = revision.previous_run_properties.as_ref().and_then(text_attributes);
+                tree.transaction(|tree| {
+                    tree.clear_attributes(range.clone());
+                    if let Some(attributes) = &previous {
+                        tree.apply_attributes(range.clone(), attributes);
+                    }
+                });
+                Resolution::Restyled(range)
+            }
+            RevisionKind::ParagraphFormat => {
+                let previous = revision.previous_paragraph_properties.as_ref().and_then(paragraph_attributes);
+                tree.set_paragraph_attributes(range.clone(), previous.as_ref());
+                Resolution::Restyled(range)
+            }
+        })
+    }
+
+    /// Accept every revision, first to last
+    pub fn accept_all(&mut self, tree: &mut PieceTree) -> Vec<Resolution> {
+        self.resolve_all(tree, Self::accept)
+    }
+
+    /// Reject every revision, first to last
+    pub fn reject_all(&mut self, tree: &mut PieceTree) -> Vec<Resolution> {
+        self.resolve_all(tree, Self::reject)
+    }
+
+    fn resolve_all(
+        &mut self,
+        tree: &mut PieceTree,
+        resolve: fn(&mut Self, &mut PieceTree, &str) -> Result<Resolution, RevisionError>,
+    ) -> Vec<Resolution> {
+        tree.transaction(|tree| {
+            let mut resolutions = Vec::with_capacity(self.revisions.len());
+            while let Some(id) = self.revisions.first().map(|revision| revision.id.clone()) {
+                resolutions.extend(resolve(self, tree, &id));
+            }
+            resolutions
+        })
+    }
+
+    /// Remove a revision from the set
+    fn take(&mut self, id: &str) -> Result<Revision, RevisionError> {
+        let index = self
+            .revisions
+            .iter()
+            .position(|revision| revision.id == id)
+            .ok_or_else(|| RevisionError::NotFound(id.to_string()))?;
+        Ok(self.revisions.remove(index))
+    }
+
+    /// Delete chars from the tree, moving the remaining revisions
+    fn remove_text(&mut self, tree: &mut PieceTree, range: Range<usize>) -> Resolution {
+        if range.is_empty() {
+            return Resolution::Unchanged;
+        }
+        let start = tree.char_to_byte_offset(range.start);
+        let end = tree.char_to_byte_offset(range.end);
+        tree.delete(start, end - start);
+        self.apply_edit(range.start, range.len(), 0);
+        Resolution::Removed(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::{Paragraph, Run, RunProperties};
+
+    fn revision(id: &str, kind: RevisionKind, start: usize, length: usize) -> Revision {
+        Revision {
+            id: id.to_string(),
+            kind,
+            author: Some("Ann".to_string()),
+            date: None,
+            start,
+            length,
+            previous_run_properties: None,
+            previous_paragraph_properties: None,
+        }
+    }
+
+    fn model() -> DocumentModel {
+        // "Hello brave new world" with "brave " inserted and "new " deleted
+        let paragraph = Paragraph {
+            text: "Hello brave new world".to_string(),
+            revisions: vec![
+                revision("1", RevisionKind::Insertion, 6, 6),
+                revision("2", RevisionKind::Deletion, 12, 4),
+            ],
+            ..Default::default()
+        };
+        let second = Paragraph {
+            text: "Plain".to_string(),
+            runs: vec![Run {
+                text: "Plain".to_string(),
+                properties: RunProperties {
+                    bold: Some(true),
+                    ..Default::default()
+                },
+            }],
+            revisions: vec![Revision {
+                previous_run_properties: Some(RunProperties::default()),
+                ..revision("3", RevisionKind::RunFormat, 0, 5)
+            }],
+            ..Default::default()
+        };
+        DocumentModel {
+            body: vec![Block::from(paragraph), Block::from(second)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_accept_and_reject() {
+        let model = model();
+        let mut tree = model.to_piece_tree();
+        let mut revisions = RevisionSet::from_model(&model);
+        assert_eq!(revisions.revisions()[2].start, 22);
+
+        assert_eq!(revisions.accept(&mut tree, "2"), Ok(Resolution::Removed(12..16)));
+        assert_eq!(tree.get_text(), "Hello brave world\nPlain");
+        assert_eq!(revisions.reject(&mut tree, "1"), Ok(Resolution::Removed(6..12)));
+        assert_eq!(tree.get_text(), "Hello world\nPlain");
+
+        // The format change moved with both deletions
+        assert_eq!(revisions.reject(&mut tree, "3"), Ok(Resolution::Restyled(12..17)));
+        assert_eq!(tree.get_attributes_at(12), None);
+        assert_eq!(revisions.reject(&mut tree, "3"), Err(RevisionError::NotFound("3".to_string())));
+        assert!(revisions.is_empty());
+    }
+
+    #[test]
+    fn test_accept_all_is_one_undo_step() {
+        let model = model();
+        let mut tree = model.to_piece_tree();
+        let mut revisions = RevisionSet::from_model(&model);
+
+        let resolutions = revisions.accept_all(&mut tree);
+        assert_eq!(resolutions.len(), 3);
+        assert_eq!(tree.get_text(), "Hello brave world\nPlain");
+        assert!(revisions.is_empty());
+
+        tree.undo();
+        assert_eq!(tree.get_text(), "Hello brave new world\nPlain");
+    }
+
+    #[test]
+    fn test_edits_move_revisions_and_round_trip() {
+        let model = model();
+        let mut revisions = RevisionSet::from_model(&model);
+        // Typing inside the insertion extends it; deleting the deleted text drops the deletion
+        revisions.apply_edit(8, 0, 2);
+        revisions.apply_edit(14, 4, 0);
+        let kept: Vec<(&str, usize, usize)> = revisions
+            .revisions()
+            .iter()
+            .map(|revision| (revision.id.as_str(), revision.start, revision.length))
+            .collect();
+        assert_eq!(kept, vec![("1", 6, 8), ("3", 20, 5)]);
+
+        let mut model = DocumentModel::from_piece_tree(&PieceTree::new("Hello braXXve world\nPlain".to_string()));
+        revisions.add_to_model(&mut model);
+        let paragraphs: Vec<&Paragraph> = model.paragraphs().collect();
+        assert_eq!(paragraphs[0].revisions[0].start, 6);
+        assert_eq!(paragraphs[1].revisions[0].start, 0);
+    }
+}
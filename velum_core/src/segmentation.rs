@@ -0,0 +1,560 @@
+//! # Segmentation Module
+//!
+//! Unicode text segmentation: grapheme clusters and words (UAX #29) for
+//! cursor movement and selection, and line break opportunities (UAX #14) for
+//! layout.
+//!
+//! Graphemes and words come from `unicode-segmentation`. Line breaking is
+//! implemented here: each char gets its line break class, resolved as LB1
+//! does by default (ambiguous and unknown chars as letters, conditional
+//! Japanese starters as non-starters, South East Asian letters as letters),
+//! and rules LB2–LB31 decide each position. Non-starters and closing
+//! punctuation therefore never begin a line and opening punctuation never
+//! ends one, which are the Japanese and Chinese no-break (kinsoku) rules, and
+//! CJK ideographs break anywhere else. Thai, Lao, Khmer and Myanmar need a
+//! dictionary to find words and do not break inside a run of letters.
+//!
+//! All offsets are byte offsets into the text, on char boundaries.
+
+use std::ops::Range;
+
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+
+/// Line break class of a char (UAX #14), after LB1 resolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineBreakClass {
+    /// Mandatory break after (BK)
+    MandatoryBreak,
+    CarriageReturn,
+    LineFeed,
+    /// Next line, U+0085 (NL)
+    NextLine,
+    Space,
+    /// Zero width space (ZW)
+    ZeroWidthSpace,
+    ZeroWidthJoiner,
+    /// Combining mark, attaching to the char before (CM)
+    CombiningMark,
+    /// Word joiner: no break either side (WJ)
+    WordJoiner,
+    /// Non-breaking glue (GL)
+    Glue,
+    /// Break after (BA)
+    BreakAfter,
+    /// Break before (BB)
+    BreakBefore,
+    /// Break either side, not between two (B2)
+    BreakBoth,
+    Hyphen,
+    /// Contingent break, e.g. an object replacement char (CB)
+    Contingent,
+    ClosePunctuation,
+    CloseParenthesis,
+    /// Exclamation and interrogation (EX)
+    Exclamation,
+    /// Inseparable, e.g. an ellipsis (IN)
+    Inseparable,
+    /// Non-starter, such as small kana and iteration marks (NS)
+    NonStarter,
+    OpenPunctuation,
+    Quotation,
+    /// Infix numeric separator (IS)
+    InfixSeparator,
+    Numeric,
+    /// Postfix numeric, e.g. '%' (PO)
+    Postfix,
+    /// Prefix numeric, e.g. '$' (PR)
+    Prefix,
+    /// Symbols allowing a break after, '/' (SY)
+    Symbol,
+    Alphabetic,
+    HebrewLetter,
+    /// Ideographic (ID)
+    Ideographic,
+    /// Hangul LV syllable (H2)
+    HangulLv,
+    /// Hangul LVT syllable (H3)
+    HangulLvt,
+    /// Hangul leading jamo (JL)
+    HangulL,
+    /// Hangul vowel jamo (JV)
+    HangulV,
+    /// Hangul trailing jamo (JT)
+    HangulT,
+    RegionalIndicator,
+}
+
+use LineBreakClass::*;
+
+/// A position where a line may or must end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineBreak {
+    /// After a hard line break char, and at the end of the text
+    Mandatory,
+    Allowed,
+}
+
+/// Line break class of a char
+pub fn line_break_class(c: char) -> LineBreakClass {
+    let code = c as u32;
+    match c {
+        '\u{0B}' | '\u{0C}' | '\u{2028}' | '\u{2029}' => return MandatoryBreak,
+        '\r' => return CarriageReturn,
+        '\n' => return LineFeed,
+        '\u{85}' => return NextLine,
+        ' ' => return Space,
+        '\u{200B}' => return ZeroWidthSpace,
+        '\u{200D}' => return ZeroWidthJoiner,
+        '\u{2060}' | '\u{FEFF}' => return WordJoiner,
+        '\u{A0}' | '\u{202F}' | '\u{2007}' | '\u{2011}' | '\u{034F}' | '\u{180E}' | '\u{0F08}' | '\u{0F0C}' | '\u{0F12}' => {
+            return Glue
+        }
+        '\t' | '\u{AD}' | '|' | '\u{058A}' | '\u{05BE}' | '\u{0F0B}' | '\u{1361}' | '\u{1680}' | '\u{2000}'..='\u{2006}'
+        | '\u{2008}'..='\u{200A}' | '\u{2010}' | '\u{2012}' | '\u{2013}' | '\u{2027}' | '\u{2056}' | '\u{2058}'..='\u{205B}'
+        | '\u{205D}'..='\u{205F}' | '\u{2E0E}'..='\u{2E15}' | '\u{2E17}' | '\u{3000}' => return BreakAfter,
+        '\u{B4}' | '\u{02C8}' | '\u{02CC}' | '\u{02DF}' | '\u{0F01}'..='\u{0F04}' | '\u{0F06}' | '\u{0F07}' | '\u{0F09}' | '\u{0F0A}'
+        | '\u{1806}' | '\u{1FFD}' => return BreakBefore,
+        '\u{2014}' | '\u{2E3A}' | '\u{2E3B}' => return BreakBoth,
+        '-' => return Hyphen,
+        '\u{FFFC}' => return Contingent,
+        ')' | ']' | '\u{FF09}' | '\u{FF3D}' => return CloseParenthesis,
+        '}' | '\u{0F3B}' | '\u{0F3D}' | '\u{169C}' | '\u{2046}' | '\u{207E}' | '\u{208E}' | '\u{2309}' | '\u{230B}' | '\u{232A}'
+        | '\u{3001}' | '\u{3002}' | '\u{3009}' | '\u{300B}' | '\u{300D}' | '\u{300F}' | '\u{3011}' | '\u{3015}' | '\u{3017}'
+        | '\u{3019}' | '\u{301B}' | '\u{301E}' | '\u{301F}' | '\u{FE11}' | '\u{FE12}' | '\u{FE5A}' | '\u{FE5C}' | '\u{FE5E}'
+        | '\u{FF0C}' | '\u{FF0E}' | '\u{FF5D}' | '\u{FF60}' | '\u{FF61}' | '\u{FF63}' | '\u{FF64}' => return ClosePunctuation,
+        '!' | '?' | '\u{05C6}' | '\u{061B}' | '\u{061E}' | '\u{061F}' | '\u{06D4}' | '\u{07F9}' | '\u{0F0D}'..='\u{0F11}' | '\u{0F14}'
+        | '\u{1802}' | '\u{1803}' | '\u{1808}' | '\u{1809}' | '\u{1944}' | '\u{1945}' | '\u{2762}' | '\u{2763}' | '\u{2CF9}'
+        | '\u{2CFE}' | '\u{2E2E}' | '\u{A60E}' | '\u{FE15}' | '\u{FE16}' | '\u{FE56}' | '\u{FE57}' | '\u{FF01}' | '\u{FF1F}' => {
+            return Exclamation
+        }
+        '\u{2024}'..='\u{2026}' | '\u{22EF}' | '\u{FE19}' => return Inseparable,
+        '\u{17D6}' | '\u{203C}' | '\u{203D}' | '\u{2047}'..='\u{2049}' | '\u{3005}' | '\u{301C}' | '\u{303B}' | '\u{303C}'
+        | '\u{309B}'..='\u{309E}' | '\u{30A0}' | '\u{30FB}' | '\u{30FD}' | '\u{30FE}' | '\u{A015}' | '\u{FE54}' | '\u{FE55}'
+        | '\u{FF1A}' | '\u{FF1B}' | '\u{FF65}' | '\u{FF9E}' | '\u{FF9F}' => return NonStarter,
+        // Small kana and the prolonged sound mark: conditional Japanese
+        // starters, resolved to non-starters (strict kinsoku)
+        '\u{3041}' | '\u{3043}' | '\u{3045}' | '\u{3047}' | '\u{3049}' | '\u{3063}' | '\u{3083}' | '\u{3085}' | '\u{3087}'
+        | '\u{308E}' | '\u{3095}' | '\u{3096}' | '\u{30A1}' | '\u{30A3}' | '\u{30A5}' | '\u{30A7}' | '\u{30A9}' | '\u{30C3}'
+        | '\u{30E3}' | '\u{30E5}' | '\u{30E7}' | '\u{30EE}' | '\u{30F5}' | '\u{30F6}' | '\u{30FC}' | '\u{31F0}'..='\u{31FF}'
+        | '\u{FF67}'..='\u{FF70}' => return NonStarter,
+        '(' | '[' | '{' | '\u{A1}' | '\u{BF}' | '\u{0F3A}' | '\u{0F3C}' | '\u{169B}' | '\u{201A}' | '\u{201E}' | '\u{2045}'
+        | '\u{207D}' | '\u{208D}' | '\u{2308}' | '\u{230A}' | '\u{2329}' | '\u{3008}' | '\u{300A}' | '\u{300C}' | '\u{300E}'
+        | '\u{3010}' | '\u{3014}' | '\u{3016}' | '\u{3018}' | '\u{301A}' | '\u{301D}' | '\u{FE59}' | '\u{FE5B}' | '\u{FE5D}'
+        | '\u{FF08}' | '\u{FF3B}' | '\u{FF5B}' | '\u{FF5F}' | '\u{FF62}' => return OpenPunctuation,
+        '"' | '\'' | '\u{AB}' | '\u{BB}' | '\u{2018}' | '\u{2019}' | '\u{201B}'..='\u{201D}' | '\u{201F}' | '\u{2039}' | '\u{203A}'
+        | '\u{275B}'..='\u{2760}' | '\u{2E00}'..='\u{2E0D}' | '\u{2E1C}' | '\u{2E1D}' | '\u{2E20}' | '\u{2E21}' => return Quotation,
+        ',' | '.' | ':' | ';' | '\u{037E}' | '\u{0589}' | '\u{060C}' | '\u{060D}' | '\u{07F8}' | '\u{2044}' | '\u{FE10}' | '\u{FE13}'
+        | '\u{FE14}' => return InfixSeparator,
+        '%' | '\u{A2}' | '\u{B0}' | '\u{0609}'..='\u{060B}' | '\u{066A}' | '\u{2030}'..='\u{2037}' | '\u{2103}' | '\u{2109}'
+        | '\u{FF05}' | '\u{FFE0}' => return Postfix,
+        '$' | '+' | '\\' | '\u{A3}'..='\u{A5}' | '\u{B1}' | '\u{20A0}'..='\u{20BF}' | '\u{2116}' | '\u{2212}' | '\u{2213}'
+        | '\u{FF04}' | '\u{FFE1}' | '\u{FFE5}' | '\u{FFE6}' => return Prefix,
+        '/' => return Symbol,
+        _ => {}
+    }
+
+    if is_decimal_digit(c) {
+        return Numeric;
+    }
+    if c.is_control() || is_combining(c) {
+        // Emoji modifiers attach like marks
+        return CombiningMark;
+    }
+    match code {
+        0x05D0..=0x05EA | 0x05EF..=0x05F2 | 0xFB1D..=0xFB4F => HebrewLetter,
+        0x1100..=0x115F | 0xA960..=0xA97C => HangulL,
+        0x1160..=0x11A7 | 0xD7B0..=0xD7C6 => HangulV,
+        0x11A8..=0x11FF | 0xD7CB..=0xD7FB => HangulT,
+        0xAC00..=0xD7A3 if (code - 0xAC00).is_multiple_of(28) => HangulLv,
+        0xAC00..=0xD7A3 => HangulLvt,
+        0x1F1E6..=0x1F1FF => RegionalIndicator,
+        0x2E80..=0x2FFF
+        | 0x3003..=0x3004
+        | 0x3006..=0x3007
+        | 0x3012..=0x3013
+        | 0x3020..=0x303A
+        | 0x303D..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA48F
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F000..=0x1FAFF
+        | 0x20000..=0x3FFFD => Ideographic,
+        _ => Alphabetic,
+    }
+}
+
+/// Whether a char is a decimal digit (general category Nd)
+fn is_decimal_digit(c: char) -> bool {
+    const ZEROS: [u32; 22] = [
+        0x30, 0x660, 0x6F0, 0x7C0, 0x966, 0x9E6, 0xA66, 0xAE6, 0xB66, 0xBE6, 0xC66, 0xCE6, 0xD66, 0xDE6, 0xE50, 0xED0, 0xF20,
+        0x1040, 0x1090, 0x17E0, 0x1810, 0xFF10,
+    ];
+    let code = c as u32;
+    ZEROS.iter().any(|zero| (zero..&(zero + 10)).contains(&&code))
+}
+
+/// Whether a char attaches to the one before it as a grapheme extension or
+/// spacing mark, as combining marks do
+fn is_combining(c: char) -> bool {
+    if c.is_ascii() {
+        return false;
+    }
+    let mut buffer = [0u8; 8];
+    buffer[0] = b'a';
+    let len = 1 + c.encode_utf8(&mut buffer[1..]).len();
+    let pair = std::str::from_utf8(&buffer[..len]).expect("encoded chars are valid UTF-8");
+    pair.graphemes(true).nth(1).is_none()
+}
+
+/// Whether an open or close parenthesis is East Asian wide, which LB30 leaves breakable
+fn is_wide(c: char) -> bool {
+    matches!(c as u32, 0x1100..=0x115F | 0x2E80..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6)
+}
+
+/// Every position the text may or must break at, as the byte offset the next
+/// line would start at, in order; the end of the text is always a mandatory break
+pub fn line_break_opportunities(text: &str) -> Vec<(usize, LineBreak)> {
+    let mut breaks = Vec::new();
+    let mut chars = text.char_indices();
+    let Some((_, first)) = chars.next() else {
+        return breaks;
+    };
+
+    // The class rules look back to: the base char before (marks folded into
+    // it, LB9), the one before that, and the last one before any spaces
+    let resolve = |class: LineBreakClass| match class {
+        CombiningMark | ZeroWidthJoiner => Alphabetic,
+        class => class,
+    };
+    let mut previous = resolve(line_break_class(first));
+    let mut previous_char = first;
+    let mut before_previous: Option<LineBreakClass> = None;
+    let mut before_spaces = previous;
+    let mut after_zwj = first == '\u{200D}';
+    let mut regional_run = usize::from(previous == RegionalIndicator);
+
+    for (offset, c) in chars {
+        let class = line_break_class(c);
+        let spaces = previous == Space;
+        let decision = decide(previous, class, before_previous, before_spaces, spaces, after_zwj, regional_run, previous_char, c);
+        if let Some(decision) = decision {
+            breaks.push((offset, decision));
+        }
+
+        after_zwj = c == '\u{200D}';
+        // LB9: marks and joiners after a base take its class
+        if matches!(class, CombiningMark | ZeroWidthJoiner)
+            && !matches!(previous, MandatoryBreak | CarriageReturn | LineFeed | NextLine | Space | ZeroWidthSpace)
+        {
+            continue;
+        }
+        before_previous = Some(previous);
+        previous = resolve(class);
+        previous_char = c;
+        if previous != Space {
+            before_spaces = previous;
+        }
+        regional_run = if previous == RegionalIndicator { regional_run + 1 } else { 0 };
+    }
+    breaks.push((text.len(), LineBreak::Mandatory));
+    breaks
+}
+
+/// Whether a line may break between a char of class `before` and one of
+/// class `after`, by rules LB4–LB31
+#[allow(clippy::too_many_arguments)]
+fn decide(
+    before: LineBreakClass,
+    after: LineBreakClass,
+    before_previous: Option<LineBreakClass>,
+    before_spaces: LineBreakClass,
+    spaces: bool,
+    after_zwj: bool,
+    regional_run: usize,
+    before_char: char,
+    after_char: char,
+) -> Option<LineBreak> {
+    let allowed = Some(LineBreak::Allowed);
+    // LB4, LB5
+    match (before, after) {
+        (CarriageReturn, LineFeed) => return None,
+        (MandatoryBreak | CarriageReturn | LineFeed | NextLine, _) => return Some(LineBreak::Mandatory),
+        _ => {}
+    }
+    // LB6, LB7
+    if matches!(after, MandatoryBreak | CarriageReturn | LineFeed | NextLine | Space | ZeroWidthSpace) {
+        return None;
+    }
+    // LB8: ZW SP* ÷
+    if before_spaces == ZeroWidthSpace {
+        return allowed;
+    }
+    // LB8a
+    if after_zwj {
+        return None;
+    }
+    // LB9 (marks after a base); LB10 resolves the rest as letters
+    if matches!(after, CombiningMark | ZeroWidthJoiner) && before != Space {
+        return None;
+    }
+    let after = match after {
+        CombiningMark | ZeroWidthJoiner => Alphabetic,
+        after => after,
+    };
+    // LB11, LB12, LB12a
+    if before == WordJoiner || after == WordJoiner || before == Glue {
+        return None;
+    }
+    if after == Glue && !matches!(before, Space | BreakAfter | Hyphen) {
+        return None;
+    }
+    // LB13
+    if matches!(after, ClosePunctuation | CloseParenthesis | Exclamation | InfixSeparator | Symbol) {
+        return None;
+    }
+    // LB14–LB17: rules that see through spaces
+    let through = if spaces { before_spaces } else { before };
+    if through == OpenPunctuation
+        || (through == Quotation && after == OpenPunctuation)
+        || (matches!(through, ClosePunctuation | CloseParenthesis) && after == NonStarter)
+        || (through == BreakBoth && after == BreakBoth)
+    {
+        return None;
+    }
+    // LB18
+    if spaces {
+        return allowed;
+    }
+    // LB19, LB20
+    if before == Quotation || after == Quotation {
+        return None;
+    }
+    if before == Contingent || after == Contingent {
+        return allowed;
+    }
+    // LB21, LB21a, LB21b, LB22
+    if matches!(after, BreakAfter | Hyphen | NonStarter) || before == BreakBefore {
+        return None;
+    }
+    if matches!(before, Hyphen | BreakAfter) && before_previous == Some(HebrewLetter) {
+        return None;
+    }
+    if before == Symbol && after == HebrewLetter {
+        return None;
+    }
+    if after == Inseparable {
+        return None;
+    }
+    let letter = |class: LineBreakClass| matches!(class, Alphabetic | HebrewLetter);
+    let hangul = |class: LineBreakClass| matches!(class, HangulL | HangulV | HangulT | HangulLv | HangulLvt);
+    let no_break = match (before, after) {
+        // LB23, LB23a, LB24
+        (b, Numeric) if letter(b) => true,
+        (Numeric, a) if letter(a) => true,
+        (Prefix, Ideographic) | (Ideographic, Postfix) => true,
+        (Prefix | Postfix, a) if letter(a) => true,
+        (b, Prefix | Postfix) if letter(b) => true,
+        // LB25, as pairs
+        (ClosePunctuation | CloseParenthesis | Numeric, Postfix | Prefix) => true,
+        (Postfix | Prefix, OpenPunctuation | Numeric) => true,
+        (Hyphen | InfixSeparator | Numeric | Symbol | OpenPunctuation, Numeric) => true,
+        // LB26, LB27
+        (HangulL, HangulL | HangulV | HangulLv | HangulLvt) => true,
+        (HangulV | HangulLv, HangulV | HangulT) => true,
+        (HangulT | HangulLvt, HangulT) => true,
+        (b, Postfix) if hangul(b) => true,
+        (Prefix, a) if hangul(a) => true,
+        // LB28, LB29
+        (b, a) if letter(b) && letter(a) => true,
+        (InfixSeparator, a) if letter(a) => true,
+        // LB30
+        (b, OpenPunctuation) if (letter(b) || b == Numeric) && !is_wide(after_char) => true,
+        (CloseParenthesis, a) if (letter(a) || a == Numeric) && !is_wide(before_char) => true,
+        // LB30a: regional indicators pair up as flags
+        (RegionalIndicator, RegionalIndicator) => regional_run % 2 == 1,
+        _ => false,
+    };
+    // LB31
+    if no_break {
+        None
+    } else {
+        allowed
+    }
+}
+
+/// Offset of the grapheme boundary after `offset`, or the end of the text
+pub fn next_grapheme_boundary(text: &str, offset: usize) -> usize {
+    let offset = floor_char_boundary(text, offset);
+    GraphemeCursor::new(offset, text.len(), true)
+        .next_boundary(text, 0)
+        .ok()
+        .flatten()
+        .unwrap_or(text.len())
+}
+
+/// Offset of the grapheme boundary before `offset`, or 0
+pub fn previous_grapheme_boundary(text: &str, offset: usize) -> usize {
+    let offset = floor_char_boundary(text, offset);
+    GraphemeCursor::new(offset, text.len(), true)
+        .prev_boundary(text, 0)
+        .ok()
+        .flatten()
+        .unwrap_or(0)
+}
+
+/// Whether `offset` falls between two grapheme clusters
+pub fn is_grapheme_boundary(text: &str, offset: usize) -> bool {
+    text.is_char_boundary(offset) && GraphemeCursor::new(offset, text.len(), true).is_boundary(text, 0).unwrap_or(true)
+}
+
+/// Whether a UAX #29 word segment is a word rather than spaces or punctuation
+pub fn is_word(segment: &str) -> bool {
+    segment.chars().any(char::is_alphanumeric)
+}
+
+/// The UAX #29 word segment at `offset`: a word, a run of spaces or a
+/// punctuation mark. At the end of the text, the last segment.
+pub fn word_at(text: &str, offset: usize) -> Range<usize> {
+    let mut last = 0..0;
+    for (start, segment) in text.split_word_bound_indices() {
+        let range = start..start + segment.len();
+        if range.contains(&offset) {
+            return range;
+        }
+        last = range;
+    }
+    last
+}
+
+/// The word a double click at `offset` selects: the word segment there, or
+/// the word just before when the offset is right after one
+pub fn word_selection_at(text: &str, offset: usize) -> Range<usize> {
+    let range = word_at(text, offset);
+    if !is_word(&text[range.clone()]) && offset > 0 {
+        let before = word_at(text, floor_char_boundary(text, offset - 1));
+        if before.end == offset && is_word(&text[before.clone()]) {
+            return before;
+        }
+    }
+    range
+}
+
+/// Start of the next word after `offset`, or the end of the text
+pub fn next_word_start(text: &str, offset: usize) -> usize {
+    text.split_word_bound_indices()
+        .find(|(start, segment)| *start > offset && is_word(segment))
+        .map_or(text.len(), |(start, _)| start)
+}
+
+/// Start of the word before `offset`, or of the word `offset` is inside; 0 if none
+pub fn previous_word_start(text: &str, offset: usize) -> usize {
+    text.split_word_bound_indices()
+        .rev()
+        .find(|(start, segment)| *start < offset && is_word(segment))
+        .map_or(0, |(start, _)| start)
+}
+
+/// End of the word at or after `offset`, or the end of the text
+pub fn next_word_end(text: &str, offset: usize) -> usize {
+    text.split_word_bound_indices()
+        .map(|(start, segment)| (start + segment.len(), segment))
+        .find(|(end, segment)| *end > offset && is_word(segment))
+        .map_or(text.len(), |(end, _)| end)
+}
+
+/// The char boundary at or before `offset`
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text cut into lines at every break opportunity
+    fn segments(text: &str) -> Vec<&str> {
+        let mut start = 0;
+        line_break_opportunities(text)
+            .into_iter()
+            .map(|(end, _)| {
+                let segment = &text[start..end];
+                start = end;
+                segment
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_latin_breaks() {
+        assert_eq!(segments("Hello, world!"), vec!["Hello, ", "world!"]);
+        assert_eq!(segments("well-known (really) costs $4.50 or 10%."), vec![
+            "well-", "known ", "(really) ", "costs ", "$4.50 ", "or ", "10%."
+        ]);
+        // No break before a no-break space or inside a quoted word
+        assert_eq!(segments("a\u{A0}b \"quoted text\""), vec!["a\u{A0}b ", "\"quoted ", "text\""]);
+        // Combining marks stay with their letter
+        assert_eq!(segments("cafe\u{301} au lait"), vec!["cafe\u{301} ", "au ", "lait"]);
+        assert_eq!(segments("http://example.com/path"), vec!["http://", "example.com/", "path"]);
+    }
+
+    #[test]
+    fn test_mandatory_breaks() {
+        let breaks = line_break_opportunities("one\r\ntwo\u{2028}three");
+        assert_eq!(breaks, vec![
+            (5, LineBreak::Mandatory),
+            (11, LineBreak::Mandatory),
+            (16, LineBreak::Mandatory),
+        ]);
+        assert!(line_break_opportunities("").is_empty());
+    }
+
+    #[test]
+    fn test_cjk_and_kinsoku() {
+        // Ideographs break anywhere, but not before closing punctuation,
+        // small kana or the prolonged sound mark, nor after an opening bracket
+        assert_eq!(segments("日本語。"), vec!["日", "本", "語。"]);
+        assert_eq!(segments("「ちょっと」"), vec!["「ちょっ", "と」"]);
+        assert_eq!(segments("コーヒー"), vec!["コー", "ヒー"]);
+        assert_eq!(segments("中文abc"), vec!["中", "文", "abc"]);
+        // Hangul syllables break like ideographs; Korean spacing still breaks words
+        assert_eq!(segments("한국어 텍스트"), vec!["한", "국", "어 ", "텍", "스", "트"]);
+        // No dictionary, so no breaks inside Thai words
+        assert_eq!(segments("ภาษาไทย"), vec!["ภาษาไทย"]);
+    }
+
+    #[test]
+    fn test_graphemes() {
+        let text = "e\u{301}👍🏽x🇫🇷";
+        assert_eq!(next_grapheme_boundary(text, 0), 3);
+        assert_eq!(next_grapheme_boundary(text, 3), 11);
+        assert_eq!(previous_grapheme_boundary(text, 11), 3);
+        assert_eq!(previous_grapheme_boundary(text, text.len()), 12);
+        assert_eq!(next_grapheme_boundary(text, text.len()), text.len());
+        assert!(!is_grapheme_boundary(text, 1));
+        assert!(is_grapheme_boundary(text, 12));
+    }
+
+    #[test]
+    fn test_words() {
+        let text = "Don't stop, e-mail 3.14 now";
+        assert_eq!(&text[word_at(text, 2)], "Don't");
+        assert_eq!(&text[word_at(text, 5)], " ");
+        assert_eq!(&text[word_selection_at(text, 10)], "stop");
+        assert_eq!(&text[word_selection_at(text, 15)], "mail");
+        assert_eq!(&text[word_at(text, 20)], "3.14");
+        assert_eq!(next_word_start(text, 0), 6);
+        assert_eq!(next_word_start(text, 6), 12);
+        assert_eq!(previous_word_start(text, 12), 6);
+        assert_eq!(previous_word_start(text, 8), 6);
+        assert_eq!(next_word_end(text, 5), 10);
+        assert_eq!(next_word_start(text, 24), text.len());
+    }
+}
@@ -0,0 +1,344 @@
+//! # Snippets Module
+//!
+//! Reusable rich fragments with `{{variable}}` placeholders, for letter
+//! templates and clause libraries.
+//!
+//! Inserting a snippet replaces each placeholder with its variable's value in
+//! the formatting of the run the placeholder starts in. Placeholders without a
+//! value keep their name as text and become tab stops the UI steps through,
+//! in text order. `{{cursor}}` marks where the caret goes afterwards; without
+//! it the caret goes after the inserted text.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::document_model::Block;
+use crate::ooxml::{Paragraph, Run};
+use crate::paste::{paragraph_runs, paste_into_tree, running_paragraphs, PastePolicy};
+use crate::piece_tree::PieceTree;
+
+/// Placeholder name marking the caret position after insertion
+pub const CURSOR_PLACEHOLDER: &str = "cursor";
+
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{\s*([A-Za-z0-9_.-]+)\s*\}\}").unwrap());
+
+/// Snippet errors
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum SnippetError {
+    #[error("Snippet '{0}' does not exist")]
+    NotFound(String),
+
+    #[error("Snippet name must not be empty")]
+    EmptyName,
+
+    #[error("JSON error: {0}")]
+    Json(String),
+}
+
+/// A named fragment with placeholders in its text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snippet {
+    pub name: String,
+    /// Paragraphs and tables, as in a document model body
+    pub body: Vec<Block>,
+}
+
+impl Snippet {
+    /// Names of the placeholders in text order, each once, `cursor` left out
+    pub fn variables(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for paragraph in running_paragraphs(&self.body) {
+            let text: String = paragraph_runs(&paragraph).iter().map(|run| run.text.as_str()).collect();
+            for cap in PLACEHOLDER.captures_iter(&text) {
+                if &cap[1] != CURSOR_PLACEHOLDER && !names.iter().any(|name| name == &cap[1]) {
+                    names.push(cap[1].to_string());
+                }
+            }
+        }
+        names
+    }
+}
+
+/// A placeholder left for the user to fill in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TabStop {
+    pub name: String,
+    /// Chars of the placeholder text in the document
+    pub range: Range<usize>,
+}
+
+/// Where an inserted snippet's text, tab stops and caret ended up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SnippetInsertion {
+    /// Chars of the inserted text
+    pub range: Range<usize>,
+    pub tab_stops: Vec<TabStop>,
+    /// Char offset for the caret
+    pub cursor: usize,
+}
+
+/// Snippets by name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnippetLibrary {
+    snippets: BTreeMap<String, Snippet>,
+}
+
+impl SnippetLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a snippet, replacing one of the same name
+    pub fn add(&mut self, name: &str, body: Vec<Block>) -> Result<&Snippet, SnippetError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(SnippetError::EmptyName);
+        }
+        let snippet = Snippet {
+            name: name.to_string(),
+            body,
+        };
+        self.snippets.insert(name.to_string(), snippet);
+        Ok(&self.snippets[name])
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Snippet> {
+        self.snippets.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Snippet> {
+        self.snippets.get(name)
+    }
+
+    /// Snippets in name order
+    pub fn snippets(&self) -> impl Iterator<Item = &Snippet> {
+        self.snippets.values()
+    }
+
+    pub fn to_json(&self) -> Result<String, SnippetError> {
+        serde_json::to_string(self).map_err(|e| SnippetError::Json(e.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SnippetError> {
+        serde_json::from_str(json).map_err(|e| SnippetError::Json(e.to_string()))
+    }
+
+    /// Insert snippet `name` into the tree at char `offset` as one undo step
+    pub fn insert(
+        &self,
+        tree: &mut PieceTree,
+        offset: usize,
+        name: &str,
+        variables: &HashMap<String, String>,
+    ) -> Result<SnippetInsertion, SnippetError> {
+        let snippet = self.get(name).ok_or_else(|| SnippetError::NotFound(name.to_string()))?;
+        let offset = offset.min(tree.char_count());
+        let expansion = expand(&snippet.body, variables);
+
+        let length = tree.char_count();
+        let blocks: Vec<Block> = expansion.paragraphs.into_iter().map(Block::from).collect();
+        paste_into_tree(tree, offset, &blocks, PastePolicy::Merge);
+        let end = offset + (tree.char_count() - length);
+
+        Ok(SnippetInsertion {
+            range: offset..end,
+            tab_stops: expansion
+                .tab_stops
+                .into_iter()
+                .map(|stop| TabStop {
+                    name: stop.name,
+                    range: offset + stop.range.start..offset + stop.range.end,
+                })
+                .collect(),
+            cursor: expansion.cursor.map_or(end, |cursor| offset + cursor),
+        })
+    }
+}
+
+/// A snippet body with its placeholders replaced
+struct Expansion {
+    /// Running paragraphs, tables turned into tab-separated rows
+    paragraphs: Vec<Paragraph>,
+    /// Offsets into the paragraphs joined with "\n"
+    tab_stops: Vec<TabStop>,
+    cursor: Option<usize>,
+}
+
+/// Replace the placeholders of `body` with their values
+fn expand(body: &[Block], variables: &HashMap<String, String>) -> Expansion {
+    let mut expansion = Expansion {
+        paragraphs: Vec::new(),
+        tab_stops: Vec::new(),
+        cursor: None,
+    };
+    let mut paragraph_start = 0;
+
+    for paragraph in running_paragraphs(body) {
+        let runs = paragraph_runs(&paragraph);
+        let text: String = runs.iter().map(|run| run.text.as_str()).collect();
+        let mut expanded: Vec<Run> = Vec::new();
+        let mut length = 0;
+        let mut copied = 0;
+
+        for cap in PLACEHOLDER.captures_iter(&text) {
+            let placeholder = cap.get(0).expect("group 0 is the whole match");
+            length += copy_runs(&runs, copied..placeholder.start(), &mut expanded);
+            copied = placeholder.end();
+
+            let name = &cap[1];
+            if name == CURSOR_PLACEHOLDER {
+                expansion.cursor = Some(paragraph_start + length);
+                continue;
+            }
+            let value = match variables.get(name) {
+                Some(value) => value.clone(),
+                None => {
+                    let start = paragraph_start + length;
+                    expansion.tab_stops.push(TabStop {
+                        name: name.to_string(),
+                        range: start..start + name.chars().count(),
+                    });
+                    name.to_string()
+                }
+            };
+            length += value.chars().count();
+            // The value takes the formatting of the run the placeholder starts in
+            let properties = run_at(&runs, placeholder.start()).map(|run| run.properties.clone()).unwrap_or_default();
+            if !value.is_empty() {
+                expanded.push(Run { text: value, properties });
+            }
+        }
+        length += copy_runs(&runs, copied..text.len(), &mut expanded);
+
+        paragraph_start += length + 1;
+        expansion.paragraphs.push(Paragraph {
+            text: expanded.iter().map(|run| run.text.as_str()).collect(),
+            runs: expanded,
+            ..paragraph
+        });
+    }
+    expansion
+}
+
+/// Copy bytes `range` of the runs' joined text, split by run; returns the chars copied
+fn copy_runs(runs: &[Run], range: Range<usize>, into: &mut Vec<Run>) -> usize {
+    let mut copied = 0;
+    let mut run_start = 0;
+    for run in runs {
+        let run_end = run_start + run.text.len();
+        let start = range.start.max(run_start);
+        let end = range.end.min(run_end);
+        if start < end {
+            let text = &run.text[start - run_start..end - run_start];
+            copied += text.chars().count();
+            into.push(Run {
+                text: text.to_string(),
+                properties: run.properties.clone(),
+            });
+        }
+        run_start = run_end;
+    }
+    copied
+}
+
+/// The run holding byte `offset` of the runs' joined text
+fn run_at(runs: &[Run], offset: usize) -> Option<&Run> {
+    let mut run_start = 0;
+    runs.iter().find(|run| {
+        run_start += run.text.len();
+        offset < run_start
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ooxml::RunProperties;
+
+    fn paragraph(runs: &[(&str, bool)]) -> Block {
+        Block::from(Paragraph {
+            text: runs.iter().map(|(text, _)| *text).collect(),
+            runs: runs
+                .iter()
+                .map(|(text, bold)| Run {
+                    text: text.to_string(),
+                    properties: RunProperties {
+                        bold: bold.then_some(true),
+                        ..Default::default()
+                    },
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_substitution_keeps_formatting() {
+        // The placeholder starts in a bold run and ends in a plain one
+        let body = vec![paragraph(&[("Dear {{ti", true), ("tle}} {{name}},", false)])];
+        let expansion = expand(&body, &vars(&[("title", "Dr."), ("name", "Ada Lovelace")]));
+
+        let runs: Vec<(&str, Option<bool>)> = expansion.paragraphs[0]
+            .runs
+            .iter()
+            .map(|run| (run.text.as_str(), run.properties.bold))
+            .collect();
+        assert_eq!(runs, vec![("Dear ", Some(true)), ("Dr.", Some(true)), (" ", None), ("Ada Lovelace", None), (",", None)]);
+        assert_eq!(expansion.paragraphs[0].text, "Dear Dr. Ada Lovelace,");
+        assert!(expansion.tab_stops.is_empty());
+    }
+
+    #[test]
+    fn test_tab_stops_and_cursor() {
+        let mut library = SnippetLibrary::new();
+        library
+            .add(
+                "clause",
+                vec![
+                    paragraph(&[("Between {{party}} and {{ counterparty }}.", false)]),
+                    paragraph(&[("Signed: {{cursor}}", false)]),
+                ],
+            )
+            .unwrap();
+        assert_eq!(library.get("clause").unwrap().variables(), ["party", "counterparty"]);
+
+        let mut tree = PieceTree::new("Intro\n".to_string());
+        let insertion = library
+            .insert(&mut tree, 6, "clause", &vars(&[("party", "Acme")]))
+            .unwrap();
+        assert_eq!(tree.get_text(), "Intro\nBetween Acme and counterparty.\nSigned: ");
+
+        let stop = &insertion.tab_stops[0];
+        assert_eq!(insertion.tab_stops.len(), 1);
+        assert_eq!(stop.name, "counterparty");
+        let text: Vec<char> = tree.get_text().chars().collect();
+        assert_eq!(text[stop.range.clone()].iter().collect::<String>(), "counterparty");
+        assert_eq!(insertion.range, 6..text.len());
+        assert_eq!(insertion.cursor, text.len());
+
+        assert_eq!(
+            library.insert(&mut tree, 0, "missing", &HashMap::new()),
+            Err(SnippetError::NotFound("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_library_json_round_trip() {
+        let mut library = SnippetLibrary::new();
+        assert_eq!(library.add("  ", Vec::new()).unwrap_err(), SnippetError::EmptyName);
+        library.add("sign-off", vec![paragraph(&[("Regards, {{name}}", true)])]).unwrap();
+
+        let loaded = SnippetLibrary::from_json(&library.to_json().unwrap()).unwrap();
+        let names: Vec<&str> = loaded.snippets().map(|snippet| snippet.name.as_str()).collect();
+        assert_eq!(names, ["sign-off"]);
+        assert_eq!(loaded.get("sign-off").unwrap().variables(), ["name"]);
+    }
+}
@@ -0,0 +1,458 @@
+//! # Style Sheet Module
+//!
+//! Named styles and their resolution to concrete formatting.
+//!
+//! Paragraphs refer to their paragraph style by ID and keep only direct
+//! formatting of their own, so restyling "Heading 1" changes every heading.
+//! The style sheet resolves that reference when formatting is needed, e.g. at
+//! layout time:
+//!
+//! 1. A style's own formatting is completed from the style it is based on, and
+//!    so on up the chain. Styles are only based on styles of their own kind; a
+//!    parent of another kind, a missing parent or a cycle ends the chain.
+//! 2. A paragraph without a style uses the default paragraph style.
+//! 3. Direct formatting overrides whatever the style gives.
+//!
+//! Runs carry no style reference, so applying a character style gives the
+//! text the direct formatting the style resolves to.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::document_model::{paragraph_attributes, paragraph_properties, run_properties, text_attributes};
+use crate::line_breaking::BreakStrategy;
+use crate::line_layout::ParagraphProperties;
+use crate::ooxml::Style;
+use crate::piece_tree::{ParagraphAttributes, PieceTree, TextAttributes};
+
+/// Errors applying a style
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum StyleSheetError {
+    #[error("Unknown style '{0}'")]
+    UnknownStyle(String),
+
+    #[error("Style '{id}' is a {kind:?} style")]
+    WrongKind { id: String, kind: StyleKind },
+}
+
+/// What a style formats, as in w:style/@w:type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StyleKind {
+    Paragraph,
+    Character,
+    Table,
+    /// List styles
+    Numbering,
+}
+
+impl StyleKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "paragraph" => Some(StyleKind::Paragraph),
+            "character" => Some(StyleKind::Character),
+            "table" => Some(StyleKind::Table),
+            "numbering" | "list" => Some(StyleKind::Numbering),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            StyleKind::Paragraph => "paragraph",
+            StyleKind::Character => "character",
+            StyleKind::Table => "table",
+            StyleKind::Numbering => "numbering",
+        }
+    }
+}
+
+/// A style definition with only the formatting it sets itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedStyle {
+    pub id: String,
+    /// Display name, e.g. "heading 1"
+    pub name: String,
+    pub kind: StyleKind,
+    pub based_on: Option<String>,
+    /// Style of the paragraph Enter starts at the end of one of this style;
+    /// None, like this style's own ID, continues this style
+    #[serde(default)]
+    pub next: Option<String>,
+    /// Paragraph formatting; unused by character styles
+    #[serde(default)]
+    pub paragraph: ParagraphAttributes,
+    #[serde(default)]
+    pub run: TextAttributes,
+    /// Whether this is the default style of its kind
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+impl NamedStyle {
+    pub fn new(id: impl Into<String>, kind: StyleKind) -> Self {
+        let id = id.into();
+        NamedStyle {
+            name: id.clone(),
+            id,
+            kind,
+            based_on: None,
+            next: None,
+            paragraph: ParagraphAttributes::default(),
+            run: TextAttributes::default(),
+            is_default: false,
+        }
+    }
+
+    /// Convert a parsed OOXML style; None for an unknown style type
+    pub fn from_ooxml(style: &Style) -> Option<Self> {
+        let mut paragraph = paragraph_attributes(&style.paragraph_properties).unwrap_or_default();
+        // A paragraph style does not refer to a paragraph style
+        paragraph.style_id = None;
+        Some(NamedStyle {
+            id: style.id.clone(),
+            name: style.name.clone().unwrap_or_else(|| style.id.clone()),
+            kind: StyleKind::from_name(&style.style_type)?,
+            based_on: style.based_on.clone(),
+            next: style.next.clone(),
+            paragraph,
+            run: text_attributes(&style.run_properties).unwrap_or_default(),
+            is_default: style.is_default,
+        })
+    }
+
+    pub fn to_ooxml(&self) -> Style {
+        Style {
+            id: self.id.clone(),
+            name: Some(self.name.clone()),
+            style_type: self.kind.name().to_string(),
+            based_on: self.based_on.clone(),
+            next: self.next.clone(),
+            paragraph_properties: paragraph_properties(&self.paragraph),
+            run_properties: run_properties(&self.run),
+            is_default: self.is_default,
+        }
+    }
+}
+
+/// Paragraph and run formatting a style resolves to
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedStyle {
+    pub paragraph: ParagraphAttributes,
+    pub run: TextAttributes,
+}
+
+/// The named styles of a document
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StyleSheet {
+    /// By ID
+    styles: BTreeMap<String, NamedStyle>,
+}
+
+impl StyleSheet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Style sheet of a parsed document's or document model's styles
+    pub fn from_ooxml_styles(styles: &HashMap<String, Style>) -> Self {
+        let mut sheet = StyleSheet::new();
+        for style in styles.values().filter_map(NamedStyle::from_ooxml) {
+            sheet.add(style);
+        }
+        sheet
+    }
+
+    /// The styles as OOXML styles indexed by ID, e.g. for a document model
+    pub fn to_ooxml_styles(&self) -> HashMap<String, Style> {
+        self.styles.iter().map(|(id, style)| (id.clone(), style.to_ooxml())).collect()
+    }
+
+    /// Add a style, replacing any with the same ID
+    ///
+    /// A new default style stops the previous default of its kind being one.
+    pub fn add(&mut self, style: NamedStyle) {
+        if style.is_default {
+            for other in self.styles.values_mut().filter(|other| other.kind == style.kind) {
+                other.is_default = false;
+            }
+        }
+        self.styles.insert(style.id.clone(), style);
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<NamedStyle> {
+        self.styles.remove(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&NamedStyle> {
+        self.styles.get(id)
+    }
+
+    /// Find a style by ID, or else by name ignoring case, so "Heading 1" finds Heading1
+    pub fn find(&self, id_or_name: &str) -> Option<&NamedStyle> {
+        self.styles.get(id_or_name).or_else(|| {
+            self.styles
+                .values()
+                .find(|style| style.name.eq_ignore_ascii_case(id_or_name))
+        })
+    }
+
+    /// Styles in ID order
+    pub fn styles(&self) -> impl Iterator<Item = &NamedStyle> {
+        self.styles.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.styles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.styles.is_empty()
+    }
+
+    /// The default style of a kind, e.g. Normal for paragraphs
+    pub fn default_style(&self, kind: StyleKind) -> Option<&NamedStyle> {
+        self.styles.values().find(|style| style.kind == kind && style.is_default)
+    }
+
+    /// Style `id` followed by the styles it is based on, nearest first
+    pub fn inheritance_chain(&self, id: &str) -> Vec<&NamedStyle> {
+        let mut chain: Vec<&NamedStyle> = Vec::new();
+        let mut next = self.styles.get(id);
+        while let Some(style) = next {
+            chain.push(style);
+            next = style
+                .based_on
+                .as_deref()
+                .and_then(|parent| self.styles.get(parent))
+                .filter(|parent| parent.kind == style.kind && !chain.iter().any(|seen| seen.id == parent.id));
+        }
+        chain
+    }
+
+    /// Formatting style `id` gives, with everything it inherits
+    pub fn resolve(&self, id: &str) -> ResolvedStyle {
+        self.inheritance_chain(id)
+            .iter()
+            .rev()
+            .fold(ResolvedStyle::default(), |resolved, style| ResolvedStyle {
+                paragraph: resolved.paragraph.merged_with(&style.paragraph),
+                run: resolved.run.merged_with(&style.run),
+            })
+    }
+
+    /// Style a paragraph with these direct attributes resolves to
+    fn paragraph_style(&self, direct: Option<&ParagraphAttributes>) -> ResolvedStyle {
+        let style_id = direct
+            .and_then(|attributes| attributes.style_id.as_deref())
+            .or_else(|| Some(self.default_style(StyleKind::Paragraph)?.id.as_str()));
+        style_id.map(|id| self.resolve(id)).unwrap_or_default()
+    }
+
+    /// Formatting of a paragraph with these direct attributes
+    pub fn effective_paragraph(&self, direct: Option<&ParagraphAttributes>) -> ParagraphAttributes {
+        let resolved = self.paragraph_style(direct).paragraph;
+        match direct {
+            Some(direct) => resolved.merged_with(direct),
+            None => resolved,
+        }
+    }
+
+    /// Formatting of a run with these direct attributes in a paragraph with these
+    pub fn effective_run(&self, paragraph: Option<&ParagraphAttributes>, direct: Option<&TextAttributes>) -> TextAttributes {
+        let default_run = self
+            .default_style(StyleKind::Character)
+            .map(|style| self.resolve(&style.id).run)
+            .unwrap_or_default();
+        let resolved = default_run.merged_with(&self.paragraph_style(paragraph).run);
+        match direct {
+            Some(direct) => resolved.merged_with(direct),
+            None => resolved,
+        }
+    }
+
+    /// Effective formatting of the run at char `offset` of `tree`
+    pub fn run_attributes_at(&self, tree: &PieceTree, offset: usize) -> TextAttributes {
+        let paragraph = tree.paragraph_attributes_at(offset);
+        self.effective_run(paragraph.as_ref(), tree.get_attributes_at(offset).as_ref())
+    }
+
+    /// Layout properties of every paragraph of `tree`, in order
+    pub fn paragraph_layout_properties(&self, tree: &PieceTree) -> Vec<ParagraphProperties> {
+        (0..tree.paragraph_count())
+            .map(|index| self.effective_paragraph(tree.paragraph_attributes(index)).layout_properties())
+            .collect()
+    }
+
+    /// Give the paragraphs `range` touches a paragraph style, as one undo step
+    ///
+    /// Direct paragraph formatting is kept. Returns false if nothing changed.
+    pub fn apply_paragraph_style(&self, tree: &mut PieceTree, range: Range<usize>, style: &str) -> Result<bool, StyleSheetError> {
+        let style = self.find_of_kind(style, StyleKind::Paragraph)?;
+        let attributes = ParagraphAttributes {
+            style_id: Some(style.id.clone()),
+            ..Default::default()
+        };
+        Ok(tree.apply_paragraph_attributes(range, &attributes))
+    }
+
+    /// Give the chars in `range` the formatting of a character style, as one undo step
+    pub fn apply_character_style(&self, tree: &mut PieceTree, range: Range<usize>, style: &str) -> Result<bool, StyleSheetError> {
+        let style = self.find_of_kind(style, StyleKind::Character)?;
+        Ok(tree.apply_attributes(range, &self.resolve(&style.id).run))
+    }
+
+    /// Choose how paragraphs of a paragraph style break their lines; None inherits it
+    pub fn set_break_strategy(&mut self, style: &str, strategy: Option<BreakStrategy>) -> Result<(), StyleSheetError> {
+        let id = self.find_of_kind(style, StyleKind::Paragraph)?.id.clone();
+        if let Some(style) = self.styles.get_mut(&id) {
+            style.paragraph.break_strategy = strategy;
+        }
+        Ok(())
+    }
+
+    fn find_of_kind(&self, id_or_name: &str, kind: StyleKind) -> Result<&NamedStyle, StyleSheetError> {
+        let style = self
+            .find(id_or_name)
+            .ok_or_else(|| StyleSheetError::UnknownStyle(id_or_name.to_string()))?;
+        if style.kind != kind {
+            return Err(StyleSheetError::WrongKind {
+                id: style.id.clone(),
+                kind: style.kind,
+            });
+        }
+        Ok(style)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_layout::Alignment;
+
+    fn style(id: &str, name: &str, kind: StyleKind, based_on: Option<&str>) -> NamedStyle {
+        NamedStyle {
+            name: name.to_string(),
+            based_on: based_on.map(str::to_string),
+            ..NamedStyle::new(id, kind)
+        }
+    }
+
+    fn sheet() -> StyleSheet {
+        let mut sheet = StyleSheet::new();
+        let mut normal = style("Normal", "Normal", StyleKind::Paragraph, None);
+        normal.is_default = true;
+        normal.paragraph.space_after = Some(160);
+        normal.run.font_family = Some("Calibri".to_string());
+        normal.run.font_size = Some(11);
+        sheet.add(normal);
+
+        let mut heading = style("Heading1", "heading 1", StyleKind::Paragraph, Some("Normal"));
+        heading.paragraph.space_before = Some(240);
+        heading.run.font_size = Some(16);
+        heading.run.bold = Some(true);
+        sheet.add(heading);
+
+        let mut title = style("Title", "Title", StyleKind::Paragraph, Some("Heading1"));
+        title.paragraph.alignment = Some(Alignment::Center);
+        sheet.add(title);
+
+        let mut emphasis = style("Emphasis", "Emphasis", StyleKind::Character, Some("Normal"));
+        emphasis.run.italic = Some(true);
+        sheet.add(emphasis);
+        sheet
+    }
+
+    #[test]
+    fn test_inheritance_resolution() {
+        let mut sheet = sheet();
+        let resolved = sheet.resolve("Title");
+        assert_eq!(resolved.paragraph.alignment, Some(Alignment::Center));
+        assert_eq!(resolved.paragraph.space_before, Some(240));
+        assert_eq!(resolved.paragraph.space_after, Some(160));
+        assert_eq!(resolved.run.font_size, Some(16));
+        assert_eq!(resolved.run.font_family.as_deref(), Some("Calibri"));
+
+        // Only styles of the same kind are inherited from
+        assert_eq!(sheet.resolve("Emphasis").run.font_family, None);
+
+        // A cycle ends where it repeats
+        sheet.add(style("Normal", "Normal", StyleKind::Paragraph, Some("Title")));
+        let chain: Vec<&str> = sheet.inheritance_chain("Heading1").iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(chain, ["Heading1", "Normal", "Title"]);
+    }
+
+    #[test]
+    fn test_direct_formatting_overrides_style() {
+        let sheet = sheet();
+        assert_eq!(sheet.find("Heading 1").map(|s| s.id.as_str()), Some("Heading1"));
+
+        let direct = ParagraphAttributes {
+            style_id: Some("Heading1".to_string()),
+            space_before: Some(0),
+            ..Default::default()
+        };
+        let paragraph = sheet.effective_paragraph(Some(&direct));
+        assert_eq!((paragraph.space_before, paragraph.space_after), (Some(0), Some(160)));
+
+        let run_direct = TextAttributes {
+            font_size: Some(20),
+            ..Default::default()
+        };
+        let run = sheet.effective_run(Some(&direct), Some(&run_direct));
+        assert_eq!((run.font_size, run.bold), (Some(20), Some(true)));
+        // Unstyled paragraphs use the default paragraph style
+        assert_eq!(sheet.effective_run(None, None).font_size, Some(11));
+    }
+
+    #[test]
+    fn test_apply_styles_to_tree() {
+        let sheet = sheet();
+        let mut tree = PieceTree::new("Intro\nBody text".to_string());
+        assert_eq!(sheet.apply_paragraph_style(&mut tree, 0..2, "Heading 1"), Ok(true));
+        assert_eq!(
+            sheet.apply_paragraph_style(&mut tree, 0..2, "Emphasis"),
+            Err(StyleSheetError::WrongKind {
+                id: "Emphasis".to_string(),
+                kind: StyleKind::Character
+            })
+        );
+        assert!(sheet.apply_character_style(&mut tree, 6..10, "Emphasis").unwrap());
+
+        let layout = sheet.paragraph_layout_properties(&tree);
+        assert_eq!(layout.len(), 2);
+        assert_eq!((layout[0].space_before, layout[1].space_before), (240.0, 0.0));
+        assert_eq!(sheet.run_attributes_at(&tree, 1).bold, Some(true));
+        let body = sheet.run_attributes_at(&tree, 7);
+        assert_eq!((body.italic, body.font_size), (Some(true), Some(11)));
+
+        // Restyling is seen at the next layout without touching the text
+        let mut restyled = sheet.clone();
+        let mut heading = restyled.get("Heading1").unwrap().clone();
+        heading.paragraph.space_before = Some(480);
+        restyled.add(heading);
+        assert_eq!(restyled.paragraph_layout_properties(&tree)[0].space_before, 480.0);
+    }
+
+    #[test]
+    fn test_style_break_strategy() {
+        let mut sheet = sheet();
+        sheet.set_break_strategy("heading 1", Some(BreakStrategy::TotalFit)).unwrap();
+        assert_eq!(
+            sheet.set_break_strategy("Emphasis", None),
+            Err(StyleSheetError::WrongKind {
+                id: "Emphasis".to_string(),
+                kind: StyleKind::Character
+            })
+        );
+
+        // Title inherits the choice from Heading1; Normal leaves it to the layout
+        let mut tree = PieceTree::new("Title\nBody".to_string());
+        sheet.apply_paragraph_style(&mut tree, 0..1, "Title").unwrap();
+        let layout = sheet.paragraph_layout_properties(&tree);
+        assert_eq!(layout[0].break_strategy, Some(BreakStrategy::TotalFit));
+        assert_eq!(layout[1].break_strategy, None);
+    }
+}
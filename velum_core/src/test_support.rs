@@ -0,0 +1,47 @@
+//! Fixtures shared by unit tests
+
+use std::io::{Cursor, Write};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// A zip archive holding the given entries, in order
+pub(crate) fn zip_fixture<N: AsRef<str>, D: AsRef<[u8]>>(entries: impl IntoIterator<Item = (N, D)>) -> Vec<u8> {
+    zip_entries(entries, FileOptions::default())
+}
+
+/// A .docx whose body holds the WordprocessingML `body`, with `extra_parts`
+/// as further entries by name, every entry written with `compression`
+///
+/// XML and PNG parts have a content type without an override of their own.
+pub(crate) fn minimal_docx(body: &str, extra_parts: &[(&str, &str)], compression: CompressionMethod) -> Vec<u8> {
+    let document = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>{}</w:body></w:document>"#,
+        body
+    );
+    let parts = [
+        (
+            "[Content_Types].xml",
+            r#"<Types><Default Extension="xml" ContentType="application/xml"/><Default Extension="png" ContentType="image/png"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/></Types>"#,
+        ),
+        (
+            "_rels/.rels",
+            r#"<Relationships><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/></Relationships>"#,
+        ),
+        ("word/document.xml", document.as_str()),
+    ];
+    zip_entries(parts.into_iter().chain(extra_parts.iter().copied()), FileOptions::default().compression_method(compression))
+}
+
+fn zip_entries<N: AsRef<str>, D: AsRef<[u8]>>(entries: impl IntoIterator<Item = (N, D)>, options: FileOptions) -> Vec<u8> {
+    let mut buffer = Cursor::new(Vec::new());
+    {
+        let mut zip = ZipWriter::new(&mut buffer);
+        for (name, data) in entries {
+            zip.start_file(name.as_ref(), options).unwrap();
+            zip.write_all(data.as_ref()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+    buffer.into_inner()
+}
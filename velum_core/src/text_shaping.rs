@@ -1,8 +1,11 @@
-use harfbuzz_rs::{Face, Font, Owned, UnicodeBuffer, shape};
+use harfbuzz_rs::{Face, Feature, Font, Language, Owned, Script, Tag, UnicodeBuffer, shape};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
 use std::path::Path;
+use std::str::FromStr;
 
 /// Represents a shaped glyph with positioning information
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct GlyphInfo {
     /// The glyph ID in the font
     pub codepoint: u32,
@@ -18,6 +21,164 @@ pub struct GlyphInfo {
     pub y_offset: f32,
 }
 
+/// An OpenType feature turned on or off for a whole run, e.g. "liga" or "smcp"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FontFeature {
+    /// Four-letter feature tag
+    pub tag: String,
+    /// 0 turns the feature off, 1 on; alternates take higher values
+    pub value: u32,
+}
+
+impl FontFeature {
+    pub fn new(tag: &str, value: u32) -> Self {
+        FontFeature { tag: tag.to_string(), value }
+    }
+
+    /// Parses "liga", "+kern", "-liga" or "salt=2", as CSS font-feature-settings
+    /// and hb-shape write them
+    pub fn parse(setting: &str) -> Option<Self> {
+        let setting = setting.trim();
+        let (tag, value) = match setting.split_once('=') {
+            Some((tag, value)) => (tag.trim(), value.trim().parse().ok()?),
+            None => match setting.strip_prefix('-') {
+                Some(tag) => (tag, 0),
+                None => (setting.strip_prefix('+').unwrap_or(setting), 1),
+            },
+        };
+        (tag.len() == 4 && tag.is_ascii()).then(|| FontFeature::new(tag, value))
+    }
+}
+
+/// What shaping needs to know besides the text and font
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShapingOptions {
+    /// BCP 47 language, for language-specific forms such as Serbian italics
+    pub language: Option<String>,
+    /// Features on top of those the script turns on by default
+    pub features: Vec<FontFeature>,
+}
+
+/// A stretch of text in one script, shaped on its own
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptRun {
+    /// Byte range in the text
+    pub range: Range<usize>,
+    /// ISO 15924 script tag, e.g. "Latn", "Arab" or "Deva"; "Zyyy" for text
+    /// with no script of its own, such as digits and punctuation alone
+    pub script: String,
+    pub rtl: bool,
+}
+
+/// Text shaped into glyphs, with how they map back to its chars
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShapedText {
+    /// Glyphs run by run in logical order; within a right-to-left run they
+    /// are in visual order, as HarfBuzz returns them. Clusters are char indices
+    pub glyphs: Vec<GlyphInfo>,
+    /// Total advance in logical pixels
+    pub width: f32,
+    /// Advance of each char: the advance of its cluster shared evenly among
+    /// the cluster's chars, so a ligature or conjunct can still be measured
+    /// and broken at its chars
+    pub char_advances: Vec<f32>,
+    /// Whether a cluster starts at each char: the places text can be broken
+    /// and a caret put without splitting a ligature, conjunct or mark
+    pub cluster_starts: Vec<bool>,
+    pub runs: Vec<ScriptRun>,
+}
+
+impl ShapedText {
+    /// Offset of each char from the start of the text, and the total width last
+    pub fn char_positions(&self) -> Vec<f32> {
+        let mut positions = Vec::with_capacity(self.char_advances.len() + 1);
+        let mut x = 0.0;
+        positions.push(x);
+        for advance in &self.char_advances {
+            x += advance;
+            positions.push(x);
+        }
+        positions
+    }
+
+    /// Width of a range of chars
+    pub fn width_of(&self, chars: Range<usize>) -> f32 {
+        let end = chars.end.min(self.char_advances.len());
+        self.char_advances[chars.start.min(end)..end].iter().sum()
+    }
+}
+
+/// Script of a char by Unicode block, as an ISO 15924 tag; None for chars
+/// that take the script of the text around them
+fn char_script(c: char) -> Option<&'static str> {
+    let script = match c as u32 {
+        0x41..=0x5A | 0x61..=0x7A | 0xC0..=0xD6 | 0xD8..=0xF6 | 0xF8..=0x24F | 0x1E00..=0x1EFF => "Latn",
+        0x370..=0x3FF | 0x1F00..=0x1FFF => "Grek",
+        0x400..=0x52F => "Cyrl",
+        0x531..=0x58F => "Armn",
+        0x591..=0x5FF | 0xFB1D..=0xFB4F => "Hebr",
+        0x600..=0x64A | 0x66E..=0x6FF | 0x750..=0x77F | 0x8A0..=0x8FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => "Arab",
+        0x700..=0x74F => "Syrc",
+        0x780..=0x7BF => "Thaa",
+        0x900..=0x97F | 0xA8E0..=0xA8FF => "Deva",
+        0x980..=0x9FF => "Beng",
+        0xA00..=0xA7F => "Guru",
+        0xA80..=0xAFF => "Gujr",
+        0xB00..=0xB7F => "Orya",
+        0xB80..=0xBFF => "Taml",
+        0xC00..=0xC7F => "Telu",
+        0xC80..=0xCFF => "Knda",
+        0xD00..=0xD7F => "Mlym",
+        0xD80..=0xDFF => "Sinh",
+        0xE00..=0xE7F => "Thai",
+        0xE80..=0xEFF => "Laoo",
+        0xF00..=0xFFF => "Tibt",
+        0x1000..=0x109F => "Mymr",
+        0x10A0..=0x10FF => "Geor",
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => "Hang",
+        0x1200..=0x139F => "Ethi",
+        0x1780..=0x17FF => "Khmr",
+        0x3040..=0x309F => "Hira",
+        0x30A0..=0x30FF | 0x31F0..=0x31FF => "Kana",
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FFFF => "Hani",
+        _ => return None,
+    };
+    Some(script)
+}
+
+/// Split text into runs of one script each. Chars without a script of their
+/// own (spaces, digits, punctuation, combining marks) join the run before
+/// them, or the first run at the start of the text
+pub fn script_runs(text: &str) -> Vec<ScriptRun> {
+    let mut runs: Vec<ScriptRun> = Vec::new();
+    let mut current: Option<&'static str> = None;
+    let mut start = 0;
+    for (byte, c) in text.char_indices() {
+        let Some(script) = char_script(c) else {
+            continue;
+        };
+        match current {
+            None => current = Some(script),
+            Some(open) if open != script => {
+                runs.push(script_run(start..byte, open));
+                start = byte;
+                current = Some(script);
+            }
+            Some(_) => {}
+        }
+    }
+    if start < text.len() || runs.is_empty() {
+        runs.push(script_run(start..text.len(), current.unwrap_or("Zyyy")));
+    }
+    runs
+}
+
+fn script_run(range: Range<usize>, script: &str) -> ScriptRun {
+    let rtl = matches!(script, "Arab" | "Hebr" | "Syrc" | "Thaa");
+    ScriptRun { range, script: script.to_string(), rtl }
+}
+
 /// A text shaper that uses HarfBuzz
 #[derive(Debug)]
 pub struct TextShaper<'a> {
@@ -42,6 +203,12 @@ impl<'a> TextShaper<'a> {
         })
     }
 
+    /// Creates a shaper that estimates widths without a font, so text measures
+    /// the same on every machine
+    pub fn without_font() -> Self {
+        TextShaper::fallback()
+    }
+
     /// Creates a new text shaper, returning None if no font can be loaded
     pub fn try_new() -> Option<Self> {
         // Try to load a font from common locations
@@ -189,46 +356,97 @@ impl<'a> TextShaper<'a> {
 
     /// Shapes text and returns the total width and glyph infos in logical pixels
     pub fn shape(&self, text: &str) -> (f32, Vec<GlyphInfo>) {
-        // For empty text or fallback fonts, use estimated widths
+        let shaped = self.shape_text(text, &ShapingOptions::default());
+        (shaped.width, shaped.glyphs)
+    }
+
+    /// Shapes text run by script, so Arabic joins, Devanagari forms
+    /// conjuncts and ligatures form in each, with the cluster map of the result
+    ///
+    /// Each run is shaped with the text around it as context, so letters
+    /// still join across run boundaries.
+    pub fn shape_text(&self, text: &str, options: &ShapingOptions) -> ShapedText {
+        let char_count = text.chars().count();
         if text.is_empty() {
-            return (0.0, Vec::new());
+            return ShapedText::default();
         }
+        let Some(font) = self.font.as_ref() else {
+            let (width, glyphs) = self.estimate_widths(text);
+            return ShapedText {
+                char_advances: glyphs.iter().map(|glyph| glyph.x_advance).collect(),
+                cluster_starts: vec![true; char_count],
+                glyphs,
+                width,
+                runs: script_runs(text),
+            };
+        };
 
-        // Use estimated widths if no font is loaded
-        if self.font.is_none() {
-            return self.estimate_widths(text);
+        // Char index of every byte offset HarfBuzz may report as a cluster
+        let mut char_at_byte = vec![0u32; text.len() + 1];
+        for (index, (byte, c)) in text.char_indices().enumerate() {
+            char_at_byte[byte..byte + c.len_utf8()].fill(index as u32);
         }
+        char_at_byte[text.len()] = char_count as u32;
 
-        let font = self.font.as_ref().unwrap();
-
-        let buffer = UnicodeBuffer::new().add_str(text);
-        let output = shape(font, buffer, &[]);
-
-        let positions = output.get_glyph_positions();
-        let infos = output.get_glyph_infos();
+        let features: Vec<Feature> = options
+            .features
+            .iter()
+            .filter_map(|feature| Some(Feature::new(Tag::from_str(&feature.tag).ok()?, feature.value, ..)))
+            .collect();
+        let language = options.language.as_deref().and_then(|language| Language::from_str(language).ok());
 
-        let mut total_width_px = 0.0;
-        let mut glyphs = Vec::with_capacity(positions.len());
-
-        for (position, info) in positions.iter().zip(infos.iter()) {
-            let x_advance_px = position.x_advance as f32 * self.scale_factor;
-            let y_advance_px = position.y_advance as f32 * self.scale_factor;
-            let x_offset_px = position.x_offset as f32 * self.scale_factor;
-            let y_offset_px = position.y_offset as f32 * self.scale_factor;
-
-            total_width_px += x_advance_px;
+        let runs = script_runs(text);
+        let mut glyphs = Vec::new();
+        let mut cluster_advances = vec![0.0f32; char_count];
+        let mut cluster_starts = vec![false; char_count];
+        for run in &runs {
+            let mut buffer = UnicodeBuffer::new().add_str_item(text, &text[run.range.clone()]);
+            if run.script != "Zyyy" {
+                let script = Script::from_iso15924_tag(Tag::from_str(&run.script).unwrap_or(Tag::new('Z', 'y', 'y', 'y')));
+                buffer = buffer.set_script(script.to_iso15924_tag()).set_direction(script.horizontal_direction());
+            }
+            if let Some(language) = language {
+                buffer = buffer.set_language(language);
+            }
+            let output = shape(font, buffer, &features);
+            for (position, info) in output.get_glyph_positions().iter().zip(output.get_glyph_infos()) {
+                let cluster = char_at_byte[(info.cluster as usize).min(text.len())];
+                let glyph = GlyphInfo {
+                    codepoint: info.codepoint,
+                    cluster,
+                    x_advance: position.x_advance as f32 * self.scale_factor,
+                    y_advance: position.y_advance as f32 * self.scale_factor,
+                    x_offset: position.x_offset as f32 * self.scale_factor,
+                    y_offset: position.y_offset as f32 * self.scale_factor,
+                };
+                if let Some(advance) = cluster_advances.get_mut(cluster as usize) {
+                    *advance += glyph.x_advance;
+                    cluster_starts[cluster as usize] = true;
+                }
+                glyphs.push(glyph);
+            }
+        }
 
-            glyphs.push(GlyphInfo {
-                codepoint: info.codepoint,
-                cluster: info.cluster,
-                x_advance: x_advance_px,
-                y_advance: y_advance_px,
-                x_offset: x_offset_px,
-                y_offset: y_offset_px,
-            });
+        // Share each cluster's advance among its chars
+        let mut char_advances = vec![0.0f32; char_count];
+        let mut start = 0;
+        while start < char_count {
+            let end = (start + 1..char_count).find(|&index| cluster_starts[index]).unwrap_or(char_count);
+            let share = cluster_advances[start] / (end - start) as f32;
+            char_advances[start..end].fill(share);
+            start = end;
+        }
+        if let Some(first) = cluster_starts.first_mut() {
+            *first = true;
         }
 
-        (total_width_px, glyphs)
+        ShapedText {
+            width: glyphs.iter().map(|glyph| glyph.x_advance).sum(),
+            glyphs,
+            char_advances,
+            cluster_starts,
+            runs,
+        }
     }
 
     /// Estimate character widths without a real font
@@ -436,6 +654,56 @@ mod tests {
         assert!(emoji_width >= 0.0, "Emoji should have non-negative width");
     }
 
+    #[test]
+    fn test_script_runs() {
+        let summary = |text: &str| -> Vec<(String, String, bool)> {
+            script_runs(text).into_iter().map(|run| (text[run.range].to_string(), run.script, run.rtl)).collect()
+        };
+        assert_eq!(summary("Hello, world"), vec![("Hello, world".to_string(), "Latn".to_string(), false)]);
+        assert_eq!(
+            summary("1. Hi مرحبا!"),
+            vec![("1. Hi ".to_string(), "Latn".to_string(), false), ("مرحبا!".to_string(), "Arab".to_string(), true)]
+        );
+        assert_eq!(summary("नमस्ते")[0].1, "Deva");
+        assert_eq!(summary("123")[0].1, "Zyyy");
+    }
+
+    #[test]
+    fn test_font_feature_parse() {
+        assert_eq!(FontFeature::parse("liga"), Some(FontFeature::new("liga", 1)));
+        assert_eq!(FontFeature::parse("-kern"), Some(FontFeature::new("kern", 0)));
+        assert_eq!(FontFeature::parse("salt=2"), Some(FontFeature::new("salt", 2)));
+        assert_eq!(FontFeature::parse("toolong"), None);
+    }
+
+    #[test]
+    fn test_cluster_map_covers_every_char() {
+        let shaper = TextShaper::new();
+        for text in ["office", "مرحبا بالعالم", "नमस्ते", "é and e\u{301}", "中文 text"] {
+            let shaped = shaper.shape_text(text, &ShapingOptions::default());
+            let chars = text.chars().count();
+            assert_eq!(shaped.char_advances.len(), chars, "{}", text);
+            assert!(shaped.cluster_starts[0]);
+            assert!(shaped.glyphs.iter().all(|glyph| (glyph.cluster as usize) < chars), "clusters are char indices");
+            assert!((shaped.char_positions()[chars] - shaped.width).abs() < 0.01);
+            assert!((shaped.width_of(0..chars) - shaped.width).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_features_change_shaping() {
+        let shaper = TextShaper::new();
+        let with = shaper.shape_text("office", &ShapingOptions::default());
+        let options = ShapingOptions { features: vec![FontFeature::new("liga", 0)], ..Default::default() };
+        let without = shaper.shape_text("office", &options);
+        // One glyph per char without ligatures; as many or fewer with them
+        assert!(with.glyphs.len() <= without.glyphs.len());
+        if shaper.has_font() {
+            assert_eq!(without.glyphs.len(), 6);
+            assert!(without.cluster_starts.iter().all(|&start| start));
+        }
+    }
+
     #[test]
     fn test_shape_multiple_times_consistent() {
         let shaper = TextShaper::new();
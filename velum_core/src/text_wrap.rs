@@ -0,0 +1,237 @@
+//! # Text Wrap Module
+//!
+//! Text flowing around floating images, as Word lays it out. An image text
+//! wraps around leaves an exclusion zone on its page, the wrap polygon
+//! [`calculate_wrap_region`] gives for it, grown by the distance kept from
+//! text:
+//!
+//! - Square and tight wrapping shorten the lines beside the zone. A line goes
+//!   on the wider side of it, as Word's "largest only" text wrapping does,
+//!   and one that would be narrower than [`MIN_LINE_WIDTH`] moves down past
+//!   the zone instead.
+//! - Top and bottom wrapping leaves the whole band of the column the zone
+//!   spans to the image, so text goes on below it.
+//! - Text flows over images behind or in front of it, and through ones with
+//!   through wrapping, as if they were not there.
+//!
+//! Tight wrapping follows the wrap polygon, which is the image's bounding box,
+//! turned with the image, until wrap polygons are read from the file.
+
+use serde::{Deserialize, Serialize};
+
+use crate::floating::{FloatingObjectSet, PlacedObject};
+use crate::image::{calculate_wrap_region, Point, RenderedImage, Size, WrapDistance, WrapPolygon, WrapType};
+use crate::ooxml::ImageAnchor;
+
+/// Narrowest line set beside an image, in points; a narrower gap is left empty
+pub const MIN_LINE_WIDTH: f32 = 36.0;
+
+/// Distance text keeps to the left and right of an image, in points: Word's
+/// default wp:anchor distL and distR of 114300 EMUs
+const SIDE_DISTANCE: f32 = 9.0;
+
+/// How text makes way for a zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    /// Lines beside the zone shorten to keep out of it
+    Around,
+    /// No line goes beside the zone
+    TopAndBottom,
+}
+
+/// Part of a page text keeps out of
+#[derive(Debug, Clone)]
+pub struct ExclusionZone {
+    pub page_index: usize,
+    pub mode: WrapMode,
+    /// In points from the top left of the page
+    pub polygon: WrapPolygon,
+}
+
+/// Where a line may go
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineSpace {
+    /// `shift` points right of the line's usual start, `width` points wide
+    Free { shift: f32, width: f32 },
+    /// Nowhere above this y, in points from the top of the page
+    Below(f32),
+}
+
+/// The exclusion zones of a document's pages
+#[derive(Debug, Clone, Default)]
+pub struct Exclusions {
+    zones: Vec<ExclusionZone>,
+}
+
+impl Exclusions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The zones of the objects of `floating` as `placed` on the pages
+    pub fn from_placed(floating: &FloatingObjectSet, placed: &[PlacedObject]) -> Self {
+        let mut exclusions = Exclusions::new();
+        for object in placed {
+            let Some((image, anchor)) = floating.get(object.index).and_then(|image| Some((image, image.anchor.as_ref()?))) else {
+                continue;
+            };
+            let wrap_type = wrap_type(anchor);
+            let mode = match wrap_type {
+                WrapType::Square | WrapType::Tight => WrapMode::Around,
+                WrapType::TopBottom => WrapMode::TopAndBottom,
+                WrapType::Through | WrapType::Behind | WrapType::InFront => continue,
+            };
+            let rendered = RenderedImage {
+                image_id: object.id.clone(),
+                position: Point::new(object.rect.x, object.rect.y),
+                size: Size::new(object.rect.width, object.rect.height),
+                wrap_type: Some(wrap_type),
+                wrap_distance: Some(WrapDistance::vertical_horizontal(0.0, SIDE_DISTANCE)),
+                ..RenderedImage::default()
+            }
+            .with_transform(image.transform.clone());
+            exclusions.push(ExclusionZone {
+                page_index: object.page_index,
+                mode,
+                polygon: calculate_wrap_region(&rendered),
+            });
+        }
+        exclusions
+    }
+
+    pub fn push(&mut self, zone: ExclusionZone) {
+        if zone.polygon.is_valid {
+            self.zones.push(zone);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+
+    pub fn zones(&self) -> &[ExclusionZone] {
+        &self.zones
+    }
+
+    /// Whether any zone is on pages `first` to `last` and, on page `first`,
+    /// reaches below `top`
+    pub fn any_from(&self, first: usize, top: f32, last: usize) -> bool {
+        self.zones.iter().any(|zone| {
+            (first..=last).contains(&zone.page_index) && (zone.page_index > first || zone.polygon.bottom() > top)
+        })
+    }
+
+    /// Where a line `height` high at `top` on page `page_index`, normally
+    /// running from `left` to `right`, may go, all in points on the page
+    pub fn line_space(&self, page_index: usize, top: f32, height: f32, left: f32, right: f32) -> LineSpace {
+        let bottom = top + height;
+        let mut taken: Vec<(f32, f32)> = Vec::new();
+        let mut below = f32::INFINITY;
+        for zone in self.zones.iter().filter(|zone| zone.page_index == page_index) {
+            let Some((start, end)) = zone.polygon.horizontal_extent(top, bottom) else {
+                continue;
+            };
+            if end <= left || start >= right {
+                continue;
+            }
+            below = below.min(zone.polygon.bottom());
+            match zone.mode {
+                WrapMode::Around => taken.push((start, end)),
+                WrapMode::TopAndBottom => return LineSpace::Below(zone.polygon.bottom()),
+            }
+        }
+        if taken.is_empty() {
+            return LineSpace::Free { shift: 0.0, width: right - left };
+        }
+
+        // The widest stretch between the zones
+        taken.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut widest = (left, left);
+        let mut from = left;
+        for (start, end) in taken {
+            if start.min(right) - from > widest.1 - widest.0 {
+                widest = (from, start.min(right));
+            }
+            from = from.max(end);
+        }
+        if right - from > widest.1 - widest.0 {
+            widest = (from, right);
+        }
+        let width = widest.1 - widest.0;
+        match width >= MIN_LINE_WIDTH.min(right - left) {
+            true => LineSpace::Free { shift: widest.0 - left, width },
+            false => LineSpace::Below(below),
+        }
+    }
+}
+
+/// How text wraps around an image anchored with `anchor`
+fn wrap_type(anchor: &ImageAnchor) -> WrapType {
+    match anchor.wrap.as_str() {
+        _ if anchor.behind_text => WrapType::Behind,
+        "tight" => WrapType::Tight,
+        "through" => WrapType::Through,
+        "topAndBottom" => WrapType::TopBottom,
+        "none" => WrapType::InFront,
+        _ => WrapType::Square,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Rect;
+
+    fn zone(mode: WrapMode, x: f32, y: f32, width: f32, height: f32) -> ExclusionZone {
+        ExclusionZone {
+            page_index: 0,
+            mode,
+            polygon: WrapPolygon::from_rect(Rect::new(x, y, width, height), WrapDistance::default()),
+        }
+    }
+
+    #[test]
+    fn test_lines_make_way_for_zones() {
+        let mut exclusions = Exclusions::new();
+        exclusions.push(zone(WrapMode::Around, 72.0, 100.0, 150.0, 100.0));
+        exclusions.push(zone(WrapMode::TopAndBottom, 72.0, 300.0, 100.0, 50.0));
+
+        // Beside the image the line goes on its wider side
+        assert_eq!(
+            exclusions.line_space(0, 120.0, 14.0, 72.0, 540.0),
+            LineSpace::Free { shift: 150.0, width: 318.0 }
+        );
+        // Above it and on other pages the line keeps its width
+        assert_eq!(exclusions.line_space(0, 80.0, 14.0, 72.0, 540.0), LineSpace::Free { shift: 0.0, width: 468.0 });
+        assert_eq!(exclusions.line_space(1, 120.0, 14.0, 72.0, 540.0), LineSpace::Free { shift: 0.0, width: 468.0 });
+        // Top and bottom wrapping leaves the band to the image
+        assert_eq!(exclusions.line_space(0, 310.0, 14.0, 72.0, 540.0), LineSpace::Below(350.0));
+        // A gap too narrow for a line is skipped
+        assert_eq!(exclusions.line_space(0, 120.0, 14.0, 72.0, 240.0), LineSpace::Below(200.0));
+
+        assert!(exclusions.any_from(0, 320.0, 0));
+        assert!(!exclusions.any_from(0, 360.0, 0));
+    }
+
+    #[test]
+    fn test_only_wrapping_images_leave_zones() {
+        let anchor = |wrap: &str, behind_text| ImageAnchor {
+            wrap: wrap.to_string(),
+            behind_text,
+            ..Default::default()
+        };
+        assert_eq!(wrap_type(&anchor("square", false)), WrapType::Square);
+        assert_eq!(wrap_type(&anchor("topAndBottom", false)), WrapType::TopBottom);
+        assert_eq!(wrap_type(&anchor("none", false)), WrapType::InFront);
+        assert_eq!(wrap_type(&anchor("none", true)), WrapType::Behind);
+
+        let mut exclusions = Exclusions::new();
+        exclusions.push(ExclusionZone {
+            page_index: 0,
+            mode: WrapMode::Around,
+            polygon: WrapPolygon { points: Vec::new(), is_valid: false },
+        });
+        assert!(exclusions.is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+//! # Track Changes Module
+//!
+//! Recording the editor's own tracked changes.
+//!
+//! With tracking on, inserted text becomes an insertion by the current author,
+//! and deleting text only marks it deleted. Deleting text the same author
+//! inserted and nobody has accepted yet removes it outright, as Word does.
+//! Recorded changes go into the document's [`RevisionSet`], so they are
+//! accepted, rejected and exported like tracked changes read from a file.
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::ooxml::{Revision, RevisionKind};
+use crate::piece_tree::PieceTree;
+use crate::revisions::RevisionSet;
+
+/// How revised text is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkStyle {
+    /// Inserted text
+    Underline,
+    /// Deleted text
+    Strikethrough,
+}
+
+/// A span of revised text to decorate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RevisionMark {
+    /// ID of the revision
+    pub id: String,
+    /// Chars of the document text
+    pub range: Range<usize>,
+    pub style: MarkStyle,
+    pub author: Option<String>,
+    /// Authors numbered in order of first appearance, to pick a color per author
+    pub author_index: usize,
+}
+
+/// Turns edits into tracked changes while tracking is on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackChangesManager {
+    enabled: bool,
+    author: String,
+}
+
+impl Default for TrackChangesManager {
+    fn default() -> Self {
+        TrackChangesManager::new("Author")
+    }
+}
+
+impl TrackChangesManager {
+    /// A manager recording changes as `author`, with tracking off
+    pub fn new(author: impl Into<String>) -> Self {
+        TrackChangesManager {
+            enabled: false,
+            author: author.into(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn set_author(&mut self, author: impl Into<String>) {
+        self.author = author.into();
+    }
+
+    /// Insert `text` at char `offset`; returns the chars inserted
+    pub fn insert(&self, tree: &mut PieceTree, revisions: &mut RevisionSet, offset: usize, text: &str) -> usize {
+        let inserted = text.chars().count();
+        tree.insert(offset, text.to_string());
+        revisions.apply_edit(offset, 0, inserted);
+        self.record_insertion(revisions, offset..offset + inserted);
+        inserted
+    }
+
+    /// Record chars some other edit, e.g. a paste, put into the text as an insertion
+    ///
+    /// The revisions must already have been told about the edit. Does nothing
+    /// while tracking is off.
+    pub fn record_insertion(&self, revisions: &mut RevisionSet, range: Range<usize>) {
+        if self.enabled && !range.is_empty() {
+            revisions.record(self.revision(revisions, RevisionKind::Insertion, range));
+        }
+    }
+
+    /// Delete chars `range`, or mark them deleted while tracking
+    ///
+    /// Returns the ranges taken out of the text, in the order they were removed;
+    /// each is relative to the text after the ones before it.
+    pub fn delete(&self, tree: &mut PieceTree, revisions: &mut RevisionSet, range: Range<usize>) -> Vec<Range<usize>> {
+        if range.is_empty() {
+            return Vec::new();
+        }
+        if !self.enabled {
+            remove(tree, revisions, range.clone());
+            return vec![range];
+        }
+
+        // Own pending insertions go away; everything else not yet deleted is marked
+        let own_insertions = covered(revisions, &range, |revision| {
+            revision.kind == RevisionKind::Insertion && revision.author.as_deref() == Some(self.author.as_str())
+        });
+        let mut skipped = own_insertions.clone();
+        skipped.extend(covered(revisions, &range, |revision| revision.kind == RevisionKind::Deletion));
+        skipped.sort_by_key(|part| part.start);
+
+        let mut start = range.start;
+        let mut marked = Vec::new();
+        for part in skipped.iter().chain(std::iter::once(&(range.end..range.end))) {
+            if start < part.start {
+                marked.push(start..part.start);
+            }
+            start = start.max(part.end);
+        }
+        for part in marked {
+            revisions.record(self.revision(revisions, RevisionKind::Deletion, part));
+        }
+
+        // Last first, so earlier offsets stay valid; one undo step for all of them
+        tree.transaction(|tree| {
+            own_insertions
+                .into_iter()
+                .rev()
+                .inspect(|part| remove(tree, revisions, part.clone()))
+                .collect()
+        })
+    }
+
+    fn revision(&self, revisions: &RevisionSet, kind: RevisionKind, range: Range<usize>) -> Revision {
+        Revision {
+            id: revisions.next_id(),
+            kind,
+            author: Some(self.author.clone()),
+            date: Some(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            start: range.start,
+            length: range.len(),
+            previous_run_properties: None,
+            previous_paragraph_properties: None,
+        }
+    }
+}
+
+/// Underline and strikethrough spans of the pending insertions and deletions
+pub fn revision_marks(revisions: &RevisionSet) -> Vec<RevisionMark> {
+    let mut authors: Vec<Option<&str>> = Vec::new();
+    revisions
+        .revisions()
+        .iter()
+        .filter(|revision| revision.length > 0)
+        .filter_map(|revision| {
+            let style = match revision.kind {
+                RevisionKind::Insertion => MarkStyle::Underline,
+                RevisionKind::Deletion => MarkStyle::Strikethrough,
+                _ => return None,
+            };
+            let author = revision.author.as_deref();
+            let author_index = authors.iter().position(|a| *a == author).unwrap_or_else(|| {
+                authors.push(author);
+                authors.len() - 1
+            });
+            Some(RevisionMark {
+                id: revision.id.clone(),
+                range: revision.start..revision.start + revision.length,
+                style,
+                author: revision.author.clone(),
+                author_index,
+            })
+        })
+        .collect()
+}
+
+/// Parts of `range` covered by revisions matching `matches`, merged and in order
+fn covered(revisions: &RevisionSet, range: &Range<usize>, matches: impl Fn(&Revision) -> bool) -> Vec<Range<usize>> {
+    let mut parts: Vec<Range<usize>> = Vec::new();
+    for revision in revisions.revisions().iter().filter(|revision| matches(revision)) {
+        let start = revision.start.max(range.start);
+        let end = (revision.start + revision.length).min(range.end);
+        if start >= end {
+            continue;
+        }
+        match parts.last_mut() {
+            Some(last) if start <= last.end => last.end = last.end.max(end),
+            _ => parts.push(start..end),
+        }
+    }
+    parts
+}
+
+/// Delete chars from the tree and move the revisions after them
+fn remove(tree: &mut PieceTree, revisions: &mut RevisionSet, range: Range<usize>) {
+    let start = tree.char_to_byte_offset(range.start);
+    let end = tree.char_to_byte_offset(range.end);
+    tree.delete(start, end - start);
+    revisions.apply_edit(range.start, range.len(), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracking(author: &str) -> TrackChangesManager {
+        let mut manager = TrackChangesManager::new(author);
+        manager.set_enabled(true);
+        manager
+    }
+
+    fn spans(revisions: &RevisionSet) -> Vec<(RevisionKind, usize, usize, &str)> {
+        revisions
+            .revisions()
+            .iter()
+            .map(|r| (r.kind, r.start, r.length, r.author.as_deref().unwrap_or("")))
+            .collect()
+    }
+
+    #[test]
+    fn test_typing_records_one_insertion() {
+        let manager = tracking("Ann");
+        let mut tree = PieceTree::new("Hello world".to_string());
+        let mut revisions = RevisionSet::new();
+
+        for (i, c) in " big".chars().enumerate() {
+            manager.insert(&mut tree, &mut revisions, 5 + i, &c.to_string());
+        }
+        assert_eq!(tree.get_text(), "Hello big world");
+        assert_eq!(spans(&revisions), vec![(RevisionKind::Insertion, 5, 4, "Ann")]);
+        assert!(revisions.revisions()[0].date.as_deref().is_some_and(|date| date.ends_with('Z')));
+
+        // Rejecting it takes the text back out
+        revisions.reject_all(&mut tree);
+        assert_eq!(tree.get_text(), "Hello world");
+    }
+
+    #[test]
+    fn test_delete_marks_text_and_removes_own_insertions() {
+        let ann = tracking("Ann");
+        let mut tree = PieceTree::new("one two three".to_string());
+        let mut revisions = RevisionSet::new();
+        ann.insert(&mut tree, &mut revisions, 4, "new ");
+        assert_eq!(tree.get_text(), "one new two three");
+
+        // "new two" spans Ann's insertion and original text
+        let removed = ann.delete(&mut tree, &mut revisions, 4..11);
+        assert_eq!(removed, vec![4..8]);
+        assert_eq!(tree.get_text(), "one two three");
+        assert_eq!(spans(&revisions), vec![(RevisionKind::Deletion, 4, 3, "Ann")]);
+
+        // Deleting over marked text only marks the rest; another author's insertion is marked too
+        let bob = tracking("Bob");
+        bob.insert(&mut tree, &mut revisions, 0, "zero ");
+        assert!(ann.delete(&mut tree, &mut revisions, 3..16).is_empty());
+        assert_eq!(tree.get_text(), "zero one two three");
+        assert_eq!(
+            spans(&revisions),
+            vec![
+                (RevisionKind::Insertion, 0, 5, "Bob"),
+                (RevisionKind::Deletion, 3, 13, "Ann"),
+            ]
+        );
+
+        let marks = revision_marks(&revisions);
+        assert_eq!(marks[0].style, MarkStyle::Underline);
+        assert_eq!((marks[1].style, marks[1].range.clone(), marks[1].author_index), (MarkStyle::Strikethrough, 3..16, 1));
+    }
+
+    #[test]
+    fn test_typing_inside_a_deletion_splits_it() {
+        let manager = tracking("Ann");
+        let mut tree = PieceTree::new("abcdef".to_string());
+        let mut revisions = RevisionSet::new();
+        manager.delete(&mut tree, &mut revisions, 1..5);
+        manager.insert(&mut tree, &mut revisions, 3, "XY");
+
+        assert_eq!(tree.get_text(), "abcXYdef");
+        assert_eq!(
+            spans(&revisions),
+            vec![
+                (RevisionKind::Deletion, 1, 2, "Ann"),
+                (RevisionKind::Insertion, 3, 2, "Ann"),
+                (RevisionKind::Deletion, 5, 2, "Ann"),
+            ]
+        );
+        let ids: Vec<&str> = revisions.revisions().iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["1", "2", "3"]);
+
+        // With tracking off, edits are plain
+        let plain = TrackChangesManager::new("Ann");
+        assert_eq!(plain.delete(&mut tree, &mut revisions, 0..1), vec![0..1]);
+        assert_eq!(tree.get_text(), "bcXYdef");
+    }
+}
@@ -0,0 +1,390 @@
+//! # View Filter Module
+//!
+//! Per-output control over which annotation content is drawn.
+//!
+//! The editing view shows markup that printed pages should not: comment
+//! highlights, revision balloons, field shading and so on. Instead of a second
+//! layout path for print and PDF, layout takes a [`ViewFilter`] for the output
+//! target and leaves out the content classes it hides.
+//!
+//! Hidden text is taken out before lines are broken, so the rest reflows; its
+//! line breaks stay, so paragraphs keep their indices. The other classes are
+//! decorations drawn over laid-out lines and never move text, except that
+//! shown revision balloons reserve a markup column beside the text.
+
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::line_layout::{LineLayout, ParagraphLayout, ParagraphProperties};
+use crate::page_layout::{PageConfig, PageLayout, Rect, RenderedLine, RenderedPage};
+
+/// Default width of the revision balloon column in points
+pub const DEFAULT_BALLOON_WIDTH: f32 = 144.0;
+/// Space between the text and the balloon column in points
+const BALLOON_GAP: f32 = 9.0;
+
+/// Kinds of content an output may leave out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentClass {
+    /// Highlight of commented text
+    CommentMarkup,
+    /// Tracked changes and comments shown in the margin
+    RevisionBalloons,
+    /// Text formatted as hidden (w:vanish)
+    HiddenText,
+    /// Gray background of field results
+    FieldShading,
+    /// Frames around content controls
+    ContentControlBorders,
+    /// Text or picture behind every page
+    Watermark,
+}
+
+impl ContentClass {
+    pub const ALL: [ContentClass; 6] = [
+        ContentClass::CommentMarkup,
+        ContentClass::RevisionBalloons,
+        ContentClass::HiddenText,
+        ContentClass::FieldShading,
+        ContentClass::ContentControlBorders,
+        ContentClass::Watermark,
+    ];
+}
+
+/// Where a layout is going
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputTarget {
+    /// The on-screen editing view
+    Editing,
+    Print,
+    Pdf,
+}
+
+impl OutputTarget {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "editing" | "screen" => Some(OutputTarget::Editing),
+            "print" => Some(OutputTarget::Print),
+            "pdf" => Some(OutputTarget::Pdf),
+            _ => None,
+        }
+    }
+}
+
+/// Content of a class on a char range of the document text
+///
+/// Watermarks belong to pages rather than text; their range is ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub class: ContentClass,
+    pub range: Range<usize>,
+}
+
+/// Where to draw shown annotation content, in content-area coordinates of a page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decoration {
+    pub class: ContentClass,
+    /// Index of the annotation drawn, in the list given to layout
+    pub annotation: usize,
+    pub page_index: usize,
+    pub rect: Rect,
+}
+
+/// Pages laid out through a view filter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilteredLayout {
+    /// Text that was laid out, without hidden text; line offsets refer to it
+    pub text: String,
+    /// Width of the text column
+    pub text_width: f32,
+    pub pages: Vec<RenderedPage>,
+    pub decorations: Vec<Decoration>,
+}
+
+/// Which content classes an output shows
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewFilter {
+    hidden: BTreeSet<ContentClass>,
+    pub balloon_width: f32,
+}
+
+impl Default for ViewFilter {
+    fn default() -> Self {
+        ViewFilter::for_target(OutputTarget::Editing)
+    }
+}
+
+impl ViewFilter {
+    /// The usual filter for a target: editing shows everything, print and PDF only the watermark
+    pub fn for_target(target: OutputTarget) -> Self {
+        let hidden = match target {
+            OutputTarget::Editing => BTreeSet::new(),
+            OutputTarget::Print | OutputTarget::Pdf => ContentClass::ALL
+                .into_iter()
+                .filter(|class| *class != ContentClass::Watermark)
+                .collect(),
+        };
+        ViewFilter {
+            hidden,
+            balloon_width: DEFAULT_BALLOON_WIDTH,
+        }
+    }
+
+    pub fn hide(&mut self, class: ContentClass) {
+        self.hidden.insert(class);
+    }
+
+    pub fn show(&mut self, class: ContentClass) {
+        self.hidden.remove(&class);
+    }
+
+    pub fn shows(&self, class: ContentClass) -> bool {
+        !self.hidden.contains(&class)
+    }
+
+    /// Width of the text column on pages with `content_width`, after any balloon column
+    pub fn text_width(&self, content_width: f32, annotations: &[Annotation]) -> f32 {
+        let balloons = self.shows(ContentClass::RevisionBalloons)
+            && annotations.iter().any(|a| a.class == ContentClass::RevisionBalloons);
+        if balloons {
+            (content_width - self.balloon_width - BALLOON_GAP).max(0.0)
+        } else {
+            content_width
+        }
+    }
+
+    /// Lay out `text` onto pages, leaving out what this filter hides
+    ///
+    /// `props` gives each paragraph's properties, in order; ranges are char offsets into `text`.
+    pub fn layout(
+        &self,
+        text: &str,
+        annotations: &[Annotation],
+        props: &[ParagraphProperties],
+        page_config: PageConfig,
+    ) -> FilteredLayout {
+        let hidden_text: Vec<Range<usize>> = if self.shows(ContentClass::HiddenText) {
+            Vec::new()
+        } else {
+            annotations
+                .iter()
+                .filter(|a| a.class == ContentClass::HiddenText)
+                .map(|a| a.range.clone())
+                .collect()
+        };
+        let visible = VisibleText::new(text, &hidden_text);
+
+        let text_width = self.text_width(page_config.content_width(), annotations);
+        let document = LineLayout::new().layout_document_with_paragraph_props(&visible.text, text_width, props);
+        let content_bounds = Rect::new(0.0, 0.0, page_config.content_width(), page_config.content_height());
+        let (width, height) = (page_config.width, page_config.height);
+        let pages: Vec<RenderedPage> = PageLayout::with_page_config(page_config)
+            .layout_pages(&document.paragraphs)
+            .into_iter()
+            .map(|page| RenderedPage {
+                page_width: width,
+                page_height: height,
+                ..RenderedPage::from(page)
+            })
+            .collect();
+
+        let paragraph_starts = paragraph_starts(&document.paragraphs);
+        let line_chars = |line: &RenderedLine| {
+            let paragraph = &document.paragraphs[line.paragraph_index];
+            let start = paragraph_starts[line.paragraph_index];
+            let chars = |byte: usize| paragraph.text.get(..byte).map_or(0, |t| t.chars().count());
+            start + chars(line.start)..start + chars(line.end)
+        };
+
+        let mut decorations = Vec::new();
+        for (index, annotation) in annotations.iter().enumerate() {
+            if !self.shows(annotation.class) {
+                continue;
+            }
+            let range = visible.to_visible(annotation.range.start)..visible.to_visible(annotation.range.end);
+            let mut decorate = |page_index: usize, rect: Rect| {
+                decorations.push(Decoration {
+                    class: annotation.class,
+                    annotation: index,
+                    page_index,
+                    rect,
+                })
+            };
+
+            match annotation.class {
+                ContentClass::Watermark => {
+                    for page in &pages {
+                        decorate(page.page_index, content_bounds);
+                    }
+                }
+                ContentClass::RevisionBalloons => {
+                    // One balloon beside the first line the change touches
+                    let first = pages.iter().find_map(|page| {
+                        let line = page.lines.iter().find(|line| {
+                            let chars = line_chars(line);
+                            chars.start <= range.start && range.start <= chars.end
+                        })?;
+                        Some((page.page_index, line))
+                    });
+                    if let Some((page_index, line)) = first {
+                        decorate(
+                            page_index,
+                            Rect::new(text_width + BALLOON_GAP, line.y, self.balloon_width, line.height),
+                        );
+                    }
+                }
+                _ => {
+                    for page in &pages {
+                        for line in &page.lines {
+                            if let Some(rect) = span_rect(line, line_chars(line), &range) {
+                                decorate(page.page_index, rect);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        FilteredLayout {
+            text: visible.text,
+            text_width,
+            pages,
+            decorations,
+        }
+    }
+}
+
+/// Document text with hidden ranges taken out
+struct VisibleText {
+    text: String,
+    /// Source char ranges taken out, in order and disjoint
+    removed: Vec<Range<usize>>,
+}
+
+impl VisibleText {
+    fn new(text: &str, hidden: &[Range<usize>]) -> Self {
+        let is_hidden = |i: usize| hidden.iter().any(|range| range.contains(&i));
+        let mut visible = String::with_capacity(text.len());
+        let mut removed: Vec<Range<usize>> = Vec::new();
+        for (i, c) in text.chars().enumerate() {
+            if c == '\n' || !is_hidden(i) {
+                visible.push(c);
+            } else if let Some(last) = removed.last_mut().filter(|last| last.end == i) {
+                last.end += 1;
+            } else {
+                removed.push(i..i + 1);
+            }
+        }
+        VisibleText { text: visible, removed }
+    }
+
+    /// Visible char offset of source char `offset`; offsets in removed text move to where it was
+    fn to_visible(&self, offset: usize) -> usize {
+        let removed_before: usize = self
+            .removed
+            .iter()
+            .map(|range| offset.min(range.end).saturating_sub(range.start))
+            .sum();
+        offset - removed_before
+    }
+}
+
+/// Char offset where each paragraph starts in the laid out text
+fn paragraph_starts(paragraphs: &[ParagraphLayout]) -> Vec<usize> {
+    let mut start = 0;
+    paragraphs
+        .iter()
+        .map(|paragraph| {
+            let this = start;
+            start += paragraph.text.chars().count() + 1;
+            this
+        })
+        .collect()
+}
+
+/// Part of `line` covering chars `range`; the horizontal extent is interpolated by chars
+fn span_rect(line: &RenderedLine, line_chars: Range<usize>, range: &Range<usize>) -> Option<Rect> {
+    let start = range.start.max(line_chars.start);
+    let end = range.end.min(line_chars.end);
+    if start >= end {
+        return None;
+    }
+    let length = line_chars.len() as f32;
+    let from = (start - line_chars.start) as f32 / length;
+    let to = (end - line_chars.start) as f32 / length;
+    Some(Rect::new(
+        line.x + line.width * from,
+        line.y,
+        line.width * (to - from),
+        line.height,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotation(class: ContentClass, range: Range<usize>) -> Annotation {
+        Annotation { class, range }
+    }
+
+    #[test]
+    fn test_target_presets() {
+        let editing = ViewFilter::for_target(OutputTarget::Editing);
+        assert!(ContentClass::ALL.iter().all(|class| editing.shows(*class)));
+
+        let mut pdf = ViewFilter::for_target(OutputTarget::Pdf);
+        let shown: Vec<_> = ContentClass::ALL.into_iter().filter(|class| pdf.shows(*class)).collect();
+        assert_eq!(shown, [ContentClass::Watermark]);
+        pdf.show(ContentClass::FieldShading);
+        assert!(pdf.shows(ContentClass::FieldShading));
+    }
+
+    #[test]
+    fn test_hidden_text_is_removed_for_print_and_decorated_for_editing() {
+        let text = "Visible secret\nNext";
+        let annotations = [
+            annotation(ContentClass::HiddenText, 7..15),
+            annotation(ContentClass::FieldShading, 15..19),
+        ];
+        let props = [ParagraphProperties::default(); 2];
+
+        let print = ViewFilter::for_target(OutputTarget::Print).layout(text, &annotations, &props, PageConfig::a4());
+        assert_eq!(print.text, "Visible\nNext");
+        assert!(print.decorations.is_empty());
+
+        let editing = ViewFilter::default().layout(text, &annotations, &props, PageConfig::a4());
+        assert_eq!(editing.text, text);
+        let classes: Vec<_> = editing.decorations.iter().map(|d| d.class).collect();
+        assert_eq!(classes, [ContentClass::HiddenText, ContentClass::FieldShading]);
+        // "secret" is the second half of its line
+        let hidden = editing.decorations[0].rect;
+        let line = &editing.pages[0].lines[0];
+        assert!(hidden.x > line.x && (hidden.right() - (line.x + line.width)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_balloons_narrow_the_text_and_watermark_covers_pages() {
+        let annotations = [
+            annotation(ContentClass::RevisionBalloons, 3..5),
+            annotation(ContentClass::Watermark, 0..0),
+        ];
+        let props = [ParagraphProperties::default()];
+        let config = PageConfig::a4();
+
+        let editing = ViewFilter::default().layout("Some text", &annotations, &props, config.clone());
+        assert_eq!(editing.text_width, config.content_width() - DEFAULT_BALLOON_WIDTH - BALLOON_GAP);
+        let balloon = &editing.decorations[0];
+        assert_eq!((balloon.class, balloon.page_index), (ContentClass::RevisionBalloons, 0));
+        assert!(balloon.rect.x > editing.text_width);
+
+        let pdf = ViewFilter::for_target(OutputTarget::Pdf).layout("Some text", &annotations, &props, config.clone());
+        assert_eq!(pdf.text_width, config.content_width());
+        assert_eq!(pdf.decorations.len(), pdf.pages.len());
+        assert!(pdf.decorations.iter().all(|d| d.class == ContentClass::Watermark));
+    }
+}
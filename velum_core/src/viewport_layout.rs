@@ -0,0 +1,1080 @@
+//! # Viewport Layout Module
+//!
+//! Lays out only the pages near the viewport, so a long document can be
+//! shown and scrolled as soon as it is opened.
+//!
+//! Paragraphs not broken into lines yet get a height estimated from their
+//! length, their line height and the chars a line has held in the paragraphs
+//! laid out so far. Until every paragraph is laid out, the text flows through
+//! pages of the content height in the order of those heights: this places
+//! every page and maps scroll positions to char offsets and back, and the
+//! pages overlapping a viewport are laid out exactly from the paragraph their
+//! estimated top falls in. [`ViewportLayout::refine`] lays out a batch of the
+//! remaining paragraphs at a time, for the frontend to call while idle or
+//! from a background task, and says how far to scroll to keep the text in
+//! view still; once none remain the document is paginated and every position
+//! is exact.
+//!
+//! Scroll positions are in points down a column of pages [`PAGE_GAP`] apart.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hit_test::{self, Affinity, CaretPosition, HighlightRect, LineCaret};
+use crate::line_layout::{LineLayout, LineLayoutInfo, ParagraphLayout, ParagraphProperties};
+use crate::ooxml::Section;
+use crate::page_layout::{Page, PageLayout, PageModel, Rect, RenderedLine};
+use crate::paragraph_hash::ParagraphHash;
+use crate::piece_tree::PieceTree;
+
+/// Space between pages in the scrolled view, in points
+pub const PAGE_GAP: f32 = 12.0;
+
+/// Pages laid out above and below the viewport, so scrolling a little shows
+/// laid-out pages
+const OVERSCAN_PAGES: usize = 1;
+
+/// Text whose width gives the chars a line holds before any is laid out
+const SAMPLE_TEXT: &str = "The quick brown fox jumps over the lazy dog and keeps on running.";
+
+/// Region of the scrolled view
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Viewport {
+    /// Scroll position of the top edge
+    pub top: f32,
+    pub height: f32,
+}
+
+/// A laid-out page and where it is in the scrolled view
+#[derive(Debug, Clone, Serialize)]
+pub struct ViewportPage {
+    /// Scroll position of the page's top edge
+    pub top: f32,
+    /// The page, numbered and with paragraph indices in the whole document
+    pub page: Page,
+}
+
+/// Pages overlapping a viewport
+#[derive(Debug, Clone, Serialize)]
+pub struct ViewportPages {
+    pub pages: Vec<ViewportPage>,
+    /// Paragraphs with lines on `pages`
+    pub paragraphs: Range<usize>,
+    /// Page count of the document, estimated until `exact`
+    pub page_count: usize,
+    /// Height of the whole scrolled view
+    pub scroll_height: f32,
+    /// Whether every paragraph is laid out, so pages and positions are exact
+    pub exact: bool,
+}
+
+/// What a call to [`ViewportLayout::refine`] did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct RefineStep {
+    /// Paragraphs broken into lines
+    pub paragraphs_laid_out: usize,
+    /// Paragraphs whose height is still estimated
+    pub remaining: usize,
+    /// How far to scroll down to keep the text at the top of the last
+    /// viewport where it was
+    pub scroll_adjustment: f32,
+    pub exact: bool,
+}
+
+/// A paragraph of the document and its height in the flow
+#[derive(Debug, Clone)]
+struct Slot {
+    /// Char offset of the paragraph start
+    start: usize,
+    /// Length in chars, excluding the paragraph break
+    length: usize,
+    hash: u64,
+    /// Whether its layout is in `layouts`, rather than a placeholder
+    laid_out: bool,
+    /// Measured from its layout, or estimated
+    height: f32,
+}
+
+/// Layout of a document that lays out paragraphs as they come into view
+pub struct ViewportLayout {
+    line_layout: LineLayout,
+    page_layout: PageLayout,
+    /// Sections the paragraphs are laid out in; none for all on the pages of `page_layout`
+    sections: Vec<Section>,
+    slots: Vec<Slot>,
+    /// Layout of each paragraph; those not laid out have no lines
+    layouts: Vec<ParagraphLayout>,
+    /// Flow position of the top of each paragraph, and of the end of the last
+    tops: Vec<f32>,
+    /// Paragraphs not laid out
+    estimated: usize,
+    /// Lines laid out other than the last of their paragraph, and the chars
+    /// on them, which average out to the chars a full line holds
+    full_lines: (usize, usize),
+    /// Most chars a laid-out line held
+    longest_line: usize,
+    chars_per_line: f32,
+    /// Where `refine` looks for the next paragraph to lay out
+    next_estimated: usize,
+    /// Every page, once every paragraph was laid out
+    model: Option<PageModel>,
+    /// First paragraph edited since `model` was paginated
+    stale_from: Option<usize>,
+    /// Pages last laid out for a viewport, while positions are estimated
+    window: Vec<Page>,
+    /// Last viewport, and the char offset at its top
+    viewport: Option<(Viewport, usize)>,
+}
+
+impl ViewportLayout {
+    /// Lays out at the column width of `page_layout`, on its pages
+    pub fn new(page_layout: PageLayout) -> Self {
+        ViewportLayout {
+            line_layout: LineLayout::new(),
+            page_layout,
+            sections: Vec::new(),
+            slots: Vec::new(),
+            layouts: Vec::new(),
+            tops: vec![0.0],
+            estimated: 0,
+            full_lines: (0, 0),
+            longest_line: 0,
+            chars_per_line: 0.0,
+            next_estimated: 0,
+            model: None,
+            stale_from: None,
+            window: Vec::new(),
+            viewport: None,
+        }
+    }
+
+    /// Lays out in `sections`, with their geometry, columns and starts, as
+    /// [`PageLayout::layout_sections`] does
+    pub fn with_sections(mut self, sections: Vec<Section>) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    /// The line layout paragraphs are broken with; changing how it breaks
+    /// lines only affects paragraphs laid out afterwards
+    pub fn line_layout_mut(&mut self) -> &mut LineLayout {
+        &mut self.line_layout
+    }
+
+    pub fn page_layout(&self) -> &PageLayout {
+        &self.page_layout
+    }
+
+    /// Paragraphs whose height is still estimated
+    pub fn estimated_count(&self) -> usize {
+        self.estimated
+    }
+
+    /// Whether every paragraph is laid out and paginated
+    pub fn is_exact(&self) -> bool {
+        self.model.is_some() && self.stale_from.is_none()
+    }
+
+    /// Pages of the whole document, once exact
+    pub fn model(&self) -> Option<&PageModel> {
+        self.model.as_ref().filter(|_| self.stale_from.is_none())
+    }
+
+    /// Page count, estimated until exact
+    pub fn page_count(&self) -> usize {
+        if let Some(model) = self.model() {
+            return model.page_count();
+        }
+        if self.slots.is_empty() {
+            return 0;
+        }
+        let flowed = (self.tops[self.slots.len()] / self.content_height()).ceil().max(1.0) as usize;
+        match self.window.last() {
+            // The window reaches the end of the document
+            Some(page) if page.lines.last().is_some_and(|line| line.paragraph_index + 1 == self.slots.len()) => {
+                page.page_index + 1
+            }
+            Some(page) => flowed.max(page.page_index + 1),
+            None => flowed,
+        }
+    }
+
+    /// Height of the whole scrolled view
+    pub fn scroll_height(&self) -> f32 {
+        (self.page_count() as f32 * self.stride() - PAGE_GAP).max(0.0)
+    }
+
+    /// Bring the paragraphs up to date with a document whose paragraphs have
+    /// the hashes `paragraphs` and layout properties `props` (missing ones
+    /// use the defaults); returns the paragraphs that changed
+    ///
+    /// Changed paragraphs get estimated heights; the others keep their
+    /// layouts. This only costs a pass over the hashes, so a document just
+    /// opened is ready to show once its paragraphs are hashed.
+    pub fn update(&mut self, paragraphs: &[ParagraphHash], props: &[ParagraphProperties]) -> Range<usize> {
+        let count = paragraphs.len();
+        let old_count = self.slots.len();
+        let props_of = |index: usize| props.get(index).copied().unwrap_or_default();
+        let unchanged = |new: usize, old: usize| {
+            self.slots[old].hash == paragraphs[new].hash && self.layouts[old].properties == props_of(new)
+        };
+        let shared = count.min(old_count);
+        let prefix = (0..shared).take_while(|&index| unchanged(index, index)).count();
+        let suffix = (0..shared - prefix)
+            .take_while(|&back| unchanged(count - 1 - back, old_count - 1 - back))
+            .count();
+        if prefix == count && prefix == old_count {
+            return prefix..prefix;
+        }
+        if self.chars_per_line == 0.0 {
+            self.calibrate();
+        }
+
+        let width = self.page_layout.column_width();
+        let dirty = prefix..count - suffix;
+        let mut slots = Vec::with_capacity(dirty.len());
+        let mut layouts = Vec::with_capacity(dirty.len());
+        for index in dirty.clone() {
+            let properties = props_of(index);
+            let mut slot = Slot {
+                start: paragraphs[index].start,
+                length: paragraphs[index].length,
+                hash: paragraphs[index].hash,
+                laid_out: false,
+                height: 0.0,
+            };
+            slot.height = self.estimate(&slot, properties);
+            slots.push(slot);
+            layouts.push(placeholder(width, properties));
+        }
+        let removed = self.slots.splice(prefix..old_count - suffix, slots);
+        self.estimated -= removed.filter(|slot| !slot.laid_out).count();
+        self.estimated += dirty.len();
+        self.layouts.splice(prefix..old_count - suffix, layouts);
+        for (slot, paragraph) in self.slots.iter_mut().zip(paragraphs).skip(dirty.end) {
+            slot.start = paragraph.start;
+        }
+
+        self.update_tops(prefix);
+        self.next_estimated = prefix;
+        if self.model.is_some() {
+            self.stale_from = Some(self.stale_from.map_or(prefix, |from| from.min(prefix)));
+        }
+        self.window.clear();
+        dirty
+    }
+
+    /// Lays out the pages overlapping `viewport`, and a page above and below
+    /// it, laying out the paragraphs on them that were not yet
+    pub fn viewport_pages(&mut self, tree: &PieceTree, viewport: Viewport) -> ViewportPages {
+        let stride = self.stride();
+        let (first_page, last_page) = self.page_range(viewport);
+        if !self.slots.is_empty() && !self.is_exact() {
+            self.lay_out_window(tree, first_page, last_page);
+        }
+        self.viewport = Some((viewport, self.offset_at(viewport.top)));
+
+        let pages: Vec<ViewportPage> = self
+            .placed_pages()
+            .iter()
+            .filter(|page| (first_page..=last_page).contains(&page.page_index))
+            .map(|page| ViewportPage {
+                top: page.page_index as f32 * stride,
+                page: page.clone(),
+            })
+            .collect();
+        let mut indices = pages.iter().flat_map(|page| page.page.lines.iter().map(|line| line.paragraph_index));
+        let first = indices.next();
+        let paragraphs = match first {
+            Some(first) => first..indices.last().unwrap_or(first) + 1,
+            None => 0..0,
+        };
+        ViewportPages {
+            pages,
+            paragraphs,
+            page_count: self.page_count(),
+            scroll_height: self.scroll_height(),
+            exact: self.is_exact(),
+        }
+    }
+
+    /// Lay out up to `max_paragraphs` of the paragraphs whose height is
+    /// estimated, then estimate the rest again from what was learned
+    ///
+    /// Once the last one is laid out the document is paginated, which makes
+    /// page count and positions exact. Until then the pages of the last
+    /// viewport are laid out again from where the text at its top moved in
+    /// the flow, which is where scrolling by the adjustment shows them.
+    pub fn refine(&mut self, tree: &PieceTree, max_paragraphs: usize) -> RefineStep {
+        if self.is_exact() || self.slots.is_empty() {
+            return RefineStep {
+                exact: self.is_exact(),
+                ..Default::default()
+            };
+        }
+        let before = self
+            .viewport
+            .map(|(_, anchor)| (self.scroll_position(anchor), self.flow_scroll_position(anchor)));
+
+        let mut paragraphs_laid_out = 0;
+        while paragraphs_laid_out < max_paragraphs && self.estimated > 0 {
+            let mut index = self.next_estimated % self.slots.len();
+            while self.slots[index].laid_out {
+                index = (index + 1) % self.slots.len();
+            }
+            self.lay_out(tree, index);
+            self.next_estimated = index + 1;
+            paragraphs_laid_out += 1;
+        }
+        self.calibrate();
+        for index in 0..self.slots.len() {
+            if !self.slots[index].laid_out {
+                let height = self.estimate(&self.slots[index], self.layouts[index].properties);
+                self.slots[index].height = height;
+            }
+        }
+        self.update_tops(0);
+        self.window.clear();
+        match (self.viewport, before) {
+            (Some((viewport, anchor)), Some((_, flow_before))) if self.estimated > 0 => {
+                let top = viewport.top + self.flow_scroll_position(anchor) - flow_before;
+                let viewport = Viewport { top, ..viewport };
+                let (first_page, last_page) = self.page_range(viewport);
+                self.lay_out_window(tree, first_page, last_page);
+                self.viewport = Some((viewport, anchor));
+            }
+            _ if self.estimated == 0 => self.paginate(),
+            _ => {}
+        }
+
+        let scroll_adjustment = match (self.viewport, before) {
+            (Some((_, anchor)), Some((shown, _))) => self.scroll_position(anchor) - shown,
+            _ => 0.0,
+        };
+        RefineStep {
+            paragraphs_laid_out,
+            remaining: self.estimated,
+            scroll_adjustment,
+            exact: self.is_exact(),
+        }
+    }
+
+    /// Scroll position of the top of the line holding char `offset`
+    pub fn scroll_position(&self, offset: usize) -> f32 {
+        let Some(index) = self.paragraph_at(offset) else {
+            return 0.0;
+        };
+        if let Some((page, line)) = self.placed_line(index, offset, Affinity::Downstream) {
+            return page.page_index as f32 * self.stride() + page.content_bounds.y + line.y;
+        }
+        self.flow_scroll_position(offset)
+    }
+
+    /// Caret at point (`x`, `y`) of page `page_index`, in points from its top
+    /// left corner: on the line nearest the point, down first, at the char
+    /// edge nearest it; None if the page is not laid out
+    pub fn position_at_point(&mut self, page_index: usize, x: f32, y: f32) -> Option<CaretPosition> {
+        let page = self.placed_pages().iter().find(|page| page.page_index == page_index)?;
+        let bounds = page.content_bounds;
+        let distance = |line: &RenderedLine| {
+            let (top, left) = (bounds.y + line.y, bounds.x + line.x);
+            let down = (top - y).max(y - (top + line.height)).max(0.0);
+            let across = (left - x).max(x - (left + line.width)).max(0.0);
+            (down, across)
+        };
+        let line = page
+            .lines
+            .iter()
+            .min_by(|a, b| {
+                let (a, b) = (distance(a), distance(b));
+                a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+            })?
+            .clone();
+        Some(self.caret_at_x(page_index, bounds, &line, x))
+    }
+
+    /// Caret for char `offset` with `affinity`, if its line is laid out on a page
+    pub fn caret_rect(&mut self, offset: usize, affinity: Affinity) -> Option<CaretPosition> {
+        let index = self.paragraph_at(offset)?;
+        let (page, line) = self.placed_line(index, offset, affinity)?;
+        let (page_index, bounds, line) = (page.page_index, page.content_bounds, line.clone());
+        let within = (offset - self.slots[index].start).min(self.slots[index].length);
+        let caret = hit_test::caret_on_line(
+            &self.layouts[index],
+            line.source_line_index,
+            within,
+            affinity,
+            self.line_layout.breaker_mut(),
+        );
+        Some(self.caret_position(page_index, bounds, &line, caret))
+    }
+
+    /// Caret on the line before the one holding the caret at `offset` with
+    /// `affinity`, or after it if `down`, nearest `x` points from the left
+    /// edge of the page; None if either line is not laid out on a page
+    pub fn caret_on_adjacent_line(&mut self, offset: usize, affinity: Affinity, x: f32, down: bool) -> Option<CaretPosition> {
+        let index = self.paragraph_at(offset)?;
+        let (_, current) = self.placed_line(index, offset, affinity)?;
+        let lines: Vec<(usize, Rect, &RenderedLine)> = self
+            .placed_pages()
+            .iter()
+            .flat_map(|page| page.lines.iter().map(move |line| (page.page_index, page.content_bounds, line)))
+            .collect();
+        let position = lines.iter().position(|(_, _, line)| std::ptr::eq(*line, current))?;
+        let target = match down {
+            true => position + 1,
+            false => position.checked_sub(1)?,
+        };
+        let (page_index, bounds, line) = lines.get(target).map(|(page, bounds, line)| (*page, *bounds, (*line).clone()))?;
+        Some(self.caret_at_x(page_index, bounds, &line, x))
+    }
+
+    /// Caret on the page before the one holding the caret at `offset` with
+    /// `affinity`, or after it if `down`, at the same height down the page and
+    /// nearest `x`; None if either is not laid out
+    pub fn caret_on_adjacent_page(&mut self, offset: usize, affinity: Affinity, x: f32, down: bool) -> Option<CaretPosition> {
+        let index = self.paragraph_at(offset)?;
+        let (page, line) = self.placed_line(index, offset, affinity)?;
+        let y = page.content_bounds.y + line.y + line.height / 2.0;
+        let target = match down {
+            true => page.page_index + 1,
+            false => page.page_index.checked_sub(1)?,
+        };
+        self.position_at_point(target, x, y)
+    }
+
+    /// Caret at the start of the line holding the caret at `offset` with
+    /// `affinity`, or at its end if `end`, if laid out on a page
+    pub fn caret_at_line_edge(&mut self, offset: usize, affinity: Affinity, end: bool) -> Option<CaretPosition> {
+        let index = self.paragraph_at(offset)?;
+        let (page, line) = self.placed_line(index, offset, affinity)?;
+        let (page_index, bounds, line) = (page.page_index, page.content_bounds, line.clone());
+        let chars = hit_test::line_chars(&self.layouts[index], line.source_line_index);
+        let (within, affinity) = match end {
+            true => (chars.end, Affinity::Upstream),
+            false => (chars.start, Affinity::Downstream),
+        };
+        let caret = hit_test::caret_on_line(
+            &self.layouts[index],
+            line.source_line_index,
+            within,
+            affinity,
+            self.line_layout.breaker_mut(),
+        );
+        Some(self.caret_position(page_index, bounds, &line, caret))
+    }
+
+    /// Highlight rectangles of the chars in `ranges` on the pages laid out, in
+    /// page and line order
+    ///
+    /// A line gets one rectangle per stretch of it the ranges cover on screen,
+    /// several where bidi reordering splits a range, and a range that takes in
+    /// a paragraph mark gets a rectangle the width of a space after the text
+    /// of the paragraph's last line.
+    pub fn selection_rects(&mut self, ranges: &[Range<usize>]) -> Vec<HighlightRect> {
+        let mut sorted: Vec<Range<usize>> = ranges.iter().filter(|range| !range.is_empty()).cloned().collect();
+        sorted.sort_by_key(|range| range.start);
+        let mut ranges: Vec<Range<usize>> = Vec::with_capacity(sorted.len());
+        for range in sorted {
+            match ranges.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => ranges.push(range),
+            }
+        }
+
+        let selected = |start: usize, end: usize| ranges.iter().any(|range| range.start < end && start < range.end);
+        let lines: Vec<(usize, Rect, RenderedLine)> = self
+            .placed_pages()
+            .iter()
+            .flat_map(|page| page.lines.iter().map(move |line| (page.page_index, page.content_bounds, line)))
+            .filter(|(_, _, line)| {
+                let slot = &self.slots[line.paragraph_index];
+                selected(slot.start, slot.start + slot.length + 1)
+            })
+            .map(|(page, bounds, line)| (page, bounds, line.clone()))
+            .collect();
+
+        let mark_width = self.line_layout.breaker_mut().calculate_text_width(" ");
+        let mut rects: Vec<HighlightRect> = Vec::new();
+        for (page, bounds, line) in lines {
+            let index = line.paragraph_index;
+            let (left, top) = (self.line_left(bounds, &line), bounds.y + line.y);
+            let (start, mark) = (self.slots[index].start, self.slots[index].start + self.slots[index].length);
+            let layout = &self.layouts[index];
+            let breaker = self.line_layout.breaker_mut();
+            let chars = hit_test::line_chars(layout, line.source_line_index);
+            let has_mark = line.source_line_index + 1 == layout.lines.len() && index + 1 < self.slots.len();
+
+            let mut spans = Vec::new();
+            for range in &ranges {
+                let from = range.start.max(start + chars.start);
+                let to = range.end.min(start + chars.end);
+                if from < to {
+                    spans.extend(hit_test::selection_spans(layout, line.source_line_index, from - start..to - start, breaker));
+                }
+                if has_mark && range.contains(&mark) {
+                    let end = hit_test::selection_spans(layout, line.source_line_index, chars.clone(), breaker)
+                        .iter()
+                        .fold(0.0f32, |end, span| end.max(span.1));
+                    spans.push((end, end + mark_width));
+                }
+            }
+            spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            for (from, to) in spans {
+                match rects.last_mut() {
+                    Some(last)
+                        if (last.page, last.paragraph, last.line) == (page, index, line.source_line_index)
+                            && (last.rect.x + last.rect.width - (left + from)).abs() < 0.01 =>
+                    {
+                        last.rect.width = left + to - last.rect.x;
+                    }
+                    _ => rects.push(HighlightRect {
+                        page,
+                        paragraph: index,
+                        line: line.source_line_index,
+                        rect: Rect::new(left + from, top, to - from, line.height),
+                    }),
+                }
+            }
+        }
+        rects
+    }
+
+    /// Scroll position of the top of the line holding char `offset` in the flow
+    fn flow_scroll_position(&self, offset: usize) -> f32 {
+        let Some(index) = self.paragraph_at(offset) else {
+            return 0.0;
+        };
+        let y = self.flow_position(index, offset);
+        let page = ((y / self.content_height()).floor() as usize).min(self.page_count().saturating_sub(1));
+        let page_top = page as f32 * self.content_height();
+        page as f32 * self.stride() + self.page_layout.page_config.margin_top + (y - page_top)
+    }
+
+    /// Char offset of the start of the line at scroll position `position`,
+    /// or of the last line above it on its page, if any is
+    pub fn offset_at(&self, position: f32) -> usize {
+        if self.slots.is_empty() {
+            return 0;
+        }
+        let stride = self.stride();
+        let page_index = (position / stride).floor().max(0.0) as usize;
+        let y = position - page_index as f32 * stride - self.page_layout.page_config.margin_top;
+        if let Some(page) = self.placed_pages().iter().find(|page| page.page_index == page_index) {
+            // Rounding can put the position a hair above the top of its line
+            let line = page.lines.iter().rev().find(|line| line.y <= y + 0.01).or(page.lines.first());
+            if let Some(line) = line {
+                let slot = &self.slots[line.paragraph_index];
+                return slot.start + char_count(&self.layouts[line.paragraph_index].text, line.start);
+            }
+        }
+        let page_index = page_index.min(self.page_count().saturating_sub(1));
+        let content_height = self.content_height();
+        self.flow_offset(page_index as f32 * content_height + y.clamp(0.0, content_height))
+    }
+
+    /// Lays out paragraph `index` if it is not yet; returns whether it was not
+    fn lay_out(&mut self, tree: &PieceTree, index: usize) -> bool {
+        let slot = &self.slots[index];
+        if slot.laid_out {
+            return false;
+        }
+        let start = tree.char_to_byte_offset(slot.start);
+        let end = tree.char_to_byte_offset(slot.start + slot.length);
+        let text = tree.get_text_range(start, end - start);
+        let properties = self.layouts[index].properties;
+        let layout = self.line_layout.layout_paragraph_with_props(&text, self.page_layout.column_width(), properties);
+        if let Some((_, full)) = layout.lines.split_last() {
+            self.full_lines.0 += full.len();
+            self.full_lines.1 += full.iter().map(|line| line.char_count).sum::<usize>();
+        }
+        let longest = layout.lines.iter().map(|line| line.char_count).max();
+        self.longest_line = self.longest_line.max(longest.unwrap_or(0));
+        let slot = &mut self.slots[index];
+        slot.height = flow_height(&layout);
+        slot.laid_out = true;
+        self.layouts[index] = layout;
+        self.estimated -= 1;
+        true
+    }
+
+    /// Lays out the paragraphs the flow puts on pages `first_page` to
+    /// `last_page`, and paginates them from the paragraph the first starts in
+    fn lay_out_window(&mut self, tree: &PieceTree, first_page: usize, last_page: usize) {
+        let content_height = self.content_height();
+        let first_page = first_page.min(self.page_count() - 1);
+        // Pages laid out hold less than the flow puts on them, so take a page more
+        let flow = first_page as f32 * content_height..(last_page + 2) as f32 * content_height;
+        let (first, end) = loop {
+            let first = self.flow_paragraph(flow.start);
+            let end = self.tops[..self.slots.len()].partition_point(|&top| top < flow.end).max(first + 1);
+            let laid_out = (first..end).filter(|&index| self.lay_out(tree, index)).count();
+            if laid_out == 0 {
+                break (first, end);
+            }
+            self.update_tops(first);
+        };
+        if self.estimated == 0 {
+            self.paginate();
+            return;
+        }
+
+        let mut pages = self.page_layout.layout_pages(&self.layouts[first..end]);
+        let is_end = end == self.slots.len();
+        pages.truncate(if is_end { pages.len() } else { last_page - first_page + 1 });
+        for page in &mut pages {
+            page.page_index += first_page;
+            page.continued_on = page.continued_on.map(|index| index + first_page);
+            page.continued_from = page.continued_from.map(|index| index + first_page);
+            for line in &mut page.lines {
+                line.paragraph_index += first;
+            }
+        }
+        self.window = pages;
+    }
+
+    /// Paginates every paragraph, picking up from the first edited one when
+    /// the pages before it are known
+    fn paginate(&mut self) {
+        let previous = self.model.take();
+        let model = match (previous, self.stale_from.take()) {
+            (Some(previous), Some(mut from)) if from > 0 => {
+                // Paragraphs kept with the first edited one may move with it
+                while from > 0 && self.layouts[from - 1].properties.keep_with_next {
+                    from -= 1;
+                }
+                match from {
+                    0 => self.page_layout.layout_sections(&self.layouts, &self.sections),
+                    _ => self.page_layout.relayout_sections(&self.layouts, &self.sections, &previous, from),
+                }
+            }
+            _ => self.page_layout.layout_sections(&self.layouts, &self.sections),
+        };
+        self.model = Some(model);
+        self.window.clear();
+    }
+
+    /// Pages laid out exactly: all of them once exact, else the window
+    fn placed_pages(&self) -> &[Page] {
+        match self.model() {
+            Some(model) => &model.pages,
+            None => &self.window,
+        }
+    }
+
+    /// Page and line holding char `offset` of paragraph `index`, if laid out on
+    /// a page; with upstream affinity, an offset starting a wrapped line is on
+    /// the line before
+    fn placed_line(&self, index: usize, offset: usize, affinity: Affinity) -> Option<(&Page, &RenderedLine)> {
+        if !self.slots[index].laid_out {
+            return None;
+        }
+        let byte = byte_offset(&self.layouts[index].text, offset - self.slots[index].start);
+        let start_page = self.model().and_then(|model| model.page_placed_on(index)).unwrap_or(0);
+        self.placed_pages()
+            .iter()
+            .skip(start_page)
+            .flat_map(|page| page.lines.iter().map(move |line| (page, line)))
+            .skip_while(|(_, line)| line.paragraph_index < index)
+            .take_while(|(_, line)| line.paragraph_index == index)
+            .take_while(|(_, line)| {
+                line.start < byte
+                    || line.start == byte && (affinity == Affinity::Downstream || line.source_line_index == 0)
+            })
+            .last()
+    }
+
+    /// Left edge of `line` on its page, with its alignment offset
+    fn line_left(&self, bounds: Rect, line: &RenderedLine) -> f32 {
+        let offset_x = self.layouts[line.paragraph_index]
+            .lines
+            .get(line.source_line_index)
+            .map_or(0.0, |info| info.offset_x);
+        bounds.x + line.x + offset_x
+    }
+
+    /// Caret on `line` of page `page_index` nearest `x` points from the page's left edge
+    fn caret_at_x(&mut self, page_index: usize, bounds: Rect, line: &RenderedLine, x: f32) -> CaretPosition {
+        let left = self.line_left(bounds, line);
+        let caret = hit_test::caret_at_x(
+            &self.layouts[line.paragraph_index],
+            line.source_line_index,
+            x - left,
+            self.line_layout.breaker_mut(),
+        );
+        self.caret_position(page_index, bounds, line, caret)
+    }
+
+    /// Document position of `caret` on `line` of page `page_index`
+    fn caret_position(&self, page_index: usize, bounds: Rect, line: &RenderedLine, caret: LineCaret) -> CaretPosition {
+        let paragraph = &self.layouts[line.paragraph_index];
+        let line_start = hit_test::line_chars(paragraph, line.source_line_index).start;
+        CaretPosition {
+            offset: self.slots[line.paragraph_index].start + caret.offset,
+            affinity: caret.affinity,
+            page: page_index,
+            paragraph: line.paragraph_index,
+            line: line.source_line_index,
+            column: caret.offset.saturating_sub(line_start),
+            rect: hit_test::caret_rect(self.line_left(bounds, line), bounds.y + line.y, line.height, &caret),
+            rtl: caret.rtl,
+        }
+    }
+
+    /// Index of the paragraph holding char `offset`
+    fn paragraph_at(&self, offset: usize) -> Option<usize> {
+        let after = self.slots.partition_point(|slot| slot.start <= offset);
+        (!self.slots.is_empty()).then(|| after.saturating_sub(1))
+    }
+
+    /// Index of the paragraph at flow position `y`
+    fn flow_paragraph(&self, y: f32) -> usize {
+        let after = self.tops[..self.slots.len()].partition_point(|&top| top <= y);
+        after.saturating_sub(1)
+    }
+
+    /// Flow position of the top of the line holding char `offset` of paragraph `index`
+    fn flow_position(&self, index: usize, offset: usize) -> f32 {
+        let slot = &self.slots[index];
+        let within = offset.saturating_sub(slot.start).min(slot.length);
+        if !slot.laid_out {
+            return self.tops[index] + slot.height * within as f32 / slot.length.max(1) as f32;
+        }
+        let layout = &self.layouts[index];
+        let byte = byte_offset(&layout.text, within);
+        let mut y = self.tops[index] + layout.space_before();
+        for (line_index, line) in layout.lines.iter().enumerate() {
+            if layout.lines.get(line_index + 1).is_none_or(|next| byte < next.start) {
+                break;
+            }
+            y += line_height(layout, line);
+        }
+        y
+    }
+
+    /// Char offset of the start of the line at flow position `y`
+    fn flow_offset(&self, y: f32) -> usize {
+        let index = self.flow_paragraph(y);
+        let slot = &self.slots[index];
+        let top = self.tops[index];
+        if !slot.laid_out {
+            let fraction = ((y - top) / slot.height.max(f32::EPSILON)).clamp(0.0, 1.0);
+            return slot.start + (slot.length as f32 * fraction) as usize;
+        }
+        let layout = &self.layouts[index];
+        let mut bottom = top + layout.space_before();
+        for (line_index, line) in layout.lines.iter().enumerate() {
+            bottom += line_height(layout, line);
+            if y < bottom || line_index + 1 == layout.lines.len() {
+                return slot.start + char_count(&layout.text, line.start);
+            }
+        }
+        slot.start
+    }
+
+    /// Recompute the flow positions of paragraph `from` and those after it
+    fn update_tops(&mut self, from: usize) {
+        self.tops.resize(self.slots.len() + 1, 0.0);
+        for index in from..self.slots.len() {
+            self.tops[index + 1] = self.tops[index] + self.slots[index].height;
+        }
+    }
+
+    /// Chars a line holds: the average of the full lines laid out, or before
+    /// any paragraph wrapped, how many chars of sample text fit a column but
+    /// at least as many as a line has held
+    fn calibrate(&mut self) {
+        self.chars_per_line = match self.full_lines {
+            (lines, chars) if lines > 0 && chars > 0 => chars as f32 / lines as f32,
+            _ => {
+                let width = self.line_layout.breaker_mut().calculate_text_width(SAMPLE_TEXT);
+                let char_width = width / SAMPLE_TEXT.chars().count() as f32;
+                let fit = self.page_layout.column_width() / char_width.max(f32::EPSILON);
+                fit.max(self.longest_line as f32).max(1.0)
+            }
+        };
+    }
+
+    /// First and last page to lay out for `viewport`
+    fn page_range(&self, viewport: Viewport) -> (usize, usize) {
+        let stride = self.stride();
+        let first_page = ((viewport.top / stride).floor().max(0.0) as usize).saturating_sub(OVERSCAN_PAGES);
+        let last_page = ((viewport.top + viewport.height) / stride).floor().max(0.0) as usize + OVERSCAN_PAGES;
+        (first_page, last_page)
+    }
+
+    /// Height of a paragraph not laid out, from its length
+    fn estimate(&self, slot: &Slot, properties: ParagraphProperties) -> f32 {
+        let width = self.page_layout.column_width();
+        let lines = (slot.length as f32 / self.chars_per_line).ceil().max(1.0);
+        let spacing = (properties.space_before + properties.space_after) * width / 1440.0;
+        lines * self.line_layout.line_height(properties) + spacing
+    }
+
+    /// Distance from the top of one page to the top of the next
+    fn stride(&self) -> f32 {
+        self.page_layout.page_config.height + PAGE_GAP
+    }
+
+    fn content_height(&self) -> f32 {
+        self.page_layout.page_config.content_height().max(1.0)
+    }
+}
+
+/// Layout standing in for a paragraph not laid out
+fn placeholder(width: f32, properties: ParagraphProperties) -> ParagraphLayout {
+    ParagraphLayout {
+        text: String::new(),
+        max_width: width,
+        content_width: width,
+        lines: Vec::new(),
+        total_height: 0.0,
+        base_line_height: 0.0,
+        actual_line_height: 0.0,
+        has_bidi: false,
+        properties,
+    }
+}
+
+/// Height of a line as pagination sees it
+fn line_height(layout: &ParagraphLayout, line: &LineLayoutInfo) -> f32 {
+    if line.line_height > 0.0 {
+        line.line_height
+    } else {
+        layout.actual_line_height
+    }
+}
+
+/// Height of a laid-out paragraph in the flow, with its spacing
+fn flow_height(layout: &ParagraphLayout) -> f32 {
+    let lines: f32 = match layout.lines.is_empty() {
+        true => layout.actual_line_height,
+        false => layout.lines.iter().map(|line| line_height(layout, line)).sum(),
+    };
+    layout.space_before() + lines + layout.space_after()
+}
+
+/// Byte offset of char `chars` of `text`
+fn byte_offset(text: &str, chars: usize) -> usize {
+    text.char_indices().nth(chars).map_or(text.len(), |(byte, _)| byte)
+}
+
+/// Chars of `text` before byte `byte`
+fn char_count(text: &str, byte: usize) -> usize {
+    text.get(..byte).map_or(0, |prefix| prefix.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_layout::PageConfig;
+    use crate::paragraph_hash::ParagraphHashes;
+    use crate::text_shaping::TextShaper;
+
+    fn layout() -> ViewportLayout {
+        let mut layout = ViewportLayout::new(PageLayout::with_page_config(PageConfig::letter()));
+        layout.line_layout_mut().breaker_mut().set_shaper(TextShaper::without_font());
+        layout
+    }
+
+    fn text(paragraphs: usize) -> String {
+        (0..paragraphs)
+            .map(|i| match i % 3 {
+                0 => format!("Heading {}", i),
+                _ => format!("Paragraph {} has enough words in it to wrap onto a second line of the page, or even a third one.", i),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Lines of every page as (page, paragraph, line, y)
+    fn lines(pages: &[Page]) -> Vec<(usize, usize, usize, u32)> {
+        pages
+            .iter()
+            .flat_map(|page| page.lines.iter().map(move |l| (page.page_index, l.paragraph_index, l.source_line_index, l.y.to_bits())))
+            .collect()
+    }
+
+    /// Pages of the whole text laid out at once
+    fn from_scratch(tree: &PieceTree) -> Vec<Page> {
+        let mut line_layout = LineLayout::new();
+        line_layout.breaker_mut().set_shaper(TextShaper::without_font());
+        let mut page_layout = PageLayout::with_page_config(PageConfig::letter());
+        let width = page_layout.column_width();
+        let paragraphs: Vec<ParagraphLayout> =
+            tree.get_text().split('\n').map(|text| line_layout.layout_paragraph_with_props(text, width, Default::default())).collect();
+        page_layout.layout_pages(&paragraphs)
+    }
+
+    fn refine_until_exact(layout: &mut ViewportLayout, tree: &PieceTree) {
+        while !layout.refine(tree, 500).exact {}
+    }
+
+    #[test]
+    fn test_only_pages_in_view_are_laid_out() {
+        let tree = PieceTree::new(text(3000));
+        let hashes = ParagraphHashes::build(&tree);
+        let mut layout = layout();
+        assert_eq!(layout.update(hashes.entries(), &[]), 0..3000);
+        assert_eq!(layout.estimated_count(), 3000);
+        let estimate = layout.page_count();
+        assert!(estimate > 50, "estimated {} pages", estimate);
+
+        // A viewport halfway down
+        let top = (layout.page_count() / 2) as f32 * (792.0 + PAGE_GAP) + 300.0;
+        let view = layout.viewport_pages(&tree, Viewport { top, height: 700.0 });
+        assert!(!view.exact);
+        assert!(3000 - layout.estimated_count() < 250);
+        assert!((2..=4).contains(&view.pages.len()));
+        assert!(view.pages.iter().any(|page| page.top <= top && top < page.top + 792.0 + PAGE_GAP));
+        assert!(view.paragraphs.start > 1000 && view.paragraphs.end < 2000, "{:?}", view.paragraphs);
+        let on_pages: Vec<usize> = view.pages.iter().flat_map(|page| page.page.lines.iter().map(|line| line.paragraph_index)).collect();
+        assert!(on_pages.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // The text at the top of the view is where it is said to be
+        let offset = layout.offset_at(top);
+        assert!(hashes.entries()[view.paragraphs.clone()].iter().any(|paragraph| paragraph.start <= offset));
+        assert!((0.0..20.0).contains(&(top - layout.scroll_position(offset))));
+    }
+
+    #[test]
+    fn test_refining_ends_in_exact_pages() {
+        let tree = PieceTree::new(text(600));
+        let hashes = ParagraphHashes::build(&tree);
+        let mut layout = layout();
+        layout.update(hashes.entries(), &[]);
+        let top = 20.0 * (792.0 + PAGE_GAP);
+        layout.viewport_pages(&tree, Viewport { top, height: 700.0 });
+        let anchor = layout.offset_at(top);
+
+        let mut scrolled = layout.scroll_position(anchor);
+        let mut steps = Vec::new();
+        loop {
+            let step = layout.refine(&tree, 100);
+            scrolled += step.scroll_adjustment;
+            steps.push(step);
+            if step.exact {
+                break;
+            }
+        }
+        assert!(steps.len() > 2);
+        assert!(steps.windows(2).all(|pair| pair[1].remaining < pair[0].remaining));
+        assert_eq!(layout.estimated_count(), 0);
+        // The anchored text is where the adjustments moved it
+        assert!((layout.scroll_position(anchor) - scrolled).abs() < 1.0);
+
+        let scratch = from_scratch(&tree);
+        assert_eq!(layout.page_count(), scratch.len());
+        assert_eq!(lines(&layout.model().unwrap().pages), lines(&scratch));
+        let view = layout.viewport_pages(&tree, Viewport { top: scrolled, height: 700.0 });
+        assert!(view.exact);
+        assert_eq!(view.scroll_height, scratch.len() as f32 * (792.0 + PAGE_GAP) - PAGE_GAP);
+
+        // Exact positions map back to the start of their line
+        for paragraph in &hashes.entries()[..60] {
+            let offset = paragraph.start + paragraph.length / 2;
+            let line_start = layout.offset_at(layout.scroll_position(offset));
+            assert!(paragraph.start <= line_start && line_start <= offset);
+        }
+        assert_eq!(layout.refine(&tree, 100), RefineStep { exact: true, ..Default::default() });
+    }
+
+    #[test]
+    fn test_edits_lay_out_only_changed_paragraphs() {
+        let mut tree = PieceTree::new(text(400));
+        let mut hashes = ParagraphHashes::build(&tree);
+        let mut layout = layout();
+        layout.update(hashes.entries(), &[]);
+        refine_until_exact(&mut layout, &tree);
+
+        // Typing in paragraph 200 makes the layout estimated again until it is in view
+        let offset = hashes.entries()[200].start + 10;
+        tree.insert_with_attrs(offset, "more words ".repeat(12), None);
+        hashes.apply_edit(&tree, offset, 0, 132);
+        assert_eq!(layout.update(hashes.entries(), &[]), 200..201);
+        assert_eq!(layout.estimated_count(), 1);
+        assert!(!layout.is_exact());
+
+        let top = layout.scroll_position(offset);
+        let view = layout.viewport_pages(&tree, Viewport { top, height: 700.0 });
+        assert!(view.exact);
+        assert!(view.paragraphs.contains(&200));
+        assert_eq!(lines(&layout.model().unwrap().pages), lines(&from_scratch(&tree)));
+    }
+
+    fn select(layout: &mut ViewportLayout, range: Range<usize>) -> Vec<HighlightRect> {
+        layout.selection_rects(std::slice::from_ref(&range))
+    }
+
+    #[test]
+    fn test_points_and_carets_round_trip() {
+        let tree = PieceTree::new(text(600));
+        let hashes = ParagraphHashes::build(&tree);
+        let mut layout = layout();
+        layout.update(hashes.entries(), &[]);
+        let view = layout.viewport_pages(&tree, Viewport { top: 0.0, height: 800.0 });
+        let first = &view.pages[0].page;
+        let bounds = first.content_bounds;
+        assert_eq!(layout.position_at_point(5, 0.0, 0.0), None);
+
+        // Above and left of the text is the start of the document
+        let start = layout.position_at_point(0, 0.0, 0.0).unwrap();
+        assert_eq!((start.offset, start.line, start.column, start.affinity), (0, 0, 0, Affinity::Downstream));
+        assert_eq!((start.rect.x, start.rect.y), (bounds.x, bounds.y));
+
+        // Every caret maps back to its own offset
+        let paragraph = &hashes.entries()[1];
+        for offset in [paragraph.start, paragraph.start + 5, paragraph.start + paragraph.length] {
+            let caret = layout.caret_rect(offset, Affinity::Downstream).unwrap();
+            assert_eq!(caret.paragraph, 1);
+            let hit = layout.position_at_point(0, caret.rect.x, caret.rect.y + caret.rect.height / 2.0).unwrap();
+            assert_eq!((hit.offset, hit.rect), (offset, caret.rect));
+        }
+
+        // The end of a paragraph is upstream of the next one, a line below
+        let end = layout.caret_rect(paragraph.start + paragraph.length, Affinity::Upstream).unwrap();
+        let next = layout.caret_rect(hashes.entries()[2].start, Affinity::Downstream).unwrap();
+        assert_eq!(next.paragraph, 2);
+        assert!(next.rect.y > end.rect.y);
+        assert_eq!(layout.caret_rect(hashes.entries()[599].start, Affinity::Downstream), None);
+    }
+
+    #[test]
+    fn test_selection_rects_cover_wrapped_lines_and_marks() {
+        let long = "Many words in a row. ".repeat(20);
+        let tree = PieceTree::new(format!("Heading\n{}\n{}", long, text(600)));
+        let hashes = ParagraphHashes::build(&tree);
+        let mut layout = layout();
+        layout.update(hashes.entries(), &[]);
+        layout.viewport_pages(&tree, Viewport { top: 0.0, height: 800.0 });
+        let (heading, paragraph) = (&hashes.entries()[0], &hashes.entries()[1]);
+        assert!(select(&mut layout, 5..5).is_empty());
+
+        // Within a line: one rectangle from caret to caret
+        let rects = select(&mut layout, 2..5);
+        let (from, to) = (layout.caret_rect(2, Affinity::Downstream).unwrap(), layout.caret_rect(5, Affinity::Downstream).unwrap());
+        assert_eq!(rects.len(), 1);
+        assert_eq!((rects[0].page, rects[0].paragraph, rects[0].line), (0, 0, 0));
+        assert_eq!((rects[0].rect.x, rects[0].rect.width), (from.rect.x, to.rect.x - from.rect.x));
+
+        // A wrapped paragraph and its mark: a rectangle per line, the last one
+        // running past the text; overlapping ranges coalesce
+        let whole = paragraph.start..paragraph.start + paragraph.length + 1;
+        let rects = layout.selection_rects(&[whole.clone(), whole.start + 3..whole.start + 9]);
+        assert!(rects.len() > 1);
+        assert!(rects.iter().enumerate().all(|(i, rect)| (rect.paragraph, rect.line) == (1, i)));
+        let end = layout.caret_rect(paragraph.start + paragraph.length, Affinity::Upstream).unwrap();
+        let last = rects.last().unwrap().rect;
+        assert!(last.x + last.width > end.rect.x);
+
+        // Only the mark of the heading
+        let rects = select(&mut layout, heading.length..heading.length + 1);
+        assert_eq!(rects.len(), 1);
+        assert_eq!(rects[0].rect.x, layout.caret_rect(heading.length, Affinity::Downstream).unwrap().rect.x);
+
+        let far = &hashes.entries()[601];
+        assert!(select(&mut layout, far.start..far.start + 3).is_empty());
+    }
+}
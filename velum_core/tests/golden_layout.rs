@@ -0,0 +1,134 @@
+// Golden visual regression tests for layout
+//
+// Each fixture in tests/golden is laid out on pages, drawn with the headless
+// rasterizer and compared with the stored PNG of every page. Text is
+// measured without a font, so the pictures are the same on every machine.
+//
+// A test fails when a page differs visibly from its golden. The rendering and
+// a picture of the changed pixels (in red) are then written next to the test
+// binaries, under target/tmp/golden. When the change is intended, rewrite the
+// goldens with
+//
+//     VELUM_UPDATE_GOLDENS=1 cargo test --test golden_layout
+//
+// and commit the new PNGs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use velum_core::line_layout::LineLayout;
+use velum_core::page_layout::{PageConfig, PageLayout};
+use velum_core::pdf::{page_from_layout, ParagraphContent};
+use velum_core::raster::{compare, Canvas};
+use velum_core::text_shaping::TextShaper;
+
+/// Pixels a point
+const SCALE: f32 = 1.0;
+
+/// Color difference a pixel may show before it counts as changed
+const PIXEL_THRESHOLD: f32 = 0.1;
+
+/// Share of a page's pixels that may change before the page fails
+const MAX_CHANGED_RATIO: f32 = 0.0005;
+
+struct Fixture {
+    /// Name of the goldens
+    name: &'static str,
+    /// Text file the paragraphs come from
+    text: &'static str,
+    page: fn() -> PageConfig,
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture { name: "paragraphs", text: "paragraphs.txt", page: PageConfig::a4 },
+    Fixture { name: "paragraphs_letter", text: "paragraphs.txt", page: PageConfig::letter },
+    Fixture { name: "long_words", text: "long_words.txt", page: PageConfig::a4 },
+    Fixture { name: "multipage", text: "multipage.txt", page: PageConfig::a4 },
+    Fixture { name: "mixed_scripts", text: "mixed_scripts.txt", page: PageConfig::a4 },
+];
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+/// Every page of a fixture, drawn
+fn render(fixture: &Fixture) -> Vec<Canvas> {
+    let text = fs::read_to_string(golden_dir().join(fixture.text)).expect("fixture text");
+
+    let config = (fixture.page)();
+    let mut line_layout = LineLayout::new();
+    line_layout.breaker_mut().set_shaper(TextShaper::without_font());
+    let layout = line_layout.layout_document(&text, config.content_width());
+
+    let model = PageLayout::with_page_config(config).layout_sections(&layout.paragraphs, &[]);
+
+    let content = vec![ParagraphContent::default(); layout.paragraphs.len()];
+    model
+        .pages
+        .iter()
+        .map(|page| Canvas::render_page(&page_from_layout(page, model.page_config(page), &layout.paragraphs, &content), SCALE))
+        .collect()
+}
+
+#[test]
+fn test_layout_matches_goldens() {
+    let update = std::env::var_os("VELUM_UPDATE_GOLDENS").is_some();
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let mut failures = Vec::new();
+
+    for fixture in FIXTURES {
+        let pages = render(fixture);
+        let golden = |page: usize| golden_dir().join(format!("{}.page{}.png", fixture.name, page + 1));
+
+        if update {
+            for (index, canvas) in pages.iter().enumerate() {
+                fs::write(golden(index), canvas.to_png()).expect("write golden");
+            }
+            // Pages the fixture no longer has
+            let mut stale = pages.len();
+            while fs::remove_file(golden(stale)).is_ok() {
+                stale += 1;
+            }
+            continue;
+        }
+
+        let golden_pages = (0..).take_while(|&page| golden(page).exists()).count();
+        if golden_pages != pages.len() {
+            failures.push(format!("{}: {} pages, golden has {}", fixture.name, pages.len(), golden_pages));
+        }
+
+        for (index, canvas) in pages.iter().enumerate().take(golden_pages) {
+            let expected = Canvas::from_png(&fs::read(golden(index)).expect("read golden")).expect("decode golden");
+            let page = format!("{}.page{}", fixture.name, index + 1);
+            let failure = match compare(&expected, canvas, PIXEL_THRESHOLD) {
+                Ok(diff) if diff.ratio() <= MAX_CHANGED_RATIO => continue,
+                Ok(diff) => {
+                    fs::create_dir_all(&output).expect("create output directory");
+                    fs::write(output.join(format!("{}.diff.png", page)), diff.image.to_png()).expect("write diff");
+                    format!("{}: {} of {} pixels changed (max delta {:.2})", page, diff.differing, diff.total, diff.max_delta)
+                }
+                Err(e) => format!("{}: {}", page, e),
+            };
+            fs::create_dir_all(&output).expect("create output directory");
+            fs::write(output.join(format!("{}.actual.png", page)), canvas.to_png()).expect("write rendering");
+            failures.push(failure);
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Layout differs from the goldens; renderings are in {}:\n{}\nIf the change is intended, rerun with VELUM_UPDATE_GOLDENS=1",
+        output.display(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_rendering_is_deterministic() {
+    let fixture = &FIXTURES[0];
+    let (first, second) = (render(fixture), render(fixture));
+    assert_eq!(first.len(), second.len());
+    for (a, b) in first.iter().zip(&second) {
+        assert_eq!(compare(a, b, 0.0).unwrap().differing, 0);
+    }
+}
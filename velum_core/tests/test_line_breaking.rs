@@ -55,3 +55,31 @@ fn test_layout_to_json() {
     let json = layout.layout_to_json("Hello", 100.0);
     assert!(json.starts_with('{'));
 }
+
+#[test]
+fn test_total_fit_against_first_fit() {
+    use velum_core::line_breaking::BreakStrategy;
+
+    let text = "When a paragraph is set one line at a time, each line takes as many words as fit \
+                and the next line has to live with whatever is left over. Choosing every break of \
+                the paragraph together trades a little time for lines that fill the measure more \
+                evenly, which matters most for justified text where the slack becomes visible \
+                gaps between the words of a line. "
+        .repeat(8);
+    let width = 300.0;
+    // Squared slack per line, leaving out the last line of the paragraph
+    let raggedness = |lines: &[Line]| -> f32 {
+        lines[..lines.len() - 1]
+            .iter()
+            .map(|line| ((width - line.width) / width * 100.0).powi(2))
+            .sum()
+    };
+
+    let greedy = LineBreaker::with_width(width).break_lines(&text, None);
+    let mut total_fit = LineBreaker::with_width(width);
+    total_fit.set_break_strategy(BreakStrategy::TotalFit);
+    let even = total_fit.break_lines(&text, None);
+
+    assert!(even.iter().all(|line| line.width <= width + 1.0));
+    assert!(raggedness(&even) <= raggedness(&greedy));
+}